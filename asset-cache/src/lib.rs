@@ -0,0 +1,204 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    OnceLock,
+  },
+};
+
+use crash_report::log;
+use serde::{de::DeserializeOwned, Serialize};
+use xxhash_rust::xxh3::Xxh3;
+
+// Artifact layout, all integers little endian:
+// magic "RCAC", version u32, payload length u64, then the payload
+const ARTIFACT_MAGIC: &[u8; 4] = b"RCAC";
+const ARTIFACT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 16;
+
+static GLOBAL_CACHE: OnceLock<AssetCache> = OnceLock::new();
+
+// Hash of everything an artifact is built from. Stable between runs and builds, unlike std's
+// hashers, since it names files that outlive the process
+pub struct ContentHasher(Xxh3);
+
+impl Default for ContentHasher {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ContentHasher {
+  pub fn new() -> Self {
+    Self(Xxh3::new())
+  }
+
+  // Lengths go in too, so ("ab", "c") and ("a", "bc") hash differently
+  pub fn update(&mut self, bytes: &[u8]) -> &mut Self {
+    self.0.update(&(bytes.len() as u64).to_le_bytes());
+    self.0.update(bytes);
+    self
+  }
+
+  pub fn finish(&self) -> u64 {
+    self.0.digest()
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AssetCacheStats {
+  pub hits: u64,
+  pub misses: u64,
+  pub stored_bytes: u64,
+}
+
+// What importing assets made, kept on disk under <dir>/<kind>/<hash>.bin so later runs load it
+// instead of doing the work again. Hashes cover the source files and import settings, so a
+// changed source just misses and gets built again. A disabled cache always misses and stores
+// nothing
+pub struct AssetCache {
+  dir: Option<PathBuf>,
+  hits: AtomicU64,
+  misses: AtomicU64,
+  stored_bytes: AtomicU64,
+  // Names temp files, so jobs storing the same artifact at once don't write into each other
+  next_temp: AtomicU64,
+}
+
+impl AssetCache {
+  pub fn disabled() -> Self {
+    Self {
+      dir: None,
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+      stored_bytes: AtomicU64::new(0),
+      next_temp: AtomicU64::new(0),
+    }
+  }
+
+  pub fn new(dir: &Path) -> Result<Self, String> {
+    fs::create_dir_all(dir)
+      .map_err(|e| format!("at creating asset cache dir {}: {e}", dir.display()))?;
+    Ok(Self { dir: Some(dir.to_path_buf()), ..Self::disabled() })
+  }
+
+  pub fn dir(&self) -> Option<&Path> {
+    self.dir.as_deref()
+  }
+
+  pub fn stats(&self) -> AssetCacheStats {
+    AssetCacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+      stored_bytes: self.stored_bytes.load(Ordering::Relaxed),
+    }
+  }
+
+  fn artifact_path(&self, kind: &str, hash: u64) -> Option<PathBuf> {
+    Some(self.dir.as_ref()?.join(kind).join(format!("{hash:016x}.bin")))
+  }
+
+  fn read_artifact(path: &Path) -> Option<Vec<u8>> {
+    let mut bytes = fs::read(path).ok()?;
+    if bytes.len() < HEADER_LEN || &bytes[..4] != ARTIFACT_MAGIC {
+      return None;
+    }
+    let version = u32::from_le_bytes(bytes[4..8].try_into().ok()?);
+    let payload_len = u64::from_le_bytes(bytes[8..16].try_into().ok()?);
+    if version != ARTIFACT_VERSION || payload_len != (bytes.len() - HEADER_LEN) as u64 {
+      return None;
+    }
+    bytes.drain(..HEADER_LEN);
+    Some(bytes)
+  }
+
+  // The artifact's bytes, None when it isn't cached or the file isn't one this build can read
+  pub fn load(&self, kind: &str, hash: u64) -> Option<Vec<u8>> {
+    let payload = self.artifact_path(kind, hash).and_then(|path| Self::read_artifact(&path));
+    let counter = if payload.is_some() { &self.hits } else { &self.misses };
+    counter.fetch_add(1, Ordering::Relaxed);
+    payload
+  }
+
+  // Written to a temp file and renamed over the artifact, so a crash never leaves half of one
+  fn write_artifact(&self, path: &Path, payload: &[u8]) -> Result<(), String> {
+    let kind_dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(kind_dir)
+      .map_err(|e| format!("at creating asset cache dir {}: {e}", kind_dir.display()))?;
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend(ARTIFACT_MAGIC);
+    bytes.extend(ARTIFACT_VERSION.to_le_bytes());
+    bytes.extend((payload.len() as u64).to_le_bytes());
+    bytes.extend(payload);
+    let temp_path = path.with_extension(format!(
+      "tmp{}-{}",
+      std::process::id(),
+      self.next_temp.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temp_path, &bytes).and_then(|_| fs::rename(&temp_path, path)).map_err(|e| {
+      let _ = fs::remove_file(&temp_path);
+      format!("at writing asset cache artifact {}: {e}", path.display())
+    })?;
+    self.stored_bytes.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+    Ok(())
+  }
+
+  // Caching is only ever a speedup, so failing to store is reported and otherwise ignored
+  pub fn store(&self, kind: &str, hash: u64, payload: &[u8]) {
+    let Some(path) = self.artifact_path(kind, hash) else { return };
+    if let Err(e) = self.write_artifact(&path, payload) {
+      log!("{e}");
+    }
+  }
+
+  // The cached artifact, else what build makes, stored for next time
+  pub fn load_or_build<T: Serialize + DeserializeOwned>(
+    &self,
+    kind: &str,
+    hash: u64,
+    build: impl FnOnce() -> Result<T, String>,
+  ) -> Result<T, String> {
+    if let Some(value) = self.load(kind, hash).and_then(|bytes| bincode::deserialize(&bytes).ok()) {
+      return Ok(value);
+    }
+    let value = build()?;
+    if self.dir.is_some() {
+      match bincode::serialize(&value) {
+        Ok(bytes) => self.store(kind, hash, &bytes),
+        Err(e) => log!("at serializing {kind} artifact: {e}"),
+      }
+    }
+    Ok(value)
+  }
+
+  // Removes every artifact, returns how many there were
+  pub fn clear(&self) -> Result<usize, String> {
+    let Some(dir) = &self.dir else {
+      return Ok(0);
+    };
+    let mut removed = 0;
+    let kind_dirs = fs::read_dir(dir).map_err(|e| format!("at listing {}: {e}", dir.display()))?;
+    for kind_dir in kind_dirs.flatten().filter(|entry| entry.path().is_dir()) {
+      let artifacts = fs::read_dir(kind_dir.path())
+        .map_err(|e| format!("at listing {}: {e}", kind_dir.path().display()))?;
+      for artifact in artifacts.flatten() {
+        fs::remove_file(artifact.path())
+          .map_err(|e| format!("at removing {}: {e}", artifact.path().display()))?;
+        removed += 1;
+      }
+    }
+    Ok(removed)
+  }
+}
+
+// Cache shared by asset loading, disabled unless init_global ran first so tools and tests don't
+// leave artifacts behind
+pub fn global() -> &'static AssetCache {
+  GLOBAL_CACHE.get_or_init(AssetCache::disabled)
+}
+
+pub fn init_global(cache: AssetCache) -> Result<&'static AssetCache, String> {
+  GLOBAL_CACHE.set(cache).map_err(|_| "global asset cache is already initialized".to_string())?;
+  Ok(global())
+}
@@ -0,0 +1,23 @@
+use ron::{extensions::Extensions, Options};
+use serde::de::DeserializeOwned;
+use vfs::Vfs;
+
+// Import settings of an asset are kept next to it as <asset path>.meta, like textures/brick.png.meta
+pub fn meta_path(asset_path: &str) -> String {
+  format!("{}.meta", vfs::normalize_path(asset_path))
+}
+
+// The asset's import settings in RON, the defaults when it has no meta file. Fields left out of
+// the file keep their defaults when the settings type has #[serde(default)], and optional ones
+// can be written without Some, like (srgb: false)
+pub fn load<T: DeserializeOwned + Default>(vfs: &Vfs, asset_path: &str) -> Result<T, String> {
+  let meta_path = meta_path(asset_path);
+  if !vfs.exists(&meta_path) {
+    return Ok(T::default());
+  }
+  let meta_str = vfs.read_to_string(&meta_path)?;
+  Options::default()
+    .with_default_extension(Extensions::IMPLICIT_SOME)
+    .from_str(&meta_str)
+    .map_err(|e| format!("at parsing import settings {meta_path}: {e}"))
+}
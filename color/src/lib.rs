@@ -0,0 +1,148 @@
+#[cfg(test)]
+mod tests;
+
+use std::ops::Mul;
+
+// x^(1/5) by newton steps, so sRGB colors can be decoded in consts. Starts above the root, the
+// steps only go down until they settle
+const fn fifth_root(x: f32) -> f32 {
+  if x <= 0.0 {
+    return 0.0;
+  }
+  let mut root = if x > 1.0 { x } else { 1.0 };
+  loop {
+    let root_4 = root * root * root * root;
+    let next = (4.0 * root + x / root_4) / 5.0;
+    if next >= root {
+      return root;
+    }
+    root = next;
+  }
+}
+
+// sRGB transfer function, for one channel
+pub const fn srgb_to_linear(value: f32) -> f32 {
+  if value <= 0.04045 {
+    return value / 12.92;
+  }
+  // x^2.4 as x^2 * (x^2)^(1/5)
+  let x = (value + 0.055) / 1.055;
+  let x_2 = x * x;
+  x_2 * fifth_root(x_2)
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+  if value <= 0.0031308 {
+    return value * 12.92;
+  }
+  1.055 * value.powf(1.0 / 2.4) - 0.055
+}
+
+// Linear RGBA, what shaders blend and light with. Colors picked in a paint program or color
+// picker are sRGB and go through from_srgb. Laid out like a vec4, so it can go in gpu structs as is
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C, align(16))]
+pub struct Color {
+  pub r: f32,
+  pub g: f32,
+  pub b: f32,
+  // Never gamma encoded
+  pub a: f32,
+}
+
+impl Color {
+  pub const TRANSPARENT: Color = Color::new(0.0, 0.0, 0.0, 0.0);
+  pub const BLACK: Color = Color::rgb(0.0, 0.0, 0.0);
+  pub const WHITE: Color = Color::rgb(1.0, 1.0, 1.0);
+  // Looks half way between black and white, 0.5 linear looks much lighter
+  pub const GRAY: Color = Color::from_srgb(0.5, 0.5, 0.5, 1.0);
+  pub const RED: Color = Color::rgb(1.0, 0.0, 0.0);
+  pub const GREEN: Color = Color::rgb(0.0, 1.0, 0.0);
+  pub const BLUE: Color = Color::rgb(0.0, 0.0, 1.0);
+  pub const YELLOW: Color = Color::rgb(1.0, 1.0, 0.0);
+  pub const CYAN: Color = Color::rgb(0.0, 1.0, 1.0);
+  pub const MAGENTA: Color = Color::rgb(1.0, 0.0, 1.0);
+
+  pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+    Self { r, g, b, a }
+  }
+
+  pub const fn rgb(r: f32, g: f32, b: f32) -> Self {
+    Self::new(r, g, b, 1.0)
+  }
+
+  pub const fn from_srgb(r: f32, g: f32, b: f32, a: f32) -> Self {
+    Self::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a)
+  }
+
+  pub const fn from_srgb8(r: u8, g: u8, b: u8, a: u8) -> Self {
+    Self::from_srgb(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0)
+  }
+
+  // Hue in degrees, wraps around. Like color pickers the result is in sRGB, so equal steps in value
+  // look equally far apart
+  pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+    let saturation = saturation.clamp(0.0, 1.0);
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let second = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+      0 => (chroma, second, 0.0),
+      1 => (second, chroma, 0.0),
+      2 => (0.0, chroma, second),
+      3 => (0.0, second, chroma),
+      4 => (second, 0.0, chroma),
+      _ => (chroma, 0.0, second),
+    };
+    let min = value - chroma;
+    Self::from_srgb(r + min, g + min, b + min, 1.0)
+  }
+
+  pub fn to_srgb(self) -> [f32; 4] {
+    [linear_to_srgb(self.r), linear_to_srgb(self.g), linear_to_srgb(self.b), self.a]
+  }
+
+  pub fn to_srgb8(self) -> [u8; 4] {
+    self.to_srgb().map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+  }
+
+  pub const fn with_alpha(self, a: f32) -> Self {
+    Self { a, ..self }
+  }
+
+  // In linear space, how light mixes
+  pub fn lerp(self, other: Color, t: f32) -> Self {
+    glam::Vec4::from(self).lerp(other.into(), t).into()
+  }
+
+  pub const fn to_array(self) -> [f32; 4] {
+    [self.r, self.g, self.b, self.a]
+  }
+}
+
+// Tints channel by channel, alpha included
+impl Mul for Color {
+  type Output = Color;
+
+  fn mul(self, rhs: Color) -> Color {
+    Color::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b, self.a * rhs.a)
+  }
+}
+
+impl From<Color> for glam::Vec4 {
+  fn from(color: Color) -> Self {
+    glam::Vec4::from_array(color.to_array())
+  }
+}
+
+impl From<glam::Vec4> for Color {
+  fn from(value: glam::Vec4) -> Self {
+    Color::new(value.x, value.y, value.z, value.w)
+  }
+}
+
+impl From<Color> for [f32; 4] {
+  fn from(color: Color) -> Self {
+    color.to_array()
+  }
+}
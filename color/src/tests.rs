@@ -0,0 +1,84 @@
+use crate::{linear_to_srgb, srgb_to_linear, Color};
+
+fn assert_close(value: [f32; 4], expected: [f32; 4], what: &str) {
+  let off = value.iter().zip(expected.iter()).any(|(a, b)| (a - b).abs() > 1e-4);
+  assert!(!off, "{what}: {value:?}, expected {expected:?}");
+}
+
+#[test]
+fn srgb_decodes_like_the_transfer_function() {
+  // The const fifth root stands in for powf(2.4)
+  for i in 0..=1000 {
+    let value = i as f32 / 1000.0;
+    let expected =
+      if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) };
+    let decoded = srgb_to_linear(value);
+    assert!((decoded - expected).abs() < 1e-6, "srgb {value} decoded to {decoded}, not {expected}");
+  }
+  assert_eq!(srgb_to_linear(0.0), 0.0);
+  assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+  // Mid gray is a lot darker in linear
+  assert!((Color::GRAY.r - 0.2140).abs() < 1e-4, "gray is {:?}", Color::GRAY);
+}
+
+#[test]
+fn srgb_and_linear_round_trip() {
+  for i in 0..=1000 {
+    let value = i as f32 / 1000.0;
+    let srgb = linear_to_srgb(srgb_to_linear(value));
+    assert!((srgb - value).abs() < 1e-5, "srgb {value} came back as {srgb}");
+    let linear = srgb_to_linear(linear_to_srgb(value));
+    assert!((linear - value).abs() < 1e-5, "linear {value} came back as {linear}");
+  }
+
+  // Every 8 bit channel comes back as it was, alpha included
+  for channel in 0..=255u8 {
+    let color = Color::from_srgb8(channel, 255 - channel, channel / 2, channel);
+    assert_eq!(color.to_srgb8(), [channel, 255 - channel, channel / 2, channel]);
+  }
+  // Alpha is never encoded
+  assert_eq!(Color::from_srgb(0.5, 0.5, 0.5, 0.5).a, 0.5);
+  assert_eq!(Color::rgb(0.2, 0.2, 0.2).with_alpha(0.25).to_srgb()[3], 0.25);
+}
+
+#[test]
+fn hsv_hues_land_on_the_primaries_and_secondaries() {
+  let hues = [
+    (0.0, Color::RED),
+    (60.0, Color::YELLOW),
+    (120.0, Color::GREEN),
+    (180.0, Color::CYAN),
+    (240.0, Color::BLUE),
+    (300.0, Color::MAGENTA),
+    // Hues wrap around both ways
+    (360.0, Color::RED),
+    (-120.0, Color::BLUE),
+    (480.0, Color::GREEN),
+  ];
+  for (hue, expected) in hues {
+    assert_close(Color::from_hsv(hue, 1.0, 1.0).to_array(), expected.to_array(), &format!("{hue}"));
+  }
+
+  // Between two sectors, in sRGB like a color picker
+  assert_close(Color::from_hsv(30.0, 1.0, 1.0).to_srgb(), [1.0, 0.5, 0.0, 1.0], "orange");
+  assert_close(Color::from_hsv(200.0, 0.5, 0.8).to_srgb(), [0.4, 0.6667, 0.8, 1.0], "muted blue");
+}
+
+#[test]
+fn hsv_saturation_and_value() {
+  // Without saturation the hue doesn't matter and value is the sRGB gray level
+  for hue in [0.0, 90.0, 250.0] {
+    assert_close(Color::from_hsv(hue, 0.0, 0.5).to_array(), Color::GRAY.to_array(), "gray");
+    assert_close(Color::from_hsv(hue, 0.0, 1.0).to_array(), Color::WHITE.to_array(), "white");
+    assert_close(Color::from_hsv(hue, 1.0, 0.0).to_array(), Color::BLACK.to_array(), "black");
+  }
+  // Saturation is clamped
+  assert_close(Color::from_hsv(0.0, 2.0, 1.0).to_array(), Color::RED.to_array(), "oversaturated");
+  assert_close(
+    Color::from_hsv(0.0, -1.0, 1.0).to_array(),
+    Color::WHITE.to_array(),
+    "undersaturated",
+  );
+  // Half saturated red at full value
+  assert_close(Color::from_hsv(0.0, 0.5, 1.0).to_srgb(), [1.0, 0.5, 0.5, 1.0], "pink");
+}
@@ -0,0 +1,125 @@
+use std::{
+  backtrace::Backtrace,
+  collections::{BTreeMap, VecDeque},
+  fmt::Write as _,
+  panic::PanicHookInfo,
+  path::{Path, PathBuf},
+  sync::{Mutex, OnceLock, Weak},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const CRASH_REPORT_DIR: &str = "./crash_reports";
+// Log lines kept for the report
+const LOG_LINES: usize = 64;
+
+// Anything the report should run on the way down, like letting the gpu finish. Held weakly so
+// registering doesn't keep what it cleans up alive
+pub trait CrashCleanup: Send + Sync {
+  fn on_crash(&self) -> Result<(), String>;
+}
+
+struct CrashState {
+  log: Mutex<VecDeque<String>>,
+  // Engine state by name, kept current by whoever owns it
+  fields: Mutex<BTreeMap<&'static str, String>>,
+  cleanups: Mutex<Vec<(&'static str, Weak<dyn CrashCleanup>)>>,
+}
+
+fn state() -> &'static CrashState {
+  static STATE: OnceLock<CrashState> = OnceLock::new();
+  STATE.get_or_init(|| CrashState {
+    log: Mutex::new(VecDeque::with_capacity(LOG_LINES)),
+    fields: Mutex::new(BTreeMap::new()),
+    cleanups: Mutex::new(vec![]),
+  })
+}
+
+// Prints to stderr like eprintln and keeps the line for the crash report
+#[macro_export]
+macro_rules! log {
+  ($($arg:tt)*) => {
+    $crate::log_line(format!($($arg)*))
+  };
+}
+
+pub fn log_line(line: String) {
+  eprintln!("{line}");
+  let Ok(mut log) = state().log.lock() else { return };
+  if log.len() == LOG_LINES {
+    log.pop_front();
+  }
+  log.push_back(line);
+}
+
+pub fn set_field(name: &'static str, value: impl ToString) {
+  let Ok(mut fields) = state().fields.lock() else { return };
+  fields.insert(name, value.to_string());
+}
+
+pub fn register_cleanup(name: &'static str, cleanup: Weak<dyn CrashCleanup>) {
+  let Ok(mut cleanups) = state().cleanups.lock() else { return };
+  cleanups.retain(|(_, cleanup)| cleanup.strong_count() > 0);
+  cleanups.push((name, cleanup));
+}
+
+// The panicking thread may hold any of the locks, so the report only takes what is free
+fn report(info: &PanicHookInfo, backtrace: &Backtrace) -> String {
+  let mut report = String::new();
+  let thread = std::thread::current();
+  let _ = writeln!(report, "panic on thread {}: {info}", thread.name().unwrap_or("unnamed"));
+  let _ = writeln!(report, "\nengine state:");
+  match state().fields.try_lock() {
+    Ok(fields) => {
+      for (name, value) in fields.iter() {
+        let _ = writeln!(report, "  {name}: {value}");
+      }
+    }
+    Err(_) => report.push_str("  unavailable\n"),
+  }
+  let _ = writeln!(report, "\nlast log lines:");
+  match state().log.try_lock() {
+    Ok(log) => {
+      for line in log.iter() {
+        let _ = writeln!(report, "  {line}");
+      }
+    }
+    Err(_) => report.push_str("  unavailable\n"),
+  }
+  let _ = writeln!(report, "\nbacktrace:\n{backtrace}");
+  report
+}
+
+fn write_report(dir: &Path, report: &str) -> Result<PathBuf, String> {
+  std::fs::create_dir_all(dir).map_err(|e| format!("at creating {}: {e}", dir.display()))?;
+  let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+  let path = dir.join(format!("crash_{timestamp}.txt"));
+  std::fs::write(&path, report).map_err(|e| format!("at writing {}: {e}", path.display()))?;
+  Ok(path)
+}
+
+fn run_cleanups() {
+  let Ok(cleanups) = state().cleanups.try_lock() else { return };
+  for (name, cleanup) in cleanups.iter() {
+    if let Some(cleanup) = cleanup.upgrade() {
+      let _ = cleanup.on_crash().inspect_err(|e| eprintln!("at {name} crash cleanup: {e}"));
+    }
+  }
+}
+
+// A panic on any thread writes a report to dir, runs the cleanups and aborts. Panics jobs
+// would catch take the process down too, none of them are expected
+pub fn install(dir: &Path) {
+  let dir = dir.to_path_buf();
+  std::panic::set_hook(Box::new(move |info| {
+    let report = report(info, &Backtrace::force_capture());
+    // Written before anything is printed, stderr may be gone
+    let written = write_report(&dir, &report);
+    eprintln!("{report}");
+    match written {
+      Ok(path) => eprintln!("crash report written to {}", path.display()),
+      Err(e) => eprintln!("at writing crash report: {e}"),
+    }
+    run_cleanups();
+    std::process::abort();
+  }));
+}
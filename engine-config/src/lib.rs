@@ -0,0 +1,1192 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+mod tests;
+
+pub const ENGINE_CONFIG_PATH: &str = "./engine.toml";
+// RESIDUE_CFG_<SECTION>_<KEY>, like RESIDUE_CFG_RENDERER_VSYNC=false. Its own prefix so other
+// RESIDUE_ vars, like the ones tests read, aren't taken for config keys
+pub const ENV_OVERRIDE_PREFIX: &str = "RESIDUE_CFG_";
+
+// Where the linear colors shaders output get encoded to sRGB for display
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorOutput {
+  // An sRGB swapchain format, the blit of the finished frame to it encodes
+  SrgbTarget,
+  // A UNORM swapchain format and a compute pass encoding the finished frame, for surfaces without
+  // sRGB formats
+  ShaderEncode,
+}
+
+// How the directional light gets shadowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShadowMode {
+  Off,
+  // A depth map rendered from the light
+  ShadowMap,
+  // Rays traced from the scene depth to the light. Needs the ray-tracing feature and a gpu
+  // supporting it, falls back to ShadowMap otherwise
+  RayTraced,
+}
+
+// How edges in the scene get smoothed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AntiAliasing {
+  // msaa_samples per pixel, none at 1
+  Msaa,
+  // Sub-pixel jittered frames blended over time. Renders with 1 sample, msaa_samples is ignored
+  Taa,
+}
+
+// Where the exposure the scene is tonemapped with comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExposureMode {
+  // Metered from the scene every frame and eased toward, like eyes adapting to the dark
+  Auto,
+  // exposure_ev as is
+  Manual,
+}
+
+// How user textures are compressed on the cpu before upload. Compressed textures take a quarter
+// to an eighth of the memory and bandwidth, at the cost of the time to compress them the first
+// time a texture is loaded. Textures are drawn uncompressed until theirs is done
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureCompression {
+  Off,
+  // BC1 for opaque textures and BC7 for ones with alpha, with the first endpoints found
+  Fast,
+  // BC7 for all of them, with endpoints refined to each block
+  Quality,
+}
+
+// Which of the camera's fovs stays put when the view's aspect ratio changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FovAxis {
+  // The camera's fov is vertical, wider views see more at the sides
+  Vertical,
+  // The camera's fov is vertical at reference_aspect and what that sees side to side is kept, so
+  // narrower views see more above and below and ultra-wide ones don't stretch
+  Horizontal,
+}
+
+// Bundles of the renderer settings trading looks for gpu time, see QualitySettings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityPreset {
+  Low,
+  Medium,
+  High,
+  // The settings as written in the renderer section
+  Custom,
+}
+
+impl QualityPreset {
+  pub const ALL: [Self; 4] = [Self::Low, Self::Medium, Self::High, Self::Custom];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Low => "low",
+      Self::Medium => "medium",
+      Self::High => "high",
+      Self::Custom => "custom",
+    }
+  }
+
+  pub fn parse(name: &str) -> Option<Self> {
+    Self::ALL.into_iter().find(|preset| preset.name() == name)
+  }
+
+  // None for Custom, whose settings come from the config
+  pub fn settings(self) -> Option<QualitySettings> {
+    let off = QualitySettings {
+      render_scale: 1.0,
+      anti_aliasing: AntiAliasing::Msaa,
+      msaa_samples: 1,
+      shadow_map_size: 2048,
+      motion_blur: false,
+      depth_of_field: false,
+      vignette: false,
+      chromatic_aberration: false,
+      film_grain: false,
+    };
+    match self {
+      Self::Low => Some(QualitySettings { render_scale: 0.75, shadow_map_size: 1024, ..off }),
+      Self::Medium => Some(QualitySettings { anti_aliasing: AntiAliasing::Taa, ..off }),
+      Self::High => Some(QualitySettings {
+        anti_aliasing: AntiAliasing::Taa,
+        shadow_map_size: 4096,
+        motion_blur: true,
+        depth_of_field: true,
+        vignette: true,
+        ..off
+      }),
+      Self::Custom => None,
+    }
+  }
+}
+
+// The part of the renderer settings quality presets set. Each can be switched while running, but
+// the sample count msaa renders with is fixed when the renderer starts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualitySettings {
+  pub render_scale: f32,
+  pub anti_aliasing: AntiAliasing,
+  pub msaa_samples: u32,
+  pub shadow_map_size: u32,
+  pub motion_blur: bool,
+  pub depth_of_field: bool,
+  pub vignette: bool,
+  pub chromatic_aberration: bool,
+  pub film_grain: bool,
+}
+
+// How the scene fits the window and where HUDs should stay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewConfig {
+  pub fov_axis: FovAxis,
+  pub reference_aspect: f32,
+  // Windows narrower than this get black bars above and below the scene, 0 for no limit
+  pub min_aspect: f32,
+  // Windows wider than this get black bars at the sides of the scene, 0 for no limit
+  pub max_aspect: f32,
+  // Share of the scene's width and height on each side HUDs are kept out of, for TVs cutting off
+  // their edges
+  pub safe_area_x: f32,
+  pub safe_area_y: f32,
+  // How much bigger than authored HUDs and text are drawn, 0 follows the window's dpi scale
+  pub ui_scale: f32,
+  // Vfs path of the font HUD text is drawn with, made into a distance field atlas at startup so it
+  // stays sharp at any size. Empty draws no text
+  pub ui_font: String,
+}
+
+impl Default for ViewConfig {
+  fn default() -> Self {
+    Self {
+      fov_axis: FovAxis::Vertical,
+      reference_aspect: 16.0 / 9.0,
+      min_aspect: 0.0,
+      max_aspect: 0.0,
+      safe_area_x: 0.0,
+      safe_area_y: 0.0,
+      ui_scale: 0.0,
+      ui_font: String::new(),
+    }
+  }
+}
+
+// Passes over the finished scene, before overlays are drawn over it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcessConfig {
+  // Blurs each pixel along how far it moved since the last frame, with the camera or its mesh.
+  // Needs a single sample per pixel, so taa or msaa_samples at 1
+  pub motion_blur: bool,
+  // Share of a frame's motion the blur covers, 1 blurs over all of it
+  pub motion_blur_intensity: f32,
+  pub exposure_mode: ExposureMode,
+  // In stops, each one up halves the brightness. At 0 the scene is shown as lit
+  pub exposure_ev: f32,
+  // Auto exposure stays between these, in stops like exposure_ev. 0 is for an average brightness
+  // of middle gray
+  pub min_exposure_ev: f32,
+  pub max_exposure_ev: f32,
+  // How fast auto exposure catches up with the scene, per second. At 1 it covers about two thirds
+  // of the way in a second
+  pub exposure_adaptation_rate: f32,
+  // Blurs what is nearer or farther than the focus distance like a camera lens would
+  pub depth_of_field: bool,
+  // In meters from the camera, what is at this distance stays sharp
+  pub dof_focus_distance: f32,
+  // F-number of the lens, lower ones blur more
+  pub dof_aperture: f32,
+  // Longer lenses blur more, 50 is about what the eye sees
+  pub dof_focal_length_mm: f32,
+  // Focuses on what is at the center of the screen instead of dof_focus_distance
+  pub dof_autofocus: bool,
+  // Darkens the frame towards its corners
+  pub vignette: bool,
+  // How dark the corners get, 1 is black
+  pub vignette_intensity: f32,
+  // Share of the way from the center to a corner the darkening starts at
+  pub vignette_radius: f32,
+  // Share of that way it takes past vignette_radius to darken fully
+  pub vignette_softness: f32,
+  // Splits red and blue apart towards the edges of the frame like a cheap lens
+  pub chromatic_aberration: bool,
+  // How far apart they are in the corners, in pixels at 1080p
+  pub chromatic_aberration_strength: f32,
+  // Noise over the frame like film has, a new pattern every frame
+  pub film_grain: bool,
+  // How much the noise moves a color in 0..1, most in the midtones
+  pub film_grain_intensity: f32,
+}
+
+impl Default for PostProcessConfig {
+  fn default() -> Self {
+    Self {
+      motion_blur: false,
+      motion_blur_intensity: 0.5,
+      exposure_mode: ExposureMode::Manual,
+      exposure_ev: 0.0,
+      min_exposure_ev: -4.0,
+      max_exposure_ev: 8.0,
+      exposure_adaptation_rate: 1.5,
+      depth_of_field: false,
+      dof_focus_distance: 10.0,
+      dof_aperture: 2.8,
+      dof_focal_length_mm: 50.0,
+      dof_autofocus: false,
+      vignette: false,
+      vignette_intensity: 0.4,
+      vignette_radius: 0.6,
+      vignette_softness: 0.5,
+      chromatic_aberration: false,
+      chromatic_aberration_strength: 3.0,
+      film_grain: false,
+      film_grain_intensity: 0.05,
+    }
+  }
+}
+
+// Most of each resource the renderer should ever have live at once. Going over is reported by
+// validation, to catch things piling up frame after frame. 0 leaves one unchecked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceBudgetConfig {
+  pub gpu_memory_mb: u32,
+  pub descriptor_sets: u32,
+  pub command_buffers: u32,
+  pub framebuffers: u32,
+}
+
+impl Default for ResourceBudgetConfig {
+  // Memory and descriptor sets grow with the scene, command buffers and framebuffers shouldn't
+  fn default() -> Self {
+    Self { gpu_memory_mb: 0, descriptor_sets: 0, command_buffers: 256, framebuffers: 256 }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RendererConfig {
+  pub vsync: bool,
+  // Clamped to what the gpu supports
+  pub msaa_samples: u32,
+  pub anti_aliasing: AntiAliasing,
+  // Scene resolution relative to the window
+  pub render_scale: f32,
+  // Frames recorded ahead of the gpu, more smooths out spikes and fewer lowers latency. Lowered to
+  // what the surface's swapchain images allow
+  pub frames_in_flight: u32,
+  pub validation: bool,
+  // Gpu to render on, counting from 1 in the order the vulkan driver lists them. 0 picks the
+  // first dedicated gpu, or the first gpu when there is none
+  pub gpu: u32,
+  // Falls back to the other mode when the surface has no matching format
+  pub color_output: ColorOutput,
+  // Culls meshes against the frustum and last frame's depth in a compute pass
+  pub gpu_culling: bool,
+  // Steps cloth in a compute pass instead of on the cpu
+  pub gpu_cloth: bool,
+  // Lets buffers made with SHADER_DEVICE_ADDRESS usage hand out their gpu address. Needs vulkan
+  // 1.2, left off with a warning when the gpu can't
+  pub buffer_device_address: bool,
+  pub shadow_mode: ShadowMode,
+  // Shadow maps covering the view out to shadow_distance, each finer than the one after it.
+  // Only used with shadow maps
+  pub shadow_cascades: u32,
+  // Side of each cascade's square shadow map
+  pub shadow_map_size: u32,
+  // How far from the camera shadow maps reach, past it the sun isn't shadowed
+  pub shadow_distance: f32,
+  // Side of the depth image shadows of spot and point lights are packed into, a power of 2.
+  // Local lights cast no shadows with shadow_mode off
+  pub shadow_atlas_size: u32,
+  // Spot and point lights whose range covers less of the screen than this, scaled by their
+  // brightness, are skipped. They fade out on the way there
+  pub light_cull_contribution: f32,
+  // Shadows of the most contributing lights are drawn, the rest are lit without one
+  pub max_shadowed_lights: u32,
+  // Mesh vertices and indices are sub allocated from gpu buffers of this many KiB. Meshes bigger
+  // than that get a buffer of their own
+  pub mesh_block_size_kb: u32,
+  // Frames the cpu may queue up ahead of the one being shown, waiting on presents where the gpu
+  // supports it and on frame fences otherwise. Lowers input latency without vsync, 0 leaves only
+  // frames_in_flight limiting it
+  pub max_frame_latency: u32,
+  // Gpu time per frame the quality governor keeps to, through the quality callbacks the game
+  // registers. 0 leaves quality alone
+  pub gpu_frame_budget_ms: f32,
+  // Textures of 1024 texels or more across get only their small mips uploaded at first, larger
+  // ones are streamed in as they cover more of the screen
+  pub texture_streaming: bool,
+  // MiB streamed textures may take up together, the largest ones lose mips when over it
+  pub texture_budget_mb: u32,
+  // Needs a gpu sampling BC formats, left off with a warning otherwise. Streamed textures are
+  // left uncompressed
+  pub texture_compression: TextureCompression,
+  pub resource_budget: ResourceBudgetConfig,
+  pub post_process: PostProcessConfig,
+  pub view: ViewConfig,
+  // Replaces the settings it bundles when the renderer starts, custom keeps them as written
+  pub quality: QualityPreset,
+}
+
+impl Default for RendererConfig {
+  fn default() -> Self {
+    Self {
+      vsync: true,
+      msaa_samples: 1,
+      anti_aliasing: AntiAliasing::Msaa,
+      render_scale: 1.0,
+      frames_in_flight: 3,
+      validation: cfg!(debug_assertions),
+      gpu: 0,
+      color_output: ColorOutput::SrgbTarget,
+      gpu_culling: true,
+      gpu_cloth: false,
+      buffer_device_address: false,
+      shadow_mode: ShadowMode::Off,
+      shadow_cascades: 1,
+      shadow_map_size: 2048,
+      shadow_distance: 60.0,
+      shadow_atlas_size: 2048,
+      light_cull_contribution: 0.005,
+      max_shadowed_lights: 8,
+      mesh_block_size_kb: 32768,
+      max_frame_latency: 0,
+      gpu_frame_budget_ms: 0.0,
+      texture_streaming: true,
+      texture_budget_mb: 512,
+      texture_compression: TextureCompression::Off,
+      resource_budget: ResourceBudgetConfig::default(),
+      post_process: PostProcessConfig::default(),
+      view: ViewConfig::default(),
+      quality: QualityPreset::Custom,
+    }
+  }
+}
+
+impl RendererConfig {
+  pub fn quality_settings(&self) -> QualitySettings {
+    QualitySettings {
+      render_scale: self.render_scale,
+      anti_aliasing: self.anti_aliasing,
+      msaa_samples: self.msaa_samples,
+      shadow_map_size: self.shadow_map_size,
+      motion_blur: self.post_process.motion_blur,
+      depth_of_field: self.post_process.depth_of_field,
+      vignette: self.post_process.vignette,
+      chromatic_aberration: self.post_process.chromatic_aberration,
+      film_grain: self.post_process.film_grain,
+    }
+  }
+
+  pub fn set_quality_settings(&mut self, settings: &QualitySettings) {
+    self.render_scale = settings.render_scale;
+    self.anti_aliasing = settings.anti_aliasing;
+    self.msaa_samples = settings.msaa_samples;
+    self.shadow_map_size = settings.shadow_map_size;
+    self.post_process.motion_blur = settings.motion_blur;
+    self.post_process.depth_of_field = settings.depth_of_field;
+    self.post_process.vignette = settings.vignette;
+    self.post_process.chromatic_aberration = settings.chromatic_aberration;
+    self.post_process.film_grain = settings.film_grain;
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicsConfig {
+  pub tick_hz: u32,
+  // Steps skipped past this are dropped so a long frame doesn't stall the next ones
+  pub max_steps_per_update: u32,
+  // Solves per step, contacts found at the start of the step are reused by all of them
+  pub substeps: u32,
+  // Validates bodies after every step and logs ones with NaNs, infinities or broken rotations
+  pub checked_math: bool,
+  // With checked_math, bad bodies are stopped where they were instead of left to spread the values
+  pub freeze_invalid_bodies: bool,
+}
+
+impl Default for PhysicsConfig {
+  fn default() -> Self {
+    Self {
+      tick_hz: 1000,
+      max_steps_per_update: 100,
+      substeps: 1,
+      checked_math: cfg!(debug_assertions),
+      freeze_invalid_bodies: true,
+    }
+  }
+}
+
+impl PhysicsConfig {
+  pub fn step_time_s(&self) -> f32 {
+    1.0 / self.tick_hz as f32
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulationConfig {
+  // Fixed game update rate, the renderer interpolates between ticks
+  pub tick_hz: u32,
+  // Ticks skipped past this are dropped so a stall doesn't turn into a burst of catch up ticks
+  pub max_ticks_per_update: u32,
+}
+
+impl Default for SimulationConfig {
+  fn default() -> Self {
+    Self { tick_hz: 60, max_ticks_per_update: 5 }
+  }
+}
+
+impl SimulationConfig {
+  pub fn tick_duration(&self) -> std::time::Duration {
+    std::time::Duration::from_secs_f64(1.0 / self.tick_hz as f64)
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+  // Borderless over the whole monitor, width and height are ignored then
+  pub fullscreen: bool,
+  // Size of the area drawn to, 0 for both leaves it to the os
+  pub width: u32,
+  pub height: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobsConfig {
+  // 0 picks one less than the core count
+  pub worker_threads: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetMount {
+  // Vfs path the files show up under, empty for the root
+  pub mount_point: String,
+  // A loose directory or an asset pack file
+  pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AssetsConfig {
+  // Mounted in order, later mounts win where they overlap
+  pub mounts: Vec<AssetMount>,
+  // Where decoded textures and imported meshes are kept between runs, empty turns caching off
+  pub cache_dir: String,
+}
+
+impl Default for AssetsConfig {
+  fn default() -> Self {
+    Self {
+      mounts: vec![AssetMount { mount_point: String::new(), path: ".".to_string() }],
+      cache_dir: "asset_cache".to_string(),
+    }
+  }
+}
+
+// UI and console text comes from string tables at <strings_dir>/<language>.toml in the assets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocalizationConfig {
+  pub language: String,
+  // Where every fallback chain ends, the language with every string
+  pub fallback_language: String,
+  pub strings_dir: String,
+}
+
+impl Default for LocalizationConfig {
+  fn default() -> Self {
+    Self {
+      language: "en".to_string(),
+      fallback_language: "en".to_string(),
+      strings_dir: "lang".to_string(),
+    }
+  }
+}
+
+// A profiler scope and how long a frame may spend in it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScopeBudget {
+  pub scope: String,
+  pub ms: f32,
+}
+
+// Frame time targets, 0 leaves one unchecked. Going over one logs a warning naming the slowest
+// scopes inside it, at most once every warning_interval_s for each
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BudgetConfig {
+  // The physics scope of a game tick
+  pub physics_ms: f32,
+  // The render thread's draw scope, less the waits for the gpu and display inside it
+  pub render_cpu_ms: f32,
+  // A frame on the gpu, only on gpus that can write timestamps
+  pub gpu_ms: f32,
+  // Any other profiler scopes by name
+  pub scopes: Vec<ScopeBudget>,
+  pub warning_interval_s: f32,
+}
+
+impl Default for BudgetConfig {
+  fn default() -> Self {
+    Self {
+      physics_ms: 0.0,
+      render_cpu_ms: 0.0,
+      gpu_ms: 0.0,
+      scopes: vec![],
+      warning_interval_s: 5.0,
+    }
+  }
+}
+
+impl BudgetConfig {
+  // Scope names and their budgets in ms, the gpu's under "gpu". Unchecked ones are left out
+  pub fn scope_budgets(&self) -> Vec<(String, f32)> {
+    [("physics", self.physics_ms), ("draw", self.render_cpu_ms), ("gpu", self.gpu_ms)]
+      .into_iter()
+      .map(|(scope, ms)| (scope.to_string(), ms))
+      .chain(self.scopes.iter().map(|budget| (budget.scope.clone(), budget.ms)))
+      .filter(|(_, ms)| *ms > 0.0)
+      .collect()
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+  pub window: WindowConfig,
+  pub renderer: RendererConfig,
+  pub physics: PhysicsConfig,
+  pub simulation: SimulationConfig,
+  pub jobs: JobsConfig,
+  pub assets: AssetsConfig,
+  pub localization: LocalizationConfig,
+  pub budgets: BudgetConfig,
+}
+
+// Keys present in the table that the default config doesn't have, as section.key
+fn unknown_keys(table: &toml::Table, known: &toml::Table, parent: &str) -> Vec<String> {
+  let mut unknown = vec![];
+  for (key, value) in table.iter() {
+    let full_key = if parent.is_empty() { key.clone() } else { format!("{parent}.{key}") };
+    match (value, known.get(key)) {
+      (_, None) => unknown.push(full_key),
+      (toml::Value::Table(table), Some(toml::Value::Table(known))) => {
+        unknown.extend(unknown_keys(table, known, &full_key))
+      }
+      _ => {}
+    }
+  }
+  unknown
+}
+
+fn parse_env_value(raw: &str) -> toml::Value {
+  toml::from_str::<toml::Table>(&format!("value = {raw}"))
+    .ok()
+    .and_then(|mut table| table.remove("value"))
+    .unwrap_or(toml::Value::String(raw.to_string()))
+}
+
+// For setting up a config in code instead of from a file, build validates like load does
+#[derive(Debug, Clone, Default)]
+pub struct EngineConfigBuilder {
+  config: EngineConfig,
+}
+
+impl EngineConfigBuilder {
+  pub fn fullscreen(mut self, fullscreen: bool) -> Self {
+    self.config.window.fullscreen = fullscreen;
+    self
+  }
+
+  pub fn window_size(mut self, width: u32, height: u32) -> Self {
+    self.config.window.width = width;
+    self.config.window.height = height;
+    self
+  }
+
+  pub fn vsync(mut self, vsync: bool) -> Self {
+    self.config.renderer.vsync = vsync;
+    self
+  }
+
+  pub fn msaa_samples(mut self, msaa_samples: u32) -> Self {
+    self.config.renderer.msaa_samples = msaa_samples;
+    self
+  }
+
+  pub fn anti_aliasing(mut self, anti_aliasing: AntiAliasing) -> Self {
+    self.config.renderer.anti_aliasing = anti_aliasing;
+    self
+  }
+
+  pub fn render_scale(mut self, render_scale: f32) -> Self {
+    self.config.renderer.render_scale = render_scale;
+    self
+  }
+
+  pub fn frames_in_flight(mut self, frames_in_flight: u32) -> Self {
+    self.config.renderer.frames_in_flight = frames_in_flight;
+    self
+  }
+
+  pub fn validation(mut self, validation: bool) -> Self {
+    self.config.renderer.validation = validation;
+    self
+  }
+
+  pub fn gpu(mut self, gpu: u32) -> Self {
+    self.config.renderer.gpu = gpu;
+    self
+  }
+
+  pub fn color_output(mut self, color_output: ColorOutput) -> Self {
+    self.config.renderer.color_output = color_output;
+    self
+  }
+
+  pub fn gpu_culling(mut self, gpu_culling: bool) -> Self {
+    self.config.renderer.gpu_culling = gpu_culling;
+    self
+  }
+
+  pub fn gpu_cloth(mut self, gpu_cloth: bool) -> Self {
+    self.config.renderer.gpu_cloth = gpu_cloth;
+    self
+  }
+
+  pub fn buffer_device_address(mut self, buffer_device_address: bool) -> Self {
+    self.config.renderer.buffer_device_address = buffer_device_address;
+    self
+  }
+
+  pub fn shadow_mode(mut self, shadow_mode: ShadowMode) -> Self {
+    self.config.renderer.shadow_mode = shadow_mode;
+    self
+  }
+
+  pub fn shadow_cascades(mut self, shadow_cascades: u32) -> Self {
+    self.config.renderer.shadow_cascades = shadow_cascades;
+    self
+  }
+
+  pub fn shadow_map_size(mut self, shadow_map_size: u32) -> Self {
+    self.config.renderer.shadow_map_size = shadow_map_size;
+    self
+  }
+
+  pub fn shadow_distance(mut self, shadow_distance: f32) -> Self {
+    self.config.renderer.shadow_distance = shadow_distance;
+    self
+  }
+
+  pub fn shadow_atlas_size(mut self, shadow_atlas_size: u32) -> Self {
+    self.config.renderer.shadow_atlas_size = shadow_atlas_size;
+    self
+  }
+
+  pub fn light_cull_contribution(mut self, light_cull_contribution: f32) -> Self {
+    self.config.renderer.light_cull_contribution = light_cull_contribution;
+    self
+  }
+
+  pub fn max_shadowed_lights(mut self, max_shadowed_lights: u32) -> Self {
+    self.config.renderer.max_shadowed_lights = max_shadowed_lights;
+    self
+  }
+
+  pub fn mesh_block_size_kb(mut self, mesh_block_size_kb: u32) -> Self {
+    self.config.renderer.mesh_block_size_kb = mesh_block_size_kb;
+    self
+  }
+
+  pub fn max_frame_latency(mut self, max_frame_latency: u32) -> Self {
+    self.config.renderer.max_frame_latency = max_frame_latency;
+    self
+  }
+
+  pub fn gpu_frame_budget_ms(mut self, gpu_frame_budget_ms: f32) -> Self {
+    self.config.renderer.gpu_frame_budget_ms = gpu_frame_budget_ms;
+    self
+  }
+
+  pub fn texture_streaming(mut self, texture_streaming: bool, texture_budget_mb: u32) -> Self {
+    self.config.renderer.texture_streaming = texture_streaming;
+    self.config.renderer.texture_budget_mb = texture_budget_mb;
+    self
+  }
+
+  pub fn texture_compression(mut self, texture_compression: TextureCompression) -> Self {
+    self.config.renderer.texture_compression = texture_compression;
+    self
+  }
+
+  pub fn quality(mut self, quality: QualityPreset) -> Self {
+    self.config.renderer.quality = quality;
+    self
+  }
+
+  pub fn motion_blur(mut self, motion_blur: bool) -> Self {
+    self.config.renderer.post_process.motion_blur = motion_blur;
+    self
+  }
+
+  pub fn motion_blur_intensity(mut self, motion_blur_intensity: f32) -> Self {
+    self.config.renderer.post_process.motion_blur_intensity = motion_blur_intensity;
+    self
+  }
+
+  pub fn exposure_mode(mut self, exposure_mode: ExposureMode) -> Self {
+    self.config.renderer.post_process.exposure_mode = exposure_mode;
+    self
+  }
+
+  pub fn exposure_ev(mut self, exposure_ev: f32) -> Self {
+    self.config.renderer.post_process.exposure_ev = exposure_ev;
+    self
+  }
+
+  pub fn exposure_ev_range(mut self, min_exposure_ev: f32, max_exposure_ev: f32) -> Self {
+    self.config.renderer.post_process.min_exposure_ev = min_exposure_ev;
+    self.config.renderer.post_process.max_exposure_ev = max_exposure_ev;
+    self
+  }
+
+  pub fn exposure_adaptation_rate(mut self, exposure_adaptation_rate: f32) -> Self {
+    self.config.renderer.post_process.exposure_adaptation_rate = exposure_adaptation_rate;
+    self
+  }
+
+  pub fn depth_of_field(
+    mut self,
+    focus_distance: f32,
+    aperture: f32,
+    focal_length_mm: f32,
+    autofocus: bool,
+  ) -> Self {
+    let post_process = &mut self.config.renderer.post_process;
+    post_process.depth_of_field = true;
+    post_process.dof_focus_distance = focus_distance;
+    post_process.dof_aperture = aperture;
+    post_process.dof_focal_length_mm = focal_length_mm;
+    post_process.dof_autofocus = autofocus;
+    self
+  }
+
+  pub fn vignette(mut self, intensity: f32, radius: f32, softness: f32) -> Self {
+    let post_process = &mut self.config.renderer.post_process;
+    post_process.vignette = true;
+    post_process.vignette_intensity = intensity;
+    post_process.vignette_radius = radius;
+    post_process.vignette_softness = softness;
+    self
+  }
+
+  pub fn chromatic_aberration(mut self, strength: f32) -> Self {
+    let post_process = &mut self.config.renderer.post_process;
+    post_process.chromatic_aberration = true;
+    post_process.chromatic_aberration_strength = strength;
+    self
+  }
+
+  pub fn film_grain(mut self, intensity: f32) -> Self {
+    let post_process = &mut self.config.renderer.post_process;
+    post_process.film_grain = true;
+    post_process.film_grain_intensity = intensity;
+    self
+  }
+
+  pub fn ui_scale(mut self, ui_scale: f32) -> Self {
+    self.config.renderer.view.ui_scale = ui_scale;
+    self
+  }
+
+  pub fn ui_font(mut self, path: &str) -> Self {
+    self.config.renderer.view.ui_font = path.to_string();
+    self
+  }
+
+  pub fn physics_tick_hz(mut self, tick_hz: u32) -> Self {
+    self.config.physics.tick_hz = tick_hz;
+    self
+  }
+
+  pub fn max_physics_steps_per_update(mut self, max_steps_per_update: u32) -> Self {
+    self.config.physics.max_steps_per_update = max_steps_per_update;
+    self
+  }
+
+  pub fn physics_substeps(mut self, substeps: u32) -> Self {
+    self.config.physics.substeps = substeps;
+    self
+  }
+
+  pub fn physics_checked_math(mut self, checked_math: bool, freeze_invalid_bodies: bool) -> Self {
+    self.config.physics.checked_math = checked_math;
+    self.config.physics.freeze_invalid_bodies = freeze_invalid_bodies;
+    self
+  }
+
+  pub fn simulation_tick_hz(mut self, tick_hz: u32) -> Self {
+    self.config.simulation.tick_hz = tick_hz;
+    self
+  }
+
+  pub fn max_simulation_ticks_per_update(mut self, max_ticks_per_update: u32) -> Self {
+    self.config.simulation.max_ticks_per_update = max_ticks_per_update;
+    self
+  }
+
+  pub fn worker_threads(mut self, worker_threads: usize) -> Self {
+    self.config.jobs.worker_threads = worker_threads;
+    self
+  }
+
+  pub fn asset_mounts(mut self, mounts: Vec<AssetMount>) -> Self {
+    self.config.assets.mounts = mounts;
+    self
+  }
+
+  pub fn asset_cache_dir(mut self, cache_dir: &str) -> Self {
+    self.config.assets.cache_dir = cache_dir.to_string();
+    self
+  }
+
+  pub fn language(mut self, language: &str) -> Self {
+    self.config.localization.language = language.to_string();
+    self
+  }
+
+  pub fn frame_budgets(mut self, physics_ms: f32, render_cpu_ms: f32, gpu_ms: f32) -> Self {
+    self.config.budgets.physics_ms = physics_ms;
+    self.config.budgets.render_cpu_ms = render_cpu_ms;
+    self.config.budgets.gpu_ms = gpu_ms;
+    self
+  }
+
+  pub fn scope_budget(mut self, scope: &str, ms: f32) -> Self {
+    self.config.budgets.scopes.push(ScopeBudget { scope: scope.to_string(), ms });
+    self
+  }
+
+  pub fn build(self) -> Result<EngineConfig, String> {
+    self.config.validate()?;
+    Ok(self.config)
+  }
+}
+
+impl EngineConfig {
+  pub fn builder() -> EngineConfigBuilder {
+    EngineConfigBuilder::default()
+  }
+
+  fn default_table() -> Result<toml::Table, String> {
+    toml::Table::try_from(Self::default())
+      .map_err(|e| format!("at serializing default config: {e}"))
+  }
+
+  fn from_table(table: toml::Table) -> Result<Self, String> {
+    let unknown = unknown_keys(&table, &Self::default_table()?, "");
+    if !unknown.is_empty() {
+      eprintln!("ignoring unknown engine config keys: {}", unknown.join(", "));
+    }
+    table.try_into().map_err(|e| format!("at parsing engine config: {e}"))
+  }
+
+  pub fn from_toml_str(config_str: &str) -> Result<Self, String> {
+    let table =
+      config_str.parse::<toml::Table>().map_err(|e| format!("at parsing engine config: {e}"))?;
+    Self::from_table(table)
+  }
+
+  // Missing files give the default config, env overrides and validation apply either way
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let mut config = if path.exists() {
+      let config_str = std::fs::read_to_string(path)
+        .map_err(|e| format!("at reading engine config {}: {e}", path.display()))?;
+      Self::from_toml_str(&config_str).map_err(|e| format!("at {}: {e}", path.display()))?
+    } else {
+      Self::default()
+    };
+    config.apply_overrides(std::env::vars())?;
+    config.validate()?;
+    Ok(config)
+  }
+
+  pub fn apply_overrides(
+    &mut self,
+    vars: impl IntoIterator<Item = (String, String)>,
+  ) -> Result<(), String> {
+    let mut table =
+      toml::Table::try_from(&*self).map_err(|e| format!("at serializing engine config: {e}"))?;
+    let mut overridden = false;
+    for (name, raw_value) in vars {
+      let Some(config_key) = name.strip_prefix(ENV_OVERRIDE_PREFIX) else { continue };
+      let config_key = config_key.to_lowercase();
+      let Some((section, key)) = config_key.split_once('_') else {
+        eprintln!("ignoring engine config override {name}: no key given");
+        continue;
+      };
+      let Some(toml::Value::Table(section_table)) = table.get_mut(section) else {
+        eprintln!("ignoring engine config override for unknown section {section}");
+        continue;
+      };
+      section_table.insert(key.to_string(), parse_env_value(&raw_value));
+      overridden = true;
+    }
+    if overridden {
+      *self = Self::from_table(table).map_err(|e| format!("at applying env overrides: {e}"))?;
+    }
+    Ok(())
+  }
+
+  pub fn validate(&self) -> Result<(), String> {
+    let mut invalid = vec![];
+    let window = &self.window;
+    if (window.width == 0) != (window.height == 0) {
+      invalid.push(format!(
+        "window.width and window.height must both be 0 or both be set, got {}x{}",
+        window.width, window.height
+      ));
+    } else if window.width > 16384 || window.height > 16384 {
+      invalid.push(format!(
+        "window.width and window.height must be at most 16384, got {}x{}",
+        window.width, window.height
+      ));
+    }
+    if ![1, 2, 4, 8, 16, 32, 64].contains(&self.renderer.msaa_samples) {
+      invalid.push(format!(
+        "renderer.msaa_samples must be a power of 2 up to 64, got {}",
+        self.renderer.msaa_samples
+      ));
+    }
+    if !(0.25..=2.0).contains(&self.renderer.render_scale) {
+      invalid.push(format!(
+        "renderer.render_scale must be between 0.25 and 2.0, got {}",
+        self.renderer.render_scale
+      ));
+    }
+    if !(2..=4).contains(&self.renderer.frames_in_flight) {
+      invalid.push(format!(
+        "renderer.frames_in_flight must be between 2 and 4, got {}",
+        self.renderer.frames_in_flight
+      ));
+    }
+    if !(1..=4).contains(&self.renderer.shadow_cascades) {
+      invalid.push(format!(
+        "renderer.shadow_cascades must be between 1 and 4, got {}",
+        self.renderer.shadow_cascades
+      ));
+    }
+    if !(256..=4096).contains(&self.renderer.shadow_map_size) {
+      invalid.push(format!(
+        "renderer.shadow_map_size must be between 256 and 4096, got {}",
+        self.renderer.shadow_map_size
+      ));
+    }
+    if !(1.0..=10000.0).contains(&self.renderer.shadow_distance) {
+      invalid.push(format!(
+        "renderer.shadow_distance must be between 1 and 10000, got {}",
+        self.renderer.shadow_distance
+      ));
+    }
+    if !self.renderer.shadow_atlas_size.is_power_of_two()
+      || !(512..=8192).contains(&self.renderer.shadow_atlas_size)
+    {
+      invalid.push(format!(
+        "renderer.shadow_atlas_size must be a power of 2 between 512 and 8192, got {}",
+        self.renderer.shadow_atlas_size
+      ));
+    }
+    if !(0.0..=1.0).contains(&self.renderer.light_cull_contribution) {
+      invalid.push(format!(
+        "renderer.light_cull_contribution must be between 0 and 1, got {}",
+        self.renderer.light_cull_contribution
+      ));
+    }
+    if self.renderer.max_shadowed_lights > 64 {
+      invalid.push(format!(
+        "renderer.max_shadowed_lights must be at most 64, got {}",
+        self.renderer.max_shadowed_lights
+      ));
+    }
+    if !(64..=1048576).contains(&self.renderer.mesh_block_size_kb) {
+      invalid.push(format!(
+        "renderer.mesh_block_size_kb must be between 64 and 1048576, got {}",
+        self.renderer.mesh_block_size_kb
+      ));
+    }
+    if !(16..=65536).contains(&self.renderer.texture_budget_mb) {
+      invalid.push(format!(
+        "renderer.texture_budget_mb must be between 16 and 65536, got {}",
+        self.renderer.texture_budget_mb
+      ));
+    }
+    if self.renderer.max_frame_latency > 4 {
+      invalid.push(format!(
+        "renderer.max_frame_latency must be at most 4, got {}",
+        self.renderer.max_frame_latency
+      ));
+    }
+    if self.renderer.gpu_frame_budget_ms != 0.0
+      && !(1.0..=1000.0).contains(&self.renderer.gpu_frame_budget_ms)
+    {
+      invalid.push(format!(
+        "renderer.gpu_frame_budget_ms must be 0 or between 1 and 1000, got {}",
+        self.renderer.gpu_frame_budget_ms
+      ));
+    }
+    let ui_scale = self.renderer.view.ui_scale;
+    if ui_scale != 0.0 && !(0.5..=4.0).contains(&ui_scale) {
+      invalid
+        .push(format!("renderer.view.ui_scale must be 0 or between 0.5 and 4, got {ui_scale}"));
+    }
+    if !(0.0..=4.0).contains(&self.renderer.post_process.motion_blur_intensity) {
+      invalid.push(format!(
+        "renderer.post_process.motion_blur_intensity must be between 0 and 4, got {}",
+        self.renderer.post_process.motion_blur_intensity
+      ));
+    }
+    let post_process = &self.renderer.post_process;
+    for (key, ev) in [
+      ("exposure_ev", post_process.exposure_ev),
+      ("min_exposure_ev", post_process.min_exposure_ev),
+      ("max_exposure_ev", post_process.max_exposure_ev),
+    ] {
+      if !(-16.0..=16.0).contains(&ev) {
+        invalid.push(format!("renderer.post_process.{key} must be between -16 and 16, got {ev}"));
+      }
+    }
+    if post_process.min_exposure_ev > post_process.max_exposure_ev {
+      invalid.push(format!(
+        "renderer.post_process.min_exposure_ev {} is over max_exposure_ev {}",
+        post_process.min_exposure_ev, post_process.max_exposure_ev
+      ));
+    }
+    if post_process.exposure_adaptation_rate <= 0.0 {
+      invalid.push(format!(
+        "renderer.post_process.exposure_adaptation_rate must be over 0, got {}",
+        post_process.exposure_adaptation_rate
+      ));
+    }
+    if !(0.1..=10000.0).contains(&post_process.dof_focus_distance) {
+      invalid.push(format!(
+        "renderer.post_process.dof_focus_distance must be between 0.1 and 10000, got {}",
+        post_process.dof_focus_distance
+      ));
+    }
+    if !(0.5..=64.0).contains(&post_process.dof_aperture) {
+      invalid.push(format!(
+        "renderer.post_process.dof_aperture must be between 0.5 and 64, got {}",
+        post_process.dof_aperture
+      ));
+    }
+    if !(8.0..=1200.0).contains(&post_process.dof_focal_length_mm) {
+      invalid.push(format!(
+        "renderer.post_process.dof_focal_length_mm must be between 8 and 1200, got {}",
+        post_process.dof_focal_length_mm
+      ));
+    }
+    for (key, value) in [
+      ("vignette_intensity", post_process.vignette_intensity),
+      ("vignette_radius", post_process.vignette_radius),
+      ("film_grain_intensity", post_process.film_grain_intensity),
+    ] {
+      if !(0.0..=1.0).contains(&value) {
+        invalid.push(format!("renderer.post_process.{key} must be between 0 and 1, got {value}"));
+      }
+    }
+    if !(0.01..=2.0).contains(&post_process.vignette_softness) {
+      invalid.push(format!(
+        "renderer.post_process.vignette_softness must be between 0.01 and 2, got {}",
+        post_process.vignette_softness
+      ));
+    }
+    if !(0.0..=32.0).contains(&post_process.chromatic_aberration_strength) {
+      invalid.push(format!(
+        "renderer.post_process.chromatic_aberration_strength must be between 0 and 32, got {}",
+        post_process.chromatic_aberration_strength
+      ));
+    }
+    if !(10..=10000).contains(&self.physics.tick_hz) {
+      invalid.push(format!(
+        "physics.tick_hz must be between 10 and 10000, got {}",
+        self.physics.tick_hz
+      ));
+    }
+    if self.physics.max_steps_per_update == 0 {
+      invalid.push("physics.max_steps_per_update must be at least 1".to_string());
+    }
+    if !(1..=16).contains(&self.physics.substeps) {
+      invalid
+        .push(format!("physics.substeps must be between 1 and 16, got {}", self.physics.substeps));
+    }
+    if !(1..=1000).contains(&self.simulation.tick_hz) {
+      invalid.push(format!(
+        "simulation.tick_hz must be between 1 and 1000, got {}",
+        self.simulation.tick_hz
+      ));
+    }
+    if self.simulation.max_ticks_per_update == 0 {
+      invalid.push("simulation.max_ticks_per_update must be at least 1".to_string());
+    }
+    for mount in self.assets.mounts.iter().filter(|mount| mount.path.is_empty()) {
+      invalid.push(format!("assets.mounts at \"{}\" has an empty path", mount.mount_point));
+    }
+    let localization = &self.localization;
+    for (key, value) in
+      [("language", &localization.language), ("fallback_language", &localization.fallback_language)]
+    {
+      if value.is_empty() || value.contains(['/', '\\', '.']) {
+        invalid
+          .push(format!("localization.{key} must be a language code like en, got \"{value}\""));
+      }
+    }
+    let budgets = &self.budgets;
+    for (key, ms) in [
+      ("physics_ms", budgets.physics_ms),
+      ("render_cpu_ms", budgets.render_cpu_ms),
+      ("gpu_ms", budgets.gpu_ms),
+    ] {
+      if !(0.0..=1000.0).contains(&ms) {
+        invalid.push(format!("budgets.{key} must be between 0 and 1000, got {ms}"));
+      }
+    }
+    for budget in budgets.scopes.iter() {
+      if budget.scope.is_empty() || !(0.0..=1000.0).contains(&budget.ms) {
+        invalid.push(format!(
+          "budgets.scopes needs a scope name and ms between 0 and 1000, got \"{}\" at {}",
+          budget.scope, budget.ms
+        ));
+      }
+    }
+    if budgets.warning_interval_s <= 0.0 {
+      invalid.push(format!(
+        "budgets.warning_interval_s must be over 0, got {}",
+        budgets.warning_interval_s
+      ));
+    }
+    if invalid.is_empty() {
+      Ok(())
+    } else {
+      Err(format!("invalid engine config: {}", invalid.join("; ")))
+    }
+  }
+}
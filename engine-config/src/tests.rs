@@ -0,0 +1,144 @@
+use crate::{
+  parse_env_value, unknown_keys, AntiAliasing, EngineConfig, QualityPreset, ScopeBudget,
+  ENV_OVERRIDE_PREFIX,
+};
+
+fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+  pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+}
+
+#[test]
+fn env_values_parse_as_toml_and_fall_back_to_strings() {
+  assert_eq!(parse_env_value("false"), toml::Value::Boolean(false));
+  assert_eq!(parse_env_value("4"), toml::Value::Integer(4));
+  assert_eq!(parse_env_value("0.5"), toml::Value::Float(0.5));
+  assert_eq!(parse_env_value("\"taa\""), toml::Value::String("taa".to_string()));
+  assert_eq!(parse_env_value("taa"), toml::Value::String("taa".to_string()));
+  assert_eq!(parse_env_value("not toml = 1"), toml::Value::String("not toml = 1".to_string()));
+}
+
+#[test]
+fn unknown_keys_are_named_with_their_section() {
+  let known = toml::Table::try_from(EngineConfig::default()).unwrap();
+  let table = "nonsense = 1\n[renderer]\nvsync = true\ntypo = 2\n[renderer.view]\nfov = 3\n"
+    .parse::<toml::Table>()
+    .unwrap();
+  let mut unknown = unknown_keys(&table, &known, "");
+  unknown.sort();
+  assert_eq!(unknown, ["nonsense", "renderer.typo", "renderer.view.fov"]);
+  assert!(unknown_keys(&known, &known, "").is_empty());
+}
+
+#[test]
+fn files_parse_over_the_defaults() {
+  let config = EngineConfig::from_toml_str(
+    "[renderer]\nvsync = false\nanti_aliasing = \"taa\"\n[physics]\ntick_hz = 120\n",
+  )
+  .unwrap();
+  assert!(!config.renderer.vsync);
+  assert_eq!(config.renderer.anti_aliasing, AntiAliasing::Taa);
+  assert_eq!(config.physics.tick_hz, 120);
+  assert_eq!(config.simulation, EngineConfig::default().simulation);
+  assert!(EngineConfig::from_toml_str("[renderer]\nvsync = 3\n").is_err());
+  assert!(EngineConfig::from_toml_str("[renderer\n").is_err());
+}
+
+#[test]
+fn overrides_set_keys_under_the_prefix() {
+  let mut config = EngineConfig::default();
+  config
+    .apply_overrides(vars(&[
+      ("RESIDUE_CFG_RENDERER_VSYNC", "false"),
+      ("RESIDUE_CFG_RENDERER_MSAA_SAMPLES", "4"),
+      ("RESIDUE_CFG_LOCALIZATION_LANGUAGE", "de"),
+      ("PATH", "/usr/bin"),
+    ]))
+    .unwrap();
+  assert!(!config.renderer.vsync);
+  assert_eq!(config.renderer.msaa_samples, 4);
+  assert_eq!(config.localization.language, "de");
+}
+
+#[test]
+fn other_residue_vars_are_not_overrides() {
+  assert_eq!(ENV_OVERRIDE_PREFIX, "RESIDUE_CFG_");
+  let mut config = EngineConfig::default();
+  config
+    .apply_overrides(vars(&[
+      ("RESIDUE_FUZZ_SEED", "12"),
+      ("RESIDUE_UPDATE_GOLDENS", "1"),
+      ("RESIDUE_VISUAL_DIFF_DIR", "/tmp/diffs"),
+    ]))
+    .unwrap();
+  assert_eq!(config, EngineConfig::default());
+}
+
+#[test]
+fn overrides_skip_unknown_sections_and_missing_keys() {
+  let mut config = EngineConfig::default();
+  config
+    .apply_overrides(vars(&[("RESIDUE_CFG_NOWHERE_VSYNC", "false"), ("RESIDUE_CFG_RENDERER", "1")]))
+    .unwrap();
+  assert_eq!(config, EngineConfig::default());
+}
+
+#[test]
+fn overrides_of_the_wrong_type_fail() {
+  let mut config = EngineConfig::default();
+  let error = config.apply_overrides(vars(&[("RESIDUE_CFG_RENDERER_VSYNC", "maybe")])).unwrap_err();
+  assert!(error.contains("env overrides"), "{error}");
+  assert_eq!(config, EngineConfig::default());
+}
+
+#[test]
+fn defaults_are_valid() {
+  assert_eq!(EngineConfig::default().validate(), Ok(()));
+  assert!(EngineConfig::builder().build().is_ok());
+}
+
+#[test]
+fn validation_names_every_bad_key() {
+  let mut config = EngineConfig::default();
+  config.window.width = 640;
+  config.renderer.msaa_samples = 3;
+  config.renderer.frames_in_flight = 9;
+  config.physics.substeps = 0;
+  config.localization.language = "../en".to_string();
+  let error = config.validate().unwrap_err();
+  for key in [
+    "window.width",
+    "renderer.msaa_samples",
+    "renderer.frames_in_flight",
+    "physics.substeps",
+    "localization.language",
+  ] {
+    assert!(error.contains(key), "{key} missing from {error}");
+  }
+}
+
+#[test]
+fn validation_checks_ranges_between_keys() {
+  let mut config = EngineConfig::default();
+  config.renderer.post_process.min_exposure_ev = 4.0;
+  config.renderer.post_process.max_exposure_ev = -4.0;
+  assert!(config.validate().unwrap_err().contains("min_exposure_ev 4 is over max_exposure_ev -4"));
+  let mut config = EngineConfig::default();
+  config.budgets.scopes.push(ScopeBudget { scope: String::new(), ms: 1.0 });
+  assert!(config.validate().unwrap_err().contains("budgets.scopes"));
+}
+
+#[test]
+fn builder_validates_like_load() {
+  assert!(EngineConfig::builder().msaa_samples(4).frames_in_flight(3).build().is_ok());
+  let error = EngineConfig::builder().render_scale(8.0).build().unwrap_err();
+  assert!(error.contains("renderer.render_scale"), "{error}");
+}
+
+#[test]
+fn quality_presets_round_trip_by_name() {
+  for preset in QualityPreset::ALL {
+    assert_eq!(QualityPreset::parse(preset.name()), Some(preset));
+  }
+  assert_eq!(QualityPreset::parse("ultra"), None);
+  assert!(QualityPreset::Custom.settings().is_none());
+}
@@ -0,0 +1,97 @@
+#[cfg(feature = "window")]
+pub use winit::keyboard::Key;
+
+use crate::Event;
+
+// Size of the window's drawable area in pixels, 0 when minimized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowResized {
+  pub width: u32,
+  pub height: u32,
+}
+
+impl Event for WindowResized {}
+
+// Physical pixels per logical pixel of the window, over 1 on high dpi screens. Published when the
+// window opens and whenever it moves to a screen with another scale
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowScaleChanged {
+  pub scale_factor: f64,
+}
+
+impl Event for WindowScaleChanged {}
+
+// Published by the renderer when the window's size changes what it draws to. The scene is drawn
+// in viewport with black bars around it, HUDs should keep to safe_area inside it. Both are x, y,
+// width and height in pixels from the window's top left. ui_scale is how much bigger than
+// authored UI is drawn, from the config or the window's scale factor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewResized {
+  pub viewport: [u32; 4],
+  pub safe_area: [u32; 4],
+  pub ui_scale: f32,
+}
+
+impl Event for ViewResized {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusLost;
+
+impl Event for FocusLost {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusGained;
+
+impl Event for FocusGained {}
+
+#[cfg(feature = "window")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyActionState {
+  Pressed,
+  Released,
+}
+
+// Published for every key event, including the ones a text input used up
+#[cfg(feature = "window")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyAction {
+  // Without modifiers applied, same as the key states in the input aggregator
+  pub key: Key,
+  pub state: KeyActionState,
+  // Held keys repeating
+  pub repeat: bool,
+}
+
+#[cfg(feature = "window")]
+impl Event for KeyAction {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GamepadConnected {
+  pub id: usize,
+  pub name: String,
+}
+
+impl Event for GamepadConnected {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GamepadDisconnected {
+  pub id: usize,
+}
+
+impl Event for GamepadDisconnected {}
+
+// A file from outside dragged onto the window and let go of, path is where it is on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDropped {
+  pub path: std::path::PathBuf,
+}
+
+impl Event for FileDropped {}
+
+// An asset changed and was read again, path is the vfs path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetReloaded {
+  pub path: String,
+}
+
+impl Event for AssetReloaded {}
@@ -0,0 +1,85 @@
+use std::{
+  any::{Any, TypeId},
+  collections::HashMap,
+  sync::{
+    mpsc::{self, Receiver, Sender},
+    Mutex, OnceLock,
+  },
+};
+
+mod events;
+#[cfg(test)]
+mod tests;
+
+pub use events::*;
+
+static GLOBAL_BUS: OnceLock<EventBus> = OnceLock::new();
+
+// Anything published on the bus, every subscriber gets its own clone
+pub trait Event: Clone + Send + 'static {}
+
+// Events queue up until the subscriber drains them, so a subscriber on another thread handles
+// them on its own schedule. Dropping the subscription unsubscribes
+pub struct Subscription<E: Event> {
+  receiver: Receiver<E>,
+}
+
+impl<E: Event> Subscription<E> {
+  pub fn try_recv(&self) -> Option<E> {
+    self.receiver.try_recv().ok()
+  }
+
+  // Everything published since the last drain, oldest first
+  pub fn drain(&self) -> impl Iterator<Item = E> + '_ {
+    self.receiver.try_iter()
+  }
+
+  // Only the newest pending event, for events where older ones are stale like resizes
+  pub fn latest(&self) -> Option<E> {
+    self.receiver.try_iter().last()
+  }
+}
+
+#[derive(Default)]
+pub struct EventBus {
+  // Senders of every subscription, keyed by the event type they carry
+  senders: Mutex<HashMap<TypeId, Vec<Box<dyn Any + Send>>>>,
+}
+
+impl EventBus {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn subscribe<E: Event>(&self) -> Subscription<E> {
+    let (sender, receiver) = mpsc::channel::<E>();
+    match self.senders.lock() {
+      Ok(mut senders) => senders.entry(TypeId::of::<E>()).or_default().push(Box::new(sender)),
+      Err(e) => eprintln!("at getting event bus lock: {e}"),
+    }
+    Subscription { receiver }
+  }
+
+  // Returns how many subscribers got the event, dropped subscriptions are cleaned up here
+  pub fn publish<E: Event>(&self, event: E) -> usize {
+    let mut senders = match self.senders.lock() {
+      Ok(senders) => senders,
+      Err(e) => {
+        eprintln!("at getting event bus lock: {e}");
+        return 0;
+      }
+    };
+    let Some(type_senders) = senders.get_mut(&TypeId::of::<E>()) else { return 0 };
+    type_senders.retain(|sender| {
+      sender
+        .downcast_ref::<Sender<E>>()
+        .is_some_and(|sender| sender.send(event.clone()).is_ok())
+    });
+    type_senders.len()
+  }
+}
+
+// Bus shared by the platform layer and engine subsystems
+pub fn global() -> &'static EventBus {
+  GLOBAL_BUS.get_or_init(EventBus::new)
+}
@@ -0,0 +1,107 @@
+use std::thread;
+
+use crate::{EventBus, FocusLost, GamepadConnected, GamepadDisconnected, WindowResized};
+#[cfg(feature = "window")]
+use crate::{Key, KeyAction, KeyActionState};
+
+fn resized(width: u32) -> WindowResized {
+  WindowResized { width, height: 100 }
+}
+
+#[test]
+fn publishing_without_subscribers_reaches_nobody() {
+  assert_eq!(EventBus::new().publish(FocusLost), 0);
+}
+
+#[test]
+fn every_subscriber_gets_its_own_copy() {
+  let bus = EventBus::new();
+  let first = bus.subscribe::<WindowResized>();
+  let second = bus.subscribe::<WindowResized>();
+  assert_eq!(bus.publish(resized(1)), 2);
+  assert_eq!(first.try_recv(), Some(resized(1)));
+  assert_eq!(second.try_recv(), Some(resized(1)));
+  assert_eq!(first.try_recv(), None);
+}
+
+#[test]
+fn events_only_go_to_subscribers_of_their_type() {
+  let bus = EventBus::new();
+  let resizes = bus.subscribe::<WindowResized>();
+  let focus = bus.subscribe::<FocusLost>();
+  assert_eq!(bus.publish(FocusLost), 1);
+  assert_eq!(resizes.try_recv(), None);
+  assert_eq!(focus.try_recv(), Some(FocusLost));
+}
+
+#[test]
+fn drain_is_oldest_first_and_latest_skips_stale_events() {
+  let bus = EventBus::new();
+  let subscription = bus.subscribe::<WindowResized>();
+  for width in 1..=3 {
+    bus.publish(resized(width));
+  }
+  assert_eq!(subscription.drain().collect::<Vec<_>>(), [resized(1), resized(2), resized(3)]);
+  for width in 4..=6 {
+    bus.publish(resized(width));
+  }
+  assert_eq!(subscription.latest(), Some(resized(6)));
+  assert_eq!(subscription.latest(), None);
+}
+
+#[test]
+fn dropped_subscriptions_are_cleaned_up_on_publish() {
+  let bus = EventBus::new();
+  let kept = bus.subscribe::<FocusLost>();
+  drop(bus.subscribe::<FocusLost>());
+  assert_eq!(bus.publish(FocusLost), 1);
+  drop(kept);
+  assert_eq!(bus.publish(FocusLost), 0);
+}
+
+#[test]
+fn events_published_on_other_threads_queue_for_the_subscriber() {
+  let bus = EventBus::new();
+  let subscription = bus.subscribe::<WindowResized>();
+  thread::scope(|s| {
+    for width in 0..4 {
+      let bus = &bus;
+      s.spawn(move || bus.publish(resized(width)));
+    }
+  });
+  let mut widths = subscription.drain().map(|event| event.width).collect::<Vec<_>>();
+  widths.sort();
+  assert_eq!(widths, [0, 1, 2, 3]);
+}
+
+#[test]
+#[cfg(feature = "window")]
+fn key_actions_keep_their_key_state_and_repeats() {
+  let bus = EventBus::new();
+  let keys = bus.subscribe::<KeyAction>();
+  let action = |state, repeat| KeyAction { key: Key::Character("w".into()), state, repeat };
+  bus.publish(action(KeyActionState::Pressed, false));
+  bus.publish(action(KeyActionState::Pressed, true));
+  bus.publish(action(KeyActionState::Released, false));
+  assert_eq!(
+    keys.drain().map(|x| (x.state, x.repeat)).collect::<Vec<_>>(),
+    [
+      (KeyActionState::Pressed, false),
+      (KeyActionState::Pressed, true),
+      (KeyActionState::Released, false)
+    ]
+  );
+}
+
+#[test]
+fn gamepad_connections_and_disconnections_are_separate_events() {
+  let bus = EventBus::new();
+  let connected = bus.subscribe::<GamepadConnected>();
+  let disconnected = bus.subscribe::<GamepadDisconnected>();
+  let pad = GamepadConnected { id: 2, name: "Xbox Controller".to_string() };
+  assert_eq!(bus.publish(pad.clone()), 1);
+  assert_eq!(bus.publish(GamepadDisconnected { id: 2 }), 1);
+  assert_eq!(connected.try_recv(), Some(pad));
+  assert_eq!(connected.try_recv(), None);
+  assert_eq!(disconnected.try_recv(), Some(GamepadDisconnected { id: 2 }));
+}
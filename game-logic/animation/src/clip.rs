@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{skeleton::{Skeleton, Transform}, KeyFramed};
+
+// Tracks left as None keep the rest pose value of the joint
+#[derive(Serialize, Deserialize)]
+pub struct JointTrack {
+  pub joint: usize,
+  pub translation: Option<KeyFramed<glam::Vec3>>,
+  pub rotation: Option<KeyFramed<glam::Quat>>,
+  pub scale: Option<KeyFramed<glam::Vec3>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AnimationClip {
+  pub name: String,
+  pub tracks: Vec<JointTrack>,
+}
+
+impl AnimationClip {
+  pub fn duration_ms(&self) -> u128 {
+    self
+      .tracks
+      .iter()
+      .flat_map(|track| {
+        [
+          track.translation.as_ref().and_then(|kf| kf.key_frames.last()).map(|kf| kf.time_ms),
+          track.rotation.as_ref().and_then(|kf| kf.key_frames.last()).map(|kf| kf.time_ms),
+          track.scale.as_ref().and_then(|kf| kf.key_frames.last()).map(|kf| kf.time_ms),
+        ]
+      })
+      .flatten()
+      .max()
+      .unwrap_or(0)
+  }
+
+  // Local joint transforms at a time, pass the result to Skeleton::skinning_matrices
+  pub fn sample(&self, skeleton: &Skeleton, time_ms: u128) -> Vec<Transform> {
+    let mut pose = skeleton.rest_pose();
+    for track in self.tracks.iter() {
+      let Some(joint_pose) = pose.get_mut(track.joint) else { continue };
+      if let Some(translation) = &track.translation {
+        joint_pose.translation = translation.value_at(time_ms);
+      }
+      if let Some(rotation) = &track.rotation {
+        // Blending quaternions component wise needs a normalize to stay a rotation
+        joint_pose.rotation = rotation.value_at(time_ms).normalize();
+      }
+      if let Some(scale) = &track.scale {
+        joint_pose.scale = scale.value_at(time_ms);
+      }
+    }
+    pose
+  }
+
+  // Skinning matrices of every joint sampled at a fixed rate over one loop of the clip, frame after
+  // frame. For playing the clip back on the GPU
+  pub fn bake(&self, skeleton: &Skeleton, frame_rate: f32) -> Vec<glam::Mat4> {
+    let frame_count = (self.duration_ms() as f32 * frame_rate / 1000.0).round().max(1.0) as usize;
+    (0..frame_count)
+      .flat_map(|frame| {
+        let time_ms = (frame as f32 * 1000.0 / frame_rate) as u128;
+        skeleton.skinning_matrices(&self.sample(skeleton, time_ms))
+      })
+      .collect()
+  }
+}
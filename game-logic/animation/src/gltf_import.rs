@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+
+use asset_cache::{AssetCache, ContentHasher};
+use serde::{Deserialize, Serialize};
+use vfs::Vfs;
+
+use crate::{
+  clip::{AnimationClip, JointTrack},
+  skeleton::{Joint, Skeleton, SkinnedMesh, SkinnedVertex, Transform},
+  Interpolation, KeyFrame, KeyFramed,
+};
+
+// Imported characters in the asset cache, bumped whenever importing changes what comes out
+const GLTF_CACHE_KIND: &str = "gltf_characters";
+const GLTF_CACHE_VERSION: u32 = 1;
+
+// Imported models without skins, for drawing as they are
+const GLTF_MODEL_CACHE_KIND: &str = "gltf_models";
+const GLTF_MODEL_CACHE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StaticVertex {
+  pub pos: glam::Vec3,
+  pub normal: glam::Vec3,
+  pub uv: glam::Vec2,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StaticModel {
+  pub vertices: Vec<StaticVertex>,
+  pub triangles: Vec<[u32; 3]>,
+}
+
+impl StaticModel {
+  // Box around every vertex, None without any
+  pub fn bounds(&self) -> Option<(glam::Vec3, glam::Vec3)> {
+    let first = self.vertices.first()?.pos;
+    Some(
+      self
+        .vertices
+        .iter()
+        .fold((first, first), |(min, max), vertex| (min.min(vertex.pos), max.max(vertex.pos))),
+    )
+  }
+}
+
+// One glTF skin with the meshes it deforms and every animation that moves its joints
+#[derive(Serialize, Deserialize)]
+pub struct AnimatedCharacter {
+  pub name: String,
+  pub skeleton: Skeleton,
+  pub meshes: Vec<SkinnedMesh>,
+  pub clips: Vec<AnimationClip>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[repr(u8)]
+pub enum UpAxis {
+  // What glTF is authored in and the engine uses
+  #[default]
+  Y,
+  // Exported from tools like Blender without converting axes
+  Z,
+}
+
+// How a glTF file is imported, from the .meta file next to it
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MeshImportSettings {
+  // Multiplied into every position, like 0.01 for files made in centimeters
+  pub scale: f32,
+  pub up_axis: UpAxis,
+}
+
+impl Default for MeshImportSettings {
+  fn default() -> Self {
+    Self { scale: 1.0, up_axis: UpAxis::Y }
+  }
+}
+
+impl MeshImportSettings {
+  // Takes the file's space to the engine's
+  pub fn conversion(&self) -> glam::Mat4 {
+    let rotation = match self.up_axis {
+      UpAxis::Y => glam::Quat::IDENTITY,
+      UpAxis::Z => glam::Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+    };
+    glam::Mat4::from_scale_rotation_translation(
+      glam::Vec3::splat(self.scale),
+      rotation,
+      glam::Vec3::ZERO,
+    )
+  }
+}
+
+fn transform_from_node(node: &gltf::Node) -> Transform {
+  let (translation, rotation, scale) = node.transform().decomposed();
+  Transform {
+    translation: glam::Vec3::from_array(translation),
+    rotation: glam::Quat::from_array(rotation),
+    scale: glam::Vec3::from_array(scale),
+  }
+}
+
+fn key_frame_interpolation(interpolation: gltf::animation::Interpolation) -> Interpolation {
+  match interpolation {
+    gltf::animation::Interpolation::Step => Interpolation::Step,
+    gltf::animation::Interpolation::Linear => Interpolation::Linear,
+    gltf::animation::Interpolation::CubicSpline => Interpolation::Hermite,
+  }
+}
+
+// Cubic spline outputs come as in tangent, value, out tangent triplets per input time
+fn make_key_framed<T>(
+  times_ms: &[u128],
+  outputs: Vec<T>,
+  interpolation: gltf::animation::Interpolation,
+) -> Result<KeyFramed<T>, String>
+where
+  T: Clone + std::ops::Mul<f32, Output = T> + std::ops::Add<Output = T>,
+{
+  let key_interpolation = key_frame_interpolation(interpolation);
+  let key_frames = if interpolation == gltf::animation::Interpolation::CubicSpline {
+    if outputs.len() != times_ms.len() * 3 {
+      return Err(format!(
+        "cubic spline sampler has {} outputs for {} inputs",
+        outputs.len(),
+        times_ms.len()
+      ));
+    }
+    times_ms
+      .iter()
+      .zip(outputs.chunks_exact(3))
+      .map(|(time_ms, triplet)| {
+        KeyFrame::with_tangents(
+          *time_ms,
+          triplet[1].clone(),
+          triplet[0].clone(),
+          triplet[2].clone(),
+          key_interpolation,
+        )
+      })
+      .collect::<Vec<_>>()
+  } else {
+    if outputs.len() != times_ms.len() {
+      return Err(format!(
+        "sampler has {} outputs for {} inputs",
+        outputs.len(),
+        times_ms.len()
+      ));
+    }
+    times_ms
+      .iter()
+      .zip(outputs)
+      .map(|(time_ms, value)| KeyFrame::new(*time_ms, value, key_interpolation))
+      .collect::<Vec<_>>()
+  };
+  if key_frames.is_empty() {
+    return Err("sampler has no key frames".to_string());
+  }
+  Ok(KeyFramed::new(key_frames))
+}
+
+// Linear blending takes the long way around unless neighbouring keys share a hemisphere
+fn align_quat_signs(rotations: &mut [glam::Quat]) {
+  for i in 1..rotations.len() {
+    if rotations[i - 1].dot(rotations[i]) < 0.0 {
+      rotations[i] = -rotations[i];
+    }
+  }
+}
+
+// conversion goes in front of the root joints, so poses and clips keep working in the file's space
+fn import_skeleton(
+  document: &gltf::Document,
+  buffers: &[gltf::buffer::Data],
+  skin: &gltf::Skin,
+  conversion: glam::Mat4,
+) -> Result<Skeleton, String> {
+  let mut node_parents = HashMap::new();
+  for node in document.nodes() {
+    for child in node.children() {
+      node_parents.insert(child.index(), node.index());
+    }
+  }
+  let joint_nodes = skin.joints().collect::<Vec<_>>();
+  let joint_of_node = joint_nodes
+    .iter()
+    .enumerate()
+    .map(|(i, node)| (node.index(), i))
+    .collect::<HashMap<_, _>>();
+  let inverse_binds = skin
+    .reader(|buffer| Some(&buffers[buffer.index()]))
+    .read_inverse_bind_matrices()
+    .map(|ibms| ibms.map(|ibm| glam::Mat4::from_cols_array_2d(&ibm)).collect::<Vec<_>>())
+    .unwrap_or_else(|| vec![glam::Mat4::IDENTITY; joint_nodes.len()]);
+  if inverse_binds.len() < joint_nodes.len() {
+    return Err(format!(
+      "skin has {} inverse bind matrices for {} joints",
+      inverse_binds.len(),
+      joint_nodes.len()
+    ));
+  }
+
+  let nodes = document.nodes().collect::<Vec<_>>();
+  let mut joints = Vec::with_capacity(joint_nodes.len());
+  for (node, inverse_bind) in joint_nodes.iter().zip(inverse_binds) {
+    // Walk up to the nearest joint, collecting the non joint nodes on the way
+    let mut parent = None;
+    let mut base = glam::Mat4::IDENTITY;
+    let mut ancestor = node_parents.get(&node.index()).copied();
+    while let Some(ancestor_idx) = ancestor {
+      if let Some(joint_idx) = joint_of_node.get(&ancestor_idx) {
+        parent = Some(*joint_idx);
+        break;
+      }
+      base = transform_from_node(&nodes[ancestor_idx]).to_mat4() * base;
+      ancestor = node_parents.get(&ancestor_idx).copied();
+    }
+    joints.push(Joint {
+      name: node.name().map(|name| name.to_string()).unwrap_or(format!("joint_{}", node.index())),
+      parent,
+      rest: transform_from_node(node),
+      // Binds against vertices that were converted too
+      inverse_bind: inverse_bind * conversion.inverse(),
+      base: if parent.is_some() { glam::Mat4::IDENTITY } else { conversion * base },
+    });
+  }
+  Skeleton::new(joints)
+}
+
+fn import_skinned_mesh(
+  buffers: &[gltf::buffer::Data],
+  mesh: &gltf::Mesh,
+  joint_count: usize,
+  conversion: glam::Mat4,
+) -> Result<SkinnedMesh, String> {
+  let name = mesh.name().map(|name| name.to_string()).unwrap_or(format!("mesh_{}", mesh.index()));
+  let mut skinned_mesh = SkinnedMesh { name: name.clone(), vertices: vec![], triangles: vec![] };
+  for primitive in mesh.primitives() {
+    if primitive.mode() != gltf::mesh::Mode::Triangles {
+      return Err(format!("mesh {name} has a primitive that isn't a triangle list"));
+    }
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let positions =
+      reader.read_positions().ok_or(format!("mesh {name} has a primitive without positions"))?;
+    let vertex_count = positions.len();
+    let normals = reader.read_normals().map(|normals| normals.collect::<Vec<_>>());
+    let uvs = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect::<Vec<_>>());
+    let joints = reader
+      .read_joints(0)
+      .ok_or(format!("mesh {name} has a primitive without joints"))?
+      .into_u16()
+      .collect::<Vec<_>>();
+    let weights = reader
+      .read_weights(0)
+      .ok_or(format!("mesh {name} has a primitive without weights"))?
+      .into_f32()
+      .collect::<Vec<_>>();
+    if joints.len() != vertex_count || weights.len() != vertex_count {
+      return Err(format!("mesh {name} has joints or weights missing for some vertices"));
+    }
+    if joints.iter().flatten().any(|joint| *joint as usize >= joint_count) {
+      return Err(format!("mesh {name} refers to joints outside its skin"));
+    }
+
+    let first_vertex = skinned_mesh.vertices.len() as u32;
+    for (i, pos) in positions.enumerate() {
+      skinned_mesh.vertices.push(SkinnedVertex {
+        pos: conversion.transform_point3(glam::Vec3::from_array(pos)),
+        normal: normals.as_ref().and_then(|normals| normals.get(i).copied()).map_or(
+          glam::Vec3::Y,
+          |normal| conversion.transform_vector3(glam::Vec3::from_array(normal)).normalize(),
+        ),
+        uv: uvs.as_ref().and_then(|uvs| uvs.get(i).copied()).map_or(
+          glam::Vec2::ZERO,
+          glam::Vec2::from_array,
+        ),
+        joints: joints[i],
+        weights: weights[i],
+      });
+    }
+    let indices = match reader.read_indices() {
+      Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+      None => (0..vertex_count as u32).collect(),
+    };
+    if indices.iter().any(|idx| *idx as usize >= vertex_count) {
+      return Err(format!("mesh {name} has out of range indices"));
+    }
+    skinned_mesh.triangles.extend(
+      indices.chunks_exact(3).map(|tri| [tri[0], tri[1], tri[2]].map(|idx| idx + first_vertex)),
+    );
+  }
+  Ok(skinned_mesh)
+}
+
+// Channels on nodes outside the skin and morph weight channels are skipped
+fn import_clip(
+  buffers: &[gltf::buffer::Data],
+  animation: &gltf::Animation,
+  joint_of_node: &HashMap<usize, usize>,
+) -> Result<Option<AnimationClip>, String> {
+  let name = animation
+    .name()
+    .map(|name| name.to_string())
+    .unwrap_or(format!("animation_{}", animation.index()));
+  let mut tracks: Vec<JointTrack> = vec![];
+  for channel in animation.channels() {
+    let Some(joint) = joint_of_node.get(&channel.target().node().index()).copied() else {
+      continue;
+    };
+    let interpolation = channel.sampler().interpolation();
+    let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+    let times_ms = reader
+      .read_inputs()
+      .ok_or(format!("animation {name} has a channel without key frame times"))?
+      .map(|time_s| (time_s.max(0.0) * 1000.0).round() as u128)
+      .collect::<Vec<_>>();
+    let outputs = reader
+      .read_outputs()
+      .ok_or(format!("animation {name} has a channel without key frame values"))?;
+
+    let track_idx = match tracks.iter().position(|track| track.joint == joint) {
+      Some(track_idx) => track_idx,
+      None => {
+        tracks.push(JointTrack { joint, translation: None, rotation: None, scale: None });
+        tracks.len() - 1
+      }
+    };
+    let track = &mut tracks[track_idx];
+    match outputs {
+      gltf::animation::util::ReadOutputs::Translations(translations) => {
+        let translations = translations.map(glam::Vec3::from_array).collect();
+        track.translation = Some(
+          make_key_framed(&times_ms, translations, interpolation)
+            .map_err(|e| format!("at animation {name} translation: {e}"))?,
+        );
+      }
+      gltf::animation::util::ReadOutputs::Rotations(rotations) => {
+        let mut rotations =
+          rotations.into_f32().map(glam::Quat::from_array).collect::<Vec<_>>();
+        if interpolation != gltf::animation::Interpolation::CubicSpline {
+          align_quat_signs(&mut rotations);
+        }
+        track.rotation = Some(
+          make_key_framed(&times_ms, rotations, interpolation)
+            .map_err(|e| format!("at animation {name} rotation: {e}"))?,
+        );
+      }
+      gltf::animation::util::ReadOutputs::Scales(scales) => {
+        let scales = scales.map(glam::Vec3::from_array).collect();
+        track.scale = Some(
+          make_key_framed(&times_ms, scales, interpolation)
+            .map_err(|e| format!("at animation {name} scale: {e}"))?,
+        );
+      }
+      gltf::animation::util::ReadOutputs::MorphTargetWeights(_) => {}
+    }
+  }
+  if tracks.is_empty() {
+    return Ok(None);
+  }
+  Ok(Some(AnimationClip { name, tracks }))
+}
+
+// Buffers in external files are looked up next to the glTF file in the vfs
+fn import_buffers(
+  vfs: &Vfs,
+  path: &str,
+  document: &gltf::Document,
+  mut blob: Option<Vec<u8>>,
+) -> Result<Vec<gltf::buffer::Data>, String> {
+  let gltf_dir = path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+  let mut buffers = vec![];
+  for buffer in document.buffers() {
+    let data = match buffer.source() {
+      gltf::buffer::Source::Uri(uri) if !uri.contains(':') => {
+        let uri = urlencoding::decode(uri).map_err(|e| format!("at decoding uri {uri}: {e}"))?;
+        let mut data = vfs.read(&format!("{gltf_dir}/{uri}"))?;
+        // Same padding gltf::import adds, accessors may read up to a 4 byte boundary
+        data.resize(data.len().next_multiple_of(4), 0);
+        gltf::buffer::Data(data)
+      }
+      // Embedded data uris and the glb binary chunk
+      source => gltf::buffer::Data::from_source_and_blob(source, None, &mut blob)
+        .map_err(|e| format!("at loading buffer {}: {e}", buffer.index()))?,
+    };
+    if data.len() < buffer.length() {
+      return Err(format!("buffer {} is shorter than its declared length", buffer.index()));
+    }
+    buffers.push(data);
+  }
+  Ok(buffers)
+}
+
+// The file parsed with its buffers and settings, and the cache key of what's imported from it
+fn load_gltf(
+  vfs: &Vfs,
+  path: &str,
+  cache_version: u32,
+) -> Result<(MeshImportSettings, gltf::Document, Vec<gltf::buffer::Data>, u64), String> {
+  let settings = asset_meta::load::<MeshImportSettings>(vfs, path)?;
+  if settings.scale <= 0.0 {
+    return Err(format!("at importing gltf {path}: scale has to be positive"));
+  }
+  // Only the glb binary chunk is copied out, into the buffer gltf hands back
+  let file_bytes = vfs.read_mapped(path)?;
+  let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(&file_bytes)
+    .map_err(|e| format!("at parsing gltf {path}: {e}"))?;
+  let buffers = import_buffers(vfs, &vfs::normalize_path(path), &document, blob)
+    .map_err(|e| format!("at loading gltf {path} buffers: {e}"))?;
+
+  let mut hasher = ContentHasher::new();
+  hasher
+    .update(&cache_version.to_le_bytes())
+    .update(&file_bytes)
+    .update(&settings.scale.to_le_bytes())
+    .update(&[settings.up_axis as u8]);
+  for buffer in buffers.iter() {
+    hasher.update(buffer);
+  }
+  Ok((settings, document, buffers, hasher.finish()))
+}
+
+// Scale and axes are converted as the file's MeshImportSettings say. Characters are kept in the
+// cache, keyed by the file, its buffers and the settings
+pub fn import_gltf_characters(
+  vfs: &Vfs,
+  cache: &AssetCache,
+  path: &str,
+) -> Result<Vec<AnimatedCharacter>, String> {
+  let (settings, document, buffers, hash) = load_gltf(vfs, path, GLTF_CACHE_VERSION)?;
+  cache.load_or_build(GLTF_CACHE_KIND, hash, || {
+    import_characters(&document, &buffers, settings.conversion())
+  })
+}
+
+// Every mesh the default scene places, merged into one where its nodes put it. Converted and
+// cached the same as characters
+pub fn import_gltf_model(vfs: &Vfs, cache: &AssetCache, path: &str) -> Result<StaticModel, String> {
+  let (settings, document, buffers, hash) = load_gltf(vfs, path, GLTF_MODEL_CACHE_VERSION)?;
+  cache.load_or_build(GLTF_MODEL_CACHE_KIND, hash, || {
+    let scene = document
+      .default_scene()
+      .or(document.scenes().next())
+      .ok_or(format!("at importing gltf {path}: it has no scenes"))?;
+    let mut model = StaticModel { vertices: vec![], triangles: vec![] };
+    let mut nodes = scene.nodes().map(|node| (node, settings.conversion())).collect::<Vec<_>>();
+    while let Some((node, parent_transform)) = nodes.pop() {
+      let transform = parent_transform * transform_from_node(&node).to_mat4();
+      if let Some(mesh) = node.mesh() {
+        append_static_mesh(&mut model, &buffers, &mesh, transform)
+          .map_err(|e| format!("at importing gltf {path}: {e}"))?;
+      }
+      nodes.extend(node.children().map(|child| (child, transform)));
+    }
+    Ok(model)
+  })
+}
+
+fn append_static_mesh(
+  model: &mut StaticModel,
+  buffers: &[gltf::buffer::Data],
+  mesh: &gltf::Mesh,
+  transform: glam::Mat4,
+) -> Result<(), String> {
+  let name = mesh.name().map(|name| name.to_string()).unwrap_or(format!("mesh_{}", mesh.index()));
+  // Mirroring transforms flip which way triangles wind
+  let flip = transform.determinant() < 0.0;
+  for primitive in mesh.primitives() {
+    if primitive.mode() != gltf::mesh::Mode::Triangles {
+      return Err(format!("mesh {name} has a primitive that isn't a triangle list"));
+    }
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let positions =
+      reader.read_positions().ok_or(format!("mesh {name} has a primitive without positions"))?;
+    let vertex_count = positions.len();
+    let normals = reader.read_normals().map(|normals| normals.collect::<Vec<_>>());
+    let uvs = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect::<Vec<_>>());
+
+    let first_vertex = model.vertices.len() as u32;
+    for (i, pos) in positions.enumerate() {
+      model.vertices.push(StaticVertex {
+        pos: transform.transform_point3(glam::Vec3::from_array(pos)),
+        normal: normals.as_ref().and_then(|normals| normals.get(i).copied()).map_or(
+          glam::Vec3::Y,
+          |normal| transform.transform_vector3(glam::Vec3::from_array(normal)).normalize(),
+        ),
+        uv: uvs.as_ref().and_then(|uvs| uvs.get(i).copied()).map_or(
+          glam::Vec2::ZERO,
+          glam::Vec2::from_array,
+        ),
+      });
+    }
+    let indices = match reader.read_indices() {
+      Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+      None => (0..vertex_count as u32).collect(),
+    };
+    if indices.iter().any(|idx| *idx as usize >= vertex_count) {
+      return Err(format!("mesh {name} has out of range indices"));
+    }
+    model.triangles.extend(indices.chunks_exact(3).map(|tri| {
+      let tri = if flip { [tri[0], tri[2], tri[1]] } else { [tri[0], tri[1], tri[2]] };
+      tri.map(|idx| idx + first_vertex)
+    }));
+  }
+  Ok(())
+}
+
+// Files a glTF file refers to by relative uri, buffers and images, decoded. Whatever copies it
+// somewhere has to bring these along
+pub fn gltf_external_files(file_bytes: &[u8]) -> Result<Vec<String>, String> {
+  let gltf::Gltf { document, .. } =
+    gltf::Gltf::from_slice(file_bytes).map_err(|e| format!("at parsing gltf: {e}"))?;
+  let buffer_uris = document.buffers().filter_map(|buffer| match buffer.source() {
+    gltf::buffer::Source::Uri(uri) => Some(uri),
+    gltf::buffer::Source::Bin => None,
+  });
+  let image_uris = document.images().filter_map(|image| match image.source() {
+    gltf::image::Source::Uri { uri, .. } => Some(uri),
+    gltf::image::Source::View { .. } => None,
+  });
+  buffer_uris
+    .chain(image_uris)
+    .filter(|uri| !uri.contains(':'))
+    .map(|uri| {
+      urlencoding::decode(uri)
+        .map(|uri| uri.into_owned())
+        .map_err(|e| format!("at decoding uri {uri}: {e}"))
+    })
+    .collect()
+}
+
+fn import_characters(
+  document: &gltf::Document,
+  buffers: &[gltf::buffer::Data],
+  conversion: glam::Mat4,
+) -> Result<Vec<AnimatedCharacter>, String> {
+  let mut characters = vec![];
+  for skin in document.skins() {
+    let name = skin.name().map(|name| name.to_string()).unwrap_or(format!("skin_{}", skin.index()));
+    let skeleton = import_skeleton(document, buffers, &skin, conversion)
+      .map_err(|e| format!("at importing skin {name}: {e}"))?;
+    let joint_of_node = skin
+      .joints()
+      .enumerate()
+      .map(|(i, node)| (node.index(), i))
+      .collect::<HashMap<_, _>>();
+
+    let mut meshes = vec![];
+    for node in document.nodes() {
+      let (Some(node_skin), Some(mesh)) = (node.skin(), node.mesh()) else { continue };
+      if node_skin.index() != skin.index() {
+        continue;
+      }
+      meshes.push(
+        import_skinned_mesh(buffers, &mesh, skeleton.joints().len(), conversion)
+          .map_err(|e| format!("at importing skin {name}: {e}"))?,
+      );
+    }
+
+    let mut clips = vec![];
+    for animation in document.animations() {
+      if let Some(clip) = import_clip(buffers, &animation, &joint_of_node)
+        .map_err(|e| format!("at importing skin {name}: {e}"))?
+      {
+        clips.push(clip);
+      }
+    }
+    characters.push(AnimatedCharacter { name, skeleton, meshes, clips });
+  }
+  Ok(characters)
+}
@@ -1,23 +1,109 @@
 use std::{ops::{Add, Mul}, rc::Rc};
 
 use glam::Vec4Swizzles;
+use serde::{Deserialize, Serialize};
 
+pub mod clip;
+pub mod gltf_import;
+pub mod skeleton;
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+  // Holds the key value until the next key
+  Step,
+  Linear,
+  // Tangents are rates of change per second, same as glTF CUBICSPLINE
+  Hermite,
+  // Tangents are offsets from the key value to the curve's control points
+  Bezier,
+}
+
+// What a track does outside its first and last key frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Infinity {
+  Clamp,
+  Loop,
+  PingPong,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyFrame<T> {
+  pub time_ms: u128,
+  pub value: T,
+  // Used by the segment ending at this key
+  pub in_tangent: T,
+  // Used by the segment starting at this key
+  pub out_tangent: T,
+  // Interpolation of the segment from this key to the next
+  pub interpolation: Interpolation,
+}
+
+impl<T> KeyFrame<T> where T: Clone + Mul<f32, Output = T> + Add<Output = T> {
+  pub fn new(time_ms: u128, value: T, interpolation: Interpolation) -> Self {
+    let zero = value.clone() * 0.0;
+    Self { time_ms, value, in_tangent: zero.clone(), out_tangent: zero, interpolation }
+  }
+
+  pub fn with_tangents(
+    time_ms: u128,
+    value: T,
+    in_tangent: T,
+    out_tangent: T,
+    interpolation: Interpolation,
+  ) -> Self {
+    Self { time_ms, value, in_tangent, out_tangent, interpolation }
+  }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct KeyFramed<T: Clone + Mul<f32, Output = T> + Add<Output = T>> {
-  pub key_frames: Vec<(u128, T)>,
+  pub key_frames: Vec<KeyFrame<T>>,
+  pub pre_infinity: Infinity,
+  pub post_infinity: Infinity,
 }
 
 impl<T> KeyFramed<T> where T: Clone + Mul<f32, Output = T> + Add<Output = T> {
+  // Needs at least one key frame, keys get sorted by time
+  pub fn new(mut key_frames: Vec<KeyFrame<T>>) -> Self {
+    key_frames.sort_by_key(|kf| kf.time_ms);
+    Self { key_frames, pre_infinity: Infinity::Clamp, post_infinity: Infinity::Clamp }
+  }
+
+  pub fn linear(key_frames: Vec<(u128, T)>) -> Self {
+    Self::new(
+      key_frames
+        .into_iter()
+        .map(|(time_ms, value)| KeyFrame::new(time_ms, value, Interpolation::Linear))
+        .collect(),
+    )
+  }
+
+  pub fn with_infinity(mut self, pre_infinity: Infinity, post_infinity: Infinity) -> Self {
+    self.pre_infinity = pre_infinity;
+    self.post_infinity = post_infinity;
+    self
+  }
+
+  pub fn duration_ms(&self) -> u128 {
+    match (self.key_frames.first(), self.key_frames.last()) {
+      (Some(first), Some(last)) => last.time_ms - first.time_ms,
+      _ => 0,
+    }
+  }
+
   pub fn search_key_frame_idx(&self, time_ms: u128) -> usize {
     let mut begin_idx = 0;
     let mut end_idx = self.key_frames.len() - 1;
-    
+
     loop {
       if begin_idx == end_idx {
         return begin_idx;
       }
       let check_idx = (begin_idx + end_idx) / 2;
-      if self.key_frames[check_idx].0 <= time_ms {
-        if self.key_frames[check_idx + 1].0 > time_ms {
+      if self.key_frames[check_idx].time_ms <= time_ms {
+        if self.key_frames[check_idx + 1].time_ms > time_ms {
           return check_idx;
         } else {
           begin_idx = check_idx + 1;
@@ -28,13 +114,66 @@ impl<T> KeyFramed<T> where T: Clone + Mul<f32, Output = T> + Add<Output = T> {
     }
   }
 
+  // Maps a time outside the key frames into them based on the infinity modes
+  fn local_time(&self, time_ms: u128) -> u128 {
+    let first_ms = self.key_frames[0].time_ms;
+    let last_ms = self.key_frames[self.key_frames.len() - 1].time_ms;
+    let duration = last_ms - first_ms;
+    if duration == 0 {
+      return first_ms;
+    }
+    if time_ms < first_ms {
+      let (cycle, offset) = ((first_ms - time_ms) / duration, (first_ms - time_ms) % duration);
+      match self.pre_infinity {
+        Infinity::Clamp => first_ms,
+        Infinity::Loop => last_ms - offset,
+        Infinity::PingPong if cycle % 2 == 0 => first_ms + offset,
+        Infinity::PingPong => last_ms - offset,
+      }
+    } else if time_ms > last_ms {
+      let (cycle, offset) = ((time_ms - last_ms) / duration, (time_ms - last_ms) % duration);
+      match self.post_infinity {
+        Infinity::Clamp => last_ms,
+        Infinity::Loop => first_ms + offset,
+        Infinity::PingPong if cycle % 2 == 0 => last_ms - offset,
+        Infinity::PingPong => first_ms + offset,
+      }
+    } else {
+      time_ms
+    }
+  }
+
+  fn segment_value(&self, start: &KeyFrame<T>, end: &KeyFrame<T>, time_ms: u128) -> T {
+    let segment_ms = (end.time_ms - start.time_ms) as f32;
+    let t = (time_ms - start.time_ms) as f32 / segment_ms;
+    let (v0, v1) = (start.value.clone(), end.value.clone());
+    match start.interpolation {
+      Interpolation::Step => v0,
+      Interpolation::Linear => v0 * (1.0 - t) + v1 * t,
+      Interpolation::Hermite => {
+        let (t2, t3) = (t * t, t * t * t);
+        let segment_s = segment_ms / 1000.0;
+        v0 * (2.0 * t3 - 3.0 * t2 + 1.0)
+          + start.out_tangent.clone() * ((t3 - 2.0 * t2 + t) * segment_s)
+          + v1 * (-2.0 * t3 + 3.0 * t2)
+          + end.in_tangent.clone() * ((t3 - t2) * segment_s)
+      }
+      Interpolation::Bezier => {
+        let s = 1.0 - t;
+        let c0 = v0.clone() + start.out_tangent.clone();
+        let c1 = v1.clone() + end.in_tangent.clone();
+        v0 * (s * s * s) + c0 * (3.0 * s * s * t) + c1 * (3.0 * s * t * t) + v1 * (t * t * t)
+      }
+    }
+  }
+
   pub fn value_at(&self, time_ms: u128) -> T {
+    let time_ms = self.local_time(time_ms);
     let kf_idx = self.search_key_frame_idx(time_ms);
     if kf_idx == self.key_frames.len() - 1 {
-      return self.key_frames[kf_idx].1.clone();
+      self.key_frames[kf_idx].value.clone()
     } else {
-      let mix_factor = (time_ms - self.key_frames[kf_idx].0) as f32 / (self.key_frames[kf_idx + 1].0 - self.key_frames[kf_idx].0) as f32;
-      return (self.key_frames[kf_idx].1.clone() * mix_factor) + (self.key_frames[kf_idx + 1].1.clone() * (1.0 - mix_factor));
+      self.segment_value(&self.key_frames[kf_idx], &self.key_frames[kf_idx + 1], time_ms)
     }
   }
 }
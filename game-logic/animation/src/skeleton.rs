@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+  pub translation: glam::Vec3,
+  pub rotation: glam::Quat,
+  pub scale: glam::Vec3,
+}
+
+impl Default for Transform {
+  fn default() -> Self {
+    Self { translation: glam::Vec3::ZERO, rotation: glam::Quat::IDENTITY, scale: glam::Vec3::ONE }
+  }
+}
+
+impl Transform {
+  pub fn to_mat4(&self) -> glam::Mat4 {
+    glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Joint {
+  pub name: String,
+  pub parent: Option<usize>,
+  // Local transform when no animation drives the joint
+  pub rest: Transform,
+  pub inverse_bind: glam::Mat4,
+  // Transform of the non joint nodes above a root joint, identity for the others
+  pub base: glam::Mat4,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Skeleton {
+  joints: Vec<Joint>,
+  // Joint indices with parents always before their children
+  eval_order: Vec<usize>,
+}
+
+impl Skeleton {
+  // Joint order is kept since skinned vertices refer to joints by index
+  pub fn new(joints: Vec<Joint>) -> Result<Self, String> {
+    let mut eval_order = Vec::with_capacity(joints.len());
+    let mut added = vec![false; joints.len()];
+    while eval_order.len() < joints.len() {
+      let prev_len = eval_order.len();
+      for (i, joint) in joints.iter().enumerate() {
+        if added[i] {
+          continue;
+        }
+        match joint.parent {
+          Some(parent) if parent >= joints.len() => {
+            return Err(format!("joint {} has an out of range parent {parent}", joint.name));
+          }
+          Some(parent) if !added[parent] => continue,
+          _ => {}
+        }
+        added[i] = true;
+        eval_order.push(i);
+      }
+      if eval_order.len() == prev_len {
+        return Err("skeleton joint hierarchy has a cycle".to_string());
+      }
+    }
+    Ok(Self { joints, eval_order })
+  }
+
+  pub fn joints(&self) -> &[Joint] {
+    &self.joints
+  }
+
+  pub fn joint_idx(&self, name: &str) -> Option<usize> {
+    self.joints.iter().position(|joint| joint.name == name)
+  }
+
+  pub fn rest_pose(&self) -> Vec<Transform> {
+    self.joints.iter().map(|joint| joint.rest).collect()
+  }
+
+  // Model space transform of every joint for a pose of local transforms
+  pub fn global_transforms(&self, pose: &[Transform]) -> Vec<glam::Mat4> {
+    let mut globals = vec![glam::Mat4::IDENTITY; self.joints.len()];
+    for &i in self.eval_order.iter() {
+      let local = pose.get(i).unwrap_or(&self.joints[i].rest).to_mat4();
+      globals[i] = match self.joints[i].parent {
+        Some(parent) => globals[parent] * local,
+        None => self.joints[i].base * local,
+      };
+    }
+    globals
+  }
+
+  // Matrices taking bind pose vertices to the posed model space
+  pub fn skinning_matrices(&self, pose: &[Transform]) -> Vec<glam::Mat4> {
+    self
+      .global_transforms(pose)
+      .into_iter()
+      .zip(self.joints.iter())
+      .map(|(global, joint)| global * joint.inverse_bind)
+      .collect()
+  }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SkinnedVertex {
+  pub pos: glam::Vec3,
+  pub normal: glam::Vec3,
+  pub uv: glam::Vec2,
+  pub joints: [u16; 4],
+  pub weights: [f32; 4],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkinnedMesh {
+  pub name: String,
+  pub vertices: Vec<SkinnedVertex>,
+  pub triangles: Vec<[u32; 3]>,
+}
+
+impl SkinnedMesh {
+  // Linear blend skinning on the CPU, gives posed positions and normals in vertex order
+  pub fn skin(&self, skinning_matrices: &[glam::Mat4]) -> Vec<(glam::Vec3, glam::Vec3)> {
+    self
+      .vertices
+      .iter()
+      .map(|vertex| {
+        let mut skin_mat = glam::Mat4::ZERO;
+        for (joint, weight) in vertex.joints.iter().zip(vertex.weights) {
+          if weight > 0.0 {
+            let joint_mat = skinning_matrices.get(*joint as usize).unwrap_or(&glam::Mat4::IDENTITY);
+            skin_mat += *joint_mat * weight;
+          }
+        }
+        (
+          skin_mat.transform_point3(vertex.pos),
+          skin_mat.transform_vector3(vertex.normal).normalize_or_zero(),
+        )
+      })
+      .collect()
+  }
+}
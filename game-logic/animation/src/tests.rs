@@ -0,0 +1,118 @@
+use crate::{Infinity, Interpolation, KeyFrame, KeyFramed};
+
+fn assert_close(value: f32, expected: f32, what: &str) {
+  assert_within(value, expected, 1e-4, what);
+}
+
+fn assert_within(value: f32, expected: f32, tolerance: f32, what: &str) {
+  assert!((value - expected).abs() < tolerance, "{what}: {value}, expected {expected}");
+}
+
+// 0 at 1s going to 10 at 2s, a second long
+fn ramp(interpolation: Interpolation) -> KeyFramed<f32> {
+  KeyFramed::new(vec![
+    KeyFrame::new(1000, 0.0, interpolation),
+    KeyFrame::new(2000, 10.0, interpolation),
+  ])
+}
+
+#[test]
+fn every_interpolation_passes_through_its_keys() {
+  let interpolations =
+    [Interpolation::Step, Interpolation::Linear, Interpolation::Hermite, Interpolation::Bezier];
+  for interpolation in interpolations {
+    // Given out of order, keys are sorted by time
+    let track = KeyFramed::new(vec![
+      KeyFrame::with_tangents(2000, 4.0, 1.0, -3.0, interpolation),
+      KeyFrame::with_tangents(0, 1.0, 2.0, 5.0, interpolation),
+      KeyFrame::with_tangents(1000, -2.0, 0.5, 0.5, interpolation),
+    ]);
+    for (time_ms, value) in [(0, 1.0), (1000, -2.0), (2000, 4.0)] {
+      assert_close(track.value_at(time_ms), value, &format!("{interpolation:?} at {time_ms}ms"));
+    }
+  }
+
+  // A single key is the value everywhere
+  let single = KeyFramed::linear(vec![(500, 3.0)]);
+  for time_ms in [0, 500, 10_000] {
+    assert_close(single.value_at(time_ms), 3.0, &format!("single key at {time_ms}ms"));
+  }
+}
+
+#[test]
+fn step_and_linear_segments() {
+  let step = ramp(Interpolation::Step);
+  assert_close(step.value_at(1500), 0.0, "step halfway");
+  assert_close(step.value_at(1999), 0.0, "step just before the next key");
+
+  let linear = ramp(Interpolation::Linear);
+  assert_close(linear.value_at(1250), 2.5, "linear a quarter in");
+  assert_close(linear.value_at(1500), 5.0, "linear halfway");
+}
+
+#[test]
+fn hermite_segments_follow_the_tangents() {
+  // Flat tangents ease in and out
+  let flat = ramp(Interpolation::Hermite);
+  assert_close(flat.value_at(1250), 1.5625, "flat hermite a quarter in");
+  assert_close(flat.value_at(1500), 5.0, "flat hermite halfway");
+
+  // Tangents are per second whatever the segment's length, the slope over a ms is close to them
+  let track = KeyFramed::new(vec![
+    KeyFrame::with_tangents(0, 0.0, 0.0, 3.0, Interpolation::Hermite),
+    KeyFrame::with_tangents(2000, 0.0, -1.0, 0.0, Interpolation::Hermite),
+  ]);
+  assert_within(track.value_at(1) / 0.001, 3.0, 0.01, "slope leaving the first key");
+  assert_within(
+    (track.value_at(2000) - track.value_at(1999)) / 0.001,
+    -1.0,
+    0.01,
+    "slope into the last key",
+  );
+  // Halfway the tangents are weighted 1/8 and -1/8, times the 2s segment
+  assert_close(track.value_at(1000), 3.0 * 0.125 * 2.0 + -1.0 * -0.125 * 2.0, "hermite halfway");
+}
+
+#[test]
+fn bezier_segments_pull_towards_the_control_points() {
+  // Tangents of zero put the control points on the keys, halfway is still halfway
+  let flat = ramp(Interpolation::Bezier);
+  assert_close(flat.value_at(1500), 5.0, "flat bezier halfway");
+
+  // Control points at 10 and 10, halfway is 3/8 * 10 + 3/8 * 10 + 1/8 * 10
+  let track = KeyFramed::new(vec![
+    KeyFrame::with_tangents(0, 0.0, 0.0, 10.0, Interpolation::Bezier),
+    KeyFrame::with_tangents(1000, 10.0, 0.0, 0.0, Interpolation::Bezier),
+  ]);
+  assert_close(track.value_at(500), 8.75, "bezier halfway");
+  // Only the end key's in tangent is used by the segment
+  let pulled_back = KeyFramed::new(vec![
+    KeyFrame::with_tangents(0, 0.0, 0.0, 10.0, Interpolation::Bezier),
+    KeyFrame::with_tangents(1000, 10.0, -10.0, 99.0, Interpolation::Bezier),
+  ]);
+  assert_close(pulled_back.value_at(500), 5.0, "bezier halfway pulled back");
+}
+
+#[test]
+fn infinities_clamp_loop_and_ping_pong_outside_the_keys() {
+  let clamp = ramp(Interpolation::Linear);
+  assert_close(clamp.value_at(0), 0.0, "clamped before");
+  assert_close(clamp.value_at(5250), 10.0, "clamped after");
+
+  let looped = ramp(Interpolation::Linear).with_infinity(Infinity::Loop, Infinity::Loop);
+  assert_close(looped.value_at(2250), 2.5, "looped a quarter into the next cycle");
+  assert_close(looped.value_at(5250), 2.5, "looped a quarter into a later cycle");
+  assert_close(looped.value_at(750), 7.5, "looped a quarter before the first key");
+
+  let ping_pong = ramp(Interpolation::Linear).with_infinity(Infinity::PingPong, Infinity::PingPong);
+  assert_close(ping_pong.value_at(2250), 7.5, "ping pong heading back");
+  assert_close(ping_pong.value_at(3250), 2.5, "ping pong heading forward again");
+  assert_close(ping_pong.value_at(4000), 10.0, "ping pong back at the last key");
+  assert_close(ping_pong.value_at(750), 2.5, "ping pong mirrored before the first key");
+  assert_close(ping_pong.value_at(0), 10.0, "ping pong two cycles before the first key");
+
+  // Each side has its own mode
+  let mixed = ramp(Interpolation::Linear).with_infinity(Infinity::Clamp, Infinity::Loop);
+  assert_close(mixed.value_at(500), 0.0, "clamped before with looping after");
+  assert_close(mixed.value_at(2500), 5.0, "looping after with clamping before");
+}
@@ -0,0 +1,117 @@
+use std::path::{Path, PathBuf};
+
+use animation::gltf_import::{gltf_external_files, import_gltf_model};
+use engine_config::AssetMount;
+
+use crate::scene::{PhysicsProperties, Scene, SceneObject, SceneShape};
+
+// Files dropped from outside the asset folders are copied into this folder of the last one mounted
+const IMPORT_DIR: &str = "imported";
+// Side of the square dropped images are laid out on
+const TEXTURE_QUAD_SIZE: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportKind {
+  // glTF, spawned as a static object drawn with it
+  Model,
+  // Laid flat on what it's dropped onto
+  Texture,
+  // Equirectangular sky, set as the environment
+  Environment,
+}
+
+impl ImportKind {
+  // By the file's extension, None for files the engine doesn't read
+  pub fn of(path: &Path) -> Option<Self> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    match extension.as_str() {
+      "gltf" | "glb" => Some(Self::Model),
+      "png" | "jpg" | "jpeg" => Some(Self::Texture),
+      "hdr" | "exr" => Some(Self::Environment),
+      _ => None,
+    }
+  }
+}
+
+// Vfs path of a file inside one of the loose directories mounted, later mounts first like the vfs
+pub fn mounted_path(mounts: &[AssetMount], path: &Path) -> Option<String> {
+  let path = path.canonicalize().ok()?;
+  mounts.iter().rev().find_map(|mount| {
+    let rel_path = path.strip_prefix(Path::new(&mount.path).canonicalize().ok()?).ok()?;
+    Some(vfs::normalize_path(&format!("{}/{}", mount.mount_point, rel_path.to_string_lossy())))
+  })
+}
+
+// Makes a file from anywhere on disk readable through the vfs and returns its vfs path. Files
+// already in a mounted directory are used where they are, others are copied into the import folder
+// with the buffers and images a glTF file points to. Earlier imports with the same name are
+// replaced
+pub fn import_file(mounts: &[AssetMount], path: &Path) -> Result<String, String> {
+  if let Some(vfs_path) = mounted_path(mounts, path) {
+    return Ok(vfs_path);
+  }
+  let mount = mounts
+    .iter()
+    .rev()
+    .find(|mount| Path::new(&mount.path).is_dir())
+    .ok_or("there's no asset directory to import into".to_string())?;
+  let file_name = path.file_name().ok_or(format!("{} isn't a file", path.display()))?;
+  let import_dir = PathBuf::from(&mount.path).join(IMPORT_DIR);
+  let mut copies = vec![(path.to_path_buf(), import_dir.join(file_name))];
+  if ImportKind::of(path) == Some(ImportKind::Model) {
+    let file_bytes =
+      std::fs::read(path).map_err(|e| format!("at reading {}: {e}", path.display()))?;
+    let src_dir = path.parent().unwrap_or(Path::new(""));
+    for uri in gltf_external_files(&file_bytes)? {
+      // Kept next to the file the same way, without climbing out of the import folder
+      let rel_path = vfs::normalize_path(&uri);
+      copies.push((src_dir.join(&uri), import_dir.join(rel_path)));
+    }
+  }
+  for (src, dst) in copies.iter() {
+    if let Some(dst_dir) = dst.parent() {
+      std::fs::create_dir_all(dst_dir)
+        .map_err(|e| format!("at making folder {}: {e}", dst_dir.display()))?;
+    }
+    std::fs::copy(src, dst)
+      .map_err(|e| format!("at copying {} to {}: {e}", src.display(), dst.display()))?;
+  }
+  Ok(vfs::normalize_path(&format!(
+    "{}/{IMPORT_DIR}/{}",
+    mount.mount_point,
+    file_name.to_string_lossy()
+  )))
+}
+
+// Object for a model or image at vfs_path, named after the file. Models keep the size they were
+// made at and collide as the box around them
+pub fn dropped_object(
+  scene: &Scene,
+  kind: ImportKind,
+  vfs_path: &str,
+) -> Result<SceneObject, String> {
+  let stem = Path::new(vfs_path).file_stem().unwrap_or_default().to_string_lossy().to_string();
+  let mut obj = SceneObject {
+    name: scene.unique_name(&stem),
+    shape: SceneShape::Rectangle { size: [TEXTURE_QUAD_SIZE; 2] },
+    position: [0.0; 3],
+    rotation: glam::Quat::IDENTITY.to_array(),
+    physics: None,
+    script: None,
+    model: None,
+    texture: None,
+    prefab: None,
+  };
+  match kind {
+    ImportKind::Model => {
+      let model = import_gltf_model(vfs::global(), asset_cache::global(), vfs_path)?;
+      let (min, max) = model.bounds().ok_or(format!("{vfs_path} has no meshes"))?;
+      obj.shape = SceneShape::Cuboid { size: (max - min).max(glam::Vec3::splat(0.01)).to_array() };
+      obj.physics = Some(PhysicsProperties { dynamic: false, mass: 0.0 });
+      obj.model = Some(vfs_path.to_string());
+    }
+    ImportKind::Texture => obj.texture = Some(vfs_path.to_string()),
+    ImportKind::Environment => return Err(format!("{vfs_path} is a sky, not an object")),
+  }
+  Ok(obj)
+}
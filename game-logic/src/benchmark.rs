@@ -0,0 +1,384 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use localization::tr;
+use render_manager::{
+  BenchmarkReport, FrameTimeSummary, MaterialCPU, MaterialHandle, MeshHandle, MeshState, Renderer,
+  RendererMessage, ShaderVariant, TextureColorSpace, TextureHandle, TriMeshTransform,
+};
+use rng::{RngService, RngStream};
+use serde::Serialize;
+
+use crate::camera_animator::{CameraKey, CameraPath, Easing, PathInterpolation};
+use crate::scene::{PhysicsProperties, Scene, SceneLight, SceneObject, SceneShape};
+
+// Generated textures are written here and loaded back through the vfs like any other
+const BENCHMARK_TEXTURE_DIR: &str = "./benchmark_textures";
+const BENCHMARK_TEXTURE_SIZE: u32 = 256;
+const CHECKER_SQUARES: u32 = 8;
+// Frames drawn without a loading screen before timing starts, for pipelines and uploads to settle
+const WARMUP_FRAMES: u64 = 60;
+const PROP_SPACING: f32 = 3.0;
+const CAMERA_PATH_KEYS: u32 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkSettings {
+  // Props drawn each with their own mesh
+  pub objects: u32,
+  // Point lights scattered over the props
+  pub lights: u32,
+  // Props cycle through a material per texture, 0 draws them untextured
+  pub textures: u32,
+  // Simulated time the camera path takes, timing stops at its end
+  pub duration: Duration,
+  pub seed: u64,
+  pub report_path: PathBuf,
+}
+
+impl Default for BenchmarkSettings {
+  fn default() -> Self {
+    Self {
+      objects: 1000,
+      lights: 32,
+      textures: 16,
+      duration: Duration::from_secs(30),
+      seed: 0,
+      report_path: PathBuf::from("./benchmark_report.toml"),
+    }
+  }
+}
+
+impl BenchmarkSettings {
+  // Props are laid out on a square grid centered on the origin
+  fn grid_side(&self) -> u32 {
+    (self.objects as f32).sqrt().ceil().max(1.0) as u32
+  }
+
+  fn half_extent(&self) -> f32 {
+    self.grid_side() as f32 * PROP_SPACING / 2.0
+  }
+}
+
+// Floor and lights, the props are spawned on their own so static batching doesn't merge them
+pub fn benchmark_scene(settings: &BenchmarkSettings) -> Scene {
+  let mut rng_service = RngService::new(settings.seed);
+  let rng = rng_service.stream("benchmark_lights");
+  let half_extent = settings.half_extent();
+  let lights = (0..settings.lights)
+    .map(|_| SceneLight {
+      position: [
+        rng.range_f32(-half_extent, half_extent),
+        rng.range_f32(2.0, 5.0),
+        rng.range_f32(-half_extent, half_extent),
+      ],
+      direction: None,
+      cone: [20.0, 30.0],
+      color: [rng.range_f32(0.3, 1.0), rng.range_f32(0.3, 1.0), rng.range_f32(0.3, 1.0)],
+      intensity: rng.range_f32(2.0, 6.0),
+      range: PROP_SPACING * 4.0,
+      casts_shadows: false,
+    })
+    .collect();
+  let floor_size = half_extent * 2.0 + PROP_SPACING * 2.0;
+  Scene {
+    seed: settings.seed,
+    origin: [0.0; 3],
+    objects: vec![SceneObject {
+      name: "floor".to_string(),
+      shape: SceneShape::Rectangle { size: [floor_size, floor_size] },
+      position: [0.0, 0.0, 0.0],
+      rotation: glam::Quat::IDENTITY.to_array(),
+      physics: Some(PhysicsProperties { dynamic: false, mass: 0.0 }),
+      script: None,
+      model: None,
+      texture: None,
+      prefab: None,
+    }],
+    lights,
+    ..Scene::default_scene()
+  }
+}
+
+// Orbits the props once over the run, rising and falling so near and far props both get drawn
+pub fn benchmark_camera_path(settings: &BenchmarkSettings) -> Result<CameraPath, String> {
+  let radius = settings.half_extent() + PROP_SPACING * 2.0;
+  let duration_ms = settings.duration.as_millis().max(CAMERA_PATH_KEYS as u128);
+  let keys = (0..=CAMERA_PATH_KEYS)
+    .map(|i| {
+      let angle = i as f32 / CAMERA_PATH_KEYS as f32 * std::f32::consts::TAU;
+      let height = if i % 2 == 0 { 3.0 } else { radius * 0.5 + 3.0 };
+      CameraKey {
+        time_ms: duration_ms * i as u128 / CAMERA_PATH_KEYS as u128,
+        pos: glam::vec3(radius * angle.cos(), height, radius * angle.sin()),
+        look_at: glam::Vec3::ZERO,
+        easing: Easing::Linear,
+      }
+    })
+    .collect();
+  CameraPath::new(keys, PathInterpolation::CatmullRom)
+}
+
+// Two tone checker board in colors picked from the stream, saved as a png
+fn write_checker_texture(idx: u32, rng: &mut RngStream) -> Result<String, String> {
+  let mut color = || {
+    let [r, g, b] = [(); 3].map(|_| rng.range_u32(40, 255) as u8);
+    image::Rgba([r, g, b, 255])
+  };
+  let (a, b) = (color(), color());
+  let square = BENCHMARK_TEXTURE_SIZE / CHECKER_SQUARES;
+  let texture =
+    image::RgbaImage::from_fn(BENCHMARK_TEXTURE_SIZE, BENCHMARK_TEXTURE_SIZE, |x, y| {
+      match (x / square + y / square) % 2 {
+        0 => a,
+        _ => b,
+      }
+    });
+  let dir = Path::new(BENCHMARK_TEXTURE_DIR);
+  std::fs::create_dir_all(dir)
+    .map_err(|e| format!("at making benchmark texture dir {}: {e}", dir.display()))?;
+  let path = dir.join(format!("checker_{idx}.png"));
+  texture.save(&path).map_err(|e| format!("at writing {}: {e}", path.display()))?;
+  Ok(path.to_string_lossy().to_string())
+}
+
+// Cuboids of random sizes and turns, one draw each
+pub struct StressProps {
+  meshes: Vec<(MeshHandle, TriMeshTransform)>,
+  materials: Vec<MaterialHandle>,
+  textures: Vec<TextureHandle>,
+}
+
+impl StressProps {
+  pub fn spawn(
+    renderer: &mut Renderer,
+    settings: &BenchmarkSettings,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<Self, String> {
+    let mut rng_service = RngService::new(settings.seed);
+    let rng = rng_service.stream("benchmark_props");
+    let mut props = Self { meshes: vec![], materials: vec![], textures: vec![] };
+    for i in 0..settings.textures {
+      let path = write_checker_texture(i, rng)?;
+      let texture = renderer.create_texture_handle();
+      messages.push(RendererMessage::UploadFlatTex(
+        format!("benchmark_texture_{i}"),
+        path,
+        TextureColorSpace::Srgb,
+        None,
+        texture,
+      ));
+      props.textures.push(texture);
+    }
+    let material_textures = match props.textures.is_empty() {
+      true => vec![None],
+      false => props.textures.iter().copied().map(Some).collect(),
+    };
+    for (i, texture) in material_textures.into_iter().enumerate() {
+      let material = renderer.create_material_handle();
+      messages.push(RendererMessage::CreateMaterial(
+        format!("benchmark_material_{i}"),
+        MaterialCPU { variant: ShaderVariant::Lit, ..Default::default() },
+        texture,
+        material,
+      ));
+      props.materials.push(material);
+    }
+    let side = settings.grid_side();
+    let half_extent = settings.half_extent();
+    for i in 0..settings.objects {
+      let size = [(); 3].map(|_| rng.range_f32(0.5, 1.5));
+      let cell = glam::vec2((i % side) as f32, (i / side) as f32) * PROP_SPACING;
+      let position = glam::vec3(
+        cell.x - half_extent + rng.range_f32(0.0, PROP_SPACING / 2.0),
+        size[1] / 2.0,
+        cell.y - half_extent + rng.range_f32(0.0, PROP_SPACING / 2.0),
+      );
+      let rotation = glam::Quat::from_rotation_y(rng.range_f32(0.0, std::f32::consts::TAU));
+      let mesh = renderer.create_mesh_handle();
+      messages.push(RendererMessage::UploadTriMesh(
+        format!("benchmark_prop_{i}"),
+        SceneShape::Cuboid { size }.make_tri_mesh(),
+        mesh,
+      ));
+      let material = props.materials[i as usize % props.materials.len()];
+      messages.push(RendererMessage::AddRenderable(mesh, Some(material)));
+      let transform = glam::Mat4::from_rotation_translation(rotation, position);
+      props.meshes.push((mesh, TriMeshTransform { transform }));
+    }
+    Ok(props)
+  }
+
+  pub fn mesh_states(&self) -> impl Iterator<Item = MeshState> + '_ {
+    self.meshes.iter().map(|(mesh, transform)| MeshState {
+      mesh: *mesh,
+      transform: *transform,
+      morph_weights: None,
+      updated_at: None,
+    })
+  }
+
+  pub fn destroy(self, messages: &mut Vec<RendererMessage>) {
+    for (mesh, _) in self.meshes {
+      messages.push(RendererMessage::DestroyTriMesh(mesh));
+    }
+    for material in self.materials {
+      messages.push(RendererMessage::DestroyMaterial(material));
+    }
+    for texture in self.textures {
+      messages.push(RendererMessage::DestroyFlatTex(texture));
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+struct FrameTimesMs {
+  average: f64,
+  p99: f64,
+  max: f64,
+}
+
+impl FrameTimesMs {
+  fn new(summary: FrameTimeSummary) -> Self {
+    let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+    Self { average: ms(summary.average), p99: ms(summary.p99), max: ms(summary.max) }
+  }
+}
+
+// What gets written to the report file, tables last as toml wants them
+#[derive(Debug, Clone, Serialize)]
+struct BenchmarkResults {
+  gpu: String,
+  objects: u32,
+  lights: u32,
+  textures: u32,
+  seed: u64,
+  frames: u64,
+  seconds: f64,
+  average_fps: f64,
+  average_draw_calls: f32,
+  max_draw_calls: u32,
+  peak_gpu_memory_bytes: u64,
+  peak_descriptor_sets: u64,
+  frame_time_ms: Option<FrameTimesMs>,
+  gpu_frame_time_ms: Option<FrameTimesMs>,
+}
+
+pub enum BenchmarkStep {
+  Waiting,
+  // Timing started, the camera should follow the path from now on
+  Started(CameraPath),
+  // Report written, the game can stop
+  Finished,
+}
+
+enum BenchmarkPhase {
+  // Frames drawn as of the first tick seen without a loading screen
+  Warmup(Option<u64>),
+  // Microseconds simulated since timing started
+  Running(u128),
+  Reporting,
+  Done,
+}
+
+// Waits for the level to be in, times the camera path and writes the report
+pub struct Benchmark {
+  settings: BenchmarkSettings,
+  props: StressProps,
+  phase: BenchmarkPhase,
+}
+
+impl Benchmark {
+  pub fn new(settings: BenchmarkSettings, props: StressProps) -> Self {
+    Self { settings, props, phase: BenchmarkPhase::Warmup(None) }
+  }
+
+  pub fn props(&self) -> &StressProps {
+    &self.props
+  }
+
+  pub fn into_props(self) -> StressProps {
+    self.props
+  }
+
+  // Each tick, frame_time is in microseconds like the rest of the game update
+  pub fn update(
+    &mut self,
+    renderer: &mut Renderer,
+    frame_time: u128,
+  ) -> Result<BenchmarkStep, String> {
+    match &mut self.phase {
+      BenchmarkPhase::Warmup(since) => {
+        // Frames are only drawn once the uploads queued before them were taken, so a run of
+        // frames without a loading screen means everything is on the gpu
+        let frames_drawn = renderer.frame_stats()?.frames_drawn;
+        if renderer.loading_progress()?.is_some() {
+          *since = None;
+          return Ok(BenchmarkStep::Waiting);
+        }
+        let since = *since.get_or_insert(frames_drawn);
+        if frames_drawn < since + WARMUP_FRAMES {
+          return Ok(BenchmarkStep::Waiting);
+        }
+        renderer.start_benchmark()?;
+        self.phase = BenchmarkPhase::Running(0);
+        Ok(BenchmarkStep::Started(benchmark_camera_path(&self.settings)?))
+      }
+      BenchmarkPhase::Running(elapsed) => {
+        *elapsed += frame_time;
+        if *elapsed >= self.settings.duration.as_micros() {
+          renderer.stop_benchmark()?;
+          self.phase = BenchmarkPhase::Reporting;
+        }
+        Ok(BenchmarkStep::Waiting)
+      }
+      BenchmarkPhase::Reporting => {
+        let Some(report) = renderer.take_benchmark_report()? else {
+          return Ok(BenchmarkStep::Waiting);
+        };
+        let gpu = renderer.engine_info()?.map(|info| info.gpu.name).unwrap_or_default();
+        self.write_report(&report, gpu)?;
+        self.phase = BenchmarkPhase::Done;
+        Ok(BenchmarkStep::Finished)
+      }
+      BenchmarkPhase::Done => Ok(BenchmarkStep::Finished),
+    }
+  }
+
+  fn write_report(&self, report: &BenchmarkReport, gpu: String) -> Result<(), String> {
+    let seconds = report.duration.as_secs_f64();
+    let results = BenchmarkResults {
+      gpu,
+      objects: self.settings.objects,
+      lights: self.settings.lights,
+      textures: self.settings.textures,
+      seed: self.settings.seed,
+      frames: report.frames,
+      seconds,
+      average_fps: report.frames as f64 / seconds.max(f64::EPSILON),
+      average_draw_calls: report.average_draw_calls,
+      max_draw_calls: report.max_draw_calls,
+      peak_gpu_memory_bytes: report.peak_resources.gpu_memory_bytes,
+      peak_descriptor_sets: report.peak_resources.descriptor_sets,
+      frame_time_ms: report.frame_time.map(FrameTimesMs::new),
+      gpu_frame_time_ms: report.gpu_frame_time.map(FrameTimesMs::new),
+    };
+    let report_str = toml::to_string_pretty(&results)
+      .map_err(|e| format!("at serializing benchmark report: {e}"))?;
+    let path = &self.settings.report_path;
+    std::fs::write(path, report_str)
+      .map_err(|e| format!("at writing benchmark report {}: {e}", path.display()))?;
+    let frame_time =
+      results.frame_time_ms.unwrap_or(FrameTimesMs { average: 0.0, p99: 0.0, max: 0.0 });
+    println!(
+      "{}",
+      tr!(
+        "console.benchmark_done",
+        frames = results.frames,
+        average = format!("{:.2}", frame_time.average),
+        p99 = format!("{:.2}", frame_time.p99),
+        path = path.display()
+      )
+    );
+    Ok(())
+  }
+}
@@ -0,0 +1,164 @@
+use geometry::smoothing::easing;
+use render_manager::{glam, Camera3D};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+  Linear,
+  EaseIn,
+  EaseOut,
+  EaseInOut,
+}
+
+impl Easing {
+  // Remaps progress through a segment, t is in 0..1
+  pub fn apply(&self, t: f32) -> f32 {
+    match self {
+      Easing::Linear => easing::linear(t),
+      Easing::EaseIn => easing::cubic_in(t),
+      Easing::EaseOut => easing::cubic_out(t),
+      Easing::EaseInOut => easing::smoothstep(t),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathInterpolation {
+  Linear,
+  // Passes through every key with smooth turns, uses the neighbouring keys as control points
+  CatmullRom,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CameraKey {
+  pub time_ms: u128,
+  pub pos: glam::Vec3,
+  pub look_at: glam::Vec3,
+  // Easing of the segment from this key to the next
+  pub easing: Easing,
+}
+
+#[derive(Debug, Clone)]
+pub struct CameraPath {
+  keys: Vec<CameraKey>,
+  interpolation: PathInterpolation,
+}
+
+fn catmull_rom(
+  p0: glam::Vec3,
+  p1: glam::Vec3,
+  p2: glam::Vec3,
+  p3: glam::Vec3,
+  t: f32,
+) -> glam::Vec3 {
+  let t2 = t * t;
+  let t3 = t2 * t;
+  0.5
+    * ((2.0 * p1)
+      + (p2 - p0) * t
+      + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+      + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+impl CameraPath {
+  pub fn new(mut keys: Vec<CameraKey>, interpolation: PathInterpolation) -> Result<Self, String> {
+    if keys.is_empty() {
+      return Err("camera path needs at least one key".to_string());
+    }
+    keys.sort_by_key(|key| key.time_ms);
+    if keys.windows(2).any(|pair| pair[0].time_ms == pair[1].time_ms) {
+      return Err("camera path has two keys at the same time".to_string());
+    }
+    Ok(Self { keys, interpolation })
+  }
+
+  pub fn duration_ms(&self) -> u128 {
+    self.keys.last().map(|key| key.time_ms).unwrap_or(0)
+  }
+
+  // Position and look at point, times outside the path clamp to the first or last key
+  pub fn sample(&self, time_ms: f32) -> (glam::Vec3, glam::Vec3) {
+    let next_idx = self.keys.partition_point(|key| (key.time_ms as f32) <= time_ms);
+    if next_idx == 0 {
+      return (self.keys[0].pos, self.keys[0].look_at);
+    }
+    if next_idx == self.keys.len() {
+      let last = self.keys[self.keys.len() - 1];
+      return (last.pos, last.look_at);
+    }
+    let (k1, k2) = (self.keys[next_idx - 1], self.keys[next_idx]);
+    let segment_t = (time_ms - k1.time_ms as f32) / (k2.time_ms - k1.time_ms) as f32;
+    let t = k1.easing.apply(segment_t);
+    match self.interpolation {
+      PathInterpolation::Linear => (k1.pos.lerp(k2.pos, t), k1.look_at.lerp(k2.look_at, t)),
+      PathInterpolation::CatmullRom => {
+        // End segments reuse the end key as the missing control point
+        let k0 = self.keys[next_idx.saturating_sub(2)];
+        let k3 = self.keys[(next_idx + 1).min(self.keys.len() - 1)];
+        (
+          catmull_rom(k0.pos, k1.pos, k2.pos, k3.pos, t),
+          catmull_rom(k0.look_at, k1.look_at, k2.look_at, k3.look_at, t),
+        )
+      }
+    }
+  }
+}
+
+// Drives the game camera along a path for cutscenes
+pub struct CameraAnimator {
+  path: CameraPath,
+  time_ms: f32,
+  playing: bool,
+  looping: bool,
+}
+
+impl CameraAnimator {
+  pub fn new(path: CameraPath, looping: bool) -> Self {
+    Self { path, time_ms: 0.0, playing: false, looping }
+  }
+
+  pub fn play(&mut self) {
+    if !self.looping && self.is_finished() {
+      self.time_ms = 0.0;
+    }
+    self.playing = true;
+  }
+
+  pub fn pause(&mut self) {
+    self.playing = false;
+  }
+
+  pub fn seek(&mut self, time_ms: u128) {
+    self.time_ms = time_ms.min(self.path.duration_ms()) as f32;
+  }
+
+  pub fn is_playing(&self) -> bool {
+    self.playing
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.time_ms >= self.path.duration_ms() as f32
+  }
+
+  // frame_time is in microseconds like the rest of the game update
+  pub fn update(&mut self, frame_time: u128) {
+    if !self.playing {
+      return;
+    }
+    let duration = self.path.duration_ms() as f32;
+    self.time_ms += frame_time as f32 / 1000.0;
+    if self.time_ms >= duration {
+      if self.looping && duration > 0.0 {
+        self.time_ms %= duration;
+      } else {
+        self.time_ms = duration;
+        self.playing = false;
+      }
+    }
+  }
+
+  pub fn apply(&self, camera: &mut Camera3D) {
+    let (pos, look_at) = self.path.sample(self.time_ms);
+    camera.pos = pos.extend(1.0);
+    camera.look_dir = (look_at - pos).extend(1.0);
+  }
+}
@@ -0,0 +1,92 @@
+use render_manager::{glam, Camera3D};
+
+use crate::camera_animator::Easing;
+use crate::scene::SceneCameraBookmark;
+
+const TRANSITION_MS: f32 = 500.0;
+
+// Where the camera is, where it looks and how wide, fov is vertical in radians
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraPose {
+  pub pos: glam::Vec3,
+  pub look_dir: glam::Vec3,
+  pub fov: f32,
+}
+
+impl CameraPose {
+  pub fn of_camera(camera: &Camera3D, fov: f32) -> Self {
+    Self {
+      pos: camera.pos.truncate(),
+      look_dir: camera.look_dir.truncate().normalize_or(glam::Vec3::NEG_Z),
+      fov,
+    }
+  }
+
+  pub fn of_bookmark(bookmark: &SceneCameraBookmark) -> Self {
+    Self {
+      pos: glam::Vec3::from(bookmark.position),
+      look_dir: glam::Vec3::from(bookmark.look_dir).normalize_or(glam::Vec3::NEG_Z),
+      fov: bookmark.fov_deg.to_radians(),
+    }
+  }
+
+  pub fn to_bookmark(self, name: &str) -> SceneCameraBookmark {
+    SceneCameraBookmark {
+      name: name.to_string(),
+      position: self.pos.to_array(),
+      look_dir: self.look_dir.to_array(),
+      fov_deg: self.fov.to_degrees(),
+    }
+  }
+
+  // Directions turn through the shorter way round
+  fn lerp(&self, to: &Self, t: f32) -> Self {
+    let turn = glam::Quat::from_rotation_arc(self.look_dir, to.look_dir);
+    Self {
+      pos: self.pos.lerp(to.pos, t),
+      look_dir: glam::Quat::IDENTITY.slerp(turn, t) * self.look_dir,
+      fov: self.fov + (to.fov - self.fov) * t,
+    }
+  }
+
+  pub fn apply(&self, camera: &mut Camera3D, fov: &mut f32) {
+    camera.pos = self.pos.extend(1.0);
+    camera.look_dir = self.look_dir.extend(0.0);
+    *fov = self.fov;
+  }
+}
+
+struct Transition {
+  from: CameraPose,
+  to: CameraPose,
+  elapsed_ms: f32,
+}
+
+// Eases the camera over to a bookmark instead of cutting to it
+#[derive(Default)]
+pub struct CameraBookmarks {
+  transition: Option<Transition>,
+}
+
+impl CameraBookmarks {
+  // Starts from wherever the camera is, even halfway through another jump
+  pub fn jump(&mut self, from: CameraPose, bookmark: &SceneCameraBookmark) {
+    self.transition =
+      Some(Transition { from, to: CameraPose::of_bookmark(bookmark), elapsed_ms: 0.0 });
+  }
+
+  pub fn cancel(&mut self) {
+    self.transition = None;
+  }
+
+  // frame_time is in microseconds like the rest of the game update
+  pub fn update(&mut self, frame_time: u128, camera: &mut Camera3D, fov: &mut f32) {
+    let Some(transition) = self.transition.as_mut() else { return };
+    transition.elapsed_ms += frame_time as f32 / 1000.0;
+    let t = (transition.elapsed_ms / TRANSITION_MS).min(1.0);
+    transition.from.lerp(&transition.to, Easing::EaseInOut.apply(t)).apply(camera, fov);
+    if t >= 1.0 {
+      self.transition = None;
+    }
+  }
+}
@@ -0,0 +1,171 @@
+use geometry::smoothing::{damp, easing, exp_decay};
+use render_manager::{glam, Camera3D};
+use rng::RngStream;
+
+const NOISE_LATTICE: usize = 256;
+// Far apart spots of the noise lattice so every shake channel moves on its own
+const CHANNEL_OFFSETS: [f32; 5] = [0.0, 41.3, 87.9, 131.7, 197.1];
+
+// 1D Perlin noise over a seeded lattice of gradients, returns roughly -1..1 and repeats every
+// NOISE_LATTICE units
+#[derive(Debug, Clone)]
+pub struct GradientNoise {
+  gradients: Vec<f32>,
+}
+
+impl GradientNoise {
+  pub fn new(seed: u64) -> Self {
+    let mut stream = RngStream::from_seed(seed);
+    Self { gradients: (0..NOISE_LATTICE).map(|_| stream.range_f32(-1.0, 1.0)).collect() }
+  }
+
+  pub fn sample(&self, x: f32) -> f32 {
+    let cell = x.floor();
+    let t = x - cell;
+    let idx = (cell as i64).rem_euclid(NOISE_LATTICE as i64) as usize;
+    let g0 = self.gradients[idx];
+    let g1 = self.gradients[(idx + 1) % NOISE_LATTICE];
+    let fade = easing::smootherstep(t);
+    // A lone gradient tops out at half a unit, double it to fill -1..1
+    2.0 * (g0 * t + (g1 * (t - 1.0) - g0 * t) * fade)
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CameraShakeConfig {
+  // Offsets at full trauma, angles in radians and offsets in world units
+  pub max_yaw: f32,
+  pub max_pitch: f32,
+  pub max_offset: f32,
+  // Noise lattice cells passed per second, higher is a faster rattle
+  pub frequency: f32,
+  // Trauma lost per second
+  pub trauma_decay: f32,
+  // Fraction of the recoil kick that recovers per second
+  pub recoil_recovery: f32,
+  // Fraction of the gap to the tilt and lean targets closed per second
+  pub tilt_smoothing: f32,
+}
+
+impl Default for CameraShakeConfig {
+  fn default() -> Self {
+    Self {
+      max_yaw: 0.08,
+      max_pitch: 0.08,
+      max_offset: 0.15,
+      frequency: 25.0,
+      trauma_decay: 1.0,
+      recoil_recovery: 8.0,
+      tilt_smoothing: 10.0,
+    }
+  }
+}
+
+// Offsets layered over whatever moved the camera this frame. The base camera is never changed so
+// controls and picking keep working on the steady camera
+pub struct CameraEffects {
+  config: CameraShakeConfig,
+  noise: GradientNoise,
+  // 0..1, the shake grows with its square so small hits stay subtle
+  trauma: f32,
+  time_s: f32,
+  // Pitch and yaw kick in radians, eases back to zero
+  recoil: glam::Vec2,
+  tilt: f32,
+  tilt_target: f32,
+  lean: f32,
+  lean_target: f32,
+}
+
+impl CameraEffects {
+  pub fn new(config: CameraShakeConfig, seed: u64) -> Self {
+    Self {
+      config,
+      noise: GradientNoise::new(seed),
+      trauma: 0.0,
+      time_s: 0.0,
+      recoil: glam::Vec2::ZERO,
+      tilt: 0.0,
+      tilt_target: 0.0,
+      lean: 0.0,
+      lean_target: 0.0,
+    }
+  }
+
+  pub fn trauma(&self) -> f32 {
+    self.trauma
+  }
+
+  // Explosions and hits stack up to full trauma
+  pub fn add_trauma(&mut self, amount: f32) {
+    self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+  }
+
+  // Instant kick in radians, positive pitch looks up and positive yaw turns right
+  pub fn add_recoil(&mut self, pitch: f32, yaw: f32) {
+    self.recoil += glam::vec2(pitch, yaw);
+  }
+
+  // Held pitch offset in radians the camera eases into, for aiming down or bracing
+  pub fn set_tilt(&mut self, pitch: f32) {
+    self.tilt_target = pitch;
+  }
+
+  // Held sideways offset in world units, positive is to the right. Camera3D always keeps y up,
+  // so leaning moves the camera without rolling it
+  pub fn set_lean(&mut self, offset: f32) {
+    self.lean_target = offset;
+  }
+
+  pub fn clear(&mut self) {
+    self.trauma = 0.0;
+    self.recoil = glam::Vec2::ZERO;
+    self.tilt = 0.0;
+    self.tilt_target = 0.0;
+    self.lean = 0.0;
+    self.lean_target = 0.0;
+  }
+
+  // frame_time is in microseconds like the rest of the game update
+  pub fn update(&mut self, frame_time: u128) {
+    let dt = frame_time as f32 / 1_000_000.0;
+    self.time_s += dt;
+    self.trauma = (self.trauma - self.config.trauma_decay * dt).max(0.0);
+    self.recoil = glam::vec2(
+      exp_decay(self.recoil.x, self.config.recoil_recovery, dt),
+      exp_decay(self.recoil.y, self.config.recoil_recovery, dt),
+    );
+    self.tilt = damp(self.tilt, self.tilt_target, self.config.tilt_smoothing, dt);
+    self.lean = damp(self.lean, self.lean_target, self.config.tilt_smoothing, dt);
+  }
+
+  fn channel(&self, channel: usize) -> f32 {
+    self.noise.sample(self.time_s * self.config.frequency + CHANNEL_OFFSETS[channel])
+  }
+
+  pub fn apply(&self, camera: &Camera3D) -> Camera3D {
+    let shake = self.trauma * self.trauma;
+    let yaw = self.config.max_yaw * shake * self.channel(0) + self.recoil.y;
+    let pitch = self.config.max_pitch * shake * self.channel(1) + self.recoil.x + self.tilt;
+    let offset = glam::vec3(self.channel(2), self.channel(3), self.channel(4))
+      * self.config.max_offset
+      * shake;
+
+    let look_dir = camera.look_dir.truncate().normalize_or_zero();
+    let right = look_dir.cross(glam::Vec3::Y).normalize_or_zero();
+    if look_dir == glam::Vec3::ZERO || right == glam::Vec3::ZERO {
+      return *camera;
+    }
+    // Keep clear of straight up and down, look_at with y up breaks there
+    let max_pitch = std::f32::consts::FRAC_PI_2 - 0.01;
+    let current_pitch = look_dir.y.asin();
+    let pitch = (current_pitch + pitch).clamp(-max_pitch, max_pitch) - current_pitch;
+    let rotation =
+      glam::Quat::from_axis_angle(glam::Vec3::Y, -yaw) * glam::Quat::from_axis_angle(right, pitch);
+
+    let mut shaken = *camera;
+    shaken.look_dir = (rotation * camera.look_dir.truncate()).extend(camera.look_dir.w);
+    shaken.pos = (camera.pos.truncate() + offset + right * self.lean).extend(camera.pos.w);
+    shaken
+  }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use render_manager::{glam, ColorGrading, ColorLutHandle, Renderer, RendererMessage};
+
+use crate::scene::{Scene, SceneGradingVolume};
+
+// Grades the frame by the grading volume the camera is in, fading between luts over the volume's
+// transition time as it crosses into or out of one
+pub struct GradingVolumes {
+  volumes: Vec<(SceneGradingVolume, ColorLutHandle)>,
+  // Outside every volume
+  default_lut: Option<ColorLutHandle>,
+  // One per file, volumes sharing a lut share its handle
+  luts: Vec<ColorLutHandle>,
+  grading: ColorGrading,
+  // Of the fade running now
+  transition_s: f32,
+}
+
+impl GradingVolumes {
+  // Queues the scene's luts for loading
+  pub fn spawn(
+    renderer: &mut Renderer,
+    scene: &Scene,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Self {
+    let mut by_path = HashMap::new();
+    let mut lut_for = |path: &str| {
+      *by_path.entry(path.to_string()).or_insert_with(|| {
+        let handle = renderer.create_color_lut_handle();
+        messages.push(RendererMessage::LoadColorLut(path.to_string(), handle));
+        handle
+      })
+    };
+    let default_lut = scene.color_lut.as_deref().map(&mut lut_for);
+    let volumes =
+      scene.grading_volumes.iter().map(|volume| (volume.clone(), lut_for(&volume.lut))).collect();
+    Self {
+      volumes,
+      default_lut,
+      luts: by_path.into_values().collect(),
+      grading: ColorGrading { from: default_lut, to: default_lut, blend: 1.0 },
+      transition_s: 0.0,
+    }
+  }
+
+  pub fn destroy(self, messages: &mut Vec<RendererMessage>) {
+    messages.extend(self.luts.into_iter().map(RendererMessage::DestroyColorLut));
+  }
+
+  // Later volumes win where they overlap. Leaving fades out over the time of the volume left
+  fn target(&self, camera_pos: glam::Vec3) -> (Option<ColorLutHandle>, f32) {
+    self
+      .volumes
+      .iter()
+      .rev()
+      .find(|(volume, _)| volume.contains(camera_pos))
+      .map(|(volume, lut)| (Some(*lut), volume.transition_s))
+      .unwrap_or((self.default_lut, self.transition_s))
+  }
+
+  pub fn update(&mut self, camera_pos: glam::Vec3, frame_time: u128) {
+    let (target, transition_s) = self.target(camera_pos);
+    if target != self.grading.to {
+      self.grading = if target == self.grading.from {
+        // Turning back part way fades back from as far as it got
+        ColorGrading { from: self.grading.to, to: target, blend: 1.0 - self.grading.blend }
+      } else {
+        // Starts from whichever lut shows more, a third lut can't be mixed in
+        let shown = if self.grading.blend < 0.5 { self.grading.from } else { self.grading.to };
+        ColorGrading { from: shown, to: target, blend: 0.0 }
+      };
+      self.transition_s = transition_s;
+    }
+    let step = match self.transition_s > 0.0 {
+      true => frame_time as f32 / 1_000_000.0 / self.transition_s,
+      false => 1.0,
+    };
+    self.grading.blend = (self.grading.blend + step).min(1.0);
+  }
+
+  // None with no luts in the scene at all
+  pub fn grading(&self) -> Option<ColorGrading> {
+    (self.grading.from.is_some() || self.grading.to.is_some()).then_some(self.grading)
+  }
+}
@@ -0,0 +1,191 @@
+use animation::{
+  clip::{AnimationClip, JointTrack},
+  gltf_import::import_gltf_characters,
+  skeleton::{Joint, Skeleton, SkinnedMesh, Transform},
+  KeyFramed,
+};
+use render_manager::{
+  BoneAnimationCPU, CrowdHandle, CrowdInstance, CrowdMeshCPU, CrowdState, CrowdVertex,
+  MaterialHandle, Renderer, RendererMessage, TriMeshCPU,
+};
+
+const CROWD_BAKE_FPS: f32 = 30.0;
+const CROWD_SPACING: f32 = 1.5;
+const MAX_CROWD_SIZE: u32 = 4096;
+const STALK_SEGMENTS: usize = 4;
+const STALK_SEGMENT_HEIGHT: f32 = 0.4;
+const STALK_SWAY_MS: u128 = 2000;
+
+// Grid of the same animated character, each one at its own point in the loop. The whole crowd is
+// skinned on the GPU and drawn with one instanced draw
+pub struct Crowd {
+  handle: CrowdHandle,
+  instances: Vec<CrowdInstance>,
+}
+
+impl Crowd {
+  // Without a glTF path every instance is a built in swaying stalk, otherwise the first skinned
+  // character in the file playing its first clip
+  pub fn spawn(
+    renderer: &mut Renderer,
+    count: u32,
+    gltf_path: Option<&str>,
+    material: MaterialHandle,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<Self, String> {
+    let count = count.clamp(1, MAX_CROWD_SIZE);
+    let (skeleton, mesh, clip) = match gltf_path {
+      Some(path) => Self::load_character(path)?,
+      None => Self::make_stalk()?,
+    };
+    let loop_s = (clip.duration_ms() as f32 / 1000.0).max(1.0 / CROWD_BAKE_FPS);
+    let animation = BoneAnimationCPU {
+      joint_count: skeleton.joints().len() as u32,
+      frame_rate: CROWD_BAKE_FPS,
+      matrices: clip.bake(&skeleton, CROWD_BAKE_FPS),
+    };
+
+    let columns = (count as f32).sqrt().ceil() as u32;
+    let half_width = (columns - 1) as f32 * CROWD_SPACING / 2.0;
+    let instances = (0..count)
+      .map(|i| {
+        let pos = glam::vec3(
+          (i % columns) as f32 * CROWD_SPACING - half_width,
+          0.0,
+          (i / columns) as f32 * CROWD_SPACING - half_width,
+        );
+        // Golden ratio steps spread the facing and the loop phase without visible patterns
+        let phase = (i as f32 * 0.618_034).fract();
+        CrowdInstance::new(
+          glam::Mat4::from_rotation_translation(
+            glam::Quat::from_rotation_y(phase * std::f32::consts::TAU),
+            pos,
+          ),
+          phase * loop_s,
+        )
+      })
+      .collect::<Vec<_>>();
+
+    let handle = renderer.create_crowd_handle();
+    messages.push(RendererMessage::CreateCrowd(
+      format!("crowd_{handle:?}"),
+      mesh,
+      animation,
+      count,
+      Some(material),
+      handle,
+    ));
+    Ok(Self { handle, instances })
+  }
+
+  pub fn destroy(self, messages: &mut Vec<RendererMessage>) {
+    messages.push(RendererMessage::DestroyCrowd(self.handle));
+  }
+
+  // frame_time in microseconds, animation times keep growing and wrap on the GPU
+  pub fn update(&mut self, frame_time: u128) {
+    let frame_time_s = frame_time as f32 / 1_000_000.0;
+    for instance in self.instances.iter_mut() {
+      instance.anim.x += frame_time_s;
+    }
+  }
+
+  pub fn state(&self) -> CrowdState {
+    CrowdState { crowd: self.handle, instances: self.instances.clone() }
+  }
+
+  fn load_character(path: &str) -> Result<(Skeleton, CrowdMeshCPU, AnimationClip), String> {
+    let character = import_gltf_characters(vfs::global(), asset_cache::global(), path)?
+      .into_iter()
+      .find(|character| !character.meshes.is_empty() && !character.clips.is_empty())
+      .ok_or(format!("no animated skinned character in {path}"))?;
+    let mut mesh = CrowdMeshCPU::default();
+    for skinned_mesh in character.meshes.iter() {
+      Self::append_skinned_mesh(&mut mesh, skinned_mesh);
+    }
+    let clip = character.clips.into_iter().next().ok_or(format!("no clip in {path}"))?;
+    Ok((character.skeleton, mesh, clip))
+  }
+
+  fn append_skinned_mesh(mesh: &mut CrowdMeshCPU, skinned_mesh: &SkinnedMesh) {
+    let vert_offset = mesh.verts.len() as u32;
+    mesh.verts.extend(skinned_mesh.vertices.iter().map(|vert| CrowdVertex {
+      pos: vert.pos.extend(1.0),
+      normal: vert.normal.extend(0.0),
+      uv: glam::vec4(vert.uv.x, vert.uv.y, 0.0, 0.0),
+      joints: vert.joints.map(|joint| joint as u32),
+      weights: glam::Vec4::from_array(vert.weights),
+    }));
+    mesh
+      .triangles
+      .extend(skinned_mesh.triangles.iter().map(|triangle| triangle.map(|idx| idx + vert_offset)));
+  }
+
+  // A column of boxes, one per joint, bending back and forth along its length
+  fn make_stalk() -> Result<(Skeleton, CrowdMeshCPU, AnimationClip), String> {
+    let joints = (0..STALK_SEGMENTS)
+      .map(|i| {
+        let rest_translation = match i {
+          0 => glam::Vec3::ZERO,
+          _ => glam::vec3(0.0, STALK_SEGMENT_HEIGHT, 0.0),
+        };
+        Joint {
+          name: format!("stalk_{i}"),
+          parent: i.checked_sub(1),
+          rest: Transform { translation: rest_translation, ..Default::default() },
+          inverse_bind: glam::Mat4::from_translation(glam::vec3(
+            0.0,
+            -(i as f32) * STALK_SEGMENT_HEIGHT,
+            0.0,
+          )),
+          base: glam::Mat4::IDENTITY,
+        }
+      })
+      .collect::<Vec<_>>();
+    let skeleton = Skeleton::new(joints)?;
+
+    let mut mesh = CrowdMeshCPU::default();
+    for i in 0..STALK_SEGMENTS {
+      // Thinner towards the top
+      let width = 0.3 - i as f32 * 0.05;
+      let segment = TriMeshCPU::make_cuboid(
+        glam::vec3(0.0, (i as f32 + 0.5) * STALK_SEGMENT_HEIGHT, 0.0),
+        glam::vec3(width, 0.0, 0.0),
+        glam::vec3(0.0, STALK_SEGMENT_HEIGHT, 0.0),
+        width,
+      );
+      let vert_offset = mesh.verts.len() as u32;
+      mesh.verts.extend(segment.vertices.iter().map(|vert| CrowdVertex {
+        pos: vert.pos,
+        normal: vert.normal.truncate().extend(0.0),
+        uv: vert.uv,
+        joints: [i as u32, 0, 0, 0],
+        weights: glam::Vec4::X,
+      }));
+      mesh
+        .triangles
+        .extend(segment.triangles.iter().map(|triangle| triangle.map(|idx| idx + vert_offset)));
+    }
+
+    let sway_keys = |amplitude: f32| {
+      (0..=4)
+        .map(|k| {
+          let angle = amplitude * (k as f32 * std::f32::consts::FRAC_PI_2).sin();
+          (STALK_SWAY_MS * k / 4, glam::Quat::from_rotation_z(angle))
+        })
+        .collect::<Vec<_>>()
+    };
+    let clip = AnimationClip {
+      name: "stalk_sway".to_string(),
+      tracks: (0..STALK_SEGMENTS)
+        .map(|joint| JointTrack {
+          joint,
+          translation: None,
+          rotation: Some(KeyFramed::linear(sway_keys(0.1 + joint as f32 * 0.05))),
+          scale: None,
+        })
+        .collect(),
+    };
+    Ok((skeleton, mesh, clip))
+  }
+}
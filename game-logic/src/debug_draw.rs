@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+
+use physics::PhysicsEngine;
+use render_manager::{glam, Color, DebugLine};
+
+// Half the size of the cross drawn at each contact point
+const CONTACT_MARK_SIZE: f32 = 0.05;
+const CONTACT_NORMAL_LENGTH: f32 = 0.5;
+// Seconds of travel the velocity line shows
+const VELOCITY_SCALE: f32 = 0.25;
+const BOUNDS_COLOR: Color = Color::YELLOW;
+const SHAPE_COLOR: Color = Color::GREEN;
+const VELOCITY_COLOR: Color = Color::from_srgb(0.3, 0.6, 1.0, 1.0);
+const CONTACT_COLOR: Color = Color::RED;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugView {
+  Bounds,
+  Shape,
+  Velocity,
+  Contacts,
+}
+
+impl DebugView {
+  pub const ALL: [Self; 4] = [Self::Bounds, Self::Shape, Self::Velocity, Self::Contacts];
+
+  pub fn parse(name: &str) -> Option<Self> {
+    match name {
+      "bounds" => Some(Self::Bounds),
+      "shape" => Some(Self::Shape),
+      "velocity" => Some(Self::Velocity),
+      "contacts" => Some(Self::Contacts),
+      _ => None,
+    }
+  }
+}
+
+// What's drawn for which physics bodies, toggled from the console. Lines are made again every
+// tick from where the bodies are, so they follow them
+#[derive(Debug, Default)]
+pub struct DebugDraw {
+  views: BTreeMap<String, [bool; DebugView::ALL.len()]>,
+}
+
+impl DebugDraw {
+  pub fn is_empty(&self) -> bool {
+    self.views.is_empty()
+  }
+
+  // Returns whether the view is on now
+  pub fn toggle(&mut self, body: &str, view: DebugView) -> bool {
+    let views = self.views.entry(body.to_string()).or_default();
+    views[view as usize] = !views[view as usize];
+    let enabled = views[view as usize];
+    if !views.contains(&true) {
+      self.views.remove(body);
+    }
+    enabled
+  }
+
+  pub fn clear(&mut self) {
+    self.views.clear();
+  }
+
+  // Bodies that are gone draw nothing until they're back
+  pub fn lines(&self, physics_engine: &PhysicsEngine) -> Vec<DebugLine> {
+    let mut lines = vec![];
+    for (body, views) in self.views.iter() {
+      let Some(shape) = physics_engine.body_debug_shape(body) else { continue };
+      let is_on = |view: DebugView| views[view as usize];
+      if let Some((min, max)) = shape.bounds.filter(|_| is_on(DebugView::Bounds)) {
+        lines.extend(box_edges(min, max).map(|(a, b)| DebugLine::new(a, b, BOUNDS_COLOR)));
+      }
+      if is_on(DebugView::Shape) {
+        lines.extend(shape.edges.iter().map(|(a, b)| DebugLine::new(*a, *b, SHAPE_COLOR)));
+      }
+      if let Some((min, max)) = shape.bounds.filter(|_| is_on(DebugView::Velocity)) {
+        let center = (min + max) * 0.5;
+        let end = center + shape.velocity * VELOCITY_SCALE;
+        lines.push(DebugLine::new(center, end, VELOCITY_COLOR));
+      }
+      if is_on(DebugView::Contacts) {
+        for (point, normal) in shape.contacts.iter() {
+          for axis in [glam::Vec3::X, glam::Vec3::Y, glam::Vec3::Z] {
+            let reach = axis * CONTACT_MARK_SIZE;
+            lines.push(DebugLine::new(point - reach, point + reach, CONTACT_COLOR));
+          }
+          let end = point + *normal * CONTACT_NORMAL_LENGTH;
+          lines.push(DebugLine::new(*point, end, CONTACT_COLOR));
+        }
+      }
+    }
+    lines
+  }
+}
+
+fn box_edges(min: glam::Vec3, max: glam::Vec3) -> impl Iterator<Item = (glam::Vec3, glam::Vec3)> {
+  let corner = move |i: usize| {
+    glam::vec3(
+      if i & 1 == 0 { min.x } else { max.x },
+      if i & 2 == 0 { min.y } else { max.y },
+      if i & 4 == 0 { min.z } else { max.z },
+    )
+  };
+  // Corners one bit apart share an edge
+  (0..8usize).flat_map(move |i| {
+    [1, 2, 4]
+      .into_iter()
+      .filter(move |bit| i & bit == 0)
+      .map(move |bit| (corner(i), corner(i | bit)))
+  })
+}
@@ -0,0 +1,351 @@
+use input_aggregator::{InputAggregator, Key, MouseButton, NamedKey};
+use localization::tr;
+use geometry::{Aabb, Direction, Plane, Point, Ray};
+use render_manager::{Camera3D, Color, Gizmo, GizmoAxis, GizmoMode, UiShape};
+
+use crate::hud;
+use crate::scene::{PhysicsProperties, Scene, SceneObject, SceneShape};
+
+// Changes the game has to mirror to the renderer after an editor update
+#[derive(Debug, Clone, Copy)]
+pub enum SceneChange {
+  Spawned(usize),
+  // Index the object had before it was removed
+  Removed(usize),
+  Moved(usize),
+  Reshaped(usize),
+}
+
+// Wide enough for the longest field at HUD_TEXT_SIZE, names run past it
+const PROPERTY_PANEL_WIDTH: f32 = 220.0;
+const PROPERTY_PANEL_PADDING: f32 = 8.0;
+const PROPERTY_PANEL_COLOR: Color = Color::new(0.0, 0.0, 0.0, 0.6);
+const PROPERTY_PANEL_HIGHLIGHT: Color = Color::from_srgb(1.0, 0.85, 0.3, 1.0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PropertyField {
+  Physics,
+  Dynamic,
+  Mass,
+}
+
+impl PropertyField {
+  fn next(&self) -> Self {
+    match self {
+      PropertyField::Physics => PropertyField::Dynamic,
+      PropertyField::Dynamic => PropertyField::Mass,
+      PropertyField::Mass => PropertyField::Physics,
+    }
+  }
+
+  fn prev(&self) -> Self {
+    match self {
+      PropertyField::Physics => PropertyField::Mass,
+      PropertyField::Dynamic => PropertyField::Physics,
+      PropertyField::Mass => PropertyField::Dynamic,
+    }
+  }
+}
+
+struct GizmoDrag {
+  object: usize,
+  axis: GizmoAxis,
+  start_transform: glam::Mat4,
+  start_param: f32,
+  scale: glam::Vec3,
+}
+
+pub struct Editor {
+  selected: Option<usize>,
+  gizmo_mode: GizmoMode,
+  hovered_axis: Option<GizmoAxis>,
+  drag: Option<GizmoDrag>,
+  property_field: PropertyField,
+}
+
+impl Editor {
+  pub fn new() -> Self {
+    Self {
+      selected: None,
+      gizmo_mode: GizmoMode::Translate,
+      hovered_axis: None,
+      drag: None,
+      property_field: PropertyField::Physics,
+    }
+  }
+
+  // Index into the scene's objects
+  #[cfg(feature = "physics")]
+  pub fn selected(&self) -> Option<usize> {
+    self.selected
+  }
+
+  pub fn gizmo(&self, scene: &Scene) -> Option<Gizmo> {
+    let obj = scene.objects.get(self.selected?)?;
+    let mut gizmo = Gizmo::new(self.gizmo_mode, obj.transform());
+    gizmo.highlighted = self.drag.as_ref().map(|drag| drag.axis).or(self.hovered_axis);
+    Some(gizmo)
+  }
+
+  // Transform to display an object with, includes the scale of an unfinished scale drag
+  pub fn display_transform(&self, scene: &Scene, idx: usize) -> Option<glam::Mat4> {
+    let transform = scene.objects.get(idx)?.transform();
+    match &self.drag {
+      Some(drag) if drag.object == idx => Some(transform * glam::Mat4::from_scale(drag.scale)),
+      _ => Some(transform),
+    }
+  }
+
+  fn axis_param(gizmo: &Gizmo, axis: GizmoAxis, ray_origin: glam::Vec3, ray_dir: glam::Vec3) -> Option<f32> {
+    let (_, rotation, origin) = gizmo.transform.to_scale_rotation_translation();
+    let ray = Ray::new(Point::from_vec3(ray_origin), Direction::from_vec3(ray_dir));
+    let axis_dir = rotation * axis.dir();
+    match gizmo.mode {
+      GizmoMode::Translate | GizmoMode::Scale => {
+        // Closest point on the axis line to the picking ray
+        ray
+          .closest_point_on_axis(Point::from_vec3(origin), Direction::from_vec3(axis_dir))
+          .map(|(_, along)| along)
+      }
+      GizmoMode::Rotate => {
+        // Angle of the picking ray hit on the rotation plane
+        let plane = Plane::new(Direction::from_vec3(axis_dir), Point::from_vec3(origin));
+        let (hit, _) = ray.intersection_with_plane(plane)?;
+        Some(plane.angle_around_normal(&hit))
+      }
+    }
+  }
+
+  fn pick_object(scene: &Scene, ray_origin: glam::Vec3, ray_dir: glam::Vec3) -> Option<usize> {
+    Self::pick_hit(scene, ray_origin, ray_dir).map(|(i, _)| i)
+  }
+
+  // Nearest object the ray goes into, with the distance along the ray to where it enters
+  fn pick_hit(scene: &Scene, ray_origin: glam::Vec3, ray_dir: glam::Vec3) -> Option<(usize, f32)> {
+    let ray = Ray::new(Point::from_vec3(ray_origin), Direction::from_vec3(ray_dir));
+    scene
+      .objects
+      .iter()
+      .enumerate()
+      .filter_map(|(i, obj)| {
+        // Test in object space, transforms only have rotation and translation so distances hold
+        let local_ray = ray.transform(obj.transform().inverse());
+        let half_extents = obj.shape.half_extents();
+        let bounds = Aabb::from_min_max(-half_extents, half_extents);
+        local_ray.intersection_with_aabb(&bounds).map(|(t_enter, _)| (i, t_enter))
+      })
+      .min_by(|a, b| a.1.total_cmp(&b.1))
+  }
+
+  // Adds obj sitting on whatever the cursor points at, or out in front of the camera when it
+  // points at nothing, and selects it
+  pub fn place(
+    &mut self,
+    scene: &mut Scene,
+    camera: &Camera3D,
+    cursor_pos: Option<glam::Vec2>,
+    mut obj: SceneObject,
+  ) -> SceneChange {
+    let (ray_origin, ray_dir) = match cursor_pos {
+      Some(cursor_pos) => camera.picking_ray(cursor_pos),
+      None => (camera.pos.truncate(), camera.look_dir.truncate().normalize()),
+    };
+    let position = match Self::pick_hit(scene, ray_origin, ray_dir) {
+      Some((_, t_enter)) => {
+        ray_origin + ray_dir * t_enter + glam::Vec3::Y * obj.shape.half_extents().y
+      }
+      None => ray_origin + ray_dir * 5.0,
+    };
+    obj.position = position.to_array();
+    scene.objects.push(obj);
+    self.selected = Some(scene.objects.len() - 1);
+    self.drag = None;
+    SceneChange::Spawned(scene.objects.len() - 1)
+  }
+
+  // The selected object's properties on a HUD panel, with the field the arrow keys edit
+  // highlighted. Returns the shapes and the panel's size, zero without one
+  pub fn property_panel(&self, scene: &Scene, top_left: glam::Vec2) -> (Vec<UiShape>, glam::Vec2) {
+    let Some(obj) = self.selected.and_then(|idx| scene.objects.get(idx)) else {
+      return (vec![], glam::Vec2::ZERO);
+    };
+    let field_line = |field, text| match self.property_field == field {
+      true => (format!("> {text}"), PROPERTY_PANEL_HIGHLIGHT),
+      false => (format!("  {text}"), Color::WHITE),
+    };
+    let mut lines = vec![(obj.name.clone(), Color::WHITE)];
+    let physics = tr!("editor.physics", value = obj.physics.is_some());
+    lines.push(field_line(PropertyField::Physics, physics));
+    if let Some(physics) = obj.physics {
+      let dynamic = tr!("editor.dynamic", value = physics.dynamic);
+      lines.push(field_line(PropertyField::Dynamic, dynamic));
+      let mass = tr!("editor.mass", value = format!("{:.2}", physics.mass));
+      lines.push(field_line(PropertyField::Mass, mass));
+    }
+    let padding = glam::Vec2::splat(PROPERTY_PANEL_PADDING);
+    let (text, text_bottom) = hud::text_lines(&lines, top_left + padding);
+    let height = text_bottom + PROPERTY_PANEL_PADDING - top_left.y;
+    let mut shapes = vec![UiShape::RoundedRect {
+      rect: glam::vec4(top_left.x, top_left.y, PROPERTY_PANEL_WIDTH, height),
+      radius: 6.0,
+      outline: 0.0,
+      color: PROPERTY_PANEL_COLOR,
+    }];
+    shapes.extend(text);
+    (shapes, glam::vec2(PROPERTY_PANEL_WIDTH, height))
+  }
+
+  fn edit_property(&mut self, scene: &mut Scene, step: f32) {
+    let Some(obj) = self.selected.and_then(|idx| scene.objects.get_mut(idx)) else { return };
+    match (self.property_field, &mut obj.physics) {
+      (PropertyField::Physics, physics) => {
+        *physics = match physics {
+          Some(_) => None,
+          None => Some(PhysicsProperties { dynamic: false, mass: 1.0 }),
+        };
+      }
+      (PropertyField::Dynamic, Some(physics)) => physics.dynamic = !physics.dynamic,
+      (PropertyField::Mass, Some(physics)) => physics.mass = (physics.mass + step).max(0.0),
+      _ => {}
+    }
+  }
+
+  // camera and cursor_pos are as the scene is drawn, see render_manager::view_camera
+  pub fn update(
+    &mut self,
+    inputs: &InputAggregator,
+    camera: &Camera3D,
+    cursor_pos: Option<glam::Vec2>,
+    scene: &mut Scene,
+  ) -> Vec<SceneChange> {
+    let mut changes = vec![];
+
+    if inputs.is_key_pressed(Key::Character("w".into())).is_just_pressed() {
+      self.gizmo_mode = GizmoMode::Translate;
+    }
+    if inputs.is_key_pressed(Key::Character("e".into())).is_just_pressed() {
+      self.gizmo_mode = GizmoMode::Rotate;
+    }
+    if inputs.is_key_pressed(Key::Character("r".into())).is_just_pressed() {
+      self.gizmo_mode = GizmoMode::Scale;
+    }
+
+    if inputs.is_key_pressed(Key::Character("n".into())).is_just_pressed() {
+      let spawn_pos = camera.pos.truncate() + camera.look_dir.truncate().normalize() * 5.0;
+      scene.objects.push(SceneObject {
+        name: scene.unique_name("cube"),
+        shape: SceneShape::Cuboid { size: [1.0, 1.0, 1.0] },
+        position: spawn_pos.to_array(),
+        rotation: glam::Quat::IDENTITY.to_array(),
+        physics: Some(PhysicsProperties { dynamic: true, mass: 1.0 }),
+        script: None,
+        model: None,
+        texture: None,
+        prefab: None,
+      });
+      self.selected = Some(scene.objects.len() - 1);
+      changes.push(SceneChange::Spawned(scene.objects.len() - 1));
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::Delete)).is_just_pressed() {
+      if let Some(idx) = self.selected.take() {
+        self.drag = None;
+        scene.objects.remove(idx);
+        changes.push(SceneChange::Removed(idx));
+      }
+    }
+
+    // Property panel, up/down picks a field and left/right edits it
+    if inputs.is_key_pressed(Key::Named(NamedKey::ArrowDown)).is_just_pressed() {
+      self.property_field = self.property_field.next();
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::ArrowUp)).is_just_pressed() {
+      self.property_field = self.property_field.prev();
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::ArrowRight)).is_just_pressed() {
+      self.edit_property(scene, 0.5);
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::ArrowLeft)).is_just_pressed() {
+      self.edit_property(scene, -0.5);
+    }
+
+    let Some(cursor_pos) = cursor_pos else {
+      self.hovered_axis = None;
+      return changes;
+    };
+    let (ray_origin, ray_dir) = camera.picking_ray(cursor_pos);
+    let gizmo = self.gizmo(scene);
+    self.hovered_axis = gizmo
+      .and_then(|gizmo| gizmo.hit_test(camera.pos.truncate(), ray_origin, ray_dir))
+      .map(|(axis, _)| axis);
+
+    let left_mouse = inputs.is_mouse_pressed(MouseButton::Left);
+    if left_mouse.is_just_pressed() {
+      match (gizmo, self.hovered_axis) {
+        (Some(gizmo), Some(axis)) => {
+          self.drag = Self::axis_param(&gizmo, axis, ray_origin, ray_dir).map(|start_param| {
+            GizmoDrag {
+              object: self.selected.unwrap_or_default(),
+              axis,
+              start_transform: gizmo.transform,
+              start_param,
+              scale: glam::Vec3::ONE,
+            }
+          });
+        }
+        _ => {
+          self.selected = Self::pick_object(scene, ray_origin, ray_dir);
+        }
+      }
+    }
+
+    if let Some(drag) = self.drag.as_mut() {
+      if left_mouse.is_pressed() {
+        let drag_gizmo = Gizmo::new(self.gizmo_mode, drag.start_transform);
+        if let Some(param) = Self::axis_param(&drag_gizmo, drag.axis, ray_origin, ray_dir) {
+          let (_, rotation, position) = drag.start_transform.to_scale_rotation_translation();
+          let axis_dir = rotation * drag.axis.dir();
+          let new_transform = match self.gizmo_mode {
+            GizmoMode::Translate => Some(glam::Mat4::from_rotation_translation(
+              rotation,
+              position + axis_dir * (param - drag.start_param),
+            )),
+            GizmoMode::Rotate => Some(glam::Mat4::from_rotation_translation(
+              glam::Quat::from_axis_angle(axis_dir, param - drag.start_param) * rotation,
+              position,
+            )),
+            GizmoMode::Scale => {
+              // Scale is only previewed while dragging, the shape is rebuilt on release
+              if drag.start_param.abs() > 1e-3 {
+                let factor = (param / drag.start_param).max(0.05);
+                drag.scale = glam::Vec3::ONE + drag.axis.dir() * (factor - 1.0);
+              }
+              None
+            }
+          };
+          if let (Some(new_transform), Some(obj)) =
+            (new_transform, scene.objects.get_mut(drag.object))
+          {
+            obj.set_transform(new_transform);
+            changes.push(SceneChange::Moved(drag.object));
+          }
+        }
+      } else {
+        if drag.scale != glam::Vec3::ONE {
+          if let Some(obj) = scene.objects.get_mut(drag.object) {
+            obj.shape = obj.shape.scaled(drag.scale);
+            changes.push(SceneChange::Reshaped(drag.object));
+          }
+        }
+        self.drag = None;
+      }
+    }
+
+    changes
+  }
+}
+
+impl Default for Editor {
+  fn default() -> Self {
+    Self::new()
+  }
+}
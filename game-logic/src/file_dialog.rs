@@ -0,0 +1,80 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DialogKind {
+  Open,
+  Save,
+}
+
+// What the tool printed when it ran and wasn't cancelled. None when it isn't installed
+fn run(command: &mut Command) -> Option<Result<Option<PathBuf>, String>> {
+  let output = match command.output() {
+    Ok(output) => output,
+    Err(e) if e.kind() == ErrorKind::NotFound => return None,
+    Err(e) => return Some(Err(format!("at showing file dialog: {e}"))),
+  };
+  let picked = String::from_utf8_lossy(&output.stdout).trim().to_string();
+  // Every tool exits with an error when the dialog is closed without picking a file
+  Some(Ok((output.status.success() && !picked.is_empty()).then(|| PathBuf::from(picked))))
+}
+
+#[cfg(target_os = "windows")]
+fn show(kind: DialogKind, start_dir: &Path) -> Result<Option<PathBuf>, String> {
+  let dialog = match kind {
+    DialogKind::Open => "OpenFileDialog",
+    DialogKind::Save => "SaveFileDialog",
+  };
+  let script = format!(
+    "Add-Type -AssemblyName System.Windows.Forms; \
+     $d = New-Object System.Windows.Forms.{dialog}; \
+     $d.Filter = 'Scenes (*.toml)|*.toml'; \
+     $d.InitialDirectory = '{}'; \
+     if ($d.ShowDialog() -eq 'OK') {{ $d.FileName }}",
+    start_dir.display().to_string().replace('\'', "''")
+  );
+  run(Command::new("powershell").args(["-NoProfile", "-Command", &script]))
+    .unwrap_or(Err("powershell isn't there to show a file dialog".to_string()))
+}
+
+#[cfg(target_os = "macos")]
+fn show(kind: DialogKind, start_dir: &Path) -> Result<Option<PathBuf>, String> {
+  let location = start_dir.display().to_string().replace('"', "\\\"");
+  let script = match kind {
+    DialogKind::Open => format!(
+      "POSIX path of (choose file with prompt \"Open scene\" of type {{\"toml\"}} \
+       default location POSIX file \"{location}\")"
+    ),
+    DialogKind::Save => format!(
+      "POSIX path of (choose file name with prompt \"Save scene as\" default name \
+       \"scene.toml\" default location POSIX file \"{location}\")"
+    ),
+  };
+  run(Command::new("osascript").args(["-e", &script]))
+    .unwrap_or(Err("osascript isn't there to show a file dialog".to_string()))
+}
+
+// Whichever of zenity and kdialog is installed
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn show(kind: DialogKind, start_dir: &Path) -> Result<Option<PathBuf>, String> {
+  let start = format!("{}/", start_dir.display());
+  let mut zenity = Command::new("zenity");
+  zenity.args(["--file-selection", "--file-filter=*.toml", &format!("--filename={start}")]);
+  if kind == DialogKind::Save {
+    zenity.args(["--save", "--confirm-overwrite"]);
+  }
+  let kdialog_mode = match kind {
+    DialogKind::Open => "--getopenfilename",
+    DialogKind::Save => "--getsavefilename",
+  };
+  run(&mut zenity)
+    .or_else(|| run(Command::new("kdialog").args([kdialog_mode, &start, "*.toml"])))
+    .unwrap_or(Err("neither zenity nor kdialog is there to show a file dialog".to_string()))
+}
+
+// Asks for a scene file with the desktop's own dialog, starting in start_dir. Blocks until it's
+// closed, None when it was cancelled
+pub fn pick_scene_file(kind: DialogKind, start_dir: &Path) -> Result<Option<PathBuf>, String> {
+  show(kind, start_dir)
+}
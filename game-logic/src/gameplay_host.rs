@@ -0,0 +1,80 @@
+use gameplay_api::{EngineHost, Vec3};
+use input_aggregator::{key_from_name, InputAggregator};
+#[cfg(feature = "physics")]
+use physics::PhysicsEngine;
+use render_manager::{ParticleEmitter, ParticleHandle, RendererMessage};
+use rng::RngService;
+
+use crate::{camera_effects::CameraEffects, scene::Scene, GameObject};
+
+// What a gameplay library's tick may touch, borrowed from the game for the tick
+pub struct GameplayContext<'a> {
+  pub inputs: &'a InputAggregator,
+  pub scene: &'a Scene,
+  pub game_objects: &'a [GameObject],
+  #[cfg(feature = "physics")]
+  pub physics_engine: &'a mut PhysicsEngine,
+  pub camera_effects: &'a mut CameraEffects,
+  pub rng: &'a mut RngService,
+  pub sparks: ParticleHandle,
+  pub sparks_emitter: &'a mut ParticleEmitter,
+  pub messages: &'a mut Vec<RendererMessage>,
+}
+
+impl GameplayContext<'_> {
+  fn object_idx(&self, name: &str) -> Option<usize> {
+    self.scene.objects.iter().position(|obj| obj.name == name)
+  }
+}
+
+impl EngineHost for GameplayContext<'_> {
+  fn key_pressed(&self, key: &str) -> bool {
+    self.inputs.is_key_pressed(key_from_name(key)).is_pressed()
+  }
+
+  fn key_just_pressed(&self, key: &str) -> bool {
+    self.inputs.is_key_pressed(key_from_name(key)).is_just_pressed()
+  }
+
+  // Where it was drawn last tick
+  fn object_position(&self, name: &str) -> Option<Vec3> {
+    let game_obj = self.game_objects.get(self.object_idx(name)?)?;
+    let position = game_obj.object_transform.transform.w_axis;
+    Some(Vec3 { x: position.x, y: position.y, z: position.z })
+  }
+
+  #[cfg(feature = "physics")]
+  fn set_object_velocity(&mut self, name: &str, velocity: Vec3) -> bool {
+    let Some(idx) = self.object_idx(name) else { return false };
+    let physics_name = self.scene.objects[idx].physics_name();
+    let velocity = glam::vec3(velocity.x, velocity.y, velocity.z);
+    self.physics_engine.set_velocity(&physics_name, velocity).is_ok()
+  }
+
+  #[cfg(not(feature = "physics"))]
+  fn set_object_velocity(&mut self, _name: &str, _velocity: Vec3) -> bool {
+    false
+  }
+
+  fn emit_sparks(&mut self, origin: Vec3, count: u32) {
+    self.sparks_emitter.origin = glam::vec3(origin.x, origin.y, origin.z);
+    self.messages.push(RendererMessage::SetParticleEmitter(self.sparks, *self.sparks_emitter));
+    self.messages.push(RendererMessage::EmitParticles(
+      self.sparks,
+      count,
+      self.rng.particles().next_u32(),
+    ));
+  }
+
+  fn add_trauma(&mut self, amount: f32) {
+    self.camera_effects.add_trauma(amount);
+  }
+
+  fn random_range(&mut self, min: f32, max: f32) -> f32 {
+    self.rng.gameplay().range_f32(min, max)
+  }
+
+  fn log(&self, message: &str) {
+    println!("[gameplay] {message}");
+  }
+}
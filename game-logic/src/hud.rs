@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use event_bus::ViewResized;
+use input_aggregator::InputAggregator;
+use render_manager::{Color, UiShape};
+use ui::{Anchor, Rect, ScalePolicy, UiNode, UiNodeId, UiTree};
+
+// Pixel height of HUD text, text only shows with a ui_font in the view config
+pub const HUD_TEXT_SIZE: f32 = 18.0;
+// Gap between the HUD and the edges of the scene
+pub const HUD_MARGIN: f32 = 12.0;
+const HUD_LINE_HEIGHT: f32 = HUD_TEXT_SIZE * 1.3;
+
+// Replies up at once, older ones scroll off the top
+const CONSOLE_LINES: usize = 8;
+// How long a reply stays up
+const CONSOLE_REPLY_SHOWN: Duration = Duration::from_secs(6);
+const CONSOLE_ERROR_COLOR: Color = Color::from_srgb(1.0, 0.45, 0.4, 1.0);
+const CONSOLE_INPUT_COLOR: Color = Color::from_srgb(1.0, 0.9, 0.5, 1.0);
+
+// Lines top down from top_left, returns the shapes and the y under the last line
+pub fn text_lines(lines: &[(String, Color)], top_left: glam::Vec2) -> (Vec<UiShape>, f32) {
+  let shapes = lines
+    .iter()
+    .enumerate()
+    .map(|(i, (text, color))| UiShape::Text {
+      position: top_left + glam::vec2(0.0, i as f32 * HUD_LINE_HEIGHT),
+      text: text.clone(),
+      size: HUD_TEXT_SIZE,
+      color: *color,
+    })
+    .collect();
+  (shapes, top_left.y + lines.len() as f32 * HUD_LINE_HEIGHT)
+}
+
+// Where the HUD goes, laid out against the safe area the renderer publishes with ViewResized.
+// Offsets are pixels at a ui_scale of 1
+pub struct HudLayout {
+  tree: UiTree,
+  // The safe area inset by the margin, what the HUD is drawn in
+  root: UiNodeId,
+  // Over the editor's property panel, so clicks on it don't go on to pick what's behind
+  property_panel: UiNodeId,
+  // Top left of the scene in the window, the UI layer's pixels are from there. None until the
+  // first ViewResized
+  viewport_min: Option<glam::Vec2>,
+}
+
+impl HudLayout {
+  pub fn new() -> Result<Self, String> {
+    let mut tree = UiTree::new(ScalePolicy::ConstantPixels);
+    let root = UiNode::new("hud", Anchor::STRETCH, glam::Vec2::splat(-2.0 * HUD_MARGIN));
+    let root = tree.add(None, root)?;
+    let property_panel = UiNode {
+      interactive: true,
+      ..UiNode::new("property_panel", Anchor::TOP_LEFT, glam::Vec2::ZERO)
+    };
+    let property_panel = tree.add(Some(root), property_panel)?;
+    Ok(Self { tree, root, property_panel, viewport_min: None })
+  }
+
+  pub fn view_resized(&mut self, view: &ViewResized) {
+    let rect = |[x, y, width, height]: [u32; 4]| {
+      let min = glam::vec2(x as f32, y as f32);
+      Rect::new(min, min + glam::vec2(width as f32, height as f32))
+    };
+    self.tree.set_safe_area(Some(rect(view.safe_area)));
+    self.tree.set_ui_scale(view.ui_scale);
+    self.viewport_min = Some(rect(view.viewport).min);
+  }
+
+  // Top left of the HUD in UI layer pixels
+  pub fn top_left(&self) -> glam::Vec2 {
+    match (self.viewport_min, self.tree.rect(self.root)) {
+      (Some(viewport_min), Some(rect)) => rect.min - viewport_min,
+      _ => glam::Vec2::splat(HUD_MARGIN),
+    }
+  }
+
+  // In UI layer pixels, zero while no panel is drawn
+  pub fn set_property_panel_size(&mut self, size: glam::Vec2) {
+    let scale = self.tree.scale();
+    if let Some(node) = self.tree.node_mut(self.property_panel) {
+      node.size = size / scale;
+    }
+  }
+
+  // Lays the HUD out against the window, call once a frame before anything picks with the cursor
+  pub fn update_input(&mut self, inputs: &InputAggregator) {
+    let (width, height) = inputs.window_size();
+    self.tree.layout(glam::vec2(width as f32, height as f32));
+    self.tree.update_input(inputs);
+  }
+
+  // The cursor is over the HUD, clicks shouldn't reach the scene
+  #[cfg_attr(not(feature = "editor"), allow(dead_code))]
+  pub fn is_pointer_captured(&self) -> bool {
+    self.tree.is_pointer_captured()
+  }
+}
+
+// What the console prints, drawn on the HUD too so replies are seen without the terminal. While
+// the console is open the line being typed is drawn under them
+pub struct ConsoleHud {
+  // Text, its color and how long it has been up
+  replies: VecDeque<(String, Color, Duration)>,
+  input: Option<String>,
+}
+
+impl ConsoleHud {
+  pub fn new() -> Self {
+    Self { replies: VecDeque::new(), input: None }
+  }
+
+  // Printed to the terminal as well, multi line text takes a line each
+  pub fn reply(&mut self, text: String) {
+    println!("{text}");
+    self.push(&text, Color::WHITE);
+  }
+
+  // Logged like other errors
+  pub fn error(&mut self, text: String) {
+    crash_report::log_line(text.clone());
+    self.push(&text, CONSOLE_ERROR_COLOR);
+  }
+
+  fn push(&mut self, text: &str, color: Color) {
+    for line in text.lines() {
+      if self.replies.len() == CONSOLE_LINES {
+        self.replies.pop_front();
+      }
+      self.replies.push_back((line.to_string(), color, Duration::ZERO));
+    }
+  }
+
+  pub fn set_input(&mut self, input: Option<String>) {
+    self.input = input;
+  }
+
+  pub fn update(&mut self, tick: Duration) {
+    for (_, _, shown) in self.replies.iter_mut() {
+      *shown += tick;
+    }
+    self.replies.retain(|(_, _, shown)| *shown < CONSOLE_REPLY_SHOWN);
+  }
+
+  pub fn shapes(&self, top_left: glam::Vec2) -> Vec<UiShape> {
+    let mut lines =
+      self.replies.iter().map(|(text, color, _)| (text.clone(), *color)).collect::<Vec<_>>();
+    lines.extend(self.input.as_ref().map(|input| (format!("> {input}"), CONSOLE_INPUT_COLOR)));
+    text_lines(&lines, top_left).0
+  }
+}
+
+impl Default for ConsoleHud {
+  fn default() -> Self {
+    Self::new()
+  }
+}
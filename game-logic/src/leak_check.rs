@@ -0,0 +1,226 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use localization::tr;
+use render_manager::{LeakedResource, Renderer, ResourceLeak, ResourceSnapshot};
+use serde::Serialize;
+
+// Frames drawn without a loading screen after loading or unloading the level, for uploads to land
+// and destroyed resources to be retired
+const SETTLE_FRAMES: u64 = 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeakCheckSettings {
+  // Times the level is loaded, played and unloaded again after the first time, which the baseline
+  // is taken after so caches filled by any level are in it
+  pub cycles: u32,
+  // Simulated time each load is played for
+  pub play_time: Duration,
+  pub report_path: PathBuf,
+}
+
+impl Default for LeakCheckSettings {
+  fn default() -> Self {
+    Self {
+      cycles: 5,
+      play_time: Duration::from_secs(10),
+      report_path: PathBuf::from("./leak_check_report.toml"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct LeakEntry {
+  kind: &'static str,
+  name: String,
+  baseline: u64,
+  live: u64,
+}
+
+impl LeakEntry {
+  fn new(leak: &ResourceLeak) -> Self {
+    let (kind, name) = match &leak.resource {
+      LeakedResource::Usage(name) => ("usage", name.to_string()),
+      LeakedResource::Registry(name) => ("registry", name.to_string()),
+      LeakedResource::Allocation(name) => ("allocation", name.clone()),
+    };
+    Self { kind, name, baseline: leak.baseline, live: leak.live }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CycleLeaks {
+  cycle: u32,
+  leaks: Vec<LeakEntry>,
+}
+
+// What gets written to the report file, tables last as toml wants them
+#[derive(Debug, Clone, Serialize)]
+struct LeakCheckResults {
+  scene: String,
+  cycles: u32,
+  leaking_cycles: u32,
+  // Only cycles that left something behind
+  cycle: Vec<CycleLeaks>,
+}
+
+pub enum LeakCheckStep {
+  Waiting,
+  // The scene file should be loaded again
+  LoadLevel,
+  // Everything made from the scene should be destroyed, leaving an empty scene
+  UnloadLevel,
+  // Report written, the game can stop
+  Finished,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Settled {
+  Play,
+  Snapshot,
+}
+
+enum LeakCheckPhase {
+  // Frames drawn as of the first tick seen without a loading screen, and what to do once settled
+  Settling(Option<u64>, Settled),
+  // Microseconds simulated since the level was in
+  Playing(u128),
+  Snapshotting,
+  Done,
+}
+
+// Loads, plays and unloads the level over and over, checking the render thread is back to what
+// it had after the first unload each time
+pub struct LeakCheck {
+  settings: LeakCheckSettings,
+  scene_path: PathBuf,
+  phase: LeakCheckPhase,
+  baseline: Option<ResourceSnapshot>,
+  // Per cycle after the baseline
+  cycle_leaks: Vec<Vec<ResourceLeak>>,
+}
+
+impl LeakCheck {
+  // Starts with the level already loading
+  pub fn new(settings: LeakCheckSettings, scene_path: PathBuf) -> Self {
+    Self {
+      settings,
+      scene_path,
+      phase: LeakCheckPhase::Settling(None, Settled::Play),
+      baseline: None,
+      cycle_leaks: vec![],
+    }
+  }
+
+  // Only once the report is written, whether any cycle left something behind
+  pub fn found_leaks(&self) -> bool {
+    matches!(self.phase, LeakCheckPhase::Done) && self.cycle_leaks.iter().any(|x| !x.is_empty())
+  }
+
+  pub fn report_path(&self) -> &Path {
+    &self.settings.report_path
+  }
+
+  // Each tick, frame_time is in microseconds like the rest of the game update
+  pub fn update(
+    &mut self,
+    renderer: &mut Renderer,
+    frame_time: u128,
+  ) -> Result<LeakCheckStep, String> {
+    match &mut self.phase {
+      LeakCheckPhase::Settling(since, settled) => {
+        let settled = *settled;
+        let frames_drawn = renderer.frame_stats()?.frames_drawn;
+        if renderer.loading_progress()?.is_some() {
+          *since = None;
+          return Ok(LeakCheckStep::Waiting);
+        }
+        let since = *since.get_or_insert(frames_drawn);
+        if frames_drawn < since + SETTLE_FRAMES {
+          return Ok(LeakCheckStep::Waiting);
+        }
+        self.phase = match settled {
+          Settled::Play => LeakCheckPhase::Playing(0),
+          Settled::Snapshot => {
+            renderer.snapshot_resources()?;
+            LeakCheckPhase::Snapshotting
+          }
+        };
+        Ok(LeakCheckStep::Waiting)
+      }
+      LeakCheckPhase::Playing(elapsed) => {
+        *elapsed += frame_time;
+        if *elapsed < self.settings.play_time.as_micros() {
+          return Ok(LeakCheckStep::Waiting);
+        }
+        self.phase = LeakCheckPhase::Settling(None, Settled::Snapshot);
+        Ok(LeakCheckStep::UnloadLevel)
+      }
+      LeakCheckPhase::Snapshotting => {
+        let Some(snapshot) = renderer.take_resource_snapshot()? else {
+          return Ok(LeakCheckStep::Waiting);
+        };
+        match &self.baseline {
+          Some(baseline) => self.cycle_leaks.push(snapshot.leaks_since(baseline)),
+          None => self.baseline = Some(snapshot),
+        }
+        if self.cycle_leaks.len() as u32 >= self.settings.cycles {
+          self.write_report()?;
+          self.phase = LeakCheckPhase::Done;
+          return Ok(LeakCheckStep::Finished);
+        }
+        self.phase = LeakCheckPhase::Settling(None, Settled::Play);
+        Ok(LeakCheckStep::LoadLevel)
+      }
+      LeakCheckPhase::Done => Ok(LeakCheckStep::Finished),
+    }
+  }
+
+  fn write_report(&self) -> Result<(), String> {
+    let cycle = self
+      .cycle_leaks
+      .iter()
+      .enumerate()
+      .filter(|(_, leaks)| !leaks.is_empty())
+      .map(|(i, leaks)| CycleLeaks {
+        cycle: i as u32 + 1,
+        leaks: leaks.iter().map(LeakEntry::new).collect(),
+      })
+      .collect::<Vec<_>>();
+    let results = LeakCheckResults {
+      scene: self.scene_path.to_string_lossy().to_string(),
+      cycles: self.cycle_leaks.len() as u32,
+      leaking_cycles: cycle.len() as u32,
+      cycle,
+    };
+    let report_str = toml::to_string_pretty(&results)
+      .map_err(|e| format!("at serializing leak check report: {e}"))?;
+    let path = &self.settings.report_path;
+    std::fs::write(path, report_str)
+      .map_err(|e| format!("at writing leak check report {}: {e}", path.display()))?;
+    // What the last cycle left is what's still piling up
+    for leak in self.cycle_leaks.last().into_iter().flatten() {
+      let entry = LeakEntry::new(leak);
+      println!(
+        "{}",
+        tr!(
+          "console.leak_check_leak",
+          kind = entry.kind,
+          name = entry.name,
+          baseline = entry.baseline,
+          live = entry.live
+        )
+      );
+    }
+    println!(
+      "{}",
+      tr!(
+        "console.leak_check_done",
+        leaking = results.leaking_cycles,
+        cycles = results.cycles,
+        path = path.display()
+      )
+    );
+    Ok(())
+  }
+}
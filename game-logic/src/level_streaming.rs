@@ -0,0 +1,200 @@
+use crash_report::log;
+use jobs::JobHandle;
+use render_manager::{glam, LightHandle, Renderer, RendererMessage};
+
+use crate::scene::{Scene, SceneObject, SceneSection, SceneStreamTrigger};
+use crate::scene_materials::SceneMaterials;
+use crate::{Game, GameObject};
+
+enum SectionState {
+  Unloaded,
+  // Its scene file is read and parsed on the job pool
+  Loading(JobHandle<Result<Scene, String>>),
+  Loaded { objects: Vec<GameObject>, lights: Vec<LightHandle> },
+}
+
+// What the game has to do to its physics, the streaming only deals with the renderer
+#[cfg_attr(not(feature = "physics"), allow(dead_code))]
+pub enum SectionChange {
+  // Objects that arrived, named like their game objects
+  Loaded(Vec<SceneObject>),
+  // Physics names of the bodies to take out
+  Unloaded(Vec<String>),
+}
+
+// Loads and unloads the scene's sections as the focus, the camera, enters stream triggers. Files
+// are loaded on the job pool so a tick never waits on one, the objects show up on the first update
+// after. Meshes of unloaded sections are destroyed through the renderer, which keeps them until
+// the frames drawing them are done. Scripts of section objects aren't run
+pub struct LevelStreaming {
+  sections: Vec<(SceneSection, SectionState)>,
+  triggers: Vec<SceneStreamTrigger>,
+  // If the focus was in each trigger last update, only entering one does anything
+  inside: Vec<bool>,
+  // The scene's, sections are moved onto it as they load
+  origin: glam::DVec3,
+}
+
+impl LevelStreaming {
+  pub fn new(scene: &Scene) -> Self {
+    let named = scene.stream_triggers.iter().flat_map(|t| t.load.iter().chain(t.unload.iter()));
+    for name in named {
+      if !scene.sections.iter().any(|section| section.name == *name) {
+        log!("stream trigger names unknown section {name}");
+      }
+    }
+    Self {
+      sections: scene
+        .sections
+        .iter()
+        .map(|section| (section.clone(), SectionState::Unloaded))
+        .collect(),
+      triggers: scene.stream_triggers.clone(),
+      inside: vec![false; scene.stream_triggers.len()],
+      origin: scene.origin(),
+    }
+  }
+
+  fn section_idx(&self, name: &str) -> Result<usize, String> {
+    self
+      .sections
+      .iter()
+      .position(|(section, _)| section.name == name)
+      .ok_or(format!("no section named {name}"))
+  }
+
+  // Starts reading the section's file, nothing happens if it is loaded or loading already
+  pub fn load(&mut self, name: &str) -> Result<(), String> {
+    let idx = self.section_idx(name)?;
+    let (section, state) = &mut self.sections[idx];
+    if matches!(state, SectionState::Unloaded) {
+      let path = section.path.clone();
+      *state =
+        SectionState::Loading(jobs::global().spawn(move || Scene::load(vfs::global(), &path)));
+    }
+    Ok(())
+  }
+
+  fn unload_idx(
+    &mut self,
+    idx: usize,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Option<SectionChange> {
+    // A load still running finishes on its own and its scene is dropped
+    let SectionState::Loaded { objects, lights } =
+      std::mem::replace(&mut self.sections[idx].1, SectionState::Unloaded)
+    else {
+      return None;
+    };
+    let mut physics_names = vec![];
+    for game_obj in objects {
+      if let Some(mesh) = game_obj.display_mesh {
+        messages.push(RendererMessage::DestroyTriMesh(mesh));
+      }
+      physics_names.extend(game_obj.physics_name.map(|(_, name)| name));
+    }
+    messages.extend(lights.into_iter().map(RendererMessage::RemoveLight));
+    Some(SectionChange::Unloaded(physics_names))
+  }
+
+  pub fn unload(
+    &mut self,
+    name: &str,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<Vec<SectionChange>, String> {
+    let idx = self.section_idx(name)?;
+    Ok(self.unload_idx(idx, messages).into_iter().collect())
+  }
+
+  // Triggers count as left too, so the ones the focus is in act again on the next update
+  pub fn unload_all(&mut self, messages: &mut Vec<RendererMessage>) -> Vec<SectionChange> {
+    self.inside.fill(false);
+    (0..self.sections.len()).filter_map(|idx| self.unload_idx(idx, messages)).collect()
+  }
+
+  // Object names get the section's in front, so they don't clash with the level's
+  fn instantiate(
+    section: &SceneSection,
+    origin: glam::DVec3,
+    mut scene: Scene,
+    renderer: &mut Renderer,
+    materials: &mut SceneMaterials,
+    messages: &mut Vec<RendererMessage>,
+  ) -> (SectionState, SectionChange) {
+    scene.rebase(origin);
+    let scene_objects = std::mem::take(&mut scene.objects)
+      .into_iter()
+      .map(|obj| SceneObject { name: format!("{}/{}", section.name, obj.name), ..obj })
+      .collect::<Vec<_>>();
+    let objects = scene_objects
+      .iter()
+      .map(|obj| GameObject::from_scene_object(renderer, obj, materials, messages))
+      .collect();
+    let lights = Game::spawn_lights(renderer, &scene, messages);
+    (SectionState::Loaded { objects, lights }, SectionChange::Loaded(scene_objects))
+  }
+
+  // Acts on the triggers the focus entered, unloading before loading so a trigger can swap two
+  // sections, then sets up the sections whose files are in
+  pub fn update(
+    &mut self,
+    focus: glam::Vec3,
+    renderer: &mut Renderer,
+    materials: &mut SceneMaterials,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Vec<SectionChange> {
+    let mut changes = vec![];
+    let mut entered = vec![];
+    for (trigger, inside) in self.triggers.iter().zip(self.inside.iter_mut()) {
+      let now_inside = trigger.contains(focus);
+      if now_inside && !*inside {
+        entered.push(trigger.clone());
+      }
+      *inside = now_inside;
+    }
+    for trigger in entered {
+      for name in trigger.unload.iter() {
+        match self.unload(name, messages) {
+          Ok(unloaded) => changes.extend(unloaded),
+          Err(e) => log!("at unloading section: {e}"),
+        }
+      }
+      for name in trigger.load.iter() {
+        let _ = self.load(name).inspect_err(|e| log!("at loading section: {e}"));
+      }
+    }
+
+    for (section, state) in self.sections.iter_mut() {
+      if !matches!(state, SectionState::Loading(job) if job.is_finished()) {
+        continue;
+      }
+      let SectionState::Loading(job) = std::mem::replace(state, SectionState::Unloaded) else {
+        continue;
+      };
+      match job.wait().and_then(|scene| scene) {
+        Ok(scene) => {
+          let (loaded, change) =
+            Self::instantiate(section, self.origin, scene, renderer, materials, messages);
+          *state = loaded;
+          changes.push(change);
+        }
+        Err(e) => log!("at loading section {}: {e}", section.name),
+      }
+    }
+    changes
+  }
+
+  pub fn objects(&self) -> impl Iterator<Item = &GameObject> {
+    self.sections.iter().flat_map(|(_, state)| match state {
+      SectionState::Loaded { objects, .. } => objects.as_slice(),
+      _ => &[],
+    })
+  }
+
+  pub fn objects_mut(&mut self) -> impl Iterator<Item = &mut GameObject> {
+    self.sections.iter_mut().flat_map(|(_, state)| match state {
+      SectionState::Loaded { objects, .. } => objects.as_mut_slice(),
+      _ => &mut [],
+    })
+  }
+}
@@ -1,203 +1,2361 @@
-use std::sync::{Arc, OnceLock};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use animation::KeyFramed;
-use input_aggregator::{InputAggregator, Key, NamedKey};
-use physics::{collision::PolygonMeshTemp, PhysicsEngine, PhysicsObject};
-use physics::geometry::{Direction, Point};
-use render_manager::{AdSurface, Camera3D, FlatTextureGPU, Renderer, RendererMessage, TriMeshCPU, TriMeshGPU, TriMeshTransform};
+use benchmark::{benchmark_scene, Benchmark, BenchmarkStep, StressProps};
+use camera_animator::{CameraAnimator, CameraKey, CameraPath, Easing, PathInterpolation};
+use camera_bookmarks::{CameraBookmarks, CameraPose};
+use camera_effects::{CameraEffects, CameraShakeConfig};
+use color_grading::GradingVolumes;
+use crowd::Crowd;
+#[cfg(feature = "physics")]
+use debug_draw::{DebugDraw, DebugView};
+#[cfg(feature = "editor")]
+use asset_import::ImportKind;
+#[cfg(feature = "editor")]
+use editor::{Editor, SceneChange};
+use crash_report::log;
+use engine_config::{EngineConfig, QualityPreset};
+#[cfg(feature = "physics")]
+use engine_config::PhysicsConfig;
+use event_bus::{AssetReloaded, FocusGained, FocusLost, Subscription, ViewResized};
+#[cfg(feature = "editor")]
+use event_bus::FileDropped;
+#[cfg(feature = "file-dialog")]
+use file_dialog::DialogKind;
+use gameplay_api::{GameplayLibrary, LinkedGameplay};
+use gameplay_host::GameplayContext;
+use hud::{ConsoleHud, HudLayout, HUD_MARGIN};
+use input_aggregator::{InputAggregator, InputPlayback, InputRecording, Key, NamedKey};
+use leak_check::{LeakCheck, LeakCheckStep};
+use level_streaming::{LevelStreaming, SectionChange};
+use lightmaps::SceneLightmaps;
+use localization::tr;
+use orbit_camera::{OrbitCamera, OrbitCameraConfig};
+#[cfg(feature = "physics")]
+use physics::{checked_math::CheckedMath, snapshot::PhysicsSnapshot, PhysicsEngine};
+use profiler::profile_scope;
+use rng::{RngService, RngSnapshot, RngStream};
+use render_manager::{make_foliage_card, CAMERA_FOV, Camera3D, Color, DepthOfFieldParams, EngineInfo, ExportedFrame, FoliageCPU, FoliageHandle, FoliageParams, FoliageScatter, FrameDiffCommand, FrameExportMode, LabelAnchor, LabelIcon, LayerMask, LightHandle, MaterialHandle, MeshHandle, MinimapSettings, MorphWeights, ParticleEmitter, ParticleHandle, QualityKnobs, RecordingOutput, RecordingSettings, RenderTarget, Renderer, RendererMessage, MeshState, ScatterParams, TriMeshCPU, TriMeshTransform, UiShape, WindParams, WorldLabel};
+#[cfg(feature = "editor")]
+use engine_config::ViewConfig;
+#[cfg(feature = "editor")]
+use render_manager::{view_camera, GridSettings};
+#[cfg(feature = "physics")]
+use render_manager::PhysicsHudStats;
+#[cfg(feature = "physics")]
+use repro::{ReproHistory, REPROS_DIR};
+use scene::{Scene, SceneObject};
+#[cfg(feature = "physics")]
+use scene::SCENE_COLLISION_BODY;
+use scene_materials::SceneMaterials;
+use scheduler::{EngineStage, SystemWork};
+use geometry::world::{world_position, WorldTransform};
+#[cfg(feature = "scripting")]
+use scene::{PhysicsProperties, SceneShape};
+#[cfg(feature = "scripting")]
+use scripting::{ScriptCommand, ScriptRuntime};
+use static_batches::StaticBatches;
+use time_of_day::{clock_time, TimeOfDay};
+use time_scale::TimeScale;
 
-mod animation;
+#[cfg(feature = "editor")]
+mod asset_import;
+mod benchmark;
+mod camera_animator;
+mod camera_bookmarks;
+mod camera_effects;
+mod color_grading;
+mod crowd;
+#[cfg(feature = "physics")]
+mod debug_draw;
+#[cfg(feature = "editor")]
+mod editor;
+#[cfg(feature = "file-dialog")]
+mod file_dialog;
+mod gameplay_host;
+mod hud;
 mod renderable;
+#[cfg(feature = "physics")]
+mod repro;
+mod leak_check;
+mod level_streaming;
 mod levels;
+mod lightmaps;
+mod orbit_camera;
+mod prefab;
+mod quality;
+mod scene;
+mod scene_materials;
+mod scheduler;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod simulation;
+mod static_batches;
+mod time_of_day;
+mod time_scale;
+#[cfg(test)]
+mod tests;
+
+pub use benchmark::BenchmarkSettings;
+pub use leak_check::LeakCheckSettings;
+#[cfg(feature = "physics")]
+pub use repro::ReproBundle;
+pub use scheduler::{Scheduler, SystemTiming};
+pub use simulation::Simulation;
+
+const SCENE_PATH: &str = "./scene.toml";
+// Generated benchmark scenes save here instead of over the scene file
+const BENCHMARK_SCENE_PATH: &str = "./benchmark_scene.toml";
+const PROFILE_TRACE_PATH: &str = "./profile_trace.json";
+// F9 recordings go in a directory per take under this
+const RECORDINGS_DIR: &str = "./recordings";
+const RECORDING_FPS: u32 = 30;
+#[cfg(feature = "physics")]
+const JUMP_SPARK_COUNT: u32 = 64;
+#[cfg(feature = "physics")]
+const JUMP_TRAUMA: f32 = 0.3;
+const DEMO_CAMERA_ORBIT_MS: u128 = 8000;
+const CONSOLE_KEY: &str = "`";
+const GRASS_CARD_WIDTH: f32 = 0.5;
+const GRASS_CARD_HEIGHT: f32 = 0.4;
+const GRASS_CARD_PLANES: u32 = 3;
+// Renderables only drawn while editing
+const EDITOR_LAYER: LayerMask = LayerMask::layer(31);
+
+// What only applies to this run of the game, usually from the command line
+#[derive(Debug, Clone, Default)]
+pub struct LaunchOptions {
+  // Loaded through the vfs and saved back to as a loose file. Without one ./scene.toml is used,
+  // or a default scene when there is none
+  pub scene_path: Option<PathBuf>,
+  // Frames captured with renderdoc once the scene is loaded, 0 for none
+  pub capture_frames: u32,
+  // Recorded input to play back instead of reading the keyboard and mouse, the game finishes once
+  // it runs out
+  pub replay_path: Option<PathBuf>,
+  // Where the input of this run is written when the game shuts down, to be played back later
+  pub record_path: Option<PathBuf>,
+  // Bundle saved with repro save, its scene is loaded in place of scene_path and its input played
+  // back from its snapshots
+  pub repro_path: Option<PathBuf>,
+  // Gameplay code built as a dynamic library, reloaded whenever it's rebuilt
+  pub gameplay_library: Option<PathBuf>,
+  // Runs a generated scene instead of the scene file and quits once the report is written
+  pub benchmark: Option<BenchmarkSettings>,
+  // Loads and unloads the scene over and over looking for what it leaves behind, quits once the
+  // report is written. Shutting down fails if anything was left
+  pub leak_check: Option<LeakCheckSettings>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+  Play,
+  Edit,
+}
 
 pub struct GameObject {
-  pub display_mesh: Arc<OnceLock<Arc<TriMeshGPU>>>,
-  pub display_tex: Arc<OnceLock<Arc<FlatTextureGPU>>>,
+  pub name: String,
+  pub display_mesh: Option<MeshHandle>,
+  pub display_material: Option<MaterialHandle>,
   pub physics_name: Option<(bool, String)>,
   pub animation_time: u128,
   pub rotation_animation: KeyFramed<f32>,
+  // Only for meshes uploaded with morph targets, the track post infinity decides if it loops
+  pub morph_animation: Option<KeyFramed<MorphWeights>>,
   pub object_transform: TriMeshTransform,
 }
 
 impl GameObject {
-  pub fn update(&mut self, frame_time: u128) -> Result<(), String> {
+  // Queues the mesh upload and registers it for drawing
+  fn from_scene_object(
+    renderer: &mut Renderer,
+    obj: &SceneObject,
+    materials: &mut SceneMaterials,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Self {
+    let material = materials.object(renderer, obj, messages);
+    Self::from_scene_mesh(renderer, obj, obj.make_tri_mesh(), material, messages)
+  }
+
+  // Static objects the scene's lightmap covers draw lightmapped, the rest with their material
+  fn from_lightmapped_scene_object(
+    renderer: &mut Renderer,
+    obj: &SceneObject,
+    materials: &mut SceneMaterials,
+    lightmaps: Option<&SceneLightmaps>,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Self {
+    match lightmaps.and_then(|lightmaps| lightmaps.object(obj)) {
+      Some((mesh, lightmapped)) => {
+        Self::from_scene_mesh(renderer, obj, mesh.clone(), lightmapped, messages)
+      }
+      None => Self::from_scene_object(renderer, obj, materials, messages),
+    }
+  }
+
+  fn from_scene_mesh(
+    renderer: &mut Renderer,
+    obj: &SceneObject,
+    mesh: TriMeshCPU,
+    material: MaterialHandle,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Self {
+    let display_mesh = renderer.create_mesh_handle();
+    let game_obj = GameObject {
+      name: obj.name.clone(),
+      display_mesh: Some(display_mesh),
+      display_material: Some(material),
+      physics_name: obj.physics.map(|physics| (physics.dynamic, obj.physics_name())),
+      object_transform: TriMeshTransform { transform: obj.transform() },
+      animation_time: 0,
+      rotation_animation: KeyFramed::linear(vec![(0, 0.0)]),
+      morph_animation: None,
+    };
+    messages.push(RendererMessage::UploadTriMesh(
+      format!("{}_{display_mesh:?}", obj.name),
+      mesh,
+      display_mesh,
+    ));
+    messages.push(RendererMessage::AddRenderable(display_mesh, Some(material)));
+    game_obj
+  }
+
+  // Randomness in per object logic comes from _rng, the object's own stream from RngService::object
+  pub fn update(&mut self, frame_time: u128, _rng: &mut RngStream) -> Result<(), String> {
+    if self.morph_animation.is_some() {
+      self.animation_time += frame_time;
+    }
     // self.animation_time += frame_time;
     // let y_angle = self.rotation_animation.value_at(self.animation_time % 5000);
     // self.object_transform.transform = glam::Mat4::from_rotation_y(y_angle);
     // let rot_mat = glam::Mat4::from_rotation_y(frame_time as f32/ 500.0);
     // self.object_transform.transform = self.object_transform.transform * rot_mat;
-    self
-      .display_mesh
-      .get()
-      .map(|mesh|
-        mesh
-          .update_transform(self.object_transform)
-          .inspect_err(|e| eprintln!("at obj transform update: {e}")));
     Ok(())
   }
+
+  pub fn morph_weights(&self) -> Option<MorphWeights> {
+    // animation_time is in microseconds like frame_time
+    let morph_animation = self.morph_animation.as_ref()?;
+    Some(morph_animation.value_at(self.animation_time / 1000))
+  }
+  // Where its body is, None without one
+  #[cfg(feature = "physics")]
+  fn physics_transform(&self, physics_engine: &PhysicsEngine) -> Option<glam::Mat4> {
+    let (_, physics_name) = self.physics_name.as_ref()?;
+    physics_engine.get_transform(physics_name)
+  }
+}
+
+// What Game::restore rewinds the simulation to, the bodies and the rng streams
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameSnapshot {
+  #[cfg(feature = "physics")]
+  pub physics: PhysicsSnapshot,
+  pub rng: RngSnapshot,
 }
 
 pub struct Game {
   game_objects: Vec<GameObject>,
+  scene: Scene,
+  scene_path: PathBuf,
+  // Read by load_scene, put in place of the scene at the start of the next tick
+  pending_scene: Option<(Scene, PathBuf)>,
+  mode: GameMode,
+  #[cfg(feature = "editor")]
+  editor: Editor,
+  // Editor picking goes through the same letterboxing and fov as the renderer
+  #[cfg(feature = "editor")]
+  view_config: ViewConfig,
+  #[cfg(feature = "editor")]
+  file_dropped_events: Subscription<FileDropped>,
   renderer: Renderer,
+  #[cfg(feature = "physics")]
   physics_engine: PhysicsEngine,
+  #[cfg(feature = "physics")]
+  physics_config: PhysicsConfig,
+  // What repro save bundles with the recent input
+  // Where dropped files are imported to as well
+  #[cfg(any(feature = "physics", feature = "editor"))]
+  engine_config: EngineConfig,
+  #[cfg(feature = "physics")]
+  repro_history: ReproHistory,
+  rng: RngService,
+  scene_materials: SceneMaterials,
+  // From the scene file, static objects it covers draw with it instead of scene_materials
+  lightmaps: Option<SceneLightmaps>,
+  sparks: ParticleHandle,
+  sparks_emitter: ParticleEmitter,
   camera: Camera3D,
-  start_time: std::time::Instant,
-  last_update: std::time::Duration,
+  // Vertical, in radians. Bookmarks carry their own
+  camera_fov: f32,
+  // Eases the camera to a scene's bookmarks, the camera path and orbit camera stop it
+  camera_bookmarks: CameraBookmarks,
+  // Set between starting and stopping a recording
+  recording: bool,
+  // Only when launched to benchmark
+  benchmark: Option<Benchmark>,
+  // Only when launched to check for leaks
+  leak_check: Option<LeakCheck>,
+  // Set once there is nothing left to run, the simulation stops then
+  finished: bool,
+  // Overrides camera controls while playing
+  camera_animator: Option<CameraAnimator>,
+  // Shake and kicks on top of the camera, only what the renderer sees is moved
+  camera_effects: CameraEffects,
+  // Third person follow cam, the camera path still wins while it plays
+  orbit_camera: Option<OrbitCamera>,
+  // Physics stats are sent to the renderer each tick while its perf hud is on
+  perf_hud: bool,
+  // Names drawn over the objects, sent each tick while on so spawned ones get theirs
+  object_labels: bool,
+  // Collision shapes and contacts of the bodies picked from the console, drawn while playing
+  #[cfg(feature = "physics")]
+  debug_draw: DebugDraw,
+  // Lens for cutscenes and aiming, set every tick to animate it. None uses the config's
+  depth_of_field: Option<DepthOfFieldParams>,
+  // Spawned from the console
+  crowd: Option<Crowd>,
+  foliage: Option<FoliageHandle>,
+  // From the scene file, replaced with the objects on reload
+  lights: Vec<LightHandle>,
+  // From the scene file like the lights
+  grading_volumes: GradingVolumes,
+  // From the scene file too, None leaves the renderer's fixed sun
+  time_of_day: Option<TimeOfDay>,
+  // Sections of the scene loaded while playing
+  level_streaming: LevelStreaming,
+  // Only while playing, objects can move in the editor
+  static_batches: Option<StaticBatches>,
+  // Console line as last echoed to stdout, it's drawn on the HUD too
+  console_echo: Option<String>,
+  console_hud: ConsoleHud,
+  hud_layout: HudLayout,
+  view_resized_events: Subscription<ViewResized>,
+  // As last sent, the renderer keeps drawing them until new ones are
+  hud_shapes: Vec<UiShape>,
+  focus_lost_events: Subscription<FocusLost>,
+  focus_gained_events: Subscription<FocusGained>,
+  // A camera path paused by losing focus plays on once it's back, one paused otherwise doesn't
+  camera_path_paused_by_focus: bool,
+  asset_reloaded_events: Subscription<AssetReloaded>,
+  // Microseconds simulated so far, advances by the fixed tick
+  sim_time: u128,
+  // Slow motion and hit-stop, what physics advances by each tick
+  time_scale: TimeScale,
+  // Ticked while playing, either takes over the built in cube jump
+  gameplay: Option<GameplayLibrary>,
+  linked_gameplay: Option<LinkedGameplay>,
+  // Stages each tick runs, with the game's own systems in them
+  scheduler: Scheduler,
+  // Read in place of the window's input, with the inputs it plays into
+  input_playback: Option<(InputPlayback, InputAggregator)>,
+  // Written to the path on shutdown
+  input_recording: Option<(InputRecording, PathBuf)>,
+  // Scripts of the scene objects, started each time play starts
+  #[cfg(feature = "scripting")]
+  scripts: ScriptRuntime,
 }
 
-impl Game {
-  pub fn new(surface: Arc<AdSurface>) -> Result<Self, String> {
-    let mut renderer = Renderer::new(surface.clone()).map_err(|e| format!("at renderer init: {e}"))?;
-    let mut physics_engine = PhysicsEngine::new(1000, 100);
-    let start_time = std::time::Instant::now();
-
-    let cube_poly_mesh = PolygonMeshTemp::new_cuboid(
-      Point::from_vec3(glam::vec3(0.0, 0.0, 0.0)),
-      Direction::from_vec3(glam::vec3(1.0, 0.0, 0.0)),
-      Direction::from_vec3(glam::vec3(0.0, 1.0, 0.0)),
-      1.0
-    );
+// Png sequence into a new take directory under RECORDINGS_DIR
+fn png_recording(fps: u32) -> RecordingSettings {
+  let take =
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+  RecordingSettings {
+    output: RecordingOutput::PngSequence(Path::new(RECORDINGS_DIR).join(format!("take_{take}"))),
+    fps,
+  }
+}
 
-    let cube_verts_cpu = TriMeshCPU::combine(
-      cube_poly_mesh
-        .get_faces()
-        .iter()
-        .map(|face| {
-          TriMeshCPU::make_planar_polygon(
-            face.iter().map(|vert| vert.as_vec3()).collect::<Vec<_>>()
-          )
-        })
-        .collect::<Vec<_>>()
-    );
-    let cube_phy_object = PhysicsObject::new(
-      cube_poly_mesh,
-      glam::vec3(0.0, 2.0, 0.0),
-      glam::Mat4::IDENTITY
-    );
-    physics_engine.add_dynamic_physics_obj("cube_physics", cube_phy_object)?;
-    let game_obj = GameObject {
-      display_mesh: Arc::new(OnceLock::new()),
-      display_tex: Arc::new(OnceLock::new()),
-      physics_name: Some((true, "cube_physics".to_string())),
-      object_transform: TriMeshTransform { transform: glam::Mat4::IDENTITY },
-      animation_time: 0,
-      rotation_animation: KeyFramed { key_frames: vec![(0, 0.0)] },
+impl Game {
+  pub fn new(
+    target: RenderTarget,
+    config: &EngineConfig,
+    launch: &LaunchOptions,
+  ) -> Result<Self, String> {
+    if let Some(settings) = &launch.benchmark {
+      let scene_path = PathBuf::from(BENCHMARK_SCENE_PATH);
+      let mut game = Self::from_scene(target, config, benchmark_scene(settings), scene_path)?;
+      let mut uploads = vec![RendererMessage::BeginLoading];
+      let props = StressProps::spawn(&mut game.renderer, settings, &mut uploads)?;
+      uploads.push(RendererMessage::EndLoading);
+      game
+        .renderer
+        .send_batch_sync(uploads)
+        .map_err(|e| format!("at sending benchmark props to renderer: {e}"))?;
+      game.benchmark = Some(Benchmark::new(settings.clone(), props));
+      return Ok(game);
+    }
+    if let Some(repro_path) = &launch.repro_path {
+      return Self::from_repro(target, config, repro_path);
+    }
+    // Loaded from wherever the vfs finds it, saves always go to the loose file
+    let scene_path = launch.scene_path.clone().unwrap_or_else(|| PathBuf::from(SCENE_PATH));
+    let scene_vfs_path = scene_path.to_string_lossy();
+    let scene = if launch.scene_path.is_some() || vfs::global().exists(&scene_vfs_path) {
+      Scene::load(vfs::global(), &scene_vfs_path)?
+    } else {
+      Scene::default_scene()
     };
+    let mut game = Self::from_scene(target, config, scene, scene_path)?;
+    if let Some(replay_path) = &launch.replay_path {
+      let playback = InputPlayback::new(InputRecording::load(replay_path)?);
+      // Random numbers come from the scene seed, another scene won't play out the same
+      if playback.seed() != game.rng.root_seed() {
+        return Err(format!(
+          "at playing back {}: recorded with seed {}, the scene has {}",
+          replay_path.display(),
+          playback.seed(),
+          game.rng.root_seed()
+        ));
+      }
+      game.input_playback = Some((playback, InputAggregator::new()));
+    }
+    if let Some(record_path) = &launch.record_path {
+      game.input_recording =
+        Some((InputRecording::new(game.rng.root_seed()), record_path.clone()));
+    }
+    if launch.capture_frames > 0 {
+      game
+        .renderer
+        .send_batch_sync(vec![RendererMessage::TriggerCapture(launch.capture_frames)])
+        .map_err(|e| format!("at requesting frame capture: {e}"))?;
+    }
+    if let Some(gameplay_library) = &launch.gameplay_library {
+      game.gameplay = Some(GameplayLibrary::load(gameplay_library)?);
+    }
+    if let Some(settings) = &launch.leak_check {
+      game.leak_check = Some(LeakCheck::new(settings.clone(), game.scene_path.clone()));
+    }
+    Ok(game)
+  }
 
-    let floor_poly_mesh = PolygonMeshTemp::new_rectangle(
-      Point::from_vec3(glam::vec3(0.0, 0.0, 0.0)),
-      Direction::from_vec3(glam::vec3(10.0, 0.0, 0.0)),
-      Direction::from_vec3(glam::vec3(0.0, 0.0, -10.0)),
-    );
-    let floor_verts_cpu = TriMeshCPU::make_planar_polygon(
-      floor_poly_mesh.get_faces().remove(0).iter().map(|face| {face.as_vec3()}).collect()
-    );
-    let floor_phy_object = PhysicsObject::new(
-      floor_poly_mesh,
-      glam::vec3(0.0, -2.0, 0.0),
-      glam::Mat4::IDENTITY
+  // The bundle's scene with physics and random numbers where its snapshots have them, its input
+  // played back from there
+  #[cfg(feature = "physics")]
+  fn from_repro(target: RenderTarget, config: &EngineConfig, path: &Path) -> Result<Self, String> {
+    let bundle = ReproBundle::load(path)?;
+    let scene = Scene::parse(&bundle.scene, &bundle.scene_path.to_string_lossy())?;
+    let mut game = Self::from_scene(target, config, scene, bundle.scene_path)?;
+    game
+      .restore(&GameSnapshot { physics: bundle.physics, rng: bundle.rng })
+      .map_err(|e| format!("at loading repro bundle {}: {e}", path.display()))?;
+    game.input_playback = Some((InputPlayback::new(bundle.inputs), InputAggregator::new()));
+    Ok(game)
+  }
+
+  #[cfg(not(feature = "physics"))]
+  fn from_repro(_: RenderTarget, _: &EngineConfig, path: &Path) -> Result<Self, String> {
+    Err(format!("at loading repro bundle {}: built without physics", path.display()))
+  }
+
+  // Gameplay built into the game, ticked alongside a library if there's one of those too
+  pub fn set_gameplay(&mut self, gameplay: LinkedGameplay) {
+    self.linked_gameplay = Some(gameplay);
+  }
+
+  // The engine's stages with whatever stages and systems the game added to them
+  pub fn set_scheduler(&mut self, scheduler: Scheduler) {
+    self.scheduler = scheduler;
+  }
+
+  pub fn system_timings(&self) -> Vec<SystemTiming> {
+    self.scheduler.timings()
+  }
+
+  fn has_gameplay(&self) -> bool {
+    self.gameplay.is_some() || self.linked_gameplay.is_some()
+  }
+
+  pub fn from_scene(
+    target: RenderTarget,
+    config: &EngineConfig,
+    scene: Scene,
+    scene_path: PathBuf,
+  ) -> Result<Self, String> {
+    let mut renderer = Renderer::new(target, config.renderer.clone())
+      .map_err(|e| format!("at renderer init: {e}"))?;
+    if config.renderer.gpu_frame_budget_ms > 0.0 {
+      let quality =
+        config.renderer.quality.settings().unwrap_or(config.renderer.quality_settings());
+      let full_quality = QualityKnobs {
+        render_scale: quality.render_scale,
+        shadow_map_size: quality.shadow_map_size,
+      };
+      renderer
+        .add_quality_callback(quality::step_quality(full_quality))
+        .map_err(|e| format!("at adding quality callback: {e}"))?;
+    }
+    #[cfg(feature = "physics")]
+    let physics_engine = Self::build_physics(&scene, &config.physics)?;
+    let mut rng = RngService::new(scene.seed);
+    let camera_effects = CameraEffects::new(
+      CameraShakeConfig::default(),
+      rng.stream("camera_shake").next_u64(),
     );
-    physics_engine.add_static_physics_obj("floor_physics", floor_phy_object)?;
-    let floor = GameObject {
-      display_mesh: Arc::new(OnceLock::new()),
-      display_tex: Arc::new(OnceLock::new()),
-      physics_name: Some((false, "floor_physics".to_string())),
-      object_transform: TriMeshTransform { transform: glam::Mat4::IDENTITY },
-      animation_time: 0,
-      rotation_animation: KeyFramed { key_frames: vec![(0, 0.0)] },
-    };
 
+    // The loading screen stays up until everything in the batch is on the gpu
+    let mut uploads = vec![RendererMessage::BeginLoading];
+    let mut scene_materials = SceneMaterials::new(&mut renderer, &mut uploads);
+    uploads.push(RendererMessage::SetRooms(scene.room_graph()?));
+    let lightmaps = SceneLightmaps::spawn(&mut renderer, &scene, &mut uploads)?;
+    let mut game_objects = vec![];
+    for obj in scene.objects.iter() {
+      game_objects.push(GameObject::from_lightmapped_scene_object(
+        &mut renderer,
+        obj,
+        &mut scene_materials,
+        lightmaps.as_ref(),
+        &mut uploads,
+      ));
+    }
+    let static_batches = StaticBatches::build(
+      &mut renderer,
+      &scene,
+      &game_objects,
+      lightmaps.as_ref(),
+      &mut uploads,
+    );
+    let lights = Self::spawn_lights(&mut renderer, &scene, &mut uploads);
+    let grading_volumes = GradingVolumes::spawn(&mut renderer, &scene, &mut uploads);
+    // After the meshes, so the probes capture them
+    uploads.push(RendererMessage::SetReflectionProbes(scene.reflection_probes()));
+    let time_of_day = Self::spawn_time_of_day(&scene)?;
+    let level_streaming = LevelStreaming::new(&scene);
+    let sparks = renderer.create_particle_handle();
+    let sparks_emitter = ParticleEmitter::default();
+    uploads.push(RendererMessage::CreateParticleSystem(
+      "jump_sparks".to_string(),
+      sparks_emitter,
+      sparks,
+    ));
+    uploads.push(RendererMessage::EndLoading);
     renderer
-      .send_batch_sync(vec![
-        RendererMessage::UploadTriMesh(
-          "triangle_main".to_string(),
-          cube_verts_cpu,
-          game_obj.display_mesh.clone()
-        ),
-        RendererMessage::UploadTriMesh(
-          "floor".to_string(),
-          floor_verts_cpu,
-          floor.display_mesh.clone()
-        ),
-        // RendererMessage::UploadFlatTex(
-        //   "./background.png".to_string(),
-        //   "./background.png".to_string(),
-        //   game_obj.display_tex.clone(),
-        // ),
-        // RendererMessage::UploadFlatTex(
-        //   "./background.png".to_string(),
-        //   "./background.png".to_string(),
-        //   floor.display_tex.clone(),
-        // ),
-      ])
+      .send_batch_sync(uploads)
       .map_err(|e| format!("at sending work to renderer: {e}"))?;
     Ok(Self {
       renderer,
+      #[cfg(feature = "physics")]
       physics_engine,
-      game_objects: vec![game_obj, floor],
-      start_time,
-      last_update: start_time.elapsed(),
+      #[cfg(feature = "physics")]
+      physics_config: config.physics.clone(),
+      #[cfg(any(feature = "physics", feature = "editor"))]
+      engine_config: config.clone(),
+      #[cfg(feature = "physics")]
+      repro_history: ReproHistory::default(),
+      rng,
+      scene_materials,
+      lightmaps,
+      sparks,
+      sparks_emitter,
+      game_objects,
+      scene,
+      scene_path,
+      mode: GameMode::Play,
+      #[cfg(feature = "editor")]
+      editor: Editor::new(),
+      #[cfg(feature = "editor")]
+      view_config: config.renderer.view.clone(),
+      #[cfg(feature = "editor")]
+      file_dropped_events: event_bus::global().subscribe(),
+      camera_animator: None,
+      camera_bookmarks: CameraBookmarks::default(),
+      recording: false,
+      benchmark: None,
+      leak_check: None,
+      finished: false,
+      camera_effects,
+      orbit_camera: None,
+      perf_hud: false,
+      object_labels: false,
+      #[cfg(feature = "physics")]
+      debug_draw: DebugDraw::default(),
+      depth_of_field: None,
+      crowd: None,
+      foliage: None,
+      lights,
+      grading_volumes,
+      time_of_day,
+      level_streaming,
+      static_batches: Some(static_batches),
+      console_echo: None,
+      console_hud: ConsoleHud::new(),
+      hud_layout: HudLayout::new()?,
+      view_resized_events: event_bus::global().subscribe(),
+      hud_shapes: vec![],
+      pending_scene: None,
+      focus_lost_events: event_bus::global().subscribe(),
+      focus_gained_events: event_bus::global().subscribe(),
+      camera_path_paused_by_focus: false,
+      asset_reloaded_events: event_bus::global().subscribe(),
+      sim_time: 0,
+      time_scale: TimeScale::default(),
+      gameplay: None,
+      linked_gameplay: None,
+      scheduler: Scheduler::default(),
+      input_playback: None,
+      input_recording: None,
+      #[cfg(feature = "scripting")]
+      scripts: ScriptRuntime::new(),
       camera: Camera3D::new(
         glam::vec4(2.0, 2.0, 2.0, 1.0),
         glam::vec4(-1.0, -1.0, -1.0, 1.0),
         1.0
-      )
+      ),
+      camera_fov: CAMERA_FOV,
     })
   }
 
-  pub fn update(&mut self, inputs: &InputAggregator) -> Result<(), String> {
-    let current_dur = self.start_time.elapsed();
-    let frame_time = current_dur.as_micros() - self.last_update.as_micros();
-    self.last_update = current_dur;
+  fn spawn_lights(
+    renderer: &mut Renderer,
+    scene: &Scene,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Vec<LightHandle> {
+    scene
+      .lights
+      .iter()
+      .map(|light| {
+        let handle = renderer.create_light_handle();
+        messages.push(RendererMessage::SetLight(handle, light.local_light()));
+        handle
+      })
+      .collect()
+  }
+
+  fn spawn_time_of_day(scene: &Scene) -> Result<Option<TimeOfDay>, String> {
+    let scene_time = scene.time_of_day.as_ref();
+    scene_time.map(|scene_time| TimeOfDay::spawn(vfs::global(), scene_time)).transpose()
+  }
 
-    if inputs.is_key_pressed(Key::Named(NamedKey::Space)).is_just_pressed() {
-      if let Some(cube_physics_obj) = self
-        .physics_engine
-        .get_dyn_obj_mut("cube_physics") {
-        cube_physics_obj.set_velocity(glam::vec3(0.0, 5.0, 0.0));
+  // Destroys everything the game made on the renderer, then stops it. Nothing the game made
+  // should show up in the renderer's leak report after this
+  pub fn shutdown(mut self) -> Result<(), String> {
+    if let Some((recording, record_path)) = self.input_recording.take() {
+      let _ = recording.save(&record_path).inspect_err(|e| log!("at saving input recording: {e}"));
+    }
+    let mut messages = vec![];
+    if let Some(static_batches) = self.static_batches.take() {
+      static_batches.destroy(&mut messages);
+    }
+    for game_obj in self.game_objects.drain(..) {
+      if let Some(mesh) = game_obj.display_mesh {
+        messages.push(RendererMessage::DestroyTriMesh(mesh));
       }
     }
+    if let Some(lightmaps) = self.lightmaps.take() {
+      lightmaps.destroy(&mut messages);
+    }
+    self.level_streaming.unload_all(&mut messages);
+    if let Some(crowd) = self.crowd.take() {
+      crowd.destroy(&mut messages);
+    }
+    if let Some(foliage) = self.foliage.take() {
+      messages.push(RendererMessage::DestroyFoliage(foliage));
+    }
+    if let Some(benchmark) = self.benchmark.take() {
+      benchmark.into_props().destroy(&mut messages);
+    }
+    for light in self.lights.drain(..) {
+      messages.push(RendererMessage::RemoveLight(light));
+    }
+    self.grading_volumes.destroy(&mut messages);
+    messages.push(RendererMessage::DestroyParticleSystem(self.sparks));
+    self.scene_materials.destroy(&mut messages);
+    self
+      .renderer
+      .send_batch_sync(messages)
+      .map_err(|e| format!("at sending work to renderer: {e}"))?;
+    self.renderer.shutdown()?;
+    match self.leak_check.filter(|leak_check| leak_check.found_leaks()) {
+      Some(leak_check) => Err(format!(
+        "leak check found resources left after unloading the level, see {}",
+        leak_check.report_path().display()
+      )),
+      None => Ok(()),
+    }
+  }
 
-    self.physics_engine.run(frame_time);
+  #[cfg(feature = "physics")]
+  fn build_physics(scene: &Scene, config: &PhysicsConfig) -> Result<PhysicsEngine, String> {
+    let mut physics_engine =
+      PhysicsEngine::new(config.tick_hz as usize, config.max_steps_per_update as usize);
+    physics_engine.set_checked_math(match (config.checked_math, config.freeze_invalid_bodies) {
+      (false, _) => CheckedMath::Off,
+      (true, false) => CheckedMath::Log,
+      (true, true) => CheckedMath::Freeze,
+    });
+    physics_engine.set_substeps(config.substeps as usize);
+    physics_engine.set_origin(scene.origin());
+    // A baked mesh that doesn't load leaves every object with its own body, like before the bake
+    let baked_objects = scene
+      .collision
+      .as_ref()
+      .filter(|collision| {
+        physics_engine
+          .load_static_mesh(SCENE_COLLISION_BODY, vfs::global(), &collision.mesh)
+          .inspect_err(|e| log!("at loading scene collision: {e}"))
+          .is_ok()
+      })
+      .map(|collision| collision.objects.as_slice())
+      .unwrap_or_default();
+    for obj in scene.objects.iter().filter(|obj| !baked_objects.contains(&obj.name)) {
+      Self::add_physics(&mut physics_engine, obj)?;
+    }
+    Ok(physics_engine)
+  }
+
+  #[cfg(feature = "physics")]
+  fn add_physics(physics_engine: &mut PhysicsEngine, obj: &SceneObject) -> Result<(), String> {
+    if obj.physics.is_none() {
+      return Ok(());
+    }
+    physics_engine.add_rigid_body(
+      &obj.physics_name(),
+      obj.physics_mesh(),
+      obj.transform(),
+      obj.physics_mass(),
+    );
+    Ok(())
+  }
 
-    let mut mesh_ftex_list = vec![];
-    for go in self.game_objects.iter_mut() {
-      if let Some((phy_exists,  phy_name)) = &go.physics_name {
-        if *phy_exists {
-          if let Some(phy_transform) = self.physics_engine.get_dynamic_object_transform(phy_name) {
-            go.object_transform.transform = phy_transform;
+  // Bodies in for the objects of sections that arrived and out for the ones unloaded
+  fn apply_section_changes(&mut self, changes: Vec<SectionChange>) {
+    #[cfg(feature = "physics")]
+    for change in changes {
+      match change {
+        SectionChange::Loaded(objects) => {
+          for obj in objects.iter() {
+            let _ = Self::add_physics(&mut self.physics_engine, obj)
+              .inspect_err(|e| log!("at adding physics of {}: {e}", obj.name));
           }
-        } else {
-          if let Some(phy_transform) = self.physics_engine.get_static_object_transform(phy_name) {
-            go.object_transform.transform = phy_transform;
+        }
+        SectionChange::Unloaded(physics_names) => {
+          for physics_name in physics_names.iter() {
+            self.physics_engine.remove_body(physics_name);
+          }
+        }
+      }
+    }
+    #[cfg(not(feature = "physics"))]
+    let _ = changes;
+  }
+
+  pub fn mode(&self) -> GameMode {
+    self.mode
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.finished
+  }
+
+  // Screen shake, amounts stack up to 1 and wear off over about a second
+  pub fn add_trauma(&mut self, amount: f32) {
+    self.camera_effects.add_trauma(amount);
+  }
+
+  pub fn camera_effects_mut(&mut self) -> &mut CameraEffects {
+    &mut self.camera_effects
+  }
+
+  pub fn rng(&mut self) -> &mut RngService {
+    &mut self.rng
+  }
+
+  pub fn snapshot(&self) -> GameSnapshot {
+    GameSnapshot {
+      #[cfg(feature = "physics")]
+      physics: self.physics_engine.snapshot(),
+      rng: self.rng.snapshot(),
+    }
+  }
+
+  // Bodies added since the snapshot was taken are left as they are
+  pub fn restore(&mut self, snapshot: &GameSnapshot) -> Result<(), String> {
+    #[cfg(feature = "physics")]
+    self.physics_engine.restore(&snapshot.physics)?;
+    self.rng.restore(&snapshot.rng);
+    Ok(())
+  }
+
+  // Blended between ticks on the render thread, only drawn with depth_of_field on in the config
+  pub fn set_depth_of_field(&mut self, params: Option<DepthOfFieldParams>) {
+    self.depth_of_field = params;
+  }
+
+  // What the console's dof changes on top of, the focal length isn't set from there
+  fn dof_base_params(&self) -> DepthOfFieldParams {
+    self.depth_of_field.unwrap_or(DepthOfFieldParams {
+      focus_distance: 10.0,
+      aperture: 2.8,
+      focal_length_mm: 50.0,
+      autofocus: false,
+    })
+  }
+
+  // Replaces the scene on the next tick like opening it from the console would. Read through the
+  // vfs now, so a scene that doesn't load is reported here and the current one stays
+  pub fn load_scene(&mut self, path: &str) -> Result<(), String> {
+    let scene = Scene::load(vfs::global(), path)?;
+    self.pending_scene = Some((scene, PathBuf::from(path)));
+    Ok(())
+  }
+
+  // Shared falls back to CpuCopy when the gpu can't export memory
+  pub fn set_frame_export(&mut self, mode: Option<FrameExportMode>) -> Result<(), String> {
+    self.renderer.set_frame_export(mode)
+  }
+
+  // Latest frame finished while exporting, a couple of frames behind the one on screen
+  pub fn exported_frame(&self) -> Result<Option<ExportedFrame>, String> {
+    self.renderer.exported_frame()
+  }
+
+  // For an about or diagnostics screen, None until the render thread is up
+  pub fn engine_info(&self) -> Result<Option<EngineInfo>, String> {
+    let info = self.renderer.engine_info()?;
+    Ok(info.map(|info| info.with_crate("game-logic", env!("CARGO_PKG_VERSION"))))
+  }
+
+  pub fn play_camera_path(&mut self, path: CameraPath, looping: bool) {
+    let mut camera_animator = CameraAnimator::new(path, looping);
+    camera_animator.play();
+    self.camera_animator = Some(camera_animator);
+  }
+
+  pub fn pause_camera_path(&mut self) {
+    if let Some(camera_animator) = self.camera_animator.as_mut() {
+      camera_animator.pause();
+    }
+  }
+
+  pub fn resume_camera_path(&mut self) {
+    if let Some(camera_animator) = self.camera_animator.as_mut() {
+      camera_animator.play();
+    }
+  }
+
+  pub fn seek_camera_path(&mut self, time_ms: u128) {
+    if let Some(camera_animator) = self.camera_animator.as_mut() {
+      camera_animator.seek(time_ms);
+    }
+  }
+
+  pub fn stop_camera_path(&mut self) {
+    self.camera_animator = None;
+  }
+
+  // Circles the scene origin once, easing in and out at the ends
+  fn demo_camera_orbit(&self) -> Result<CameraPath, String> {
+    let start = self.camera.pos.truncate();
+    let radius = glam::vec2(start.x, start.z).length().max(1.0);
+    let keys = (0..=4)
+      .map(|i| {
+        let angle = start.z.atan2(start.x) + i as f32 * std::f32::consts::FRAC_PI_2;
+        CameraKey {
+          time_ms: DEMO_CAMERA_ORBIT_MS * i / 4,
+          pos: glam::vec3(radius * angle.cos(), start.y, radius * angle.sin()),
+          look_at: glam::Vec3::ZERO,
+          easing: match i {
+            0 => Easing::EaseIn,
+            3 => Easing::EaseOut,
+            _ => Easing::Linear,
+          },
+        }
+      })
+      .collect::<Vec<_>>();
+    CameraPath::new(keys, PathInterpolation::CatmullRom)
+  }
+
+  pub fn save_scene(&self, path: &Path) -> Result<(), String> {
+    self.scene.save(path)
+  }
+
+  // Places a prefab from the scene file as a new instance, saved with the scene like the ones
+  // loaded from it. Returns the instance's name, its objects are named instance/object
+  pub fn spawn_prefab(&mut self, name: &str, transform: glam::Mat4) -> Result<String, String> {
+    let mut messages = vec![];
+    let instance = self.spawn_prefab_instance(name, transform, &mut messages)?;
+    self
+      .renderer
+      .send_batch_sync(messages)
+      .map_err(|e| format!("at sending prefab {name} to renderer: {e}"))?;
+    Ok(instance)
+  }
+
+  fn spawn_prefab_instance(
+    &mut self,
+    name: &str,
+    transform: glam::Mat4,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<String, String> {
+    let first = self.scene.objects.len();
+    let instance = self.scene.spawn_prefab(name, transform)?;
+    // Objects spawned while playing stay after the scene's
+    let runtime_objects = self.game_objects.split_off(first.min(self.game_objects.len()));
+    for obj in self.scene.objects[first..].iter() {
+      let game_obj =
+        GameObject::from_scene_object(&mut self.renderer, obj, &mut self.scene_materials, messages);
+      self.game_objects.push(game_obj);
+    }
+    self.game_objects.extend(runtime_objects);
+    #[cfg(any(feature = "physics", feature = "scripting"))]
+    if self.mode == GameMode::Play {
+      for obj in self.scene.objects[first..].iter() {
+        #[cfg(feature = "physics")]
+        let _ = Self::add_physics(&mut self.physics_engine, obj)
+          .inspect_err(|e| log!("at adding physics of {}: {e}", obj.name));
+        #[cfg(feature = "scripting")]
+        if let Some(script) = &obj.script {
+          let _ = self
+            .scripts
+            .add_component(&obj.name, script)
+            .inspect_err(|e| log!("at starting script of {}: {e}", obj.name));
+        }
+      }
+    }
+    Ok(instance)
+  }
+
+  fn set_mode(&mut self, mode: GameMode, messages: &mut Vec<RendererMessage>) -> Result<(), String> {
+    if let Some(static_batches) = self.static_batches.take() {
+      static_batches.destroy(messages);
+    }
+    self.despawn_runtime_objects(messages);
+    let changes = self.level_streaming.unload_all(messages);
+    self.apply_section_changes(changes);
+    #[cfg(feature = "scripting")]
+    self.scripts.clear();
+    // Physics only runs while playing, lines left from before would stay where they were
+    messages.push(RendererMessage::SetDebugLines(vec![]));
+    match mode {
+      // Physics always starts from the edited scene
+      GameMode::Play => {
+        #[cfg(feature = "physics")]
+        {
+          self.physics_engine = Self::build_physics(&self.scene, &self.physics_config)?;
+          self.repro_history = ReproHistory::default();
+        }
+        self.rng.reseed(self.scene.seed);
+        self.static_batches = Some(StaticBatches::build(
+          &mut self.renderer,
+          &self.scene,
+          &self.game_objects,
+          self.lightmaps.as_ref(),
+          messages,
+        ));
+        messages.push(RendererMessage::SetEditorGrid(None));
+        messages.push(RendererMessage::SetGizmo(None));
+        #[cfg(feature = "scripting")]
+        self.start_scripts();
+      }
+      #[cfg(not(feature = "editor"))]
+      GameMode::Edit => return Err("built without the editor".to_string()),
+      #[cfg(feature = "editor")]
+      GameMode::Edit => {
+        for (go, obj) in self.game_objects.iter_mut().zip(self.scene.objects.iter()) {
+          go.object_transform.transform = obj.transform();
+        }
+        messages.push(RendererMessage::SetEditorGrid(Some(GridSettings::default())));
+      }
+    }
+    self.mode = mode;
+    Ok(())
+  }
+
+  // Replaces every object with what the scene file has now, the mode is entered again so physics
+  // or the edit transforms start from the new scene
+  fn reload_scene(&mut self, messages: &mut Vec<RendererMessage>) -> Result<(), String> {
+    let scene = Scene::load(vfs::global(), &self.scene_path.to_string_lossy())?;
+    self.respawn_scene(scene, messages)
+  }
+
+  // Replaces everything made from the current scene with what the new one makes
+  fn respawn_scene(
+    &mut self,
+    scene: Scene,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<(), String> {
+    messages.push(RendererMessage::SetRooms(scene.room_graph()?));
+    if let Some(static_batches) = self.static_batches.take() {
+      static_batches.destroy(messages);
+    }
+    self.respawn_scene_objects(&scene, messages)?;
+    for light in self.lights.drain(..) {
+      messages.push(RendererMessage::RemoveLight(light));
+    }
+    self.lights = Self::spawn_lights(&mut self.renderer, &scene, messages);
+    let grading_volumes = GradingVolumes::spawn(&mut self.renderer, &scene, messages);
+    std::mem::replace(&mut self.grading_volumes, grading_volumes).destroy(messages);
+    messages.push(RendererMessage::SetReflectionProbes(scene.reflection_probes()));
+    self.time_of_day = Self::spawn_time_of_day(&scene)?;
+    let changes = self.level_streaming.unload_all(messages);
+    self.apply_section_changes(changes);
+    self.level_streaming = LevelStreaming::new(&scene);
+    // The old level's meshes left holes all over the mesh buffers
+    messages.push(RendererMessage::CompactMeshBuffers);
+    self.scene = scene;
+    #[cfg(feature = "editor")]
+    {
+      self.editor = Editor::new();
+    }
+    self.set_mode(self.mode, messages)
+  }
+
+  // Every object of scene drawn again from scratch, along with its lightmap
+  fn respawn_scene_objects(
+    &mut self,
+    scene: &Scene,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<(), String> {
+    for game_obj in self.game_objects.drain(..) {
+      if let Some(mesh) = game_obj.display_mesh {
+        messages.push(RendererMessage::DestroyTriMesh(mesh));
+      }
+    }
+    if let Some(lightmaps) = self.lightmaps.take() {
+      lightmaps.destroy(messages);
+    }
+    self.lightmaps = SceneLightmaps::spawn(&mut self.renderer, scene, messages)?;
+    for obj in scene.objects.iter() {
+      let game_obj = GameObject::from_lightmapped_scene_object(
+        &mut self.renderer,
+        obj,
+        &mut self.scene_materials,
+        self.lightmaps.as_ref(),
+        messages,
+      );
+      self.game_objects.push(game_obj);
+    }
+    Ok(())
+  }
+
+  // Level build step, bakes the scene as edited and draws it with the new lightmap right away
+  fn bake_lightmap(&mut self, messages: &mut Vec<RendererMessage>) -> Result<(), String> {
+    let lightmap = lightmaps::bake(&self.scene, &self.scene_path)?;
+    self.console_hud.reply(tr!("console.lightmap_baked", path = lightmap.texture));
+    self.scene.lightmap = Some(lightmap);
+    let scene = self.scene.clone();
+    self.respawn_scene_objects(&scene, messages)?;
+    // Captured again with the baked light
+    messages.push(RendererMessage::SetReflectionProbes(scene.reflection_probes()));
+    Ok(())
+  }
+
+  #[cfg(feature = "physics")]
+  fn bake_collision(&mut self) -> Result<(), String> {
+    let collision = self.scene.bake_collision(&self.scene_path)?;
+    self.console_hud.reply(tr!("console.collision_baked", path = collision.mesh));
+    self.scene.collision = Some(collision);
+    Ok(())
+  }
+
+  // Moves the scene's origin to under the camera, so large worlds can be edited far out without
+  // f32 positions getting coarse. Everything made from the scene is made again at the new
+  // positions, which only works out while nothing is simulated
+  fn recenter_origin(&mut self, messages: &mut Vec<RendererMessage>) -> Result<(), String> {
+    if self.mode != GameMode::Edit {
+      return Err("the origin only moves while editing".to_string());
+    }
+    let shift = self.camera.pos.truncate().round();
+    let mut scene = self.scene.clone();
+    scene.shift_origin(shift);
+    self.camera.pos -= shift.extend(0.0);
+    // Bookmark jumps and paths ease between positions from before the shift
+    self.camera_bookmarks.cancel();
+    self.stop_camera_path();
+    self.respawn_scene(scene, messages)
+  }
+
+  // Where a scene object is drawn, in world space
+  pub fn world_transform(&self, name: &str) -> Option<WorldTransform> {
+    let idx = self.scene.objects.iter().position(|obj| obj.name == name)?;
+    let transform = self.game_objects.get(idx)?.object_transform.transform;
+    Some(WorldTransform::from_local(self.scene.origin(), transform))
+  }
+
+  // Objects spawned while playing come after the scene's, they go when play stops
+  fn despawn_runtime_objects(&mut self, messages: &mut Vec<RendererMessage>) {
+    let scene_len = self.scene.objects.len().min(self.game_objects.len());
+    for game_obj in self.game_objects.drain(scene_len..) {
+      if let Some(mesh) = game_obj.display_mesh {
+        messages.push(RendererMessage::DestroyTriMesh(mesh));
+      }
+    }
+  }
+
+  // A script that doesn't load leaves its object without one, the rest still run
+  #[cfg(feature = "scripting")]
+  fn start_scripts(&mut self) {
+    for obj in self.scene.objects.iter() {
+      let Some(script) = &obj.script else { continue };
+      let _ = self
+        .scripts
+        .add_component(&obj.name, script)
+        .inspect_err(|e| log!("at starting script of {}: {e}", obj.name));
+    }
+  }
+
+  #[cfg(feature = "scripting")]
+  fn tick_scripts(
+    &mut self,
+    inputs: &InputAggregator,
+    frame_time: u128,
+    messages: &mut Vec<RendererMessage>,
+  ) {
+    profile_scope!("scripts");
+    let positions = self
+      .game_objects
+      .iter()
+      .map(|go| (go.name.clone(), go.object_transform.transform.w_axis.truncate()))
+      .collect();
+    let commands = self.scripts.tick(inputs, positions, frame_time as f32 / 1_000_000.0);
+    for command in commands {
+      match command {
+        ScriptCommand::Spawn { name, size, position, script } => {
+          let obj = SceneObject {
+            name,
+            shape: SceneShape::Cuboid { size: [size; 3] },
+            position: position.to_array(),
+            rotation: glam::Quat::IDENTITY.to_array(),
+            physics: Some(PhysicsProperties { dynamic: true, mass: 1.0 }),
+            script,
+            model: None,
+            texture: None,
+            prefab: None,
+          };
+          let game_obj = GameObject::from_scene_object(
+            &mut self.renderer,
+            &obj,
+            &mut self.scene_materials,
+            messages,
+          );
+          self.game_objects.push(game_obj);
+          #[cfg(feature = "physics")]
+          let _ = Self::add_physics(&mut self.physics_engine, &obj)
+            .inspect_err(|e| log!("at adding physics of spawned {}: {e}", obj.name));
+          if let Some(script) = &obj.script {
+            let _ = self
+              .scripts
+              .add_component(&obj.name, script)
+              .inspect_err(|e| log!("at starting script of {}: {e}", obj.name));
+          }
+        }
+        #[cfg(feature = "physics")]
+        ScriptCommand::ApplyImpulse { name, impulse } => {
+          let Some(go) = self.game_objects.iter().find(|go| go.name == name) else { continue };
+          let Some((true, physics_name)) = &go.physics_name else { continue };
+          let center = go.object_transform.transform.w_axis.truncate();
+          let _ = self
+            .physics_engine
+            .apply_impulse(physics_name, impulse, center)
+            .inspect_err(|e| log!("at applying impulse to {name}: {e}"));
+        }
+        // Nothing moves without physics
+        #[cfg(not(feature = "physics"))]
+        ScriptCommand::ApplyImpulse { .. } => {}
+        ScriptCommand::AddTrauma(amount) => self.camera_effects.add_trauma(amount),
+      }
+    }
+  }
+
+  fn handle_events(&mut self, messages: &mut Vec<RendererMessage>) {
+    if let Some(view) = self.view_resized_events.drain().last() {
+      self.hud_layout.view_resized(&view);
+    }
+    if self.focus_lost_events.drain().count() > 0
+      && self.camera_animator.as_ref().is_some_and(|animator| animator.is_playing())
+    {
+      self.pause_camera_path();
+      self.camera_path_paused_by_focus = true;
+    }
+    if self.focus_gained_events.drain().count() > 0 && self.camera_path_paused_by_focus {
+      self.resume_camera_path();
+      self.camera_path_paused_by_focus = false;
+    }
+    let scene_path = vfs::normalize_path(&self.scene_path.to_string_lossy());
+    let reloaded = self.asset_reloaded_events.drain().collect::<Vec<_>>();
+    for event in reloaded.iter() {
+      match localization::reload(&event.path) {
+        Ok(true) => self.console_hud.reply(tr!("console.strings_reloaded", path = event.path)),
+        Ok(false) => {}
+        Err(e) => log!("at reloading strings: {e}"),
+      }
+    }
+    #[cfg(feature = "scripting")]
+    for event in reloaded.iter() {
+      match self.scripts.reload(&event.path) {
+        Ok(true) => self.console_hud.reply(tr!("console.script_reloaded", path = event.path)),
+        Ok(false) => {}
+        Err(e) => log!("at reloading script: {e}"),
+      }
+    }
+    if let Some(time_of_day) = self.time_of_day.as_mut() {
+      for event in reloaded.iter() {
+        match time_of_day.reload(vfs::global(), &event.path) {
+          Ok(true) => {
+            self.console_hud.reply(tr!("console.time_of_day_reloaded", path = event.path))
+          }
+          Ok(false) => {}
+          Err(e) => log!("at reloading time of day curves: {e}"),
+        }
+      }
+    }
+    if reloaded.iter().any(|event| vfs::normalize_path(&event.path) == scene_path) {
+      let _ = self
+        .reload_scene(messages)
+        .inspect(|_| self.console_hud.reply(tr!("console.scene_reloaded", path = scene_path)))
+        .inspect_err(|e| log!("at reloading scene: {e}"));
+    }
+  }
+
+  // Models and images go where the cursor points, skies replace the environment
+  #[cfg(feature = "editor")]
+  fn import_dropped_file(
+    &mut self,
+    path: &Path,
+    camera: &Camera3D,
+    cursor_pos: Option<glam::Vec2>,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<(), String> {
+    let kind = ImportKind::of(path).ok_or("it isn't a model or image the engine reads")?;
+    let vfs_path = asset_import::import_file(&self.engine_config.assets.mounts, path)?;
+    match kind {
+      ImportKind::Environment => {
+        messages.push(RendererMessage::SetEnvironment(Some(vfs_path.clone())));
+      }
+      ImportKind::Model | ImportKind::Texture => {
+        let obj = asset_import::dropped_object(&self.scene, kind, &vfs_path)?;
+        let change = self.editor.place(&mut self.scene, camera, cursor_pos, obj);
+        self.apply_scene_change(change, messages);
+      }
+    }
+    self.console_hud.reply(tr!("console.file_imported", path = vfs_path));
+    Ok(())
+  }
+
+  // Opens a scene picked in the desktop's file dialog in place of the current one. It has to be
+  // in an asset directory, scenes are read through the vfs
+  #[cfg(feature = "file-dialog")]
+  fn open_scene_dialog(&mut self, messages: &mut Vec<RendererMessage>) -> Result<(), String> {
+    let start_dir = std::env::current_dir().map_err(|e| format!("at opening scene: {e}"))?;
+    let Some(path) = file_dialog::pick_scene_file(DialogKind::Open, &start_dir)? else {
+      return Ok(());
+    };
+    let vfs_path = asset_import::mounted_path(&self.engine_config.assets.mounts, &path)
+      .ok_or(format!("{} isn't in an asset directory", path.display()))?;
+    let scene = Scene::load(vfs::global(), &vfs_path)?;
+    self.respawn_scene(scene, messages)?;
+    self.scene_path = PathBuf::from(&vfs_path);
+    self.console_hud.reply(tr!("console.scene_opened", path = vfs_path));
+    Ok(())
+  }
+
+  // Saves to a file picked in the desktop's file dialog, later saves go there too
+  #[cfg(feature = "file-dialog")]
+  fn save_scene_dialog(&mut self) -> Result<(), String> {
+    let start_dir = std::env::current_dir().map_err(|e| format!("at saving scene: {e}"))?;
+    let Some(path) = file_dialog::pick_scene_file(DialogKind::Save, &start_dir)? else {
+      return Ok(());
+    };
+    self.save_scene(&path)?;
+    self.console_hud.reply(tr!("console.scene_saved", path = path.display()));
+    // Kept as a vfs path where it can be, so reloading it when it changes still works
+    self.scene_path = asset_import::mounted_path(&self.engine_config.assets.mounts, &path)
+      .map(PathBuf::from)
+      .unwrap_or(path);
+    Ok(())
+  }
+
+  #[cfg(feature = "editor")]
+  fn apply_scene_change(&mut self, change: SceneChange, messages: &mut Vec<RendererMessage>) {
+    match change {
+      SceneChange::Spawned(idx) => {
+        let game_obj = GameObject::from_scene_object(
+          &mut self.renderer,
+          &self.scene.objects[idx],
+          &mut self.scene_materials,
+          messages,
+        );
+        self.game_objects.insert(idx, game_obj);
+      }
+      SceneChange::Removed(idx) => {
+        let game_obj = self.game_objects.remove(idx);
+        if let Some(mesh) = game_obj.display_mesh {
+          messages.push(RendererMessage::DestroyTriMesh(mesh));
+        }
+      }
+      SceneChange::Moved(idx) => {
+        self.game_objects[idx].object_transform.transform = self.scene.objects[idx].transform();
+      }
+      SceneChange::Reshaped(idx) => {
+        if let Some(mesh) = self.game_objects[idx].display_mesh {
+          messages.push(RendererMessage::DestroyTriMesh(mesh));
+        }
+        self.game_objects[idx] = GameObject::from_scene_object(
+          &mut self.renderer,
+          &self.scene.objects[idx],
+          &mut self.scene_materials,
+          messages,
+        );
+      }
+    }
+  }
+
+  // Replaces the crowd there is, a count of 0 just removes it
+  fn spawn_crowd(
+    &mut self,
+    count: &str,
+    gltf_path: Option<&str>,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<(), String> {
+    let count = count.parse::<u32>().map_err(|e| format!("at parsing crowd size {count}: {e}"))?;
+    if let Some(crowd) = self.crowd.take() {
+      crowd.destroy(messages);
+    }
+    if count > 0 {
+      let crowd =
+        Crowd::spawn(&mut self.renderer, count, gltf_path, self.scene_materials.lit, messages)?;
+      self.crowd = Some(crowd);
+    }
+    Ok(())
+  }
+
+  // Grass over the faces of static objects that point up, replacing the grass there is. A density
+  // of 0 just removes it
+  fn spawn_foliage(
+    &mut self,
+    density: &str,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<(), String> {
+    let density =
+      density.parse::<f32>().map_err(|e| format!("at parsing foliage density {density}: {e}"))?;
+    if let Some(foliage) = self.foliage.take() {
+      messages.push(RendererMessage::DestroyFoliage(foliage));
+    }
+    if density <= 0.0 {
+      return Ok(());
+    }
+    let mut scatter = FoliageScatter::default();
+    for (i, obj) in self.scene.objects.iter().enumerate() {
+      if obj.physics.is_some_and(|physics| physics.dynamic) {
+        continue;
+      }
+      let params = ScatterParams {
+        density,
+        seed: self.scene.seed as u32 ^ i as u32,
+        ..Default::default()
+      };
+      scatter.add_mesh(&obj.shape.make_tri_mesh(), obj.transform(), &params);
+    }
+    if scatter.instances.is_empty() {
+      return Err("no static surfaces facing up to grow grass on".to_string());
+    }
+    self.console_hud.reply(tr!("console.foliage_scattered", count = scatter.instances.len()));
+    let handle = self.renderer.create_foliage_handle();
+    messages.push(RendererMessage::CreateFoliage(
+      format!("foliage_{handle:?}"),
+      FoliageCPU {
+        card: make_foliage_card(GRASS_CARD_WIDTH, GRASS_CARD_HEIGHT, GRASS_CARD_PLANES),
+        scatter,
+        params: FoliageParams::default(),
+      },
+      None,
+      handle,
+    ));
+    self.foliage = Some(handle);
+    Ok(())
+  }
+
+  fn set_wind(&self, strength: &str, messages: &mut Vec<RendererMessage>) -> Result<(), String> {
+    let strength =
+      strength.parse::<f32>().map_err(|e| format!("at parsing wind strength {strength}: {e}"))?;
+    messages.push(RendererMessage::SetWind(WindParams { strength, ..Default::default() }));
+    Ok(())
+  }
+
+  // Brief freeze for landing hits, the camera and effects keep moving through it
+  pub fn hit_stop(&mut self, duration: Duration) {
+    self.time_scale.hit_stop(duration.as_micros());
+  }
+
+  pub fn set_time_scale(&mut self, scale: f32) {
+    self.time_scale.set_scale(scale);
+  }
+
+  fn set_orbit_target(&mut self, target: &str, boom_length: Option<&str>) -> Result<(), String> {
+    if !self.scene.objects.iter().any(|obj| obj.name == target) {
+      return Err(format!("no scene object named {target} to orbit"));
+    }
+    let mut orbit_camera = OrbitCamera::new(OrbitCameraConfig::default(), target);
+    orbit_camera.match_camera(&self.camera);
+    if let Some(boom_length) = boom_length {
+      orbit_camera.set_boom_length(
+        boom_length
+          .parse::<f32>()
+          .map_err(|e| format!("at parsing boom length {boom_length}: {e}"))?,
+      );
+    }
+    self.orbit_camera = Some(orbit_camera);
+    Ok(())
+  }
+
+  fn bookmark_idx(&self, name: &str) -> Result<usize, String> {
+    self
+      .scene
+      .camera_bookmarks
+      .iter()
+      .position(|bookmark| bookmark.name == name)
+      .ok_or(format!("no camera bookmark named {name}"))
+  }
+
+  // Replaces the bookmark with the same name, they only reach the file when the scene is saved
+  fn save_bookmark(&mut self, name: &str) {
+    let bookmark = CameraPose::of_camera(&self.camera, self.camera_fov).to_bookmark(name);
+    match self.bookmark_idx(name) {
+      Ok(idx) => self.scene.camera_bookmarks[idx] = bookmark,
+      Err(_) => self.scene.camera_bookmarks.push(bookmark),
+    }
+  }
+
+  fn jump_to_bookmark(&mut self, idx: usize) {
+    let Some(bookmark) = self.scene.camera_bookmarks.get(idx) else { return };
+    self.orbit_camera = None;
+    self.camera_bookmarks.jump(CameraPose::of_camera(&self.camera, self.camera_fov), bookmark);
+  }
+
+  // Number keys jump to the bookmark in that place, with control held the camera is saved there
+  // instead. Saving past the last bookmark adds a new one after it
+  #[cfg(feature = "editor")]
+  fn update_bookmark_keys(&mut self, inputs: &mut InputAggregator) {
+    for idx in 0..9 {
+      let key = Key::Character((idx + 1).to_string().into());
+      if !inputs.is_key_pressed(key).is_just_pressed() {
+        continue;
+      }
+      if !inputs.modifiers().control_key() {
+        self.jump_to_bookmark(idx);
+        continue;
+      }
+      let name = match self.scene.camera_bookmarks.get(idx) {
+        Some(bookmark) => bookmark.name.clone(),
+        None => format!("view {}", self.scene.camera_bookmarks.len() + 1),
+      };
+      self.save_bookmark(&name);
+      self.console_hud.reply(tr!("console.bookmark_saved", name = name));
+    }
+  }
+
+  fn start_recording(&mut self, settings: RecordingSettings) -> Result<(), String> {
+    let output = match &settings.output {
+      RecordingOutput::PngSequence(dir) => dir.display().to_string(),
+      RecordingOutput::Pipe(program, _) => program.clone(),
+    };
+    self.renderer.start_recording(settings)?;
+    self.recording = true;
+    self.console_hud.reply(tr!("console.recording_started", output = output));
+    Ok(())
+  }
+
+  // object is a scene object's name, or selected for the one picked in the editor
+  #[cfg(feature = "physics")]
+  fn toggle_debug_view(
+    &mut self,
+    object: &str,
+    view: &str,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<(), String> {
+    let view = DebugView::parse(view).ok_or(format!("unknown debug view {view}"))?;
+    let obj = match object {
+      #[cfg(feature = "editor")]
+      "selected" => self
+        .editor
+        .selected()
+        .and_then(|idx| self.scene.objects.get(idx))
+        .ok_or("no object selected in the editor")?,
+      _ => self
+        .scene
+        .objects
+        .iter()
+        .find(|obj| obj.name == object)
+        .ok_or(format!("no object named {object}"))?,
+    };
+    if obj.physics.is_none() {
+      return Err(format!("{} has no physics body to draw", obj.name));
+    }
+    let enabled = self.debug_draw.toggle(&obj.physics_name(), view);
+    if self.debug_draw.is_empty() {
+      messages.push(RendererMessage::SetDebugLines(vec![]));
+    }
+    self.console_hud.reply(
+      tr!(
+        "console.debug_view_enabled",
+        view = format!("{view:?}"),
+        object = obj.name,
+        enabled = enabled
+      )
+    );
+    Ok(())
+  }
+
+  #[cfg(feature = "physics")]
+  fn save_repro(&mut self) -> Result<(), String> {
+    let bundle = self
+      .repro_history
+      .bundle(&self.scene_path, self.scene.to_toml()?, &self.engine_config)
+      .ok_or("nothing played yet to save a repro bundle of")?;
+    let saved_at =
+      SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+    std::fs::create_dir_all(REPROS_DIR)
+      .map_err(|e| format!("at creating repro bundle directory {REPROS_DIR}: {e}"))?;
+    let path = Path::new(REPROS_DIR).join(format!("repro_{saved_at}.bin"));
+    bundle.save(&path)?;
+    let seconds = self.engine_config.simulation.tick_duration() * bundle.inputs.ticks as u32;
+    let seconds = format!("{:.1}", seconds.as_secs_f32());
+    self.console_hud.reply(tr!("console.repro_saved", seconds = seconds, path = path.display()));
+    Ok(())
+  }
+
+  fn stop_recording(&mut self) -> Result<(), String> {
+    self.renderer.stop_recording()?;
+    self.recording = false;
+    self.console_hud.reply(tr!("console.recording_stopped"));
+    Ok(())
+  }
+
+  fn run_console_command(
+    &mut self,
+    line: &str,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<(), String> {
+    match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+      [] => Ok(()),
+      ["save"] => self
+        .save_scene(&self.scene_path)
+        .inspect(|_| {
+          self.console_hud.reply(tr!("console.scene_saved", path = self.scene_path.display()))
+        }),
+      #[cfg(feature = "file-dialog")]
+      ["save", "as"] => self.save_scene_dialog(),
+      #[cfg(feature = "file-dialog")]
+      ["open"] => self.open_scene_dialog(messages),
+      ["trace"] => profiler::export_chrome_trace(Path::new(PROFILE_TRACE_PATH))
+        .inspect(|_| {
+          self.console_hud.reply(tr!("console.trace_written", path = PROFILE_TRACE_PATH))
+        }),
+      ["mode", "play"] => self.set_mode(GameMode::Play, messages),
+      ["mode", "edit"] => self.set_mode(GameMode::Edit, messages),
+      ["reload", path] => {
+        event_bus::global().publish(AssetReloaded { path: path.to_string() });
+        Ok(())
+      }
+      ["crowd", count] => self.spawn_crowd(count, None, messages),
+      ["crowd", count, gltf_path] => self.spawn_crowd(count, Some(gltf_path), messages),
+      ["foliage", density] => self.spawn_foliage(density, messages),
+      ["prefab", name] => {
+        let position =
+          self.camera.pos.truncate() + self.camera.look_dir.truncate().normalize() * 5.0;
+        self
+          .spawn_prefab_instance(name, glam::Mat4::from_translation(position), messages)
+          .map(|instance| {
+            self.console_hud.reply(tr!("console.prefab_spawned", instance = instance))
+          })
+      }
+      ["wind", strength] => self.set_wind(strength, messages),
+      ["environment", "off"] => {
+        messages.push(RendererMessage::SetEnvironment(None));
+        Ok(())
+      }
+      ["environment", path] => {
+        messages.push(RendererMessage::SetEnvironment(Some(path.to_string())));
+        Ok(())
+      }
+      ["camera", "orbit", target] => self.set_orbit_target(target, None),
+      ["camera", "orbit", target, boom_length] => self.set_orbit_target(target, Some(boom_length)),
+      ["camera", "free"] => {
+        self.orbit_camera = None;
+        Ok(())
+      }
+      ["minimap", "on"] => {
+        let settings =
+          MinimapSettings { layers: LayerMask::ALL.without(EDITOR_LAYER), ..Default::default() };
+        messages.push(RendererMessage::SetMinimap(Some(settings)));
+        Ok(())
+      }
+      ["minimap", "off"] => {
+        messages.push(RendererMessage::SetMinimap(None));
+        Ok(())
+      }
+      ["memory", "on"] => {
+        messages.push(RendererMessage::SetMemoryHeatmap(true));
+        Ok(())
+      }
+      ["memory", "off"] => {
+        messages.push(RendererMessage::SetMemoryHeatmap(false));
+        Ok(())
+      }
+      ["perf", "on"] => {
+        self.perf_hud = true;
+        messages.push(RendererMessage::SetPerfHud(true));
+        Ok(())
+      }
+      ["perf", "off"] => {
+        self.perf_hud = false;
+        messages.push(RendererMessage::SetPerfHud(false));
+        Ok(())
+      }
+      // Heatmap of what changed between two frames, like before and after a physics change
+      ["diff", "before"] => {
+        messages.push(RendererMessage::FrameDiff(FrameDiffCommand::CaptureBefore));
+        Ok(())
+      }
+      ["diff", "after"] => {
+        messages.push(RendererMessage::FrameDiff(FrameDiffCommand::CaptureAfter));
+        Ok(())
+      }
+      ["diff", "show"] => {
+        messages.push(RendererMessage::FrameDiff(FrameDiffCommand::Show(true)));
+        Ok(())
+      }
+      ["diff", "hide"] => {
+        messages.push(RendererMessage::FrameDiff(FrameDiffCommand::Show(false)));
+        Ok(())
+      }
+      ["diff", "clear"] => {
+        messages.push(RendererMessage::FrameDiff(FrameDiffCommand::Clear));
+        Ok(())
+      }
+      ["labels", "on"] => {
+        self.object_labels = true;
+        Ok(())
+      }
+      ["labels", "off"] => {
+        self.object_labels = false;
+        messages.push(RendererMessage::SetWorldLabels(vec![]));
+        Ok(())
+      }
+      ["origin"] => {
+        let camera = world_position(self.scene.origin(), self.camera.pos.truncate());
+        self.console_hud.reply(tr!(
+          "console.origin",
+          camera = camera,
+          origin = self.scene.origin()
+        ));
+        Ok(())
+      }
+      ["origin", "recenter"] => {
+        self.recenter_origin(messages)?;
+        self.console_hud.reply(tr!("console.origin_moved", origin = self.scene.origin()));
+        Ok(())
+      }
+      // In the order they ran last tick
+      ["systems"] => {
+        for timing in self.system_timings() {
+          let ms = format!("{:.3}", timing.time.as_secs_f64() * 1000.0);
+          self.console_hud.reply(
+            tr!("console.system_timing", stage = timing.stage, system = timing.system, ms = ms)
+          );
+        }
+        Ok(())
+      }
+      #[cfg(feature = "physics")]
+      ["repro", "save"] => self.save_repro(),
+      #[cfg(feature = "physics")]
+      ["debug", "off"] => {
+        self.debug_draw.clear();
+        messages.push(RendererMessage::SetDebugLines(vec![]));
+        Ok(())
+      }
+      #[cfg(feature = "physics")]
+      ["debug", object, view] => self.toggle_debug_view(object, view, messages),
+      ["timescale", scale] => {
+        let scale =
+          scale.parse::<f32>().map_err(|e| format!("at parsing time scale {scale}: {e}"))?;
+        self.set_time_scale(scale);
+        self.console_hud.reply(tr!("console.time_scale", scale = self.time_scale.scale()));
+        Ok(())
+      }
+      ["hitstop", millis] => {
+        let millis =
+          millis.parse::<u64>().map_err(|e| format!("at parsing hit-stop length {millis}: {e}"))?;
+        self.hit_stop(Duration::from_millis(millis));
+        Ok(())
+      }
+      ["validation", "on"] => {
+        validation::set_enabled(true);
+        Ok(())
+      }
+      ["validation", "off"] => {
+        validation::set_enabled(false);
+        Ok(())
+      }
+      ["validation", "report"] => {
+        self.console_hud.reply(validation::summary());
+        Ok(())
+      }
+      ["dof", "off"] => {
+        self.set_depth_of_field(None);
+        Ok(())
+      }
+      ["dof", "auto", aperture] => {
+        let aperture =
+          aperture.parse::<f32>().map_err(|e| format!("at parsing aperture {aperture}: {e}"))?;
+        let params = DepthOfFieldParams { aperture, autofocus: true, ..self.dof_base_params() };
+        self.set_depth_of_field(Some(params));
+        Ok(())
+      }
+      ["dof", focus_distance, aperture] => {
+        let focus_distance = focus_distance
+          .parse::<f32>()
+          .map_err(|e| format!("at parsing focus distance {focus_distance}: {e}"))?;
+        let aperture =
+          aperture.parse::<f32>().map_err(|e| format!("at parsing aperture {aperture}: {e}"))?;
+        let params =
+          DepthOfFieldParams { focus_distance, aperture, autofocus: false, ..self.dof_base_params() };
+        self.set_depth_of_field(Some(params));
+        Ok(())
+      }
+      ["about"] => {
+        let info = self.engine_info()?.ok_or("renderer isn't up yet")?;
+        for line in info.report_lines() {
+          self.console_hud.reply(line);
+        }
+        Ok(())
+      }
+      ["record"] => self.start_recording(png_recording(RECORDING_FPS)),
+      ["record", "stop"] => self.stop_recording(),
+      ["record", "pipe", fps, program, args @ ..] => {
+        let fps = fps.parse::<u32>().map_err(|e| format!("at parsing recording fps {fps}: {e}"))?;
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        self.start_recording(RecordingSettings {
+          output: RecordingOutput::Pipe(program.to_string(), args),
+          fps,
+        })
+      }
+      ["record", fps] => {
+        let fps = fps.parse::<u32>().map_err(|e| format!("at parsing recording fps {fps}: {e}"))?;
+        self.start_recording(png_recording(fps))
+      }
+      ["quality", preset] => {
+        let quality = QualityPreset::parse(preset).ok_or(format!("no quality preset {preset}"))?;
+        messages.push(RendererMessage::SetQuality(quality));
+        self.console_hud.reply(tr!("console.quality_set", preset = quality.name()));
+        Ok(())
+      }
+      ["capture", frames] => {
+        let frames =
+          frames.parse::<u32>().map_err(|e| format!("at parsing capture frames {frames}: {e}"))?;
+        messages.push(RendererMessage::TriggerCapture(frames));
+        Ok(())
+      }
+      ["language", language] => localization::set_language(language)
+        .map(|_| self.console_hud.reply(tr!("console.language_set", language = language))),
+      ["cache"] => {
+        let cache = asset_cache::global();
+        let stats = cache.stats();
+        match cache.dir() {
+          Some(dir) => self.console_hud.reply(
+            tr!(
+              "console.cache_stats",
+              dir = dir.display(),
+              hits = stats.hits,
+              misses = stats.misses,
+              stored = stats.stored_bytes
+            )
+          ),
+          None => self.console_hud.reply(tr!("console.cache_off")),
+        }
+        Ok(())
+      }
+      ["time"] => {
+        match &self.time_of_day {
+          Some(time_of_day) => {
+            self.console_hud.reply(tr!(
+              "console.time_of_day",
+              time = clock_time(time_of_day.hour())
+            ));
           }
+          None => self.console_hud.reply(tr!("console.time_of_day_off")),
         }
+        Ok(())
+      }
+      ["time", hour] => {
+        let hour = hour.parse::<f32>().map_err(|e| format!("at parsing hour {hour}: {e}"))?;
+        let time_of_day = self.time_of_day.as_mut().ok_or(tr!("console.time_of_day_off"))?;
+        time_of_day.set_hour(hour);
+        self.console_hud.reply(tr!("console.time_of_day", time = clock_time(time_of_day.hour())));
+        Ok(())
+      }
+      ["section", "load", name] if self.mode == GameMode::Play => self.level_streaming.load(name),
+      ["section", "unload", name] => {
+        let changes = self.level_streaming.unload(name, messages)?;
+        self.apply_section_changes(changes);
+        Ok(())
+      }
+      ["section", "load", _] => Err("sections only load while playing".to_string()),
+      ["bookmark"] => {
+        for (i, bookmark) in self.scene.camera_bookmarks.iter().enumerate() {
+          self.console_hud.reply(tr!("console.bookmark", key = i + 1, name = bookmark.name));
+        }
+        Ok(())
+      }
+      ["bookmark", "save", name] => {
+        self.save_bookmark(name);
+        self.console_hud.reply(tr!("console.bookmark_saved", name = name));
+        Ok(())
+      }
+      ["bookmark", "remove", name] => {
+        let idx = self.bookmark_idx(name)?;
+        self.scene.camera_bookmarks.remove(idx);
+        Ok(())
+      }
+      ["bookmark", "goto", name] => {
+        let idx = self.bookmark_idx(name)?;
+        self.jump_to_bookmark(idx);
+        Ok(())
       }
-      go.update(frame_time)?;
+      ["lightmap", "bake"] if self.mode == GameMode::Edit => self.bake_lightmap(messages),
+      ["lightmap", "bake"] => Err(tr!("console.lightmap_edit_only")),
+      // Physics is built from the scene when play starts, it takes the bake from there
+      #[cfg(feature = "physics")]
+      ["collision", "bake"] if self.mode == GameMode::Edit => self.bake_collision(),
+      #[cfg(feature = "physics")]
+      ["collision", "bake"] => Err(tr!("console.collision_edit_only")),
+      ["cache", "clear"] => asset_cache::global()
+        .clear()
+        .map(|count| self.console_hud.reply(tr!("console.cache_cleared", count = count))),
+      _ => Err(tr!("console.unknown_command", line = line)),
     }
-    for go in self.game_objects.iter() {
-      let Some(mesh) = go
-        .display_mesh
-        .get()
-        .cloned() else { continue };
-      let ftex = go
-        .display_tex
-        .get()
-        .cloned();
-      mesh_ftex_list.push((mesh, ftex));
+  }
+
+  // The console owns the text input while it is open, Enter runs the line and Escape closes it
+  fn update_console(&mut self, inputs: &mut InputAggregator, messages: &mut Vec<RendererMessage>) {
+    if !inputs.is_text_input_active() {
+      if inputs.is_key_pressed(Key::Character(CONSOLE_KEY.into())).is_just_pressed() {
+        inputs.begin_text_input("");
+      }
+      return;
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::Enter)).is_just_pressed() {
+      let line = inputs.end_text_input().unwrap_or_default();
+      println!("\r> {line}\x1b[K");
+      self.console_echo = None;
+      self.console_hud.set_input(None);
+      if let Err(e) = self.run_console_command(line.trim(), messages) {
+        self.console_hud.error(format!("console: {e}"));
+      }
+      return;
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::Escape)).is_just_pressed() {
+      inputs.end_text_input();
+      println!("\r\x1b[K");
+      self.console_echo = None;
+      self.console_hud.set_input(None);
+      return;
+    }
+    let display_text = inputs.text_input().map(|text_input| text_input.display_text());
+    if display_text != self.console_echo {
+      print!("\r> {}\x1b[K", display_text.as_deref().unwrap_or_default());
+      let _ = std::io::stdout().flush();
+      self.console_hud.set_input(Some(display_text.clone().unwrap_or_default()));
+      self.console_echo = display_text;
+    }
+  }
+
+  // Advances one fixed tick
+  pub fn update(&mut self, inputs: &mut InputAggregator, tick: Duration) -> Result<(), String> {
+    // What came in since the last tick is what this tick sees
+    inputs.start_recording();
+    let events = inputs.take_recorded();
+    // The window's input isn't what the game sees while playing something back
+    #[cfg(feature = "physics")]
+    if self.input_playback.is_none() && self.mode == GameMode::Play {
+      self.repro_history.record_tick(&self.rng, &self.physics_engine, events.clone());
+    }
+    if let Some((recording, _)) = self.input_recording.as_mut() {
+      recording.record_tick(events);
+    }
+    let update_result = match self.input_playback.take() {
+      Some((mut playback, mut replay_inputs)) => {
+        let (width, height) = inputs.window_size();
+        replay_inputs.update_window_size(width, height);
+        playback.play_tick(&mut replay_inputs);
+        let update_result = self.update_frame(&mut replay_inputs, tick.as_micros());
+        replay_inputs.clear_key_states();
+        self.finished |= playback.is_finished();
+        self.input_playback = Some((playback, replay_inputs));
+        update_result
+      }
+      None => self.update_frame(inputs, tick.as_micros()),
+    };
+    profiler::end_frame();
+    update_result
+  }
+
+  fn update_frame(&mut self, inputs: &mut InputAggregator, frame_time: u128) -> Result<(), String> {
+    profile_scope!("game_update");
+    self.sim_time += frame_time;
+    let mut messages = vec![];
+    if let Some((scene, scene_path)) = self.pending_scene.take() {
+      self.respawn_scene(scene, &mut messages)?;
+      self.console_hud.reply(tr!("console.scene_opened", path = scene_path.display()));
+      self.scene_path = scene_path;
+    }
+    // Put back whether or not a system failed, the next tick runs them all again
+    let mut scheduler = self.scheduler.take();
+    let result = scheduler.run(|work| match work {
+      SystemWork::Engine(stage) => self.run_engine_stage(stage, inputs, frame_time, &mut messages),
+      SystemWork::Game(system) => {
+        self.tick_game_system(system, inputs, frame_time, &mut messages);
+        Ok(())
+      }
+    });
+    self.scheduler = scheduler;
+    result?;
+    if self.object_labels {
+      messages.push(RendererMessage::SetWorldLabels(self.object_labels()));
+    }
+    self.console_hud.update(Duration::from_micros(frame_time as u64));
+    let hud_shapes = self.hud_shapes();
+    if hud_shapes != self.hud_shapes {
+      messages.push(RendererMessage::SetUiShapes(hud_shapes.clone()));
+      self.hud_shapes = hud_shapes;
+    }
+
+    profile_scope!("send_to_renderer");
+    self.renderer.submit_frame(messages)?;
+    Ok(())
+  }
+
+  fn run_engine_stage(
+    &mut self,
+    stage: EngineStage,
+    inputs: &mut InputAggregator,
+    frame_time: u128,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<(), String> {
+    match stage {
+      EngineStage::Input => self.update_input(inputs, messages),
+      EngineStage::Gameplay => {
+        self.update_gameplay(inputs, frame_time, messages);
+        Ok(())
+      }
+      EngineStage::Physics => {
+        self.update_physics(frame_time, messages);
+        Ok(())
+      }
+      EngineStage::Animation => self.update_animation(inputs, frame_time, messages),
+      EngineStage::Render => {
+        self.update_render_snapshot(inputs);
+        Ok(())
+      }
+    }
+  }
+
+  // Gets what gameplay libraries get, so it's only ticked while playing too
+  fn tick_game_system(
+    &mut self,
+    system: &mut LinkedGameplay,
+    inputs: &InputAggregator,
+    frame_time: u128,
+    messages: &mut Vec<RendererMessage>,
+  ) {
+    if self.mode != GameMode::Play {
+      return;
+    }
+    let mut context = GameplayContext {
+      inputs,
+      scene: &self.scene,
+      game_objects: &self.game_objects,
+      #[cfg(feature = "physics")]
+      physics_engine: &mut self.physics_engine,
+      camera_effects: &mut self.camera_effects,
+      rng: &mut self.rng,
+      sparks: self.sparks,
+      sparks_emitter: &mut self.sparks_emitter,
+      messages,
+    };
+    let tick = Duration::from_micros(frame_time as u64);
+    let _ = system.tick(&mut context, tick).inspect_err(|e| log!("at game system tick: {e}"));
+  }
+  fn update_input(
+    &mut self,
+    inputs: &mut InputAggregator,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<(), String> {
+    if inputs.is_key_pressed(Key::Named(NamedKey::F2)).is_just_pressed() {
+      profiler::set_enabled(!profiler::is_enabled());
+      self.console_hud.reply(tr!("console.profiler_enabled", enabled = profiler::is_enabled()));
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::F4)).is_just_pressed() {
+      let _ = self
+        .renderer
+        .frame_stats()
+        .inspect(|stats| println!("frame stats: {stats:?}"))
+        .inspect_err(|e| log!("at getting frame stats: {e}"));
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::F8)).is_just_pressed() {
+      let _ = self
+        .renderer
+        .trigger_capture(1)
+        .inspect_err(|e| log!("at triggering frame capture: {e}"));
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::F9)).is_just_pressed() {
+      let _ = match self.recording {
+        true => self.stop_recording(),
+        false => self.start_recording(png_recording(RECORDING_FPS)),
+      }
+      .inspect_err(|e| log!("at toggling recording: {e}"));
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::F3)).is_just_pressed() {
+      let _ = profiler::export_chrome_trace(Path::new(PROFILE_TRACE_PATH))
+        .inspect(|_| {
+          self.console_hud.reply(tr!("console.trace_written", path = PROFILE_TRACE_PATH))
+        })
+        .inspect_err(|e| log!("at exporting profile trace: {e}"));
+    }
+    self.update_console(inputs, messages);
+    self.handle_events(messages);
+    #[cfg(feature = "editor")]
+    if inputs.is_key_pressed(Key::Named(NamedKey::Tab)).is_just_pressed() {
+      let new_mode = match self.mode {
+        GameMode::Play => GameMode::Edit,
+        GameMode::Edit => GameMode::Play,
+      };
+      self.set_mode(new_mode, messages)?;
+    }
+
+    if let Some(gameplay) = self.gameplay.as_mut() {
+      match gameplay.reload_if_changed() {
+        Ok(true) => {
+          self.console_hud.reply(tr!("console.gameplay_reloaded", path = gameplay.path().display()))
+        }
+        Ok(false) => {}
+        Err(e) => log!("at reloading gameplay: {e}"),
+      }
+    }
+    Ok(())
+  }
+
+  fn update_gameplay(
+    &mut self,
+    inputs: &InputAggregator,
+    frame_time: u128,
+    messages: &mut Vec<RendererMessage>,
+  ) {
+    if self.has_gameplay() && self.mode == GameMode::Play {
+      let mut context = GameplayContext {
+        inputs,
+        scene: &self.scene,
+        game_objects: &self.game_objects,
+        #[cfg(feature = "physics")]
+        physics_engine: &mut self.physics_engine,
+        camera_effects: &mut self.camera_effects,
+        rng: &mut self.rng,
+        sparks: self.sparks,
+        sparks_emitter: &mut self.sparks_emitter,
+        messages,
+      };
+      let tick = Duration::from_micros(frame_time as u64);
+      if let Some(gameplay) = self.gameplay.as_mut() {
+        let _ = gameplay.tick(&mut context, tick).inspect_err(|e| log!("at gameplay tick: {e}"));
+      }
+      if let Some(gameplay) = self.linked_gameplay.as_mut() {
+        let _ = gameplay.tick(&mut context, tick).inspect_err(|e| log!("at gameplay tick: {e}"));
+      }
+    }
+
+    #[cfg(feature = "scripting")]
+    if self.mode == GameMode::Play {
+      self.tick_scripts(inputs, frame_time, messages);
+    }
+
+    #[cfg(feature = "physics")]
+    if self.mode == GameMode::Play
+      && !self.has_gameplay()
+      && inputs.is_key_pressed(Key::Named(NamedKey::Space)).is_just_pressed()
+    {
+      if let Some(cube_transform) = self.physics_engine.get_transform("cube_physics") {
+        let gameplay_rng = self.rng.gameplay();
+        let velocity = glam::vec3(
+          gameplay_rng.range_f32(-0.5, 0.5),
+          5.0,
+          gameplay_rng.range_f32(-0.5, 0.5),
+        );
+        let _ = self.physics_engine.set_velocity("cube_physics", velocity);
+        self.sparks_emitter.origin = cube_transform.w_axis.truncate();
+        messages.push(RendererMessage::SetParticleEmitter(self.sparks, self.sparks_emitter));
+        messages.push(RendererMessage::EmitParticles(
+          self.sparks,
+          JUMP_SPARK_COUNT,
+          self.rng.particles().next_u32(),
+        ));
+        self.camera_effects.add_trauma(JUMP_TRAUMA);
+      }
+    }
+  }
+
+  #[cfg_attr(not(feature = "physics"), allow(unused_variables, clippy::ptr_arg))]
+  fn update_physics(&mut self, frame_time: u128, messages: &mut Vec<RendererMessage>) {
+    #[cfg(feature = "physics")]
+    if self.mode == GameMode::Play {
+      profile_scope!("physics");
+      let game_time = self.time_scale.advance(frame_time);
+      self.physics_engine.run(game_time);
+      crash_report::set_field("physics bodies", self.physics_engine.body_count());
+      if self.perf_hud {
+        messages.push(RendererMessage::SetPhysicsStats(PhysicsHudStats {
+          bodies: self.physics_engine.body_count(),
+          contact_pairs: self.physics_engine.contact_pair_count(),
+        }));
+      }
+      if !self.debug_draw.is_empty() {
+        messages.push(RendererMessage::SetDebugLines(self.debug_draw.lines(&self.physics_engine)));
+      }
+    }
+    // Nothing is simulated, hit-stops still run out
+    #[cfg(not(feature = "physics"))]
+    if self.mode == GameMode::Play {
+      self.time_scale.advance(frame_time);
+    }
+  }
+
+  fn update_animation(
+    &mut self,
+    inputs: &mut InputAggregator,
+    frame_time: u128,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<(), String> {
+    if let Some(benchmark) = self.benchmark.as_mut() {
+      match benchmark.update(&mut self.renderer, frame_time)? {
+        BenchmarkStep::Waiting => {}
+        BenchmarkStep::Started(path) => self.play_camera_path(path, false),
+        BenchmarkStep::Finished => self.finished = true,
+      }
+    }
+    if let Some(leak_check) = self.leak_check.as_mut() {
+      match leak_check.update(&mut self.renderer, frame_time)? {
+        LeakCheckStep::Waiting => {}
+        LeakCheckStep::LoadLevel => {
+          let scene = Scene::load(vfs::global(), &self.scene_path.to_string_lossy())?;
+          self.pending_scene = Some((scene, self.scene_path.clone()));
+        }
+        // Same seed, only what the level made goes away
+        LeakCheckStep::UnloadLevel => {
+          let scene = Scene { seed: self.scene.seed, objects: vec![], ..Scene::default_scene() };
+          self.pending_scene = Some((scene, self.scene_path.clone()));
+        }
+        LeakCheckStep::Finished => self.finished = true,
+      }
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::F6)).is_just_pressed() {
+      match self.camera_animator.as_ref().map(|animator| animator.is_playing()) {
+        Some(true) => self.pause_camera_path(),
+        Some(false) => self.resume_camera_path(),
+        None => self.play_camera_path(self.demo_camera_orbit()?, false),
+      }
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::F7)).is_just_pressed() {
+      self.stop_camera_path();
+    }
+    if inputs.is_key_pressed(Key::Named(NamedKey::Home)).is_just_pressed() {
+      self.seek_camera_path(0);
+    }
+    if let Some(camera_animator) = self.camera_animator.as_mut() {
+      camera_animator.update(frame_time);
+      camera_animator.apply(&mut self.camera);
     }
-    if inputs.is_key_pressed(Key::Character("a".into())).is_pressed() {
+
+    if self.camera_animator.is_some() || self.orbit_camera.is_some() {
+      self.camera_bookmarks.cancel();
+    }
+    self.camera_bookmarks.update(frame_time, &mut self.camera, &mut self.camera_fov);
+
+    self.camera_effects.update(frame_time);
+
+    if self.camera_animator.is_none()
+      && self.orbit_camera.is_none()
+      && inputs.is_key_pressed(Key::Character("a".into())).is_pressed()
+    {
       self.camera.pos += glam::vec4(-1.0, 0.0, 1.0, 0.0) * frame_time as f32/500.0;
     }
-    if inputs.is_key_pressed(Key::Character("d".into())).is_pressed() {
+    if self.camera_animator.is_none()
+      && self.orbit_camera.is_none()
+      && inputs.is_key_pressed(Key::Character("d".into())).is_pressed()
+    {
       self.camera.pos -= glam::vec4(-1.0, 0.0, 1.0, 0.0) * frame_time as f32/500.0;
     }
 
-    self.renderer.send_batch_sync(vec![
-      RendererMessage::SetCamera(self.camera),
-      RendererMessage::DrawTriangleMeshesWithFlatTexture(mesh_ftex_list),
-    ])?;
+    self.hud_layout.update_input(inputs);
+
+    // Only the editor imports dropped files, ones dropped while playing are let go
+    #[cfg(feature = "editor")]
+    let dropped_files =
+      self.file_dropped_events.drain().map(|event| event.path).collect::<Vec<_>>();
+    #[cfg(feature = "editor")]
+    if self.mode == GameMode::Edit {
+      profile_scope!("editor");
+      let (picking_camera, cursor_pos) = view_camera(
+        &self.view_config,
+        self.camera,
+        self.camera_fov,
+        inputs.window_size(),
+        inputs.cursor_pos(),
+      );
+      // Clicks on the HUD don't pick through it
+      let cursor_pos = cursor_pos.filter(|_| !self.hud_layout.is_pointer_captured());
+      let changes = self.editor.update(inputs, &picking_camera, cursor_pos, &mut self.scene);
+      for change in changes {
+        self.apply_scene_change(change, messages);
+      }
+      for path in dropped_files {
+        let _ = self
+          .import_dropped_file(&path, &picking_camera, cursor_pos, messages)
+          .inspect_err(|e| log!("at importing {}: {e}", path.display()));
+      }
+      if inputs.is_key_pressed(Key::Named(NamedKey::F5)).is_just_pressed() {
+        let _ = self
+          .save_scene(&self.scene_path)
+          .inspect(|_| {
+            self.console_hud.reply(tr!("console.scene_saved", path = self.scene_path.display()))
+          })
+          .inspect_err(|e| log!("at saving scene: {e}"));
+      }
+      messages.push(RendererMessage::SetGizmo(self.editor.gizmo(&self.scene)));
+      self.update_bookmark_keys(inputs);
+    }
+
+    if self.mode == GameMode::Play {
+      let focus = self.camera.pos.truncate();
+      let changes =
+        self.level_streaming.update(focus, &mut self.renderer, &mut self.scene_materials, messages);
+      self.apply_section_changes(changes);
+    }
+    #[cfg_attr(not(feature = "editor"), allow(unused_variables))]
+    for (i, go) in self.game_objects.iter_mut().enumerate() {
+      match self.mode {
+        #[cfg(feature = "physics")]
+        GameMode::Play => {
+          if let Some(transform) = go.physics_transform(&self.physics_engine) {
+            go.object_transform.transform = transform;
+          }
+        }
+        #[cfg(feature = "editor")]
+        GameMode::Edit => {
+          if let Some(transform) = self.editor.display_transform(&self.scene, i) {
+            go.object_transform.transform = transform;
+          }
+        }
+        // Without physics or the editor objects stay where the scene puts them
+        #[allow(unreachable_patterns)]
+        _ => {}
+      }
+      go.update(frame_time, self.rng.object(&go.name))?;
+    }
+    // Sections are only loaded while playing
+    for go in self.level_streaming.objects_mut() {
+      #[cfg(feature = "physics")]
+      if let Some(transform) = go.physics_transform(&self.physics_engine) {
+        go.object_transform.transform = transform;
+      }
+      go.update(frame_time, self.rng.object(&go.name))?;
+    }
+    if let Some(crowd) = self.crowd.as_mut() {
+      crowd.update(frame_time);
+    }
+    // After the objects so it follows where the target is drawn this frame
+    if let Some(orbit_camera) = self.orbit_camera.as_mut() {
+      let target_idx = self.scene.objects.iter().position(|obj| obj.name == orbit_camera.target());
+      match target_idx.and_then(|idx| self.game_objects.get(idx)) {
+        Some(target) => {
+          let target_pos = target.object_transform.transform.w_axis.truncate();
+          orbit_camera.update(
+            inputs,
+            frame_time,
+            target_pos,
+            #[cfg(feature = "physics")]
+            Some(&self.physics_engine),
+          );
+          if self.camera_animator.is_none() {
+            orbit_camera.apply(&mut self.camera);
+          }
+        }
+        // Target deleted in the editor or gone after a reload
+        None => self.orbit_camera = None,
+      }
+    }
+    self.grading_volumes.update(self.camera.pos.truncate(), frame_time);
+    if let Some(time_of_day) = self.time_of_day.as_mut().filter(|_| self.mode == GameMode::Play) {
+      time_of_day.update(frame_time);
+    }
     Ok(())
   }
+
+  // A dot over each object with its name, dimmed behind walls so nothing gets lost while debugging
+  fn object_labels(&self) -> Vec<WorldLabel> {
+    self
+      .game_objects
+      .iter()
+      .chain(self.level_streaming.objects())
+      .filter_map(|go| {
+        let anchor = LabelAnchor::Mesh(go.display_mesh?, glam::Vec3::Y);
+        let mut label = WorldLabel::new(anchor, &go.name);
+        label.icon = Some(LabelIcon::Dot);
+        label.through_walls = Some(Color::WHITE.with_alpha(0.35));
+        Some(label)
+      })
+      .collect()
+  }
+
+  // The editor's property panel in the top left of the HUD's safe area, console replies under it
+  fn hud_shapes(&mut self) -> Vec<UiShape> {
+    let top_left = self.hud_layout.top_left();
+    #[cfg(feature = "editor")]
+    let (mut shapes, panel_size) = match self.mode {
+      GameMode::Edit => self.editor.property_panel(&self.scene, top_left),
+      GameMode::Play => (vec![], glam::Vec2::ZERO),
+    };
+    #[cfg(not(feature = "editor"))]
+    let (mut shapes, panel_size) = (vec![], glam::Vec2::ZERO);
+    self.hud_layout.set_property_panel_size(panel_size);
+    let console_top = match shapes.is_empty() {
+      true => top_left.y,
+      false => top_left.y + panel_size.y + HUD_MARGIN,
+    };
+    shapes.extend(self.console_hud.shapes(glam::vec2(top_left.x, console_top)));
+    shapes
+  }
+
+  fn update_render_snapshot(&mut self, inputs: &InputAggregator) {
+    let snapshot = self.renderer.snapshot_mut();
+    snapshot.sim_time = self.sim_time;
+    snapshot.input_received_at = inputs.input_received_at();
+    snapshot.camera = self.camera_effects.apply(&self.camera);
+    snapshot.camera_fov = self.camera_fov;
+    snapshot.depth_of_field = self.depth_of_field;
+    snapshot.color_grading = self.grading_volumes.grading();
+    if let Some(time_of_day) = &self.time_of_day {
+      time_of_day.apply(snapshot);
+    }
+    snapshot.camera_layers = match self.mode {
+      GameMode::Edit => LayerMask::ALL,
+      GameMode::Play => LayerMask::ALL.without(EDITOR_LAYER),
+    };
+    snapshot.meshes.clear();
+    for go in self.game_objects.iter().chain(self.level_streaming.objects()) {
+      let Some(mesh) = go.display_mesh else { continue };
+      snapshot.meshes.push(MeshState {
+        mesh,
+        transform: go.object_transform,
+        morph_weights: go.morph_weights(),
+        updated_at: None,
+      });
+    }
+    snapshot.meshes.extend(self.static_batches.iter().flat_map(|batches| batches.mesh_states()));
+    if let Some(benchmark) = &self.benchmark {
+      snapshot.meshes.extend(benchmark.props().mesh_states());
+    }
+    snapshot.crowds.clear();
+    snapshot.crowds.extend(self.crowd.as_ref().map(|crowd| crowd.state()));
+  }
 }
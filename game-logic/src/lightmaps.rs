@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use render_manager::{
+  bake_lightmap, unwrap_lightmap_uvs, Color, LightmapInstance, LightmapLights, MaterialCPU,
+  MaterialHandle, MaterialParams, Renderer, RendererMessage, ShaderVariant, SunLight,
+  TextureColorSpace, TextureHandle, TriMeshCPU,
+};
+
+use crate::scene::{Scene, SceneLightmap, SceneLightmapObject, SceneObject, SceneShape};
+use crate::time_of_day::TimeOfDayCurves;
+
+// Lightmaps only light objects that never move
+pub fn is_static(obj: &SceneObject) -> bool {
+  !obj.physics.is_some_and(|physics| physics.dynamic)
+}
+
+// Models have no uvs to spare for the lightmap and the lightmapped material has no room for an
+// object's own texture, those are lit at runtime
+fn is_baked(obj: &SceneObject) -> bool {
+  is_static(obj) && obj.model.is_none() && obj.texture.is_none()
+}
+
+// Meshes of the baked objects with lightmap uvs, by object name. Unwrapping the same shapes in the
+// same order lays them out the same, so loads agree with the bake however the scene changed since
+fn unwrap_baked_meshes(
+  lightmap: &SceneLightmap,
+) -> Result<HashMap<String, (SceneShape, TriMeshCPU)>, String> {
+  let mut meshes = lightmap.objects.iter().map(|obj| obj.shape.make_tri_mesh()).collect::<Vec<_>>();
+  unwrap_lightmap_uvs(&mut meshes, &lightmap.settings())?;
+  Ok(
+    lightmap
+      .objects
+      .iter()
+      .zip(meshes)
+      .map(|(obj, mesh)| (obj.name.clone(), (obj.shape, mesh)))
+      .collect(),
+  )
+}
+
+// Sun at the scene's starting hour, and the share of its ambient lit materials get as the sky
+fn scene_lights(scene: &Scene) -> Result<LightmapLights, String> {
+  let sun = match &scene.time_of_day {
+    Some(scene_time) => {
+      let curves = match &scene_time.curves {
+        Some(path) => TimeOfDayCurves::load(vfs::global(), path)?,
+        None => TimeOfDayCurves::default(),
+      };
+      curves.light(scene_time.start_hour).0
+    }
+    None => SunLight::default(),
+  };
+  let ambient = MaterialParams::default().ambient;
+  Ok(LightmapLights {
+    sun: Some(sun),
+    local: scene.lights.iter().map(|light| light.local_light()).collect(),
+    sky: sun.ambient * Color::rgb(ambient, ambient, ambient),
+  })
+}
+
+// Bakes the scene's static objects into a png next to the scene file. Settings are kept from the
+// scene's last bake
+pub fn bake(scene: &Scene, scene_path: &Path) -> Result<SceneLightmap, String> {
+  let stem = scene_path.file_stem().unwrap_or_default().to_string_lossy();
+  let texture_path = scene_path.with_file_name(format!("{stem}_lightmap.png"));
+  let lightmap = SceneLightmap {
+    texture: texture_path.to_string_lossy().to_string(),
+    objects: scene
+      .objects
+      .iter()
+      .filter(|obj| is_baked(obj))
+      .map(|obj| SceneLightmapObject { name: obj.name.clone(), shape: obj.shape })
+      .collect(),
+    ..scene.lightmap.clone().unwrap_or(SceneLightmap::new(String::new()))
+  };
+  let meshes = unwrap_baked_meshes(&lightmap)?;
+  let instances = scene
+    .objects
+    .iter()
+    .filter_map(|obj| {
+      Some(LightmapInstance {
+        mesh: &meshes.get(&obj.name)?.1,
+        transform: obj.transform(),
+        albedo: MaterialParams::default().base_color,
+      })
+    })
+    .collect::<Vec<_>>();
+  bake_lightmap(&instances, &scene_lights(scene)?, &lightmap.settings())?
+    .save(&texture_path)
+    .map_err(|e| format!("at baking lightmap: {e}"))?;
+  Ok(lightmap)
+}
+
+// A scene's baked lightmap on the renderer, with the meshes its static objects draw with
+pub struct SceneLightmaps {
+  texture: TextureHandle,
+  material: MaterialHandle,
+  meshes: HashMap<String, (SceneShape, TriMeshCPU)>,
+}
+
+impl SceneLightmaps {
+  // None when the scene has no lightmap or its texture is missing, objects are lit at runtime then
+  pub fn spawn(
+    renderer: &mut Renderer,
+    scene: &Scene,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Result<Option<Self>, String> {
+    let Some(lightmap) = scene.lightmap.as_ref() else { return Ok(None) };
+    if !vfs::global().exists(&lightmap.texture) {
+      return Ok(None);
+    }
+    let meshes = unwrap_baked_meshes(lightmap)?;
+    let texture = renderer.create_texture_handle();
+    let material = renderer.create_material_handle();
+    messages.push(RendererMessage::UploadFlatTex(
+      format!("scene_lightmap_{texture:?}"),
+      lightmap.texture.clone(),
+      TextureColorSpace::Srgb,
+      None,
+      texture,
+    ));
+    messages.push(RendererMessage::CreateLightmappedMaterial(
+      format!("scene_lightmapped_{material:?}"),
+      MaterialCPU { variant: ShaderVariant::Lightmapped, ..Default::default() },
+      None,
+      texture,
+      material,
+    ));
+    Ok(Some(Self { texture, material, meshes }))
+  }
+
+  // Mesh and material to draw the object with, None for objects the bake didn't cover or that
+  // were reshaped since
+  pub fn object(&self, obj: &SceneObject) -> Option<(&TriMeshCPU, MaterialHandle)> {
+    let (_, mesh) = self.meshes.get(&obj.name).filter(|(shape, _)| *shape == obj.shape)?;
+    is_baked(obj).then_some((mesh, self.material))
+  }
+
+  pub fn destroy(self, messages: &mut Vec<RendererMessage>) {
+    messages.push(RendererMessage::DestroyMaterial(self.material));
+    messages.push(RendererMessage::DestroyFlatTex(self.texture));
+  }
+}
@@ -0,0 +1,152 @@
+use geometry::smoothing::{damp, damp_vec3, wrap_angle};
+use input_aggregator::{InputAggregator, MouseButton};
+#[cfg(feature = "physics")]
+use physics::PhysicsEngine;
+use render_manager::{glam, Camera3D};
+
+#[derive(Debug, Clone, Copy)]
+pub struct OrbitCameraConfig {
+  // Boom length with nothing in the way and the shortest it gets pushed in to
+  pub boom_length: f32,
+  pub min_boom_length: f32,
+  // Radius of the sphere swept along the boom, keeps the near plane off the walls
+  #[cfg_attr(not(feature = "physics"), allow(dead_code))]
+  pub probe_radius: f32,
+  // Radians turned for a drag across the whole window
+  pub sensitivity: f32,
+  pub min_pitch: f32,
+  pub max_pitch: f32,
+  // Orbit center above the target origin, so the camera looks over its shoulders
+  pub target_offset: glam::Vec3,
+  // Fraction of the gap closed per second when following the target and when the boom extends
+  // back out. Blocked booms shorten at once so the camera never goes through a wall
+  pub follow_smoothing: f32,
+  pub boom_smoothing: f32,
+}
+
+impl Default for OrbitCameraConfig {
+  fn default() -> Self {
+    Self {
+      boom_length: 5.0,
+      min_boom_length: 0.5,
+      probe_radius: 0.2,
+      sensitivity: std::f32::consts::TAU,
+      min_pitch: -1.2,
+      max_pitch: 1.4,
+      target_offset: glam::vec3(0.0, 0.5, 0.0),
+      follow_smoothing: 12.0,
+      boom_smoothing: 4.0,
+    }
+  }
+}
+
+// Third person camera circling a target, dragged round with the right mouse button
+pub struct OrbitCamera {
+  config: OrbitCameraConfig,
+  // Scene object followed
+  target: String,
+  yaw: f32,
+  // Positive looks down at the target from above
+  pitch: f32,
+  focus: Option<glam::Vec3>,
+  boom: f32,
+  last_cursor: Option<(f32, f32)>,
+}
+
+impl OrbitCamera {
+  pub fn new(config: OrbitCameraConfig, target: &str) -> Self {
+    Self {
+      config,
+      target: target.to_string(),
+      yaw: 0.0,
+      pitch: 0.4,
+      focus: None,
+      boom: config.boom_length,
+      last_cursor: None,
+    }
+  }
+
+  pub fn target(&self) -> &str {
+    &self.target
+  }
+
+  pub fn set_boom_length(&mut self, boom_length: f32) {
+    self.config.boom_length = boom_length.max(self.config.min_boom_length);
+  }
+
+  // Picks up yaw and pitch from where the camera is looking so switching to orbit doesn't jump
+  pub fn match_camera(&mut self, camera: &Camera3D) {
+    let look_dir = camera.look_dir.truncate().normalize_or_zero();
+    if look_dir == glam::Vec3::ZERO {
+      return;
+    }
+    self.yaw = look_dir.x.atan2(-look_dir.z);
+    self.pitch = (-look_dir.y).asin().clamp(self.config.min_pitch, self.config.max_pitch);
+  }
+
+  fn update_angles(&mut self, inputs: &InputAggregator) {
+    let dragging = inputs.is_mouse_pressed(MouseButton::Right).is_pressed();
+    let cursor = inputs.cursor_pos().filter(|_| dragging);
+    if let (Some(cursor), Some(last_cursor)) = (cursor, self.last_cursor) {
+      self.yaw = wrap_angle(self.yaw + (cursor.0 - last_cursor.0) * self.config.sensitivity);
+      self.pitch = (self.pitch + (cursor.1 - last_cursor.1) * self.config.sensitivity)
+        .clamp(self.config.min_pitch, self.config.max_pitch);
+    }
+    self.last_cursor = cursor;
+  }
+
+  // Direction from the focus out to the camera
+  fn boom_dir(&self) -> glam::Vec3 {
+    glam::vec3(
+      -self.yaw.sin() * self.pitch.cos(),
+      self.pitch.sin(),
+      self.yaw.cos() * self.pitch.cos(),
+    )
+  }
+
+  // frame_time is in microseconds like the rest of the game update
+  pub fn update(
+    &mut self,
+    inputs: &InputAggregator,
+    frame_time: u128,
+    target_pos: glam::Vec3,
+    // Keeps the boom out of static geometry, without it the camera goes through walls
+    #[cfg(feature = "physics")] physics_engine: Option<&PhysicsEngine>,
+  ) {
+    let dt = frame_time as f32 / 1_000_000.0;
+    self.update_angles(inputs);
+
+    let target_focus = target_pos + self.config.target_offset;
+    let focus = match self.focus {
+      Some(focus) => damp_vec3(focus, target_focus, self.config.follow_smoothing, dt),
+      None => target_focus,
+    };
+    self.focus = Some(focus);
+
+    // Pulled in to where the boom hits static geometry
+    #[cfg(feature = "physics")]
+    let free_boom = physics_engine
+      .and_then(|physics_engine| {
+        let (boom_dir, probe_radius, boom_length) =
+          (self.boom_dir(), self.config.probe_radius, self.config.boom_length);
+        physics_engine.spherecast_static(focus, boom_dir, probe_radius, boom_length)
+      })
+      .map(|(_, dist)| dist)
+      .unwrap_or(self.config.boom_length);
+    #[cfg(not(feature = "physics"))]
+    let free_boom = self.config.boom_length;
+    let free_boom = free_boom.max(self.config.min_boom_length);
+    if free_boom < self.boom {
+      self.boom = free_boom;
+    } else {
+      self.boom = damp(self.boom, free_boom, self.config.boom_smoothing, dt);
+    }
+  }
+
+  pub fn apply(&self, camera: &mut Camera3D) {
+    let Some(focus) = self.focus else { return };
+    let pos = focus + self.boom_dir() * self.boom;
+    camera.pos = pos.extend(1.0);
+    camera.look_dir = (focus - pos).extend(1.0);
+  }
+}
@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+
+use crate::scene::{PhysicsProperties, SceneObject, SceneShape};
+
+// Closer than this and an instance's object still counts as what the prefab has
+const OVERRIDE_EPSILON: f32 = 1e-4;
+
+// Objects placed together and instanced as one. Object transforms are in the prefab's space, and
+// prefabs can hold instances of other prefabs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Prefab {
+  pub name: String,
+  #[serde(default)]
+  pub objects: Vec<SceneObject>,
+  #[serde(default)]
+  pub instances: Vec<PrefabInstance>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrefabInstance {
+  pub name: String,
+  pub prefab: String,
+  pub position: [f32; 3],
+  // Quaternion as x, y, z, w
+  pub rotation: [f32; 4],
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub overrides: Vec<PrefabOverride>,
+}
+
+impl PrefabInstance {
+  pub fn transform(&self) -> glam::Mat4 {
+    glam::Mat4::from_rotation_translation(
+      glam::Quat::from_array(self.rotation),
+      glam::Vec3::from_array(self.position),
+    )
+  }
+}
+
+// Changes one object of an instance from what its prefab has. Left out properties stay as the
+// prefab has them, transforms are in the instance's space
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct PrefabOverride {
+  // Object name in the prefab, objects of nested instances as "instance/object"
+  pub object: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub shape: Option<SceneShape>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub position: Option<[f32; 3]>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub rotation: Option<[f32; 4]>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub physics: Option<PhysicsProperties>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub script: Option<String>,
+  // Physics or script the prefab has are taken off
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub no_physics: bool,
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub no_script: bool,
+  // The object isn't in the instance at all
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub removed: bool,
+}
+
+impl PrefabOverride {
+  fn apply(&self, obj: &mut SceneObject) {
+    if let Some(shape) = self.shape {
+      obj.shape = shape;
+    }
+    if let Some(position) = self.position {
+      obj.position = position;
+    }
+    if let Some(rotation) = self.rotation {
+      obj.rotation = rotation;
+    }
+    if self.no_physics {
+      obj.physics = None;
+    }
+    if let Some(physics) = self.physics {
+      obj.physics = Some(physics);
+    }
+    if self.no_script {
+      obj.script = None;
+    }
+    if let Some(script) = &self.script {
+      obj.script = Some(script.clone());
+    }
+  }
+
+  // What takes the prefab's object to current, None when they're the same
+  fn diff(object: &str, prefab_obj: &SceneObject, current: &SceneObject) -> Option<Self> {
+    let position_same = glam::Vec3::from_array(prefab_obj.position)
+      .abs_diff_eq(glam::Vec3::from_array(current.position), OVERRIDE_EPSILON);
+    let rotation_dot =
+      glam::Quat::from_array(prefab_obj.rotation).dot(glam::Quat::from_array(current.rotation));
+    let over = Self {
+      object: object.to_string(),
+      shape: (prefab_obj.shape != current.shape).then_some(current.shape),
+      position: (!position_same).then_some(current.position),
+      rotation: (rotation_dot.abs() < 1.0 - OVERRIDE_EPSILON).then_some(current.rotation),
+      physics: current.physics.filter(|_| prefab_obj.physics != current.physics),
+      script: current.script.clone().filter(|_| prefab_obj.script != current.script),
+      no_physics: prefab_obj.physics.is_some() && current.physics.is_none(),
+      no_script: prefab_obj.script.is_some() && current.script.is_none(),
+      removed: false,
+    };
+    (over != Self { object: object.to_string(), ..Default::default() }).then_some(over)
+  }
+}
+
+// Which instance an object in the scene came from, and its path in the instance's prefab
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefabLink {
+  pub instance: String,
+  pub path: String,
+}
+
+fn find_prefab<'a>(prefabs: &'a [Prefab], name: &str) -> Result<&'a Prefab, String> {
+  prefabs.iter().find(|prefab| prefab.name == name).ok_or(format!("unknown prefab {name}"))
+}
+
+// Every object of the prefab by path, nested instances flattened into the prefab's space.
+// expanding holds the prefabs being expanded further up, so a prefab holding itself is an error
+fn prefab_objects(
+  prefabs: &[Prefab],
+  name: &str,
+  expanding: &mut Vec<String>,
+) -> Result<Vec<(String, SceneObject)>, String> {
+  if expanding.iter().any(|outer| outer == name) {
+    return Err(format!("prefab {name} holds an instance of itself"));
+  }
+  let prefab = find_prefab(prefabs, name)?;
+  expanding.push(name.to_string());
+  let mut objects =
+    prefab.objects.iter().map(|obj| (obj.name.clone(), obj.clone())).collect::<Vec<_>>();
+  for instance in prefab.instances.iter() {
+    let nested = instance_objects(prefabs, instance, &instance.overrides, expanding)
+      .map_err(|e| format!("at instance {} in prefab {name}: {e}", instance.name))?;
+    objects
+      .extend(nested.into_iter().map(|(path, obj)| (format!("{}/{path}", instance.name), obj)));
+  }
+  expanding.pop();
+  Ok(objects)
+}
+
+// The instance's objects by path in the space the instance is placed in
+fn instance_objects(
+  prefabs: &[Prefab],
+  instance: &PrefabInstance,
+  overrides: &[PrefabOverride],
+  expanding: &mut Vec<String>,
+) -> Result<Vec<(String, SceneObject)>, String> {
+  let mut objects = prefab_objects(prefabs, &instance.prefab, expanding)?;
+  for over in overrides.iter() {
+    let idx = objects.iter().position(|(path, _)| path == &over.object).ok_or(format!(
+      "override of {} which prefab {} doesn't have",
+      over.object, instance.prefab
+    ))?;
+    match over.removed {
+      true => drop(objects.remove(idx)),
+      false => over.apply(&mut objects[idx].1),
+    }
+  }
+  let transform = instance.transform();
+  for (_, obj) in objects.iter_mut() {
+    obj.set_transform(transform * obj.transform());
+  }
+  Ok(objects)
+}
+
+// Scene objects for an instance placed in the scene, named instance/path
+pub fn expand_instance(
+  prefabs: &[Prefab],
+  instance: &PrefabInstance,
+) -> Result<Vec<SceneObject>, String> {
+  let objects = instance_objects(prefabs, instance, &instance.overrides, &mut vec![])
+    .map_err(|e| format!("at instance {}: {e}", instance.name))?;
+  Ok(
+    objects
+      .into_iter()
+      .map(|(path, mut obj)| {
+        obj.name = format!("{}/{path}", instance.name);
+        obj.prefab = Some(PrefabLink { instance: instance.name.clone(), path });
+        obj
+      })
+      .collect(),
+  )
+}
+
+// Overrides that give the instance's objects as they are in the scene now, from edits made to
+// them after the instance was expanded
+pub fn collect_overrides(
+  prefabs: &[Prefab],
+  instance: &PrefabInstance,
+  scene_objects: &[SceneObject],
+) -> Result<Vec<PrefabOverride>, String> {
+  let prefab_objects = instance_objects(prefabs, instance, &[], &mut vec![])
+    .map_err(|e| format!("at instance {}: {e}", instance.name))?;
+  let to_instance = instance.transform().inverse();
+  let overrides = prefab_objects
+    .iter()
+    .filter_map(|(path, prefab_obj)| {
+      let current = scene_objects.iter().find(|obj| {
+        obj.prefab.as_ref().is_some_and(|link| link.instance == instance.name && &link.path == path)
+      });
+      let Some(current) = current else {
+        return Some(PrefabOverride { object: path.clone(), removed: true, ..Default::default() });
+      };
+      // Compared in the instance's space, where overrides keep transforms
+      let mut prefab_local = prefab_obj.clone();
+      prefab_local.set_transform(to_instance * prefab_obj.transform());
+      let mut current_local = current.clone();
+      current_local.set_transform(to_instance * current.transform());
+      PrefabOverride::diff(path, &prefab_local, &current_local)
+    })
+    .collect();
+  Ok(overrides)
+}
@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use render_manager::{QualityKnobs, QualityPressure};
+
+// As far as the game lets quality drop to stay in the gpu budget
+const MIN_RENDER_SCALE: f32 = 0.5;
+const RENDER_SCALE_STEP: f32 = 0.125;
+const MIN_SHADOW_MAP_SIZE: u32 = 512;
+
+// One step per call. Sheds shadow map texels before resolution and gets them back in the other
+// order, never above the quality the game started with
+pub fn step_quality(
+  full_quality: QualityKnobs,
+) -> impl FnMut(QualityPressure, Duration, &mut QualityKnobs) + Send + 'static {
+  move |pressure, sustained_gpu_time, knobs| {
+    let before = *knobs;
+    match pressure {
+      QualityPressure::OverBudget if knobs.shadow_map_size > MIN_SHADOW_MAP_SIZE => {
+        knobs.shadow_map_size = (knobs.shadow_map_size / 2).max(MIN_SHADOW_MAP_SIZE);
+      }
+      QualityPressure::OverBudget => {
+        knobs.render_scale = (knobs.render_scale - RENDER_SCALE_STEP).max(MIN_RENDER_SCALE);
+      }
+      QualityPressure::Headroom if knobs.render_scale < full_quality.render_scale => {
+        knobs.render_scale =
+          (knobs.render_scale + RENDER_SCALE_STEP).min(full_quality.render_scale);
+      }
+      QualityPressure::Headroom => {
+        knobs.shadow_map_size = (knobs.shadow_map_size * 2).min(full_quality.shadow_map_size);
+      }
+    }
+    if *knobs != before {
+      println!("gpu frames at {sustained_gpu_time:?}, quality {before:?} -> {knobs:?}");
+    }
+  }
+}
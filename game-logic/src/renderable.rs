@@ -1,10 +1,9 @@
-use std::sync::{Arc, OnceLock};
-use render_manager::{FlatTextureGPU, Renderer, RendererMessage, TriMeshGPU};
+use render_manager::{MeshHandle, TextureHandle};
 
 pub enum Renderable {
   TriangleMeshFlatTexture{
-    mesh: Arc<OnceLock<Arc<TriMeshGPU>>>,
-    texture: Arc<OnceLock<Arc<FlatTextureGPU>>>,
+    mesh: MeshHandle,
+    texture: Option<TextureHandle>,
   },
 }
 
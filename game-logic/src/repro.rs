@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use engine_config::EngineConfig;
+use input_aggregator::{InputEvent, InputRecording};
+use physics::{snapshot::PhysicsSnapshot, PhysicsEngine};
+use rng::{RngService, RngSnapshot};
+use serde::{Deserialize, Serialize};
+
+// repro save writes bundles here, named by when they were saved
+pub const REPROS_DIR: &str = "./repros";
+// A new starting point this often, so a bundle has between one and two of these of input
+const ANCHOR_TICKS: u64 = 600;
+const MAX_ANCHORS: usize = 2;
+
+// State at the start of a tick and the input of every tick since
+struct Anchor {
+  rng: RngSnapshot,
+  physics: PhysicsSnapshot,
+  inputs: InputRecording,
+}
+
+// Everything another machine needs to play the last stretch of a session again. Only physics and
+// random numbers are restored, whatever else the scene has starts from the scene
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReproBundle {
+  pub scene_path: PathBuf,
+  // The scene as it was played, with edits that weren't saved
+  pub scene: String,
+  pub config: EngineConfig,
+  pub rng: RngSnapshot,
+  pub physics: PhysicsSnapshot,
+  // Played back from the tick the snapshots were taken at. Keys held down then aren't in it
+  pub inputs: InputRecording,
+}
+
+impl ReproBundle {
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let bytes = std::fs::read(path)
+      .map_err(|e| format!("at reading repro bundle {}: {e}", path.display()))?;
+    bincode::deserialize(&bytes)
+      .map_err(|e| format!("at parsing repro bundle {}: {e}", path.display()))
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), String> {
+    let bytes =
+      bincode::serialize(self).map_err(|e| format!("at serializing repro bundle: {e}"))?;
+    std::fs::write(path, bytes)
+      .map_err(|e| format!("at writing repro bundle {}: {e}", path.display()))
+  }
+}
+
+// Recent input on top of the last couple of snapshots, so a bundle can be saved whenever
+// something goes wrong without having started a recording first
+#[derive(Default)]
+pub struct ReproHistory {
+  anchors: VecDeque<Anchor>,
+}
+
+impl ReproHistory {
+  // At the start of every tick, with the input it's about to see
+  pub fn record_tick(
+    &mut self,
+    rng: &RngService,
+    physics: &PhysicsEngine,
+    events: Vec<InputEvent>,
+  ) {
+    if self.anchors.back().is_none_or(|anchor| anchor.inputs.ticks >= ANCHOR_TICKS) {
+      if self.anchors.len() == MAX_ANCHORS {
+        self.anchors.pop_front();
+      }
+      self.anchors.push_back(Anchor {
+        rng: rng.snapshot(),
+        physics: physics.snapshot(),
+        inputs: InputRecording::new(rng.root_seed()),
+      });
+    }
+    for anchor in self.anchors.iter_mut() {
+      anchor.inputs.record_tick(events.clone());
+    }
+  }
+
+  // From the oldest snapshot kept, None before the first tick
+  pub fn bundle(
+    &self,
+    scene_path: &Path,
+    scene: String,
+    config: &EngineConfig,
+  ) -> Option<ReproBundle> {
+    let anchor = self.anchors.front()?;
+    Some(ReproBundle {
+      scene_path: scene_path.to_path_buf(),
+      scene,
+      config: config.clone(),
+      rng: anchor.rng.clone(),
+      physics: anchor.physics.clone(),
+      inputs: anchor.inputs.clone(),
+    })
+  }
+}
@@ -0,0 +1,696 @@
+use std::path::Path;
+
+use animation::gltf_import::{import_gltf_model, StaticModel};
+use crash_report::log;
+use geometry::{Direction, Point};
+#[cfg(feature = "physics")]
+use physics::static_mesh::StaticTriangleMesh;
+use physics_structs::primitives::{polygon_face::PolygonFace, RigidBodyType};
+use physics_structs::Mass;
+use render_manager::{
+  Color, ConvexRoom, LightShape, LightmapSettings, LocalLight, Portal, ReflectionProbe, RoomGraph,
+  TriMeshCPU, TriMeshVertex, CAMERA_FOV,
+};
+use serde::{Deserialize, Serialize};
+use vfs::Vfs;
+
+use crate::prefab::{self, Prefab, PrefabInstance, PrefabLink};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SceneShape {
+  Cuboid { size: [f32; 3] },
+  Rectangle { size: [f32; 2] },
+}
+
+impl SceneShape {
+  pub fn half_extents(&self) -> glam::Vec3 {
+    match self {
+      SceneShape::Cuboid { size } => glam::Vec3::from_array(*size) / 2.0,
+      // Rectangles are flat on the xz plane, keep a bit of thickness so they can be picked
+      SceneShape::Rectangle { size } => glam::vec3(size[0] / 2.0, 0.01, size[1] / 2.0),
+    }
+  }
+
+  pub fn scaled(&self, scale: glam::Vec3) -> Self {
+    match self {
+      SceneShape::Cuboid { size } => {
+        SceneShape::Cuboid { size: (glam::Vec3::from_array(*size) * scale).to_array() }
+      }
+      SceneShape::Rectangle { size } => {
+        SceneShape::Rectangle { size: [size[0] * scale.x, size[1] * scale.z] }
+      }
+    }
+  }
+
+  pub fn make_poly_mesh(&self) -> Vec<PolygonFace> {
+    match self {
+      SceneShape::Cuboid { size } => PolygonFace::new_cuboid(
+        Point::from_vec3(glam::Vec3::ZERO),
+        Direction::from_vec3(glam::vec3(size[0], 0.0, 0.0)),
+        Direction::from_vec3(glam::vec3(0.0, size[1], 0.0)),
+        size[2],
+      ),
+      SceneShape::Rectangle { size } => vec![PolygonFace::new_rectangle(
+        Point::from_vec3(glam::Vec3::ZERO),
+        Direction::from_vec3(glam::vec3(size[0], 0.0, 0.0)),
+        Direction::from_vec3(glam::vec3(0.0, 0.0, -size[1])),
+      )],
+    }
+  }
+
+  pub fn make_tri_mesh(&self) -> TriMeshCPU {
+    TriMeshCPU::combine(
+      self
+        .make_poly_mesh()
+        .iter()
+        .map(|face| {
+          TriMeshCPU::make_planar_polygon(face.get_verts().iter().map(|x| x.as_vec3()).collect())
+        })
+        .collect(),
+    )
+  }
+}
+
+// Centers the model on the origin and stretches it to fill the box
+fn fit_model(model: &StaticModel, half_extents: glam::Vec3) -> Result<TriMeshCPU, String> {
+  let (min, max) = model.bounds().ok_or("model has no vertices".to_string())?;
+  let center = (min + max) / 2.0;
+  let scale = half_extents * 2.0 / (max - min).max(glam::Vec3::splat(f32::EPSILON));
+  let vertices = model
+    .vertices
+    .iter()
+    .map(|vertex| TriMeshVertex {
+      pos: ((vertex.pos - center) * scale).extend(1.0),
+      normal: (vertex.normal / scale).normalize_or(glam::Vec3::Y).extend(0.0),
+      uv: glam::vec4(vertex.uv.x, vertex.uv.y, 0.0, 0.0),
+    })
+    .collect();
+  Ok(TriMeshCPU::new(vertices, model.triangles.clone()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PhysicsProperties {
+  pub dynamic: bool,
+  pub mass: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneObject {
+  pub name: String,
+  pub shape: SceneShape,
+  pub position: [f32; 3],
+  // Quaternion as x, y, z, w
+  pub rotation: [f32; 4],
+  pub physics: Option<PhysicsProperties>,
+  // Rhai script run on the object while playing, a vfs path
+  #[serde(default)]
+  pub script: Option<String>,
+  // glTF file drawn stretched to fit the shape, which is still what it collides as. A vfs path
+  #[serde(default)]
+  pub model: Option<String>,
+  // Image the object is drawn with instead of the scene's plain material, a vfs path
+  #[serde(default)]
+  pub texture: Option<String>,
+  // Set on objects expanded from a prefab instance, they're saved as the instance's overrides
+  #[serde(skip)]
+  pub prefab: Option<PrefabLink>,
+}
+
+impl SceneObject {
+  pub fn transform(&self) -> glam::Mat4 {
+    glam::Mat4::from_rotation_translation(
+      glam::Quat::from_array(self.rotation),
+      glam::Vec3::from_array(self.position),
+    )
+  }
+
+  pub fn set_transform(&mut self, transform: glam::Mat4) {
+    let (_, rotation, position) = transform.to_scale_rotation_translation();
+    self.position = position.to_array();
+    self.rotation = rotation.normalize().to_array();
+  }
+
+  // What the object is drawn as. Models that don't import are logged and drawn as the shape
+  pub fn make_tri_mesh(&self) -> TriMeshCPU {
+    let Some(model_path) = &self.model else { return self.shape.make_tri_mesh() };
+    let model = import_gltf_model(vfs::global(), asset_cache::global(), model_path);
+    match model.and_then(|model| fit_model(&model, self.shape.half_extents())) {
+      Ok(mesh) => mesh,
+      Err(e) => {
+        log!("at drawing model of {}: {e}", self.name);
+        self.shape.make_tri_mesh()
+      }
+    }
+  }
+
+  pub fn physics_name(&self) -> String {
+    format!("{}_physics", self.name)
+  }
+
+  pub fn physics_mesh(&self) -> Vec<RigidBodyType> {
+    self.shape.make_poly_mesh().into_iter().map(RigidBodyType::PolygonFace).collect()
+  }
+
+  // Static bodies never move, so they're infinitely heavy to the solver
+  pub fn physics_mass(&self) -> Mass {
+    match self.physics {
+      Some(physics) if physics.dynamic => Mass::Finite(physics.mass.max(f32::EPSILON)),
+      _ => Mass::Infinite,
+    }
+  }
+}
+
+// Render geometry as a static collision triangle soup, positions moved by transform
+#[cfg(feature = "physics")]
+pub fn bake_collision_mesh(
+  mesh: &TriMeshCPU,
+  transform: glam::Mat4,
+) -> Result<StaticTriangleMesh, String> {
+  let positions = mesh
+    .vertices
+    .iter()
+    .map(|vert| transform.transform_point3(vert.pos.truncate()))
+    .collect::<Vec<_>>();
+  StaticTriangleMesh::bake(&positions, &mesh.triangles)
+}
+
+// Spot light when it has a direction, point light otherwise
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneLight {
+  pub position: [f32; 3],
+  #[serde(default)]
+  pub direction: Option<[f32; 3]>,
+  // Spot lights only, degrees from direction the light starts fading and ends at
+  #[serde(default = "SceneLight::default_cone")]
+  pub cone: [f32; 2],
+  // Linear rgb
+  pub color: [f32; 3],
+  pub intensity: f32,
+  pub range: f32,
+  #[serde(default)]
+  pub casts_shadows: bool,
+}
+
+impl SceneLight {
+  fn default_cone() -> [f32; 2] {
+    [20.0, 30.0]
+  }
+
+  pub fn local_light(&self) -> LocalLight {
+    let shape = match self.direction {
+      Some(direction) => LightShape::Spot {
+        direction: glam::Vec3::from_array(direction),
+        inner_angle: self.cone[0].to_radians(),
+        outer_angle: self.cone[1].to_radians(),
+      },
+      None => LightShape::Point,
+    };
+    LocalLight {
+      position: glam::Vec3::from_array(self.position),
+      shape,
+      color: Color::rgb(self.color[0], self.color[1], self.color[2]),
+      intensity: self.intensity,
+      range: self.range,
+      casts_shadows: self.casts_shadows,
+    }
+  }
+}
+
+// Axis aligned, objects in rooms the camera can't see into are not drawn
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneRoom {
+  pub name: String,
+  pub min: [f32; 3],
+  pub max: [f32; 3],
+}
+
+// Opening between two rooms by name, the corners go around a convex polygon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScenePortal {
+  pub rooms: [String; 2],
+  pub vertices: Vec<[f32; 3]>,
+}
+
+// Axis aligned, the frame is graded through the volume's color lut while the camera is in it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneGradingVolume {
+  pub min: [f32; 3],
+  pub max: [f32; 3],
+  // Vfs path of a .cube file or a lut strip image
+  pub lut: String,
+  // Seconds the grading takes to fade in when the camera enters, and out when it leaves
+  #[serde(default = "SceneGradingVolume::default_transition")]
+  pub transition_s: f32,
+}
+
+impl SceneGradingVolume {
+  fn default_transition() -> f32 {
+    1.0
+  }
+
+  pub fn contains(&self, pos: glam::Vec3) -> bool {
+    (0..3).all(|i| (self.min[i]..=self.max[i]).contains(&pos[i]))
+  }
+}
+
+// Captured from position when the scene loads, lit and lightmapped materials inside min..max
+// reflect it projected onto the box. Smaller boxes win where they overlap
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneReflectionProbe {
+  pub position: [f32; 3],
+  pub min: [f32; 3],
+  pub max: [f32; 3],
+  // Fades into the probes around it over this far inside its box
+  #[serde(default = "SceneReflectionProbe::default_blend_distance")]
+  pub blend_distance: f32,
+}
+
+impl SceneReflectionProbe {
+  fn default_blend_distance() -> f32 {
+    1.0
+  }
+
+  pub fn reflection_probe(&self) -> ReflectionProbe {
+    ReflectionProbe {
+      position: glam::Vec3::from_array(self.position),
+      box_min: glam::Vec3::from_array(self.min),
+      box_max: glam::Vec3::from_array(self.max),
+      blend_distance: self.blend_distance,
+    }
+  }
+}
+
+// Sun and sky following the hour, from a curves file or the built in day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneTimeOfDay {
+  // Vfs path of a time of day curves toml
+  #[serde(default)]
+  pub curves: Option<String>,
+  #[serde(default = "SceneTimeOfDay::default_start_hour")]
+  pub start_hour: f32,
+  // Real seconds for a whole day while playing, 0 holds the hour
+  #[serde(default = "SceneTimeOfDay::default_day_length")]
+  pub day_length_s: f32,
+}
+
+impl SceneTimeOfDay {
+  fn default_start_hour() -> f32 {
+    12.0
+  }
+
+  fn default_day_length() -> f32 {
+    1200.0
+  }
+}
+
+// Part of the level kept in its own scene file and only loaded while playing, when a stream
+// trigger asks for it. Only the file's objects and lights are used
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneSection {
+  pub name: String,
+  // Vfs path of the section's scene file
+  pub path: String,
+}
+
+// Axis aligned, entering it loads and unloads sections by name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneStreamTrigger {
+  pub min: [f32; 3],
+  pub max: [f32; 3],
+  #[serde(default)]
+  pub load: Vec<String>,
+  #[serde(default)]
+  pub unload: Vec<String>,
+}
+
+impl SceneStreamTrigger {
+  pub fn contains(&self, pos: glam::Vec3) -> bool {
+    (0..3).all(|i| (self.min[i]..=self.max[i]).contains(&pos[i]))
+  }
+}
+
+// Light baked onto the objects that never move, by the lightmap bake console command
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneLightmap {
+  // Vfs path of the baked png, written next to the scene file
+  pub texture: String,
+  #[serde(default = "SceneLightmap::default_resolution")]
+  pub resolution: u32,
+  #[serde(default = "SceneLightmap::default_texels_per_unit")]
+  pub texels_per_unit: f32,
+  #[serde(default = "SceneLightmap::default_bounces")]
+  pub bounces: u32,
+  // Rays gathered per texel each bounce
+  #[serde(default = "SceneLightmap::default_samples")]
+  pub samples: u32,
+  // What was baked, in the order it was laid out. Objects reshaped or added since are lit at
+  // runtime until the next bake
+  #[serde(default)]
+  pub objects: Vec<SceneLightmapObject>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneLightmapObject {
+  pub name: String,
+  pub shape: SceneShape,
+}
+
+// Body the baked collision mesh is loaded as
+#[cfg(feature = "physics")]
+pub const SCENE_COLLISION_BODY: &str = "scene_collision";
+
+// The static objects' collision baked into one mesh by the collision bake console command. It's
+// loaded mapped when play starts, in place of the baked objects' own bodies
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneCollision {
+  // Vfs path of the baked mesh, written next to the scene file
+  pub mesh: String,
+  // Objects added or made static since get bodies of their own until the next bake
+  pub objects: Vec<String>,
+}
+
+impl SceneLightmap {
+  fn default_resolution() -> u32 {
+    LightmapSettings::default().resolution
+  }
+
+  fn default_texels_per_unit() -> f32 {
+    LightmapSettings::default().texels_per_unit
+  }
+
+  fn default_bounces() -> u32 {
+    LightmapSettings::default().bounces
+  }
+
+  fn default_samples() -> u32 {
+    LightmapSettings::default().samples
+  }
+
+  pub fn new(texture: String) -> Self {
+    Self {
+      texture,
+      resolution: Self::default_resolution(),
+      texels_per_unit: Self::default_texels_per_unit(),
+      bounces: Self::default_bounces(),
+      samples: Self::default_samples(),
+      objects: vec![],
+    }
+  }
+
+  pub fn settings(&self) -> LightmapSettings {
+    LightmapSettings {
+      resolution: self.resolution,
+      texels_per_unit: self.texels_per_unit,
+      bounces: self.bounces,
+      samples: self.samples,
+      ..Default::default()
+    }
+  }
+}
+
+// Camera pose saved from the editor to jump back to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneCameraBookmark {
+  pub name: String,
+  pub position: [f32; 3],
+  pub look_dir: [f32; 3],
+  // Vertical
+  #[serde(default = "SceneCameraBookmark::default_fov")]
+  pub fov_deg: f32,
+}
+
+impl SceneCameraBookmark {
+  fn default_fov() -> f32 {
+    CAMERA_FOV.to_degrees()
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+  // Root seed for the rng streams, play mode restarts from it every time
+  #[serde(default)]
+  pub seed: u64,
+  // Where in the world the scene's positions are measured from. Moved along with the camera in
+  // large worlds, so the positions stay small enough for f32
+  #[serde(default)]
+  pub origin: [f64; 3],
+  pub objects: Vec<SceneObject>,
+  // Outdoor scenes leave these out and everything is drawn
+  #[serde(default)]
+  pub rooms: Vec<SceneRoom>,
+  #[serde(default)]
+  pub portals: Vec<ScenePortal>,
+  // Lights besides the sun
+  #[serde(default)]
+  pub lights: Vec<SceneLight>,
+  // Color lut graded through outside every grading volume, none leaves colors as tonemapped
+  #[serde(default)]
+  pub color_lut: Option<String>,
+  // Later volumes win where they overlap
+  #[serde(default)]
+  pub grading_volumes: Vec<SceneGradingVolume>,
+  // Scenes without one keep the renderer's fixed sun and clear color
+  #[serde(default)]
+  pub time_of_day: Option<SceneTimeOfDay>,
+  #[serde(default)]
+  pub sections: Vec<SceneSection>,
+  #[serde(default)]
+  pub stream_triggers: Vec<SceneStreamTrigger>,
+  #[serde(default)]
+  pub prefabs: Vec<Prefab>,
+  // Their objects are added to objects on load, after the scene's own
+  #[serde(default)]
+  pub instances: Vec<PrefabInstance>,
+  // In the order the editor's number keys go through them
+  #[serde(default)]
+  pub camera_bookmarks: Vec<SceneCameraBookmark>,
+  // None until baked, everything is lit at runtime then
+  #[serde(default)]
+  pub lightmap: Option<SceneLightmap>,
+  // None until baked, every static object has its own body then
+  #[serde(default)]
+  pub collision: Option<SceneCollision>,
+  #[serde(default)]
+  pub reflection_probes: Vec<SceneReflectionProbe>,
+}
+
+impl Scene {
+  pub fn reflection_probes(&self) -> Vec<ReflectionProbe> {
+    self.reflection_probes.iter().map(|probe| probe.reflection_probe()).collect()
+  }
+
+  // Parsed where the vfs has it, big levels in packs aren't copied before parsing
+  pub fn load(vfs: &Vfs, path: &str) -> Result<Self, String> {
+    let scene_bytes =
+      vfs.read_mapped(path).map_err(|e| format!("at reading scene file {path}: {e}"))?;
+    let scene_str = std::str::from_utf8(&scene_bytes)
+      .map_err(|e| format!("at reading scene file {path} as text: {e}"))?;
+    Self::parse(scene_str, path)
+  }
+
+  // Scene file text, path is only for errors
+  pub fn parse(scene_str: &str, path: &str) -> Result<Self, String> {
+    let mut scene: Self =
+      toml::from_str(scene_str).map_err(|e| format!("at parsing scene file {path}: {e}"))?;
+    for instance in scene.instances.iter() {
+      let objects = prefab::expand_instance(&scene.prefabs, instance)
+        .map_err(|e| format!("at loading scene file {path}: {e}"))?;
+      scene.objects.extend(objects);
+    }
+    Ok(scene)
+  }
+
+  pub fn origin(&self) -> glam::DVec3 {
+    glam::DVec3::from_array(self.origin)
+  }
+
+  // Moves the origin by shift, everything in the scene stays where it is in the world
+  pub fn shift_origin(&mut self, shift: glam::Vec3) {
+    let moved = |position: &mut [f32; 3]| {
+      *position = (glam::Vec3::from_array(*position) - shift).to_array();
+    };
+    self.objects.iter_mut().for_each(|x| moved(&mut x.position));
+    self.instances.iter_mut().for_each(|x| moved(&mut x.position));
+    self.lights.iter_mut().for_each(|x| moved(&mut x.position));
+    self.camera_bookmarks.iter_mut().for_each(|x| moved(&mut x.position));
+    for room in self.rooms.iter_mut() {
+      moved(&mut room.min);
+      moved(&mut room.max);
+    }
+    self.portals.iter_mut().flat_map(|x| x.vertices.iter_mut()).for_each(moved);
+    for volume in self.grading_volumes.iter_mut() {
+      moved(&mut volume.min);
+      moved(&mut volume.max);
+    }
+    for trigger in self.stream_triggers.iter_mut() {
+      moved(&mut trigger.min);
+      moved(&mut trigger.max);
+    }
+    for probe in self.reflection_probes.iter_mut() {
+      moved(&mut probe.position);
+      moved(&mut probe.min);
+      moved(&mut probe.max);
+    }
+    // Baked at the old positions, it has to be baked again
+    self.collision = None;
+    self.origin = (self.origin() + shift.as_dvec3()).to_array();
+  }
+
+  // Same world positions measured from origin instead, for sections saved with their own
+  pub fn rebase(&mut self, origin: glam::DVec3) {
+    self.shift_origin(geometry::world::local_position(self.origin(), origin));
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), String> {
+    std::fs::write(path, self.to_toml()?)
+      .map_err(|e| format!("at writing scene file {}: {e}", path.display()))
+  }
+
+  // Instanced objects go back into their instances, as overrides where they differ from the prefab
+  pub fn to_toml(&self) -> Result<String, String> {
+    let mut saved = self.clone();
+    for instance in saved.instances.iter_mut() {
+      instance.overrides = prefab::collect_overrides(&self.prefabs, instance, &self.objects)?;
+    }
+    saved.objects.retain(|obj| obj.prefab.is_none());
+    toml::to_string_pretty(&saved).map_err(|e| format!("at serializing scene: {e}"))
+  }
+
+  pub fn room_graph(&self) -> Result<RoomGraph, String> {
+    let room_idx = |name: &String| {
+      self
+        .rooms
+        .iter()
+        .position(|room| &room.name == name)
+        .ok_or(format!("portal refers to unknown room {name}"))
+    };
+    let portals = self
+      .portals
+      .iter()
+      .map(|portal| {
+        Ok(Portal {
+          rooms: [room_idx(&portal.rooms[0])?, room_idx(&portal.rooms[1])?],
+          vertices: portal.vertices.iter().map(|vertex| glam::Vec3::from_array(*vertex)).collect(),
+        })
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    let rooms = self
+      .rooms
+      .iter()
+      .map(|room| {
+        ConvexRoom::from_box(
+          &room.name,
+          glam::Vec3::from_array(room.min),
+          glam::Vec3::from_array(room.max),
+        )
+      })
+      .collect();
+    Ok(RoomGraph { rooms, portals })
+  }
+
+  pub fn default_scene() -> Self {
+    Self {
+      seed: 0,
+      origin: [0.0; 3],
+      objects: vec![
+        SceneObject {
+          name: "cube".to_string(),
+          shape: SceneShape::Cuboid { size: [1.0, 1.0, 1.0] },
+          position: [0.0, 2.0, 0.0],
+          rotation: glam::Quat::IDENTITY.to_array(),
+          physics: Some(PhysicsProperties { dynamic: true, mass: 1.0 }),
+          script: None,
+          model: None,
+          texture: None,
+          prefab: None,
+        },
+        SceneObject {
+          name: "floor".to_string(),
+          shape: SceneShape::Rectangle { size: [10.0, 10.0] },
+          position: [0.0, -2.0, 0.0],
+          rotation: glam::Quat::IDENTITY.to_array(),
+          physics: Some(PhysicsProperties { dynamic: false, mass: 0.0 }),
+          script: None,
+          model: None,
+          texture: None,
+          prefab: None,
+        },
+      ],
+      rooms: vec![],
+      portals: vec![],
+      lights: vec![],
+      color_lut: None,
+      grading_volumes: vec![],
+      time_of_day: None,
+      sections: vec![],
+      stream_triggers: vec![],
+      prefabs: vec![],
+      instances: vec![],
+      camera_bookmarks: vec![],
+      lightmap: None,
+      collision: None,
+      reflection_probes: vec![],
+    }
+  }
+
+  #[cfg(feature = "physics")]
+  fn static_physics_objects(&self) -> impl Iterator<Item = &SceneObject> {
+    self.objects.iter().filter(|obj| obj.physics.is_some_and(|physics| !physics.dynamic))
+  }
+
+  // Every static object merged into one world space collision mesh for the level
+  #[cfg(feature = "physics")]
+  pub fn bake_static_collision(&self) -> Result<StaticTriangleMesh, String> {
+    let meshes = self
+      .static_physics_objects()
+      .map(|obj| {
+        let mut mesh = obj.shape.make_tri_mesh();
+        for vert in mesh.vertices.iter_mut() {
+          vert.pos = obj.transform() * vert.pos;
+        }
+        mesh
+      })
+      .collect();
+    bake_collision_mesh(&TriMeshCPU::combine(meshes), glam::Mat4::IDENTITY)
+      .map_err(|e| format!("at baking scene collision: {e}"))
+  }
+
+  // Bakes the static collision next to the scene file, the scene keeps it once it's saved
+  #[cfg(feature = "physics")]
+  pub fn bake_collision(&self, scene_path: &Path) -> Result<SceneCollision, String> {
+    let stem = scene_path.file_stem().unwrap_or_default().to_string_lossy();
+    let mesh_path = scene_path.with_file_name(format!("{stem}_collision.bin"));
+    self.bake_static_collision()?.write_to_file(&mesh_path)?;
+    Ok(SceneCollision {
+      mesh: mesh_path.to_string_lossy().to_string(),
+      objects: self.static_physics_objects().map(|obj| obj.name.clone()).collect(),
+    })
+  }
+
+  pub fn unique_name(&self, prefix: &str) -> String {
+    (0..)
+      .map(|i| format!("{prefix}_{i}"))
+      .find(|name| self.objects.iter().all(|obj| &obj.name != name))
+      .unwrap_or(prefix.to_string())
+  }
+
+  // Places the prefab as a new instance, its objects are added after the scene's. Returns the
+  // instance's name
+  pub fn spawn_prefab(&mut self, prefab: &str, transform: glam::Mat4) -> Result<String, String> {
+    let (_, rotation, position) = transform.to_scale_rotation_translation();
+    let name = (0..)
+      .map(|i| format!("{prefab}_{i}"))
+      .find(|name| self.instances.iter().all(|instance| &instance.name != name))
+      .unwrap_or(prefab.to_string());
+    let instance = PrefabInstance {
+      name: name.clone(),
+      prefab: prefab.to_string(),
+      position: position.to_array(),
+      rotation: rotation.normalize().to_array(),
+      overrides: vec![],
+    };
+    self.objects.extend(prefab::expand_instance(&self.prefabs, &instance)?);
+    self.instances.push(instance);
+    Ok(name)
+  }
+}
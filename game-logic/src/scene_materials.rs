@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use crash_report::log;
+use render_manager::{
+  MaterialCPU, MaterialHandle, Renderer, RendererMessage, ShaderVariant, TextureColorSpace,
+  TextureHandle,
+};
+
+use crate::scene::SceneObject;
+
+// What scene objects draw with where no lightmap covers them. Objects with a texture of their own
+// share one material per texture, made the first time an object with it spawns and kept until the
+// game goes away
+pub struct SceneMaterials {
+  pub lit: MaterialHandle,
+  textured: HashMap<String, (TextureHandle, MaterialHandle)>,
+}
+
+impl SceneMaterials {
+  pub fn new(renderer: &mut Renderer, messages: &mut Vec<RendererMessage>) -> Self {
+    let lit = renderer.create_material_handle();
+    messages.push(RendererMessage::CreateMaterial(
+      "scene_lit".to_string(),
+      MaterialCPU { variant: ShaderVariant::Lit, ..Default::default() },
+      None,
+      lit,
+    ));
+    Self { lit, textured: HashMap::new() }
+  }
+
+  // Objects whose texture is missing are logged and drawn plain
+  pub fn object(
+    &mut self,
+    renderer: &mut Renderer,
+    obj: &SceneObject,
+    messages: &mut Vec<RendererMessage>,
+  ) -> MaterialHandle {
+    let Some(texture_path) = &obj.texture else { return self.lit };
+    if let Some((_, material)) = self.textured.get(texture_path) {
+      return *material;
+    }
+    if !vfs::global().exists(texture_path) {
+      log!("at drawing {}: there's no texture {texture_path}", obj.name);
+      return self.lit;
+    }
+    let texture = renderer.create_texture_handle();
+    let material = renderer.create_material_handle();
+    messages.push(RendererMessage::UploadFlatTex(
+      format!("scene_texture_{texture:?}"),
+      texture_path.clone(),
+      TextureColorSpace::Srgb,
+      None,
+      texture,
+    ));
+    messages.push(RendererMessage::CreateMaterial(
+      format!("scene_textured_{material:?}"),
+      MaterialCPU { variant: ShaderVariant::Lit, ..Default::default() },
+      Some(texture),
+      material,
+    ));
+    self.textured.insert(texture_path.clone(), (texture, material));
+    material
+  }
+
+  pub fn destroy(&mut self, messages: &mut Vec<RendererMessage>) {
+    messages.push(RendererMessage::DestroyMaterial(self.lit));
+    for (_, (texture, material)) in self.textured.drain() {
+      messages.push(RendererMessage::DestroyMaterial(material));
+      messages.push(RendererMessage::DestroyFlatTex(texture));
+    }
+  }
+}
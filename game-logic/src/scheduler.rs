@@ -0,0 +1,213 @@
+use std::time::{Duration, Instant};
+
+use gameplay_api::{Gameplay, LinkedGameplay};
+
+// The engine's own work each tick, one system in the stage of the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineStage {
+  // Console, editor mode switch, window events and gameplay reloads
+  Input,
+  // Gameplay libraries, linked gameplay and scripts
+  Gameplay,
+  Physics,
+  // Cameras, the editor, streaming and everything moving objects to where they're drawn
+  Animation,
+  // What the renderer draws this tick. It's sent once every stage has run, so messages from
+  // stages after it still go with this tick
+  Render,
+}
+
+impl EngineStage {
+  pub const ALL: [Self; 5] =
+    [Self::Input, Self::Gameplay, Self::Physics, Self::Animation, Self::Render];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Input => "input",
+      Self::Gameplay => "gameplay",
+      Self::Physics => "physics",
+      Self::Animation => "animation",
+      Self::Render => "render",
+    }
+  }
+}
+
+pub(crate) enum SystemWork<'a> {
+  Engine(EngineStage),
+  Game(&'a mut LinkedGameplay),
+}
+
+enum Work {
+  Engine(EngineStage),
+  Game(LinkedGameplay),
+}
+
+struct System {
+  name: String,
+  work: Work,
+  // Wall time of its last run
+  time: Duration,
+}
+
+struct Stage {
+  name: String,
+  // Stages that have to run before this one
+  after: Vec<String>,
+  systems: Vec<System>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemTiming {
+  pub stage: String,
+  pub system: String,
+  pub time: Duration,
+}
+
+// Named stages run one after the other each tick, each only once every stage it comes after has
+// run. Stages with no order between them run in the order they were added. Game systems are ticked
+// like linked gameplay, so only while playing
+pub struct Scheduler {
+  // In the order they run
+  stages: Vec<Stage>,
+}
+
+impl Default for Scheduler {
+  fn default() -> Self {
+    let mut stages = Vec::<Stage>::new();
+    for engine_stage in EngineStage::ALL {
+      let name = engine_stage.name().to_string();
+      stages.push(Stage {
+        after: stages.last().map(|x| vec![x.name.clone()]).unwrap_or_default(),
+        systems: vec![System {
+          name: name.clone(),
+          work: Work::Engine(engine_stage),
+          time: Duration::ZERO,
+        }],
+        name,
+      });
+    }
+    Self { stages }
+  }
+}
+
+impl Scheduler {
+  fn stage_idx(&self, name: &str) -> Result<usize, String> {
+    self.stages.iter().position(|x| x.name == name).ok_or(format!("no stage named {name}"))
+  }
+
+  // Empty stage running after the stages in after and before the ones in before. Nothing changes
+  // when that can't be done
+  pub fn add_stage(&mut self, name: &str, after: &[&str], before: &[&str]) -> Result<(), String> {
+    if self.stage_idx(name).is_ok() {
+      return Err(format!("at adding stage {name}: there already is one"));
+    }
+    for other in after.iter().chain(before.iter()) {
+      self.stage_idx(other).map_err(|e| format!("at adding stage {name}: {e}"))?;
+    }
+    let mut constraints =
+      self.stages.iter().map(|x| (x.name.as_str(), x.after.clone())).collect::<Vec<_>>();
+    constraints.push((name, after.iter().map(|x| x.to_string()).collect()));
+    for (stage, stage_after) in constraints.iter_mut() {
+      if before.contains(stage) {
+        stage_after.push(name.to_string());
+      }
+    }
+    let order = run_order(&constraints).map_err(|e| format!("at adding stage {name}: {e}"))?;
+    let new_afters = order.iter().map(|&idx| constraints[idx].1.clone()).collect::<Vec<_>>();
+    self.stages.push(Stage { name: name.to_string(), after: vec![], systems: vec![] });
+    let mut stages = self.stages.drain(..).map(Some).collect::<Vec<_>>();
+    self.stages = order.iter().filter_map(|&idx| stages[idx].take()).collect();
+    for (stage, after) in self.stages.iter_mut().zip(new_afters) {
+      stage.after = after;
+    }
+    Ok(())
+  }
+
+  // Runs after the systems already in the stage
+  pub fn add_system<G: Gameplay + Send + 'static>(
+    &mut self,
+    stage: &str,
+    name: &str,
+    system: G,
+  ) -> Result<(), String> {
+    let stage_idx = self.stage_idx(stage).map_err(|e| format!("at adding system {name}: {e}"))?;
+    let stage = &mut self.stages[stage_idx];
+    if stage.systems.iter().any(|x| x.name == name) {
+      return Err(format!("at adding system {name}: stage {} already has one", stage.name));
+    }
+    stage.systems.push(System {
+      name: name.to_string(),
+      work: Work::Game(LinkedGameplay::new(system)),
+      time: Duration::ZERO,
+    });
+    Ok(())
+  }
+
+  pub fn stage_names(&self) -> Vec<&str> {
+    self.stages.iter().map(|x| x.name.as_str()).collect()
+  }
+
+  // Every system in the order they run, timed at their last run
+  pub fn timings(&self) -> Vec<SystemTiming> {
+    self
+      .stages
+      .iter()
+      .flat_map(|stage| {
+        stage.systems.iter().map(|system| SystemTiming {
+          stage: stage.name.clone(),
+          system: system.name.clone(),
+          time: system.time,
+        })
+      })
+      .collect()
+  }
+
+  // The stages for running while the game they're in is borrowed, put back after
+  pub(crate) fn take(&mut self) -> Self {
+    Self { stages: std::mem::take(&mut self.stages) }
+  }
+
+  // Stops at the first system that fails, the rest of the tick doesn't run
+  pub(crate) fn run(
+    &mut self,
+    mut run_system: impl FnMut(SystemWork) -> Result<(), String>,
+  ) -> Result<(), String> {
+    for system in self.stages.iter_mut().flat_map(|stage| stage.systems.iter_mut()) {
+      let start = Instant::now();
+      let work = match &mut system.work {
+        Work::Engine(engine_stage) => SystemWork::Engine(*engine_stage),
+        Work::Game(gameplay) => SystemWork::Game(gameplay),
+      };
+      let result = run_system(work);
+      system.time = start.elapsed();
+      result?;
+    }
+    Ok(())
+  }
+}
+
+// Indices of the stages in an order where each comes after the ones it names, picking the earliest
+// one ready whenever there's a choice
+fn run_order(constraints: &[(&str, Vec<String>)]) -> Result<Vec<usize>, String> {
+  let mut order = Vec::with_capacity(constraints.len());
+  while order.len() < constraints.len() {
+    let ready = (0..constraints.len()).find(|idx| {
+      !order.contains(idx)
+        && constraints[*idx]
+          .1
+          .iter()
+          .all(|after| order.iter().any(|&done: &usize| constraints[done].0 == after.as_str()))
+    });
+    match ready {
+      Some(idx) => order.push(idx),
+      None => {
+        let stuck = (0..constraints.len())
+          .filter(|idx| !order.contains(idx))
+          .map(|idx| constraints[idx].0)
+          .collect::<Vec<_>>();
+        return Err(format!("stages {stuck:?} would have to run before each other"));
+      }
+    }
+  }
+  Ok(order)
+}
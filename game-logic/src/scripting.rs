@@ -0,0 +1,232 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex, MutexGuard},
+};
+
+use crash_report::log;
+use input_aggregator::{InputAggregator, Key, KeyState};
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST, FLOAT};
+
+// Per call into a script, so a stuck loop can't hang the simulation thread
+const MAX_SCRIPT_OPERATIONS: u64 = 100_000;
+
+// What the game does for scripts once they've all run for the tick
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptCommand {
+  // Dynamic cube that's gone once play stops, with its own script if given
+  Spawn { name: String, size: f32, position: glam::Vec3, script: Option<String> },
+  ApplyImpulse { name: String, impulse: glam::Vec3 },
+  AddTrauma(f32),
+}
+
+// What scripts see of the game, taken before any of them run so the order they run in doesn't
+// change what they read
+#[derive(Debug, Default)]
+struct ScriptFrame {
+  // Character keys by their text like "a", named ones by winit's name like "Space"
+  keys: HashMap<String, KeyState>,
+  positions: HashMap<String, glam::Vec3>,
+}
+
+#[derive(Debug, Default)]
+struct ScriptIo {
+  frame: ScriptFrame,
+  commands: Vec<ScriptCommand>,
+  // Counts up for spawned names, so names scripts get back are never reused
+  spawned: u32,
+}
+
+fn lock(io: &Mutex<ScriptIo>) -> MutexGuard<'_, ScriptIo> {
+  io.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn key_name(key: &Key) -> String {
+  match key {
+    Key::Character(text) => text.to_string(),
+    Key::Named(named) => format!("{named:?}"),
+    _ => format!("{key:?}"),
+  }
+}
+
+fn vec3_array(position: glam::Vec3) -> Dynamic {
+  Dynamic::from_array(position.to_array().map(|x| Dynamic::from_float(x as FLOAT)).to_vec())
+}
+
+// Engine services scripts can call, queued into io or read from its frame
+fn register_services(engine: &mut Engine, io: &Arc<Mutex<ScriptIo>>) {
+  let keys_io = io.clone();
+  engine.register_fn("key_pressed", move |key: &str| {
+    lock(&keys_io).frame.keys.get(key).is_some_and(|state| state.is_pressed())
+  });
+  let keys_io = io.clone();
+  engine.register_fn("key_just_pressed", move |key: &str| {
+    lock(&keys_io).frame.keys.get(key).is_some_and(|state| state.is_just_pressed())
+  });
+  // Array of x, y, z, or () for objects that aren't there
+  let positions_io = io.clone();
+  engine.register_fn("position", move |name: &str| {
+    lock(&positions_io).frame.positions.get(name).copied().map(vec3_array).unwrap_or(Dynamic::UNIT)
+  });
+  let spawn = |io: &Arc<Mutex<ScriptIo>>,
+               name: &str,
+               size: FLOAT,
+               position: [FLOAT; 3],
+               script: Option<String>| {
+    let mut io = lock(io);
+    io.spawned += 1;
+    let name = format!("{name}#{}", io.spawned);
+    let position = glam::vec3(position[0] as f32, position[1] as f32, position[2] as f32);
+    io.commands.push(ScriptCommand::Spawn {
+      name: name.clone(),
+      size: size as f32,
+      position,
+      script,
+    });
+    name
+  };
+  let spawn_io = io.clone();
+  engine.register_fn("spawn_cube", move |name: &str, size: FLOAT, x: FLOAT, y: FLOAT, z: FLOAT| {
+    spawn(&spawn_io, name, size, [x, y, z], None)
+  });
+  let spawn_io = io.clone();
+  engine.register_fn(
+    "spawn_cube",
+    move |name: &str, size: FLOAT, x: FLOAT, y: FLOAT, z: FLOAT, script: &str| {
+      spawn(&spawn_io, name, size, [x, y, z], Some(script.to_string()))
+    },
+  );
+  let impulse_io = io.clone();
+  engine.register_fn("apply_impulse", move |name: &str, x: FLOAT, y: FLOAT, z: FLOAT| {
+    let impulse = glam::vec3(x as f32, y as f32, z as f32);
+    lock(&impulse_io)
+      .commands
+      .push(ScriptCommand::ApplyImpulse { name: name.to_string(), impulse });
+  });
+  let trauma_io = io.clone();
+  engine.register_fn("add_trauma", move |amount: FLOAT| {
+    lock(&trauma_io).commands.push(ScriptCommand::AddTrauma(amount as f32));
+  });
+  engine.on_print(|text| println!("[script] {text}"));
+  engine.on_debug(|text, source, pos| println!("[script] {} {pos}: {text}", source.unwrap_or("")));
+}
+
+// A script attached to an object, its functions see state as this
+struct ScriptComponent {
+  object: String,
+  path: String,
+  state: Dynamic,
+  started: bool,
+  // Set after an error, it's skipped until the script is reloaded
+  failed: bool,
+}
+
+// Rhai scripts on scene objects. Each object's script has init() called before its first tick and
+// tick(dt) every tick while playing, dt in seconds. Both are optional. this starts as a map with
+// the object's name, and whatever else the script puts in it stays between ticks and reloads
+pub struct ScriptRuntime {
+  engine: Engine,
+  io: Arc<Mutex<ScriptIo>>,
+  // By vfs path, compiled once however many objects use them
+  scripts: HashMap<String, AST>,
+  components: Vec<ScriptComponent>,
+}
+
+impl ScriptRuntime {
+  pub fn new() -> Self {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_SCRIPT_OPERATIONS);
+    let io = Arc::new(Mutex::new(ScriptIo::default()));
+    register_services(&mut engine, &io);
+    Self { engine, io, scripts: HashMap::new(), components: vec![] }
+  }
+
+  fn compile(&self, path: &str) -> Result<AST, String> {
+    let source = vfs::global().read_to_string(path)?;
+    self.engine.compile(source).map_err(|e| format!("at compiling script {path}: {e}"))
+  }
+
+  pub fn add_component(&mut self, object: &str, path: &str) -> Result<(), String> {
+    let path = vfs::normalize_path(path);
+    if !self.scripts.contains_key(&path) {
+      let ast = self.compile(&path)?;
+      self.scripts.insert(path.clone(), ast);
+    }
+    let mut state = Map::new();
+    state.insert("name".into(), object.into());
+    self.components.push(ScriptComponent {
+      object: object.to_string(),
+      path,
+      state: state.into(),
+      started: false,
+      failed: false,
+    });
+    Ok(())
+  }
+
+  pub fn clear(&mut self) {
+    self.components.clear();
+    lock(&self.io).commands.clear();
+  }
+
+  // Compiles the file again for every object using it, their state is kept. False when no object
+  // uses it
+  pub fn reload(&mut self, path: &str) -> Result<bool, String> {
+    let path = vfs::normalize_path(path);
+    if !self.scripts.contains_key(&path) {
+      return Ok(false);
+    }
+    let ast = self.compile(&path)?;
+    self.scripts.insert(path.clone(), ast);
+    for component in self.components.iter_mut().filter(|component| component.path == path) {
+      component.failed = false;
+    }
+    Ok(true)
+  }
+
+  fn call(
+    engine: &Engine,
+    ast: &AST,
+    component: &mut ScriptComponent,
+    fn_name: &str,
+    args: impl rhai::FuncArgs,
+  ) -> Result<(), String> {
+    if !ast.iter_functions().any(|function| function.name == fn_name) {
+      return Ok(());
+    }
+    let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut component.state);
+    engine
+      .call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, fn_name, args)
+      .map(|_| ())
+      .map_err(|e| format!("at {fn_name} of {} on {}: {e}", component.path, component.object))
+  }
+
+  // Runs every script for a tick of dt seconds, positions are where objects were drawn last tick
+  pub fn tick(
+    &mut self,
+    inputs: &InputAggregator,
+    positions: HashMap<String, glam::Vec3>,
+    dt: f32,
+  ) -> Vec<ScriptCommand> {
+    {
+      let mut io = lock(&self.io);
+      io.frame.keys = inputs.key_states().map(|(key, state)| (key_name(key), state)).collect();
+      io.frame.positions = positions;
+    }
+    for component in self.components.iter_mut().filter(|component| !component.failed) {
+      let Some(ast) = self.scripts.get(&component.path) else { continue };
+      let mut result = Ok(());
+      if !component.started {
+        component.started = true;
+        result = Self::call(&self.engine, ast, component, "init", ());
+      }
+      if result.is_ok() {
+        result = Self::call(&self.engine, ast, component, "tick", (dt as FLOAT,));
+      }
+      if let Err(e) = result {
+        log!("{e}, stopped until the script is reloaded");
+        component.failed = true;
+      }
+    }
+    std::mem::take(&mut lock(&self.io).commands)
+  }
+}
@@ -0,0 +1,91 @@
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  },
+  time::Instant,
+};
+
+use crash_report::log;
+use engine_config::SimulationConfig;
+use input_aggregator::InputAggregator;
+use jobs::JobHandle;
+
+use crate::Game;
+
+// Runs the game at a fixed tick rate on its own thread. Each tick hands a snapshot to the render
+// thread, so neither waits on the other. Inputs are filled in by the window thread between ticks
+pub struct Simulation {
+  stop: Arc<AtomicBool>,
+  // Set when the game finished on its own, like after a benchmark
+  finished: Arc<AtomicBool>,
+  sim_job: Option<JobHandle<Game>>,
+}
+
+impl Simulation {
+  pub fn start(
+    mut game: Game,
+    inputs: Arc<Mutex<InputAggregator>>,
+    config: &SimulationConfig,
+  ) -> Result<Self, String> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let sim_stop = stop.clone();
+    let finished = Arc::new(AtomicBool::new(false));
+    let sim_finished = finished.clone();
+    let tick_duration = config.tick_duration();
+    let max_ticks_per_update = config.max_ticks_per_update;
+
+    let sim_job = jobs::global().spawn_dedicated("simulation", move || {
+      let mut next_tick = Instant::now();
+      while !sim_stop.load(Ordering::Acquire) && !game.is_finished() {
+        let mut ticks = 0;
+        while next_tick <= Instant::now() && ticks < max_ticks_per_update {
+          let mut inputs = match inputs.lock() {
+            Ok(inputs) => inputs,
+            Err(e) => e.into_inner(),
+          };
+          let _ =
+            game.update(&mut inputs, tick_duration).inspect_err(|e| log!("at updating game: {e}"));
+          inputs.clear_key_states();
+          next_tick += tick_duration;
+          ticks += 1;
+        }
+        // Still behind after the catch up ticks, the missed ones are dropped
+        let now = Instant::now();
+        if next_tick < now {
+          next_tick = now;
+        }
+        std::thread::sleep(next_tick - now);
+      }
+      sim_finished.store(game.is_finished(), Ordering::Release);
+      game
+    })?;
+    Ok(Self { stop, finished, sim_job: Some(sim_job) })
+  }
+
+  pub fn is_running(&self) -> bool {
+    self.sim_job.as_ref().is_some_and(|sim_job| !sim_job.is_finished())
+  }
+
+  // Stopped because the game was done, not because of an error
+  pub fn is_finished(&self) -> bool {
+    self.finished.load(Ordering::Acquire)
+  }
+
+  // Lets the tick in progress finish and hands the game back. Shutting the game down after this
+  // stops the renderer, so the render thread never outlives the thread feeding it
+  pub fn stop(mut self) -> Result<Game, String> {
+    self.stop.store(true, Ordering::Release);
+    let sim_job = self.sim_job.take().ok_or("simulation already stopped".to_string())?;
+    sim_job.wait().map_err(|e| format!("at stopping simulation: {e}"))
+  }
+}
+
+impl Drop for Simulation {
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::Release);
+    if let Some(sim_job) = self.sim_job.take() {
+      let _ = sim_job.wait().inspect_err(|e| log!("at stopping simulation: {e}"));
+    }
+  }
+}
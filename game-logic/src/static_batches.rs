@@ -0,0 +1,85 @@
+use render_manager::{
+  MaterialHandle, MeshHandle, MeshState, Renderer, RendererMessage, StaticBatcher, TriMeshTransform,
+};
+
+use crate::{lightmaps::SceneLightmaps, scene::Scene, GameObject};
+
+// Batches close at this many vertices, so room culling and the gpu culling still have pieces to
+// skip instead of one mesh spanning the level
+const MAX_BATCH_VERTICES: usize = 1 << 16;
+
+// Scene objects that never move, merged per room and material while playing. Their own meshes are
+// hidden until the batches are destroyed, for editing or before the objects go away
+pub struct StaticBatches {
+  batches: Vec<(MeshHandle, TriMeshTransform)>,
+  hidden: Vec<MeshHandle>,
+}
+
+impl StaticBatches {
+  // Objects without physics or with static physics, grouped by the room their origin is in.
+  // Groups of one are left to draw on their own. Lightmapped objects are merged with their
+  // lightmap uvs
+  pub fn build(
+    renderer: &mut Renderer,
+    scene: &Scene,
+    game_objects: &[GameObject],
+    lightmaps: Option<&SceneLightmaps>,
+    messages: &mut Vec<RendererMessage>,
+  ) -> Self {
+    let mut batcher = StaticBatcher::<(Option<usize>, MaterialHandle)>::new(MAX_BATCH_VERTICES);
+    let mut batched_meshes = vec![];
+    for (obj, go) in scene.objects.iter().zip(game_objects.iter()) {
+      let (Some(mesh), Some(material)) = (go.display_mesh, go.display_material) else { continue };
+      if obj.physics.is_some_and(|physics| physics.dynamic) || go.morph_animation.is_some() {
+        continue;
+      }
+      let room = scene
+        .rooms
+        .iter()
+        .position(|room| (0..3).all(|i| (room.min[i]..=room.max[i]).contains(&obj.position[i])));
+      let lightmapped = lightmaps
+        .and_then(|lightmaps| lightmaps.object(obj))
+        .filter(|(_, lightmapped)| *lightmapped == material);
+      match lightmapped {
+        Some((lightmapped, _)) => batcher.add((room, material), lightmapped, obj.transform()),
+        None => batcher.add((room, material), &obj.make_tri_mesh(), obj.transform()),
+      };
+      batched_meshes.push(mesh);
+    }
+    let (batches, _) = batcher.finish();
+    let mut static_batches = Self { batches: vec![], hidden: vec![] };
+    for batch in batches.into_iter().filter(|batch| batch.instances.len() > 1) {
+      let handle = renderer.create_mesh_handle();
+      messages.push(RendererMessage::UploadTriMesh(
+        format!("static_batch_{handle:?}"),
+        batch.mesh,
+        handle,
+      ));
+      messages.push(RendererMessage::AddRenderable(handle, Some(batch.key.1)));
+      for id in batch.instances {
+        messages.push(RendererMessage::SetRenderableVisible(batched_meshes[id], false));
+        static_batches.hidden.push(batched_meshes[id]);
+      }
+      static_batches.batches.push((handle, TriMeshTransform { transform: batch.transform }));
+    }
+    static_batches
+  }
+
+  pub fn destroy(self, messages: &mut Vec<RendererMessage>) {
+    for (handle, _) in self.batches {
+      messages.push(RendererMessage::DestroyTriMesh(handle));
+    }
+    for mesh in self.hidden {
+      messages.push(RendererMessage::SetRenderableVisible(mesh, true));
+    }
+  }
+
+  pub fn mesh_states(&self) -> impl Iterator<Item = MeshState> + '_ {
+    self.batches.iter().map(|(mesh, transform)| MeshState {
+      mesh: *mesh,
+      transform: *transform,
+      morph_weights: None,
+      updated_at: None,
+    })
+  }
+}
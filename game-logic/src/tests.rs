@@ -0,0 +1,364 @@
+use std::time::{Duration, Instant};
+
+use gameplay_api::{Gameplay, Services};
+use render_manager::{Renderer, RendererMessage};
+use vfs::test_support::TempDir;
+
+use crate::level_streaming::{LevelStreaming, SectionChange};
+use crate::prefab::{collect_overrides, expand_instance, Prefab, PrefabInstance, PrefabOverride};
+use crate::scene::{
+  PhysicsProperties, Scene, SceneObject, SceneSection, SceneShape, SceneStreamTrigger,
+};
+use crate::scene_materials::SceneMaterials;
+use crate::scheduler::{EngineStage, Scheduler, SystemWork};
+
+fn cube(name: &str, position: [f32; 3]) -> SceneObject {
+  SceneObject {
+    name: name.to_string(),
+    shape: SceneShape::Cuboid { size: [1.0; 3] },
+    position,
+    rotation: [0.0, 0.0, 0.0, 1.0],
+    physics: Some(PhysicsProperties { dynamic: true, mass: 1.0 }),
+    script: Some("spin.rhai".to_string()),
+    model: None,
+    texture: None,
+    prefab: None,
+  }
+}
+
+fn instance(name: &str, prefab: &str, position: [f32; 3]) -> PrefabInstance {
+  PrefabInstance {
+    name: name.to_string(),
+    prefab: prefab.to_string(),
+    position,
+    rotation: [0.0, 0.0, 0.0, 1.0],
+    overrides: vec![],
+  }
+}
+
+// A table with a lamp instance on it, the lamp has a base and a bulb
+fn furniture() -> Vec<Prefab> {
+  vec![
+    Prefab {
+      name: "lamp".to_string(),
+      objects: vec![cube("base", [0.0; 3]), cube("bulb", [0.0, 1.0, 0.0])],
+      instances: vec![],
+    },
+    Prefab {
+      name: "table".to_string(),
+      objects: vec![cube("top", [0.0; 3])],
+      instances: vec![instance("lamp", "lamp", [0.0, 2.0, 0.0])],
+    },
+  ]
+}
+
+fn names(objects: &[SceneObject]) -> Vec<&str> {
+  objects.iter().map(|obj| obj.name.as_str()).collect()
+}
+
+#[test]
+fn nested_instances_are_flattened_into_the_scene() {
+  let objects = expand_instance(&furniture(), &instance("t", "table", [10.0, 0.0, 0.0])).unwrap();
+  assert_eq!(names(&objects), ["t/top", "t/lamp/base", "t/lamp/bulb"]);
+  assert_eq!(objects[2].position, [10.0, 3.0, 0.0]);
+  let link = objects[2].prefab.as_ref().unwrap();
+  assert_eq!((link.instance.as_str(), link.path.as_str()), ("t", "lamp/bulb"));
+}
+
+#[test]
+fn overrides_reach_objects_of_nested_instances() {
+  let mut placed = instance("t", "table", [0.0; 3]);
+  placed.overrides = vec![
+    PrefabOverride {
+      object: "lamp/bulb".to_string(),
+      position: Some([0.0, 5.0, 0.0]),
+      no_physics: true,
+      ..Default::default()
+    },
+    PrefabOverride { object: "top".to_string(), no_script: true, ..Default::default() },
+  ];
+  let objects = expand_instance(&furniture(), &placed).unwrap();
+  let bulb = objects.iter().find(|obj| obj.name == "t/lamp/bulb").unwrap();
+  assert_eq!(bulb.position, [0.0, 5.0, 0.0]);
+  assert_eq!(bulb.physics, None);
+  assert!(bulb.script.is_some());
+  let top = objects.iter().find(|obj| obj.name == "t/top").unwrap();
+  assert_eq!(top.script, None);
+  assert!(top.physics.is_some());
+}
+
+#[test]
+fn overrides_in_prefabs_apply_before_the_instances_own() {
+  let mut prefabs = furniture();
+  prefabs[1].instances[0].overrides =
+    vec![PrefabOverride { object: "bulb".to_string(), no_script: true, ..Default::default() }];
+  let mut placed = instance("t", "table", [0.0; 3]);
+  placed.overrides = vec![PrefabOverride {
+    object: "lamp/bulb".to_string(),
+    script: Some("flicker.rhai".to_string()),
+    ..Default::default()
+  }];
+  let objects = expand_instance(&prefabs, &placed).unwrap();
+  let bulb = objects.iter().find(|obj| obj.name == "t/lamp/bulb").unwrap();
+  assert_eq!(bulb.script.as_deref(), Some("flicker.rhai"));
+}
+
+#[test]
+fn removed_objects_are_left_out() {
+  let mut placed = instance("t", "table", [0.0; 3]);
+  placed.overrides =
+    vec![PrefabOverride { object: "lamp/base".to_string(), removed: true, ..Default::default() }];
+  let objects = expand_instance(&furniture(), &placed).unwrap();
+  assert_eq!(names(&objects), ["t/top", "t/lamp/bulb"]);
+}
+
+#[test]
+fn overriding_an_object_the_prefab_lacks_fails() {
+  let mut placed = instance("t", "table", [0.0; 3]);
+  placed.overrides =
+    vec![PrefabOverride { object: "lamp/shade".to_string(), removed: true, ..Default::default() }];
+  let e = expand_instance(&furniture(), &placed).unwrap_err();
+  assert!(e.contains("override of lamp/shade which prefab table doesn't have"), "{e}");
+}
+
+#[test]
+fn prefabs_holding_themselves_fail() {
+  let mut prefabs = furniture();
+  prefabs[0].instances.push(instance("again", "lamp", [0.0; 3]));
+  let e = expand_instance(&prefabs, &instance("l", "lamp", [0.0; 3])).unwrap_err();
+  assert!(e.contains("prefab lamp holds an instance of itself"), "{e}");
+}
+
+#[test]
+fn prefabs_holding_each_other_fail() {
+  let mut prefabs = furniture();
+  prefabs[0].instances.push(instance("stand", "table", [0.0; 3]));
+  let e = expand_instance(&prefabs, &instance("t", "table", [0.0; 3])).unwrap_err();
+  assert!(e.contains("prefab table holds an instance of itself"), "{e}");
+}
+
+#[test]
+fn unknown_prefabs_fail() {
+  let e = expand_instance(&furniture(), &instance("c", "chair", [0.0; 3])).unwrap_err();
+  assert!(e.contains("unknown prefab chair"), "{e}");
+}
+
+#[test]
+fn collected_overrides_expand_back_to_the_edited_objects() {
+  let prefabs = furniture();
+  let mut placed = instance("t", "table", [4.0, 0.0, 0.0]);
+  let mut objects = expand_instance(&prefabs, &placed).unwrap();
+  objects[2].position[1] += 1.5;
+  objects[2].physics = None;
+  objects.remove(1);
+
+  placed.overrides = collect_overrides(&prefabs, &placed, &objects).unwrap();
+  assert_eq!(placed.overrides.len(), 2);
+  assert!(placed.overrides.iter().any(|x| x.object == "lamp/base" && x.removed));
+  let bulb = placed.overrides.iter().find(|x| x.object == "lamp/bulb").unwrap();
+  assert!(bulb.no_physics && bulb.position.is_some() && bulb.script.is_none());
+  assert_eq!(expand_instance(&prefabs, &placed).unwrap(), objects);
+}
+
+#[test]
+fn unedited_instances_collect_no_overrides() {
+  let prefabs = furniture();
+  let placed = instance("t", "table", [4.0, 0.0, 0.0]);
+  let objects = expand_instance(&prefabs, &placed).unwrap();
+  assert_eq!(collect_overrides(&prefabs, &placed, &objects).unwrap(), vec![]);
+}
+
+struct Idle;
+
+impl Gameplay for Idle {
+  fn load(_: &[u8]) -> Self {
+    Idle
+  }
+
+  fn tick(&mut self, _: &mut Services, _: u64) {}
+
+  fn save(&self) -> Vec<u8> {
+    vec![]
+  }
+}
+
+// Engine stages by name and game systems as game, in the order the scheduler ran them
+fn run_order(scheduler: &mut Scheduler) -> Vec<&'static str> {
+  let mut ran = vec![];
+  scheduler
+    .run(|work| {
+      ran.push(match work {
+        SystemWork::Engine(stage) => stage.name(),
+        SystemWork::Game(_) => "game",
+      });
+      Ok(())
+    })
+    .unwrap();
+  ran
+}
+
+#[test]
+fn engine_stages_run_in_tick_order() {
+  let mut scheduler = Scheduler::default();
+  assert_eq!(scheduler.stage_names(), EngineStage::ALL.map(EngineStage::name));
+  assert_eq!(run_order(&mut scheduler), EngineStage::ALL.map(EngineStage::name));
+}
+
+#[test]
+fn added_stages_run_between_the_ones_they_name() {
+  let mut scheduler = Scheduler::default();
+  scheduler.add_stage("ai", &["input"], &["physics"]).unwrap();
+  scheduler.add_stage("late", &["render"], &[]).unwrap();
+  scheduler.add_stage("early", &[], &["input"]).unwrap();
+  let names = scheduler.stage_names();
+  let idx = |name| names.iter().position(|x| *x == name).unwrap();
+  assert!(idx("input") < idx("ai") && idx("ai") < idx("physics"));
+  assert!(idx("render") < idx("late"));
+  assert_eq!(idx("early"), 0);
+
+  scheduler.add_system("ai", "brains", Idle).unwrap();
+  scheduler.add_system("render", "overlay", Idle).unwrap();
+  let ran = run_order(&mut scheduler);
+  // Gameplay was added before ai, so it wins when both are ready
+  assert_eq!(ran, ["input", "gameplay", "game", "physics", "animation", "render", "game"]);
+  let timings = scheduler.timings();
+  let systems = timings.iter().map(|x| (x.stage.as_str(), x.system.as_str())).collect::<Vec<_>>();
+  assert_eq!(systems[2], ("ai", "brains"));
+  assert_eq!(systems[6], ("render", "overlay"));
+}
+
+#[test]
+fn stage_order_cycles_are_rejected() {
+  let mut scheduler = Scheduler::default();
+  let before = scheduler.stage_names().join(",");
+  let e = scheduler.add_stage("loop", &["render"], &["input"]).unwrap_err();
+  assert!(e.contains("would have to run before each other"), "{e}");
+  assert_eq!(scheduler.stage_names().join(","), before);
+  assert!(scheduler.add_stage("input", &[], &[]).is_err());
+  assert!(scheduler.add_stage("ai", &["brains"], &[]).is_err());
+  assert!(scheduler.add_system("brains", "ai", Idle).is_err());
+}
+
+#[test]
+fn a_failing_system_stops_the_tick() {
+  let mut scheduler = Scheduler::default();
+  let mut ran = vec![];
+  let result = scheduler.run(|work| {
+    let SystemWork::Engine(stage) = work else { return Ok(()) };
+    ran.push(stage);
+    match stage {
+      EngineStage::Physics => Err("solver blew up".to_string()),
+      _ => Ok(()),
+    }
+  });
+  assert_eq!(result, Err("solver blew up".to_string()));
+  assert_eq!(ran, [EngineStage::Input, EngineStage::Gameplay, EngineStage::Physics]);
+}
+
+// A scene with sections a and b mounted under mount_point, entering the box at x 10 loads a and
+// the one at x 20 swaps it for b
+fn streamed_scene(dir: &TempDir, mount_point: &str) -> Scene {
+  for (section, obj) in [("a", "box"), ("b", "crate")] {
+    let scene = Scene { objects: vec![cube(obj, [0.0; 3])], ..Scene::default_scene() };
+    scene.save(&dir.path().join(format!("{section}.toml"))).unwrap();
+  }
+  vfs::global().mount_dir(mount_point, dir.path()).unwrap();
+  Scene {
+    sections: ["a", "b"]
+      .map(|name| SceneSection {
+        name: name.to_string(),
+        path: format!("{mount_point}/{name}.toml"),
+      })
+      .to_vec(),
+    stream_triggers: vec![
+      SceneStreamTrigger {
+        min: [9.0, -1.0, -1.0],
+        max: [11.0, 1.0, 1.0],
+        load: vec!["a".to_string()],
+        unload: vec![],
+      },
+      SceneStreamTrigger {
+        min: [19.0, -1.0, -1.0],
+        max: [21.0, 1.0, 1.0],
+        load: vec!["b".to_string()],
+        unload: vec!["a".to_string()],
+      },
+    ],
+    ..Scene::default_scene()
+  }
+}
+
+// Updates with the focus at x until a section finishes loading
+fn update_until_loaded(
+  streaming: &mut LevelStreaming,
+  x: f32,
+  renderer: &mut Renderer,
+  materials: &mut SceneMaterials,
+  messages: &mut Vec<RendererMessage>,
+) -> Vec<SectionChange> {
+  let start = Instant::now();
+  let mut changes = vec![];
+  while !changes.iter().any(|x| matches!(x, SectionChange::Loaded(_))) {
+    assert!(start.elapsed() < Duration::from_secs(10), "section never loaded");
+    changes.extend(streaming.update(glam::vec3(x, 0.0, 0.0), renderer, materials, messages));
+    std::thread::sleep(Duration::from_millis(1));
+  }
+  changes
+}
+
+#[test]
+fn entering_triggers_loads_and_swaps_sections() {
+  let dir = TempDir::new("level_streaming_swap");
+  let scene = streamed_scene(&dir, "streaming_swap");
+  let mut renderer = Renderer::detached();
+  let mut messages = vec![];
+  let mut materials = SceneMaterials::new(&mut renderer, &mut messages);
+  let mut streaming = LevelStreaming::new(&scene);
+
+  let changes = streaming.update(glam::Vec3::ZERO, &mut renderer, &mut materials, &mut messages);
+  assert!(changes.is_empty());
+  assert_eq!(streaming.objects().count(), 0);
+
+  let changes =
+    update_until_loaded(&mut streaming, 10.0, &mut renderer, &mut materials, &mut messages);
+  let [SectionChange::Loaded(objects)] = changes.as_slice() else { panic!("a didn't load") };
+  assert_eq!(names(objects), ["a/box"]);
+  assert_eq!(streaming.objects().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["a/box"]);
+
+  messages.clear();
+  let changes =
+    update_until_loaded(&mut streaming, 20.0, &mut renderer, &mut materials, &mut messages);
+  let [SectionChange::Unloaded(physics_names), SectionChange::Loaded(objects)] = changes.as_slice()
+  else {
+    panic!("a and b didn't swap")
+  };
+  assert_eq!(physics_names, &["a/box_physics"]);
+  assert!(messages.iter().any(|x| matches!(x, RendererMessage::DestroyTriMesh(_))));
+  assert_eq!(names(objects), ["b/crate"]);
+  assert_eq!(streaming.objects().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["b/crate"]);
+  vfs::global().unmount("streaming_swap").unwrap();
+}
+
+#[test]
+fn unloading_sections_by_name() {
+  let dir = TempDir::new("level_streaming_unload");
+  let scene = streamed_scene(&dir, "streaming_unload");
+  let mut renderer = Renderer::detached();
+  let mut messages = vec![];
+  let mut materials = SceneMaterials::new(&mut renderer, &mut messages);
+  let mut streaming = LevelStreaming::new(&scene);
+
+  assert!(streaming.unload("a", &mut messages).unwrap().is_empty());
+  assert!(streaming.load("c").is_err());
+  assert!(streaming.unload("c", &mut messages).is_err());
+
+  streaming.load("b").unwrap();
+  update_until_loaded(&mut streaming, 0.0, &mut renderer, &mut materials, &mut messages);
+  let changes = streaming.unload_all(&mut messages);
+  let [SectionChange::Unloaded(physics_names)] = changes.as_slice() else {
+    panic!("b didn't unload")
+  };
+  assert_eq!(physics_names, &["b/crate_physics"]);
+  assert_eq!(streaming.objects().count(), 0);
+  vfs::global().unmount("streaming_unload").unwrap();
+}
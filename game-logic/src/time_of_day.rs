@@ -0,0 +1,200 @@
+use render_manager::{glam, Color, FrameSnapshot, SunLight};
+use serde::Deserialize;
+use vfs::Vfs;
+
+use crate::scene::SceneTimeOfDay;
+
+const HOURS_PER_DAY: f32 = 24.0;
+
+// Lighting at one hour of the day, colors are linear rgb
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct TimeOfDayKey {
+  pub hour: f32,
+  pub sun_color: [f32; 3],
+  pub sun_intensity: f32,
+  // Tints the ambient share of lit materials
+  pub ambient: [f32; 3],
+  // Shows where nothing is drawn
+  pub sky_color: [f32; 3],
+}
+
+fn lerp3(from: [f32; 3], to: [f32; 3], t: f32) -> [f32; 3] {
+  glam::Vec3::from_array(from).lerp(glam::Vec3::from_array(to), t).to_array()
+}
+
+fn rgb(color: [f32; 3]) -> Color {
+  Color::rgb(color[0], color[1], color[2])
+}
+
+impl TimeOfDayKey {
+  fn lerp(&self, other: &Self, t: f32) -> Self {
+    Self {
+      hour: self.hour + (other.hour - self.hour) * t,
+      sun_color: lerp3(self.sun_color, other.sun_color, t),
+      sun_intensity: self.sun_intensity + (other.sun_intensity - self.sun_intensity) * t,
+      ambient: lerp3(self.ambient, other.ambient, t),
+      sky_color: lerp3(self.sky_color, other.sky_color, t),
+    }
+  }
+}
+
+// Curve asset, a toml file with a [[keys]] table per hour. Between keys the values blend linearly,
+// the last key of the day blends into the first across midnight
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TimeOfDayCurves {
+  // Degrees above the horizon the sun gets at noon
+  #[serde(default = "TimeOfDayCurves::default_noon_elevation")]
+  pub noon_elevation_deg: f32,
+  // Degrees around y from +x the sun rises at, it sets opposite
+  #[serde(default)]
+  pub sunrise_azimuth_deg: f32,
+  pub keys: Vec<TimeOfDayKey>,
+}
+
+impl Default for TimeOfDayCurves {
+  // Sun up from 6 to 18, warm at both ends, with a dim blue night
+  fn default() -> Self {
+    let key = |hour, sun_color, sun_intensity, ambient, sky_color| TimeOfDayKey {
+      hour,
+      sun_color,
+      sun_intensity,
+      ambient,
+      sky_color,
+    };
+    Self {
+      noon_elevation_deg: Self::default_noon_elevation(),
+      sunrise_azimuth_deg: 0.0,
+      keys: vec![
+        key(0.0, [0.0; 3], 0.0, [0.08, 0.1, 0.2], [0.005, 0.008, 0.02]),
+        key(5.0, [0.0; 3], 0.0, [0.1, 0.12, 0.22], [0.01, 0.015, 0.04]),
+        key(6.5, [1.0, 0.55, 0.3], 0.6, [0.45, 0.38, 0.4], [0.6, 0.35, 0.25]),
+        key(9.0, [1.0, 0.9, 0.8], 0.9, [0.8, 0.85, 0.95], [0.35, 0.5, 0.8]),
+        key(12.0, [1.0, 1.0, 0.97], 1.0, [0.9, 0.95, 1.0], [0.3, 0.5, 0.9]),
+        key(16.0, [1.0, 0.9, 0.75], 0.9, [0.8, 0.8, 0.85], [0.35, 0.48, 0.75]),
+        key(17.8, [1.0, 0.45, 0.2], 0.5, [0.45, 0.35, 0.4], [0.65, 0.3, 0.2]),
+        key(19.0, [0.0; 3], 0.0, [0.12, 0.12, 0.25], [0.04, 0.03, 0.08]),
+      ],
+    }
+  }
+}
+
+impl TimeOfDayCurves {
+  fn default_noon_elevation() -> f32 {
+    60.0
+  }
+
+  pub fn parse(curves_str: &str) -> Result<Self, String> {
+    let mut curves: Self = toml::from_str(curves_str).map_err(|e| e.to_string())?;
+    if curves.keys.is_empty() {
+      return Err("time of day curves need at least one key".to_string());
+    }
+    if let Some(key) = curves.keys.iter().find(|key| !(0.0..HOURS_PER_DAY).contains(&key.hour)) {
+      return Err(format!("time of day key hour {} is outside 0 to 24", key.hour));
+    }
+    curves.keys.sort_by(|a, b| a.hour.total_cmp(&b.hour));
+    Ok(curves)
+  }
+
+  pub fn load(vfs: &Vfs, path: &str) -> Result<Self, String> {
+    let curves_str =
+      vfs.read_to_string(path).map_err(|e| format!("at reading time of day curves {path}: {e}"))?;
+    Self::parse(&curves_str).map_err(|e| format!("at parsing time of day curves {path}: {e}"))
+  }
+
+  // Blend of the keys on either side of the hour
+  pub fn sample(&self, hour: f32) -> TimeOfDayKey {
+    let hour = hour.rem_euclid(HOURS_PER_DAY);
+    let next = self.keys.iter().position(|key| key.hour > hour).unwrap_or(0);
+    let prev = (next + self.keys.len() - 1) % self.keys.len();
+    let (from, to) = (&self.keys[prev], &self.keys[next]);
+    let span = (to.hour - from.hour).rem_euclid(HOURS_PER_DAY);
+    let t = match span > 0.0 {
+      true => (hour - from.hour).rem_euclid(HOURS_PER_DAY) / span,
+      false => 0.0,
+    };
+    TimeOfDayKey { hour, ..from.lerp(to, t) }
+  }
+
+  // Towards the sun. It rises at 6, is highest at 12 and sets at 18, then carries on under the
+  // horizon through the night
+  pub fn sun_direction(&self, hour: f32) -> glam::Vec3 {
+    let angle = (hour - 6.0) / HOURS_PER_DAY * std::f32::consts::TAU;
+    let rise = glam::Quat::from_rotation_y(self.sunrise_azimuth_deg.to_radians()) * glam::Vec3::X;
+    let elevation = self.noon_elevation_deg.to_radians();
+    let noon = glam::Vec3::Y * elevation.sin() + glam::Vec3::Y.cross(rise) * elevation.cos();
+    (rise * angle.cos() + noon * angle.sin()).normalize()
+  }
+
+  // The sun and the sky color at the hour
+  pub fn light(&self, hour: f32) -> (SunLight, Color) {
+    let key = self.sample(hour);
+    let sun = SunLight {
+      direction: self.sun_direction(hour),
+      color: rgb(key.sun_color),
+      intensity: key.sun_intensity,
+      ambient: rgb(key.ambient),
+    };
+    (sun, rgb(key.sky_color))
+  }
+}
+
+// Hours as hh:mm on a 24 hour clock
+pub fn clock_time(hour: f32) -> String {
+  let minutes = (hour.rem_euclid(HOURS_PER_DAY) * 60.0).round() as u32 % (24 * 60);
+  format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+// Moves the sun and sky through the day. The hour only runs while playing, the editor keeps the
+// light still
+pub struct TimeOfDay {
+  curves: TimeOfDayCurves,
+  // Vfs path the curves came from, None for the built in ones
+  curves_path: Option<String>,
+  hour: f32,
+  // Real seconds a whole day takes, 0 holds the hour
+  day_length_s: f32,
+}
+
+impl TimeOfDay {
+  pub fn spawn(vfs: &Vfs, scene_time: &SceneTimeOfDay) -> Result<Self, String> {
+    let curves = match &scene_time.curves {
+      Some(path) => TimeOfDayCurves::load(vfs, path)?,
+      None => TimeOfDayCurves::default(),
+    };
+    Ok(Self {
+      curves,
+      curves_path: scene_time.curves.as_ref().map(|path| vfs::normalize_path(path)),
+      hour: scene_time.start_hour.rem_euclid(HOURS_PER_DAY),
+      day_length_s: scene_time.day_length_s.max(0.0),
+    })
+  }
+
+  pub fn hour(&self) -> f32 {
+    self.hour
+  }
+
+  pub fn set_hour(&mut self, hour: f32) {
+    self.hour = hour.rem_euclid(HOURS_PER_DAY);
+  }
+
+  pub fn update(&mut self, frame_time: u128) {
+    if self.day_length_s > 0.0 {
+      let elapsed_s = frame_time as f32 / 1_000_000.0;
+      self.set_hour(self.hour + elapsed_s / self.day_length_s * HOURS_PER_DAY);
+    }
+  }
+
+  // Loads the curves again if path is their file, true when it was
+  pub fn reload(&mut self, vfs: &Vfs, path: &str) -> Result<bool, String> {
+    let Some(curves_path) = self.curves_path.as_ref().filter(|p| **p == vfs::normalize_path(path))
+    else {
+      return Ok(false);
+    };
+    self.curves = TimeOfDayCurves::load(vfs, curves_path)?;
+    Ok(true)
+  }
+
+  pub fn apply(&self, snapshot: &mut FrameSnapshot) {
+    (snapshot.sun, snapshot.sky_color) = self.curves.light(self.hour);
+  }
+}
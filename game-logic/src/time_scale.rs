@@ -0,0 +1,47 @@
+// Fastest the game can be run, past this physics would need more steps than a tick allows
+const MAX_TIME_SCALE: f32 = 4.0;
+
+// Game time handed out per fixed tick, in microseconds. Ticks keep their real rate so input, the
+// camera and rendering stay live, only what physics and gameplay advance by is scaled or held
+#[derive(Debug, Clone)]
+pub struct TimeScale {
+  scale: f32,
+  // Real microseconds of freeze left
+  hit_stop_left: u128,
+  // Scaled time not handed out yet, so slow motion doesn't round away part of every tick
+  carry: f64,
+}
+
+impl Default for TimeScale {
+  fn default() -> Self {
+    Self { scale: 1.0, hit_stop_left: 0, carry: 0.0 }
+  }
+}
+
+impl TimeScale {
+  pub fn scale(&self) -> f32 {
+    self.scale
+  }
+
+  // 0 pauses until set back, 1 is real time
+  pub fn set_scale(&mut self, scale: f32) {
+    self.scale = scale.clamp(0.0, MAX_TIME_SCALE);
+  }
+
+  // Freezes game time for the next duration microseconds of real time, a stop already running
+  // only gets longer
+  pub fn hit_stop(&mut self, duration: u128) {
+    self.hit_stop_left = self.hit_stop_left.max(duration);
+  }
+
+  // Game time for a tick of frame_time real microseconds. A stop ending mid tick lets the rest of
+  // the tick through
+  pub fn advance(&mut self, frame_time: u128) -> u128 {
+    let stopped = self.hit_stop_left.min(frame_time);
+    self.hit_stop_left -= stopped;
+    self.carry += (frame_time - stopped) as f64 * self.scale as f64;
+    let game_time = self.carry.floor();
+    self.carry -= game_time;
+    game_time as u128
+  }
+}
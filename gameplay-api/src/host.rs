@@ -0,0 +1,303 @@
+use std::{
+  ffi::c_void,
+  fs,
+  path::{Path, PathBuf},
+  time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+  EngineServices, Gameplay, GameplayVTable, Services, Vec3, GAMEPLAY_API_VERSION,
+  GAMEPLAY_ENTRY_POINT,
+};
+
+// How often the library file is looked at for a rebuild
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+// A library written to more recently than this may still be mid build
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+// The engine side of EngineServices, what gameplay calls into for a tick
+pub trait EngineHost {
+  fn key_pressed(&self, key: &str) -> bool;
+  fn key_just_pressed(&self, key: &str) -> bool;
+  fn object_position(&self, name: &str) -> Option<Vec3>;
+  fn set_object_velocity(&mut self, name: &str, velocity: Vec3) -> bool;
+  fn emit_sparks(&mut self, origin: Vec3, count: u32);
+  fn add_trauma(&mut self, amount: f32);
+  fn random_range(&mut self, min: f32, max: f32) -> f32;
+  fn log(&self, message: &str);
+}
+
+// Strings from gameplay, bad utf8 is replaced instead of dropping the call
+fn str_arg<'a>(ptr: *const u8, len: usize) -> std::borrow::Cow<'a, str> {
+  match ptr.is_null() {
+    true => "".into(),
+    false => String::from_utf8_lossy(unsafe { std::slice::from_raw_parts(ptr, len) }),
+  }
+}
+
+fn host<'a, H: EngineHost>(ctx: *mut c_void) -> &'a mut H {
+  unsafe { &mut *(ctx as *mut H) }
+}
+
+extern "C" fn key_pressed<H: EngineHost>(ctx: *mut c_void, key: *const u8, len: usize) -> bool {
+  host::<H>(ctx).key_pressed(&str_arg(key, len))
+}
+
+extern "C" fn key_just_pressed<H: EngineHost>(
+  ctx: *mut c_void,
+  key: *const u8,
+  len: usize,
+) -> bool {
+  host::<H>(ctx).key_just_pressed(&str_arg(key, len))
+}
+
+extern "C" fn object_position<H: EngineHost>(
+  ctx: *mut c_void,
+  name: *const u8,
+  len: usize,
+  out: *mut Vec3,
+) -> bool {
+  match (host::<H>(ctx).object_position(&str_arg(name, len)), out.is_null()) {
+    (Some(position), false) => {
+      unsafe { *out = position };
+      true
+    }
+    _ => false,
+  }
+}
+
+extern "C" fn set_object_velocity<H: EngineHost>(
+  ctx: *mut c_void,
+  name: *const u8,
+  len: usize,
+  velocity: Vec3,
+) -> bool {
+  host::<H>(ctx).set_object_velocity(&str_arg(name, len), velocity)
+}
+
+extern "C" fn emit_sparks<H: EngineHost>(ctx: *mut c_void, origin: Vec3, count: u32) {
+  host::<H>(ctx).emit_sparks(origin, count)
+}
+
+extern "C" fn add_trauma<H: EngineHost>(ctx: *mut c_void, amount: f32) {
+  host::<H>(ctx).add_trauma(amount)
+}
+
+extern "C" fn random_range<H: EngineHost>(ctx: *mut c_void, min: f32, max: f32) -> f32 {
+  host::<H>(ctx).random_range(min, max)
+}
+
+extern "C" fn log<H: EngineHost>(ctx: *mut c_void, message: *const u8, len: usize) {
+  host::<H>(ctx).log(&str_arg(message, len))
+}
+
+impl EngineServices {
+  // Calls land on host, which has to outlive every use of what's returned
+  fn for_host<H: EngineHost>(host: &mut H) -> Self {
+    Self {
+      ctx: host as *mut H as *mut c_void,
+      key_pressed: key_pressed::<H>,
+      key_just_pressed: key_just_pressed::<H>,
+      object_position: object_position::<H>,
+      set_object_velocity: set_object_velocity::<H>,
+      emit_sparks: emit_sparks::<H>,
+      add_trauma: add_trauma::<H>,
+      random_range: random_range::<H>,
+      log: log::<H>,
+    }
+  }
+}
+
+struct LoadedGameplay {
+  vtable: GameplayVTable,
+  // Null once a tick panicked
+  gameplay: *mut c_void,
+  // Where the library was copied to and loaded from
+  copy_path: PathBuf,
+  // Last so it's unloaded after nothing points into it
+  library: libloading::Library,
+}
+
+impl LoadedGameplay {
+  fn load(path: &Path, copy_path: PathBuf, state: &[u8]) -> Result<Self, String> {
+    fs::copy(path, &copy_path)
+      .map_err(|e| format!("at copying gameplay library to {copy_path:?}: {e}"))?;
+    let library = unsafe { libloading::Library::new(&copy_path) }
+      .map_err(|e| format!("at loading gameplay library {copy_path:?}: {e}"))?;
+    let vtable = unsafe {
+      let entry_point = library
+        .get::<extern "C" fn() -> GameplayVTable>(GAMEPLAY_ENTRY_POINT)
+        .map_err(|e| format!("at finding gameplay entry point: {e}"))?;
+      entry_point()
+    };
+    if vtable.api_version != GAMEPLAY_API_VERSION {
+      return Err(format!(
+        "gameplay library is built for api version {}, the engine has {GAMEPLAY_API_VERSION}",
+        vtable.api_version
+      ));
+    }
+    let gameplay = (vtable.create)(state.as_ptr(), state.len());
+    Ok(Self { vtable, gameplay, copy_path, library })
+  }
+
+  fn save(&self) -> Vec<u8> {
+    if self.gameplay.is_null() {
+      return vec![];
+    }
+    let mut len = 0;
+    let bytes = (self.vtable.save)(self.gameplay, &mut len);
+    match bytes.is_null() {
+      true => vec![],
+      false => unsafe { std::slice::from_raw_parts(bytes, len) }.to_vec(),
+    }
+  }
+
+  // The copy is removed after the library is unloaded, windows won't delete it before
+  fn unload(self) {
+    if !self.gameplay.is_null() {
+      (self.vtable.destroy)(self.gameplay);
+    }
+    let Self { copy_path, library, .. } = self;
+    drop(library);
+    let _ = fs::remove_file(copy_path);
+  }
+}
+
+// Gameplay code from a dynamic library, reloaded when the file is rebuilt. The library is copied
+// before loading so the build can write over the original while it's in use. State the old
+// library saves is what the new one starts from
+pub struct GameplayLibrary {
+  path: PathBuf,
+  loaded: Option<LoadedGameplay>,
+  // Of the file that's loaded
+  loaded_modified: Option<SystemTime>,
+  loads: u32,
+  last_poll: Instant,
+}
+
+fn modified(path: &Path) -> Result<SystemTime, String> {
+  fs::metadata(path)
+    .and_then(|metadata| metadata.modified())
+    .map_err(|e| format!("at reading modified time of {path:?}: {e}"))
+}
+
+impl GameplayLibrary {
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let mut library = Self {
+      path: path.to_path_buf(),
+      loaded: None,
+      loaded_modified: None,
+      loads: 0,
+      last_poll: Instant::now(),
+    };
+    library.reload()?;
+    Ok(library)
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  // Times the library was loaded, counting the first
+  pub fn loads(&self) -> u32 {
+    self.loads
+  }
+
+  // Loads the file again, with the state the current library saves. When the new one fails to
+  // load the current one stays
+  pub fn reload(&mut self) -> Result<(), String> {
+    let modified = modified(&self.path)?;
+    let state = self.loaded.as_ref().map(|loaded| loaded.save()).unwrap_or_default();
+    let file_name = self.path.file_name().unwrap_or(self.path.as_os_str()).to_string_lossy();
+    let copy_path =
+      std::env::temp_dir().join(format!("{}_{}_{file_name}", std::process::id(), self.loads));
+    let new_loaded = LoadedGameplay::load(&self.path, copy_path, &state)?;
+    if let Some(old_loaded) = self.loaded.replace(new_loaded) {
+      old_loaded.unload();
+    }
+    self.loaded_modified = Some(modified);
+    self.loads += 1;
+    Ok(())
+  }
+
+  // Reloads once the file has changed and the build writing it is done, true when it did
+  pub fn reload_if_changed(&mut self) -> Result<bool, String> {
+    if self.last_poll.elapsed() < POLL_INTERVAL {
+      return Ok(false);
+    }
+    self.last_poll = Instant::now();
+    let modified = modified(&self.path)?;
+    let settled = modified.elapsed().is_ok_and(|age| age >= SETTLE_TIME);
+    if Some(modified) == self.loaded_modified || !settled {
+      return Ok(false);
+    }
+    self.reload().map(|_| true)
+  }
+
+  pub fn tick<H: EngineHost>(&mut self, host: &mut H, tick: Duration) -> Result<(), String> {
+    let Some(loaded) = self.loaded.as_mut().filter(|loaded| !loaded.gameplay.is_null()) else {
+      return Ok(());
+    };
+    let services = EngineServices::for_host(host);
+    if (loaded.vtable.tick)(loaded.gameplay, &services, tick.as_micros() as u64) {
+      return Ok(());
+    }
+    // Its state may be half updated, only a reload starts it again
+    (loaded.vtable.destroy)(loaded.gameplay);
+    loaded.gameplay = std::ptr::null_mut();
+    Err("gameplay panicked, it's stopped until the library is rebuilt".to_string())
+  }
+
+  pub fn save_state(&self) -> Vec<u8> {
+    self.loaded.as_ref().map(|loaded| loaded.save()).unwrap_or_default()
+  }
+}
+
+// Gameplay::tick for a gameplay behind a box
+trait TickGameplay: Send {
+  fn tick_gameplay(&mut self, services: &mut Services, tick_us: u64);
+}
+
+impl<G: Gameplay + Send> TickGameplay for G {
+  fn tick_gameplay(&mut self, services: &mut Services, tick_us: u64) {
+    self.tick(services, tick_us)
+  }
+}
+
+// Gameplay built into the game's own binary, ticked through the same services as a library's.
+// Nothing to reload, so a panic stops it for the rest of the run
+pub struct LinkedGameplay {
+  gameplay: Option<Box<dyn TickGameplay>>,
+}
+
+impl LinkedGameplay {
+  pub fn new<G: Gameplay + Send + 'static>(gameplay: G) -> Self {
+    Self { gameplay: Some(Box::new(gameplay)) }
+  }
+
+  pub fn tick<H: EngineHost>(&mut self, host: &mut H, tick: Duration) -> Result<(), String> {
+    let Some(gameplay) = self.gameplay.as_mut() else { return Ok(()) };
+    let raw_services = EngineServices::for_host(host);
+    let mut services = Services::new(&raw_services);
+    let tick_us = tick.as_micros() as u64;
+    let ticked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+      gameplay.tick_gameplay(&mut services, tick_us)
+    }));
+    if ticked.is_ok() {
+      return Ok(());
+    }
+    self.gameplay = None;
+    Err("gameplay panicked, it's stopped until the game restarts".to_string())
+  }
+}
+
+// The library's state is only reached through &mut self, so one thread at a time
+unsafe impl Send for GameplayLibrary {}
+
+impl Drop for GameplayLibrary {
+  fn drop(&mut self) {
+    if let Some(loaded) = self.loaded.take() {
+      loaded.unload();
+    }
+  }
+}
@@ -0,0 +1,178 @@
+use std::ffi::c_void;
+
+pub use host::{EngineHost, GameplayLibrary, LinkedGameplay};
+
+mod host;
+#[cfg(test)]
+mod tests;
+
+// Bumped whenever anything crossing the library boundary changes, libraries built against another
+// version aren't loaded
+pub const GAMEPLAY_API_VERSION: u32 = 1;
+// Exported by gameplay libraries as an extern "C" fn() -> GameplayVTable, export_gameplay! adds it
+pub const GAMEPLAY_ENTRY_POINT: &[u8] = b"residue_gameplay\0";
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vec3 {
+  pub x: f32,
+  pub y: f32,
+  pub z: f32,
+}
+
+// What the engine lets gameplay do, handed to every tick. Only C types cross so the engine and the
+// library don't have to be built by the same compiler. Strings are utf8 bytes and a length
+#[repr(C)]
+pub struct EngineServices {
+  pub ctx: *mut c_void,
+  // Character keys by their text like "a", named ones by winit's name like "Space"
+  pub key_pressed: extern "C" fn(ctx: *mut c_void, key: *const u8, key_len: usize) -> bool,
+  pub key_just_pressed: extern "C" fn(ctx: *mut c_void, key: *const u8, key_len: usize) -> bool,
+  // Scene objects by name, false when there's none
+  pub object_position:
+    extern "C" fn(ctx: *mut c_void, name: *const u8, name_len: usize, out: *mut Vec3) -> bool,
+  pub set_object_velocity:
+    extern "C" fn(ctx: *mut c_void, name: *const u8, name_len: usize, velocity: Vec3) -> bool,
+  pub emit_sparks: extern "C" fn(ctx: *mut c_void, origin: Vec3, count: u32),
+  pub add_trauma: extern "C" fn(ctx: *mut c_void, amount: f32),
+  // From the game's gameplay rng stream, so replays see the same numbers
+  pub random_range: extern "C" fn(ctx: *mut c_void, min: f32, max: f32) -> f32,
+  pub log: extern "C" fn(ctx: *mut c_void, message: *const u8, message_len: usize),
+}
+
+// A gameplay library's functions. The gameplay pointer is the library's own state, only ever
+// passed back to it
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GameplayVTable {
+  pub api_version: u32,
+  // From what save returned before a reload, or no bytes for a fresh start
+  pub create: extern "C" fn(state: *const u8, state_len: usize) -> *mut c_void,
+  // False when gameplay panicked, it isn't ticked again until the next reload
+  pub tick:
+    extern "C" fn(gameplay: *mut c_void, services: *const EngineServices, tick_us: u64) -> bool,
+  // The bytes stay valid until the next save or destroy
+  pub save: extern "C" fn(gameplay: *mut c_void, state_len: *mut usize) -> *const u8,
+  pub destroy: extern "C" fn(gameplay: *mut c_void),
+}
+
+// Gameplay code as the library writes it, export_gameplay! makes the C side
+pub trait Gameplay {
+  // Empty state for a fresh start, else what save returned in the library before the reload.
+  // It may come from an older build, so anything unreadable should start fresh
+  fn load(state: &[u8]) -> Self;
+  fn tick(&mut self, services: &mut Services, tick_us: u64);
+  fn save(&self) -> Vec<u8>;
+}
+
+// Safe calls into EngineServices for gameplay code
+pub struct Services<'a> {
+  raw: &'a EngineServices,
+}
+
+impl<'a> Services<'a> {
+  // From what the engine passed to tick, for export_gameplay!
+  #[doc(hidden)]
+  pub fn new(raw: &'a EngineServices) -> Self {
+    Self { raw }
+  }
+
+  pub fn key_pressed(&self, key: &str) -> bool {
+    (self.raw.key_pressed)(self.raw.ctx, key.as_ptr(), key.len())
+  }
+
+  pub fn key_just_pressed(&self, key: &str) -> bool {
+    (self.raw.key_just_pressed)(self.raw.ctx, key.as_ptr(), key.len())
+  }
+
+  pub fn object_position(&self, name: &str) -> Option<Vec3> {
+    let mut position = Vec3::default();
+    (self.raw.object_position)(self.raw.ctx, name.as_ptr(), name.len(), &mut position)
+      .then_some(position)
+  }
+
+  // False for objects without a dynamic body
+  pub fn set_object_velocity(&mut self, name: &str, velocity: Vec3) -> bool {
+    (self.raw.set_object_velocity)(self.raw.ctx, name.as_ptr(), name.len(), velocity)
+  }
+
+  pub fn emit_sparks(&mut self, origin: Vec3, count: u32) {
+    (self.raw.emit_sparks)(self.raw.ctx, origin, count)
+  }
+
+  pub fn add_trauma(&mut self, amount: f32) {
+    (self.raw.add_trauma)(self.raw.ctx, amount)
+  }
+
+  pub fn random_range(&mut self, min: f32, max: f32) -> f32 {
+    (self.raw.random_range)(self.raw.ctx, min, max)
+  }
+
+  pub fn log(&self, message: &str) {
+    (self.raw.log)(self.raw.ctx, message.as_ptr(), message.len())
+  }
+}
+
+// Gameplay state with the bytes last saved, so they outlive the save call
+#[doc(hidden)]
+pub struct Exported<T> {
+  pub gameplay: T,
+  pub saved: Vec<u8>,
+}
+
+// Exports a Gameplay type from a cdylib as the entry point the engine loads. Panics are caught at
+// the boundary, unwinding into the engine would abort it
+#[macro_export]
+macro_rules! export_gameplay {
+  ($gameplay:ty) => {
+    #[no_mangle]
+    pub extern "C" fn residue_gameplay() -> $crate::GameplayVTable {
+      extern "C" fn create(state: *const u8, state_len: usize) -> *mut ::std::ffi::c_void {
+        let state = match state.is_null() {
+          true => &[][..],
+          false => unsafe { ::std::slice::from_raw_parts(state, state_len) },
+        };
+        let gameplay = ::std::panic::catch_unwind(|| <$gameplay as $crate::Gameplay>::load(state))
+          .unwrap_or_else(|_| <$gameplay as $crate::Gameplay>::load(&[]));
+        let exported = $crate::Exported { gameplay, saved: vec![] };
+        Box::into_raw(Box::new(exported)) as *mut ::std::ffi::c_void
+      }
+
+      extern "C" fn tick(
+        gameplay: *mut ::std::ffi::c_void,
+        services: *const $crate::EngineServices,
+        tick_us: u64,
+      ) -> bool {
+        let exported = unsafe { &mut *(gameplay as *mut $crate::Exported<$gameplay>) };
+        let mut services = $crate::Services::new(unsafe { &*services });
+        ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+          $crate::Gameplay::tick(&mut exported.gameplay, &mut services, tick_us)
+        }))
+        .is_ok()
+      }
+
+      extern "C" fn save(gameplay: *mut ::std::ffi::c_void, state_len: *mut usize) -> *const u8 {
+        let exported = unsafe { &mut *(gameplay as *mut $crate::Exported<$gameplay>) };
+        exported.saved = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+          $crate::Gameplay::save(&exported.gameplay)
+        }))
+        .unwrap_or_default();
+        unsafe { *state_len = exported.saved.len() };
+        exported.saved.as_ptr()
+      }
+
+      extern "C" fn destroy(gameplay: *mut ::std::ffi::c_void) {
+        let exported = unsafe { Box::from_raw(gameplay as *mut $crate::Exported<$gameplay>) };
+        let _ = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| drop(exported)));
+      }
+
+      $crate::GameplayVTable {
+        api_version: $crate::GAMEPLAY_API_VERSION,
+        create,
+        tick,
+        save,
+        destroy,
+      }
+    }
+  };
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use crate::{EngineHost, Gameplay, LinkedGameplay, Services, Vec3};
+
+// Just enough engine for gameplay to call into
+#[derive(Default)]
+struct FakeHost {
+  space_down: bool,
+  trauma: f32,
+}
+
+impl EngineHost for FakeHost {
+  fn key_pressed(&self, key: &str) -> bool {
+    self.space_down && key == "Space"
+  }
+
+  fn key_just_pressed(&self, key: &str) -> bool {
+    self.key_pressed(key)
+  }
+
+  fn object_position(&self, _name: &str) -> Option<Vec3> {
+    None
+  }
+
+  fn set_object_velocity(&mut self, _name: &str, _velocity: Vec3) -> bool {
+    false
+  }
+
+  fn emit_sparks(&mut self, _origin: Vec3, _count: u32) {}
+
+  fn add_trauma(&mut self, amount: f32) {
+    self.trauma += amount;
+  }
+
+  fn random_range(&mut self, min: f32, _max: f32) -> f32 {
+    min
+  }
+
+  fn log(&self, _message: &str) {}
+}
+
+// Shakes the camera while space is held, panics on the tick it's told to
+struct Shaker {
+  ticks: u32,
+  panic_on: Option<u32>,
+}
+
+impl Gameplay for Shaker {
+  fn load(_state: &[u8]) -> Self {
+    Self { ticks: 0, panic_on: None }
+  }
+
+  fn tick(&mut self, services: &mut Services, _tick_us: u64) {
+    self.ticks += 1;
+    if self.panic_on == Some(self.ticks) {
+      panic!("shaker broke on tick {}", self.ticks);
+    }
+    if services.key_pressed("Space") {
+      services.add_trauma(0.5);
+    }
+  }
+
+  fn save(&self) -> Vec<u8> {
+    vec![]
+  }
+}
+
+const TICK: Duration = Duration::from_micros(16_667);
+
+#[test]
+fn linked_gameplay_calls_into_the_host() {
+  let mut gameplay = LinkedGameplay::new(Shaker { ticks: 0, panic_on: None });
+  let mut host = FakeHost::default();
+  gameplay.tick(&mut host, TICK).unwrap();
+  host.space_down = true;
+  gameplay.tick(&mut host, TICK).unwrap();
+  gameplay.tick(&mut host, TICK).unwrap();
+  assert_eq!(host.trauma, 1.0);
+}
+
+#[test]
+fn linked_gameplay_stops_after_a_panic() {
+  let mut gameplay = LinkedGameplay::new(Shaker { ticks: 0, panic_on: Some(2) });
+  let mut host = FakeHost { space_down: true, ..Default::default() };
+  gameplay.tick(&mut host, TICK).unwrap();
+  assert!(gameplay.tick(&mut host, TICK).is_err());
+  gameplay.tick(&mut host, TICK).unwrap();
+  assert_eq!(host.trauma, 0.5);
+}
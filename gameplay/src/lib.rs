@@ -0,0 +1,48 @@
+use gameplay_api::{export_gameplay, Gameplay, Services, Vec3};
+
+const CUBE: &str = "cube";
+const JUMP_KEY: &str = "Space";
+const JUMP_SPEED: f32 = 5.0;
+// Most sideways speed a jump gets, so the cube doesn't go straight up every time
+const JUMP_SPREAD: f32 = 0.5;
+const JUMP_SPARK_COUNT: u32 = 64;
+const JUMP_TRAUMA: f32 = 0.3;
+
+// The cube jump, kept across reloads so tweaking it doesn't reset the count
+#[derive(Debug, Default)]
+struct CubeJump {
+  jumps: u32,
+}
+
+impl Gameplay for CubeJump {
+  fn load(state: &[u8]) -> Self {
+    let jumps = state.try_into().map(u32::from_le_bytes).unwrap_or(0);
+    Self { jumps }
+  }
+
+  fn tick(&mut self, services: &mut Services, _tick_us: u64) {
+    if !services.key_just_pressed(JUMP_KEY) {
+      return;
+    }
+    let velocity = Vec3 {
+      x: services.random_range(-JUMP_SPREAD, JUMP_SPREAD),
+      y: JUMP_SPEED,
+      z: services.random_range(-JUMP_SPREAD, JUMP_SPREAD),
+    };
+    if !services.set_object_velocity(CUBE, velocity) {
+      return;
+    }
+    if let Some(position) = services.object_position(CUBE) {
+      services.emit_sparks(position, JUMP_SPARK_COUNT);
+    }
+    services.add_trauma(JUMP_TRAUMA);
+    self.jumps += 1;
+    services.log(&format!("jump {}", self.jumps));
+  }
+
+  fn save(&self) -> Vec<u8> {
+    self.jumps.to_le_bytes().to_vec()
+  }
+}
+
+export_gameplay!(CubeJump);
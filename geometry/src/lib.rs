@@ -1,6 +1,11 @@
 use glam::Vec4Swizzles;
 pub use glam;
 
+pub mod smoothing;
+pub mod world;
+#[cfg(test)]
+mod tests;
+
 pub fn vec4_from_vec3(v: glam::Vec3, w: f32) -> glam::Vec4 {
   glam::Vec4::new(v.x, v.y, v.z, w)
 }
@@ -93,6 +98,19 @@ impl Direction {
   pub fn is_zero(&self) -> bool {
     self.dir.length_squared() == 0.0
   }
+
+  // Two directions making a right handed basis with this one, first cross second is this. Needs
+  // a normalized direction. Branchless, from Building an Orthonormal Basis, Revisited (Duff et al)
+  pub fn orthonormal_basis(&self) -> (Self, Self) {
+    let n = self.dir;
+    let sign = 1.0f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+      Self::from_vec3(glam::vec3(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x)),
+      Self::from_vec3(glam::vec3(b, sign + n.y * n.y * a, -n.y)),
+    )
+  }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -137,8 +155,9 @@ impl LineSegment {
     if dir_dot_p == 0.0 {
       return None;
     }
+    // t is how far from start to end the hit is
     let a_dot_p = self.get_start().as_vec4().dot(plane_eq);
-    let t = a_dot_p / dir_dot_p;
+    let t = -a_dot_p / dir_dot_p;
     let intersection = Point::from_vec3(self.start + (dir.as_vec3() * t));
     Some((intersection, t))
   }
@@ -191,6 +210,14 @@ impl Plane {
   pub fn project_point(&self, point: &Point) -> Point {
     Point::from_vec3(point.as_vec3() + self.project_direction(point).as_vec3())
   }
+
+  // Angle of the point around the plane's normal through its point, in -pi..pi. Zero is along
+  // the first direction of the normal's orthonormal basis, so only differences mean anything
+  pub fn angle_around_normal(&self, point: &Point) -> f32 {
+    let (tangent, bitangent) = self.dir.orthonormal_basis();
+    let radial = point.as_vec3() - self.point.as_vec3();
+    radial.dot(bitangent.as_vec3()).atan2(radial.dot(tangent.as_vec3()))
+  }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -218,4 +245,381 @@ impl Orientation {
   pub fn add(&self, other: Self) -> Self {
     Self::new(self.position + other.position, other.rotation * self.rotation)
   }
-}
\ No newline at end of file
+
+  // Lerps the position and slerps the rotation, t of 0 is self and 1 is other
+  pub fn interpolate(&self, other: Self, t: f32) -> Self {
+    let rotation_1 = glam::Quat::from_mat4(&self.rotation);
+    let rotation_2 = glam::Quat::from_mat4(&other.rotation);
+    Self::new(
+      self.position.lerp(other.position, t),
+      glam::Mat4::from_quat(rotation_1.slerp(rotation_2, t)),
+    )
+  }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ray {
+  origin: glam::Vec3,
+  dir: glam::Vec3,
+}
+
+impl Ray {
+  pub fn new(origin: Point, dir: Direction) -> Self {
+    Self { origin: origin.as_vec3(), dir: dir.as_vec3().normalize() }
+  }
+
+  pub fn from_points(start: Point, through: Point) -> Self {
+    Self::new(start, Direction::from_points(through, start))
+  }
+
+  pub fn get_origin(&self) -> Point {
+    Point::from_vec3(self.origin)
+  }
+
+  pub fn get_direction(&self) -> Direction {
+    Direction::from_vec3(self.dir)
+  }
+
+  pub fn point_at(&self, t: f32) -> Point {
+    Point::from_vec3(self.origin + self.dir * t)
+  }
+
+  pub fn transform(&self, transform: glam::Mat4) -> Self {
+    Self::new(self.get_origin().transform(transform), self.get_direction().transform(transform))
+  }
+
+  pub fn intersection_with_plane(&self, plane: Plane) -> Option<(Point, f32)> {
+    let plane_eq = plane.get_plane_eq();
+    let dir_dot_p = self.get_direction().as_vec4().dot(plane_eq);
+    if dir_dot_p == 0.0 {
+      return None;
+    }
+    let t = -self.get_origin().as_vec4().dot(plane_eq) / dir_dot_p;
+    if t < 0.0 {
+      return None;
+    }
+    Some((self.point_at(t), t))
+  }
+
+  // Point on the axis line closest to the ray's line and how far along the axis it is, in units of
+  // axis_dir's length. None when they are close enough to parallel that it would run off far away
+  pub fn closest_point_on_axis(
+    &self,
+    axis_origin: Point,
+    axis_dir: Direction,
+  ) -> Option<(Point, f32)> {
+    let axis_dir = axis_dir.as_vec3();
+    let axis_len_sq = axis_dir.length_squared();
+    let b = axis_dir.dot(self.dir);
+    let denom = axis_len_sq - b * b;
+    if denom.abs() <= 1e-5 * axis_len_sq {
+      return None;
+    }
+    let w = axis_origin.as_vec3() - self.origin;
+    let s = (b * self.dir.dot(w) - axis_dir.dot(w)) / denom;
+    Some((Point::from_vec3(axis_origin.as_vec3() + axis_dir * s), s))
+  }
+
+  // Moller-Trumbore, hits on both faces of the triangle
+  pub fn intersection_with_triangle(&self, triangle: &Triangle) -> Option<(Point, f32)> {
+    let [a, b, c] = triangle.verts;
+    let edge_1 = b - a;
+    let edge_2 = c - a;
+    let p_vec = self.dir.cross(edge_2);
+    let det = edge_1.dot(p_vec);
+    // Against the triangle's size, small triangles have small determinants at any angle
+    if det.abs() <= f32::EPSILON * edge_1.cross(edge_2).length() {
+      return None;
+    }
+    let inv_det = 1.0 / det;
+    let t_vec = self.origin - a;
+    let u = t_vec.dot(p_vec) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+      return None;
+    }
+    let q_vec = t_vec.cross(edge_1);
+    let v = self.dir.dot(q_vec) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+      return None;
+    }
+    let t = edge_2.dot(q_vec) * inv_det;
+    if t < 0.0 {
+      return None;
+    }
+    Some((self.point_at(t), t))
+  }
+
+  // Returns entry and exit distances along the ray, entry is 0 when origin is inside the box
+  pub fn intersection_with_aabb(&self, aabb: &Aabb) -> Option<(f32, f32)> {
+    // Axes the ray doesn't move along only need the origin in their slab. The slab math would
+    // give 0 * inf for rays sliding along a face
+    let parallel = self.dir.cmpeq(glam::Vec3::ZERO);
+    let in_slab = self.origin.cmpge(aabb.min) & self.origin.cmple(aabb.max);
+    if (parallel & !in_slab).any() {
+      return None;
+    }
+    let inv_dir = self.dir.recip();
+    let t_1 = (aabb.min - self.origin) * inv_dir;
+    let t_2 = (aabb.max - self.origin) * inv_dir;
+    let t_near = glam::Vec3::select(parallel, glam::Vec3::NEG_INFINITY, t_1.min(t_2));
+    let t_far = glam::Vec3::select(parallel, glam::Vec3::INFINITY, t_1.max(t_2));
+    let t_enter = t_near.max_element().max(0.0);
+    let t_exit = t_far.min_element();
+    if t_enter > t_exit {
+      return None;
+    }
+    Some((t_enter, t_exit))
+  }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Triangle {
+  verts: [glam::Vec3; 3],
+}
+
+impl Triangle {
+  pub fn from_vec3s(a: glam::Vec3, b: glam::Vec3, c: glam::Vec3) -> Self {
+    Self { verts: [a, b, c] }
+  }
+
+  pub fn from_points(a: Point, b: Point, c: Point) -> Self {
+    Self::from_vec3s(a.as_vec3(), b.as_vec3(), c.as_vec3())
+  }
+
+  pub fn get_verts(&self) -> [Point; 3] {
+    self.verts.map(Point::from_vec3)
+  }
+
+  pub fn get_normal(&self) -> Direction {
+    Direction::from_vec3((self.verts[1] - self.verts[0]).cross(self.verts[2] - self.verts[0]))
+      .normalize()
+  }
+
+  pub fn get_plane(&self) -> Plane {
+    Plane::new(self.get_normal(), Point::from_vec3(self.verts[0]))
+  }
+
+  pub fn get_edges(&self) -> [LineSegment; 3] {
+    [
+      LineSegment::from_vec3s(self.verts[0], self.verts[1]),
+      LineSegment::from_vec3s(self.verts[1], self.verts[2]),
+      LineSegment::from_vec3s(self.verts[2], self.verts[0]),
+    ]
+  }
+
+  pub fn transform(&self, transform: glam::Mat4) -> Self {
+    Self { verts: self.get_verts().map(|v| v.transform(transform).as_vec3()) }
+  }
+
+  pub fn displace(&self, displacement: glam::Vec3) -> Self {
+    Self { verts: self.verts.map(|v| v + displacement) }
+  }
+
+  // Voronoi region walk from Real-Time Collision Detection (Ericson) 5.1.5
+  pub fn closest_point(&self, point: &Point) -> Point {
+    let [a, b, c] = self.verts;
+    let p = point.as_vec3();
+    let ab = b - a;
+    let ac = c - a;
+    // Triangles flat as a line have no face, the walk below would divide by their zero area
+    if ab.cross(ac).length_squared() <= f32::EPSILON * ab.length_squared() * ac.length_squared() {
+      return self
+        .get_edges()
+        .map(|edge| edge.closest_point(point))
+        .into_iter()
+        .min_by(|x, y| x.as_vec3().distance_squared(p).total_cmp(&y.as_vec3().distance_squared(p)))
+        .unwrap_or(Point::from_vec3(a));
+    }
+
+    let ap = p - a;
+    let d_1 = ab.dot(ap);
+    let d_2 = ac.dot(ap);
+    if d_1 <= 0.0 && d_2 <= 0.0 {
+      return Point::from_vec3(a);
+    }
+
+    let bp = p - b;
+    let d_3 = ab.dot(bp);
+    let d_4 = ac.dot(bp);
+    if d_3 >= 0.0 && d_4 <= d_3 {
+      return Point::from_vec3(b);
+    }
+
+    let vc = d_1 * d_4 - d_3 * d_2;
+    if vc <= 0.0 && d_1 >= 0.0 && d_3 <= 0.0 {
+      return Point::from_vec3(a + ab * (d_1 / (d_1 - d_3)));
+    }
+
+    let cp = p - c;
+    let d_5 = ab.dot(cp);
+    let d_6 = ac.dot(cp);
+    if d_6 >= 0.0 && d_5 <= d_6 {
+      return Point::from_vec3(c);
+    }
+
+    let vb = d_5 * d_2 - d_1 * d_6;
+    if vb <= 0.0 && d_2 >= 0.0 && d_6 <= 0.0 {
+      return Point::from_vec3(a + ac * (d_2 / (d_2 - d_6)));
+    }
+
+    let va = d_3 * d_6 - d_5 * d_4;
+    if va <= 0.0 && (d_4 - d_3) >= 0.0 && (d_5 - d_6) >= 0.0 {
+      return Point::from_vec3(b + (c - b) * ((d_4 - d_3) / ((d_4 - d_3) + (d_5 - d_6))));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    Point::from_vec3(a + ab * (vb * denom) + ac * (vc * denom))
+  }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+  min: glam::Vec3,
+  max: glam::Vec3,
+}
+
+impl Aabb {
+  pub fn from_min_max(min: glam::Vec3, max: glam::Vec3) -> Self {
+    Self { min: min.min(max), max: min.max(max) }
+  }
+
+  pub fn from_points(points: &[Point]) -> Self {
+    let mut min = glam::Vec3::splat(f32::MAX);
+    let mut max = glam::Vec3::splat(f32::MIN);
+    for point in points {
+      min = min.min(point.as_vec3());
+      max = max.max(point.as_vec3());
+    }
+    if points.is_empty() {
+      return Self { min: glam::Vec3::ZERO, max: glam::Vec3::ZERO };
+    }
+    Self { min, max }
+  }
+
+  pub fn get_min(&self) -> Point {
+    Point::from_vec3(self.min)
+  }
+
+  pub fn get_max(&self) -> Point {
+    Point::from_vec3(self.max)
+  }
+
+  pub fn get_center(&self) -> Point {
+    Point::from_vec3((self.min + self.max) / 2.0)
+  }
+
+  pub fn get_half_extents(&self) -> glam::Vec3 {
+    (self.max - self.min) / 2.0
+  }
+
+  pub fn contains_point(&self, point: &Point) -> bool {
+    let p = point.as_vec3();
+    p.cmpge(self.min).all() && p.cmple(self.max).all()
+  }
+
+  pub fn intersects(&self, other: &Self) -> bool {
+    self.min.cmple(other.max).all() && self.max.cmpge(other.min).all()
+  }
+
+  pub fn merge(&self, other: &Self) -> Self {
+    Self { min: self.min.min(other.min), max: self.max.max(other.max) }
+  }
+
+  pub fn displace(&self, displacement: glam::Vec3) -> Self {
+    Self { min: self.min + displacement, max: self.max + displacement }
+  }
+
+  pub fn closest_point(&self, point: &Point) -> Point {
+    Point::from_vec3(point.as_vec3().clamp(self.min, self.max))
+  }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Capsule {
+  segment: LineSegment,
+  radius: f32,
+}
+
+impl Capsule {
+  pub fn new(segment: LineSegment, radius: f32) -> Self {
+    Self { segment, radius }
+  }
+
+  pub fn get_segment(&self) -> LineSegment {
+    self.segment
+  }
+
+  pub fn get_radius(&self) -> f32 {
+    self.radius
+  }
+
+  pub fn transform(&self, transform: glam::Mat4) -> Self {
+    Self { segment: self.segment.transform(transform), radius: self.radius }
+  }
+
+  pub fn displace(&self, displacement: glam::Vec3) -> Self {
+    Self { segment: self.segment.displace(displacement), radius: self.radius }
+  }
+
+  pub fn get_aabb(&self) -> Aabb {
+    let radius = glam::Vec3::splat(self.radius);
+    Aabb::from_min_max(
+      self.segment.start.min(self.segment.end) - radius,
+      self.segment.start.max(self.segment.end) + radius,
+    )
+  }
+
+  // Surface distance between the capsules, negative when they overlap
+  pub fn closest_distance(&self, other: &Self) -> f32 {
+    let (p_1, p_2) = self.segment.closest_points_with_segment(&other.segment);
+    p_1.as_vec3().distance(p_2.as_vec3()) - self.radius - other.radius
+  }
+}
+
+impl LineSegment {
+  pub fn closest_point(&self, point: &Point) -> Point {
+    let dir = self.end - self.start;
+    let len_sq = dir.length_squared();
+    if len_sq == 0.0 {
+      return self.get_start();
+    }
+    let t = ((point.as_vec3() - self.start).dot(dir) / len_sq).clamp(0.0, 1.0);
+    Point::from_vec3(self.start + dir * t)
+  }
+
+  // Closest points on two segments, from Real-Time Collision Detection (Ericson) 5.1.9
+  pub fn closest_points_with_segment(&self, other: &Self) -> (Point, Point) {
+    let d_1 = self.end - self.start;
+    let d_2 = other.end - other.start;
+    let r = self.start - other.start;
+    let a = d_1.length_squared();
+    let e = d_2.length_squared();
+    let f = d_2.dot(r);
+
+    if a <= f32::EPSILON && e <= f32::EPSILON {
+      return (self.get_start(), other.get_start());
+    }
+    let (s, t) = if a <= f32::EPSILON {
+      (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+      let c = d_1.dot(r);
+      if e <= f32::EPSILON {
+        ((-c / a).clamp(0.0, 1.0), 0.0)
+      } else {
+        let b = d_1.dot(d_2);
+        let denom = a * e - b * b;
+        let mut s = if denom != 0.0 { ((b * f - c * e) / denom).clamp(0.0, 1.0) } else { 0.0 };
+        let mut t = (b * s + f) / e;
+        if t < 0.0 {
+          t = 0.0;
+          s = (-c / a).clamp(0.0, 1.0);
+        } else if t > 1.0 {
+          t = 1.0;
+          s = ((b - c) / a).clamp(0.0, 1.0);
+        }
+        (s, t)
+      }
+    };
+    (Point::from_vec3(self.start + d_1 * s), Point::from_vec3(other.start + d_2 * t))
+  }
+}
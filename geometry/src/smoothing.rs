@@ -0,0 +1,149 @@
+use std::f32::consts::{PI, TAU};
+
+// Blend factor that closes the same share of a gap per second whatever the frame time. rate is
+// how fast, after 1 / rate seconds about 63% of the gap is gone
+pub fn exp_smoothing_factor(rate: f32, dt: f32) -> f32 {
+  1.0 - (-rate * dt).exp()
+}
+
+// Exponential smoothing towards target, the frame rate independent form of lerp(target, k)
+pub fn damp(current: f32, target: f32, rate: f32, dt: f32) -> f32 {
+  current + (target - current) * exp_smoothing_factor(rate, dt)
+}
+
+pub fn damp_vec3(current: glam::Vec3, target: glam::Vec3, rate: f32, dt: f32) -> glam::Vec3 {
+  current.lerp(target, exp_smoothing_factor(rate, dt))
+}
+
+// Fraction of a quantity left after decaying for dt, for things that fade to zero by themselves
+pub fn exp_decay(value: f32, rate: f32, dt: f32) -> f32 {
+  value * (-rate * dt).exp()
+}
+
+// Critically damped spring, gets to the target as fast as it can without overshooting. The state
+// carries the velocity between frames, smooth_time is roughly the seconds it takes to arrive
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Spring {
+  pub value: f32,
+  pub velocity: f32,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct SpringVec3 {
+  pub value: glam::Vec3,
+  pub velocity: glam::Vec3,
+}
+
+// Closed form step of the spring, a Taylor fit of exp(-x) as in Game Programming Gems 4 so large
+// dt stays stable
+fn spring_decay(smooth_time: f32, dt: f32) -> (f32, f32) {
+  let omega = 2.0 / smooth_time.max(1e-4);
+  let x = omega * dt;
+  (omega, 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x))
+}
+
+impl Spring {
+  pub fn new(value: f32) -> Self {
+    Self { value, velocity: 0.0 }
+  }
+
+  pub fn update(&mut self, target: f32, smooth_time: f32, dt: f32) -> f32 {
+    let (omega, decay) = spring_decay(smooth_time, dt);
+    let offset = self.value - target;
+    let temp = (self.velocity + omega * offset) * dt;
+    self.velocity = (self.velocity - omega * temp) * decay;
+    self.value = target + (offset + temp) * decay;
+    self.value
+  }
+}
+
+impl SpringVec3 {
+  pub fn new(value: glam::Vec3) -> Self {
+    Self { value, velocity: glam::Vec3::ZERO }
+  }
+
+  pub fn update(&mut self, target: glam::Vec3, smooth_time: f32, dt: f32) -> glam::Vec3 {
+    let (omega, decay) = spring_decay(smooth_time, dt);
+    let offset = self.value - target;
+    let temp = (self.velocity + omega * offset) * dt;
+    self.velocity = (self.velocity - omega * temp) * decay;
+    self.value = target + (offset + temp) * decay;
+    self.value
+  }
+}
+
+// Same angle in -PI..PI
+pub fn wrap_angle(angle: f32) -> f32 {
+  (angle + PI).rem_euclid(TAU) - PI
+}
+
+// Shortest signed turn from one angle to another
+pub fn angle_delta(from: f32, to: f32) -> f32 {
+  wrap_angle(to - from)
+}
+
+// Lerps the short way round, 350 to 10 degrees passes through 0 and not 180
+pub fn lerp_angle(from: f32, to: f32, t: f32) -> f32 {
+  from + angle_delta(from, to) * t
+}
+
+pub fn damp_angle(current: f32, target: f32, rate: f32, dt: f32) -> f32 {
+  lerp_angle(current, target, exp_smoothing_factor(rate, dt))
+}
+
+// Easing curves, t in 0..1 maps to 0..1
+pub mod easing {
+  pub fn linear(t: f32) -> f32 {
+    t.clamp(0.0, 1.0)
+  }
+
+  pub fn quad_in(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t
+  }
+
+  pub fn quad_out(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    1.0 - (1.0 - t) * (1.0 - t)
+  }
+
+  pub fn cubic_in(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * t
+  }
+
+  pub fn cubic_out(t: f32) -> f32 {
+    1.0 - (1.0 - t.clamp(0.0, 1.0)).powi(3)
+  }
+
+  pub fn cubic_in_out(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+      4.0 * t * t * t
+    } else {
+      1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+    }
+  }
+
+  pub fn sine_in_out(t: f32) -> f32 {
+    -((std::f32::consts::PI * t.clamp(0.0, 1.0)).cos() - 1.0) / 2.0
+  }
+
+  pub fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+  }
+
+  // Flat first and second derivative at both ends
+  pub fn smootherstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+  }
+
+  // Runs a little past 1 before settling, for things that pop into place
+  pub fn back_out(t: f32) -> f32 {
+    let overshoot = 1.70158;
+    let t = t.clamp(0.0, 1.0) - 1.0;
+    1.0 + (overshoot + 1.0) * t * t * t + overshoot * t * t
+  }
+}
@@ -0,0 +1,492 @@
+use std::f32::consts::FRAC_PI_2;
+
+use rng::test_support::fuzz;
+use rng::RngStream;
+
+use crate::world::{origin_shift, WorldTransform};
+use crate::{Aabb, Capsule, Direction, LineSegment, Plane, Point, Ray, Triangle};
+
+// Sizes from millimetres to kilometres, where float error shows up
+fn random_scale(rng: &mut RngStream) -> f32 {
+  *rng.pick(&[1e-3, 1.0, 1e3]).unwrap()
+}
+
+fn random_vec3(rng: &mut RngStream, scale: f32) -> glam::Vec3 {
+  glam::Vec3::from_array(rng.in_unit_sphere()) * scale
+}
+
+// Axis aligned a tenth of the time, the zero components are the usual edge cases
+fn random_dir(rng: &mut RngStream) -> glam::Vec3 {
+  if rng.chance(0.1) {
+    return *rng.pick(&[glam::Vec3::X, glam::Vec3::NEG_Y, glam::Vec3::Z]).unwrap();
+  }
+  glam::Vec3::from_array(rng.in_unit_sphere()).try_normalize().unwrap_or(glam::Vec3::Y)
+}
+
+fn random_rigid_transform(rng: &mut RngStream, scale: f32) -> glam::Mat4 {
+  let rotation = glam::Quat::from_axis_angle(random_dir(rng), rng.range_f32(-3.2, 3.2));
+  glam::Mat4::from_rotation_translation(rotation, random_vec3(rng, scale))
+}
+
+fn check_finite(what: &str, values: &[f32]) -> Result<(), String> {
+  match values.iter().all(|x| x.is_finite()) {
+    true => Ok(()),
+    false => Err(format!("{what} is not finite: {values:?}")),
+  }
+}
+
+#[test]
+fn orthonormal_basis_is_right_handed_for_any_direction() {
+  let dirs = [
+    glam::Vec3::X,
+    glam::Vec3::Y,
+    glam::Vec3::Z,
+    glam::Vec3::NEG_Z,
+    glam::vec3(1.0, 2.0, -3.0),
+    glam::vec3(-0.3, 0.1, 0.9),
+    // Almost straight down -z, right by where the sign of z flips
+    glam::vec3(1e-4, -1e-4, -1.0),
+  ];
+  for dir in dirs.map(|dir| Direction::from_vec3(dir).normalize()) {
+    let (tangent, bitangent) = dir.orthonormal_basis();
+    let (t, b, n) = (tangent.as_vec3(), bitangent.as_vec3(), dir.as_vec3());
+    assert!((t.length() - 1.0).abs() < 1e-5 && (b.length() - 1.0).abs() < 1e-5, "{n}");
+    assert!(t.dot(b).abs() < 1e-5 && t.dot(n).abs() < 1e-5 && b.dot(n).abs() < 1e-5, "{n}");
+    assert!(t.cross(b).distance(n) < 1e-5, "{n}");
+  }
+}
+
+#[test]
+fn ray_finds_closest_axis_point_and_angle_on_plane() {
+  // Looking down -z from above the x axis, over the point 2 along it
+  let ray =
+    Ray::new(Point::from_vec3(glam::vec3(2.0, 3.0, 5.0)), Direction::from_vec3(glam::Vec3::NEG_Z));
+  let (point, along) = ray
+    .closest_point_on_axis(Point::from_vec3(glam::Vec3::ZERO), Direction::from_vec3(glam::Vec3::X))
+    .unwrap();
+  assert!(point.as_vec3().distance(glam::vec3(2.0, 0.0, 0.0)) < 1e-5);
+  assert!((along - 2.0).abs() < 1e-5);
+  // Along is in lengths of the axis direction
+  let (_, along) = ray
+    .closest_point_on_axis(
+      Point::from_vec3(glam::vec3(1.0, 0.0, 0.0)),
+      Direction::from_vec3(glam::vec3(0.5, 0.0, 0.0)),
+    )
+    .unwrap();
+  assert!((along - 2.0).abs() < 1e-5);
+  let parallel = Direction::from_vec3(glam::Vec3::Z);
+  assert!(ray.closest_point_on_axis(Point::from_vec3(glam::Vec3::ZERO), parallel).is_none());
+
+  let plane = Plane::new(Direction::from_vec3(glam::Vec3::Z), Point::from_vec3(glam::Vec3::ZERO));
+  let (hit, t) = ray.intersection_with_plane(plane).unwrap();
+  assert!((t - 5.0).abs() < 1e-5);
+  let quarter_turn = Point::from_vec3(glam::vec3(-3.0, 2.0, 0.0));
+  let turned = plane.angle_around_normal(&quarter_turn) - plane.angle_around_normal(&hit);
+  assert!((turned.rem_euclid(2.0 * std::f32::consts::PI) - FRAC_PI_2).abs() < 1e-5);
+}
+
+#[test]
+fn segment_plane_intersections_lie_on_the_plane_between_the_ends() {
+  fuzz("segment plane intersection", |rng| {
+    let scale = random_scale(rng);
+    let normal = random_dir(rng);
+    let plane = Plane::new(Direction::from_vec3(normal), Point::from_vec3(random_vec3(rng, scale)));
+    // Ends on either side of the plane, anywhere along it
+    let along = |rng: &mut RngStream| {
+      plane.project_point(&Point::from_vec3(random_vec3(rng, scale * 4.0))).as_vec3()
+    };
+    let start = along(rng) + normal * rng.range_f32(0.01, 2.0) * scale;
+    let end = along(rng) - normal * rng.range_f32(0.01, 2.0) * scale;
+    let tolerance = 1e-4 * scale * 8.0;
+
+    let (hit, t) = LineSegment::from_vec3s(start, end)
+      .intersection_with_plane(plane)
+      .ok_or("segment crossing the plane missed it")?;
+    check_finite("hit", &[hit.as_vec3().x, hit.as_vec3().y, hit.as_vec3().z, t])?;
+    if !(-1e-4..=1.0 + 1e-4).contains(&t) {
+      return Err(format!("hit is {t} of the way along the segment"));
+    }
+    if plane.dist_from_point(&hit).abs() > tolerance {
+      return Err(format!("hit is {} off the plane", plane.dist_from_point(&hit)));
+    }
+    // Same point from the other end, and with the plane facing the other way
+    let (reversed_hit, reversed_t) = LineSegment::from_vec3s(end, start)
+      .intersection_with_plane(plane.opposite())
+      .ok_or("reversed segment missed the plane")?;
+    if reversed_hit.as_vec3().distance(hit.as_vec3()) > tolerance
+      || (reversed_t - (1.0 - t)).abs() > 1e-3
+    {
+      return Err(format!("reversed hit {reversed_hit:?} at {reversed_t} but {hit:?} at {t}"));
+    }
+    Ok(())
+  });
+}
+
+#[test]
+fn ray_triangle_hits_match_where_the_ray_was_aimed_under_rigid_transforms() {
+  fuzz("ray triangle intersection", |rng| {
+    let scale = random_scale(rng);
+    let [a, b, c] = [(); 3].map(|_| random_vec3(rng, scale));
+    let triangle = Triangle::from_vec3s(a, b, c);
+    let normal = (b - a).cross(c - a);
+    // Slivers are allowed to miss, barely there triangles have no good answer
+    if normal.length() < 0.05 * scale * scale {
+      return Ok(());
+    }
+    // Aimed inside, clear of the edges, from a direction that isn't grazing the triangle
+    let (u, v) = (rng.range_f32(0.05, 0.9), rng.range_f32(0.05, 0.9));
+    let (u, v) = if u + v > 0.95 { (0.95 - v, 0.95 - u) } else { (u, v) };
+    let target = a + (b - a) * u + (c - a) * v;
+    let dir = random_dir(rng);
+    let dir = if dir.dot(normal.normalize()).abs() < 0.2 { normal.normalize() } else { dir };
+    let dist = rng.range_f32(0.1, 4.0) * scale;
+    let ray = Ray::new(Point::from_vec3(target - dir * dist), Direction::from_vec3(dir));
+    let tolerance = 1e-3 * scale * 8.0;
+
+    let (hit, t) = ray.intersection_with_triangle(&triangle).ok_or("aimed ray missed")?;
+    check_finite("hit", &[hit.as_vec3().x, hit.as_vec3().y, hit.as_vec3().z, t])?;
+    if t < 0.0 || (t - dist).abs() > tolerance || hit.as_vec3().distance(target) > tolerance {
+      return Err(format!("hit {hit:?} at {t}, aimed at {target} from {dist} away"));
+    }
+    // Moving the ray and triangle together doesn't move the hit relative to them
+    let transform = random_rigid_transform(rng, scale);
+    let (_, moved_t) = ray
+      .transform(transform)
+      .intersection_with_triangle(&triangle.transform(transform))
+      .ok_or("ray moved with the triangle missed it")?;
+    if (moved_t - t).abs() > tolerance {
+      return Err(format!("hit at {moved_t} once moved, {t} before"));
+    }
+    Ok(())
+  });
+}
+
+#[test]
+fn closest_point_on_triangle_is_no_farther_than_any_of_it() {
+  fuzz("triangle closest point", |rng| {
+    let scale = random_scale(rng);
+    let [a, b, c] = [(); 3].map(|_| random_vec3(rng, scale));
+    // Collinear and collapsed triangles too, they come out of degenerate meshes
+    let c = match rng.range_u32(0, 4) {
+      0 => a + (b - a) * rng.range_f32(-1.0, 2.0),
+      1 => a,
+      _ => c,
+    };
+    let triangle = Triangle::from_vec3s(a, b, c);
+    let point = random_vec3(rng, scale * 4.0);
+    let closest = triangle.closest_point(&Point::from_vec3(point)).as_vec3();
+    check_finite("closest point", &closest.to_array())?;
+    let dist = closest.distance(point);
+    let tolerance = 1e-4 * scale * 8.0;
+    let samples = (0..16).map(|_| {
+      let (u, v) = (rng.next_f32(), rng.next_f32());
+      let (u, v) = if u + v > 1.0 { (1.0 - u, 1.0 - v) } else { (u, v) };
+      a + (b - a) * u + (c - a) * v
+    });
+    for sample in [a, b, c].into_iter().chain(samples.collect::<Vec<_>>()) {
+      if sample.distance(point) < dist - tolerance {
+        return Err(format!("{sample} is closer to {point} than {closest}"));
+      }
+    }
+    Ok(())
+  });
+}
+
+#[test]
+fn ray_aabb_entry_is_on_the_box_before_the_exit() {
+  fuzz("ray aabb intersection", |rng| {
+    let scale = random_scale(rng);
+    let aabb = Aabb::from_min_max(random_vec3(rng, scale), random_vec3(rng, scale));
+    let origin = random_vec3(rng, scale * 3.0);
+    let ray = Ray::new(Point::from_vec3(origin), Direction::from_vec3(random_dir(rng)));
+    let tolerance = 1e-4 * scale * 8.0;
+    let grown = Aabb::from_min_max(
+      aabb.get_min().as_vec3() - tolerance,
+      aabb.get_max().as_vec3() + tolerance,
+    );
+
+    let inside = aabb.contains_point(&Point::from_vec3(origin));
+    let Some((enter, exit)) = ray.intersection_with_aabb(&aabb) else {
+      return match inside {
+        true => Err("ray starting inside the box missed it".to_string()),
+        false => Ok(()),
+      };
+    };
+    check_finite("entry and exit", &[enter, exit])?;
+    if enter < 0.0 || enter > exit || (inside && enter != 0.0) {
+      return Err(format!("entered at {enter} and left at {exit}, started inside: {inside}"));
+    }
+    for t in [enter, exit] {
+      if !grown.contains_point(&ray.point_at(t)) {
+        return Err(format!("{:?} at {t} along the ray is off the box", ray.point_at(t)));
+      }
+    }
+    Ok(())
+  });
+}
+
+#[test]
+fn plane_distances_survive_rigid_transforms_and_flipping() {
+  fuzz("plane distance", |rng| {
+    let scale = random_scale(rng);
+    let plane =
+      Plane::new(Direction::from_vec3(random_dir(rng)), Point::from_vec3(random_vec3(rng, scale)));
+    let point = Point::from_vec3(random_vec3(rng, scale * 4.0));
+    let transform = random_rigid_transform(rng, scale);
+    let dist = plane.dist_from_point(&point);
+    let moved_dist = plane.transform(transform).dist_from_point(&point.transform(transform));
+    let flipped_dist = plane.opposite().dist_from_point(&point);
+    check_finite("distances", &[dist, moved_dist, flipped_dist])?;
+    let tolerance = 1e-4 * scale * 8.0;
+    if (moved_dist - dist).abs() > tolerance || (flipped_dist + dist).abs() > tolerance {
+      return Err(format!("{dist} from the plane, {moved_dist} moved and {flipped_dist} flipped"));
+    }
+    Ok(())
+  });
+}
+
+#[test]
+fn world_transforms_keep_millimetres_thousands_of_kilometres_out() {
+  fuzz("world transform", |rng| {
+    let far = glam::DVec3::from_array(rng.in_unit_sphere().map(|x| x as f64)) * 5e6;
+    let origin = far.round();
+    let local = random_rigid_transform(rng, 1e3);
+    let world = WorldTransform::from_local(origin, local);
+    // Anywhere the origin moves to, the object ends up where it was
+    let shift = random_vec3(rng, 1e3).round();
+    let moved_origin = origin + shift.as_dvec3();
+    let moved = WorldTransform::from_local(moved_origin, world.local(moved_origin));
+    let error = (moved.position - world.position).length();
+    if error > 1e-3 {
+      return Err(format!("{:?} moved {error} after the origin moved by {shift}", world.position));
+    }
+    Ok(())
+  });
+}
+
+#[test]
+fn origin_only_shifts_once_the_focus_is_far_out() {
+  assert_eq!(origin_shift(glam::vec3(100.0, 0.0, 0.0), 1000.0), None);
+  let shift = origin_shift(glam::vec3(1200.4, -3.6, 0.0), 1000.0).unwrap();
+  assert_eq!(shift, glam::vec3(1200.0, -4.0, 0.0));
+}
+
+fn ray(origin: glam::Vec3, dir: glam::Vec3) -> Ray {
+  Ray::new(Point::from_vec3(origin), Direction::from_vec3(dir))
+}
+
+fn close(a: glam::Vec3, b: glam::Vec3) -> bool {
+  a.distance(b) < 1e-5
+}
+
+#[test]
+fn ray_plane_hits_misses_behind_and_parallel() {
+  let plane = Plane::new(Direction::from_vec3(glam::Vec3::Y), Point::from_vec3(glam::Vec3::Y));
+  let (hit, t) =
+    ray(glam::vec3(2.0, 5.0, 0.0), glam::Vec3::NEG_Y).intersection_with_plane(plane).unwrap();
+  assert!(close(hit.as_vec3(), glam::vec3(2.0, 1.0, 0.0)) && (t - 4.0).abs() < 1e-5);
+  // From underneath too, planes have no back face for rays
+  let (_, t) = ray(glam::Vec3::ZERO, glam::Vec3::Y).intersection_with_plane(plane).unwrap();
+  assert!((t - 1.0).abs() < 1e-5);
+  // Behind the origin
+  assert!(ray(glam::vec3(0.0, 5.0, 0.0), glam::Vec3::Y).intersection_with_plane(plane).is_none());
+  // Parallel, off the plane and in it
+  assert!(ray(glam::vec3(0.0, 5.0, 0.0), glam::Vec3::X).intersection_with_plane(plane).is_none());
+  assert!(ray(glam::Vec3::Y, glam::Vec3::X).intersection_with_plane(plane).is_none());
+  // Starting on the plane hits right away
+  let (hit, t) = ray(glam::Vec3::Y, glam::Vec3::NEG_Y).intersection_with_plane(plane).unwrap();
+  assert!(close(hit.as_vec3(), glam::Vec3::Y) && t.abs() < 1e-5);
+}
+
+#[test]
+fn ray_triangle_hits_misses_parallel_and_edges() {
+  let triangle = Triangle::from_vec3s(glam::Vec3::ZERO, glam::Vec3::X * 2.0, glam::Vec3::Y * 2.0);
+  let down = glam::Vec3::NEG_Z;
+  let (hit, t) =
+    ray(glam::vec3(0.5, 0.5, 3.0), down).intersection_with_triangle(&triangle).unwrap();
+  assert!(close(hit.as_vec3(), glam::vec3(0.5, 0.5, 0.0)) && (t - 3.0).abs() < 1e-5);
+  // Back face
+  let (_, t) =
+    ray(glam::vec3(0.5, 0.5, -1.0), glam::Vec3::Z).intersection_with_triangle(&triangle).unwrap();
+  assert!((t - 1.0).abs() < 1e-5);
+  // Outside the hypotenuse, past the vertices and behind
+  assert!(ray(glam::vec3(1.5, 1.5, 3.0), down).intersection_with_triangle(&triangle).is_none());
+  assert!(ray(glam::vec3(-0.1, 0.5, 3.0), down).intersection_with_triangle(&triangle).is_none());
+  assert!(ray(glam::vec3(0.5, 0.5, -3.0), down).intersection_with_triangle(&triangle).is_none());
+  // Parallel, in its plane and above it
+  let along = glam::Vec3::X;
+  assert!(ray(glam::vec3(-1.0, 0.5, 0.0), along).intersection_with_triangle(&triangle).is_none());
+  assert!(ray(glam::vec3(-1.0, 0.5, 1.0), along).intersection_with_triangle(&triangle).is_none());
+  // Edges and vertices count as hits
+  for target in [
+    glam::vec3(1.0, 0.0, 0.0),
+    glam::vec3(0.0, 1.0, 0.0),
+    glam::vec3(1.0, 1.0, 0.0),
+    glam::Vec3::ZERO,
+    glam::vec3(2.0, 0.0, 0.0),
+  ] {
+    let (hit, _) = ray(target + glam::Vec3::Z, down).intersection_with_triangle(&triangle).unwrap();
+    assert!(close(hit.as_vec3(), target), "{target}");
+  }
+  // Degenerate triangles have nothing to hit
+  let flat = Triangle::from_vec3s(glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::X * 2.0);
+  assert!(ray(glam::vec3(1.0, 0.0, 1.0), down).intersection_with_triangle(&flat).is_none());
+}
+
+#[test]
+fn ray_aabb_hits_misses_parallel_and_edges() {
+  let aabb = Aabb::from_min_max(glam::Vec3::splat(-1.0), glam::Vec3::splat(1.0));
+  let (enter, exit) =
+    ray(glam::vec3(-5.0, 0.2, 0.3), glam::Vec3::X).intersection_with_aabb(&aabb).unwrap();
+  assert!((enter - 4.0).abs() < 1e-5 && (exit - 6.0).abs() < 1e-5);
+  // Diagonal, through opposite corners
+  let (enter, exit) =
+    ray(glam::Vec3::splat(-2.0), glam::Vec3::ONE).intersection_with_aabb(&aabb).unwrap();
+  assert!((enter - 3f32.sqrt()).abs() < 1e-5 && (exit - 3.0 * 3f32.sqrt()).abs() < 1e-5);
+  // From inside
+  let (enter, exit) =
+    ray(glam::Vec3::ZERO, glam::Vec3::NEG_Y).intersection_with_aabb(&aabb).unwrap();
+  assert!(enter == 0.0 && (exit - 1.0).abs() < 1e-5);
+  // Pointing away, and passing beside it
+  assert!(ray(glam::vec3(-5.0, 0.0, 0.0), glam::Vec3::NEG_X)
+    .intersection_with_aabb(&aabb)
+    .is_none());
+  assert!(ray(glam::vec3(-5.0, 0.0, 0.0), glam::vec3(1.0, 1.0, 0.0))
+    .intersection_with_aabb(&aabb)
+    .is_none());
+  // Parallel to a face, outside its slab and inside it
+  assert!(ray(glam::vec3(-5.0, 2.0, 0.0), glam::Vec3::X).intersection_with_aabb(&aabb).is_none());
+  assert!(ray(glam::vec3(-5.0, 0.5, 0.0), glam::Vec3::X).intersection_with_aabb(&aabb).is_some());
+  // Touching an edge at a single point, and sliding along a face or an edge
+  let (enter, exit) = ray(glam::vec3(-3.0, 0.0, -1.0), glam::vec3(1.0, 0.0, 1.0))
+    .intersection_with_aabb(&aabb)
+    .unwrap();
+  assert!((enter - 8f32.sqrt()).abs() < 1e-5 && (exit - enter).abs() < 1e-5);
+  let (enter, exit) =
+    ray(glam::vec3(-5.0, 1.0, 0.0), glam::Vec3::X).intersection_with_aabb(&aabb).unwrap();
+  assert!((enter - 4.0).abs() < 1e-5 && (exit - 6.0).abs() < 1e-5);
+  let (enter, exit) =
+    ray(glam::vec3(-5.0, -1.0, 1.0), glam::Vec3::X).intersection_with_aabb(&aabb).unwrap();
+  assert!((enter - 4.0).abs() < 1e-5 && (exit - 6.0).abs() < 1e-5);
+  // Going the other way with a negative zero
+  let (enter, _) = ray(glam::vec3(5.0, 1.0, 0.0), glam::vec3(-1.0, -0.0, 0.0))
+    .intersection_with_aabb(&aabb)
+    .unwrap();
+  assert!((enter - 4.0).abs() < 1e-5);
+}
+
+#[test]
+fn closest_point_on_triangle_in_each_region() {
+  let triangle = Triangle::from_vec3s(glam::Vec3::ZERO, glam::Vec3::X * 2.0, glam::Vec3::Y * 2.0);
+  let closest = |p: glam::Vec3| triangle.closest_point(&Point::from_vec3(p)).as_vec3();
+  // Over the face, past each vertex and past each edge
+  assert!(close(closest(glam::vec3(0.5, 0.5, 3.0)), glam::vec3(0.5, 0.5, 0.0)));
+  assert!(close(closest(glam::vec3(-1.0, -1.0, 1.0)), glam::Vec3::ZERO));
+  assert!(close(closest(glam::vec3(3.0, -1.0, 0.0)), glam::vec3(2.0, 0.0, 0.0)));
+  assert!(close(closest(glam::vec3(-1.0, 3.0, 0.0)), glam::vec3(0.0, 2.0, 0.0)));
+  assert!(close(closest(glam::vec3(1.0, -1.0, 0.0)), glam::vec3(1.0, 0.0, 0.0)));
+  assert!(close(closest(glam::vec3(-1.0, 1.0, -2.0)), glam::vec3(0.0, 1.0, 0.0)));
+  assert!(close(closest(glam::vec3(2.0, 2.0, 0.0)), glam::vec3(1.0, 1.0, 0.0)));
+  // Points on an edge or a vertex are their own closest point
+  assert!(close(closest(glam::vec3(1.0, 1.0, 0.0)), glam::vec3(1.0, 1.0, 0.0)));
+  assert!(close(closest(glam::vec3(0.0, 2.0, 0.0)), glam::vec3(0.0, 2.0, 0.0)));
+  // Collinear triangles fall back to their edges
+  let flat = Triangle::from_vec3s(glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::X * 2.0);
+  let on_flat = flat.closest_point(&Point::from_vec3(glam::vec3(1.5, 1.0, 0.0))).as_vec3();
+  assert!(close(on_flat, glam::vec3(1.5, 0.0, 0.0)));
+}
+
+#[test]
+fn aabb_contains_intersects_and_closest_point_at_the_boundary() {
+  let aabb = Aabb::from_min_max(glam::Vec3::ONE, glam::Vec3::ZERO);
+  assert!(close(aabb.get_min().as_vec3(), glam::Vec3::ZERO));
+  assert!(aabb.contains_point(&Point::from_vec3(glam::Vec3::splat(0.5))));
+  assert!(aabb.contains_point(&Point::from_vec3(glam::vec3(1.0, 0.0, 0.5))));
+  assert!(!aabb.contains_point(&Point::from_vec3(glam::vec3(1.01, 0.5, 0.5))));
+
+  let apart = aabb.displace(glam::vec3(2.0, 0.0, 0.0));
+  let face_to_face = aabb.displace(glam::vec3(1.0, 0.0, 0.0));
+  let corner_to_corner = aabb.displace(glam::Vec3::ONE);
+  let overlapping = aabb.displace(glam::Vec3::splat(0.5));
+  assert!(!aabb.intersects(&apart));
+  assert!(aabb.intersects(&face_to_face) && aabb.intersects(&corner_to_corner));
+  assert!(aabb.intersects(&overlapping) && overlapping.intersects(&aabb));
+  let merged = aabb.merge(&apart);
+  assert!(close(merged.get_max().as_vec3(), glam::vec3(3.0, 1.0, 1.0)));
+
+  let closest = |p: glam::Vec3| aabb.closest_point(&Point::from_vec3(p)).as_vec3();
+  assert!(close(closest(glam::Vec3::splat(0.25)), glam::Vec3::splat(0.25)));
+  assert!(close(closest(glam::vec3(3.0, 0.5, 0.5)), glam::vec3(1.0, 0.5, 0.5)));
+  assert!(close(closest(glam::vec3(-1.0, 2.0, -3.0)), glam::vec3(0.0, 1.0, 0.0)));
+}
+
+#[test]
+fn closest_points_on_segments_crossing_skew_parallel_and_touching() {
+  let segment = LineSegment::from_vec3s(glam::Vec3::ZERO, glam::vec3(2.0, 0.0, 0.0));
+  let closest = |p: glam::Vec3| segment.closest_point(&Point::from_vec3(p)).as_vec3();
+  assert!(close(closest(glam::vec3(1.0, 3.0, 0.0)), glam::vec3(1.0, 0.0, 0.0)));
+  assert!(close(closest(glam::vec3(-1.0, 1.0, 0.0)), glam::Vec3::ZERO));
+  assert!(close(closest(glam::vec3(5.0, 0.0, 0.0)), glam::vec3(2.0, 0.0, 0.0)));
+  let point = LineSegment::from_vec3s(glam::Vec3::ONE, glam::Vec3::ONE);
+  assert!(close(
+    point.closest_point(&Point::from_vec3(glam::Vec3::ZERO)).as_vec3(),
+    glam::Vec3::ONE
+  ));
+
+  let points = |other: LineSegment| {
+    let (p_1, p_2) = segment.closest_points_with_segment(&other);
+    (p_1.as_vec3(), p_2.as_vec3())
+  };
+  // Crossing, and passing over it
+  let (p_1, p_2) =
+    points(LineSegment::from_vec3s(glam::vec3(1.0, -1.0, 0.0), glam::vec3(1.0, 1.0, 0.0)));
+  assert!(close(p_1, glam::vec3(1.0, 0.0, 0.0)) && close(p_2, p_1));
+  let (p_1, p_2) =
+    points(LineSegment::from_vec3s(glam::vec3(0.5, -1.0, 2.0), glam::vec3(0.5, 1.0, 2.0)));
+  assert!(close(p_1, glam::vec3(0.5, 0.0, 0.0)) && close(p_2, glam::vec3(0.5, 0.0, 2.0)));
+  // Lines crossing past the end of one of them clamp to it
+  let (p_1, p_2) =
+    points(LineSegment::from_vec3s(glam::vec3(4.0, -1.0, 1.0), glam::vec3(4.0, 1.0, 1.0)));
+  assert!(close(p_1, glam::vec3(2.0, 0.0, 0.0)) && close(p_2, glam::vec3(4.0, 0.0, 1.0)));
+  // Parallel, overlapping and end to end
+  let (p_1, p_2) =
+    points(LineSegment::from_vec3s(glam::vec3(1.0, 1.0, 0.0), glam::vec3(3.0, 1.0, 0.0)));
+  assert!((p_1.distance(p_2) - 1.0).abs() < 1e-5 && p_1.x >= 1.0 - 1e-5);
+  let (p_1, p_2) =
+    points(LineSegment::from_vec3s(glam::vec3(3.0, 0.0, 0.0), glam::vec3(5.0, 0.0, 0.0)));
+  assert!(close(p_1, glam::vec3(2.0, 0.0, 0.0)) && close(p_2, glam::vec3(3.0, 0.0, 0.0)));
+  // Touching end to end, and one's end on the other's middle
+  let (p_1, p_2) =
+    points(LineSegment::from_vec3s(glam::vec3(2.0, 0.0, 0.0), glam::vec3(2.0, 3.0, 0.0)));
+  assert!(close(p_1, glam::vec3(2.0, 0.0, 0.0)) && close(p_2, p_1));
+  let (p_1, p_2) =
+    points(LineSegment::from_vec3s(glam::vec3(1.0, 0.0, 0.0), glam::vec3(1.0, 0.0, 3.0)));
+  assert!(close(p_1, glam::vec3(1.0, 0.0, 0.0)) && close(p_2, p_1));
+  // Collapsed to points
+  let (p_1, p_2) = points(point);
+  assert!(close(p_1, glam::vec3(1.0, 0.0, 0.0)) && close(p_2, glam::Vec3::ONE));
+}
+
+#[test]
+fn capsule_distances_apart_touching_overlapping_and_parallel() {
+  let capsule = |start: glam::Vec3, end: glam::Vec3, radius: f32| {
+    Capsule::new(LineSegment::from_vec3s(start, end), radius)
+  };
+  let upright = capsule(glam::Vec3::ZERO, glam::Vec3::Y * 2.0, 0.5);
+  // Side by side, parallel
+  let beside = capsule(glam::vec3(3.0, 0.0, 0.0), glam::vec3(3.0, 2.0, 0.0), 0.5);
+  assert!((upright.closest_distance(&beside) - 2.0).abs() < 1e-5);
+  // Crossing over it
+  let across = capsule(glam::vec3(-2.0, 1.0, 2.0), glam::vec3(2.0, 1.0, 2.0), 0.25);
+  assert!((upright.closest_distance(&across) - 1.25).abs() < 1e-5);
+  // Cap to cap, touching and overlapping
+  let on_top = capsule(glam::Vec3::Y * 3.0, glam::Vec3::Y * 5.0, 0.5);
+  assert!(upright.closest_distance(&on_top).abs() < 1e-5);
+  let sunk = on_top.displace(glam::Vec3::NEG_Y * 0.5);
+  assert!((upright.closest_distance(&sunk) + 0.5).abs() < 1e-5);
+  assert!((sunk.closest_distance(&upright) + 0.5).abs() < 1e-5);
+
+  let aabb = upright.get_aabb();
+  assert!(close(aabb.get_min().as_vec3(), glam::vec3(-0.5, -0.5, -0.5)));
+  assert!(close(aabb.get_max().as_vec3(), glam::vec3(0.5, 2.5, 0.5)));
+}
@@ -0,0 +1,41 @@
+// Everything runs in f32 coordinates measured from an origin that's kept in f64. Far from zero f32
+// steps get big enough to see, so the origin is moved along to wherever things are happening and
+// f32 positions stay small
+
+pub fn world_position(origin: glam::DVec3, local: glam::Vec3) -> glam::DVec3 {
+  origin + local.as_dvec3()
+}
+
+pub fn local_position(origin: glam::DVec3, world: glam::DVec3) -> glam::Vec3 {
+  (world - origin).as_vec3()
+}
+
+// How far to move the origin so focus is back near it, None while it's within max_distance.
+// Whole units so positions moved by it shift by exactly the same amount
+pub fn origin_shift(focus: glam::Vec3, max_distance: f32) -> Option<glam::Vec3> {
+  (focus.length() > max_distance).then(|| focus.round())
+}
+
+// Placement of something anywhere in the world, rotation and scale don't lose precision with
+// distance so only the position is f64
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorldTransform {
+  pub position: glam::DVec3,
+  pub rotation: glam::Quat,
+  pub scale: glam::Vec3,
+}
+
+impl WorldTransform {
+  pub fn from_local(origin: glam::DVec3, transform: glam::Mat4) -> Self {
+    let (scale, rotation, position) = transform.to_scale_rotation_translation();
+    Self { position: world_position(origin, position), rotation, scale }
+  }
+
+  pub fn local(&self, origin: glam::DVec3) -> glam::Mat4 {
+    glam::Mat4::from_scale_rotation_translation(
+      self.scale,
+      self.rotation,
+      local_position(origin, self.position),
+    )
+  }
+}
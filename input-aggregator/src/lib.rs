@@ -1,5 +1,36 @@
 use std::collections::HashMap;
-pub use winit::keyboard::{Key, NamedKey};
+use std::time::Instant;
+pub use winit::event::{Ime, MouseButton};
+pub use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+mod recording;
+#[cfg(test)]
+mod tests;
+mod text_input;
+
+pub use recording::{InputEvent, InputPlayback, InputRecording, TickInput};
+pub use text_input::TextInput;
+
+// Winit's names for the named keys gameplay and embedding hosts are likely to want, anything else
+// is a character key
+pub fn key_from_name(name: &str) -> Key {
+  let named = match name {
+    "Space" => NamedKey::Space,
+    "Enter" => NamedKey::Enter,
+    "Tab" => NamedKey::Tab,
+    "Escape" => NamedKey::Escape,
+    "Backspace" => NamedKey::Backspace,
+    "Shift" => NamedKey::Shift,
+    "Control" => NamedKey::Control,
+    "Alt" => NamedKey::Alt,
+    "ArrowUp" => NamedKey::ArrowUp,
+    "ArrowDown" => NamedKey::ArrowDown,
+    "ArrowLeft" => NamedKey::ArrowLeft,
+    "ArrowRight" => NamedKey::ArrowRight,
+    _ => return Key::Character(name.into()),
+  };
+  Key::Named(named)
+}
 
 
 #[derive(Debug, Clone, Copy)]
@@ -32,18 +63,83 @@ impl KeyState {
 
 pub struct InputAggregator {
   key_states: HashMap<winit::keyboard::Key, KeyState>,
+  mouse_button_states: HashMap<MouseButton, KeyState>,
+  // Normalized to 0..1 of the window, y going down
+  cursor_pos: Option<(f32, f32)>,
+  window_size: (u32, u32),
+  modifiers: ModifiersState,
+  // Set while a UI element has text focus, key presses go to it instead of the key states
+  text_input: Option<TextInput>,
+  // Opened on the first copy or paste, stays None if the platform has no clipboard
+  clipboard: Option<arboard::Clipboard>,
+  // When the oldest input since the last clear_key_states arrived from the window
+  input_received_at: Option<Instant>,
+  // Events since the last take_recorded, while recording
+  recorded: Option<Vec<InputEvent>>,
 }
 
 impl InputAggregator {
   pub fn new() -> Self {
-    InputAggregator { key_states: HashMap::new() }
+    InputAggregator {
+      key_states: HashMap::new(),
+      mouse_button_states: HashMap::new(),
+      cursor_pos: None,
+      window_size: (1, 1),
+      modifiers: ModifiersState::empty(),
+      text_input: None,
+      clipboard: None,
+      input_received_at: None,
+      recorded: None,
+    }
+  }
+
+  // Keeps the events from now on for take_recorded. Text typed into ui fields isn't kept, only
+  // what reaches the key states
+  pub fn start_recording(&mut self) {
+    self.recorded.get_or_insert_with(Vec::new);
+  }
+
+  pub fn take_recorded(&mut self) -> Vec<InputEvent> {
+    self.recorded.as_mut().map(std::mem::take).unwrap_or_default()
+  }
+
+  fn record(&mut self, event: InputEvent) {
+    if let Some(recorded) = self.recorded.as_mut() {
+      recorded.push(event);
+    }
+  }
+
+  // Keys without a name or text can't be written out, nothing binds them anyway
+  fn record_key(&mut self, key: &Key, pressed: bool) {
+    if !matches!(key, Key::Named(_) | Key::Character(_)) {
+      return;
+    }
+    self.record(match pressed {
+      true => InputEvent::KeyPressed(key.clone()),
+      false => InputEvent::KeyReleased(key.clone()),
+    });
+  }
+
+  pub fn input_received_at(&self) -> Option<Instant> {
+    self.input_received_at
+  }
+
+  // With the time the window got the event, not when it got here
+  pub fn record_input(&mut self, received_at: Instant) {
+    self.input_received_at = Some(self.input_received_at.unwrap_or(received_at).min(received_at));
   }
 
   pub fn is_key_pressed(&self, key: winit::keyboard::Key) -> KeyState {
     self.key_states.get(&key).cloned().unwrap_or(KeyState::Idle)
   }
 
+  // Every key that has been pressed, including ones back to idle
+  pub fn key_states(&self) -> impl Iterator<Item = (&Key, KeyState)> {
+    self.key_states.iter().map(|(key, state)| (key, *state))
+  }
+
   pub fn update_key_pressed(&mut self, key: winit::keyboard::Key) {
+    self.record_key(&key, true);
     self
       .key_states
       .entry(key)
@@ -52,6 +148,7 @@ impl InputAggregator {
   }
 
   pub fn update_key_released(&mut self, key: winit::keyboard::Key) {
+    self.record_key(&key, false);
     self
       .key_states
       .entry(key)
@@ -59,8 +156,151 @@ impl InputAggregator {
       .or_insert(KeyState::Released);
   }
 
+  pub fn is_mouse_pressed(&self, button: MouseButton) -> KeyState {
+    self.mouse_button_states.get(&button).cloned().unwrap_or(KeyState::Idle)
+  }
+
+  pub fn update_mouse_pressed(&mut self, button: MouseButton) {
+    self.record(InputEvent::MousePressed(button));
+    self.mouse_button_states.insert(button, KeyState::Pressed);
+  }
+
+  pub fn update_mouse_released(&mut self, button: MouseButton) {
+    self.record(InputEvent::MouseReleased(button));
+    self.mouse_button_states.insert(button, KeyState::Released);
+  }
+
+  pub fn cursor_pos(&self) -> Option<(f32, f32)> {
+    self.cursor_pos
+  }
+
+  pub fn window_size(&self) -> (u32, u32) {
+    self.window_size
+  }
+
+  pub fn update_cursor_pos(&mut self, x: f64, y: f64) {
+    self.set_cursor_pos(Some((
+      (x / self.window_size.0.max(1) as f64) as f32,
+      (y / self.window_size.1.max(1) as f64) as f32,
+    )));
+  }
+
+  pub fn update_cursor_left(&mut self) {
+    self.set_cursor_pos(None);
+  }
+
+  fn set_cursor_pos(&mut self, cursor_pos: Option<(f32, f32)>) {
+    self.record(match cursor_pos {
+      Some((x, y)) => InputEvent::CursorMoved(x, y),
+      None => InputEvent::CursorLeft,
+    });
+    self.cursor_pos = cursor_pos;
+  }
+
+  pub fn update_window_size(&mut self, width: u32, height: u32) {
+    self.window_size = (width, height);
+  }
+
+  pub fn modifiers(&self) -> ModifiersState {
+    self.modifiers
+  }
+
+  pub fn update_modifiers(&mut self, modifiers: ModifiersState) {
+    self.record(InputEvent::Modifiers(modifiers.bits()));
+    self.modifiers = modifiers;
+  }
+
+  pub fn is_text_input_active(&self) -> bool {
+    self.text_input.is_some()
+  }
+
+  pub fn text_input(&self) -> Option<&TextInput> {
+    self.text_input.as_ref()
+  }
+
+  // Called by whatever takes text focus, replaces any text input already going on
+  pub fn begin_text_input(&mut self, text: &str) {
+    self.text_input = Some(TextInput::new(text));
+  }
+
+  // Gives back the entered text when a text input was active
+  pub fn end_text_input(&mut self) -> Option<String> {
+    self.text_input.take().map(|mut text_input| text_input.take_text())
+  }
+
+  fn clipboard(clipboard: &mut Option<arboard::Clipboard>) -> Option<&mut arboard::Clipboard> {
+    if clipboard.is_none() {
+      *clipboard = arboard::Clipboard::new().inspect_err(|e| eprintln!("no clipboard: {e}")).ok();
+    }
+    clipboard.as_mut()
+  }
+
+  // Edits the active text input with a key press, text is what the key typed. Returns whether
+  // the press was used up, Enter and Escape never are so the focused UI can submit or close
+  pub fn update_text_key(&mut self, key: &Key, text: Option<&str>) -> bool {
+    let Some(text_input) = self.text_input.as_mut() else { return false };
+    // Keys go to the IME while it is composing
+    if !text_input.preedit().is_empty() {
+      return true;
+    }
+    let (ctrl, shift) = (self.modifiers.control_key(), self.modifiers.shift_key());
+    match key {
+      Key::Named(NamedKey::Enter | NamedKey::Escape) => return false,
+      Key::Named(NamedKey::ArrowLeft) if ctrl => text_input.move_word_left(shift),
+      Key::Named(NamedKey::ArrowLeft) => text_input.move_left(shift),
+      Key::Named(NamedKey::ArrowRight) if ctrl => text_input.move_word_right(shift),
+      Key::Named(NamedKey::ArrowRight) => text_input.move_right(shift),
+      Key::Named(NamedKey::Home) => text_input.move_home(shift),
+      Key::Named(NamedKey::End) => text_input.move_end(shift),
+      Key::Named(NamedKey::Backspace) => text_input.backspace(),
+      Key::Named(NamedKey::Delete) => text_input.delete(),
+      Key::Character(c) if ctrl => match c.to_lowercase().as_str() {
+        "a" => text_input.select_all(),
+        "c" | "x" => {
+          if let Some(selected) = text_input.selected_text().map(|x| x.to_string()) {
+            if let Some(clipboard) = Self::clipboard(&mut self.clipboard) {
+              let _ = clipboard.set_text(selected).inspect_err(|e| eprintln!("at copying: {e}"));
+            }
+            if c.eq_ignore_ascii_case("x") {
+              text_input.delete_selection();
+            }
+          }
+        }
+        "v" => {
+          if let Some(clipboard) = Self::clipboard(&mut self.clipboard) {
+            match clipboard.get_text() {
+              Ok(pasted) => text_input.insert_str(&pasted),
+              Err(e) => eprintln!("at pasting: {e}"),
+            }
+          }
+        }
+        _ => {}
+      },
+      _ => {
+        if let Some(text) = text {
+          text_input.insert_str(text);
+        }
+      }
+    }
+    true
+  }
+
+  pub fn update_ime(&mut self, ime: Ime) {
+    let Some(text_input) = self.text_input.as_mut() else { return };
+    match ime {
+      Ime::Enabled => {}
+      Ime::Preedit(preedit, preedit_cursor) => text_input.set_preedit(preedit, preedit_cursor),
+      Ime::Commit(committed) => {
+        text_input.clear_preedit();
+        text_input.insert_str(&committed);
+      }
+      Ime::Disabled => text_input.clear_preedit(),
+    }
+  }
+
   pub fn clear_key_states(&mut self) {
-    for (_, v) in self.key_states.iter_mut() {
+    self.input_received_at = None;
+    for v in self.key_states.values_mut().chain(self.mouse_button_states.values_mut()) {
       *v = match v {
         KeyState::Idle => KeyState::Idle,
         KeyState::Pressed => KeyState::Held,
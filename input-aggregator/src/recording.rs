@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use winit::event::MouseButton;
+use winit::keyboard::{Key, ModifiersState};
+
+use crate::InputAggregator;
+
+// A change the window reported, the way the simulation sees it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+  KeyPressed(Key),
+  KeyReleased(Key),
+  MousePressed(MouseButton),
+  MouseReleased(MouseButton),
+  // Normalized to the window like cursor_pos
+  CursorMoved(f32, f32),
+  CursorLeft,
+  // ModifiersState bits
+  Modifiers(u32),
+}
+
+// Input that reached the simulation in one tick
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TickInput {
+  pub tick: u64,
+  pub events: Vec<InputEvent>,
+}
+
+// Input of a run tick by tick, ticks nothing happened in are left out
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputRecording {
+  // Of the scene it was recorded in, the same input plays out differently in another one
+  pub seed: u64,
+  // How long the run was, counting ticks without input
+  pub ticks: u64,
+  #[serde(default)]
+  pub inputs: Vec<TickInput>,
+}
+
+impl InputRecording {
+  pub fn new(seed: u64) -> Self {
+    Self { seed, ..Default::default() }
+  }
+
+  pub fn record_tick(&mut self, events: Vec<InputEvent>) {
+    if !events.is_empty() {
+      self.inputs.push(TickInput { tick: self.ticks, events });
+    }
+    self.ticks += 1;
+  }
+
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let text = std::fs::read_to_string(path)
+      .map_err(|e| format!("at reading input recording {}: {e}", path.display()))?;
+    toml::from_str(&text).map_err(|e| format!("at parsing input recording {}: {e}", path.display()))
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), String> {
+    let text = toml::to_string(self).map_err(|e| format!("at serializing input recording: {e}"))?;
+    std::fs::write(path, text)
+      .map_err(|e| format!("at writing input recording {}: {e}", path.display()))
+  }
+}
+
+// Feeds a recording back tick by tick, in place of the window
+pub struct InputPlayback {
+  recording: InputRecording,
+  tick: u64,
+  next_input: usize,
+}
+
+impl InputPlayback {
+  pub fn new(recording: InputRecording) -> Self {
+    Self { recording, tick: 0, next_input: 0 }
+  }
+
+  pub fn seed(&self) -> u64 {
+    self.recording.seed
+  }
+
+  // Every recorded tick has been played
+  pub fn is_finished(&self) -> bool {
+    self.tick >= self.recording.ticks
+  }
+
+  // Applies the next tick's events, call once per tick before reading the inputs
+  pub fn play_tick(&mut self, inputs: &mut InputAggregator) {
+    while let Some(tick_input) = self.recording.inputs.get(self.next_input) {
+      if tick_input.tick > self.tick {
+        break;
+      }
+      for event in tick_input.events.iter() {
+        inputs.apply_event(event);
+      }
+      self.next_input += 1;
+    }
+    self.tick += 1;
+  }
+}
+
+impl InputAggregator {
+  // Replays an event like the window sending it
+  pub fn apply_event(&mut self, event: &InputEvent) {
+    match event {
+      InputEvent::KeyPressed(key) => self.update_key_pressed(key.clone()),
+      InputEvent::KeyReleased(key) => self.update_key_released(key.clone()),
+      InputEvent::MousePressed(button) => self.update_mouse_pressed(*button),
+      InputEvent::MouseReleased(button) => self.update_mouse_released(*button),
+      InputEvent::CursorMoved(x, y) => self.set_cursor_pos(Some((*x, *y))),
+      InputEvent::CursorLeft => self.update_cursor_left(),
+      InputEvent::Modifiers(bits) => {
+        self.update_modifiers(ModifiersState::from_bits_truncate(*bits))
+      }
+    }
+  }
+}
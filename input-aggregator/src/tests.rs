@@ -0,0 +1,73 @@
+use winit::event::MouseButton;
+use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+use crate::{InputAggregator, InputEvent, InputPlayback, InputRecording};
+
+// Records a few ticks the way the simulation does, taking the events at the start of each
+fn record_ticks(ticks: &[&dyn Fn(&mut InputAggregator)]) -> InputRecording {
+  let mut inputs = InputAggregator::new();
+  inputs.update_window_size(200, 100);
+  inputs.start_recording();
+  let mut recording = InputRecording::new(7);
+  for tick in ticks {
+    tick(&mut inputs);
+    recording.record_tick(inputs.take_recorded());
+    inputs.clear_key_states();
+  }
+  recording
+}
+
+#[test]
+fn recordings_leave_out_ticks_without_input() {
+  let recording = record_ticks(&[
+    &|inputs| inputs.update_key_pressed(Key::Named(NamedKey::Space)),
+    &|_| {},
+    &|_| {},
+    &|inputs| inputs.update_cursor_pos(50.0, 25.0),
+  ]);
+  assert_eq!(recording.ticks, 4);
+  let ticks = recording.inputs.iter().map(|x| x.tick).collect::<Vec<_>>();
+  assert_eq!(ticks, vec![0, 3]);
+  assert_eq!(recording.inputs[1].events, vec![InputEvent::CursorMoved(0.25, 0.25)]);
+}
+
+#[test]
+fn recordings_play_back_the_same_after_saving() {
+  let recording = record_ticks(&[
+    &|inputs| {
+      inputs.update_modifiers(ModifiersState::SHIFT);
+      inputs.update_key_pressed(Key::Character("w".into()));
+    },
+    &|inputs| inputs.update_mouse_pressed(MouseButton::Left),
+    &|_| {},
+    &|inputs| {
+      inputs.update_key_released(Key::Character("w".into()));
+      inputs.update_cursor_left();
+    },
+  ]);
+  let path = std::env::temp_dir().join(format!("input_recording_{}.toml", std::process::id()));
+  recording.save(&path).unwrap();
+  let loaded = InputRecording::load(&path);
+  let _ = std::fs::remove_file(&path);
+  let loaded = loaded.unwrap();
+  assert_eq!(loaded, recording);
+
+  let mut playback = InputPlayback::new(loaded);
+  let mut inputs = InputAggregator::new();
+  let w = Key::Character("w".into());
+  playback.play_tick(&mut inputs);
+  assert!(inputs.is_key_pressed(w.clone()).is_just_pressed());
+  assert!(inputs.modifiers().shift_key());
+  inputs.clear_key_states();
+  playback.play_tick(&mut inputs);
+  assert!(inputs.is_mouse_pressed(MouseButton::Left).is_just_pressed());
+  assert!(inputs.is_key_pressed(w.clone()).is_pressed());
+  inputs.clear_key_states();
+  playback.play_tick(&mut inputs);
+  inputs.clear_key_states();
+  assert!(!playback.is_finished());
+  playback.play_tick(&mut inputs);
+  assert!(!inputs.is_key_pressed(w).is_pressed());
+  assert_eq!(inputs.cursor_pos(), None);
+  assert!(playback.is_finished());
+}
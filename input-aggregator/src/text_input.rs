@@ -0,0 +1,171 @@
+// Single line text being edited. Offsets are in bytes and always on char boundaries
+#[derive(Debug, Clone, Default)]
+pub struct TextInput {
+  text: String,
+  cursor: usize,
+  // Other end of the selection from the cursor
+  anchor: Option<usize>,
+  // IME composition that isn't committed yet, shown at the cursor but not part of the text
+  preedit: String,
+  preedit_cursor: Option<(usize, usize)>,
+}
+
+impl TextInput {
+  pub fn new(text: &str) -> Self {
+    let mut text_input = Self::default();
+    text_input.insert_str(text);
+    text_input
+  }
+
+  pub fn text(&self) -> &str {
+    &self.text
+  }
+
+  pub fn cursor(&self) -> usize {
+    self.cursor
+  }
+
+  pub fn preedit(&self) -> &str {
+    &self.preedit
+  }
+
+  // Cursor or selection inside the preedit text, as reported by the IME
+  pub fn preedit_cursor(&self) -> Option<(usize, usize)> {
+    self.preedit_cursor
+  }
+
+  // Start and end of the selection, None when nothing is selected
+  pub fn selection(&self) -> Option<(usize, usize)> {
+    let anchor = self.anchor.filter(|anchor| *anchor != self.cursor)?;
+    Some((anchor.min(self.cursor), anchor.max(self.cursor)))
+  }
+
+  pub fn selected_text(&self) -> Option<&str> {
+    self.selection().map(|(start, end)| &self.text[start..end])
+  }
+
+  // Text with the preedit spliced in at the cursor, for drawing
+  pub fn display_text(&self) -> String {
+    let mut display_text = self.text.clone();
+    display_text.insert_str(self.cursor, &self.preedit);
+    display_text
+  }
+
+  fn prev_boundary(&self, idx: usize) -> usize {
+    self.text[..idx].char_indices().next_back().map(|(i, _)| i).unwrap_or(0)
+  }
+
+  fn next_boundary(&self, idx: usize) -> usize {
+    idx + self.text[idx..].chars().next().map(|c| c.len_utf8()).unwrap_or(0)
+  }
+
+  // Start of the word before idx, skipping whitespace right before it
+  fn prev_word_boundary(&self, idx: usize) -> usize {
+    let before = self.text[..idx].trim_end();
+    before.rfind(char::is_whitespace).map(|i| self.next_boundary(i)).unwrap_or(0)
+  }
+
+  // End of the word after idx, skipping whitespace right after it
+  fn next_word_boundary(&self, idx: usize) -> usize {
+    let after = &self.text[idx..];
+    let word_start = after.len() - after.trim_start().len();
+    let word_len = after[word_start..].find(char::is_whitespace).unwrap_or(after.len() - word_start);
+    idx + word_start + word_len
+  }
+
+  fn move_cursor(&mut self, new_cursor: usize, select: bool) {
+    if select {
+      self.anchor.get_or_insert(self.cursor);
+    } else {
+      self.anchor = None;
+    }
+    self.cursor = new_cursor;
+  }
+
+  pub fn move_left(&mut self, select: bool) {
+    match self.selection() {
+      // Collapses the selection to its start, like most text fields
+      Some((start, _)) if !select => self.move_cursor(start, false),
+      _ => self.move_cursor(self.prev_boundary(self.cursor), select),
+    }
+  }
+
+  pub fn move_right(&mut self, select: bool) {
+    match self.selection() {
+      Some((_, end)) if !select => self.move_cursor(end, false),
+      _ => self.move_cursor(self.next_boundary(self.cursor), select),
+    }
+  }
+
+  pub fn move_word_left(&mut self, select: bool) {
+    self.move_cursor(self.prev_word_boundary(self.cursor), select);
+  }
+
+  pub fn move_word_right(&mut self, select: bool) {
+    self.move_cursor(self.next_word_boundary(self.cursor), select);
+  }
+
+  pub fn move_home(&mut self, select: bool) {
+    self.move_cursor(0, select);
+  }
+
+  pub fn move_end(&mut self, select: bool) {
+    self.move_cursor(self.text.len(), select);
+  }
+
+  pub fn select_all(&mut self) {
+    self.anchor = Some(0);
+    self.cursor = self.text.len();
+  }
+
+  // Returns whether anything was selected
+  pub fn delete_selection(&mut self) -> bool {
+    let Some((start, end)) = self.selection() else { return false };
+    self.text.replace_range(start..end, "");
+    self.cursor = start;
+    self.anchor = None;
+    true
+  }
+
+  // Replaces the selection. Control characters are dropped, so pasted newlines don't end up in
+  // the line
+  pub fn insert_str(&mut self, text: &str) {
+    self.delete_selection();
+    let text = text.chars().filter(|c| !c.is_control()).collect::<String>();
+    self.text.insert_str(self.cursor, &text);
+    self.cursor += text.len();
+  }
+
+  pub fn backspace(&mut self) {
+    if !self.delete_selection() && self.cursor > 0 {
+      let prev = self.prev_boundary(self.cursor);
+      self.text.replace_range(prev..self.cursor, "");
+      self.cursor = prev;
+    }
+  }
+
+  pub fn delete(&mut self) {
+    if !self.delete_selection() && self.cursor < self.text.len() {
+      let next = self.next_boundary(self.cursor);
+      self.text.replace_range(self.cursor..next, "");
+    }
+  }
+
+  pub fn set_preedit(&mut self, preedit: String, preedit_cursor: Option<(usize, usize)>) {
+    self.preedit = preedit;
+    self.preedit_cursor = preedit_cursor;
+  }
+
+  pub fn clear_preedit(&mut self) {
+    self.preedit.clear();
+    self.preedit_cursor = None;
+  }
+
+  // Empties the input and returns what was in it, for submitting a line
+  pub fn take_text(&mut self) -> String {
+    self.cursor = 0;
+    self.anchor = None;
+    self.clear_preedit();
+    std::mem::take(&mut self.text)
+  }
+}
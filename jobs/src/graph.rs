@@ -0,0 +1,164 @@
+use std::{
+  panic::{catch_unwind, AssertUnwindSafe},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+  },
+};
+
+use crate::{panic_message, JobPool, Scope};
+
+type GraphJob<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JobId(usize);
+
+struct GraphNode<'a> {
+  name: &'static str,
+  job: GraphJob<'a>,
+  dependents: Vec<usize>,
+  dependency_count: usize,
+}
+
+// Jobs making up one frame of work, each job starts once all the jobs it depends on finished.
+// Jobs may borrow anything that outlives the graph since run blocks until everything is done
+pub struct JobGraph<'a> {
+  nodes: Vec<GraphNode<'a>>,
+}
+
+struct GraphRun<'a> {
+  names: Vec<&'static str>,
+  jobs: Vec<Mutex<Option<GraphJob<'a>>>>,
+  dependents: Vec<Vec<usize>>,
+  remaining_dependencies: Vec<AtomicUsize>,
+  first_error: Mutex<Option<String>>,
+}
+
+impl<'a> GraphRun<'a> {
+  fn launch<'scope, 'env>(&'scope self, scope: &'scope Scope<'scope, 'env>, idx: usize)
+  where
+    'a: 'scope,
+  {
+    scope.spawn(move || {
+      let Some(job) = self.jobs[idx].lock().ok().and_then(|mut job| job.take()) else { return };
+      if let Err(payload) = catch_unwind(AssertUnwindSafe(job)) {
+        // Dependents of a failed job never run
+        if let Ok(mut first_error) = self.first_error.lock() {
+          first_error.get_or_insert(format!(
+            "at running job {}: {}",
+            self.names[idx],
+            panic_message(payload.as_ref())
+          ));
+        }
+        return;
+      }
+      for dependent in self.dependents[idx].iter() {
+        if self.remaining_dependencies[*dependent].fetch_sub(1, Ordering::SeqCst) == 1 {
+          self.launch(scope, *dependent);
+        }
+      }
+    });
+  }
+}
+
+impl<'a> JobGraph<'a> {
+  pub fn new() -> Self {
+    Self { nodes: vec![] }
+  }
+
+  pub fn len(&self) -> usize {
+    self.nodes.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.nodes.is_empty()
+  }
+
+  pub fn add_job(&mut self, name: &'static str, job: impl FnOnce() + Send + 'a) -> JobId {
+    self.nodes.push(GraphNode {
+      name,
+      job: Box::new(job),
+      dependents: vec![],
+      dependency_count: 0,
+    });
+    JobId(self.nodes.len() - 1)
+  }
+
+  pub fn add_dependency(&mut self, job: JobId, depends_on: JobId) -> Result<(), String> {
+    if job.0 >= self.nodes.len() || depends_on.0 >= self.nodes.len() {
+      return Err(format!("at adding job dependency: invalid job id {job:?} or {depends_on:?}"));
+    }
+    if job == depends_on {
+      return Err(format!(
+        "at adding job dependency: {} depends on itself",
+        self.nodes[job.0].name
+      ));
+    }
+    if self.nodes[depends_on.0].dependents.contains(&job.0) {
+      return Ok(());
+    }
+    self.nodes[depends_on.0].dependents.push(job.0);
+    self.nodes[job.0].dependency_count += 1;
+    Ok(())
+  }
+
+  fn check_acyclic(&self) -> Result<(), String> {
+    let mut remaining = self.nodes.iter().map(|node| node.dependency_count).collect::<Vec<_>>();
+    let mut ready =
+      (0..self.nodes.len()).filter(|i| self.nodes[*i].dependency_count == 0).collect::<Vec<_>>();
+    let mut visited_count = 0;
+    while let Some(idx) = ready.pop() {
+      visited_count += 1;
+      for dependent in self.nodes[idx].dependents.iter() {
+        remaining[*dependent] -= 1;
+        if remaining[*dependent] == 0 {
+          ready.push(*dependent);
+        }
+      }
+    }
+    if visited_count == self.nodes.len() {
+      return Ok(());
+    }
+    let cycle_names = (0..self.nodes.len())
+      .filter(|i| remaining[*i] > 0)
+      .map(|i| self.nodes[i].name)
+      .collect::<Vec<_>>();
+    Err(format!("at checking job graph: dependency cycle between {cycle_names:?}"))
+  }
+
+  pub fn run(self, pool: &JobPool) -> Result<(), String> {
+    self.check_acyclic()?;
+    let roots =
+      (0..self.nodes.len()).filter(|i| self.nodes[*i].dependency_count == 0).collect::<Vec<_>>();
+    let mut graph_run = GraphRun {
+      names: vec![],
+      jobs: vec![],
+      dependents: vec![],
+      remaining_dependencies: vec![],
+      first_error: Mutex::new(None),
+    };
+    for node in self.nodes {
+      graph_run.names.push(node.name);
+      graph_run.jobs.push(Mutex::new(Some(node.job)));
+      graph_run.dependents.push(node.dependents);
+      graph_run.remaining_dependencies.push(AtomicUsize::new(node.dependency_count));
+    }
+
+    pool.scope(|s| {
+      for root in roots {
+        graph_run.launch(s, root);
+      }
+    })?;
+    match graph_run.first_error.into_inner() {
+      Ok(None) => Ok(()),
+      Ok(Some(e)) => Err(e),
+      Err(e) => Err(format!("at getting job graph error lock: {e}")),
+    }
+  }
+}
+
+impl Default for JobGraph<'_> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -0,0 +1,437 @@
+use std::{
+  any::Any,
+  cell::Cell,
+  collections::VecDeque,
+  marker::PhantomData,
+  panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+  sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError,
+  },
+  thread::JoinHandle,
+  time::Duration,
+};
+
+pub use graph::{JobGraph, JobId};
+pub use triple_buffer::{triple_buffer, TripleBufferReader, TripleBufferWriter};
+
+mod graph;
+#[cfg(test)]
+mod tests;
+mod triple_buffer;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// Jobs run by threads waiting in help_until nest on their stack, past this many they only run
+// their own forks, which nest no deeper than the work forking them
+const MAX_HELP_DEPTH: usize = 4;
+
+static NEXT_POOL_ID: AtomicUsize = AtomicUsize::new(1);
+static GLOBAL_POOL: OnceLock<JobPool> = OnceLock::new();
+
+thread_local! {
+  // (pool id, worker index) of the pool owning the current thread
+  static CURRENT_WORKER: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+  // Jobs help_until is running on the current thread, one inside the other
+  static HELP_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+  payload
+    .downcast_ref::<&str>()
+    .map(|x| x.to_string())
+    .or(payload.downcast_ref::<String>().cloned())
+    .unwrap_or("unknown panic".to_string())
+}
+
+struct PoolShared {
+  pool_id: usize,
+  injector: Mutex<VecDeque<Job>>,
+  local_queues: Vec<Mutex<VecDeque<Job>>>,
+  queued_count: AtomicUsize,
+  sleep_lock: Mutex<()>,
+  // Idle workers and threads waiting in help_until sleep on it, woken by new jobs. Waiting threads
+  // are also woken by jobs finishing
+  wake: Condvar,
+  waiting_helpers: AtomicUsize,
+  shutdown: AtomicBool,
+}
+
+impl PoolShared {
+  fn current_worker(&self) -> Option<usize> {
+    CURRENT_WORKER.with(|worker| match worker.get() {
+      Some((pool_id, worker_idx)) if pool_id == self.pool_id => Some(worker_idx),
+      _ => None,
+    })
+  }
+
+  fn push(&self, job: Job) {
+    self.queued_count.fetch_add(1, Ordering::SeqCst);
+    // Workers push to their own queue so forked jobs stay on the same core unless stolen
+    let queue = match self.current_worker() {
+      Some(worker_idx) => &self.local_queues[worker_idx],
+      None => &self.injector,
+    };
+    match queue.lock() {
+      Ok(mut queue) => queue.push_back(job),
+      Err(e) => e.into_inner().push_back(job),
+    }
+    // Taking the lock orders the notify after a worker's empty check, so wakeups aren't lost
+    let _sleep_guard = self.sleep_lock.lock();
+    // A waiting thread too deep to take it could get the only wakeup
+    match self.waiting_helpers.load(Ordering::SeqCst) {
+      0 => self.wake.notify_one(),
+      _ => self.wake.notify_all(),
+    }
+  }
+
+  // Never fails, a job that panicked holding the lock leaves nothing behind it to protect
+  fn sleep_guard(&self) -> MutexGuard<'_, ()> {
+    self.sleep_lock.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  // After a job finishes, for threads in help_until waiting on it
+  fn notify_finished(&self) {
+    if self.waiting_helpers.load(Ordering::SeqCst) > 0 {
+      let _sleep_guard = self.sleep_guard();
+      self.wake.notify_all();
+    }
+  }
+
+  fn pop_from(queue: &Mutex<VecDeque<Job>>, newest: bool) -> Option<Job> {
+    let mut queue = queue.lock().ok()?;
+    if newest {
+      queue.pop_back()
+    } else {
+      queue.pop_front()
+    }
+  }
+
+  // Without steal only the newest job the current worker forked
+  fn find_job(&self, steal: bool) -> Option<Job> {
+    let worker_idx = self.current_worker();
+    let own_job = worker_idx.and_then(|idx| Self::pop_from(&self.local_queues[idx], true));
+    let job = own_job
+      .or_else(|| steal.then(|| Self::pop_from(&self.injector, false)).flatten())
+      .or_else(|| {
+        if !steal {
+          return None;
+        }
+        // Steal the oldest job of another worker, starting after our own index to spread thieves
+        let start = worker_idx.map(|x| x + 1).unwrap_or(0);
+        (0..self.local_queues.len())
+          .map(|i| (start + i) % self.local_queues.len())
+          .filter(|i| Some(*i) != worker_idx)
+          .find_map(|i| Self::pop_from(&self.local_queues[i], false))
+      });
+    if job.is_some() {
+      self.queued_count.fetch_sub(1, Ordering::SeqCst);
+    }
+    job
+  }
+
+  // Runs queued jobs on the calling thread until done returns true, sleeping while there are none.
+  // Only returns once done does, Scope relies on it
+  fn help_until(&self, done: impl Fn() -> bool) {
+    let depth = HELP_DEPTH.get();
+    let steal = depth < MAX_HELP_DEPTH;
+    while !done() {
+      if let Some(job) = self.find_job(steal) {
+        HELP_DEPTH.set(depth + 1);
+        job();
+        HELP_DEPTH.set(depth);
+        continue;
+      }
+      let sleep_guard = self.sleep_guard();
+      // Counted before checking done, so a job finishing after the check sees a waiter and takes
+      // the lock to notify, which it only gets once this thread is waiting
+      self.waiting_helpers.fetch_add(1, Ordering::SeqCst);
+      let nothing_to_take = !steal || self.queued_count.load(Ordering::SeqCst) == 0;
+      if !done() && nothing_to_take {
+        drop(self.wake.wait(sleep_guard));
+      }
+      self.waiting_helpers.fetch_sub(1, Ordering::SeqCst);
+    }
+  }
+
+  fn worker_loop(&self, worker_idx: usize) {
+    CURRENT_WORKER.with(|worker| worker.set(Some((self.pool_id, worker_idx))));
+    loop {
+      if let Some(job) = self.find_job(true) {
+        job();
+        continue;
+      }
+      let Ok(sleep_guard) = self.sleep_lock.lock() else { return };
+      if self.shutdown.load(Ordering::SeqCst) {
+        return;
+      }
+      if self.queued_count.load(Ordering::SeqCst) == 0 {
+        let _ = self.wake.wait_timeout(sleep_guard, Duration::from_millis(100));
+      }
+    }
+  }
+}
+
+struct JobSlot<T> {
+  result: Mutex<Option<Result<T, String>>>,
+  finished: AtomicBool,
+}
+
+impl<T> JobSlot<T> {
+  fn new() -> Self {
+    Self { result: Mutex::new(None), finished: AtomicBool::new(false) }
+  }
+
+  fn complete(&self, result: Result<T, String>) {
+    match self.result.lock() {
+      Ok(mut slot) => *slot = Some(result),
+      Err(e) => *e.into_inner() = Some(result),
+    }
+    self.finished.store(true, Ordering::SeqCst);
+  }
+}
+
+pub struct JobHandle<T> {
+  slot: Arc<JobSlot<T>>,
+  shared: Arc<PoolShared>,
+}
+
+impl<T> JobHandle<T> {
+  pub fn is_finished(&self) -> bool {
+    self.slot.finished.load(Ordering::SeqCst)
+  }
+
+  // Helps with other queued jobs while waiting, so it is fine to wait from inside a job
+  pub fn wait(self) -> Result<T, String> {
+    self.shared.help_until(|| self.slot.finished.load(Ordering::SeqCst));
+    self
+      .slot
+      .result
+      .lock()
+      .map_err(|e| format!("at getting job result lock: {e}"))?
+      .take()
+      .unwrap_or(Err("job result already taken".to_string()))
+  }
+}
+
+// Jobs spawned on a scope may borrow from outside it, JobPool::scope waits for all of them
+pub struct Scope<'scope, 'env: 'scope> {
+  shared: Arc<PoolShared>,
+  pending: AtomicUsize,
+  panic_message: Mutex<Option<String>>,
+  scope: PhantomData<&'scope mut &'scope ()>,
+  env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+  pub fn spawn(&'scope self, f: impl FnOnce() + Send + 'scope) {
+    self.pending.fetch_add(1, Ordering::SeqCst);
+    let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+      let shared = self.shared.clone();
+      if let Err(payload) = catch_unwind(AssertUnwindSafe(f)) {
+        if let Ok(mut first_panic) = self.panic_message.lock() {
+          first_panic.get_or_insert(panic_message(payload.as_ref()));
+        }
+      }
+      // The last use of anything borrowed, the scope may be gone right after
+      self.pending.fetch_sub(1, Ordering::SeqCst);
+      shared.notify_finished();
+    });
+    // SAFETY: the job only borrows the scope and what f borrows, all of which outlive 'scope.
+    // JobPool::scope is the only way to get a Scope and it doesn't return, or unwind, before
+    // pending is back to zero: f(&scope) runs under catch_unwind, help_until only returns once
+    // its condition holds, and the panic is resumed after it. Jobs decrement pending as their
+    // last step, after f and its captures are dropped by the call, so by the time pending is zero
+    // no job touches 'scope data again. Jobs panicking are caught above so pending always drops
+    let job = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+    self.shared.push(job);
+  }
+}
+
+pub struct JobPool {
+  shared: Arc<PoolShared>,
+  workers: Vec<JoinHandle<()>>,
+  dedicated_threads: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl JobPool {
+  pub fn new(name: &str, thread_count: usize) -> Result<Self, String> {
+    let thread_count = thread_count.max(1);
+    let shared = Arc::new(PoolShared {
+      pool_id: NEXT_POOL_ID.fetch_add(1, Ordering::Relaxed),
+      injector: Mutex::new(VecDeque::new()),
+      local_queues: (0..thread_count).map(|_| Mutex::new(VecDeque::new())).collect(),
+      queued_count: AtomicUsize::new(0),
+      sleep_lock: Mutex::new(()),
+      wake: Condvar::new(),
+      waiting_helpers: AtomicUsize::new(0),
+      shutdown: AtomicBool::new(false),
+    });
+    let workers = (0..thread_count)
+      .map(|worker_idx| {
+        let worker_shared = shared.clone();
+        std::thread::Builder::new()
+          .name(format!("{name} worker {worker_idx}"))
+          .spawn(move || worker_shared.worker_loop(worker_idx))
+          .map_err(|e| format!("at spawning job worker thread {worker_idx}: {e}"))
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    Ok(Self { shared, workers, dedicated_threads: Mutex::new(vec![]) })
+  }
+
+  pub fn thread_count(&self) -> usize {
+    self.workers.len()
+  }
+
+  pub fn spawn<T: Send + 'static>(&self, f: impl FnOnce() -> T + Send + 'static) -> JobHandle<T> {
+    let slot = Arc::new(JobSlot::new());
+    let job_slot = slot.clone();
+    // Weak so jobs still queued when the pool drops don't keep it alive
+    let shared = Arc::downgrade(&self.shared);
+    self.shared.push(Box::new(move || {
+      let result = catch_unwind(AssertUnwindSafe(f))
+        .map_err(|e| format!("at running job: {}", panic_message(e.as_ref())));
+      job_slot.complete(result);
+      if let Some(shared) = shared.upgrade() {
+        shared.notify_finished();
+      }
+    }));
+    JobHandle { slot, shared: self.shared.clone() }
+  }
+
+  // For work that lives as long as the engine (like the render loop) and would otherwise block a
+  // worker forever. The thread is joined when the pool is dropped
+  pub fn spawn_dedicated<T: Send + 'static>(
+    &self,
+    name: &str,
+    f: impl FnOnce() -> T + Send + 'static,
+  ) -> Result<JobHandle<T>, String> {
+    let slot = Arc::new(JobSlot::new());
+    let job_slot = slot.clone();
+    let shared = Arc::downgrade(&self.shared);
+    let thread = std::thread::Builder::new()
+      .name(name.to_string())
+      .spawn(move || {
+        let result = catch_unwind(AssertUnwindSafe(f))
+          .map_err(|e| format!("at running dedicated job: {}", panic_message(e.as_ref())));
+        job_slot.complete(result);
+        if let Some(shared) = shared.upgrade() {
+          shared.notify_finished();
+        }
+      })
+      .map_err(|e| format!("at spawning dedicated thread {name}: {e}"))?;
+    let mut dedicated_threads = self
+      .dedicated_threads
+      .lock()
+      .map_err(|e| format!("at getting dedicated threads lock: {e}"))?;
+    dedicated_threads.retain(|thread| !thread.is_finished());
+    dedicated_threads.push(thread);
+    Ok(JobHandle { slot, shared: self.shared.clone() })
+  }
+
+  pub fn scope<'env, T>(
+    &self,
+    f: impl for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+  ) -> Result<T, String> {
+    let scope = Scope {
+      shared: self.shared.clone(),
+      pending: AtomicUsize::new(0),
+      panic_message: Mutex::new(None),
+      scope: PhantomData,
+      env: PhantomData,
+    };
+    let result = catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    self.shared.help_until(|| scope.pending.load(Ordering::SeqCst) == 0);
+    let result = match result {
+      Ok(result) => result,
+      Err(payload) => resume_unwind(payload),
+    };
+    let first_panic = scope
+      .panic_message
+      .lock()
+      .map_err(|e| format!("at getting scoped job panic lock: {e}"))?
+      .take();
+    match first_panic {
+      None => Ok(result),
+      Some(panic_message) => Err(format!("at running scoped job: {panic_message}")),
+    }
+  }
+
+  // Fork-join, b may run on another worker while a runs on the calling thread
+  pub fn join<A: Send, B: Send>(
+    &self,
+    a: impl FnOnce() -> A + Send,
+    b: impl FnOnce() -> B + Send,
+  ) -> Result<(A, B), String> {
+    let mut b_result = None;
+    let a_result = self.scope(|s| {
+      let b_slot = &mut b_result;
+      s.spawn(move || *b_slot = Some(b()));
+      a()
+    })?;
+    let b_result = b_result.ok_or("at running joined job: result missing".to_string())?;
+    Ok((a_result, b_result))
+  }
+
+  pub fn for_each_mut<T: Send>(
+    &self,
+    items: &mut [T],
+    f: impl Fn(usize, &mut T) + Sync,
+  ) -> Result<(), String> {
+    let chunk_size = items.len().div_ceil(self.thread_count() * 4).max(1);
+    let f = &f;
+    self.scope(|s| {
+      for (chunk_idx, chunk) in items.chunks_mut(chunk_size).enumerate() {
+        s.spawn(move || {
+          for (i, item) in chunk.iter_mut().enumerate() {
+            f(chunk_idx * chunk_size + i, item);
+          }
+        });
+      }
+    })
+  }
+
+  pub fn map<T: Sync, R: Send>(
+    &self,
+    items: &[T],
+    f: impl Fn(&T) -> R + Sync,
+  ) -> Result<Vec<R>, String> {
+    let mut results = items.iter().map(|_| None).collect::<Vec<_>>();
+    self.for_each_mut(&mut results, |i, result| *result = Some(f(&items[i])))?;
+    results.into_iter().map(|result| result.ok_or("job map result missing".to_string())).collect()
+  }
+}
+
+impl Drop for JobPool {
+  fn drop(&mut self) {
+    {
+      let _sleep_guard = self.shared.sleep_lock.lock();
+      self.shared.shutdown.store(true, Ordering::SeqCst);
+      self.shared.wake.notify_all();
+    }
+    for worker in self.workers.drain(..) {
+      let _ = worker.join().inspect_err(|_| eprintln!("at joining job worker thread"));
+    }
+    let dedicated_threads = match self.dedicated_threads.get_mut() {
+      Ok(dedicated_threads) => std::mem::take(dedicated_threads),
+      Err(e) => std::mem::take(e.into_inner()),
+    };
+    for thread in dedicated_threads {
+      let _ = thread.join().inspect_err(|_| eprintln!("at joining dedicated thread"));
+    }
+  }
+}
+
+// Pool shared by engine subsystems, sized from the core count unless init_global ran first
+pub fn global() -> &'static JobPool {
+  GLOBAL_POOL.get_or_init(|| {
+    let thread_count = std::thread::available_parallelism().map(|x| x.get()).unwrap_or(2);
+    JobPool::new("engine", thread_count.saturating_sub(1)).expect("at creating global job pool")
+  })
+}
+
+pub fn init_global(thread_count: usize) -> Result<&'static JobPool, String> {
+  let pool = JobPool::new("engine", thread_count)?;
+  GLOBAL_POOL.set(pool).map_err(|_| "global job pool is already initialized".to_string())?;
+  Ok(global())
+}
@@ -0,0 +1,115 @@
+use std::{
+  panic::{catch_unwind, AssertUnwindSafe},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+  },
+  time::{Duration, Instant},
+};
+
+use crate::JobPool;
+
+fn fib(pool: &JobPool, n: u64) -> u64 {
+  if n < 2 {
+    return n;
+  }
+  let (a, b) = pool.join(|| fib(pool, n - 1), || fib(pool, n - 2)).unwrap();
+  a + b
+}
+
+#[test]
+fn scoped_job_panics_come_back_as_errors_after_the_others_finish() {
+  let pool = JobPool::new("test", 4).unwrap();
+  let finished = AtomicUsize::new(0);
+  let result = pool.scope(|s| {
+    s.spawn(|| panic!("scoped job broke"));
+    for _ in 0..8 {
+      s.spawn(|| {
+        std::thread::sleep(Duration::from_millis(5));
+        finished.fetch_add(1, Ordering::SeqCst);
+      });
+    }
+  });
+  let e = result.expect_err("the panic should be reported");
+  assert!(e.contains("scoped job broke"), "{e}");
+  assert_eq!(finished.load(Ordering::SeqCst), 8);
+  // The pool keeps working after it
+  assert_eq!(pool.map(&[1, 2, 3], |x| x * 2).unwrap(), vec![2, 4, 6]);
+  let handle = pool.spawn(|| -> u32 { panic!("spawned job broke") });
+  assert!(handle.wait().unwrap_err().contains("spawned job broke"));
+}
+
+#[test]
+fn scope_outlives_the_jobs_borrowing_from_it_even_when_unwinding() {
+  let pool = JobPool::new("test", 2).unwrap();
+  let written = Mutex::new(vec![]);
+  let unwound = catch_unwind(AssertUnwindSafe(|| {
+    pool.scope(|s| {
+      for i in 0..4 {
+        let written = &written;
+        s.spawn(move || {
+          std::thread::sleep(Duration::from_millis(20));
+          written.lock().unwrap().push(i);
+        });
+      }
+      panic!("scope body broke");
+    })
+  }));
+  assert!(unwound.is_err());
+  // Every job borrowing written ran to the end before the panic left the scope
+  let mut written = written.into_inner().unwrap();
+  written.sort();
+  assert_eq!(written, vec![0, 1, 2, 3]);
+
+  let mut items = vec![0u64; 1000];
+  pool.for_each_mut(&mut items, |i, item| *item = i as u64 * 3).unwrap();
+  assert!(items.iter().enumerate().all(|(i, item)| *item == i as u64 * 3));
+}
+
+#[test]
+fn stolen_and_nested_jobs_each_run_once() {
+  let pool = JobPool::new("test", 4).unwrap();
+  // Forks push to the forking worker's own queue, the others only get to them by stealing
+  assert_eq!(fib(&pool, 20), 6765);
+
+  let runs = (0..256).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+  let busy_worker_started = AtomicUsize::new(0);
+  pool
+    .scope(|s| {
+      s.spawn(|| {
+        busy_worker_started.store(1, Ordering::SeqCst);
+        pool
+          .scope(|inner| {
+            for run in &runs {
+              inner.spawn(move || {
+                run.fetch_add(1, Ordering::SeqCst);
+              });
+            }
+          })
+          .unwrap();
+      });
+    })
+    .unwrap();
+  assert_eq!(busy_worker_started.load(Ordering::SeqCst), 1);
+  assert!(runs.iter().all(|run| run.load(Ordering::SeqCst) == 1));
+}
+
+#[test]
+fn waiting_sleeps_until_the_job_finishes() {
+  let pool = JobPool::new("test", 1).unwrap();
+  let start = Instant::now();
+  let handle = pool.spawn(|| {
+    std::thread::sleep(Duration::from_millis(50));
+    7
+  });
+  assert_eq!(handle.wait().unwrap(), 7);
+  assert!(start.elapsed() >= Duration::from_millis(50));
+  // A dedicated thread finishing wakes the waiter too, with no job queued to help with
+  let dedicated = pool
+    .spawn_dedicated("test dedicated", || {
+      std::thread::sleep(Duration::from_millis(20));
+      "done"
+    })
+    .unwrap();
+  assert_eq!(dedicated.wait().unwrap(), "done");
+}
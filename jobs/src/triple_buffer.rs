@@ -0,0 +1,60 @@
+use std::sync::{Arc, Mutex};
+
+// Latest value handoff between one writer and one reader thread. The writer fills its back buffer
+// and publishes it without waiting on the reader, the reader picks up whatever was published last
+// and skips anything it was too slow to see. Buffers are swapped, never copied, so big values can
+// keep their allocations around
+pub struct TripleBufferWriter<T> {
+  back: T,
+  // Buffer in the middle, and whether it holds a value the reader hasn't taken yet
+  middle: Arc<Mutex<(T, bool)>>,
+}
+
+pub struct TripleBufferReader<T> {
+  front: T,
+  middle: Arc<Mutex<(T, bool)>>,
+}
+
+// All three buffers start as clones of the initial value
+pub fn triple_buffer<T: Clone>(initial: T) -> (TripleBufferWriter<T>, TripleBufferReader<T>) {
+  let middle = Arc::new(Mutex::new((initial.clone(), false)));
+  let writer = TripleBufferWriter { back: initial.clone(), middle: middle.clone() };
+  let reader = TripleBufferReader { front: initial, middle };
+  (writer, reader)
+}
+
+impl<T> TripleBufferWriter<T> {
+  // Holds an older value once a publish swapped it out, overwrite all of it before publishing
+  pub fn back_mut(&mut self) -> &mut T {
+    &mut self.back
+  }
+
+  pub fn publish(&mut self) {
+    let mut middle = match self.middle.lock() {
+      Ok(middle) => middle,
+      Err(e) => e.into_inner(),
+    };
+    std::mem::swap(&mut self.back, &mut middle.0);
+    middle.1 = true;
+  }
+}
+
+impl<T> TripleBufferReader<T> {
+  // Takes the latest published value if there is one, returns whether the front changed
+  pub fn update(&mut self) -> bool {
+    let mut middle = match self.middle.lock() {
+      Ok(middle) => middle,
+      Err(e) => e.into_inner(),
+    };
+    if !middle.1 {
+      return false;
+    }
+    std::mem::swap(&mut self.front, &mut middle.0);
+    middle.1 = false;
+    true
+  }
+
+  pub fn front(&self) -> &T {
+    &self.front
+  }
+}
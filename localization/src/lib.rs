@@ -0,0 +1,200 @@
+use std::{
+  collections::HashMap,
+  fmt::Display,
+  sync::{OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use serde::Deserialize;
+use vfs::Vfs;
+
+#[cfg(test)]
+mod tests;
+
+pub const DEFAULT_STRINGS_DIR: &str = "lang";
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+static GLOBAL_LOCALIZATION: OnceLock<RwLock<Localization>> = OnceLock::new();
+
+// One language's strings, from <strings dir>/<language>.toml. Text can have {name} placeholders
+// for the arguments tr! is given
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct StringTable {
+  // Looked in for keys this table doesn't have, like pt-BR falling back to pt
+  #[serde(default)]
+  pub fallback: Option<String>,
+  #[serde(default)]
+  pub strings: HashMap<String, String>,
+}
+
+impl StringTable {
+  pub fn load(vfs: &Vfs, path: &str) -> Result<Self, String> {
+    let table_str =
+      vfs.read_to_string(path).map_err(|e| format!("at reading string table {path}: {e}"))?;
+    toml::from_str(&table_str).map_err(|e| format!("at parsing string table {path}: {e}"))
+  }
+}
+
+// String tables of the current language and the ones it falls back to. Keys are looked up in the
+// language first, then each fallback in turn, and shown as is when no table has them
+pub struct Localization {
+  strings_dir: String,
+  // End of every fallback chain, the language every key is written in first
+  base_language: String,
+  // The language first, then what it falls back to
+  chain: Vec<String>,
+  tables: HashMap<String, StringTable>,
+}
+
+impl Localization {
+  // No tables, every key shows as is until a language is set
+  pub fn empty(strings_dir: &str, base_language: &str) -> Self {
+    Self {
+      strings_dir: vfs::normalize_path(strings_dir),
+      base_language: base_language.to_string(),
+      chain: vec![base_language.to_string()],
+      tables: HashMap::new(),
+    }
+  }
+
+  pub fn load(
+    vfs: &Vfs,
+    strings_dir: &str,
+    language: &str,
+    base_language: &str,
+  ) -> Result<Self, String> {
+    let mut localization = Self::empty(strings_dir, base_language);
+    localization.set_language(vfs, language)?;
+    Ok(localization)
+  }
+
+  pub fn language(&self) -> &str {
+    &self.chain[0]
+  }
+
+  pub fn fallback_chain(&self) -> &[String] {
+    &self.chain
+  }
+
+  pub fn table_path(&self, language: &str) -> String {
+    format!("{}/{language}.toml", self.strings_dir)
+  }
+
+  // Languages with a table in the strings dir
+  pub fn available_languages(&self, vfs: &Vfs) -> Vec<String> {
+    vfs
+      .list(&self.strings_dir)
+      .iter()
+      .filter_map(|path| path.strip_suffix(".toml"))
+      .filter_map(|path| path.rsplit('/').next())
+      .map(|language| language.to_string())
+      .collect()
+  }
+
+  // Loads the language and everything down its fallback chain. When a table doesn't load the
+  // current language stays
+  pub fn set_language(&mut self, vfs: &Vfs, language: &str) -> Result<(), String> {
+    let mut chain = vec![];
+    let mut tables = HashMap::new();
+    let mut next = Some(language.to_string());
+    while let Some(language) = next.take() {
+      if chain.contains(&language) {
+        break;
+      }
+      let table = StringTable::load(vfs, &self.table_path(&language))?;
+      next = table.fallback.clone();
+      if next.is_none() && language != self.base_language {
+        next = Some(self.base_language.clone());
+      }
+      chain.push(language.clone());
+      tables.insert(language, table);
+    }
+    self.chain = chain;
+    self.tables = tables;
+    Ok(())
+  }
+
+  // Loads the table again when it's one the current language uses, false when it isn't
+  pub fn reload(&mut self, vfs: &Vfs, path: &str) -> Result<bool, String> {
+    let path = vfs::normalize_path(path);
+    if !self.chain.iter().any(|language| self.table_path(language) == path) {
+      return Ok(false);
+    }
+    // The table's fallback may have changed, so the whole chain is loaded again
+    let language = self.chain[0].clone();
+    self.set_language(vfs, &language).map(|_| true).map_err(|e| format!("at reloading {path}: {e}"))
+  }
+
+  pub fn get(&self, key: &str) -> Option<&str> {
+    self
+      .chain
+      .iter()
+      .find_map(|language| self.tables.get(language)?.strings.get(key))
+      .map(|text| text.as_str())
+  }
+
+  // The key's text with {name} placeholders filled from args, the key itself when it's missing
+  pub fn translate(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut text = self.get(key).unwrap_or(key).to_string();
+    for (name, value) in args.iter() {
+      text = text.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    text
+  }
+}
+
+// Localization shared by engine subsystems, the default language from the global vfs unless
+// init_global ran first
+pub fn global() -> &'static RwLock<Localization> {
+  GLOBAL_LOCALIZATION.get_or_init(|| {
+    let localization =
+      Localization::load(vfs::global(), DEFAULT_STRINGS_DIR, DEFAULT_LANGUAGE, DEFAULT_LANGUAGE)
+        .unwrap_or_else(|e| {
+          eprintln!("at loading default strings: {e}");
+          Localization::empty(DEFAULT_STRINGS_DIR, DEFAULT_LANGUAGE)
+        });
+    RwLock::new(localization)
+  })
+}
+
+pub fn init_global(localization: Localization) -> Result<(), String> {
+  GLOBAL_LOCALIZATION
+    .set(RwLock::new(localization))
+    .map_err(|_| "global localization is already initialized".to_string())
+}
+
+fn read() -> RwLockReadGuard<'static, Localization> {
+  global().read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write() -> RwLockWriteGuard<'static, Localization> {
+  global().write().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn language() -> String {
+  read().language().to_string()
+}
+
+// Strings looked up after this are in the new language
+pub fn set_language(language: &str) -> Result<(), String> {
+  write().set_language(vfs::global(), language)
+}
+
+pub fn reload(path: &str) -> Result<bool, String> {
+  write().reload(vfs::global(), path)
+}
+
+pub fn translate(key: &str, args: &[(&str, &dyn Display)]) -> String {
+  read().translate(key, args)
+}
+
+// Text for a key in the current language, like tr!("console.scene_saved", path = path) for a
+// string with a {path} placeholder
+#[macro_export]
+macro_rules! tr {
+  ($key:expr) => {
+    $crate::translate($key, &[])
+  };
+  ($key:expr, $($name:ident = $value:expr),+ $(,)?) => {
+    $crate::translate($key, &[$((stringify!($name), &$value as &dyn ::std::fmt::Display)),+])
+  };
+}
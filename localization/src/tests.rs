@@ -0,0 +1,159 @@
+use std::fs;
+
+use vfs::test_support::TempDir;
+use vfs::Vfs;
+
+use crate::Localization;
+
+fn write_table(temp: &TempDir, language: &str, table: &str) {
+  fs::write(temp.path().join(format!("{language}.toml")), table).unwrap();
+}
+
+// Mounted as the strings dir
+fn mount(temp: &TempDir) -> Vfs {
+  let vfs = Vfs::new();
+  vfs.mount_dir("lang", temp.path()).unwrap();
+  vfs
+}
+
+fn write_tables(temp: &TempDir) {
+  write_table(
+    temp,
+    "en",
+    r#"
+[strings]
+"greeting" = "hello {name}"
+"farewell" = "bye"
+"color" = "color"
+"only_en" = "only in english"
+"#,
+  );
+  write_table(
+    temp,
+    "pt",
+    r#"
+[strings]
+"greeting" = "olá {name}"
+"farewell" = "tchau"
+"color" = "cor"
+"#,
+  );
+  write_table(
+    temp,
+    "pt-BR",
+    r#"
+fallback = "pt"
+
+[strings]
+"farewell" = "falou"
+"#,
+  );
+}
+
+#[test]
+fn keys_fall_back_down_the_chain_to_the_base_language() {
+  let temp = TempDir::new("lang_fallback");
+  write_tables(&temp);
+  let vfs = mount(&temp);
+  let localization = Localization::load(&vfs, "lang", "pt-BR", "en").unwrap();
+  assert_eq!(localization.fallback_chain(), ["pt-BR", "pt", "en"]);
+  assert_eq!(localization.get("farewell"), Some("falou"));
+  assert_eq!(localization.get("color"), Some("cor"));
+  assert_eq!(localization.get("only_en"), Some("only in english"));
+  // Keys no table has show as is
+  assert_eq!(localization.get("missing"), None);
+  assert_eq!(localization.translate("missing", &[]), "missing");
+
+  // Tables without a fallback go straight to the base language
+  let localization = Localization::load(&vfs, "lang", "pt", "en").unwrap();
+  assert_eq!(localization.fallback_chain(), ["pt", "en"]);
+  let localization = Localization::load(&vfs, "lang", "en", "en").unwrap();
+  assert_eq!(localization.fallback_chain(), ["en"]);
+}
+
+#[test]
+fn fallback_cycles_stop_at_the_first_repeat() {
+  let temp = TempDir::new("lang_cycle");
+  write_tables(&temp);
+  write_table(&temp, "a", "fallback = \"b\"\n[strings]\n\"x\" = \"from a\"\n");
+  write_table(&temp, "b", "fallback = \"a\"\n[strings]\n\"y\" = \"from b\"\n");
+  let localization = Localization::load(&mount(&temp), "lang", "a", "en").unwrap();
+  assert_eq!(localization.fallback_chain(), ["a", "b"]);
+  assert_eq!(localization.get("y"), Some("from b"));
+  assert_eq!(localization.get("farewell"), None);
+}
+
+#[test]
+fn switching_language_at_runtime_changes_every_lookup() {
+  let temp = TempDir::new("lang_switch");
+  write_tables(&temp);
+  let vfs = mount(&temp);
+  let mut localization = Localization::empty("lang", "en");
+  assert_eq!(localization.translate("farewell", &[]), "farewell");
+
+  localization.set_language(&vfs, "en").unwrap();
+  assert_eq!(localization.language(), "en");
+  assert_eq!(localization.get("farewell"), Some("bye"));
+  localization.set_language(&vfs, "pt-BR").unwrap();
+  assert_eq!(localization.language(), "pt-BR");
+  assert_eq!(localization.get("farewell"), Some("falou"));
+  assert_eq!(localization.get("color"), Some("cor"));
+
+  // A language without a table, or with a broken one, leaves the current one up
+  assert!(localization.set_language(&vfs, "fr").is_err());
+  write_table(&temp, "de", "strings = 3");
+  assert!(localization.set_language(&vfs, "de").is_err());
+  assert_eq!(localization.language(), "pt-BR");
+  assert_eq!(localization.get("farewell"), Some("falou"));
+
+  let mut languages = localization.available_languages(&vfs);
+  languages.sort();
+  assert_eq!(languages, ["de", "en", "pt", "pt-BR"]);
+}
+
+#[test]
+fn reloading_a_table_in_the_chain_picks_up_its_changes() {
+  let temp = TempDir::new("lang_reload");
+  write_tables(&temp);
+  let vfs = mount(&temp);
+  let mut localization = Localization::load(&vfs, "lang", "pt-BR", "en").unwrap();
+  write_table(&temp, "pt", "[strings]\n\"color\" = \"cores\"\n");
+  write_table(&temp, "other", "[strings]\n");
+  assert!(!localization.reload(&vfs, "lang/other.toml").unwrap());
+  assert_eq!(localization.get("color"), Some("cor"));
+  assert!(localization.reload(&vfs, "./lang/pt.toml").unwrap());
+  assert_eq!(localization.get("color"), Some("cores"));
+  assert_eq!(localization.get("greeting"), Some("hello {name}"));
+}
+
+#[test]
+fn arguments_fill_their_placeholders() {
+  let temp = TempDir::new("lang_args");
+  write_tables(&temp);
+  write_table(
+    &temp,
+    "en",
+    r#"
+[strings]
+"greeting" = "hello {name}"
+"score" = "{name} has {points} points, {name} wins"
+"#,
+  );
+  let localization = Localization::load(&mount(&temp), "lang", "en", "en").unwrap();
+  assert_eq!(localization.translate("greeting", &[("name", &"Ana")]), "hello Ana");
+  assert_eq!(
+    localization.translate("score", &[("name", &"Ana"), ("points", &12)]),
+    "Ana has 12 points, Ana wins"
+  );
+  // Placeholders without an argument stay, arguments without a placeholder are left out
+  assert_eq!(localization.translate("greeting", &[]), "hello {name}");
+  assert_eq!(localization.translate("greeting", &[("other", &1.5)]), "hello {name}");
+
+  // tr! goes through the global localization
+  crate::init_global(localization).unwrap();
+  let name = "Bo";
+  assert_eq!(crate::tr!("greeting", name = name), "hello Bo");
+  assert_eq!(crate::tr!("score", name = "Ana", points = 3 + 4), "Ana has 7 points, Ana wins");
+  assert_eq!(crate::tr!("missing"), "missing");
+  assert_eq!(crate::language(), "en");
+}
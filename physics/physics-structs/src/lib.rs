@@ -1,3 +1,5 @@
+use geometry::glam;
+
 pub mod primitives;
 
 #[derive(Debug, Copy, Clone)]
@@ -10,8 +10,9 @@ pub struct PolygonFace {
 
 impl PolygonFace {
   pub fn new(verts: Vec<Point>) -> PolygonFace {
-    let edges = (0..verts.len() - 1)
-      .map(|i| LineSegment::from_points(verts[i], verts[i + 1]))
+    // Closed, the last edge goes back to the first vertex
+    let edges = (0..verts.len())
+      .map(|i| LineSegment::from_points(verts[i], verts[(i + 1) % verts.len()]))
       .collect::<Vec<_>>();
     let normal = edges[0].get_direction().cross(edges[1].get_direction());
     let face = Plane::new(normal, verts[0]);
@@ -1,30 +1,18 @@
 use geometry::{glam, Orientation, Point};
 
-static INV_ROOT_3: f32 = 0.577350269189625764508;
-static ROOT_3: f32 = 1.732050807568877293527;
+static INV_ROOT_3: f32 = 0.577_350_26;
+static ROOT_3: f32 = 1.732_050_8;
 
-static REGULAR_TETRAHEDRON_VERTS: [[Point; 3]; 4] = [
+fn regular_tetrahedron_verts() -> Vec<[Point; 3]> {
   [
-    Point::from_vec3(glam::Vec3::new(-1.0, -1.0, -1.0)),
-    Point::from_vec3(glam::Vec3::new(1.0, 1.0, -1.0)),
-    Point::from_vec3(glam::Vec3::new(-1.0, 1.0, 1.0)),
-  ],
-  [
-    Point::from_vec3(glam::Vec3::new(-1.0, 1.0, 1.0)),
-    Point::from_vec3(glam::Vec3::new(1.0, 1.0, -1.0)),
-    Point::from_vec3(glam::Vec3::new(1.0, -1.0, 1.0)),
-  ],
-  [
-    Point::from_vec3(glam::Vec3::new(1.0, 1.0, -1.0)),
-    Point::from_vec3(glam::Vec3::new(-1.0, -1.0, -1.0)),
-    Point::from_vec3(glam::Vec3::new(1.0, -1.0, 1.0)),
-  ],
-  [
-    Point::from_vec3(glam::Vec3::new(-1.0, -1.0, -1.0)),
-    Point::from_vec3(glam::Vec3::new(-1.0, 1.0, 1.0)),
-    Point::from_vec3(glam::Vec3::new(1.0, -1.0, 1.0)),
-  ],
-];
+    [[-1.0, -1.0, -1.0], [1.0, 1.0, -1.0], [-1.0, 1.0, 1.0]],
+    [[-1.0, 1.0, 1.0], [1.0, 1.0, -1.0], [1.0, -1.0, 1.0]],
+    [[1.0, 1.0, -1.0], [-1.0, -1.0, -1.0], [1.0, -1.0, 1.0]],
+    [[-1.0, -1.0, -1.0], [-1.0, 1.0, 1.0], [1.0, -1.0, 1.0]],
+  ]
+  .map(|triangle| triangle.map(|x| Point::from_vec3(glam::Vec3::from_array(x))))
+  .to_vec()
+}
 
 fn subdivide_sphere_triangles(triangles: Vec<[Point; 3]>) -> Vec<[Point; 3]> {
   let mut new_sphere_triangles = vec![];
@@ -54,7 +42,7 @@ impl Sphere {
   }
 
   pub fn to_triangles(&self, subdivision: usize) -> Vec<[Point; 3]> {
-    let mut triangles = REGULAR_TETRAHEDRON_VERTS.to_vec();
+    let mut triangles = regular_tetrahedron_verts();
     for _ in 0..subdivision {
       triangles = subdivide_sphere_triangles(triangles);
     }
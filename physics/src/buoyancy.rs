@@ -0,0 +1,127 @@
+use crate::structs::RigidBodyType;
+use crate::GRAVITY;
+use geometry::{glam, Plane, Point};
+
+// Submerged volume is worked out from this many points a side in each primitive's box
+const SAMPLES_PER_AXIS: usize = 4;
+
+#[derive(Debug, Clone)]
+pub enum FluidRegion {
+  Box { min: glam::Vec3, max: glam::Vec3 },
+  // Points behind every plane are inside, planes face out
+  Convex { planes: Vec<Plane> },
+}
+
+impl FluidRegion {
+  pub(crate) fn translate(&mut self, offset: glam::Vec3) {
+    match self {
+      FluidRegion::Box { min, max } => {
+        *min += offset;
+        *max += offset;
+      }
+      FluidRegion::Convex { planes } => {
+        planes.iter_mut().for_each(|plane| *plane = plane.displace(offset));
+      }
+    }
+  }
+
+  pub fn contains(&self, point: glam::Vec3) -> bool {
+    match self {
+      FluidRegion::Box { min, max } => point.cmpge(*min).all() && point.cmple(*max).all(),
+      FluidRegion::Convex { planes } => {
+        planes.iter().all(|plane| plane.dist_from_point(&Point::from_vec3(point)) <= 0.0)
+      }
+    }
+  }
+}
+
+// Water and the like. Drags are the share of velocity lost per second when fully submerged
+#[derive(Debug, Clone)]
+pub struct FluidVolume {
+  pub region: FluidRegion,
+  pub density: f32,
+  pub linear_drag: f32,
+  pub angular_drag: f32,
+}
+
+// What of a body is inside a fluid volume, in world space
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Submersion {
+  pub volume: f32,
+  pub center: glam::Vec3,
+  // Submerged share of the body's volume
+  pub fraction: f32,
+}
+
+fn grid_points(min: glam::Vec3, max: glam::Vec3) -> impl Iterator<Item = glam::Vec3> {
+  let cell = (max - min) / SAMPLES_PER_AXIS as f32;
+  (0..SAMPLES_PER_AXIS.pow(3)).map(move |i| {
+    let cell_idx = glam::UVec3::new(
+      (i % SAMPLES_PER_AXIS) as u32,
+      (i / SAMPLES_PER_AXIS % SAMPLES_PER_AXIS) as u32,
+      (i / (SAMPLES_PER_AXIS * SAMPLES_PER_AXIS)) as u32,
+    );
+    min + (cell_idx.as_vec3() + 0.5) * cell
+  })
+}
+
+// Body space points standing for the body's volume, with the volume each stands for. Polygons
+// are taken together as the box around them, spheres one by one
+pub fn volume_samples(mesh: &[RigidBodyType]) -> Vec<(glam::Vec3, f32)> {
+  let mut samples = vec![];
+  let mut polygon_box: Option<(glam::Vec3, glam::Vec3)> = None;
+  for prim in mesh.iter() {
+    match prim {
+      RigidBodyType::PolygonFace(p_mesh) => {
+        for vert in p_mesh.get_verts().iter() {
+          let point = vert.as_vec3();
+          polygon_box =
+            Some(polygon_box.map_or((point, point), |(min, max)| (min.min(point), max.max(point))));
+        }
+      }
+      RigidBodyType::Sphere(sphere) => {
+        let center = sphere.center.as_vec3();
+        let reach = glam::Vec3::splat(sphere.radius);
+        let inside = grid_points(center - reach, center + reach)
+          .filter(|point| point.distance_squared(center) <= sphere.radius * sphere.radius)
+          .collect::<Vec<_>>();
+        let volume = 4.0 / 3.0 * std::f32::consts::PI * sphere.radius.powi(3);
+        let sample_volume = volume / inside.len().max(1) as f32;
+        samples.extend(inside.into_iter().map(|point| (point, sample_volume)));
+      }
+    }
+  }
+  if let Some((min, max)) = polygon_box {
+    let size = max - min;
+    let sample_volume = size.x * size.y * size.z / SAMPLES_PER_AXIS.pow(3) as f32;
+    samples.extend(grid_points(min, max).map(|point| (point, sample_volume)));
+  }
+  samples
+}
+
+impl FluidVolume {
+  // Samples are from volume_samples, placed in the world by transform
+  pub fn submersion(&self, samples: &[(glam::Vec3, f32)], transform: glam::Mat4) -> Submersion {
+    let mut submersion = Submersion::default();
+    let mut total_volume = 0.0;
+    for &(point, volume) in samples.iter() {
+      total_volume += volume;
+      let point = transform.transform_point3(point);
+      if self.region.contains(point) {
+        submersion.volume += volume;
+        submersion.center += point * volume;
+      }
+    }
+    if submersion.volume > 0.0 {
+      submersion.center /= submersion.volume;
+      submersion.fraction = submersion.volume / total_volume;
+    }
+    submersion
+  }
+
+  // Upward force of the displaced fluid, applied at the submersion's center so tilted bodies right
+  // themselves
+  pub fn buoyant_force(&self, submersion: &Submersion) -> glam::Vec3 {
+    glam::Vec3::Y * self.density * GRAVITY * submersion.volume
+  }
+}
@@ -0,0 +1,72 @@
+use crate::solver::ContactPoint;
+use crate::RigidBody;
+use geometry::glam;
+
+// How far the rotation's basis may drift from unit length and right angles before it counts as
+// broken. Integrating small rotations every step drifts a little on its own
+const ROTATION_TOLERANCE: f32 = 1e-3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CheckedMath {
+  #[default]
+  Off,
+  // Validates bodies after every step and logs the ones gone bad
+  Log,
+  // Like Log, and puts the bad bodies back where the step started and stops moving them, so one
+  // NaN doesn't spread through contacts to the rest of the simulation
+  Freeze,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BodyStateViolation {
+  Position(glam::Vec3),
+  Velocity(glam::Vec3),
+  AngularVelocity(glam::Vec3),
+  // Largest difference of the rotation times its transpose from identity
+  Rotation(f32),
+}
+
+// Everything wrong with the body's state, empty when it's fine
+pub fn body_violations(body: &RigidBody) -> Vec<BodyStateViolation> {
+  let info = &body.physics_info;
+  let mut violations = vec![];
+  if !info.orientation.position.is_finite() {
+    violations.push(BodyStateViolation::Position(info.orientation.position));
+  }
+  if !info.velocity.is_finite() {
+    violations.push(BodyStateViolation::Velocity(info.velocity));
+  }
+  if !info.angular_velocity.is_finite() {
+    violations.push(BodyStateViolation::AngularVelocity(info.angular_velocity));
+  }
+  let rotation = glam::Mat3::from_mat4(info.orientation.rotation);
+  let drift = (rotation * rotation.transpose() - glam::Mat3::IDENTITY)
+    .to_cols_array()
+    .iter()
+    .fold(0.0f32, |max, x| if x.is_finite() { max.max(x.abs()) } else { f32::INFINITY });
+  if drift > ROTATION_TOLERANCE {
+    violations.push(BodyStateViolation::Rotation(drift));
+  }
+  violations
+}
+
+// Logs the body with the contacts it had this step, they are usually where the bad values came in
+pub fn report_violations(
+  bodies: &[RigidBody],
+  body_idx: usize,
+  violations: &[BodyStateViolation],
+  contacts: &[ContactPoint],
+) {
+  let body = &bodies[body_idx];
+  eprintln!(
+    "at checking rigid body {}: {violations:?}, orientation {:?}, previous orientation {:?}",
+    body.name, body.physics_info.orientation, body.physics_info.prev_orientation
+  );
+  for contact in contacts.iter().filter(|x| x.body_1 == body_idx || x.body_2 == body_idx) {
+    let other = if contact.body_1 == body_idx { contact.body_2 } else { contact.body_1 };
+    eprintln!(
+      "  contact with {}: point {}, normal {}, penetration {}",
+      bodies[other].name, contact.point, contact.normal, contact.penetration
+    );
+  }
+}
@@ -0,0 +1,78 @@
+use geometry::glam;
+
+use crate::structs::RigidBodyType;
+use crate::PhysicsEngine;
+
+// Segments in each of the three rings drawn around a sphere
+const SPHERE_RING_SEGMENTS: usize = 16;
+
+// A body the way the collision code sees it, all in world space, for drawing over the scene
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BodyDebugShape {
+  // Box around its polygons and spheres, None for bodies without either
+  pub bounds: Option<(glam::Vec3, glam::Vec3)>,
+  // Polygon outlines and a ring around each sphere on every axis. Level geometry has none, its
+  // triangles are too many to draw every tick
+  pub edges: Vec<(glam::Vec3, glam::Vec3)>,
+  pub velocity: glam::Vec3,
+  // Points it touched another body at in the last step, with the normal facing away from it
+  pub contacts: Vec<(glam::Vec3, glam::Vec3)>,
+}
+
+impl PhysicsEngine {
+  // None when there's no body with that name
+  pub fn body_debug_shape(&self, name: &str) -> Option<BodyDebugShape> {
+    let body_idx = *self.rigid_body_names.get(name)?;
+    let body = &self.rigid_bodies[body_idx];
+    let transform = body.physics_info.orientation.get_full_transform();
+    let mut shape = BodyDebugShape { velocity: body.physics_info.velocity, ..Default::default() };
+    let mut grow = |min: glam::Vec3, max: glam::Vec3| {
+      shape.bounds = Some(shape.bounds.map_or((min, max), |(lo, hi)| (lo.min(min), hi.max(max))));
+    };
+    let mut edges = vec![];
+    for prim in body.mesh.iter() {
+      match prim {
+        RigidBodyType::PolygonFace(p_mesh) => {
+          let verts =
+            p_mesh.get_verts().iter().map(|x| x.transform(transform).as_vec3()).collect::<Vec<_>>();
+          verts.iter().for_each(|x| grow(*x, *x));
+          for (i, vert) in verts.iter().enumerate() {
+            edges.push((*vert, verts[(i + 1) % verts.len()]));
+          }
+        }
+        RigidBodyType::Sphere(sphere) => {
+          let center = transform.transform_point3(sphere.center.as_vec3());
+          let reach = glam::Vec3::splat(sphere.radius);
+          grow(center - reach, center + reach);
+          for (axis_1, axis_2) in [
+            (glam::Vec3::X, glam::Vec3::Y),
+            (glam::Vec3::Y, glam::Vec3::Z),
+            (glam::Vec3::Z, glam::Vec3::X),
+          ] {
+            let ring_point = |i: usize| {
+              let angle = i as f32 / SPHERE_RING_SEGMENTS as f32 * std::f32::consts::TAU;
+              center + (axis_1 * angle.cos() + axis_2 * angle.sin()) * sphere.radius
+            };
+            edges.extend((0..SPHERE_RING_SEGMENTS).map(|i| (ring_point(i), ring_point(i + 1))));
+          }
+        }
+      }
+    }
+    if let Some(static_mesh) = self.static_meshes.get(&body_idx) {
+      if let Some((min, max)) = static_mesh.bounds_min_max() {
+        grow(min, max);
+      }
+    }
+    shape.edges = edges;
+    shape.contacts = self
+      .last_contacts
+      .iter()
+      .filter_map(|contact| match body_idx {
+        idx if idx == contact.body_1 => Some((contact.point, -contact.normal)),
+        idx if idx == contact.body_2 => Some((contact.point, contact.normal)),
+        _ => None,
+      })
+      .collect();
+    Some(shape)
+  }
+}
@@ -1,12 +1,46 @@
-use force::SingleBodyForce;
+use buoyancy::FluidVolume;
+use checked_math::CheckedMath;
+use force::{CouplingForce, SingleBodyForce};
 use geometry::{glam, Direction, LineSegment, Orientation, Plane, Point};
+use material::{MaterialPairRule, MaterialTable, PhysicsMaterial};
+use profiler::profile_scope;
+use projectile::{Projectile, ProjectileConfig, ProjectileEvent, ProjectileHit, ProjectilePool};
+use rope::{Rope, RopeAnchor};
+use solver::{ContactPoint, ContactSolver, SolverConfig};
+use static_mesh::StaticTriangleMesh;
 use std::collections::HashMap;
-use structs::RigidBodyType;
+use structs::{Mass, MomentOfInertia, RigidBodyType};
+use time_zone::TimeZone;
+use vehicle::Vehicle;
 
-mod force;
-pub mod structs;
+pub use geometry;
 
+pub mod buoyancy;
+pub mod checked_math;
+pub mod debug_shape;
+pub mod force;
+pub mod material;
+mod origin;
+pub mod projectile;
+pub mod rope;
+pub mod snapshot;
+pub mod solver;
+pub mod static_mesh;
+pub mod structs;
+#[cfg(test)]
+mod tests;
+pub mod time_zone;
+pub mod vehicle;
 
+// How far behind a static mesh face a point still counts as touching it
+const STATIC_CONTACT_DEPTH: f32 = 0.1;
+// How far in front of a face a point counts as touching it without closing in on it
+const SPECULATIVE_MARGIN: f32 = 0.005;
+// Pull of gravity along -y for what the engine moves itself, fluids and ropes. Bodies fall by
+// their own forces
+const GRAVITY: f32 = 9.81;
+// Contacts facing within about 45 degrees of up count as standing on the body under them
+const RIDER_MIN_UP: f32 = 0.7;
 
 #[derive(Debug, Copy, Clone)]
 pub struct RigidBodyInfo {
@@ -17,6 +51,8 @@ pub struct RigidBodyInfo {
   angular_velocity: glam::Vec3,
   angular_acceleration: glam::Vec3,
   orientation: Orientation,
+  // Where the last step started, for blending towards orientation when rendering between steps
+  prev_orientation: Orientation,
 }
 
 impl Default for RigidBodyInfo {
@@ -29,11 +65,25 @@ impl Default for RigidBodyInfo {
       angular_velocity: glam::Vec3::ZERO,
       angular_acceleration: glam::Vec3::ZERO,
       orientation: Orientation::new(glam::Vec3::ZERO, glam::Mat4::IDENTITY),
+      prev_orientation: Orientation::new(glam::Vec3::ZERO, glam::Mat4::IDENTITY),
     }
   }
 }
 
 impl RigidBodyInfo {
+  // Impulse at a world space point, turns the body as well when it's off center
+  pub fn apply_impulse(&mut self, impulse: glam::Vec3, point: glam::Vec3) {
+    if let Mass::Finite(mass) = self.mass {
+      self.velocity += impulse / mass;
+    }
+    // Inertia is given in body space, rotate its inverse into world space
+    if let MomentOfInertia::Finite(inertia) = self.moment_of_inertia {
+      let rotation = glam::Mat3::from_mat4(self.orientation.rotation);
+      let inv_inertia = rotation * inertia.inverse() * rotation.transpose();
+      self.angular_velocity += inv_inertia * (point - self.orientation.position).cross(impulse);
+    }
+  }
+
   pub fn apply_bounds(&mut self, bounds: Vec<Direction>) {
     for bound in bounds.iter() {
       if self.velocity.dot(bound.as_vec3()) > 0.0 {
@@ -65,6 +115,27 @@ impl RigidBodyInfo {
   }
 }
 
+// How a radial impulse weakens from its center out to its radius
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RadialFalloff {
+  None,
+  Linear,
+  // 1 - x^2 of the distance, stays strong longer than linear
+  Quadratic,
+}
+
+impl RadialFalloff {
+  // For the distance as a fraction of the radius
+  fn scale(&self, t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    match self {
+      RadialFalloff::None => 1.0,
+      RadialFalloff::Linear => 1.0 - t,
+      RadialFalloff::Quadratic => 1.0 - t * t,
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
 pub enum CollisionInfo {
   FutureCollision {
@@ -84,6 +155,21 @@ pub enum CollisionInfo {
   NoCollision
 }
 
+// Where a kinematic body has to be and how long it has left to get there
+#[derive(Debug, Copy, Clone)]
+struct KinematicTarget {
+  orientation: Orientation,
+  time_left: f32,
+  // Bodies standing on it move with it, see set_carry_riders
+  carry_riders: bool,
+}
+
+// Solid box of the given size
+fn box_inertia(mass: f32, size: glam::Vec3) -> glam::Mat3 {
+  let sq = size * size;
+  glam::Mat3::from_diagonal(glam::vec3(sq.y + sq.z, sq.x + sq.z, sq.x + sq.y) * mass / 12.0)
+}
+
 #[derive(Debug, Clone)]
 pub struct RigidBody {
   name: String,
@@ -91,15 +177,236 @@ pub struct RigidBody {
   physics_info: RigidBodyInfo,
   collision_mask: u32,
   body_forces: Vec<SingleBodyForce>,
+  // Id in the engine's MaterialTable
+  material: usize,
+  // Set by checked math on bad values, the body stays where it was and nothing moves it
+  frozen: bool,
+  // Some for bodies moved from outside, like animated platforms and doors
+  kinematic: Option<KinematicTarget>,
+  // Platform velocity added to the body while it stands on a kinematic body carrying riders
+  carried_velocity: glam::Vec3,
+}
+
+impl RigidBody {
+  // Box around the body's polygons and spheres in its own space
+  fn local_size(&self) -> glam::Vec3 {
+    let mut bounds: Option<(glam::Vec3, glam::Vec3)> = None;
+    let mut grow = |min: glam::Vec3, max: glam::Vec3| {
+      bounds = Some(bounds.map_or((min, max), |(lo, hi)| (lo.min(min), hi.max(max))));
+    };
+    for prim in self.mesh.iter() {
+      match prim {
+        RigidBodyType::PolygonFace(p_mesh) => {
+          p_mesh.get_verts().iter().for_each(|x| grow(x.as_vec3(), x.as_vec3()))
+        }
+        RigidBodyType::Sphere(sphere) => {
+          let reach = glam::Vec3::splat(sphere.radius);
+          grow(sphere.center.as_vec3() - reach, sphere.center.as_vec3() + reach)
+        }
+      }
+    }
+    bounds.map_or(glam::Vec3::ZERO, |(min, max)| max - min)
+  }
+
+  fn set_mass(&mut self, mass: Mass) {
+    let moment_of_inertia = match mass {
+      Mass::Infinite => MomentOfInertia::Infinite,
+      // Flat or empty meshes would have no inertia around some axis, a little keeps it invertible
+      Mass::Finite(mass) => {
+        MomentOfInertia::Finite(box_inertia(mass, self.local_size().max(glam::Vec3::splat(0.01))))
+      }
+    };
+    let info = &mut self.physics_info;
+    info.mass = mass;
+    info.moment_of_inertia = moment_of_inertia;
+    if matches!(mass, Mass::Infinite) {
+      info.velocity = glam::Vec3::ZERO;
+      info.acceleration = glam::Vec3::ZERO;
+      info.angular_velocity = glam::Vec3::ZERO;
+      info.angular_acceleration = glam::Vec3::ZERO;
+    }
+  }
 }
 
 pub struct PhysicsEngine {
   rigid_bodies: Vec<RigidBody>,
   rigid_body_names: HashMap<String, usize>,
-  coupling_forces: HashMap<(String, String), SingleBodyForce>,
+  coupling_forces: HashMap<(String, String), CouplingForce>,
+  materials: MaterialTable,
+  solver: ContactSolver,
+  // Level geometry by the index of the immovable body standing in for it
+  static_meshes: HashMap<usize, StaticTriangleMesh>,
+  checked_math: CheckedMath,
+  // Solves per step, see set_substeps
+  substeps: usize,
+  fluid_volumes: Vec<FluidVolume>,
+  ropes: Vec<Rope>,
+  vehicles: Vec<Vehicle>,
+  time_zones: Vec<TimeZone>,
+  projectiles: ProjectilePool,
+  // Waiting for take_projectile_events
+  projectile_events: Vec<ProjectileEvent>,
+  step_time: f32,
+  step_us: u128,
+  max_steps_per_update: usize,
+  // Time run was given that hasn't made up a whole step yet
+  pending_us: u128,
+  // Found by the broad phase in the last step
+  contact_pair_count: usize,
+  // What the solver got in the last sub-step of the last step, for body_debug_shape
+  last_contacts: Vec<ContactPoint>,
+  // World position of zero in the engine, see shift_origin
+  origin: glam::DVec3,
 }
 
 impl PhysicsEngine {
+  // Steps are 1 / tick_hz seconds long, run takes at most max_steps_per_update of them a call
+  pub fn new(tick_hz: usize, max_steps_per_update: usize) -> Self {
+    let tick_hz = tick_hz.max(1);
+    Self {
+      rigid_bodies: vec![],
+      rigid_body_names: HashMap::new(),
+      coupling_forces: HashMap::new(),
+      materials: MaterialTable::default(),
+      solver: ContactSolver::new(SolverConfig::default()),
+      static_meshes: HashMap::new(),
+      checked_math: CheckedMath::Off,
+      substeps: 1,
+      fluid_volumes: vec![],
+      ropes: vec![],
+      vehicles: vec![],
+      time_zones: vec![],
+      projectiles: ProjectilePool::default(),
+      projectile_events: vec![],
+      step_time: 1.0 / tick_hz as f32,
+      step_us: 1_000_000 / tick_hz as u128,
+      max_steps_per_update: max_steps_per_update.max(1),
+      pending_us: 0,
+      contact_pair_count: 0,
+      last_contacts: vec![],
+      origin: glam::DVec3::ZERO,
+    }
+  }
+
+  fn body_idx(&self, name: &str) -> Result<usize, String> {
+    self.rigid_body_names.get(name).copied().ok_or(format!("no rigid body named {name}"))
+  }
+
+  // Replaces the body if one has the name already
+  fn insert_body(&mut self, body: RigidBody) {
+    match self.rigid_body_names.get(&body.name) {
+      Some(&body_idx) => {
+        self.static_meshes.remove(&body_idx);
+        self.rigid_bodies[body_idx] = body;
+      }
+      None => {
+        self.rigid_body_names.insert(body.name.clone(), self.rigid_bodies.len());
+        self.rigid_bodies.push(body);
+      }
+    }
+  }
+
+  // Bodies of infinite mass never move, like floors and walls. The others fall with gravity and
+  // turn like solid boxes the size of their mesh
+  pub fn add_rigid_body(
+    &mut self,
+    name: &str,
+    mesh: Vec<RigidBodyType>,
+    transform: glam::Mat4,
+    mass: Mass,
+  ) {
+    let (_, rotation, position) = transform.to_scale_rotation_translation();
+    let orientation = Orientation::new(position, glam::Mat4::from_quat(rotation));
+    let body_forces = match mass {
+      Mass::Infinite => vec![],
+      Mass::Finite(_) => vec![SingleBodyForce::ConstantAcceleration {
+        value: Direction::from_vec3(glam::vec3(0.0, -GRAVITY, 0.0)),
+      }],
+    };
+    let mut body = RigidBody {
+      name: name.to_string(),
+      mesh,
+      physics_info: RigidBodyInfo {
+        orientation,
+        prev_orientation: orientation,
+        ..RigidBodyInfo::default()
+      },
+      collision_mask: u32::MAX,
+      body_forces,
+      material: 0,
+      frozen: false,
+      kinematic: None,
+      carried_velocity: glam::Vec3::ZERO,
+    };
+    body.set_mass(mass);
+    self.insert_body(body);
+  }
+
+  // Keeps the body's forces, a body added with infinite mass doesn't start falling
+  pub fn set_mass(&mut self, name: &str, mass: Mass) -> Result<(), String> {
+    let body_idx = self.body_idx(name)?;
+    self.rigid_bodies[body_idx].set_mass(mass);
+    Ok(())
+  }
+
+  // Replaces the forces on the body, gravity included
+  pub fn set_body_forces(
+    &mut self,
+    name: &str,
+    forces: Vec<SingleBodyForce>,
+  ) -> Result<(), String> {
+    let body_idx = self.body_idx(name)?;
+    self.rigid_bodies[body_idx].body_forces = forces;
+    Ok(())
+  }
+
+  pub fn get_transform(&self, name: &str) -> Option<glam::Mat4> {
+    let body = &self.rigid_bodies[*self.rigid_body_names.get(name)?];
+    Some(body.physics_info.orientation.get_full_transform())
+  }
+
+  pub fn get_velocity(&self, name: &str) -> Option<glam::Vec3> {
+    Some(self.rigid_bodies[*self.rigid_body_names.get(name)?].physics_info.velocity)
+  }
+
+  pub fn set_velocity(&mut self, name: &str, velocity: glam::Vec3) -> Result<(), String> {
+    let body_idx = self.body_idx(name)?;
+    self.rigid_bodies[body_idx].physics_info.velocity = velocity;
+    Ok(())
+  }
+
+  // At a world space point, off center impulses spin the body too
+  pub fn apply_impulse(
+    &mut self,
+    name: &str,
+    impulse: glam::Vec3,
+    point: glam::Vec3,
+  ) -> Result<(), String> {
+    let body_idx = self.body_idx(name)?;
+    self.rigid_bodies[body_idx].physics_info.apply_impulse(impulse, point);
+    Ok(())
+  }
+
+  // Simulates time_us microseconds in fixed steps, what doesn't make up a whole step waits for the
+  // next call. Time past max_steps_per_update steps is dropped so a long hitch slows the world down
+  // instead of making the next frames longer too
+  pub fn run(&mut self, time_us: u128) {
+    self.pending_us += time_us;
+    for _ in 0..self.max_steps_per_update {
+      if self.pending_us < self.step_us {
+        return;
+      }
+      self.step();
+      self.pending_us -= self.step_us;
+    }
+    self.pending_us %= self.step_us;
+  }
+
+  // How far the time given to run is into the next step, for get_interpolated_transform
+  pub fn step_alpha(&self) -> f32 {
+    self.pending_us as f32 / self.step_us as f32
+  }
+
   fn solve_const_acc(d: f32, u: f32, a: f32) -> Vec<f32> {
     let mut roots = Vec::with_capacity(2);
 
@@ -110,26 +417,499 @@ impl PhysicsEngine {
     } else {
       let det = (u * u) - (2.0 * a * d);
       if det >= 0.0 {
-        let sqrt_det = det.sqrt();
-        let root_1 = (-u - sqrt_det) / a;
-        let root_2 = (-u + sqrt_det) / a;
-        if root_1 >= 0.0 {
-          roots.push(root_1);
+        // Without -u + sqrt(det), which cancels out to noise for the small root when a is small
+        let q = -0.5 * (u + det.sqrt().copysign(u));
+        let both_roots = if q == 0.0 { [0.0, 0.0] } else { [2.0 * q / a, d / q] };
+        roots.extend(both_roots.into_iter().filter(|x| x.is_finite() && *x >= 0.0));
+      }
+    }
+
+    roots
+  }
+
+  // Meant for level load, registering a name again replaces the material
+  pub fn register_material(&mut self, name: &str, material: PhysicsMaterial) -> usize {
+    self.materials.register_material(name, material)
+  }
+
+  // Overrides the combine rules for contacts between the two materials, in either order
+  pub fn set_material_pair(
+    &mut self,
+    material_1: &str,
+    material_2: &str,
+    rule: MaterialPairRule,
+  ) -> Result<(), String> {
+    self.materials.set_pair_rule(material_1, material_2, rule)
+  }
+
+  pub fn set_body_material(&mut self, body_name: &str, material_name: &str) -> Result<(), String> {
+    let material = self.materials.material_id(material_name)?;
+    let Some(&body_idx) = self.rigid_body_names.get(body_name) else {
+      return Err(format!("no rigid body named {body_name}"));
+    };
+    self.rigid_bodies[body_idx].material = material;
+    Ok(())
+  }
+
+  // Blend from the start of the last step at alpha 0 to its end at 1, alpha being how far the
+  // render time is into the next step
+  pub fn get_interpolated_transform(&self, name: &str, alpha: f32) -> Option<glam::Mat4> {
+    let info = &self.rigid_bodies[*self.rigid_body_names.get(name)?].physics_info;
+    let orientation = info.prev_orientation.interpolate(info.orientation, alpha.clamp(0.0, 1.0));
+    Some(orientation.get_full_transform())
+  }
+
+  // Moved by set_kinematic_target instead of forces and contacts. Its mass is infinite so contacts
+  // never move it, but it has the velocity of its motion so what it runs into gets pushed along
+  pub fn add_kinematic_body(
+    &mut self,
+    name: &str,
+    mesh: Vec<RigidBodyType>,
+    transform: glam::Mat4,
+  ) {
+    let (_, rotation, position) = transform.to_scale_rotation_translation();
+    let orientation = Orientation::new(position, glam::Mat4::from_quat(rotation));
+    let body = RigidBody {
+      name: name.to_string(),
+      mesh,
+      physics_info: RigidBodyInfo {
+        orientation,
+        prev_orientation: orientation,
+        ..RigidBodyInfo::default()
+      },
+      collision_mask: u32::MAX,
+      body_forces: vec![],
+      material: 0,
+      frozen: false,
+      kinematic: Some(KinematicTarget { orientation, time_left: 0.0, carry_riders: false }),
+      carried_velocity: glam::Vec3::ZERO,
+    };
+    self.insert_body(body);
+  }
+
+  // Where the kinematic body should be time_s from now, usually the animated transform for the
+  // next frame with the frame time. It moves there at a constant speed over the steps in between
+  // and stops when it arrives
+  pub fn set_kinematic_target(
+    &mut self,
+    name: &str,
+    transform: glam::Mat4,
+    time_s: f32,
+  ) -> Result<(), String> {
+    let Some(&body_idx) = self.rigid_body_names.get(name) else {
+      return Err(format!("no rigid body named {name}"));
+    };
+    let body = &mut self.rigid_bodies[body_idx];
+    let Some(target) = body.kinematic.as_mut() else {
+      return Err(format!("rigid body {name} is not kinematic"));
+    };
+    let (_, rotation, position) = transform.to_scale_rotation_translation();
+    target.orientation = Orientation::new(position, glam::Mat4::from_quat(rotation));
+    target.time_left = time_s.max(0.0);
+    Ok(())
+  }
+
+  // For platforms and lifts, not doors. Off for new kinematic bodies
+  pub fn set_carry_riders(&mut self, name: &str, carry_riders: bool) -> Result<(), String> {
+    let Some(&body_idx) = self.rigid_body_names.get(name) else {
+      return Err(format!("no rigid body named {name}"));
+    };
+    let Some(target) = self.rigid_bodies[body_idx].kinematic.as_mut() else {
+      return Err(format!("rigid body {name} is not kinematic"));
+    };
+    target.carry_riders = carry_riders;
+    Ok(())
+  }
+
+  // Bodies standing on a platform carrying riders get its velocity under them added, with what
+  // they got last time taken back off, so they move with it instead of dragging along on friction.
+  // Leaving it keeps the last of it, like jumping off a moving platform, until they land on
+  // something else
+  fn carry_riders(&mut self, contacts: &[ContactPoint]) {
+    let mut carried = vec![None; self.rigid_bodies.len()];
+    let mut landed = vec![false; self.rigid_bodies.len()];
+    for contact in contacts.iter() {
+      // Normals face from body_2 towards body_1, so up means body_1 stands on body_2
+      let sides = [
+        (contact.body_1, contact.body_2, contact.normal),
+        (contact.body_2, contact.body_1, -contact.normal),
+      ];
+      for (rider, platform, normal) in sides {
+        let rider_body = &self.rigid_bodies[rider];
+        if normal.dot(glam::Vec3::Y) < RIDER_MIN_UP
+          || rider_body.kinematic.is_some()
+          || rider_body.frozen
+          || matches!(rider_body.physics_info.mass, Mass::Infinite)
+        {
+          continue;
         }
-        if root_2 >= 0.0 {
-          roots.push(root_2);
+        let platform_body = &self.rigid_bodies[platform];
+        if !platform_body.kinematic.is_some_and(|x| x.carry_riders) {
+          landed[rider] = true;
+          continue;
         }
+        let info = &platform_body.physics_info;
+        let r = contact.point - info.orientation.position;
+        carried[rider] = Some(info.velocity + info.angular_velocity.cross(r));
       }
     }
+    for (body_idx, body) in self.rigid_bodies.iter_mut().enumerate() {
+      if let Some(carried) = carried[body_idx] {
+        body.physics_info.velocity += carried - body.carried_velocity;
+        body.carried_velocity = carried;
+      } else if landed[body_idx] {
+        body.carried_velocity = glam::Vec3::ZERO;
+      }
+    }
+  }
 
-    roots
+  // Sets kinematic bodies' velocities to reach their targets, for the step of step_time starting
+  fn drive_kinematic_bodies(&mut self, step_time: f32) {
+    for body in self.rigid_bodies.iter_mut() {
+      let Some(target) = body.kinematic.as_mut() else { continue };
+      let info = &mut body.physics_info;
+      info.acceleration = glam::Vec3::ZERO;
+      info.angular_acceleration = glam::Vec3::ZERO;
+      if target.time_left <= 0.0 {
+        // Arrived, drop what integrating the motion drifted by
+        info.orientation = target.orientation;
+        info.velocity = glam::Vec3::ZERO;
+        info.angular_velocity = glam::Vec3::ZERO;
+        continue;
+      }
+      let time_s = target.time_left.max(step_time);
+      info.velocity = (target.orientation.position - info.orientation.position) / time_s;
+      let rotation = glam::Quat::from_mat4(&target.orientation.rotation)
+        * glam::Quat::from_mat4(&info.orientation.rotation).inverse();
+      // The short way round
+      let rotation = if rotation.w < 0.0 { -rotation } else { rotation };
+      let (axis, angle) = rotation.to_axis_angle();
+      info.angular_velocity = axis * angle / time_s;
+      target.time_left -= step_time;
+    }
+  }
+
+  // Level collision goes in as a body of infinite mass so contacts with it go through the solver
+  // like any other pair. Adding a name again replaces the mesh
+  pub fn add_static_mesh(&mut self, name: &str, mesh: StaticTriangleMesh) {
+    if let Some(&body_idx) = self.rigid_body_names.get(name) {
+      self.static_meshes.insert(body_idx, mesh);
+      return;
+    }
+    self.rigid_bodies.push(RigidBody {
+      name: name.to_string(),
+      mesh: vec![],
+      physics_info: RigidBodyInfo::default(),
+      collision_mask: u32::MAX,
+      body_forces: vec![],
+      material: 0,
+      frozen: false,
+      kinematic: None,
+      carried_velocity: glam::Vec3::ZERO,
+    });
+    self.rigid_body_names.insert(name.to_string(), self.rigid_bodies.len() - 1);
+    self.static_meshes.insert(self.rigid_bodies.len() - 1, mesh);
+  }
+
+  // The mesh is viewed where the vfs has it, see StaticTriangleMesh::load
+  pub fn load_static_mesh(&mut self, name: &str, vfs: &vfs::Vfs, path: &str) -> Result<(), String> {
+    let mesh = StaticTriangleMesh::load(vfs, path)?;
+    self.add_static_mesh(name, mesh);
+    Ok(())
+  }
+
+  // Closest hit on any static mesh as (body name, distance)
+  pub fn raycast_static(
+    &self,
+    origin: glam::Vec3,
+    dir: glam::Vec3,
+    max_dist: f32,
+  ) -> Option<(&str, f32)> {
+    let dir = dir.normalize_or_zero();
+    self
+      .static_meshes
+      .iter()
+      .filter_map(|(&body_idx, mesh)| Some((body_idx, mesh.raycast(origin, dir, max_dist)?.0)))
+      .min_by(|a, b| a.1.total_cmp(&b.1))
+      .map(|(body_idx, dist)| (self.rigid_bodies[body_idx].name.as_str(), dist))
+  }
+
+  // Like raycast_static for a sphere, the distance is how far its center gets before touching.
+  // Cameras use it to stay off walls
+  pub fn spherecast_static(
+    &self,
+    origin: glam::Vec3,
+    dir: glam::Vec3,
+    radius: f32,
+    max_dist: f32,
+  ) -> Option<(&str, f32)> {
+    let dir = dir.normalize_or_zero();
+    self
+      .static_meshes
+      .iter()
+      .filter_map(|(&body_idx, mesh)| {
+        Some((body_idx, mesh.spherecast(origin, dir, radius, max_dist)?.0))
+      })
+      .min_by(|a, b| a.1.total_cmp(&b.1))
+      .map(|(body_idx, dist)| (self.rigid_bodies[body_idx].name.as_str(), dist))
+  }
+
+  // Closest body a projectile touches going from where it is to end. Moving bodies are tested by
+  // their box, level geometry by its triangles
+  fn sweep_projectile(&self, projectile: &Projectile, end: glam::Vec3) -> Option<ProjectileHit> {
+    let config = projectile.config();
+    let start = projectile.position();
+    let max_dist = start.distance(end);
+    let dir = (end - start).try_normalize()?;
+    let ignore_idx = config.ignore_body.as_ref().and_then(|x| self.rigid_body_names.get(x));
+    let mut closest: Option<(usize, f32, glam::Vec3)> = None;
+    for body_idx in 0..self.rigid_bodies.len() {
+      if Some(&body_idx) == ignore_idx
+        || self.rigid_bodies[body_idx].collision_mask & config.collision_mask == 0
+      {
+        continue;
+      }
+      let max_dist = closest.map_or(max_dist, |x| x.1);
+      let hit = match self.static_meshes.get(&body_idx) {
+        Some(mesh) => {
+          let mesh_hit = match config.radius > 0.0 {
+            true => mesh.spherecast(start, dir, config.radius, max_dist),
+            false => mesh.raycast(start, dir, max_dist),
+          };
+          mesh_hit.and_then(|(dist, triangle_idx)| {
+            let [a, b, c] = mesh.triangle(triangle_idx)?;
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            Some((dist, if normal.dot(dir) > 0.0 { -normal } else { normal }))
+          })
+        }
+        None => self.body_box(body_idx).and_then(|(min, max)| {
+          let reach = glam::Vec3::splat(config.radius);
+          projectile::ray_box(start, dir, max_dist, min - reach, max + reach)
+        }),
+      };
+      if let Some((dist, normal)) = hit {
+        closest = Some((body_idx, dist, normal));
+      }
+    }
+    let (body_idx, distance, normal) = closest?;
+    Some(ProjectileHit {
+      body: self.rigid_bodies[body_idx].name.clone(),
+      point: start + dir * distance - normal * config.radius,
+      normal,
+      distance,
+    })
+  }
+
+  // Returns the projectile's id. Projectiles aren't bodies, they only sweep through the world and
+  // despawn on the first thing they hit or once their lifetime is up
+  pub fn spawn_projectile(
+    &mut self,
+    position: glam::Vec3,
+    velocity: glam::Vec3,
+    config: ProjectileConfig,
+  ) -> u64 {
+    self.projectiles.spawn(position, velocity, config)
+  }
+
+  pub fn projectile(&self, id: u64) -> Option<&Projectile> {
+    self.projectiles.get(id)
+  }
+
+  // For drawing them
+  pub fn projectiles(&self) -> impl Iterator<Item = &Projectile> {
+    self.projectiles.iter()
+  }
+
+  // Takes the body out with its static mesh and the forces coupling it to others, false when
+  // there is none by that name. Bodies after it move down an index, so cached contacts are dropped
+  pub fn remove_body(&mut self, name: &str) -> bool {
+    let Some(body_idx) = self.rigid_body_names.remove(name) else {
+      return false;
+    };
+    self.rigid_bodies.remove(body_idx);
+    for idx in self.rigid_body_names.values_mut().filter(|idx| **idx > body_idx) {
+      *idx -= 1;
+    }
+    self.static_meshes = std::mem::take(&mut self.static_meshes)
+      .into_iter()
+      .filter(|(idx, _)| *idx != body_idx)
+      .map(|(idx, mesh)| (if idx > body_idx { idx - 1 } else { idx }, mesh))
+      .collect();
+    self.coupling_forces.retain(|(body_1, body_2), _| body_1 != name && body_2 != name);
+    self.solver.clear_warm_start();
+    self.last_contacts.clear();
+    true
+  }
+
+  // Static and dynamic, including the bodies standing in for static meshes
+  pub fn body_count(&self) -> usize {
+    self.rigid_bodies.len()
+  }
+
+  // Pairs the broad phase passed on to the narrow phase in the last step
+  pub fn contact_pair_count(&self) -> usize {
+    self.contact_pair_count
+  }
+
+  pub fn projectile_count(&self) -> usize {
+    self.projectiles.len()
+  }
+
+  // Gone without an event
+  pub fn despawn_projectile(&mut self, id: u64) -> bool {
+    self.projectiles.despawn(id).is_some()
+  }
+
+  pub fn clear_projectiles(&mut self) {
+    self.projectiles.clear();
+    self.projectile_events.clear();
+  }
+
+  // Impacts and expiries since the last call, in the order they happened
+  pub fn take_projectile_events(&mut self) -> Vec<ProjectileEvent> {
+    std::mem::take(&mut self.projectile_events)
+  }
+
+  // Vertices of the body's polygons pushed into a static mesh. Triangle soups have no inside, so
+  // only points just behind a face count
+  fn static_mesh_contacts(&self, body_idx: usize, static_idx: usize) -> Vec<ContactPoint> {
+    let body = &self.rigid_bodies[body_idx];
+    let mesh = &self.static_meshes[&static_idx];
+    if body.collision_mask & self.rigid_bodies[static_idx].collision_mask == 0 {
+      return vec![];
+    }
+    let transform = body.physics_info.orientation.get_full_transform();
+    let mut contacts = vec![];
+    for prim in body.mesh.iter() {
+      let RigidBodyType::PolygonFace(p_mesh) = prim else { continue };
+      for vert in p_mesh.get_verts().iter() {
+        let point = vert.transform(transform).as_vec3();
+        let Some(contact) = mesh.point_contact(point, STATIC_CONTACT_DEPTH) else { continue };
+        contacts.push(ContactPoint {
+          body_1: body_idx,
+          body_2: static_idx,
+          point,
+          normal: contact.normal,
+          penetration: contact.penetration,
+        });
+      }
+    }
+    contacts
+  }
+
+  pub fn solver_config(&self) -> SolverConfig {
+    self.solver.config()
+  }
+
+  pub fn set_solver_config(&mut self, config: SolverConfig) {
+    self.solver.set_config(config)
+  }
+
+  // Meant for debugging, checking every body every step isn't free
+  pub fn set_checked_math(&mut self, checked_math: CheckedMath) {
+    self.checked_math = checked_math;
+  }
+
+  pub fn is_body_frozen(&self, name: &str) -> bool {
+    self.rigid_body_names.get(name).is_some_and(|&body_idx| self.rigid_bodies[body_idx].frozen)
+  }
+
+  // Bodies are checked after every step, bad ones are logged with the contacts they had in it and
+  // frozen at where the step started if asked to
+  fn check_bodies(&mut self, contacts: &[ContactPoint]) {
+    if self.checked_math == CheckedMath::Off {
+      return;
+    }
+    for body_idx in 0..self.rigid_bodies.len() {
+      if self.rigid_bodies[body_idx].frozen {
+        continue;
+      }
+      let violations = checked_math::body_violations(&self.rigid_bodies[body_idx]);
+      if violations.is_empty() {
+        continue;
+      }
+      checked_math::report_violations(&self.rigid_bodies, body_idx, &violations, contacts);
+      if self.checked_math == CheckedMath::Freeze {
+        let body = &mut self.rigid_bodies[body_idx];
+        let info = &mut body.physics_info;
+        info.orientation = info.prev_orientation;
+        info.velocity = glam::Vec3::ZERO;
+        info.acceleration = glam::Vec3::ZERO;
+        info.angular_velocity = glam::Vec3::ZERO;
+        info.angular_acceleration = glam::Vec3::ZERO;
+        // Immovable to the solver, so contacts with it can't push bad values into other bodies
+        info.mass = Mass::Infinite;
+        info.moment_of_inertia = MomentOfInertia::Infinite;
+        body.frozen = true;
+        eprintln!("froze rigid body {}", body.name);
+      }
+    }
+  }
+
+  // Vertices of body i's polygons pushed into a face of body j, or in front of one close enough to
+  // reach it within time_s at the speed they're closing. Faces are one sided, so each vertex
+  // touches the face it is least deep behind. Normals face out of body j's faces
+  fn vertex_face_contacts(&self, i: usize, j: usize, time_s: f32) -> Vec<ContactPoint> {
+    let (body, other) = (&self.rigid_bodies[i], &self.rigid_bodies[j]);
+    let other_transform = other.physics_info.orientation.get_full_transform();
+    let faces = other
+      .mesh
+      .iter()
+      .filter_map(|prim| match prim {
+        RigidBodyType::PolygonFace(p_mesh) => Some(p_mesh.transformed(other_transform)),
+        RigidBodyType::Sphere(_) => None,
+      })
+      .collect::<Vec<_>>();
+    let transform = body.physics_info.orientation.get_full_transform();
+    let mut points: Vec<Point> = vec![];
+    for prim in body.mesh.iter() {
+      let RigidBodyType::PolygonFace(p_mesh) = prim else { continue };
+      for vert in p_mesh.get_verts().iter() {
+        let point = vert.transform(transform);
+        // Neighbouring faces share their corners
+        if !points.iter().any(|x| x.as_vec3() == point.as_vec3()) {
+          points.push(point);
+        }
+      }
+    }
+    let point_velocity = |info: &RigidBodyInfo, point: glam::Vec3| {
+      info.velocity + info.angular_velocity.cross(point - info.orientation.position)
+    };
+    let mut contacts = vec![];
+    for point in points {
+      let rel_vel = point_velocity(&body.physics_info, point.as_vec3())
+        - point_velocity(&other.physics_info, point.as_vec3());
+      let mut touching: Option<(f32, glam::Vec3)> = None;
+      for face in faces.iter() {
+        let normal = face.get_face().get_direction().as_vec3().normalize_or_zero();
+        let dist = face.get_face().dist_from_point(&point);
+        let reach = (-rel_vel.dot(normal)).max(0.0) * time_s + SPECULATIVE_MARGIN;
+        if dist < -STATIC_CONTACT_DEPTH
+          || dist > reach
+          || face.get_bound_planes().iter().any(|x| x.dist_from_point(&point) < 0.0)
+          || touching.is_some_and(|(closest, _)| closest >= dist)
+        {
+          continue;
+        }
+        touching = Some((dist, normal));
+      }
+      let Some((dist, normal)) = touching else { continue };
+      contacts.push(ContactPoint {
+        body_1: i,
+        body_2: j,
+        point: point.as_vec3(),
+        normal,
+        penetration: -dist,
+      });
+    }
+    contacts
   }
 
   pub fn plane_slip_time(
     point: Point,
     point_vel: glam::Vec3,
     point_acc: glam::Vec3,
-    plane: Plane,
+    _plane: Plane,
     bounds: &[Plane],
     plane_vel: glam::Vec3,
     plane_acc: glam::Vec3,
@@ -141,7 +921,7 @@ impl PhysicsEngine {
       )
       .map(|x| x.0)
       .collect::<Vec<f32>>();
-    coll_times.iter().reduce(f32::max).copied()
+    coll_times.into_iter().reduce(f32::max)
   }
 
   pub fn plane_point_coll_time(
@@ -163,7 +943,7 @@ impl PhysicsEngine {
         point_rel_acc.dot(plane.get_direction().as_vec3()),
       );
 
-      let Some(time_s) = coll_times else { return None };
+      let time_s = coll_times.into_iter().reduce(f32::min)?;
       let point_displacement = point_vel * time_s + 0.5 * point_acc * time_s * time_s;
       let plane_displacement = plane_vel * time_s + 0.5 * plane_acc * time_s * time_s;
       let vert_fwd = point.displace(point_displacement);
@@ -197,17 +977,18 @@ impl PhysicsEngine {
     } else {
       perp_plane
     };
+    // Segment 2's start against the plane through segment 1, moving with their own motions
     let coll_time_opt = Self::plane_point_coll_time(
       line_segment_2.get_start(),
-      vel_1,
-      acc_1,
-      perp_plane,
-      &[],
       vel_2,
       acc_2,
+      perp_plane,
+      &[],
+      vel_1,
+      acc_1,
     );
 
-    let Some((coll_time, displacement_2, displacement_1)) = coll_time_opt else { return None };
+    let (coll_time, displacement_2, displacement_1) = coll_time_opt?;
 
     let displaced_ls_1 = line_segment_1.displace(displacement_1);
     let displaced_ls_2 = line_segment_2.displace(displacement_2);
@@ -241,24 +1022,24 @@ impl PhysicsEngine {
     let body_1_transform = body_1.physics_info.orientation.get_full_transform();
     let body_2_transform = body_2.physics_info.orientation.get_full_transform();
 
-    if !(body_1.collision_mask & body_2.collision_mask) {
+    if body_1.collision_mask & body_2.collision_mask == 0 {
       return (min_collision_time, collision_plane, collision_point);
     }
     for prim_1 in body_1.mesh.iter() {
       for prim_2 in body_2.mesh.iter() {
         match prim_1 {
-          RigidBodyType::PolygonPlane(p_mesh_1) => match &prim_2 {
-            RigidBodyType::PolygonPlane(p_mesh_2) => {
+          RigidBodyType::PolygonFace(p_mesh_1) => match &prim_2 {
+            RigidBodyType::PolygonFace(p_mesh_2) => {
               let transformed_mesh_1 = p_mesh_1.transformed(body_1_transform);
               let transformed_mesh_2 = p_mesh_2.transformed(body_2_transform);
 
-              for vert_2 in p_mesh_2.get_vertices().iter() {
+              for vert_2 in transformed_mesh_2.get_verts().iter() {
                 let point_coll_time = Self::plane_point_coll_time(
                   *vert_2,
                   body_2.physics_info.velocity,
                   body_2.physics_info.acceleration,
                   transformed_mesh_1.get_face(),
-                  &transformed_mesh_1.get_bound_planes(),
+                  transformed_mesh_1.get_bound_planes(),
                   body_1.physics_info.velocity,
                   body_1.physics_info.acceleration,
                 );
@@ -272,13 +1053,13 @@ impl PhysicsEngine {
                 }
               }
 
-              for vert_1 in p_mesh_1.get_vertices().iter() {
+              for vert_1 in transformed_mesh_1.get_verts().iter() {
                 let point_coll_time = Self::plane_point_coll_time(
                   *vert_1,
                   body_1.physics_info.velocity,
                   body_1.physics_info.acceleration,
                   transformed_mesh_2.get_face(),
-                  &transformed_mesh_2.get_bound_planes(),
+                  transformed_mesh_2.get_bound_planes(),
                   body_2.physics_info.velocity,
                   body_2.physics_info.acceleration,
                 );
@@ -326,21 +1107,391 @@ impl PhysicsEngine {
     (min_collision_time, collision_plane, collision_point)
   }
 
-  pub fn run_one_ms(&mut self) {
-    let mut min_collision_time = f32::MAX;
-    let mut remaining_sim_time = 0.001;
-    let mut coll_details = (0..self.rigid_bodies.len())
-      .map(|_| Vec::with_capacity(self.rigid_bodies.len()))
+  // World space box around the body's polygons, None for bodies without any
+  fn body_box(&self, body_idx: usize) -> Option<(glam::Vec3, glam::Vec3)> {
+    let body = &self.rigid_bodies[body_idx];
+    let transform = body.physics_info.orientation.get_full_transform();
+    let mut bounds: Option<(glam::Vec3, glam::Vec3)> = None;
+    for prim in body.mesh.iter() {
+      let RigidBodyType::PolygonFace(p_mesh) = prim else { continue };
+      for vert in p_mesh.get_verts().iter() {
+        let point = vert.transform(transform).as_vec3();
+        bounds = Some(bounds.map_or((point, point), |(min, max)| (min.min(point), max.max(point))));
+      }
+    }
+    bounds
+  }
+
+  // Box around where the body's polygons can get to within time_s, padded by the static contact
+  // depth. None for bodies without polygons
+  fn swept_box(&self, body_idx: usize, time_s: f32) -> Option<(glam::Vec3, glam::Vec3)> {
+    let info = &self.rigid_bodies[body_idx].physics_info;
+    let (min, max) = self.body_box(body_idx)?;
+    let radius = (max - min).length() * 0.5;
+    let disp = info.velocity * time_s + 0.5 * info.acceleration * time_s * time_s;
+    let spin = (info.angular_velocity.length() * time_s
+      + 0.5 * info.angular_acceleration.length() * time_s * time_s)
+      * radius;
+    let pad = glam::Vec3::splat(spin + STATIC_CONTACT_DEPTH);
+    Some((min.min(min + disp) - pad, max.max(max + disp) + pad))
+  }
+
+  // Pairs of bodies that may touch within time_s, the broad phase. Body pairs are the ones whose
+  // swept boxes overlap and that aren't both immovable, static mesh pairs the ones whose swept box
+  // reaches the mesh
+  fn contact_pairs(&self, time_s: f32) -> Result<Vec<(usize, usize)>, String> {
+    let swept_boxes = (0..self.rigid_bodies.len())
+      .map(|body_idx| match self.static_meshes.contains_key(&body_idx) {
+        true => None,
+        false => self.swept_box(body_idx, time_s),
+      })
+      .collect::<Vec<_>>();
+    let movable = self
+      .rigid_bodies
+      .iter()
+      .map(|x| x.kinematic.is_some() || matches!(x.physics_info.mass, Mass::Finite(_)))
+      .collect::<Vec<_>>();
+    // One job per body checks it against every body after it, jobs only read the boxes
+    let body_idxs = (0..self.rigid_bodies.len()).collect::<Vec<_>>();
+    let coll_rows = jobs::global()
+      .map(&body_idxs, |&i| {
+        let Some((min_i, max_i)) = swept_boxes[i] else { return vec![] };
+        (i + 1..swept_boxes.len())
+          .filter(|&j| {
+            let Some((min_j, max_j)) = swept_boxes[j] else { return false };
+            (movable[i] || movable[j])
+              && self.rigid_bodies[i].collision_mask & self.rigid_bodies[j].collision_mask != 0
+              && min_i.cmple(max_j).all()
+              && min_j.cmple(max_i).all()
+          })
+          .collect::<Vec<_>>()
+      })
+      .map_err(|e| format!("at checking body collisions: {e}"))?;
+    let mut pairs = vec![];
+    for (i, coll_row) in coll_rows.into_iter().enumerate() {
+      pairs.extend(coll_row.into_iter().map(|j| (i, j)));
+    }
+    for (&static_idx, mesh) in self.static_meshes.iter() {
+      for body_idx in 0..self.rigid_bodies.len() {
+        if self.static_meshes.contains_key(&body_idx)
+          || self.rigid_bodies[body_idx].collision_mask
+            & self.rigid_bodies[static_idx].collision_mask
+            == 0
+        {
+          continue;
+        }
+        let Some((min, max)) = self.swept_box(body_idx, time_s) else { continue };
+        if !mesh.triangles_in_box(min, max).is_empty() {
+          pairs.push((body_idx, static_idx));
+        }
+      }
+    }
+    Ok(pairs)
+  }
+
+  // Bodies with their box within radius of center and the point of it closest to center. Level
+  // geometry isn't included
+  fn bodies_in_sphere(&self, center: glam::Vec3, radius: f32) -> Vec<(usize, glam::Vec3)> {
+    (0..self.rigid_bodies.len())
+      .filter(|body_idx| !self.static_meshes.contains_key(body_idx))
+      .filter_map(|body_idx| {
+        let (min, max) = self.body_box(body_idx)?;
+        let closest = center.clamp(min, max);
+        (closest.distance_squared(center) <= radius * radius).then_some((body_idx, closest))
+      })
+      .collect()
+  }
+
+  // Names of bodies touching the sphere, for things like explosion damage
+  pub fn overlap_sphere(&self, center: glam::Vec3, radius: f32) -> Vec<&str> {
+    self
+      .bodies_in_sphere(center, radius)
+      .into_iter()
+      .map(|(body_idx, _)| self.rigid_bodies[body_idx].name.as_str())
+      .collect()
+  }
+
+  // Pushes bodies in the sphere away from center, at the point of each closest to it so off
+  // center hits spin them. Kinematic and frozen bodies aren't moved
+  pub fn apply_radial_impulse(
+    &mut self,
+    center: glam::Vec3,
+    radius: f32,
+    strength: f32,
+    falloff: RadialFalloff,
+  ) {
+    if radius <= 0.0 {
+      return;
+    }
+    for (body_idx, point) in self.bodies_in_sphere(center, radius) {
+      let body = &mut self.rigid_bodies[body_idx];
+      if body.kinematic.is_some() || body.frozen {
+        continue;
+      }
+      let info = &mut body.physics_info;
+      // From inside the body the push goes out through its center
+      let dir = match (point - center).try_normalize() {
+        Some(dir) => dir,
+        None => (info.orientation.position - center).normalize_or(glam::Vec3::Y),
+      };
+      let scale = falloff.scale(point.distance(center) / radius);
+      info.apply_impulse(dir * strength * scale, point);
+    }
+  }
+
+  // Water and other fluids bodies float in, meant for level load. Returns the volume's index
+  pub fn add_fluid_volume(&mut self, volume: FluidVolume) -> usize {
+    self.fluid_volumes.push(volume);
+    self.fluid_volumes.len() - 1
+  }
+
+  pub fn clear_fluid_volumes(&mut self) {
+    self.fluid_volumes.clear();
+  }
+
+  // Slow motion around a point, returns the zone's index. Contacts still resolve at the full step,
+  // only how far bodies in the zone move is scaled
+  pub fn add_time_zone(&mut self, zone: TimeZone) -> usize {
+    self.time_zones.push(zone);
+    self.time_zones.len() - 1
+  }
+
+  // For moving or easing a zone in and out
+  pub fn time_zone_mut(&mut self, zone_idx: usize) -> Option<&mut TimeZone> {
+    self.time_zones.get_mut(zone_idx)
+  }
+
+  pub fn clear_time_zones(&mut self) {
+    self.time_zones.clear();
+  }
+
+  // How fast the body's time runs, for slowing its animation and sounds to match
+  pub fn body_time_scale(&self, name: &str) -> Option<f32> {
+    let body = &self.rigid_bodies[*self.rigid_body_names.get(name)?];
+    Some(time_zone::time_scale_at(&self.time_zones, body.physics_info.orientation.position))
+  }
+
+  // Pushes bodies in fluid volumes up by what they displace and slows them by how much of them is
+  // under, for time_s. Only bodies moved by forces and contacts float
+  fn apply_buoyancy(&mut self, time_s: f32) {
+    if self.fluid_volumes.is_empty() {
+      return;
+    }
+    for body_idx in 0..self.rigid_bodies.len() {
+      let body = &self.rigid_bodies[body_idx];
+      if body.kinematic.is_some()
+        || body.frozen
+        || self.static_meshes.contains_key(&body_idx)
+        || matches!(body.physics_info.mass, Mass::Infinite)
+      {
+        continue;
+      }
+      let samples = buoyancy::volume_samples(&body.mesh);
+      let transform = body.physics_info.orientation.get_full_transform();
+      for fluid in self.fluid_volumes.iter() {
+        let submersion = fluid.submersion(&samples, transform);
+        if submersion.volume <= 0.0 {
+          continue;
+        }
+        let info = &mut self.rigid_bodies[body_idx].physics_info;
+        info.apply_impulse(fluid.buoyant_force(&submersion) * time_s, submersion.center);
+        info.velocity *= (1.0 - fluid.linear_drag * submersion.fraction * time_s).max(0.0);
+        info.angular_velocity *= (1.0 - fluid.angular_drag * submersion.fraction * time_s).max(0.0);
+      }
+    }
+  }
+
+  // Returns the rope's index, ropes are stepped after the bodies so their ends follow them
+  pub fn add_rope(&mut self, rope: Rope) -> usize {
+    self.ropes.push(rope);
+    self.ropes.len() - 1
+  }
+
+  pub fn rope(&self, rope_idx: usize) -> Option<&Rope> {
+    self.ropes.get(rope_idx)
+  }
+
+  pub fn rope_mut(&mut self, rope_idx: usize) -> Option<&mut Rope> {
+    self.ropes.get_mut(rope_idx)
+  }
+
+  pub fn clear_ropes(&mut self) {
+    self.ropes.clear();
+  }
+
+  // None when the body it's on is gone
+  fn anchor_position(&self, anchor: &RopeAnchor) -> Option<glam::Vec3> {
+    match anchor {
+      RopeAnchor::World(position) => Some(*position),
+      RopeAnchor::Body { name, offset } => {
+        let body = &self.rigid_bodies[*self.rigid_body_names.get(name)?];
+        Some(body.physics_info.orientation.get_full_transform().transform_point3(*offset))
+      }
+    }
+  }
+
+  fn step_ropes(&mut self, time_s: f32) {
+    for rope_idx in 0..self.ropes.len() {
+      let (start, end) = self.ropes[rope_idx].anchors();
+      let start = start.and_then(|x| self.anchor_position(x));
+      let end = end.and_then(|x| self.anchor_position(x));
+      self.ropes[rope_idx].step(time_s, start, end, self.static_meshes.values());
+    }
+  }
+
+  // Returns the vehicle's index. Its body should already be added, with its mass and inertia
+  pub fn add_vehicle(&mut self, vehicle: Vehicle) -> Result<usize, String> {
+    if !self.rigid_body_names.contains_key(vehicle.body()) {
+      return Err(format!("vehicle body {} not found", vehicle.body()));
+    }
+    self.vehicles.push(vehicle);
+    Ok(self.vehicles.len() - 1)
+  }
+
+  pub fn vehicle(&self, vehicle_idx: usize) -> Option<&Vehicle> {
+    self.vehicles.get(vehicle_idx)
+  }
+
+  // For setting the inputs every frame
+  pub fn vehicle_mut(&mut self, vehicle_idx: usize) -> Option<&mut Vehicle> {
+    self.vehicles.get_mut(vehicle_idx)
+  }
+
+  pub fn clear_vehicles(&mut self) {
+    self.vehicles.clear();
+  }
+
+  // Where to draw the vehicle's wheels this step, None when it or its body is gone
+  pub fn vehicle_wheel_transforms(&self, vehicle_idx: usize) -> Option<Vec<glam::Mat4>> {
+    let vehicle = self.vehicles.get(vehicle_idx)?;
+    let body = &self.rigid_bodies[*self.rigid_body_names.get(vehicle.body())?];
+    Some(vehicle.wheel_transforms(body.physics_info.orientation.get_full_transform()))
+  }
+
+  // Wheels only stand on level geometry. Frozen and kinematic bodies keep their wheels still
+  fn step_vehicles(&mut self, time_s: f32) {
+    for vehicle_idx in 0..self.vehicles.len() {
+      let Some(&body_idx) = self.rigid_body_names.get(self.vehicles[vehicle_idx].body()) else {
+        continue;
+      };
+      let body = &self.rigid_bodies[body_idx];
+      if body.frozen || body.kinematic.is_some() {
+        continue;
+      }
+      let info = body.physics_info;
+      let transform = info.orientation.get_full_transform();
+      let hits = self.vehicles[vehicle_idx]
+        .wheel_rays(transform)
+        .into_iter()
+        .map(|(origin, dir, max_dist)| Some(self.raycast_static(origin, dir, max_dist)?.1))
+        .collect::<Vec<_>>();
+      let impulses = self.vehicles[vehicle_idx].step(
+        time_s,
+        transform,
+        info.orientation.position,
+        info.velocity,
+        info.angular_velocity,
+        &hits,
+      );
+      for (impulse, point) in impulses {
+        self.rigid_bodies[body_idx].physics_info.apply_impulse(impulse, point);
+      }
+    }
+  }
+
+  // Contacts of the given pairs touching within time_s, the narrow phase
+  fn pair_contacts(&self, pairs: &[(usize, usize)], time_s: f32) -> Vec<ContactPoint> {
+    let mut contacts = vec![];
+    for &(i, j) in pairs.iter() {
+      if self.static_meshes.contains_key(&j) {
+        contacts.extend(self.static_mesh_contacts(i, j));
+        continue;
+      }
+      contacts.extend(self.vertex_face_contacts(i, j, time_s));
+      contacts.extend(self.vertex_face_contacts(j, i, time_s));
+    }
+    contacts
+  }
+
+  pub fn substeps(&self) -> usize {
+    self.substeps.max(1)
+  }
+
+  // Splits every step into this many solves and integrations. Pairs are only looked for once a
+  // step, so fast scenes get steadier without paying for the broad phase again
+  pub fn set_substeps(&mut self, substeps: usize) {
+    self.substeps = substeps.max(1);
+  }
+
+  // Forces are the only thing setting acceleration, bodies without any keep what they were given
+  fn apply_body_forces(&mut self) {
+    for body in self.rigid_bodies.iter_mut() {
+      let Mass::Finite(mass) = body.physics_info.mass else { continue };
+      if body.kinematic.is_some() || body.frozen || body.body_forces.is_empty() {
+        continue;
+      }
+      body.physics_info.acceleration = body
+        .body_forces
+        .iter()
+        .map(|force| match force {
+          SingleBodyForce::ConstantForce { value } => value.as_vec3() / mass,
+          SingleBodyForce::ConstantAcceleration { value } => value.as_vec3(),
+        })
+        .sum();
+    }
+  }
+
+  fn step(&mut self) {
+    profile_scope!("physics_step");
+    for body in self.rigid_bodies.iter_mut() {
+      body.physics_info.prev_orientation = body.physics_info.orientation;
+    }
+    let step_time = self.step_time;
+    self.apply_body_forces();
+    self.drive_kinematic_bodies(step_time);
+    let pairs = match self.contact_pairs(step_time) {
+      Ok(pairs) => pairs,
+      Err(e) => {
+        eprintln!("{e}");
+        return;
+      }
+    };
+    self.contact_pair_count = pairs.len();
+    // Each sub-step only redoes contacts for the pairs found above, the solver's warm start
+    // carries their impulses over from the sub-step before
+    let substeps = self.substeps();
+    let substep_time = step_time / substeps as f32;
+    // Zones are looked up once a step, like the pairs
+    let time_scales = self
+      .rigid_bodies
+      .iter()
+      .map(|x| time_zone::time_scale_at(&self.time_zones, x.physics_info.orientation.position))
       .collect::<Vec<_>>();
-    while remaining_sim_time > 0.0 {
-      for i in 0..self.rigid_bodies.len() {
-        for j in i + 1..self.rigid_bodies.len() {
-          let (body_coll_time, collision_plane, collision_point) =
-            Self::rigid_body_coll_time(&self.rigid_bodies[i], &self.rigid_bodies[j]);
-          coll_details[i][j] = (body_coll_time, collision_plane, collision_point);
-          coll_details[j][i] = (body_coll_time, collision_plane, collision_point);
+    let mut contacts = vec![];
+    for _ in 0..substeps {
+      // Everything touching within the sub-step goes to the solver together
+      contacts = self.pair_contacts(&pairs, substep_time);
+      self.carry_riders(&contacts);
+      self.apply_buoyancy(substep_time);
+      self.step_vehicles(substep_time);
+      self.solver.solve(&mut self.rigid_bodies, &contacts, &self.materials, substep_time);
+      for (body, time_scale) in self.rigid_bodies.iter_mut().zip(time_scales.iter()) {
+        if !body.frozen {
+          body.physics_info.update(substep_time * time_scale, vec![]);
         }
       }
+      self.step_ropes(substep_time);
     }
+    // Once a step against where the bodies ended up, projectiles don't push back
+    let mut projectiles = std::mem::take(&mut self.projectiles);
+    let events = projectiles.step(
+      step_time,
+      |x| time_zone::time_scale_at(&self.time_zones, x),
+      |x, end| self.sweep_projectile(x, end),
+    );
+    self.projectiles = projectiles;
+    self.projectile_events.extend(events);
+    self.check_bodies(&contacts);
+    self.last_contacts = contacts;
   }
 }
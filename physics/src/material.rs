@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+pub const DEFAULT_MATERIAL: &str = "default";
+
+// How the values of two touching materials are combined. When the two materials have different
+// rules the later one in this list wins
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CombineRule {
+  Average,
+  Min,
+  Multiply,
+  Max,
+}
+
+impl CombineRule {
+  pub fn combine(&self, a: f32, b: f32) -> f32 {
+    match self {
+      CombineRule::Average => (a + b) * 0.5,
+      CombineRule::Min => a.min(b),
+      CombineRule::Multiply => a * b,
+      CombineRule::Max => a.max(b),
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhysicsMaterial {
+  // 0 keeps none of the approach speed, 1 bounces back at the same speed
+  pub restitution: f32,
+  // Coulomb friction coefficient, the most tangential impulse per unit of normal impulse
+  pub friction: f32,
+  pub restitution_combine: CombineRule,
+  pub friction_combine: CombineRule,
+}
+
+impl Default for PhysicsMaterial {
+  fn default() -> Self {
+    Self {
+      restitution: 0.0,
+      friction: 0.5,
+      restitution_combine: CombineRule::Average,
+      friction_combine: CombineRule::Average,
+    }
+  }
+}
+
+// Overrides for one pair of materials, None falls back to the combine rules
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct MaterialPairRule {
+  pub restitution: Option<f32>,
+  pub friction: Option<f32>,
+}
+
+// What a contact between two materials resolves with
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ContactMaterial {
+  pub restitution: f32,
+  pub friction: f32,
+}
+
+// Materials by name and the pair overrides between them. Pairs are unordered, ice on metal is the
+// same rule as metal on ice. Always has DEFAULT_MATERIAL as id 0
+#[derive(Debug, Clone)]
+pub struct MaterialTable {
+  materials: Vec<PhysicsMaterial>,
+  material_ids: HashMap<String, usize>,
+  pair_rules: HashMap<(usize, usize), MaterialPairRule>,
+}
+
+impl Default for MaterialTable {
+  fn default() -> Self {
+    Self {
+      materials: vec![PhysicsMaterial::default()],
+      material_ids: HashMap::from([(DEFAULT_MATERIAL.to_string(), 0)]),
+      pair_rules: HashMap::new(),
+    }
+  }
+}
+
+impl MaterialTable {
+  fn pair_key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+  }
+
+  // Registering a name again replaces the material, bodies and pair rules using it keep its id
+  pub fn register_material(&mut self, name: &str, material: PhysicsMaterial) -> usize {
+    if let Some(&id) = self.material_ids.get(name) {
+      self.materials[id] = material;
+      return id;
+    }
+    self.materials.push(material);
+    self.material_ids.insert(name.to_string(), self.materials.len() - 1);
+    self.materials.len() - 1
+  }
+
+  pub fn material_id(&self, name: &str) -> Result<usize, String> {
+    self.material_ids.get(name).copied().ok_or(format!("no physics material named {name}"))
+  }
+
+  pub fn set_pair_rule(&mut self, a: &str, b: &str, rule: MaterialPairRule) -> Result<(), String> {
+    let key = Self::pair_key(self.material_id(a)?, self.material_id(b)?);
+    self.pair_rules.insert(key, rule);
+    Ok(())
+  }
+
+  pub fn clear_pair_rule(&mut self, a: &str, b: &str) -> Result<(), String> {
+    let key = Self::pair_key(self.material_id(a)?, self.material_id(b)?);
+    self.pair_rules.remove(&key);
+    Ok(())
+  }
+
+  // Unknown ids resolve as the default material
+  pub fn contact(&self, a: usize, b: usize) -> ContactMaterial {
+    let material_a = self.materials.get(a).unwrap_or(&self.materials[0]);
+    let material_b = self.materials.get(b).unwrap_or(&self.materials[0]);
+    let rule = self.pair_rules.get(&Self::pair_key(a, b)).copied().unwrap_or_default();
+    let restitution_combine = material_a.restitution_combine.max(material_b.restitution_combine);
+    let friction_combine = material_a.friction_combine.max(material_b.friction_combine);
+    ContactMaterial {
+      restitution: rule
+        .restitution
+        .unwrap_or(restitution_combine.combine(material_a.restitution, material_b.restitution)),
+      friction: rule
+        .friction
+        .unwrap_or(friction_combine.combine(material_a.friction, material_b.friction)),
+    }
+  }
+}
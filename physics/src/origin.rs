@@ -0,0 +1,54 @@
+use geometry::glam;
+use geometry::world::{origin_shift, world_position};
+
+use crate::PhysicsEngine;
+
+impl PhysicsEngine {
+  // Where in the world the engine's positions are measured from
+  pub fn origin(&self) -> glam::DVec3 {
+    self.origin
+  }
+
+  // Only says where what's in the engine is measured from, nothing moves. For engines filled from
+  // a scene that isn't at the world's zero
+  pub fn set_origin(&mut self, origin: glam::DVec3) {
+    self.origin = origin;
+  }
+
+  // Moves the origin by shift, everything in the engine moves by -shift so it stays where it is in
+  // the world and the next step carries on as if nothing happened. All of it moves in the one call
+  // between steps, contacts cached for warm starting included
+  pub fn shift_origin(&mut self, shift: glam::Vec3) {
+    let offset = -shift;
+    for body in self.rigid_bodies.iter_mut() {
+      body.physics_info.orientation.position += offset;
+      body.physics_info.prev_orientation.position += offset;
+      if let Some(target) = body.kinematic.as_mut() {
+        target.orientation.position += offset;
+      }
+    }
+    self.static_meshes.values_mut().for_each(|x| x.translate(offset));
+    self.ropes.iter_mut().for_each(|x| x.translate(offset));
+    self.fluid_volumes.iter_mut().for_each(|x| x.region.translate(offset));
+    self.time_zones.iter_mut().for_each(|x| x.center += offset);
+    self.projectiles.translate(offset);
+    self.solver.translate(offset);
+    self.last_contacts.iter_mut().for_each(|x| x.point += offset);
+    self.origin += shift.as_dvec3();
+  }
+
+  // Shifts the origin to under the body once it's more than max_distance out, the player's body
+  // say. Returns the shift so whatever else is placed relative to the origin can move with it
+  pub fn recenter_on(&mut self, name: &str, max_distance: f32) -> Option<glam::Vec3> {
+    let body = &self.rigid_bodies[*self.rigid_body_names.get(name)?];
+    let shift = origin_shift(body.physics_info.orientation.position, max_distance)?;
+    self.shift_origin(shift);
+    Some(shift)
+  }
+
+  // Exact however far out the body is, the f32 position alone is only good near the origin
+  pub fn world_position(&self, name: &str) -> Option<glam::DVec3> {
+    let body = &self.rigid_bodies[*self.rigid_body_names.get(name)?];
+    Some(world_position(self.origin, body.physics_info.orientation.position))
+  }
+}
@@ -0,0 +1,204 @@
+use crate::GRAVITY;
+use geometry::glam;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct ProjectileConfig {
+  // 0 for a ray, otherwise swept as a sphere
+  pub radius: f32,
+  // Share of gravity that pulls on it, 0 flies straight
+  pub gravity_scale: f32,
+  // Share of velocity lost per second
+  pub drag: f32,
+  pub lifetime_s: f32,
+  // Checked against body collision masks like bodies are against each other
+  pub collision_mask: u32,
+  // Usually the shooter, so shots don't hit what fired them
+  pub ignore_body: Option<String>,
+}
+
+impl Default for ProjectileConfig {
+  fn default() -> Self {
+    Self {
+      radius: 0.0,
+      gravity_scale: 1.0,
+      drag: 0.0,
+      lifetime_s: 5.0,
+      collision_mask: u32::MAX,
+      ignore_body: None,
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Projectile {
+  id: u64,
+  position: glam::Vec3,
+  velocity: glam::Vec3,
+  age_s: f32,
+  config: ProjectileConfig,
+}
+
+impl Projectile {
+  pub fn id(&self) -> u64 {
+    self.id
+  }
+
+  pub fn position(&self) -> glam::Vec3 {
+    self.position
+  }
+
+  pub fn velocity(&self) -> glam::Vec3 {
+    self.velocity
+  }
+
+  pub fn config(&self) -> &ProjectileConfig {
+    &self.config
+  }
+}
+
+// What a sweep ran into, the point is where the projectile's surface touched
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectileHit {
+  pub body: String,
+  pub point: glam::Vec3,
+  pub normal: glam::Vec3,
+  // How far along the sweep the projectile's center got
+  pub distance: f32,
+}
+
+// Projectiles are gone by the time their event is seen
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProjectileEvent {
+  Impact { projectile: u64, hit: ProjectileHit, velocity: glam::Vec3 },
+  Expired { projectile: u64, position: glam::Vec3 },
+}
+
+// Slots are kept once made, so firing doesn't allocate after the first volley
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProjectilePool {
+  slots: Vec<Option<Projectile>>,
+  free_slots: Vec<usize>,
+  slot_by_id: HashMap<u64, usize>,
+  next_id: u64,
+}
+
+impl ProjectilePool {
+  pub(crate) fn spawn(
+    &mut self,
+    position: glam::Vec3,
+    velocity: glam::Vec3,
+    config: ProjectileConfig,
+  ) -> u64 {
+    let id = self.next_id;
+    self.next_id += 1;
+    let projectile = Projectile { id, position, velocity, age_s: 0.0, config };
+    let slot = match self.free_slots.pop() {
+      Some(slot) => {
+        self.slots[slot] = Some(projectile);
+        slot
+      }
+      None => {
+        self.slots.push(Some(projectile));
+        self.slots.len() - 1
+      }
+    };
+    self.slot_by_id.insert(id, slot);
+    id
+  }
+
+  pub(crate) fn get(&self, id: u64) -> Option<&Projectile> {
+    self.slots[*self.slot_by_id.get(&id)?].as_ref()
+  }
+
+  pub(crate) fn despawn(&mut self, id: u64) -> Option<Projectile> {
+    let slot = self.slot_by_id.remove(&id)?;
+    self.free_slots.push(slot);
+    self.slots[slot].take()
+  }
+
+  pub(crate) fn iter(&self) -> impl Iterator<Item = &Projectile> {
+    self.slots.iter().flatten()
+  }
+
+  pub(crate) fn translate(&mut self, offset: glam::Vec3) {
+    self.slots.iter_mut().flatten().for_each(|projectile| projectile.position += offset);
+  }
+
+  pub(crate) fn len(&self) -> usize {
+    self.slot_by_id.len()
+  }
+
+  pub(crate) fn clear(&mut self) {
+    let ids = self.slot_by_id.keys().copied().collect::<Vec<_>>();
+    for id in ids {
+      self.despawn(id);
+    }
+  }
+
+  // Moves every projectile for time_s scaled by time_scale at where it is, sweep gets the
+  // projectile and where it would end up and returns what it hits on the way. Hit and expired
+  // projectiles are despawned
+  pub(crate) fn step(
+    &mut self,
+    time_s: f32,
+    time_scale: impl Fn(glam::Vec3) -> f32,
+    sweep: impl Fn(&Projectile, glam::Vec3) -> Option<ProjectileHit>,
+  ) -> Vec<ProjectileEvent> {
+    let mut events = vec![];
+    let mut despawned = vec![];
+    for projectile in self.slots.iter_mut().flatten() {
+      let time_s = time_s * time_scale(projectile.position);
+      let config = &projectile.config;
+      projectile.velocity += glam::Vec3::NEG_Y * GRAVITY * config.gravity_scale * time_s;
+      projectile.velocity *= (1.0 - config.drag * time_s).max(0.0);
+      let end = projectile.position + projectile.velocity * time_s;
+      if let Some(hit) = sweep(projectile, end) {
+        let velocity = projectile.velocity;
+        events.push(ProjectileEvent::Impact { projectile: projectile.id, hit, velocity });
+        despawned.push(projectile.id);
+        continue;
+      }
+      projectile.position = end;
+      projectile.age_s += time_s;
+      if projectile.age_s >= config.lifetime_s {
+        let position = projectile.position;
+        events.push(ProjectileEvent::Expired { projectile: projectile.id, position });
+        despawned.push(projectile.id);
+      }
+    }
+    for id in despawned {
+      self.despawn(id);
+    }
+    events
+  }
+}
+
+// Entry distance and face normal of a ray into a box, None if it misses, enters past max_dist
+// or starts inside
+pub(crate) fn ray_box(
+  origin: glam::Vec3,
+  dir: glam::Vec3,
+  max_dist: f32,
+  min: glam::Vec3,
+  max: glam::Vec3,
+) -> Option<(f32, glam::Vec3)> {
+  let inv_dir = dir.recip();
+  let t_1 = (min - origin) * inv_dir;
+  let t_2 = (max - origin) * inv_dir;
+  let t_near = t_1.min(t_2);
+  let t_entry = t_near.max_element();
+  let t_exit = t_1.max(t_2).min_element();
+  if t_entry < 0.0 || t_entry > t_exit || t_entry > max_dist {
+    return None;
+  }
+  // The face entered last is the one hit, it faces back against the ray
+  let axis = if t_near.x == t_entry {
+    glam::Vec3::X
+  } else if t_near.y == t_entry {
+    glam::Vec3::Y
+  } else {
+    glam::Vec3::Z
+  };
+  Some((t_entry, -axis * dir.dot(axis).signum()))
+}
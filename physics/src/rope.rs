@@ -0,0 +1,152 @@
+use crate::static_mesh::StaticTriangleMesh;
+use crate::{GRAVITY, STATIC_CONTACT_DEPTH};
+use geometry::glam;
+
+// Constraint passes per solve, fewer lets long ropes stretch under their own weight
+const ROPE_ITERATIONS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RopeAnchor {
+  World(glam::Vec3),
+  // Offset is in the body's space. The rope end follows the body but doesn't pull on it
+  Body { name: String, offset: glam::Vec3 },
+}
+
+#[derive(Debug, Copy, Clone)]
+struct RopeParticle {
+  position: glam::Vec3,
+  prev_position: glam::Vec3,
+}
+
+// Chain of particles kept apart by distance constraints, solved like xpbd so stiffness doesn't
+// change with the step length
+#[derive(Debug, Clone)]
+pub struct Rope {
+  particles: Vec<RopeParticle>,
+  segment_length: f32,
+  // How much a segment gives under load, 0 for one that doesn't stretch
+  compliance: f32,
+  // Share of velocity lost per second
+  damping: f32,
+  radius: f32,
+  start: Option<RopeAnchor>,
+  end: Option<RopeAnchor>,
+}
+
+impl Rope {
+  // Hangs straight from start to end, unanchored
+  pub fn new(
+    start: glam::Vec3,
+    end: glam::Vec3,
+    segments: usize,
+    compliance: f32,
+    damping: f32,
+    radius: f32,
+  ) -> Self {
+    let segments = segments.max(1);
+    let particles = (0..=segments)
+      .map(|i| {
+        let position = start.lerp(end, i as f32 / segments as f32);
+        RopeParticle { position, prev_position: position }
+      })
+      .collect();
+    Self {
+      particles,
+      segment_length: start.distance(end) / segments as f32,
+      compliance: compliance.max(0.0),
+      damping: damping.max(0.0),
+      radius,
+      start: None,
+      end: None,
+    }
+  }
+
+  pub fn set_anchors(&mut self, start: Option<RopeAnchor>, end: Option<RopeAnchor>) {
+    self.start = start;
+    self.end = end;
+  }
+
+  pub fn anchors(&self) -> (Option<&RopeAnchor>, Option<&RopeAnchor>) {
+    (self.start.as_ref(), self.end.as_ref())
+  }
+
+  // Particle positions from start to end, for drawing the rope
+  // Anchors on bodies move with them
+  pub(crate) fn translate(&mut self, offset: glam::Vec3) {
+    for particle in self.particles.iter_mut() {
+      particle.position += offset;
+      particle.prev_position += offset;
+    }
+    for anchor in [self.start.as_mut(), self.end.as_mut()].into_iter().flatten() {
+      if let RopeAnchor::World(point) = anchor {
+        *point += offset;
+      }
+    }
+  }
+
+  pub fn points(&self) -> Vec<glam::Vec3> {
+    self.particles.iter().map(|x| x.position).collect()
+  }
+
+  pub fn radius(&self) -> f32 {
+    self.radius
+  }
+
+  // Ends are pinned to the given positions, None leaves that end hanging free. Particles collide
+  // with level geometry as points
+  pub(crate) fn step<'a>(
+    &mut self,
+    time_s: f32,
+    start: Option<glam::Vec3>,
+    end: Option<glam::Vec3>,
+    static_meshes: impl Iterator<Item = &'a StaticTriangleMesh> + Clone,
+  ) {
+    if time_s <= 0.0 {
+      return;
+    }
+    let last = self.particles.len() - 1;
+    let pins = [(0, start), (last, end)];
+    let keep = (1.0 - self.damping * time_s).max(0.0);
+    let gravity = glam::Vec3::NEG_Y * GRAVITY * time_s * time_s;
+    for particle in self.particles.iter_mut() {
+      let velocity = (particle.position - particle.prev_position) * keep;
+      particle.prev_position = particle.position;
+      particle.position += velocity + gravity;
+    }
+    let mut inv_masses = vec![1.0; self.particles.len()];
+    for (idx, pin) in pins {
+      let Some(pin) = pin else { continue };
+      self.particles[idx].position = pin;
+      inv_masses[idx] = 0.0;
+    }
+
+    let alpha = self.compliance / (time_s * time_s);
+    let mut lambdas = vec![0.0; last];
+    for _ in 0..ROPE_ITERATIONS {
+      for i in 0..last {
+        let w = inv_masses[i] + inv_masses[i + 1];
+        let delta = self.particles[i + 1].position - self.particles[i].position;
+        let length = delta.length();
+        if w == 0.0 || length == 0.0 {
+          continue;
+        }
+        let dir = delta / length;
+        let d_lambda = (self.segment_length - length - alpha * lambdas[i]) / (w + alpha);
+        lambdas[i] += d_lambda;
+        self.particles[i].position -= dir * d_lambda * inv_masses[i];
+        self.particles[i + 1].position += dir * d_lambda * inv_masses[i + 1];
+      }
+    }
+
+    for (particle, inv_mass) in self.particles.iter_mut().zip(inv_masses) {
+      if inv_mass == 0.0 {
+        continue;
+      }
+      for mesh in static_meshes.clone() {
+        if let Some(contact) = mesh.point_contact(particle.position, STATIC_CONTACT_DEPTH) {
+          particle.position += contact.normal * contact.penetration;
+        }
+      }
+    }
+  }
+}
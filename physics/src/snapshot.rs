@@ -0,0 +1,166 @@
+use geometry::world::local_position;
+use geometry::{glam, Orientation};
+use serde::{Deserialize, Serialize};
+
+use crate::{KinematicTarget, PhysicsEngine};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct OrientationSnapshot {
+  position: [f32; 3],
+  rotation: [f32; 16],
+}
+
+impl OrientationSnapshot {
+  fn new(orientation: &Orientation) -> Self {
+    Self {
+      position: orientation.position.to_array(),
+      rotation: orientation.rotation.to_cols_array(),
+    }
+  }
+
+  fn orientation(&self) -> Orientation {
+    Orientation::new(
+      glam::Vec3::from_array(self.position),
+      glam::Mat4::from_cols_array(&self.rotation),
+    )
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct KinematicSnapshot {
+  orientation: OrientationSnapshot,
+  time_left: f32,
+  carry_riders: bool,
+}
+
+// What moves on a body, its shape, mass, forces and material come from whoever added it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct BodySnapshot {
+  name: String,
+  orientation: OrientationSnapshot,
+  prev_orientation: OrientationSnapshot,
+  velocity: [f32; 3],
+  angular_velocity: [f32; 3],
+  frozen: bool,
+  carried_velocity: [f32; 3],
+  kinematic: Option<KinematicSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct WarmImpulseSnapshot {
+  body_1: String,
+  body_2: String,
+  // Point, normal impulse and tangent impulses of each cached contact
+  impulses: Vec<([f32; 3], f32, [f32; 2])>,
+}
+
+// Rigid body state and solver warm starting at a step boundary, restored onto an engine with the
+// same bodies added the same way it runs on bit for bit like the one it was taken from.
+// Projectiles, ropes, vehicles and fluids aren't in it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhysicsSnapshot {
+  bodies: Vec<BodySnapshot>,
+  warm_impulses: Vec<WarmImpulseSnapshot>,
+  pending_us: u128,
+  origin: [f64; 3],
+}
+
+impl PhysicsSnapshot {
+  pub fn body_count(&self) -> usize {
+    self.bodies.len()
+  }
+}
+
+impl PhysicsEngine {
+  pub fn snapshot(&self) -> PhysicsSnapshot {
+    let bodies = self
+      .rigid_bodies
+      .iter()
+      .map(|body| BodySnapshot {
+        name: body.name.clone(),
+        orientation: OrientationSnapshot::new(&body.physics_info.orientation),
+        prev_orientation: OrientationSnapshot::new(&body.physics_info.prev_orientation),
+        velocity: body.physics_info.velocity.to_array(),
+        angular_velocity: body.physics_info.angular_velocity.to_array(),
+        frozen: body.frozen,
+        carried_velocity: body.carried_velocity.to_array(),
+        kinematic: body.kinematic.as_ref().map(|target| KinematicSnapshot {
+          orientation: OrientationSnapshot::new(&target.orientation),
+          time_left: target.time_left,
+          carry_riders: target.carry_riders,
+        }),
+      })
+      .collect();
+    // Sorted as the cache is a hash map, so the same state makes the same bytes
+    let mut warm_impulses = self
+      .solver
+      .warm_impulses()
+      .into_iter()
+      .map(|((body_1, body_2), impulses)| WarmImpulseSnapshot {
+        body_1: self.rigid_bodies[body_1].name.clone(),
+        body_2: self.rigid_bodies[body_2].name.clone(),
+        impulses: impulses
+          .into_iter()
+          .map(|(point, normal_impulse, tangent_impulses)| {
+            (point.to_array(), normal_impulse, tangent_impulses)
+          })
+          .collect(),
+      })
+      .collect::<Vec<_>>();
+    warm_impulses.sort_by(|a, b| (&a.body_1, &a.body_2).cmp(&(&b.body_1, &b.body_2)));
+    PhysicsSnapshot {
+      bodies,
+      warm_impulses,
+      pending_us: self.pending_us,
+      origin: self.origin.to_array(),
+    }
+  }
+
+  // Bodies are matched by name, every one in the snapshot has to be in the engine. Ones added
+  // since it was taken are left as they are
+  pub fn restore(&mut self, snapshot: &PhysicsSnapshot) -> Result<(), String> {
+    let missing = snapshot
+      .bodies
+      .iter()
+      .filter(|x| !self.rigid_body_names.contains_key(&x.name))
+      .map(|x| x.name.as_str())
+      .collect::<Vec<_>>();
+    if !missing.is_empty() {
+      return Err(format!("at restoring physics snapshot: no rigid bodies named {missing:?}"));
+    }
+    // The rest of the engine is moved onto the snapshot's origin, its bodies are set as they were
+    self.shift_origin(local_position(self.origin, glam::DVec3::from_array(snapshot.origin)));
+    for body_snapshot in snapshot.bodies.iter() {
+      let body = &mut self.rigid_bodies[self.rigid_body_names[&body_snapshot.name]];
+      body.physics_info.orientation = body_snapshot.orientation.orientation();
+      body.physics_info.prev_orientation = body_snapshot.prev_orientation.orientation();
+      body.physics_info.velocity = glam::Vec3::from_array(body_snapshot.velocity);
+      body.physics_info.angular_velocity = glam::Vec3::from_array(body_snapshot.angular_velocity);
+      body.frozen = body_snapshot.frozen;
+      body.carried_velocity = glam::Vec3::from_array(body_snapshot.carried_velocity);
+      body.kinematic = body_snapshot.kinematic.map(|target| KinematicTarget {
+        orientation: target.orientation.orientation(),
+        time_left: target.time_left,
+        carry_riders: target.carry_riders,
+      });
+    }
+    let warm_impulses = snapshot
+      .warm_impulses
+      .iter()
+      .map(|x| {
+        let (body_1, body_2) = (self.rigid_body_names[&x.body_1], self.rigid_body_names[&x.body_2]);
+        let impulses = x
+          .impulses
+          .iter()
+          .map(|(point, normal_impulse, tangent_impulses)| {
+            (glam::Vec3::from_array(*point), *normal_impulse, *tangent_impulses)
+          })
+          .collect();
+        ((body_1.min(body_2), body_1.max(body_2)), impulses)
+      })
+      .collect();
+    self.solver.set_warm_impulses(warm_impulses);
+    self.pending_us = snapshot.pending_us;
+    Ok(())
+  }
+}
@@ -0,0 +1,425 @@
+use crate::material::MaterialTable;
+use crate::{Mass, MomentOfInertia, RigidBody, RigidBodyInfo};
+use geometry::glam;
+use std::collections::HashMap;
+
+// Contacts of last step closer than this to a new contact of the same pair seed its impulses
+const WARM_START_DISTANCE: f32 = 0.02;
+// Approach speeds under this don't bounce, keeps resting bodies from jittering
+const RESTITUTION_THRESHOLD: f32 = 0.5;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PositionCorrection {
+  None,
+  // Feeds the penetration back into the velocity solve as a bias, adds energy to the bodies
+  Baumgarte { factor: f32, slop: f32 },
+  // Pushes the bodies apart with separate pseudo velocities that are dropped after the step
+  SplitImpulse { factor: f32, slop: f32, iterations: usize },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SolverConfig {
+  pub velocity_iterations: usize,
+  pub position_correction: PositionCorrection,
+  pub warm_starting: bool,
+}
+
+impl Default for SolverConfig {
+  fn default() -> Self {
+    Self {
+      velocity_iterations: 8,
+      position_correction: PositionCorrection::Baumgarte { factor: 0.2, slop: 0.005 },
+      warm_starting: true,
+    }
+  }
+}
+
+// A touching point between two bodies, normal facing from body_2 towards body_1. Negative
+// penetration is a gap the bodies are about to close
+#[derive(Debug, Copy, Clone)]
+pub struct ContactPoint {
+  pub body_1: usize,
+  pub body_2: usize,
+  pub point: glam::Vec3,
+  pub normal: glam::Vec3,
+  pub penetration: f32,
+}
+
+// Cached impulses by body pair as point, normal impulse and tangent impulses
+pub(crate) type WarmImpulses = Vec<((usize, usize), Vec<(glam::Vec3, f32, [f32; 2])>)>;
+
+#[derive(Debug, Copy, Clone)]
+struct CachedImpulse {
+  point: glam::Vec3,
+  normal_impulse: f32,
+  tangent_impulses: [f32; 2],
+}
+
+#[derive(Debug, Copy, Clone)]
+struct ContactConstraint {
+  contact: ContactPoint,
+  r_1: glam::Vec3,
+  r_2: glam::Vec3,
+  tangents: [glam::Vec3; 2],
+  normal_mass: f32,
+  tangent_masses: [f32; 2],
+  friction: f32,
+  velocity_bias: f32,
+  normal_impulse: f32,
+  tangent_impulses: [f32; 2],
+  pseudo_impulse: f32,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct BodyMotion {
+  inv_mass: f32,
+  inv_inertia: glam::Mat3,
+  velocity: glam::Vec3,
+  angular_velocity: glam::Vec3,
+  pseudo_velocity: glam::Vec3,
+  pseudo_angular_velocity: glam::Vec3,
+}
+
+impl BodyMotion {
+  fn new(info: &RigidBodyInfo) -> Self {
+    let inv_mass = match info.mass {
+      Mass::Infinite => 0.0,
+      Mass::Finite(mass) => 1.0 / mass,
+    };
+    // Inertia is given in body space, rotate its inverse into world space
+    let inv_inertia = match info.moment_of_inertia {
+      MomentOfInertia::Infinite => glam::Mat3::ZERO,
+      MomentOfInertia::Finite(inertia) => {
+        let rotation = glam::Mat3::from_mat4(info.orientation.rotation);
+        rotation * inertia.inverse() * rotation.transpose()
+      }
+    };
+    Self {
+      inv_mass,
+      inv_inertia,
+      velocity: info.velocity,
+      angular_velocity: info.angular_velocity,
+      pseudo_velocity: glam::Vec3::ZERO,
+      pseudo_angular_velocity: glam::Vec3::ZERO,
+    }
+  }
+
+  fn point_velocity(&self, r: glam::Vec3) -> glam::Vec3 {
+    self.velocity + self.angular_velocity.cross(r)
+  }
+
+  fn apply_impulse(&mut self, impulse: glam::Vec3, r: glam::Vec3) {
+    self.velocity += impulse * self.inv_mass;
+    self.angular_velocity += self.inv_inertia * r.cross(impulse);
+  }
+
+  fn apply_pseudo_impulse(&mut self, impulse: glam::Vec3, r: glam::Vec3) {
+    self.pseudo_velocity += impulse * self.inv_mass;
+    self.pseudo_angular_velocity += self.inv_inertia * r.cross(impulse);
+  }
+
+  // Inverse of the effective mass seen along dir at r
+  fn inv_mass_along(&self, r: glam::Vec3, dir: glam::Vec3) -> f32 {
+    let r_cross_dir = r.cross(dir);
+    self.inv_mass + (self.inv_inertia * r_cross_dir).cross(r).dot(dir)
+  }
+}
+
+// Sequential impulses over every contact of a step. Impulses of the last step are kept per body
+// pair so stacks start close to their resting solution
+#[derive(Debug, Default, Clone)]
+pub struct ContactSolver {
+  config: SolverConfig,
+  warm_cache: HashMap<(usize, usize), Vec<CachedImpulse>>,
+}
+
+impl ContactSolver {
+  pub fn new(config: SolverConfig) -> Self {
+    Self { config, warm_cache: HashMap::new() }
+  }
+
+  pub fn config(&self) -> SolverConfig {
+    self.config
+  }
+
+  pub fn set_config(&mut self, config: SolverConfig) {
+    if !config.warm_starting {
+      self.warm_cache.clear();
+    }
+    self.config = config;
+  }
+
+  // Cached impulses point at body indices, drop them when the bodies are reordered
+  pub fn clear_warm_start(&mut self) {
+    self.warm_cache.clear();
+  }
+
+  // For snapshots
+  pub(crate) fn warm_impulses(&self) -> WarmImpulses {
+    self
+      .warm_cache
+      .iter()
+      .map(|(pair, impulses)| {
+        let impulses = impulses
+          .iter()
+          .map(|x| (x.point, x.normal_impulse, x.tangent_impulses))
+          .collect();
+        (*pair, impulses)
+      })
+      .collect()
+  }
+
+  // Cached contacts are matched by where they are, so they move with the bodies
+  pub(crate) fn translate(&mut self, offset: glam::Vec3) {
+    self.warm_cache.values_mut().flatten().for_each(|cached| cached.point += offset);
+  }
+
+  pub(crate) fn set_warm_impulses(&mut self, warm_impulses: WarmImpulses) {
+    self.warm_cache = warm_impulses
+      .into_iter()
+      .map(|(pair, impulses)| {
+        let impulses = impulses
+          .into_iter()
+          .map(|(point, normal_impulse, tangent_impulses)| CachedImpulse {
+            point,
+            normal_impulse,
+            tangent_impulses,
+          })
+          .collect();
+        (pair, impulses)
+      })
+      .collect();
+  }
+
+  fn pair_key(contact: &ContactPoint) -> (usize, usize) {
+    (contact.body_1.min(contact.body_2), contact.body_1.max(contact.body_2))
+  }
+
+  fn tangent_basis(normal: glam::Vec3) -> [glam::Vec3; 2] {
+    let (tangent_1, tangent_2) = normal.any_orthonormal_pair();
+    [tangent_1, tangent_2]
+  }
+
+  fn warm_impulse(&self, contact: &ContactPoint) -> Option<CachedImpulse> {
+    let cached = self.warm_cache.get(&Self::pair_key(contact))?;
+    let closest = cached.iter().min_by(|a, b| {
+      a.point.distance_squared(contact.point).total_cmp(&b.point.distance_squared(contact.point))
+    })?;
+    if closest.point.distance(contact.point) > WARM_START_DISTANCE {
+      return None;
+    }
+    // Cache is stored for body_1 < body_2, flip when this contact has them the other way
+    if contact.body_1 > contact.body_2 {
+      return Some(CachedImpulse {
+        point: closest.point,
+        normal_impulse: closest.normal_impulse,
+        tangent_impulses: [-closest.tangent_impulses[0], -closest.tangent_impulses[1]],
+      });
+    }
+    Some(*closest)
+  }
+
+  fn build_constraint(
+    &self,
+    contact: ContactPoint,
+    bodies: &[RigidBody],
+    motions: &[BodyMotion],
+    materials: &MaterialTable,
+    time_s: f32,
+  ) -> ContactConstraint {
+    let body_1 = &bodies[contact.body_1];
+    let body_2 = &bodies[contact.body_2];
+    let motion_1 = &motions[contact.body_1];
+    let motion_2 = &motions[contact.body_2];
+    let material = materials.contact(body_1.material, body_2.material);
+
+    let r_1 = contact.point - body_1.physics_info.orientation.position;
+    let r_2 = contact.point - body_2.physics_info.orientation.position;
+    // Tangents come from the pair's normal in body index order, so warm started friction lines up
+    // whichever way round the pair is reported
+    let tangents = match contact.body_1 > contact.body_2 {
+      true => Self::tangent_basis(-contact.normal),
+      false => Self::tangent_basis(contact.normal),
+    };
+    let inverse_or_zero = |k: f32| if k > 0.0 { 1.0 / k } else { 0.0 };
+    let normal_mass = inverse_or_zero(
+      motion_1.inv_mass_along(r_1, contact.normal) + motion_2.inv_mass_along(r_2, contact.normal),
+    );
+    let tangent_masses = tangents.map(|tangent| {
+      inverse_or_zero(motion_1.inv_mass_along(r_1, tangent) + motion_2.inv_mass_along(r_2, tangent))
+    });
+
+    // Bounce target comes from the approach speed before any impulse of this step
+    let approach_speed =
+      (motion_1.point_velocity(r_1) - motion_2.point_velocity(r_2)).dot(contact.normal);
+    let mut velocity_bias = if contact.penetration < 0.0 {
+      // Still apart, they may close the gap within the step but not go past it
+      contact.penetration / time_s
+    } else if approach_speed < -RESTITUTION_THRESHOLD {
+      -material.restitution * approach_speed
+    } else {
+      0.0
+    };
+    if let PositionCorrection::Baumgarte { factor, slop } = self.config.position_correction {
+      velocity_bias += factor / time_s * (contact.penetration - slop).max(0.0);
+    }
+
+    let (normal_impulse, tangent_impulses) = match self.config.warm_starting {
+      true => self
+        .warm_impulse(&contact)
+        .map(|x| (x.normal_impulse, x.tangent_impulses))
+        .unwrap_or((0.0, [0.0; 2])),
+      false => (0.0, [0.0; 2]),
+    };
+
+    ContactConstraint {
+      contact,
+      r_1,
+      r_2,
+      tangents,
+      normal_mass,
+      tangent_masses,
+      friction: material.friction,
+      velocity_bias,
+      normal_impulse,
+      tangent_impulses,
+      pseudo_impulse: 0.0,
+    }
+  }
+
+  fn apply_pair(
+    motions: &mut [BodyMotion],
+    constraint: &ContactConstraint,
+    impulse: glam::Vec3,
+    pseudo: bool,
+  ) {
+    let (i, j) = (constraint.contact.body_1, constraint.contact.body_2);
+    if pseudo {
+      motions[i].apply_pseudo_impulse(impulse, constraint.r_1);
+      motions[j].apply_pseudo_impulse(-impulse, constraint.r_2);
+    } else {
+      motions[i].apply_impulse(impulse, constraint.r_1);
+      motions[j].apply_impulse(-impulse, constraint.r_2);
+    }
+  }
+
+  fn solve_velocity(motions: &mut [BodyMotion], constraint: &mut ContactConstraint) {
+    let (i, j) = (constraint.contact.body_1, constraint.contact.body_2);
+
+    // Friction first so the normal impulse, which matters more for stacking, gets the last word
+    for axis in 0..2 {
+      let tangent = constraint.tangents[axis];
+      let rel_vel =
+        motions[i].point_velocity(constraint.r_1) - motions[j].point_velocity(constraint.r_2);
+      let max_friction = constraint.friction * constraint.normal_impulse;
+      let lambda = -rel_vel.dot(tangent) * constraint.tangent_masses[axis];
+      let old_impulse = constraint.tangent_impulses[axis];
+      constraint.tangent_impulses[axis] =
+        (old_impulse + lambda).clamp(-max_friction, max_friction);
+      let delta = constraint.tangent_impulses[axis] - old_impulse;
+      Self::apply_pair(motions, constraint, tangent * delta, false);
+    }
+
+    let normal = constraint.contact.normal;
+    let rel_vel =
+      motions[i].point_velocity(constraint.r_1) - motions[j].point_velocity(constraint.r_2);
+    let lambda = (constraint.velocity_bias - rel_vel.dot(normal)) * constraint.normal_mass;
+    // Accumulated impulse can only push, individual iterations may pull back what was too much
+    let old_impulse = constraint.normal_impulse;
+    constraint.normal_impulse = (old_impulse + lambda).max(0.0);
+    let delta = constraint.normal_impulse - old_impulse;
+    Self::apply_pair(motions, constraint, normal * delta, false);
+  }
+
+  fn solve_position(
+    motions: &mut [BodyMotion],
+    constraint: &mut ContactConstraint,
+    factor: f32,
+    slop: f32,
+    time_s: f32,
+  ) {
+    let (i, j) = (constraint.contact.body_1, constraint.contact.body_2);
+    let normal = constraint.contact.normal;
+    let rel_pseudo_vel = (motions[i].pseudo_velocity
+      + motions[i].pseudo_angular_velocity.cross(constraint.r_1))
+      - (motions[j].pseudo_velocity + motions[j].pseudo_angular_velocity.cross(constraint.r_2));
+    let target = factor / time_s * (constraint.contact.penetration - slop).max(0.0);
+    let lambda = (target - rel_pseudo_vel.dot(normal)) * constraint.normal_mass;
+    let old_impulse = constraint.pseudo_impulse;
+    constraint.pseudo_impulse = (old_impulse + lambda).max(0.0);
+    let delta = constraint.pseudo_impulse - old_impulse;
+    Self::apply_pair(motions, constraint, normal * delta, true);
+  }
+
+  // Changes velocities of the bodies so the contacts stop approaching, and with split impulses
+  // moves them out of each other. Integrating the step is left to the caller
+  pub fn solve(
+    &mut self,
+    bodies: &mut [RigidBody],
+    contacts: &[ContactPoint],
+    materials: &MaterialTable,
+    time_s: f32,
+  ) {
+    if time_s <= 0.0 {
+      return;
+    }
+    let mut motions = bodies.iter().map(|x| BodyMotion::new(&x.physics_info)).collect::<Vec<_>>();
+    let mut constraints = contacts
+      .iter()
+      .filter(|x| x.body_1 != x.body_2 && x.body_1 < bodies.len() && x.body_2 < bodies.len())
+      .map(|x| self.build_constraint(*x, bodies, &motions, materials, time_s))
+      .filter(|x| x.normal_mass > 0.0)
+      .collect::<Vec<_>>();
+
+    for constraint in constraints.iter() {
+      let impulse = constraint.contact.normal * constraint.normal_impulse
+        + constraint.tangents[0] * constraint.tangent_impulses[0]
+        + constraint.tangents[1] * constraint.tangent_impulses[1];
+      Self::apply_pair(&mut motions, constraint, impulse, false);
+    }
+
+    for _ in 0..self.config.velocity_iterations {
+      for constraint in constraints.iter_mut() {
+        Self::solve_velocity(&mut motions, constraint);
+      }
+    }
+
+    if let PositionCorrection::SplitImpulse { factor, slop, iterations } =
+      self.config.position_correction
+    {
+      for _ in 0..iterations {
+        for constraint in constraints.iter_mut() {
+          Self::solve_position(&mut motions, constraint, factor, slop, time_s);
+        }
+      }
+    }
+
+    for (body, motion) in bodies.iter_mut().zip(motions.iter()) {
+      let info = &mut body.physics_info;
+      info.velocity = motion.velocity;
+      info.angular_velocity = motion.angular_velocity;
+      // Pseudo velocities only move the body, nothing of them is kept for the next step
+      info.orientation.position += motion.pseudo_velocity * time_s;
+      let rotation = motion.pseudo_angular_velocity * time_s;
+      if rotation.length_squared() != 0.0 {
+        info.orientation.rotation =
+          glam::Mat4::from_axis_angle(rotation.normalize(), rotation.length())
+            * info.orientation.rotation;
+      }
+    }
+
+    self.warm_cache.clear();
+    if self.config.warm_starting {
+      for constraint in constraints.iter() {
+        let sign = if constraint.contact.body_1 > constraint.contact.body_2 { -1.0 } else { 1.0 };
+        self.warm_cache.entry(Self::pair_key(&constraint.contact)).or_default().push(
+          CachedImpulse {
+            point: constraint.contact.point,
+            normal_impulse: constraint.normal_impulse,
+            tangent_impulses: constraint.tangent_impulses.map(|x| x * sign),
+          },
+        );
+      }
+    }
+  }
+}
@@ -0,0 +1,508 @@
+use geometry::glam;
+use std::path::Path;
+use std::sync::Arc;
+
+// File layout, all values little endian:
+// magic "RCOL", version u32, triangle count u32, node count u32,
+// per triangle: 3 corners of 3 f32, per node: min and max of 3 f32, first u32, count u32
+// Triangles and nodes are laid out the same as in memory, so loaded files are used in place. That
+// takes a little endian machine, which is all the engine runs on
+const COLLISION_MAGIC: &[u8; 4] = b"RCOL";
+const COLLISION_VERSION: u32 = 1;
+const COLLISION_HEADER_LEN: usize = 16;
+pub const COLLISION_EXTENSION: &str = "rcol";
+
+const MAX_LEAF_TRIANGLES: usize = 4;
+// Triangles with less area than this can't give a normal and are dropped while baking
+const MIN_TRIANGLE_AREA: f32 = 1e-8;
+
+// Leaves have count > 0 and own triangles first..first + count. Inner nodes have count 0, their
+// left child is the next node and first is the index of the right child
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)]
+struct BvhNode {
+  min: glam::Vec3,
+  max: glam::Vec3,
+  first: u32,
+  count: u32,
+}
+
+// Safety: plain numbers with no padding between them, any bit pattern is a node
+unsafe impl bytemuck::Zeroable for BvhNode {}
+unsafe impl bytemuck::Pod for BvhNode {}
+
+impl BvhNode {
+  fn overlaps(&self, min: glam::Vec3, max: glam::Vec3) -> bool {
+    self.min.cmple(max).all() && self.max.cmpge(min).all()
+  }
+
+  // Entry distance of the ray into the box, None if it misses or enters past max_dist
+  fn ray_entry(&self, origin: glam::Vec3, inv_dir: glam::Vec3, max_dist: f32) -> Option<f32> {
+    let t_1 = (self.min - origin) * inv_dir;
+    let t_2 = (self.max - origin) * inv_dir;
+    let t_near = t_1.min(t_2).max_element().max(0.0);
+    let t_far = t_1.max(t_2).min_element().min(max_dist);
+    (t_near <= t_far).then_some(t_near)
+  }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct StaticContact {
+  pub triangle: usize,
+  pub normal: glam::Vec3,
+  pub penetration: f32,
+}
+
+// Bytes a loaded mesh is viewed in, a mapped file say
+pub type SharedBytes = Arc<dyn AsRef<[u8]> + Send + Sync>;
+
+#[derive(Clone)]
+enum MeshData {
+  Owned { triangles: Vec<[glam::Vec3; 3]>, nodes: Vec<BvhNode> },
+  // A whole collision file, checked when it was loaded
+  Viewed { bytes: SharedBytes, triangle_count: usize },
+}
+
+impl Default for MeshData {
+  fn default() -> Self {
+    Self::Owned { triangles: vec![], nodes: vec![] }
+  }
+}
+
+// Non convex triangle soup for level geometry that never moves, baked once from the render mesh.
+// Meshes loaded from shared bytes are queried straight from them, they're only copied when moved
+#[derive(Clone, Default)]
+pub struct StaticTriangleMesh {
+  data: MeshData,
+}
+
+impl std::fmt::Debug for StaticTriangleMesh {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("StaticTriangleMesh")
+      .field("triangles", &self.triangles())
+      .field("nodes", &self.nodes())
+      .finish()
+  }
+}
+
+// The sections of a collision file after the header. None when they can't be viewed in place,
+// where the bytes don't start at a multiple of 4
+fn collision_sections(
+  bytes: &[u8],
+  triangle_count: usize,
+) -> Option<(&[[glam::Vec3; 3]], &[BvhNode])> {
+  let (triangles, nodes) = bytes[COLLISION_HEADER_LEN..].split_at(triangle_count * 36);
+  Some((bytemuck::try_cast_slice(triangles).ok()?, bytemuck::try_cast_slice(nodes).ok()?))
+}
+
+impl StaticTriangleMesh {
+  fn triangles(&self) -> &[[glam::Vec3; 3]] {
+    match &self.data {
+      MeshData::Owned { triangles, .. } => triangles,
+      MeshData::Viewed { bytes, triangle_count } => {
+        collision_sections((**bytes).as_ref(), *triangle_count).map_or(&[], |x| x.0)
+      }
+    }
+  }
+
+  fn nodes(&self) -> &[BvhNode] {
+    match &self.data {
+      MeshData::Owned { nodes, .. } => nodes,
+      MeshData::Viewed { bytes, triangle_count } => {
+        collision_sections((**bytes).as_ref(), *triangle_count).map_or(&[], |x| x.1)
+      }
+    }
+  }
+
+  // Copies a viewed mesh out of its bytes first
+  fn owned(&mut self) -> (&mut Vec<[glam::Vec3; 3]>, &mut Vec<BvhNode>) {
+    if let MeshData::Viewed { .. } = self.data {
+      let (triangles, nodes) = (self.triangles().to_vec(), self.nodes().to_vec());
+      self.data = MeshData::Owned { triangles, nodes };
+    }
+    match &mut self.data {
+      MeshData::Owned { triangles, nodes } => (triangles, nodes),
+      MeshData::Viewed { .. } => unreachable!("viewed meshes were just copied"),
+    }
+  }
+
+  pub fn bake(positions: &[glam::Vec3], indices: &[[u32; 3]]) -> Result<Self, String> {
+    let mut triangles = Vec::with_capacity(indices.len());
+    for (i, triangle) in indices.iter().enumerate() {
+      let corners = triangle
+        .map(|idx| positions.get(idx as usize).copied().ok_or(idx))
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|idx| format!("triangle {i} uses vertex {idx} of {}", positions.len()))?;
+      let corners = [corners[0], corners[1], corners[2]];
+      if (corners[1] - corners[0]).cross(corners[2] - corners[0]).length() * 0.5 < MIN_TRIANGLE_AREA
+      {
+        continue;
+      }
+      triangles.push(corners);
+    }
+    let triangle_count = triangles.len();
+    let mut mesh = Self { data: MeshData::Owned { triangles, nodes: vec![] } };
+    if triangle_count > 0 {
+      mesh.build_node(0, triangle_count);
+    }
+    Ok(mesh)
+  }
+
+  fn bounds(triangles: &[[glam::Vec3; 3]]) -> (glam::Vec3, glam::Vec3) {
+    triangles
+      .iter()
+      .flatten()
+      .fold((glam::Vec3::splat(f32::MAX), glam::Vec3::splat(f32::MIN)), |(min, max), corner| {
+        (min.min(*corner), max.max(*corner))
+      })
+  }
+
+  fn centroid(triangle: &[glam::Vec3; 3]) -> glam::Vec3 {
+    (triangle[0] + triangle[1] + triangle[2]) / 3.0
+  }
+
+  // Splits at the median centroid along the longest side, triangles are reordered in place
+  fn build_node(&mut self, first: usize, count: usize) {
+    let (triangles, nodes) = self.owned();
+    let (min, max) = Self::bounds(&triangles[first..first + count]);
+    let node_idx = nodes.len();
+    nodes.push(BvhNode { min, max, first: first as u32, count: count as u32 });
+    if count <= MAX_LEAF_TRIANGLES {
+      return;
+    }
+    let size = max - min;
+    let axis = match size.max_element() {
+      x if x == size.x => 0,
+      y if y == size.y => 1,
+      _ => 2,
+    };
+    let half = count / 2;
+    triangles[first..first + count].select_nth_unstable_by(half, |a, b| {
+      Self::centroid(a)[axis].total_cmp(&Self::centroid(b)[axis])
+    });
+    self.build_node(first, half);
+    let (_, nodes) = self.owned();
+    nodes[node_idx].first = nodes.len() as u32;
+    nodes[node_idx].count = 0;
+    self.build_node(first + half, count - half);
+  }
+
+  // Same triangles and tree, only moved
+  pub(crate) fn translate(&mut self, offset: glam::Vec3) {
+    let (triangles, nodes) = self.owned();
+    triangles.iter_mut().flatten().for_each(|corner| *corner += offset);
+    for node in nodes.iter_mut() {
+      node.min += offset;
+      node.max += offset;
+    }
+  }
+
+  pub fn triangle_count(&self) -> usize {
+    self.triangles().len()
+  }
+
+  pub fn triangle(&self, idx: usize) -> Option<[glam::Vec3; 3]> {
+    self.triangles().get(idx).copied()
+  }
+
+  pub fn bounds_min_max(&self) -> Option<(glam::Vec3, glam::Vec3)> {
+    self.nodes().first().map(|root| (root.min, root.max))
+  }
+
+  // Indices of the triangles whose leaf boxes touch the box, may include some that don't
+  pub fn triangles_in_box(&self, min: glam::Vec3, max: glam::Vec3) -> Vec<usize> {
+    let mut found = vec![];
+    let nodes = self.nodes();
+    let mut stack = Vec::from_iter((!nodes.is_empty()).then_some(0));
+    while let Some(node_idx) = stack.pop() {
+      let node = nodes[node_idx];
+      if !node.overlaps(min, max) {
+        continue;
+      }
+      if node.count > 0 {
+        found.extend(node.first as usize..(node.first + node.count) as usize);
+      } else {
+        stack.push(node.first as usize);
+        stack.push(node_idx + 1);
+      }
+    }
+    found
+  }
+
+  // Möller-Trumbore, both faces count as hits
+  fn ray_triangle(origin: glam::Vec3, dir: glam::Vec3, triangle: &[glam::Vec3; 3]) -> Option<f32> {
+    let edge_1 = triangle[1] - triangle[0];
+    let edge_2 = triangle[2] - triangle[0];
+    let p = dir.cross(edge_2);
+    let det = edge_1.dot(p);
+    if det.abs() < f32::EPSILON {
+      return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - triangle[0];
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+      return None;
+    }
+    let q = s.cross(edge_1);
+    let v = dir.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+      return None;
+    }
+    let t = edge_2.dot(q) * inv_det;
+    (t >= 0.0).then_some(t)
+  }
+
+  // Closest hit along the ray as (distance, triangle index), dir has to be normalized
+  pub fn raycast(
+    &self,
+    origin: glam::Vec3,
+    dir: glam::Vec3,
+    max_dist: f32,
+  ) -> Option<(f32, usize)> {
+    let inv_dir = dir.recip();
+    let mut closest: Option<(f32, usize)> = None;
+    let nodes = self.nodes();
+    let mut stack = Vec::from_iter((!nodes.is_empty()).then_some(0));
+    while let Some(node_idx) = stack.pop() {
+      let node = nodes[node_idx];
+      let max_dist = closest.map(|x| x.0).unwrap_or(max_dist);
+      if node.ray_entry(origin, inv_dir, max_dist).is_none() {
+        continue;
+      }
+      if node.count == 0 {
+        stack.push(node.first as usize);
+        stack.push(node_idx + 1);
+        continue;
+      }
+      for triangle_idx in node.first as usize..(node.first + node.count) as usize {
+        let Some(t) = Self::ray_triangle(origin, dir, &self.triangles()[triangle_idx]) else {
+          continue;
+        };
+        if t <= max_dist && closest.is_none_or(|x| t < x.0) {
+          closest = Some((t, triangle_idx));
+        }
+      }
+    }
+    closest
+  }
+
+  // First touch of a sphere swept from origin along a normalized dir against one triangle. Checks
+  // the face pushed out by radius, then the capsules round the edges and the spheres on the corners
+  fn sphere_triangle(
+    origin: glam::Vec3,
+    dir: glam::Vec3,
+    radius: f32,
+    triangle: &[glam::Vec3; 3],
+  ) -> Option<f32> {
+    let [a, b, c] = *triangle;
+    let face_normal = (b - a).cross(c - a).normalize();
+    let mut closest: Option<f32> = None;
+    let mut keep_closest = |t: f32| {
+      if t >= 0.0 && closest.is_none_or(|x| t < x) {
+        closest = Some(t);
+      }
+    };
+
+    // Both faces block, sweep against the one the sphere starts in front of
+    let normal = match (origin - a).dot(face_normal) < 0.0 {
+      true => -face_normal,
+      false => face_normal,
+    };
+    let approach = dir.dot(normal);
+    if approach < 0.0 {
+      let t = (radius - (origin - a).dot(normal)) / approach;
+      let touch = origin + dir * t.max(0.0) - normal * radius;
+      let inside = [(a, b), (b, c), (c, a)]
+        .iter()
+        .all(|(start, end)| (*end - *start).cross(touch - *start).dot(face_normal) >= 0.0);
+      if inside {
+        keep_closest(t.max(0.0));
+      }
+    }
+
+    for (start, end) in [(a, b), (b, c), (c, a)] {
+      // Ray against the infinite cylinder round the edge, kept if the hit is along the segment
+      let edge = end - start;
+      let m = origin - start;
+      let (edge_len_sq, m_dot_edge, dir_dot_edge) = (edge.dot(edge), m.dot(edge), dir.dot(edge));
+      let qa = edge_len_sq - dir_dot_edge * dir_dot_edge;
+      let qb = edge_len_sq * m.dot(dir) - dir_dot_edge * m_dot_edge;
+      let qc = edge_len_sq * (m.dot(m) - radius * radius) - m_dot_edge * m_dot_edge;
+      let discriminant = qb * qb - qa * qc;
+      if qa.abs() > f32::EPSILON && discriminant >= 0.0 {
+        // Already round the edge and closing in counts as touching right away
+        let t = ((-qb - discriminant.sqrt()) / qa).max(if qb < 0.0 { 0.0 } else { f32::MIN });
+        let along = m_dot_edge + t * dir_dot_edge;
+        if (0.0..=edge_len_sq).contains(&along) {
+          keep_closest(t);
+        }
+      }
+
+      // Ray against the sphere on the corner
+      let b_half = m.dot(dir);
+      let discriminant = b_half * b_half - (m.dot(m) - radius * radius);
+      if discriminant >= 0.0 {
+        keep_closest((-b_half - discriminant.sqrt()).max(if b_half < 0.0 {
+          0.0
+        } else {
+          f32::MIN
+        }));
+      }
+    }
+    closest
+  }
+
+  // Closest touch of a swept sphere as (distance the center travels, triangle index), dir has to
+  // be normalized. Spheres starting against a triangle and moving into it stop at 0
+  pub fn spherecast(
+    &self,
+    origin: glam::Vec3,
+    dir: glam::Vec3,
+    radius: f32,
+    max_dist: f32,
+  ) -> Option<(f32, usize)> {
+    let inv_dir = dir.recip();
+    let reach = glam::Vec3::splat(radius);
+    let mut closest: Option<(f32, usize)> = None;
+    let nodes = self.nodes();
+    let mut stack = Vec::from_iter((!nodes.is_empty()).then_some(0));
+    while let Some(node_idx) = stack.pop() {
+      let node = nodes[node_idx];
+      let grown = BvhNode { min: node.min - reach, max: node.max + reach, ..node };
+      let max_dist = closest.map(|x| x.0).unwrap_or(max_dist);
+      if grown.ray_entry(origin, inv_dir, max_dist).is_none() {
+        continue;
+      }
+      if node.count == 0 {
+        stack.push(node.first as usize);
+        stack.push(node_idx + 1);
+        continue;
+      }
+      for triangle_idx in node.first as usize..(node.first + node.count) as usize {
+        let Some(t) = Self::sphere_triangle(origin, dir, radius, &self.triangles()[triangle_idx])
+        else {
+          continue;
+        };
+        if t <= max_dist && closest.is_none_or(|x| t < x.0) {
+          closest = Some((t, triangle_idx));
+        }
+      }
+    }
+    closest
+  }
+
+  // How far a point is pushed into the mesh, measured against the front face of the triangle it
+  // is under. Points deeper than max_depth are taken as being on the far side of a thin wall
+  pub fn point_contact(&self, point: glam::Vec3, max_depth: f32) -> Option<StaticContact> {
+    let reach = glam::Vec3::splat(max_depth);
+    let mut contact: Option<StaticContact> = None;
+    for triangle_idx in self.triangles_in_box(point - reach, point + reach) {
+      let [a, b, c] = self.triangles()[triangle_idx];
+      let normal = (b - a).cross(c - a).normalize();
+      let dist = (point - a).dot(normal);
+      if dist >= 0.0 || dist < -max_depth {
+        continue;
+      }
+      // Inside test on the projection of the point onto the triangle
+      let projected = point - normal * dist;
+      let inside = [(a, b), (b, c), (c, a)]
+        .iter()
+        .all(|(start, end)| (*end - *start).cross(projected - *start).dot(normal) >= 0.0);
+      if inside && contact.is_none_or(|x| -dist < x.penetration) {
+        contact = Some(StaticContact { triangle: triangle_idx, normal, penetration: -dist });
+      }
+    }
+    contact
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let (triangles, nodes) = (self.triangles(), self.nodes());
+    let mut bytes =
+      Vec::with_capacity(COLLISION_HEADER_LEN + triangles.len() * 36 + nodes.len() * 32);
+    bytes.extend_from_slice(COLLISION_MAGIC);
+    bytes.extend_from_slice(&COLLISION_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(nodes.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(bytemuck::cast_slice(triangles));
+    bytes.extend_from_slice(bytemuck::cast_slice(nodes));
+    bytes
+  }
+
+  // Triangle and node counts of a whole collision file, once it's safe to query
+  fn check_bytes(bytes: &[u8]) -> Result<(usize, usize), String> {
+    if bytes.get(..4) != Some(COLLISION_MAGIC.as_slice()) {
+      return Err("not a collision mesh".to_string());
+    }
+    let Some(header) = bytes.get(4..COLLISION_HEADER_LEN) else {
+      return Err("collision mesh is too short".to_string());
+    };
+    let word =
+      |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+    let (version, triangle_count, node_count) = (word(0), word(4) as usize, word(8) as usize);
+    if version != COLLISION_VERSION {
+      return Err(format!("unsupported collision mesh version {version}"));
+    }
+    if bytes.len() != COLLISION_HEADER_LEN + triangle_count * 36 + node_count * 32 {
+      return Err(format!(
+        "collision mesh of {triangle_count} triangles and {node_count} nodes has {} bytes",
+        bytes.len()
+      ));
+    }
+    // A bad file must not send queries out of bounds or around in a loop. Nodes are read one at a
+    // time so this works wherever the bytes start
+    let nodes_start = COLLISION_HEADER_LEN + triangle_count * 36;
+    for node_idx in 0..node_count {
+      let node_bytes = &bytes[nodes_start + node_idx * 32..nodes_start + (node_idx + 1) * 32];
+      let node: BvhNode = bytemuck::pod_read_unaligned(node_bytes);
+      let in_bounds = match node.count {
+        0 => node_idx + 1 < node_count && (node.first as usize) < node_count,
+        _ => node.first as usize + node.count as usize <= triangle_count,
+      };
+      if !in_bounds || (node.count == 0 && node.first as usize <= node_idx + 1) {
+        return Err(format!("collision mesh node {node_idx} points out of bounds"));
+      }
+    }
+    Ok((triangle_count, node_count))
+  }
+
+  // Copies the triangles and nodes out of bytes
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+    let (triangle_count, _) = Self::check_bytes(bytes)?;
+    let (triangles, nodes) = bytes[COLLISION_HEADER_LEN..].split_at(triangle_count * 36);
+    Ok(Self {
+      data: MeshData::Owned {
+        triangles: bytemuck::pod_collect_to_vec(triangles),
+        nodes: bytemuck::pod_collect_to_vec(nodes),
+      },
+    })
+  }
+
+  // Queries the mesh straight from bytes, which are kept alive as long as the mesh is. Bytes that
+  // don't start 4 byte aligned can't be viewed and are copied like from_bytes
+  pub fn from_shared_bytes(bytes: SharedBytes) -> Result<Self, String> {
+    let (triangle_count, _) = Self::check_bytes((*bytes).as_ref())?;
+    if collision_sections((*bytes).as_ref(), triangle_count).is_none() {
+      return Self::from_bytes((*bytes).as_ref());
+    }
+    Ok(Self { data: MeshData::Viewed { bytes, triangle_count } })
+  }
+
+  // Whether the mesh is still read from the bytes it was loaded from
+  pub fn is_viewed(&self) -> bool {
+    matches!(self.data, MeshData::Viewed { .. })
+  }
+
+  pub fn write_to_file(&self, path: &Path) -> Result<(), String> {
+    std::fs::write(path, self.to_bytes())
+      .map_err(|e| format!("at writing collision mesh {}: {e}", path.display()))
+  }
+
+  // Viewed where the vfs has it, in packs that's the mapped pack and nothing is read up front
+  pub fn load(vfs: &vfs::Vfs, path: &str) -> Result<Self, String> {
+    let bytes =
+      vfs.read_mapped(path).map_err(|e| format!("at reading collision mesh {path}: {e}"))?;
+    Self::from_shared_bytes(Arc::new(bytes)).map_err(|e| format!("at loading {path}: {e}"))
+  }
+}
@@ -0,0 +1,2 @@
+pub use physics_structs::primitives::{polygon_face::PolygonFace, sphere::Sphere, RigidBodyType};
+pub use physics_structs::{Mass, MomentOfInertia};
@@ -0,0 +1,624 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use geometry::{glam, Direction, LineSegment, Plane, Point};
+use rng::test_support::fuzz;
+use rng::RngStream;
+use vfs::test_support::TempDir;
+
+use crate::static_mesh::StaticTriangleMesh;
+use crate::structs::{Mass, PolygonFace, RigidBodyType};
+use crate::PhysicsEngine;
+
+// Set, running the ignored tests, to write the hashes the scenarios ended with as the expected ones
+// for this platform
+const UPDATE_HASHES_VAR: &str = "RESIDUE_UPDATE_PHYSICS_HASHES";
+// Fixed steps each scenario runs for
+const SCENARIO_STEPS: usize = 4000;
+// Steps of the quick check the default test run does, long enough for the thrown boxes to collide
+const SHORT_SCENARIO_STEPS: usize = 240;
+
+// Engine settings that change results, a scenario's hash is only expected to match with the same
+#[derive(Debug, Clone, Copy)]
+struct Mode {
+  name: &'static str,
+  tick_hz: usize,
+  substeps: usize,
+}
+
+const MODES: [Mode; 2] = [
+  Mode { name: "default", tick_hz: 60, substeps: 1 },
+  Mode { name: "substeps", tick_hz: 120, substeps: 4 },
+];
+
+struct Body {
+  name: String,
+  size: glam::Vec3,
+  position: glam::Vec3,
+  rotation: glam::Quat,
+  // None for static bodies
+  mass: Option<f32>,
+  velocity: glam::Vec3,
+}
+
+impl Body {
+  fn fixed(name: &str, size: glam::Vec3, position: glam::Vec3, rotation: glam::Quat) -> Self {
+    Self {
+      name: name.to_string(),
+      size,
+      position,
+      rotation,
+      mass: None,
+      velocity: glam::Vec3::ZERO,
+    }
+  }
+
+  fn dynamic(name: &str, size: glam::Vec3, position: glam::Vec3, mass: f32) -> Self {
+    Self {
+      name: name.to_string(),
+      size,
+      position,
+      rotation: glam::Quat::IDENTITY,
+      mass: Some(mass),
+      velocity: glam::Vec3::ZERO,
+    }
+  }
+
+  // Same as the cuboids scenes make
+  fn make_mesh(&self) -> Vec<RigidBodyType> {
+    PolygonFace::new_cuboid(
+      Point::from_vec3(glam::Vec3::ZERO),
+      Direction::from_vec3(glam::vec3(self.size.x, 0.0, 0.0)),
+      Direction::from_vec3(glam::vec3(0.0, self.size.y, 0.0)),
+      self.size.z,
+    )
+    .into_iter()
+    .map(RigidBodyType::PolygonFace)
+    .collect()
+  }
+
+  fn transform(&self) -> glam::Mat4 {
+    glam::Mat4::from_rotation_translation(self.rotation, self.position)
+  }
+}
+
+fn floor() -> Body {
+  Body::fixed(
+    "floor",
+    glam::vec3(40.0, 1.0, 40.0),
+    glam::vec3(0.0, -0.5, 0.0),
+    glam::Quat::IDENTITY,
+  )
+}
+
+// Cubes on top of each other, each a little off the one under it so the stack has to settle
+fn stack_scenario() -> Vec<Body> {
+  let mut bodies = vec![floor()];
+  for i in 0..6 {
+    let offset = glam::vec3(0.03 * (i % 3) as f32, 0.0, -0.02 * (i % 2) as f32);
+    let position = glam::vec3(0.0, 0.5 + i as f32 * 1.01, 0.0) + offset;
+    bodies.push(Body::dynamic(&format!("stack_{i}"), glam::Vec3::ONE, position, 1.0));
+  }
+  bodies
+}
+
+// Boxes of different weights sliding off a tilted slab onto the floor
+fn ramp_scenario() -> Vec<Body> {
+  let tilt = glam::Quat::from_rotation_z(0.35);
+  let mut bodies =
+    vec![floor(), Body::fixed("ramp", glam::vec3(8.0, 0.5, 4.0), glam::vec3(0.0, 2.0, 0.0), tilt)];
+  for i in 0..3 {
+    let along = tilt * glam::vec3(1.0 + i as f32 * 1.5, 0.8, -1.0 + i as f32);
+    let position = glam::vec3(0.0, 2.0, 0.0) + along;
+    let size = glam::Vec3::splat(0.6 + 0.1 * i as f32);
+    bodies.push(Body::dynamic(&format!("slider_{i}"), size, position, 0.5 + i as f32));
+  }
+  bodies
+}
+
+// Boxes thrown at each other over the floor, head on and glancing
+fn collision_scenario() -> Vec<Body> {
+  let mut bodies = vec![floor()];
+  let throws = [
+    (glam::vec3(-6.0, 1.0, 0.0), glam::vec3(8.0, 0.0, 0.0), 1.0),
+    (glam::vec3(6.0, 1.0, 0.2), glam::vec3(-6.0, 0.0, 0.0), 3.0),
+    (glam::vec3(0.3, 1.0, -6.0), glam::vec3(0.0, 1.0, 7.0), 2.0),
+    (glam::vec3(0.0, 5.0, 0.0), glam::vec3(0.5, 0.0, -0.5), 0.5),
+  ];
+  for (i, (position, velocity, mass)) in throws.into_iter().enumerate() {
+    let mut body = Body::dynamic(&format!("thrown_{i}"), glam::Vec3::ONE, position, mass);
+    body.velocity = velocity;
+    bodies.push(body);
+  }
+  bodies
+}
+
+// Name and the bodies it starts with
+type Scenario = (&'static str, fn() -> Vec<Body>);
+
+const SCENARIOS: [Scenario; 3] =
+  [("stack", stack_scenario), ("ramp", ramp_scenario), ("collisions", collision_scenario)];
+
+// FNV-1a, DefaultHasher isn't promised to stay the same between rust versions
+fn hash_bits(hash: u64, value: f32) -> u64 {
+  value
+    .to_bits()
+    .to_le_bytes()
+    .iter()
+    .fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+// Hash of where every dynamic body ended up, bit for bit
+fn run_scenario(bodies: &[Body], mode: Mode, steps: usize) -> Result<u64, String> {
+  let mut physics_engine = PhysicsEngine::new(mode.tick_hz, 1);
+  physics_engine.set_substeps(mode.substeps);
+  for body in bodies.iter() {
+    let mass = body.mass.map_or(Mass::Infinite, Mass::Finite);
+    physics_engine.add_rigid_body(&body.name, body.make_mesh(), body.transform(), mass);
+    physics_engine.set_velocity(&body.name, body.velocity)?;
+  }
+  let step_micros = 1_000_000 / mode.tick_hz as u128;
+  for _ in 0..steps {
+    physics_engine.run(step_micros);
+  }
+  let mut hash = 0xcbf2_9ce4_8422_2325;
+  for body in bodies.iter().filter(|x| x.mass.is_some()) {
+    let transform = physics_engine
+      .get_transform(&body.name)
+      .ok_or(format!("dynamic body {} went missing", body.name))?;
+    hash = transform.to_cols_array().into_iter().fold(hash, hash_bits);
+  }
+  Ok(hash)
+}
+
+fn platform() -> String {
+  format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)
+}
+
+fn hashes_path() -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR"))
+    .join("tests")
+    .join("determinism")
+    .join(format!("{}.txt", platform()))
+}
+
+// Lines of scenario, mode and hash in hex, # for comments
+fn read_hashes(path: &Path) -> Result<BTreeMap<(String, String), u64>, String> {
+  let text =
+    std::fs::read_to_string(path).map_err(|e| format!("at reading {}: {e}", path.display()))?;
+  let mut hashes = BTreeMap::new();
+  for line in text.lines().map(str::trim).filter(|x| !x.is_empty() && !x.starts_with('#')) {
+    let [scenario, mode, hash] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+      return Err(format!("at reading {}: bad line {line}", path.display()));
+    };
+    let hash = u64::from_str_radix(hash, 16)
+      .map_err(|e| format!("at reading {}: bad hash in {line}: {e}", path.display()))?;
+    hashes.insert((scenario.to_string(), mode.to_string()), hash);
+  }
+  Ok(hashes)
+}
+
+fn write_hashes(path: &Path, hashes: &BTreeMap<(String, String), u64>) -> Result<(), String> {
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir).map_err(|e| format!("at making dir {}: {e}", dir.display()))?;
+  }
+  let mut text = format!(
+    "# Physics state hashes after {SCENARIO_STEPS} steps on {}, rewrite with {UPDATE_HASHES_VAR}\n",
+    platform()
+  );
+  for ((scenario, mode), hash) in hashes.iter() {
+    text += &format!("{scenario} {mode} {hash:016x}\n");
+  }
+  std::fs::write(path, text).map_err(|e| format!("at writing {}: {e}", path.display()))
+}
+
+#[test]
+fn a_short_physics_scenario_ends_in_the_same_state_every_run() {
+  let bodies = collision_scenario();
+  for mode in MODES {
+    let hash = run_scenario(&bodies, mode, SHORT_SCENARIO_STEPS).unwrap();
+    let again = run_scenario(&bodies, mode, SHORT_SCENARIO_STEPS).unwrap();
+    assert_eq!(hash, again, "collisions in mode {} diverged between runs", mode.name);
+  }
+}
+
+#[test]
+#[ignore = "takes minutes, run with --ignored after changing the physics"]
+fn physics_scenarios_end_in_the_same_state_every_run() {
+  let mut hashes = BTreeMap::new();
+  for (scenario, make_bodies) in SCENARIOS {
+    let bodies = make_bodies();
+    for mode in MODES {
+      let hash = run_scenario(&bodies, mode, SCENARIO_STEPS).unwrap();
+      // Again from scratch, anything depending on more than the inputs shows up here
+      let again = run_scenario(&bodies, mode, SCENARIO_STEPS).unwrap();
+      assert_eq!(hash, again, "{scenario} in mode {} diverged between runs", mode.name);
+      hashes.insert((scenario.to_string(), mode.name.to_string()), hash);
+    }
+  }
+
+  // Recording is opt in, a platform without hashes fails until someone records and checks them in
+  let path = hashes_path();
+  if std::env::var_os(UPDATE_HASHES_VAR).is_some() {
+    write_hashes(&path, &hashes).unwrap();
+    eprintln!("recorded physics hashes {}", path.display());
+    return;
+  }
+  let expected = read_hashes(&path)
+    .map_err(|e| format!("{e}, run with {UPDATE_HASHES_VAR} set to record this platform's hashes"))
+    .unwrap();
+  let mismatches = hashes
+    .iter()
+    .filter(|(key, hash)| expected.get(*key) != Some(*hash))
+    .map(|((scenario, mode), hash)| {
+      let expected = expected.get(&(scenario.clone(), mode.clone()));
+      format!("{scenario} {mode}: {hash:016x}, expected {expected:016x?}")
+    })
+    .collect::<Vec<_>>();
+  assert!(
+    mismatches.is_empty(),
+    "physics diverged from {}, set {UPDATE_HASHES_VAR} if the change was meant:\n{}",
+    path.display(),
+    mismatches.join("\n")
+  );
+}
+
+fn random_vec3(rng: &mut RngStream, scale: f32) -> glam::Vec3 {
+  glam::Vec3::from_array(rng.in_unit_sphere()) * scale
+}
+
+fn random_dir(rng: &mut RngStream) -> glam::Vec3 {
+  glam::Vec3::from_array(rng.in_unit_sphere()).try_normalize().unwrap_or(glam::Vec3::Y)
+}
+
+// Small accelerations a quarter of the time, where the quadratic loses precision, none another
+fn random_acc(rng: &mut RngStream) -> f32 {
+  match rng.range_u32(0, 4) {
+    0 => 0.0,
+    1 => rng.range_f32(-1e-3, 1e-3),
+    _ => rng.range_f32(-20.0, 20.0),
+  }
+}
+
+#[test]
+fn constant_acceleration_roots_are_future_times_the_gap_closes() {
+  fuzz("constant acceleration roots", |rng| {
+    let d = rng.range_f32(0.0, 2.0) * *rng.pick(&[1e-3, 1.0, 1e3]).unwrap();
+    let (u, a) = (rng.range_f32(-20.0, 20.0), random_acc(rng));
+    for t in PhysicsEngine::solve_const_acc(d, u, a) {
+      let gap = d + u * t + 0.5 * a * t * t;
+      let size = d.abs() + (u * t).abs() + (0.5 * a * t * t).abs();
+      if !t.is_finite() || t < 0.0 || gap.abs() > 1e-4 * size {
+        return Err(format!("gap {d} closing at {u} by {a} has root {t}, leaving {gap}"));
+      }
+    }
+    Ok(())
+  });
+}
+
+#[test]
+fn point_plane_collisions_land_the_point_on_the_plane() {
+  fuzz("point plane collision time", |rng| {
+    let normal = random_dir(rng);
+    let plane = Plane::new(Direction::from_vec3(normal), Point::from_vec3(random_vec3(rng, 5.0)));
+    let dist = rng.range_f32(0.0, 2.0);
+    let point = Point::from_vec3(plane.get_point().as_vec3() + normal * dist);
+    let (point_vel, plane_vel) = (random_vec3(rng, 10.0), random_vec3(rng, 10.0));
+    let (point_acc, plane_acc) = (random_dir(rng) * random_acc(rng), glam::Vec3::ZERO);
+
+    let Some((time_s, point_disp, plane_disp)) = PhysicsEngine::plane_point_coll_time(
+      point,
+      point_vel,
+      point_acc,
+      plane,
+      &[],
+      plane_vel,
+      plane_acc,
+    ) else {
+      // Misses are fine unless the point was sure to get there, pulled towards the plane or
+      // coming in too fast to be turned around first
+      let closing = (point_vel - plane_vel).dot(normal);
+      let turning = (point_acc - plane_acc).dot(normal);
+      let sure_hit = dist == 0.0
+        || turning < 0.0
+        || (closing < 0.0 && closing * closing > 2.0 * turning * dist * 1.001);
+      return match !sure_hit {
+        true => Ok(()),
+        false => Err(format!("{dist} away closing at {closing} by {turning} never hit")),
+      };
+    };
+    let moved_dist = plane.displace(plane_disp).dist_from_point(&point.displace(point_disp));
+    // Float error grows with how far they went, tiny accelerations take ages to turn points around
+    let tolerance = 1e-4 * (1.0 + dist + point_disp.length() + plane_disp.length());
+    if !time_s.is_finite() || time_s < 0.0 || moved_dist.abs() > tolerance {
+      return Err(format!("hit at {time_s} left the point {moved_dist} from the plane"));
+    }
+    Ok(())
+  });
+}
+
+#[test]
+fn crossing_segments_collide_at_the_same_time_either_way_round() {
+  fuzz("segment collision time", |rng| {
+    let dir_1 = random_dir(rng);
+    let dir_2 = random_dir(rng);
+    let across = dir_1.cross(dir_2);
+    // Near parallel segments slide along each other instead of crossing
+    if across.length() < 0.3 {
+      return Ok(());
+    }
+    let across = across.normalize();
+    let center = random_vec3(rng, 5.0);
+    let gap = rng.range_f32(0.01, 2.0);
+    let segment_1 = LineSegment::from_vec3s(center - dir_1, center + dir_1);
+    let segment_2 = LineSegment::from_vec3s(
+      center + across * gap - dir_2 * rng.range_f32(0.2, 1.0),
+      center + across * gap + dir_2 * rng.range_f32(0.2, 1.0),
+    );
+    // Closing the gap head on, with whatever sliding along it on top
+    let speed = rng.range_f32(0.5, 10.0);
+    let slide = random_vec3(rng, 0.05).reject_from(across);
+    let (vel_1, vel_2) = (across * speed * 0.5 + slide, -across * speed * 0.5);
+    let expected = gap / speed;
+
+    let zero = glam::Vec3::ZERO;
+    let forward = PhysicsEngine::line_seg_coll_time(segment_1, vel_1, zero, segment_2, vel_2, zero);
+    let backward =
+      PhysicsEngine::line_seg_coll_time(segment_2, vel_2, zero, segment_1, vel_1, zero);
+    let (Some((forward_time, disp_1, _)), Some((backward_time, _, _))) = (forward, backward) else {
+      return Err(format!("missed a hit {expected} away: {forward:?} and {backward:?}"));
+    };
+    let tolerance = 1e-3 * (1.0 + expected);
+    if (forward_time - expected).abs() > tolerance || (backward_time - expected).abs() > tolerance {
+      return Err(format!("hit at {forward_time} and {backward_time}, {expected} expected"));
+    }
+    if disp_1.distance(vel_1 * forward_time) > tolerance {
+      return Err(format!("segment 1 moved {disp_1} by {forward_time}"));
+    }
+    Ok(())
+  });
+}
+
+// Case seed 9 of the roots property, -u + sqrt(det) cancelled the small root down to noise
+#[test]
+fn tiny_gap_closing_fast_has_an_accurate_small_root() {
+  let (d, u, a) = (0.0000051667694, -14.701511, 16.823399);
+  let roots = PhysicsEngine::solve_const_acc(d, u, a);
+  assert!(!roots.is_empty(), "gap {d} closing at {u} by {a} never closed");
+  for t in roots {
+    let gap = d + u * t + 0.5 * a * t * t;
+    let size = d.abs() + (u * t).abs() + (0.5 * a * t * t).abs();
+    assert!(gap.abs() <= 1e-4 * size, "root {t} left a gap of {gap}");
+  }
+}
+
+// Case seed 0 of the segment property, segment 2's start was moved with segment 1's velocity
+#[test]
+fn segments_closing_head_on_hit_when_the_gap_closes() {
+  let segment_1 = LineSegment::from_vec3s(
+    glam::vec3(-4.1447515, 1.0308304, 0.78971386),
+    glam::vec3(-3.7217557, 2.0658324, -0.86855173),
+  );
+  let segment_2 = LineSegment::from_vec3s(
+    glam::vec3(-3.1862378, 1.4446055, -0.6509211),
+    glam::vec3(-3.4233234, 1.5532751, 0.4326318),
+  );
+  let vel_1 = glam::vec3(2.7860723, -0.1605061, 0.6328612);
+  let vel_2 = glam::vec3(-2.7890406, 0.13967364, -0.6242608);
+  let zero = glam::Vec3::ZERO;
+  let expected = 0.10480079;
+
+  let forward = PhysicsEngine::line_seg_coll_time(segment_1, vel_1, zero, segment_2, vel_2, zero);
+  let backward = PhysicsEngine::line_seg_coll_time(segment_2, vel_2, zero, segment_1, vel_1, zero);
+  for (order, hit) in [("forward", forward), ("backward", backward)] {
+    let (time_s, _, _) = hit.unwrap_or_else(|| panic!("{order} missed the hit"));
+    assert!((time_s - expected).abs() < 1e-3, "{order} hit at {time_s}, {expected} expected");
+  }
+}
+
+#[test]
+fn contact_pairs_are_counted_for_the_last_step() {
+  let bodies = [
+    floor(),
+    Body::dynamic("resting", glam::Vec3::ONE, glam::vec3(0.0, 0.5, 0.0), 1.0),
+    Body::dynamic("falling", glam::Vec3::ONE, glam::vec3(10.0, 30.0, 0.0), 1.0),
+  ];
+  let mut physics_engine = PhysicsEngine::new(60, 1);
+  for body in bodies.iter() {
+    let mass = body.mass.map_or(Mass::Infinite, Mass::Finite);
+    physics_engine.add_rigid_body(&body.name, body.make_mesh(), body.transform(), mass);
+  }
+  assert_eq!(physics_engine.contact_pair_count(), 0);
+  physics_engine.run(1_000_000 / 60);
+  // Only the cube on the floor, the other one is still far above it
+  assert_eq!(physics_engine.contact_pair_count(), 1);
+}
+
+#[test]
+fn restored_snapshots_run_on_like_the_engine_they_were_taken_from() {
+  let bodies = collision_scenario();
+  let new_engine = || {
+    let mut physics_engine = PhysicsEngine::new(60, 1);
+    for body in bodies.iter() {
+      let mass = body.mass.map_or(Mass::Infinite, Mass::Finite);
+      physics_engine.add_rigid_body(&body.name, body.make_mesh(), body.transform(), mass);
+      physics_engine.set_velocity(&body.name, body.velocity).unwrap();
+    }
+    physics_engine
+  };
+  let step_micros = 1_000_000 / 60;
+  let mut original = new_engine();
+  for _ in 0..200 {
+    original.run(step_micros);
+  }
+  // Through bytes like a repro bundle, half a step in so the leftover time is kept too
+  original.run(step_micros / 2);
+  let snapshot = bincode::serialize(&original.snapshot()).unwrap();
+  let snapshot = bincode::deserialize(&snapshot).unwrap();
+  let mut restored = new_engine();
+  restored.restore(&snapshot).unwrap();
+  assert_eq!(restored.snapshot(), snapshot);
+
+  for _ in 0..400 {
+    original.run(step_micros);
+    restored.run(step_micros);
+  }
+  for body in bodies.iter() {
+    let expected = original.get_transform(&body.name).unwrap().to_cols_array().map(f32::to_bits);
+    let got = restored.get_transform(&body.name).unwrap().to_cols_array().map(f32::to_bits);
+    assert_eq!(got, expected, "{} diverged after restoring", body.name);
+  }
+
+  let mut empty = PhysicsEngine::new(60, 1);
+  assert!(empty.restore(&snapshot).is_err());
+}
+
+#[test]
+fn debug_shapes_show_the_box_outline_and_last_contacts_of_a_body() {
+  let bodies = [floor(), Body::dynamic("cube", glam::Vec3::ONE, glam::vec3(0.0, 0.5, 0.0), 1.0)];
+  let mut physics_engine = PhysicsEngine::new(60, 1);
+  for body in bodies.iter() {
+    let mass = body.mass.map_or(Mass::Infinite, Mass::Finite);
+    physics_engine.add_rigid_body(&body.name, body.make_mesh(), body.transform(), mass);
+  }
+  assert!(physics_engine.body_debug_shape("missing").is_none());
+  physics_engine.run(1_000_000 / 60);
+
+  let shape = physics_engine.body_debug_shape("cube").unwrap();
+  let (min, max) = shape.bounds.unwrap();
+  assert!((max - min - glam::Vec3::ONE).abs().max_element() < 0.05, "{min} {max}");
+  // Six faces of four edges
+  assert_eq!(shape.edges.len(), 24);
+  assert!(!shape.contacts.is_empty());
+  // The floor pushes the cube up, so the normals face down out of it
+  for (point, normal) in shape.contacts.iter() {
+    assert!(point.y.abs() < 0.1, "contact at {point}");
+    assert!(normal.y < -0.9, "normal {normal}");
+  }
+}
+
+#[test]
+fn shifted_origins_leave_bodies_where_they_are_in_the_world() {
+  let bodies = stack_scenario();
+  let new_engine = || {
+    let mut physics_engine = PhysicsEngine::new(60, 1);
+    for body in bodies.iter() {
+      let mass = body.mass.map_or(Mass::Infinite, Mass::Finite);
+      physics_engine.add_rigid_body(&body.name, body.make_mesh(), body.transform(), mass);
+    }
+    physics_engine
+  };
+  let step_micros = 1_000_000 / 60;
+  let mut unshifted = new_engine();
+  let mut shifted = new_engine();
+  // Halfway through settling, with contacts cached
+  for _ in 0..60 {
+    unshifted.run(step_micros);
+    shifted.run(step_micros);
+  }
+  let shift = glam::vec3(300.0, 0.0, -200.0);
+  shifted.shift_origin(shift);
+  assert_eq!(shifted.origin(), shift.as_dvec3());
+  for _ in 0..120 {
+    unshifted.run(step_micros);
+    shifted.run(step_micros);
+  }
+  for body in bodies.iter() {
+    let expected = unshifted.world_position(&body.name).unwrap();
+    let got = shifted.world_position(&body.name).unwrap();
+    assert!((got - expected).length() < 1e-2, "{} at {got}, not {expected}", body.name);
+  }
+
+  assert_eq!(shifted.recenter_on(&bodies[0].name, 1000.0), None);
+  let shift = shifted.recenter_on(&bodies[0].name, 10.0).unwrap();
+  assert!(shifted.get_transform(&bodies[0].name).unwrap().w_axis.truncate().length() < 1.0);
+  assert_eq!(shifted.origin(), glam::vec3(300.0, 0.0, -200.0).as_dvec3() + shift.as_dvec3());
+
+  // Restoring moves the rest of the engine onto the snapshot's origin
+  let mut restored = new_engine();
+  restored.restore(&shifted.snapshot()).unwrap();
+  assert_eq!(restored.origin(), shifted.origin());
+  for body in bodies.iter() {
+    assert_eq!(restored.get_transform(&body.name), shifted.get_transform(&body.name));
+  }
+}
+
+// Bytes one past the start of a buffer, so never 4 byte aligned
+struct OffsetBytes(Vec<u8>);
+
+impl AsRef<[u8]> for OffsetBytes {
+  fn as_ref(&self) -> &[u8] {
+    &self.0[1..]
+  }
+}
+
+#[test]
+fn collision_meshes_are_queried_straight_from_their_bytes() {
+  // A 4 by 4 grid of quads on the ground
+  let positions =
+    (0..25).map(|i| glam::vec3((i % 5) as f32, 0.0, (i / 5) as f32)).collect::<Vec<_>>();
+  let indices = (0..16)
+    .flat_map(|quad| {
+      let corner = quad / 4 * 5 + quad % 4;
+      [[corner, corner + 5, corner + 1], [corner + 1, corner + 5, corner + 6]]
+    })
+    .collect::<Vec<_>>();
+  let baked = StaticTriangleMesh::bake(&positions, &indices).unwrap();
+  let bytes = baked.to_bytes();
+  let down = glam::Vec3::NEG_Y;
+  let expected_hit = baked.raycast(glam::vec3(2.3, 5.0, 1.7), down, 10.0);
+  assert!(expected_hit.is_some());
+
+  let viewed = StaticTriangleMesh::from_shared_bytes(Arc::new(bytes.clone())).unwrap();
+  let mut unaligned_bytes = vec![0];
+  unaligned_bytes.extend_from_slice(&bytes);
+  let copied =
+    StaticTriangleMesh::from_shared_bytes(Arc::new(OffsetBytes(unaligned_bytes))).unwrap();
+  assert!(viewed.is_viewed());
+  assert!(!copied.is_viewed());
+  for mesh in [&viewed, &copied] {
+    assert_eq!(mesh.triangle_count(), baked.triangle_count());
+    assert_eq!(mesh.bounds_min_max(), baked.bounds_min_max());
+    assert_eq!(mesh.raycast(glam::vec3(2.3, 5.0, 1.7), down, 10.0), expected_hit);
+    assert_eq!(mesh.to_bytes(), bytes);
+  }
+
+  // Moving a viewed mesh copies it out of the bytes
+  let mut moved = viewed.clone();
+  moved.translate(glam::Vec3::Y);
+  assert!(!moved.is_viewed());
+  assert!(viewed.is_viewed());
+  let (t, _) = moved.raycast(glam::vec3(2.3, 5.0, 1.7), down, 10.0).unwrap();
+  assert!((t - (expected_hit.unwrap().0 - 1.0)).abs() < 1e-5);
+
+  // Bad node links are caught however the bytes are given
+  let mut bad_bytes = bytes.clone();
+  let first_node = 16 + baked.triangle_count() * 36;
+  bad_bytes[first_node + 24..first_node + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+  assert!(StaticTriangleMesh::from_bytes(&bad_bytes).is_err());
+  assert!(StaticTriangleMesh::from_shared_bytes(Arc::new(bad_bytes)).is_err());
+}
+
+#[test]
+fn collision_meshes_load_through_the_vfs_mapped_from_packs() {
+  let positions =
+    [glam::vec3(-5.0, 0.0, -5.0), glam::vec3(-5.0, 0.0, 5.0), glam::vec3(5.0, 0.0, 0.0)];
+  let baked = StaticTriangleMesh::bake(&positions, &[[0, 1, 2]]).unwrap();
+  let temp = TempDir::new("physics_collision_pack");
+  let src = temp.path().join("src");
+  std::fs::create_dir_all(src.join("levels")).unwrap();
+  baked.write_to_file(&src.join("levels/one.collision")).unwrap();
+  let pack_path = temp.path().join("levels.rpak");
+  vfs::write_pack(&src, &pack_path).unwrap();
+  let vfs = vfs::Vfs::new();
+  vfs.mount("", &pack_path).unwrap();
+
+  assert!(StaticTriangleMesh::load(&vfs, "levels/one.collision").unwrap().is_viewed());
+  let mut physics_engine = PhysicsEngine::new(60, 4);
+  physics_engine.load_static_mesh("level", &vfs, "levels/one.collision").unwrap();
+  let (name, dist) =
+    physics_engine.raycast_static(glam::vec3(0.0, 3.0, 0.0), glam::Vec3::NEG_Y, 10.0).unwrap();
+  assert_eq!((name, dist), ("level", 3.0));
+  assert!(physics_engine.load_static_mesh("missing", &vfs, "levels/two.collision").is_err());
+}
@@ -0,0 +1,19 @@
+use geometry::glam;
+
+// Bullet time bubble, bodies with their center inside advance by scale of every step
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimeZone {
+  pub center: glam::Vec3,
+  pub radius: f32,
+  pub scale: f32,
+}
+
+// The slowest zone a point is in wins, 1 outside all of them
+pub fn time_scale_at(zones: &[TimeZone], point: glam::Vec3) -> f32 {
+  zones
+    .iter()
+    .filter(|zone| zone.center.distance_squared(point) <= zone.radius * zone.radius)
+    .map(|zone| zone.scale.max(0.0))
+    .reduce(f32::min)
+    .unwrap_or(1.0)
+}
@@ -0,0 +1,219 @@
+use geometry::glam;
+
+// Below this forward speed slip is measured against it instead, so cars at rest don't divide by 0
+const SLIP_MIN_SPEED: f32 = 1.0;
+
+// Mounts are in body space, with +y up and +z forward. Wheels hang below them along -y
+#[derive(Debug, Clone)]
+pub struct WheelConfig {
+  pub mount: glam::Vec3,
+  pub radius: f32,
+  // Suspension length with nothing on it
+  pub rest_length: f32,
+  pub stiffness: f32,
+  pub damping: f32,
+  // Share of the wheel's load the tyre can push back with before sliding
+  pub grip: f32,
+  // Force per unit of forward slip ratio and per radian of sideways slip, before grip runs out
+  pub slip_stiffness: f32,
+  pub cornering_stiffness: f32,
+  // Spin inertia of the wheel. Light wheels with stiff tyres need sub-steps to stay steady
+  pub inertia: f32,
+  pub driven: bool,
+  pub steered: bool,
+}
+
+impl WheelConfig {
+  // Undriven and unsteered, sprung for a car of about a tonne on four wheels
+  pub fn new(mount: glam::Vec3, radius: f32, rest_length: f32) -> Self {
+    Self {
+      mount,
+      radius,
+      rest_length,
+      stiffness: 30000.0,
+      damping: 3000.0,
+      grip: 1.0,
+      slip_stiffness: 10000.0,
+      cornering_stiffness: 10000.0,
+      inertia: 1.5,
+      driven: false,
+      steered: false,
+    }
+  }
+}
+
+// Torques are per wheel. Positive steering turns steered wheels from +z towards +x
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct VehicleInput {
+  pub engine_torque: f32,
+  pub brake_torque: f32,
+  pub steer_angle: f32,
+}
+
+#[derive(Debug, Clone)]
+struct Wheel {
+  config: WheelConfig,
+  steer_angle: f32,
+  suspension_length: f32,
+  compression: f32,
+  spin_angle: f32,
+  // Radians per second, positive rolls the car along +z
+  spin_velocity: f32,
+  grounded: bool,
+}
+
+impl Wheel {
+  fn spin(&mut self, torque: f32, brake_torque: f32, time_s: f32) {
+    self.spin_velocity += torque / self.config.inertia * time_s;
+    // Brakes slow the wheel down to a stop but don't turn it back
+    let brake_change = brake_torque.max(0.0) / self.config.inertia * time_s;
+    self.spin_velocity =
+      self.spin_velocity.signum() * (self.spin_velocity.abs() - brake_change).max(0.0);
+    self.spin_angle = (self.spin_angle + self.spin_velocity * time_s) % std::f32::consts::TAU;
+  }
+}
+
+// Car on a rigid body held up by rays cast down from each wheel's mount, springs push the body
+// up and tyre friction pushes it along the ground
+#[derive(Debug, Clone)]
+pub struct Vehicle {
+  body: String,
+  wheels: Vec<Wheel>,
+  input: VehicleInput,
+}
+
+impl Vehicle {
+  pub fn new(body: &str, wheels: Vec<WheelConfig>) -> Self {
+    let wheels = wheels
+      .into_iter()
+      .map(|config| Wheel {
+        steer_angle: 0.0,
+        suspension_length: config.rest_length,
+        compression: 0.0,
+        spin_angle: 0.0,
+        spin_velocity: 0.0,
+        grounded: false,
+        config,
+      })
+      .collect();
+    Self { body: body.to_string(), wheels, input: VehicleInput::default() }
+  }
+
+  pub fn body(&self) -> &str {
+    &self.body
+  }
+
+  pub fn input(&self) -> VehicleInput {
+    self.input
+  }
+
+  pub fn set_input(&mut self, input: VehicleInput) {
+    self.input = input;
+  }
+
+  pub fn wheel_count(&self) -> usize {
+    self.wheels.len()
+  }
+
+  pub fn wheel_grounded(&self, wheel_idx: usize) -> bool {
+    self.wheels.get(wheel_idx).is_some_and(|x| x.grounded)
+  }
+
+  // Radians per second, for engine sound and the like
+  pub fn wheel_spin_velocity(&self, wheel_idx: usize) -> Option<f32> {
+    self.wheels.get(wheel_idx).map(|x| x.spin_velocity)
+  }
+
+  // Where to draw each wheel with the body at body_transform, steered, spinning about its x axle
+  // and sitting where its suspension is
+  pub fn wheel_transforms(&self, body_transform: glam::Mat4) -> Vec<glam::Mat4> {
+    self
+      .wheels
+      .iter()
+      .map(|wheel| {
+        let hub = wheel.config.mount - glam::Vec3::Y * wheel.suspension_length;
+        body_transform
+          * glam::Mat4::from_translation(hub)
+          * glam::Mat4::from_rotation_y(wheel.steer_angle)
+          * glam::Mat4::from_rotation_x(-wheel.spin_angle)
+      })
+      .collect()
+  }
+
+  // World space origin, direction and length of each wheel's suspension ray
+  pub(crate) fn wheel_rays(
+    &self,
+    body_transform: glam::Mat4,
+  ) -> Vec<(glam::Vec3, glam::Vec3, f32)> {
+    let down = body_transform.transform_vector3(glam::Vec3::NEG_Y).normalize_or(glam::Vec3::NEG_Y);
+    self
+      .wheels
+      .iter()
+      .map(|wheel| {
+        let origin = body_transform.transform_point3(wheel.config.mount);
+        (origin, down, wheel.config.rest_length + wheel.config.radius)
+      })
+      .collect()
+  }
+
+  // Steps the wheels given how far each ray went before hitting the ground, returning impulses to
+  // apply to the body as (impulse, world point). Velocities are the body's, about its center
+  pub(crate) fn step(
+    &mut self,
+    time_s: f32,
+    body_transform: glam::Mat4,
+    center: glam::Vec3,
+    velocity: glam::Vec3,
+    angular_velocity: glam::Vec3,
+    hits: &[Option<f32>],
+  ) -> Vec<(glam::Vec3, glam::Vec3)> {
+    if time_s <= 0.0 {
+      return vec![];
+    }
+    let up = body_transform.transform_vector3(glam::Vec3::Y).normalize_or(glam::Vec3::Y);
+    let mut impulses = vec![];
+    for (wheel, hit) in self.wheels.iter_mut().zip(hits.iter()) {
+      wheel.steer_angle = if wheel.config.steered { self.input.steer_angle } else { 0.0 };
+      let drive_torque = if wheel.config.driven { self.input.engine_torque } else { 0.0 };
+      let Some(dist) = *hit else {
+        wheel.suspension_length = wheel.config.rest_length;
+        wheel.compression = 0.0;
+        wheel.grounded = false;
+        wheel.spin(drive_torque, self.input.brake_torque, time_s);
+        continue;
+      };
+      let config = &wheel.config;
+      let length = (dist - config.radius).clamp(0.0, config.rest_length);
+      let compression = config.rest_length - length;
+      let compression_speed = (compression - wheel.compression) / time_s;
+      // Springs only push, a wheel leaving the ground doesn't pull the body down with it
+      let load = (config.stiffness * compression + config.damping * compression_speed).max(0.0);
+
+      let steer = glam::Mat4::from_rotation_y(wheel.steer_angle);
+      let forward = (body_transform * steer).transform_vector3(glam::Vec3::Z).normalize_or_zero();
+      let side = (body_transform * steer).transform_vector3(glam::Vec3::X).normalize_or_zero();
+      let point = body_transform.transform_point3(config.mount) - up * dist;
+      let point_velocity = velocity + angular_velocity.cross(point - center);
+      let forward_speed = point_velocity.dot(forward);
+      let slip_speed = forward_speed.abs().max(SLIP_MIN_SPEED);
+      let slip_ratio = (wheel.spin_velocity * config.radius - forward_speed) / slip_speed;
+      let slip_angle = point_velocity.dot(side).atan2(slip_speed);
+      let mut friction =
+        glam::vec2(config.slip_stiffness * slip_ratio, -config.cornering_stiffness * slip_angle);
+      // Forward and sideways grip come out of the same budget, a spinning wheel slides sideways
+      let max_friction = config.grip * load;
+      if friction.length() > max_friction {
+        friction = friction.normalize_or_zero() * max_friction;
+      }
+      let torque = drive_torque - friction.x * config.radius;
+
+      wheel.suspension_length = length;
+      wheel.compression = compression;
+      wheel.grounded = true;
+      wheel.spin(torque, self.input.brake_torque, time_s);
+      let force = up * load + forward * friction.x + side * friction.y;
+      impulses.push((force * time_s, point));
+    }
+    impulses
+  }
+}
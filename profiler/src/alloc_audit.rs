@@ -0,0 +1,58 @@
+use std::{
+  alloc::{GlobalAlloc, Layout, System},
+  cell::Cell,
+  sync::atomic::{AtomicBool, Ordering},
+};
+
+// Set by the first allocation the counting allocator serves, counts read before are None
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// With the tracy feature every allocation and free is reported to Tracy's memory view too
+#[cfg(feature = "tracy")]
+static INNER: tracy_client::ProfiledAllocator<System> =
+  tracy_client::ProfiledAllocator::new(System, 0);
+#[cfg(not(feature = "tracy"))]
+static INNER: System = System;
+
+thread_local! {
+  static THREAD_ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+// The system allocator, counting allocations per thread to find them on paths that should have
+// none. Binaries opt in by making it their global allocator
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    count_allocation();
+    INNER.alloc(layout)
+  }
+
+  unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+    count_allocation();
+    INNER.alloc_zeroed(layout)
+  }
+
+  unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    count_allocation();
+    INNER.realloc(ptr, layout, new_size)
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    INNER.dealloc(ptr, layout)
+  }
+}
+
+fn count_allocation() {
+  if !INSTALLED.load(Ordering::Relaxed) {
+    INSTALLED.store(true, Ordering::Relaxed);
+  }
+  // Gone while the thread exits, those aren't counted
+  let _ = THREAD_ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+// Allocations and reallocations the calling thread made so far, None without the counting
+// allocator. Take the difference of two calls for what ran between them
+pub fn thread_allocations() -> Option<u64> {
+  INSTALLED.load(Ordering::Relaxed).then(|| THREAD_ALLOCATIONS.with(Cell::get))
+}
@@ -0,0 +1,176 @@
+use std::{
+  collections::HashMap,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, OnceLock,
+  },
+  time::{Duration, Instant},
+};
+
+use crash_report::log;
+
+use crate::FrameRecord;
+
+// What gpu frame times are reported under with report_time
+pub const GPU_FRAME_BUDGET: &str = "gpu";
+// Scopes starting with this are spent waiting on other threads or the gpu, they don't count
+// against the budget of the scope they're in
+const WAIT_SCOPE_PREFIX: &str = "wait_";
+// Named in each warning
+const SLOWEST_SCOPES_SHOWN: usize = 3;
+const DEFAULT_WARNING_INTERVAL: Duration = Duration::from_secs(5);
+
+struct BudgetState {
+  budget: Duration,
+  // Since the last warning
+  frames_over: u32,
+  worst: Duration,
+  worst_slowest: Vec<(&'static str, Duration)>,
+  last_warning: Option<Instant>,
+}
+
+struct Budgets {
+  // Set while any budget is, scopes are recorded for them even with the profiler off
+  active: AtomicBool,
+  warning_interval: Mutex<Duration>,
+  by_name: Mutex<HashMap<String, BudgetState>>,
+}
+
+fn budgets() -> &'static Budgets {
+  static BUDGETS: OnceLock<Budgets> = OnceLock::new();
+  BUDGETS.get_or_init(|| Budgets {
+    active: AtomicBool::new(false),
+    warning_interval: Mutex::new(DEFAULT_WARNING_INTERVAL),
+    by_name: Mutex::new(HashMap::new()),
+  })
+}
+
+pub(crate) fn is_active() -> bool {
+  budgets().active.load(Ordering::Relaxed)
+}
+
+// Time a frame may spend in the scopes called name, checked every frame of every thread. None
+// removes it
+pub fn set_budget(name: &str, budget: Option<Duration>) {
+  let Ok(mut by_name) = budgets().by_name.lock() else { return };
+  match budget {
+    Some(budget) => {
+      by_name.insert(
+        name.to_string(),
+        BudgetState {
+          budget,
+          frames_over: 0,
+          worst: Duration::ZERO,
+          worst_slowest: vec![],
+          last_warning: None,
+        },
+      );
+    }
+    None => {
+      by_name.remove(name);
+    }
+  }
+  budgets().active.store(!by_name.is_empty(), Ordering::Relaxed);
+}
+
+// Each budget warns at most once per interval, counting the frames over it in between
+pub fn set_budget_warning_interval(interval: Duration) {
+  if let Ok(mut warning_interval) = budgets().warning_interval.lock() {
+    *warning_interval = interval;
+  }
+}
+
+impl FrameRecord {
+  // Time spent in the scopes called name this frame, less waits right inside them. None when the
+  // frame had none
+  pub fn budget_time(&self, name: &str) -> Option<Duration> {
+    let mut total_us = None;
+    for (scope_idx, scope) in self.scopes.iter().enumerate().filter(|(_, x)| x.name == name) {
+      let waits_us = self
+        .children(Some(scope_idx))
+        .filter(|(_, child)| child.name.starts_with(WAIT_SCOPE_PREFIX))
+        .map(|(_, child)| child.duration_us())
+        .sum::<u64>();
+      *total_us.get_or_insert(0) += scope.duration_us().saturating_sub(waits_us);
+    }
+    total_us.map(Duration::from_micros)
+  }
+
+  // Scopes right inside the ones called name, by name and total time with the slowest first.
+  // Waits are left out
+  pub fn slowest_children(&self, name: &str, count: usize) -> Vec<(&'static str, Duration)> {
+    let mut by_child = HashMap::<&'static str, u64>::new();
+    for (scope_idx, _) in self.scopes.iter().enumerate().filter(|(_, x)| x.name == name) {
+      for (_, child) in self.children(Some(scope_idx)) {
+        if !child.name.starts_with(WAIT_SCOPE_PREFIX) {
+          *by_child.entry(child.name).or_default() += child.duration_us();
+        }
+      }
+    }
+    let mut slowest = by_child.into_iter().collect::<Vec<_>>();
+    slowest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    slowest
+      .into_iter()
+      .take(count)
+      .map(|(child, time_us)| (child, Duration::from_micros(time_us)))
+      .collect()
+  }
+}
+
+fn millis(duration: Duration) -> f32 {
+  duration.as_secs_f32() * 1000.0
+}
+
+fn check(name: &str, actual: Duration, slowest: impl FnOnce() -> Vec<(&'static str, Duration)>) {
+  let warning_interval = budgets().warning_interval.lock().map(|x| *x).unwrap_or_default();
+  let Ok(mut by_name) = budgets().by_name.lock() else { return };
+  let Some(state) = by_name.get_mut(name).filter(|state| actual > state.budget) else { return };
+  state.frames_over += 1;
+  if actual > state.worst {
+    state.worst = actual;
+    state.worst_slowest = slowest();
+  }
+  let now = Instant::now();
+  if state.last_warning.is_some_and(|last| now.duration_since(last) < warning_interval) {
+    return;
+  }
+  let slowest = state
+    .worst_slowest
+    .iter()
+    .map(|(child, time)| format!("{child} {:.1} ms", millis(*time)))
+    .collect::<Vec<_>>();
+  let slowest = match slowest.is_empty() {
+    true => String::new(),
+    false => format!(", slowest inside: {}", slowest.join(", ")),
+  };
+  log!(
+    "over budget: {name} took {:.1} ms of {:.1} ms at worst, over in {} frames{slowest}",
+    millis(state.worst),
+    millis(state.budget),
+    state.frames_over
+  );
+  state.frames_over = 0;
+  state.worst = Duration::ZERO;
+  state.worst_slowest.clear();
+  state.last_warning = Some(now);
+}
+
+// Checks every budget against a finished frame, from end_frame
+pub(crate) fn check_frame(frame: &FrameRecord) {
+  let names = match budgets().by_name.lock() {
+    Ok(by_name) => by_name.keys().cloned().collect::<Vec<_>>(),
+    Err(_) => return,
+  };
+  for name in names {
+    if let Some(actual) = frame.budget_time(&name) {
+      check(&name, actual, || frame.slowest_children(&name, SLOWEST_SCOPES_SHOWN));
+    }
+  }
+}
+
+// For times measured outside the scopes, like GPU_FRAME_BUDGET from gpu timestamps
+pub fn report_time(name: &str, actual: Duration) {
+  if is_active() {
+    check(name, actual, Vec::new);
+  }
+}
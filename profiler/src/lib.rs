@@ -0,0 +1,305 @@
+use std::{
+  cell::RefCell,
+  collections::VecDeque,
+  fmt::Write,
+  path::Path,
+  sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Mutex, OnceLock,
+  },
+  time::Instant,
+};
+
+pub use alloc_audit::{thread_allocations, CountingAllocator};
+pub use budget::{report_time, set_budget, set_budget_warning_interval, GPU_FRAME_BUDGET};
+#[cfg(feature = "tracy")]
+pub use tracy::GpuTimeline;
+
+mod alloc_audit;
+mod budget;
+#[cfg(feature = "tracy")]
+mod tracy;
+
+const DEFAULT_FRAME_HISTORY: usize = 300;
+
+#[derive(Debug, Clone)]
+pub struct ScopeRecord {
+  pub name: &'static str,
+  pub depth: u32,
+  pub parent: Option<usize>,
+  pub start_us: u64,
+  pub end_us: u64,
+}
+
+impl ScopeRecord {
+  pub fn duration_us(&self) -> u64 {
+    self.end_us.saturating_sub(self.start_us)
+  }
+}
+
+// Scopes of a single thread between two end_frame calls, parents always come before children
+#[derive(Debug, Clone)]
+pub struct FrameRecord {
+  pub thread_name: String,
+  pub thread_id: u64,
+  pub frame_index: u64,
+  pub start_us: u64,
+  pub end_us: u64,
+  pub scopes: Vec<ScopeRecord>,
+}
+
+impl FrameRecord {
+  pub fn children(&self, parent: Option<usize>) -> impl Iterator<Item = (usize, &ScopeRecord)> {
+    self.scopes.iter().enumerate().filter(move |(_, scope)| scope.parent == parent)
+  }
+}
+
+struct Profiler {
+  enabled: AtomicBool,
+  epoch: Instant,
+  next_thread_id: AtomicU64,
+  frame_history: Mutex<(usize, VecDeque<FrameRecord>)>,
+}
+
+struct ThreadProfiler {
+  thread_name: String,
+  thread_id: u64,
+  frame_index: u64,
+  frame_start_us: u64,
+  open_scopes: Vec<usize>,
+  scopes: Vec<ScopeRecord>,
+  #[cfg(feature = "tracy")]
+  tracy_frame: tracy_client::FrameName,
+}
+
+thread_local! {
+  static THREAD_PROFILER: RefCell<Option<ThreadProfiler>> = const { RefCell::new(None) };
+}
+
+fn profiler() -> &'static Profiler {
+  static PROFILER: OnceLock<Profiler> = OnceLock::new();
+  PROFILER.get_or_init(|| Profiler {
+    enabled: AtomicBool::new(false),
+    epoch: Instant::now(),
+    next_thread_id: AtomicU64::new(1),
+    frame_history: Mutex::new((DEFAULT_FRAME_HISTORY, VecDeque::new())),
+  })
+}
+
+fn now_us() -> u64 {
+  profiler().epoch.elapsed().as_micros() as u64
+}
+
+fn with_thread_profiler<R>(f: impl FnOnce(&mut ThreadProfiler) -> R) -> R {
+  THREAD_PROFILER.with(|cell| {
+    let mut thread_profiler = cell.borrow_mut();
+    let thread_profiler = thread_profiler.get_or_insert_with(|| {
+      let current = std::thread::current();
+      let thread_id = profiler().next_thread_id.fetch_add(1, Ordering::Relaxed);
+      let thread_name =
+        current.name().map(|x| x.to_string()).unwrap_or(format!("thread {thread_id}"));
+      ThreadProfiler {
+        #[cfg(feature = "tracy")]
+        tracy_frame: tracy::register_thread(&thread_name),
+        thread_name,
+        thread_id,
+        frame_index: 0,
+        frame_start_us: now_us(),
+        open_scopes: vec![],
+        scopes: vec![],
+      }
+    });
+    f(thread_profiler)
+  })
+}
+
+pub fn set_enabled(enabled: bool) {
+  profiler().enabled.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+  profiler().enabled.load(Ordering::Relaxed)
+}
+
+// Scopes are kept for budgets too, only the frame history needs the profiler on
+fn is_recording() -> bool {
+  is_enabled() || budget::is_active()
+}
+
+pub fn set_frame_history(frame_count: usize) {
+  let Ok(mut frame_history) = profiler().frame_history.lock() else { return };
+  frame_history.0 = frame_count.max(1);
+  while frame_history.1.len() > frame_history.0 {
+    frame_history.1.pop_front();
+  }
+}
+
+// Ends the scope when dropped. Scopes still open at the end of a frame are closed with it
+pub struct ScopeGuard {
+  frame_and_scope: Option<(u64, usize)>,
+  // Zones go to Tracy whether or not the profiler is recording
+  #[cfg(feature = "tracy")]
+  _tracy_span: tracy_client::Span,
+}
+
+impl Drop for ScopeGuard {
+  fn drop(&mut self) {
+    let Some((frame_index, scope_idx)) = self.frame_and_scope else { return };
+    with_thread_profiler(|thread_profiler| {
+      if thread_profiler.frame_index != frame_index {
+        return;
+      }
+      thread_profiler.scopes[scope_idx].end_us = now_us();
+      if let Some(pos) = thread_profiler.open_scopes.iter().rposition(|x| *x == scope_idx) {
+        thread_profiler.open_scopes.truncate(pos);
+      }
+    });
+  }
+}
+
+pub fn scope(name: &'static str) -> ScopeGuard {
+  if !is_recording() {
+    return ScopeGuard {
+      frame_and_scope: None,
+      #[cfg(feature = "tracy")]
+      _tracy_span: tracy::span(name),
+    };
+  }
+  let frame_and_scope = with_thread_profiler(|thread_profiler| {
+    let start_us = now_us();
+    let parent = thread_profiler.open_scopes.last().copied();
+    let scope_idx = thread_profiler.scopes.len();
+    thread_profiler.scopes.push(ScopeRecord {
+      name,
+      depth: thread_profiler.open_scopes.len() as u32,
+      parent,
+      start_us,
+      end_us: start_us,
+    });
+    thread_profiler.open_scopes.push(scope_idx);
+    (thread_profiler.frame_index, scope_idx)
+  });
+  ScopeGuard {
+    frame_and_scope: Some(frame_and_scope),
+    #[cfg(feature = "tracy")]
+    _tracy_span: tracy::span(name),
+  }
+}
+
+// Marks a frame boundary for the calling thread, every thread keeps its own frame count
+pub fn end_frame() {
+  #[cfg(feature = "tracy")]
+  with_thread_profiler(|thread_profiler| tracy::frame_mark(thread_profiler.tracy_frame));
+  if !is_recording() {
+    with_thread_profiler(|thread_profiler| {
+      thread_profiler.open_scopes.clear();
+      thread_profiler.scopes.clear();
+      thread_profiler.frame_index += 1;
+      thread_profiler.frame_start_us = now_us();
+    });
+    return;
+  }
+  let frame = with_thread_profiler(|thread_profiler| {
+    let end_us = now_us();
+    for scope_idx in thread_profiler.open_scopes.drain(..) {
+      thread_profiler.scopes[scope_idx].end_us = end_us;
+    }
+    let frame = FrameRecord {
+      thread_name: thread_profiler.thread_name.clone(),
+      thread_id: thread_profiler.thread_id,
+      frame_index: thread_profiler.frame_index,
+      start_us: thread_profiler.frame_start_us,
+      end_us,
+      scopes: std::mem::take(&mut thread_profiler.scopes),
+    };
+    thread_profiler.frame_index += 1;
+    thread_profiler.frame_start_us = end_us;
+    frame
+  });
+  if budget::is_active() {
+    budget::check_frame(&frame);
+  }
+  if !is_enabled() {
+    return;
+  }
+  let Ok(mut frame_history) = profiler().frame_history.lock() else { return };
+  if frame_history.1.len() >= frame_history.0 {
+    frame_history.1.pop_front();
+  }
+  frame_history.1.push_back(frame);
+}
+
+pub fn recent_frames() -> Vec<FrameRecord> {
+  profiler()
+    .frame_history
+    .lock()
+    .map(|frame_history| frame_history.1.iter().cloned().collect())
+    .unwrap_or_default()
+}
+
+pub fn clear() {
+  if let Ok(mut frame_history) = profiler().frame_history.lock() {
+    frame_history.1.clear();
+  }
+}
+
+fn escape_json(s: &str) -> String {
+  let mut escaped = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '"' => escaped.push_str("\\\""),
+      '\\' => escaped.push_str("\\\\"),
+      '\n' => escaped.push_str("\\n"),
+      c if (c as u32) < 0x20 => {
+        let _ = write!(escaped, "\\u{:04x}", c as u32);
+      }
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+// Trace event format understood by chrome://tracing and perfetto
+pub fn chrome_trace_json(frames: &[FrameRecord]) -> String {
+  let mut events = vec![];
+  let mut named_threads = vec![];
+  for frame in frames {
+    if !named_threads.contains(&frame.thread_id) {
+      named_threads.push(frame.thread_id);
+      events.push(format!(
+        "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"{}\"}}}}",
+        frame.thread_id,
+        escape_json(&frame.thread_name)
+      ));
+    }
+    events.push(format!(
+      "{{\"name\":\"frame {}\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{},\"dur\":{}}}",
+      frame.frame_index,
+      frame.thread_id,
+      frame.start_us,
+      frame.end_us.saturating_sub(frame.start_us)
+    ));
+    for scope in frame.scopes.iter() {
+      events.push(format!(
+        "{{\"name\":\"{}\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{},\"dur\":{}}}",
+        escape_json(scope.name),
+        frame.thread_id,
+        scope.start_us,
+        scope.duration_us()
+      ));
+    }
+  }
+  format!("{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ms\"}}\n", events.join(",\n"))
+}
+
+pub fn export_chrome_trace(path: &Path) -> Result<(), String> {
+  std::fs::write(path, chrome_trace_json(&recent_frames()))
+    .map_err(|e| format!("at writing trace file {}: {e}", path.display()))
+}
+
+#[macro_export]
+macro_rules! profile_scope {
+  ($name:expr) => {
+    let _profile_scope_guard = $crate::scope($name);
+  };
+}
@@ -0,0 +1,52 @@
+use tracy_client::{Client, FrameName, GpuContext, GpuContextType, Span};
+
+// The client starts with the process and streams to whichever Tracy viewer connects, these only
+// forward what the profiler already sees
+
+// Once per thread, its frames are marked under its name
+pub(crate) fn register_thread(thread_name: &str) -> FrameName {
+  let thread_name = thread_name.replace('\0', " ");
+  Client::start().set_thread_name(&thread_name);
+  FrameName::new_leak(thread_name)
+}
+
+pub(crate) fn span(name: &'static str) -> Span {
+  Client::start().span_alloc(Some(name), "", "", 0, 0)
+}
+
+pub(crate) fn frame_mark(frame: FrameName) {
+  Client::start().secondary_frame_mark(frame);
+}
+
+// Zones on a gpu queue from timestamps read back from a query pool, in its ticks. The context is
+// made with the first zone, whose start lines the gpu timeline up with the cpu one
+pub struct GpuTimeline {
+  name: &'static str,
+  // Nanoseconds per tick
+  period: f32,
+  context: Option<GpuContext>,
+}
+
+impl GpuTimeline {
+  pub fn new(name: &'static str, period: f32) -> Self {
+    Self { name, period, context: None }
+  }
+
+  pub fn zone(&mut self, name: &'static str, start_ticks: u64, end_ticks: u64) {
+    if self.context.is_none() {
+      let context = Client::start().new_gpu_context(
+        Some(self.name),
+        GpuContextType::Vulkan,
+        start_ticks as i64,
+        self.period,
+      );
+      // Only fails past 255 contexts
+      self.context = context.ok();
+    }
+    let Some(context) = &self.context else { return };
+    let Ok(mut span) = context.span_alloc(name, "", "", 0) else { return };
+    span.end_zone();
+    span.upload_timestamp_start(start_ticks as i64);
+    span.upload_timestamp_end(end_ticks as i64);
+  }
+}
@@ -0,0 +1,76 @@
+use ash::vk;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdQueueFamilyInfo {
+  pub index: u32,
+  pub flags: vk::QueueFlags,
+  pub queue_count: u32,
+  // 0 when the family can't write timestamps
+  pub timestamp_valid_bits: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdMemoryHeapInfo {
+  pub size: u64,
+  pub device_local: bool,
+  // Properties of each memory type allocated from this heap
+  pub memory_types: Vec<vk::MemoryPropertyFlags>,
+}
+
+// What a device was made on and with, for logs and diagnostics screens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdDeviceInfo {
+  pub name: String,
+  pub device_type: vk::PhysicalDeviceType,
+  pub vendor_id: u32,
+  pub device_id: u32,
+  // Highest vulkan version the gpu supports, major minor patch
+  pub api_version: (u32, u32, u32),
+  // In the vendor's own numbering, see driver_version_string
+  pub driver_version: String,
+  // Empty on gpus older than vulkan 1.2
+  pub driver_name: String,
+  pub driver_info: String,
+  // Enabled at device creation
+  pub extensions: Vec<String>,
+  pub features: Vec<&'static str>,
+  pub queue_families: Vec<AdQueueFamilyInfo>,
+  pub memory_heaps: Vec<AdMemoryHeapInfo>,
+}
+
+impl AdDeviceInfo {
+  pub fn api_version_string(&self) -> String {
+    let (major, minor, patch) = self.api_version;
+    format!("{major}.{minor}.{patch}")
+  }
+
+  pub fn device_local_bytes(&self) -> u64 {
+    self.memory_heaps.iter().filter(|heap| heap.device_local).map(|heap| heap.size).sum()
+  }
+}
+
+const NVIDIA_VENDOR_ID: u32 = 0x10de;
+const INTEL_VENDOR_ID: u32 = 0x8086;
+
+// Drivers pack their versions differently, NVIDIA in 10.8.8.6 bits and Intel on Windows in 18.14.
+// Everyone else uses vulkan's own packing
+pub fn driver_version_string(vendor_id: u32, driver_version: u32) -> String {
+  match vendor_id {
+    NVIDIA_VENDOR_ID => format!(
+      "{}.{}.{}.{}",
+      driver_version >> 22,
+      (driver_version >> 14) & 0xff,
+      (driver_version >> 6) & 0xff,
+      driver_version & 0x3f
+    ),
+    INTEL_VENDOR_ID if cfg!(target_os = "windows") => {
+      format!("{}.{}", driver_version >> 14, driver_version & 0x3fff)
+    }
+    _ => format!(
+      "{}.{}.{}",
+      vk::api_version_major(driver_version),
+      vk::api_version_minor(driver_version),
+      vk::api_version_patch(driver_version)
+    ),
+  }
+}
@@ -0,0 +1,150 @@
+use std::{
+  collections::HashMap,
+  sync::atomic::{AtomicU64, Ordering},
+};
+
+use gpu_allocator::AllocatorReport;
+
+#[derive(Debug, Clone)]
+pub struct AllocationDiagnostics {
+  pub name: String,
+  // Within its memory block
+  pub offset: u64,
+  pub size: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryBlockDiagnostics {
+  pub size: u64,
+  // Sorted by offset
+  pub allocations: Vec<AllocationDiagnostics>,
+}
+
+impl MemoryBlockDiagnostics {
+  pub fn used_bytes(&self) -> u64 {
+    self.allocations.iter().map(|allocation| allocation.size).sum()
+  }
+
+  // 0 while the free space is one range, closer to 1 the more of it is in small gaps that only
+  // fit small allocations
+  pub fn fragmentation(&self) -> f32 {
+    let mut free_ranges = vec![];
+    let mut range_start = 0;
+    for allocation in self.allocations.iter() {
+      free_ranges.push(allocation.offset.saturating_sub(range_start));
+      range_start = range_start.max(allocation.offset + allocation.size);
+    }
+    free_ranges.push(self.size.saturating_sub(range_start));
+    let total_free = free_ranges.iter().sum::<u64>();
+    let largest_free = free_ranges.iter().max().copied().unwrap_or(0);
+    match total_free {
+      0 => 0.0,
+      _ => 1.0 - largest_free as f32 / total_free as f32,
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocatorDiagnostics {
+  // As given to AdAshDevice::create_allocator
+  pub name: String,
+  pub blocks: Vec<MemoryBlockDiagnostics>,
+}
+
+impl AllocatorDiagnostics {
+  pub(crate) fn from_report(name: &str, report: AllocatorReport) -> Self {
+    let blocks = report
+      .blocks
+      .iter()
+      .map(|block| {
+        let mut allocations = report.allocations[block.allocations.clone()]
+          .iter()
+          .map(|allocation| AllocationDiagnostics {
+            name: allocation.name.clone(),
+            offset: allocation.offset,
+            size: allocation.size,
+          })
+          .collect::<Vec<_>>();
+        allocations.sort_by_key(|allocation| allocation.offset);
+        MemoryBlockDiagnostics { size: block.size, allocations }
+      })
+      .collect();
+    Self { name: name.to_string(), blocks }
+  }
+
+  pub fn reserved_bytes(&self) -> u64 {
+    self.blocks.iter().map(|block| block.size).sum()
+  }
+
+  pub fn used_bytes(&self) -> u64 {
+    self.blocks.iter().map(|block| block.used_bytes()).sum()
+  }
+}
+
+// Snapshot of every live allocator made by an AdAshDevice
+#[derive(Debug, Clone, Default)]
+pub struct MemoryDiagnostics {
+  pub allocators: Vec<AllocatorDiagnostics>,
+}
+
+impl MemoryDiagnostics {
+  pub fn reserved_bytes(&self) -> u64 {
+    self.allocators.iter().map(|allocator| allocator.reserved_bytes()).sum()
+  }
+
+  pub fn used_bytes(&self) -> u64 {
+    self.allocators.iter().map(|allocator| allocator.used_bytes()).sum()
+  }
+
+  // Bytes per allocation name over all allocators, largest first
+  pub fn usage_by_name(&self) -> Vec<(String, u64)> {
+    let mut usage = HashMap::<&str, u64>::new();
+    for block in self.allocators.iter().flat_map(|allocator| allocator.blocks.iter()) {
+      for allocation in block.allocations.iter() {
+        *usage.entry(&allocation.name).or_default() += allocation.size;
+      }
+    }
+    let mut usage =
+      usage.into_iter().map(|(name, bytes)| (name.to_string(), bytes)).collect::<Vec<_>>();
+    usage.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    usage
+  }
+}
+
+// Objects made on a device that aren't destroyed yet, counted by the wrappers making them. Cheap
+// enough to read every frame, unlike the memory reports
+#[derive(Debug, Default)]
+pub struct AdLiveObjects {
+  command_buffers: AtomicU64,
+  descriptor_sets: AtomicU64,
+  framebuffers: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum AdLiveObjectKind {
+  CommandBuffer,
+  DescriptorSet,
+  Framebuffer,
+}
+
+impl AdLiveObjects {
+  fn counter(&self, kind: AdLiveObjectKind) -> &AtomicU64 {
+    match kind {
+      AdLiveObjectKind::CommandBuffer => &self.command_buffers,
+      AdLiveObjectKind::DescriptorSet => &self.descriptor_sets,
+      AdLiveObjectKind::Framebuffer => &self.framebuffers,
+    }
+  }
+
+  pub fn created(&self, kind: AdLiveObjectKind, count: u64) {
+    self.counter(kind).fetch_add(count, Ordering::Relaxed);
+  }
+
+  pub fn destroyed(&self, kind: AdLiveObjectKind, count: u64) {
+    self.counter(kind).fetch_sub(count, Ordering::Relaxed);
+  }
+
+  pub fn count(&self, kind: AdLiveObjectKind) -> u64 {
+    self.counter(kind).load(Ordering::Relaxed)
+  }
+}
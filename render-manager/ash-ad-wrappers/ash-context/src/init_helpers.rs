@@ -8,10 +8,7 @@ pub unsafe fn init_instance(
   layers: Vec<*const c_char>,
   extensions: Vec<*const c_char>,
 ) -> Result<ash::Instance, String> {
-  let mandatory_layers = HashSet::from([
-    #[cfg(debug_assertions)]
-    c"VK_LAYER_KHRONOS_validation".as_ptr(),
-  ]);
+  let mandatory_layers = HashSet::new();
   let mandatory_extensions = HashSet::from([
     #[cfg(debug_assertions)]
     ext::debug_utils::NAME.as_ptr(),
@@ -51,7 +48,9 @@ pub unsafe fn init_instance(
     .application_version(0)
     .engine_name(c"Residue Engine")
     .engine_version(0)
-    .api_version(vk::API_VERSION_1_0);
+    // Only the highest version used, devices still run at the version they report. 1.2 is for
+    // buffer device addresses
+    .api_version(vk::API_VERSION_1_2);
 
   #[cfg(target_os = "macos")]
   let vk_instance_create_info = vk::InstanceCreateInfo::default()
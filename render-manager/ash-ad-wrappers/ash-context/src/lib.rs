@@ -1,13 +1,37 @@
-use std::{collections::HashMap, ffi::c_char, sync::Arc};
+use std::{
+  collections::HashMap,
+  ffi::{c_char, CStr, FromBytesUntilNulError},
+  sync::{Arc, Mutex, Weak},
+};
 
 pub use ash;
-use ash::vk;
+use ash::{khr, vk};
 pub use getset;
 pub use gpu_allocator;
 use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
 
+pub use device_info::{driver_version_string, AdDeviceInfo, AdMemoryHeapInfo, AdQueueFamilyInfo};
+pub use diagnostics::{
+  AdLiveObjectKind, AdLiveObjects, AllocationDiagnostics, AllocatorDiagnostics,
+  MemoryBlockDiagnostics, MemoryDiagnostics,
+};
+
+mod device_info;
+mod diagnostics;
 mod init_helpers;
 
+// Extension exporting memory as an fd on unix or a HANDLE on windows and the handle type it
+// exports, None where there is neither
+pub fn external_memory_extension() -> Option<(&'static CStr, vk::ExternalMemoryHandleTypeFlags)> {
+  if cfg!(windows) {
+    Some((khr::external_memory_win32::NAME, vk::ExternalMemoryHandleTypeFlags::OPAQUE_WIN32))
+  } else if cfg!(unix) {
+    Some((khr::external_memory_fd::NAME, vk::ExternalMemoryHandleTypeFlags::OPAQUE_FD))
+  } else {
+    None
+  }
+}
+
 #[derive(getset::Getters)]
 pub struct AdAshInstance {
   #[getset(get = "pub")]
@@ -17,10 +41,14 @@ pub struct AdAshInstance {
 }
 
 impl AdAshInstance {
-  pub fn new() -> Result<Self, String> {
+  pub fn new(validation: bool) -> Result<Self, String> {
     unsafe {
       let ash_entry = ash::Entry::load().map_err(|e| format!("at VK load: {e}"))?;
-      let ash_instance = init_helpers::init_instance(&ash_entry, vec![], vec![])?;
+      let layers = match validation {
+        true => vec![c"VK_LAYER_KHRONOS_validation".as_ptr()],
+        false => vec![],
+      };
+      let ash_instance = init_helpers::init_instance(&ash_entry, layers, vec![])?;
       Ok(Self { inner: ash_instance, ash_entry })
     }
   }
@@ -44,6 +72,173 @@ impl AdAshInstance {
     }
   }
 
+  // Core from vulkan 1.2, older devices don't get it even if they have the extension
+  pub fn supports_buffer_device_address(&self, gpu: vk::PhysicalDevice) -> bool {
+    unsafe {
+      if self.inner.get_physical_device_properties(gpu).api_version < vk::API_VERSION_1_2 {
+        return false;
+      }
+      let mut vk12_features = vk::PhysicalDeviceVulkan12Features::default();
+      let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut vk12_features);
+      self.inner.get_physical_device_features2(gpu, &mut features);
+      vk12_features.buffer_device_address == vk::TRUE
+    }
+  }
+
+  // Views a multiview render pass can draw at once, 0 when the gpu can't. Core from vulkan 1.1
+  pub fn multiview_view_count(&self, gpu: vk::PhysicalDevice) -> u32 {
+    unsafe {
+      if self.inner.get_physical_device_properties(gpu).api_version < vk::API_VERSION_1_1 {
+        return 0;
+      }
+      let mut vk11_features = vk::PhysicalDeviceVulkan11Features::default();
+      let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut vk11_features);
+      self.inner.get_physical_device_features2(gpu, &mut features);
+      if vk11_features.multiview != vk::TRUE {
+        return 0;
+      }
+      let mut multiview_props = vk::PhysicalDeviceMultiviewProperties::default();
+      let mut props = vk::PhysicalDeviceProperties2::default().push_next(&mut multiview_props);
+      self.inner.get_physical_device_properties2(gpu, &mut props);
+      multiview_props.max_multiview_view_count
+    }
+  }
+
+  // Acceleration structures and ray tracing pipelines, both extensions and their features. They
+  // need buffer device addresses too
+  pub fn supports_ray_tracing(&self, gpu: vk::PhysicalDevice) -> bool {
+    if !self.supports_buffer_device_address(gpu) {
+      return false;
+    }
+    unsafe {
+      let Ok(extension_props) = self.inner.enumerate_device_extension_properties(gpu) else {
+        return false;
+      };
+      let has_extension = |name: &CStr| {
+        extension_props.iter().any(|props| props.extension_name_as_c_str() == Ok(name))
+      };
+      if !has_extension(khr::acceleration_structure::NAME)
+        || !has_extension(khr::ray_tracing_pipeline::NAME)
+        || !has_extension(khr::deferred_host_operations::NAME)
+      {
+        return false;
+      }
+      let mut as_features = vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default();
+      let mut rt_features = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default();
+      let mut features = vk::PhysicalDeviceFeatures2::default()
+        .push_next(&mut as_features)
+        .push_next(&mut rt_features);
+      self.inner.get_physical_device_features2(gpu, &mut features);
+      as_features.acceleration_structure == vk::TRUE && rt_features.ray_tracing_pipeline == vk::TRUE
+    }
+  }
+
+  // Present ids and waiting on them, both extensions and their features
+  pub fn supports_present_wait(&self, gpu: vk::PhysicalDevice) -> bool {
+    unsafe {
+      let Ok(extension_props) = self.inner.enumerate_device_extension_properties(gpu) else {
+        return false;
+      };
+      let has_extension = |name: &CStr| {
+        extension_props.iter().any(|props| props.extension_name_as_c_str() == Ok(name))
+      };
+      if !has_extension(khr::present_id::NAME) || !has_extension(khr::present_wait::NAME) {
+        return false;
+      }
+      let mut present_id_features = vk::PhysicalDevicePresentIdFeaturesKHR::default();
+      let mut present_wait_features = vk::PhysicalDevicePresentWaitFeaturesKHR::default();
+      let mut features = vk::PhysicalDeviceFeatures2::default()
+        .push_next(&mut present_id_features)
+        .push_next(&mut present_wait_features);
+      self.inner.get_physical_device_features2(gpu, &mut features);
+      present_id_features.present_id == vk::TRUE && present_wait_features.present_wait == vk::TRUE
+    }
+  }
+
+  // Core feature, views of several cubes sampled as one CUBE_ARRAY
+  pub fn supports_image_cube_array(&self, gpu: vk::PhysicalDevice) -> bool {
+    unsafe { self.inner.get_physical_device_features(gpu).image_cube_array == vk::TRUE }
+  }
+
+  // Core feature, filtering along the direction a surface is seen at
+  pub fn supports_sampler_anisotropy(&self, gpu: vk::PhysicalDevice) -> bool {
+    unsafe { self.inner.get_physical_device_features(gpu).sampler_anisotropy == vk::TRUE }
+  }
+
+  // Core feature, sampling BC1 to BC7 compressed images
+  pub fn supports_texture_compression_bc(&self, gpu: vk::PhysicalDevice) -> bool {
+    unsafe { self.inner.get_physical_device_features(gpu).texture_compression_bc == vk::TRUE }
+  }
+
+  // Memory other processes and apis can import. Core from vulkan 1.1, with the platform's handle
+  // extension from external_memory_extension on top
+  pub fn supports_external_memory(&self, gpu: vk::PhysicalDevice) -> bool {
+    let Some((extension, _)) = external_memory_extension() else { return false };
+    unsafe {
+      if self.inner.get_physical_device_properties(gpu).api_version < vk::API_VERSION_1_1 {
+        return false;
+      }
+      self.inner.enumerate_device_extension_properties(gpu).is_ok_and(|extension_props| {
+        extension_props.iter().any(|props| props.extension_name_as_c_str() == Ok(extension))
+      })
+    }
+  }
+
+  // Descriptors written straight into command buffers, without sets to allocate
+  pub fn supports_push_descriptor(&self, gpu: vk::PhysicalDevice) -> bool {
+    unsafe {
+      self.inner.enumerate_device_extension_properties(gpu).is_ok_and(|extension_props| {
+        extension_props
+          .iter()
+          .any(|props| props.extension_name_as_c_str() == Ok(khr::push_descriptor::NAME))
+      })
+    }
+  }
+
+  // Which sparse features the gpu has and the queue families that can bind sparse memory
+  pub fn sparse_support(&self, gpu: vk::PhysicalDevice) -> AdSparseSupport {
+    let features = unsafe { self.inner.get_physical_device_features(gpu) };
+    let queue_families = self
+      .get_queue_family_props(gpu)
+      .iter()
+      .enumerate()
+      .filter(|(_, x)| x.queue_flags.contains(vk::QueueFlags::SPARSE_BINDING))
+      .map(|(i, _)| i as u32)
+      .collect();
+    AdSparseSupport {
+      binding: features.sparse_binding == vk::TRUE,
+      residency_buffer: features.sparse_residency_buffer == vk::TRUE,
+      residency_image_2d: features.sparse_residency_image2_d == vk::TRUE,
+      residency_aliased: features.sparse_residency_aliased == vk::TRUE,
+      queue_families,
+    }
+  }
+
+  // Empty when sparse 2D images of the format and usage aren't supported
+  pub fn sparse_image_format_props(
+    &self,
+    gpu: vk::PhysicalDevice,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+  ) -> Vec<vk::SparseImageFormatProperties> {
+    unsafe {
+      self.inner.get_physical_device_sparse_image_format_properties(
+        gpu,
+        format,
+        vk::ImageType::TYPE_2D,
+        vk::SampleCountFlags::TYPE_1,
+        usage,
+        vk::ImageTiling::OPTIMAL,
+      )
+    }
+  }
+
+  // Nanoseconds per timestamp tick, None when graphics and compute queues can't write timestamps
+  pub fn timestamp_period(&self, gpu: vk::PhysicalDevice) -> Option<f32> {
+    let limits = unsafe { self.inner.get_physical_device_properties(gpu) }.limits;
+    (limits.timestamp_compute_and_graphics == vk::TRUE).then_some(limits.timestamp_period)
+  }
+
   pub fn get_queue_family_props(&self, gpu: vk::PhysicalDevice) -> Vec<vk::QueueFamilyProperties> {
     unsafe { self.inner.get_physical_device_queue_family_properties(gpu) }
   }
@@ -130,6 +325,32 @@ impl Drop for AdAshInstance {
   }
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdSparseSupport {
+  // Resources bound to memory after creation, fully bound before use
+  pub binding: bool,
+  // Resources used with only some of their pages bound
+  pub residency_buffer: bool,
+  pub residency_image_2d: bool,
+  // Pages of several resources bound to the same memory
+  pub residency_aliased: bool,
+  pub queue_families: Vec<u32>,
+}
+
+impl AdSparseSupport {
+  // Features to create the device with for everything supported
+  pub fn enable_features(
+    &self,
+    features: vk::PhysicalDeviceFeatures,
+  ) -> vk::PhysicalDeviceFeatures {
+    features
+      .sparse_binding(self.binding)
+      .sparse_residency_buffer(self.binding && self.residency_buffer)
+      .sparse_residency_image2_d(self.binding && self.residency_image_2d)
+      .sparse_residency_aliased(self.binding && self.residency_aliased)
+  }
+}
+
 #[derive(Hash, PartialEq, Eq, Copy, Clone)]
 pub enum GPUQueueType {
   Graphics,
@@ -147,16 +368,63 @@ pub struct AdAshDevice {
   gpu: vk::PhysicalDevice,
   #[getset(get = "pub")]
   ash_instance: Arc<AdAshInstance>, // To avoid destroying instance till device is destroyed
+  // Enabled at device creation, allocators made after allocate memory buffers can take the
+  // address of
+  #[getset(get_copy = "pub")]
+  buffer_device_address: bool,
+  // Acceleration structure and ray tracing pipeline features are on, the extensions are up to the
+  // caller
+  #[getset(get_copy = "pub")]
+  ray_tracing: bool,
+  // Present id and present wait features are on, the extensions are up to the caller
+  #[getset(get_copy = "pub")]
+  present_wait: bool,
+  // Most views a render pass subpass can have, 0 when multiview is off
+  #[getset(get_copy = "pub")]
+  multiview_view_count: u32,
+  // Taken from the features the device was made with
+  #[getset(get_copy = "pub")]
+  sparse_binding: bool,
+  #[getset(get_copy = "pub")]
+  sparse_residency_buffer: bool,
+  #[getset(get_copy = "pub")]
+  sparse_residency_image_2d: bool,
+  // Names of the extensions the device was made with
+  #[getset(get = "pub")]
+  extensions: Vec<String>,
+  // Loaded when the device was made with the push descriptor extension
+  #[getset(get = "pub")]
+  push_descriptor_device: Option<khr::push_descriptor::Device>,
+  // Every pipeline is made through it, so the driver keeps the binaries of pipelines that were
+  // dropped and making them again is quick, like going back to a shader after a failed reload
+  #[getset(get_copy = "pub")]
+  pipeline_cache: vk::PipelineCache,
+  // Every allocator made through create_allocator, for memory_diagnostics
+  allocators: Mutex<Vec<(String, Weak<Mutex<Allocator>>)>>,
+  #[getset(get = "pub")]
+  live_objects: AdLiveObjects,
 }
 
 impl AdAshDevice {
+  #[allow(clippy::too_many_arguments)]
   pub fn new(
     ash_instance: Arc<AdAshInstance>,
     gpu: vk::PhysicalDevice,
     extensions: Vec<*const c_char>,
     features: vk::PhysicalDeviceFeatures,
+    // Check supports_buffer_device_address first, device creation fails without it
+    buffer_device_address: bool,
+    // Check supports_ray_tracing first and pass the extensions from ray tracing wrappers along
+    ray_tracing: bool,
+    // Check supports_present_wait first and pass both extensions along
+    present_wait: bool,
+    // From multiview_view_count, 0 leaves multiview off
+    multiview_view_count: u32,
     queue_counts: HashMap<u32, u32>,
   ) -> Result<Self, String> {
+    if ray_tracing && !buffer_device_address {
+      return Err("ray tracing needs buffer device addresses on".to_string());
+    }
     let queue_priorities = [1.0, 1.0, 1.0, 1.0];
     let q_create_infos = queue_counts
       .iter()
@@ -166,36 +434,209 @@ impl AdAshDevice {
           .queue_priorities(&queue_priorities[0..(*q_count as usize)])
       })
       .collect::<Vec<_>>();
-    let device_create_info = vk::DeviceCreateInfo::default()
+    let mut vk11_features = vk::PhysicalDeviceVulkan11Features::default().multiview(true);
+    let mut vk12_features =
+      vk::PhysicalDeviceVulkan12Features::default().buffer_device_address(buffer_device_address);
+    let mut device_create_info = vk::DeviceCreateInfo::default()
       .queue_create_infos(&q_create_infos)
       .enabled_extension_names(&extensions)
       .enabled_features(&features);
+    let mut as_features =
+      vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default().acceleration_structure(true);
+    let mut rt_features =
+      vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default().ray_tracing_pipeline(true);
+    if multiview_view_count > 0 {
+      device_create_info = device_create_info.push_next(&mut vk11_features);
+    }
+    if buffer_device_address {
+      device_create_info = device_create_info.push_next(&mut vk12_features);
+    }
+    if ray_tracing {
+      device_create_info =
+        device_create_info.push_next(&mut as_features).push_next(&mut rt_features);
+    }
+    let mut present_id_features =
+      vk::PhysicalDevicePresentIdFeaturesKHR::default().present_id(true);
+    let mut present_wait_features =
+      vk::PhysicalDevicePresentWaitFeaturesKHR::default().present_wait(true);
+    if present_wait {
+      device_create_info = device_create_info
+        .push_next(&mut present_id_features)
+        .push_next(&mut present_wait_features);
+    }
     let vk_device = unsafe {
       ash_instance
         .inner
         .create_device(gpu, &device_create_info, None)
         .map_err(|e| format!("at vk device create: {e}"))?
     };
+    let extensions = extensions
+      .iter()
+      .map(|name| unsafe { CStr::from_ptr(*name) }.to_string_lossy().to_string())
+      .collect::<Vec<_>>();
+    let push_descriptor_device = extensions
+      .iter()
+      .any(|name| *name == khr::push_descriptor::NAME.to_string_lossy())
+      .then(|| khr::push_descriptor::Device::new(&ash_instance.inner, &vk_device));
+    let pipeline_cache = unsafe {
+      vk_device
+        .create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)
+        .map_err(|e| format!("at creating vk pipeline cache: {e}"))?
+    };
 
-    Ok(Self { inner: vk_device, gpu, ash_instance })
+    Ok(Self {
+      inner: vk_device,
+      gpu,
+      ash_instance,
+      buffer_device_address,
+      ray_tracing,
+      present_wait,
+      multiview_view_count,
+      sparse_binding: features.sparse_binding == vk::TRUE,
+      sparse_residency_buffer: features.sparse_residency_buffer == vk::TRUE,
+      sparse_residency_image_2d: features.sparse_residency_image2_d == vk::TRUE,
+      extensions,
+      push_descriptor_device,
+      pipeline_cache,
+      allocators: Mutex::new(vec![]),
+      live_objects: AdLiveObjects::default(),
+    })
   }
 
-  pub fn create_allocator(&self) -> Result<Allocator, String> {
-    Allocator::new(&AllocatorCreateDesc {
+  // The device was made with the extension from external_memory_extension
+  pub fn external_memory(&self) -> bool {
+    external_memory_extension().is_some_and(|(extension, _)| {
+      self.extensions.iter().any(|name| *name == extension.to_string_lossy())
+    })
+  }
+
+  // The device was made with the push descriptor extension, see supports_push_descriptor
+  pub fn push_descriptor(&self) -> bool {
+    self.push_descriptor_device.is_some()
+  }
+
+  // The name is only used to tell allocators apart in memory_diagnostics
+  pub fn create_allocator(&self, name: &str) -> Result<Arc<Mutex<Allocator>>, String> {
+    let allocator = Allocator::new(&AllocatorCreateDesc {
       instance: self.ash_instance.inner.clone(),
       device: self.inner.clone(),
       physical_device: self.gpu,
       debug_settings: Default::default(),
-      buffer_device_address: false,
+      buffer_device_address: self.buffer_device_address,
       allocation_sizes: Default::default(),
     })
-    .map_err(|e| format!("at creating gpu allocator: {e}"))
+    .map_err(|e| format!("at creating gpu allocator: {e}"))?;
+    let allocator = Arc::new(Mutex::new(allocator));
+    self
+      .allocators
+      .lock()
+      .map_err(|e| format!("at getting allocator list lock: {e}"))?
+      .push((name.to_string(), Arc::downgrade(&allocator)));
+    Ok(allocator)
+  }
+
+  // Locks each allocator in turn while reading its blocks, dropped allocators are left out
+  pub fn memory_diagnostics(&self) -> Result<MemoryDiagnostics, String> {
+    let mut allocators =
+      self.allocators.lock().map_err(|e| format!("at getting allocator list lock: {e}"))?;
+    allocators.retain(|(_, allocator)| allocator.strong_count() > 0);
+    let mut diagnostics = MemoryDiagnostics::default();
+    for (name, allocator) in allocators.iter() {
+      let Some(allocator) = allocator.upgrade() else { continue };
+      let report = allocator
+        .lock()
+        .map_err(|e| format!("at getting allocator {name} lock: {e}"))?
+        .generate_report();
+      diagnostics.allocators.push(AllocatorDiagnostics::from_report(name, report));
+    }
+    Ok(diagnostics)
+  }
+
+  // Reads the gpu's properties again, call it once and keep the result
+  pub fn device_info(&self) -> AdDeviceInfo {
+    let instance = &self.ash_instance.inner;
+    let props = unsafe { instance.get_physical_device_properties(self.gpu) };
+    let mut driver_props = vk::PhysicalDeviceDriverProperties::default();
+    // Driver properties are core from vulkan 1.2
+    if props.api_version >= vk::API_VERSION_1_2 {
+      let mut props2 = vk::PhysicalDeviceProperties2::default().push_next(&mut driver_props);
+      unsafe { instance.get_physical_device_properties2(self.gpu, &mut props2) };
+    }
+    let c_str_field = |field: Result<&CStr, FromBytesUntilNulError>| {
+      field.map(|x| x.to_string_lossy().to_string()).unwrap_or_default()
+    };
+    let features = [
+      ("buffer_device_address", self.buffer_device_address),
+      ("ray_tracing", self.ray_tracing),
+      ("present_wait", self.present_wait),
+      ("multiview", self.multiview_view_count > 0),
+      ("sparse_binding", self.sparse_binding),
+      ("sparse_residency_buffer", self.sparse_residency_buffer),
+      ("sparse_residency_image_2d", self.sparse_residency_image_2d),
+      ("external_memory", self.external_memory()),
+      ("push_descriptor", self.push_descriptor()),
+    ]
+    .into_iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(name, _)| name)
+    .collect();
+    let queue_families = self
+      .ash_instance
+      .get_queue_family_props(self.gpu)
+      .iter()
+      .enumerate()
+      .map(|(index, family)| AdQueueFamilyInfo {
+        index: index as u32,
+        flags: family.queue_flags,
+        queue_count: family.queue_count,
+        timestamp_valid_bits: family.timestamp_valid_bits,
+      })
+      .collect();
+    let memory_props = unsafe { instance.get_physical_device_memory_properties(self.gpu) };
+    let memory_types = &memory_props.memory_types[..memory_props.memory_type_count as usize];
+    let memory_heaps = memory_props.memory_heaps[..memory_props.memory_heap_count as usize]
+      .iter()
+      .enumerate()
+      .map(|(heap_idx, heap)| AdMemoryHeapInfo {
+        size: heap.size,
+        device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+        memory_types: memory_types
+          .iter()
+          .filter(|memory_type| memory_type.heap_index as usize == heap_idx)
+          .map(|memory_type| memory_type.property_flags)
+          .collect(),
+      })
+      .collect();
+    AdDeviceInfo {
+      name: c_str_field(props.device_name_as_c_str()),
+      device_type: props.device_type,
+      vendor_id: props.vendor_id,
+      device_id: props.device_id,
+      api_version: (
+        vk::api_version_major(props.api_version),
+        vk::api_version_minor(props.api_version),
+        vk::api_version_patch(props.api_version),
+      ),
+      driver_version: driver_version_string(props.vendor_id, props.driver_version),
+      driver_name: c_str_field(driver_props.driver_name_as_c_str()),
+      driver_info: c_str_field(driver_props.driver_info_as_c_str()),
+      extensions: self.extensions.clone(),
+      features,
+      queue_families,
+      memory_heaps,
+    }
+  }
+
+  // Every queue of the device, for when nothing made on it can be in use anymore
+  pub fn wait_idle(&self) -> Result<(), String> {
+    unsafe { self.inner.device_wait_idle().map_err(|e| format!("at waiting for device idle: {e}")) }
   }
 }
 
 impl Drop for AdAshDevice {
   fn drop(&mut self) {
     unsafe {
+      self.inner.destroy_pipeline_cache(self.pipeline_cache, None);
       self.inner.destroy_device(None);
     }
   }
@@ -1,12 +1,21 @@
-use std::sync::{Arc, Mutex};
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
 
 use ash_context::gpu_allocator::{
   vulkan::{Allocation, AllocationCreateDesc, AllocationScheme, Allocator},
   MemoryLocation,
 };
-use ash_context::{ash::vk, getset, AdAshDevice};
+use ash_context::{
+  ash::{khr, vk},
+  getset, AdAshDevice, AdLiveObjectKind,
+};
 use ash_queue_wrappers::AdCommandBuffer;
 use ash_sync_wrappers::AdFence;
+use validation::ValidationCategory;
+
+pub use image;
 
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct AdAllocation {
@@ -47,13 +56,21 @@ impl AdAllocation {
       .map(|alloc| {
         alloc
           .mapped_slice_mut()
-          .map(|x| x[offset..bytes.len()].copy_from_slice(bytes))
+          .map(|x| x[offset..offset + bytes.len()].copy_from_slice(bytes))
           .ok_or(format!("at mapping buffer {} 's memory", &self.name))
       })
       .ok_or(format!("no memory allocated for buffer {}", &self.name))??; // second ? for failure in mapped_slice_mut
     Ok(())
   }
 
+  pub fn read_data(&self, offset: usize, len: usize) -> Result<Vec<u8>, String> {
+    let alloc =
+      self.inner.as_ref().ok_or(format!("no memory allocated for buffer {}", &self.name))?;
+    let mapped =
+      alloc.mapped_slice().ok_or(format!("at mapping buffer {} 's memory", &self.name))?;
+    Ok(mapped[offset..offset + len].to_vec())
+  }
+
   pub fn rename(&mut self, name: &str) -> Result<(), String> {
     let curr_allocation = self.inner.as_mut().ok_or(format!("memory not allocated to rename"))?;
     self
@@ -83,11 +100,15 @@ pub struct AdBuffer {
   inner: vk::Buffer,
   #[getset(get_copy = "pub")]
   size: vk::DeviceSize,
+  #[getset(get_copy = "pub")]
+  usage: vk::BufferUsageFlags,
   #[getset(get = "pub")]
   name: String,
   ash_device: Arc<AdAshDevice>,
   #[getset(get = "pub")]
   allocation: Mutex<AdAllocation>,
+  // Fence of the last submit reading the buffer and its reset count then, see mark_in_flight
+  in_flight: Mutex<Option<(Arc<AdFence>, u64)>>,
 }
 
 impl AdBuffer {
@@ -100,6 +121,14 @@ impl AdBuffer {
     size: vk::DeviceSize,
     usage: vk::BufferUsageFlags,
   ) -> Result<AdBuffer, String> {
+    if usage.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS)
+      && !ash_device.buffer_device_address()
+    {
+      return Err(format!(
+        "buffer {name} wants SHADER_DEVICE_ADDRESS usage but the device was made without buffer \
+         device addresses"
+      ));
+    }
     unsafe {
       let buffer = ash_device
         .inner()
@@ -122,9 +151,11 @@ impl AdBuffer {
       Ok(Self {
         inner: buffer,
         size,
+        usage,
         name: name.to_string(),
         ash_device,
         allocation: Mutex::new(allocation),
+        in_flight: Mutex::new(None),
       })
     }
   }
@@ -181,11 +212,48 @@ impl AdBuffer {
     Ok(buffer)
   }
 
+  // For shaders reading the buffer through a pointer instead of a descriptor. Only buffers made
+  // with SHADER_DEVICE_ADDRESS usage have one
+  pub fn device_address(&self) -> Result<vk::DeviceAddress, String> {
+    if !self.usage.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS) {
+      return Err(format!("buffer {} wasn't made with SHADER_DEVICE_ADDRESS usage", self.name));
+    }
+    Ok(unsafe {
+      self
+        .ash_device
+        .inner()
+        .get_buffer_device_address(&vk::BufferDeviceAddressInfo::default().buffer(self.inner))
+    })
+  }
+
+  // For buffers the cpu rewrites while frames use them. Writes before the fence's submit is done
+  // are reported to validation, nothing waits on it
+  pub fn mark_in_flight(&self, fence: &Arc<AdFence>) {
+    if !validation::is_enabled() {
+      return;
+    }
+    if let Ok(mut in_flight) = self.in_flight.lock() {
+      *in_flight = Some((fence.clone(), fence.reset_count()));
+    }
+  }
+
+  fn is_in_flight(&self) -> bool {
+    let Ok(in_flight) = self.in_flight.lock() else { return false };
+    in_flight.as_ref().is_some_and(|(fence, reset_count)| {
+      fence.is_done_since(*reset_count).map(|done| !done).unwrap_or(false)
+    })
+  }
+
   pub fn write_data<T>(&self, offset: usize, struct_slice: &[T]) -> Result<(), String> {
     let data = Self::get_byte_slice(struct_slice);
     if offset + data.len() > self.size as usize {
       return Err(format!("buffer {} only supports {} bytes", &self.name, self.size));
     }
+    if validation::is_enabled() {
+      validation::check(ValidationCategory::BufferInFlight, !self.is_in_flight(), || {
+        format!("buffer {} written while a submitted frame may still read it", self.name)
+      });
+    }
     self
       .allocation
       .lock()
@@ -193,6 +261,25 @@ impl AdBuffer {
       .write_data(offset, data)
   }
 
+  // The buffer needs host visible memory, and whatever wrote it on the gpu must be waited on and
+  // made visible to the host first
+  pub fn read_data<T: Copy>(&self, offset: usize, count: usize) -> Result<Vec<T>, String> {
+    let len = count * std::mem::size_of::<T>();
+    if offset + len > self.size as usize {
+      return Err(format!("buffer {} only has {} bytes", &self.name, self.size));
+    }
+    let bytes = self
+      .allocation
+      .lock()
+      .map_err(|e| format!("at getting lock for buffer mem allocation: {e}"))?
+      .read_data(offset, len)?;
+    Ok((0..count)
+      .map(|i| unsafe {
+        std::ptr::read_unaligned(bytes.as_ptr().add(i * std::mem::size_of::<T>()) as *const T)
+      })
+      .collect())
+  }
+
   pub fn get_byte_slice<T>(struct_slice: &[T]) -> &[u8] {
     unsafe {
       struct_slice.align_to::<u8>().1
@@ -222,11 +309,44 @@ pub struct AdImage {
   format: vk::Format,
   #[getset(get_copy = "pub")]
   resolution: vk::Extent3D,
+  // 6 for cubemaps, one per face
+  #[getset(get_copy = "pub")]
+  array_layers: u32,
+  #[getset(get_copy = "pub")]
+  usage: vk::ImageUsageFlags,
   #[getset(get = "pub")]
   name: String,
   ash_device: Arc<AdAshDevice>,
+  // Shared with every other image made with new_2d_aliased on the same memory
   #[getset(get = "pub")]
-  allocation: Mutex<AdAllocation>,
+  allocation: Arc<Mutex<AdAllocation>>,
+}
+
+// Every mip level is half the size of the one before, down to 1 texel
+#[derive(Clone, Copy)]
+pub struct AdImage2dDesc {
+  pub format: vk::Format,
+  pub resolution: vk::Extent2D,
+  pub usage: vk::ImageUsageFlags,
+  pub mip_levels: u32,
+}
+
+impl AdImage2dDesc {
+  pub fn mip_extent(&self, mip: u32) -> vk::Extent2D {
+    vk::Extent2D {
+      width: (self.resolution.width >> mip).max(1),
+      height: (self.resolution.height >> mip).max(1),
+    }
+  }
+}
+
+// Where an image made from cpu side texels lives and how it gets there
+#[derive(Clone, Copy)]
+pub struct AdImageUpload<'a> {
+  pub mem_location: MemoryLocation,
+  pub cmd_buffer: &'a AdCommandBuffer,
+  // Layout the image is left in once the copy is done
+  pub init_layout: vk::ImageLayout,
 }
 
 impl AdImage {
@@ -240,19 +360,132 @@ impl AdImage {
     usage: vk::ImageUsageFlags,
     samples: vk::SampleCountFlags,
     mip_levels: u32,
+  ) -> Result<Arc<Self>, String> {
+    Self::new_2d_layers(
+      ash_device,
+      allocator,
+      mem_location,
+      name,
+      format,
+      resolution,
+      usage,
+      samples,
+      mip_levels,
+      vk::ImageCreateFlags::empty(),
+      1,
+    )
+  }
+
+  // Single sampled with one mip level, viewable as a 2D array or a layer at a time
+  #[allow(clippy::too_many_arguments)]
+  pub fn new_2d_array(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    mem_location: MemoryLocation,
+    name: &str,
+    format: vk::Format,
+    resolution: vk::Extent2D,
+    usage: vk::ImageUsageFlags,
+    array_layers: u32,
+  ) -> Result<Arc<Self>, String> {
+    Self::new_2d_layers(
+      ash_device,
+      allocator,
+      mem_location,
+      name,
+      format,
+      resolution,
+      usage,
+      vk::SampleCountFlags::TYPE_1,
+      1,
+      vk::ImageCreateFlags::empty(),
+      array_layers,
+    )
+  }
+
+  // Faces are layers in +X, -X, +Y, -Y, +Z, -Z order, viewable as a cube or a 2D array
+  #[allow(clippy::too_many_arguments)]
+  pub fn new_cube(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    mem_location: MemoryLocation,
+    name: &str,
+    format: vk::Format,
+    face_size: u32,
+    usage: vk::ImageUsageFlags,
+    mip_levels: u32,
+  ) -> Result<Arc<Self>, String> {
+    Self::new_2d_layers(
+      ash_device,
+      allocator,
+      mem_location,
+      name,
+      format,
+      vk::Extent2D { width: face_size, height: face_size },
+      usage,
+      vk::SampleCountFlags::TYPE_1,
+      mip_levels,
+      vk::ImageCreateFlags::CUBE_COMPATIBLE,
+      6,
+    )
+  }
+
+  // cube_count cubes of six layers each, cube i starting at layer 6 * i. Viewing them as one
+  // CUBE_ARRAY needs the imageCubeArray feature
+  #[allow(clippy::too_many_arguments)]
+  pub fn new_cube_array(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    mem_location: MemoryLocation,
+    name: &str,
+    format: vk::Format,
+    face_size: u32,
+    usage: vk::ImageUsageFlags,
+    mip_levels: u32,
+    cube_count: u32,
+  ) -> Result<Arc<Self>, String> {
+    Self::new_2d_layers(
+      ash_device,
+      allocator,
+      mem_location,
+      name,
+      format,
+      vk::Extent2D { width: face_size, height: face_size },
+      usage,
+      vk::SampleCountFlags::TYPE_1,
+      mip_levels,
+      vk::ImageCreateFlags::CUBE_COMPATIBLE,
+      6 * cube_count,
+    )
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn new_2d_layers(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    mem_location: MemoryLocation,
+    name: &str,
+    format: vk::Format,
+    resolution: vk::Extent2D,
+    usage: vk::ImageUsageFlags,
+    samples: vk::SampleCountFlags,
+    mip_levels: u32,
+    flags: vk::ImageCreateFlags,
+    array_layers: u32,
   ) -> Result<Arc<Self>, String> {
     unsafe {
       let vk_image = ash_device
         .inner()
         .create_image(
           &vk::ImageCreateInfo::default()
+            .flags(flags)
             .usage(usage)
             .format(format)
             .extent(vk::Extent3D::from(resolution).depth(1))
             .samples(samples)
             .mip_levels(mip_levels)
             .image_type(vk::ImageType::TYPE_2D)
-            .array_layers(1),
+            .array_layers(array_layers),
           None,
         )
         .map_err(|e| format!("at vk image create: {e}"))?;
@@ -279,8 +512,95 @@ impl AdImage {
           .width(resolution.width)
           .height(resolution.height)
           .depth(1),
+        array_layers,
+        usage,
         format,
-        allocation: Mutex::new(allocation),
+        allocation: Arc::new(Mutex::new(allocation)),
+      }))
+    }
+  }
+
+  // Single sampled and single layered, the kind new_2d_aliased makes
+  fn aliasable_2d_info(
+    format: vk::Format,
+    resolution: vk::Extent2D,
+    usage: vk::ImageUsageFlags,
+    mip_levels: u32,
+  ) -> vk::ImageCreateInfo<'static> {
+    vk::ImageCreateInfo::default()
+      .usage(usage)
+      .format(format)
+      .extent(vk::Extent3D::from(resolution).depth(1))
+      .samples(vk::SampleCountFlags::TYPE_1)
+      .mip_levels(mip_levels)
+      .image_type(vk::ImageType::TYPE_2D)
+      .array_layers(1)
+  }
+
+  // What new_2d_aliased would need of the memory it is given. Makes and destroys an image to ask
+  pub fn aliased_2d_requirements(
+    ash_device: &AdAshDevice,
+    format: vk::Format,
+    resolution: vk::Extent2D,
+    usage: vk::ImageUsageFlags,
+    mip_levels: u32,
+  ) -> Result<vk::MemoryRequirements, String> {
+    unsafe {
+      let vk_image = ash_device
+        .inner()
+        .create_image(&Self::aliasable_2d_info(format, resolution, usage, mip_levels), None)
+        .map_err(|e| format!("at vk image create: {e}"))?;
+      let requirements = ash_device.inner().get_image_memory_requirements(vk_image);
+      ash_device.inner().destroy_image(vk_image, None);
+      Ok(requirements)
+    }
+  }
+
+  // Bound to the start of memory, which other images may be bound to as well. Their contents
+  // overwrite each other, so only one of them can be in use at a time and each use has to start
+  // from UNDEFINED, after a barrier on the last use of whichever had the memory before
+  pub fn new_2d_aliased(
+    ash_device: Arc<AdAshDevice>,
+    memory: Arc<Mutex<AdAllocation>>,
+    name: &str,
+    format: vk::Format,
+    resolution: vk::Extent2D,
+    usage: vk::ImageUsageFlags,
+    mip_levels: u32,
+  ) -> Result<Arc<Self>, String> {
+    unsafe {
+      let vk_image = ash_device
+        .inner()
+        .create_image(&Self::aliasable_2d_info(format, resolution, usage, mip_levels), None)
+        .map_err(|e| format!("at vk image create: {e}"))?;
+      let requirements = ash_device.inner().get_image_memory_requirements(vk_image);
+      let bind_result = memory
+        .lock()
+        .map_err(|e| format!("at getting image mem lock: {e}"))
+        .and_then(|allocation| {
+          let altn = allocation.inner().as_ref().ok_or("mem not allocated")?;
+          if altn.size() < requirements.size || altn.offset() % requirements.alignment != 0 {
+            return Err(format!("memory {} can't hold image {name}", allocation.name()));
+          }
+          ash_device
+            .inner()
+            .bind_image_memory(vk_image, altn.memory(), altn.offset())
+            .map_err(|e| format!("at image mem bind: {e}"))
+        });
+      if let Err(e) = bind_result {
+        ash_device.inner().destroy_image(vk_image, None);
+        return Err(e);
+      }
+      Ok(Arc::new(Self {
+        ash_device,
+        inner: vk_image,
+        itype: vk::ImageType::TYPE_2D,
+        name: name.to_string(),
+        resolution: vk::Extent3D::from(resolution).depth(1),
+        array_layers: 1,
+        usage,
+        format,
+        allocation: memory,
       }))
     }
   }
@@ -317,7 +637,7 @@ impl AdImage {
       mem_location,
       name,
       vk::Format::R8G8B8A8_SRGB,
-      vk::Extent2D::default().width(image_info.width()).height(image_info.height()),
+      vk::Extent2D::default().width(image_rgba8.width()).height(image_rgba8.height()),
       vk::ImageUsageFlags::TRANSFER_DST | usage,
       vk::SampleCountFlags::TYPE_1,
       1,
@@ -393,66 +713,135 @@ impl AdImage {
     cmd_buffer: &AdCommandBuffer,
     init_layout: vk::ImageLayout,
   ) -> Result<Arc<Self>, String> {
+    let image_rgba8 = Self::decode_file_rgba8(file_path)?;
+    let upload = AdImageUpload { mem_location, cmd_buffer, init_layout };
+    Self::new_2d_from_rgba8(ash_device, allocator, name, usage, &image_rgba8, upload)
+  }
+
+  // Doesn't touch the device, so it can run on any thread ahead of the upload
+  pub fn decode_file_rgba8(file_path: &str) -> Result<image::RgbaImage, String> {
     let image_info = image::open(file_path).map_err(|e| format!("at loading file: {e}"))?;
-    let image_rgba8 = image_info.to_rgba8();
+    Ok(image_info.to_rgba8())
+  }
+
+  // For image files read through something other than the OS filesystem, like asset packs
+  pub fn decode_rgba8(file_bytes: &[u8]) -> Result<image::RgbaImage, String> {
+    let image_info =
+      image::load_from_memory(file_bytes).map_err(|e| format!("at decoding image: {e}"))?;
+    Ok(image_info.to_rgba8())
+  }
+
+  // Texels are taken as sRGB encoded colors, use new_2d_from_data for linear data
+  pub fn new_2d_from_rgba8(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    name: &str,
+    usage: vk::ImageUsageFlags,
+    image_rgba8: &image::RgbaImage,
+    upload: AdImageUpload,
+  ) -> Result<Arc<Self>, String> {
+    let desc = AdImage2dDesc {
+      format: vk::Format::R8G8B8A8_SRGB,
+      resolution: vk::Extent2D::default().width(image_rgba8.width()).height(image_rgba8.height()),
+      usage,
+      mip_levels: 1,
+    };
+    Self::new_2d_from_data(ash_device, allocator, name, desc, &[image_rgba8], upload)
+  }
 
+  // levels are tightly packed texels of desc's format, row after row, one per mip level from the
+  // largest down
+  pub fn new_2d_from_data(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    name: &str,
+    desc: AdImage2dDesc,
+    levels: &[&[u8]],
+    upload: AdImageUpload,
+  ) -> Result<Arc<Self>, String> {
+    if levels.is_empty() || levels.len() != desc.mip_levels as usize {
+      return Err(format!("got {} levels for {} mip levels", levels.len(), desc.mip_levels));
+    }
+    let AdImageUpload { mem_location, cmd_buffer, init_layout } = upload;
+    // Copies read from offsets aligned for any texel size
+    let mut offsets = Vec::with_capacity(levels.len());
+    let mut stage_size = 0;
+    for data in levels.iter() {
+      offsets.push(stage_size);
+      stage_size = (stage_size + data.len()).next_multiple_of(16);
+    }
     let stage_buffer = AdBuffer::new(
       ash_device.clone(),
       allocator.clone(),
       MemoryLocation::CpuToGpu,
       &format!("{name}_stage_buffer"),
       vk::BufferCreateFlags::default(),
-      image_rgba8.len() as vk::DeviceSize,
+      stage_size as vk::DeviceSize,
       vk::BufferUsageFlags::TRANSFER_SRC,
     )
-      .map_err(|e| format!("at stage buffer create:: {e}"))?;
-    stage_buffer.write_data(0, &image_rgba8)?;
+    .map_err(|e| format!("at stage buffer create: {e}"))?;
+    for (data, offset) in levels.iter().zip(offsets.iter()) {
+      stage_buffer.write_data(*offset, data)?;
+    }
 
     let image_2d = AdImage::new_2d(
       ash_device.clone(),
       allocator,
       mem_location,
       name,
-      vk::Format::R8G8B8A8_SRGB,
-      vk::Extent2D::default().width(image_info.width()).height(image_info.height()),
-      vk::ImageUsageFlags::TRANSFER_DST | usage,
+      desc.format,
+      desc.resolution,
+      vk::ImageUsageFlags::TRANSFER_DST | desc.usage,
       vk::SampleCountFlags::TYPE_1,
-      1,
+      desc.mip_levels,
     )?;
+    let all_mips = vk::ImageSubresourceRange::default()
+      .aspect_mask(vk::ImageAspectFlags::COLOR)
+      .base_array_layer(0)
+      .layer_count(1)
+      .base_mip_level(0)
+      .level_count(desc.mip_levels);
+    let queue_family = cmd_buffer.cmd_pool().queue().family_index();
 
     cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
-
     cmd_buffer.pipeline_barrier(
       vk::PipelineStageFlags::ALL_COMMANDS,
       vk::PipelineStageFlags::ALL_COMMANDS,
       vk::DependencyFlags::BY_REGION,
       &[],
       &[],
-      &[
-        vk::ImageMemoryBarrier::default()
-          .image(image_2d.inner)
-          .subresource_range(vk::ImageSubresourceRange::default().aspect_mask(vk::ImageAspectFlags::COLOR).base_array_layer(0).layer_count(1).base_mip_level(0).level_count(1))
-          .src_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
-          .dst_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
-          .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-          .old_layout(vk::ImageLayout::UNDEFINED)
-          .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-          .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-      ]
+      &[vk::ImageMemoryBarrier::default()
+        .image(image_2d.inner)
+        .subresource_range(all_mips)
+        .src_queue_family_index(queue_family)
+        .dst_queue_family_index(queue_family)
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)],
     );
+    let regions = offsets
+      .iter()
+      .enumerate()
+      .map(|(level, offset)| {
+        vk::BufferImageCopy::default()
+          .buffer_offset(*offset as vk::DeviceSize)
+          .image_offset(vk::Offset3D::default())
+          .image_extent(vk::Extent3D::from(desc.mip_extent(level as u32)).depth(1))
+          .image_subresource(
+            vk::ImageSubresourceLayers::default()
+              .aspect_mask(vk::ImageAspectFlags::COLOR)
+              .base_array_layer(0)
+              .layer_count(1)
+              .mip_level(level as u32),
+          )
+      })
+      .collect::<Vec<_>>();
     cmd_buffer.copy_buffer_to_image(
       stage_buffer.inner(),
       image_2d.inner,
       vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-      &[vk::BufferImageCopy::default()
-        .image_offset(vk::Offset3D::default())
-        .image_extent(image_2d.resolution())
-        .image_subresource(vk::ImageSubresourceLayers::default()
-          .aspect_mask(vk::ImageAspectFlags::COLOR)
-          .base_array_layer(0)
-          .layer_count(1)
-          .mip_level(0)
-        )]
+      &regions,
     );
     cmd_buffer.pipeline_barrier(
       vk::PipelineStageFlags::ALL_COMMANDS,
@@ -460,19 +849,16 @@ impl AdImage {
       vk::DependencyFlags::BY_REGION,
       &[],
       &[],
-      &[
-        vk::ImageMemoryBarrier::default()
-          .image(image_2d.inner)
-          .subresource_range(vk::ImageSubresourceRange::default().aspect_mask(vk::ImageAspectFlags::COLOR).base_array_layer(0).layer_count(1).base_mip_level(0).level_count(1))
-          .src_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
-          .dst_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
-          .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-          .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-          .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-          .new_layout(init_layout)
-      ]
+      &[vk::ImageMemoryBarrier::default()
+        .image(image_2d.inner)
+        .subresource_range(all_mips)
+        .src_queue_family_index(queue_family)
+        .dst_queue_family_index(queue_family)
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)
+        .new_layout(init_layout)],
     );
-
     cmd_buffer.end()?;
     let fence = AdFence::new(ash_device.clone(), vk::FenceCreateFlags::empty())?;
     cmd_buffer.submit(&[], &[], Some(&fence))?;
@@ -480,17 +866,168 @@ impl AdImage {
     Ok(image_2d)
   }
 
-  pub fn possible_image_aspect(&self) -> vk::ImageAspectFlags {
-    match self.format {
-      vk::Format::D16_UNORM | vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
-      vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
-      _ => vk::ImageAspectFlags::COLOR,
+  // Single sampled with one mip level, for volume lookups like color grading tables
+  pub fn new_3d(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    mem_location: MemoryLocation,
+    name: &str,
+    format: vk::Format,
+    resolution: vk::Extent3D,
+    usage: vk::ImageUsageFlags,
+  ) -> Result<Arc<Self>, String> {
+    unsafe {
+      let vk_image = ash_device
+        .inner()
+        .create_image(
+          &vk::ImageCreateInfo::default()
+            .usage(usage)
+            .format(format)
+            .extent(resolution)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .mip_levels(1)
+            .image_type(vk::ImageType::TYPE_3D)
+            .array_layers(1),
+          None,
+        )
+        .map_err(|e| format!("at vk image create: {e}"))?;
+      let allocation = AdAllocation::new(
+        allocator,
+        name,
+        mem_location,
+        ash_device.inner().get_image_memory_requirements(vk_image),
+      )?;
+      ash_device
+        .inner()
+        .bind_image_memory(
+          vk_image,
+          allocation.inner().as_ref().ok_or("mem not allocated")?.memory(),
+          allocation.inner().as_ref().ok_or("mem not allocated")?.offset(),
+        )
+        .map_err(|e| format!("at image mem bind: {e}"))?;
+      Ok(Arc::new(Self {
+        ash_device,
+        inner: vk_image,
+        itype: vk::ImageType::TYPE_3D,
+        name: name.to_string(),
+        resolution,
+        array_layers: 1,
+        usage,
+        format,
+        allocation: Arc::new(Mutex::new(allocation)),
+      }))
     }
   }
 
-  pub fn full_range_offset_3d(&self) -> [vk::Offset3D; 2] {
-    [
-      vk::Offset3D::default(),
+  // data has to be tightly packed texels of format, row after row then slice after slice
+  #[allow(clippy::too_many_arguments)]
+  pub fn new_3d_from_data(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    mem_location: MemoryLocation,
+    name: &str,
+    format: vk::Format,
+    resolution: vk::Extent3D,
+    usage: vk::ImageUsageFlags,
+    data: &[u8],
+    cmd_buffer: &AdCommandBuffer,
+    init_layout: vk::ImageLayout,
+  ) -> Result<Arc<Self>, String> {
+    let stage_buffer = AdBuffer::new(
+      ash_device.clone(),
+      allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      &format!("{name}_stage_buffer"),
+      vk::BufferCreateFlags::default(),
+      data.len() as vk::DeviceSize,
+      vk::BufferUsageFlags::TRANSFER_SRC,
+    )
+    .map_err(|e| format!("at stage buffer create: {e}"))?;
+    stage_buffer.write_data(0, data)?;
+
+    let image_3d = AdImage::new_3d(
+      ash_device.clone(),
+      allocator,
+      mem_location,
+      name,
+      format,
+      resolution,
+      vk::ImageUsageFlags::TRANSFER_DST | usage,
+    )?;
+    let whole_image = vk::ImageSubresourceRange::default()
+      .aspect_mask(vk::ImageAspectFlags::COLOR)
+      .base_array_layer(0)
+      .layer_count(1)
+      .base_mip_level(0)
+      .level_count(1);
+    let queue_family = cmd_buffer.cmd_pool().queue().family_index();
+
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::ALL_COMMANDS,
+      vk::PipelineStageFlags::ALL_COMMANDS,
+      vk::DependencyFlags::BY_REGION,
+      &[],
+      &[],
+      &[vk::ImageMemoryBarrier::default()
+        .image(image_3d.inner)
+        .subresource_range(whole_image)
+        .src_queue_family_index(queue_family)
+        .dst_queue_family_index(queue_family)
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)],
+    );
+    cmd_buffer.copy_buffer_to_image(
+      stage_buffer.inner(),
+      image_3d.inner,
+      vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+      &[vk::BufferImageCopy::default()
+        .image_offset(vk::Offset3D::default())
+        .image_extent(resolution)
+        .image_subresource(
+          vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_array_layer(0)
+            .layer_count(1)
+            .mip_level(0),
+        )],
+    );
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::ALL_COMMANDS,
+      vk::PipelineStageFlags::ALL_COMMANDS,
+      vk::DependencyFlags::BY_REGION,
+      &[],
+      &[],
+      &[vk::ImageMemoryBarrier::default()
+        .image(image_3d.inner)
+        .subresource_range(whole_image)
+        .src_queue_family_index(queue_family)
+        .dst_queue_family_index(queue_family)
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)
+        .new_layout(init_layout)],
+    );
+    cmd_buffer.end()?;
+    let fence = AdFence::new(ash_device.clone(), vk::FenceCreateFlags::empty())?;
+    cmd_buffer.submit(&[], &[], Some(&fence))?;
+    fence.wait(999999999)?;
+    Ok(image_3d)
+  }
+
+  pub fn possible_image_aspect(&self) -> vk::ImageAspectFlags {
+    match self.format {
+      vk::Format::D16_UNORM | vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+      vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+      _ => vk::ImageAspectFlags::COLOR,
+    }
+  }
+
+  pub fn full_range_offset_3d(&self) -> [vk::Offset3D; 2] {
+    [
+      vk::Offset3D::default(),
       vk::Offset3D::default()
         .x(self.resolution.width as i32)
         .y(self.resolution.height as i32)
@@ -507,6 +1044,543 @@ impl Drop for AdImage {
   }
 }
 
+// Platform handle to exported memory, closed when dropped unless passed on
+#[cfg(unix)]
+pub type AdExternalHandle = std::os::fd::OwnedFd;
+#[cfg(windows)]
+pub type AdExternalHandle = std::os::windows::io::OwnedHandle;
+
+// 2D image in its own device local memory, which other processes or apis can import and use
+// without copies. Importers create the image with the same format, resolution, usage and optimal
+// tiling and bind the whole memory to it as a dedicated allocation
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct AdExportImage {
+  #[getset(get_copy = "pub")]
+  inner: vk::Image,
+  #[getset(get_copy = "pub")]
+  format: vk::Format,
+  #[getset(get_copy = "pub")]
+  resolution: vk::Extent2D,
+  #[getset(get_copy = "pub")]
+  usage: vk::ImageUsageFlags,
+  // Of the memory, can be more than the texels take
+  #[getset(get_copy = "pub")]
+  size: vk::DeviceSize,
+  #[getset(get_copy = "pub")]
+  handle_type: vk::ExternalMemoryHandleTypeFlags,
+  #[getset(get = "pub")]
+  name: String,
+  memory: vk::DeviceMemory,
+  ash_device: Arc<AdAshDevice>,
+}
+
+impl AdExportImage {
+  // Needs the device made with ash_context::external_memory_extension
+  pub fn new_2d(
+    ash_device: Arc<AdAshDevice>,
+    name: &str,
+    format: vk::Format,
+    resolution: vk::Extent2D,
+    usage: vk::ImageUsageFlags,
+  ) -> Result<Self, String> {
+    let Some((_, handle_type)) =
+      ash_context::external_memory_extension().filter(|_| ash_device.external_memory())
+    else {
+      return Err(format!("export image {name} needs the device made with external memory"));
+    };
+    unsafe {
+      let mut external_info =
+        vk::ExternalMemoryImageCreateInfo::default().handle_types(handle_type);
+      let vk_image = ash_device
+        .inner()
+        .create_image(
+          &vk::ImageCreateInfo::default()
+            .usage(usage)
+            .format(format)
+            .extent(vk::Extent3D::from(resolution).depth(1))
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .mip_levels(1)
+            .image_type(vk::ImageType::TYPE_2D)
+            .array_layers(1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .push_next(&mut external_info),
+          None,
+        )
+        .map_err(|e| format!("at vk export image create: {e}"))?;
+      let requirements = ash_device.inner().get_image_memory_requirements(vk_image);
+      let memory_props = ash_device
+        .ash_instance()
+        .inner()
+        .get_physical_device_memory_properties(ash_device.gpu());
+      let memory_type = (0..memory_props.memory_type_count).find(|i| {
+        requirements.memory_type_bits & (1 << i) != 0
+          && memory_props.memory_types[*i as usize]
+            .property_flags
+            .contains(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+      });
+      let Some(memory_type) = memory_type else {
+        ash_device.inner().destroy_image(vk_image, None);
+        return Err(format!("no device local memory export image {name} can be in"));
+      };
+      let mut export_info = vk::ExportMemoryAllocateInfo::default().handle_types(handle_type);
+      let mut dedicated_info = vk::MemoryDedicatedAllocateInfo::default().image(vk_image);
+      let memory = ash_device.inner().allocate_memory(
+        &vk::MemoryAllocateInfo::default()
+          .allocation_size(requirements.size)
+          .memory_type_index(memory_type)
+          .push_next(&mut export_info)
+          .push_next(&mut dedicated_info),
+        None,
+      );
+      let memory = match memory {
+        Ok(memory) => memory,
+        Err(e) => {
+          ash_device.inner().destroy_image(vk_image, None);
+          return Err(format!("at allocating export image mem: {e}"));
+        }
+      };
+      if let Err(e) = ash_device.inner().bind_image_memory(vk_image, memory, 0) {
+        ash_device.inner().destroy_image(vk_image, None);
+        ash_device.inner().free_memory(memory, None);
+        return Err(format!("at export image mem bind: {e}"));
+      }
+      Ok(Self {
+        inner: vk_image,
+        format,
+        resolution,
+        usage,
+        size: requirements.size,
+        handle_type,
+        name: name.to_string(),
+        memory,
+        ash_device,
+      })
+    }
+  }
+
+  // A new handle to the memory every call, the caller owns it
+  #[cfg(unix)]
+  pub fn export_handle(&self) -> Result<AdExternalHandle, String> {
+    use std::os::fd::FromRawFd;
+    let loader = khr::external_memory_fd::Device::new(
+      self.ash_device.ash_instance().inner(),
+      self.ash_device.inner(),
+    );
+    unsafe {
+      let fd = loader
+        .get_memory_fd(
+          &vk::MemoryGetFdInfoKHR::default().memory(self.memory).handle_type(self.handle_type),
+        )
+        .map_err(|e| format!("at exporting image {} 's memory: {e}", self.name))?;
+      Ok(AdExternalHandle::from_raw_fd(fd))
+    }
+  }
+
+  // A new handle to the memory every call, the caller owns it
+  #[cfg(windows)]
+  pub fn export_handle(&self) -> Result<AdExternalHandle, String> {
+    use std::os::windows::io::{FromRawHandle, RawHandle};
+    let loader = khr::external_memory_win32::Device::new(
+      self.ash_device.ash_instance().inner(),
+      self.ash_device.inner(),
+    );
+    unsafe {
+      let handle = loader
+        .get_memory_win32_handle(
+          &vk::MemoryGetWin32HandleInfoKHR::default()
+            .memory(self.memory)
+            .handle_type(self.handle_type),
+        )
+        .map_err(|e| format!("at exporting image {} 's memory: {e}", self.name))?;
+      Ok(AdExternalHandle::from_raw_handle(handle as RawHandle))
+    }
+  }
+
+  pub fn full_range_offset_3d(&self) -> [vk::Offset3D; 2] {
+    [
+      vk::Offset3D::default(),
+      vk::Offset3D::default()
+        .x(self.resolution.width as i32)
+        .y(self.resolution.height as i32)
+        .z(1),
+    ]
+  }
+}
+
+impl Drop for AdExportImage {
+  fn drop(&mut self) {
+    unsafe {
+      self.ash_device.inner().destroy_image(self.inner, None);
+      self.ash_device.inner().free_memory(self.memory, None);
+    }
+  }
+}
+
+// Buffer with memory bound a page at a time through AdQueue::bind_sparse instead of at creation.
+// Without residency every page has to be bound before the buffer is used
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct AdSparseBuffer {
+  #[getset(get_copy = "pub")]
+  inner: vk::Buffer,
+  #[getset(get_copy = "pub")]
+  size: vk::DeviceSize,
+  #[getset(get_copy = "pub")]
+  usage: vk::BufferUsageFlags,
+  #[getset(get = "pub")]
+  name: String,
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  // The alignment is the page size
+  requirements: vk::MemoryRequirements,
+  pages: Mutex<HashMap<u64, AdAllocation>>,
+}
+
+impl AdSparseBuffer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    name: &str,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+    residency: bool,
+  ) -> Result<Self, String> {
+    if !ash_device.sparse_binding() {
+      return Err(format!("sparse buffer {name} needs the device made with sparse binding"));
+    }
+    if residency && !ash_device.sparse_residency_buffer() {
+      return Err(format!("sparse buffer {name} needs the device made with buffer residency"));
+    }
+    let mut flags = vk::BufferCreateFlags::SPARSE_BINDING;
+    if residency {
+      flags |= vk::BufferCreateFlags::SPARSE_RESIDENCY;
+    }
+    unsafe {
+      let buffer = ash_device
+        .inner()
+        .create_buffer(&vk::BufferCreateInfo::default().flags(flags).size(size).usage(usage), None)
+        .map_err(|e| format!("at vk sparse buffer create: {e}"))?;
+      let requirements = ash_device.inner().get_buffer_memory_requirements(buffer);
+      Ok(Self {
+        inner: buffer,
+        size,
+        usage,
+        name: name.to_string(),
+        ash_device,
+        allocator,
+        requirements,
+        pages: Mutex::new(HashMap::new()),
+      })
+    }
+  }
+
+  pub fn page_size(&self) -> vk::DeviceSize {
+    self.requirements.alignment
+  }
+
+  pub fn page_count(&self) -> u64 {
+    self.requirements.size.div_ceil(self.page_size())
+  }
+
+  pub fn is_page_bound(&self, page: u64) -> bool {
+    self.pages.lock().is_ok_and(|pages| pages.contains_key(&page))
+  }
+
+  // Allocates memory for the pages that have none, it is only bound once the binds go through
+  // AdQueue::bind_sparse
+  pub fn bind_pages(
+    &self,
+    mem_location: MemoryLocation,
+    pages: std::ops::Range<u64>,
+  ) -> Result<Vec<vk::SparseMemoryBind>, String> {
+    if pages.end > self.page_count() {
+      return Err(format!("sparse buffer {} only has {} pages", self.name, self.page_count()));
+    }
+    let mut bound =
+      self.pages.lock().map_err(|e| format!("at getting sparse buffer pages lock: {e}"))?;
+    let mut binds = vec![];
+    for page in pages {
+      if bound.contains_key(&page) {
+        continue;
+      }
+      let allocation = AdAllocation::new(
+        self.allocator.clone(),
+        &format!("{}_page_{page}", self.name),
+        mem_location,
+        vk::MemoryRequirements {
+          size: self.page_size(),
+          alignment: self.page_size(),
+          memory_type_bits: self.requirements.memory_type_bits,
+        },
+      )?;
+      let memory = allocation.inner().as_ref().ok_or("no allocation".to_string())?;
+      binds.push(
+        vk::SparseMemoryBind::default()
+          .resource_offset(page * self.page_size())
+          .size(self.page_size())
+          .memory(unsafe { memory.memory() })
+          .memory_offset(memory.offset()),
+      );
+      bound.insert(page, allocation);
+    }
+    Ok(binds)
+  }
+
+  // Binds leaving the pages without memory, with the memory they had. It has to be kept until the
+  // binds are done on the queue
+  pub fn unbind_pages(
+    &self,
+    pages: std::ops::Range<u64>,
+  ) -> Result<(Vec<vk::SparseMemoryBind>, Vec<AdAllocation>), String> {
+    let mut bound =
+      self.pages.lock().map_err(|e| format!("at getting sparse buffer pages lock: {e}"))?;
+    Ok(
+      pages
+        .filter_map(|page| bound.remove(&page).map(|allocation| (page, allocation)))
+        .map(|(page, allocation)| {
+          let bind = vk::SparseMemoryBind::default()
+            .resource_offset(page * self.page_size())
+            .size(self.page_size());
+          (bind, allocation)
+        })
+        .unzip(),
+    )
+  }
+}
+
+impl Drop for AdSparseBuffer {
+  fn drop(&mut self) {
+    unsafe {
+      self.ash_device.inner().destroy_buffer(self.inner, None);
+    }
+  }
+}
+
+// Partially resident 2D color image, single sampled with one layer. Levels are bound in tiles
+// until the mip tail, which is bound as a whole. Formats needing metadata aren't supported
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct AdSparseImage {
+  #[getset(get_copy = "pub")]
+  inner: vk::Image,
+  #[getset(get_copy = "pub")]
+  format: vk::Format,
+  #[getset(get_copy = "pub")]
+  resolution: vk::Extent2D,
+  #[getset(get_copy = "pub")]
+  mip_levels: u32,
+  #[getset(get_copy = "pub")]
+  usage: vk::ImageUsageFlags,
+  #[getset(get = "pub")]
+  name: String,
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  // The alignment is the size of a tile's memory
+  requirements: vk::MemoryRequirements,
+  // Texels a tile covers
+  #[getset(get_copy = "pub")]
+  tile_extent: vk::Extent3D,
+  // Levels from this one on are in the mip tail
+  #[getset(get_copy = "pub")]
+  mip_tail_first_lod: u32,
+  mip_tail_size: vk::DeviceSize,
+  mip_tail_offset: vk::DeviceSize,
+  // By level, then tile x and y
+  tiles: Mutex<HashMap<(u32, u32, u32), AdAllocation>>,
+  mip_tail: Mutex<Option<AdAllocation>>,
+}
+
+impl AdSparseImage {
+  pub fn new_2d(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    name: &str,
+    format: vk::Format,
+    resolution: vk::Extent2D,
+    usage: vk::ImageUsageFlags,
+    mip_levels: u32,
+  ) -> Result<Self, String> {
+    if !ash_device.sparse_binding() || !ash_device.sparse_residency_image_2d() {
+      return Err(format!(
+        "sparse image {name} needs the device made with sparse binding and 2D image residency"
+      ));
+    }
+    let image_create_info = vk::ImageCreateInfo::default()
+      .flags(vk::ImageCreateFlags::SPARSE_BINDING | vk::ImageCreateFlags::SPARSE_RESIDENCY)
+      .image_type(vk::ImageType::TYPE_2D)
+      .format(format)
+      .extent(resolution.into())
+      .mip_levels(mip_levels)
+      .array_layers(1)
+      .samples(vk::SampleCountFlags::TYPE_1)
+      .tiling(vk::ImageTiling::OPTIMAL)
+      .usage(usage)
+      .sharing_mode(vk::SharingMode::EXCLUSIVE)
+      .initial_layout(vk::ImageLayout::UNDEFINED);
+    unsafe {
+      let image = ash_device
+        .inner()
+        .create_image(&image_create_info, None)
+        .map_err(|e| format!("at vk sparse image create: {e}"))?;
+      let requirements = ash_device.inner().get_image_memory_requirements(image);
+      let sparse_requirements = ash_device.inner().get_image_sparse_memory_requirements(image);
+      let color_requirements = sparse_requirements
+        .iter()
+        .find(|x| x.format_properties.aspect_mask.contains(vk::ImageAspectFlags::COLOR));
+      let needs_metadata = sparse_requirements
+        .iter()
+        .any(|x| x.format_properties.aspect_mask.contains(vk::ImageAspectFlags::METADATA));
+      let color_requirements = match color_requirements {
+        Some(color_requirements) if !needs_metadata => *color_requirements,
+        _ => {
+          ash_device.inner().destroy_image(image, None);
+          return Err(format!("sparse image {name}'s format {format:?} has no usable tiles"));
+        }
+      };
+      Ok(Self {
+        inner: image,
+        format,
+        resolution,
+        mip_levels,
+        usage,
+        name: name.to_string(),
+        ash_device,
+        allocator,
+        requirements,
+        tile_extent: color_requirements.format_properties.image_granularity,
+        mip_tail_first_lod: color_requirements.image_mip_tail_first_lod,
+        mip_tail_size: color_requirements.image_mip_tail_size,
+        mip_tail_offset: color_requirements.image_mip_tail_offset,
+        tiles: Mutex::new(HashMap::new()),
+        mip_tail: Mutex::new(None),
+      })
+    }
+  }
+
+  pub fn mip_extent(&self, mip: u32) -> vk::Extent2D {
+    vk::Extent2D {
+      width: (self.resolution.width >> mip).max(1),
+      height: (self.resolution.height >> mip).max(1),
+    }
+  }
+
+  // Tiles across and down a level, 0 for levels in the mip tail
+  pub fn tile_count(&self, mip: u32) -> (u32, u32) {
+    if mip >= self.mip_tail_first_lod.min(self.mip_levels) {
+      return (0, 0);
+    }
+    let extent = self.mip_extent(mip);
+    (extent.width.div_ceil(self.tile_extent.width), extent.height.div_ceil(self.tile_extent.height))
+  }
+
+  fn tile_bind(&self, mip: u32, x: u32, y: u32) -> Result<vk::SparseImageMemoryBind, String> {
+    let (tiles_x, tiles_y) = self.tile_count(mip);
+    if x >= tiles_x || y >= tiles_y {
+      return Err(format!("sparse image {} has no tile {x},{y} in level {mip}", self.name));
+    }
+    let extent = self.mip_extent(mip);
+    let offset = vk::Offset3D {
+      x: (x * self.tile_extent.width) as i32,
+      y: (y * self.tile_extent.height) as i32,
+      z: 0,
+    };
+    // Tiles at the right and bottom edges only cover what is left of the level
+    let tile_extent = vk::Extent3D {
+      width: self.tile_extent.width.min(extent.width - offset.x as u32),
+      height: self.tile_extent.height.min(extent.height - offset.y as u32),
+      depth: 1,
+    };
+    Ok(
+      vk::SparseImageMemoryBind::default()
+        .subresource(vk::ImageSubresource {
+          aspect_mask: vk::ImageAspectFlags::COLOR,
+          mip_level: mip,
+          array_layer: 0,
+        })
+        .offset(offset)
+        .extent(tile_extent),
+    )
+  }
+
+  pub fn is_tile_bound(&self, mip: u32, x: u32, y: u32) -> bool {
+    self.tiles.lock().is_ok_and(|tiles| tiles.contains_key(&(mip, x, y)))
+  }
+
+  // None when the tile already has memory, which is only bound once the bind goes through
+  // AdQueue::bind_sparse
+  pub fn bind_tile(
+    &self,
+    mem_location: MemoryLocation,
+    mip: u32,
+    x: u32,
+    y: u32,
+  ) -> Result<Option<vk::SparseImageMemoryBind>, String> {
+    let bind = self.tile_bind(mip, x, y)?;
+    let mut tiles =
+      self.tiles.lock().map_err(|e| format!("at getting sparse image tiles lock: {e}"))?;
+    if tiles.contains_key(&(mip, x, y)) {
+      return Ok(None);
+    }
+    let allocation = AdAllocation::new(
+      self.allocator.clone(),
+      &format!("{}_tile_{mip}_{x}_{y}", self.name),
+      mem_location,
+      vk::MemoryRequirements { size: self.requirements.alignment, ..self.requirements },
+    )?;
+    let memory = allocation.inner().as_ref().ok_or("no allocation".to_string())?;
+    let bind = bind.memory(unsafe { memory.memory() }).memory_offset(memory.offset());
+    tiles.insert((mip, x, y), allocation);
+    Ok(Some(bind))
+  }
+
+  // The bind leaving the tile without memory, with the memory it had. It has to be kept until the
+  // bind is done on the queue
+  pub fn unbind_tile(
+    &self,
+    mip: u32,
+    x: u32,
+    y: u32,
+  ) -> Result<Option<(vk::SparseImageMemoryBind, AdAllocation)>, String> {
+    let bind = self.tile_bind(mip, x, y)?;
+    let mut tiles =
+      self.tiles.lock().map_err(|e| format!("at getting sparse image tiles lock: {e}"))?;
+    Ok(tiles.remove(&(mip, x, y)).map(|allocation| (bind, allocation)))
+  }
+
+  // Opaque binds for the levels in the mip tail, empty when it is already bound or every level
+  // is tiled
+  pub fn bind_mip_tail(
+    &self,
+    mem_location: MemoryLocation,
+  ) -> Result<Vec<vk::SparseMemoryBind>, String> {
+    let mut mip_tail =
+      self.mip_tail.lock().map_err(|e| format!("at getting sparse image mip tail lock: {e}"))?;
+    if self.mip_tail_first_lod >= self.mip_levels || mip_tail.is_some() {
+      return Ok(vec![]);
+    }
+    let allocation = AdAllocation::new(
+      self.allocator.clone(),
+      &format!("{}_mip_tail", self.name),
+      mem_location,
+      vk::MemoryRequirements { size: self.mip_tail_size, ..self.requirements },
+    )?;
+    let memory = allocation.inner().as_ref().ok_or("no allocation".to_string())?;
+    let bind = vk::SparseMemoryBind::default()
+      .resource_offset(self.mip_tail_offset)
+      .size(self.mip_tail_size)
+      .memory(unsafe { memory.memory() })
+      .memory_offset(memory.offset());
+    *mip_tail = Some(allocation);
+    Ok(vec![bind])
+  }
+}
+
+impl Drop for AdSparseImage {
+  fn drop(&mut self) {
+    unsafe {
+      self.ash_device.inner().destroy_image(self.inner, None);
+    }
+  }
+}
+
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct AdImageView {
   #[getset(get = "pub")]
@@ -514,6 +1588,8 @@ pub struct AdImageView {
   #[getset(get_copy = "pub")]
   view_type: vk::ImageViewType,
   #[getset(get_copy = "pub")]
+  subresource_range: vk::ImageSubresourceRange,
+  #[getset(get_copy = "pub")]
   inner: vk::ImageView,
 }
 
@@ -522,6 +1598,21 @@ impl AdImageView {
     image: Arc<AdImage>,
     view_type: vk::ImageViewType,
     subresource_range: vk::ImageSubresourceRange,
+  ) -> Result<Arc<AdImageView>, String> {
+    Self::create_swizzled_view(image, view_type, subresource_range, vk::ComponentMapping {
+      r: vk::ComponentSwizzle::R,
+      g: vk::ComponentSwizzle::G,
+      b: vk::ComponentSwizzle::B,
+      a: vk::ComponentSwizzle::A,
+    })
+  }
+
+  // For images with fewer channels than what samples of them are read as
+  pub fn create_swizzled_view(
+    image: Arc<AdImage>,
+    view_type: vk::ImageViewType,
+    subresource_range: vk::ImageSubresourceRange,
+    components: vk::ComponentMapping,
   ) -> Result<Arc<AdImageView>, String> {
     // Check view type support
     if (view_type == vk::ImageViewType::TYPE_1D && image.itype() != vk::ImageType::TYPE_1D)
@@ -529,7 +1620,7 @@ impl AdImageView {
       || (view_type == vk::ImageViewType::TYPE_2D
         && (image.itype() != vk::ImageType::TYPE_2D && image.itype() != vk::ImageType::TYPE_3D))
       || (view_type == vk::ImageViewType::TYPE_2D_ARRAY
-        && (image.itype() != vk::ImageType::TYPE_1D && image.itype() != vk::ImageType::TYPE_3D))
+        && (image.itype() != vk::ImageType::TYPE_2D && image.itype() != vk::ImageType::TYPE_3D))
       || (view_type == vk::ImageViewType::CUBE && image.itype() != vk::ImageType::TYPE_2D)
       || (view_type == vk::ImageViewType::CUBE_ARRAY && image.itype() != vk::ImageType::TYPE_2D)
       || (view_type == vk::ImageViewType::TYPE_3D && image.itype() != vk::ImageType::TYPE_3D)
@@ -542,12 +1633,7 @@ impl AdImageView {
       .format(image.format())
       .view_type(view_type)
       .subresource_range(subresource_range)
-      .components(vk::ComponentMapping {
-        r: vk::ComponentSwizzle::R,
-        g: vk::ComponentSwizzle::G,
-        b: vk::ComponentSwizzle::B,
-        a: vk::ComponentSwizzle::A,
-      });
+      .components(components);
     let image_view = unsafe {
       image
         .ash_device
@@ -559,8 +1645,69 @@ impl AdImageView {
       inner: image_view,
       image,
       view_type,
+      subresource_range,
     }))
   }
+
+  // Barrier over the view's subresources on the cmd buffer's queue, access masks are left to the
+  // caller
+  pub fn layout_barrier(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+  ) -> vk::ImageMemoryBarrier<'static> {
+    vk::ImageMemoryBarrier::default()
+      .image(self.image.inner())
+      .subresource_range(self.subresource_range)
+      .src_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+      .dst_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+      .old_layout(old_layout)
+      .new_layout(new_layout)
+  }
+
+  // Moves the view into GENERAL for compute shaders reading and writing it as a storage image.
+  // src_stage and src_access are whatever last used it in old_layout
+  pub fn transition_to_general(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    old_layout: vk::ImageLayout,
+    src_stage: vk::PipelineStageFlags,
+    src_access: vk::AccessFlags,
+  ) {
+    cmd_buffer.pipeline_barrier(
+      src_stage,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[self
+        .layout_barrier(cmd_buffer, old_layout, vk::ImageLayout::GENERAL)
+        .src_access_mask(src_access)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)],
+    );
+  }
+
+  // Back out of GENERAL once compute writes are done, for dst_stage to use it in new_layout
+  pub fn transition_from_general(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    new_layout: vk::ImageLayout,
+    dst_stage: vk::PipelineStageFlags,
+    dst_access: vk::AccessFlags,
+  ) {
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      dst_stage,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[self
+        .layout_barrier(cmd_buffer, vk::ImageLayout::GENERAL, new_layout)
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(dst_access)],
+    );
+  }
 }
 
 impl Drop for AdImageView {
@@ -580,10 +1727,17 @@ pub struct AdSampler {
 
 impl AdSampler {
   pub fn new(ash_device: Arc<AdAshDevice>) -> Result<Self, String> {
+    Self::new_with_info(ash_device, &vk::SamplerCreateInfo::default())
+  }
+
+  pub fn new_with_info(
+    ash_device: Arc<AdAshDevice>,
+    create_info: &vk::SamplerCreateInfo,
+  ) -> Result<Self, String> {
     unsafe {
       let vk_sampler = ash_device
         .inner()
-        .create_sampler(&vk::SamplerCreateInfo::default(), None)
+        .create_sampler(create_info, None)
         .map_err(|e| format!("at vk sampler create: {e}"))?;
       Ok(Self { ash_device, inner: vk_sampler })
     }
@@ -601,18 +1755,60 @@ impl Drop for AdSampler {
 #[derive(Clone)]
 pub enum AdDescriptorBinding {
   StorageBuffer(Arc<AdBuffer>),
+  // Offset and size within the buffer, the offset has to be a multiple of the device's
+  // minStorageBufferOffsetAlignment
+  StorageBufferRange((Arc<AdBuffer>, vk::DeviceSize, vk::DeviceSize)),
   UniformBuffer(Arc<AdBuffer>),
   Image2D((Arc<AdImageView>, vk::ImageLayout)),
   Sampler2D((Arc<AdImageView>, vk::ImageLayout, Arc<AdSampler>)),
+  // Separate sampler to pair with an Image2D binding
+  Sampler(Arc<AdSampler>),
+  StorageImage((Arc<AdImageView>, vk::ImageLayout)),
 }
 
 impl AdDescriptorBinding {
   pub fn get_descriptor_type(&self) -> vk::DescriptorType {
     match self {
       Self::StorageBuffer(_) => vk::DescriptorType::STORAGE_BUFFER,
+      Self::StorageBufferRange(_) => vk::DescriptorType::STORAGE_BUFFER,
       Self::UniformBuffer(_) => vk::DescriptorType::UNIFORM_BUFFER,
       Self::Image2D(_) => vk::DescriptorType::SAMPLED_IMAGE,
       Self::Sampler2D(_) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+      Self::Sampler(_) => vk::DescriptorType::SAMPLER,
+      Self::StorageImage(_) => vk::DescriptorType::STORAGE_IMAGE,
+    }
+  }
+
+  // Catches what vulkan would only flag at draw time, if validation layers are on at all
+  pub fn validate(&self) -> Result<(), String> {
+    match self {
+      AdDescriptorBinding::StorageImage((view, layout)) => {
+        if !view.image().usage().contains(vk::ImageUsageFlags::STORAGE) {
+          return Err(format!(
+            "image {} bound as a storage image wasn't created with STORAGE usage",
+            view.image().name()
+          ));
+        }
+        if *layout != vk::ImageLayout::GENERAL {
+          return Err(format!(
+            "image {} bound as a storage image in {layout:?}, storage images must be GENERAL",
+            view.image().name()
+          ));
+        }
+        Ok(())
+      }
+      AdDescriptorBinding::StorageBufferRange((buffer, offset, range)) => {
+        if offset + range > buffer.size() {
+          return Err(format!(
+            "range {offset}..{} bound past the end of buffer {} with {} bytes",
+            offset + range,
+            buffer.name(),
+            buffer.size()
+          ));
+        }
+        Ok(())
+      }
+      _ => Ok(()),
     }
   }
 
@@ -624,6 +1820,11 @@ impl AdDescriptorBinding {
         let buffer_info = vk::DescriptorBufferInfo::default().buffer(v.inner()).offset(0).range(v.size());
         (Some(buffer_info), None)
       }
+      AdDescriptorBinding::StorageBufferRange((v, offset, range)) => {
+        let buffer_info =
+          vk::DescriptorBufferInfo::default().buffer(v.inner()).offset(*offset).range(*range);
+        (Some(buffer_info), None)
+      }
       AdDescriptorBinding::UniformBuffer(v) => {
         let buffer_info = 
             vk::DescriptorBufferInfo::default().buffer(v.inner()).offset(0).range(v.size());
@@ -639,6 +1840,14 @@ impl AdDescriptorBinding {
             vk::DescriptorImageInfo::default().sampler(v.2.inner()).image_view(v.0.inner()).image_layout(v.1);
         (None, Some(image_info))
       }
+      AdDescriptorBinding::Sampler(v) => {
+        (None, Some(vk::DescriptorImageInfo::default().sampler(v.inner())))
+      }
+      AdDescriptorBinding::StorageImage(v) => {
+        let image_info =
+            vk::DescriptorImageInfo::default().image_view(v.0.inner()).image_layout(v.1);
+        (None, Some(image_info))
+      }
     }
   }
 }
@@ -650,12 +1859,36 @@ pub struct AdDescriptorSetLayout {
   inner: vk::DescriptorSetLayout,
   #[getset(get = "pub")]
   bindings: Vec<(vk::ShaderStageFlags, vk::DescriptorType)>,
+  // Made with new_push, sets of it are pushed with push and can't be allocated
+  #[getset(get_copy = "pub")]
+  push_descriptor: bool,
 }
 
 impl AdDescriptorSetLayout {
   pub fn new(
     ash_device: Arc<AdAshDevice>,
     bindings: &[(vk::ShaderStageFlags, vk::DescriptorType)],
+  ) -> Result<Self, String> {
+    Self::new_with_flags(ash_device, bindings, vk::DescriptorSetLayoutCreateFlags::empty())
+  }
+
+  // For bindings that change every draw or dispatch, written into the command buffer with push
+  // instead of a set allocated for each. Check AdAshDevice::push_descriptor first
+  pub fn new_push(
+    ash_device: Arc<AdAshDevice>,
+    bindings: &[(vk::ShaderStageFlags, vk::DescriptorType)],
+  ) -> Result<Self, String> {
+    if !ash_device.push_descriptor() {
+      return Err("at creating push descriptor set layout: device can't push them".to_string());
+    }
+    let flags = vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR;
+    Self::new_with_flags(ash_device, bindings, flags)
+  }
+
+  fn new_with_flags(
+    ash_device: Arc<AdAshDevice>,
+    bindings: &[(vk::ShaderStageFlags, vk::DescriptorType)],
+    flags: vk::DescriptorSetLayoutCreateFlags,
   ) -> Result<Self, String> {
     let vk_descriptor_bindings = bindings
       .iter()
@@ -669,13 +1902,18 @@ impl AdDescriptorSetLayout {
       })
       .collect::<Vec<_>>();
     let dsl_create_info =
-      vk::DescriptorSetLayoutCreateInfo::default().bindings(&vk_descriptor_bindings);
+      vk::DescriptorSetLayoutCreateInfo::default().flags(flags).bindings(&vk_descriptor_bindings);
     unsafe {
       let descriptor_set_layout = ash_device
         .inner()
         .create_descriptor_set_layout(&dsl_create_info, None)
         .map_err(|e| format!("at creating vk descriptor set layout: {e}"))?;
-      Ok(AdDescriptorSetLayout { ash_device, inner: descriptor_set_layout, bindings: bindings.to_vec() })
+      Ok(AdDescriptorSetLayout {
+        ash_device,
+        inner: descriptor_set_layout,
+        bindings: bindings.to_vec(),
+        push_descriptor: flags.contains(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR),
+      })
     }
   }
 
@@ -697,8 +1935,69 @@ impl AdDescriptorSetLayout {
         .inner()
         .create_descriptor_set_layout(&dsl_create_info, None)
         .map_err(|e| format!("at creating vk descriptor set layout: {e}"))?;
-      Ok(AdDescriptorSetLayout { ash_device, inner: descriptor_set_layout, bindings: bindings.iter().map(|x| (x.1, x.2)).collect() })
+      Ok(AdDescriptorSetLayout {
+        ash_device,
+        inner: descriptor_set_layout,
+        bindings: bindings.iter().map(|x| (x.1, x.2)).collect(),
+        push_descriptor: false,
+      })
+    }
+  }
+
+  // Binds the bindings as set number set of pipeline_layout for the draws or dispatches recorded
+  // after, with no set to allocate or free. The layout has to be from new_push
+  pub fn push(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    bind_point: vk::PipelineBindPoint,
+    pipeline_layout: vk::PipelineLayout,
+    set: u32,
+    bindings: &[AdDescriptorBinding],
+  ) -> Result<(), String> {
+    let push_device = match self.ash_device.push_descriptor_device() {
+      Some(push_device) if self.push_descriptor => push_device,
+      _ => return Err("at pushing descriptors: layout isn't a push descriptor one".to_string()),
+    };
+    for (binding_id, binding) in bindings.iter().enumerate() {
+      binding.validate().map_err(|e| format!("at pushing descriptors: {e}"))?;
+      AdDescriptorSet::validate_binding_type(self, binding_id as u32, binding)
+        .map_err(|e| format!("at pushing descriptors: {e}"))?;
+    }
+    let desc_infos = bindings
+      .iter()
+      .map(|b| {
+        let (b_info, i_info) = b.get_descriptor_info();
+        (b_info.map(|x| vec![x]).unwrap_or_default(), i_info.map(|x| vec![x]).unwrap_or_default())
+      })
+      .collect::<Vec<_>>();
+    let write_infos = bindings
+      .iter()
+      .zip(desc_infos.iter())
+      .enumerate()
+      .map(|(j, (b, (b_info, i_info)))| {
+        let mut write_info = vk::WriteDescriptorSet::default()
+          .dst_binding(j as _)
+          .descriptor_type(b.get_descriptor_type())
+          .descriptor_count(1);
+        if !b_info.is_empty() {
+          write_info = write_info.buffer_info(b_info);
+        }
+        if !i_info.is_empty() {
+          write_info = write_info.image_info(i_info);
+        }
+        write_info
+      })
+      .collect::<Vec<_>>();
+    unsafe {
+      push_device.cmd_push_descriptor_set(
+        cmd_buffer.inner(),
+        bind_point,
+        pipeline_layout,
+        set,
+        &write_infos,
+      );
     }
+    Ok(())
   }
 }
 
@@ -770,18 +2069,69 @@ impl AdDescriptorSet {
     desc_pool: Arc<AdDescriptorPool>,
     desc_data: &[(Arc<AdDescriptorSetLayout>, Vec<AdDescriptorBinding>)],
   ) -> Result<Vec<Self>, String> {
+    Self::validate_bindings(desc_data)?;
+    let vk_dsets = Self::allocate_vk(&desc_pool, desc_data)
+      .map_err(|e| format!("at allocating vk dsets: {e}"))?;
+    Ok(Self::write_vk(desc_pool, desc_data, vk_dsets))
+  }
+
+  fn validate_bindings(
+    desc_data: &[(Arc<AdDescriptorSetLayout>, Vec<AdDescriptorBinding>)],
+  ) -> Result<(), String> {
+    for (layout, bindings) in desc_data.iter() {
+      for binding in bindings.iter() {
+        binding.validate().map_err(|e| format!("at creating dset: {e}"))?;
+      }
+      for (binding_id, binding) in bindings.iter().enumerate() {
+        Self::validate_binding_type(layout, binding_id as u32, binding)
+          .map_err(|e| format!("at creating dset: {e}"))?;
+      }
+    }
+    Ok(())
+  }
+
+  // Vulkan only flags these at draw time, if its validation layers are on at all
+  fn validate_binding_type(
+    layout: &AdDescriptorSetLayout,
+    binding_id: u32,
+    binding: &AdDescriptorBinding,
+  ) -> Result<(), String> {
+    if !validation::is_enabled() {
+      return Ok(());
+    }
+    let layout_type = layout.bindings().get(binding_id as usize).map(|(_, x)| *x);
+    if layout_type == Some(binding.get_descriptor_type()) {
+      return Ok(());
+    }
+    let message = format!(
+      "binding {binding_id} is a {:?} where the layout has {layout_type:?}",
+      binding.get_descriptor_type()
+    );
+    validation::report(ValidationCategory::DescriptorType, || message.clone());
+    Err(message)
+  }
+
+  // The vk error is kept so running out of pool memory can be told apart
+  fn allocate_vk(
+    desc_pool: &AdDescriptorPool,
+    desc_data: &[(Arc<AdDescriptorSetLayout>, Vec<AdDescriptorBinding>)],
+  ) -> Result<Vec<vk::DescriptorSet>, vk::Result> {
     unsafe {
-      let vk_dsets = desc_pool
-        .ash_device
-        .inner()
-        .allocate_descriptor_sets(
-          &vk::DescriptorSetAllocateInfo::default()
-            .descriptor_pool(desc_pool.inner)
-            .set_layouts(&desc_data.iter().map(|x| x.0.inner).collect::<Vec<_>>()),
-        )
-        .map_err(|e| format!("at allocating vk dsets: {e}"))?;
+      desc_pool.ash_device.inner().allocate_descriptor_sets(
+        &vk::DescriptorSetAllocateInfo::default()
+          .descriptor_pool(desc_pool.inner)
+          .set_layouts(&desc_data.iter().map(|x| x.0.inner).collect::<Vec<_>>()),
+      )
+    }
+  }
 
-      let dsets = vk_dsets
+  fn write_vk(
+    desc_pool: Arc<AdDescriptorPool>,
+    desc_data: &[(Arc<AdDescriptorSetLayout>, Vec<AdDescriptorBinding>)],
+    vk_dsets: Vec<vk::DescriptorSet>,
+  ) -> Vec<Self> {
+    unsafe {
+      vk_dsets
         .iter()
         .enumerate()
         .map(|(i, vk_dset)| {
@@ -809,6 +2159,7 @@ impl AdDescriptorSet {
             write_infos.push(write_info);
           }
           desc_pool.ash_device.inner().update_descriptor_sets(&write_infos, &[]);
+          desc_pool.ash_device.live_objects().created(AdLiveObjectKind::DescriptorSet, 1);
 
           Self {
             inner: *vk_dset,
@@ -817,13 +2168,18 @@ impl AdDescriptorSet {
             desc_layout: desc_data[i].0.clone(),
           }
         })
-        .collect::<Vec<_>>();
-
-      Ok(dsets)
+        .collect::<Vec<_>>()
     }
   }
 
-  pub fn set_binding(&mut self, binding_id: u32, binding: AdDescriptorBinding) {
+  pub fn set_binding(
+    &mut self,
+    binding_id: u32,
+    binding: AdDescriptorBinding,
+  ) -> Result<(), String> {
+    binding.validate().map_err(|e| format!("at setting dset binding {binding_id}: {e}"))?;
+    Self::validate_binding_type(&self.desc_layout, binding_id, &binding)
+      .map_err(|e| format!("at setting dset binding {binding_id}: {e}"))?;
     let (buffer_info, image_info) = binding.get_descriptor_info();
     let buffer_info = buffer_info.map(|x| vec![x]).unwrap_or(vec![]);
     let image_info = image_info.map(|x| vec![x]).unwrap_or(vec![]);
@@ -843,6 +2199,7 @@ impl AdDescriptorSet {
       self.desc_pool.ash_device.inner().update_descriptor_sets(&[write_info], &[]);
     }
     self.bindings[binding_id as usize] = binding;
+    Ok(())
   }
 }
 
@@ -857,5 +2214,139 @@ impl Drop for AdDescriptorSet {
           .free_descriptor_sets(self.desc_pool.inner, &[self.inner]);
       }
     }
+    self.desc_pool.ash_device.live_objects().destroyed(AdLiveObjectKind::DescriptorSet, 1);
+  }
+}
+
+// Largest pool a manager makes, each new pool doubles the last up to this many sets
+const MAX_MANAGED_POOL_SETS: u32 = 1024;
+
+#[derive(Debug, Clone, Default)]
+pub struct AdDescriptorPoolStats {
+  pub name: String,
+  pub pool_count: usize,
+  // Sets every pool was made to hold, summed
+  pub set_capacity: u32,
+  // Allocated and not dropped yet
+  pub live_sets: u32,
+  pub allocated_sets: u64,
+  // Allocations a pool had no room for, each sent the manager on to another pool
+  pub failed_allocations: u64,
+  // Over every set allocated, what new pools are sized from
+  pub descriptor_counts: Vec<(vk::DescriptorType, u64)>,
+}
+
+struct PoolManagerState {
+  pools: Vec<(Arc<AdDescriptorPool>, u32)>,
+  allocated_sets: u64,
+  failed_allocations: u64,
+  descriptor_counts: HashMap<vk::DescriptorType, u64>,
+}
+
+// Allocates sets from pools it makes as they fill up, instead of one pool sized by a guess.
+// Every pool is sized for the average set allocated so far, so it fits how the layouts are used
+pub struct AdDescriptorPoolManager {
+  ash_device: Arc<AdAshDevice>,
+  name: String,
+  initial_sets: u32,
+  state: Mutex<PoolManagerState>,
+}
+
+impl AdDescriptorPoolManager {
+  // The first pool holds initial_sets sets, nothing is made until the first allocation
+  pub fn new(ash_device: Arc<AdAshDevice>, name: &str, initial_sets: u32) -> Self {
+    Self {
+      ash_device,
+      name: name.to_string(),
+      initial_sets: initial_sets.clamp(1, MAX_MANAGED_POOL_SETS),
+      state: Mutex::new(PoolManagerState {
+        pools: vec![],
+        allocated_sets: 0,
+        failed_allocations: 0,
+        descriptor_counts: HashMap::new(),
+      }),
+    }
+  }
+
+  pub fn allocate(
+    &self,
+    desc_data: &[(Arc<AdDescriptorSetLayout>, Vec<AdDescriptorBinding>)],
+  ) -> Result<Vec<AdDescriptorSet>, String> {
+    AdDescriptorSet::validate_bindings(desc_data)?;
+    let mut state = self
+      .state
+      .lock()
+      .map_err(|e| format!("at getting {} dset pool manager lock: {e}", self.name))?;
+    let mut request_counts = HashMap::new();
+    for (layout, _) in desc_data.iter() {
+      for (_, descriptor_type) in layout.bindings().iter() {
+        *request_counts.entry(*descriptor_type).or_insert(0u32) += 1;
+      }
+    }
+    state.allocated_sets += desc_data.len() as u64;
+    for (descriptor_type, count) in request_counts.iter() {
+      *state.descriptor_counts.entry(*descriptor_type).or_insert(0) += *count as u64;
+    }
+
+    // Newest first, older pools only have room where sets were freed
+    for i in (0..state.pools.len()).rev() {
+      let pool = state.pools[i].0.clone();
+      match AdDescriptorSet::allocate_vk(&pool, desc_data) {
+        Ok(vk_dsets) => return Ok(AdDescriptorSet::write_vk(pool, desc_data, vk_dsets)),
+        Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {
+          state.failed_allocations += 1;
+        }
+        Err(e) => return Err(format!("at allocating vk dsets from {} pool: {e}", self.name)),
+      }
+    }
+
+    let pool_sets = match state.pools.last() {
+      Some((_, last_sets)) => (last_sets * 2).min(MAX_MANAGED_POOL_SETS),
+      None => self.initial_sets,
+    }
+    .max(desc_data.len() as u32);
+    let pool_sizes = state
+      .descriptor_counts
+      .iter()
+      .map(|(descriptor_type, total)| {
+        let average_count = (*total * pool_sets as u64).div_ceil(state.allocated_sets) as u32;
+        vk::DescriptorPoolSize {
+          ty: *descriptor_type,
+          descriptor_count: average_count
+            .max(request_counts.get(descriptor_type).copied().unwrap_or(0))
+            .max(1),
+        }
+      })
+      .collect::<Vec<_>>();
+    let pool = Arc::new(AdDescriptorPool::new(
+      self.ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      pool_sets,
+      &pool_sizes,
+    )?);
+    state.pools.push((pool.clone(), pool_sets));
+    let vk_dsets = AdDescriptorSet::allocate_vk(&pool, desc_data)
+      .map_err(|e| format!("at allocating vk dsets from new {} pool: {e}", self.name))?;
+    Ok(AdDescriptorSet::write_vk(pool, desc_data, vk_dsets))
+  }
+
+  pub fn stats(&self) -> Result<AdDescriptorPoolStats, String> {
+    let state = self
+      .state
+      .lock()
+      .map_err(|e| format!("at getting {} dset pool manager lock: {e}", self.name))?;
+    let mut descriptor_counts =
+      state.descriptor_counts.iter().map(|(ty, count)| (*ty, *count)).collect::<Vec<_>>();
+    descriptor_counts.sort_by_key(|(ty, _)| ty.as_raw());
+    Ok(AdDescriptorPoolStats {
+      name: self.name.clone(),
+      pool_count: state.pools.len(),
+      set_capacity: state.pools.iter().map(|(_, sets)| sets).sum(),
+      // Every set holds on to its pool
+      live_sets: state.pools.iter().map(|(pool, _)| Arc::strong_count(pool) as u32 - 1).sum(),
+      allocated_sets: state.allocated_sets,
+      failed_allocations: state.failed_allocations,
+      descriptor_counts,
+    })
   }
 }
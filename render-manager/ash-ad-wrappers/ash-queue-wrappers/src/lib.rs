@@ -1,11 +1,22 @@
-use std::sync::Arc;
+use std::{
+  cell::RefCell,
+  collections::HashMap,
+  sync::{
+    atomic::{AtomicU32, AtomicU64, Ordering},
+    Arc, Mutex, Weak,
+  },
+  thread::ThreadId,
+};
 
 use ash_context::{
   ash::{self, vk},
-  getset, AdAshDevice,
+  getset, AdAshDevice, AdLiveObjectKind,
 };
 use ash_sync_wrappers::{AdFence, AdSemaphore};
 
+#[cfg(test)]
+mod tests;
+
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct AdQueue {
   #[getset(get = "pub")]
@@ -34,6 +45,64 @@ impl AdQueue {
     }
   }
 
+  pub fn supports_sparse_binding(&self) -> bool {
+    self
+      .ash_device
+      .ash_instance()
+      .get_queue_family_props(self.ash_device.gpu())
+      .get(self.family_index as usize)
+      .is_some_and(|x| x.queue_flags.contains(vk::QueueFlags::SPARSE_BINDING))
+  }
+
+  // Binds run in order with submits to the queue, waiting on and signalling semaphores like a
+  // submit does. Memory unbound here has to outlive the fence or signal semaphores
+  pub fn bind_sparse(
+    &self,
+    binds: &AdSparseBinds,
+    wait_semaphores: &[&AdSemaphore],
+    signal_semaphores: &[&AdSemaphore],
+    fence: Option<&AdFence>,
+  ) -> Result<(), String> {
+    if !self.supports_sparse_binding() {
+      return Err(format!("queue family {} can't bind sparse memory", self.family_index));
+    }
+    let buffer_binds = binds
+      .buffers
+      .iter()
+      .map(|(buffer, binds)| vk::SparseBufferMemoryBindInfo::default().buffer(*buffer).binds(binds))
+      .collect::<Vec<_>>();
+    let image_opaque_binds = binds
+      .image_opaques
+      .iter()
+      .map(|(image, binds)| {
+        vk::SparseImageOpaqueMemoryBindInfo::default().image(*image).binds(binds)
+      })
+      .collect::<Vec<_>>();
+    let image_binds = binds
+      .images
+      .iter()
+      .map(|(image, binds)| vk::SparseImageMemoryBindInfo::default().image(*image).binds(binds))
+      .collect::<Vec<_>>();
+    let wait_semaphores = wait_semaphores.iter().map(|x| x.inner()).collect::<Vec<_>>();
+    let signal_semaphores = signal_semaphores.iter().map(|x| x.inner()).collect::<Vec<_>>();
+    unsafe {
+      self
+        .ash_device
+        .inner()
+        .queue_bind_sparse(
+          self.inner,
+          &[vk::BindSparseInfo::default()
+            .wait_semaphores(&wait_semaphores)
+            .buffer_binds(&buffer_binds)
+            .image_opaque_binds(&image_opaque_binds)
+            .image_binds(&image_binds)
+            .signal_semaphores(&signal_semaphores)],
+          fence.map_or(vk::Fence::null(), |x| x.inner()),
+        )
+        .map_err(|e| format!("error binding sparse memory: {e}"))
+    }
+  }
+
   pub fn wait(&self) -> Result<(), String> {
     unsafe {
       self
@@ -45,16 +114,107 @@ impl AdQueue {
   }
 }
 
+// Memory binds for AdQueue::bind_sparse, per buffer or image. Opaque binds are for fully bound
+// resources and image mip tails, image binds for tiles of partially resident images
+#[derive(Debug, Clone, Default)]
+pub struct AdSparseBinds {
+  pub buffers: Vec<(vk::Buffer, Vec<vk::SparseMemoryBind>)>,
+  pub image_opaques: Vec<(vk::Image, Vec<vk::SparseMemoryBind>)>,
+  pub images: Vec<(vk::Image, Vec<vk::SparseImageMemoryBind>)>,
+}
+
+impl AdSparseBinds {
+  pub fn is_empty(&self) -> bool {
+    self.buffers.iter().all(|x| x.1.is_empty())
+      && self.image_opaques.iter().all(|x| x.1.is_empty())
+      && self.images.iter().all(|x| x.1.is_empty())
+  }
+}
+
+// Timestamp queries, reset and written from command buffers
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct AdQueryPool {
+  ash_device: Arc<AdAshDevice>,
+  #[getset(get_copy = "pub")]
+  inner: vk::QueryPool,
+  #[getset(get_copy = "pub")]
+  query_count: u32,
+}
+
+impl AdQueryPool {
+  pub fn new_timestamps(ash_device: Arc<AdAshDevice>, query_count: u32) -> Result<Self, String> {
+    unsafe {
+      ash_device
+        .inner()
+        .create_query_pool(
+          &vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(query_count),
+          None,
+        )
+        .map_err(|e| format!("at creating vk query pool: {e}"))
+        .map(|vk_query_pool| Self { ash_device, inner: vk_query_pool, query_count })
+    }
+  }
+
+  // Without waiting, None until every query in the range was written and finished on the gpu
+  pub fn get_timestamps(&self, first_query: u32, count: u32) -> Result<Option<Vec<u64>>, String> {
+    let mut timestamps = vec![0u64; count as usize];
+    let result = unsafe {
+      self.ash_device.inner().get_query_pool_results(
+        self.inner,
+        first_query,
+        &mut timestamps,
+        vk::QueryResultFlags::TYPE_64,
+      )
+    };
+    match result {
+      Ok(()) => Ok(Some(timestamps)),
+      Err(vk::Result::NOT_READY) => Ok(None),
+      Err(e) => Err(format!("at getting vk query pool results: {e}")),
+    }
+  }
+}
+
+impl Drop for AdQueryPool {
+  fn drop(&mut self) {
+    unsafe {
+      self.ash_device.inner().destroy_query_pool(self.inner, None);
+    }
+  }
+}
+
+// Vulkan leaves pools to be synchronized by the user. Pools from new are shared and whoever uses
+// them keeps it to one thread at a time. Pools owned by a thread, like the ones from
+// AdCommandPoolRegistry, error when their buffers are allocated, begun or reset on any other
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct AdCommandPool {
   #[getset(get_copy = "pub")]
   inner: vk::CommandPool,
   #[getset(get = "pub")]
   queue: Arc<AdQueue>,
+  #[getset(get_copy = "pub")]
+  owner: Option<ThreadId>,
 }
 
 impl AdCommandPool {
   pub fn new(queue: Arc<AdQueue>, flags: vk::CommandPoolCreateFlags) -> Result<Self, String> {
+    Self::create(queue, flags, None)
+  }
+
+  // Only the calling thread may record from it
+  pub fn new_thread_owned(
+    queue: Arc<AdQueue>,
+    flags: vk::CommandPoolCreateFlags,
+  ) -> Result<Self, String> {
+    Self::create(queue, flags, Some(std::thread::current().id()))
+  }
+
+  fn create(
+    queue: Arc<AdQueue>,
+    flags: vk::CommandPoolCreateFlags,
+    owner: Option<ThreadId>,
+  ) -> Result<Self, String> {
     unsafe {
       let cmd_pool = queue
         .ash_device()
@@ -66,7 +226,18 @@ impl AdCommandPool {
           None,
         )
         .map_err(|e| format!("at vk cmd pool create: {e}"))?;
-      Ok(Self { inner: cmd_pool, queue })
+      Ok(Self { inner: cmd_pool, queue, owner })
+    }
+  }
+
+  pub fn check_thread(&self) -> Result<(), String> {
+    let current = std::thread::current().id();
+    match self.owner {
+      Some(owner) if owner != current => Err(format!(
+        "cmd pool of queue family {} owned by {owner:?} used on {current:?}",
+        self.queue.family_index()
+      )),
+      _ => Ok(()),
     }
   }
 }
@@ -79,12 +250,132 @@ impl Drop for AdCommandPool {
   }
 }
 
+static NEXT_REGISTRY_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+  // Pools the current thread got, by (registry id, queue family). The registry holds them
+  static THREAD_CMD_POOLS: RefCell<HashMap<(u64, u32), Weak<AdCommandPool>>> =
+    RefCell::new(HashMap::new());
+}
+
+// Hands every thread its own pool per queue family, owned by that thread, so threads recording at
+// the same time never share one. Pools live as long as the registry or the buffers made from them
+pub struct AdCommandPoolRegistry {
+  id: u64,
+  flags: vk::CommandPoolCreateFlags,
+  // One queue per family, what buffers from that family's pools submit to
+  queues: HashMap<u32, Arc<AdQueue>>,
+  pools: Mutex<HashMap<(ThreadId, u32), Arc<AdCommandPool>>>,
+}
+
+impl AdCommandPoolRegistry {
+  // Queues after the first of a family are ignored
+  pub fn new(queues: &[Arc<AdQueue>], flags: vk::CommandPoolCreateFlags) -> Self {
+    let mut family_queues = HashMap::new();
+    for queue in queues {
+      family_queues.entry(queue.family_index()).or_insert(queue.clone());
+    }
+    Self {
+      id: NEXT_REGISTRY_ID.fetch_add(1, Ordering::Relaxed),
+      flags,
+      queues: family_queues,
+      pools: Mutex::new(HashMap::new()),
+    }
+  }
+
+  // The calling thread's pool, made on first use
+  pub fn pool(&self, queue_family: u32) -> Result<Arc<AdCommandPool>, String> {
+    let cached = THREAD_CMD_POOLS.with(|cell| {
+      cell.borrow().get(&(self.id, queue_family)).and_then(|pool| pool.upgrade())
+    });
+    if let Some(pool) = cached {
+      return Ok(pool);
+    }
+    let queue = self
+      .queues
+      .get(&queue_family)
+      .ok_or(format!("no queue of family {queue_family} in cmd pool registry"))?;
+    let pool = Arc::new(AdCommandPool::new_thread_owned(queue.clone(), self.flags)?);
+    self
+      .pools
+      .lock()
+      .map_err(|e| format!("at getting cmd pool registry lock: {e}"))?
+      .insert((std::thread::current().id(), queue_family), pool.clone());
+    THREAD_CMD_POOLS.with(|cell| {
+      cell.borrow_mut().insert((self.id, queue_family), Arc::downgrade(&pool));
+    });
+    Ok(pool)
+  }
+
+  // Pools of threads that are gone stay until the registry drops, as they can't be told apart
+  pub fn pool_count(&self) -> Result<usize, String> {
+    Ok(self.pools.lock().map_err(|e| format!("at getting cmd pool registry lock: {e}"))?.len())
+  }
+}
+
+// Largest vkCmdUpdateBuffer the spec allows
+pub const MAX_UPDATE_BUFFER_SIZE: usize = 65536;
+// Semaphores a submit or present can wait on, and a submit signal. Their handles are gathered on
+// the stack so frames submit without allocating
+pub const MAX_SUBMIT_SEMAPHORES: usize = 8;
+
+// What vkCmdUpdateBuffer takes, a size and offset that are multiples of 4 and some data up to the
+// max
+fn check_update_buffer(offset: vk::DeviceSize, len: usize) -> Result<(), String> {
+  if len == 0 || len > MAX_UPDATE_BUFFER_SIZE || !len.is_multiple_of(4) {
+    return Err(format!(
+      "at updating vk buffer: {len} bytes isn't a multiple of 4 up to {MAX_UPDATE_BUFFER_SIZE}"
+    ));
+  }
+  if !offset.is_multiple_of(4) {
+    return Err(format!("at updating vk buffer: offset {offset} isn't a multiple of 4"));
+  }
+  Ok(())
+}
+
+// Vertices and instances of one non indexed draw, laid out like vk::DrawIndirectCommand
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AdDrawRange {
+  pub vertex_count: u32,
+  pub instance_count: u32,
+  pub first_vertex: u32,
+  pub first_instance: u32,
+}
+
+impl AdDrawRange {
+  // One instance of the first vertex_count vertices
+  pub fn vertices(vertex_count: u32) -> Self {
+    Self { vertex_count, instance_count: 1, ..Default::default() }
+  }
+}
+
+// Indices and instances of one indexed draw, laid out like vk::DrawIndexedIndirectCommand.
+// vertex_offset is added to every index before the vertex is fetched
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AdIndexedDrawRange {
+  pub index_count: u32,
+  pub instance_count: u32,
+  pub first_index: u32,
+  pub vertex_offset: i32,
+  pub first_instance: u32,
+}
+
+impl AdIndexedDrawRange {
+  pub fn indices(index_count: u32) -> Self {
+    Self { index_count, instance_count: 1, ..Default::default() }
+  }
+}
+
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct AdCommandBuffer {
   #[getset(get = "pub")]
   cmd_pool: Arc<AdCommandPool>,
   #[getset(get_copy = "pub")]
   inner: vk::CommandBuffer,
+  // Recorded since the last begin, with the ones of executed secondary buffers
+  draw_calls: AtomicU32,
 }
 
 impl AdCommandBuffer {
@@ -93,6 +384,7 @@ impl AdCommandBuffer {
     level: vk::CommandBufferLevel,
     count: u32,
   ) -> Result<Vec<Self>, String> {
+    cmd_pool.check_thread()?;
     let cmd_buffers = unsafe {
       cmd_pool
         .queue()
@@ -106,9 +398,15 @@ impl AdCommandBuffer {
         )
         .map_err(|e| format!("at creating command buffer: {e}"))?
         .iter()
-        .map(|&x| AdCommandBuffer { cmd_pool: cmd_pool.clone(), inner: x })
+        .map(|&x| AdCommandBuffer {
+          cmd_pool: cmd_pool.clone(),
+          inner: x,
+          draw_calls: AtomicU32::new(0),
+        })
         .collect::<Vec<_>>()
     };
+    let live_objects = cmd_pool.queue().ash_device().live_objects();
+    live_objects.created(AdLiveObjectKind::CommandBuffer, cmd_buffers.len() as u64);
     Ok(cmd_buffers)
   }
 
@@ -116,7 +414,18 @@ impl AdCommandBuffer {
     self.cmd_pool.queue().ash_device().inner()
   }
 
+  // Indirect draws count as their max draw count, whatever the gpu ends up drawing
+  pub fn draw_calls(&self) -> u32 {
+    self.draw_calls.load(Ordering::Relaxed)
+  }
+
+  fn count_draws(&self, count: u32) {
+    self.draw_calls.fetch_add(count, Ordering::Relaxed);
+  }
+
   pub fn begin(&self, flags: vk::CommandBufferUsageFlags) -> Result<(), String> {
+    self.cmd_pool.check_thread()?;
+    self.draw_calls.store(0, Ordering::Relaxed);
     unsafe {
       self
         .get_ash_device()
@@ -125,6 +434,32 @@ impl AdCommandBuffer {
     }
   }
 
+  // For secondary buffers recorded to run inside a render pass of a primary buffer
+  pub fn begin_secondary(
+    &self,
+    flags: vk::CommandBufferUsageFlags,
+    render_pass: vk::RenderPass,
+    subpass: u32,
+    framebuffer: vk::Framebuffer,
+  ) -> Result<(), String> {
+    self.cmd_pool.check_thread()?;
+    self.draw_calls.store(0, Ordering::Relaxed);
+    unsafe {
+      self
+        .get_ash_device()
+        .begin_command_buffer(
+          self.inner,
+          &vk::CommandBufferBeginInfo::default().flags(flags).inheritance_info(
+            &vk::CommandBufferInheritanceInfo::default()
+              .render_pass(render_pass)
+              .subpass(subpass)
+              .framebuffer(framebuffer),
+          ),
+        )
+        .map_err(|e| format!("at secondary cmd buffer begin: {e}"))
+    }
+  }
+
   pub fn end(&self) -> Result<(), String> {
     unsafe {
       self
@@ -140,6 +475,21 @@ impl AdCommandBuffer {
     wait_semaphores: &[(&AdSemaphore, vk::PipelineStageFlags)],
     fence: Option<&AdFence>,
   ) -> Result<(), String> {
+    if signal_semaphores.len().max(wait_semaphores.len()) > MAX_SUBMIT_SEMAPHORES {
+      return Err(format!("at submitting cmd buffer: over {MAX_SUBMIT_SEMAPHORES} semaphores"));
+    }
+    let mut signal_handles = [vk::Semaphore::null(); MAX_SUBMIT_SEMAPHORES];
+    for (handle, semaphore) in signal_handles.iter_mut().zip(signal_semaphores) {
+      *handle = semaphore.inner();
+    }
+    let mut wait_handles = [vk::Semaphore::null(); MAX_SUBMIT_SEMAPHORES];
+    let mut wait_stages = [vk::PipelineStageFlags::empty(); MAX_SUBMIT_SEMAPHORES];
+    for ((handle, stage), (semaphore, wait_stage)) in
+      wait_handles.iter_mut().zip(wait_stages.iter_mut()).zip(wait_semaphores)
+    {
+      *handle = semaphore.inner();
+      *stage = *wait_stage;
+    }
     unsafe {
       self
         .get_ash_device()
@@ -147,9 +497,9 @@ impl AdCommandBuffer {
           self.cmd_pool.queue().inner(),
           &[vk::SubmitInfo::default()
             .command_buffers(&[self.inner])
-            .signal_semaphores(&signal_semaphores.iter().map(|x| x.inner()).collect::<Vec<_>>())
-            .wait_semaphores(&wait_semaphores.iter().map(|x| x.0.inner()).collect::<Vec<_>>())
-            .wait_dst_stage_mask(&wait_semaphores.iter().map(|x| x.1).collect::<Vec<_>>())],
+            .signal_semaphores(&signal_handles[..signal_semaphores.len()])
+            .wait_semaphores(&wait_handles[..wait_semaphores.len()])
+            .wait_dst_stage_mask(&wait_stages[..wait_semaphores.len()])],
           fence.map_or(vk::Fence::null(), |x| x.inner()),
         )
         .map_err(|e| format!("error submitting cmd buffer: {e}"))
@@ -157,6 +507,7 @@ impl AdCommandBuffer {
   }
 
   pub fn reset(&self) -> Result<(), String> {
+    self.cmd_pool.check_thread()?;
     unsafe {
       self
         .get_ash_device()
@@ -216,6 +567,26 @@ impl AdCommandBuffer {
     }
   }
 
+  // Leaves the sets before first_set bound
+  pub fn bind_descriptor_sets_from(
+    &self,
+    pipeline_bind_point: vk::PipelineBindPoint,
+    layout: vk::PipelineLayout,
+    first_set: u32,
+    descriptor_sets: &[vk::DescriptorSet],
+  ) {
+    unsafe {
+      self.get_ash_device().cmd_bind_descriptor_sets(
+        self.inner,
+        pipeline_bind_point,
+        layout,
+        first_set,
+        descriptor_sets,
+        &[],
+      )
+    }
+  }
+
   pub fn set_push_constant_data(&self, layout: vk::PipelineLayout, stages: vk::ShaderStageFlags, data: &[u8]) {
     unsafe {
       self.get_ash_device().cmd_push_constants(
@@ -240,9 +611,150 @@ impl AdCommandBuffer {
     }
   }
 
+  pub fn draw_range(&self, range: AdDrawRange) {
+    self.count_draws(1);
+    unsafe {
+      self.get_ash_device().cmd_draw(
+        self.inner,
+        range.vertex_count,
+        range.instance_count,
+        range.first_vertex,
+        range.first_instance,
+      );
+    }
+  }
+
   pub fn draw(&self, vert_count: u32) {
+    self.draw_range(AdDrawRange::vertices(vert_count));
+  }
+
+  pub fn draw_instanced(&self, vert_count: u32, instance_count: u32) {
+    self.draw_range(AdDrawRange { instance_count, ..AdDrawRange::vertices(vert_count) });
+  }
+
+  pub fn draw_instance_range(&self, vert_count: u32, instance_count: u32, first_instance: u32) {
+    self.draw_range(AdDrawRange {
+      instance_count,
+      first_instance,
+      ..AdDrawRange::vertices(vert_count)
+    });
+  }
+
+  // Same pipeline and bindings for every range, recorded one draw after another
+  pub fn multi_draw(&self, ranges: &[AdDrawRange]) {
+    for range in ranges.iter().filter(|range| range.vertex_count > 0 && range.instance_count > 0) {
+      self.draw_range(*range);
+    }
+  }
+
+  pub fn bind_index_buffer(
+    &self,
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    index_type: vk::IndexType,
+  ) {
+    unsafe {
+      self.get_ash_device().cmd_bind_index_buffer(self.inner, buffer, offset, index_type);
+    }
+  }
+
+  // Reads the index buffer from bind_index_buffer
+  pub fn draw_indexed_range(&self, range: AdIndexedDrawRange) {
+    self.count_draws(1);
+    unsafe {
+      self.get_ash_device().cmd_draw_indexed(
+        self.inner,
+        range.index_count,
+        range.instance_count,
+        range.first_index,
+        range.vertex_offset,
+        range.first_instance,
+      );
+    }
+  }
+
+  pub fn draw_indexed(&self, index_count: u32, first_index: u32, vertex_offset: i32) {
+    self.draw_indexed_range(AdIndexedDrawRange {
+      first_index,
+      vertex_offset,
+      ..AdIndexedDrawRange::indices(index_count)
+    });
+  }
+
+  pub fn multi_draw_indexed(&self, ranges: &[AdIndexedDrawRange]) {
+    for range in ranges.iter().filter(|range| range.index_count > 0 && range.instance_count > 0) {
+      self.draw_indexed_range(*range);
+    }
+  }
+
+  pub fn draw_indirect(&self, buffer: vk::Buffer, offset: vk::DeviceSize, draw_count: u32) {
+    self.count_draws(draw_count);
+    unsafe {
+      self.get_ash_device().cmd_draw_indirect(
+        self.inner,
+        buffer,
+        offset,
+        draw_count,
+        std::mem::size_of::<vk::DrawIndirectCommand>() as u32,
+      );
+    }
+  }
+
+  // The buffer holds AdIndexedDrawRange entries back to back
+  pub fn draw_indexed_indirect(&self, buffer: vk::Buffer, offset: vk::DeviceSize, draw_count: u32) {
+    self.count_draws(draw_count);
+    unsafe {
+      self.get_ash_device().cmd_draw_indexed_indirect(
+        self.inner,
+        buffer,
+        offset,
+        draw_count,
+        std::mem::size_of::<AdIndexedDrawRange>() as u32,
+      );
+    }
+  }
+
+  pub fn dispatch(&self, group_count_x: u32, group_count_y: u32, group_count_z: u32) {
+    unsafe {
+      self.get_ash_device().cmd_dispatch(self.inner, group_count_x, group_count_y, group_count_z);
+    }
+  }
+
+  // Workgroup ids start at base instead of 0, so part of a grid can be dispatched on its own.
+  // AdComputePipeline creates every pipeline so it can be dispatched this way
+  pub fn dispatch_base(&self, base: [u32; 3], group_count: [u32; 3]) {
+    unsafe {
+      self.get_ash_device().cmd_dispatch_base(
+        self.inner,
+        base[0],
+        base[1],
+        base[2],
+        group_count[0],
+        group_count[1],
+        group_count[2],
+      );
+    }
+  }
+
+  pub fn reset_query_pool(&self, query_pool: vk::QueryPool, first_query: u32, count: u32) {
+    unsafe {
+      self.get_ash_device().cmd_reset_query_pool(self.inner, query_pool, first_query, count);
+    }
+  }
+
+  pub fn write_timestamp(&self, stage: vk::PipelineStageFlags, query_pool: vk::QueryPool, query: u32) {
     unsafe {
-      self.cmd_pool.queue().ash_device().inner().cmd_draw(self.inner, vert_count, 1, 0, 0);
+      self.get_ash_device().cmd_write_timestamp(self.inner, stage, query_pool, query);
+    }
+  }
+
+  pub fn execute_commands(&self, secondary_cmd_buffers: &[&AdCommandBuffer]) {
+    self.count_draws(secondary_cmd_buffers.iter().map(|x| x.draw_calls()).sum());
+    unsafe {
+      self.get_ash_device().cmd_execute_commands(
+        self.inner,
+        &secondary_cmd_buffers.iter().map(|x| x.inner).collect::<Vec<_>>(),
+      );
     }
   }
 
@@ -297,6 +809,81 @@ impl AdCommandBuffer {
     }
   }
 
+  pub fn clear_color_image(
+    &self,
+    image: vk::Image,
+    image_layout: vk::ImageLayout,
+    color: &vk::ClearColorValue,
+    ranges: &[vk::ImageSubresourceRange],
+  ) {
+    unsafe {
+      self.get_ash_device().cmd_clear_color_image(self.inner, image, image_layout, color, ranges);
+    }
+  }
+
+  pub fn clear_depth_stencil_image(
+    &self,
+    image: vk::Image,
+    image_layout: vk::ImageLayout,
+    depth_stencil: &vk::ClearDepthStencilValue,
+    ranges: &[vk::ImageSubresourceRange],
+  ) {
+    unsafe {
+      self.get_ash_device().cmd_clear_depth_stencil_image(
+        self.inner,
+        image,
+        image_layout,
+        depth_stencil,
+        ranges,
+      );
+    }
+  }
+
+  // Repeats the u32 over size bytes, size is vk::WHOLE_SIZE or a multiple of 4 like the offset
+  pub fn fill_buffer(
+    &self,
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    data: u32,
+  ) {
+    unsafe {
+      self.get_ash_device().cmd_fill_buffer(self.inner, buffer, offset, size, data);
+    }
+  }
+
+  // The data is copied into the command buffer when recording, so only for small updates
+  pub fn update_buffer(
+    &self,
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    data: &[u8],
+  ) -> Result<(), String> {
+    check_update_buffer(offset, data.len())?;
+    unsafe {
+      self.get_ash_device().cmd_update_buffer(self.inner, buffer, offset, data);
+    }
+    Ok(())
+  }
+
+  pub fn copy_image_to_buffer(
+    &self,
+    src_image: vk::Image,
+    src_image_layout: vk::ImageLayout,
+    dst_buffer: vk::Buffer,
+    regions: &[vk::BufferImageCopy],
+  ) {
+    unsafe {
+      self.get_ash_device().cmd_copy_image_to_buffer(
+        self.inner,
+        src_image,
+        src_image_layout,
+        dst_buffer,
+        regions,
+      );
+    }
+  }
+
   pub fn blit_image(
     &self,
     src_image: vk::Image,
@@ -325,5 +912,7 @@ impl Drop for AdCommandBuffer {
     unsafe {
       self.get_ash_device().free_command_buffers(self.cmd_pool.inner(), &[self.inner]);
     }
+    let live_objects = self.cmd_pool.queue().ash_device().live_objects();
+    live_objects.destroyed(AdLiveObjectKind::CommandBuffer, 1);
   }
 }
@@ -0,0 +1,23 @@
+use crate::{check_update_buffer, MAX_UPDATE_BUFFER_SIZE};
+
+#[test]
+fn update_buffer_takes_aligned_sizes_up_to_the_max() {
+  assert!(check_update_buffer(0, 4).is_ok());
+  assert!(check_update_buffer(256, MAX_UPDATE_BUFFER_SIZE).is_ok());
+}
+
+#[test]
+fn update_buffer_rejects_bad_sizes() {
+  for len in [0, 3, 6, MAX_UPDATE_BUFFER_SIZE + 4] {
+    let e = check_update_buffer(0, len).expect_err(&format!("{len} bytes went through"));
+    assert!(e.contains(&format!("{len} bytes")), "{e}");
+  }
+}
+
+#[test]
+fn update_buffer_rejects_unaligned_offsets() {
+  for offset in [1, 2, 6, 1026] {
+    let e = check_update_buffer(offset, 16).expect_err(&format!("offset {offset} went through"));
+    assert!(e.contains(&format!("offset {offset}")), "{e}");
+  }
+}
@@ -4,16 +4,27 @@ use std::{
 
 use ash_context::{
   ash::{self, vk},
-  getset, AdAshDevice,
+  getset, AdAshDevice, AdLiveObjectKind,
 };
 use ash_data_wrappers::{AdDescriptorSetLayout, AdImageView};
 
+pub use naga;
+pub use shader_source::{
+  compile_shader, compile_shader_file, parse_shader, shader_stage_from_path, AdShaderBinding,
+  AdShaderLanguage, AdShaderReflection,
+};
+
+mod shader_source;
+
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct AdRenderPass {
   #[getset(get = "pub")]
   ash_device: Arc<AdAshDevice>,
   #[getset(get_copy = "pub")]
   inner: vk::RenderPass,
+  // Per subpass, bit i set draws view i. Empty when the pass isn't multiview
+  #[getset(get = "pub")]
+  view_masks: Vec<u32>,
 }
 
 impl AdRenderPass {
@@ -24,20 +35,61 @@ impl AdRenderPass {
     subpasses: &[vk::SubpassDescription],
     dependencies: &[vk::SubpassDependency],
   ) -> Result<Self, String> {
+    Self::new_multiview(ash_device, flags, attachments, subpasses, dependencies, &[], &[])
+  }
+
+  // Every subpass draws each view in its mask, to the attachment layer of the same index, with
+  // gl_ViewIndex telling shaders which one. Dependencies between views of one subpass need
+  // VIEW_LOCAL. Correlation masks hint at views that see about the same, like the two eyes
+  pub fn new_multiview(
+    ash_device: Arc<AdAshDevice>,
+    flags: vk::RenderPassCreateFlags,
+    attachments: &[vk::AttachmentDescription],
+    subpasses: &[vk::SubpassDescription],
+    dependencies: &[vk::SubpassDependency],
+    view_masks: &[u32],
+    correlation_masks: &[u32],
+  ) -> Result<Self, String> {
+    if !view_masks.is_empty() {
+      if view_masks.len() != subpasses.len() {
+        return Err(format!(
+          "multiview render pass needs a view mask for each of its {} subpasses, got {}",
+          subpasses.len(),
+          view_masks.len()
+        ));
+      }
+      let max_views = ash_device.multiview_view_count();
+      if max_views == 0 {
+        return Err("multiview render pass on a device made without multiview".to_string());
+      }
+      let past_max = |mask: &&u32| **mask == 0 || 32 - mask.leading_zeros() > max_views;
+      if let Some(mask) = view_masks.iter().find(past_max) {
+        return Err(format!("view mask {mask:#b} is empty or past the device's {max_views} views"));
+      }
+    }
+    let mut multiview_info = vk::RenderPassMultiviewCreateInfo::default()
+      .view_masks(view_masks)
+      .correlation_masks(correlation_masks);
+    let mut create_info = vk::RenderPassCreateInfo::default()
+      .flags(flags)
+      .attachments(attachments)
+      .subpasses(subpasses)
+      .dependencies(dependencies);
+    if !view_masks.is_empty() {
+      create_info = create_info.push_next(&mut multiview_info);
+    }
     let vk_render_pass = unsafe {
       ash_device
         .inner()
-        .create_render_pass(
-          &vk::RenderPassCreateInfo::default()
-            .flags(flags)
-            .attachments(attachments)
-            .subpasses(subpasses)
-            .dependencies(dependencies),
-          None,
-        )
+        .create_render_pass(&create_info, None)
         .map_err(|e| format!("at vk render pass create: {e}"))?
     };
-    Ok(AdRenderPass { ash_device, inner: vk_render_pass })
+    Ok(AdRenderPass { ash_device, inner: vk_render_pass, view_masks: view_masks.to_vec() })
+  }
+
+  // Views the subpass draws, 1 outside multiview
+  pub fn view_count(&self, subpass_id: u32) -> u32 {
+    self.view_masks.get(subpass_id as usize).map_or(1, |mask| mask.count_ones())
   }
 }
 
@@ -117,6 +169,9 @@ impl Drop for AdShaderModule {
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct AdPipeline {
   render_pass: Arc<AdRenderPass>,
+  // Views of its subpass, the view mask comes with the render pass
+  #[getset(get_copy = "pub")]
+  view_count: u32,
   #[getset(get_copy = "pub")]
   layout: vk::PipelineLayout,
   #[getset(get_copy = "pub")]
@@ -133,6 +188,7 @@ impl AdPipeline {
     rasterizer_config: vk::PipelineRasterizationStateCreateInfo,
     blend_info: &vk::PipelineColorBlendStateCreateInfo,
     depth_info: &vk::PipelineDepthStencilStateCreateInfo,
+    samples: vk::SampleCountFlags,
   ) -> Result<Self, String> {
     let empty_vert_input_info = vk::PipelineVertexInputStateCreateInfo::default();
     let triangle_input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::default()
@@ -143,7 +199,7 @@ impl AdPipeline {
       vk::PipelineViewportStateCreateInfo::default().scissor_count(1).viewport_count(1);
     let msaa_state = vk::PipelineMultisampleStateCreateInfo::default()
       .sample_shading_enable(false)
-      .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+      .rasterization_samples(samples);
     let mut shader_modules = shaders
       .iter()
       .map(|(_, path)| AdShaderModule::from_bytes(render_pass.ash_device().clone(), path))
@@ -193,84 +249,43 @@ impl AdPipeline {
       .color_blend_state(&blend_info)
       .depth_stencil_state(&depth_info)
       .rasterization_state(&rasterizer_config);
+    let ash_device = render_pass.ash_device();
     let pipeline = unsafe {
-      render_pass
-        .ash_device()
-        .inner()
-        .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_create_info], None)
-        .map_err(|(_, e)| format!("at creating vk pipeline: {e}"))?
-        .swap_remove(0)
+      ash_device.inner().create_graphics_pipelines(
+        ash_device.pipeline_cache(),
+        &[pipeline_create_info],
+        None,
+      )
     };
     for mut shader_mod in shader_modules.drain(..) {
       shader_mod.manual_destroy();
     }
-    Ok(AdPipeline { render_pass, layout: pipeline_layout, inner: pipeline })
+    // Some drivers only refuse a shader here, the layout isn't needed then
+    let pipeline = match pipeline {
+      Ok(mut pipelines) => pipelines.swap_remove(0),
+      Err((_, e)) => {
+        unsafe { ash_device.inner().destroy_pipeline_layout(pipeline_layout, None) };
+        return Err(format!("at creating vk pipeline: {e}"));
+      }
+    };
+    let view_count = render_pass.view_count(subpass_id);
+    Ok(AdPipeline { render_pass, view_count, layout: pipeline_layout, inner: pipeline })
   }
 
-  // fn get_set_binding(
-  //   ast: &spirv_cross::spirv::Ast<spirv_cross::glsl::Target>,
-  //   id: u32
-  // ) -> Result<(u32, u32), String> {
-  //   let set = ast
-  //     .get_decoration(id, spirv_cross::spirv::Decoration::DescriptorSet)
-  //     .map_err(|e| format!("at getting desriptor set id: {e}"))?;
-  //   let binding = ast
-  //     .get_decoration(id, spirv_cross::spirv::Decoration::Binding)
-  //     .map_err(|e| format!("at getting desriptor set id: {e}"))?;
-  //   Ok((set, binding))
-  // }
-
-  // pub fn make_dset_layouts_for_shaders(shaders: HashMap<vk::ShaderStageFlags, &[u8]>)
-  // -> Result<Vec<AdDescriptorSetLayout>, String> {
-  //   let mut set_binding_info: HashMap<u32, _> = HashMap::new();
-  //   for (stage, shader_code) in shaders.iter() {
-  //     let shader_words = AdShaderModule::bytes_to_words(*shader_code);
-  //     let shader_mod = spirv_cross::spirv::Module::from_words(shader_words);
-  //     let shader_ast = spirv_cross::spirv::Ast::<spirv_cross::glsl::Target>::parse(&shader_mod)
-  //       .map_err(|e| format!("at making shader ast: {e}"))?;
-  //     let shader_resources = shader_ast.get_shader_resources()
-  //       .map_err(|e| format!("at getting shader resources: {e}"))?;
-
-  //     // Uniform Buffers
-  //     for ub_resource in shader_resources.uniform_buffers.iter() {
-  //       let (set, binding) = Self::get_set_binding(&shader_ast, ub_resource.id)?;
-  //       let binding_info = set_binding_info
-  //         .entry(set)
-  //         .or_insert(HashMap::new())
-  //         .entry(binding)
-  //         .or_insert((*stage, AdDescriptorBinding::UniformBuffer(None)));
-  //       binding_info.0 = binding_info.0 | *stage;
-  //     }
-  //     // Storage Buffers
-  //     for sb_resource in shader_resources.storage_buffers.iter() {
-  //       let (set, binding) = Self::get_set_binding(&shader_ast, sb_resource.id)?;
-  //       let binding_info = set_binding_info
-  //         .entry(set)
-  //         .or_insert(HashMap::new())
-  //         .entry(binding)
-  //         .or_insert((*stage, AdDescriptorBinding::StorageBuffer(None)));
-  //       binding_info.0 = binding_info.0 | *stage;
-  //     }
-  //     // Sampled Images
-  //     for si_resource in shader_resources.sampled_images.iter() {
-  //       let (set, binding) = Self::get_set_binding(&shader_ast, si_resource.id)?;
-  //       let binding_info = set_binding_info
-  //         .entry(set)
-  //         .or_insert(HashMap::new())
-  //         .entry(binding)
-  //         .or_insert((*stage, AdDescriptorBinding::Sampler2D(None)));
-  //       binding_info.0 = binding_info.0 | *stage;
-  //     }
-  //   }
-
-  //   for (set, binding_map) in set_binding_info.iter() {
-  //     let dsl_create_info = vec![];
-  //     for (binding, binding_info) in binding_map.iter() {
-  //       dsl_create_info.push((*binding, binding_info.0, binding_info.1.clone()));
-  //     }
-  //   }
-  //   Ok(())
-  // }
+  // Descriptors and push constants of the shaders new takes, for making its set layouts. naga
+  // can't read SPIR-V with combined image samplers, those shaders still need theirs by hand
+  pub fn reflect_shaders(
+    shaders: &HashMap<vk::ShaderStageFlags, &[u8]>,
+  ) -> Result<AdShaderReflection, String> {
+    let mut reflection = AdShaderReflection::default();
+    for (stage, shader_code) in shaders.iter() {
+      let stage_reflection =
+        AdShaderReflection::from_source(AdShaderLanguage::Spirv, shader_code, *stage)
+          .map_err(|e| format!("at reflecting {stage:?} shader: {e}"))?;
+      reflection.merge(&stage_reflection)?;
+    }
+    Ok(reflection)
+  }
 }
 
 impl Drop for AdPipeline {
@@ -282,6 +297,91 @@ impl Drop for AdPipeline {
   }
 }
 
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct AdComputePipeline {
+  ash_device: Arc<AdAshDevice>,
+  #[getset(get_copy = "pub")]
+  layout: vk::PipelineLayout,
+  #[getset(get_copy = "pub")]
+  inner: vk::Pipeline,
+}
+
+impl AdComputePipeline {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    shader: &[u8],
+    set_layouts: &[&AdDescriptorSetLayout],
+    push_constant_len: u32,
+  ) -> Result<Self, String> {
+    let mut shader_module = AdShaderModule::from_bytes(ash_device.clone(), shader)?;
+    let set_layouts_vec = set_layouts.iter().map(|x| x.inner()).collect::<Vec<_>>();
+    let mut push_layouts_info = vec![];
+    if push_constant_len != 0 {
+      push_layouts_info.push(
+        vk::PushConstantRange::default()
+          .offset(0)
+          .size(push_constant_len)
+          .stage_flags(vk::ShaderStageFlags::COMPUTE),
+      );
+    }
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
+      .set_layouts(&set_layouts_vec)
+      .push_constant_ranges(&push_layouts_info);
+    let pipeline_layout = unsafe {
+      ash_device
+        .inner()
+        .create_pipeline_layout(&pipeline_layout_info, None)
+        .map_err(|e| format!("at creating vk compute pipeline layout: {e}"))?
+    };
+
+    // Lets AdCommandBuffer::dispatch_base start past workgroup 0
+    let pipeline_create_info = vk::ComputePipelineCreateInfo::default()
+      .flags(vk::PipelineCreateFlags::DISPATCH_BASE)
+      .layout(pipeline_layout)
+      .stage(
+        vk::PipelineShaderStageCreateInfo::default()
+          .stage(vk::ShaderStageFlags::COMPUTE)
+          .name(c"main")
+          .module(shader_module.inner()),
+      );
+    let pipeline = unsafe {
+      ash_device
+        .inner()
+        .create_compute_pipelines(ash_device.pipeline_cache(), &[pipeline_create_info], None)
+        .map_err(|(_, e)| format!("at creating vk compute pipeline: {e}"))?
+        .swap_remove(0)
+    };
+    shader_module.manual_destroy();
+    Ok(Self { ash_device, layout: pipeline_layout, inner: pipeline })
+  }
+
+  // Set layouts and push constants from the shader itself, see AdPipeline::reflect_shaders
+  pub fn new_reflected(
+    ash_device: Arc<AdAshDevice>,
+    shader: &[u8],
+  ) -> Result<(Self, Vec<Arc<AdDescriptorSetLayout>>), String> {
+    let shaders = HashMap::from([(vk::ShaderStageFlags::COMPUTE, shader)]);
+    let reflection = AdPipeline::reflect_shaders(&shaders)?;
+    let set_layouts = reflection.set_layouts(ash_device.clone())?;
+    let pipeline = Self::new(
+      ash_device,
+      shader,
+      &set_layouts.iter().map(|x| x.as_ref()).collect::<Vec<_>>(),
+      reflection.push_constant_size,
+    )?;
+    Ok((pipeline, set_layouts))
+  }
+}
+
+impl Drop for AdComputePipeline {
+  fn drop(&mut self) {
+    unsafe {
+      self.ash_device.inner().destroy_pipeline(self.inner, None);
+      self.ash_device.inner().destroy_pipeline_layout(self.layout, None);
+    }
+  }
+}
+
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct AdFrameBuffer {
   #[getset(get = "pub")]
@@ -318,6 +418,7 @@ impl AdFrameBuffer {
         )
         .map_err(|e| format!("at creating vk frame buffer: {e}"))?
     };
+    render_pass.ash_device().live_objects().created(AdLiveObjectKind::Framebuffer, 1);
     Ok(Arc::new(Self { render_pass, attachments, resolution, layers, inner: vk_framebuffer }))
   }
 }
@@ -327,5 +428,6 @@ impl Drop for AdFrameBuffer {
     unsafe {
       self.render_pass.ash_device().inner().destroy_framebuffer(self.inner, None);
     }
+    self.render_pass.ash_device().live_objects().destroyed(AdLiveObjectKind::Framebuffer, 1);
   }
 }
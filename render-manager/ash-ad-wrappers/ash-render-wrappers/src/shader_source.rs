@@ -0,0 +1,264 @@
+use std::{collections::BTreeMap, fs, path::Path, sync::Arc};
+
+use ash_context::{ash::vk, AdAshDevice};
+use ash_data_wrappers::AdDescriptorSetLayout;
+
+// What a shader is written in, naga turns WGSL and GLSL into SPIR-V
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdShaderLanguage {
+  Wgsl,
+  Glsl,
+  Spirv,
+}
+
+impl AdShaderLanguage {
+  // .wgsl, .spv, or glslc's stage extensions like .vert and .comp for GLSL
+  pub fn from_path(path: &Path) -> Option<Self> {
+    let file_name = path.file_name()?.to_str()?;
+    if file_name.ends_with(".wgsl") {
+      Some(Self::Wgsl)
+    } else if file_name.ends_with(".spv") {
+      Some(Self::Spirv)
+    } else {
+      shader_stage_from_path(path).map(|_| Self::Glsl)
+    }
+  }
+}
+
+// From glslc style names like water.frag, water.frag.wgsl or water.frag.spv
+pub fn shader_stage_from_path(path: &Path) -> Option<vk::ShaderStageFlags> {
+  let file_name = path.file_name()?.to_str()?;
+  let file_name = file_name.strip_suffix(".spv").or(file_name.strip_suffix(".wgsl"));
+  let file_name = file_name.unwrap_or(path.file_name()?.to_str()?);
+  match file_name.rsplit('.').next()? {
+    "vert" => Some(vk::ShaderStageFlags::VERTEX),
+    "frag" => Some(vk::ShaderStageFlags::FRAGMENT),
+    "comp" => Some(vk::ShaderStageFlags::COMPUTE),
+    "task" => Some(vk::ShaderStageFlags::TASK_EXT),
+    "mesh" => Some(vk::ShaderStageFlags::MESH_EXT),
+    _ => None,
+  }
+}
+
+fn naga_stage(stage: vk::ShaderStageFlags) -> Result<naga::ShaderStage, String> {
+  match stage {
+    vk::ShaderStageFlags::VERTEX => Ok(naga::ShaderStage::Vertex),
+    vk::ShaderStageFlags::FRAGMENT => Ok(naga::ShaderStage::Fragment),
+    vk::ShaderStageFlags::COMPUTE => Ok(naga::ShaderStage::Compute),
+    vk::ShaderStageFlags::TASK_EXT => Ok(naga::ShaderStage::Task),
+    vk::ShaderStageFlags::MESH_EXT => Ok(naga::ShaderStage::Mesh),
+    _ => Err(format!("naga can't make {stage:?} shaders")),
+  }
+}
+
+fn source_text(source: &[u8]) -> Result<&str, String> {
+  std::str::from_utf8(source).map_err(|e| format!("at reading shader source as utf8: {e}"))
+}
+
+// Parses a shader into naga's IR, keeping only the entry point of stage and naming it main like
+// the pipelines expect
+pub fn parse_shader(
+  language: AdShaderLanguage,
+  source: &[u8],
+  stage: vk::ShaderStageFlags,
+) -> Result<naga::Module, String> {
+  let naga_stage = naga_stage(stage)?;
+  let mut module = match language {
+    AdShaderLanguage::Wgsl => {
+      let source = source_text(source)?;
+      naga::front::wgsl::parse_str(source)
+        .map_err(|e| format!("at parsing wgsl: {}", e.emit_to_string(source)))?
+    }
+    AdShaderLanguage::Glsl => {
+      let source = source_text(source)?;
+      naga::front::glsl::Frontend::default()
+        .parse(&naga::front::glsl::Options::from(naga_stage), source)
+        .map_err(|e| format!("at parsing glsl: {}", e.emit_to_string(source)))?
+    }
+    AdShaderLanguage::Spirv => naga::front::spv::parse_u8_slice(source, &Default::default())
+      .map_err(|e| format!("at parsing spirv: {e}"))?,
+  };
+  module.entry_points.retain(|entry_point| entry_point.stage == naga_stage);
+  match module.entry_points.as_mut_slice() {
+    [entry_point] => entry_point.name = "main".to_string(),
+    [] => return Err(format!("no {stage:?} entry point in shader")),
+    _ => return Err(format!("more than one {stage:?} entry point in shader")),
+  }
+  Ok(module)
+}
+
+fn validate(module: &naga::Module) -> Result<naga::valid::ModuleInfo, String> {
+  naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+    .validate(module)
+    .map_err(|e| format!("at validating shader: {}", e.into_inner()))
+}
+
+// SPIR-V words for vulkan from a WGSL or GLSL shader, at build time from a build script or at
+// runtime. SPIR-V is passed through as is. Positions are left in vulkan's clip space like the
+// glslc compiled shaders, and naga has no combined image samplers so textures and samplers are
+// bound apart
+pub fn compile_shader(
+  language: AdShaderLanguage,
+  source: &[u8],
+  stage: vk::ShaderStageFlags,
+) -> Result<Vec<u32>, String> {
+  if language == AdShaderLanguage::Spirv {
+    if !source.len().is_multiple_of(4) {
+      return Err("spv data should be multiple of 4 bytes".to_string());
+    }
+    return Ok(
+      source
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect(),
+    );
+  }
+  let module = parse_shader(language, source, stage)?;
+  let info = validate(&module)?;
+  let mut options = naga::back::spv::Options::default();
+  options.flags.remove(naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+  naga::back::spv::write_vec(&module, &info, &options, None)
+    .map_err(|e| format!("at writing spirv: {e}"))
+}
+
+// Language and stage come from the file name, see AdShaderLanguage::from_path
+pub fn compile_shader_file(path: &Path) -> Result<Vec<u32>, String> {
+  let (Some(language), Some(stage)) =
+    (AdShaderLanguage::from_path(path), shader_stage_from_path(path))
+  else {
+    return Err(format!("can't tell the language and stage of shader {path:?}"));
+  };
+  let source = fs::read(path).map_err(|e| format!("at reading shader {path:?}: {e}"))?;
+  compile_shader(language, &source, stage).map_err(|e| format!("at compiling {path:?}: {e}"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdShaderBinding {
+  pub set: u32,
+  pub binding: u32,
+  // Every stage that uses the binding
+  pub stages: vk::ShaderStageFlags,
+  pub descriptor_type: vk::DescriptorType,
+}
+
+// Descriptors and push constants the entry points of a pipeline's shaders use
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdShaderReflection {
+  // By set then binding
+  pub bindings: Vec<AdShaderBinding>,
+  pub push_constant_stages: vk::ShaderStageFlags,
+  pub push_constant_size: u32,
+}
+
+fn descriptor_type(
+  module: &naga::Module,
+  variable: &naga::GlobalVariable,
+) -> Result<vk::DescriptorType, String> {
+  let name = variable.name.as_deref().unwrap_or("unnamed");
+  match (variable.space, &module.types[variable.ty].inner) {
+    (naga::AddressSpace::Uniform, _) => Ok(vk::DescriptorType::UNIFORM_BUFFER),
+    (naga::AddressSpace::Storage { .. }, _) => Ok(vk::DescriptorType::STORAGE_BUFFER),
+    (naga::AddressSpace::Handle, naga::TypeInner::Sampler { .. }) => {
+      Ok(vk::DescriptorType::SAMPLER)
+    }
+    (naga::AddressSpace::Handle, naga::TypeInner::Image { class, .. }) => match class {
+      naga::ImageClass::Storage { .. } => Ok(vk::DescriptorType::STORAGE_IMAGE),
+      naga::ImageClass::Sampled { .. } | naga::ImageClass::Depth { .. } => {
+        Ok(vk::DescriptorType::SAMPLED_IMAGE)
+      }
+    },
+    (naga::AddressSpace::Handle, naga::TypeInner::AccelerationStructure { .. }) => {
+      Ok(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
+    }
+    (space, ty) => Err(format!("no descriptor type for {name}, a {ty:?} in {space:?}")),
+  }
+}
+
+impl AdShaderReflection {
+  pub fn from_module(module: &naga::Module, stage: vk::ShaderStageFlags) -> Result<Self, String> {
+    let naga_stage = naga_stage(stage)?;
+    let Some(entry_idx) = module.entry_points.iter().position(|x| x.stage == naga_stage) else {
+      return Err(format!("no {stage:?} entry point in shader"));
+    };
+    let info = validate(module)?;
+    let entry_info = info.get_entry_point(entry_idx);
+    let mut reflection = Self::default();
+    for (handle, variable) in module.global_variables.iter() {
+      if entry_info[handle].is_empty() {
+        continue;
+      }
+      if variable.space == naga::AddressSpace::PushConstant {
+        reflection.push_constant_stages = stage;
+        reflection.push_constant_size = module.types[variable.ty].inner.size(module.to_ctx());
+        continue;
+      }
+      let Some(resource_binding) = &variable.binding else {
+        continue;
+      };
+      reflection.bindings.push(AdShaderBinding {
+        set: resource_binding.group,
+        binding: resource_binding.binding,
+        stages: stage,
+        descriptor_type: descriptor_type(module, variable)?,
+      });
+    }
+    reflection.bindings.sort_by_key(|binding| (binding.set, binding.binding));
+    Ok(reflection)
+  }
+
+  pub fn from_source(
+    language: AdShaderLanguage,
+    source: &[u8],
+    stage: vk::ShaderStageFlags,
+  ) -> Result<Self, String> {
+    Self::from_module(&parse_shader(language, source, stage)?, stage)
+  }
+
+  // Adds another stage's shader of the same pipeline, bindings both use have to agree on the type
+  pub fn merge(&mut self, other: &Self) -> Result<(), String> {
+    let mut by_slot = self.bindings.iter().chain(other.bindings.iter()).fold(
+      BTreeMap::<(u32, u32), Vec<&AdShaderBinding>>::new(),
+      |mut by_slot, binding| {
+        by_slot.entry((binding.set, binding.binding)).or_default().push(binding);
+        by_slot
+      },
+    );
+    let mut bindings = vec![];
+    for ((set, binding), uses) in by_slot.iter_mut() {
+      let descriptor_type = uses[0].descriptor_type;
+      if let Some(other_use) = uses.iter().find(|x| x.descriptor_type != descriptor_type) {
+        return Err(format!(
+          "set {set} binding {binding} is a {descriptor_type:?} and a {:?} in different stages",
+          other_use.descriptor_type
+        ));
+      }
+      let stages = uses.iter().fold(vk::ShaderStageFlags::empty(), |stages, x| stages | x.stages);
+      bindings.push(AdShaderBinding { set: *set, binding: *binding, stages, descriptor_type });
+    }
+    self.bindings = bindings;
+    self.push_constant_stages |= other.push_constant_stages;
+    self.push_constant_size = self.push_constant_size.max(other.push_constant_size);
+    Ok(())
+  }
+
+  // One layout per set up to the highest used, sets in between are left empty
+  pub fn set_layouts(
+    &self,
+    ash_device: Arc<AdAshDevice>,
+  ) -> Result<Vec<Arc<AdDescriptorSetLayout>>, String> {
+    let set_count = self.bindings.iter().map(|binding| binding.set + 1).max().unwrap_or(0);
+    (0..set_count)
+      .map(|set| {
+        let bindings = self
+          .bindings
+          .iter()
+          .filter(|binding| binding.set == set)
+          .map(|binding| (binding.binding, binding.stages, binding.descriptor_type))
+          .collect::<Vec<_>>();
+        AdDescriptorSetLayout::new_sparse(ash_device.clone(), &bindings)
+          .map(Arc::new)
+          .map_err(|e| format!("at making layout of set {set}: {e}"))
+      })
+      .collect()
+  }
+}
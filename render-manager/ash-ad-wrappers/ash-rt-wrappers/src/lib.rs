@@ -0,0 +1,702 @@
+use std::{
+  ffi::c_char,
+  sync::{Arc, Mutex},
+};
+
+use ash_context::{
+  ash::{khr, vk},
+  getset,
+  gpu_allocator::{vulkan::Allocator, MemoryLocation},
+  AdAshDevice,
+};
+use ash_data_wrappers::{AdBuffer, AdDescriptorSet, AdDescriptorSetLayout};
+use ash_queue_wrappers::AdCommandBuffer;
+use ash_render_wrappers::AdShaderModule;
+use ash_sync_wrappers::AdFence;
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+  value.div_ceil(alignment.max(1)) * alignment.max(1)
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct AdRayTracingDevice {
+  #[getset(get = "pub")]
+  ash_device: Arc<AdAshDevice>,
+  #[getset(get = "pub")]
+  as_device: khr::acceleration_structure::Device,
+  #[getset(get = "pub")]
+  rt_device: khr::ray_tracing_pipeline::Device,
+  #[getset(get_copy = "pub")]
+  shader_group_handle_size: u32,
+  #[getset(get_copy = "pub")]
+  shader_group_handle_alignment: u32,
+  #[getset(get_copy = "pub")]
+  shader_group_base_alignment: u32,
+  #[getset(get_copy = "pub")]
+  max_ray_recursion_depth: u32,
+  #[getset(get_copy = "pub")]
+  scratch_alignment: u32,
+}
+
+impl AdRayTracingDevice {
+  // Device extensions to create the AdAshDevice with, along with ray_tracing on
+  pub fn required_extensions() -> Vec<*const c_char> {
+    vec![
+      khr::acceleration_structure::NAME.as_ptr(),
+      khr::ray_tracing_pipeline::NAME.as_ptr(),
+      khr::deferred_host_operations::NAME.as_ptr(),
+    ]
+  }
+
+  pub fn new(ash_device: Arc<AdAshDevice>) -> Result<Self, String> {
+    if !ash_device.ray_tracing() {
+      return Err("device was made without ray tracing".to_string());
+    }
+    let ash_instance = ash_device.ash_instance().inner();
+    let mut rt_props = vk::PhysicalDeviceRayTracingPipelinePropertiesKHR::default();
+    let mut as_props = vk::PhysicalDeviceAccelerationStructurePropertiesKHR::default();
+    let mut props =
+      vk::PhysicalDeviceProperties2::default().push_next(&mut rt_props).push_next(&mut as_props);
+    unsafe { ash_instance.get_physical_device_properties2(ash_device.gpu(), &mut props) };
+    Ok(Self {
+      as_device: khr::acceleration_structure::Device::new(ash_instance, ash_device.inner()),
+      rt_device: khr::ray_tracing_pipeline::Device::new(ash_instance, ash_device.inner()),
+      shader_group_handle_size: rt_props.shader_group_handle_size,
+      shader_group_handle_alignment: rt_props.shader_group_handle_alignment,
+      shader_group_base_alignment: rt_props.shader_group_base_alignment,
+      max_ray_recursion_depth: rt_props.max_ray_recursion_depth,
+      scratch_alignment: as_props.min_acceleration_structure_scratch_offset_alignment,
+      ash_device,
+    })
+  }
+
+  // Scratch space has to start at an aligned address, the buffer is padded to get there
+  fn create_scratch_buffer(
+    &self,
+    allocator: Arc<Mutex<Allocator>>,
+    name: &str,
+    size: vk::DeviceSize,
+  ) -> Result<(AdBuffer, vk::DeviceAddress), String> {
+    let alignment = self.scratch_alignment as u64;
+    let buffer = AdBuffer::new(
+      self.ash_device.clone(),
+      allocator,
+      MemoryLocation::GpuOnly,
+      name,
+      vk::BufferCreateFlags::empty(),
+      size + alignment,
+      vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+    )?;
+    let address = align_up(buffer.device_address()?, alignment);
+    Ok((buffer, address))
+  }
+}
+
+// Triangles in buffers made with SHADER_DEVICE_ADDRESS and
+// ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR usage. Positions are 3 floats at the start of
+// each vertex, indices are u32
+pub struct AdBlasTriangles<'a> {
+  pub vertex_buffer: &'a AdBuffer,
+  pub vertex_stride: vk::DeviceSize,
+  pub vertex_count: u32,
+  pub index_buffer: &'a AdBuffer,
+  pub index_count: u32,
+  // Skips any hit shaders, what most level geometry wants
+  pub opaque: bool,
+}
+
+pub struct AdTlasInstance<'a> {
+  pub blas: &'a AdAccelerationStructure,
+  // Top 3 rows of the object to world matrix, row major
+  pub transform: [f32; 12],
+  // Shows up as gl_InstanceCustomIndexEXT, only the low 24 bits are kept
+  pub custom_index: u32,
+  // Rays only hit instances sharing a bit with their cull mask
+  pub mask: u8,
+  pub hit_group_offset: u32,
+  pub flags: vk::GeometryInstanceFlagsKHR,
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct AdAccelerationStructure {
+  #[getset(get = "pub")]
+  rt_device: Arc<AdRayTracingDevice>,
+  #[getset(get_copy = "pub")]
+  inner: vk::AccelerationStructureKHR,
+  #[getset(get_copy = "pub")]
+  level: vk::AccelerationStructureTypeKHR,
+  #[getset(get_copy = "pub")]
+  device_address: vk::DeviceAddress,
+  #[getset(get = "pub")]
+  name: String,
+  #[getset(get = "pub")]
+  buffer: AdBuffer,
+  // Only top levels keep these, they get rebuilt every time the instances move
+  #[getset(get_copy = "pub")]
+  max_instances: u32,
+  instance_buffer: Option<AdBuffer>,
+  scratch: Option<(AdBuffer, vk::DeviceAddress)>,
+}
+
+impl AdAccelerationStructure {
+  fn new(
+    rt_device: Arc<AdRayTracingDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    name: &str,
+    level: vk::AccelerationStructureTypeKHR,
+    size: vk::DeviceSize,
+  ) -> Result<Self, String> {
+    let buffer = AdBuffer::new(
+      rt_device.ash_device.clone(),
+      allocator,
+      MemoryLocation::GpuOnly,
+      &format!("{name}_storage"),
+      vk::BufferCreateFlags::empty(),
+      size,
+      vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+        | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+    )?;
+    let create_info =
+      vk::AccelerationStructureCreateInfoKHR::default().buffer(buffer.inner()).size(size).ty(level);
+    unsafe {
+      let inner = rt_device
+        .as_device
+        .create_acceleration_structure(&create_info, None)
+        .map_err(|e| format!("at creating vk acceleration structure {name}: {e}"))?;
+      let device_address = rt_device.as_device.get_acceleration_structure_device_address(
+        &vk::AccelerationStructureDeviceAddressInfoKHR::default().acceleration_structure(inner),
+      );
+      Ok(Self {
+        rt_device,
+        inner,
+        level,
+        device_address,
+        name: name.to_string(),
+        buffer,
+        max_instances: 0,
+        instance_buffer: None,
+        scratch: None,
+      })
+    }
+  }
+
+  fn build_sizes(
+    rt_device: &AdRayTracingDevice,
+    build_info: &vk::AccelerationStructureBuildGeometryInfoKHR,
+    max_primitive_counts: &[u32],
+  ) -> vk::AccelerationStructureBuildSizesInfoKHR<'static> {
+    let mut size_info = vk::AccelerationStructureBuildSizesInfoKHR::default();
+    unsafe {
+      rt_device.as_device.get_acceleration_structure_build_sizes(
+        vk::AccelerationStructureBuildTypeKHR::DEVICE,
+        build_info,
+        max_primitive_counts,
+        &mut size_info,
+      );
+    }
+    size_info
+  }
+
+  // Builds on the queue of cmd_buffer and waits for it, the geometry buffers can go after
+  pub fn build_blas(
+    rt_device: Arc<AdRayTracingDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_buffer: &AdCommandBuffer,
+    name: &str,
+    triangles: &[AdBlasTriangles],
+  ) -> Result<Self, String> {
+    let mut geometries = vec![];
+    for t in triangles {
+      let triangles_data = vk::AccelerationStructureGeometryTrianglesDataKHR::default()
+        .vertex_format(vk::Format::R32G32B32_SFLOAT)
+        .vertex_data(vk::DeviceOrHostAddressConstKHR {
+          device_address: t.vertex_buffer.device_address()?,
+        })
+        .vertex_stride(t.vertex_stride)
+        .max_vertex(t.vertex_count.saturating_sub(1))
+        .index_type(vk::IndexType::UINT32)
+        .index_data(vk::DeviceOrHostAddressConstKHR {
+          device_address: t.index_buffer.device_address()?,
+        });
+      geometries.push(
+        vk::AccelerationStructureGeometryKHR::default()
+          .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
+          .geometry(vk::AccelerationStructureGeometryDataKHR { triangles: triangles_data })
+          .flags(match t.opaque {
+            true => vk::GeometryFlagsKHR::OPAQUE,
+            false => vk::GeometryFlagsKHR::empty(),
+          }),
+      );
+    }
+    let primitive_counts = triangles.iter().map(|t| t.index_count / 3).collect::<Vec<_>>();
+    let range_infos = primitive_counts
+      .iter()
+      .map(|count| vk::AccelerationStructureBuildRangeInfoKHR::default().primitive_count(*count))
+      .collect::<Vec<_>>();
+
+    let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+      .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
+      .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+      .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+      .geometries(&geometries);
+    let size_info = Self::build_sizes(&rt_device, &build_info, &primitive_counts);
+    let blas = Self::new(
+      rt_device.clone(),
+      allocator.clone(),
+      name,
+      vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+      size_info.acceleration_structure_size,
+    )?;
+    let (_scratch_buffer, scratch_address) = rt_device.create_scratch_buffer(
+      allocator,
+      &format!("{name}_scratch"),
+      size_info.build_scratch_size,
+    )?;
+    build_info = build_info
+      .dst_acceleration_structure(blas.inner)
+      .scratch_data(vk::DeviceOrHostAddressKHR { device_address: scratch_address });
+
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+    unsafe {
+      rt_device.as_device.cmd_build_acceleration_structures(
+        cmd_buffer.inner(),
+        &[build_info],
+        &[&range_infos],
+      );
+    }
+    cmd_buffer.end()?;
+    let tmp_fence = AdFence::new(rt_device.ash_device.clone(), vk::FenceCreateFlags::default())?;
+    cmd_buffer.submit(&[], &[], Some(&tmp_fence))?;
+    tmp_fence.wait(999999999)?;
+    Ok(blas)
+  }
+
+  fn tlas_geometry(
+    instance_address: vk::DeviceAddress,
+  ) -> vk::AccelerationStructureGeometryKHR<'static> {
+    vk::AccelerationStructureGeometryKHR::default()
+      .geometry_type(vk::GeometryTypeKHR::INSTANCES)
+      .geometry(vk::AccelerationStructureGeometryDataKHR {
+        instances: vk::AccelerationStructureGeometryInstancesDataKHR::default()
+          .data(vk::DeviceOrHostAddressConstKHR { device_address: instance_address }),
+      })
+  }
+
+  // Empty until cmd_build_tlas. Rebuilding rewrites the instance buffer, so keep one per frame in
+  // flight
+  pub fn new_tlas(
+    rt_device: Arc<AdRayTracingDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    name: &str,
+    max_instances: u32,
+  ) -> Result<Self, String> {
+    let max_instances = max_instances.max(1);
+    let instance_buffer = AdBuffer::new(
+      rt_device.ash_device.clone(),
+      allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      &format!("{name}_instances"),
+      vk::BufferCreateFlags::empty(),
+      (size_of::<vk::AccelerationStructureInstanceKHR>() * max_instances as usize) as u64,
+      vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+        | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+    )?;
+    let geometries = [Self::tlas_geometry(instance_buffer.device_address()?)];
+    let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+      .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
+      .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+      .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+      .geometries(&geometries);
+    let size_info = Self::build_sizes(&rt_device, &build_info, &[max_instances]);
+    let mut tlas = Self::new(
+      rt_device.clone(),
+      allocator.clone(),
+      name,
+      vk::AccelerationStructureTypeKHR::TOP_LEVEL,
+      size_info.acceleration_structure_size,
+    )?;
+    tlas.scratch = Some(rt_device.create_scratch_buffer(
+      allocator,
+      &format!("{name}_scratch"),
+      size_info.build_scratch_size,
+    )?);
+    tlas.instance_buffer = Some(instance_buffer);
+    tlas.max_instances = max_instances;
+    Ok(tlas)
+  }
+
+  // Records a full rebuild and a barrier so ray queries and trace calls after it see the result
+  pub fn cmd_build_tlas(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    instances: &[AdTlasInstance],
+  ) -> Result<(), String> {
+    let (Some(instance_buffer), Some((_, scratch_address))) =
+      (&self.instance_buffer, &self.scratch)
+    else {
+      return Err(format!("acceleration structure {} isn't a top level", self.name));
+    };
+    if instances.len() > self.max_instances as usize {
+      return Err(format!(
+        "tlas {} holds {} instances, got {}",
+        self.name,
+        self.max_instances,
+        instances.len()
+      ));
+    }
+    let vk_instances = instances
+      .iter()
+      .map(|instance| vk::AccelerationStructureInstanceKHR {
+        transform: vk::TransformMatrixKHR { matrix: instance.transform },
+        instance_custom_index_and_mask: vk::Packed24_8::new(instance.custom_index, instance.mask),
+        instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
+          instance.hit_group_offset,
+          instance.flags.as_raw() as u8,
+        ),
+        acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+          device_handle: instance.blas.device_address,
+        },
+      })
+      .collect::<Vec<_>>();
+    instance_buffer.write_data(0, &vk_instances)?;
+
+    let geometries = [Self::tlas_geometry(instance_buffer.device_address()?)];
+    let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+      .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
+      .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+      .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+      .geometries(&geometries)
+      .dst_acceleration_structure(self.inner)
+      .scratch_data(vk::DeviceOrHostAddressKHR { device_address: *scratch_address });
+    let range_infos = [vk::AccelerationStructureBuildRangeInfoKHR::default()
+      .primitive_count(vk_instances.len() as u32)];
+    unsafe {
+      self.rt_device.as_device.cmd_build_acceleration_structures(
+        cmd_buffer.inner(),
+        &[build_info],
+        &[&range_infos],
+      );
+    }
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR,
+      vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR
+        | vk::PipelineStageFlags::FRAGMENT_SHADER
+        | vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR)
+        .dst_access_mask(vk::AccessFlags::ACCELERATION_STRUCTURE_READ_KHR)],
+      &[],
+      &[],
+    );
+    Ok(())
+  }
+
+  // new_tlas and cmd_build_tlas in one, waiting for the build like build_blas
+  pub fn build_tlas(
+    rt_device: Arc<AdRayTracingDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_buffer: &AdCommandBuffer,
+    name: &str,
+    instances: &[AdTlasInstance],
+  ) -> Result<Self, String> {
+    let tlas = Self::new_tlas(rt_device.clone(), allocator, name, instances.len() as u32)?;
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+    tlas.cmd_build_tlas(cmd_buffer, instances)?;
+    cmd_buffer.end()?;
+    let tmp_fence = AdFence::new(rt_device.ash_device.clone(), vk::FenceCreateFlags::default())?;
+    cmd_buffer.submit(&[], &[], Some(&tmp_fence))?;
+    tmp_fence.wait(999999999)?;
+    Ok(tlas)
+  }
+
+  // AdDescriptorBinding has no acceleration structure kind, so the binding is written here. Keep
+  // this alive as long as the set is used
+  pub fn write_to_dset(&self, dset: &AdDescriptorSet, binding: u32) {
+    let handles = [self.inner];
+    let mut as_write =
+      vk::WriteDescriptorSetAccelerationStructureKHR::default().acceleration_structures(&handles);
+    let write_info = vk::WriteDescriptorSet::default()
+      .dst_set(dset.inner())
+      .dst_binding(binding)
+      .descriptor_type(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
+      .descriptor_count(1)
+      .push_next(&mut as_write);
+    unsafe {
+      self.rt_device.ash_device.inner().update_descriptor_sets(&[write_info], &[]);
+    }
+  }
+}
+
+impl Drop for AdAccelerationStructure {
+  fn drop(&mut self) {
+    unsafe {
+      self.rt_device.as_device.destroy_acceleration_structure(self.inner, None);
+    }
+  }
+}
+
+pub struct AdHitGroup<'a> {
+  pub closest_hit: Option<&'a [u8]>,
+  pub any_hit: Option<&'a [u8]>,
+}
+
+// Spirv of each stage. Miss shaders and hit groups are picked by their index in trace calls
+pub struct AdRayTracingShaders<'a> {
+  pub raygen: &'a [u8],
+  pub miss: Vec<&'a [u8]>,
+  pub hit_groups: Vec<AdHitGroup<'a>>,
+}
+
+// Handles of each shader group laid out the way trace calls read them: raygen, then miss shaders,
+// then hit groups, each region starting at the base alignment
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct AdShaderBindingTable {
+  #[getset(get = "pub")]
+  buffer: AdBuffer,
+  #[getset(get_copy = "pub")]
+  raygen_region: vk::StridedDeviceAddressRegionKHR,
+  #[getset(get_copy = "pub")]
+  miss_region: vk::StridedDeviceAddressRegionKHR,
+  #[getset(get_copy = "pub")]
+  hit_region: vk::StridedDeviceAddressRegionKHR,
+}
+
+impl AdShaderBindingTable {
+  fn new(
+    rt_device: &AdRayTracingDevice,
+    allocator: Arc<Mutex<Allocator>>,
+    name: &str,
+    pipeline: vk::Pipeline,
+    miss_count: u32,
+    hit_count: u32,
+  ) -> Result<Self, String> {
+    let handle_size = rt_device.shader_group_handle_size as u64;
+    let base_alignment = rt_device.shader_group_base_alignment as u64;
+    let group_count = 1 + miss_count + hit_count;
+    let handles = unsafe {
+      rt_device
+        .rt_device
+        .get_ray_tracing_shader_group_handles(
+          pipeline,
+          0,
+          group_count,
+          (handle_size * group_count as u64) as usize,
+        )
+        .map_err(|e| format!("at getting shader group handles: {e}"))?
+    };
+
+    let stride = align_up(handle_size, rt_device.shader_group_handle_alignment as u64);
+    let raygen_size = align_up(stride, base_alignment);
+    let miss_size = align_up(stride * miss_count as u64, base_alignment);
+    let hit_size = align_up(stride * hit_count as u64, base_alignment);
+    let buffer = AdBuffer::new(
+      rt_device.ash_device.clone(),
+      allocator,
+      MemoryLocation::CpuToGpu,
+      name,
+      vk::BufferCreateFlags::empty(),
+      raygen_size + miss_size + hit_size + base_alignment,
+      vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+    )?;
+    let buffer_address = buffer.device_address()?;
+    let start = align_up(buffer_address, base_alignment);
+
+    let mut table = vec![0u8; (raygen_size + miss_size + hit_size) as usize];
+    let region_starts = [0, raygen_size, raygen_size + miss_size];
+    let group_regions = (0..group_count).map(|group| match group {
+      0 => (region_starts[0], 0),
+      g if g <= miss_count => (region_starts[1], g - 1),
+      g => (region_starts[2], g - 1 - miss_count),
+    });
+    for (group, (region_start, idx_in_region)) in group_regions.enumerate() {
+      let dst = (region_start + idx_in_region as u64 * stride) as usize;
+      let src = group * handle_size as usize;
+      table[dst..dst + handle_size as usize]
+        .copy_from_slice(&handles[src..src + handle_size as usize]);
+    }
+    buffer.write_data((start - buffer_address) as usize, &table)?;
+
+    let region = |offset: u64, stride: u64, size: u64| match size {
+      0 => vk::StridedDeviceAddressRegionKHR::default(),
+      _ => vk::StridedDeviceAddressRegionKHR::default()
+        .device_address(start + offset)
+        .stride(stride)
+        .size(size),
+    };
+    Ok(Self {
+      raygen_region: region(region_starts[0], raygen_size, raygen_size),
+      miss_region: region(region_starts[1], stride, miss_size),
+      hit_region: region(region_starts[2], stride, hit_size),
+      buffer,
+    })
+  }
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct AdRayTracingPipeline {
+  rt_device: Arc<AdRayTracingDevice>,
+  #[getset(get_copy = "pub")]
+  layout: vk::PipelineLayout,
+  #[getset(get_copy = "pub")]
+  inner: vk::Pipeline,
+  #[getset(get = "pub")]
+  sbt: AdShaderBindingTable,
+}
+
+impl AdRayTracingPipeline {
+  // Push constants are visible to every stage
+  pub fn new(
+    rt_device: Arc<AdRayTracingDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    name: &str,
+    shaders: &AdRayTracingShaders,
+    set_layouts: &[&AdDescriptorSetLayout],
+    push_constant_len: u32,
+    max_recursion_depth: u32,
+  ) -> Result<Self, String> {
+    if max_recursion_depth > rt_device.max_ray_recursion_depth {
+      return Err(format!(
+        "ray recursion depth {max_recursion_depth} is over the device limit {}",
+        rt_device.max_ray_recursion_depth
+      ));
+    }
+    let ash_device = rt_device.ash_device.clone();
+    let mut modules = vec![];
+    let mut stages = vec![];
+    let mut add_stage = |spv: &[u8], stage: vk::ShaderStageFlags| -> Result<u32, String> {
+      let module = AdShaderModule::from_bytes(ash_device.clone(), spv)?;
+      stages.push(
+        vk::PipelineShaderStageCreateInfo::default()
+          .stage(stage)
+          .name(c"main")
+          .module(module.inner()),
+      );
+      modules.push(module);
+      Ok(stages.len() as u32 - 1)
+    };
+
+    let general_group = |stage_idx: u32| {
+      vk::RayTracingShaderGroupCreateInfoKHR::default()
+        .ty(vk::RayTracingShaderGroupTypeKHR::GENERAL)
+        .general_shader(stage_idx)
+        .closest_hit_shader(vk::SHADER_UNUSED_KHR)
+        .any_hit_shader(vk::SHADER_UNUSED_KHR)
+        .intersection_shader(vk::SHADER_UNUSED_KHR)
+    };
+    let mut groups =
+      vec![general_group(add_stage(shaders.raygen, vk::ShaderStageFlags::RAYGEN_KHR)?)];
+    for miss in shaders.miss.iter() {
+      groups.push(general_group(add_stage(miss, vk::ShaderStageFlags::MISS_KHR)?));
+    }
+    for hit_group in shaders.hit_groups.iter() {
+      let closest_hit = match hit_group.closest_hit {
+        Some(spv) => add_stage(spv, vk::ShaderStageFlags::CLOSEST_HIT_KHR)?,
+        None => vk::SHADER_UNUSED_KHR,
+      };
+      let any_hit = match hit_group.any_hit {
+        Some(spv) => add_stage(spv, vk::ShaderStageFlags::ANY_HIT_KHR)?,
+        None => vk::SHADER_UNUSED_KHR,
+      };
+      groups.push(
+        vk::RayTracingShaderGroupCreateInfoKHR::default()
+          .ty(vk::RayTracingShaderGroupTypeKHR::TRIANGLES_HIT_GROUP)
+          .general_shader(vk::SHADER_UNUSED_KHR)
+          .closest_hit_shader(closest_hit)
+          .any_hit_shader(any_hit)
+          .intersection_shader(vk::SHADER_UNUSED_KHR),
+      );
+    }
+
+    let set_layouts_vec = set_layouts.iter().map(|x| x.inner()).collect::<Vec<_>>();
+    let mut push_layouts_info = vec![];
+    if push_constant_len != 0 {
+      push_layouts_info.push(
+        vk::PushConstantRange::default().offset(0).size(push_constant_len).stage_flags(
+          vk::ShaderStageFlags::RAYGEN_KHR
+            | vk::ShaderStageFlags::MISS_KHR
+            | vk::ShaderStageFlags::CLOSEST_HIT_KHR
+            | vk::ShaderStageFlags::ANY_HIT_KHR,
+        ),
+      );
+    }
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
+      .set_layouts(&set_layouts_vec)
+      .push_constant_ranges(&push_layouts_info);
+    let pipeline_layout = unsafe {
+      ash_device
+        .inner()
+        .create_pipeline_layout(&pipeline_layout_info, None)
+        .map_err(|e| format!("at creating vk ray tracing pipeline layout: {e}"))?
+    };
+
+    let pipeline_create_info = vk::RayTracingPipelineCreateInfoKHR::default()
+      .stages(&stages)
+      .groups(&groups)
+      .max_pipeline_ray_recursion_depth(max_recursion_depth)
+      .layout(pipeline_layout);
+    let pipeline = unsafe {
+      rt_device.rt_device.create_ray_tracing_pipelines(
+        vk::DeferredOperationKHR::null(),
+        rt_device.ash_device.pipeline_cache(),
+        &[pipeline_create_info],
+        None,
+      )
+    };
+    for module in modules.iter_mut() {
+      module.manual_destroy();
+    }
+    let pipeline = match pipeline {
+      Ok(mut pipelines) => pipelines.swap_remove(0),
+      Err((_, e)) => {
+        unsafe { ash_device.inner().destroy_pipeline_layout(pipeline_layout, None) };
+        return Err(format!("at creating vk ray tracing pipeline: {e}"));
+      }
+    };
+
+    let sbt = AdShaderBindingTable::new(
+      &rt_device,
+      allocator,
+      &format!("{name}_sbt"),
+      pipeline,
+      shaders.miss.len() as u32,
+      shaders.hit_groups.len() as u32,
+    );
+    let sbt = match sbt {
+      Ok(sbt) => sbt,
+      Err(e) => {
+        unsafe {
+          ash_device.inner().destroy_pipeline(pipeline, None);
+          ash_device.inner().destroy_pipeline_layout(pipeline_layout, None);
+        }
+        return Err(e);
+      }
+    };
+    Ok(Self { rt_device, layout: pipeline_layout, inner: pipeline, sbt })
+  }
+
+  // The pipeline and its descriptor sets have to be bound at RAY_TRACING_KHR first
+  pub fn cmd_trace_rays(&self, cmd_buffer: &AdCommandBuffer, width: u32, height: u32, depth: u32) {
+    unsafe {
+      self.rt_device.rt_device.cmd_trace_rays(
+        cmd_buffer.inner(),
+        &self.sbt.raygen_region,
+        &self.sbt.miss_region,
+        &self.sbt.hit_region,
+        &vk::StridedDeviceAddressRegionKHR::default(),
+        width,
+        height,
+        depth,
+      );
+    }
+  }
+}
+
+impl Drop for AdRayTracingPipeline {
+  fn drop(&mut self) {
+    unsafe {
+      self.rt_device.ash_device.inner().destroy_pipeline(self.inner, None);
+      self.rt_device.ash_device.inner().destroy_pipeline_layout(self.layout, None);
+    }
+  }
+}
@@ -4,7 +4,7 @@ use ash_context::{
   ash::{khr, vk},
   getset, AdAshDevice, AdAshInstance,
 };
-use ash_queue_wrappers::{AdCommandBuffer, AdQueue};
+use ash_queue_wrappers::{AdCommandBuffer, AdQueue, MAX_SUBMIT_SEMAPHORES};
 use ash_sync_wrappers::{AdFence, AdSemaphore};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
@@ -130,6 +130,8 @@ impl Drop for AdSurface {
 
 pub struct AdSwapchainDevice {
   inner: khr::swapchain::Device,
+  // Only when the device was made with present wait on
+  present_wait: Option<khr::present_wait::Device>,
   ash_device: Arc<AdAshDevice>,
 }
 
@@ -137,7 +139,10 @@ impl AdSwapchainDevice {
   pub fn new(ash_device: Arc<AdAshDevice>) -> Self {
     let swapchain_device =
       khr::swapchain::Device::new(ash_device.ash_instance().inner(), ash_device.inner());
-    Self { inner: swapchain_device, ash_device }
+    let present_wait = ash_device.present_wait().then(|| {
+      khr::present_wait::Device::new(ash_device.ash_instance().inner(), ash_device.inner())
+    });
+    Self { inner: swapchain_device, present_wait, ash_device }
   }
 }
 
@@ -160,6 +165,9 @@ pub struct AdSwapchain {
   present_mode: vk::PresentModeKHR,
   #[getset(get_copy = "pub")]
   initialized: bool,
+  // Id of the last present, counting from 1 for each vk swapchain
+  #[getset(get_copy = "pub")]
+  last_present_id: u64,
 }
 
 impl AdSwapchain {
@@ -214,6 +222,7 @@ impl AdSwapchain {
         pre_transform,
         present_mode,
         initialized: false,
+        last_present_id: 0,
       })
     }
   }
@@ -267,14 +276,21 @@ impl AdSwapchain {
       self.resolution = surface_caps.current_extent;
     }
     self.initialized = false;
+    self.last_present_id = 0;
     Ok(())
   }
 
+  pub fn get_image_count(&self) -> usize {
+    self.images.len()
+  }
+
+  // None when the swapchain is out of date, the semaphore/fence are not signalled in that case.
+  // Some((idx, true)) is a suboptimal acquire: the image is usable and sync objects are signalled
   pub fn acquire_next_image(
     &mut self,
     semaphore: Option<&AdSemaphore>,
     fence: Option<&AdFence>,
-  ) -> Result<(u32, bool), String> {
+  ) -> Result<Option<(u32, bool)>, String> {
     unsafe {
       match self.swapchain_device.inner.acquire_next_image(
         self.inner,
@@ -282,10 +298,10 @@ impl AdSwapchain {
         semaphore.map(|x| x.inner()).unwrap_or(vk::Semaphore::null()),
         fence.map(|x| x.inner()).unwrap_or(vk::Fence::null()),
       ) {
-        Ok((idx, refresh_needed)) => Ok((idx, refresh_needed)),
+        Ok((idx, refresh_needed)) => Ok(Some((idx, refresh_needed))),
         Err(e) => {
           if e == vk::Result::ERROR_OUT_OF_DATE_KHR {
-            return Ok((0, true));
+            return Ok(None);
           }
           Err(format!("at vk acquire image: {e}"))
         }
@@ -294,26 +310,78 @@ impl AdSwapchain {
   }
 
   pub fn present_image(
-    &self,
+    &mut self,
     image_idx: u32,
-    wait_semaphores: Vec<&AdSemaphore>,
+    wait_semaphores: &[&AdSemaphore],
   ) -> Result<(), String> {
+    if wait_semaphores.len() > MAX_SUBMIT_SEMAPHORES {
+      return Err(format!("at presenting: over {MAX_SUBMIT_SEMAPHORES} semaphores"));
+    }
+    let mut wait_handles = [vk::Semaphore::null(); MAX_SUBMIT_SEMAPHORES];
+    for (handle, semaphore) in wait_handles.iter_mut().zip(wait_semaphores) {
+      *handle = semaphore.inner();
+    }
+    let wait_semaphores = &wait_handles[..wait_semaphores.len()];
+    let swapchains = [self.inner];
+    let image_indices = [image_idx];
+    let present_ids = [self.last_present_id + 1];
+    let mut present_id_info = vk::PresentIdKHR::default().present_ids(&present_ids);
+    let mut present_info = vk::PresentInfoKHR::default()
+      .swapchains(&swapchains)
+      .wait_semaphores(wait_semaphores)
+      .image_indices(&image_indices);
+    if self.swapchain_device.present_wait.is_some() {
+      present_info = present_info.push_next(&mut present_id_info);
+    }
     unsafe {
       self
         .swapchain_device
         .inner
-        .queue_present(
-          self.present_queue.inner(),
-          &vk::PresentInfoKHR::default()
-            .swapchains(&[self.inner])
-            .wait_semaphores(&wait_semaphores.iter().map(|x| x.inner()).collect::<Vec<_>>())
-            .image_indices(&[image_idx]),
-        )
+        .queue_present(self.present_queue.inner(), &present_info)
         .map_err(|e| format!("at vk present: {e}"))?;
     }
+    self.last_present_id += 1;
     Ok(())
   }
 
+  // Blocks until no more than `queued` presents are waiting to be shown. False when the device
+  // can't wait on presents, nothing is waited on then
+  pub fn wait_for_presents(&self, queued: u64) -> Result<bool, String> {
+    let Some(present_wait) = &self.swapchain_device.present_wait else {
+      return Ok(false);
+    };
+    if self.last_present_id <= queued {
+      return Ok(true);
+    }
+    let wait_res = unsafe {
+      present_wait.wait_for_present(self.inner, self.last_present_id - queued, 999999999)
+    };
+    match wait_res {
+      // Out of date swapchains are refreshed on the next acquire, a timeout just lets the frame
+      // go ahead
+      Ok(())
+      | Err(vk::Result::SUBOPTIMAL_KHR)
+      | Err(vk::Result::ERROR_OUT_OF_DATE_KHR)
+      | Err(vk::Result::TIMEOUT) => Ok(true),
+      Err(e) => Err(format!("at vk present wait: {e}")),
+    }
+  }
+
+  // Whether the present got to the display, without waiting for it. None when the device can't
+  // tell
+  pub fn is_present_shown(&self, present_id: u64) -> Result<Option<bool>, String> {
+    let Some(present_wait) = &self.swapchain_device.present_wait else {
+      return Ok(None);
+    };
+    match unsafe { present_wait.wait_for_present(self.inner, present_id, 0) } {
+      Ok(()) | Err(vk::Result::SUBOPTIMAL_KHR) => Ok(Some(true)),
+      Err(vk::Result::TIMEOUT) => Ok(Some(false)),
+      // Won't be shown now, the swapchain gets refreshed
+      Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(None),
+      Err(e) => Err(format!("at vk present wait: {e}")),
+    }
+  }
+
   pub fn initialize(&mut self, cmd_buffer: &AdCommandBuffer) -> Result<(), String> {
     if !self.initialized {
       cmd_buffer.begin(vk::CommandBufferUsageFlags::default())?;
@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::sync::{
+  atomic::{AtomicU64, Ordering},
+  Arc,
+};
 
 use ash_context::{ash::vk, getset, AdAshDevice};
 
@@ -37,6 +40,8 @@ pub struct AdFence {
   ash_device: Arc<AdAshDevice>,
   #[getset(get_copy = "pub")]
   inner: vk::Fence,
+  // Bumped on every reset, tells submits sharing the fence apart
+  resets: AtomicU64,
 }
 
 impl AdFence {
@@ -46,10 +51,22 @@ impl AdFence {
         .inner()
         .create_fence(&vk::FenceCreateInfo::default().flags(flags), None)
         .map_err(|e| format!("at create vk semaphore: {e}"))
-        .map(|vk_fence| Self { ash_device, inner: vk_fence })
+        .map(|vk_fence| Self { ash_device, inner: vk_fence, resets: AtomicU64::new(0) })
     }
   }
 
+  pub fn reset_count(&self) -> u64 {
+    self.resets.load(Ordering::Acquire)
+  }
+
+  // Whether the submit made after the given reset is done. Resets since then mean it was waited on
+  pub fn is_done_since(&self, reset_count: u64) -> Result<bool, String> {
+    if self.reset_count() != reset_count {
+      return Ok(true);
+    }
+    self.is_signalled()
+  }
+
   pub fn wait(&self, timeout: u64) -> Result<(), String> {
     unsafe {
       self
@@ -60,14 +77,27 @@ impl AdFence {
     }
   }
 
+  // Without waiting
+  pub fn is_signalled(&self) -> Result<bool, String> {
+    unsafe {
+      self
+        .ash_device
+        .inner()
+        .get_fence_status(self.inner)
+        .map_err(|e| format!("at getting vk fence status: {e}"))
+    }
+  }
+
   pub fn reset(&self) -> Result<(), String> {
     unsafe {
       self
         .ash_device
         .inner()
         .reset_fences(&[self.inner])
-        .map_err(|e| format!("at vk fence reset: {e}"))
+        .map_err(|e| format!("at vk fence reset: {e}"))?;
     }
+    self.resets.fetch_add(1, Ordering::AcqRel);
+    Ok(())
   }
 
   pub fn wait_and_reset(&self, timeout: u64) -> Result<(), String> {
@@ -0,0 +1,486 @@
+use std::{
+  collections::HashMap,
+  ops::Deref,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  },
+};
+
+use ash_context::{ash::vk, gpu_allocator, gpu_allocator::vulkan::Allocator, AdAshDevice};
+use ash_data_wrappers::{
+  AdBuffer, AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout,
+  AdImage, AdImageView,
+};
+use ash_queue_wrappers::{AdCommandBuffer, AdCommandPool, AdQueue};
+use ash_render_wrappers::{AdComputePipeline, AdPipeline};
+use ash_sync_wrappers::AdFence;
+use graphics_backend::{
+  BackendBuffer, BackendImage, BackendPipeline, Binding, BufferDesc, CommandList, Extent2D, Format,
+  GraphicsDevice, ImageDesc, MemoryLocation,
+};
+
+// Sets the first descriptor pool holds, it grows from there
+const BACKEND_DSET_POOL_SETS: u32 = 16;
+
+fn vk_format(format: Format) -> vk::Format {
+  match format {
+    Format::Rgba8Unorm => vk::Format::R8G8B8A8_UNORM,
+    Format::Rgba8Srgb => vk::Format::R8G8B8A8_SRGB,
+    Format::Rgba16Float => vk::Format::R16G16B16A16_SFLOAT,
+    Format::Rgba32Float => vk::Format::R32G32B32A32_SFLOAT,
+    Format::R32Float => vk::Format::R32_SFLOAT,
+    Format::R32Uint => vk::Format::R32_UINT,
+  }
+}
+
+// The backend format of a vulkan one, for images made outside the backend
+pub fn backend_format(format: vk::Format) -> Result<Format, String> {
+  match format {
+    vk::Format::R8G8B8A8_UNORM => Ok(Format::Rgba8Unorm),
+    vk::Format::R8G8B8A8_SRGB => Ok(Format::Rgba8Srgb),
+    vk::Format::R16G16B16A16_SFLOAT => Ok(Format::Rgba16Float),
+    vk::Format::R32G32B32A32_SFLOAT => Ok(Format::Rgba32Float),
+    vk::Format::R32_SFLOAT => Ok(Format::R32Float),
+    vk::Format::R32_UINT => Ok(Format::R32Uint),
+    _ => Err(format!("no backend format for {format:?}")),
+  }
+}
+
+fn vk_memory_location(location: MemoryLocation) -> gpu_allocator::MemoryLocation {
+  match location {
+    MemoryLocation::GpuOnly => gpu_allocator::MemoryLocation::GpuOnly,
+    MemoryLocation::CpuToGpu => gpu_allocator::MemoryLocation::CpuToGpu,
+    MemoryLocation::GpuToCpu => gpu_allocator::MemoryLocation::GpuToCpu,
+  }
+}
+
+fn color_range() -> vk::ImageSubresourceRange {
+  vk::ImageSubresourceRange::default()
+    .aspect_mask(vk::ImageAspectFlags::COLOR)
+    .base_mip_level(0)
+    .level_count(1)
+    .base_array_layer(0)
+    .layer_count(1)
+}
+
+fn check_range(buffer: &AdBuffer, offset: u64, len: u64) -> Result<(), String> {
+  match offset.checked_add(len) {
+    Some(end) if end <= buffer.size() => Ok(()),
+    _ => Err(format!("{len} bytes at {offset} don't fit in buffer {}", buffer.name())),
+  }
+}
+
+#[derive(Clone)]
+pub struct VulkanBuffer {
+  inner: Arc<AdBuffer>,
+  location: MemoryLocation,
+}
+
+impl VulkanBuffer {
+  // For passing to the vulkan wrappers directly
+  pub fn inner(&self) -> &Arc<AdBuffer> {
+    &self.inner
+  }
+}
+
+impl BackendBuffer for VulkanBuffer {
+  fn name(&self) -> &str {
+    self.inner.name()
+  }
+
+  fn size(&self) -> u64 {
+    self.inner.size()
+  }
+
+  fn write(&self, offset: u64, bytes: &[u8]) -> Result<(), String> {
+    if !self.location.is_host_visible() {
+      return Err(format!("buffer {} isn't host visible", self.inner.name()));
+    }
+    self.inner.write_data(offset as usize, bytes)
+  }
+
+  fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+    if !self.location.is_host_visible() {
+      return Err(format!("buffer {} isn't host visible", self.inner.name()));
+    }
+    self.inner.read_data(offset as usize, len as usize)
+  }
+}
+
+// Always in GENERAL once a command list has used it
+#[derive(Clone)]
+pub struct VulkanImage {
+  view: Arc<AdImageView>,
+  format: Format,
+  // What it's in until then, its contents are kept unless it's UNDEFINED
+  first_layout: vk::ImageLayout,
+  in_general: Arc<AtomicBool>,
+}
+
+impl VulkanImage {
+  // An image made outside the backend, like a framebuffer attachment, that is in layout now.
+  // Moving it on from GENERAL after a command list has used it is up to the caller
+  pub fn from_view(view: Arc<AdImageView>, layout: vk::ImageLayout) -> Result<Self, String> {
+    let format = backend_format(view.image().format())?;
+    Ok(Self { view, format, first_layout: layout, in_general: Arc::new(AtomicBool::new(false)) })
+  }
+
+  pub fn view(&self) -> &Arc<AdImageView> {
+    &self.view
+  }
+
+  fn ensure_general(&self, cmd_buffer: &AdCommandBuffer) {
+    if self.in_general.swap(true, Ordering::AcqRel) {
+      return;
+    }
+    let (src_stage, src_access) = match self.first_layout {
+      vk::ImageLayout::UNDEFINED => (vk::PipelineStageFlags::TOP_OF_PIPE, vk::AccessFlags::NONE),
+      _ => (vk::PipelineStageFlags::ALL_COMMANDS, vk::AccessFlags::MEMORY_WRITE),
+    };
+    self.view.transition_to_general(cmd_buffer, self.first_layout, src_stage, src_access);
+  }
+}
+
+impl BackendImage for VulkanImage {
+  fn name(&self) -> &str {
+    self.view.image().name()
+  }
+
+  fn format(&self) -> Format {
+    self.format
+  }
+
+  fn extent(&self) -> Extent2D {
+    let resolution = self.view.image().resolution();
+    Extent2D { width: resolution.width, height: resolution.height }
+  }
+}
+
+pub struct VulkanPipeline {
+  name: String,
+  inner: AdComputePipeline,
+  // Set 0 of the shader, the one dispatch bindings go to
+  set_layout: Option<Arc<AdDescriptorSetLayout>>,
+}
+
+impl VulkanPipeline {
+  pub fn inner(&self) -> &AdComputePipeline {
+    &self.inner
+  }
+}
+
+impl BackendPipeline for VulkanPipeline {
+  fn name(&self) -> &str {
+    &self.name
+  }
+}
+
+// What a command list records into, its own or one the renderer submits
+enum Recording<'a> {
+  Owned(AdCommandBuffer),
+  Borrowed(&'a AdCommandBuffer),
+}
+
+impl Deref for Recording<'_> {
+  type Target = AdCommandBuffer;
+
+  fn deref(&self) -> &AdCommandBuffer {
+    match self {
+      Self::Owned(cmd_buffer) => cmd_buffer,
+      Self::Borrowed(cmd_buffer) => cmd_buffer,
+    }
+  }
+}
+
+// Descriptor sets and buffers recorded commands use, kept until the gpu is done with them
+pub struct RecordedResources {
+  _dsets: Vec<AdDescriptorSet>,
+  _buffers: Vec<Arc<AdBuffer>>,
+}
+
+pub struct VulkanCommandList<'a> {
+  cmd_buffer: Recording<'a>,
+  dset_pools: Arc<AdDescriptorPoolManager>,
+  // Kept alive until the submit is waited on
+  dsets: Vec<AdDescriptorSet>,
+  buffers: Vec<Arc<AdBuffer>>,
+}
+
+impl VulkanCommandList<'_> {
+  // For lists from VulkanDevice::record_into, what has to outlive the submit of the command
+  // buffer they recorded into
+  pub fn finish(self) -> RecordedResources {
+    RecordedResources { _dsets: self.dsets, _buffers: self.buffers }
+  }
+}
+
+impl CommandList for VulkanCommandList<'_> {
+  type Buffer = VulkanBuffer;
+  type Image = VulkanImage;
+  type Pipeline = VulkanPipeline;
+
+  fn copy_buffer(
+    &mut self,
+    src: &VulkanBuffer,
+    src_offset: u64,
+    dst: &VulkanBuffer,
+    dst_offset: u64,
+    size: u64,
+  ) -> Result<(), String> {
+    check_range(&src.inner, src_offset, size)?;
+    check_range(&dst.inner, dst_offset, size)?;
+    self.cmd_buffer.copy_buffer_to_buffer_cmd(
+      src.inner.inner(),
+      dst.inner.inner(),
+      &[vk::BufferCopy { src_offset, dst_offset, size }],
+    );
+    self.buffers.extend([src.inner.clone(), dst.inner.clone()]);
+    Ok(())
+  }
+
+  fn copy_buffer_to_image(&mut self, src: &VulkanBuffer, dst: &VulkanImage) -> Result<(), String> {
+    let extent = dst.extent();
+    let size = extent.width as u64 * extent.height as u64 * dst.format.bytes_per_pixel() as u64;
+    check_range(&src.inner, 0, size)?;
+    dst.ensure_general(&self.cmd_buffer);
+    self.cmd_buffer.copy_buffer_to_image(
+      src.inner.inner(),
+      dst.view.image().inner(),
+      vk::ImageLayout::GENERAL,
+      &[vk::BufferImageCopy::default()
+        .image_subresource(
+          vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .layer_count(1),
+        )
+        .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })],
+    );
+    self.buffers.push(src.inner.clone());
+    Ok(())
+  }
+
+  fn clear_image(&mut self, image: &VulkanImage, color: [f32; 4]) -> Result<(), String> {
+    image.ensure_general(&self.cmd_buffer);
+    self.cmd_buffer.clear_color_image(
+      image.view.image().inner(),
+      vk::ImageLayout::GENERAL,
+      &vk::ClearColorValue { float32: color },
+      &[color_range()],
+    );
+    Ok(())
+  }
+
+  fn dispatch(
+    &mut self,
+    pipeline: &VulkanPipeline,
+    bindings: &[Binding<'_, VulkanBuffer, VulkanImage>],
+    push_constants: &[u8],
+    group_counts: [u32; 3],
+  ) -> Result<(), String> {
+    let dset = match &pipeline.set_layout {
+      Some(set_layout) => {
+        let mut descriptors = vec![];
+        for binding in bindings.iter() {
+          descriptors.push(match binding {
+            Binding::UniformBuffer(buffer) => {
+              AdDescriptorBinding::UniformBuffer(buffer.inner.clone())
+            }
+            Binding::StorageBuffer(buffer) => {
+              AdDescriptorBinding::StorageBuffer(buffer.inner.clone())
+            }
+            Binding::StorageImage(image) => {
+              image.ensure_general(&self.cmd_buffer);
+              AdDescriptorBinding::StorageImage((image.view.clone(), vk::ImageLayout::GENERAL))
+            }
+          });
+        }
+        let dset = self
+          .dset_pools
+          .allocate(&[(set_layout.clone(), descriptors)])
+          .map_err(|e| format!("at making bindings of {}: {e}", pipeline.name))?
+          .remove(0);
+        Some(dset)
+      }
+      None if bindings.is_empty() => None,
+      None => return Err(format!("pipeline {} takes no bindings", pipeline.name)),
+    };
+    self.cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, pipeline.inner.inner());
+    if let Some(dset) = dset {
+      self.cmd_buffer.bind_descriptor_sets(
+        vk::PipelineBindPoint::COMPUTE,
+        pipeline.inner.layout(),
+        &[dset.inner()],
+      );
+      self.dsets.push(dset);
+    }
+    if !push_constants.is_empty() {
+      self.cmd_buffer.set_push_constant_data(
+        pipeline.inner.layout(),
+        vk::ShaderStageFlags::COMPUTE,
+        push_constants,
+      );
+    }
+    let [x, y, z] = group_counts;
+    self.cmd_buffer.dispatch(x, y, z);
+    Ok(())
+  }
+
+  fn barrier(&mut self) {
+    self.cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::ALL_COMMANDS,
+      vk::PipelineStageFlags::ALL_COMMANDS,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+        .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE)],
+      &[],
+      &[],
+    );
+  }
+}
+
+// The vulkan wrappers behind the GraphicsDevice trait, submitting to one queue
+pub struct VulkanDevice {
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  cmd_pool: Arc<AdCommandPool>,
+  dset_pools: Arc<AdDescriptorPoolManager>,
+  fence: AdFence,
+}
+
+impl VulkanDevice {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    queue: Arc<AdQueue>,
+  ) -> Result<Self, String> {
+    let cmd_pool = Arc::new(AdCommandPool::new(queue, vk::CommandPoolCreateFlags::TRANSIENT)?);
+    let dset_pools =
+      Arc::new(AdDescriptorPoolManager::new(ash_device.clone(), "backend", BACKEND_DSET_POOL_SETS));
+    let fence = AdFence::new(ash_device.clone(), vk::FenceCreateFlags::empty())?;
+    Ok(Self { ash_device, allocator, cmd_pool, dset_pools, fence })
+  }
+
+  // Records after what's already in cmd_buffer, which the caller ends and submits like a frame's
+  pub fn record_into<'a>(&self, cmd_buffer: &'a AdCommandBuffer) -> VulkanCommandList<'a> {
+    VulkanCommandList {
+      cmd_buffer: Recording::Borrowed(cmd_buffer),
+      dset_pools: self.dset_pools.clone(),
+      dsets: vec![],
+      buffers: vec![],
+    }
+  }
+}
+
+impl GraphicsDevice for VulkanDevice {
+  type Buffer = VulkanBuffer;
+  type Image = VulkanImage;
+  type Pipeline = VulkanPipeline;
+  type CommandList = VulkanCommandList<'static>;
+
+  fn name(&self) -> String {
+    format!("vulkan on {}", self.ash_device.device_info().name)
+  }
+
+  fn create_buffer(&self, desc: &BufferDesc) -> Result<VulkanBuffer, String> {
+    let mut usage = vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST;
+    for (wanted, flag) in [
+      (desc.usage.vertex, vk::BufferUsageFlags::VERTEX_BUFFER),
+      (desc.usage.index, vk::BufferUsageFlags::INDEX_BUFFER),
+      (desc.usage.uniform, vk::BufferUsageFlags::UNIFORM_BUFFER),
+      (desc.usage.storage, vk::BufferUsageFlags::STORAGE_BUFFER),
+      (desc.usage.indirect, vk::BufferUsageFlags::INDIRECT_BUFFER),
+    ] {
+      if wanted {
+        usage |= flag;
+      }
+    }
+    let inner = AdBuffer::new(
+      self.ash_device.clone(),
+      self.allocator.clone(),
+      vk_memory_location(desc.location),
+      &desc.name,
+      vk::BufferCreateFlags::empty(),
+      desc.size,
+      usage,
+    )?;
+    Ok(VulkanBuffer { inner: Arc::new(inner), location: desc.location })
+  }
+
+  fn create_image(&self, desc: &ImageDesc) -> Result<VulkanImage, String> {
+    let mut usage = vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST;
+    if desc.sampled {
+      usage |= vk::ImageUsageFlags::SAMPLED;
+    }
+    if desc.storage {
+      usage |= vk::ImageUsageFlags::STORAGE;
+    }
+    let image = AdImage::new_2d(
+      self.ash_device.clone(),
+      self.allocator.clone(),
+      gpu_allocator::MemoryLocation::GpuOnly,
+      &desc.name,
+      vk_format(desc.format),
+      vk::Extent2D { width: desc.extent.width, height: desc.extent.height },
+      usage,
+      vk::SampleCountFlags::TYPE_1,
+      1,
+    )?;
+    let view = AdImageView::create_view(image, vk::ImageViewType::TYPE_2D, color_range())?;
+    Ok(VulkanImage {
+      view,
+      format: desc.format,
+      first_layout: vk::ImageLayout::UNDEFINED,
+      in_general: Arc::new(AtomicBool::new(false)),
+    })
+  }
+
+  fn create_compute_pipeline(&self, name: &str, spirv: &[u32]) -> Result<VulkanPipeline, String> {
+    let spv_bytes = spirv.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>();
+    let shaders = HashMap::from([(vk::ShaderStageFlags::COMPUTE, &spv_bytes[..])]);
+    let reflection = AdPipeline::reflect_shaders(&shaders)
+      .map_err(|e| format!("at reflecting pipeline {name}: {e}"))?;
+    // Dispatch fills set 0 in order, so the shader has to use each binding from 0 up
+    if reflection.bindings.iter().enumerate().any(|(i, x)| x.set != 0 || x.binding != i as u32) {
+      return Err(format!("pipeline {name} has to use set 0 bindings from 0 up without gaps"));
+    }
+    let set_layout = reflection.set_layouts(self.ash_device.clone())?.into_iter().next();
+    let inner = AdComputePipeline::new(
+      self.ash_device.clone(),
+      &spv_bytes,
+      &set_layout.iter().map(|x| x.as_ref()).collect::<Vec<_>>(),
+      reflection.push_constant_size,
+    )?;
+    Ok(VulkanPipeline { name: name.to_string(), inner, set_layout })
+  }
+
+  fn begin_commands(&self) -> Result<VulkanCommandList<'static>, String> {
+    let cmd_buffer =
+      AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+    Ok(VulkanCommandList {
+      cmd_buffer: Recording::Owned(cmd_buffer),
+      dset_pools: self.dset_pools.clone(),
+      dsets: vec![],
+      buffers: vec![],
+    })
+  }
+
+  fn submit_and_wait(&self, commands: VulkanCommandList<'static>) -> Result<(), String> {
+    // Host visible buffers are read right after
+    commands.cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::ALL_COMMANDS,
+      vk::PipelineStageFlags::HOST,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+        .dst_access_mask(vk::AccessFlags::HOST_READ)],
+      &[],
+      &[],
+    );
+    commands.cmd_buffer.end()?;
+    commands.cmd_buffer.submit(&[], &[], Some(&self.fence))?;
+    self.fence.wait_and_reset(u64::MAX)
+  }
+}
@@ -3,5 +3,12 @@ pub use ash_data_wrappers;
 pub use ash_debug_wrappers;
 pub use ash_queue_wrappers;
 pub use ash_render_wrappers;
+#[cfg(feature = "ray-tracing")]
+pub use ash_rt_wrappers;
 pub use ash_surface_wrappers;
 pub use ash_sync_wrappers;
+pub use graphics_backend;
+
+pub mod backend;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,150 @@
+pub use null::{NullBuffer, NullCommand, NullCommandList, NullDevice, NullImage, NullPipeline};
+
+mod null;
+#[cfg(test)]
+mod tests;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  Rgba8Unorm,
+  Rgba8Srgb,
+  Rgba16Float,
+  Rgba32Float,
+  R32Float,
+  R32Uint,
+}
+
+impl Format {
+  pub fn bytes_per_pixel(&self) -> u32 {
+    match self {
+      Self::Rgba8Unorm | Self::Rgba8Srgb | Self::R32Float | Self::R32Uint => 4,
+      Self::Rgba16Float => 8,
+      Self::Rgba32Float => 16,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryLocation {
+  // Only the gpu reads and writes it, filled with copies from upload buffers
+  GpuOnly,
+  CpuToGpu,
+  GpuToCpu,
+}
+
+impl MemoryLocation {
+  pub fn is_host_visible(&self) -> bool {
+    *self != Self::GpuOnly
+  }
+}
+
+// Every buffer can be copied to and from as well
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BufferUsage {
+  pub vertex: bool,
+  pub index: bool,
+  pub uniform: bool,
+  pub storage: bool,
+  pub indirect: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferDesc {
+  pub name: String,
+  pub size: u64,
+  pub usage: BufferUsage,
+  pub location: MemoryLocation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent2D {
+  pub width: u32,
+  pub height: u32,
+}
+
+// 2D with one mip level, in gpu memory. Every image can be cleared and copied into as well
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageDesc {
+  pub name: String,
+  pub format: Format,
+  pub extent: Extent2D,
+  pub sampled: bool,
+  pub storage: bool,
+}
+
+pub trait BackendBuffer {
+  fn name(&self) -> &str;
+  fn size(&self) -> u64;
+  // Host visible buffers only
+  fn write(&self, offset: u64, bytes: &[u8]) -> Result<(), String>;
+  fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>, String>;
+}
+
+pub trait BackendImage {
+  fn name(&self) -> &str;
+  fn format(&self) -> Format;
+  fn extent(&self) -> Extent2D;
+}
+
+pub trait BackendPipeline {
+  fn name(&self) -> &str;
+}
+
+// A resource a compute shader reads or writes, the nth one given to dispatch is binding n of set 0
+pub enum Binding<'a, B, I> {
+  UniformBuffer(&'a B),
+  StorageBuffer(&'a B),
+  StorageImage(&'a I),
+}
+
+// Commands run in order on submit, with a barrier needed between a write and what reads it
+pub trait CommandList {
+  type Buffer: BackendBuffer;
+  type Image: BackendImage;
+  type Pipeline: BackendPipeline;
+
+  fn copy_buffer(
+    &mut self,
+    src: &Self::Buffer,
+    src_offset: u64,
+    dst: &Self::Buffer,
+    dst_offset: u64,
+    size: u64,
+  ) -> Result<(), String>;
+  // Tightly packed pixels from the start of src into the whole image
+  fn copy_buffer_to_image(&mut self, src: &Self::Buffer, dst: &Self::Image) -> Result<(), String>;
+  fn clear_image(&mut self, image: &Self::Image, color: [f32; 4]) -> Result<(), String>;
+  fn dispatch(
+    &mut self,
+    pipeline: &Self::Pipeline,
+    bindings: &[Binding<'_, Self::Buffer, Self::Image>],
+    push_constants: &[u8],
+    group_counts: [u32; 3],
+  ) -> Result<(), String>;
+  // Everything recorded before finishes, and its writes are seen, before anything after starts
+  fn barrier(&mut self);
+}
+
+// What renderers need from a gpu, so they can run on backends other than vulkan or on none at all
+// in tests. Compute and transfer for now, render passes still go through the vulkan wrappers
+pub trait GraphicsDevice {
+  type Buffer: BackendBuffer;
+  type Image: BackendImage;
+  type Pipeline: BackendPipeline;
+  type CommandList: CommandList<
+    Buffer = Self::Buffer,
+    Image = Self::Image,
+    Pipeline = Self::Pipeline,
+  >;
+
+  fn name(&self) -> String;
+  fn create_buffer(&self, desc: &BufferDesc) -> Result<Self::Buffer, String>;
+  fn create_image(&self, desc: &ImageDesc) -> Result<Self::Image, String>;
+  // From SPIR-V words with a compute entry point named main
+  fn create_compute_pipeline(&self, name: &str, spirv: &[u32]) -> Result<Self::Pipeline, String>;
+  fn begin_commands(&self) -> Result<Self::CommandList, String>;
+  // Returns once the gpu is done with the commands
+  fn submit_and_wait(&self, commands: Self::CommandList) -> Result<(), String>;
+}
@@ -0,0 +1,284 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{
+  BackendBuffer, BackendImage, BackendPipeline, Binding, BufferDesc, CommandList, Extent2D, Format,
+  GraphicsDevice, ImageDesc,
+};
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+fn lock<'a, T>(data: &'a Mutex<T>, name: &str) -> Result<MutexGuard<'a, T>, String> {
+  data.lock().map_err(|e| format!("at getting {name} lock: {e}"))
+}
+
+fn check_range(name: &str, size: u64, offset: u64, len: u64) -> Result<(), String> {
+  match offset.checked_add(len) {
+    Some(end) if end <= size => Ok(()),
+    _ => Err(format!("{len} bytes at {offset} don't fit in {name} of {size} bytes")),
+  }
+}
+
+// Memory kept on the cpu, clones share it
+#[derive(Debug, Clone)]
+pub struct NullBuffer {
+  desc: Arc<BufferDesc>,
+  data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl NullBuffer {
+  pub fn desc(&self) -> &BufferDesc {
+    &self.desc
+  }
+}
+
+impl BackendBuffer for NullBuffer {
+  fn name(&self) -> &str {
+    &self.desc.name
+  }
+
+  fn size(&self) -> u64 {
+    self.desc.size
+  }
+
+  fn write(&self, offset: u64, bytes: &[u8]) -> Result<(), String> {
+    if !self.desc.location.is_host_visible() {
+      return Err(format!("buffer {} isn't host visible", self.desc.name));
+    }
+    check_range(&self.desc.name, self.desc.size, offset, bytes.len() as u64)?;
+    lock(&self.data, &self.desc.name)?[offset as usize..offset as usize + bytes.len()]
+      .copy_from_slice(bytes);
+    Ok(())
+  }
+
+  fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+    if !self.desc.location.is_host_visible() {
+      return Err(format!("buffer {} isn't host visible", self.desc.name));
+    }
+    check_range(&self.desc.name, self.desc.size, offset, len)?;
+    Ok(lock(&self.data, &self.desc.name)?[offset as usize..(offset + len) as usize].to_vec())
+  }
+}
+
+// Tightly packed pixels on the cpu, clones share them
+#[derive(Debug, Clone)]
+pub struct NullImage {
+  desc: Arc<ImageDesc>,
+  pixels: Arc<Mutex<Vec<u8>>>,
+}
+
+impl NullImage {
+  pub fn desc(&self) -> &ImageDesc {
+    &self.desc
+  }
+
+  // There's no copying images back into buffers, tests look at the pixels here instead
+  pub fn pixels(&self) -> Result<Vec<u8>, String> {
+    Ok(lock(&self.pixels, &self.desc.name)?.clone())
+  }
+
+  fn byte_size(&self) -> u64 {
+    let extent = self.desc.extent;
+    extent.width as u64 * extent.height as u64 * self.desc.format.bytes_per_pixel() as u64
+  }
+}
+
+impl BackendImage for NullImage {
+  fn name(&self) -> &str {
+    &self.desc.name
+  }
+
+  fn format(&self) -> Format {
+    self.desc.format
+  }
+
+  fn extent(&self) -> Extent2D {
+    self.desc.extent
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct NullPipeline {
+  name: String,
+}
+
+impl BackendPipeline for NullPipeline {
+  fn name(&self) -> &str {
+    &self.name
+  }
+}
+
+// What a null command list was asked to do, by resource names
+#[derive(Debug, Clone, PartialEq)]
+pub enum NullCommand {
+  CopyBuffer { src: String, dst: String, size: u64 },
+  CopyBufferToImage { src: String, dst: String },
+  ClearImage { image: String, color: [f32; 4] },
+  // Nothing runs, the shader's outputs are left as they were
+  Dispatch { pipeline: String, bindings: usize, push_constant_size: usize, group_counts: [u32; 3] },
+  Barrier,
+}
+
+type NullOp = Box<dyn FnOnce() -> Result<(), String> + Send>;
+
+pub struct NullCommandList {
+  commands: Vec<NullCommand>,
+  ops: Vec<NullOp>,
+}
+
+fn float_to_bytes(format: Format, color: [f32; 4]) -> Vec<u8> {
+  let unorm = |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+  match format {
+    Format::Rgba8Unorm | Format::Rgba8Srgb => color.map(unorm).to_vec(),
+    Format::Rgba16Float => {
+      color.iter().flat_map(|x| half::f16::from_f32(*x).to_bits().to_le_bytes()).collect()
+    }
+    Format::Rgba32Float => color.iter().flat_map(|x| x.to_le_bytes()).collect(),
+    Format::R32Float => color[0].to_le_bytes().to_vec(),
+    Format::R32Uint => (color[0] as u32).to_le_bytes().to_vec(),
+  }
+}
+
+impl CommandList for NullCommandList {
+  type Buffer = NullBuffer;
+  type Image = NullImage;
+  type Pipeline = NullPipeline;
+
+  fn copy_buffer(
+    &mut self,
+    src: &NullBuffer,
+    src_offset: u64,
+    dst: &NullBuffer,
+    dst_offset: u64,
+    size: u64,
+  ) -> Result<(), String> {
+    check_range(&src.desc.name, src.desc.size, src_offset, size)?;
+    check_range(&dst.desc.name, dst.desc.size, dst_offset, size)?;
+    self.commands.push(NullCommand::CopyBuffer {
+      src: src.desc.name.clone(),
+      dst: dst.desc.name.clone(),
+      size,
+    });
+    let (src, dst) = (src.clone(), dst.clone());
+    self.ops.push(Box::new(move || {
+      let bytes = lock(&src.data, &src.desc.name)?
+        [src_offset as usize..(src_offset + size) as usize]
+        .to_vec();
+      lock(&dst.data, &dst.desc.name)?[dst_offset as usize..(dst_offset + size) as usize]
+        .copy_from_slice(&bytes);
+      Ok(())
+    }));
+    Ok(())
+  }
+
+  fn copy_buffer_to_image(&mut self, src: &NullBuffer, dst: &NullImage) -> Result<(), String> {
+    let size = dst.byte_size();
+    check_range(&src.desc.name, src.desc.size, 0, size)?;
+    self.commands.push(NullCommand::CopyBufferToImage {
+      src: src.desc.name.clone(),
+      dst: dst.desc.name.clone(),
+    });
+    let (src, dst) = (src.clone(), dst.clone());
+    self.ops.push(Box::new(move || {
+      let bytes = lock(&src.data, &src.desc.name)?[..size as usize].to_vec();
+      *lock(&dst.pixels, &dst.desc.name)? = bytes;
+      Ok(())
+    }));
+    Ok(())
+  }
+
+  fn clear_image(&mut self, image: &NullImage, color: [f32; 4]) -> Result<(), String> {
+    self.commands.push(NullCommand::ClearImage { image: image.desc.name.clone(), color });
+    let image = image.clone();
+    self.ops.push(Box::new(move || {
+      let pixel = float_to_bytes(image.desc.format, color);
+      let mut pixels = lock(&image.pixels, &image.desc.name)?;
+      for texel in pixels.chunks_exact_mut(pixel.len()) {
+        texel.copy_from_slice(&pixel);
+      }
+      Ok(())
+    }));
+    Ok(())
+  }
+
+  fn dispatch(
+    &mut self,
+    pipeline: &NullPipeline,
+    bindings: &[Binding<'_, NullBuffer, NullImage>],
+    push_constants: &[u8],
+    group_counts: [u32; 3],
+  ) -> Result<(), String> {
+    self.commands.push(NullCommand::Dispatch {
+      pipeline: pipeline.name.clone(),
+      bindings: bindings.len(),
+      push_constant_size: push_constants.len(),
+      group_counts,
+    });
+    Ok(())
+  }
+
+  fn barrier(&mut self) {
+    self.commands.push(NullCommand::Barrier);
+  }
+}
+
+// Keeps resources on the cpu and runs copies and clears there, shaders are never run. Everything
+// submitted is kept in order for tests to look at
+#[derive(Default)]
+pub struct NullDevice {
+  submitted: Mutex<Vec<NullCommand>>,
+}
+
+impl NullDevice {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn submitted_commands(&self) -> Result<Vec<NullCommand>, String> {
+    Ok(lock(&self.submitted, "null device submits")?.clone())
+  }
+}
+
+impl GraphicsDevice for NullDevice {
+  type Buffer = NullBuffer;
+  type Image = NullImage;
+  type Pipeline = NullPipeline;
+  type CommandList = NullCommandList;
+
+  fn name(&self) -> String {
+    "null".to_string()
+  }
+
+  fn create_buffer(&self, desc: &BufferDesc) -> Result<NullBuffer, String> {
+    let data = vec![0; desc.size as usize];
+    Ok(NullBuffer { desc: Arc::new(desc.clone()), data: Arc::new(Mutex::new(data)) })
+  }
+
+  fn create_image(&self, desc: &ImageDesc) -> Result<NullImage, String> {
+    if desc.extent.width == 0 || desc.extent.height == 0 {
+      return Err(format!("image {} has no pixels", desc.name));
+    }
+    let extent = desc.extent;
+    let size =
+      extent.width as usize * extent.height as usize * desc.format.bytes_per_pixel() as usize;
+    Ok(NullImage { desc: Arc::new(desc.clone()), pixels: Arc::new(Mutex::new(vec![0; size])) })
+  }
+
+  fn create_compute_pipeline(&self, name: &str, spirv: &[u32]) -> Result<NullPipeline, String> {
+    if spirv.first() != Some(&SPIRV_MAGIC) {
+      return Err(format!("shader of pipeline {name} isn't SPIR-V"));
+    }
+    Ok(NullPipeline { name: name.to_string() })
+  }
+
+  fn begin_commands(&self) -> Result<NullCommandList, String> {
+    Ok(NullCommandList { commands: vec![], ops: vec![] })
+  }
+
+  fn submit_and_wait(&self, commands: NullCommandList) -> Result<(), String> {
+    for op in commands.ops {
+      op()?;
+    }
+    lock(&self.submitted, "null device submits")?.extend(commands.commands);
+    Ok(())
+  }
+}
@@ -0,0 +1,132 @@
+use crate::{
+  BackendBuffer, Binding, BufferDesc, BufferUsage, CommandList, Extent2D, Format, GraphicsDevice,
+  ImageDesc, MemoryLocation, NullBuffer, NullCommand, NullDevice, NullImage,
+};
+
+// Minimal SPIR-V header, the null device only looks at the magic number
+const SPIRV_HEADER: [u32; 5] = [0x0723_0203, 0x0001_0000, 0, 1, 0];
+
+fn buffer(device: &NullDevice, name: &str, size: u64, location: MemoryLocation) -> NullBuffer {
+  let usage = BufferUsage { storage: true, ..Default::default() };
+  device.create_buffer(&BufferDesc { name: name.to_string(), size, usage, location }).unwrap()
+}
+
+fn image(device: &NullDevice, name: &str, format: Format) -> NullImage {
+  device
+    .create_image(&ImageDesc {
+      name: name.to_string(),
+      format,
+      extent: Extent2D { width: 2, height: 2 },
+      sampled: true,
+      storage: false,
+    })
+    .unwrap()
+}
+
+#[test]
+fn copies_run_on_submit_in_order() {
+  let device = NullDevice::new();
+  let upload = buffer(&device, "upload", 16, MemoryLocation::CpuToGpu);
+  let gpu = buffer(&device, "gpu", 16, MemoryLocation::GpuOnly);
+  let readback = buffer(&device, "readback", 16, MemoryLocation::GpuToCpu);
+  upload.write(0, &(0..16).collect::<Vec<u8>>()).unwrap();
+
+  let mut commands = device.begin_commands().unwrap();
+  commands.copy_buffer(&upload, 4, &gpu, 0, 8).unwrap();
+  commands.barrier();
+  commands.copy_buffer(&gpu, 0, &readback, 8, 8).unwrap();
+  // Nothing happens until it's submitted
+  assert_eq!(readback.read(0, 16).unwrap(), vec![0; 16]);
+  device.submit_and_wait(commands).unwrap();
+  assert_eq!(readback.read(8, 8).unwrap(), (4..12).collect::<Vec<u8>>());
+  assert_eq!(readback.read(0, 8).unwrap(), vec![0; 8]);
+
+  let copy = |src: &str, dst: &str| NullCommand::CopyBuffer {
+    src: src.to_string(),
+    dst: dst.to_string(),
+    size: 8,
+  };
+  assert_eq!(
+    device.submitted_commands().unwrap(),
+    vec![copy("upload", "gpu"), NullCommand::Barrier, copy("gpu", "readback")]
+  );
+}
+
+#[test]
+fn images_are_filled_by_copies_and_clears() {
+  let device = NullDevice::new();
+  let upload = buffer(&device, "upload", 16, MemoryLocation::CpuToGpu);
+  let texels = (0..16).collect::<Vec<u8>>();
+  upload.write(0, &texels).unwrap();
+  let copied = image(&device, "copied", Format::Rgba8Unorm);
+  let cleared = image(&device, "cleared", Format::Rgba16Float);
+
+  let mut commands = device.begin_commands().unwrap();
+  commands.copy_buffer_to_image(&upload, &copied).unwrap();
+  commands.clear_image(&cleared, [1.0, 0.0, 0.5, 1.0]).unwrap();
+  device.submit_and_wait(commands).unwrap();
+  assert_eq!(copied.pixels().unwrap(), texels);
+  let half = |x: f32| half::f16::from_f32(x).to_bits().to_le_bytes();
+  let pixel = [half(1.0), half(0.0), half(0.5), half(1.0)].concat();
+  assert_eq!(cleared.pixels().unwrap(), pixel.repeat(4));
+
+  // A float image is twice the size of the buffer
+  let wide = image(&device, "wide", Format::Rgba32Float);
+  let mut commands = device.begin_commands().unwrap();
+  assert!(commands.copy_buffer_to_image(&upload, &wide).is_err());
+}
+
+#[test]
+fn dispatches_are_recorded_but_not_run() {
+  let device = NullDevice::new();
+  let pipeline = device.create_compute_pipeline("scale", &SPIRV_HEADER).unwrap();
+  let input = buffer(&device, "input", 16, MemoryLocation::CpuToGpu);
+  let output = buffer(&device, "output", 16, MemoryLocation::GpuToCpu);
+  input.write(0, &[7; 16]).unwrap();
+
+  let mut commands = device.begin_commands().unwrap();
+  commands
+    .dispatch(
+      &pipeline,
+      &[Binding::StorageBuffer(&input), Binding::StorageBuffer(&output)],
+      &3u32.to_le_bytes(),
+      [2, 1, 1],
+    )
+    .unwrap();
+  device.submit_and_wait(commands).unwrap();
+  assert_eq!(output.read(0, 16).unwrap(), vec![0; 16]);
+  assert_eq!(
+    device.submitted_commands().unwrap(),
+    vec![NullCommand::Dispatch {
+      pipeline: "scale".to_string(),
+      bindings: 2,
+      push_constant_size: 4,
+      group_counts: [2, 1, 1],
+    }]
+  );
+}
+
+#[test]
+fn bad_resources_and_accesses_are_errors() {
+  let device = NullDevice::new();
+  let gpu_only = buffer(&device, "gpu_only", 16, MemoryLocation::GpuOnly);
+  assert!(gpu_only.write(0, &[1, 2, 3, 4]).is_err());
+  assert!(gpu_only.read(0, 4).is_err());
+
+  let upload = buffer(&device, "upload", 16, MemoryLocation::CpuToGpu);
+  assert!(upload.write(14, &[1, 2, 3, 4]).is_err());
+  assert!(upload.read(u64::MAX, 2).is_err());
+  let mut commands = device.begin_commands().unwrap();
+  assert!(commands.copy_buffer(&upload, 8, &gpu_only, 0, 16).is_err());
+
+  assert!(device.create_compute_pipeline("not_spirv", &[1, 2, 3]).is_err());
+  assert!(device.create_compute_pipeline("empty", &[]).is_err());
+  let empty = ImageDesc {
+    name: "empty".to_string(),
+    format: Format::Rgba8Unorm,
+    extent: Extent2D { width: 0, height: 4 },
+    sampled: true,
+    storage: false,
+  };
+  assert!(device.create_image(&empty).is_err());
+}
@@ -0,0 +1,449 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout,
+  },
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
+  ash_sync_wrappers::AdFence,
+};
+
+use crate::{
+  foliage::WindParams,
+  triangle_mesh::{g_vec4_from_vec3, TriMeshCPU, TriMeshGPU, TriMeshGenerator, TriMeshVertex},
+};
+
+// Constraint passes per cpu step
+const CLOTH_ITERATIONS: usize = 8;
+// Neighbours every particle is kept at its rest distance from, as column and row offsets. Direct
+// ones hold the cloth together, diagonals keep it from shearing and the ones two away from folding
+pub const CLOTH_NEIGHBOURS: [(i32, i32); 12] = [
+  (1, 0),
+  (-1, 0),
+  (0, 1),
+  (0, -1),
+  (1, 1),
+  (-1, -1),
+  (1, -1),
+  (-1, 1),
+  (2, 0),
+  (-2, 0),
+  (0, 2),
+  (0, -2),
+];
+
+// Grid of particles hanging from its first row, columns along +x and rows down -y in model space
+#[derive(Debug, Clone)]
+pub struct ClothCPU {
+  pub columns: u32,
+  pub rows: u32,
+  pub spacing: f32,
+  // Particles held where they start, as row * columns + column
+  pub pinned: Vec<u32>,
+  // How much the cloth stretches and shears under load, 0 for not at all
+  pub compliance: f32,
+  // Same for folding, cloth usually gives a lot more here
+  pub bend_compliance: f32,
+  // Share of velocity lost per second
+  pub damping: f32,
+  // How hard wind pushes on the cloth where it faces it
+  pub wind_response: f32,
+}
+
+impl ClothCPU {
+  // Hung from both top corners
+  pub fn new(columns: u32, rows: u32, spacing: f32) -> Self {
+    Self {
+      columns,
+      rows,
+      spacing,
+      pinned: vec![0, columns.saturating_sub(1)],
+      compliance: 0.0,
+      bend_compliance: 0.001,
+      damping: 0.5,
+      wind_response: 0.5,
+    }
+  }
+
+  pub fn particle_count(&self) -> usize {
+    (self.columns * self.rows) as usize
+  }
+
+  pub fn validate(&self) -> Result<(), String> {
+    if self.columns < 2 || self.rows < 2 {
+      return Err(format!(
+        "cloth needs at least 2x2 particles, got {}x{}",
+        self.columns, self.rows
+      ));
+    }
+    if self.spacing <= 0.0 {
+      return Err(format!("cloth spacing must be positive, got {}", self.spacing));
+    }
+    if let Some(pin) = self.pinned.iter().find(|pin| **pin as usize >= self.particle_count()) {
+      return Err(format!("cloth pins particle {pin} of {}", self.particle_count()));
+    }
+    Ok(())
+  }
+
+  pub fn rest_positions(&self) -> Vec<glam::Vec3> {
+    (0..self.rows)
+      .flat_map(|row| {
+        (0..self.columns)
+          .map(move |column| glam::vec3(column as f32, -(row as f32), 0.0) * self.spacing)
+      })
+      .collect()
+  }
+
+  pub fn triangles(&self) -> Vec<[u32; 3]> {
+    let mut triangles = Vec::with_capacity(((self.columns - 1) * (self.rows - 1) * 2) as usize);
+    for row in 0..self.rows - 1 {
+      for column in 0..self.columns - 1 {
+        let v00 = row * self.columns + column;
+        let (v01, v10) = (v00 + 1, v00 + self.columns);
+        triangles.push([v00, v10, v01]);
+        triangles.push([v01, v10, v10 + 1]);
+      }
+    }
+    triangles
+  }
+
+  // Hanging flat, for shadows and anything else that wants the shape up front
+  pub fn rest_mesh(&self) -> TriMeshCPU {
+    TriMeshCPU::new(
+      cloth_vertices(self.columns, self.rows, &self.rest_positions()),
+      self.triangles(),
+    )
+  }
+
+  // Furthest any particle can get from the origin with the cloth stretched out from a pin
+  pub fn bounding_radius(&self) -> f32 {
+    (self.columns + self.rows) as f32 * self.spacing
+  }
+}
+
+// Facing +z when hanging flat, normals come from the neighbouring particles
+pub fn cloth_vertices(columns: u32, rows: u32, positions: &[glam::Vec3]) -> Vec<TriMeshVertex> {
+  let at = |column: u32, row: u32| positions[(row * columns + column) as usize];
+  let mut vertices = Vec::with_capacity(positions.len());
+  for row in 0..rows {
+    for column in 0..columns {
+      let across = at((column + 1).min(columns - 1), row) - at(column.saturating_sub(1), row);
+      let up = at(column, row.saturating_sub(1)) - at(column, (row + 1).min(rows - 1));
+      vertices.push(TriMeshVertex {
+        pos: g_vec4_from_vec3(at(column, row), 1.0),
+        normal: g_vec4_from_vec3(across.cross(up).normalize_or(glam::Vec3::Z), 0.0),
+        uv: glam::vec4(
+          column as f32 / (columns - 1) as f32,
+          row as f32 / (rows - 1) as f32,
+          0.0,
+          0.0,
+        ),
+      });
+    }
+  }
+  vertices
+}
+
+// Wind as an acceleration on cloth facing it square on, gusting with the same speed and size
+// foliage sways with
+pub fn cloth_wind(wind: WindParams, time: f32) -> glam::Vec3 {
+  let direction = wind.direction.normalize_or_zero();
+  let gust_phase = time * wind.speed / wind.gust_size.max(0.001) * std::f32::consts::TAU;
+  let gust = 0.75 + 0.25 * gust_phase.sin();
+  glam::vec3(direction.x, 0.0, direction.y) * wind.strength * gust
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ClothConstraint {
+  particles: [usize; 2],
+  rest_length: f32,
+  compliance: f32,
+}
+
+// Position based cloth stepped on the cpu, constraints are solved like xpbd so stiffness doesn't
+// change with the frame time
+pub struct ClothSim {
+  columns: u32,
+  rows: u32,
+  positions: Vec<glam::Vec3>,
+  prev_positions: Vec<glam::Vec3>,
+  inv_masses: Vec<f32>,
+  constraints: Vec<ClothConstraint>,
+  damping: f32,
+  wind_response: f32,
+}
+
+impl ClothSim {
+  pub fn new(cloth: &ClothCPU) -> Result<Self, String> {
+    cloth.validate()?;
+    let positions = cloth.rest_positions();
+    let mut inv_masses = vec![1.0; positions.len()];
+    for pin in cloth.pinned.iter() {
+      inv_masses[*pin as usize] = 0.0;
+    }
+    let mut constraints = vec![];
+    for row in 0..cloth.rows as i32 {
+      for column in 0..cloth.columns as i32 {
+        // Each pair once, from the particle before the other
+        for (d_column, d_row) in
+          CLOTH_NEIGHBOURS.iter().filter(|(c, r)| *r > 0 || *r == 0 && *c > 0)
+        {
+          let (other_column, other_row) = (column + d_column, row + d_row);
+          if other_column < 0
+            || other_column >= cloth.columns as i32
+            || other_row >= cloth.rows as i32
+          {
+            continue;
+          }
+          let bend = d_column.abs() == 2 || d_row.abs() == 2;
+          constraints.push(ClothConstraint {
+            particles: [
+              (row * cloth.columns as i32 + column) as usize,
+              (other_row * cloth.columns as i32 + other_column) as usize,
+            ],
+            rest_length: glam::vec2(*d_column as f32, *d_row as f32).length() * cloth.spacing,
+            compliance: if bend { cloth.bend_compliance } else { cloth.compliance },
+          });
+        }
+      }
+    }
+    Ok(Self {
+      columns: cloth.columns,
+      rows: cloth.rows,
+      prev_positions: positions.clone(),
+      positions,
+      inv_masses,
+      constraints,
+      damping: cloth.damping,
+      wind_response: cloth.wind_response,
+    })
+  }
+
+  pub fn positions(&self) -> &[glam::Vec3] {
+    &self.positions
+  }
+
+  // Gravity and wind are accelerations in the cloth's model space
+  pub fn step(&mut self, time_s: f32, gravity: glam::Vec3, wind: glam::Vec3) {
+    if time_s <= 0.0 {
+      return;
+    }
+    let keep = (1.0 - self.damping * time_s).max(0.0);
+    let vertices = cloth_vertices(self.columns, self.rows, &self.positions);
+    for (idx, vertex) in vertices.iter().enumerate() {
+      if self.inv_masses[idx] == 0.0 {
+        continue;
+      }
+      let velocity = (self.positions[idx] - self.prev_positions[idx]) * keep;
+      // Wind pushes on what faces it relative to how the cloth is already moving, either side
+      let normal = vertex.normal.truncate();
+      let wind_push = normal * (wind - velocity / time_s).dot(normal) * self.wind_response;
+      self.prev_positions[idx] = self.positions[idx];
+      self.positions[idx] += velocity + (gravity + wind_push) * time_s * time_s;
+    }
+
+    let mut lambdas = vec![0.0; self.constraints.len()];
+    for _ in 0..CLOTH_ITERATIONS {
+      for (constraint, lambda) in self.constraints.iter().zip(lambdas.iter_mut()) {
+        let [a, b] = constraint.particles;
+        let w = self.inv_masses[a] + self.inv_masses[b];
+        let delta = self.positions[b] - self.positions[a];
+        let length = delta.length();
+        if w == 0.0 || length == 0.0 {
+          continue;
+        }
+        let alpha = constraint.compliance / (time_s * time_s);
+        let d_lambda = (constraint.rest_length - length - alpha * *lambda) / (w + alpha);
+        *lambda += d_lambda;
+        let dir = delta / length;
+        self.positions[a] -= dir * d_lambda * self.inv_masses[a];
+        self.positions[b] += dir * d_lambda * self.inv_masses[b];
+      }
+    }
+  }
+
+  pub fn vertices(&self) -> Vec<TriMeshVertex> {
+    cloth_vertices(self.columns, self.rows, &self.positions)
+  }
+}
+
+enum ClothSolver {
+  // Written to the mesh's vertex buffer after every step
+  Cpu(Mutex<ClothSim>, Arc<AdBuffer>),
+  // Particles, scratch particles, previous positions and the mesh's vertices for the compute pass
+  Gpu(Arc<AdDescriptorSet>),
+}
+
+// Drawn like any other mesh, from vertices the cpu sim or the cloth compute pass writes
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct ClothGPU {
+  #[getset(get = "pub")]
+  mesh: Arc<TriMeshGPU>,
+  #[getset(get_copy = "pub")]
+  columns: u32,
+  #[getset(get_copy = "pub")]
+  rows: u32,
+  #[getset(get_copy = "pub")]
+  spacing: f32,
+  #[getset(get_copy = "pub")]
+  compliance: f32,
+  #[getset(get_copy = "pub")]
+  bend_compliance: f32,
+  #[getset(get_copy = "pub")]
+  damping: f32,
+  #[getset(get_copy = "pub")]
+  wind_response: f32,
+  solver: ClothSolver,
+}
+
+impl ClothGPU {
+  // None for cloth on the cpu
+  pub fn sim_dset(&self) -> Option<&Arc<AdDescriptorSet>> {
+    match &self.solver {
+      ClothSolver::Cpu(..) => None,
+      ClothSolver::Gpu(sim_dset) => Some(sim_dset),
+    }
+  }
+
+  pub fn particle_count(&self) -> u32 {
+    self.columns * self.rows
+  }
+
+  // Steps cloth on the cpu and writes the vertices the next draws read. Nothing for cloth the
+  // compute pass steps
+  pub fn step_cpu(&self, time_s: f32, gravity: glam::Vec3, wind: glam::Vec3) -> Result<(), String> {
+    let ClothSolver::Cpu(sim, vert_buffer) = &self.solver else { return Ok(()) };
+    let mut sim = sim.lock().map_err(|e| format!("at locking cloth sim: {e}"))?;
+    sim.step(time_s, gravity, wind);
+    vert_buffer.write_data(0, &sim.vertices())
+  }
+
+  // Cpu cloth rewrites its vertices, see AdBuffer::mark_in_flight
+  pub fn mark_in_flight(&self, fence: &Arc<AdFence>) {
+    if let ClothSolver::Cpu(_, vert_buffer) = &self.solver {
+      vert_buffer.mark_in_flight(fence);
+    }
+  }
+}
+
+#[derive(getset::Getters)]
+pub struct ClothGenerator {
+  allocator: Arc<Mutex<Allocator>>,
+  cmd_pool: Arc<AdCommandPool>,
+  #[getset(get = "pub")]
+  sim_dset_pools: AdDescriptorPoolManager,
+  #[getset(get = "pub")]
+  sim_dset_layout: Arc<AdDescriptorSetLayout>,
+}
+
+impl ClothGenerator {
+  pub fn new(
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_pool: Arc<AdCommandPool>,
+  ) -> Result<Self, String> {
+    let ash_device = cmd_pool.queue().ash_device().clone();
+    let sim_dset_pools = AdDescriptorPoolManager::new(ash_device.clone(), "cloth", 16);
+    let sim_dset_layout = AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[(vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER); 4],
+    )?;
+    Ok(Self { allocator, cmd_pool, sim_dset_pools, sim_dset_layout: Arc::new(sim_dset_layout) })
+  }
+
+  // Starts hanging flat. On the gpu the compute pass steps it, otherwise step_cpu has to
+  pub fn create_cloth(
+    &self,
+    name: &str,
+    cloth: &ClothCPU,
+    tri_mesh_gen: &TriMeshGenerator,
+    on_gpu: bool,
+  ) -> Result<ClothGPU, String> {
+    cloth.validate().map_err(|e| format!("at cloth {name}: {e}"))?;
+    let ash_device = self.cmd_pool.queue().ash_device().clone();
+    let rest_vertices = cloth.rest_mesh().vertices;
+    let vert_size = (std::mem::size_of::<TriMeshVertex>() * rest_vertices.len()) as u64;
+
+    let (vert_buffer, solver) = if on_gpu {
+      let cmd_buffer =
+        AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+      // Inverse mass in w, 0 for pinned particles
+      let mut particles =
+        cloth.rest_positions().into_iter().map(|pos| pos.extend(1.0)).collect::<Vec<_>>();
+      for pin in cloth.pinned.iter() {
+        particles[*pin as usize].w = 0.0;
+      }
+      let particle_buffer = |suffix: &str| {
+        AdBuffer::from_data(
+          ash_device.clone(),
+          self.allocator.clone(),
+          MemoryLocation::GpuOnly,
+          &format!("{name}_{suffix}"),
+          vk::BufferCreateFlags::empty(),
+          vk::BufferUsageFlags::STORAGE_BUFFER,
+          &particles,
+          &cmd_buffer,
+        )
+      };
+      let vert_buffer = Arc::new(AdBuffer::from_data(
+        ash_device.clone(),
+        self.allocator.clone(),
+        MemoryLocation::GpuOnly,
+        &format!("{name}_vb"),
+        vk::BufferCreateFlags::empty(),
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        &rest_vertices,
+        &cmd_buffer,
+      )?);
+      let sim_dset = self
+        .sim_dset_pools
+        .allocate(&[(
+          self.sim_dset_layout.clone(),
+          vec![
+            AdDescriptorBinding::StorageBuffer(Arc::new(particle_buffer("cpb")?)),
+            AdDescriptorBinding::StorageBuffer(Arc::new(particle_buffer("csb")?)),
+            AdDescriptorBinding::StorageBuffer(Arc::new(particle_buffer("cpp")?)),
+            AdDescriptorBinding::StorageBuffer(vert_buffer.clone()),
+          ],
+        )])?
+        .remove(0);
+      (vert_buffer.clone(), ClothSolver::Gpu(Arc::new(sim_dset)))
+    } else {
+      let vert_buffer = Arc::new(AdBuffer::new(
+        ash_device.clone(),
+        self.allocator.clone(),
+        MemoryLocation::CpuToGpu,
+        &format!("{name}_vb"),
+        vk::BufferCreateFlags::empty(),
+        vert_size,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+      )?);
+      vert_buffer.write_data(0, &rest_vertices)?;
+      (vert_buffer.clone(), ClothSolver::Cpu(Mutex::new(ClothSim::new(cloth)?), vert_buffer))
+    };
+
+    // Only the transform's motion shows up in motion vectors, the simulation's doesn't
+    let mesh = tri_mesh_gen.create_tri_mesh_over(
+      name,
+      vert_buffer.clone(),
+      vert_buffer,
+      &cloth.triangles(),
+      cloth.bounding_radius(),
+    )?;
+    Ok(ClothGPU {
+      mesh: Arc::new(mesh),
+      columns: cloth.columns,
+      rows: cloth.rows,
+      spacing: cloth.spacing,
+      compliance: cloth.compliance,
+      bend_compliance: cloth.bend_compliance,
+      damping: cloth.damping,
+      wind_response: cloth.wind_response,
+      solver,
+    })
+  }
+}
@@ -0,0 +1,268 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+  },
+  ash_data_wrappers::{
+    image::{self, RgbaImage},
+    AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout, AdImage,
+    AdImageView, AdSampler,
+  },
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
+};
+
+// Larger tables don't grade any smoother and a 256 cube is already 128MB of half floats
+pub const MAX_LUT_SIZE: u32 = 128;
+
+// 3D color lookup table, colors in and out are sRGB encoded in 0..1 like a graded screenshot
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorLutCPU {
+  pub size: u32,
+  // size cubed, red changing fastest then green then blue
+  pub texels: Vec<glam::Vec3>,
+}
+
+impl ColorLutCPU {
+  // Maps every color to itself, exactly with linear filtering at any size
+  pub fn identity(size: u32) -> Self {
+    let max = (size - 1).max(1) as f32;
+    let texels = (0..size * size * size)
+      .map(|i| glam::vec3((i % size) as f32, (i / size % size) as f32, (i / size / size) as f32))
+      .map(|texel| texel / max)
+      .collect();
+    Self { size, texels }
+  }
+
+  fn check_size(size: u32) -> Result<(), String> {
+    if !(2..=MAX_LUT_SIZE).contains(&size) {
+      return Err(format!("lut size {size} isn't within 2..={MAX_LUT_SIZE}"));
+    }
+    Ok(())
+  }
+
+  // Adobe's .cube text format. Only 3D tables over the default 0..1 domain are supported
+  pub fn parse_cube(text: &str) -> Result<Self, String> {
+    let mut size = None;
+    let mut texels = vec![];
+    for (line_idx, line) in text.lines().enumerate() {
+      let line_no = line_idx + 1;
+      let tokens = line.split_whitespace().collect::<Vec<_>>();
+      let parse_floats = |tokens: &[&str]| {
+        tokens
+          .iter()
+          .map(|token| {
+            token.parse::<f32>().map_err(|e| format!("at line {line_no} value {token}: {e}"))
+          })
+          .collect::<Result<Vec<_>, String>>()
+      };
+      match tokens.as_slice() {
+        [] => {}
+        [comment, ..] if comment.starts_with('#') => {}
+        ["TITLE", ..] => {}
+        ["LUT_3D_SIZE", value] => {
+          let value =
+            value.parse::<u32>().map_err(|e| format!("at line {line_no} lut size {value}: {e}"))?;
+          Self::check_size(value)?;
+          size = Some(value);
+        }
+        ["LUT_1D_SIZE", ..] => {
+          return Err("1D luts aren't supported, only LUT_3D_SIZE".to_string())
+        }
+        ["DOMAIN_MIN", values @ ..] if parse_floats(values)? != [0.0; 3] => {
+          return Err(format!("at line {line_no}: only a DOMAIN_MIN of 0 0 0 is supported"));
+        }
+        ["DOMAIN_MAX", values @ ..] if parse_floats(values)? != [1.0; 3] => {
+          return Err(format!("at line {line_no}: only a DOMAIN_MAX of 1 1 1 is supported"));
+        }
+        ["DOMAIN_MIN" | "DOMAIN_MAX", ..] => {}
+        [_, _, _] => {
+          let values = parse_floats(&tokens)?;
+          texels.push(glam::vec3(values[0], values[1], values[2]));
+        }
+        _ => return Err(format!("at line {line_no}: unexpected {line:?}")),
+      }
+    }
+    let size = size.ok_or("no LUT_3D_SIZE in cube file")?;
+    if texels.len() != (size * size * size) as usize {
+      return Err(format!(
+        "lut of size {size} needs {} entries, got {}",
+        size.pow(3),
+        texels.len()
+      ));
+    }
+    Ok(Self { size, texels })
+  }
+
+  // Blue slices side by side, size*size wide and size tall, like Unreal and Unity export
+  pub fn from_strip(image: &RgbaImage) -> Result<Self, String> {
+    let size = image.height();
+    Self::check_size(size)?;
+    if image.width() != size * size {
+      return Err(format!(
+        "lut strip of height {size} should be {} wide, got {}",
+        size * size,
+        image.width()
+      ));
+    }
+    let texels = (0..size * size * size)
+      .map(|i| {
+        let (r, g, b) = (i % size, i / size % size, i / size / size);
+        let pixel = image.get_pixel(b * size + r, g);
+        glam::vec3(pixel[0] as f32, pixel[1] as f32, pixel[2] as f32) / 255.0
+      })
+      .collect();
+    Ok(Self { size, texels })
+  }
+
+  // .cube files by extension, anything else is read as a strip image
+  pub fn decode(path: &str, file_bytes: &[u8]) -> Result<Self, String> {
+    if path.to_lowercase().ends_with(".cube") {
+      let text =
+        std::str::from_utf8(file_bytes).map_err(|e| format!("at reading cube file: {e}"))?;
+      return Self::parse_cube(text);
+    }
+    let image = image::load_from_memory(file_bytes)
+      .map_err(|e| format!("at decoding lut image: {e}"))?
+      .to_rgba8();
+    Self::from_strip(&image)
+  }
+
+  // Reads through the global vfs, path is a vfs path
+  pub fn load(path: &str) -> Result<Self, String> {
+    Self::decode(path, &vfs::global().read(path)?)
+      .map_err(|e| format!("at loading lut {path}: {e}"))
+  }
+
+  fn rgba16f_texels(&self) -> Vec<u8> {
+    self
+      .texels
+      .iter()
+      .flat_map(|texel| texel.extend(1.0).to_array())
+      .flat_map(|value| half::f16::from_f32(value).to_ne_bytes())
+      .collect()
+  }
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct ColorLutGPU {
+  #[getset(get = "pub")]
+  dset: Arc<AdDescriptorSet>,
+  #[getset(get = "pub")]
+  image_view: Arc<AdImageView>,
+  #[getset(get_copy = "pub")]
+  size: u32,
+}
+
+// Uploads tables as 3D textures with a set each, for the tonemap pass to bind alongside its own
+#[derive(getset::Getters)]
+pub struct ColorLutGenerator {
+  #[getset(get = "pub")]
+  dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pools: AdDescriptorPoolManager,
+  sampler: Arc<AdSampler>,
+  allocator: Arc<Mutex<Allocator>>,
+  cmd_pool: Arc<AdCommandPool>,
+  identity: Arc<ColorLutGPU>,
+}
+
+impl ColorLutGenerator {
+  pub fn new(
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_pool: Arc<AdCommandPool>,
+  ) -> Result<Self, String> {
+    let ash_device = cmd_pool.queue().ash_device().clone();
+    let dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[(vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)],
+    )?);
+    // Colors between entries are blended, the edges of the table hold for anything outside it
+    let sampler = Arc::new(AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    )?);
+    let dset_pools = AdDescriptorPoolManager::new(ash_device, "color_lut", 8);
+    let identity = Arc::new(upload_lut(
+      &dset_layout,
+      &dset_pools,
+      &sampler,
+      &allocator,
+      &cmd_pool,
+      "color_lut_identity",
+      &ColorLutCPU::identity(2),
+    )?);
+    Ok(Self { dset_layout, dset_pools, sampler, allocator, cmd_pool, identity })
+  }
+
+  pub fn upload(&self, name: &str, lut: &ColorLutCPU) -> Result<ColorLutGPU, String> {
+    upload_lut(
+      &self.dset_layout,
+      &self.dset_pools,
+      &self.sampler,
+      &self.allocator,
+      &self.cmd_pool,
+      name,
+      lut,
+    )
+  }
+
+  // Bound in place of a table that isn't there, grades nothing
+  pub fn identity(&self) -> Arc<ColorLutGPU> {
+    self.identity.clone()
+  }
+}
+
+fn upload_lut(
+  dset_layout: &Arc<AdDescriptorSetLayout>,
+  dset_pools: &AdDescriptorPoolManager,
+  sampler: &Arc<AdSampler>,
+  allocator: &Arc<Mutex<Allocator>>,
+  cmd_pool: &Arc<AdCommandPool>,
+  name: &str,
+  lut: &ColorLutCPU,
+) -> Result<ColorLutGPU, String> {
+  let ash_device = cmd_pool.queue().ash_device().clone();
+  let cmd_buffer =
+    AdCommandBuffer::new(cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+  let lut_image = AdImage::new_3d_from_data(
+    ash_device,
+    allocator.clone(),
+    MemoryLocation::GpuOnly,
+    name,
+    vk::Format::R16G16B16A16_SFLOAT,
+    vk::Extent3D { width: lut.size, height: lut.size, depth: lut.size },
+    vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+    &lut.rgba16f_texels(),
+    &cmd_buffer,
+    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+  )?;
+  let image_view = AdImageView::create_view(
+    lut_image,
+    vk::ImageViewType::TYPE_3D,
+    vk::ImageSubresourceRange {
+      aspect_mask: vk::ImageAspectFlags::COLOR,
+      base_mip_level: 0,
+      level_count: 1,
+      base_array_layer: 0,
+      layer_count: 1,
+    },
+  )?;
+  let dset = dset_pools
+    .allocate(&[(
+      dset_layout.clone(),
+      vec![AdDescriptorBinding::Sampler2D((
+        image_view.clone(),
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        sampler.clone(),
+      ))],
+    )])?
+    .remove(0);
+  Ok(ColorLutGPU { dset: Arc::new(dset), image_view, size: lut.size })
+}
@@ -0,0 +1,288 @@
+use std::sync::{
+  atomic::{AtomicU32, Ordering},
+  Arc, Mutex,
+};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout,
+    AdImage, AdImage2dDesc, AdImageUpload, AdImageView, AdSampler,
+  },
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
+};
+
+use crate::material::MaterialGPU;
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct CrowdVertex {
+  pub pos: glam::Vec4,
+  pub normal: glam::Vec4,
+  pub uv: glam::Vec4,
+  pub joints: [u32; 4],
+  pub weights: glam::Vec4,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CrowdMeshCPU {
+  pub verts: Vec<CrowdVertex>,
+  pub triangles: Vec<[u32; 3]>,
+}
+
+// Skinning matrices of every joint sampled at a fixed rate over one loop of an animation, frame
+// after frame. The last frame blends back into the first
+#[derive(Debug, Clone, Default)]
+pub struct BoneAnimationCPU {
+  pub joint_count: u32,
+  pub frame_rate: f32,
+  pub matrices: Vec<glam::Mat4>,
+}
+
+impl BoneAnimationCPU {
+  pub fn frame_count(&self) -> u32 {
+    match self.joint_count {
+      0 => 0,
+      joint_count => self.matrices.len() as u32 / joint_count,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct CrowdInstance {
+  pub transform: glam::Mat4,
+  // x is the animation time in seconds, wrapped around the loop on the GPU
+  pub anim: glam::Vec4,
+}
+
+impl CrowdInstance {
+  pub fn new(transform: glam::Mat4, anim_time_s: f32) -> Self {
+    Self { transform, anim: glam::vec4(anim_time_s, 0.0, 0.0, 0.0) }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct CrowdData {
+  // joint count, frame count, unused, unused
+  counts: [u32; 4],
+  // frame rate, unused
+  params: glam::Vec4,
+}
+
+// Every instance shares the mesh, the baked animation and the material, so a crowd is drawn with
+// a single instanced draw
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct CrowdGPU {
+  #[getset(get = "pub")]
+  dset: Arc<AdDescriptorSet>,
+  #[getset(get_copy = "pub")]
+  indx_count: usize,
+  #[getset(get_copy = "pub")]
+  max_instances: u32,
+  instance_count: AtomicU32,
+  // Kept alive for as long as the crowd refers to it
+  #[getset(get = "pub")]
+  material: Arc<MaterialGPU>,
+}
+
+impl CrowdGPU {
+  pub fn instance_count(&self) -> u32 {
+    self.instance_count.load(Ordering::Acquire)
+  }
+
+  // Instances past max_instances are dropped
+  pub fn update_instances(&self, instances: &[CrowdInstance]) -> Result<(), String> {
+    let AdDescriptorBinding::StorageBuffer(ib) = &self.dset.bindings()[2] else {
+      return Err("Crowd constructed with improper instance buffer".to_string());
+    };
+    let instances = &instances[..instances.len().min(self.max_instances as usize)];
+    ib.write_data(0, instances)?;
+    self.instance_count.store(instances.len() as u32, Ordering::Release);
+    Ok(())
+  }
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct CrowdGenerator {
+  allocator: Arc<Mutex<Allocator>>,
+  cmd_pool: Arc<AdCommandPool>,
+  sampler: Arc<AdSampler>,
+  #[getset(get = "pub")]
+  crowd_dset_pools: AdDescriptorPoolManager,
+  #[getset(get = "pub")]
+  crowd_dset_layout: Arc<AdDescriptorSetLayout>,
+}
+
+impl CrowdGenerator {
+  pub fn new(
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_pool: Arc<AdCommandPool>,
+  ) -> Result<Self, String> {
+    let ash_device = cmd_pool.queue().ash_device().clone();
+    let dset_pools = AdDescriptorPoolManager::new(ash_device.clone(), "crowd", 16);
+    let dset_layout = AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::SAMPLER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::UNIFORM_BUFFER),
+      ],
+    )?;
+    // Bone texels are only ever fetched, filtering never applies
+    let sampler = AdSampler::new(ash_device.clone())?;
+    Ok(Self {
+      allocator,
+      cmd_pool,
+      sampler: Arc::new(sampler),
+      crowd_dset_pools: dset_pools,
+      crowd_dset_layout: Arc::new(dset_layout),
+    })
+  }
+
+  pub fn create_crowd(
+    &self,
+    name: &str,
+    mesh: &CrowdMeshCPU,
+    animation: &BoneAnimationCPU,
+    max_instances: u32,
+    material: Arc<MaterialGPU>,
+  ) -> Result<CrowdGPU, String> {
+    let frame_count = animation.frame_count();
+    if frame_count == 0 {
+      return Err(format!("crowd {name} has no animation frames"));
+    }
+    if max_instances == 0 {
+      return Err(format!("crowd {name} has no room for instances"));
+    }
+    if let Some(vert) =
+      mesh.verts.iter().find(|vert| vert.joints.iter().any(|joint| *joint >= animation.joint_count))
+    {
+      return Err(format!(
+        "crowd {name} vertex uses joints {:?} missing from animation",
+        vert.joints
+      ));
+    }
+    let ash_device = self.cmd_pool.queue().ash_device().clone();
+    let cmd_buffer =
+      AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+
+    let vert_buffer = AdBuffer::from_data(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("{name}_vb"),
+      vk::BufferCreateFlags::empty(),
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+      &mesh.verts,
+      &cmd_buffer,
+    )?;
+    let indx_buffer = AdBuffer::from_data(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("{name}_ib"),
+      vk::BufferCreateFlags::empty(),
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+      &mesh.triangles,
+      &cmd_buffer,
+    )?;
+    let instance_buffer = AdBuffer::new(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      &format!("{name}_instb"),
+      vk::BufferCreateFlags::empty(),
+      (std::mem::size_of::<CrowdInstance>() * max_instances as usize) as _,
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+    )?;
+    let crowd_buffer = AdBuffer::new(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      &format!("{name}_cb"),
+      vk::BufferCreateFlags::empty(),
+      std::mem::size_of::<CrowdData>() as _,
+      vk::BufferUsageFlags::UNIFORM_BUFFER,
+    )?;
+    crowd_buffer.write_data(
+      0,
+      &[CrowdData {
+        counts: [animation.joint_count, frame_count, 0, 0],
+        params: glam::vec4(animation.frame_rate, 0.0, 0.0, 0.0),
+      }],
+    )?;
+
+    // Each joint takes the top 3 rows of its matrix as 3 texels, one frame per texture row
+    let bone_texels = animation
+      .matrices
+      .iter()
+      .flat_map(|matrix| {
+        let rows = matrix.transpose();
+        [rows.x_axis, rows.y_axis, rows.z_axis]
+      })
+      .collect::<Vec<_>>();
+    let bone_image = AdImage::new_2d_from_data(
+      ash_device.clone(),
+      self.allocator.clone(),
+      &format!("{name}_bones"),
+      AdImage2dDesc {
+        format: vk::Format::R32G32B32A32_SFLOAT,
+        resolution: vk::Extent2D { width: animation.joint_count * 3, height: frame_count },
+        usage: vk::ImageUsageFlags::SAMPLED,
+        mip_levels: 1,
+      },
+      &[AdBuffer::get_byte_slice(&bone_texels)],
+      AdImageUpload {
+        mem_location: MemoryLocation::GpuOnly,
+        cmd_buffer: &cmd_buffer,
+        init_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+      },
+    )?;
+    let bone_image_view = AdImageView::create_view(
+      bone_image,
+      vk::ImageViewType::TYPE_2D,
+      vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+      },
+    )?;
+
+    let crowd_dset = self
+      .crowd_dset_pools
+      .allocate(&[(
+        self.crowd_dset_layout.clone(),
+        vec![
+          AdDescriptorBinding::StorageBuffer(Arc::new(vert_buffer)),
+          AdDescriptorBinding::StorageBuffer(Arc::new(indx_buffer)),
+          AdDescriptorBinding::StorageBuffer(Arc::new(instance_buffer)),
+          AdDescriptorBinding::Image2D((
+            bone_image_view,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          )),
+          AdDescriptorBinding::Sampler(self.sampler.clone()),
+          AdDescriptorBinding::UniformBuffer(Arc::new(crowd_buffer)),
+        ],
+      )])?
+      .remove(0);
+
+    Ok(CrowdGPU {
+      dset: Arc::new(crowd_dset),
+      indx_count: mesh.triangles.len() * 3,
+      max_instances,
+      instance_count: AtomicU32::new(0),
+      material,
+    })
+  }
+}
@@ -1,4 +1,8 @@
-use std::sync::{Arc, Mutex};
+use std::{
+  collections::HashMap,
+  hash::{DefaultHasher, Hash, Hasher},
+  sync::{Arc, Mutex, Weak},
+};
 
 use ash_ad_wrappers::{
   ash_context::{
@@ -7,49 +11,418 @@ use ash_ad_wrappers::{
     gpu_allocator::{vulkan::Allocator, MemoryLocation},
   },
   ash_data_wrappers::{
-    AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout, AdImage,
-    AdImageView, AdSampler,
+    image::{self, ColorType, DynamicImage, RgbaImage},
+    AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout, AdImage,
+    AdImage2dDesc, AdImageUpload, AdImageView, AdSampler,
   },
-  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool, AdQueue},
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
 };
+use asset_cache::ContentHasher;
+use serde::Deserialize;
+
+use crate::sampler_cache::{SamplerCache, SamplerDesc};
+use crate::texture_compression::CompressedFlatTexture;
 
 static FLAT_TEX_ALBEDO_DEFAULT: &[u8] = include_bytes!("flat_texture/albedo_default.png");
+// Decoded textures in the asset cache. The version is bumped whenever decoding or the artifact
+// layout changes, so textures cached by older builds miss and get decoded again
+const TEXTURE_CACHE_KIND: &str = "flat_texture";
+const TEXTURE_CACHE_VERSION: u32 = 1;
+
+// How a texture's texels are meant to be read. Colors authored for display like albedo are sRGB
+// encoded and get decoded to linear by the sampler, data like normals or masks is used as stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureColorSpace {
+  Srgb,
+  Linear,
+}
+
+impl TextureColorSpace {
+  pub fn rgba8_format(&self) -> vk::Format {
+    match self {
+      Self::Srgb => vk::Format::R8G8B8A8_SRGB,
+      Self::Linear => vk::Format::R8G8B8A8_UNORM,
+    }
+  }
+}
+
+// What sampling outside 0..1 reads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum TextureAddressMode {
+  #[default]
+  Repeat,
+  ClampToEdge,
+  MirroredRepeat,
+}
+
+impl TextureAddressMode {
+  pub fn vk_address_mode(&self) -> vk::SamplerAddressMode {
+    match self {
+      Self::Repeat => vk::SamplerAddressMode::REPEAT,
+      Self::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+      Self::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+    }
+  }
+}
+
+// How texels between texel centers and between mips are read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum TextureFilter {
+  // The closest texel of the closest mip
+  #[default]
+  Nearest,
+  // Blended from the closest four texels of the two closest mips
+  Linear,
+}
+
+impl TextureFilter {
+  pub fn vk_filter(&self) -> vk::Filter {
+    match self {
+      Self::Nearest => vk::Filter::NEAREST,
+      Self::Linear => vk::Filter::LINEAR,
+    }
+  }
+}
+
+// How a texture file is imported, from the .meta file next to it. Left out settings keep what
+// the texture was asked to load with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default)]
+pub struct TextureImportSettings {
+  // false for data like normal maps that was saved from an image editor as a color
+  pub srgb: Option<bool>,
+  // Without it large textures get streamed mips and smaller ones a single level. true always
+  // uploads the whole chain, false only ever the full size level
+  pub mips: Option<bool>,
+  pub address_mode: TextureAddressMode,
+  pub filter: TextureFilter,
+  // Up to 16 samples along surfaces seen at a grazing angle, clamped to what the gpu can. Keeps
+  // floors and roads sharp into the distance. 0 and 1 leave it off
+  pub anisotropy: u32,
+}
+
+impl TextureImportSettings {
+  pub fn color_space(&self, requested: TextureColorSpace) -> TextureColorSpace {
+    match self.srgb {
+      Some(true) => TextureColorSpace::Srgb,
+      Some(false) => TextureColorSpace::Linear,
+      None => requested,
+    }
+  }
+
+  pub fn sampler(&self) -> SamplerDesc {
+    SamplerDesc {
+      filter: self.filter,
+      address_mode: self.address_mode,
+      anisotropy: self.anisotropy,
+      lod_bias: 0.0,
+    }
+  }
+}
+
+// How texels are stored on the gpu. Textures with fewer channels are spread over rgb by their
+// view, so shaders sample every format the same
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureFormat {
+  Rgba8,
+  // Grayscale masks
+  R8,
+  // Grayscale with alpha
+  Rg8,
+  // Heightmaps and other 16 bit data
+  R16,
+  // HDR and EXR images, always linear
+  Rgba16F,
+}
+
+impl TextureFormat {
+  const ALL: [Self; 5] = [Self::Rgba8, Self::R8, Self::Rg8, Self::R16, Self::Rgba16F];
+
+  fn cache_tag(&self) -> u8 {
+    Self::ALL.iter().position(|format| format == self).unwrap_or(0) as u8
+  }
+
+  // Keeps what the file has where a format fits it. Only linear textures get fewer channels,
+  // single channel sRGB formats aren't always sampleable
+  pub fn for_image(image: &DynamicImage, color_space: TextureColorSpace) -> Self {
+    match (image.color(), color_space) {
+      (ColorType::Rgb32F | ColorType::Rgba32F, _) => Self::Rgba16F,
+      (ColorType::L8, TextureColorSpace::Linear) => Self::R8,
+      (ColorType::La8, TextureColorSpace::Linear) => Self::Rg8,
+      (ColorType::L16, TextureColorSpace::Linear) => Self::R16,
+      _ => Self::Rgba8,
+    }
+  }
+
+  pub fn vk_format(&self, color_space: TextureColorSpace) -> Result<vk::Format, String> {
+    match (self, color_space) {
+      (Self::Rgba8, color_space) => Ok(color_space.rgba8_format()),
+      (Self::Rgba16F, _) => Ok(vk::Format::R16G16B16A16_SFLOAT),
+      (Self::R8, TextureColorSpace::Linear) => Ok(vk::Format::R8_UNORM),
+      (Self::Rg8, TextureColorSpace::Linear) => Ok(vk::Format::R8G8_UNORM),
+      (Self::R16, TextureColorSpace::Linear) => Ok(vk::Format::R16_UNORM),
+      (format, TextureColorSpace::Srgb) => Err(format!("{format:?} textures have to be linear")),
+    }
+  }
+
+  fn components(&self) -> vk::ComponentMapping {
+    let (r, g, b, a) = match self {
+      Self::R8 | Self::R16 => (
+        vk::ComponentSwizzle::R,
+        vk::ComponentSwizzle::R,
+        vk::ComponentSwizzle::R,
+        vk::ComponentSwizzle::ONE,
+      ),
+      Self::Rg8 => (
+        vk::ComponentSwizzle::R,
+        vk::ComponentSwizzle::R,
+        vk::ComponentSwizzle::R,
+        vk::ComponentSwizzle::G,
+      ),
+      Self::Rgba8 | Self::Rgba16F => (
+        vk::ComponentSwizzle::R,
+        vk::ComponentSwizzle::G,
+        vk::ComponentSwizzle::B,
+        vk::ComponentSwizzle::A,
+      ),
+    };
+    vk::ComponentMapping { r, g, b, a }
+  }
+
+  // Channels per texel and bytes per channel
+  fn channel_layout(&self) -> (usize, usize) {
+    match self {
+      Self::Rgba8 => (4, 1),
+      Self::R8 => (1, 1),
+      Self::Rg8 => (2, 1),
+      Self::R16 => (1, 2),
+      Self::Rgba16F => (4, 2),
+    }
+  }
+
+  pub fn texel_bytes(&self) -> usize {
+    let (channels, channel_bytes) = self.channel_layout();
+    channels * channel_bytes
+  }
+
+  fn read_channel(&self, bytes: &[u8]) -> f32 {
+    match self {
+      Self::Rgba8 | Self::R8 | Self::Rg8 => bytes[0] as f32,
+      Self::R16 => u16::from_ne_bytes([bytes[0], bytes[1]]) as f32,
+      Self::Rgba16F => half::f16::from_ne_bytes([bytes[0], bytes[1]]).to_f32(),
+    }
+  }
+
+  fn write_channel(&self, value: f32, out: &mut Vec<u8>) {
+    match self {
+      Self::Rgba8 | Self::R8 | Self::Rg8 => out.push(value.round() as u8),
+      Self::R16 => out.extend((value.round() as u16).to_ne_bytes()),
+      Self::Rgba16F => out.extend(half::f16::from_f32(value).to_ne_bytes()),
+    }
+  }
+
+  // Box filters texels to the next mip level. Values are averaged as stored, so sRGB colors come
+  // out a little dark, which is hard to see at the distances smaller mips are used at
+  fn downsample(&self, extent: vk::Extent2D, texels: &[u8]) -> (vk::Extent2D, Vec<u8>) {
+    let (channels, channel_bytes) = self.channel_layout();
+    let texel_bytes = channels * channel_bytes;
+    let half_extent = mip_extent(extent, 1);
+    let mut out =
+      Vec::with_capacity((half_extent.width * half_extent.height) as usize * texel_bytes);
+    for y in 0..half_extent.height {
+      for x in 0..half_extent.width {
+        // Odd sizes repeat the last row or column
+        let xs = [(x * 2).min(extent.width - 1), (x * 2 + 1).min(extent.width - 1)];
+        let ys = [(y * 2).min(extent.height - 1), (y * 2 + 1).min(extent.height - 1)];
+        for channel in 0..channels {
+          let mut sum = 0.0;
+          for (sx, sy) in [(xs[0], ys[0]), (xs[1], ys[0]), (xs[0], ys[1]), (xs[1], ys[1])] {
+            let offset = (sy * extent.width + sx) as usize * texel_bytes + channel * channel_bytes;
+            sum += self.read_channel(&texels[offset..offset + channel_bytes]);
+          }
+          self.write_channel(sum / 4.0, &mut out);
+        }
+      }
+    }
+    (half_extent, out)
+  }
+
+  // Tightly packed, converted from whatever the image has
+  fn texels(&self, image: &DynamicImage) -> Vec<u8> {
+    match self {
+      Self::Rgba8 => image.to_rgba8().into_raw(),
+      Self::R8 => image.to_luma8().into_raw(),
+      Self::Rg8 => image.to_luma_alpha8().into_raw(),
+      Self::R16 => image.to_luma16().iter().flat_map(|texel| texel.to_ne_bytes()).collect(),
+      Self::Rgba16F => image
+        .to_rgba32f()
+        .iter()
+        .flat_map(|texel| half::f16::from_f32(*texel).to_ne_bytes())
+        .collect(),
+    }
+  }
+}
+
+// Levels a full mip chain of a texture this size has, down to 1x1
+pub fn mip_count(resolution: vk::Extent2D) -> u32 {
+  32 - resolution.width.max(resolution.height).max(1).leading_zeros()
+}
+
+pub fn mip_extent(resolution: vk::Extent2D, level: u32) -> vk::Extent2D {
+  vk::Extent2D {
+    width: (resolution.width >> level).max(1),
+    height: (resolution.height >> level).max(1),
+  }
+}
+
+// Texels ready for upload with a hash of the file bytes they came from
+pub struct DecodedFlatTexture {
+  pub content_hash: u64,
+  pub format: TextureFormat,
+  pub resolution: vk::Extent2D,
+  pub texels: Vec<u8>,
+}
+
+impl DecodedFlatTexture {
+  // Without a format the one the file fits best is picked, see TextureFormat::for_image
+  pub fn decode(
+    file_bytes: &[u8],
+    color_space: TextureColorSpace,
+    format: Option<TextureFormat>,
+  ) -> Result<Self, String> {
+    let mut hasher = DefaultHasher::new();
+    file_bytes.hash(&mut hasher);
+    let image =
+      image::load_from_memory(file_bytes).map_err(|e| format!("at decoding image: {e}"))?;
+    let format = format.unwrap_or_else(|| TextureFormat::for_image(&image, color_space));
+    Ok(Self {
+      content_hash: hasher.finish(),
+      format,
+      resolution: vk::Extent2D { width: image.width(), height: image.height() },
+      texels: format.texels(&image),
+    })
+  }
+
+  // Levels from first_mip down to 1x1, each made from the one before it
+  pub fn mips(&self, first_mip: u32) -> Vec<(vk::Extent2D, Vec<u8>)> {
+    let mut mips = vec![];
+    let mut level = (self.resolution, self.texels.clone());
+    for mip in 0..mip_count(self.resolution) {
+      let next =
+        (mip + 1 < mip_count(self.resolution)).then(|| self.format.downsample(level.0, &level.1));
+      if mip >= first_mip {
+        mips.push(level);
+      }
+      let Some(next) = next else { break };
+      level = next;
+    }
+    mips
+  }
+
+  // Artifact layout, integers little endian: content hash u64, format u8, width u32, height u32,
+  // then the texels
+  fn to_cache_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(17 + self.texels.len());
+    bytes.extend(self.content_hash.to_le_bytes());
+    bytes.push(self.format.cache_tag());
+    bytes.extend(self.resolution.width.to_le_bytes());
+    bytes.extend(self.resolution.height.to_le_bytes());
+    bytes.extend(&self.texels);
+    bytes
+  }
+
+  fn from_cache_bytes(mut bytes: Vec<u8>) -> Option<Self> {
+    if bytes.len() < 17 {
+      return None;
+    }
+    let content_hash = u64::from_le_bytes(bytes[0..8].try_into().ok()?);
+    let format = *TextureFormat::ALL.get(bytes[8] as usize)?;
+    let width = u32::from_le_bytes(bytes[9..13].try_into().ok()?);
+    let height = u32::from_le_bytes(bytes[13..17].try_into().ok()?);
+    let texels = bytes.split_off(17);
+    if texels.len() != width as usize * height as usize * format.texel_bytes() {
+      return None;
+    }
+    Some(Self { content_hash, format, resolution: vk::Extent2D { width, height }, texels })
+  }
+
+  // Reads through the global vfs, path is a vfs path. What decoding makes is kept in the global
+  // asset cache, keyed by the file's bytes and how it's read
+  pub fn load(
+    path: &str,
+    color_space: TextureColorSpace,
+    format: Option<TextureFormat>,
+  ) -> Result<Self, String> {
+    let file_bytes = vfs::global().read(path)?;
+    let cache_hash = ContentHasher::new()
+      .update(&TEXTURE_CACHE_VERSION.to_le_bytes())
+      .update(&file_bytes)
+      .update(&[color_space as u8, format.map_or(u8::MAX, |format| format.cache_tag())])
+      .finish();
+    let cache = asset_cache::global();
+    if let Some(cached) =
+      cache.load(TEXTURE_CACHE_KIND, cache_hash).and_then(Self::from_cache_bytes)
+    {
+      return Ok(cached);
+    }
+    let decoded = Self::decode(&file_bytes, color_space, format)?;
+    cache.store(TEXTURE_CACHE_KIND, cache_hash, &decoded.to_cache_bytes());
+    Ok(decoded)
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlatTextureDedupStats {
+  pub uploaded_textures: u64,
+  // Uploads that got a texture already on the gpu with the same file contents
+  pub deduplicated_textures: u64,
+  pub deduplicated_bytes: u64,
+}
 
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct FlatTextureGPU {
   #[getset(get = "pub")]
   dset: Arc<AdDescriptorSet>,
+  #[getset(get = "pub")]
+  image_view: Arc<AdImageView>,
+  #[getset(get = "pub")]
+  sampler: Arc<AdSampler>,
 }
 
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct FlatTextureGenerator {
   #[getset(get = "pub")]
   tex_dset_layout: Arc<AdDescriptorSetLayout>,
-  tex_dset_pool: Arc<AdDescriptorPool>,
+  #[getset(get = "pub")]
+  tex_dset_pools: AdDescriptorPoolManager,
+  // What textures get unless their import settings say otherwise
+  #[getset(get = "pub")]
   sampler: Arc<AdSampler>,
+  #[getset(get = "pub")]
+  sampler_cache: SamplerCache,
   allocator: Arc<Mutex<Allocator>>,
   cmd_pool: Arc<AdCommandPool>,
   default_texture: Arc<FlatTextureGPU>,
+  // Only held weakly, a texture is uploaded again once everything using it is gone
+  by_content:
+    HashMap<(u64, TextureColorSpace, TextureFormat, TextureImportSettings), Weak<FlatTextureGPU>>,
+  #[getset(get_copy = "pub")]
+  dedup_stats: FlatTextureDedupStats,
 }
 
 impl FlatTextureGenerator {
-  pub fn new(allocator: Arc<Mutex<Allocator>>, queue: Arc<AdQueue>) -> Result<Self, String> {
-    let ash_device = queue.ash_device().clone();
-    let dset_pool = Arc::new(AdDescriptorPool::new(
-      ash_device.clone(),
-      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
-      1000,
-      &[vk::DescriptorPoolSize {
-        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-        descriptor_count: 1000,
-      }],
-    )?);
+  pub fn new(
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_pool: Arc<AdCommandPool>,
+  ) -> Result<Self, String> {
+    let ash_device = cmd_pool.queue().ash_device().clone();
+    let dset_pools = AdDescriptorPoolManager::new(ash_device.clone(), "flat_texture", 64);
     let dset_layout = Arc::new(AdDescriptorSetLayout::new(
       ash_device.clone(),
       &[(vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)],
     )?);
-    let cmd_pool = Arc::new(AdCommandPool::new(queue, vk::CommandPoolCreateFlags::TRANSIENT)?);
-    let sampler = Arc::new(AdSampler::new(ash_device.clone())?);
+    let sampler_cache = SamplerCache::new(ash_device.clone());
+    let sampler = sampler_cache.get(SamplerDesc::default())?;
 
     // Upload default Flat Texture
 
@@ -77,46 +450,140 @@ impl FlatTextureGenerator {
       },
     )?;
 
-    let tex_dset = AdDescriptorSet::new(
-      dset_pool.clone(),
-      &[(
+    let tex_dset = dset_pools
+      .allocate(&[(
         dset_layout.clone(),
         vec![AdDescriptorBinding::Sampler2D((
-          tex_image_view,
+          tex_image_view.clone(),
           vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
           sampler.clone(),
         ))],
-      )],
-    )?
+      )])?
       .remove(0);
 
-    let default_tex = Arc::new(FlatTextureGPU { dset: Arc::new(tex_dset) });
+    let default_tex = Arc::new(FlatTextureGPU {
+      dset: Arc::new(tex_dset),
+      image_view: tex_image_view,
+      sampler: sampler.clone(),
+    });
 
     Ok(Self {
       allocator,
       cmd_pool,
       sampler,
-      tex_dset_pool: dset_pool,
+      sampler_cache,
+      tex_dset_pools: dset_pools,
       tex_dset_layout: dset_layout,
       default_texture: default_tex,
+      by_content: HashMap::new(),
+      dedup_stats: FlatTextureDedupStats::default(),
     })
   }
 
-  pub fn upload_flat_texture(&self, name: &str, path: &str) -> Result<FlatTextureGPU, String> {
+  // Files with the same bytes share one gpu texture whatever name they were loaded under, as
+  // long as they are read in the same color space with the same import settings. Only a full
+  // mip chain or a single level, streamed textures are uploaded with upload_flat_texture_mips
+  pub fn upload_deduplicated(
+    &mut self,
+    name: &str,
+    decoded: &DecodedFlatTexture,
+    color_space: TextureColorSpace,
+    settings: TextureImportSettings,
+  ) -> Result<Arc<FlatTextureGPU>, String> {
+    let key = (decoded.content_hash, color_space, decoded.format, settings);
+    if let Some(existing) = self.by_content.get(&key).and_then(|tex| tex.upgrade()) {
+      self.dedup_stats.deduplicated_textures += 1;
+      self.dedup_stats.deduplicated_bytes += decoded.texels.len() as u64;
+      return Ok(existing);
+    }
+    let uploaded = match settings.mips {
+      Some(true) => self.upload_flat_texture_mips(
+        name,
+        decoded.format,
+        color_space,
+        settings.sampler(),
+        &decoded.mips(0),
+      )?,
+      _ => self.upload_flat_texture_level(
+        name,
+        decoded.format,
+        color_space,
+        settings.sampler(),
+        decoded.resolution,
+        &decoded.texels,
+      )?,
+    };
+    let uploaded = Arc::new(uploaded);
+    self.by_content.retain(|_, tex| tex.strong_count() > 0);
+    self.by_content.insert(key, Arc::downgrade(&uploaded));
+    self.dedup_stats.uploaded_textures += 1;
+    Ok(uploaded)
+  }
+
+  pub fn upload_flat_texture_rgba8(
+    &self,
+    name: &str,
+    image_rgba8: &RgbaImage,
+    color_space: TextureColorSpace,
+  ) -> Result<FlatTextureGPU, String> {
+    self.upload_flat_texture_texels(
+      name,
+      TextureFormat::Rgba8,
+      color_space,
+      vk::Extent2D { width: image_rgba8.width(), height: image_rgba8.height() },
+      image_rgba8,
+    )
+  }
+
+  // texels have to be tightly packed in format, row after row
+  pub fn upload_flat_texture_texels(
+    &self,
+    name: &str,
+    format: TextureFormat,
+    color_space: TextureColorSpace,
+    resolution: vk::Extent2D,
+    texels: &[u8],
+  ) -> Result<FlatTextureGPU, String> {
+    self.upload_flat_texture_level(
+      name,
+      format,
+      color_space,
+      SamplerDesc::default(),
+      resolution,
+      texels,
+    )
+  }
+
+  fn upload_flat_texture_level(
+    &self,
+    name: &str,
+    format: TextureFormat,
+    color_space: TextureColorSpace,
+    sampler: SamplerDesc,
+    resolution: vk::Extent2D,
+    texels: &[u8],
+  ) -> Result<FlatTextureGPU, String> {
     let ash_device = self.cmd_pool.queue().ash_device().clone();
     let cmd_buffer =
       AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
-    let tex_image = AdImage::new_2d_from_file(
+    let tex_image = AdImage::new_2d_from_data(
       ash_device.clone(),
       self.allocator.clone(),
-      MemoryLocation::GpuOnly,
       name,
-      vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
-      path,
-      &cmd_buffer,
-      vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+      AdImage2dDesc {
+        format: format.vk_format(color_space)?,
+        resolution,
+        usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        mip_levels: 1,
+      },
+      &[texels],
+      AdImageUpload {
+        mem_location: MemoryLocation::GpuOnly,
+        cmd_buffer: &cmd_buffer,
+        init_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+      },
     )?;
-    let tex_image_view = AdImageView::create_view(
+    let tex_image_view = AdImageView::create_swizzled_view(
       tex_image,
       vk::ImageViewType::TYPE_2D,
       vk::ImageSubresourceRange {
@@ -126,22 +593,112 @@ impl FlatTextureGenerator {
         base_array_layer: 0,
         layer_count: 1,
       },
+      format.components(),
+    )?;
+
+    let sampler = self.sampler_cache.get(sampler)?;
+    let tex_dset = self
+      .tex_dset_pools
+      .allocate(&[(
+        self.tex_dset_layout.clone(),
+        vec![AdDescriptorBinding::Sampler2D((
+          tex_image_view.clone(),
+          vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          sampler.clone(),
+        ))],
+      )])?
+      .remove(0);
+
+    Ok(FlatTextureGPU { dset: Arc::new(tex_dset), image_view: tex_image_view, sampler })
+  }
+
+  // mips are levels from the largest down like DecodedFlatTexture::mips makes them, for textures
+  // only part of the chain is kept on the gpu for
+  pub fn upload_flat_texture_mips(
+    &self,
+    name: &str,
+    format: TextureFormat,
+    color_space: TextureColorSpace,
+    sampler: SamplerDesc,
+    mips: &[(vk::Extent2D, Vec<u8>)],
+  ) -> Result<FlatTextureGPU, String> {
+    self.upload_mip_chain(name, format.vk_format(color_space)?, format.components(), sampler, mips)
+  }
+
+  // Compressed mips go up as they are and are sampled as the rgba the blocks decode to
+  pub fn upload_compressed(
+    &self,
+    name: &str,
+    compressed: &CompressedFlatTexture,
+    color_space: TextureColorSpace,
+    sampler: SamplerDesc,
+  ) -> Result<FlatTextureGPU, String> {
+    self.upload_mip_chain(
+      name,
+      compressed.format.vk_format(color_space),
+      vk::ComponentMapping::default(),
+      sampler,
+      &compressed.mips,
+    )
+  }
+
+  fn upload_mip_chain(
+    &self,
+    name: &str,
+    vk_format: vk::Format,
+    components: vk::ComponentMapping,
+    sampler: SamplerDesc,
+    mips: &[(vk::Extent2D, Vec<u8>)],
+  ) -> Result<FlatTextureGPU, String> {
+    let ash_device = self.cmd_pool.queue().ash_device().clone();
+    let cmd_buffer =
+      AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+    let (resolution, _) = mips.first().ok_or("no mip levels to upload")?;
+    let mip_data = mips.iter().map(|(_, texels)| texels.as_slice()).collect::<Vec<_>>();
+    let tex_image = AdImage::new_2d_from_data(
+      ash_device,
+      self.allocator.clone(),
+      name,
+      AdImage2dDesc {
+        format: vk_format,
+        resolution: *resolution,
+        usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        mip_levels: mips.len() as u32,
+      },
+      &mip_data,
+      AdImageUpload {
+        mem_location: MemoryLocation::GpuOnly,
+        cmd_buffer: &cmd_buffer,
+        init_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+      },
+    )?;
+    let tex_image_view = AdImageView::create_swizzled_view(
+      tex_image,
+      vk::ImageViewType::TYPE_2D,
+      vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: mips.len() as u32,
+        base_array_layer: 0,
+        layer_count: 1,
+      },
+      components,
     )?;
 
-    let tex_dset = AdDescriptorSet::new(
-      self.tex_dset_pool.clone(),
-      &[(
+    let sampler = self.sampler_cache.get(sampler)?;
+    let tex_dset = self
+      .tex_dset_pools
+      .allocate(&[(
         self.tex_dset_layout.clone(),
         vec![AdDescriptorBinding::Sampler2D((
-          tex_image_view,
+          tex_image_view.clone(),
           vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-          self.sampler.clone(),
+          sampler.clone(),
         ))],
-      )],
-    )?
-    .remove(0);
+      )])?
+      .remove(0);
 
-    Ok(FlatTextureGPU { dset: Arc::new(tex_dset) })
+    Ok(FlatTextureGPU { dset: Arc::new(tex_dset), image_view: tex_image_view, sampler })
   }
 
   pub fn get_default_texture(&self) -> Arc<FlatTextureGPU> {
@@ -0,0 +1,511 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+  },
+  ash_data_wrappers::{
+    image::{Rgba, RgbaImage},
+    AdBuffer, AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout,
+    AdSampler,
+  },
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
+};
+
+use crate::{
+  flat_texture::{FlatTextureGPU, FlatTextureGenerator, TextureColorSpace},
+  material::{MaterialCPU, MaterialGPU, MaterialGenerator, ShaderVariant},
+  triangle_mesh::{g_vec4_from_vec3, TriMeshCPU, TriMeshVertex},
+};
+
+// Instances are sorted into square cells this wide on xz, cells are culled by distance together
+const FOLIAGE_CELL_SIZE: f32 = 8.0;
+// Scatters stop adding instances past this
+const MAX_SCATTER_INSTANCES: usize = 1 << 20;
+const WIND_NOISE_SIZE: u32 = 64;
+// Lattice cells per side of the wind noise octaves, the texture tiles with every one of them
+const WIND_NOISE_OCTAVES: [(u32, f32); 3] = [(4, 0.6), (8, 0.3), (16, 0.1)];
+const DEFAULT_BLADE_TEXTURE_SIZE: (u32, u32) = (64, 128);
+// Base x and width as fractions of the texture width, height as a fraction of its height and how
+// far the tip leans
+const DEFAULT_BLADES: [(f32, f32, f32, f32); 6] = [
+  (0.12, 0.10, 0.70, 0.05),
+  (0.28, 0.12, 0.95, -0.08),
+  (0.45, 0.14, 1.00, 0.04),
+  (0.58, 0.10, 0.80, 0.12),
+  (0.74, 0.12, 0.90, -0.05),
+  (0.88, 0.10, 0.60, -0.10),
+];
+
+// Integer hash, every input bit flips about half the output bits
+fn hash_u32(mut x: u32) -> u32 {
+  x ^= x >> 16;
+  x = x.wrapping_mul(0x7feb_352d);
+  x ^= x >> 15;
+  x = x.wrapping_mul(0x846c_a68b);
+  x ^= x >> 16;
+  x
+}
+
+fn hash_unit(x: u32) -> f32 {
+  (hash_u32(x) >> 8) as f32 / (1 << 24) as f32
+}
+
+struct ScatterRng(u32);
+
+impl ScatterRng {
+  fn next_f32(&mut self) -> f32 {
+    self.0 = self.0.wrapping_add(0x9e37_79b9);
+    hash_unit(self.0)
+  }
+
+  fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+    min + (max - min) * self.next_f32()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct FoliageInstance {
+  // xyz root of the card, w scale
+  pub pos_scale: glam::Vec4,
+  // Rotation around y in radians, density rank, sway phase in radians, unused. Instances with a
+  // higher rank thin out first with distance
+  pub params: glam::Vec4,
+}
+
+impl FoliageInstance {
+  pub fn new(pos: glam::Vec3, yaw: f32, scale: f32, density_rank: f32) -> Self {
+    Self {
+      pos_scale: pos.extend(scale),
+      params: glam::vec4(yaw, density_rank, density_rank * std::f32::consts::TAU * 7.0, 0.0),
+    }
+  }
+
+  pub fn pos(&self) -> glam::Vec3 {
+    self.pos_scale.truncate()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScatterParams {
+  // Instances per square unit of surface
+  pub density: f32,
+  // Faces whose normal's y is under this are left bare, 0.7 is about 45 degrees
+  pub min_up: f32,
+  pub scale_range: [f32; 2],
+  pub seed: u32,
+}
+
+impl Default for ScatterParams {
+  fn default() -> Self {
+    Self { density: 8.0, min_up: 0.7, scale_range: [0.7, 1.3], seed: 0 }
+  }
+}
+
+// Where the cards of a foliage go, scattered over surfaces or placed by hand
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FoliageScatter {
+  pub instances: Vec<FoliageInstance>,
+}
+
+impl FoliageScatter {
+  // Spreads instances over the faces of the mesh placed by transform, area weighted so density
+  // is even across triangles of different sizes. The same seed gives the same scatter
+  pub fn on_mesh(mesh: &TriMeshCPU, transform: glam::Mat4, params: &ScatterParams) -> Self {
+    let mut scatter = Self::default();
+    scatter.add_mesh(mesh, transform, params);
+    scatter
+  }
+
+  pub fn add_mesh(&mut self, mesh: &TriMeshCPU, transform: glam::Mat4, params: &ScatterParams) {
+    let mut rng = ScatterRng(hash_u32(params.seed));
+    for triangle in mesh.triangles.iter() {
+      let [a, b, c] =
+        triangle.map(|idx| transform.transform_point3(mesh.vertices[idx as usize].pos.truncate()));
+      let cross = (b - a).cross(c - a);
+      let area = cross.length() / 2.0;
+      if area <= 0.0 || cross.normalize().y < params.min_up {
+        continue;
+      }
+      let expected = area * params.density;
+      let count = expected as u32 + (rng.next_f32() < expected.fract()) as u32;
+      for _ in 0..count {
+        if self.instances.len() >= MAX_SCATTER_INSTANCES {
+          return;
+        }
+        // Uniform over the triangle
+        let r1 = rng.next_f32().sqrt();
+        let r2 = rng.next_f32();
+        let pos = a * (1.0 - r1) + b * (r1 * (1.0 - r2)) + c * (r1 * r2);
+        self.instances.push(FoliageInstance::new(
+          pos,
+          rng.range_f32(0.0, std::f32::consts::TAU),
+          rng.range_f32(params.scale_range[0], params.scale_range[1]),
+          rng.next_f32(),
+        ));
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FoliageParams {
+  // Instances start thinning out past this distance from the camera
+  pub fade_start: f32,
+  // None are drawn past this
+  pub max_distance: f32,
+  // How far card tips move with the wind, in units at full wind strength
+  pub sway: f32,
+}
+
+impl Default for FoliageParams {
+  fn default() -> Self {
+    Self { fade_start: 20.0, max_distance: 40.0, sway: 0.25 }
+  }
+}
+
+// Shared by every foliage, set once for the scene
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindParams {
+  // On the xz plane
+  pub direction: glam::Vec2,
+  pub strength: f32,
+  // Units per second the gusts travel
+  pub speed: f32,
+  // Size of a gust in world units
+  pub gust_size: f32,
+}
+
+impl Default for WindParams {
+  fn default() -> Self {
+    Self { direction: glam::vec2(1.0, 0.3), strength: 1.0, speed: 3.0, gust_size: 12.0 }
+  }
+}
+
+pub struct FoliageCPU {
+  // Root at the origin, growing up +y. Only its height is swayed, the base stays put
+  pub card: TriMeshCPU,
+  pub scatter: FoliageScatter,
+  pub params: FoliageParams,
+}
+
+// Quads of width by height crossed around the y axis, the usual grass or bush card
+pub fn make_foliage_card(width: f32, height: f32, planes: u32) -> TriMeshCPU {
+  let planes = planes.max(1);
+  TriMeshCPU::combine(
+    (0..planes)
+      .map(|plane| {
+        let angle = plane as f32 * std::f32::consts::PI / planes as f32;
+        let tangent = glam::vec3(angle.cos(), 0.0, -angle.sin()) * (width / 2.0);
+        let normal = g_vec4_from_vec3(tangent.cross(glam::Vec3::Y).normalize(), 0.0);
+        let up = glam::Vec3::Y * height;
+        let corners = [
+          (-tangent, 0.0, 1.0),
+          (tangent, 1.0, 1.0),
+          (tangent + up, 1.0, 0.0),
+          (up - tangent, 0.0, 0.0),
+        ];
+        TriMeshCPU::new(
+          corners
+            .iter()
+            .map(|(pos, u, v)| TriMeshVertex {
+              pos: g_vec4_from_vec3(*pos, 1.0),
+              normal,
+              uv: glam::vec4(*u, *v, 0.0, 0.0),
+            })
+            .collect(),
+          vec![[0, 1, 2], [2, 3, 0]],
+        )
+      })
+      .collect(),
+  )
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct FoliageData {
+  // fade start, max distance, sway, card height
+  params: glam::Vec4,
+}
+
+// Bounds of a run of instances sorted into the same cell
+#[derive(Debug, Clone, Copy)]
+struct FoliageCell {
+  center: glam::Vec3,
+  radius: f32,
+  first_instance: u32,
+  instance_count: u32,
+}
+
+// Every instance shares the card and the material, visible cells are drawn as instance ranges
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct FoliageGPU {
+  #[getset(get = "pub")]
+  dset: Arc<AdDescriptorSet>,
+  #[getset(get_copy = "pub")]
+  indx_count: usize,
+  cells: Vec<FoliageCell>,
+  max_distance: f32,
+  // Kept alive for as long as the foliage refers to it
+  #[getset(get = "pub")]
+  material: Arc<MaterialGPU>,
+}
+
+impl FoliageGPU {
+  // First instance and count of the runs of cells within max distance, next to each other runs
+  // are merged into one
+  pub fn visible_ranges(&self, camera_pos: glam::Vec3) -> Vec<(u32, u32)> {
+    let mut ranges: Vec<(u32, u32)> = vec![];
+    for cell in self.cells.iter() {
+      if cell.center.distance(camera_pos) - cell.radius > self.max_distance {
+        continue;
+      }
+      match ranges.last_mut() {
+        Some((first, count)) if *first + *count == cell.first_instance => {
+          *count += cell.instance_count
+        }
+        _ => ranges.push((cell.first_instance, cell.instance_count)),
+      }
+    }
+    ranges
+  }
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct FoliageGenerator {
+  allocator: Arc<Mutex<Allocator>>,
+  cmd_pool: Arc<AdCommandPool>,
+  // Linear so gusts move smoothly over the noise texels
+  noise_sampler: Arc<AdSampler>,
+  wind_noise: Arc<FlatTextureGPU>,
+  #[getset(get = "pub")]
+  foliage_dset_pools: AdDescriptorPoolManager,
+  #[getset(get = "pub")]
+  foliage_dset_layout: Arc<AdDescriptorSetLayout>,
+  // Alpha cutout grass blades, for foliage created without a material
+  #[getset(get = "pub")]
+  default_material: Arc<MaterialGPU>,
+}
+
+impl FoliageGenerator {
+  pub fn new(
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_pool: Arc<AdCommandPool>,
+    flat_tex_gen: &FlatTextureGenerator,
+    material_gen: &MaterialGenerator,
+  ) -> Result<Self, String> {
+    let ash_device = cmd_pool.queue().ash_device().clone();
+    let dset_pools = AdDescriptorPoolManager::new(ash_device.clone(), "foliage", 16);
+    let dset_layout = AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::UNIFORM_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::SAMPLER),
+      ],
+    )?;
+    let noise_sampler = AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::REPEAT)
+        .address_mode_v(vk::SamplerAddressMode::REPEAT),
+    )?;
+    let wind_noise = flat_tex_gen.upload_flat_texture_rgba8(
+      "foliage_wind_noise",
+      &Self::make_wind_noise(),
+      TextureColorSpace::Linear,
+    )?;
+    let blade_texture = flat_tex_gen.upload_flat_texture_rgba8(
+      "foliage_default_blades",
+      &Self::make_default_blades(),
+      TextureColorSpace::Srgb,
+    )?;
+    let default_material = material_gen.create_material(
+      "foliage_default_material",
+      &MaterialCPU {
+        variant: ShaderVariant::AlphaCutout,
+        double_sided: true,
+        ..Default::default()
+      },
+      Arc::new(blade_texture),
+      None,
+    )?;
+    Ok(Self {
+      allocator,
+      cmd_pool,
+      noise_sampler: Arc::new(noise_sampler),
+      wind_noise: Arc::new(wind_noise),
+      foliage_dset_pools: dset_pools,
+      foliage_dset_layout: Arc::new(dset_layout),
+      default_material: Arc::new(default_material),
+    })
+  }
+
+  // Tiling value noise, smoothly interpolated lattice values of a few octaves
+  fn make_wind_noise() -> RgbaImage {
+    let size = WIND_NOISE_SIZE;
+    RgbaImage::from_fn(size, size, |x, y| {
+      let value =
+        WIND_NOISE_OCTAVES.iter().enumerate().fold(0.0, |value, (octave, (cells, amp))| {
+          let lattice =
+            |lx: u32, ly: u32| hash_unit((octave as u32) << 24 | (ly % cells) << 12 | (lx % cells));
+          let pos = glam::vec2(x as f32, y as f32) * *cells as f32 / size as f32;
+          let (lx, ly) = (pos.x as u32, pos.y as u32);
+          let t = pos.fract();
+          let t = t * t * (3.0 - 2.0 * t);
+          let top = lattice(lx, ly) + (lattice(lx + 1, ly) - lattice(lx, ly)) * t.x;
+          let bottom = lattice(lx, ly + 1) + (lattice(lx + 1, ly + 1) - lattice(lx, ly + 1)) * t.x;
+          value + (top + (bottom - top) * t.y) * amp
+        });
+      let value = (value.clamp(0.0, 1.0) * 255.0) as u8;
+      Rgba([value, value, value, 255])
+    })
+  }
+
+  // Tapering blades, darker at the root
+  fn make_default_blades() -> RgbaImage {
+    let (width, height) = DEFAULT_BLADE_TEXTURE_SIZE;
+    RgbaImage::from_fn(width, height, |x, y| {
+      let u = (x as f32 + 0.5) / width as f32;
+      let h = 1.0 - (y as f32 + 0.5) / height as f32;
+      let on_blade = DEFAULT_BLADES.iter().any(|(base, blade_width, blade_height, lean)| {
+        let along = h / blade_height;
+        let center = base + lean * along * along;
+        along <= 1.0 && (u - center).abs() < blade_width * (1.0 - along) / 2.0
+      });
+      let shade = 0.35 + 0.65 * h;
+      match on_blade {
+        true => Rgba([(70.0 * shade) as u8, (150.0 * shade) as u8, (40.0 * shade) as u8, 255]),
+        false => Rgba([40, 90, 25, 0]),
+      }
+    })
+  }
+
+  // No material uses the default blades
+  pub fn create_foliage(
+    &self,
+    name: &str,
+    foliage: &FoliageCPU,
+    material: Option<Arc<MaterialGPU>>,
+  ) -> Result<FoliageGPU, String> {
+    if foliage.card.triangles.is_empty() {
+      return Err(format!("foliage {name} card has no triangles"));
+    }
+    if foliage.scatter.instances.is_empty() {
+      return Err(format!("foliage {name} has no instances"));
+    }
+    let card_height = foliage.card.vertices.iter().fold(0.0f32, |h, vert| h.max(vert.pos.y));
+    let max_scale =
+      foliage.scatter.instances.iter().fold(0.0f32, |s, instance| s.max(instance.pos_scale.w));
+    let cell_of = |instance: &FoliageInstance| {
+      let cell = (instance.pos() / FOLIAGE_CELL_SIZE).floor();
+      (cell.x as i32, cell.z as i32)
+    };
+    let mut instances = foliage.scatter.instances.clone();
+    instances.sort_by_key(cell_of);
+    let mut cells: Vec<FoliageCell> = vec![];
+    for run in instances.chunk_by(|a, b| cell_of(a) == cell_of(b)) {
+      let (min, max) = run.iter().fold(
+        (glam::Vec3::splat(f32::MAX), glam::Vec3::splat(f32::MIN)),
+        |(min, max), instance| (min.min(instance.pos()), max.max(instance.pos())),
+      );
+      let first_instance =
+        cells.last().map(|cell| cell.first_instance + cell.instance_count).unwrap_or(0);
+      cells.push(FoliageCell {
+        center: (min + max) / 2.0,
+        radius: (max - min).length() / 2.0 + card_height * max_scale,
+        first_instance,
+        instance_count: run.len() as u32,
+      });
+    }
+
+    let ash_device = self.cmd_pool.queue().ash_device().clone();
+    let cmd_buffer =
+      AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+    let vert_buffer = AdBuffer::from_data(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("{name}_vb"),
+      vk::BufferCreateFlags::empty(),
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+      &foliage.card.vertices,
+      &cmd_buffer,
+    )?;
+    let indx_buffer = AdBuffer::from_data(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("{name}_ib"),
+      vk::BufferCreateFlags::empty(),
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+      &foliage.card.triangles,
+      &cmd_buffer,
+    )?;
+    let instance_buffer = AdBuffer::from_data(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("{name}_instb"),
+      vk::BufferCreateFlags::empty(),
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+      &instances,
+      &cmd_buffer,
+    )?;
+    let foliage_buffer = AdBuffer::new(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      &format!("{name}_fb"),
+      vk::BufferCreateFlags::empty(),
+      std::mem::size_of::<FoliageData>() as _,
+      vk::BufferUsageFlags::UNIFORM_BUFFER,
+    )?;
+    let params = &foliage.params;
+    foliage_buffer.write_data(
+      0,
+      &[FoliageData {
+        params: glam::vec4(
+          params.fade_start.min(params.max_distance),
+          params.max_distance,
+          params.sway,
+          card_height,
+        ),
+      }],
+    )?;
+
+    let foliage_dset = self
+      .foliage_dset_pools
+      .allocate(&[(
+        self.foliage_dset_layout.clone(),
+        vec![
+          AdDescriptorBinding::StorageBuffer(Arc::new(vert_buffer)),
+          AdDescriptorBinding::StorageBuffer(Arc::new(indx_buffer)),
+          AdDescriptorBinding::StorageBuffer(Arc::new(instance_buffer)),
+          AdDescriptorBinding::UniformBuffer(Arc::new(foliage_buffer)),
+          AdDescriptorBinding::Image2D((
+            self.wind_noise.image_view().clone(),
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          )),
+          AdDescriptorBinding::Sampler(self.noise_sampler.clone()),
+        ],
+      )])?
+      .remove(0);
+
+    Ok(FoliageGPU {
+      dset: Arc::new(foliage_dset),
+      indx_count: foliage.card.triangles.len() * 3,
+      cells,
+      max_distance: params.max_distance,
+      material: material.unwrap_or(self.default_material.clone()),
+    })
+  }
+}
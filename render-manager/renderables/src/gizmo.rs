@@ -0,0 +1,192 @@
+use color::Color;
+
+use crate::triangle_mesh::TriMeshCPU;
+
+// Gizmos are modelled with unit size and scaled with camera distance so they keep a constant
+// size on screen
+const GIZMO_SCREEN_SIZE: f32 = 0.15;
+const GIZMO_SHAFT_LENGTH: f32 = 0.8;
+const GIZMO_SHAFT_THICKNESS: f32 = 0.03;
+const GIZMO_RING_RADIUS: f32 = 0.8;
+const GIZMO_RING_SEGMENTS: usize = 32;
+// Handles are thin, hit tests use a wider radius so they are easier to grab
+const GIZMO_HIT_RADIUS: f32 = 0.08;
+const GIZMO_HIGHLIGHT_COLOR: Color = Color::from_srgb(1.0, 0.9, 0.2, 1.0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GizmoMode {
+  Translate,
+  Rotate,
+  Scale,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GizmoAxis {
+  X,
+  Y,
+  Z,
+}
+
+impl GizmoAxis {
+  pub const ALL: [GizmoAxis; 3] = [GizmoAxis::X, GizmoAxis::Y, GizmoAxis::Z];
+
+  pub fn dir(&self) -> glam::Vec3 {
+    match self {
+      GizmoAxis::X => glam::Vec3::X,
+      GizmoAxis::Y => glam::Vec3::Y,
+      GizmoAxis::Z => glam::Vec3::Z,
+    }
+  }
+
+  // Two directions perpendicular to the axis, in right handed order
+  fn perpendiculars(&self) -> (glam::Vec3, glam::Vec3) {
+    match self {
+      GizmoAxis::X => (glam::Vec3::Y, glam::Vec3::Z),
+      GizmoAxis::Y => (glam::Vec3::Z, glam::Vec3::X),
+      GizmoAxis::Z => (glam::Vec3::X, glam::Vec3::Y),
+    }
+  }
+
+  pub fn color(&self, highlighted: bool) -> Color {
+    if highlighted {
+      return GIZMO_HIGHLIGHT_COLOR;
+    }
+    match self {
+      GizmoAxis::X => Color::from_srgb(0.9, 0.2, 0.2, 1.0),
+      GizmoAxis::Y => Color::from_srgb(0.2, 0.9, 0.2, 1.0),
+      GizmoAxis::Z => Color::from_srgb(0.2, 0.3, 0.9, 1.0),
+    }
+  }
+}
+
+pub fn make_gizmo_handle_mesh(mode: GizmoMode, axis: GizmoAxis) -> TriMeshCPU {
+  let dir = axis.dir();
+  let (p, q) = axis.perpendiculars();
+  match mode {
+    GizmoMode::Translate | GizmoMode::Scale => {
+      let shaft = TriMeshCPU::make_cuboid(
+        dir * GIZMO_SHAFT_LENGTH / 2.0,
+        dir * GIZMO_SHAFT_LENGTH,
+        p * GIZMO_SHAFT_THICKNESS,
+        GIZMO_SHAFT_THICKNESS,
+      );
+      let head = match mode {
+        GizmoMode::Translate => TriMeshCPU::make_cuboid(dir * 0.9, dir * 0.2, p * 0.1, 0.1),
+        _ => TriMeshCPU::make_cuboid(dir * 0.9, dir * 0.12, p * 0.12, 0.12),
+      };
+      shaft.merge(head)
+    }
+    GizmoMode::Rotate => {
+      let step = std::f32::consts::TAU / GIZMO_RING_SEGMENTS as f32;
+      // Segments overlap a bit so the ring has no gaps on the outer edge
+      let segment_len = step * GIZMO_RING_RADIUS * 1.05;
+      TriMeshCPU::combine(
+        (0..GIZMO_RING_SEGMENTS)
+          .map(|i| {
+            let angle = step * i as f32;
+            let radial = p * angle.cos() + q * angle.sin();
+            let tangent = q * angle.cos() - p * angle.sin();
+            TriMeshCPU::make_cuboid(
+              radial * GIZMO_RING_RADIUS,
+              tangent * segment_len,
+              dir * GIZMO_SHAFT_THICKNESS,
+              GIZMO_SHAFT_THICKNESS,
+            )
+          })
+          .collect(),
+      )
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Gizmo {
+  pub mode: GizmoMode,
+  // Position and orientation of the gizmo, scale is ignored
+  pub transform: glam::Mat4,
+  pub highlighted: Option<GizmoAxis>,
+}
+
+impl Gizmo {
+  pub fn new(mode: GizmoMode, transform: glam::Mat4) -> Self {
+    Self { mode, transform, highlighted: None }
+  }
+
+  fn origin_and_rotation(&self) -> (glam::Vec3, glam::Quat) {
+    let (_, rotation, origin) = self.transform.to_scale_rotation_translation();
+    (origin, rotation)
+  }
+
+  pub fn screen_scale(&self, camera_pos: glam::Vec3) -> f32 {
+    let (origin, _) = self.origin_and_rotation();
+    (origin - camera_pos).length().max(0.001) * GIZMO_SCREEN_SIZE
+  }
+
+  pub fn handle_transform(&self, camera_pos: glam::Vec3) -> glam::Mat4 {
+    let (origin, rotation) = self.origin_and_rotation();
+    glam::Mat4::from_scale_rotation_translation(
+      glam::Vec3::splat(self.screen_scale(camera_pos)),
+      rotation,
+      origin,
+    )
+  }
+
+  // Returns the closest handle hit by a picking ray along with the ray distance
+  pub fn hit_test(
+    &self,
+    camera_pos: glam::Vec3,
+    ray_origin: glam::Vec3,
+    ray_dir: glam::Vec3,
+  ) -> Option<(GizmoAxis, f32)> {
+    let (origin, rotation) = self.origin_and_rotation();
+    let scale = self.screen_scale(camera_pos);
+    let ray_dir = ray_dir.normalize();
+    GizmoAxis::ALL
+      .iter()
+      .filter_map(|axis| {
+        let axis_dir = rotation * axis.dir();
+        let t = match self.mode {
+          GizmoMode::Translate | GizmoMode::Scale => {
+            let (t, dist) =
+              ray_segment_distance(ray_origin, ray_dir, origin, origin + axis_dir * scale);
+            (dist <= GIZMO_HIT_RADIUS * scale).then_some(t)?
+          }
+          GizmoMode::Rotate => {
+            let denom = ray_dir.dot(axis_dir);
+            if denom.abs() < 1e-6 {
+              return None;
+            }
+            let t = (origin - ray_origin).dot(axis_dir) / denom;
+            let ring_dist = (ray_origin + ray_dir * t - origin).length();
+            ((ring_dist - GIZMO_RING_RADIUS * scale).abs() <= GIZMO_HIT_RADIUS * scale)
+              .then_some(t)?
+          }
+        };
+        (t >= 0.0).then_some((*axis, t))
+      })
+      .min_by(|a, b| a.1.total_cmp(&b.1))
+  }
+}
+
+// Distance along the ray and the shortest distance between a ray and a segment
+fn ray_segment_distance(
+  ray_origin: glam::Vec3,
+  ray_dir: glam::Vec3,
+  seg_start: glam::Vec3,
+  seg_end: glam::Vec3,
+) -> (f32, f32) {
+  let seg_dir = seg_end - seg_start;
+  let r = ray_origin - seg_start;
+  let b = ray_dir.dot(seg_dir);
+  let c = seg_dir.dot(seg_dir);
+  let d = ray_dir.dot(r);
+  let e = seg_dir.dot(r);
+  let denom = c - b * b;
+  let mut s = if denom.abs() > 1e-6 { ((b * d - e) / -denom).clamp(0.0, 1.0) } else { 0.0 };
+  let t = (b * s - d).max(0.0);
+  // Re-project onto the segment in case the ray parameter was clamped
+  s = ((t * b + e) / c).clamp(0.0, 1.0);
+  let closest_ray = ray_origin + ray_dir * t;
+  let closest_seg = seg_start + seg_dir * s;
+  (t, (closest_ray - closest_seg).length())
+}
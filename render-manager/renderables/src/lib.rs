@@ -1,7 +1,27 @@
+pub use color;
 pub use glam;
 use glam::Vec4Swizzles;
+pub mod cloth;
+pub mod color_lut;
+pub mod crowd;
 pub mod flat_texture;
+pub mod foliage;
+pub mod gizmo;
+pub mod light;
+pub mod material;
+pub mod mesh_arena;
+pub mod multiview;
+pub mod particles;
+pub mod sampler_cache;
+pub mod reflection_probe;
+pub mod sdf_font;
+pub mod skinning;
+pub mod static_batch;
+pub mod texture_compression;
 pub mod triangle_mesh;
+pub mod water;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -26,4 +46,21 @@ impl Camera3D {
         glam::Vec3 { x: 0.0f32, y: 1.0f32, z: 0.0f32 },
       );
   }
+
+  // Ray through a point on screen for picking, screen_pos is in 0..1 with y going down.
+  // Needs the view projection matrix refreshed with the aspect ratio of the target
+  pub fn picking_ray(&self, screen_pos: glam::Vec2) -> (glam::Vec3, glam::Vec3) {
+    // Shaders flip y after the view projection, so screen top maps to +1
+    let ndc = glam::vec2(screen_pos.x * 2.0 - 1.0, 1.0 - screen_pos.y * 2.0);
+    let inv_vp = self.view_proj_mat.inverse();
+    let near = inv_vp.project_point3(glam::vec3(ndc.x, ndc.y, 0.0));
+    let far = inv_vp.project_point3(glam::vec3(ndc.x, ndc.y, 1.0));
+    (near, (far - near).normalize())
+  }
+
+  // World position of depth buffer value depth at screen_pos, same conventions as picking_ray
+  pub fn unproject(&self, screen_pos: glam::Vec2, depth: f32) -> glam::Vec3 {
+    let ndc = glam::vec2(screen_pos.x * 2.0 - 1.0, 1.0 - screen_pos.y * 2.0);
+    self.view_proj_mat.inverse().project_point3(glam::vec3(ndc.x, ndc.y, depth))
+  }
 }
@@ -0,0 +1,75 @@
+use color::Color;
+
+// Towards the sun when nothing sets one, high up and off to the side
+pub const DEFAULT_SUN_DIR: glam::Vec3 = glam::vec3(0.371391, 0.928477, 0.0);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightShape {
+  Point,
+  // Full brightness within inner_angle of direction, fading out by outer_angle. Both in radians
+  Spot { direction: glam::Vec3, inner_angle: f32, outer_angle: f32 },
+}
+
+// Lights other than the sun, only lit materials are shaded by them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalLight {
+  pub position: glam::Vec3,
+  pub shape: LightShape,
+  pub color: Color,
+  pub intensity: f32,
+  // Falls off to nothing this far from position
+  pub range: f32,
+  // Only meshes cast shadows, like with the sun
+  pub casts_shadows: bool,
+}
+
+impl Default for LocalLight {
+  fn default() -> Self {
+    Self {
+      position: glam::Vec3::ZERO,
+      shape: LightShape::Point,
+      color: Color::WHITE,
+      intensity: 1.0,
+      range: 10.0,
+      casts_shadows: false,
+    }
+  }
+}
+
+// Light from far away shading every lit material, and the ambient light filling in where it
+// doesn't reach
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunLight {
+  // Towards the sun
+  pub direction: glam::Vec3,
+  pub color: Color,
+  pub intensity: f32,
+  // Tints the ambient share of lit materials, white leaves it as the material has it
+  pub ambient: Color,
+}
+
+impl Default for SunLight {
+  fn default() -> Self {
+    Self { direction: DEFAULT_SUN_DIR, color: Color::WHITE, intensity: 1.0, ambient: Color::WHITE }
+  }
+}
+
+impl SunLight {
+  // Direction turns from one to the other instead of cutting under the arc between them
+  pub fn lerp(&self, other: &Self, t: f32) -> Self {
+    let from = self.direction.normalize_or(DEFAULT_SUN_DIR);
+    let to = other.direction.normalize_or(DEFAULT_SUN_DIR);
+    let turn = glam::Quat::IDENTITY.slerp(glam::Quat::from_rotation_arc(from, to), t);
+    Self {
+      direction: turn * from,
+      color: self.color.lerp(other.color, t),
+      intensity: self.intensity + (other.intensity - self.intensity) * t,
+      ambient: self.ambient.lerp(other.ambient, t),
+    }
+  }
+
+  // Color times intensity, what lit materials are shaded with
+  pub fn radiance(&self) -> glam::Vec3 {
+    glam::Vec4::from(self.color).truncate() * self.intensity
+  }
+}
@@ -0,0 +1,290 @@
+use std::{
+  collections::BTreeMap,
+  sync::{Arc, Mutex, PoisonError, RwLock},
+};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout,
+  },
+};
+use color::Color;
+
+use crate::flat_texture::FlatTextureGPU;
+
+// Each variant is a separate fragment shader build, see triangle_material.glsl
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShaderVariant {
+  Unlit,
+  Lit,
+  // Lit, with fragments under the alpha cutoff discarded
+  AlphaCutout,
+  // Albedo lit by the material's baked lightmap, sampled with the mesh's second uvs
+  Lightmapped,
+}
+
+// Vertex streams a material reads. Meshes without one draw as if their colors were white and
+// their second uvs the same as the first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VertexStreamUse {
+  // Multiplied with the albedo
+  pub colors: bool,
+  // Samples the albedo with the second uvs, like a lightmap drawn unlit
+  pub albedo_uv2: bool,
+}
+
+impl VertexStreamUse {
+  pub fn any(&self) -> bool {
+    self.colors || self.albedo_uv2
+  }
+}
+
+// Everything that needs a separate pipeline, materials sharing a key share the pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MaterialPipelineKey {
+  pub variant: ShaderVariant,
+  pub double_sided: bool,
+  // Binds the mesh's vertex streams, which of them are read is in the material's params
+  pub vertex_streams: bool,
+}
+
+impl MaterialPipelineKey {
+  // Defines that turn triangle_unlit.frag into this key's variant, same as the other .frag files
+  pub fn shader_defines(&self) -> BTreeMap<String, String> {
+    let defines: &[&str] = match self.variant {
+      ShaderVariant::Unlit => &[],
+      ShaderVariant::Lit => &["LIT"],
+      ShaderVariant::AlphaCutout => &["LIT", "ALPHA_CUTOUT"],
+      ShaderVariant::Lightmapped => &["LIGHTMAPPED"],
+    };
+    let streams: &[&str] = if self.vertex_streams { &["VERTEX_STREAMS"] } else { &[] };
+    defines.iter().chain(streams).map(|name| (name.to_string(), String::new())).collect()
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialParams {
+  // Multiplied with the albedo texture
+  pub base_color: Color,
+  pub alpha_cutoff: f32,
+  // Light reaching faces turned away from the sun, lit variants only
+  pub ambient: f32,
+  // Blurs the reflection probes from mirror like at 0 to fully rough at 1
+  pub roughness: f32,
+  // Share of the probes' reflection seen head on, more reaches the eye at grazing angles
+  pub reflectivity: f32,
+}
+
+impl Default for MaterialParams {
+  fn default() -> Self {
+    Self {
+      base_color: Color::WHITE,
+      alpha_cutoff: 0.5,
+      ambient: 0.2,
+      roughness: 0.5,
+      reflectivity: 0.04,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialCPU {
+  pub variant: ShaderVariant,
+  pub double_sided: bool,
+  pub vertex_streams: VertexStreamUse,
+  pub params: MaterialParams,
+}
+
+impl Default for MaterialCPU {
+  fn default() -> Self {
+    Self {
+      variant: ShaderVariant::Unlit,
+      double_sided: false,
+      vertex_streams: VertexStreamUse::default(),
+      params: MaterialParams::default(),
+    }
+  }
+}
+
+impl MaterialCPU {
+  pub fn pipeline_key(&self) -> MaterialPipelineKey {
+    MaterialPipelineKey {
+      variant: self.variant,
+      double_sided: self.double_sided,
+      // Lightmaps are laid out over the second uvs
+      vertex_streams: self.vertex_streams.any() || self.variant == ShaderVariant::Lightmapped,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct MaterialData {
+  base_color: glam::Vec4,
+  // alpha cutoff, ambient, 1 to multiply in vertex colors, 1 to sample the albedo with uv2
+  params: glam::Vec4,
+  // roughness, reflectivity, unused, unused
+  surface: glam::Vec4,
+}
+
+impl MaterialData {
+  fn new(params: &MaterialParams, vertex_streams: VertexStreamUse) -> Self {
+    Self {
+      base_color: params.base_color.into(),
+      params: glam::vec4(
+        params.alpha_cutoff,
+        params.ambient,
+        vertex_streams.colors as u32 as f32,
+        vertex_streams.albedo_uv2 as u32 as f32,
+      ),
+      surface: glam::vec4(params.roughness.clamp(0.0, 1.0), params.reflectivity, 0.0, 0.0),
+    }
+  }
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct MaterialGPU {
+  #[getset(get_copy = "pub")]
+  pipeline_key: MaterialPipelineKey,
+  // Kept for when the params are rewritten
+  #[getset(get_copy = "pub")]
+  vertex_streams: VertexStreamUse,
+  // Swapped together when texture streaming changes which mips the albedo has
+  binding: RwLock<(Arc<AdDescriptorSet>, Arc<FlatTextureGPU>)>,
+  // Bound by every material, only lightmapped ones read it
+  lightmap: Arc<FlatTextureGPU>,
+}
+
+impl MaterialGPU {
+  pub fn dset(&self) -> Arc<AdDescriptorSet> {
+    self.binding.read().unwrap_or_else(PoisonError::into_inner).0.clone()
+  }
+
+  // Kept alive for as long as the material refers to it
+  pub fn albedo(&self) -> Arc<FlatTextureGPU> {
+    self.binding.read().unwrap_or_else(PoisonError::into_inner).1.clone()
+  }
+
+  pub fn update_params(&self, params: &MaterialParams) -> Result<(), String> {
+    let dset = self.dset();
+    let AdDescriptorBinding::UniformBuffer(pb) = &dset.bindings()[2] else {
+      return Err("Material constructed with improper params buffer".to_string());
+    };
+    pb.write_data(0, &[MaterialData::new(params, self.vertex_streams)])
+  }
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct MaterialGenerator {
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  #[getset(get = "pub")]
+  material_dset_pools: AdDescriptorPoolManager,
+  #[getset(get = "pub")]
+  material_dset_layout: Arc<AdDescriptorSetLayout>,
+}
+
+impl MaterialGenerator {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+  ) -> Result<Self, String> {
+    let dset_pools = AdDescriptorPoolManager::new(ash_device.clone(), "material", 64);
+    let dset_layout = AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLER),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::UNIFORM_BUFFER),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLER),
+      ],
+    )?;
+    Ok(Self {
+      ash_device,
+      allocator,
+      material_dset_pools: dset_pools,
+      material_dset_layout: Arc::new(dset_layout),
+    })
+  }
+
+  // Materials without a lightmap bind the albedo in its place
+  pub fn create_material(
+    &self,
+    name: &str,
+    material: &MaterialCPU,
+    albedo: Arc<FlatTextureGPU>,
+    lightmap: Option<Arc<FlatTextureGPU>>,
+  ) -> Result<MaterialGPU, String> {
+    let params_buffer = AdBuffer::new(
+      self.ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      &format!("{name}_mpb"),
+      vk::BufferCreateFlags::empty(),
+      std::mem::size_of::<MaterialData>() as _,
+      vk::BufferUsageFlags::UNIFORM_BUFFER,
+    )?;
+    params_buffer.write_data(0, &[MaterialData::new(&material.params, material.vertex_streams)])?;
+
+    let lightmap = lightmap.unwrap_or(albedo.clone());
+    let material_dset = self.allocate_dset(&albedo, Arc::new(params_buffer), &lightmap)?;
+    Ok(MaterialGPU {
+      pipeline_key: material.pipeline_key(),
+      vertex_streams: material.vertex_streams,
+      binding: RwLock::new((Arc::new(material_dset), albedo)),
+      lightmap,
+    })
+  }
+
+  fn allocate_dset(
+    &self,
+    albedo: &FlatTextureGPU,
+    params_buffer: Arc<AdBuffer>,
+    lightmap: &FlatTextureGPU,
+  ) -> Result<AdDescriptorSet, String> {
+    Ok(
+      self
+        .material_dset_pools
+        .allocate(&[(
+          self.material_dset_layout.clone(),
+          vec![
+            AdDescriptorBinding::Image2D((
+              albedo.image_view().clone(),
+              vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )),
+            AdDescriptorBinding::Sampler(albedo.sampler().clone()),
+            AdDescriptorBinding::UniformBuffer(params_buffer),
+            AdDescriptorBinding::Image2D((
+              lightmap.image_view().clone(),
+              vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )),
+            AdDescriptorBinding::Sampler(lightmap.sampler().clone()),
+          ],
+        )])?
+        .remove(0),
+    )
+  }
+
+  // Points the material at another albedo, keeping its params. The old descriptor set and texture
+  // are handed back since frames in flight may still be using them
+  pub fn swap_albedo(
+    &self,
+    material: &MaterialGPU,
+    albedo: Arc<FlatTextureGPU>,
+  ) -> Result<(Arc<AdDescriptorSet>, Arc<FlatTextureGPU>), String> {
+    let old_dset = material.dset();
+    let AdDescriptorBinding::UniformBuffer(pb) = &old_dset.bindings()[2] else {
+      return Err("Material constructed with improper params buffer".to_string());
+    };
+    let material_dset = self.allocate_dset(&albedo, pb.clone(), &material.lightmap)?;
+    let mut binding = material.binding.write().unwrap_or_else(PoisonError::into_inner);
+    Ok(std::mem::replace(&mut *binding, (Arc::new(material_dset), albedo)))
+  }
+}
@@ -0,0 +1,373 @@
+use std::{
+  collections::HashMap,
+  ops::Range,
+  sync::{Arc, Mutex, RwLock, Weak},
+};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+  },
+  ash_data_wrappers::{AdBuffer, AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet},
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
+  ash_sync_wrappers::AdFence,
+};
+
+// The largest minStorageBufferOffsetAlignment vulkan allows, so ranges can be bound on any gpu
+pub const ARENA_ALIGNMENT: u64 = 256;
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+  value.div_ceil(alignment) * alignment
+}
+
+// First fit over the free ranges of one block, freed ranges merge back with free neighbours
+#[derive(Debug, Clone)]
+pub struct RangeAllocator {
+  size: u64,
+  // Sorted, never empty or touching each other
+  free: Vec<Range<u64>>,
+}
+
+impl RangeAllocator {
+  pub fn new(size: u64) -> Self {
+    Self { size, free: vec![Range { start: 0, end: size }] }
+  }
+
+  pub fn size(&self) -> u64 {
+    self.size
+  }
+
+  pub fn free_bytes(&self) -> u64 {
+    self.free.iter().map(|range| range.end - range.start).sum()
+  }
+
+  pub fn largest_free(&self) -> u64 {
+    self.free.iter().map(|range| range.end - range.start).max().unwrap_or(0)
+  }
+
+  // Offset of the range, None when no free range fits it aligned
+  pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+    let (idx, start) = self.free.iter().enumerate().find_map(|(idx, range)| {
+      let start = align_up(range.start, alignment);
+      (start + size <= range.end).then_some((idx, start))
+    })?;
+    let range = self.free.remove(idx);
+    if start + size < range.end {
+      self.free.insert(idx, start + size..range.end);
+    }
+    if range.start < start {
+      self.free.insert(idx, range.start..start);
+    }
+    Some(start)
+  }
+
+  pub fn free(&mut self, offset: u64, size: u64) {
+    let idx = self.free.partition_point(|range| range.start < offset);
+    let mut freed = offset..offset + size;
+    if self.free.get(idx).is_some_and(|next| next.start == freed.end) {
+      freed.end = self.free.remove(idx).end;
+    }
+    match idx.checked_sub(1).and_then(|prev| self.free.get_mut(prev)) {
+      Some(prev) if prev.end == freed.start => prev.end = freed.end,
+      _ => self.free.insert(idx, freed),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArenaRange {
+  pub block: usize,
+  pub offset: u64,
+  pub size: u64,
+}
+
+// Live ranges by id packed back to back into fresh blocks, in block and offset order so meshes
+// uploaded together stay together. Returns the new blocks and where every range went
+pub fn plan_compaction(
+  live: &[(u64, ArenaRange)],
+  block_size: u64,
+) -> (Vec<RangeAllocator>, Vec<(u64, ArenaRange)>) {
+  let mut sorted = live.to_vec();
+  sorted.sort_by_key(|(_, range)| (range.block, range.offset));
+  let mut blocks: Vec<RangeAllocator> = vec![];
+  let mut placed = vec![];
+  // Blocks of their own are never filled further
+  let mut open_block = None;
+  for (id, range) in sorted {
+    let fits_open = open_block.and_then(|block: usize| {
+      blocks[block].allocate(range.size, ARENA_ALIGNMENT).map(|offset| (block, offset))
+    });
+    let (block, offset) = match fits_open {
+      Some(fit) => fit,
+      None => {
+        let mut new_block = RangeAllocator::new(range.size.max(block_size));
+        let offset = new_block.allocate(range.size, ARENA_ALIGNMENT).unwrap_or(0);
+        blocks.push(new_block);
+        if range.size <= block_size {
+          open_block = Some(blocks.len() - 1);
+        }
+        (blocks.len() - 1, offset)
+      }
+    };
+    placed.push((id, ArenaRange { block, offset, size: range.size }));
+  }
+  (blocks, placed)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MeshArenaStats {
+  // Bytes of the blocks on the gpu, and of the ranges in them still used by meshes
+  pub block_bytes: u64,
+  pub live_bytes: u64,
+  // Totals over every compaction so far
+  pub compactions: u64,
+  pub moved_bytes: u64,
+  pub reclaimed_bytes: u64,
+}
+
+struct LiveAllocation {
+  range: ArenaRange,
+  vertex_bytes: u64,
+  index_bytes: u64,
+  // Set binding the vertices at 0 and 5 and the indices at 1, rebuilt when the range moves
+  dset: Weak<RwLock<Arc<AdDescriptorSet>>>,
+}
+
+impl LiveAllocation {
+  fn bindings(&self, buffer: &Arc<AdBuffer>) -> [AdDescriptorBinding; 2] {
+    let index_offset = align_up(self.vertex_bytes, ARENA_ALIGNMENT);
+    [
+      AdDescriptorBinding::StorageBufferRange((
+        buffer.clone(),
+        self.range.offset,
+        self.vertex_bytes,
+      )),
+      AdDescriptorBinding::StorageBufferRange((
+        buffer.clone(),
+        self.range.offset + index_offset,
+        self.index_bytes,
+      )),
+    ]
+  }
+}
+
+struct ArenaState {
+  blocks: Vec<(Arc<AdBuffer>, RangeAllocator)>,
+  live: HashMap<u64, LiveAllocation>,
+  next_id: u64,
+  blocks_made: u64,
+  stats: MeshArenaStats,
+}
+
+impl ArenaState {
+  fn stats(&self) -> MeshArenaStats {
+    MeshArenaStats {
+      block_bytes: self.blocks.iter().map(|(_, ranges)| ranges.size()).sum(),
+      live_bytes: self.live.values().map(|allocation| allocation.range.size).sum(),
+      ..self.stats
+    }
+  }
+}
+
+// A mesh's range in the arena, given back when dropped
+pub struct ArenaAllocation {
+  state: Arc<Mutex<ArenaState>>,
+  id: u64,
+}
+
+impl ArenaAllocation {
+  // The set made with the bindings from upload, so compaction can point it at the new range
+  pub fn track_dset(&self, dset: Weak<RwLock<Arc<AdDescriptorSet>>>) -> Result<(), String> {
+    let mut state = self.state.lock().map_err(|e| format!("at getting mesh arena lock: {e}"))?;
+    if let Some(allocation) = state.live.get_mut(&self.id) {
+      allocation.dset = dset;
+    }
+    Ok(())
+  }
+}
+
+impl Drop for ArenaAllocation {
+  fn drop(&mut self) {
+    let Ok(mut state) = self.state.lock() else { return };
+    let Some(allocation) = state.live.remove(&self.id) else { return };
+    if let Some((_, ranges)) = state.blocks.get_mut(allocation.range.block) {
+      ranges.free(allocation.range.offset, allocation.range.size);
+    }
+  }
+}
+
+// Vertices and indices of many meshes sub allocated from a few large storage buffers, meshes
+// bigger than block_size get a block of their own. Freed ranges leave holes that only compact
+// gives back to the gpu allocator
+pub struct MeshArena {
+  allocator: Arc<Mutex<Allocator>>,
+  cmd_pool: Arc<AdCommandPool>,
+  block_size: u64,
+  state: Arc<Mutex<ArenaState>>,
+}
+
+impl MeshArena {
+  pub fn new(
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_pool: Arc<AdCommandPool>,
+    block_size: u64,
+  ) -> Self {
+    Self {
+      allocator,
+      cmd_pool,
+      block_size,
+      state: Arc::new(Mutex::new(ArenaState {
+        blocks: vec![],
+        live: HashMap::new(),
+        next_id: 0,
+        blocks_made: 0,
+        stats: MeshArenaStats::default(),
+      })),
+    }
+  }
+
+  pub fn stats(&self) -> Result<MeshArenaStats, String> {
+    Ok(self.state.lock().map_err(|e| format!("at getting mesh arena lock: {e}"))?.stats())
+  }
+
+  fn new_block(&self, state: &mut ArenaState, size: u64) -> Result<Arc<AdBuffer>, String> {
+    state.blocks_made += 1;
+    Ok(Arc::new(AdBuffer::new(
+      self.cmd_pool.queue().ash_device().clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("mesh_arena_block_{}", state.blocks_made),
+      vk::BufferCreateFlags::empty(),
+      size,
+      vk::BufferUsageFlags::STORAGE_BUFFER
+        | vk::BufferUsageFlags::TRANSFER_SRC
+        | vk::BufferUsageFlags::TRANSFER_DST,
+    )?))
+  }
+
+  fn copy_and_wait(
+    &self,
+    copies: &[(vk::Buffer, vk::Buffer, vk::BufferCopy)],
+  ) -> Result<(), String> {
+    let ash_device = self.cmd_pool.queue().ash_device().clone();
+    let cmd_buffer =
+      AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+    for (src, dst, region) in copies.iter() {
+      cmd_buffer.copy_buffer_to_buffer_cmd(*src, *dst, &[*region]);
+    }
+    cmd_buffer.end()?;
+    let fence = AdFence::new(ash_device, vk::FenceCreateFlags::empty())?;
+    cmd_buffer.submit(&[], &[], Some(&fence))?;
+    fence.wait(999999999)
+  }
+
+  // Vertices first, indices from the next aligned offset. Returns the bindings for a mesh set's
+  // vertex and index slots, the set should be handed to track_dset after
+  pub fn upload(
+    &self,
+    name: &str,
+    vertices: &[u8],
+    indices: &[u8],
+  ) -> Result<(ArenaAllocation, [AdDescriptorBinding; 2]), String> {
+    let vertex_bytes = vertices.len() as u64;
+    let index_bytes = indices.len() as u64;
+    let index_offset = align_up(vertex_bytes, ARENA_ALIGNMENT);
+    let size = index_offset + index_bytes;
+    let stage = AdBuffer::new(
+      self.cmd_pool.queue().ash_device().clone(),
+      self.allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      &format!("{name}_stage"),
+      vk::BufferCreateFlags::empty(),
+      size,
+      vk::BufferUsageFlags::TRANSFER_SRC,
+    )?;
+    stage.write_data(0, vertices)?;
+    stage.write_data(index_offset as usize, indices)?;
+
+    let mut state = self.state.lock().map_err(|e| format!("at getting mesh arena lock: {e}"))?;
+    let fit = state.blocks.iter_mut().enumerate().find_map(|(block, (_, ranges))| {
+      ranges.allocate(size, ARENA_ALIGNMENT).map(|offset| (block, offset))
+    });
+    let (block, offset) = match fit {
+      Some(fit) => fit,
+      None => {
+        let mut ranges = RangeAllocator::new(size.max(self.block_size));
+        let offset = ranges.allocate(size, ARENA_ALIGNMENT).unwrap_or(0);
+        let buffer = self.new_block(&mut state, ranges.size())?;
+        state.blocks.push((buffer, ranges));
+        (state.blocks.len() - 1, offset)
+      }
+    };
+    let buffer = state.blocks[block].0.clone();
+    self.copy_and_wait(&[(
+      stage.inner(),
+      buffer.inner(),
+      vk::BufferCopy { src_offset: 0, dst_offset: offset, size },
+    )])?;
+
+    let allocation = LiveAllocation {
+      range: ArenaRange { block, offset, size },
+      vertex_bytes,
+      index_bytes,
+      dset: Weak::new(),
+    };
+    let bindings = allocation.bindings(&buffer);
+    let id = state.next_id;
+    state.next_id += 1;
+    state.live.insert(id, allocation);
+    Ok((ArenaAllocation { state: self.state.clone(), id }, bindings))
+  }
+
+  // Moves live ranges together into as few blocks as they fit in, rewrites the sets binding them
+  // and gives the old blocks back. Nothing is moved unless it frees memory. The gpu can't be using
+  // any mesh meanwhile, so only while it's idle like behind a loading screen
+  pub fn compact(&self, dset_pools: &AdDescriptorPoolManager) -> Result<MeshArenaStats, String> {
+    let mut state = self.state.lock().map_err(|e| format!("at getting mesh arena lock: {e}"))?;
+    let live =
+      state.live.iter().map(|(id, allocation)| (*id, allocation.range)).collect::<Vec<_>>();
+    let (new_ranges, placed) = plan_compaction(&live, self.block_size);
+    let block_bytes = state.stats().block_bytes;
+    let new_block_bytes = new_ranges.iter().map(|ranges| ranges.size()).sum::<u64>();
+    if new_block_bytes >= block_bytes {
+      return Ok(state.stats());
+    }
+
+    let mut new_blocks = vec![];
+    for ranges in new_ranges {
+      new_blocks.push((self.new_block(&mut state, ranges.size())?, ranges));
+    }
+    let copies = placed
+      .iter()
+      .map(|(id, to)| {
+        let from = state.live[id].range;
+        (
+          state.blocks[from.block].0.inner(),
+          new_blocks[to.block].0.inner(),
+          vk::BufferCopy { src_offset: from.offset, dst_offset: to.offset, size: to.size },
+        )
+      })
+      .collect::<Vec<_>>();
+    self.copy_and_wait(&copies)?;
+
+    for (id, to) in placed {
+      let Some(allocation) = state.live.get_mut(&id) else { continue };
+      allocation.range = to;
+      let Some(dset_cell) = allocation.dset.upgrade() else { continue };
+      let mut dset = dset_cell.write().map_err(|e| format!("at getting mesh dset lock: {e}"))?;
+      let mut bindings = dset.bindings().clone();
+      let [vertex_binding, index_binding] = allocation.bindings(&new_blocks[to.block].0);
+      bindings[0] = vertex_binding.clone();
+      bindings[1] = index_binding;
+      bindings[5] = vertex_binding;
+      *dset = Arc::new(dset_pools.allocate(&[(dset.desc_layout().clone(), bindings)])?.remove(0));
+    }
+    state.blocks = new_blocks;
+    state.stats.compactions += 1;
+    state.stats.moved_bytes += live.iter().map(|(_, range)| range.size).sum::<u64>();
+    state.stats.reclaimed_bytes += block_bytes - new_block_bytes;
+    Ok(state.stats())
+  }
+}
@@ -0,0 +1,50 @@
+use crate::Camera3D;
+use glam::Vec4Swizzles;
+
+// MAX_MULTIVIEW_VIEWS in common_structs.glsl has to match. Six for the faces of a cubemap
+pub const MAX_VIEWS: usize = 6;
+
+// Camera of each view a multiview pass draws, shaders pick theirs with gl_ViewIndex. Laid out
+// like MultiviewCamData
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct MultiviewCameras {
+  pub views: [Camera3D; MAX_VIEWS],
+  // View count, unused, unused, unused
+  pub count: glam::UVec4,
+}
+
+impl MultiviewCameras {
+  // Views past MAX_VIEWS are left out
+  pub fn new(cameras: &[Camera3D]) -> Self {
+    let mut views = [Camera3D {
+      pos: glam::Vec4::W,
+      look_dir: glam::Vec4::NEG_Z,
+      view_proj_mat: glam::Mat4::IDENTITY,
+    }; MAX_VIEWS];
+    let count = cameras.len().min(MAX_VIEWS);
+    views[..count].copy_from_slice(&cameras[..count]);
+    Self { views, count: glam::uvec4(count as u32, 0, 0, 0) }
+  }
+
+  // Left and right eye, eye_distance apart across the camera's look direction
+  pub fn stereo(camera: &Camera3D, eye_distance: f32, fov: f32, aspect_ratio: f32) -> Self {
+    let right = camera.look_dir.xyz().cross(glam::Vec3::Y).try_normalize().unwrap_or(glam::Vec3::X);
+    let eye = |side: f32| {
+      let mut eye_camera =
+        Camera3D { pos: camera.pos + (right * side * eye_distance * 0.5).extend(0.0), ..*camera };
+      eye_camera.refresh_vp_matrix(fov, aspect_ratio);
+      eye_camera
+    };
+    Self::new(&[eye(-1.0), eye(1.0)])
+  }
+
+  pub fn view_count(&self) -> u32 {
+    self.count.x
+  }
+
+  // Mask of a subpass drawing every view
+  pub fn view_mask(&self) -> u32 {
+    (1 << self.view_count()) - 1
+  }
+}
@@ -0,0 +1,241 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout,
+  },
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
+};
+use color::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParticleCollision {
+  None,
+  Bounce,
+  Kill,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParticleEmitter {
+  pub origin: glam::Vec3,
+  pub velocity: glam::Vec3,
+  // Random speed added in every direction on spawn
+  pub spread: f32,
+  pub lifetime_s: f32,
+  pub size: f32,
+  pub color: Color,
+  pub gravity: glam::Vec3,
+  // Fraction of the speed into a surface kept after bouncing off it
+  pub restitution: f32,
+  // How far behind the depth buffer a particle still counts as touching the surface
+  pub surface_thickness: f32,
+  pub collision: ParticleCollision,
+  // Fixed once the system is created
+  pub max_particles: u32,
+}
+
+impl Default for ParticleEmitter {
+  fn default() -> Self {
+    Self {
+      origin: glam::Vec3::ZERO,
+      velocity: glam::vec3(0.0, 4.0, 0.0),
+      spread: 2.0,
+      lifetime_s: 1.5,
+      size: 0.05,
+      color: Color::rgb(1.0, 0.6, 0.2),
+      gravity: glam::vec3(0.0, -9.8, 0.0),
+      restitution: 0.4,
+      surface_thickness: 0.3,
+      collision: ParticleCollision::Bounce,
+      max_particles: 1024,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct EmitterData {
+  origin: glam::Vec4,
+  velocity: glam::Vec4,
+  gravity: glam::Vec4,
+  color: glam::Vec4,
+  params: glam::Vec4,
+  flags: [u32; 4],
+}
+
+impl From<&ParticleEmitter> for EmitterData {
+  fn from(emitter: &ParticleEmitter) -> Self {
+    let collision_mode = match emitter.collision {
+      ParticleCollision::None => 0,
+      ParticleCollision::Bounce => 1,
+      ParticleCollision::Kill => 2,
+    };
+    Self {
+      origin: emitter.origin.extend(emitter.spread),
+      velocity: emitter.velocity.extend(emitter.lifetime_s),
+      gravity: emitter.gravity.extend(emitter.restitution),
+      color: emitter.color.into(),
+      params: glam::vec4(emitter.size, emitter.surface_thickness, 0.0, 0.0),
+      flags: [collision_mode, 0, 0, 0],
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+struct ParticleData {
+  pos_life: glam::Vec4,
+  vel_lifetime: glam::Vec4,
+}
+
+// Per dispatch values of the simulation shader
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ParticleSimParams {
+  // capacity, first spawn slot, spawn count, seed
+  pub counters: [u32; 4],
+  // frame time in seconds, unused
+  pub params: glam::Vec4,
+}
+
+struct SpawnState {
+  next_slot: u32,
+  pending: u32,
+  seed: u32,
+}
+
+// Particles only live on the GPU, the CPU side just tracks which ring slots to respawn next
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct ParticleSystemGPU {
+  #[getset(get = "pub")]
+  dset: Arc<AdDescriptorSet>,
+  #[getset(get_copy = "pub")]
+  capacity: u32,
+  spawn_state: Mutex<SpawnState>,
+}
+
+impl ParticleSystemGPU {
+  pub fn update_emitter(&self, emitter: &ParticleEmitter) -> Result<(), String> {
+    let AdDescriptorBinding::UniformBuffer(eb) = &self.dset.bindings()[1] else {
+      return Err("Particle system constructed with improper emitter buffer".to_string());
+    };
+    eb.write_data(0, &[EmitterData::from(emitter)])
+  }
+
+  pub fn emit(&self, count: u32, seed: u32) -> Result<(), String> {
+    let mut spawn_state =
+      self.spawn_state.lock().map_err(|e| format!("at getting particle spawn lock: {e}"))?;
+    spawn_state.pending = (spawn_state.pending + count).min(self.capacity);
+    spawn_state.seed = spawn_state.seed.rotate_left(5) ^ seed;
+    Ok(())
+  }
+
+  // Hands the spawns queued since the last frame to the simulation
+  pub fn take_sim_params(&self, frame_time_s: f32) -> Result<ParticleSimParams, String> {
+    let mut spawn_state =
+      self.spawn_state.lock().map_err(|e| format!("at getting particle spawn lock: {e}"))?;
+    let sim_params = ParticleSimParams {
+      counters: [self.capacity, spawn_state.next_slot, spawn_state.pending, spawn_state.seed],
+      params: glam::vec4(frame_time_s, 0.0, 0.0, 0.0),
+    };
+    spawn_state.next_slot = (spawn_state.next_slot + spawn_state.pending) % self.capacity;
+    spawn_state.pending = 0;
+    Ok(sim_params)
+  }
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct ParticleSystemGenerator {
+  allocator: Arc<Mutex<Allocator>>,
+  cmd_pool: Arc<AdCommandPool>,
+  #[getset(get = "pub")]
+  particle_dset_pools: AdDescriptorPoolManager,
+  #[getset(get = "pub")]
+  particle_dset_layout: Arc<AdDescriptorSetLayout>,
+}
+
+impl ParticleSystemGenerator {
+  pub fn new(
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_pool: Arc<AdCommandPool>,
+  ) -> Result<Self, String> {
+    let ash_device = cmd_pool.queue().ash_device().clone();
+    let dset_pools = AdDescriptorPoolManager::new(ash_device.clone(), "particle", 16);
+    let dset_layout = AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (
+          vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::VERTEX,
+          vk::DescriptorType::STORAGE_BUFFER,
+        ),
+        (
+          vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::VERTEX,
+          vk::DescriptorType::UNIFORM_BUFFER,
+        ),
+      ],
+    )?;
+    Ok(Self {
+      allocator,
+      cmd_pool,
+      particle_dset_pools: dset_pools,
+      particle_dset_layout: Arc::new(dset_layout),
+    })
+  }
+
+  pub fn create_particle_system(
+    &self,
+    name: &str,
+    emitter: &ParticleEmitter,
+  ) -> Result<ParticleSystemGPU, String> {
+    if emitter.max_particles == 0 {
+      return Err(format!("particle system {name} has no room for particles"));
+    }
+    let ash_device = self.cmd_pool.queue().ash_device().clone();
+    let cmd_buffer =
+      AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+
+    // Every particle starts dead
+    let particle_buffer = AdBuffer::from_data(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("{name}_pb"),
+      vk::BufferCreateFlags::empty(),
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+      &vec![ParticleData::default(); emitter.max_particles as usize],
+      &cmd_buffer,
+    )?;
+    let emitter_buffer = AdBuffer::new(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      &format!("{name}_eb"),
+      vk::BufferCreateFlags::empty(),
+      std::mem::size_of::<EmitterData>() as _,
+      vk::BufferUsageFlags::UNIFORM_BUFFER,
+    )?;
+    emitter_buffer.write_data(0, &[EmitterData::from(emitter)])?;
+
+    let particle_dset = self
+      .particle_dset_pools
+      .allocate(&[(
+        self.particle_dset_layout.clone(),
+        vec![
+          AdDescriptorBinding::StorageBuffer(Arc::new(particle_buffer)),
+          AdDescriptorBinding::UniformBuffer(Arc::new(emitter_buffer)),
+        ],
+      )])?
+      .remove(0);
+
+    Ok(ParticleSystemGPU {
+      dset: Arc::new(particle_dset),
+      capacity: emitter.max_particles,
+      spawn_state: Mutex::new(SpawnState { next_slot: 0, pending: 0, seed: 0 }),
+    })
+  }
+}
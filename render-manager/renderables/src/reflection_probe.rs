@@ -0,0 +1,83 @@
+use crate::Camera3D;
+
+// Cubemap of the scene captured from position, reflected by lit materials inside its box. The
+// reflections are projected onto the box's walls so they line up with a room the box is fit to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReflectionProbe {
+  // Kept inside the box
+  pub position: glam::Vec3,
+  pub box_min: glam::Vec3,
+  pub box_max: glam::Vec3,
+  // The probe fades out over this far inside its box's faces, into the probes around it
+  pub blend_distance: f32,
+}
+
+impl Default for ReflectionProbe {
+  fn default() -> Self {
+    Self {
+      position: glam::Vec3::ZERO,
+      box_min: glam::Vec3::splat(-5.0),
+      box_max: glam::Vec3::splat(5.0),
+      blend_distance: 1.0,
+    }
+  }
+}
+
+impl ReflectionProbe {
+  pub fn volume(&self) -> f32 {
+    (self.box_max - self.box_min).max(glam::Vec3::ZERO).element_product()
+  }
+
+  // 1 deep in the box, falling to 0 at its faces over blend_distance and 0 outside.
+  // triangle_material.glsl weighs probes the same way
+  pub fn weight(&self, point: glam::Vec3) -> f32 {
+    let inside = (point - self.box_min).min(self.box_max - point).min_element();
+    match inside {
+      inside if inside < 0.0 => 0.0,
+      _ if self.blend_distance <= 0.0 => 1.0,
+      inside => (inside / self.blend_distance).min(1.0),
+    }
+  }
+
+  // Direction from the probe to where a ray from point along dir leaves the box, what the
+  // cubemap is sampled with. point has to be inside the box
+  pub fn box_projected_dir(&self, point: glam::Vec3, dir: glam::Vec3) -> glam::Vec3 {
+    let to_max = (self.box_max - point) / dir;
+    let to_min = (self.box_min - point) / dir;
+    let exit = to_max.max(to_min).min_element();
+    point + dir * exit - self.position
+  }
+
+  // 90 degree cameras for the faces in +X, -X, +Y, -Y, +Z, -Z order. What they draw is the face
+  // mirrored horizontally, with the y flip the shaders do
+  pub fn face_cameras(&self, near: f32, far: f32) -> [Camera3D; 6] {
+    [
+      (glam::Vec3::X, glam::Vec3::Y),
+      (glam::Vec3::NEG_X, glam::Vec3::Y),
+      (glam::Vec3::Y, glam::Vec3::NEG_Z),
+      (glam::Vec3::NEG_Y, glam::Vec3::Z),
+      (glam::Vec3::Z, glam::Vec3::Y),
+      (glam::Vec3::NEG_Z, glam::Vec3::Y),
+    ]
+    .map(|(dir, up)| Camera3D {
+      pos: self.position.extend(1.0),
+      look_dir: dir.extend(0.0),
+      view_proj_mat: glam::Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, near, far)
+        * glam::Mat4::look_at_rh(self.position, self.position + dir, up),
+    })
+  }
+}
+
+// How much each probe adds to the reflection at point. Earlier probes take what they cover first,
+// later ones only fill in the rest, so smaller probes should come before the ones around them
+pub fn blend_weights(probes: &[ReflectionProbe], point: glam::Vec3) -> Vec<f32> {
+  let mut total = 0.0;
+  probes
+    .iter()
+    .map(|probe| {
+      let weight = probe.weight(point) * (1.0 - total);
+      total += weight;
+      weight
+    })
+    .collect()
+}
@@ -0,0 +1,106 @@
+use std::{
+  collections::HashMap,
+  hash::{Hash, Hasher},
+  sync::{Arc, Mutex},
+};
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_data_wrappers::AdSampler,
+};
+
+use crate::flat_texture::{TextureAddressMode, TextureFilter};
+
+// Everything a sampler is made from. Samplers with the same one are the same sampler
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SamplerDesc {
+  pub filter: TextureFilter,
+  pub address_mode: TextureAddressMode,
+  // Samples along surfaces seen at a grazing angle, clamped to what the gpu can. 0 and 1 leave it
+  // off
+  pub anisotropy: u32,
+  // Added to the mip level picked, negative is sharper
+  pub lod_bias: f32,
+}
+
+// By bits, so -0.0 and 0.0 biases make two samplers. Harmless, nothing's kept apart that shouldn't
+impl PartialEq for SamplerDesc {
+  fn eq(&self, other: &Self) -> bool {
+    (self.filter, self.address_mode, self.anisotropy, self.lod_bias.to_bits())
+      == (other.filter, other.address_mode, other.anisotropy, other.lod_bias.to_bits())
+  }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    (self.filter, self.address_mode, self.anisotropy, self.lod_bias.to_bits()).hash(state);
+  }
+}
+
+// One sampler per desc, shared by every texture asking for it. Kept until the cache goes, there
+// are only ever a handful
+pub struct SamplerCache {
+  ash_device: Arc<AdAshDevice>,
+  // 1 when the gpu can't filter anisotropically
+  max_anisotropy: f32,
+  samplers: Mutex<HashMap<SamplerDesc, Arc<AdSampler>>>,
+}
+
+impl SamplerCache {
+  pub fn new(ash_device: Arc<AdAshDevice>) -> Self {
+    let ash_instance = ash_device.ash_instance();
+    let max_anisotropy = match ash_instance.supports_sampler_anisotropy(ash_device.gpu()) {
+      true => unsafe {
+        ash_instance
+          .inner()
+          .get_physical_device_properties(ash_device.gpu())
+          .limits
+          .max_sampler_anisotropy
+      },
+      false => 1.0,
+    };
+    Self { ash_device, max_anisotropy, samplers: Mutex::new(HashMap::new()) }
+  }
+
+  // Every mip is sampled, textures with a single level just have the one
+  pub fn get(&self, desc: SamplerDesc) -> Result<Arc<AdSampler>, String> {
+    let mut samplers =
+      self.samplers.lock().map_err(|e| format!("at getting sampler cache lock: {e}"))?;
+    if let Some(sampler) = samplers.get(&desc) {
+      return Ok(sampler.clone());
+    }
+    let filter = desc.filter.vk_filter();
+    let mipmap_mode = match desc.filter {
+      TextureFilter::Nearest => vk::SamplerMipmapMode::NEAREST,
+      TextureFilter::Linear => vk::SamplerMipmapMode::LINEAR,
+    };
+    let address_mode = desc.address_mode.vk_address_mode();
+    let anisotropy = (desc.anisotropy as f32).min(self.max_anisotropy);
+    let sampler = Arc::new(AdSampler::new_with_info(
+      self.ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(filter)
+        .min_filter(filter)
+        .mipmap_mode(mipmap_mode)
+        .address_mode_u(address_mode)
+        .address_mode_v(address_mode)
+        .address_mode_w(address_mode)
+        .anisotropy_enable(anisotropy > 1.0)
+        .max_anisotropy(anisotropy.max(1.0))
+        .mip_lod_bias(desc.lod_bias)
+        .max_lod(vk::LOD_CLAMP_NONE),
+    )?);
+    samplers.insert(desc, sampler.clone());
+    Ok(sampler)
+  }
+
+  pub fn len(&self) -> usize {
+    self.samplers.lock().map_or(0, |samplers| samplers.len())
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
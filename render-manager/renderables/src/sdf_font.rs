@@ -0,0 +1,358 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use ab_glyph::{Font, FontArc, ScaleFont};
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+  },
+  ash_data_wrappers::{AdImage, AdImage2dDesc, AdImageUpload, AdImageView},
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
+};
+
+// Pixel height glyphs are rasterized at in the atlas. Text any size is drawn from these, the
+// distance field keeps edges sharp well past this
+pub const SDF_GLYPH_SIZE: f32 = 32.0;
+// Pixels of the atlas the distance field reaches out from each edge, glyphs are padded by it
+pub const SDF_SPREAD: u32 = 4;
+const ATLAS_WIDTH: u32 = 512;
+// Printable ascii and latin-1, anything else is drawn as the fallback
+const ATLAS_CHARS: [std::ops::RangeInclusive<char>; 2] = [' '..='~', '\u{a1}'..='\u{ff}'];
+const FALLBACK_CHAR: char = '?';
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SdfGlyph {
+  // left, top, right, bottom in 0..1 of the atlas
+  pub uv: glam::Vec4,
+  // From the pen on the baseline to the top left of the padded bitmap, pixels at SDF_GLYPH_SIZE
+  pub offset: glam::Vec2,
+  // Of the padded bitmap, zero for glyphs with nothing to draw like spaces
+  pub size: glam::Vec2,
+  pub advance: f32,
+}
+
+// A glyph of laid out text, in pixels from the top left of the first line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedGlyph {
+  // left, top, width, height
+  pub rect: glam::Vec4,
+  pub uv: glam::Vec4,
+}
+
+// Glyphs of a font as a single channel signed distance field. 0.5 is the edge, going up inside
+// the glyph and down outside it by 0.5 every SDF_SPREAD pixels of the atlas
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdfFontCPU {
+  pub width: u32,
+  pub height: u32,
+  pub texels: Vec<u8>,
+  pub glyphs: HashMap<char, SdfGlyph>,
+  // Baseline of the first line from its top, and from one line to the next, at SDF_GLYPH_SIZE
+  pub ascent: f32,
+  pub line_height: f32,
+  // Between glyph pairs at SDF_GLYPH_SIZE, only pairs with any
+  pub kerning: HashMap<(char, char), f32>,
+}
+
+impl SdfFontCPU {
+  // Nothing to draw, for when no font is configured. Text laid out with it takes no space
+  pub fn empty() -> Self {
+    Self {
+      width: 1,
+      height: 1,
+      texels: vec![0],
+      glyphs: HashMap::new(),
+      ascent: 0.0,
+      line_height: 0.0,
+      kerning: HashMap::new(),
+    }
+  }
+
+  // TrueType or OpenType font bytes
+  pub fn from_font_bytes(font_bytes: Vec<u8>) -> Result<Self, String> {
+    let font = FontArc::try_from_vec(font_bytes).map_err(|e| format!("at reading font: {e}"))?;
+    let scaled = font.as_scaled(SDF_GLYPH_SIZE);
+    let chars = ATLAS_CHARS.into_iter().flatten().collect::<Vec<_>>();
+
+    let mut bitmaps = vec![];
+    for c in chars.iter().copied() {
+      let glyph_id = font.glyph_id(c);
+      if c != ' ' && glyph_id.0 == 0 {
+        continue;
+      }
+      let advance = scaled.h_advance(glyph_id);
+      let glyph = glyph_id.with_scale(SDF_GLYPH_SIZE);
+      let Some(outlined) = font.outline_glyph(glyph) else {
+        bitmaps.push((c, advance, glam::Vec2::ZERO, 0, 0, vec![]));
+        continue;
+      };
+      let bounds = outlined.px_bounds();
+      let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+      let mut coverage = vec![0.0; (width * height) as usize];
+      outlined.draw(|x, y, c| coverage[(y * width + x) as usize] = c);
+      let field = signed_distance_field(&coverage, width, height, SDF_SPREAD);
+      let offset = glam::vec2(bounds.min.x, bounds.min.y) - SDF_SPREAD as f32;
+      let padding = 2 * SDF_SPREAD;
+      bitmaps.push((c, advance, offset, width + padding, height + padding, field));
+    }
+
+    // Shelves left to right, a new one under the tallest glyph of the last once it's full
+    let mut placed = Vec::with_capacity(bitmaps.len());
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    for (_, _, _, width, height, _) in bitmaps.iter() {
+      if x + width > ATLAS_WIDTH {
+        (x, y, shelf_height) = (0, y + shelf_height + 1, 0);
+      }
+      placed.push((x, y));
+      x += width + 1;
+      shelf_height = shelf_height.max(*height);
+    }
+    let atlas_height = (y + shelf_height).max(1);
+
+    let mut texels = vec![0; (ATLAS_WIDTH * atlas_height) as usize];
+    let mut glyphs = HashMap::with_capacity(bitmaps.len());
+    for ((c, advance, offset, width, height, field), (x, y)) in bitmaps.into_iter().zip(placed) {
+      for row in 0..height {
+        let start = ((y + row) * ATLAS_WIDTH + x) as usize;
+        texels[start..start + width as usize]
+          .copy_from_slice(&field[(row * width) as usize..((row + 1) * width) as usize]);
+      }
+      let atlas_size = glam::vec2(ATLAS_WIDTH as f32, atlas_height as f32);
+      let uv_min = glam::vec2(x as f32, y as f32) / atlas_size;
+      let uv_max = glam::vec2((x + width) as f32, (y + height) as f32) / atlas_size;
+      let size = glam::vec2(width as f32, height as f32);
+      glyphs.insert(
+        c,
+        SdfGlyph { uv: uv_min.extend(uv_max.x).extend(uv_max.y), offset, size, advance },
+      );
+    }
+
+    let mut kerning = HashMap::new();
+    for first in chars.iter().copied().filter(|c| glyphs.contains_key(c)) {
+      for second in chars.iter().copied().filter(|c| glyphs.contains_key(c)) {
+        let kern = scaled.kern(font.glyph_id(first), font.glyph_id(second));
+        if kern != 0.0 {
+          kerning.insert((first, second), kern);
+        }
+      }
+    }
+    Ok(Self {
+      width: ATLAS_WIDTH,
+      height: atlas_height,
+      texels,
+      glyphs,
+      ascent: scaled.ascent(),
+      line_height: scaled.height() + scaled.line_gap(),
+      kerning,
+    })
+  }
+
+  // Reads through the global vfs, path is a vfs path
+  pub fn load(path: &str) -> Result<Self, String> {
+    Self::from_font_bytes(vfs::global().read(path)?)
+      .map_err(|e| format!("at loading font {path}: {e}"))
+  }
+
+  fn glyph(&self, c: char) -> Option<&SdfGlyph> {
+    self.glyphs.get(&c).or_else(|| self.glyphs.get(&FALLBACK_CHAR))
+  }
+
+  // Pixels of text at size the distance field's whole 0..1 covers, for turning samples back into
+  // distances on screen
+  pub fn distance_range(&self, size: f32) -> f32 {
+    2.0 * SDF_SPREAD as f32 * size / SDF_GLYPH_SIZE
+  }
+
+  // size is the pixel height text is drawn at, lines are split at newlines
+  pub fn layout(&self, text: &str, size: f32) -> Vec<PlacedGlyph> {
+    let scale = size / SDF_GLYPH_SIZE;
+    let mut placed = vec![];
+    for (line_idx, line) in text.lines().enumerate() {
+      let baseline = (self.ascent + line_idx as f32 * self.line_height) * scale;
+      let mut pen = 0.0;
+      let mut prev = None;
+      for c in line.chars() {
+        let Some(glyph) = self.glyph(c) else { continue };
+        if let Some(prev) = prev {
+          pen += self.kerning.get(&(prev, c)).copied().unwrap_or_default() * scale;
+        }
+        if glyph.size != glam::Vec2::ZERO {
+          let top_left = glam::vec2(pen, baseline) + glyph.offset * scale;
+          let size = glyph.size * scale;
+          placed.push(PlacedGlyph { rect: top_left.extend(size.x).extend(size.y), uv: glyph.uv });
+        }
+        pen += glyph.advance * scale;
+        prev = Some(c);
+      }
+    }
+    placed
+  }
+
+  // Width of the widest line and the height of all of them, in pixels at size
+  pub fn measure(&self, text: &str, size: f32) -> glam::Vec2 {
+    let scale = size / SDF_GLYPH_SIZE;
+    let mut extent = glam::Vec2::ZERO;
+    for line in text.lines() {
+      let mut width = 0.0;
+      let mut prev = None;
+      for c in line.chars() {
+        let Some(glyph) = self.glyph(c) else { continue };
+        if let Some(prev) = prev {
+          width += self.kerning.get(&(prev, c)).copied().unwrap_or_default();
+        }
+        width += glyph.advance;
+        prev = Some(c);
+      }
+      extent.x = extent.x.max(width * scale);
+      extent.y += self.line_height * scale;
+    }
+    extent
+  }
+}
+
+// Felzenszwalb and Huttenlocher's squared distance transform along one row or column, f is 0 on
+// what's measured to and infinite elsewhere
+fn squared_distances_1d(f: &[f32], out: &mut [f32]) {
+  let n = f.len();
+  let mut vertices = vec![0; n];
+  let mut bounds = vec![0.0; n + 1];
+  let mut k = 0;
+  bounds[0] = f32::NEG_INFINITY;
+  bounds[1] = f32::INFINITY;
+  let intersection = |q: usize, v: usize| {
+    ((f[q] + (q * q) as f32) - (f[v] + (v * v) as f32)) / (2.0 * q as f32 - 2.0 * v as f32)
+  };
+  for q in 1..n {
+    if f[q] == f32::INFINITY {
+      continue;
+    }
+    if f[vertices[k]] == f32::INFINITY {
+      vertices[k] = q;
+      continue;
+    }
+    let mut s = intersection(q, vertices[k]);
+    while k > 0 && s <= bounds[k] {
+      k -= 1;
+      s = intersection(q, vertices[k]);
+    }
+    k += 1;
+    vertices[k] = q;
+    bounds[k] = s;
+    bounds[k + 1] = f32::INFINITY;
+  }
+  k = 0;
+  for (q, out) in out.iter_mut().enumerate() {
+    while bounds[k + 1] < q as f32 {
+      k += 1;
+    }
+    let v = vertices[k];
+    *out = match f[v] {
+      f32::INFINITY => f32::INFINITY,
+      fv => (q as f32 - v as f32).powi(2) + fv,
+    };
+  }
+}
+
+// Distance from each pixel center to the nearest one where measured_to is true
+fn distances(measured_to: &[bool], width: usize, height: usize) -> Vec<f32> {
+  let mut grid =
+    measured_to.iter().map(|x| if *x { 0.0 } else { f32::INFINITY }).collect::<Vec<_>>();
+  let mut column = vec![0.0; height];
+  let mut column_out = vec![0.0; height];
+  for x in 0..width {
+    for y in 0..height {
+      column[y] = grid[y * width + x];
+    }
+    squared_distances_1d(&column, &mut column_out);
+    for y in 0..height {
+      grid[y * width + x] = column_out[y];
+    }
+  }
+  let mut row_out = vec![0.0; width];
+  for row in grid.chunks_mut(width) {
+    squared_distances_1d(row, &mut row_out);
+    row.copy_from_slice(&row_out);
+  }
+  grid.into_iter().map(f32::sqrt).collect()
+}
+
+// Coverage of a width by height bitmap to a field padded by spread on every side, see SdfFontCPU.
+// Pixels partly covered are on the edge, their coverage places it within them
+pub fn signed_distance_field(coverage: &[f32], width: u32, height: u32, spread: u32) -> Vec<u8> {
+  let (padded_width, padded_height) =
+    ((width + 2 * spread) as usize, (height + 2 * spread) as usize);
+  let mut padded = vec![0.0; padded_width * padded_height];
+  for y in 0..height as usize {
+    let start = (y + spread as usize) * padded_width + spread as usize;
+    padded[start..start + width as usize]
+      .copy_from_slice(&coverage[y * width as usize..(y + 1) * width as usize]);
+  }
+  let inside = padded.iter().map(|c| *c >= 0.5).collect::<Vec<_>>();
+  let outside = inside.iter().map(|x| !x).collect::<Vec<_>>();
+  let to_inside = distances(&inside, padded_width, padded_height);
+  let to_outside = distances(&outside, padded_width, padded_height);
+  padded
+    .iter()
+    .enumerate()
+    .map(|(i, c)| {
+      // Positive outside, in pixels from the edge
+      let distance = match (*c > 0.0 && *c < 1.0, inside[i]) {
+        (true, _) => 0.5 - c,
+        (false, true) => 0.5 - to_outside[i],
+        (false, false) => to_inside[i] - 0.5,
+      };
+      let value = 0.5 - distance / (2.0 * spread as f32);
+      (value.clamp(0.0, 1.0) * 255.0).round() as u8
+    })
+    .collect()
+}
+
+// The atlas as an R8 texture, sampled linearly by the sdf shape renderer
+pub struct SdfFontGPU {
+  image_view: Arc<AdImageView>,
+}
+
+impl SdfFontGPU {
+  pub fn upload(
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_pool: Arc<AdCommandPool>,
+    name: &str,
+    font: &SdfFontCPU,
+  ) -> Result<Self, String> {
+    let ash_device = cmd_pool.queue().ash_device().clone();
+    let cmd_buffer = AdCommandBuffer::new(cmd_pool, vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+    let image = AdImage::new_2d_from_data(
+      ash_device,
+      allocator,
+      name,
+      AdImage2dDesc {
+        format: vk::Format::R8_UNORM,
+        resolution: vk::Extent2D { width: font.width, height: font.height },
+        usage: vk::ImageUsageFlags::SAMPLED,
+        mip_levels: 1,
+      },
+      &[&font.texels],
+      AdImageUpload {
+        mem_location: MemoryLocation::GpuOnly,
+        cmd_buffer: &cmd_buffer,
+        init_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+      },
+    )?;
+    let image_view = AdImageView::create_view(
+      image,
+      vk::ImageViewType::TYPE_2D,
+      vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .level_count(1)
+        .layer_count(1),
+    )?;
+    Ok(Self { image_view })
+  }
+
+  pub fn image_view(&self) -> &Arc<AdImageView> {
+    &self.image_view
+  }
+}
@@ -0,0 +1,217 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout,
+  },
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
+  ash_sync_wrappers::AdFence,
+};
+
+use crate::{
+  crowd::CrowdVertex,
+  triangle_mesh::{TriMeshCPU, TriMeshGPU, TriMeshGenerator, TriMeshVertex},
+};
+
+// Skinned from the same vertices as crowds, but posed by its own joint matrices instead of a baked
+// animation
+#[derive(Debug, Clone, Default)]
+pub struct SkinnedMeshCPU {
+  pub verts: Vec<CrowdVertex>,
+  pub triangles: Vec<[u32; 3]>,
+  pub joint_count: u32,
+  // How far from the origin vertices get over every pose, poses aren't known up front for
+  // culling. Never less than the bind pose reach
+  pub bounding_radius: f32,
+}
+
+impl SkinnedMeshCPU {
+  pub fn bind_pose(&self) -> TriMeshCPU {
+    TriMeshCPU::new(
+      self
+        .verts
+        .iter()
+        .map(|vert| TriMeshVertex { pos: vert.pos, normal: vert.normal, uv: vert.uv })
+        .collect(),
+      self.triangles.clone(),
+    )
+  }
+}
+
+// The mesh draws like any other, its vertex buffer is what the skinning pass wrote last. The joint
+// buffer holds the pose to draw, then the pose the last frame was drawn in, which the skinning pass
+// also poses into the mesh's previous vertices for motion vectors
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct SkinnedMeshGPU {
+  #[getset(get = "pub")]
+  skin_dset: Arc<AdDescriptorSet>,
+  #[getset(get = "pub")]
+  mesh: Arc<TriMeshGPU>,
+  #[getset(get_copy = "pub")]
+  vertex_count: u32,
+  #[getset(get_copy = "pub")]
+  joint_count: u32,
+}
+
+impl SkinnedMeshGPU {
+  fn write_joints(&self, first: usize, matrices: &[glam::Mat4]) -> Result<(), String> {
+    if matrices.len() != self.joint_count as usize {
+      return Err(format!(
+        "got {} joint matrices for a mesh with {} joints",
+        matrices.len(),
+        self.joint_count
+      ));
+    }
+    let AdDescriptorBinding::StorageBuffer(jb) = &self.skin_dset.bindings()[1] else {
+      return Err("Skinned mesh constructed with improper joint buffer".to_string());
+    };
+    jb.write_data(first * std::mem::size_of::<glam::Mat4>(), matrices)
+  }
+
+  // One matrix per joint, from the bind pose to the pose in the mesh's model space
+  pub fn update_joints(&self, matrices: &[glam::Mat4]) -> Result<(), String> {
+    self.write_joints(0, matrices)
+  }
+
+  // Left alone by update_joints, the renderer sets it once per frame
+  pub fn update_prev_joints(&self, matrices: &[glam::Mat4]) -> Result<(), String> {
+    self.write_joints(self.joint_count as usize, matrices)
+  }
+
+  // Joints are rewritten from the cpu, see AdBuffer::mark_in_flight
+  pub fn mark_in_flight(&self, fence: &Arc<AdFence>) {
+    if let AdDescriptorBinding::StorageBuffer(jb) = &self.skin_dset.bindings()[1] {
+      jb.mark_in_flight(fence);
+    }
+  }
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct SkinnedMeshGenerator {
+  allocator: Arc<Mutex<Allocator>>,
+  cmd_pool: Arc<AdCommandPool>,
+  #[getset(get = "pub")]
+  skin_dset_pools: AdDescriptorPoolManager,
+  #[getset(get = "pub")]
+  skin_dset_layout: Arc<AdDescriptorSetLayout>,
+}
+
+impl SkinnedMeshGenerator {
+  pub fn new(
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_pool: Arc<AdCommandPool>,
+  ) -> Result<Self, String> {
+    let ash_device = cmd_pool.queue().ash_device().clone();
+    let dset_pools = AdDescriptorPoolManager::new(ash_device.clone(), "skin", 16);
+    let dset_layout = AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+      ],
+    )?;
+    Ok(Self {
+      allocator,
+      cmd_pool,
+      skin_dset_pools: dset_pools,
+      skin_dset_layout: Arc::new(dset_layout),
+    })
+  }
+
+  // Starts out in the bind pose
+  pub fn create_skinned_mesh(
+    &self,
+    name: &str,
+    mesh: &SkinnedMeshCPU,
+    tri_mesh_gen: &TriMeshGenerator,
+  ) -> Result<SkinnedMeshGPU, String> {
+    if mesh.joint_count == 0 {
+      return Err(format!("skinned mesh {name} has no joints"));
+    }
+    if let Some(vert) =
+      mesh.verts.iter().find(|vert| vert.joints.iter().any(|joint| *joint >= mesh.joint_count))
+    {
+      return Err(format!(
+        "skinned mesh {name} vertex uses joints {:?} past its {} joints",
+        vert.joints, mesh.joint_count
+      ));
+    }
+    let ash_device = self.cmd_pool.queue().ash_device().clone();
+    let cmd_buffer =
+      AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+
+    let source_buffer = AdBuffer::from_data(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("{name}_svb"),
+      vk::BufferCreateFlags::empty(),
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+      &mesh.verts,
+      &cmd_buffer,
+    )?;
+    let joint_buffer = AdBuffer::new(
+      ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      &format!("{name}_jb"),
+      vk::BufferCreateFlags::empty(),
+      (std::mem::size_of::<glam::Mat4>() * mesh.joint_count as usize * 2) as _,
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+    )?;
+    joint_buffer.write_data(0, &vec![glam::Mat4::IDENTITY; mesh.joint_count as usize * 2])?;
+    // Rewritten by every skinning pass, never uploaded to
+    let vertex_buffer = |suffix: &str| {
+      AdBuffer::new(
+        ash_device.clone(),
+        self.allocator.clone(),
+        MemoryLocation::GpuOnly,
+        &format!("{name}_{suffix}"),
+        vk::BufferCreateFlags::empty(),
+        (std::mem::size_of::<TriMeshVertex>() * mesh.verts.len().max(1)) as _,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+      )
+      .map(Arc::new)
+    };
+    let skinned_buffer = vertex_buffer("vb")?;
+    // Only positions get written
+    let prev_skinned_buffer = vertex_buffer("pvb")?;
+
+    let bind_radius =
+      mesh.verts.iter().map(|vert| vert.pos.truncate().length()).fold(0.0, f32::max);
+    let tri_mesh = tri_mesh_gen.create_tri_mesh_over(
+      name,
+      skinned_buffer.clone(),
+      prev_skinned_buffer.clone(),
+      &mesh.triangles,
+      mesh.bounding_radius.max(bind_radius),
+    )?;
+
+    let skin_dset = self
+      .skin_dset_pools
+      .allocate(&[(
+        self.skin_dset_layout.clone(),
+        vec![
+          AdDescriptorBinding::StorageBuffer(Arc::new(source_buffer)),
+          AdDescriptorBinding::StorageBuffer(Arc::new(joint_buffer)),
+          AdDescriptorBinding::StorageBuffer(skinned_buffer),
+          AdDescriptorBinding::StorageBuffer(prev_skinned_buffer),
+        ],
+      )])?
+      .remove(0);
+
+    Ok(SkinnedMeshGPU {
+      skin_dset: Arc::new(skin_dset),
+      mesh: Arc::new(tri_mesh),
+      vertex_count: mesh.verts.len() as u32,
+      joint_count: mesh.joint_count,
+    })
+  }
+}
@@ -0,0 +1,120 @@
+use std::ops::Range;
+
+use crate::triangle_mesh::{g_vec4_from_vec3, TriMeshCPU, TriMeshVertex};
+
+// Where one added mesh ended up, so it can still be culled, picked or drawn on its own
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchedInstance {
+  pub batch: usize,
+  pub triangles: Range<u32>,
+  pub vertices: Range<u32>,
+  // World space center and radius
+  pub bounds: glam::Vec4,
+}
+
+// Meshes merged in world space, vertices are relative to the translation in transform so the
+// bounding radius the renderer works out stays tight around them
+pub struct StaticBatch<K> {
+  pub key: K,
+  pub mesh: TriMeshCPU,
+  pub transform: glam::Mat4,
+  // World space center and radius
+  pub bounds: glam::Vec4,
+  // Ids add gave out for the meshes in this batch
+  pub instances: Vec<usize>,
+}
+
+// Merges meshes that never move into a few large ones at level load. Meshes with equal keys, like
+// the same material in the same culling group, share a batch until it reaches max_batch_vertices
+pub struct StaticBatcher<K> {
+  max_batch_vertices: usize,
+  batches: Vec<StaticBatch<K>>,
+  instances: Vec<BatchedInstance>,
+}
+
+fn sphere_around(points: impl Iterator<Item = glam::Vec3> + Clone) -> glam::Vec4 {
+  let (min, max) = points
+    .clone()
+    .fold((glam::Vec3::splat(f32::MAX), glam::Vec3::splat(f32::MIN)), |(min, max), point| {
+      (min.min(point), max.max(point))
+    });
+  if min.x > max.x {
+    return glam::Vec4::ZERO;
+  }
+  let center = (min + max) * 0.5;
+  let radius = points.map(|point| point.distance(center)).fold(0.0, f32::max);
+  center.extend(radius)
+}
+
+impl<K: PartialEq> StaticBatcher<K> {
+  pub fn new(max_batch_vertices: usize) -> Self {
+    Self { max_batch_vertices: max_batch_vertices.max(1), batches: vec![], instances: vec![] }
+  }
+
+  // Id of the instance, in the order meshes were added
+  pub fn add(&mut self, key: K, mesh: &TriMeshCPU, transform: glam::Mat4) -> usize {
+    let normal_transform = glam::Mat3::from_mat4(transform).inverse().transpose();
+    let vertices = mesh
+      .vertices
+      .iter()
+      .map(|vertex| TriMeshVertex {
+        pos: transform * vertex.pos.truncate().extend(1.0),
+        normal: g_vec4_from_vec3(
+          (normal_transform * vertex.normal.truncate()).normalize_or_zero(),
+          vertex.normal.w,
+        ),
+        uv: vertex.uv,
+      })
+      .collect::<Vec<_>>();
+    let bounds = sphere_around(vertices.iter().map(|vertex| vertex.pos.truncate()));
+    let batch = match self.batches.iter().rposition(|batch| {
+      batch.key == key && batch.mesh.vertices.len() + vertices.len() <= self.max_batch_vertices
+    }) {
+      Some(batch) => batch,
+      None => {
+        self.batches.push(StaticBatch {
+          key,
+          mesh: TriMeshCPU::new(vec![], vec![]),
+          transform: glam::Mat4::IDENTITY,
+          bounds: glam::Vec4::ZERO,
+          instances: vec![],
+        });
+        self.batches.len() - 1
+      }
+    };
+    let id = self.instances.len();
+    let batch_mesh = &mut self.batches[batch].mesh;
+    let first_vertex = batch_mesh.vertices.len() as u32;
+    let first_triangle = batch_mesh.triangles.len() as u32;
+    batch_mesh
+      .triangles
+      .extend(mesh.triangles.iter().map(|triangle| triangle.map(|idx| idx + first_vertex)));
+    batch_mesh.streams.append(first_vertex as usize, &mesh.streams, mesh.vertices.len());
+    batch_mesh.vertices.extend(vertices);
+    self.instances.push(BatchedInstance {
+      batch,
+      triangles: first_triangle..batch_mesh.triangles.len() as u32,
+      vertices: first_vertex..batch_mesh.vertices.len() as u32,
+      bounds,
+    });
+    self.batches[batch].instances.push(id);
+    id
+  }
+
+  pub fn instance(&self, id: usize) -> Option<&BatchedInstance> {
+    self.instances.get(id)
+  }
+
+  // Moves each batch's vertices around its center, ready for upload with its transform
+  pub fn finish(mut self) -> (Vec<StaticBatch<K>>, Vec<BatchedInstance>) {
+    for batch in self.batches.iter_mut() {
+      batch.bounds = sphere_around(batch.mesh.vertices.iter().map(|vertex| vertex.pos.truncate()));
+      let center = batch.bounds.truncate();
+      for vertex in batch.mesh.vertices.iter_mut() {
+        vertex.pos -= center.extend(0.0);
+      }
+      batch.transform = glam::Mat4::from_translation(center);
+    }
+    (self.batches, self.instances)
+  }
+}
@@ -0,0 +1,376 @@
+use std::ops::Range;
+
+use ash_ad_wrappers::ash_context::ash::vk;
+use asset_cache::ContentHasher;
+
+use crate::flat_texture::{mip_count, mip_extent, TextureColorSpace};
+
+// Compressed textures in the asset cache, see CompressedFlatTexture::to_cache_bytes. Bumped
+// whenever the encoders or the artifact layout change
+const COMPRESSED_CACHE_KIND: &str = "compressed_texture";
+const COMPRESSED_CACHE_VERSION: u32 = 1;
+// Out of 64, how far along from the first endpoint each of BC7 mode 6's 16 colors is
+const BC7_WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+// Least squares endpoint fits tried per block with BlockQuality::Refined, kept when they help
+const REFINE_PASSES: u32 = 2;
+
+type Block = [[f32; 4]; 16];
+
+// 4x4 texel blocks of rgba8 textures the gpu samples without decompressing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockFormat {
+  // 8 bytes a block, opaque textures only
+  Bc1,
+  // 16 bytes a block, with alpha. Only mode 6, one pair of rgba endpoints per block
+  Bc7,
+}
+
+impl BlockFormat {
+  const ALL: [Self; 2] = [Self::Bc1, Self::Bc7];
+
+  pub fn block_bytes(&self) -> usize {
+    match self {
+      Self::Bc1 => 8,
+      Self::Bc7 => 16,
+    }
+  }
+
+  pub fn vk_format(&self, color_space: TextureColorSpace) -> vk::Format {
+    match (self, color_space) {
+      (Self::Bc1, TextureColorSpace::Srgb) => vk::Format::BC1_RGB_SRGB_BLOCK,
+      (Self::Bc1, TextureColorSpace::Linear) => vk::Format::BC1_RGB_UNORM_BLOCK,
+      (Self::Bc7, TextureColorSpace::Srgb) => vk::Format::BC7_SRGB_BLOCK,
+      (Self::Bc7, TextureColorSpace::Linear) => vk::Format::BC7_UNORM_BLOCK,
+    }
+  }
+
+  // Blocks at the right and bottom edges are whole even where the level isn't
+  pub fn level_bytes(&self, extent: vk::Extent2D) -> usize {
+    block_rows(extent) as usize * extent.width.div_ceil(4) as usize * self.block_bytes()
+  }
+
+  fn cache_tag(&self) -> u8 {
+    Self::ALL.iter().position(|format| format == self).unwrap_or(0) as u8
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockQuality {
+  // Endpoints at the ends of the block's colors along their main axis
+  Fast,
+  // Then fitted again to the colors they ended up picking, for lower error at a few times the time
+  Refined,
+}
+
+pub fn block_rows(extent: vk::Extent2D) -> u32 {
+  extent.height.div_ceil(4)
+}
+
+// Whether rgba8 texels have no alpha below 255, so BC1 can hold them
+pub fn is_opaque(texels: &[u8]) -> bool {
+  texels.chunks_exact(4).all(|texel| texel[3] == u8::MAX)
+}
+
+// Texels of the 4x4 block at block_x, block_y, repeating the last row and column past the edges
+fn read_block(extent: vk::Extent2D, texels: &[u8], block_x: u32, block_y: u32) -> Block {
+  let mut block = [[0.0; 4]; 16];
+  for (i, texel) in block.iter_mut().enumerate() {
+    let x = (block_x * 4 + i as u32 % 4).min(extent.width - 1);
+    let y = (block_y * 4 + i as u32 / 4).min(extent.height - 1);
+    let offset = (y * extent.width + x) as usize * 4;
+    for channel in 0..4 {
+      texel[channel] = texels[offset + channel] as f32;
+    }
+  }
+  block
+}
+
+fn distance_sq(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+  (0..4).map(|channel| (a[channel] - b[channel]).powi(2)).sum()
+}
+
+// Ends of the block's colors along the axis they spread the most on, found by power iteration
+// on their covariance
+fn principal_endpoints(block: &Block) -> ([f32; 4], [f32; 4]) {
+  let mut mean = [0.0; 4];
+  for texel in block {
+    for channel in 0..4 {
+      mean[channel] += texel[channel] / 16.0;
+    }
+  }
+  let mut covariance = [[0.0; 4]; 4];
+  for texel in block {
+    for row in 0..4 {
+      for col in 0..4 {
+        covariance[row][col] += (texel[row] - mean[row]) * (texel[col] - mean[col]);
+      }
+    }
+  }
+  let mut axis = [1.0, 1.0, 1.0, 1.0];
+  for _ in 0..8 {
+    let mut next = [0.0; 4];
+    for row in 0..4 {
+      next[row] = (0..4).map(|col| covariance[row][col] * axis[col]).sum();
+    }
+    let length = next.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if length < 1e-6 {
+      break;
+    }
+    axis = next.map(|x| x / length);
+  }
+  let project = |texel: &[f32; 4]| (0..4).map(|c| (texel[c] - mean[c]) * axis[c]).sum::<f32>();
+  let (min, max) =
+    block.iter().map(project).fold((f32::MAX, f32::MIN), |(min, max), t| (min.min(t), max.max(t)));
+  let along = |t: f32| [0, 1, 2, 3].map(|c| (mean[c] + axis[c] * t).clamp(0.0, 255.0));
+  (along(min), along(max))
+}
+
+// Endpoints best reproducing the block with each texel at its weight between them, None when the
+// weights don't pin them down
+fn fit_endpoints(block: &Block, weights: &[f32; 16]) -> Option<([f32; 4], [f32; 4])> {
+  let (mut a, mut b, mut c) = (0.0, 0.0, 0.0);
+  let (mut x0, mut x1) = ([0.0; 4], [0.0; 4]);
+  for (texel, w) in block.iter().zip(weights) {
+    a += (1.0 - w) * (1.0 - w);
+    b += (1.0 - w) * w;
+    c += w * w;
+    for channel in 0..4 {
+      x0[channel] += (1.0 - w) * texel[channel];
+      x1[channel] += w * texel[channel];
+    }
+  }
+  let det = a * c - b * b;
+  if det.abs() < 1e-6 {
+    return None;
+  }
+  let e0 = [0, 1, 2, 3].map(|ch| ((c * x0[ch] - b * x1[ch]) / det).clamp(0.0, 255.0));
+  let e1 = [0, 1, 2, 3].map(|ch| ((a * x1[ch] - b * x0[ch]) / det).clamp(0.0, 255.0));
+  Some((e0, e1))
+}
+
+fn to_565(color: &[f32; 4]) -> u16 {
+  let r = (color[0] * 31.0 / 255.0).round() as u16;
+  let g = (color[1] * 63.0 / 255.0).round() as u16;
+  let b = (color[2] * 31.0 / 255.0).round() as u16;
+  (r << 11) | (g << 5) | b
+}
+
+fn from_565(color: u16) -> [f32; 4] {
+  let (r, g, b) = ((color >> 11) & 31, (color >> 5) & 63, color & 31);
+  [((r << 3) | (r >> 2)) as f32, ((g << 2) | (g >> 4)) as f32, ((b << 3) | (b >> 2)) as f32, 255.0]
+}
+
+// Both endpoints, then 2 bit indices from the first texel up. The first endpoint is kept above
+// the second so the block uses four colors, not three and transparent black
+fn encode_bc1(block: &Block, endpoints: ([f32; 4], [f32; 4])) -> ([u8; 8], f32, [f32; 16]) {
+  let (mut color_0, mut color_1) = (to_565(&endpoints.1), to_565(&endpoints.0));
+  if color_0 < color_1 {
+    std::mem::swap(&mut color_0, &mut color_1);
+  }
+  let (end_0, end_1) = (from_565(color_0), from_565(color_1));
+  let lerp = |t: f32| [0, 1, 2, 3].map(|c| end_0[c] + (end_1[c] - end_0[c]) * t);
+  let palette_weights = [0.0, 1.0, 1.0 / 3.0, 2.0 / 3.0];
+  let palette = palette_weights.map(lerp);
+  let mut indices = 0u32;
+  let mut error = 0.0;
+  let mut weights = [0.0; 16];
+  for (i, texel) in block.iter().enumerate() {
+    let rgb = [texel[0], texel[1], texel[2], 255.0];
+    // Equal endpoints switch the block to three colors, where only the first is still the same
+    let candidates = if color_0 == color_1 { 1 } else { 4 };
+    let (index, texel_error) = (0..candidates)
+      .map(|index| (index, distance_sq(&rgb, &palette[index])))
+      .fold((0, f32::MAX), |best, x| if x.1 < best.1 { x } else { best });
+    indices |= (index as u32) << (i * 2);
+    error += texel_error;
+    weights[i] = palette_weights[index];
+  }
+  let mut bytes = [0; 8];
+  bytes[0..2].copy_from_slice(&color_0.to_le_bytes());
+  bytes[2..4].copy_from_slice(&color_1.to_le_bytes());
+  bytes[4..8].copy_from_slice(&indices.to_le_bytes());
+  (bytes, error, weights)
+}
+
+// 7 bits per channel and the shared lowest bit that comes closest to endpoint
+fn quantize_bc7(endpoint: &[f32; 4]) -> ([u32; 4], u32) {
+  [0, 1]
+    .map(|p_bit| {
+      let quantized = endpoint.map(|x| ((x - p_bit as f32) / 2.0).round().clamp(0.0, 127.0) as u32);
+      let error =
+        (0..4).map(|c| ((quantized[c] * 2 + p_bit) as f32 - endpoint[c]).powi(2)).sum::<f32>();
+      (quantized, p_bit, error)
+    })
+    .into_iter()
+    .fold(None, |best: Option<([u32; 4], u32, f32)>, x| match best {
+      Some(best) if best.2 <= x.2 => Some(best),
+      _ => Some(x),
+    })
+    .map(|(quantized, p_bit, _)| (quantized, p_bit))
+    .unwrap_or(([0; 4], 0))
+}
+
+// Mode 6: the mode bit, both endpoints a channel at a time with 7 bits each, their lowest bits,
+// then 4 bit indices with the first texel's top bit left out as 0
+fn encode_bc7(block: &Block, endpoints: ([f32; 4], [f32; 4])) -> ([u8; 16], f32, [f32; 16]) {
+  let (mut end_0, mut p_0) = quantize_bc7(&endpoints.0);
+  let (mut end_1, mut p_1) = quantize_bc7(&endpoints.1);
+  let expand = |end: [u32; 4], p_bit: u32| end.map(|x| x * 2 + p_bit);
+  let (full_0, full_1) = (expand(end_0, p_0), expand(end_1, p_1));
+  let palette = BC7_WEIGHTS
+    .map(|w| [0, 1, 2, 3].map(|c| (((64 - w) * full_0[c] + w * full_1[c] + 32) >> 6) as f32));
+  let mut indices = [0u32; 16];
+  let mut error = 0.0;
+  for (i, texel) in block.iter().enumerate() {
+    let (index, texel_error) = (0..16)
+      .map(|index| (index, distance_sq(texel, &palette[index])))
+      .fold((0, f32::MAX), |best, x| if x.1 < best.1 { x } else { best });
+    indices[i] = index as u32;
+    error += texel_error;
+  }
+  if indices[0] >= 8 {
+    std::mem::swap(&mut end_0, &mut end_1);
+    std::mem::swap(&mut p_0, &mut p_1);
+    indices = indices.map(|index| 15 - index);
+  }
+  let mut bits = 0u128;
+  let mut bit_count = 0;
+  let mut push = |value: u32, count: u32| {
+    bits |= (value as u128) << bit_count;
+    bit_count += count;
+  };
+  push(1 << 6, 7);
+  for channel in 0..4 {
+    push(end_0[channel], 7);
+    push(end_1[channel], 7);
+  }
+  push(p_0, 1);
+  push(p_1, 1);
+  for (i, index) in indices.iter().enumerate() {
+    push(*index, if i == 0 { 3 } else { 4 });
+  }
+  let weights = indices.map(|index| BC7_WEIGHTS[index as usize] as f32 / 64.0);
+  (bits.to_le_bytes(), error, weights)
+}
+
+fn encode_block(format: BlockFormat, quality: BlockQuality, block: &Block) -> Vec<u8> {
+  let encode = |endpoints| match format {
+    BlockFormat::Bc1 => {
+      let (bytes, error, weights) = encode_bc1(block, endpoints);
+      (bytes.to_vec(), error, weights)
+    }
+    BlockFormat::Bc7 => {
+      let (bytes, error, weights) = encode_bc7(block, endpoints);
+      (bytes.to_vec(), error, weights)
+    }
+  };
+  let mut best = encode(principal_endpoints(block));
+  if quality == BlockQuality::Refined {
+    for _ in 0..REFINE_PASSES {
+      // BC1 may have swapped the endpoints, its weights are measured from the first one it wrote
+      let Some(endpoints) = fit_endpoints(block, &best.2) else { break };
+      let refit = encode(match format {
+        BlockFormat::Bc1 => (endpoints.1, endpoints.0),
+        BlockFormat::Bc7 => endpoints,
+      });
+      if refit.1 >= best.1 {
+        break;
+      }
+      best = refit;
+    }
+  }
+  best.0
+}
+
+// Block rows of a level of rgba8 texels, a row of blocks after another, left to right in each.
+// Levels are split into ranges of rows so the encoding can be spread over jobs and frames
+pub fn encode_block_rows(
+  format: BlockFormat,
+  quality: BlockQuality,
+  extent: vk::Extent2D,
+  texels: &[u8],
+  rows: Range<u32>,
+) -> Vec<u8> {
+  let blocks_across = extent.width.div_ceil(4);
+  let mut out = Vec::with_capacity(rows.len() * blocks_across as usize * format.block_bytes());
+  for block_y in rows {
+    for block_x in 0..blocks_across {
+      out.extend(encode_block(format, quality, &read_block(extent, texels, block_x, block_y)));
+    }
+  }
+  out
+}
+
+// Levels of blocks from the full size one down, all the way to 1x1 or only the first
+pub struct CompressedFlatTexture {
+  pub format: BlockFormat,
+  pub resolution: vk::Extent2D,
+  pub mips: Vec<(vk::Extent2D, Vec<u8>)>,
+}
+
+impl CompressedFlatTexture {
+  // Key in the asset cache for the rgba8 texels of a texture's first level compressed like this
+  pub fn cache_hash(
+    texels: &[u8],
+    resolution: vk::Extent2D,
+    levels: u32,
+    format: BlockFormat,
+    quality: BlockQuality,
+  ) -> u64 {
+    ContentHasher::new()
+      .update(&COMPRESSED_CACHE_VERSION.to_le_bytes())
+      .update(&resolution.width.to_le_bytes())
+      .update(&resolution.height.to_le_bytes())
+      .update(&levels.to_le_bytes())
+      .update(&[format.cache_tag(), quality as u8])
+      .update(texels)
+      .finish()
+  }
+
+  pub fn load_cached(hash: u64) -> Option<Self> {
+    asset_cache::global().load(COMPRESSED_CACHE_KIND, hash).and_then(Self::from_cache_bytes)
+  }
+
+  pub fn store_cached(&self, hash: u64) {
+    asset_cache::global().store(COMPRESSED_CACHE_KIND, hash, &self.to_cache_bytes());
+  }
+
+  pub fn bytes(&self) -> u64 {
+    self.mips.iter().map(|(_, blocks)| blocks.len() as u64).sum()
+  }
+
+  // Artifact layout, integers little endian: format u8, width u32, height u32, levels u32, then
+  // the blocks of every level from the largest down
+  pub fn to_cache_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(13 + self.bytes() as usize);
+    bytes.push(self.format.cache_tag());
+    bytes.extend(self.resolution.width.to_le_bytes());
+    bytes.extend(self.resolution.height.to_le_bytes());
+    bytes.extend((self.mips.len() as u32).to_le_bytes());
+    for (_, blocks) in &self.mips {
+      bytes.extend(blocks);
+    }
+    bytes
+  }
+
+  pub fn from_cache_bytes(bytes: Vec<u8>) -> Option<Self> {
+    let header = bytes.get(0..13)?;
+    let format = *BlockFormat::ALL.get(header[0] as usize)?;
+    let width = u32::from_le_bytes(header[1..5].try_into().ok()?);
+    let height = u32::from_le_bytes(header[5..9].try_into().ok()?);
+    let levels = u32::from_le_bytes(header[9..13].try_into().ok()?);
+    let resolution = vk::Extent2D { width, height };
+    if width == 0 || height == 0 || levels == 0 || levels > mip_count(resolution) {
+      return None;
+    }
+    let mut mips = vec![];
+    let mut offset = 13;
+    for mip in 0..levels {
+      let extent = mip_extent(resolution, mip);
+      let blocks = bytes.get(offset..offset + format.level_bytes(extent))?;
+      mips.push((extent, blocks.to_vec()));
+      offset += blocks.len();
+    }
+    (offset == bytes.len()).then_some(Self { format, resolution, mips })
+  }
+}
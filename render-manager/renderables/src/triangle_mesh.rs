@@ -1,4 +1,7 @@
-use std::sync::{Arc, Mutex};
+use std::{
+  ops::{Add, Mul},
+  sync::{Arc, Mutex, PoisonError, RwLock},
+};
 
 use ash_ad_wrappers::{
   ash_context::{
@@ -7,17 +10,26 @@ use ash_ad_wrappers::{
     gpu_allocator::{vulkan::Allocator, MemoryLocation},
   },
   ash_data_wrappers::{
-    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdBuffer, AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout,
   },
-  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool, AdQueue},
-  ash_sync_wrappers::AdFence,
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
+};
+#[cfg(feature = "ray-tracing")]
+use ash_ad_wrappers::ash_rt_wrappers::{
+  AdAccelerationStructure, AdBlasTriangles, AdRayTracingDevice,
 };
 
+use crate::mesh_arena::{ArenaAllocation, MeshArena, MeshArenaStats};
+use color::Color;
+
+// Matches the weight array size in common_structs.glsl
+pub const MAX_MORPH_TARGETS: usize = 8;
+
 pub fn g_vec4_from_vec3(v: glam::Vec3, w: f32) -> glam::Vec4 {
   glam::vec4(v.x, v.y, v.z, w)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct TriMeshVertex {
   pub pos: glam::Vec4,
@@ -31,12 +43,146 @@ pub struct TriMeshTransform {
   pub transform: glam::Mat4,
 }
 
+// ObjectData in the shaders. The transform the last frame was drawn with gives motion vectors
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct TriMeshObjectData {
+  transform: glam::Mat4,
+  prev_transform: glam::Mat4,
+}
+
+impl Default for TriMeshObjectData {
+  fn default() -> Self {
+    Self { transform: glam::Mat4::IDENTITY, prev_transform: glam::Mat4::IDENTITY }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct MorphTargetDelta {
+  pub pos: glam::Vec4,
+  pub normal: glam::Vec4,
+}
+
+// Offsets added to every vertex of the base mesh, scaled by the target's weight
+pub struct MorphTargetCPU {
+  pub name: String,
+  pub deltas: Vec<MorphTargetDelta>,
+}
+
+// Per target blend weights, usable as a KeyFramed value
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[repr(C)]
+pub struct MorphWeights(pub [f32; MAX_MORPH_TARGETS]);
+
+impl Mul<f32> for MorphWeights {
+  type Output = Self;
+
+  fn mul(self, rhs: f32) -> Self {
+    Self(self.0.map(|w| w * rhs))
+  }
+}
+
+impl Add for MorphWeights {
+  type Output = Self;
+
+  fn add(self, rhs: Self) -> Self {
+    let mut sum = self.0;
+    for (w, rhs_w) in sum.iter_mut().zip(rhs.0) {
+      *w += rhs_w;
+    }
+    Self(sum)
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct MorphData {
+  weights: MorphWeights,
+  // target count, vertex count, unused, unused
+  counts: [u32; 4],
+}
+
+// StreamsData in the shaders
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct StreamsData {
+  // 1 where the mesh has colors, second uvs, unused, unused
+  present: [u32; 4],
+}
+
+fn append_stream<T: Copy>(
+  stream: &mut Option<Vec<T>>,
+  len: usize,
+  other: Option<&[T]>,
+  other_len: usize,
+  fill: T,
+) {
+  if stream.is_none() && other.is_none() {
+    return;
+  }
+  let stream = stream.get_or_insert_with(|| vec![fill; len]);
+  match other {
+    Some(other) => stream.extend_from_slice(other),
+    None => stream.extend(std::iter::repeat_n(fill, other_len)),
+  }
+}
+
+// Per vertex data besides TriMeshVertex, each in its own buffer that only materials reading it
+// bind. Streams that are set have an entry per vertex
+#[derive(Debug, Clone, Default)]
+pub struct VertexStreams {
+  pub colors: Option<Vec<Color>>,
+  // Second uv set, like the lightmap layout
+  pub uv2: Option<Vec<glam::Vec2>>,
+}
+
+impl VertexStreams {
+  pub fn is_empty(&self) -> bool {
+    self.colors.is_none() && self.uv2.is_none()
+  }
+
+  // Adds other's entries after the first len vertices. When only one side has a stream the other's
+  // vertices get white or zero uvs in it
+  pub fn append(&mut self, len: usize, other: &Self, other_len: usize) {
+    append_stream(&mut self.colors, len, other.colors.as_deref(), other_len, Color::WHITE);
+    append_stream(&mut self.uv2, len, other.uv2.as_deref(), other_len, glam::Vec2::ZERO);
+  }
+
+  fn validate(&self, vertex_count: usize) -> Result<(), String> {
+    let lens =
+      [("colors", self.colors.as_ref().map(Vec::len)), ("uv2", self.uv2.as_ref().map(Vec::len))];
+    for (name, len) in lens {
+      if let Some(len) = len.filter(|len| *len != vertex_count) {
+        return Err(format!("{name} stream has {len} entries for {vertex_count} vertices"));
+      }
+    }
+    Ok(())
+  }
+}
+
+#[derive(Clone)]
 pub struct TriMeshCPU {
   pub vertices: Vec<TriMeshVertex>,
   pub triangles: Vec<[u32; 3]>,
+  pub streams: VertexStreams,
 }
 
 impl TriMeshCPU {
+  pub fn new(vertices: Vec<TriMeshVertex>, triangles: Vec<[u32; 3]>) -> Self {
+    Self { vertices, triangles, streams: VertexStreams::default() }
+  }
+
+  pub fn with_colors(mut self, colors: Vec<Color>) -> Result<Self, String> {
+    self.streams.colors = Some(colors);
+    self.streams.validate(self.vertices.len()).map(|_| self)
+  }
+
+  pub fn with_uv2(mut self, uv2: Vec<glam::Vec2>) -> Result<Self, String> {
+    self.streams.uv2 = Some(uv2);
+    self.streams.validate(self.vertices.len()).map(|_| self)
+  }
+
   pub fn merge(mut self, mut other: Self) -> Self {
     let curr_vert_len = self.vertices.len() as u32;
     for t in other.triangles.iter_mut() {
@@ -44,13 +190,14 @@ impl TriMeshCPU {
         *idx += curr_vert_len;
       }
     }
+    self.streams.append(self.vertices.len(), &other.streams, other.vertices.len());
     self.vertices.append(&mut other.vertices);
     self.triangles.append(&mut other.triangles);
     self
   }
 
   pub fn combine(inp: Vec<Self>) -> Self {
-    let mut empty_mesh = Self{vertices: Vec::new(), triangles: Vec::new()};
+    let mut empty_mesh = Self::new(Vec::new(), Vec::new());
     for mesh in inp {
       empty_mesh = empty_mesh.merge(mesh);
     }
@@ -82,7 +229,7 @@ impl TriMeshCPU {
       },
     ];
     let triangles = vec![[0, 1, 2], [2, 3, 0]];
-    Self { vertices: verts, triangles }
+    Self::new(verts, triangles)
   }
 
   pub fn make_cuboid(
@@ -92,7 +239,7 @@ impl TriMeshCPU {
     z_len: f32,
   ) -> Self {
     let axis_z = axis_x.cross(axis_y).normalize() * z_len;
-    Self { vertices: vec![], triangles: vec![] }
+    Self::new(vec![], vec![])
       .merge(Self::make_rect(center + (axis_x / 2.0), axis_y, axis_z))
       .merge(Self::make_rect(center - (axis_x / 2.0), axis_z, axis_y))
       .merge(Self::make_rect(center + (axis_y / 2.0), axis_z, axis_x))
@@ -127,161 +274,519 @@ impl TriMeshCPU {
         [0, i, i + 1]
       })
       .collect::<Vec<_>>();
-    Self{vertices, triangles}
+    Self::new(vertices, triangles)
+  }
+
+  // Open tube of sides faces around a line through points, for ropes and cables rebuilt every
+  // frame. Rings are carried along the line without twisting, v of the uvs is the length so far
+  pub fn make_tube(points: &[glam::Vec3], radius: f32, sides: u32) -> Self {
+    let sides = sides.max(3);
+    if points.len() < 2 {
+      return Self::new(vec![], vec![]);
+    }
+    let mut vertices = Vec::with_capacity(points.len() * (sides as usize + 1));
+    let mut side = glam::Vec3::ZERO;
+    let mut length = 0.0;
+    for (i, point) in points.iter().enumerate() {
+      let along = (points[(i + 1).min(points.len() - 1)] - points[i.saturating_sub(1)])
+        .normalize_or(glam::Vec3::Y);
+      side = (side - along * side.dot(along)).normalize_or(along.any_orthonormal_vector());
+      let up = along.cross(side);
+      if i > 0 {
+        length += point.distance(points[i - 1]);
+      }
+      // One more than sides so the seam gets its own uvs
+      for k in 0..=sides {
+        let angle = k as f32 / sides as f32 * std::f32::consts::TAU;
+        let normal = side * angle.cos() + up * angle.sin();
+        vertices.push(TriMeshVertex {
+          pos: g_vec4_from_vec3(*point + normal * radius, 1.0),
+          normal: g_vec4_from_vec3(normal, 0.0),
+          uv: glam::vec4(k as f32 / sides as f32, length, 0.0, 0.0),
+        });
+      }
+    }
+    let ring = sides + 1;
+    let mut triangles = Vec::with_capacity((points.len() - 1) * sides as usize * 2);
+    for i in 0..points.len() as u32 - 1 {
+      for k in 0..sides {
+        let (v00, v01) = (i * ring + k, i * ring + k + 1);
+        let (v10, v11) = (v00 + ring, v01 + ring);
+        triangles.push([v00, v01, v11]);
+        triangles.push([v11, v10, v00]);
+      }
+    }
+    Self::new(vertices, triangles)
+  }
+}
+
+#[cfg(feature = "ray-tracing")]
+impl TriMeshCPU {
+  // Positions only, the vertex and index copies are dropped once the build is done
+  pub fn build_blas(
+    &self,
+    rt_device: Arc<AdRayTracingDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_buffer: &AdCommandBuffer,
+    name: &str,
+  ) -> Result<AdAccelerationStructure, String> {
+    let usage = vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+      | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS;
+    let vertex_buffer = AdBuffer::from_data(
+      rt_device.ash_device().clone(),
+      allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("{name}_blas_vertices"),
+      vk::BufferCreateFlags::empty(),
+      usage,
+      &self.vertices,
+      cmd_buffer,
+    )?;
+    let index_buffer = AdBuffer::from_data(
+      rt_device.ash_device().clone(),
+      allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("{name}_blas_indices"),
+      vk::BufferCreateFlags::empty(),
+      usage,
+      &self.triangles,
+      cmd_buffer,
+    )?;
+    AdAccelerationStructure::build_blas(
+      rt_device,
+      allocator,
+      cmd_buffer,
+      name,
+      &[AdBlasTriangles {
+        vertex_buffer: &vertex_buffer,
+        vertex_stride: size_of::<TriMeshVertex>() as u64,
+        vertex_count: self.vertices.len() as u32,
+        index_buffer: &index_buffer,
+        index_count: self.triangles.len() as u32 * 3,
+        opaque: true,
+      }],
+    )
   }
 }
 
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct TriMeshGPU {
+  // Replaced when the mesh arena moves the vertices and indices
+  dset: Arc<RwLock<Arc<AdDescriptorSet>>>,
+  // Given back to the arena when the mesh is dropped, None when the vertices are someone else's
+  _arena_allocation: Option<ArenaAllocation>,
+  // Set 3 of pipelines reading vertex streams, the generator's empty one without streams
   #[getset(get = "pub")]
-  dset: Arc<AdDescriptorSet>,
+  streams_dset: Arc<AdDescriptorSet>,
   #[getset(get_copy = "pub")]
   indx_count: usize,
+  #[getset(get_copy = "pub")]
+  morph_target_count: usize,
+  // Around the mesh origin, covers every morph target at full weight too
+  #[getset(get_copy = "pub")]
+  bounding_radius: f32,
+  // Center and radius of the bounds under the last transform set
+  world_bounds: Mutex<glam::Vec4>,
 }
 
 impl TriMeshGPU {
+  // Writes only swap the set, so a poisoned lock still holds a usable one
+  pub fn dset(&self) -> Arc<AdDescriptorSet> {
+    self.dset.read().unwrap_or_else(PoisonError::into_inner).clone()
+  }
+
   pub fn update_transform(&self, t: TriMeshTransform) -> Result<(), String> {
-    let AdDescriptorBinding::UniformBuffer(ob) = &self.dset.bindings()[2] else {
+    let dset = self.dset();
+    let AdDescriptorBinding::UniformBuffer(ob) = &dset.bindings()[2] else {
       return Err("Triangle mesh constructed with improper object data buffer".to_string())
     };
     ob.write_data(0, &[t])?;
+    let (scale, _, center) = t.transform.to_scale_rotation_translation();
+    *self.world_bounds.lock().map_err(|e| format!("at getting world bounds lock: {e}"))? =
+      center.extend(self.bounding_radius * scale.abs().max_element());
     Ok(())
   }
+
+  // Left alone by update_transform, the renderer sets it once per frame
+  pub fn update_prev_transform(&self, prev_transform: glam::Mat4) -> Result<(), String> {
+    let dset = self.dset();
+    let AdDescriptorBinding::UniformBuffer(ob) = &dset.bindings()[2] else {
+      return Err("Triangle mesh constructed with improper object data buffer".to_string())
+    };
+    ob.write_data(std::mem::offset_of!(TriMeshObjectData, prev_transform), &[prev_transform])
+  }
+
+  pub fn world_bounds(&self) -> Result<glam::Vec4, String> {
+    self
+      .world_bounds
+      .lock()
+      .map(|bounds| *bounds)
+      .map_err(|e| format!("at getting world bounds lock: {e}"))
+  }
+
+  pub fn update_morph_weights(&self, weights: MorphWeights) -> Result<(), String> {
+    if self.morph_target_count == 0 {
+      return Err("Triangle mesh has no morph targets".to_string());
+    }
+    let dset = self.dset();
+    let AdDescriptorBinding::UniformBuffer(mb) = &dset.bindings()[4] else {
+      return Err("Triangle mesh constructed with improper morph weight buffer".to_string());
+    };
+    // Counts come after the weights and never change
+    mb.write_data(0, &[weights])
+  }
 }
 
 #[derive(getset::Getters, getset::CopyGetters)]
 pub struct TriMeshGenerator {
   allocator: Arc<Mutex<Allocator>>,
   cmd_pool: Arc<AdCommandPool>,
-  mesh_dset_pool: Arc<AdDescriptorPool>,
+  #[getset(get = "pub")]
+  mesh_dset_pools: AdDescriptorPoolManager,
   #[getset(get = "pub")]
   mesh_dset_layout: Arc<AdDescriptorSetLayout>,
+  #[getset(get = "pub")]
+  streams_dset_layout: Arc<AdDescriptorSetLayout>,
+  // Bound in place of the morph buffers for meshes without morph targets
+  empty_morph_deltas: Arc<AdBuffer>,
+  empty_morph_weights: Arc<AdBuffer>,
+  // Shared by meshes without vertex streams, marks every stream as missing
+  empty_streams_dset: Arc<AdDescriptorSet>,
+  mesh_arena: MeshArena,
 }
 
 impl TriMeshGenerator {
-  pub fn new(allocator: Arc<Mutex<Allocator>>, queue: Arc<AdQueue>) -> Result<Self, String> {
-    let ash_device = queue.ash_device().clone();
-    let dset_pool = AdDescriptorPool::new(
+  // Vertices and indices go in mesh arena blocks of block_size bytes
+  pub fn new(
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_pool: Arc<AdCommandPool>,
+    block_size: u64,
+  ) -> Result<Self, String> {
+    let ash_device = cmd_pool.queue().ash_device().clone();
+    let dset_pools = AdDescriptorPoolManager::new(ash_device.clone(), "mesh", 256);
+    let dset_layout = AdDescriptorSetLayout::new(
       ash_device.clone(),
-      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
-      3000,
       &[
-        vk::DescriptorPoolSize { ty: vk::DescriptorType::STORAGE_BUFFER, descriptor_count: 2000 },
-        vk::DescriptorPoolSize { ty: vk::DescriptorType::UNIFORM_BUFFER, descriptor_count: 1000 },
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::UNIFORM_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::UNIFORM_BUFFER),
+        // Vertices as the last frame drew them, for motion vectors
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
       ],
     )?;
-    let dset_layout = AdDescriptorSetLayout::new(
+    let empty_morph_deltas = AdBuffer::new(
+      ash_device.clone(),
+      allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      "empty_morph_deltas",
+      vk::BufferCreateFlags::empty(),
+      std::mem::size_of::<MorphTargetDelta>() as _,
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+    )?;
+    empty_morph_deltas.write_data(0, &[MorphTargetDelta::default()])?;
+    let empty_morph_weights = AdBuffer::new(
+      ash_device.clone(),
+      allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      "empty_morph_weights",
+      vk::BufferCreateFlags::empty(),
+      std::mem::size_of::<MorphData>() as _,
+      vk::BufferUsageFlags::UNIFORM_BUFFER,
+    )?;
+    empty_morph_weights.write_data(0, &[MorphData { weights: MorphWeights::default(), counts: [0; 4] }])?;
+    let streams_dset_layout = Arc::new(AdDescriptorSetLayout::new(
       ash_device.clone(),
       &[
         (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
         (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
         (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::UNIFORM_BUFFER),
       ],
+    )?);
+    let empty_streams_dset = Self::create_streams_dset(
+      &allocator,
+      &cmd_pool,
+      &dset_pools,
+      &streams_dset_layout,
+      "empty",
+      &VertexStreams::default(),
     )?;
-    let cmd_pool = AdCommandPool::new(queue, vk::CommandPoolCreateFlags::TRANSIENT)?;
     Ok(Self {
+      mesh_arena: MeshArena::new(allocator.clone(), cmd_pool.clone(), block_size),
       allocator,
-      cmd_pool: Arc::new(cmd_pool),
-      mesh_dset_pool: Arc::new(dset_pool),
+      cmd_pool,
+      mesh_dset_pools: dset_pools,
       mesh_dset_layout: Arc::new(dset_layout),
+      streams_dset_layout,
+      empty_morph_deltas: Arc::new(empty_morph_deltas),
+      empty_morph_weights: Arc::new(empty_morph_weights),
+      empty_streams_dset: Arc::new(empty_streams_dset),
     })
   }
 
+  // A stream the mesh doesn't have gets a one entry placeholder, the shaders never read it
+  fn create_streams_dset(
+    allocator: &Arc<Mutex<Allocator>>,
+    cmd_pool: &Arc<AdCommandPool>,
+    dset_pools: &AdDescriptorPoolManager,
+    dset_layout: &Arc<AdDescriptorSetLayout>,
+    name: &str,
+    streams: &VertexStreams,
+  ) -> Result<AdDescriptorSet, String> {
+    let ash_device = cmd_pool.queue().ash_device().clone();
+    let colors = streams.colors.as_deref().unwrap_or(&[Color::WHITE]);
+    let uv2 = streams.uv2.as_deref().unwrap_or(&[glam::Vec2::ZERO]);
+    let color_buffer = AdBuffer::from_data(
+      ash_device.clone(),
+      allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("{name}_colors"),
+      vk::BufferCreateFlags::empty(),
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+      colors,
+      &AdCommandBuffer::new(cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0),
+    )?;
+    let uv2_buffer = AdBuffer::from_data(
+      ash_device.clone(),
+      allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("{name}_uv2"),
+      vk::BufferCreateFlags::empty(),
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+      uv2,
+      &AdCommandBuffer::new(cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0),
+    )?;
+    let present_buffer = AdBuffer::new(
+      ash_device,
+      allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      &format!("{name}_streams"),
+      vk::BufferCreateFlags::empty(),
+      std::mem::size_of::<StreamsData>() as _,
+      vk::BufferUsageFlags::UNIFORM_BUFFER,
+    )?;
+    present_buffer.write_data(
+      0,
+      &[StreamsData {
+        present: [streams.colors.is_some() as u32, streams.uv2.is_some() as u32, 0, 0],
+      }],
+    )?;
+    Ok(
+      dset_pools
+        .allocate(&[(
+          dset_layout.clone(),
+          vec![
+            AdDescriptorBinding::StorageBuffer(Arc::new(color_buffer)),
+            AdDescriptorBinding::StorageBuffer(Arc::new(uv2_buffer)),
+            AdDescriptorBinding::UniformBuffer(Arc::new(present_buffer)),
+          ],
+        )])?
+        .remove(0),
+    )
+  }
+
+  pub fn mesh_arena_stats(&self) -> Result<MeshArenaStats, String> {
+    self.mesh_arena.stats()
+  }
+
+  // See MeshArena::compact, no mesh from this generator can be in use on the gpu
+  pub fn compact_mesh_arena(&self) -> Result<MeshArenaStats, String> {
+    self.mesh_arena.compact(&self.mesh_dset_pools)
+  }
+
   pub fn upload_tri_mesh(
     &self,
     name: &str,
     tri_mesh_cpu: &TriMeshCPU,
   ) -> Result<TriMeshGPU, String> {
-    let ash_device = self.cmd_pool.queue().ash_device().clone();
-    let cmd_buffer =
-      AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+    self.upload_morph_tri_mesh(name, tri_mesh_cpu, &[])
+  }
 
-    let vert_buffer_data = AdBuffer::get_byte_slice(&tri_mesh_cpu.vertices);
-    let vert_buffer = AdBuffer::new(
-      ash_device.clone(),
-      self.allocator.clone(),
-      MemoryLocation::GpuOnly,
-      &format!("{name}_vb"),
-      vk::BufferCreateFlags::empty(),
-      vert_buffer_data.len() as _,
-      vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+  pub fn upload_morph_tri_mesh(
+    &self,
+    name: &str,
+    tri_mesh_cpu: &TriMeshCPU,
+    morph_targets: &[MorphTargetCPU],
+  ) -> Result<TriMeshGPU, String> {
+    if morph_targets.len() > MAX_MORPH_TARGETS {
+      return Err(format!(
+        "mesh {name} has {} morph targets, at most {MAX_MORPH_TARGETS} are supported",
+        morph_targets.len()
+      ));
+    }
+    if let Some(target) =
+      morph_targets.iter().find(|target| target.deltas.len() != tri_mesh_cpu.vertices.len())
+    {
+      return Err(format!(
+        "morph target {} of mesh {name} has {} deltas for {} vertices",
+        target.name,
+        target.deltas.len(),
+        tri_mesh_cpu.vertices.len()
+      ));
+    }
+    tri_mesh_cpu
+      .streams
+      .validate(tri_mesh_cpu.vertices.len())
+      .map_err(|e| format!("at uploading mesh {name}: {e}"))?;
+    let streams_dset = match tri_mesh_cpu.streams.is_empty() {
+      true => self.empty_streams_dset.clone(),
+      false => Arc::new(Self::create_streams_dset(
+        &self.allocator,
+        &self.cmd_pool,
+        &self.mesh_dset_pools,
+        &self.streams_dset_layout,
+        name,
+        &tri_mesh_cpu.streams,
+      )?),
+    };
+    let ash_device = self.cmd_pool.queue().ash_device().clone();
+    let (arena_allocation, [vert_binding, indx_binding]) = self.mesh_arena.upload(
+      name,
+      AdBuffer::get_byte_slice(&tri_mesh_cpu.vertices),
+      AdBuffer::get_byte_slice(&tri_mesh_cpu.triangles),
     )?;
-    let vert_buffer_stage = AdBuffer::new(
+
+    let objt_buffer = AdBuffer::new(
       ash_device.clone(),
       self.allocator.clone(),
       MemoryLocation::CpuToGpu,
-      &format!("{name}_vb_stage"),
+      &format!("{name}_ob"),
       vk::BufferCreateFlags::empty(),
-      vert_buffer_data.len() as _,
-      vk::BufferUsageFlags::TRANSFER_SRC,
+      std::mem::size_of::<TriMeshObjectData>() as _,
+      vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
     )?;
-    vert_buffer_stage.write_data(0, vert_buffer_data)?;
+    objt_buffer.write_data(0, &[TriMeshObjectData::default()])?;
+
+    // Deltas are stored target after target, each with one entry per vertex
+    let (morph_delta_buffer, morph_weight_buffer) = if morph_targets.is_empty() {
+      (self.empty_morph_deltas.clone(), self.empty_morph_weights.clone())
+    } else {
+      let morph_deltas =
+        morph_targets.iter().flat_map(|target| target.deltas.iter().copied()).collect::<Vec<_>>();
+      let morph_delta_buffer = AdBuffer::from_data(
+        ash_device.clone(),
+        self.allocator.clone(),
+        MemoryLocation::GpuOnly,
+        &format!("{name}_mdb"),
+        vk::BufferCreateFlags::empty(),
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        &morph_deltas,
+        &AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0),
+      )?;
+      let morph_weight_buffer = AdBuffer::new(
+        ash_device.clone(),
+        self.allocator.clone(),
+        MemoryLocation::CpuToGpu,
+        &format!("{name}_mwb"),
+        vk::BufferCreateFlags::empty(),
+        std::mem::size_of::<MorphData>() as _,
+        vk::BufferUsageFlags::UNIFORM_BUFFER,
+      )?;
+      morph_weight_buffer.write_data(
+        0,
+        &[MorphData {
+          weights: MorphWeights::default(),
+          counts: [morph_targets.len() as u32, tri_mesh_cpu.vertices.len() as u32, 0, 0],
+        }],
+      )?;
+      (Arc::new(morph_delta_buffer), Arc::new(morph_weight_buffer))
+    };
+
+    let mesh_dset = self
+      .mesh_dset_pools
+      .allocate(&[(
+        self.mesh_dset_layout.clone(),
+        vec![
+          vert_binding.clone(),
+          indx_binding,
+          AdDescriptorBinding::UniformBuffer(Arc::new(objt_buffer)),
+          AdDescriptorBinding::StorageBuffer(morph_delta_buffer),
+          AdDescriptorBinding::UniformBuffer(morph_weight_buffer),
+          // Uploaded vertices never change, only the transform moves them
+          vert_binding,
+        ],
+      )])?
+      .remove(0);
+
+    let base_radius =
+      tri_mesh_cpu.vertices.iter().map(|vertex| vertex.pos.truncate().length()).fold(0.0, f32::max);
+    let morph_radius = morph_targets
+      .iter()
+      .map(|target| {
+        target.deltas.iter().map(|delta| delta.pos.truncate().length()).fold(0.0, f32::max)
+      })
+      .sum::<f32>();
 
-    let indx_buffer_data = AdBuffer::get_byte_slice(&tri_mesh_cpu.triangles);
-    let indx_buffer = AdBuffer::new(
+    let dset = Arc::new(RwLock::new(Arc::new(mesh_dset)));
+    arena_allocation.track_dset(Arc::downgrade(&dset))?;
+    Ok(TriMeshGPU {
+      dset,
+      _arena_allocation: Some(arena_allocation),
+      streams_dset,
+      indx_count: tri_mesh_cpu.triangles.len() * 3,
+      morph_target_count: morph_targets.len(),
+      bounding_radius: base_radius + morph_radius,
+      world_bounds: Mutex::new(glam::Vec3::ZERO.extend(base_radius + morph_radius)),
+    })
+  }
+
+  // Drawn from vertices something else writes, like a compute pass. The buffers need to hold
+  // TriMeshVertex entries and stay shared with the writer, prev_vert_buffer has the positions the
+  // last frame was drawn with
+  pub fn create_tri_mesh_over(
+    &self,
+    name: &str,
+    vert_buffer: Arc<AdBuffer>,
+    prev_vert_buffer: Arc<AdBuffer>,
+    triangles: &[[u32; 3]],
+    bounding_radius: f32,
+  ) -> Result<TriMeshGPU, String> {
+    let ash_device = self.cmd_pool.queue().ash_device().clone();
+    let indx_buffer = AdBuffer::from_data(
       ash_device.clone(),
       self.allocator.clone(),
       MemoryLocation::GpuOnly,
       &format!("{name}_ib"),
       vk::BufferCreateFlags::empty(),
-      vert_buffer_data.len() as _,
-      vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+      triangles,
+      &AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0),
     )?;
-    let indx_buffer_stage = AdBuffer::new(
-      ash_device.clone(),
-      self.allocator.clone(),
-      MemoryLocation::CpuToGpu,
-      &format!("{name}_ib_stage"),
-      vk::BufferCreateFlags::empty(),
-      vert_buffer_data.len() as _,
-      vk::BufferUsageFlags::TRANSFER_SRC,
-    )?;
-    indx_buffer_stage.write_data(0, indx_buffer_data)?;
-
-    let obj_transform = vec![TriMeshTransform { transform: glam::Mat4::IDENTITY }];
-    let objt_buffer_data = AdBuffer::get_byte_slice(&obj_transform);
     let objt_buffer = AdBuffer::new(
       ash_device.clone(),
       self.allocator.clone(),
       MemoryLocation::CpuToGpu,
       &format!("{name}_ob"),
       vk::BufferCreateFlags::empty(),
-      vert_buffer_data.len() as _,
-      vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+      std::mem::size_of::<TriMeshObjectData>() as _,
+      vk::BufferUsageFlags::UNIFORM_BUFFER,
     )?;
-    objt_buffer.write_data(0, objt_buffer_data)?;
-
-    // Copy from stage buffers to gpu local
-    cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
-    cmd_buffer.copy_buffer_to_buffer_cmd(
-      vert_buffer_stage.inner(),
-      vert_buffer.inner(),
-      &[vk::BufferCopy { src_offset: 0, dst_offset: 0, size: vert_buffer.size() }],
-    );
-    cmd_buffer.copy_buffer_to_buffer_cmd(
-      indx_buffer_stage.inner(),
-      indx_buffer.inner(),
-      &[vk::BufferCopy { src_offset: 0, dst_offset: 0, size: indx_buffer.size() }],
-    );
-    cmd_buffer.end()?;
-
-    let fence = AdFence::new(ash_device.clone(), vk::FenceCreateFlags::empty())?;
-    cmd_buffer.submit(&[], &[], Some(&fence))?;
-    fence.wait(999999999)?;
-
-    let mesh_dset = AdDescriptorSet::new(
-      self.mesh_dset_pool.clone(),
-      &[(
+    objt_buffer.write_data(0, &[TriMeshObjectData::default()])?;
+
+    let mesh_dset = self
+      .mesh_dset_pools
+      .allocate(&[(
         self.mesh_dset_layout.clone(),
         vec![
-          AdDescriptorBinding::StorageBuffer(Arc::new(vert_buffer)),
+          AdDescriptorBinding::StorageBuffer(vert_buffer),
           AdDescriptorBinding::StorageBuffer(Arc::new(indx_buffer)),
           AdDescriptorBinding::UniformBuffer(Arc::new(objt_buffer)),
+          AdDescriptorBinding::StorageBuffer(self.empty_morph_deltas.clone()),
+          AdDescriptorBinding::UniformBuffer(self.empty_morph_weights.clone()),
+          AdDescriptorBinding::StorageBuffer(prev_vert_buffer),
         ],
-      )],
-    )?
-    .remove(0);
+      )])?
+      .remove(0);
 
-    Ok(TriMeshGPU { dset: Arc::new(mesh_dset), indx_count: tri_mesh_cpu.triangles.len() * 3 })
+    Ok(TriMeshGPU {
+      dset: Arc::new(RwLock::new(Arc::new(mesh_dset))),
+      _arena_allocation: None,
+      streams_dset: self.empty_streams_dset.clone(),
+      indx_count: triangles.len() * 3,
+      morph_target_count: 0,
+      bounding_radius,
+      world_bounds: Mutex::new(glam::Vec3::ZERO.extend(bounding_radius)),
+    })
   }
 }
@@ -0,0 +1,227 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    image::{Rgba, RgbaImage},
+    AdBuffer, AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout,
+    AdSampler,
+  },
+};
+use color::Color;
+
+use crate::flat_texture::{FlatTextureGPU, FlatTextureGenerator, TextureColorSpace};
+
+const DEFAULT_NORMAL_MAP_SIZE: u32 = 128;
+// Integer frequencies so the generated normal map tiles, with amplitude and phase
+const DEFAULT_NORMAL_MAP_WAVES: [(f32, f32, f32, f32); 5] = [
+  (1.0, 2.0, 0.30, 0.0),
+  (3.0, -1.0, 0.20, 1.3),
+  (-2.0, 5.0, 0.12, 2.1),
+  (7.0, 3.0, 0.06, 0.4),
+  (-5.0, -9.0, 0.04, 4.2),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaterParams {
+  // Alpha is the opacity looking straight down
+  pub color: Color,
+  // Reflected at grazing angles when the plane has no reflection texture
+  pub sky_color: Color,
+  // Two normal map layers blended together, in uv per second
+  pub scroll: [glam::Vec2; 2],
+  // World units one repeat of the normal map covers
+  pub tile_size: f32,
+  pub normal_strength: f32,
+  // Higher keeps the reflection to flatter view angles
+  pub fresnel_power: f32,
+  // How far the normals bend the reflection, in screen uv
+  pub distortion: f32,
+}
+
+impl Default for WaterParams {
+  fn default() -> Self {
+    Self {
+      color: Color::new(0.02, 0.12, 0.16, 0.75),
+      sky_color: Color::rgb(0.45, 0.6, 0.75),
+      scroll: [glam::vec2(0.03, 0.01), glam::vec2(-0.01, 0.025)],
+      tile_size: 4.0,
+      normal_strength: 0.6,
+      fresnel_power: 5.0,
+      distortion: 0.03,
+    }
+  }
+}
+
+// A flat rectangle on the local xz plane, centered on the origin of its transform. Planes with
+// reflection pick up the planar reflection of the scene, which is only right for level planes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaterPlaneCPU {
+  pub transform: glam::Mat4,
+  pub size: glam::Vec2,
+  pub reflection: bool,
+  pub params: WaterParams,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct WaterData {
+  transform: glam::Mat4,
+  color: glam::Vec4,
+  sky_color: glam::Vec4,
+  // xy first normal layer scroll, zw second
+  scroll: glam::Vec4,
+  // size x, size z, tile size, fresnel power
+  shape: glam::Vec4,
+  // normal strength, distortion, reflection, unused
+  params: glam::Vec4,
+}
+
+impl WaterData {
+  fn new(plane: &WaterPlaneCPU) -> Self {
+    let params = &plane.params;
+    Self {
+      transform: plane.transform,
+      color: params.color.into(),
+      sky_color: params.sky_color.into(),
+      scroll: glam::vec4(
+        params.scroll[0].x,
+        params.scroll[0].y,
+        params.scroll[1].x,
+        params.scroll[1].y,
+      ),
+      shape: glam::vec4(plane.size.x, plane.size.y, params.tile_size, params.fresnel_power),
+      params: glam::vec4(
+        params.normal_strength,
+        params.distortion,
+        if plane.reflection { 1.0 } else { 0.0 },
+        0.0,
+      ),
+    }
+  }
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct WaterPlaneGPU {
+  #[getset(get = "pub")]
+  dset: Arc<AdDescriptorSet>,
+  // Kept alive for as long as the plane refers to it
+  #[getset(get = "pub")]
+  normal_map: Arc<FlatTextureGPU>,
+  #[getset(get_copy = "pub")]
+  reflection: bool,
+  // World height of the plane, where the reflection is mirrored
+  #[getset(get_copy = "pub")]
+  height: f32,
+}
+
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct WaterPlaneGenerator {
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  sampler: Arc<AdSampler>,
+  #[getset(get = "pub")]
+  water_dset_pools: AdDescriptorPoolManager,
+  #[getset(get = "pub")]
+  water_dset_layout: Arc<AdDescriptorSetLayout>,
+  default_normal_map: Arc<FlatTextureGPU>,
+}
+
+impl WaterPlaneGenerator {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    flat_tex_gen: &FlatTextureGenerator,
+  ) -> Result<Self, String> {
+    let dset_pools = AdDescriptorPoolManager::new(ash_device.clone(), "water", 4);
+    let dset_layout = AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (
+          vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+          vk::DescriptorType::UNIFORM_BUFFER,
+        ),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLER),
+      ],
+    )?;
+    let default_normal_map = flat_tex_gen.upload_flat_texture_rgba8(
+      "water_default_normal_map",
+      &Self::make_default_normal_map(),
+      TextureColorSpace::Linear,
+    )?;
+    Ok(Self {
+      ash_device,
+      allocator,
+      sampler: flat_tex_gen.sampler().clone(),
+      water_dset_pools: dset_pools,
+      water_dset_layout: Arc::new(dset_layout),
+      default_normal_map: Arc::new(default_normal_map),
+    })
+  }
+
+  // Tiling ripples from a few sine waves, tangent space normals with z up
+  fn make_default_normal_map() -> RgbaImage {
+    let size = DEFAULT_NORMAL_MAP_SIZE;
+    RgbaImage::from_fn(size, size, |x, y| {
+      let uv = glam::vec2(x as f32, y as f32) / size as f32;
+      let slope = DEFAULT_NORMAL_MAP_WAVES.iter().fold(
+        glam::Vec2::ZERO,
+        |slope, (freq_u, freq_v, amplitude, phase)| {
+          let freq = glam::vec2(*freq_u, *freq_v) * std::f32::consts::TAU;
+          slope + freq * amplitude * (freq.dot(uv) + phase).cos() / std::f32::consts::TAU
+        },
+      );
+      let normal = glam::vec3(-slope.x, -slope.y, 1.0).normalize() * 0.5 + 0.5;
+      Rgba([(normal.x * 255.0) as u8, (normal.y * 255.0) as u8, (normal.z * 255.0) as u8, 255])
+    })
+  }
+
+  // No normal map uses the generated ripples
+  pub fn create_water_plane(
+    &self,
+    name: &str,
+    plane: &WaterPlaneCPU,
+    normal_map: Option<Arc<FlatTextureGPU>>,
+  ) -> Result<WaterPlaneGPU, String> {
+    if plane.size.x <= 0.0 || plane.size.y <= 0.0 {
+      return Err(format!("water plane {name} has size {}", plane.size));
+    }
+    let water_buffer = AdBuffer::new(
+      self.ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      &format!("{name}_water"),
+      vk::BufferCreateFlags::empty(),
+      std::mem::size_of::<WaterData>() as _,
+      vk::BufferUsageFlags::UNIFORM_BUFFER,
+    )?;
+    water_buffer.write_data(0, &[WaterData::new(plane)])?;
+    let normal_map = normal_map.unwrap_or(self.default_normal_map.clone());
+    let water_dset = self
+      .water_dset_pools
+      .allocate(&[(
+        self.water_dset_layout.clone(),
+        vec![
+          AdDescriptorBinding::UniformBuffer(Arc::new(water_buffer)),
+          AdDescriptorBinding::Image2D((
+            normal_map.image_view().clone(),
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          )),
+          AdDescriptorBinding::Sampler(self.sampler.clone()),
+        ],
+      )])?
+      .remove(0);
+    Ok(WaterPlaneGPU {
+      dset: Arc::new(water_dset),
+      normal_map,
+      reflection: plane.reflection,
+      height: plane.transform.w_axis.y,
+    })
+  }
+}
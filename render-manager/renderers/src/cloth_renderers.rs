@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_data_wrappers::AdBuffer,
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::AdComputePipeline,
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{
+  cloth::{ClothGPU, ClothGenerator},
+  glam,
+};
+
+static CLOTH_SIM_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/cloth_sim.comp.spv");
+
+const CLOTH_GROUP_SIZE: u32 = 64;
+// Odd so the last solve lands back in the particle buffer
+const CLOTH_SOLVE_PASSES: u32 = 15;
+
+const PASS_INTEGRATE: u32 = 0;
+const PASS_SOLVE_FROM_SCRATCH: u32 = 1;
+const PASS_SOLVE_TO_SCRATCH: u32 = 2;
+const PASS_WRITE_VERTICES: u32 = 3;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct ClothPushConstants {
+  // columns, rows, pass, unused
+  counts: [u32; 4],
+  // xyz in model space, time step
+  gravity: glam::Vec4,
+  // xyz in model space, wind response
+  wind: glam::Vec4,
+  // spacing, stretch compliance and bend compliance over the time step squared, velocity kept
+  params: glam::Vec4,
+}
+
+// Cloth stepped by the gpu, with gravity and wind already in its model space
+pub struct ClothStep {
+  pub cloth: Arc<ClothGPU>,
+  pub gravity: glam::Vec3,
+  pub wind: glam::Vec3,
+}
+
+// Steps gpu cloth in a compute pass before anything draws it, writing vertices the plain mesh
+// pipelines draw
+pub struct ClothRenderer {
+  pipeline: AdComputePipeline,
+}
+
+impl ClothRenderer {
+  pub fn new(ash_device: Arc<AdAshDevice>, cloth_gen: &ClothGenerator) -> Result<Self, String> {
+    let pipeline = AdComputePipeline::new(
+      ash_device,
+      CLOTH_SIM_SHADER_CODE,
+      &[cloth_gen.sim_dset_layout()],
+      std::mem::size_of::<ClothPushConstants>() as u32,
+    )?;
+    Ok(Self { pipeline })
+  }
+
+  fn compute_barrier(cmd_buffer: &AdCommandBuffer) {
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)],
+      &[],
+      &[],
+    );
+  }
+
+  // Must be recorded outside a render pass, before the frame's first pass drawing meshes. A time
+  // step of 0 only rewrites the vertices
+  pub fn simulate(&self, cmd_buffer: &AdCommandBuffer, steps: &[ClothStep], time_s: f32) {
+    let steps =
+      steps.iter().filter_map(|step| Some((step, step.cloth.sim_dset()?))).collect::<Vec<_>>();
+    if steps.is_empty() {
+      return;
+    }
+    // Previous frame's draws may still read the vertices being rewritten
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::VERTEX_SHADER,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[],
+    );
+
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.pipeline.inner());
+    let mut passes = vec![];
+    if time_s > 0.0 {
+      passes.push(PASS_INTEGRATE);
+      passes.extend((0..CLOTH_SOLVE_PASSES).map(|i| match i % 2 {
+        0 => PASS_SOLVE_FROM_SCRATCH,
+        _ => PASS_SOLVE_TO_SCRATCH,
+      }));
+    }
+    passes.push(PASS_WRITE_VERTICES);
+    let inv_dt2 = if time_s > 0.0 { 1.0 / (time_s * time_s) } else { 0.0 };
+    // Pass by pass over every cloth, so one barrier covers all of them
+    for (pass_idx, pass) in passes.iter().enumerate() {
+      if pass_idx > 0 {
+        Self::compute_barrier(cmd_buffer);
+      }
+      for (step, sim_dset) in steps.iter() {
+        let cloth = &step.cloth;
+        cmd_buffer.bind_descriptor_sets(
+          vk::PipelineBindPoint::COMPUTE,
+          self.pipeline.layout(),
+          &[sim_dset.inner()],
+        );
+        cmd_buffer.set_push_constant_data(
+          self.pipeline.layout(),
+          vk::ShaderStageFlags::COMPUTE,
+          AdBuffer::get_byte_slice(&[ClothPushConstants {
+            counts: [cloth.columns(), cloth.rows(), *pass, 0],
+            gravity: step.gravity.extend(time_s),
+            wind: step.wind.extend(cloth.wind_response()),
+            params: glam::vec4(
+              cloth.spacing(),
+              cloth.compliance() * inv_dt2,
+              cloth.bend_compliance() * inv_dt2,
+              (1.0 - cloth.damping() * time_s).max(0.0),
+            ),
+          }]),
+        );
+        cmd_buffer.dispatch(cloth.particle_count().div_ceil(CLOTH_GROUP_SIZE), 1, 1);
+      }
+    }
+
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::VERTEX_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+      &[],
+      &[],
+    );
+  }
+}
@@ -0,0 +1,39 @@
+use ash_ad_wrappers::graphics_backend::{BackendImage, Binding, CommandList, GraphicsDevice};
+use include_bytes_aligned::include_bytes_aligned;
+
+static SRGB_ENCODE_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/srgb_encode.comp.spv");
+
+const ENCODE_GROUP_SIZE: u32 = 8;
+
+// Encodes the finished scene color to sRGB in place. Only needed when the swapchain is UNORM, the
+// blit to it copies values as they are. Written against the graphics backend, the color images
+// need storage use
+pub struct SrgbEncodeRenderer<D: GraphicsDevice> {
+  pipeline: D::Pipeline,
+}
+
+impl<D: GraphicsDevice> SrgbEncodeRenderer<D> {
+  pub fn new(device: &D) -> Result<Self, String> {
+    let spirv = SRGB_ENCODE_SHADER_CODE
+      .chunks_exact(4)
+      .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+      .collect::<Vec<_>>();
+    Ok(Self { pipeline: device.create_compute_pipeline("srgb_encode", &spirv)? })
+  }
+
+  // Must be recorded after every pass drawing into color and before it is blitted. commands can be
+  // the device's own or ones going into a frame
+  pub fn encode<C: CommandList<Image = D::Image, Pipeline = D::Pipeline>>(
+    &self,
+    commands: &mut C,
+    color: &D::Image,
+  ) -> Result<(), String> {
+    let extent = color.extent();
+    commands.dispatch(
+      &self.pipeline,
+      &[Binding::StorageImage(color)],
+      &[],
+      [extent.width.div_ceil(ENCODE_GROUP_SIZE), extent.height.div_ceil(ENCODE_GROUP_SIZE), 1],
+    )
+  }
+}
@@ -0,0 +1,369 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdComputePipeline, AdFrameBuffer},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{glam, Camera3D};
+
+use crate::{
+  hiz_renderers::{HiZGenerator, HiZPyramid},
+  triangle_mesh_renderers::TriMeshDraw,
+};
+
+static MESH_CULL_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/mesh_cull.comp.spv");
+static MESH_CULL_COMPACT_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/mesh_cull_compact.comp.spv");
+
+const CULL_GROUP_SIZE: u32 = 64;
+const MIN_OBJECT_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct CullObject {
+  // xyz world center, w radius
+  sphere: glam::Vec4,
+  // vertex count, unused, unused, unused
+  draw: glam::UVec4,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct CullFrame {
+  planes: [glam::Vec4; 6],
+  pyramid_view_proj: glam::Mat4,
+  // pyramid width, pyramid height, pyramid mip count, occlusion enabled
+  pyramid: glam::Vec4,
+  // object count, unused, unused, unused
+  counts: glam::UVec4,
+}
+
+// Planes of the view volume with depth in 0..1, normals pointing in
+pub fn frustum_planes(view_proj: glam::Mat4) -> [glam::Vec4; 6] {
+  let rows = [view_proj.row(0), view_proj.row(1), view_proj.row(2), view_proj.row(3)];
+  [
+    rows[3] + rows[0],
+    rows[3] - rows[0],
+    rows[3] + rows[1],
+    rows[3] - rows[1],
+    rows[2],
+    rows[3] - rows[2],
+  ]
+  .map(|plane| plane / plane.truncate().length())
+}
+
+// Buffers of one frame in flight, grown when the draws outgrow them
+struct CullFrameBuffers {
+  capacity: usize,
+  objects: Arc<AdBuffer>,
+  frame: Arc<AdBuffer>,
+  draws: Arc<AdBuffer>,
+  dset: AdDescriptorSet,
+  // Bounds version the objects buffer was written at
+  version: Option<u64>,
+}
+
+// One pyramid shared by all frames, queue order and the build barriers keep a frame's build after
+// the previous frame's cull
+struct CullPyramid {
+  pyramid: HiZPyramid,
+  dset: AdDescriptorSet,
+  // Camera of the last build, None until the first one
+  view_proj: Option<glam::Mat4>,
+}
+
+// Culls the draws of TriMeshMaterialRenderer on the gpu. Each batch slot's bounds are tested
+// against the frustum and against a depth pyramid built from the previous frame's depth, writing
+// an indirect draw per slot with no instances when culled, and the list of visible slots. The
+// pyramid is a frame behind, objects coming out from behind others show up a frame late
+pub struct MeshCullRenderer {
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  cull_pipeline: AdComputePipeline,
+  compact_pipeline: AdComputePipeline,
+  cull_dset_layout: Arc<AdDescriptorSetLayout>,
+  pyramid_dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  hiz_gen: HiZGenerator,
+  frames: Vec<Option<CullFrameBuffers>>,
+  pyramid: Option<CullPyramid>,
+}
+
+impl MeshCullRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    frames_in_flight: usize,
+    samples: vk::SampleCountFlags,
+  ) -> Result<Self, String> {
+    let cull_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::UNIFORM_BUFFER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+      ],
+    )?);
+    let pyramid_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[(vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::SAMPLED_IMAGE)],
+    )?);
+    // Frame sets are replaced when they grow, the pyramid set with the framebuffers
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      64,
+      &[
+        vk::DescriptorPoolSize { ty: vk::DescriptorType::STORAGE_BUFFER, descriptor_count: 128 },
+        vk::DescriptorPoolSize { ty: vk::DescriptorType::UNIFORM_BUFFER, descriptor_count: 64 },
+        vk::DescriptorPoolSize { ty: vk::DescriptorType::SAMPLED_IMAGE, descriptor_count: 64 },
+      ],
+    )?);
+
+    let cull_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      MESH_CULL_SHADER_CODE,
+      &[&cull_dset_layout, &pyramid_dset_layout],
+      0,
+    )?;
+    let compact_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      MESH_CULL_COMPACT_SHADER_CODE,
+      &[&cull_dset_layout],
+      0,
+    )?;
+    let hiz_gen = HiZGenerator::new(ash_device.clone(), allocator.clone(), samples)?;
+
+    Ok(Self {
+      ash_device,
+      allocator,
+      cull_pipeline,
+      compact_pipeline,
+      cull_dset_layout,
+      pyramid_dset_layout,
+      dset_pool,
+      hiz_gen,
+      frames: (0..frames_in_flight).map(|_| None).collect(),
+      pyramid: None,
+    })
+  }
+
+  fn create_frame_buffers(
+    &self,
+    frame_idx: usize,
+    capacity: usize,
+  ) -> Result<CullFrameBuffers, String> {
+    let new_buffer = |name: &str, size: usize, usage: vk::BufferUsageFlags| {
+      AdBuffer::new(
+        self.ash_device.clone(),
+        self.allocator.clone(),
+        MemoryLocation::CpuToGpu,
+        &format!("mesh_cull_{name}_{frame_idx}"),
+        vk::BufferCreateFlags::empty(),
+        size as _,
+        usage,
+      )
+      .map(Arc::new)
+    };
+    let objects = new_buffer(
+      "objects",
+      capacity * std::mem::size_of::<CullObject>(),
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+    )?;
+    let frame =
+      new_buffer("frame", std::mem::size_of::<CullFrame>(), vk::BufferUsageFlags::UNIFORM_BUFFER)?;
+    let draws = Arc::new(AdBuffer::new(
+      self.ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("mesh_cull_draws_{frame_idx}"),
+      vk::BufferCreateFlags::empty(),
+      (capacity * std::mem::size_of::<vk::DrawIndirectCommand>()) as _,
+      vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+    )?);
+    // Count then the indices
+    let visible = Arc::new(AdBuffer::new(
+      self.ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      &format!("mesh_cull_visible_{frame_idx}"),
+      vk::BufferCreateFlags::empty(),
+      ((capacity + 1) * std::mem::size_of::<u32>()) as _,
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+    )?);
+    let dset = AdDescriptorSet::new(
+      self.dset_pool.clone(),
+      &[(
+        self.cull_dset_layout.clone(),
+        vec![
+          AdDescriptorBinding::StorageBuffer(objects.clone()),
+          AdDescriptorBinding::UniformBuffer(frame.clone()),
+          AdDescriptorBinding::StorageBuffer(draws.clone()),
+          AdDescriptorBinding::StorageBuffer(visible),
+        ],
+      )],
+    )?
+    .remove(0);
+    Ok(CullFrameBuffers { capacity, objects, frame, draws, dset, version: None })
+  }
+
+  // Replaces the pyramid, frames still using the old one have to be finished. Submits on
+  // cmd_buffer and waits
+  pub fn resize_pyramid(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffers: &[Arc<AdFrameBuffer>],
+  ) -> Result<(), String> {
+    self.pyramid = None;
+    let pyramid =
+      self.hiz_gen.create_pyramid(cmd_buffer, "mesh_cull_depth_pyramid", frame_buffers)?;
+    let dset = AdDescriptorSet::new(
+      self.dset_pool.clone(),
+      &[(
+        self.pyramid_dset_layout.clone(),
+        vec![AdDescriptorBinding::Image2D((pyramid.view().clone(), vk::ImageLayout::GENERAL))],
+      )],
+    )?
+    .remove(0);
+    self.pyramid = Some(CullPyramid { pyramid, dset, view_proj: None });
+    Ok(())
+  }
+
+  // Bounds are rewritten only when bounds_version changes, which has to happen whenever the
+  // batches or the transforms of their meshes do
+  fn write_objects(
+    &mut self,
+    frame_idx: usize,
+    batches: &[TriMeshDraw],
+    bounds_version: u64,
+  ) -> Result<(), String> {
+    let capacity = self.frames[frame_idx].as_ref().map(|frame| frame.capacity);
+    if capacity.is_none() || capacity < Some(batches.len()) {
+      let new_capacity = batches.len().next_power_of_two().max(MIN_OBJECT_CAPACITY);
+      self.frames[frame_idx] = Some(self.create_frame_buffers(frame_idx, new_capacity)?);
+    }
+    let Some(frame) = self.frames[frame_idx].as_mut() else {
+      return Err("mesh cull frame buffers not created".to_string());
+    };
+    if frame.version == Some(bounds_version) {
+      return Ok(());
+    }
+    let objects = batches
+      .iter()
+      .map(|(mesh, _)| {
+        Ok(CullObject {
+          sphere: mesh.world_bounds()?,
+          draw: glam::uvec4(mesh.indx_count() as u32, 0, 0, 0),
+        })
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    frame.objects.write_data(0, &objects)?;
+    frame.version = Some(bounds_version);
+    Ok(())
+  }
+
+  // Must be recorded outside a render pass, before the draws using draw_buffer
+  pub fn cull(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    camera: Camera3D,
+    batches: &[TriMeshDraw],
+    bounds_version: u64,
+  ) -> Result<(), String> {
+    self.write_objects(frame_idx, batches, bounds_version)?;
+    let Some(frame) = self.frames[frame_idx].as_ref() else {
+      return Err("mesh cull frame buffers not created".to_string());
+    };
+    let Some(pyramid) = self.pyramid.as_ref() else {
+      return Err("depth pyramid not created".to_string());
+    };
+    let pyramid_res = pyramid.pyramid.resolution();
+    frame.frame.write_data(
+      0,
+      &[CullFrame {
+        planes: frustum_planes(camera.view_proj_mat),
+        pyramid_view_proj: pyramid.view_proj.unwrap_or_default(),
+        pyramid: glam::vec4(
+          pyramid_res.width as f32,
+          pyramid_res.height as f32,
+          pyramid.pyramid.mip_count() as f32,
+          if pyramid.view_proj.is_some() { 1.0 } else { 0.0 },
+        ),
+        counts: glam::uvec4(batches.len() as u32, 0, 0, 0),
+      }],
+    )?;
+
+    if batches.is_empty() {
+      return Ok(());
+    }
+
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.cull_pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.cull_pipeline.layout(),
+      &[frame.dset.inner(), pyramid.dset.inner()],
+    );
+    cmd_buffer.dispatch((batches.len() as u32).div_ceil(CULL_GROUP_SIZE), 1, 1);
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+      &[],
+      &[],
+    );
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.compact_pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.compact_pipeline.layout(),
+      &[frame.dset.inner()],
+    );
+    cmd_buffer.dispatch(1, 1, 1);
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::SHADER_READ)],
+      &[],
+      &[],
+    );
+    Ok(())
+  }
+
+  // Indirect draws written by the last cull of the frame, one per batch
+  pub fn draw_buffer(&self, frame_idx: usize) -> Option<&AdBuffer> {
+    self.frames.get(frame_idx)?.as_ref().map(|frame| frame.draws.as_ref())
+  }
+
+  // Must be recorded outside a render pass, after the occluding geometry is drawn into
+  // frame_buffer. The next frame's cull tests against it
+  pub fn build_pyramid(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+  ) -> Result<(), String> {
+    let Some(pyramid) = self.pyramid.as_mut() else {
+      return Err("depth pyramid not created".to_string());
+    };
+    self.hiz_gen.build(cmd_buffer, &pyramid.pyramid, frame_idx, frame_buffer)?;
+    pyramid.view_proj = Some(camera.view_proj_mat);
+    Ok(())
+  }
+}
@@ -0,0 +1,530 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdImage, AdImageView,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdFrameBuffer, AdPipeline, AdRenderPass},
+  ash_sync_wrappers::AdFence,
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{color::Color, glam, Camera3D};
+
+static OVERLAY_RECT_VERT_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/overlay_rect.vert.spv");
+static OVERLAY_RECT_FRAG_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/overlay_rect.frag.spv");
+static DEBUG_LINE_VERT_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/debug_line.vert.spv");
+
+const MAX_OVERLAY_RECTS: usize = 4096;
+const MAX_DEBUG_LINES: usize = 16384;
+// In pixels of the target
+const DEBUG_LINE_WIDTH: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct OverlayRect {
+  // left, top, width, height in normalized device coordinates, y pointing down
+  pub rect: glam::Vec4,
+  // Blended over the scene by alpha
+  pub color: Color,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct DebugLine {
+  // World space, w is unused
+  pub start: glam::Vec4,
+  pub end: glam::Vec4,
+  pub color: Color,
+}
+
+impl DebugLine {
+  pub fn new(start: glam::Vec3, end: glam::Vec3, color: Color) -> Self {
+    Self { start: start.extend(1.0), end: end.extend(1.0), color }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct DebugLinePushConstants {
+  camera: Camera3D,
+  // Target width and height, then the line width, all in pixels
+  viewport: glam::Vec4,
+}
+
+// Draws world space lines a few pixels wide and flat screen space rects over everything else, for debug views like the memory heatmap.
+// Lines and rects are written every frame into buffers per frame in flight. Lines aren't hidden
+// by the scene, they're for seeing collision shapes and contacts inside of things. Color builds up
+// premultiplied with alpha as coverage, so a layer cleared to transparent black can be blended
+// over the scene later
+pub struct DebugOverlayRenderer {
+  line_pipeline: AdPipeline,
+  rect_pipeline: AdPipeline,
+  render_pass: Arc<AdRenderPass>,
+  // Rects at binding 0, lines at 1
+  overlay_dsets: Vec<AdDescriptorSet>,
+  // Draws into framebuffers of its own, see new_layer
+  cleared: bool,
+  color_format: vk::Format,
+  depth_format: vk::Format,
+}
+
+impl DebugOverlayRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+    frames_in_flight: usize,
+  ) -> Result<Self, String> {
+    Self::with_target(
+      ash_device,
+      allocator,
+      color_format,
+      depth_format,
+      samples,
+      frames_in_flight,
+      false,
+    )
+  }
+
+  // Draws into framebuffers from create_framebuffers, cleared to transparent black each pass, for
+  // a layer at a resolution other than the scene's
+  pub fn new_layer(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    frames_in_flight: usize,
+  ) -> Result<Self, String> {
+    Self::with_target(
+      ash_device,
+      allocator,
+      color_format,
+      depth_format,
+      vk::SampleCountFlags::TYPE_1,
+      frames_in_flight,
+      true,
+    )
+  }
+
+  fn with_target(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+    frames_in_flight: usize,
+    cleared: bool,
+  ) -> Result<Self, String> {
+    // Same attachments as the tri renderer framebuffers like the editor overlay. Drawn last so
+    // depth and the msaa color aren't kept
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+    let mut attachments = vec![
+      vk::AttachmentDescription::default()
+        .format(color_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .initial_layout(match cleared {
+          true => vk::ImageLayout::UNDEFINED,
+          false => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        })
+        .final_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .load_op(match (cleared, multisampled) {
+          (true, _) => vk::AttachmentLoadOp::CLEAR,
+          (false, true) => vk::AttachmentLoadOp::DONT_CARE,
+          (false, false) => vk::AttachmentLoadOp::LOAD,
+        })
+        .store_op(vk::AttachmentStoreOp::STORE),
+      vk::AttachmentDescription::default()
+        .format(depth_format)
+        .samples(samples)
+        .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE),
+    ];
+    if multisampled {
+      attachments.push(
+        vk::AttachmentDescription::default()
+          .format(color_format)
+          .samples(samples)
+          .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+          .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+          .load_op(vk::AttachmentLoadOp::LOAD)
+          .store_op(vk::AttachmentStoreOp::DONT_CARE),
+      );
+    }
+    let output_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(0)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let msaa_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(2)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let depth_attachment_ref = vk::AttachmentReference::default()
+      .attachment(1)
+      .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let subpass = match multisampled {
+      true => vk::SubpassDescription::default()
+        .color_attachments(&msaa_attachment_ref)
+        .resolve_attachments(&output_attachment_ref),
+      false => vk::SubpassDescription::default().color_attachments(&output_attachment_ref),
+    };
+    let render_pass = AdRenderPass::new(
+      ash_device.clone(),
+      vk::RenderPassCreateFlags::default(),
+      &attachments,
+      &[subpass
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(&depth_attachment_ref)],
+      &[
+        vk::SubpassDependency::default()
+          .src_subpass(vk::SUBPASS_EXTERNAL)
+          .dst_subpass(0)
+          .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+          .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+          .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+          .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+          ),
+        vk::SubpassDependency::default()
+          .src_subpass(0)
+          .dst_subpass(vk::SUBPASS_EXTERNAL)
+          .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+          .dst_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+          )
+          .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+          .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::TRANSFER_READ),
+      ],
+    )?;
+    let render_pass = Arc::new(render_pass);
+
+    let overlay_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::VERTEX, vk::DescriptorType::STORAGE_BUFFER),
+      ],
+    )?);
+    let overlay_dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      frames_in_flight as u32,
+      &[vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_BUFFER,
+        descriptor_count: 2 * frames_in_flight as u32,
+      }],
+    )?);
+    let new_buffer = |name: String, size: usize| {
+      AdBuffer::new(
+        ash_device.clone(),
+        allocator.clone(),
+        MemoryLocation::CpuToGpu,
+        &name,
+        vk::BufferCreateFlags::empty(),
+        size as _,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+      )
+      .map(|buffer| AdDescriptorBinding::StorageBuffer(Arc::new(buffer)))
+    };
+    let overlay_bindings = (0..frames_in_flight)
+      .map(|i| {
+        Ok((
+          overlay_dset_layout.clone(),
+          vec![
+            new_buffer(
+              format!("debug_overlay_rects_{i}"),
+              std::mem::size_of::<OverlayRect>() * MAX_OVERLAY_RECTS,
+            )?,
+            new_buffer(
+              format!("debug_overlay_lines_{i}"),
+              std::mem::size_of::<DebugLine>() * MAX_DEBUG_LINES,
+            )?,
+          ],
+        ))
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    let overlay_dsets = AdDescriptorSet::new(overlay_dset_pool, &overlay_bindings)?;
+
+    let rect_pipeline = AdPipeline::new(
+      render_pass.clone(),
+      0,
+      HashMap::from([
+        (vk::ShaderStageFlags::VERTEX, OVERLAY_RECT_VERT_SHADER_CODE),
+        (vk::ShaderStageFlags::FRAGMENT, OVERLAY_RECT_FRAG_SHADER_CODE),
+      ]),
+      &[&overlay_dset_layout],
+      (vk::ShaderStageFlags::VERTEX, 0),
+      vk::PipelineRasterizationStateCreateInfo::default()
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0),
+      &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
+        vk::PipelineColorBlendAttachmentState::default()
+          .color_write_mask(vk::ColorComponentFlags::RGBA)
+          .blend_enable(true)
+          .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+          .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+          .color_blend_op(vk::BlendOp::ADD)
+          .src_alpha_blend_factor(vk::BlendFactor::ONE)
+          .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+          .alpha_blend_op(vk::BlendOp::ADD),
+      ]),
+      &vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(false)
+        .depth_write_enable(false),
+      samples,
+    )?;
+
+    // Same blending, the rects go over the lines
+    let line_pipeline = AdPipeline::new(
+      render_pass.clone(),
+      0,
+      HashMap::from([
+        (vk::ShaderStageFlags::VERTEX, DEBUG_LINE_VERT_SHADER_CODE),
+        (vk::ShaderStageFlags::FRAGMENT, OVERLAY_RECT_FRAG_SHADER_CODE),
+      ]),
+      &[&overlay_dset_layout],
+      (vk::ShaderStageFlags::VERTEX, std::mem::size_of::<DebugLinePushConstants>() as u32),
+      vk::PipelineRasterizationStateCreateInfo::default()
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0),
+      &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
+        vk::PipelineColorBlendAttachmentState::default()
+          .color_write_mask(vk::ColorComponentFlags::RGBA)
+          .blend_enable(true)
+          .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+          .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+          .color_blend_op(vk::BlendOp::ADD)
+          .src_alpha_blend_factor(vk::BlendFactor::ONE)
+          .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+          .alpha_blend_op(vk::BlendOp::ADD),
+      ]),
+      &vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(false)
+        .depth_write_enable(false),
+      samples,
+    )?;
+
+    Ok(Self {
+      line_pipeline,
+      rect_pipeline,
+      render_pass,
+      overlay_dsets,
+      cleared,
+      color_format,
+      depth_format,
+    })
+  }
+
+  // Color and depth images to draw a layer into, for renderers made with new_layer. The color
+  // images start in TRANSFER_SRC_OPTIMAL like the tri renderer's
+  pub fn create_framebuffers(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    allocator: Arc<Mutex<Allocator>>,
+    resolution: vk::Extent2D,
+    count: usize,
+    // On top of what the render pass and the blit need, like STORAGE for compute passes
+    extra_color_usage: vk::ImageUsageFlags,
+  ) -> Result<Vec<Arc<AdFrameBuffer>>, String> {
+    if !self.cleared {
+      return Err("only overlay layers have framebuffers of their own".to_string());
+    }
+    let ash_device = self.render_pass.ash_device().clone();
+    let images = (0..count)
+      .map(|i| {
+        let color_img = AdImage::new_2d(
+          ash_device.clone(),
+          allocator.clone(),
+          MemoryLocation::GpuOnly,
+          &format!("overlay_layer_color_image_{i}"),
+          self.color_format,
+          resolution,
+          vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | extra_color_usage,
+          vk::SampleCountFlags::TYPE_1,
+          1,
+        )
+        .map_err(|e| format!("at creating overlay layer color image {i}: {e}"))?;
+        let depth_img = AdImage::new_2d(
+          ash_device.clone(),
+          allocator.clone(),
+          MemoryLocation::GpuOnly,
+          &format!("overlay_layer_depth_image_{i}"),
+          self.depth_format,
+          resolution,
+          vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+          vk::SampleCountFlags::TYPE_1,
+          1,
+        )
+        .map_err(|e| format!("at creating overlay layer depth image {i}: {e}"))?;
+        Ok((color_img, depth_img))
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+
+    let layout_barrier = |image: &AdImage, dst_access: vk::AccessFlags, new_layout| {
+      vk::ImageMemoryBarrier::default()
+        .image(image.inner())
+        .subresource_range(
+          vk::ImageSubresourceRange::default()
+            .aspect_mask(image.possible_image_aspect())
+            .layer_count(1)
+            .level_count(1),
+        )
+        .src_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+        .dst_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+        .src_access_mask(vk::AccessFlags::NONE)
+        .dst_access_mask(dst_access)
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .new_layout(new_layout)
+    };
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::default())?;
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TOP_OF_PIPE,
+      vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+      vk::DependencyFlags::BY_REGION,
+      &[],
+      &[],
+      &images
+        .iter()
+        .flat_map(|(color_img, depth_img)| {
+          [
+            layout_barrier(
+              color_img,
+              vk::AccessFlags::TRANSFER_READ,
+              vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ),
+            layout_barrier(
+              depth_img,
+              vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+              vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ),
+          ]
+        })
+        .collect::<Vec<_>>(),
+    );
+    cmd_buffer.end()?;
+    let fence = AdFence::new(ash_device, vk::FenceCreateFlags::empty())?;
+    cmd_buffer.submit(&[], &[], Some(&fence))?;
+    fence.wait(999999999)?;
+    fence.reset()?;
+
+    let color_range = vk::ImageSubresourceRange::default()
+      .aspect_mask(vk::ImageAspectFlags::COLOR)
+      .layer_count(1)
+      .level_count(1);
+    images
+      .into_iter()
+      .map(|(color_img, depth_img)| {
+        let attachments = vec![
+          AdImageView::create_view(color_img, vk::ImageViewType::TYPE_2D, color_range)?,
+          AdImageView::create_view(
+            depth_img,
+            vk::ImageViewType::TYPE_2D,
+            color_range.aspect_mask(vk::ImageAspectFlags::DEPTH),
+          )?,
+        ];
+        AdFrameBuffer::new(self.render_pass.clone(), attachments, resolution, 1)
+      })
+      .collect()
+  }
+
+  // Lines past MAX_DEBUG_LINES and rects past MAX_OVERLAY_RECTS are dropped. frame_idx picks the
+  // buffers, they must not be in use by a frame still in flight
+  pub fn render(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    frame_idx: usize,
+    camera: Camera3D,
+    lines: &[DebugLine],
+    rects: &[OverlayRect],
+  ) -> Result<(), String> {
+    let overlay_dset = &self.overlay_dsets[frame_idx];
+    let [AdDescriptorBinding::StorageBuffer(rect_buffer), AdDescriptorBinding::StorageBuffer(line_buffer)] =
+      &overlay_dset.bindings()[..]
+    else {
+      return Err("debug overlay constructed with improper rect or line buffers".to_string());
+    };
+    let rects = &rects[..rects.len().min(MAX_OVERLAY_RECTS)];
+    rect_buffer.write_data(0, rects)?;
+    let lines = &lines[..lines.len().min(MAX_DEBUG_LINES)];
+    line_buffer.write_data(0, lines)?;
+
+    cmd_buffer.begin_render_pass(
+      self.render_pass.inner(),
+      frame_buffer.inner(),
+      vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: frame_buffer.resolution() },
+      match self.cleared {
+        true => &[vk::ClearValue { color: vk::ClearColorValue { float32: [0.0; 4] } }],
+        false => &[],
+      },
+      vk::SubpassContents::INLINE,
+    );
+    cmd_buffer.set_view_port(&[vk::Viewport {
+      x: 0.0,
+      y: 0.0,
+      width: frame_buffer.resolution().width as f32,
+      height: frame_buffer.resolution().height as f32,
+      min_depth: 0.0,
+      max_depth: 1.0,
+    }]);
+    cmd_buffer.set_scissor(&[vk::Rect2D {
+      offset: vk::Offset2D { x: 0, y: 0 },
+      extent: frame_buffer.resolution(),
+    }]);
+    if !lines.is_empty() {
+      let resolution = frame_buffer.resolution();
+      let push_constants = DebugLinePushConstants {
+        camera,
+        viewport: glam::vec4(
+          resolution.width as f32,
+          resolution.height as f32,
+          DEBUG_LINE_WIDTH,
+          0.0,
+        ),
+      };
+      cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, self.line_pipeline.inner());
+      cmd_buffer.bind_descriptor_sets(
+        vk::PipelineBindPoint::GRAPHICS,
+        self.line_pipeline.layout(),
+        &[overlay_dset.inner()],
+      );
+      cmd_buffer.set_push_constant_data(
+        self.line_pipeline.layout(),
+        vk::ShaderStageFlags::VERTEX,
+        AdBuffer::get_byte_slice(&[push_constants]),
+      );
+      cmd_buffer.draw(lines.len() as u32 * 6);
+    }
+    if !rects.is_empty() {
+      cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, self.rect_pipeline.inner());
+      cmd_buffer.bind_descriptor_sets(
+        vk::PipelineBindPoint::GRAPHICS,
+        self.rect_pipeline.layout(),
+        &[overlay_dset.inner()],
+      );
+      cmd_buffer.draw(rects.len() as u32 * 6);
+    }
+    cmd_buffer.end_render_pass();
+    Ok(())
+  }
+}
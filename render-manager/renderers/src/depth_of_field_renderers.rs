@@ -0,0 +1,443 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdImageView, AdSampler,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdComputePipeline, AdFrameBuffer},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{glam, Camera3D};
+
+use crate::{
+  post_renderers::{begin_scene_color_read, copy_into_scene_color, POST_FORMAT, POST_GROUP_SIZE},
+  render_targets::{color_range, depth_barrier, reallocate_dsets},
+  transient_images::{TransientImageDesc, TransientImages},
+};
+
+static DOF_COC_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/dof_coc.comp.spv");
+static DOF_COC_MS_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/dof_coc_ms.comp.spv");
+static DOF_BLUR_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/dof_blur.comp.spv");
+static DOF_COMPOSITE_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/dof_composite.comp.spv");
+
+// Largest circle of confusion in pixels, anything more out of focus is blurred as much as this
+pub const MAX_COC_PIXELS: f32 = 16.0;
+// Samples each layer gathers over its disk
+pub const DOF_SAMPLES: u32 = 48;
+// Height of a full frame 35mm sensor, focal lengths are given for it
+const SENSOR_HEIGHT_MM: f32 = 24.0;
+const MAX_DOF_SETS: u32 = 64;
+const DOF_COC_IMAGE: &str = "dof_coc_image";
+const DOF_NEAR_IMAGE: &str = "dof_near_image";
+const DOF_FAR_IMAGE: &str = "dof_far_image";
+const DOF_OUTPUT_IMAGE: &str = "dof_output_image";
+
+// Circle of confusion radius in pixels at infinity for a thin lens focused at focus_distance
+// meters, the radius at a view depth z is this times (z - focus_distance) / z
+pub fn lens_coefficient(
+  focus_distance: f32,
+  aperture: f32,
+  focal_length_mm: f32,
+  screen_height: u32,
+) -> f32 {
+  let focal_length = focal_length_mm / 1000.0;
+  // The lens can't focus closer than its focal length
+  let focus_distance = focus_distance.max(focal_length * 1.01);
+  let diameter = focal_length * focal_length / (aperture * (focus_distance - focal_length));
+  // Half the diameter, from meters on the sensor to pixels
+  diameter * 500.0 / SENSOR_HEIGHT_MM * screen_height as f32
+}
+
+#[repr(C)]
+struct CocPushConstants {
+  inv_view_proj: glam::Mat4,
+  camera_pos: glam::Vec4,
+  look_dir: glam::Vec4,
+  // focus distance, lens coefficient, max radius in pixels, sample count
+  params: glam::Vec4,
+}
+
+// Shared by the frames in flight, the queue runs their passes one after another
+struct DofTargets {
+  coc_view: Arc<AdImageView>,
+  near_view: Arc<AdImageView>,
+  far_view: Arc<AdImageView>,
+  output_view: Arc<AdImageView>,
+}
+
+// Blurs the scene color of TriMeshMaterialRenderer framebuffers by a circle of confusion worked out
+// from their depth, in a near and a far layer gathered separately and blended back over the scene.
+// The scene color images need SAMPLED and TRANSFER_DST usage
+pub struct DepthOfFieldRenderer {
+  coc_pipeline: AdComputePipeline,
+  blur_pipeline: AdComputePipeline,
+  composite_pipeline: AdComputePipeline,
+  coc_dset_layout: Arc<AdDescriptorSetLayout>,
+  blur_dset_layout: Arc<AdDescriptorSetLayout>,
+  composite_dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  sampler: Arc<AdSampler>,
+  // The circles of confusion are 32 bit floats, which not every gpu filters
+  coc_sampler: Arc<AdSampler>,
+  samples: vk::SampleCountFlags,
+  targets: Option<DofTargets>,
+  // One each per frame in flight
+  coc_dsets: Vec<AdDescriptorSet>,
+  near_dsets: Vec<AdDescriptorSet>,
+  far_dsets: Vec<AdDescriptorSet>,
+  composite_dsets: Vec<AdDescriptorSet>,
+}
+
+impl DepthOfFieldRenderer {
+  pub fn new(ash_device: Arc<AdAshDevice>, samples: vk::SampleCountFlags) -> Result<Self, String> {
+    let coc_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_IMAGE),
+      ],
+    )?);
+    let blur_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_IMAGE),
+      ],
+    )?);
+    let composite_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_IMAGE),
+      ],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_DOF_SETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLED_IMAGE,
+          descriptor_count: MAX_DOF_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+          descriptor_count: MAX_DOF_SETS * 3,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_IMAGE,
+          descriptor_count: MAX_DOF_SETS,
+        },
+      ],
+    )?);
+    let coc_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      match samples != vk::SampleCountFlags::TYPE_1 {
+        true => DOF_COC_MS_SHADER_CODE,
+        false => DOF_COC_SHADER_CODE,
+      },
+      &[&coc_dset_layout],
+      std::mem::size_of::<CocPushConstants>() as u32,
+    )?;
+    let blur_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      DOF_BLUR_SHADER_CODE,
+      &[&blur_dset_layout],
+      std::mem::size_of::<glam::Vec4>() as u32,
+    )?;
+    let composite_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      DOF_COMPOSITE_SHADER_CODE,
+      &[&composite_dset_layout],
+      0,
+    )?;
+    let sampler = Arc::new(AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    )?);
+    let coc_sampler = Arc::new(AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::NEAREST)
+        .min_filter(vk::Filter::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    )?);
+    Ok(Self {
+      coc_pipeline,
+      blur_pipeline,
+      composite_pipeline,
+      coc_dset_layout,
+      blur_dset_layout,
+      composite_dset_layout,
+      dset_pool,
+      sampler,
+      coc_sampler,
+      samples,
+      targets: None,
+      coc_dsets: vec![],
+      near_dsets: vec![],
+      far_dsets: vec![],
+      composite_dsets: vec![],
+    })
+  }
+
+  // Its targets, only used while it is applied in pass
+  pub fn transient_images(pass: u32) -> Vec<TransientImageDesc> {
+    let storage = vk::ImageUsageFlags::STORAGE;
+    let sampled = storage | vk::ImageUsageFlags::SAMPLED;
+    let copied = storage | vk::ImageUsageFlags::TRANSFER_SRC;
+    vec![
+      TransientImageDesc::in_pass(DOF_COC_IMAGE, vk::Format::R32_SFLOAT, sampled, pass),
+      TransientImageDesc::in_pass(DOF_NEAR_IMAGE, POST_FORMAT, sampled, pass),
+      TransientImageDesc::in_pass(DOF_FAR_IMAGE, POST_FORMAT, sampled, pass),
+      TransientImageDesc::in_pass(DOF_OUTPUT_IMAGE, POST_FORMAT, copied, pass),
+    ]
+  }
+
+  // With transient_images made for the same framebuffers
+  pub fn resize_targets(
+    &mut self,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+    transient_images: &TransientImages,
+  ) -> Result<(), String> {
+    let target = |name: &str| {
+      AdImageView::create_view(
+        transient_images.get(name)?,
+        vk::ImageViewType::TYPE_2D,
+        color_range(),
+      )
+    };
+    let targets = DofTargets {
+      coc_view: target(DOF_COC_IMAGE)?,
+      near_view: target(DOF_NEAR_IMAGE)?,
+      far_view: target(DOF_FAR_IMAGE)?,
+      output_view: target(DOF_OUTPUT_IMAGE)?,
+    };
+    let sampled_binding = |view: &Arc<AdImageView>| {
+      AdDescriptorBinding::Sampler2D((view.clone(), vk::ImageLayout::GENERAL, self.sampler.clone()))
+    };
+    let storage_binding = |view: &Arc<AdImageView>| {
+      AdDescriptorBinding::StorageImage((view.clone(), vk::ImageLayout::GENERAL))
+    };
+    reallocate_dsets(
+      &mut self.coc_dsets,
+      &self.dset_pool,
+      &self.coc_dset_layout,
+      scene_frame_buffers.iter().map(|fb| {
+        vec![
+          AdDescriptorBinding::Image2D((
+            fb.attachments()[1].clone(),
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+          )),
+          storage_binding(&targets.coc_view),
+        ]
+      }),
+    )?;
+    let blur_bindings = |layer_view: &Arc<AdImageView>| {
+      scene_frame_buffers
+        .iter()
+        .map(|fb| {
+          vec![
+            sampled_binding(&fb.attachments()[0]),
+            AdDescriptorBinding::Sampler2D((
+              targets.coc_view.clone(),
+              vk::ImageLayout::GENERAL,
+              self.coc_sampler.clone(),
+            )),
+            storage_binding(layer_view),
+          ]
+        })
+        .collect::<Vec<_>>()
+    };
+    reallocate_dsets(
+      &mut self.near_dsets,
+      &self.dset_pool,
+      &self.blur_dset_layout,
+      blur_bindings(&targets.near_view),
+    )?;
+    reallocate_dsets(
+      &mut self.far_dsets,
+      &self.dset_pool,
+      &self.blur_dset_layout,
+      blur_bindings(&targets.far_view),
+    )?;
+    reallocate_dsets(
+      &mut self.composite_dsets,
+      &self.dset_pool,
+      &self.composite_dset_layout,
+      scene_frame_buffers.iter().map(|fb| {
+        vec![
+          sampled_binding(&fb.attachments()[0]),
+          sampled_binding(&targets.near_view),
+          sampled_binding(&targets.far_view),
+          storage_binding(&targets.output_view),
+        ]
+      }),
+    )?;
+    self.targets = Some(targets);
+    Ok(())
+  }
+
+  fn compute_barrier(cmd_buffer: &AdCommandBuffer) {
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+      &[],
+      &[],
+    );
+  }
+
+  // After the scene is drawn into scene_frame_buffer with camera, with the scene color in
+  // TRANSFER_SRC_OPTIMAL and the depth in DEPTH_STENCIL_ATTACHMENT_OPTIMAL, where both are left.
+  // lens_coefficient is from the function of the same name
+  pub fn apply(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+    focus_distance: f32,
+    lens_coefficient: f32,
+  ) -> Result<(), String> {
+    let (Some(targets), Some(coc_dset), Some(near_dset), Some(far_dset), Some(composite_dset)) = (
+      &self.targets,
+      self.coc_dsets.get(frame_idx),
+      self.near_dsets.get(frame_idx),
+      self.far_dsets.get(frame_idx),
+      self.composite_dsets.get(frame_idx),
+    ) else {
+      return Err(format!("no depth of field targets for frame {frame_idx}"));
+    };
+    begin_scene_color_read(cmd_buffer, scene_frame_buffer);
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[depth_barrier(
+        cmd_buffer,
+        scene_frame_buffer,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+      )
+      .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+      .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+    );
+    // Last frame's passes and copy may still be reading the targets being rewritten
+    for layer_view in [&targets.coc_view, &targets.near_view, &targets.far_view] {
+      layer_view.transition_to_general(
+        cmd_buffer,
+        vk::ImageLayout::UNDEFINED,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::NONE,
+      );
+    }
+    targets.output_view.transition_to_general(
+      cmd_buffer,
+      vk::ImageLayout::UNDEFINED,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::AccessFlags::NONE,
+    );
+
+    let resolution = scene_frame_buffer.resolution();
+    let dispatch = || {
+      cmd_buffer.dispatch(
+        resolution.width.div_ceil(POST_GROUP_SIZE),
+        resolution.height.div_ceil(POST_GROUP_SIZE),
+        1,
+      )
+    };
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.coc_pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.coc_pipeline.layout(),
+      &[coc_dset.inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.coc_pipeline.layout(),
+      vk::ShaderStageFlags::COMPUTE,
+      AdBuffer::get_byte_slice(&[CocPushConstants {
+        inv_view_proj: camera.view_proj_mat.inverse(),
+        camera_pos: camera.pos,
+        look_dir: camera.look_dir.truncate().normalize_or_zero().extend(0.0),
+        params: glam::vec4(
+          focus_distance,
+          lens_coefficient,
+          MAX_COC_PIXELS,
+          self.samples.as_raw() as f32,
+        ),
+      }]),
+    );
+    dispatch();
+
+    Self::compute_barrier(cmd_buffer);
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.blur_pipeline.inner());
+    for (layer_dset, near_layer) in [(near_dset, 1.0), (far_dset, 0.0)] {
+      cmd_buffer.bind_descriptor_sets(
+        vk::PipelineBindPoint::COMPUTE,
+        self.blur_pipeline.layout(),
+        &[layer_dset.inner()],
+      );
+      cmd_buffer.set_push_constant_data(
+        self.blur_pipeline.layout(),
+        vk::ShaderStageFlags::COMPUTE,
+        AdBuffer::get_byte_slice(&[glam::vec4(
+          MAX_COC_PIXELS,
+          DOF_SAMPLES as f32,
+          near_layer,
+          0.0,
+        )]),
+      );
+      dispatch();
+    }
+
+    Self::compute_barrier(cmd_buffer);
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.composite_pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.composite_pipeline.layout(),
+      &[composite_dset.inner()],
+    );
+    dispatch();
+    copy_into_scene_color(cmd_buffer, targets.output_view.image(), scene_frame_buffer);
+
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[depth_barrier(
+        cmd_buffer,
+        scene_frame_buffer,
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+      )
+      .src_access_mask(vk::AccessFlags::NONE)
+      .dst_access_mask(
+        vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+          | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+      )],
+    );
+    Ok(())
+  }
+}
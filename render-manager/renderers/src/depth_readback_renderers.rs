@@ -0,0 +1,400 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdComputePipeline, AdFrameBuffer},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::glam;
+
+use crate::render_targets::{depth_barrier, reallocate_dsets};
+
+static DEPTH_READBACK_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/depth_readback.comp.spv");
+static DEPTH_READBACK_MS_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/depth_readback_ms.comp.spv");
+static DEPTH_POINTS_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/depth_points.comp.spv");
+static DEPTH_POINTS_MS_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/depth_points_ms.comp.spv");
+
+// The shader reads the whole square in one 16x16 group
+pub const MAX_DEPTH_READBACK_RADIUS: u32 = 7;
+const MAX_READBACK_TEXELS: usize =
+  ((MAX_DEPTH_READBACK_RADIUS * 2 + 1) * (MAX_DEPTH_READBACK_RADIUS * 2 + 1)) as usize;
+const MAX_READBACK_SETS: u32 = 16;
+// Texels read by one DepthPointsRenderer::record, more are dropped
+pub const MAX_DEPTH_POINTS: usize = 1024;
+const DEPTH_POINTS_GROUP_SIZE: u32 = 64;
+
+// Copies the depth around a texel of TriMeshMaterialRenderer framebuffers into host visible
+// buffers, one per frame in flight. Multisampled depth keeps the nearest sample of each texel
+pub struct DepthReadbackRenderer {
+  pipeline: AdComputePipeline,
+  dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  readback_buffers: Vec<Arc<AdBuffer>>,
+  // One per framebuffer
+  dsets: Vec<AdDescriptorSet>,
+  samples: vk::SampleCountFlags,
+}
+
+impl DepthReadbackRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    samples: vk::SampleCountFlags,
+    frames_in_flight: usize,
+  ) -> Result<Self, String> {
+    let dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+      ],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_READBACK_SETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLED_IMAGE,
+          descriptor_count: MAX_READBACK_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_BUFFER,
+          descriptor_count: MAX_READBACK_SETS,
+        },
+      ],
+    )?);
+    let pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      match samples != vk::SampleCountFlags::TYPE_1 {
+        true => DEPTH_READBACK_MS_SHADER_CODE,
+        false => DEPTH_READBACK_SHADER_CODE,
+      },
+      &[&dset_layout],
+      std::mem::size_of::<glam::IVec4>() as u32,
+    )?;
+    let readback_buffers = (0..frames_in_flight)
+      .map(|i| {
+        AdBuffer::new(
+          ash_device.clone(),
+          allocator.clone(),
+          MemoryLocation::GpuToCpu,
+          &format!("depth_readback_{i}"),
+          vk::BufferCreateFlags::empty(),
+          (MAX_READBACK_TEXELS * std::mem::size_of::<f32>()) as _,
+          vk::BufferUsageFlags::STORAGE_BUFFER,
+        )
+        .map(Arc::new)
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    Ok(Self { pipeline, dset_layout, dset_pool, readback_buffers, dsets: vec![], samples })
+  }
+
+  pub fn resize_targets(&mut self, frame_buffers: &[Arc<AdFrameBuffer>]) -> Result<(), String> {
+    reallocate_dsets(
+      &mut self.dsets,
+      &self.dset_pool,
+      &self.dset_layout,
+      frame_buffers.iter().zip(self.readback_buffers.iter()).map(|(fb, buffer)| {
+        vec![
+          AdDescriptorBinding::Image2D((
+            fb.attachments()[1].clone(),
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+          )),
+          AdDescriptorBinding::StorageBuffer(buffer.clone()),
+        ]
+      }),
+    )
+  }
+
+  // Must be recorded outside a render pass, after the depth is drawn into frame_buffer, which is
+  // frame_buffers[frame_idx] of resize_targets. The square of texels radius around texel is in
+  // read(frame_idx) once the frame's submit is done
+  pub fn record(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    frame_buffer: &AdFrameBuffer,
+    texel: glam::IVec2,
+    radius: u32,
+  ) -> Result<(), String> {
+    let Some(dset) = self.dsets.get(frame_idx) else {
+      return Err(format!("no depth readback set for frame {frame_idx}"));
+    };
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[depth_barrier(
+        cmd_buffer,
+        frame_buffer,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+      )
+      .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+      .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+    );
+
+    let radius = radius.min(MAX_DEPTH_READBACK_RADIUS);
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.pipeline.layout(),
+      &[dset.inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.pipeline.layout(),
+      vk::ShaderStageFlags::COMPUTE,
+      AdBuffer::get_byte_slice(&[glam::ivec4(
+        texel.x,
+        texel.y,
+        radius as i32,
+        self.samples.as_raw() as i32,
+      )]),
+    );
+    cmd_buffer.dispatch(1, 1, 1);
+
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::HOST
+        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::HOST_READ)],
+      &[],
+      &[depth_barrier(
+        cmd_buffer,
+        frame_buffer,
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+      )
+      .src_access_mask(vk::AccessFlags::NONE)
+      .dst_access_mask(
+        vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+          | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+      )],
+    );
+    Ok(())
+  }
+
+  // Depths a record with radius wrote for frame_idx, row by row from the top left. Only valid after
+  // the frame's fence is waited on
+  pub fn read(&self, frame_idx: usize, radius: u32) -> Result<Vec<f32>, String> {
+    let Some(buffer) = self.readback_buffers.get(frame_idx) else {
+      return Err(format!("no depth readback buffer for frame {frame_idx}"));
+    };
+    let side = radius.min(MAX_DEPTH_READBACK_RADIUS) as usize * 2 + 1;
+    buffer.read_data(0, side * side)
+  }
+}
+
+// Like DepthReadbackRenderer but for scattered texels, like under every world label, each read on
+// its own
+pub struct DepthPointsRenderer {
+  pipeline: AdComputePipeline,
+  dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  // Per frame in flight, texels written by the cpu and depths read back by it
+  texel_buffers: Vec<Arc<AdBuffer>>,
+  depth_buffers: Vec<Arc<AdBuffer>>,
+  // One per framebuffer
+  dsets: Vec<AdDescriptorSet>,
+  samples: vk::SampleCountFlags,
+}
+
+impl DepthPointsRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    samples: vk::SampleCountFlags,
+    frames_in_flight: usize,
+  ) -> Result<Self, String> {
+    let dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+      ],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_READBACK_SETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLED_IMAGE,
+          descriptor_count: MAX_READBACK_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_BUFFER,
+          descriptor_count: 2 * MAX_READBACK_SETS,
+        },
+      ],
+    )?);
+    let pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      match samples != vk::SampleCountFlags::TYPE_1 {
+        true => DEPTH_POINTS_MS_SHADER_CODE,
+        false => DEPTH_POINTS_SHADER_CODE,
+      },
+      &[&dset_layout],
+      std::mem::size_of::<glam::IVec4>() as u32,
+    )?;
+    let new_buffers = |name: &str, mem_location: MemoryLocation, stride: usize| {
+      (0..frames_in_flight)
+        .map(|i| {
+          AdBuffer::new(
+            ash_device.clone(),
+            allocator.clone(),
+            mem_location,
+            &format!("{name}_{i}"),
+            vk::BufferCreateFlags::empty(),
+            (MAX_DEPTH_POINTS * stride) as _,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+          )
+          .map(Arc::new)
+        })
+        .collect::<Result<Vec<_>, String>>()
+    };
+    let texel_buffers = new_buffers(
+      "depth_point_texels",
+      MemoryLocation::CpuToGpu,
+      std::mem::size_of::<glam::IVec2>(),
+    )?;
+    let depth_buffers =
+      new_buffers("depth_point_depths", MemoryLocation::GpuToCpu, std::mem::size_of::<f32>())?;
+    Ok(Self {
+      pipeline,
+      dset_layout,
+      dset_pool,
+      texel_buffers,
+      depth_buffers,
+      dsets: vec![],
+      samples,
+    })
+  }
+
+  pub fn resize_targets(&mut self, frame_buffers: &[Arc<AdFrameBuffer>]) -> Result<(), String> {
+    reallocate_dsets(
+      &mut self.dsets,
+      &self.dset_pool,
+      &self.dset_layout,
+      frame_buffers.iter().zip(self.texel_buffers.iter().zip(self.depth_buffers.iter())).map(
+        |(fb, (texel_buffer, depth_buffer))| {
+          vec![
+            AdDescriptorBinding::Image2D((
+              fb.attachments()[1].clone(),
+              vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            )),
+            AdDescriptorBinding::StorageBuffer(texel_buffer.clone()),
+            AdDescriptorBinding::StorageBuffer(depth_buffer.clone()),
+          ]
+        },
+      ),
+    )?;
+    Ok(())
+  }
+
+  // Same as DepthReadbackRenderer::record, texels past MAX_DEPTH_POINTS are dropped. The depths
+  // are in read(frame_idx) in the same order once the frame's submit is done
+  pub fn record(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    frame_buffer: &AdFrameBuffer,
+    texels: &[glam::IVec2],
+  ) -> Result<(), String> {
+    let (Some(dset), Some(texel_buffer)) =
+      (self.dsets.get(frame_idx), self.texel_buffers.get(frame_idx))
+    else {
+      return Err(format!("no depth point set for frame {frame_idx}"));
+    };
+    let texels = &texels[..texels.len().min(MAX_DEPTH_POINTS)];
+    if texels.is_empty() {
+      return Ok(());
+    }
+    texel_buffer.write_data(0, texels)?;
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[depth_barrier(
+        cmd_buffer,
+        frame_buffer,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+      )
+      .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+      .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+    );
+
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.pipeline.layout(),
+      &[dset.inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.pipeline.layout(),
+      vk::ShaderStageFlags::COMPUTE,
+      AdBuffer::get_byte_slice(&[glam::ivec4(
+        texels.len() as i32,
+        self.samples.as_raw() as i32,
+        0,
+        0,
+      )]),
+    );
+    cmd_buffer.dispatch((texels.len() as u32).div_ceil(DEPTH_POINTS_GROUP_SIZE), 1, 1);
+
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::HOST
+        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::HOST_READ)],
+      &[],
+      &[depth_barrier(
+        cmd_buffer,
+        frame_buffer,
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+      )
+      .src_access_mask(vk::AccessFlags::NONE)
+      .dst_access_mask(
+        vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+          | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+      )],
+    );
+    Ok(())
+  }
+
+  // The first count depths a record wrote for frame_idx. Only valid after the frame's fence is
+  // waited on
+  pub fn read(&self, frame_idx: usize, count: usize) -> Result<Vec<f32>, String> {
+    let Some(buffer) = self.depth_buffers.get(frame_idx) else {
+      return Err(format!("no depth point buffer for frame {frame_idx}"));
+    };
+    buffer.read_data(0, count.min(MAX_DEPTH_POINTS))
+  }
+}
@@ -0,0 +1,279 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_data_wrappers::AdBuffer,
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdFrameBuffer, AdPipeline, AdRenderPass},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{
+  color::Color,
+  triangle_mesh::{TriMeshGPU, TriMeshGenerator},
+  Camera3D,
+};
+
+static GRID_VERT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/editor_grid.vert.spv");
+static GRID_FRAG_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/editor_grid.frag.spv");
+static GIZMO_VERT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/triangle.vert.spv");
+static GIZMO_FRAG_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/editor_gizmo.frag.spv");
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct GridSettings {
+  pub color: Color,
+  pub cell_size: f32,
+  // In pixels
+  pub line_width: f32,
+  pub fade_distance: f32,
+  pub opacity: f32,
+}
+
+impl Default for GridSettings {
+  fn default() -> Self {
+    Self { color: Color::GRAY, cell_size: 1.0, line_width: 1.0, fade_distance: 50.0, opacity: 0.6 }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct GridPushConstants {
+  camera: Camera3D,
+  grid: GridSettings,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct GizmoPushConstants {
+  camera: Camera3D,
+  color: Color,
+}
+
+// Draws editor helpers over the output of TriMeshMaterialRenderer. The render pass is compatible
+// with its framebuffers and loads their color and depth instead of clearing
+pub struct EditorOverlayRenderer {
+  grid_pipeline: AdPipeline,
+  gizmo_pipeline: AdPipeline,
+  render_pass: Arc<AdRenderPass>,
+}
+
+impl EditorOverlayRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    tri_mesh_gen: &TriMeshGenerator,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+  ) -> Result<Self, String> {
+    // Same attachments as the tri renderer framebuffers, with msaa the overlay draws over the
+    // multisampled color and resolves it into attachment 0 again
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+    let mut attachments = vec![
+      vk::AttachmentDescription::default()
+        .format(color_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .initial_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .final_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .load_op(match multisampled {
+          true => vk::AttachmentLoadOp::DONT_CARE,
+          false => vk::AttachmentLoadOp::LOAD,
+        })
+        .store_op(vk::AttachmentStoreOp::STORE),
+      vk::AttachmentDescription::default()
+        .format(depth_format)
+        .samples(samples)
+        .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE),
+    ];
+    if multisampled {
+      attachments.push(
+        vk::AttachmentDescription::default()
+          .format(color_format)
+          .samples(samples)
+          .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+          .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+          .load_op(vk::AttachmentLoadOp::LOAD)
+          // The debug overlay resolves it again after
+          .store_op(vk::AttachmentStoreOp::STORE),
+      );
+    }
+    let output_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(0)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let msaa_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(2)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let depth_attachment_ref = vk::AttachmentReference::default()
+      .attachment(1)
+      .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let subpass = match multisampled {
+      true => vk::SubpassDescription::default()
+        .color_attachments(&msaa_attachment_ref)
+        .resolve_attachments(&output_attachment_ref),
+      false => vk::SubpassDescription::default().color_attachments(&output_attachment_ref),
+    };
+    let render_pass = AdRenderPass::new(
+      ash_device.clone(),
+      vk::RenderPassCreateFlags::default(),
+      &attachments,
+      &[subpass
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(&depth_attachment_ref)],
+      &[
+        vk::SubpassDependency::default()
+          .src_subpass(vk::SUBPASS_EXTERNAL)
+          .dst_subpass(0)
+          .src_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+          )
+          .dst_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+          )
+          .src_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+          )
+          .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_READ
+              | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+          ),
+        vk::SubpassDependency::default()
+          .src_subpass(0)
+          .dst_subpass(vk::SUBPASS_EXTERNAL)
+          .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+          .dst_stage_mask(vk::PipelineStageFlags::TRANSFER)
+          .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+          .dst_access_mask(vk::AccessFlags::TRANSFER_READ),
+      ],
+    )?;
+    let render_pass = Arc::new(render_pass);
+
+    let no_cull_rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
+      .cull_mode(vk::CullModeFlags::NONE)
+      .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+      .polygon_mode(vk::PolygonMode::FILL)
+      .line_width(1.0);
+
+    let grid_pipeline = AdPipeline::new(
+      render_pass.clone(),
+      0,
+      HashMap::from([
+        (vk::ShaderStageFlags::VERTEX, GRID_VERT_SHADER_CODE),
+        (vk::ShaderStageFlags::FRAGMENT, GRID_FRAG_SHADER_CODE),
+      ]),
+      &[],
+      (
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        std::mem::size_of::<GridPushConstants>() as u32,
+      ),
+      no_cull_rasterizer_info,
+      &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
+        vk::PipelineColorBlendAttachmentState::default()
+          .color_write_mask(vk::ColorComponentFlags::RGBA)
+          .blend_enable(true)
+          .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+          .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+          .color_blend_op(vk::BlendOp::ADD)
+          .src_alpha_blend_factor(vk::BlendFactor::ONE)
+          .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+          .alpha_blend_op(vk::BlendOp::ADD),
+      ]),
+      // Grid is hidden behind scene geometry but doesn't occlude anything itself
+      &vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL),
+      samples,
+    )?;
+
+    let gizmo_pipeline = AdPipeline::new(
+      render_pass.clone(),
+      0,
+      HashMap::from([
+        (vk::ShaderStageFlags::VERTEX, GIZMO_VERT_SHADER_CODE),
+        (vk::ShaderStageFlags::FRAGMENT, GIZMO_FRAG_SHADER_CODE),
+      ]),
+      &[tri_mesh_gen.mesh_dset_layout()],
+      (
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        std::mem::size_of::<GizmoPushConstants>() as u32,
+      ),
+      no_cull_rasterizer_info,
+      &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
+        vk::PipelineColorBlendAttachmentState::default()
+          .color_write_mask(vk::ColorComponentFlags::RGBA)
+          .blend_enable(false),
+      ]),
+      // Gizmos always draw on top of the scene
+      &vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(false)
+        .depth_write_enable(false),
+      samples,
+    )?;
+
+    Ok(Self { grid_pipeline, gizmo_pipeline, render_pass })
+  }
+
+  pub fn render(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+    grid: Option<GridSettings>,
+    gizmo_handles: &[(Arc<TriMeshGPU>, Color)],
+  ) {
+    cmd_buffer.begin_render_pass(
+      self.render_pass.inner(),
+      frame_buffer.inner(),
+      vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: frame_buffer.resolution() },
+      &[],
+      vk::SubpassContents::INLINE,
+    );
+
+    cmd_buffer.set_view_port(&[vk::Viewport {
+      x: 0.0,
+      y: 0.0,
+      width: frame_buffer.resolution().width as f32,
+      height: frame_buffer.resolution().height as f32,
+      min_depth: 0.0,
+      max_depth: 1.0,
+    }]);
+    cmd_buffer.set_scissor(&[vk::Rect2D {
+      offset: vk::Offset2D { x: 0, y: 0 },
+      extent: frame_buffer.resolution(),
+    }]);
+
+    if let Some(grid) = grid {
+      cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, self.grid_pipeline.inner());
+      cmd_buffer.set_push_constant_data(
+        self.grid_pipeline.layout(),
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        AdBuffer::get_byte_slice(&[GridPushConstants { camera, grid }]),
+      );
+      cmd_buffer.draw(3);
+    }
+
+    if !gizmo_handles.is_empty() {
+      cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, self.gizmo_pipeline.inner());
+      for (handle_mesh, color) in gizmo_handles.iter() {
+        cmd_buffer.bind_descriptor_sets(
+          vk::PipelineBindPoint::GRAPHICS,
+          self.gizmo_pipeline.layout(),
+          &[handle_mesh.dset().inner()],
+        );
+        cmd_buffer.set_push_constant_data(
+          self.gizmo_pipeline.layout(),
+          vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+          AdBuffer::get_byte_slice(&[GizmoPushConstants { camera, color: *color }]),
+        );
+        cmd_buffer.draw(handle_mesh.indx_count() as _);
+      }
+    }
+    cmd_buffer.end_render_pass();
+  }
+}
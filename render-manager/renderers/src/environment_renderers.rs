@@ -0,0 +1,334 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPoolManager, AdDescriptorSet, AdDescriptorSetLayout,
+    AdImage, AdImageView, AdSampler,
+  },
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
+  ash_render_wrappers::AdComputePipeline,
+  ash_sync_wrappers::AdFence,
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{flat_texture::FlatTextureGPU, glam};
+
+static EQUIRECT_TO_CUBE_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/equirect_to_cube.comp.spv");
+static ENVIRONMENT_PREFILTER_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/environment_prefilter.comp.spv");
+
+const CUBE_GROUP_SIZE: u32 = 8;
+const MIN_FACE_SIZE: u32 = 16;
+const MAX_FACE_SIZE: u32 = 1024;
+// The last level is fully rough, more levels only split the same range finer
+const MAX_ROUGHNESS_LEVELS: u32 = 6;
+const PREFILTER_SAMPLES: u32 = 256;
+
+// Cubemap of an environment, R16G16B16A16_SFLOAT and always in the GENERAL layout. The first
+// level is the environment as is, each level after is prefiltered for a rougher GGX lobe
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct EnvironmentMap {
+  #[getset(get = "pub")]
+  image: Arc<AdImage>,
+  // Cube view of every level, for textureLod with the level of a roughness
+  #[getset(get = "pub")]
+  view: Arc<AdImageView>,
+  #[getset(get_copy = "pub")]
+  roughness_levels: u32,
+}
+
+impl EnvironmentMap {
+  pub fn face_size(&self) -> u32 {
+    self.image.resolution().width
+  }
+
+  // Linear over the levels, from mirror like to fully rough
+  pub fn level_roughness(&self, level: u32) -> f32 {
+    match self.roughness_levels {
+      0 | 1 => 0.0,
+      levels => level as f32 / (levels - 1) as f32,
+    }
+  }
+}
+
+// What each dispatch of a pass reads and writes. Pushed while recording on devices that can push
+// descriptors, so nothing is allocated for them, and in sets allocated up front otherwise
+pub enum EnvironmentBindings {
+  Pushed(Vec<Vec<AdDescriptorBinding>>),
+  Sets(Vec<AdDescriptorSet>),
+}
+
+impl EnvironmentBindings {
+  fn len(&self) -> usize {
+    match self {
+      Self::Pushed(bindings) => bindings.len(),
+      Self::Sets(dsets) => dsets.len(),
+    }
+  }
+}
+
+// Turns equirectangular HDR images into EnvironmentMaps for skyboxes and image based lighting
+pub struct EnvironmentGenerator {
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  // Has to be on a queue that can run compute
+  cmd_pool: Arc<AdCommandPool>,
+  equirect_pipeline: AdComputePipeline,
+  prefilter_pipeline: AdComputePipeline,
+  dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pools: AdDescriptorPoolManager,
+  // Wraps around horizontally, the poles clamp
+  sampler: Arc<AdSampler>,
+}
+
+impl EnvironmentGenerator {
+  pub fn new(
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_pool: Arc<AdCommandPool>,
+  ) -> Result<Self, String> {
+    let ash_device = cmd_pool.queue().ash_device().clone();
+    let layout_bindings = [
+      (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+      (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_IMAGE),
+    ];
+    let dset_layout = Arc::new(match ash_device.push_descriptor() {
+      true => AdDescriptorSetLayout::new_push(ash_device.clone(), &layout_bindings)?,
+      false => AdDescriptorSetLayout::new(ash_device.clone(), &layout_bindings)?,
+    });
+    let dset_pools = AdDescriptorPoolManager::new(ash_device.clone(), "environment", 8);
+    let equirect_pipeline =
+      AdComputePipeline::new(ash_device.clone(), EQUIRECT_TO_CUBE_SHADER_CODE, &[&dset_layout], 0)?;
+    let prefilter_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      ENVIRONMENT_PREFILTER_SHADER_CODE,
+      &[&dset_layout],
+      std::mem::size_of::<glam::Vec4>() as u32,
+    )?;
+    let sampler = Arc::new(AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::REPEAT)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    )?);
+    Ok(Self {
+      ash_device,
+      allocator,
+      cmd_pool,
+      equirect_pipeline,
+      prefilter_pipeline,
+      dset_layout,
+      dset_pools,
+      sampler,
+    })
+  }
+
+  // A quarter of the equirect width keeps about its texel density, as a power of 2
+  pub fn face_size_for(equirect_res: vk::Extent3D) -> u32 {
+    let quarter = (equirect_res.width / 4).max(1);
+    (1 << (31 - quarter.leading_zeros())).clamp(MIN_FACE_SIZE, MAX_FACE_SIZE)
+  }
+
+  // equirect is best uploaded as Rgba16F to keep its range. Submits the passes and waits for them
+  pub fn create_environment(
+    &self,
+    name: &str,
+    equirect: &FlatTextureGPU,
+  ) -> Result<EnvironmentMap, String> {
+    let face_size = Self::face_size_for(equirect.image_view().image().resolution());
+    let roughness_levels = (32 - face_size.leading_zeros()).min(MAX_ROUGHNESS_LEVELS);
+    let image = AdImage::new_cube(
+      self.ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      name,
+      vk::Format::R16G16B16A16_SFLOAT,
+      face_size,
+      vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+      roughness_levels,
+    )?;
+    let level_range = |base_mip_level: u32, level_count: u32| {
+      vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(base_mip_level)
+        .level_count(level_count)
+        .base_array_layer(0)
+        .layer_count(6)
+    };
+    let view = AdImageView::create_view(
+      image.clone(),
+      vk::ImageViewType::CUBE,
+      level_range(0, roughness_levels),
+    )?;
+    // Read while the other levels are written
+    let first_level_view =
+      AdImageView::create_view(image.clone(), vk::ImageViewType::CUBE, level_range(0, 1))?;
+    let level_views = (0..roughness_levels)
+      .map(|level| {
+        AdImageView::create_view(
+          image.clone(),
+          vk::ImageViewType::TYPE_2D_ARRAY,
+          level_range(level, 1),
+        )
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+
+    let equirect_bindings = self.bindings(vec![vec![
+      AdDescriptorBinding::Sampler2D((
+        equirect.image_view().clone(),
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        self.sampler.clone(),
+      )),
+      AdDescriptorBinding::StorageImage((level_views[0].clone(), vk::ImageLayout::GENERAL)),
+    ]])?;
+    let prefilter_bindings = self.prefilter_bindings(&first_level_view, &level_views[1..])?;
+
+    let cmd_buffer =
+      AdCommandBuffer::new(self.cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 1)?.remove(0);
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TOP_OF_PIPE,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[vk::ImageMemoryBarrier::default()
+        .image(image.inner())
+        .subresource_range(level_range(0, roughness_levels))
+        .src_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+        .dst_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+        .src_access_mask(vk::AccessFlags::NONE)
+        .dst_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .new_layout(vk::ImageLayout::GENERAL)],
+    );
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.equirect_pipeline.inner());
+    self.bind(&cmd_buffer, self.equirect_pipeline.layout(), &equirect_bindings, 0)?;
+    let group_count = face_size.div_ceil(CUBE_GROUP_SIZE);
+    cmd_buffer.dispatch(group_count, group_count, 6);
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+      &[],
+      &[],
+    );
+    self.record_prefilter(&cmd_buffer, &prefilter_bindings, face_size)?;
+    cmd_buffer.end()?;
+    let fence = AdFence::new(self.ash_device.clone(), vk::FenceCreateFlags::empty())?;
+    cmd_buffer.submit(&[], &[], Some(&fence))?;
+    fence.wait(999999999)?;
+
+    Ok(EnvironmentMap { image, view, roughness_levels })
+  }
+
+  fn bindings(
+    &self,
+    bindings: Vec<Vec<AdDescriptorBinding>>,
+  ) -> Result<EnvironmentBindings, String> {
+    if self.dset_layout.push_descriptor() {
+      return Ok(EnvironmentBindings::Pushed(bindings));
+    }
+    let dsets = self
+      .dset_pools
+      .allocate(&bindings.into_iter().map(|x| (self.dset_layout.clone(), x)).collect::<Vec<_>>())?;
+    Ok(EnvironmentBindings::Sets(dsets))
+  }
+
+  fn bind(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    pipeline_layout: vk::PipelineLayout,
+    bindings: &EnvironmentBindings,
+    idx: usize,
+  ) -> Result<(), String> {
+    match bindings {
+      EnvironmentBindings::Pushed(bindings) => self.dset_layout.push(
+        cmd_buffer,
+        vk::PipelineBindPoint::COMPUTE,
+        pipeline_layout,
+        0,
+        &bindings[idx],
+      ),
+      EnvironmentBindings::Sets(dsets) => {
+        cmd_buffer.bind_descriptor_sets(
+          vk::PipelineBindPoint::COMPUTE,
+          pipeline_layout,
+          &[dsets[idx].inner()],
+        );
+        Ok(())
+      }
+    }
+  }
+
+  // Bindings for record_prefilter, reading a cube view of a cubemap's first level and writing 2D
+  // array views of the levels after it, in order
+  pub fn prefilter_bindings(
+    &self,
+    first_level_view: &Arc<AdImageView>,
+    level_views: &[Arc<AdImageView>],
+  ) -> Result<EnvironmentBindings, String> {
+    self.bindings(
+      level_views
+        .iter()
+        .map(|level_view| {
+          vec![
+            AdDescriptorBinding::Sampler2D((
+              first_level_view.clone(),
+              vk::ImageLayout::GENERAL,
+              self.sampler.clone(),
+            )),
+            AdDescriptorBinding::StorageImage((level_view.clone(), vk::ImageLayout::GENERAL)),
+          ]
+        })
+        .collect(),
+    )
+  }
+
+  // Convolves the first level of a cubemap in the GENERAL layout into the levels after it, each
+  // for a rougher GGX lobe up to fully rough. The first level has to be written before, the
+  // levels can be read by compute and fragment shaders after
+  pub fn record_prefilter(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    bindings: &EnvironmentBindings,
+    face_size: u32,
+  ) -> Result<(), String> {
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.prefilter_pipeline.inner());
+    for i in 0..bindings.len() {
+      let level = i as u32 + 1;
+      let level_size = (face_size >> level).max(1);
+      let roughness = level as f32 / bindings.len() as f32;
+      self.bind(cmd_buffer, self.prefilter_pipeline.layout(), bindings, i)?;
+      cmd_buffer.set_push_constant_data(
+        self.prefilter_pipeline.layout(),
+        vk::ShaderStageFlags::COMPUTE,
+        AdBuffer::get_byte_slice(&[glam::vec4(roughness, PREFILTER_SAMPLES as f32, 0.0, 0.0)]),
+      );
+      let group_count = level_size.div_ceil(CUBE_GROUP_SIZE);
+      cmd_buffer.dispatch(group_count, group_count, 6);
+    }
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+      &[],
+      &[],
+    );
+    Ok(())
+  }
+}
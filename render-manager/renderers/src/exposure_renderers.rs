@@ -0,0 +1,383 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdSampler,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdComputePipeline, AdFrameBuffer},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{
+  color_lut::{ColorLutGPU, ColorLutGenerator},
+  glam,
+};
+
+use crate::{
+  post_renderers::{begin_scene_color_read, POST_GROUP_SIZE},
+  render_targets::reallocate_dsets,
+};
+
+static LUMINANCE_HISTOGRAM_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/luminance_histogram.comp.spv");
+static EXPOSURE_ADAPT_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/exposure_adapt.comp.spv");
+static TONEMAP_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/tonemap.comp.spv");
+
+const HISTOGRAM_BINS: usize = 64;
+const HISTOGRAM_GROUP_SIZE: u32 = 16;
+// Luminance the histogram covers in log2, about 0.001 to 64. Darker pixels aren't metered, brighter
+// ones count as the brightest bin
+const MIN_LOG_LUMINANCE: f32 = -10.0;
+const LOG_LUMINANCE_RANGE: f32 = 16.0;
+const MAX_EXPOSURE_SETS: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TonemapExposure {
+  // Metered from the scene, moving adaptation of the way from the last exposure to it, clamped to
+  // the ev range
+  Metered { adaptation: f32, min_ev: f32, max_ev: f32 },
+  // In stops, each one up halves the brightness
+  Fixed(f32),
+}
+
+// Graded colors are mixed from the from table towards the to table by blend
+#[derive(Clone, Copy)]
+pub struct ColorGradingLuts<'a> {
+  pub from: &'a ColorLutGPU,
+  pub to: &'a ColorLutGPU,
+  pub blend: f32,
+}
+
+#[repr(C)]
+struct AdaptPushConstants {
+  // min log2 luminance, log2 luminance range, adaptation, pixel count
+  params: glam::Vec4,
+  // min ev, max ev, unused, unused
+  ev_range: glam::Vec4,
+}
+
+// Tonemaps the HDR scene color of TriMeshMaterialRenderer framebuffers in place, with an exposure
+// either given or metered from a luminance histogram of the scene, and color grades the result.
+// The metered exposure is kept on the gpu and shared by the frames in flight, which the queue runs
+// one after another. The color images need SAMPLED and STORAGE usage
+pub struct ExposureRenderer {
+  histogram_pipeline: AdComputePipeline,
+  adapt_pipeline: AdComputePipeline,
+  tonemap_pipeline: AdComputePipeline,
+  histogram_dset_layout: Arc<AdDescriptorSetLayout>,
+  tonemap_dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  sampler: Arc<AdSampler>,
+  histogram_buffer: Arc<AdBuffer>,
+  exposure_buffer: Arc<AdBuffer>,
+  adapt_dset: AdDescriptorSet,
+  // One each per frame in flight
+  histogram_dsets: Vec<AdDescriptorSet>,
+  tonemap_dsets: Vec<AdDescriptorSet>,
+  // Bound while nothing is graded, the shader always reads both tables' sets
+  identity_lut: Arc<ColorLutGPU>,
+}
+
+impl ExposureRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    color_lut_gen: &ColorLutGenerator,
+  ) -> Result<Self, String> {
+    let histogram_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+      ],
+    )?);
+    let adapt_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+      ],
+    )?);
+    let tonemap_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_IMAGE),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER),
+      ],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_EXPOSURE_SETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+          descriptor_count: MAX_EXPOSURE_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_IMAGE,
+          descriptor_count: MAX_EXPOSURE_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_BUFFER,
+          descriptor_count: MAX_EXPOSURE_SETS * 2,
+        },
+      ],
+    )?);
+    let histogram_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      LUMINANCE_HISTOGRAM_SHADER_CODE,
+      &[&histogram_dset_layout],
+      std::mem::size_of::<glam::Vec4>() as u32,
+    )?;
+    let adapt_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      EXPOSURE_ADAPT_SHADER_CODE,
+      &[&adapt_dset_layout],
+      std::mem::size_of::<AdaptPushConstants>() as u32,
+    )?;
+    let tonemap_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      TONEMAP_SHADER_CODE,
+      &[&tonemap_dset_layout, color_lut_gen.dset_layout(), color_lut_gen.dset_layout()],
+      std::mem::size_of::<glam::Vec4>() as u32,
+    )?;
+    let sampler = Arc::new(AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::NEAREST)
+        .min_filter(vk::Filter::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    )?);
+
+    // Small and written once, kept host visible so they start cleared without a submit
+    let histogram_buffer = Arc::new(AdBuffer::new(
+      ash_device.clone(),
+      allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      "luminance_histogram",
+      vk::BufferCreateFlags::empty(),
+      (HISTOGRAM_BINS * std::mem::size_of::<u32>()) as _,
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+    )?);
+    histogram_buffer.write_data(0, &[0u32; HISTOGRAM_BINS])?;
+    let exposure_buffer = Arc::new(AdBuffer::new(
+      ash_device,
+      allocator,
+      MemoryLocation::CpuToGpu,
+      "exposure",
+      vk::BufferCreateFlags::empty(),
+      std::mem::size_of::<glam::Vec4>() as _,
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+    )?);
+    exposure_buffer.write_data(0, &[glam::vec4(0.0, 1.0, 0.0, 0.0)])?;
+    let adapt_dset = AdDescriptorSet::new(
+      dset_pool.clone(),
+      &[(
+        adapt_dset_layout,
+        vec![
+          AdDescriptorBinding::StorageBuffer(histogram_buffer.clone()),
+          AdDescriptorBinding::StorageBuffer(exposure_buffer.clone()),
+        ],
+      )],
+    )?
+    .remove(0);
+
+    Ok(Self {
+      histogram_pipeline,
+      adapt_pipeline,
+      tonemap_pipeline,
+      histogram_dset_layout,
+      tonemap_dset_layout,
+      dset_pool,
+      sampler,
+      histogram_buffer,
+      exposure_buffer,
+      adapt_dset,
+      histogram_dsets: vec![],
+      tonemap_dsets: vec![],
+      identity_lut: color_lut_gen.identity(),
+    })
+  }
+
+  pub fn resize_targets(
+    &mut self,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+  ) -> Result<(), String> {
+    reallocate_dsets(
+      &mut self.histogram_dsets,
+      &self.dset_pool,
+      &self.histogram_dset_layout,
+      scene_frame_buffers.iter().map(|fb| {
+        vec![
+          AdDescriptorBinding::Sampler2D((
+            fb.attachments()[0].clone(),
+            vk::ImageLayout::GENERAL,
+            self.sampler.clone(),
+          )),
+          AdDescriptorBinding::StorageBuffer(self.histogram_buffer.clone()),
+        ]
+      }),
+    )?;
+    reallocate_dsets(
+      &mut self.tonemap_dsets,
+      &self.dset_pool,
+      &self.tonemap_dset_layout,
+      scene_frame_buffers.iter().map(|fb| {
+        vec![
+          AdDescriptorBinding::StorageImage((
+            fb.attachments()[0].clone(),
+            vk::ImageLayout::GENERAL,
+          )),
+          AdDescriptorBinding::StorageBuffer(self.exposure_buffer.clone()),
+        ]
+      }),
+    )
+  }
+
+  fn meter(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    histogram_dset: &AdDescriptorSet,
+    resolution: vk::Extent2D,
+    adaptation: f32,
+    ev_range: (f32, f32),
+  ) {
+    let compute_barrier = || {
+      cmd_buffer.pipeline_barrier(
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::DependencyFlags::empty(),
+        &[vk::MemoryBarrier::default()
+          .src_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+          .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)],
+        &[],
+        &[],
+      );
+    };
+
+    // Last frame's adaptation cleared the histogram and its tonemap read the exposure
+    compute_barrier();
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.histogram_pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.histogram_pipeline.layout(),
+      &[histogram_dset.inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.histogram_pipeline.layout(),
+      vk::ShaderStageFlags::COMPUTE,
+      AdBuffer::get_byte_slice(&[glam::vec4(
+        MIN_LOG_LUMINANCE,
+        1.0 / LOG_LUMINANCE_RANGE,
+        0.0,
+        0.0,
+      )]),
+    );
+    cmd_buffer.dispatch(
+      resolution.width.div_ceil(HISTOGRAM_GROUP_SIZE),
+      resolution.height.div_ceil(HISTOGRAM_GROUP_SIZE),
+      1,
+    );
+
+    compute_barrier();
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.adapt_pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.adapt_pipeline.layout(),
+      &[self.adapt_dset.inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.adapt_pipeline.layout(),
+      vk::ShaderStageFlags::COMPUTE,
+      AdBuffer::get_byte_slice(&[AdaptPushConstants {
+        params: glam::vec4(
+          MIN_LOG_LUMINANCE,
+          LOG_LUMINANCE_RANGE,
+          adaptation,
+          (resolution.width * resolution.height) as f32,
+        ),
+        ev_range: glam::vec4(ev_range.0, ev_range.1, 0.0, 0.0),
+      }]),
+    );
+    cmd_buffer.dispatch(1, 1, 1);
+    compute_barrier();
+  }
+
+  // After the scene is drawn into scene_frame_buffer, which is the framebuffer of frame_idx, and
+  // before the overlays. Takes and leaves the color in TRANSFER_SRC_OPTIMAL. No grading leaves the
+  // tonemapped colors as they are
+  pub fn tonemap(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+    exposure: TonemapExposure,
+    grading: Option<ColorGradingLuts>,
+  ) -> Result<(), String> {
+    let (Some(histogram_dset), Some(tonemap_dset)) =
+      (self.histogram_dsets.get(frame_idx), self.tonemap_dsets.get(frame_idx))
+    else {
+      return Err(format!("no exposure sets for frame {frame_idx}"));
+    };
+    let resolution = scene_frame_buffer.resolution();
+    begin_scene_color_read(cmd_buffer, scene_frame_buffer);
+    let fixed_brightness = match exposure {
+      TonemapExposure::Metered { adaptation, min_ev, max_ev } => {
+        self.meter(cmd_buffer, histogram_dset, resolution, adaptation, (min_ev, max_ev));
+        None
+      }
+      TonemapExposure::Fixed(ev) => Some((-ev).exp2()),
+    };
+
+    let luts = grading.unwrap_or(ColorGradingLuts {
+      from: &self.identity_lut,
+      to: &self.identity_lut,
+      blend: 0.0,
+    });
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.tonemap_pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.tonemap_pipeline.layout(),
+      &[tonemap_dset.inner(), luts.from.dset().inner(), luts.to.dset().inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.tonemap_pipeline.layout(),
+      vk::ShaderStageFlags::COMPUTE,
+      AdBuffer::get_byte_slice(&[glam::vec4(
+        fixed_brightness.unwrap_or(1.0),
+        fixed_brightness.is_some() as u32 as f32,
+        luts.blend.clamp(0.0, 1.0),
+        grading.is_some() as u32 as f32,
+      )]),
+    );
+    cmd_buffer.dispatch(
+      resolution.width.div_ceil(POST_GROUP_SIZE),
+      resolution.height.div_ceil(POST_GROUP_SIZE),
+      1,
+    );
+    // Overlays draw over the result, then it is encoded or blitted to the swapchain
+    scene_frame_buffer.attachments()[0].transition_from_general(
+      cmd_buffer,
+      vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+      vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+        | vk::PipelineStageFlags::COMPUTE_SHADER
+        | vk::PipelineStageFlags::TRANSFER,
+      vk::AccessFlags::COLOR_ATTACHMENT_READ
+        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+        | vk::AccessFlags::SHADER_READ
+        | vk::AccessFlags::SHADER_WRITE
+        | vk::AccessFlags::TRANSFER_READ,
+    );
+    Ok(())
+  }
+}
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdImageView, AdSampler,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdComputePipeline, AdFrameBuffer},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::glam;
+
+use crate::{
+  post_renderers::{begin_scene_color_read, copy_into_scene_color, POST_FORMAT, POST_GROUP_SIZE},
+  render_targets::{color_range, reallocate_dsets},
+  transient_images::{TransientImageDesc, TransientImages},
+};
+
+static FILM_EFFECTS_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/film_effects.comp.spv");
+
+// Aberration strengths are given for a frame this tall, so it looks the same at any resolution
+const ABERRATION_REFERENCE_HEIGHT: f32 = 1080.0;
+// Grain seeds wrap around at this, well within what a float holds exactly
+const GRAIN_SEED_PERIOD: u64 = 1 << 20;
+const MAX_FILM_EFFECTS_SETS: u32 = 16;
+const FILM_EFFECTS_IMAGE: &str = "film_effects_output_image";
+
+// Darkening towards the corners, see PostProcessConfig for what each does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vignette {
+  pub intensity: f32,
+  pub radius: f32,
+  pub softness: f32,
+}
+
+// Effects left out are skipped
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FilmEffectsParams {
+  pub vignette: Option<Vignette>,
+  // How far apart red and blue are in the corners, in pixels at 1080p
+  pub chromatic_aberration: Option<f32>,
+  // How much the grain moves a color in 0..1
+  pub film_grain: Option<f32>,
+}
+
+impl FilmEffectsParams {
+  pub fn is_off(&self) -> bool {
+    self.vignette.is_none_or(|vignette| vignette.intensity <= 0.0)
+      && self.chromatic_aberration.is_none_or(|strength| strength <= 0.0)
+      && self.film_grain.is_none_or(|intensity| intensity <= 0.0)
+  }
+}
+
+// Aberration strength in pixels at 1080p to pixels of a frame screen_height tall
+pub fn aberration_pixels(strength: f32, screen_height: u32) -> f32 {
+  strength * screen_height as f32 / ABERRATION_REFERENCE_HEIGHT
+}
+
+#[repr(C)]
+struct FilmEffectsPushConstants {
+  // intensity, radius, softness, unused
+  vignette: glam::Vec4,
+  // aberration in pixels, grain intensity, grain seed, unused
+  params: glam::Vec4,
+}
+
+// Vignette, chromatic aberration and film grain over the scene color of TriMeshMaterialRenderer
+// framebuffers, in one pass after the tonemap. The scene color images need SAMPLED and
+// TRANSFER_DST usage
+pub struct FilmEffectsRenderer {
+  pipeline: AdComputePipeline,
+  dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  sampler: Arc<AdSampler>,
+  // Shared by the frames in flight, the queue runs their passes one after another
+  output_view: Option<Arc<AdImageView>>,
+  // One per frame in flight
+  dsets: Vec<AdDescriptorSet>,
+}
+
+impl FilmEffectsRenderer {
+  pub fn new(ash_device: Arc<AdAshDevice>) -> Result<Self, String> {
+    let dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_IMAGE),
+      ],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_FILM_EFFECTS_SETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+          descriptor_count: MAX_FILM_EFFECTS_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_IMAGE,
+          descriptor_count: MAX_FILM_EFFECTS_SETS,
+        },
+      ],
+    )?);
+    let pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      FILM_EFFECTS_SHADER_CODE,
+      &[&dset_layout],
+      std::mem::size_of::<FilmEffectsPushConstants>() as u32,
+    )?;
+    // Aberration samples between texels, and off the edge it holds the edge color
+    let sampler = Arc::new(AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    )?);
+    Ok(Self { pipeline, dset_layout, dset_pool, sampler, output_view: None, dsets: vec![] })
+  }
+
+  // Its output, only used while the effects are applied in pass
+  pub fn transient_images(pass: u32) -> Vec<TransientImageDesc> {
+    let usage = vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC;
+    vec![TransientImageDesc::in_pass(FILM_EFFECTS_IMAGE, POST_FORMAT, usage, pass)]
+  }
+
+  // With transient_images made for the same framebuffers
+  pub fn resize_targets(
+    &mut self,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+    transient_images: &TransientImages,
+  ) -> Result<(), String> {
+    let output_view = AdImageView::create_view(
+      transient_images.get(FILM_EFFECTS_IMAGE)?,
+      vk::ImageViewType::TYPE_2D,
+      color_range(),
+    )?;
+    reallocate_dsets(
+      &mut self.dsets,
+      &self.dset_pool,
+      &self.dset_layout,
+      scene_frame_buffers.iter().map(|fb| {
+        vec![
+          AdDescriptorBinding::Sampler2D((
+            fb.attachments()[0].clone(),
+            vk::ImageLayout::GENERAL,
+            self.sampler.clone(),
+          )),
+          AdDescriptorBinding::StorageImage((output_view.clone(), vk::ImageLayout::GENERAL)),
+        ]
+      }),
+    )?;
+    self.output_view = Some(output_view);
+    Ok(())
+  }
+
+  // After the tonemap into scene_frame_buffer, which is the framebuffer of frame_idx, and before
+  // the overlays. Takes and leaves the scene color in TRANSFER_SRC_OPTIMAL. The grain changes with
+  // grain_seed, a frame number does
+  pub fn apply(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+    effects: &FilmEffectsParams,
+    grain_seed: u64,
+  ) -> Result<(), String> {
+    let (Some(output_view), Some(dset)) = (&self.output_view, self.dsets.get(frame_idx)) else {
+      return Err(format!("no film effects targets for frame {frame_idx}"));
+    };
+    if effects.is_off() {
+      return Ok(());
+    }
+    begin_scene_color_read(cmd_buffer, scene_frame_buffer);
+    // Last frame's copy may still be reading the output being rewritten
+    output_view.transition_to_general(
+      cmd_buffer,
+      vk::ImageLayout::UNDEFINED,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::AccessFlags::NONE,
+    );
+
+    let resolution = scene_frame_buffer.resolution();
+    let vignette = effects.vignette.map_or(glam::Vec4::ZERO, |vignette| {
+      glam::vec4(vignette.intensity, vignette.radius, vignette.softness, 0.0)
+    });
+    let aberration =
+      aberration_pixels(effects.chromatic_aberration.unwrap_or(0.0), resolution.height);
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.pipeline.layout(),
+      &[dset.inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.pipeline.layout(),
+      vk::ShaderStageFlags::COMPUTE,
+      AdBuffer::get_byte_slice(&[FilmEffectsPushConstants {
+        vignette,
+        params: glam::vec4(
+          aberration,
+          effects.film_grain.unwrap_or(0.0),
+          (grain_seed % GRAIN_SEED_PERIOD) as f32,
+          0.0,
+        ),
+      }]),
+    );
+    cmd_buffer.dispatch(
+      resolution.width.div_ceil(POST_GROUP_SIZE),
+      resolution.height.div_ceil(POST_GROUP_SIZE),
+      1,
+    );
+    copy_into_scene_color(cmd_buffer, output_view.image(), scene_frame_buffer);
+    Ok(())
+  }
+}
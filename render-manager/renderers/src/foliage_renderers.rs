@@ -0,0 +1,277 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_data_wrappers::{AdBuffer, AdDescriptorSet, AdDescriptorSetLayout},
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdFrameBuffer, AdPipeline, AdRenderPass},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{
+  foliage::{FoliageGPU, FoliageGenerator, WindParams},
+  glam,
+  material::{MaterialGenerator, ShaderVariant},
+  Camera3D,
+};
+
+static FOLIAGE_VERT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/foliage.vert.spv");
+static UNLIT_FRAG_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/triangle_unlit.frag.spv");
+static LIT_FRAG_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/triangle_lit.frag.spv");
+static CUTOUT_FRAG_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/triangle_cutout.frag.spv");
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct FoliageFrame {
+  camera: Camera3D,
+  // xy wind direction on xz, strength, gust speed
+  wind: glam::Vec4,
+  // time in seconds, gust size, unused, unused
+  frame: glam::Vec4,
+}
+
+// Draws foliage cards over the output of TriMeshMaterialRenderer, one instanced draw per run of
+// cells in range. Cards are swayed in the vertex shader and always double sided, materials only
+// pick the fragment shader, the alpha cutout one for the usual grass and leaf textures
+pub struct FoliageRenderer {
+  // Built the first time a material needs one, every pipeline has the same layout
+  pipelines: HashMap<ShaderVariant, AdPipeline>,
+  foliage_dset_layout: Arc<AdDescriptorSetLayout>,
+  material_dset_layout: Arc<AdDescriptorSetLayout>,
+  shadow_dset_layout: Arc<AdDescriptorSetLayout>,
+  render_pass: Arc<AdRenderPass>,
+  samples: vk::SampleCountFlags,
+}
+
+impl FoliageRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    foliage_gen: &FoliageGenerator,
+    material_gen: &MaterialGenerator,
+    shadow_dset_layout: Arc<AdDescriptorSetLayout>,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+  ) -> Result<Self, String> {
+    // Same attachments as the tri renderer framebuffers, keeping depth and msaa color for the
+    // particles and the editor overlay drawn after
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+    let mut attachments = vec![
+      vk::AttachmentDescription::default()
+        .format(color_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .initial_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .final_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .load_op(match multisampled {
+          true => vk::AttachmentLoadOp::DONT_CARE,
+          false => vk::AttachmentLoadOp::LOAD,
+        })
+        .store_op(vk::AttachmentStoreOp::STORE),
+      vk::AttachmentDescription::default()
+        .format(depth_format)
+        .samples(samples)
+        .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::STORE),
+    ];
+    if multisampled {
+      attachments.push(
+        vk::AttachmentDescription::default()
+          .format(color_format)
+          .samples(samples)
+          .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+          .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+          .load_op(vk::AttachmentLoadOp::LOAD)
+          .store_op(vk::AttachmentStoreOp::STORE),
+      );
+    }
+    let output_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(0)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let msaa_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(2)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let depth_attachment_ref = vk::AttachmentReference::default()
+      .attachment(1)
+      .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let subpass = match multisampled {
+      true => vk::SubpassDescription::default()
+        .color_attachments(&msaa_attachment_ref)
+        .resolve_attachments(&output_attachment_ref),
+      false => vk::SubpassDescription::default().color_attachments(&output_attachment_ref),
+    };
+    let render_pass = AdRenderPass::new(
+      ash_device.clone(),
+      vk::RenderPassCreateFlags::default(),
+      &attachments,
+      &[subpass
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(&depth_attachment_ref)],
+      &[
+        vk::SubpassDependency::default()
+          .src_subpass(vk::SUBPASS_EXTERNAL)
+          .dst_subpass(0)
+          .src_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+          )
+          .dst_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+          )
+          .src_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+          )
+          .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_READ
+              | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+          ),
+        vk::SubpassDependency::default()
+          .src_subpass(0)
+          .dst_subpass(vk::SUBPASS_EXTERNAL)
+          .src_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+          )
+          .dst_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+              | vk::PipelineStageFlags::TRANSFER,
+          )
+          .src_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+          )
+          .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_READ
+              | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+              | vk::AccessFlags::TRANSFER_READ,
+          ),
+      ],
+    )?;
+    let render_pass = Arc::new(render_pass);
+
+    Ok(Self {
+      pipelines: HashMap::new(),
+      foliage_dset_layout: foliage_gen.foliage_dset_layout().clone(),
+      material_dset_layout: material_gen.material_dset_layout().clone(),
+      shadow_dset_layout,
+      render_pass,
+      samples,
+    })
+  }
+
+  fn create_pipeline(&self, variant: ShaderVariant) -> Result<AdPipeline, String> {
+    let frag_shader_code = match variant {
+      // Foliage sways and has no second uvs, so it is never lightmapped
+      ShaderVariant::Unlit | ShaderVariant::Lightmapped => UNLIT_FRAG_SHADER_CODE,
+      ShaderVariant::Lit => LIT_FRAG_SHADER_CODE,
+      ShaderVariant::AlphaCutout => CUTOUT_FRAG_SHADER_CODE,
+    };
+    AdPipeline::new(
+      self.render_pass.clone(),
+      0,
+      HashMap::from([
+        (vk::ShaderStageFlags::VERTEX, FOLIAGE_VERT_SHADER_CODE),
+        (vk::ShaderStageFlags::FRAGMENT, frag_shader_code),
+      ]),
+      &[&self.foliage_dset_layout, &self.material_dset_layout, &self.shadow_dset_layout],
+      (
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        std::mem::size_of::<FoliageFrame>() as u32,
+      ),
+      vk::PipelineRasterizationStateCreateInfo::default()
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0),
+      &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
+        vk::PipelineColorBlendAttachmentState::default()
+          .color_write_mask(vk::ColorComponentFlags::RGBA)
+          .blend_enable(false),
+      ]),
+      &vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS),
+      self.samples,
+    )
+    .map_err(|e| format!("at creating foliage {variant:?} pipeline: {e}"))
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  pub fn render(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+    shadow_dset: &AdDescriptorSet,
+    time_s: f32,
+    wind: WindParams,
+    foliages: &[Arc<FoliageGPU>],
+  ) -> Result<(), String> {
+    for foliage in foliages.iter() {
+      let variant = foliage.material().pipeline_key().variant;
+      if !self.pipelines.contains_key(&variant) {
+        let pipeline = self.create_pipeline(variant)?;
+        self.pipelines.insert(variant, pipeline);
+      }
+    }
+
+    cmd_buffer.begin_render_pass(
+      self.render_pass.inner(),
+      frame_buffer.inner(),
+      vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: frame_buffer.resolution() },
+      &[],
+      vk::SubpassContents::INLINE,
+    );
+    cmd_buffer.set_view_port(&[vk::Viewport {
+      x: 0.0,
+      y: 0.0,
+      width: frame_buffer.resolution().width as f32,
+      height: frame_buffer.resolution().height as f32,
+      min_depth: 0.0,
+      max_depth: 1.0,
+    }]);
+    cmd_buffer.set_scissor(&[vk::Rect2D {
+      offset: vk::Offset2D { x: 0, y: 0 },
+      extent: frame_buffer.resolution(),
+    }]);
+
+    let wind_dir = wind.direction.normalize_or_zero();
+    let frame = FoliageFrame {
+      camera,
+      wind: glam::vec4(wind_dir.x, wind_dir.y, wind.strength, wind.speed),
+      frame: glam::vec4(time_s, wind.gust_size.max(0.001), 0.0, 0.0),
+    };
+    for foliage in foliages.iter() {
+      let Some(pipeline) = self.pipelines.get(&foliage.material().pipeline_key().variant) else {
+        continue;
+      };
+      let ranges = foliage.visible_ranges(camera.pos.truncate());
+      if ranges.is_empty() {
+        continue;
+      }
+      cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, pipeline.inner());
+      cmd_buffer.set_push_constant_data(
+        pipeline.layout(),
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        AdBuffer::get_byte_slice(&[frame]),
+      );
+      cmd_buffer.bind_descriptor_sets(
+        vk::PipelineBindPoint::GRAPHICS,
+        pipeline.layout(),
+        &[foliage.dset().inner(), foliage.material().dset().inner(), shadow_dset.inner()],
+      );
+      for (first_instance, instance_count) in ranges {
+        cmd_buffer.draw_instance_range(foliage.indx_count() as _, instance_count, first_instance);
+      }
+    }
+    cmd_buffer.end_render_pass();
+    Ok(())
+  }
+}
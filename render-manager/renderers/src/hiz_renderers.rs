@@ -0,0 +1,360 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdImage, AdImageView,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdComputePipeline, AdFrameBuffer},
+  ash_sync_wrappers::AdFence,
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::glam;
+
+use crate::render_targets::depth_barrier;
+
+static DEPTH_PYRAMID_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/depth_pyramid.comp.spv");
+static DEPTH_PYRAMID_MS_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/depth_pyramid_ms.comp.spv");
+static DEPTH_PYRAMID_REDUCE_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/depth_pyramid_reduce.comp.spv");
+
+const PYRAMID_GROUP_SIZE: u32 = 8;
+// Enough sets for a few pyramids over every frame in flight
+const MAX_PYRAMID_SETS: u32 = 256;
+
+// Farthest depth mip chain of a set of framebuffers' depth, R32_SFLOAT and always in the GENERAL
+// layout. The first level is the power of 2 at or under the depth size on each side, each level
+// after halves it down to 1x1
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct HiZPyramid {
+  #[getset(get = "pub")]
+  image: Arc<AdImage>,
+  // Every level, for texelFetch with the level as lod
+  #[getset(get = "pub")]
+  view: Arc<AdImageView>,
+  #[getset(get = "pub")]
+  level_views: Vec<Arc<AdImageView>>,
+  // One per framebuffer, reading its depth into the first level
+  depth_dsets: Vec<AdDescriptorSet>,
+  // Level i + 1 from level i
+  reduce_dsets: Vec<AdDescriptorSet>,
+}
+
+impl HiZPyramid {
+  pub fn resolution(&self) -> vk::Extent2D {
+    let res = self.image.resolution();
+    vk::Extent2D { width: res.width, height: res.height }
+  }
+
+  pub fn mip_count(&self) -> u32 {
+    self.level_views.len() as u32
+  }
+}
+
+// Builds HiZPyramids from the depth attachment of TriMeshMaterialRenderer framebuffers in
+// compute passes, for occlusion tests and screen space tracing
+pub struct HiZGenerator {
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  depth_pipeline: AdComputePipeline,
+  reduce_pipeline: AdComputePipeline,
+  level_dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  samples: vk::SampleCountFlags,
+}
+
+impl HiZGenerator {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    samples: vk::SampleCountFlags,
+  ) -> Result<Self, String> {
+    let level_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_IMAGE),
+      ],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_PYRAMID_SETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLED_IMAGE,
+          descriptor_count: MAX_PYRAMID_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_IMAGE,
+          descriptor_count: MAX_PYRAMID_SETS,
+        },
+      ],
+    )?);
+    let depth_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      match samples != vk::SampleCountFlags::TYPE_1 {
+        true => DEPTH_PYRAMID_MS_SHADER_CODE,
+        false => DEPTH_PYRAMID_SHADER_CODE,
+      },
+      &[&level_dset_layout],
+      std::mem::size_of::<glam::UVec4>() as u32,
+    )?;
+    let reduce_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      DEPTH_PYRAMID_REDUCE_SHADER_CODE,
+      &[&level_dset_layout],
+      std::mem::size_of::<glam::UVec4>() as u32,
+    )?;
+    Ok(Self {
+      ash_device,
+      allocator,
+      depth_pipeline,
+      reduce_pipeline,
+      level_dset_layout,
+      dset_pool,
+      samples,
+    })
+  }
+
+  // Sized to the first framebuffer, all of them need the same resolution. Submits the layout
+  // change on cmd_buffer and waits for it
+  pub fn create_pyramid(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    name: &str,
+    frame_buffers: &[Arc<AdFrameBuffer>],
+  ) -> Result<HiZPyramid, String> {
+    let Some(depth_res) = frame_buffers.first().map(|fb| fb.resolution()) else {
+      return Err(format!("no framebuffers to make depth pyramid {name} for"));
+    };
+    let prev_power_of_2 = |x: u32| if x <= 1 { 1 } else { 1 << (31 - x.leading_zeros()) };
+    let pyramid_res = vk::Extent2D {
+      width: prev_power_of_2(depth_res.width),
+      height: prev_power_of_2(depth_res.height),
+    };
+    let mip_count = 32 - pyramid_res.width.max(pyramid_res.height).leading_zeros();
+    let image = AdImage::new_2d(
+      self.ash_device.clone(),
+      self.allocator.clone(),
+      MemoryLocation::GpuOnly,
+      name,
+      vk::Format::R32_SFLOAT,
+      pyramid_res,
+      vk::ImageUsageFlags::STORAGE
+        | vk::ImageUsageFlags::SAMPLED
+        | vk::ImageUsageFlags::TRANSFER_DST,
+      vk::SampleCountFlags::TYPE_1,
+      mip_count,
+    )?;
+    let level_range = |base_mip_level: u32, level_count: u32| {
+      vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(base_mip_level)
+        .level_count(level_count)
+        .base_array_layer(0)
+        .layer_count(1)
+    };
+    let view = AdImageView::create_view(
+      image.clone(),
+      vk::ImageViewType::TYPE_2D,
+      level_range(0, mip_count),
+    )?;
+    let level_views = (0..mip_count)
+      .map(|level| {
+        AdImageView::create_view(image.clone(), vk::ImageViewType::TYPE_2D, level_range(level, 1))
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+
+    let depth_dsets = AdDescriptorSet::new(
+      self.dset_pool.clone(),
+      &frame_buffers
+        .iter()
+        .map(|fb| {
+          (
+            self.level_dset_layout.clone(),
+            vec![
+              AdDescriptorBinding::Image2D((
+                fb.attachments()[1].clone(),
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+              )),
+              AdDescriptorBinding::StorageImage((level_views[0].clone(), vk::ImageLayout::GENERAL)),
+            ],
+          )
+        })
+        .collect::<Vec<_>>(),
+    )?;
+    let reduce_dsets = AdDescriptorSet::new(
+      self.dset_pool.clone(),
+      &level_views
+        .windows(2)
+        .map(|views| {
+          (
+            self.level_dset_layout.clone(),
+            vec![
+              AdDescriptorBinding::Image2D((views[0].clone(), vk::ImageLayout::GENERAL)),
+              AdDescriptorBinding::StorageImage((views[1].clone(), vk::ImageLayout::GENERAL)),
+            ],
+          )
+        })
+        .collect::<Vec<_>>(),
+    )?;
+
+    // Cleared to the far plane so reading it before the first build culls nothing
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::default())?;
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TOP_OF_PIPE,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[vk::ImageMemoryBarrier::default()
+        .image(image.inner())
+        .subresource_range(level_range(0, mip_count))
+        .src_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+        .dst_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+        .src_access_mask(vk::AccessFlags::NONE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .new_layout(vk::ImageLayout::GENERAL)],
+    );
+    cmd_buffer.clear_color_image(
+      image.inner(),
+      vk::ImageLayout::GENERAL,
+      &vk::ClearColorValue { float32: [1.0; 4] },
+      &[level_range(0, mip_count)],
+    );
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)],
+      &[],
+      &[],
+    );
+    cmd_buffer.end()?;
+    let fence = AdFence::new(self.ash_device.clone(), vk::FenceCreateFlags::empty())?;
+    cmd_buffer.submit(&[], &[], Some(&fence))?;
+    fence.wait(999999999)?;
+
+    Ok(HiZPyramid { image, view, level_views, depth_dsets, reduce_dsets })
+  }
+
+  // Must be recorded outside a render pass, after the depth is drawn into frame_buffer, which is
+  // frame_buffers[frame_idx] of create_pyramid. Waits for earlier compute and fragment reads of
+  // the pyramid, and makes the new levels visible to later ones, the next frame's included
+  pub fn build(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    pyramid: &HiZPyramid,
+    frame_idx: usize,
+    frame_buffer: &AdFrameBuffer,
+  ) -> Result<(), String> {
+    let Some(depth_dset) = pyramid.depth_dsets.get(frame_idx) else {
+      return Err(format!("no depth pyramid set for frame {frame_idx}"));
+    };
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+        | vk::PipelineStageFlags::COMPUTE_SHADER
+        | vk::PipelineStageFlags::FRAGMENT_SHADER,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[depth_barrier(
+        cmd_buffer,
+        frame_buffer,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+      )
+      .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+      .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+    );
+
+    let push = glam::uvec4(self.samples.as_raw(), 0, 0, 0);
+    let mut level_res = pyramid.resolution();
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.depth_pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.depth_pipeline.layout(),
+      &[depth_dset.inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.depth_pipeline.layout(),
+      vk::ShaderStageFlags::COMPUTE,
+      AdBuffer::get_byte_slice(&[push]),
+    );
+    cmd_buffer.dispatch(
+      level_res.width.div_ceil(PYRAMID_GROUP_SIZE),
+      level_res.height.div_ceil(PYRAMID_GROUP_SIZE),
+      1,
+    );
+
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.reduce_pipeline.inner());
+    cmd_buffer.set_push_constant_data(
+      self.reduce_pipeline.layout(),
+      vk::ShaderStageFlags::COMPUTE,
+      AdBuffer::get_byte_slice(&[push]),
+    );
+    for reduce_dset in pyramid.reduce_dsets.iter() {
+      cmd_buffer.pipeline_barrier(
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::DependencyFlags::empty(),
+        &[vk::MemoryBarrier::default()
+          .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+          .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+        &[],
+        &[],
+      );
+      level_res.width = (level_res.width / 2).max(1);
+      level_res.height = (level_res.height / 2).max(1);
+      cmd_buffer.bind_descriptor_sets(
+        vk::PipelineBindPoint::COMPUTE,
+        self.reduce_pipeline.layout(),
+        &[reduce_dset.inner()],
+      );
+      cmd_buffer.dispatch(
+        level_res.width.div_ceil(PYRAMID_GROUP_SIZE),
+        level_res.height.div_ceil(PYRAMID_GROUP_SIZE),
+        1,
+      );
+    }
+
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::COMPUTE_SHADER
+        | vk::PipelineStageFlags::FRAGMENT_SHADER
+        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+      &[],
+      &[depth_barrier(
+        cmd_buffer,
+        frame_buffer,
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+      )
+      .src_access_mask(vk::AccessFlags::NONE)
+      .dst_access_mask(
+        vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+          | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+      )],
+    );
+    Ok(())
+  }
+}
@@ -1 +1,32 @@
+pub mod cloth_renderers;
+pub mod color_renderers;
+pub mod crowd_renderers;
+pub mod cull_renderers;
+pub mod debug_renderers;
+pub mod depth_of_field_renderers;
+pub mod depth_readback_renderers;
+pub mod editor_renderers;
+pub mod environment_renderers;
+pub mod exposure_renderers;
+pub mod film_effects_renderers;
+pub mod foliage_renderers;
+pub mod hiz_renderers;
+pub mod particle_renderers;
+pub mod post_renderers;
+pub mod reflection_probe_renderers;
+pub mod render_targets;
+#[cfg(feature = "ray-tracing")]
+pub mod rt_shadow_renderers;
+pub mod sdf_renderers;
+pub mod shader_preprocessor;
+pub mod shadow_atlas;
+pub mod shadow_renderers;
+pub mod skinning_renderers;
+pub mod taa_renderers;
+pub mod transient_images;
 pub mod triangle_mesh_renderers;
+pub mod ui_renderers;
+pub mod velocity_renderers;
+pub mod water_renderers;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
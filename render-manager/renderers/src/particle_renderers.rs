@@ -0,0 +1,341 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdComputePipeline, AdFrameBuffer, AdPipeline, AdRenderPass},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{
+  particles::{ParticleSimParams, ParticleSystemGPU, ParticleSystemGenerator},
+  Camera3D,
+};
+
+use crate::render_targets::depth_barrier;
+
+static PARTICLE_SIM_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/particle_sim.comp.spv");
+static PARTICLE_SIM_MS_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/particle_sim_ms.comp.spv");
+static PARTICLE_VERT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/particle.vert.spv");
+static PARTICLE_FRAG_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/particle.frag.spv");
+
+const SIM_GROUP_SIZE: u32 = 64;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct ParticleSimPushConstants {
+  camera: Camera3D,
+  sim: ParticleSimParams,
+}
+
+// Simulates particles in a compute pass that reads the scene depth for collisions, then draws
+// them as camera facing quads over the output of TriMeshMaterialRenderer. Has to run before the
+// editor overlay, which doesn't keep the depth buffer around
+pub struct ParticleRenderer {
+  sim_pipeline: AdComputePipeline,
+  draw_pipeline: AdPipeline,
+  render_pass: Arc<AdRenderPass>,
+  depth_dset_layout: Arc<AdDescriptorSetLayout>,
+  depth_dset_pool: Arc<AdDescriptorPool>,
+}
+
+impl ParticleRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    particle_gen: &ParticleSystemGenerator,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+  ) -> Result<Self, String> {
+    // Same attachments as the tri renderer framebuffers, keeping depth and msaa color for the
+    // editor overlay drawn after
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+    let mut attachments = vec![
+      vk::AttachmentDescription::default()
+        .format(color_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .initial_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .final_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .load_op(match multisampled {
+          true => vk::AttachmentLoadOp::DONT_CARE,
+          false => vk::AttachmentLoadOp::LOAD,
+        })
+        .store_op(vk::AttachmentStoreOp::STORE),
+      vk::AttachmentDescription::default()
+        .format(depth_format)
+        .samples(samples)
+        .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::STORE),
+    ];
+    if multisampled {
+      attachments.push(
+        vk::AttachmentDescription::default()
+          .format(color_format)
+          .samples(samples)
+          .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+          .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+          .load_op(vk::AttachmentLoadOp::LOAD)
+          .store_op(vk::AttachmentStoreOp::STORE),
+      );
+    }
+    let output_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(0)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let msaa_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(2)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let depth_attachment_ref = vk::AttachmentReference::default()
+      .attachment(1)
+      .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let subpass = match multisampled {
+      true => vk::SubpassDescription::default()
+        .color_attachments(&msaa_attachment_ref)
+        .resolve_attachments(&output_attachment_ref),
+      false => vk::SubpassDescription::default().color_attachments(&output_attachment_ref),
+    };
+    let render_pass = AdRenderPass::new(
+      ash_device.clone(),
+      vk::RenderPassCreateFlags::default(),
+      &attachments,
+      &[subpass
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(&depth_attachment_ref)],
+      &[
+        vk::SubpassDependency::default()
+          .src_subpass(vk::SUBPASS_EXTERNAL)
+          .dst_subpass(0)
+          .src_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+          )
+          .dst_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+          )
+          .src_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+          )
+          .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_READ
+              | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+          ),
+        vk::SubpassDependency::default()
+          .src_subpass(0)
+          .dst_subpass(vk::SUBPASS_EXTERNAL)
+          .src_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+          )
+          .dst_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+              | vk::PipelineStageFlags::TRANSFER,
+          )
+          .src_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+          )
+          .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_READ
+              | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+              | vk::AccessFlags::TRANSFER_READ,
+          ),
+      ],
+    )?;
+    let render_pass = Arc::new(render_pass);
+
+    let depth_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[(vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::SAMPLED_IMAGE)],
+    )?);
+    // One set per frame in flight, replaced whenever the framebuffers are
+    let depth_dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      16,
+      &[vk::DescriptorPoolSize { ty: vk::DescriptorType::SAMPLED_IMAGE, descriptor_count: 16 }],
+    )?);
+
+    let sim_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      match multisampled {
+        true => PARTICLE_SIM_MS_SHADER_CODE,
+        false => PARTICLE_SIM_SHADER_CODE,
+      },
+      &[particle_gen.particle_dset_layout(), &depth_dset_layout],
+      std::mem::size_of::<ParticleSimPushConstants>() as u32,
+    )?;
+
+    let draw_pipeline = AdPipeline::new(
+      render_pass.clone(),
+      0,
+      HashMap::from([
+        (vk::ShaderStageFlags::VERTEX, PARTICLE_VERT_SHADER_CODE),
+        (vk::ShaderStageFlags::FRAGMENT, PARTICLE_FRAG_SHADER_CODE),
+      ]),
+      &[particle_gen.particle_dset_layout()],
+      (vk::ShaderStageFlags::VERTEX, std::mem::size_of::<Camera3D>() as u32),
+      vk::PipelineRasterizationStateCreateInfo::default()
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0),
+      // Additive so overlapping sparks don't need sorting
+      &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
+        vk::PipelineColorBlendAttachmentState::default()
+          .color_write_mask(vk::ColorComponentFlags::RGBA)
+          .blend_enable(true)
+          .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+          .dst_color_blend_factor(vk::BlendFactor::ONE)
+          .color_blend_op(vk::BlendOp::ADD)
+          .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+          .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+          .alpha_blend_op(vk::BlendOp::ADD),
+      ]),
+      &vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL),
+      samples,
+    )?;
+
+    Ok(Self { sim_pipeline, draw_pipeline, render_pass, depth_dset_layout, depth_dset_pool })
+  }
+
+  pub fn create_depth_dsets(
+    &self,
+    frame_buffers: &[Arc<AdFrameBuffer>],
+  ) -> Result<Vec<AdDescriptorSet>, String> {
+    AdDescriptorSet::new(
+      self.depth_dset_pool.clone(),
+      &frame_buffers
+        .iter()
+        .map(|fb| {
+          (
+            self.depth_dset_layout.clone(),
+            vec![AdDescriptorBinding::Image2D((
+              fb.attachments()[1].clone(),
+              vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ))],
+          )
+        })
+        .collect::<Vec<_>>(),
+    )
+  }
+
+  // Must be recorded outside a render pass, after the scene geometry is drawn into frame_buffer
+  pub fn simulate(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    depth_dset: &AdDescriptorSet,
+    camera: Camera3D,
+    systems: &[(Arc<ParticleSystemGPU>, ParticleSimParams)],
+  ) {
+    // Previous frame's draw may still read the particle buffers being rewritten
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::VERTEX_SHADER,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[depth_barrier(
+        cmd_buffer,
+        frame_buffer,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+      )
+      .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+      .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+    );
+
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.sim_pipeline.inner());
+    for (system, sim) in systems.iter() {
+      cmd_buffer.bind_descriptor_sets(
+        vk::PipelineBindPoint::COMPUTE,
+        self.sim_pipeline.layout(),
+        &[system.dset().inner(), depth_dset.inner()],
+      );
+      cmd_buffer.set_push_constant_data(
+        self.sim_pipeline.layout(),
+        vk::ShaderStageFlags::COMPUTE,
+        AdBuffer::get_byte_slice(&[ParticleSimPushConstants { camera, sim: *sim }]),
+      );
+      cmd_buffer.dispatch(system.capacity().div_ceil(SIM_GROUP_SIZE), 1, 1);
+    }
+
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+      &[],
+      &[depth_barrier(
+        cmd_buffer,
+        frame_buffer,
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+      )
+      .src_access_mask(vk::AccessFlags::NONE)
+      .dst_access_mask(
+        vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+          | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+      )],
+    );
+  }
+
+  pub fn render(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+    systems: &[Arc<ParticleSystemGPU>],
+  ) {
+    cmd_buffer.begin_render_pass(
+      self.render_pass.inner(),
+      frame_buffer.inner(),
+      vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: frame_buffer.resolution() },
+      &[],
+      vk::SubpassContents::INLINE,
+    );
+    cmd_buffer.set_view_port(&[vk::Viewport {
+      x: 0.0,
+      y: 0.0,
+      width: frame_buffer.resolution().width as f32,
+      height: frame_buffer.resolution().height as f32,
+      min_depth: 0.0,
+      max_depth: 1.0,
+    }]);
+    cmd_buffer.set_scissor(&[vk::Rect2D {
+      offset: vk::Offset2D { x: 0, y: 0 },
+      extent: frame_buffer.resolution(),
+    }]);
+
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, self.draw_pipeline.inner());
+    for system in systems.iter() {
+      cmd_buffer.bind_descriptor_sets(
+        vk::PipelineBindPoint::GRAPHICS,
+        self.draw_pipeline.layout(),
+        &[system.dset().inner()],
+      );
+      cmd_buffer.set_push_constant_data(
+        self.draw_pipeline.layout(),
+        vk::ShaderStageFlags::VERTEX,
+        AdBuffer::get_byte_slice(&[camera]),
+      );
+      // Two triangles per particle, dead ones are collapsed in the vertex shader
+      cmd_buffer.draw(system.capacity() * 6);
+    }
+    cmd_buffer.end_render_pass();
+  }
+}
@@ -0,0 +1,274 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdImage, AdImageView, AdSampler,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdComputePipeline, AdFrameBuffer},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::glam;
+
+use crate::{
+  render_targets::{color_range, reallocate_dsets},
+  transient_images::{TransientImageDesc, TransientImages},
+  velocity_renderers::VelocityRenderer,
+};
+
+static MOTION_BLUR_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/motion_blur.comp.spv");
+
+pub const POST_GROUP_SIZE: u32 = 8;
+// Intermediate the post passes write before it's copied over the scene color
+pub const POST_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+// Longest blur in pixels, faster motion is blurred as much as this
+pub const MAX_BLUR_PIXELS: f32 = 32.0;
+// Samples along the longest blur, shorter ones take one per pixel
+pub const MAX_BLUR_SAMPLES: u32 = 16;
+const MAX_MOTION_BLUR_SETS: u32 = 16;
+const MOTION_BLUR_IMAGE: &str = "motion_blur_image";
+
+// Scene color from TRANSFER_SRC_OPTIMAL, where it's kept between passes, to GENERAL for a compute
+// pass to sample
+pub fn begin_scene_color_read(cmd_buffer: &AdCommandBuffer, scene_frame_buffer: &AdFrameBuffer) {
+  scene_frame_buffer.attachments()[0].transition_to_general(
+    cmd_buffer,
+    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+    vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::TRANSFER_WRITE,
+  );
+}
+
+// Copies a full size image in GENERAL written by a compute pass over the scene color, which is in
+// GENERAL from begin_scene_color_read, and puts the scene color back in TRANSFER_SRC_OPTIMAL
+pub fn copy_into_scene_color(
+  cmd_buffer: &AdCommandBuffer,
+  image: &AdImage,
+  scene_frame_buffer: &AdFrameBuffer,
+) {
+  let queue_family = cmd_buffer.cmd_pool().queue().family_index();
+  let color_image = scene_frame_buffer.attachments()[0].image();
+  cmd_buffer.pipeline_barrier(
+    vk::PipelineStageFlags::COMPUTE_SHADER,
+    vk::PipelineStageFlags::TRANSFER,
+    vk::DependencyFlags::empty(),
+    &[vk::MemoryBarrier::default()
+      .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+      .dst_access_mask(vk::AccessFlags::TRANSFER_READ)],
+    &[],
+    &[vk::ImageMemoryBarrier::default()
+      .image(color_image.inner())
+      .subresource_range(color_range())
+      .src_queue_family_index(queue_family)
+      .dst_queue_family_index(queue_family)
+      .src_access_mask(vk::AccessFlags::SHADER_READ)
+      .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+      .old_layout(vk::ImageLayout::GENERAL)
+      .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)],
+  );
+  let color_layers = vk::ImageSubresourceLayers::default()
+    .aspect_mask(vk::ImageAspectFlags::COLOR)
+    .mip_level(0)
+    .base_array_layer(0)
+    .layer_count(1);
+  cmd_buffer.blit_image(
+    image.inner(),
+    vk::ImageLayout::GENERAL,
+    color_image.inner(),
+    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+    &[vk::ImageBlit::default()
+      .src_subresource(color_layers)
+      .src_offsets(image.full_range_offset_3d())
+      .dst_subresource(color_layers)
+      .dst_offsets(color_image.full_range_offset_3d())],
+    vk::Filter::NEAREST,
+  );
+  // Later post passes and overlays use the result, then it is encoded or blitted to the swapchain
+  cmd_buffer.pipeline_barrier(
+    vk::PipelineStageFlags::TRANSFER,
+    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+      | vk::PipelineStageFlags::COMPUTE_SHADER
+      | vk::PipelineStageFlags::TRANSFER,
+    vk::DependencyFlags::empty(),
+    &[],
+    &[],
+    &[vk::ImageMemoryBarrier::default()
+      .image(color_image.inner())
+      .subresource_range(color_range())
+      .src_queue_family_index(queue_family)
+      .dst_queue_family_index(queue_family)
+      .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+      .dst_access_mask(
+        vk::AccessFlags::COLOR_ATTACHMENT_READ
+          | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+          | vk::AccessFlags::SHADER_READ
+          | vk::AccessFlags::SHADER_WRITE
+          | vk::AccessFlags::TRANSFER_READ,
+      )
+      .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+      .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)],
+  );
+}
+
+// Blurs the scene color along the velocity of each pixel, see motion_blur.comp. The scene color
+// images need SAMPLED and TRANSFER_DST usage
+pub struct MotionBlurRenderer {
+  pipeline: AdComputePipeline,
+  dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  sampler: Arc<AdSampler>,
+  // Shared by the frames in flight, the queue runs their blurs one after another
+  output_view: Option<Arc<AdImageView>>,
+  // One per frame in flight
+  dsets: Vec<AdDescriptorSet>,
+}
+
+impl MotionBlurRenderer {
+  pub fn new(ash_device: Arc<AdAshDevice>) -> Result<Self, String> {
+    let dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_IMAGE),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::UNIFORM_BUFFER),
+      ],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_MOTION_BLUR_SETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+          descriptor_count: MAX_MOTION_BLUR_SETS * 3,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_IMAGE,
+          descriptor_count: MAX_MOTION_BLUR_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::UNIFORM_BUFFER,
+          descriptor_count: MAX_MOTION_BLUR_SETS,
+        },
+      ],
+    )?);
+    let pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      MOTION_BLUR_SHADER_CODE,
+      &[&dset_layout],
+      std::mem::size_of::<glam::Vec4>() as u32,
+    )?;
+    let sampler = Arc::new(AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    )?);
+    Ok(Self { pipeline, dset_layout, dset_pool, sampler, output_view: None, dsets: vec![] })
+  }
+
+  // Its output, only used while the blur is applied in pass
+  pub fn transient_images(pass: u32) -> Vec<TransientImageDesc> {
+    let usage = vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC;
+    vec![TransientImageDesc::in_pass(MOTION_BLUR_IMAGE, POST_FORMAT, usage, pass)]
+  }
+
+  // After velocity_renderer's targets are resized for the same framebuffers, with transient_images
+  // made for them
+  pub fn resize_targets(
+    &mut self,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+    velocity_renderer: &VelocityRenderer,
+    transient_images: &TransientImages,
+  ) -> Result<(), String> {
+    let output_view = AdImageView::create_view(
+      transient_images.get(MOTION_BLUR_IMAGE)?,
+      vk::ImageViewType::TYPE_2D,
+      color_range(),
+    )?;
+    reallocate_dsets(
+      &mut self.dsets,
+      &self.dset_pool,
+      &self.dset_layout,
+      scene_frame_buffers.iter().enumerate().map(|(frame_idx, fb)| {
+        vec![
+          AdDescriptorBinding::Sampler2D((
+            fb.attachments()[0].clone(),
+            vk::ImageLayout::GENERAL,
+            self.sampler.clone(),
+          )),
+          AdDescriptorBinding::Sampler2D((
+            fb.attachments()[1].clone(),
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            self.sampler.clone(),
+          )),
+          AdDescriptorBinding::Sampler2D((
+            velocity_renderer.velocity_view(frame_idx).clone(),
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            self.sampler.clone(),
+          )),
+          AdDescriptorBinding::StorageImage((output_view.clone(), vk::ImageLayout::GENERAL)),
+          AdDescriptorBinding::UniformBuffer(
+            velocity_renderer.frame_data_buffer(frame_idx).clone(),
+          ),
+        ]
+      }),
+    )?;
+    self.output_view = Some(output_view);
+    Ok(())
+  }
+
+  // Between the velocity render and finish of frame_idx, with the scene color in
+  // TRANSFER_SRC_OPTIMAL, where it's left. intensity scales the velocity, blurs longer than
+  // MAX_BLUR_PIXELS are shortened to it
+  pub fn apply(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+    intensity: f32,
+  ) -> Result<(), String> {
+    let (Some(output_view), Some(dset)) = (&self.output_view, self.dsets.get(frame_idx)) else {
+      return Err(format!("no motion blur targets for frame {frame_idx}"));
+    };
+    begin_scene_color_read(cmd_buffer, scene_frame_buffer);
+    // Last frame's copy may still be reading the output being rewritten
+    output_view.transition_to_general(
+      cmd_buffer,
+      vk::ImageLayout::UNDEFINED,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::AccessFlags::NONE,
+    );
+    let resolution = scene_frame_buffer.resolution();
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.pipeline.layout(),
+      &[dset.inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.pipeline.layout(),
+      vk::ShaderStageFlags::COMPUTE,
+      AdBuffer::get_byte_slice(&[glam::vec4(
+        intensity,
+        MAX_BLUR_PIXELS,
+        MAX_BLUR_SAMPLES as f32,
+        0.0,
+      )]),
+    );
+    cmd_buffer.dispatch(
+      resolution.width.div_ceil(POST_GROUP_SIZE),
+      resolution.height.div_ceil(POST_GROUP_SIZE),
+      1,
+    );
+    copy_into_scene_color(cmd_buffer, output_view.image(), scene_frame_buffer);
+    Ok(())
+  }
+}
@@ -0,0 +1,332 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorSet, AdImage, AdImageView, AdSampler,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::AdFrameBuffer,
+  ash_sync_wrappers::AdFence,
+};
+use renderables::{glam, reflection_probe::ReflectionProbe};
+
+use crate::{
+  environment_renderers::{EnvironmentBindings, EnvironmentGenerator},
+  triangle_mesh_renderers::{TriMeshDraw, TriMeshMaterialRenderer},
+};
+
+// MAX_REFLECTION_PROBES in common_structs.glsl has to match
+pub const MAX_REFLECTION_PROBES: usize = 8;
+pub const PROBE_FACE_SIZE: u32 = 128;
+// Down to 4x4 faces, the last level is fully rough
+const PROBE_ROUGHNESS_LEVELS: u32 = 6;
+const PROBE_NEAR: f32 = 0.05;
+const PROBE_FAR: f32 = 500.0;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ReflectionProbeData {
+  position: glam::Vec4,
+  box_min: glam::Vec4,
+  box_max: glam::Vec4,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ReflectionProbesData {
+  params: glam::Vec4,
+  probes: [ReflectionProbeData; MAX_REFLECTION_PROBES],
+}
+
+impl ReflectionProbesData {
+  fn new(probes: &[ReflectionProbe]) -> Self {
+    let mut probes_data = [ReflectionProbeData {
+      position: glam::Vec4::ZERO,
+      box_min: glam::Vec4::ZERO,
+      box_max: glam::Vec4::ZERO,
+    }; MAX_REFLECTION_PROBES];
+    for (data, probe) in probes_data.iter_mut().zip(probes.iter()) {
+      *data = ReflectionProbeData {
+        position: probe.position.extend(probe.blend_distance),
+        box_min: probe.box_min.extend(0.0),
+        box_max: probe.box_max.extend(0.0),
+      };
+    }
+    Self {
+      params: glam::vec4(probes.len() as f32, (PROBE_ROUGHNESS_LEVELS - 1) as f32, 0.0, 0.0),
+      probes: probes_data,
+    }
+  }
+}
+
+// Cubemaps captured from the scene's reflection probes, read by lit and lightmapped materials
+// through the shadow set. Probe i is cube i of one R16G16B16A16_SFLOAT cube array, always in the
+// GENERAL layout, with prefiltered levels for rougher materials like an EnvironmentMap
+#[derive(getset::Getters)]
+pub struct ReflectionProbeRenderer {
+  image: Arc<AdImage>,
+  // Every cube and level, for textureLod with the level of a roughness
+  view: Arc<AdImageView>,
+  sampler: Arc<AdSampler>,
+  probe_buffers: Vec<Arc<AdBuffer>>,
+  // For the sets that shouldn't reflect anything
+  no_probe_buffer: Arc<AdBuffer>,
+  // Per cube, the prefilter bindings of each level after the first
+  prefilter_bindings: Vec<EnvironmentBindings>,
+  // Smallest first, so they take precedence over the probes around them
+  #[getset(get = "pub")]
+  probes: Vec<ReflectionProbe>,
+}
+
+impl ReflectionProbeRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_buffer: &AdCommandBuffer,
+    environment_gen: &EnvironmentGenerator,
+    frames_in_flight: usize,
+  ) -> Result<Self, String> {
+    let image = AdImage::new_cube_array(
+      ash_device.clone(),
+      allocator.clone(),
+      MemoryLocation::GpuOnly,
+      "reflection_probes",
+      vk::Format::R16G16B16A16_SFLOAT,
+      PROBE_FACE_SIZE,
+      vk::ImageUsageFlags::STORAGE
+        | vk::ImageUsageFlags::SAMPLED
+        | vk::ImageUsageFlags::TRANSFER_DST,
+      PROBE_ROUGHNESS_LEVELS,
+      MAX_REFLECTION_PROBES as u32,
+    )?;
+    let range = |base_mip_level: u32, level_count: u32, base_array_layer: u32, layer_count: u32| {
+      vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(base_mip_level)
+        .level_count(level_count)
+        .base_array_layer(base_array_layer)
+        .layer_count(layer_count)
+    };
+    let layer_count = 6 * MAX_REFLECTION_PROBES as u32;
+    let view = AdImageView::create_view(
+      image.clone(),
+      vk::ImageViewType::CUBE_ARRAY,
+      range(0, PROBE_ROUGHNESS_LEVELS, 0, layer_count),
+    )?;
+    let prefilter_bindings = (0..MAX_REFLECTION_PROBES as u32)
+      .map(|cube| {
+        let first_level_view = AdImageView::create_view(
+          image.clone(),
+          vk::ImageViewType::CUBE,
+          range(0, 1, 6 * cube, 6),
+        )?;
+        let level_views = (1..PROBE_ROUGHNESS_LEVELS)
+          .map(|level| {
+            AdImageView::create_view(
+              image.clone(),
+              vk::ImageViewType::TYPE_2D_ARRAY,
+              range(level, 1, 6 * cube, 6),
+            )
+          })
+          .collect::<Result<Vec<_>, String>>()?;
+        environment_gen.prefilter_bindings(&first_level_view, &level_views)
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    let sampler = Arc::new(AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .max_lod(vk::LOD_CLAMP_NONE),
+    )?);
+
+    let probes_buffer = |name: &str| {
+      AdBuffer::new(
+        ash_device.clone(),
+        allocator.clone(),
+        MemoryLocation::CpuToGpu,
+        name,
+        vk::BufferCreateFlags::empty(),
+        std::mem::size_of::<ReflectionProbesData>() as u64,
+        vk::BufferUsageFlags::UNIFORM_BUFFER,
+      )
+      .map(Arc::new)
+    };
+    let probe_buffers = (0..frames_in_flight)
+      .map(|i| probes_buffer(&format!("reflection_probe_data_{i}")))
+      .collect::<Result<Vec<_>, String>>()?;
+    let no_probe_buffer = probes_buffer("reflection_probe_data_none")?;
+    for buffer in probe_buffers.iter().chain(std::iter::once(&no_probe_buffer)) {
+      buffer.write_data(0, &[ReflectionProbesData::new(&[])])?;
+    }
+
+    // Black until captured
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::default())?;
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TOP_OF_PIPE,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[view
+        .layout_barrier(cmd_buffer, vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL)
+        .src_access_mask(vk::AccessFlags::NONE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)],
+    );
+    cmd_buffer.clear_color_image(
+      image.inner(),
+      vk::ImageLayout::GENERAL,
+      &vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] },
+      &[view.subresource_range()],
+    );
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)],
+      &[],
+      &[],
+    );
+    cmd_buffer.end()?;
+    let fence = AdFence::new(ash_device, vk::FenceCreateFlags::empty())?;
+    cmd_buffer.submit(&[], &[], Some(&fence))?;
+    fence.wait(999999999)?;
+
+    Ok(Self {
+      image,
+      view,
+      sampler,
+      probe_buffers,
+      no_probe_buffer,
+      prefilter_bindings,
+      probes: vec![],
+    })
+  }
+
+  // Bindings 6 to 8 of a shadow set, the frame's probes or none at all
+  pub fn dset_bindings(&self, frame_idx: Option<usize>) -> Vec<AdDescriptorBinding> {
+    let buffer = match frame_idx {
+      Some(frame_idx) => self.probe_buffers[frame_idx].clone(),
+      None => self.no_probe_buffer.clone(),
+    };
+    vec![
+      AdDescriptorBinding::UniformBuffer(buffer),
+      AdDescriptorBinding::Image2D((self.view.clone(), vk::ImageLayout::GENERAL)),
+      AdDescriptorBinding::Sampler(self.sampler.clone()),
+    ]
+  }
+
+  // Sorted smallest first. They reflect whatever their cubes had until captured
+  pub fn set_probes(&mut self, probes: &[ReflectionProbe]) -> Result<(), String> {
+    if probes.len() > MAX_REFLECTION_PROBES {
+      return Err(format!("{} reflection probes, at most {MAX_REFLECTION_PROBES}", probes.len()));
+    }
+    let mut probes = probes.to_vec();
+    probes.sort_by(|a, b| a.volume().total_cmp(&b.volume()));
+    self.probes = probes;
+    Ok(())
+  }
+
+  pub fn update(&self, frame_idx: usize) -> Result<(), String> {
+    self.probe_buffers[frame_idx].write_data(0, &[ReflectionProbesData::new(&self.probes)])
+  }
+
+  // Draws the batches around every probe into its cube and prefilters it. frame_buffer is a
+  // PROBE_FACE_SIZE square one from tri_mesh_renderer. The faces are drawn with the probes as
+  // they were before, so capturing again adds another bounce of reflections
+  #[allow(clippy::too_many_arguments)]
+  pub fn capture(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    tri_mesh_renderer: &TriMeshMaterialRenderer,
+    shadow_dset: &AdDescriptorSet,
+    batches: &[TriMeshDraw],
+    environment_gen: &EnvironmentGenerator,
+  ) -> Result<(), String> {
+    let face_image = frame_buffer.attachments()[0].image();
+    let queue_family = cmd_buffer.cmd_pool().queue().family_index();
+    for (cube, probe) in self.probes.iter().enumerate() {
+      let cube_range = vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(PROBE_ROUGHNESS_LEVELS)
+        .base_array_layer(6 * cube as u32)
+        .layer_count(6);
+      // Earlier probes' faces and this frame's materials may still be reading the cube
+      cmd_buffer.pipeline_barrier(
+        vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &[vk::ImageMemoryBarrier::default()
+          .image(self.image.inner())
+          .subresource_range(cube_range)
+          .src_queue_family_index(queue_family)
+          .dst_queue_family_index(queue_family)
+          .src_access_mask(vk::AccessFlags::SHADER_READ)
+          .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+          .old_layout(vk::ImageLayout::GENERAL)
+          .new_layout(vk::ImageLayout::GENERAL)],
+      );
+      for (face, camera) in probe.face_cameras(PROBE_NEAR, PROBE_FAR).into_iter().enumerate() {
+        tri_mesh_renderer.render(cmd_buffer, frame_buffer, camera, shadow_dset, batches, None)?;
+        let [start, end] = face_image.full_range_offset_3d();
+        cmd_buffer.blit_image(
+          face_image.inner(),
+          vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+          self.image.inner(),
+          vk::ImageLayout::GENERAL,
+          &[vk::ImageBlit::default()
+            .src_subresource(
+              vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(0)
+                .base_array_layer(0)
+                .layer_count(1),
+            )
+            .src_offsets([start, end])
+            .dst_subresource(
+              vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(0)
+                .base_array_layer(6 * cube as u32 + face as u32)
+                .layer_count(1),
+            )
+            // The cameras draw the faces mirrored
+            .dst_offsets([
+              vk::Offset3D { x: end.x, y: start.y, z: start.z },
+              vk::Offset3D { x: start.x, y: end.y, z: end.z },
+            ])],
+          vk::Filter::NEAREST,
+        );
+      }
+      cmd_buffer.pipeline_barrier(
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::DependencyFlags::empty(),
+        &[vk::MemoryBarrier::default()
+          .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+          .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)],
+        &[],
+        &[],
+      );
+      environment_gen.record_prefilter(
+        cmd_buffer,
+        &self.prefilter_bindings[cube],
+        PROBE_FACE_SIZE,
+      )?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{
+  ash_context::ash::vk,
+  ash_data_wrappers::{
+    AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::AdFrameBuffer,
+};
+
+// The single mip and layer of the scene color and the post targets
+pub fn color_range() -> vk::ImageSubresourceRange {
+  vk::ImageSubresourceRange::default()
+    .aspect_mask(vk::ImageAspectFlags::COLOR)
+    .base_mip_level(0)
+    .level_count(1)
+    .base_array_layer(0)
+    .layer_count(1)
+}
+
+// Replaces dsets with one set of dset_layout per list of bindings, usually one per framebuffer.
+// Renderers reading the scene targets rebind them through this on every resize, so their pools
+// only need room for the sets of the frames in flight. The caller has waited for those frames
+pub fn reallocate_dsets(
+  dsets: &mut Vec<AdDescriptorSet>,
+  dset_pool: &Arc<AdDescriptorPool>,
+  dset_layout: &Arc<AdDescriptorSetLayout>,
+  bindings: impl IntoIterator<Item = Vec<AdDescriptorBinding>>,
+) -> Result<(), String> {
+  let desc_data = bindings.into_iter().map(|x| (dset_layout.clone(), x)).collect::<Vec<_>>();
+  // The old sets go back to the pool before the new ones are taken
+  dsets.clear();
+  *dsets = AdDescriptorSet::new(dset_pool.clone(), &desc_data)?;
+  Ok(())
+}
+
+// Transitions the depth attachment of a scene framebuffer, for the passes sampling it
+pub fn depth_barrier(
+  cmd_buffer: &AdCommandBuffer,
+  frame_buffer: &AdFrameBuffer,
+  old_layout: vk::ImageLayout,
+  new_layout: vk::ImageLayout,
+) -> vk::ImageMemoryBarrier<'static> {
+  let depth_img = frame_buffer.attachments()[1].image();
+  vk::ImageMemoryBarrier::default()
+    .image(depth_img.inner())
+    .subresource_range(
+      vk::ImageSubresourceRange::default()
+        .aspect_mask(depth_img.possible_image_aspect())
+        .layer_count(1)
+        .base_array_layer(0)
+        .level_count(1)
+        .base_mip_level(0),
+    )
+    .src_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+    .dst_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+    .old_layout(old_layout)
+    .new_layout(new_layout)
+}
@@ -0,0 +1,284 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, gpu_allocator::vulkan::Allocator, AdAshDevice},
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdSampler,
+  },
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool, AdQueue},
+  ash_rt_wrappers::{
+    AdAccelerationStructure, AdHitGroup, AdRayTracingDevice, AdRayTracingPipeline,
+    AdRayTracingShaders, AdTlasInstance,
+  },
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{glam, triangle_mesh::TriMeshCPU, Camera3D};
+
+use crate::shadow_renderers::ShadowRenderer;
+
+static SHADOW_TRACE_RGEN_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/shadow_trace.rgen.spv");
+static SHADOW_TRACE_RMISS_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/shadow_trace.rmiss.spv");
+
+// Past the camera far plane, anything further can't shadow what's on screen
+const MAX_TRACE_DISTANCE: f32 = 1000.0;
+// Along the sun direction, keeps rays from hitting the surface they start on
+const RAY_ORIGIN_OFFSET: f32 = 0.05;
+// Per frame sets, each resize makes a new batch of target sets
+const MAX_TRACE_SETS: u32 = 64;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ShadowTrace {
+  inv_view_proj: glam::Mat4,
+  sun_dir: glam::Vec4,
+  params: glam::Vec4,
+}
+
+// Traces a ray to the sun from every pixel of ShadowRenderer's depth prepass into its mask. Meshes
+// need a bottom level structure from create_blas to be traced against, morph targets aren't
+// applied so they shadow in their base pose
+pub struct RtShadowRenderer {
+  rt_device: Arc<AdRayTracingDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  pipeline: AdRayTracingPipeline,
+  target_dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  sampler: Arc<AdSampler>,
+  // Per frame in flight, rebuilt every frame and grown when there are more casters
+  tlases: Vec<AdAccelerationStructure>,
+  tlas_dsets: Vec<AdDescriptorSet>,
+  // Prepass depth and mask per frame in flight
+  target_dsets: Vec<AdDescriptorSet>,
+  target_resolution: vk::Extent2D,
+  // Bottom level builds wait on their own buffer, off the per frame ones
+  blas_cmd_buffer: AdCommandBuffer,
+}
+
+impl RtShadowRenderer {
+  // queue needs compute support for the acceleration structure builds
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    queue: Arc<AdQueue>,
+    shadow_renderer: &ShadowRenderer,
+  ) -> Result<Self, String> {
+    let rt_device = Arc::new(AdRayTracingDevice::new(ash_device.clone())?);
+    let tlas_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[(vk::ShaderStageFlags::RAYGEN_KHR, vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)],
+    )?);
+    let target_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::RAYGEN_KHR, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::RAYGEN_KHR, vk::DescriptorType::SAMPLER),
+        (vk::ShaderStageFlags::RAYGEN_KHR, vk::DescriptorType::STORAGE_IMAGE),
+      ],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_TRACE_SETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+          descriptor_count: MAX_TRACE_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLED_IMAGE,
+          descriptor_count: MAX_TRACE_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLER,
+          descriptor_count: MAX_TRACE_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_IMAGE,
+          descriptor_count: MAX_TRACE_SETS,
+        },
+      ],
+    )?);
+    let sampler = Arc::new(AdSampler::new(ash_device.clone())?);
+    // Hits keep the payload at 0, the empty hit group is only there for the binding table
+    let pipeline = AdRayTracingPipeline::new(
+      rt_device.clone(),
+      allocator.clone(),
+      "shadow_trace",
+      &AdRayTracingShaders {
+        raygen: SHADOW_TRACE_RGEN_SHADER_CODE,
+        miss: vec![SHADOW_TRACE_RMISS_SHADER_CODE],
+        hit_groups: vec![AdHitGroup { closest_hit: None, any_hit: None }],
+      },
+      &[&tlas_dset_layout, &target_dset_layout],
+      std::mem::size_of::<ShadowTrace>() as u32,
+      1,
+    )?;
+
+    let frames_in_flight = shadow_renderer.mask_views().len();
+    let tlases = (0..frames_in_flight)
+      .map(|i| {
+        AdAccelerationStructure::new_tlas(
+          rt_device.clone(),
+          allocator.clone(),
+          &format!("shadow_tlas_{i}"),
+          1,
+        )
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    let tlas_dsets = AdDescriptorSet::new(
+      dset_pool.clone(),
+      &(0..frames_in_flight).map(|_| (tlas_dset_layout.clone(), vec![])).collect::<Vec<_>>(),
+    )?;
+    for (tlas, dset) in tlases.iter().zip(tlas_dsets.iter()) {
+      tlas.write_to_dset(dset, 0);
+    }
+
+    let blas_cmd_pool =
+      Arc::new(AdCommandPool::new(queue, vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)?);
+    let blas_cmd_buffer = AdCommandBuffer::new(blas_cmd_pool, vk::CommandBufferLevel::PRIMARY, 1)?
+      .pop()
+      .ok_or("no blas cmd buffer made")?;
+
+    let mut rt_shadow_renderer = Self {
+      rt_device,
+      allocator,
+      pipeline,
+      target_dset_layout,
+      dset_pool,
+      sampler,
+      tlases,
+      tlas_dsets,
+      target_dsets: vec![],
+      target_resolution: vk::Extent2D::default(),
+      blas_cmd_buffer,
+    };
+    rt_shadow_renderer.set_targets(shadow_renderer)?;
+    Ok(rt_shadow_renderer)
+  }
+
+  // Call again after ShadowRenderer::resize made new targets
+  pub fn set_targets(&mut self, shadow_renderer: &ShadowRenderer) -> Result<(), String> {
+    self.target_dsets = AdDescriptorSet::new(
+      self.dset_pool.clone(),
+      &shadow_renderer
+        .prepass_frame_buffers()
+        .iter()
+        .zip(shadow_renderer.mask_views().iter())
+        .map(|(prepass_fb, mask_view)| {
+          (
+            self.target_dset_layout.clone(),
+            vec![
+              AdDescriptorBinding::Image2D((
+                prepass_fb.attachments()[0].clone(),
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+              )),
+              AdDescriptorBinding::Sampler(self.sampler.clone()),
+              AdDescriptorBinding::StorageImage((mask_view.clone(), vk::ImageLayout::GENERAL)),
+            ],
+          )
+        })
+        .collect::<Vec<_>>(),
+    )?;
+    self.target_resolution =
+      shadow_renderer.prepass_frame_buffers().first().map(|fb| fb.resolution()).unwrap_or_default();
+    Ok(())
+  }
+
+  // Builds and waits, in the mesh's own space like the vertices the mesh pipelines read
+  pub fn create_blas(
+    &self,
+    name: &str,
+    mesh: &TriMeshCPU,
+  ) -> Result<AdAccelerationStructure, String> {
+    mesh.build_blas(self.rt_device.clone(), self.allocator.clone(), &self.blas_cmd_buffer, name)
+  }
+
+  // After ShadowRenderer::render drew the prepass for frame_idx, outside a render pass. The
+  // frame's previous submission has to be done, its top level structure is rebuilt in place.
+  // Leaves the mask ready for fragment shaders
+  pub fn trace(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    camera: Camera3D,
+    sun_dir: glam::Vec3,
+    casters: &[(&AdAccelerationStructure, glam::Mat4)],
+  ) -> Result<(), String> {
+    if casters.len() > self.tlases[frame_idx].max_instances() as usize {
+      let tlas = AdAccelerationStructure::new_tlas(
+        self.rt_device.clone(),
+        self.allocator.clone(),
+        &format!("shadow_tlas_{frame_idx}"),
+        (casters.len() as u32).next_power_of_two(),
+      )?;
+      tlas.write_to_dset(&self.tlas_dsets[frame_idx], 0);
+      self.tlases[frame_idx] = tlas;
+    }
+    let instances = casters
+      .iter()
+      .map(|(blas, transform)| {
+        // Rows of the top 3x4, glam matrices are column major
+        let rows = transform.transpose().to_cols_array();
+        AdTlasInstance {
+          blas,
+          transform: rows[..12].try_into().unwrap_or_default(),
+          custom_index: 0,
+          mask: 0xFF,
+          hit_group_offset: 0,
+          flags: vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE,
+        }
+      })
+      .collect::<Vec<_>>();
+    self.tlases[frame_idx].cmd_build_tlas(cmd_buffer, &instances)?;
+
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::FRAGMENT_SHADER,
+      vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)],
+      &[],
+      &[],
+    );
+    let trace_data = ShadowTrace {
+      inv_view_proj: camera.view_proj_mat.inverse(),
+      sun_dir: sun_dir.normalize_or(glam::Vec3::Y).extend(RAY_ORIGIN_OFFSET),
+      params: glam::vec4(MAX_TRACE_DISTANCE, 0.0, 0.0, 0.0),
+    };
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::RAY_TRACING_KHR, self.pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::RAY_TRACING_KHR,
+      self.pipeline.layout(),
+      &[self.tlas_dsets[frame_idx].inner(), self.target_dsets[frame_idx].inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.pipeline.layout(),
+      vk::ShaderStageFlags::RAYGEN_KHR
+        | vk::ShaderStageFlags::MISS_KHR
+        | vk::ShaderStageFlags::CLOSEST_HIT_KHR
+        | vk::ShaderStageFlags::ANY_HIT_KHR,
+      AdBuffer::get_byte_slice(&[trace_data]),
+    );
+    self.pipeline.cmd_trace_rays(
+      cmd_buffer,
+      self.target_resolution.width,
+      self.target_resolution.height,
+      1,
+    );
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR,
+      vk::PipelineStageFlags::FRAGMENT_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+      &[],
+      &[],
+    );
+    Ok(())
+  }
+}
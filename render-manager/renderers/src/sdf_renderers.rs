@@ -0,0 +1,338 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdSampler,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdFrameBuffer, AdPipeline, AdRenderPass},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{color::Color, glam, sdf_font::SdfFontGPU};
+
+static SDF_SHAPE_VERT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/sdf_shape.vert.spv");
+static SDF_SHAPE_FRAG_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/sdf_shape.frag.spv");
+
+const MAX_SDF_SHAPES: usize = 16384;
+// Pixels quads are grown by on every side so edges have room to fade out, matches the shader
+const EDGE_PADDING: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SdfShapeKind {
+  RoundedRect = 0,
+  Line = 1,
+  Glyph = 2,
+}
+
+// What the shape shader draws, in pixels of the target with y pointing down. Made with the
+// constructors below, which grow the quad for the edges
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct SdfShape {
+  // left, top, width, height of the quad drawn
+  pub rect: glam::Vec4,
+  pub color: Color,
+  // Corner radius and outline width for rects, start and end for lines, atlas uvs for glyphs
+  pub params: glam::Vec4,
+  pub kind: SdfShapeKind,
+  // Outline width for rects, 0 when filled, and the width of lines
+  pub thickness: f32,
+  // Pixels the glyph atlas's whole 0..1 covers at the size drawn, see SdfFontCPU::distance_range
+  pub distance_range: f32,
+  pub pad: f32,
+}
+
+impl SdfShape {
+  // rect is left, top, width, height. Radius is cut down to fit, a square with a radius of half
+  // its side is a circle
+  pub fn rounded_rect(rect: glam::Vec4, radius: f32, outline: f32, color: Color) -> Self {
+    Self {
+      rect: rect - glam::vec4(EDGE_PADDING, EDGE_PADDING, -2.0 * EDGE_PADDING, -2.0 * EDGE_PADDING),
+      color,
+      params: glam::vec4(radius.max(0.0), 0.0, 0.0, 0.0),
+      kind: SdfShapeKind::RoundedRect,
+      thickness: outline.max(0.0),
+      distance_range: 0.0,
+      pad: 0.0,
+    }
+  }
+
+  pub fn circle(center: glam::Vec2, radius: f32, outline: f32, color: Color) -> Self {
+    let rect = (center - radius).extend(2.0 * radius).extend(2.0 * radius);
+    Self::rounded_rect(rect, radius, outline, color)
+  }
+
+  // Round capped
+  pub fn line(start: glam::Vec2, end: glam::Vec2, thickness: f32, color: Color) -> Self {
+    let reach = thickness * 0.5 + EDGE_PADDING;
+    let top_left = start.min(end) - reach;
+    let size = (start - end).abs() + 2.0 * reach;
+    Self {
+      rect: top_left.extend(size.x).extend(size.y),
+      color,
+      params: start.extend(end.x).extend(end.y),
+      kind: SdfShapeKind::Line,
+      thickness,
+      distance_range: 0.0,
+      pad: 0.0,
+    }
+  }
+
+  // rect and uv from SdfFontCPU::layout, the atlas padding already leaves room for the edges
+  pub fn glyph(rect: glam::Vec4, uv: glam::Vec4, distance_range: f32, color: Color) -> Self {
+    Self {
+      rect,
+      color,
+      params: uv,
+      kind: SdfShapeKind::Glyph,
+      thickness: 0.0,
+      distance_range,
+      pad: 0.0,
+    }
+  }
+}
+
+// Draws rounded rects, circles, lines and text from signed distances worked out per pixel, so
+// edges are a pixel wide fade at any size. Loads what's already in framebuffers laid out like
+// DebugOverlayRenderer::create_framebuffers and blends over it the same way, shapes are written
+// every frame into a buffer per frame in flight
+pub struct SdfShapeRenderer {
+  pipeline: AdPipeline,
+  render_pass: Arc<AdRenderPass>,
+  // Shapes at binding 0, then the font atlas and its sampler
+  shape_dsets: Vec<AdDescriptorSet>,
+}
+
+impl SdfShapeRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    frames_in_flight: usize,
+    font: &SdfFontGPU,
+  ) -> Result<Self, String> {
+    // Depth is only there for the framebuffers to match the overlay layer's
+    let attachments = [
+      vk::AttachmentDescription::default()
+        .format(color_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .initial_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .final_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::STORE),
+      vk::AttachmentDescription::default()
+        .format(depth_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE),
+    ];
+    let color_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(0)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let depth_attachment_ref = vk::AttachmentReference::default()
+      .attachment(1)
+      .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let render_pass = Arc::new(AdRenderPass::new(
+      ash_device.clone(),
+      vk::RenderPassCreateFlags::default(),
+      &attachments,
+      &[vk::SubpassDescription::default()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&color_attachment_ref)
+        .depth_stencil_attachment(&depth_attachment_ref)],
+      &[
+        vk::SubpassDependency::default()
+          .src_subpass(vk::SUBPASS_EXTERNAL)
+          .dst_subpass(0)
+          .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+          .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+          .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+          .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+          ),
+        vk::SubpassDependency::default()
+          .src_subpass(0)
+          .dst_subpass(vk::SUBPASS_EXTERNAL)
+          .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+          .dst_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::TRANSFER
+              | vk::PipelineStageFlags::COMPUTE_SHADER,
+          )
+          .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+          .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_READ
+              | vk::AccessFlags::TRANSFER_READ
+              | vk::AccessFlags::SHADER_READ
+              | vk::AccessFlags::SHADER_WRITE,
+          ),
+      ],
+    )?);
+
+    let shape_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (
+          vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+          vk::DescriptorType::STORAGE_BUFFER,
+        ),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLER),
+      ],
+    )?);
+    let shape_dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      frames_in_flight as u32,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_BUFFER,
+          descriptor_count: frames_in_flight as u32,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLED_IMAGE,
+          descriptor_count: frames_in_flight as u32,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLER,
+          descriptor_count: frames_in_flight as u32,
+        },
+      ],
+    )?);
+    // Distances between texels are blended, that's what keeps edges smooth when scaled up
+    let sampler = Arc::new(AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    )?);
+    let shape_bindings = (0..frames_in_flight)
+      .map(|i| {
+        let shape_buffer = AdBuffer::new(
+          ash_device.clone(),
+          allocator.clone(),
+          MemoryLocation::CpuToGpu,
+          &format!("sdf_shapes_{i}"),
+          vk::BufferCreateFlags::empty(),
+          (std::mem::size_of::<SdfShape>() * MAX_SDF_SHAPES) as _,
+          vk::BufferUsageFlags::STORAGE_BUFFER,
+        )?;
+        Ok((
+          shape_dset_layout.clone(),
+          vec![
+            AdDescriptorBinding::StorageBuffer(Arc::new(shape_buffer)),
+            AdDescriptorBinding::Image2D((
+              font.image_view().clone(),
+              vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )),
+            AdDescriptorBinding::Sampler(sampler.clone()),
+          ],
+        ))
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    let shape_dsets = AdDescriptorSet::new(shape_dset_pool, &shape_bindings)?;
+
+    let pipeline = AdPipeline::new(
+      render_pass.clone(),
+      0,
+      HashMap::from([
+        (vk::ShaderStageFlags::VERTEX, SDF_SHAPE_VERT_SHADER_CODE),
+        (vk::ShaderStageFlags::FRAGMENT, SDF_SHAPE_FRAG_SHADER_CODE),
+      ]),
+      &[&shape_dset_layout],
+      (vk::ShaderStageFlags::VERTEX, std::mem::size_of::<glam::Vec2>() as u32),
+      vk::PipelineRasterizationStateCreateInfo::default()
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0),
+      &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
+        vk::PipelineColorBlendAttachmentState::default()
+          .color_write_mask(vk::ColorComponentFlags::RGBA)
+          .blend_enable(true)
+          .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+          .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+          .color_blend_op(vk::BlendOp::ADD)
+          .src_alpha_blend_factor(vk::BlendFactor::ONE)
+          .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+          .alpha_blend_op(vk::BlendOp::ADD),
+      ]),
+      &vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(false)
+        .depth_write_enable(false),
+      vk::SampleCountFlags::TYPE_1,
+    )?;
+
+    Ok(Self { pipeline, render_pass, shape_dsets })
+  }
+
+  // Shapes past MAX_SDF_SHAPES are dropped, later ones are drawn over earlier ones. frame_idx
+  // picks the buffer, it must not be in use by a frame still in flight
+  pub fn render(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    frame_idx: usize,
+    shapes: &[SdfShape],
+  ) -> Result<(), String> {
+    if shapes.is_empty() {
+      return Ok(());
+    }
+    let shape_dset = &self.shape_dsets[frame_idx];
+    let Some(AdDescriptorBinding::StorageBuffer(shape_buffer)) = shape_dset.bindings().first()
+    else {
+      return Err("sdf shape renderer constructed without a shape buffer".to_string());
+    };
+    let shapes = &shapes[..shapes.len().min(MAX_SDF_SHAPES)];
+    shape_buffer.write_data(0, shapes)?;
+
+    let resolution = frame_buffer.resolution();
+    cmd_buffer.begin_render_pass(
+      self.render_pass.inner(),
+      frame_buffer.inner(),
+      vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: resolution },
+      &[],
+      vk::SubpassContents::INLINE,
+    );
+    cmd_buffer.set_view_port(&[vk::Viewport {
+      x: 0.0,
+      y: 0.0,
+      width: resolution.width as f32,
+      height: resolution.height as f32,
+      min_depth: 0.0,
+      max_depth: 1.0,
+    }]);
+    cmd_buffer
+      .set_scissor(&[vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: resolution }]);
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, self.pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::GRAPHICS,
+      self.pipeline.layout(),
+      &[shape_dset.inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.pipeline.layout(),
+      vk::ShaderStageFlags::VERTEX,
+      AdBuffer::get_byte_slice(&[glam::vec2(resolution.width as f32, resolution.height as f32)]),
+    );
+    cmd_buffer.draw(shapes.len() as u32 * 6);
+    cmd_buffer.end_render_pass();
+    Ok(())
+  }
+}
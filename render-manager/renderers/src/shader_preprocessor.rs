@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use ash_ad_wrappers::ash_context::getset;
+use vfs::{normalize_path, Vfs};
+
+// Shader sources the preprocessor resolves includes from. Paths are relative to the shader root
+// and always use '/' separators
+#[derive(Default)]
+pub struct ShaderSourceFs {
+  sources: HashMap<String, String>,
+}
+
+impl ShaderSourceFs {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // Returns whether the stored source changed
+  pub fn insert(&mut self, path: &str, source: String) -> bool {
+    let path = normalize_path(path);
+    if self.sources.get(&path) == Some(&source) {
+      return false;
+    }
+    self.sources.insert(path, source);
+    true
+  }
+
+  pub fn remove(&mut self, path: &str) -> bool {
+    self.sources.remove(&normalize_path(path)).is_some()
+  }
+
+  pub fn get(&self, path: &str) -> Option<&str> {
+    self.sources.get(&normalize_path(path)).map(|source| source.as_str())
+  }
+
+  // Loads every file under a vfs directory, can be called again to pick up edits. Paths are kept
+  // relative to the directory. Returns the paths that were added, changed or removed since the
+  // last load
+  pub fn load_vfs(&mut self, vfs: &Vfs, dir: &str) -> Result<Vec<String>, String> {
+    let dir = normalize_path(dir);
+    let mut found = HashMap::new();
+    for vfs_path in vfs.list(&dir) {
+      // Compiled shaders and other binaries live next to the sources
+      let Ok(source) = vfs.read_to_string(&vfs_path) else { continue };
+      let rel_path = match dir.is_empty() {
+        true => vfs_path.as_str(),
+        false => vfs_path.strip_prefix(&format!("{dir}/")).unwrap_or(&vfs_path),
+      };
+      found.insert(rel_path.to_string(), source);
+    }
+
+    let mut changed = self
+      .sources
+      .keys()
+      .filter(|path| !found.contains_key(*path))
+      .cloned()
+      .collect::<Vec<_>>();
+    for path in changed.iter() {
+      self.sources.remove(path);
+    }
+    for (path, source) in found {
+      if self.sources.get(&path) != Some(&source) {
+        changed.push(path.clone());
+        self.sources.insert(path, source);
+      }
+    }
+    changed.sort();
+    Ok(changed)
+  }
+}
+
+fn parent_dir(path: &str) -> &str {
+  path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+// Path inside the quotes or angle brackets of an #include line
+fn parse_include(directive: &str) -> Option<&str> {
+  let rest = directive.strip_prefix("#include")?.trim();
+  let (open, close) = match rest.chars().next()? {
+    '"' => ('"', '"'),
+    '<' => ('<', '>'),
+    _ => return None,
+  };
+  let rest = rest.strip_prefix(open)?;
+  rest.find(close).map(|end| &rest[..end])
+}
+
+#[derive(getset::Getters)]
+pub struct PreprocessedShader {
+  // GLSL with includes inlined and defines injected, ready for the compiler
+  #[getset(get = "pub")]
+  source: String,
+  // Every file read to build the source, the entry file included
+  #[getset(get = "pub")]
+  dependencies: BTreeSet<String>,
+}
+
+// Defines go right after the #version line, the source's own #ifdefs are left to the compiler.
+// Includes are resolved relative to the including file first, then to the shader root. Each file
+// is only inlined once per shader, so headers don't need include guards
+pub fn preprocess(
+  fs: &ShaderSourceFs,
+  entry: &str,
+  defines: &BTreeMap<String, String>,
+) -> Result<PreprocessedShader, String> {
+  let entry = normalize_path(entry);
+  let mut state = PreprocessState { output: String::new(), dependencies: BTreeSet::new() };
+  let define_block = defines
+    .iter()
+    .map(|(name, value)| match value.is_empty() {
+      true => format!("#define {name}\n"),
+      false => format!("#define {name} {value}\n"),
+    })
+    .collect::<String>();
+  state.dependencies.insert(entry.clone());
+  let source = fs.get(&entry).ok_or(format!("shader source {entry} not found"))?;
+
+  let mut injected = false;
+  for line in source.lines() {
+    if !injected && line.trim_start().starts_with("#version") {
+      state.output.push_str(line);
+      state.output.push('\n');
+      state.output.push_str(&define_block);
+      injected = true;
+      continue;
+    }
+    if !injected && !line.trim().is_empty() && !line.trim_start().starts_with("//") {
+      state.output.push_str(&define_block);
+      injected = true;
+    }
+    state.process_line(fs, &entry, line)?;
+  }
+  Ok(PreprocessedShader { source: state.output, dependencies: state.dependencies })
+}
+
+struct PreprocessState {
+  output: String,
+  dependencies: BTreeSet<String>,
+}
+
+impl PreprocessState {
+  fn process_line(&mut self, fs: &ShaderSourceFs, file: &str, line: &str) -> Result<(), String> {
+    let directive = line.trim_start();
+    if directive.starts_with("#pragma") && directive["#pragma".len()..].trim() == "once" {
+      return Ok(());
+    }
+    if !directive.starts_with("#include") {
+      self.output.push_str(line);
+      self.output.push('\n');
+      return Ok(());
+    }
+
+    let include = parse_include(directive).ok_or(format!("at {file}: malformed {directive}"))?;
+    let relative = normalize_path(&format!("{}/{include}", parent_dir(file)));
+    let include_path = match fs.get(&relative) {
+      Some(_) => relative,
+      None => normalize_path(include),
+    };
+    let source =
+      fs.get(&include_path).ok_or(format!("at {file}: included file {include} not found"))?;
+    // Also stops include cycles, since files being inlined are already in the dependencies
+    if !self.dependencies.insert(include_path.clone()) {
+      return Ok(());
+    }
+
+    for included_line in source.lines() {
+      if included_line.trim_start().starts_with("#version") {
+        return Err(format!("at {include_path}: #version is only allowed in the entry file"));
+      }
+      self.process_line(fs, &include_path, included_line)?;
+    }
+    Ok(())
+  }
+}
+
+// Remembers which files each compiled shader was built from, so a changed include can be mapped
+// to the shaders that need a recompile
+#[derive(Default)]
+pub struct ShaderDependencyGraph {
+  dependencies: HashMap<String, BTreeSet<String>>,
+}
+
+impl ShaderDependencyGraph {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn record(&mut self, entry: &str, shader: &PreprocessedShader) {
+    self.dependencies.insert(normalize_path(entry), shader.dependencies.clone());
+  }
+
+  pub fn forget(&mut self, entry: &str) {
+    self.dependencies.remove(&normalize_path(entry));
+  }
+
+  // Entry shaders built from any of the changed files
+  pub fn dependents(&self, changed: &[String]) -> BTreeSet<String> {
+    let changed = changed.iter().map(|path| normalize_path(path)).collect::<BTreeSet<_>>();
+    self
+      .dependencies
+      .iter()
+      .filter(|(_, deps)| !deps.is_disjoint(&changed))
+      .map(|(entry, _)| entry.clone())
+      .collect()
+  }
+}
@@ -6,10 +6,269 @@ struct VertexData {
 
 struct ObjectData {
   mat4 transform;
+  // What the last frame was drawn with
+  mat4 prev_transform;
+};
+
+struct MorphDelta {
+  vec4 position;
+  vec4 normal;
+};
+
+struct MorphData {
+  // Up to 8 target weights, 4 per vec4
+  vec4 weights[2];
+  // target count, vertex count, unused, unused
+  uvec4 counts;
+};
+
+struct StreamsData {
+  // 1 where the mesh has colors, second uvs, unused, unused
+  uvec4 present;
+};
+
+struct CrowdVertexData {
+  vec4 position;
+  vec4 normal;
+  vec4 uv;
+  uvec4 joints;
+  vec4 weights;
+};
+
+struct CrowdInstanceData {
+  mat4 transform;
+  // animation time in seconds, unused
+  vec4 anim;
+};
+
+struct CrowdData {
+  // joint count, frame count, unused, unused
+  uvec4 counts;
+  // frame rate, unused
+  vec4 params;
+};
+
+struct MaterialData {
+  vec4 base_color;
+  // alpha cutoff, ambient light, 1 to multiply in vertex colors, 1 to sample the albedo with uv2
+  vec4 params;
+  // roughness, reflectivity, unused, unused
+  vec4 surface;
 };
 
 struct CamData {
   vec4 pos;
   vec4 look_at;
   mat4 view_proj_mat;
-};
\ No newline at end of file
+};
+
+// MAX_VIEWS in renderables multiview.rs has to match
+#define MAX_MULTIVIEW_VIEWS 6
+
+// Cameras of a multiview pass, index with gl_ViewIndex
+struct MultiviewCamData {
+  CamData views[MAX_MULTIVIEW_VIEWS];
+  // view count, unused, unused, unused
+  uvec4 count;
+};
+
+// MAX_SHADOW_CASCADES in shadow_renderers.rs has to match
+#define MAX_SHADOW_CASCADES 4
+
+struct ShadowData {
+  // Projection of each cascade's layer of the shadow map, nearest first, before the y flip
+  mat4 light_view_proj[MAX_SHADOW_CASCADES];
+  // mode (0 off, 1 shadow map, 2 traced mask), depth bias, 1 / map width, 1 / map height
+  vec4 params;
+  // cascade count, share of a cascade from its border blended into the next, unused, unused
+  vec4 cascade_params;
+  // xyz towards the sun, w unused
+  vec4 sun_dir;
+  // rgb sun color times intensity, w unused
+  vec4 sun_color;
+  // rgb color of the ambient term, w unused
+  vec4 ambient;
+};
+
+// MAX_LOCAL_LIGHTS and MAX_LIGHT_SHADOW_SLOTS in shadow_renderers.rs have to match
+#define MAX_LOCAL_LIGHTS 64
+#define MAX_LIGHT_SHADOW_SLOTS 64
+
+struct LocalLightData {
+  // xyz position, w range
+  vec4 position;
+  // rgb color times intensity, w shape (0 point, 1 spot)
+  vec4 color;
+  // xyz spot direction, w cos of the outer angle
+  vec4 direction;
+  // cos of the inner angle, first shadow slot or -1 without a shadow, unused, unused
+  vec4 params;
+};
+
+struct LightShadowSlotData {
+  // Before the y flip, like light_view_proj
+  mat4 view_proj;
+  // Offset and scale of the slot in atlas uvs
+  vec4 rect;
+};
+
+struct LocalLightsData {
+  // light count, 1 / atlas size, depth bias, unused
+  vec4 params;
+  LocalLightData lights[MAX_LOCAL_LIGHTS];
+  // Points take six in a row, one per cube face in +X, -X, +Y, -Y, +Z, -Z order
+  LightShadowSlotData slots[MAX_LIGHT_SHADOW_SLOTS];
+};
+
+// MAX_REFLECTION_PROBES in reflection_probe_renderers.rs has to match
+#define MAX_REFLECTION_PROBES 8
+
+struct ReflectionProbeData {
+  // xyz position, w distance the probe fades out over inside its box
+  vec4 position;
+  // xyz box corners, w unused
+  vec4 box_min;
+  vec4 box_max;
+};
+
+struct ReflectionProbesData {
+  // probe count, level of the fully rough prefilter, unused, unused
+  vec4 params;
+  // Smallest box first, probe i is cube i of the array
+  ReflectionProbeData probes[MAX_REFLECTION_PROBES];
+};
+
+struct ShadowTraceData {
+  mat4 inv_view_proj;
+  // xyz towards the sun, w ray origin offset
+  vec4 sun_dir;
+  // max ray distance, unused, unused, unused
+  vec4 params;
+};
+struct GridData {
+  vec4 color;
+  // cell size, line width in pixels, fade distance, opacity
+  vec4 params;
+};
+
+struct ParticleData {
+  // xyz position, w remaining life in seconds
+  vec4 pos_life;
+  // xyz velocity, w full lifetime in seconds
+  vec4 vel_lifetime;
+};
+
+struct EmitterData {
+  // xyz origin, w random speed added on spawn
+  vec4 origin;
+  // xyz spawn velocity, w lifetime in seconds
+  vec4 velocity;
+  // xyz gravity, w fraction of normal speed kept after a bounce
+  vec4 gravity;
+  vec4 color;
+  // size, surface thickness, unused, unused
+  vec4 params;
+  // collision mode (0 none, 1 bounce, 2 kill), unused
+  uvec4 flags;
+};
+
+struct ParticleSimData {
+  // capacity, first spawn slot, spawn count, seed
+  uvec4 counters;
+  // frame time in seconds, unused
+  vec4 params;
+};
+
+struct OverlayRectData {
+  // left, top, width, height in normalized device coordinates
+  vec4 rect;
+  vec4 color;
+};
+
+struct SdfShapeData {
+  // left, top, width, height of the quad drawn, in pixels of the target
+  vec4 rect;
+  vec4 color;
+  // Corner radius and outline width for rects, start and end for lines, atlas uvs for glyphs
+  vec4 params;
+  uint kind;
+  float thickness;
+  float distance_range;
+  float pad;
+};
+
+struct DebugLineData {
+  // World space, w unused
+  vec4 start;
+  vec4 end;
+  vec4 color;
+};
+
+struct WaterData {
+  mat4 transform;
+  vec4 color;
+  vec4 sky_color;
+  // xy first normal layer scroll, zw second, in uv per second
+  vec4 scroll;
+  // size x, size z, tile size, fresnel power
+  vec4 shape;
+  // normal strength, distortion, reflection, unused
+  vec4 params;
+};
+
+struct WaterFrameData {
+  CamData camera;
+  // time in seconds, unused, 1 / target width, 1 / target height
+  vec4 frame;
+  // xyz towards the sun, w highlight brightness
+  vec4 sun;
+};
+
+struct FoliageInstanceData {
+  // xyz root, w scale
+  vec4 pos_scale;
+  // rotation around y, density rank, sway phase, unused
+  vec4 params;
+};
+
+struct FoliageData {
+  // fade start, max distance, sway, card height
+  vec4 params;
+};
+
+struct FoliageFrameData {
+  CamData camera;
+  // xy wind direction on xz, strength, gust speed
+  vec4 wind;
+  // time in seconds, gust size, unused, unused
+  vec4 frame;
+};
+
+struct CullObjectData {
+  // xyz world center, w radius
+  vec4 sphere;
+  // vertex count, unused, unused, unused
+  uvec4 draw;
+};
+
+struct CullFrameData {
+  // Frustum planes of the current camera, normals pointing in
+  vec4 planes[6];
+  // Camera the depth pyramid was built with
+  mat4 pyramid_view_proj;
+  // pyramid width, pyramid height, pyramid mip count, occlusion enabled
+  vec4 pyramid;
+  // object count, unused, unused, unused
+  uvec4 counts;
+};
+
+struct VelocityFrameData {
+  // What the scene was drawn with this frame, jittered with taa
+  mat4 scene_view_proj;
+  // Without jitter, for motion vectors
+  mat4 view_proj;
+  mat4 inv_view_proj;
+  mat4 prev_view_proj;
+  // 1 / width, 1 / height, unused, unused
+  vec4 params;
+};
@@ -0,0 +1,59 @@
+#version 460
+
+#include "common_structs.glsl"
+
+layout (location = 0) out vec4 outGlobalPos;
+layout (location = 1) out vec4 outUV;
+layout (location = 2) out vec4 outNormal;
+
+layout(std430, set = 0, binding = 0) readonly buffer VertexArray { CrowdVertexData verts[]; } vertex_buffer;
+layout(std430, set = 0, binding = 1) readonly buffer IndexArray { uint inds[]; } index_buffer;
+layout(std430, set = 0, binding = 2) readonly buffer InstanceArray { CrowdInstanceData instances[]; } instance_buffer;
+// 3 texels per joint holding the top rows of its skinning matrix, one row per baked frame
+layout(set = 0, binding = 3) uniform texture2D bone_texture;
+layout(set = 0, binding = 4) uniform sampler bone_sampler;
+layout(std140, set = 0, binding = 5) uniform CrowdWrap { CrowdData data; } crowd_data;
+
+layout(push_constant) uniform CamWrap { CamData data; } camera_buffer;
+
+vec4 invert_y_axis(vec4 v) {
+  return vec4(v.x, -v.y, v.z, v.w);
+}
+
+mat4 joint_matrix(uint joint, uint frame) {
+  int x = int(joint * 3);
+  int y = int(frame);
+  vec4 row0 = texelFetch(sampler2D(bone_texture, bone_sampler), ivec2(x, y), 0);
+  vec4 row1 = texelFetch(sampler2D(bone_texture, bone_sampler), ivec2(x + 1, y), 0);
+  vec4 row2 = texelFetch(sampler2D(bone_texture, bone_sampler), ivec2(x + 2, y), 0);
+  return transpose(mat4(row0, row1, row2, vec4(0.0, 0.0, 0.0, 1.0)));
+}
+
+void main() {
+  uint vert_id = index_buffer.inds[gl_VertexIndex];
+  CrowdVertexData vert = vertex_buffer.verts[vert_id];
+  CrowdInstanceData instance = instance_buffer.instances[gl_InstanceIndex];
+
+  // Loops the baked frames, blending into the next one
+  uint frame_count = crowd_data.data.counts.y;
+  float frame_pos = mod(instance.anim.x * crowd_data.data.params.x, float(frame_count));
+  uint frame = min(uint(frame_pos), frame_count - 1);
+  uint next_frame = (frame + 1) % frame_count;
+  float blend = fract(frame_pos);
+
+  mat4 skin = mat4(0.0);
+  for (uint i = 0; i < 4; i++) {
+    float weight = vert.weights[i];
+    if (weight != 0.0) {
+      uint joint = vert.joints[i];
+      skin += (weight * (1.0 - blend)) * joint_matrix(joint, frame);
+      skin += (weight * blend) * joint_matrix(joint, next_frame);
+    }
+  }
+  mat4 model = instance.transform * skin;
+  vec4 global_pos = model * vert.position;
+  gl_Position = invert_y_axis(camera_buffer.data.view_proj_mat * global_pos);
+  outGlobalPos = global_pos;
+  outUV = vert.uv;
+  outNormal = vec4(normalize(mat3(model) * vert.normal.xyz), 0.0);
+}
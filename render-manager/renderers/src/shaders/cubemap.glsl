@@ -0,0 +1,15 @@
+const float PI = 3.14159265359;
+
+// Direction through the center of a texel on a cubemap face, faces in +X, -X, +Y, -Y, +Z, -Z
+// order with the orientation vulkan samples them in
+vec3 cube_dir(ivec3 texel, ivec2 face_size) {
+  vec2 uv = (vec2(texel.xy) + 0.5) / vec2(face_size) * 2.0 - 1.0;
+  switch (texel.z) {
+    case 0: return normalize(vec3(1.0, -uv.y, -uv.x));
+    case 1: return normalize(vec3(-1.0, -uv.y, uv.x));
+    case 2: return normalize(vec3(uv.x, 1.0, uv.y));
+    case 3: return normalize(vec3(uv.x, -1.0, -uv.y));
+    case 4: return normalize(vec3(uv.x, -uv.y, 1.0));
+    default: return normalize(vec3(-uv.x, -uv.y, -1.0));
+  }
+}
@@ -0,0 +1,45 @@
+#version 460
+
+#include "common_structs.glsl"
+
+layout (location = 0) out vec4 outColor;
+
+layout(std430, set = 0, binding = 1) readonly buffer LineArray { DebugLineData lines[]; } line_buffer;
+
+// viewport is the target's width and height in pixels, then the line width in pixels
+layout(push_constant) uniform LineWrap { CamData cam; vec4 viewport; } line_push;
+
+// Moves p along the line to q until it's in front of the near plane, depth is 0 there
+vec4 clip_to_near(vec4 p, vec4 q) {
+  if (p.z >= 0.0) {
+    return p;
+  }
+  return mix(p, q, p.z / (p.z - q.z));
+}
+
+void main() {
+  // Two triangles per line, x along it and y out to either side
+  const vec2 corners[6] = vec2[](
+    vec2(0.0, -1.0), vec2(1.0, -1.0), vec2(0.0, 1.0),
+    vec2(0.0, 1.0), vec2(1.0, -1.0), vec2(1.0, 1.0)
+  );
+  DebugLineData line = line_buffer.lines[gl_VertexIndex / 6];
+  vec4 start = line_push.cam.view_proj_mat * vec4(line.start.xyz, 1.0);
+  vec4 end = line_push.cam.view_proj_mat * vec4(line.end.xyz, 1.0);
+  outColor = line.color;
+  // Wholly behind the camera, put past the far plane so it's clipped
+  if (start.z < 0.0 && end.z < 0.0) {
+    gl_Position = vec4(0.0, 0.0, 2.0, 1.0);
+    return;
+  }
+  start = clip_to_near(start, end);
+  end = clip_to_near(end, start);
+
+  vec2 corner = corners[gl_VertexIndex % 6];
+  vec2 along = (end.xy / end.w - start.xy / start.w) * line_push.viewport.xy;
+  vec2 dir = length(along) > 0.0 ? normalize(along) : vec2(1.0, 0.0);
+  // Half the width either side, a pixel is 2 / size in normalized device coordinates
+  vec2 side = vec2(-dir.y, dir.x) * line_push.viewport.z / line_push.viewport.xy;
+  vec4 pos = corner.x == 0.0 ? start : end;
+  gl_Position = vec4(pos.xy + side * corner.y * pos.w, pos.zw);
+}
@@ -0,0 +1,52 @@
+// Shared body of the depth point readback, included with MULTISAMPLED_DEPTH defined when the scene
+// depth buffer is multisampled. Copies the depth under each texel of a list into a buffer in the
+// same order, keeping the nearest sample of each
+
+#extension GL_EXT_samplerless_texture_functions : require
+
+layout(local_size_x = 64) in;
+
+#ifdef MULTISAMPLED_DEPTH
+layout(set = 0, binding = 0) uniform texture2DMS scene_depth;
+#else
+layout(set = 0, binding = 0) uniform texture2D scene_depth;
+#endif
+layout(set = 0, binding = 1) readonly buffer TexelWrap { ivec2 texels[]; } texel_buffer;
+layout(set = 0, binding = 2) buffer DepthWrap { float depths[]; } depth_buffer;
+
+// point count, sample count
+layout(push_constant) uniform PointsWrap { ivec4 params; } points_buffer;
+
+ivec2 depth_size() {
+#ifdef MULTISAMPLED_DEPTH
+  return textureSize(scene_depth);
+#else
+  return textureSize(scene_depth, 0);
+#endif
+}
+
+float nearest_depth(ivec2 texel) {
+#ifdef MULTISAMPLED_DEPTH
+  float depth = 1.0;
+  for (int i = 0; i < points_buffer.params.y; i++) {
+    depth = min(depth, texelFetch(scene_depth, texel, i).x);
+  }
+  return depth;
+#else
+  return texelFetch(scene_depth, texel, 0).x;
+#endif
+}
+
+void main() {
+  int point = int(gl_GlobalInvocationID.x);
+  if (point >= points_buffer.params.x) {
+    return;
+  }
+  ivec2 texel = texel_buffer.texels[point];
+  // Off screen counts as the far plane
+  float depth = 1.0;
+  if (all(greaterThanEqual(texel, ivec2(0))) && all(lessThan(texel, depth_size()))) {
+    depth = nearest_depth(texel);
+  }
+  depth_buffer.depths[point] = depth;
+}
@@ -0,0 +1,54 @@
+// Shared body of the depth pyramid's first level, included with MULTISAMPLED_DEPTH defined when
+// the scene depth buffer is multisampled. Each texel keeps the farthest depth under it
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+#ifdef MULTISAMPLED_DEPTH
+layout(set = 0, binding = 0) uniform texture2DMS scene_depth;
+#else
+layout(set = 0, binding = 0) uniform texture2D scene_depth;
+#endif
+layout(set = 0, binding = 1, r32f) uniform image2D pyramid_level;
+
+// sample count, unused, unused, unused
+layout(push_constant) uniform PyramidWrap { uvec4 params; } pyramid_buffer;
+
+ivec2 depth_size() {
+#ifdef MULTISAMPLED_DEPTH
+  return textureSize(scene_depth);
+#else
+  return textureSize(scene_depth, 0);
+#endif
+}
+
+float farthest_depth(ivec2 texel) {
+#ifdef MULTISAMPLED_DEPTH
+  float depth = 0.0;
+  for (int i = 0; i < int(pyramid_buffer.params.x); i++) {
+    depth = max(depth, texelFetch(scene_depth, texel, i).x);
+  }
+  return depth;
+#else
+  return texelFetch(scene_depth, texel, 0).x;
+#endif
+}
+
+void main() {
+  ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+  ivec2 size = imageSize(pyramid_level);
+  if (texel.x >= size.x || texel.y >= size.y) {
+    return;
+  }
+  // The level is at most the depth size, so a texel covers up to 3 depth texels each way
+  ivec2 src_size = depth_size();
+  vec2 ratio = vec2(src_size) / vec2(size);
+  ivec2 src_min = ivec2(floor(vec2(texel) * ratio));
+  ivec2 src_max = min(ivec2(ceil(vec2(texel + 1) * ratio)), src_size) - 1;
+  float depth = 0.0;
+  for (int y = src_min.y; y <= src_max.y; y++) {
+    for (int x = src_min.x; x <= src_max.x; x++) {
+      depth = max(depth, farthest_depth(ivec2(x, y)));
+    }
+  }
+  imageStore(pyramid_level, texel, vec4(depth));
+}
@@ -0,0 +1,54 @@
+// Shared body of the depth readback, included with MULTISAMPLED_DEPTH defined when the scene depth
+// buffer is multisampled. Copies the square of depth texels around a center into a buffer, row by
+// row, keeping the nearest sample of each
+
+#extension GL_EXT_samplerless_texture_functions : require
+
+layout(local_size_x = 16, local_size_y = 16) in;
+
+#ifdef MULTISAMPLED_DEPTH
+layout(set = 0, binding = 0) uniform texture2DMS scene_depth;
+#else
+layout(set = 0, binding = 0) uniform texture2D scene_depth;
+#endif
+layout(set = 0, binding = 1) writeonly buffer DepthWrap { float depths[]; } depth_buffer;
+
+// center x, center y, radius, sample count
+layout(push_constant) uniform ReadbackWrap { ivec4 params; } readback_buffer;
+
+ivec2 depth_size() {
+#ifdef MULTISAMPLED_DEPTH
+  return textureSize(scene_depth);
+#else
+  return textureSize(scene_depth, 0);
+#endif
+}
+
+float nearest_depth(ivec2 texel) {
+#ifdef MULTISAMPLED_DEPTH
+  float depth = 1.0;
+  for (int i = 0; i < readback_buffer.params.w; i++) {
+    depth = min(depth, texelFetch(scene_depth, texel, i).x);
+  }
+  return depth;
+#else
+  return texelFetch(scene_depth, texel, 0).x;
+#endif
+}
+
+void main() {
+  int radius = readback_buffer.params.z;
+  int side = radius * 2 + 1;
+  ivec2 offset = ivec2(gl_LocalInvocationID.xy);
+  if (offset.x >= side || offset.y >= side) {
+    return;
+  }
+  ivec2 texel = readback_buffer.params.xy + offset - radius;
+  ivec2 size = depth_size();
+  // Off screen counts as the far plane
+  float depth = 1.0;
+  if (all(greaterThanEqual(texel, ivec2(0))) && all(lessThan(texel, size))) {
+    depth = nearest_depth(texel);
+  }
+  depth_buffer.depths[offset.y * side + offset.x] = depth;
+}
@@ -0,0 +1,54 @@
+// Shared body of the depth of field's circle of confusion pass, included with MULTISAMPLED_DEPTH
+// defined when the scene depth buffer is multisampled. Each texel gets the radius in pixels the
+// lens blurs it over, negative in front of the focus distance and positive behind it
+
+#extension GL_EXT_samplerless_texture_functions : require
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+#ifdef MULTISAMPLED_DEPTH
+layout(set = 0, binding = 0) uniform texture2DMS scene_depth;
+#else
+layout(set = 0, binding = 0) uniform texture2D scene_depth;
+#endif
+layout(set = 0, binding = 1, r32f) uniform writeonly image2D coc_out;
+
+layout(push_constant) uniform CocParams {
+  mat4 inv_view_proj;
+  // xyz, unused
+  vec4 camera_pos;
+  // normalized xyz, unused
+  vec4 look_dir;
+  // focus distance, lens coefficient in pixels, max radius in pixels, sample count
+  vec4 params;
+} coc_params;
+
+float nearest_depth(ivec2 texel) {
+#ifdef MULTISAMPLED_DEPTH
+  float depth = 1.0;
+  for (int i = 0; i < int(coc_params.params.w); i++) {
+    depth = min(depth, texelFetch(scene_depth, texel, i).x);
+  }
+  return depth;
+#else
+  return texelFetch(scene_depth, texel, 0).x;
+#endif
+}
+
+void main() {
+  ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+  ivec2 size = imageSize(coc_out);
+  if (texel.x >= size.x || texel.y >= size.y) {
+    return;
+  }
+  vec2 uv = (vec2(texel) + 0.5) / vec2(size);
+  // Shaders flip y after the view projection, so the top row is at +1
+  vec3 ndc = vec3(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, nearest_depth(texel));
+  vec4 world = coc_params.inv_view_proj * vec4(ndc, 1.0);
+  vec3 from_camera = world.xyz / world.w - coc_params.camera_pos.xyz;
+  float view_depth = max(dot(from_camera, coc_params.look_dir.xyz), 0.001);
+  float focus = coc_params.params.x;
+  float max_coc = coc_params.params.z;
+  float coc = coc_params.params.y * (view_depth - focus) / view_depth;
+  imageStore(coc_out, texel, vec4(clamp(coc, -max_coc, max_coc)));
+}
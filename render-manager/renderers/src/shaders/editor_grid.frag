@@ -0,0 +1,54 @@
+#version 460
+
+#include "common_structs.glsl"
+
+layout (location = 0) in vec4 inNearPos;
+layout (location = 1) in vec4 inFarPos;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout(push_constant) uniform GridWrap { CamData cam; GridData grid; } grid_buffer;
+
+void main() {
+  vec3 near_pos = inNearPos.xyz;
+  vec3 far_pos = inFarPos.xyz;
+  float dy = far_pos.y - near_pos.y;
+  if (abs(dy) < 0.000001) {
+    discard;
+  }
+  // Intersection of the view ray with the y = 0 plane
+  float t = -near_pos.y / dy;
+  if (t <= 0.0 || t >= 1.0) {
+    discard;
+  }
+  vec3 pos = near_pos + t * (far_pos - near_pos);
+
+  float cell_size = grid_buffer.grid.params.x;
+  float line_width = grid_buffer.grid.params.y;
+  float fade_distance = grid_buffer.grid.params.z;
+  float opacity = grid_buffer.grid.params.w;
+
+  vec2 coord = pos.xz / cell_size;
+  vec2 deriv = max(fwidth(coord), vec2(0.000001));
+  vec2 line_dist = abs(fract(coord - 0.5) - 0.5) / deriv;
+  float line_alpha = 1.0 - min(min(line_dist.x, line_dist.y) / line_width, 1.0);
+
+  vec3 color = grid_buffer.grid.color.rgb;
+  // World axes drawn in the gizmo axis colors
+  if (abs(coord.y) / deriv.y < line_width) {
+    color = vec3(0.9, 0.2, 0.2);
+  }
+  if (abs(coord.x) / deriv.x < line_width) {
+    color = vec3(0.2, 0.3, 0.9);
+  }
+
+  float fade = clamp(1.0 - length(pos - grid_buffer.cam.pos.xyz) / fade_distance, 0.0, 1.0);
+  float alpha = line_alpha * fade * opacity;
+  if (alpha <= 0.001) {
+    discard;
+  }
+
+  vec4 clip_pos = grid_buffer.cam.view_proj_mat * vec4(pos, 1.0);
+  gl_FragDepth = clamp(clip_pos.z / clip_pos.w, 0.0, 1.0);
+  outFragColor = vec4(color, alpha);
+}
@@ -0,0 +1,23 @@
+#version 460
+
+#include "common_structs.glsl"
+
+layout (location = 0) out vec4 outNearPos;
+layout (location = 1) out vec4 outFarPos;
+
+layout(push_constant) uniform GridWrap { CamData cam; GridData grid; } grid_buffer;
+
+vec3 unproject(mat4 inv_view_proj, vec2 ndc, float depth) {
+  vec4 p = inv_view_proj * vec4(ndc, depth, 1.0);
+  return p.xyz / p.w;
+}
+
+void main() {
+  // Fullscreen triangle, points on the near and far planes are interpolated per pixel
+  vec2 ndc = vec2(float((gl_VertexIndex << 1) & 2), float(gl_VertexIndex & 2)) * 2.0 - 1.0;
+  gl_Position = vec4(ndc, 0.0, 1.0);
+  mat4 inv_view_proj = inverse(grid_buffer.cam.view_proj_mat);
+  // Camera matrix has y flipped compared to the screen
+  outNearPos = vec4(unproject(inv_view_proj, vec2(ndc.x, -ndc.y), 0.0), 1.0);
+  outFarPos = vec4(unproject(inv_view_proj, vec2(ndc.x, -ndc.y), 1.0), 1.0);
+}
@@ -0,0 +1,75 @@
+#version 460
+
+#include "common_structs.glsl"
+
+layout (location = 0) out vec4 outGlobalPos;
+layout (location = 1) out vec4 outUV;
+layout (location = 2) out vec4 outNormal;
+
+layout(std430, set = 0, binding = 0) readonly buffer VertexArray { VertexData verts[]; } vertex_buffer;
+layout(std430, set = 0, binding = 1) readonly buffer IndexArray { uint inds[]; } index_buffer;
+layout(std430, set = 0, binding = 2) readonly buffer InstanceArray { FoliageInstanceData instances[]; } instance_buffer;
+layout(std140, set = 0, binding = 3) uniform FoliageWrap { FoliageData data; } foliage;
+// Tiling gust strength in r
+layout(set = 0, binding = 4) uniform texture2D wind_noise;
+layout(set = 0, binding = 5) uniform sampler wind_sampler;
+
+layout(push_constant) uniform FrameWrap { FoliageFrameData data; } frame;
+
+// How much faster than the gusts each card flutters on its own
+const float FLUTTER_RATE = 2.7;
+const float FLUTTER_AMOUNT = 0.2;
+
+vec4 invert_y_axis(vec4 v) {
+  return vec4(v.x, -v.y, v.z, v.w);
+}
+
+void main() {
+  FoliageInstanceData instance = instance_buffer.instances[gl_InstanceIndex];
+  vec3 root = instance.pos_scale.xyz;
+  float fade_start = foliage.data.params.x;
+  float max_distance = foliage.data.params.y;
+
+  // Higher ranked instances drop out first as the density falls off with distance, shrinking
+  // into the ground instead of popping
+  float dist = distance(frame.data.camera.pos.xyz, root);
+  float density = 1.0 - clamp((dist - fade_start) / max(max_distance - fade_start, 0.001), 0.0, 1.0);
+  float scale = instance.pos_scale.w * smoothstep(0.0, 0.1, density - instance.params.y);
+  if (scale <= 0.0) {
+    // Outside the clip volume, the whole card gets clipped
+    gl_Position = vec4(0.0, 0.0, 2.0, 1.0);
+    outGlobalPos = vec4(0.0);
+    outUV = vec4(0.0);
+    outNormal = vec4(0.0);
+    return;
+  }
+
+  uint vert_id = index_buffer.inds[gl_VertexIndex];
+  VertexData vert = vertex_buffer.verts[vert_id];
+  float yaw = instance.params.x;
+  mat3 rotation = mat3(
+    vec3(cos(yaw), 0.0, -sin(yaw)),
+    vec3(0.0, 1.0, 0.0),
+    vec3(sin(yaw), 0.0, cos(yaw))
+  );
+  vec3 local_pos = rotation * vert.position.xyz * scale;
+
+  // Roots stay put and tips bend the most
+  float bend = clamp(vert.position.y / max(foliage.data.params.w, 0.001), 0.0, 1.0);
+  bend *= bend;
+  vec2 wind_dir = frame.data.wind.xy;
+  float time = frame.data.frame.x;
+  vec2 noise_uv = (root.xz - wind_dir * frame.data.wind.w * time) / frame.data.frame.y;
+  float gust = textureLod(sampler2D(wind_noise, wind_sampler), noise_uv, 0.0).r;
+  float flutter = sin(time * FLUTTER_RATE + instance.params.z) * FLUTTER_AMOUNT;
+  vec2 sway = wind_dir * (frame.data.wind.z * foliage.data.params.z * (gust + flutter) * bend * scale);
+  // Bent tips come down a little so cards don't look stretched
+  vec3 offset = vec3(sway.x, -0.5 * dot(sway, sway), sway.y);
+
+  vec4 global_pos = vec4(root + local_pos + offset, 1.0);
+  gl_Position = invert_y_axis(frame.data.camera.view_proj_mat * global_pos);
+  outGlobalPos = global_pos;
+  outUV = vert.uv;
+  // Leaning towards up keeps thin cards from going dark edge on to the sun
+  outNormal = vec4(normalize(mix(rotation * vert.normal.xyz, vec3(0.0, 1.0, 0.0), 0.5)), 0.0);
+}
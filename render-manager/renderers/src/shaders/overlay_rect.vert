@@ -0,0 +1,18 @@
+#version 460
+
+#include "common_structs.glsl"
+
+layout (location = 0) out vec4 outColor;
+
+layout(std430, set = 0, binding = 0) readonly buffer RectArray { OverlayRectData rects[]; } rect_buffer;
+
+void main() {
+  // Two triangles per rect
+  const vec2 corners[6] = vec2[](
+    vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0),
+    vec2(0.0, 1.0), vec2(1.0, 0.0), vec2(1.0, 1.0)
+  );
+  OverlayRectData rect = rect_buffer.rects[gl_VertexIndex / 6];
+  gl_Position = vec4(rect.rect.xy + corners[gl_VertexIndex % 6] * rect.rect.zw, 0.0, 1.0);
+  outColor = rect.color;
+}
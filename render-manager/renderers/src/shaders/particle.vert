@@ -0,0 +1,38 @@
+#version 460
+
+#include "common_structs.glsl"
+
+layout (location = 0) out vec4 outColor;
+layout (location = 1) out vec2 outCorner;
+
+layout(std430, set = 0, binding = 0) readonly buffer ParticleArray { ParticleData particles[]; } particle_buffer;
+layout(std140, set = 0, binding = 1) uniform EmitterWrap { EmitterData data; } emitter_buffer;
+
+layout(push_constant) uniform CamWrap { CamData data; } camera_buffer;
+
+const vec2 QUAD_CORNERS[6] = vec2[6](
+  vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+  vec2(1.0, 1.0), vec2(-1.0, 1.0), vec2(-1.0, -1.0)
+);
+
+void main() {
+  ParticleData particle = particle_buffer.particles[gl_VertexIndex / 6];
+  vec2 corner = QUAD_CORNERS[gl_VertexIndex % 6];
+  outCorner = corner;
+  if (particle.pos_life.w <= 0.0) {
+    // Dead particles collapse to a point outside the clip volume
+    gl_Position = vec4(0.0, 0.0, -1.0, 1.0);
+    outColor = vec4(0.0);
+    return;
+  }
+  // Camera facing quad
+  vec3 look_dir = normalize(camera_buffer.data.look_at.xyz);
+  vec3 right = normalize(cross(look_dir, vec3(0.0, 1.0, 0.0)));
+  vec3 up = cross(right, look_dir);
+  float half_size = emitter_buffer.data.params.x * 0.5;
+  vec3 pos = particle.pos_life.xyz + (right * corner.x + up * corner.y) * half_size;
+  vec4 clip_pos = camera_buffer.data.view_proj_mat * vec4(pos, 1.0);
+  gl_Position = vec4(clip_pos.x, -clip_pos.y, clip_pos.z, clip_pos.w);
+  float fade = clamp(particle.pos_life.w / max(particle.vel_lifetime.w, 0.0001), 0.0, 1.0);
+  outColor = vec4(emitter_buffer.data.color.rgb, emitter_buffer.data.color.a * fade);
+}
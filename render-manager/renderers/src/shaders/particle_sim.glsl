@@ -0,0 +1,121 @@
+// Shared body of the particle simulation, included with MULTISAMPLED_DEPTH defined when the
+// scene depth buffer is multisampled
+
+#include "common_structs.glsl"
+
+layout(local_size_x = 64) in;
+
+layout(std430, set = 0, binding = 0) buffer ParticleArray { ParticleData particles[]; } particle_buffer;
+layout(std140, set = 0, binding = 1) uniform EmitterWrap { EmitterData data; } emitter_buffer;
+
+#ifdef MULTISAMPLED_DEPTH
+layout(set = 1, binding = 0) uniform texture2DMS scene_depth;
+#else
+layout(set = 1, binding = 0) uniform texture2D scene_depth;
+#endif
+
+layout(push_constant) uniform SimWrap { CamData cam; ParticleSimData sim; } sim_buffer;
+
+const uint COLLISION_NONE = 0;
+const uint COLLISION_BOUNCE = 1;
+const uint COLLISION_KILL = 2;
+
+uint pcg_hash(uint v) {
+  uint state = v * 747796405u + 2891336453u;
+  uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+  return (word >> 22u) ^ word;
+}
+
+float rand01(inout uint state) {
+  state = pcg_hash(state);
+  return float(state >> 8) / 16777216.0;
+}
+
+ivec2 depth_size() {
+#ifdef MULTISAMPLED_DEPTH
+  return textureSize(scene_depth);
+#else
+  return textureSize(scene_depth, 0);
+#endif
+}
+
+float load_depth(ivec2 texel) {
+  // Sample 0 for multisampled depth, mip 0 otherwise
+  return texelFetch(scene_depth, clamp(texel, ivec2(0), depth_size() - 1), 0).x;
+}
+
+// Camera matrix has y flipped compared to the screen, texel rows go down
+vec3 unproject_texel(mat4 inv_view_proj, ivec2 texel) {
+  vec2 uv = (vec2(texel) + 0.5) / vec2(depth_size());
+  vec4 p = inv_view_proj * vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, load_depth(texel), 1.0);
+  return p.xyz / p.w;
+}
+
+void spawn(uint idx) {
+  uint state = pcg_hash(idx ^ pcg_hash(sim_buffer.sim.counters.w));
+  vec3 dir = vec3(rand01(state), rand01(state), rand01(state)) * 2.0 - 1.0;
+  float lifetime = emitter_buffer.data.velocity.w * (0.75 + 0.25 * rand01(state));
+  vec3 vel = emitter_buffer.data.velocity.xyz + dir * emitter_buffer.data.origin.w;
+  particle_buffer.particles[idx].pos_life = vec4(emitter_buffer.data.origin.xyz, lifetime);
+  particle_buffer.particles[idx].vel_lifetime = vec4(vel, lifetime);
+}
+
+void main() {
+  uint idx = gl_GlobalInvocationID.x;
+  uint capacity = sim_buffer.sim.counters.x;
+  if (idx >= capacity) {
+    return;
+  }
+  // Spawns take the slots after the last spawn like a ring, replacing the oldest particles
+  uint spawn_offset = (idx + capacity - sim_buffer.sim.counters.y) % capacity;
+  if (spawn_offset < sim_buffer.sim.counters.z) {
+    spawn(idx);
+    return;
+  }
+
+  ParticleData particle = particle_buffer.particles[idx];
+  float dt = sim_buffer.sim.params.x;
+  float life = particle.pos_life.w - dt;
+  if (life <= 0.0) {
+    particle_buffer.particles[idx].pos_life.w = 0.0;
+    return;
+  }
+  vec3 prev_pos = particle.pos_life.xyz;
+  vec3 vel = particle.vel_lifetime.xyz + emitter_buffer.data.gravity.xyz * dt;
+  vec3 pos = prev_pos + vel * dt;
+
+  uint collision_mode = emitter_buffer.data.flags.x;
+  vec4 clip_pos = sim_buffer.cam.view_proj_mat * vec4(pos, 1.0);
+  if (collision_mode != COLLISION_NONE && clip_pos.w > 0.0) {
+    vec3 ndc = clip_pos.xyz / clip_pos.w;
+    vec2 uv = vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (all(greaterThanEqual(uv, vec2(0.0))) && all(lessThan(uv, vec2(1.0))) && ndc.z <= 1.0) {
+      ivec2 texel = ivec2(uv * vec2(depth_size()));
+      mat4 inv_view_proj = inverse(sim_buffer.cam.view_proj_mat);
+      vec3 surface_pos = unproject_texel(inv_view_proj, texel);
+      vec3 cam_pos = sim_buffer.cam.pos.xyz;
+      float behind = length(pos - cam_pos) - length(surface_pos - cam_pos);
+      // Only counts as contact close to the visible surface, not anywhere behind it
+      if (behind >= 0.0 && behind <= emitter_buffer.data.params.y) {
+        if (collision_mode == COLLISION_KILL) {
+          particle_buffer.particles[idx].pos_life.w = 0.0;
+          return;
+        }
+        vec3 dx = unproject_texel(inv_view_proj, texel + ivec2(1, 0)) - surface_pos;
+        vec3 dy = unproject_texel(inv_view_proj, texel + ivec2(0, 1)) - surface_pos;
+        vec3 normal = normalize(cross(dx, dy));
+        if (dot(normal, cam_pos - surface_pos) < 0.0) {
+          normal = -normal;
+        }
+        float normal_speed = dot(vel, normal);
+        if (normal_speed < 0.0) {
+          vec3 tangent_vel = vel - normal_speed * normal;
+          vel = tangent_vel - normal_speed * emitter_buffer.data.gravity.w * normal;
+        }
+        pos = prev_pos;
+      }
+    }
+  }
+  particle_buffer.particles[idx].pos_life = vec4(pos, life);
+  particle_buffer.particles[idx].vel_lifetime.xyz = vel;
+}
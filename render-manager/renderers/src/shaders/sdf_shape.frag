@@ -0,0 +1,54 @@
+#version 460
+
+#include "common_structs.glsl"
+
+#define SDF_ROUNDED_RECT 0
+#define SDF_LINE 1
+#define SDF_GLYPH 2
+// Pixels quads are grown by on every side so edges have room to fade out
+#define SDF_EDGE_PADDING 1.0
+
+layout (location = 0) in vec2 inPixelPos;
+layout (location = 1) in vec2 inAtlasUv;
+layout (location = 2) flat in uint inShapeIdx;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout(std430, set = 0, binding = 0) readonly buffer ShapeArray { SdfShapeData shapes[]; } shape_buffer;
+layout(set = 0, binding = 1) uniform texture2D font_atlas;
+layout(set = 0, binding = 2) uniform sampler font_sampler;
+
+float rounded_rect_distance(vec2 pos, vec2 center, vec2 half_size, float radius) {
+  vec2 q = abs(pos - center) - half_size + radius;
+  return length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - radius;
+}
+
+float segment_distance(vec2 pos, vec2 start, vec2 end) {
+  vec2 to_pos = pos - start;
+  vec2 along = end - start;
+  float h = clamp(dot(to_pos, along) / max(dot(along, along), 1e-6), 0.0, 1.0);
+  return length(to_pos - along * h);
+}
+
+void main() {
+  SdfShapeData shape = shape_buffer.shapes[inShapeIdx];
+  // In pixels, positive outside the shape
+  float distance;
+  if (shape.kind == SDF_ROUNDED_RECT) {
+    vec2 half_size = shape.rect.zw * 0.5 - SDF_EDGE_PADDING;
+    vec2 center = shape.rect.xy + shape.rect.zw * 0.5;
+    float radius = min(shape.params.x, min(half_size.x, half_size.y));
+    distance = rounded_rect_distance(inPixelPos, center, half_size, radius);
+    if (shape.thickness > 0.0) {
+      // Outlines are inside the edge, so they line up with the filled shape
+      distance = abs(distance + shape.thickness * 0.5) - shape.thickness * 0.5;
+    }
+  } else if (shape.kind == SDF_LINE) {
+    distance = segment_distance(inPixelPos, shape.params.xy, shape.params.zw) - shape.thickness * 0.5;
+  } else {
+    distance = (0.5 - texture(sampler2D(font_atlas, font_sampler), inAtlasUv).r) * shape.distance_range;
+  }
+  // A pixel wide fade across the edge
+  float coverage = clamp(0.5 - distance, 0.0, 1.0);
+  outFragColor = vec4(shape.color.rgb, shape.color.a * coverage);
+}
@@ -0,0 +1,27 @@
+#version 460
+
+#include "common_structs.glsl"
+
+layout (location = 0) out vec2 outPixelPos;
+layout (location = 1) out vec2 outAtlasUv;
+layout (location = 2) flat out uint outShapeIdx;
+
+layout(std430, set = 0, binding = 0) readonly buffer ShapeArray { SdfShapeData shapes[]; } shape_buffer;
+
+layout(push_constant) uniform Target { vec2 resolution; } target;
+
+void main() {
+  // Two triangles per shape
+  const vec2 corners[6] = vec2[](
+    vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0),
+    vec2(0.0, 1.0), vec2(1.0, 0.0), vec2(1.0, 1.0)
+  );
+  uint shape_idx = gl_VertexIndex / 6;
+  SdfShapeData shape = shape_buffer.shapes[shape_idx];
+  vec2 corner = corners[gl_VertexIndex % 6];
+  vec2 pixel_pos = shape.rect.xy + corner * shape.rect.zw;
+  gl_Position = vec4(pixel_pos / target.resolution * 2.0 - 1.0, 0.0, 1.0);
+  outPixelPos = pixel_pos;
+  outAtlasUv = mix(shape.params.xy, shape.params.zw, corner);
+  outShapeIdx = shape_idx;
+}
@@ -0,0 +1,5 @@
+#version 460
+
+#define LIT
+#define ALPHA_CUTOUT
+#include "triangle_material.glsl"
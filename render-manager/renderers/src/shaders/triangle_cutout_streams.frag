@@ -0,0 +1,6 @@
+#version 460
+
+#define LIT
+#define ALPHA_CUTOUT
+#define VERTEX_STREAMS
+#include "triangle_material.glsl"
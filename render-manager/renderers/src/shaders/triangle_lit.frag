@@ -0,0 +1,4 @@
+#version 460
+
+#define LIT
+#include "triangle_material.glsl"
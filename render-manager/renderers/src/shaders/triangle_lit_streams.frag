@@ -0,0 +1,5 @@
+#version 460
+
+#define LIT
+#define VERTEX_STREAMS
+#include "triangle_material.glsl"
@@ -0,0 +1,234 @@
+#include "common_structs.glsl"
+
+layout (location = 0) in vec4 inGlobalPos;
+layout (location = 1) in vec4 inUV;
+layout (location = 2) in vec4 inNormal;
+#ifdef VERTEX_STREAMS
+layout (location = 3) in vec4 inColor;
+layout (location = 4) in vec2 inUV2;
+#endif
+
+layout (location = 0) out vec4 outFragColor;
+
+layout(set = 1, binding = 0) uniform texture2D albedo_texture;
+layout(set = 1, binding = 1) uniform sampler albedo_sampler;
+layout(std140, set = 1, binding = 2) uniform MaterialWrap { MaterialData data; } material;
+#ifdef LIGHTMAPPED
+layout(set = 1, binding = 3) uniform texture2D lightmap_texture;
+layout(set = 1, binding = 4) uniform sampler lightmap_sampler;
+
+// Baked light is stored divided by this, LIGHTMAP_RANGE in lightmap.rs has to match
+const float LIGHTMAP_RANGE = 4.0;
+#endif
+
+layout(push_constant) uniform CamWrap { CamData data; } camera_buffer;
+
+#if defined(LIT) || defined(LIGHTMAPPED)
+#define REFLECTIONS
+#endif
+
+#ifdef LIT
+layout(std140, set = 2, binding = 0) uniform ShadowWrap { ShadowData data; } shadow;
+layout(set = 2, binding = 1) uniform texture2DArray shadow_map;
+layout(set = 2, binding = 2) uniform texture2D shadow_mask;
+layout(set = 2, binding = 3) uniform sampler shadow_sampler;
+layout(std140, set = 2, binding = 4) uniform LocalLightsWrap { LocalLightsData data; } local_lights;
+layout(set = 2, binding = 5) uniform texture2D shadow_atlas;
+
+// Visibility from one cascade's layer, -1 past it. edge goes from 0 at the layer's border to 1 at
+// its center
+float cascade_visibility(int cascade, out float edge) {
+  vec4 light_pos = shadow.data.light_view_proj[cascade] * inGlobalPos;
+  vec2 uv = vec2(light_pos.x, -light_pos.y) * 0.5 + 0.5;
+  edge = 2.0 * min(min(uv.x, uv.y), min(1.0 - uv.x, 1.0 - uv.y));
+  if (edge < 0.0 || light_pos.z > 1.0) {
+    return -1.0;
+  }
+  float lit = 0.0;
+  for (int x = -1; x <= 1; x++) {
+    for (int y = -1; y <= 1; y++) {
+      vec3 tap = vec3(uv + vec2(x, y) * shadow.data.params.zw, cascade);
+      float map_depth = texture(sampler2DArray(shadow_map, shadow_sampler), tap).r;
+      lit += light_pos.z - shadow.data.params.y <= map_depth ? 1.0 : 0.0;
+    }
+  }
+  return lit / 9.0;
+}
+
+// 0 in shadow to 1 in full sun
+float sun_visibility() {
+  uint mode = uint(shadow.data.params.x);
+  if (mode == 1) {
+    // Nearest cascade covering the fragment, faded into the next one near its border so the
+    // change in detail doesn't show as a seam. Past the last one, lit rather than a hard edge
+    int cascade_count = int(shadow.data.cascade_params.x);
+    float blend_band = shadow.data.cascade_params.y;
+    for (int cascade = 0; cascade < cascade_count; cascade++) {
+      float edge;
+      float visibility = cascade_visibility(cascade, edge);
+      if (visibility < 0.0) {
+        continue;
+      }
+      if (edge >= blend_band) {
+        return visibility;
+      }
+      float next_edge;
+      float next = cascade + 1 < cascade_count ? cascade_visibility(cascade + 1, next_edge) : 1.0;
+      return mix(next < 0.0 ? 1.0 : next, visibility, edge / blend_band);
+    }
+    return 1.0;
+  }
+  if (mode == 2) {
+    return texelFetch(sampler2D(shadow_mask, shadow_sampler), ivec2(gl_FragCoord.xy), 0).r;
+  }
+  return 1.0;
+}
+
+// Visibility from the light's slot in the shadow atlas, points use the slot of the cube face the
+// fragment is in
+float local_light_visibility(LocalLightData light, vec3 to_frag) {
+  int slot = int(light.params.y);
+  if (slot < 0) {
+    return 1.0;
+  }
+  if (light.color.w == 0.0) {
+    vec3 dist = abs(to_frag);
+    if (dist.x >= dist.y && dist.x >= dist.z) {
+      slot += to_frag.x > 0.0 ? 0 : 1;
+    } else if (dist.y >= dist.z) {
+      slot += to_frag.y > 0.0 ? 2 : 3;
+    } else {
+      slot += to_frag.z > 0.0 ? 4 : 5;
+    }
+  }
+  LightShadowSlotData shadow_slot = local_lights.data.slots[slot];
+  vec4 light_pos = shadow_slot.view_proj * inGlobalPos;
+  vec3 ndc = light_pos.xyz / light_pos.w;
+  vec2 uv = vec2(ndc.x, -ndc.y) * 0.5 + 0.5;
+  if (light_pos.w <= 0.0 || any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || ndc.z > 1.0) {
+    return 1.0;
+  }
+  float texel = local_lights.data.params.y;
+  // Taps past the slot's border would read other lights' shadows
+  vec2 slot_min = shadow_slot.rect.xy + 0.5 * texel;
+  vec2 slot_max = shadow_slot.rect.xy + shadow_slot.rect.zw - 0.5 * texel;
+  vec2 atlas_uv = shadow_slot.rect.xy + uv * shadow_slot.rect.zw;
+  float lit = 0.0;
+  for (int x = -1; x <= 1; x++) {
+    for (int y = -1; y <= 1; y++) {
+      vec2 tap = clamp(atlas_uv + vec2(x, y) * texel, slot_min, slot_max);
+      float map_depth = texture(sampler2D(shadow_atlas, shadow_sampler), tap).r;
+      lit += ndc.z - local_lights.data.params.z <= map_depth ? 1.0 : 0.0;
+    }
+  }
+  return lit / 9.0;
+}
+
+// Diffuse light from the spot and point lights in range
+vec3 local_light_diffuse(vec3 normal) {
+  vec3 diffuse = vec3(0.0);
+  int light_count = int(local_lights.data.params.x);
+  for (int i = 0; i < light_count; i++) {
+    LocalLightData light = local_lights.data.lights[i];
+    vec3 to_frag = inGlobalPos.xyz - light.position.xyz;
+    float dist = length(to_frag);
+    if (dist >= light.position.w) {
+      continue;
+    }
+    vec3 to_light = -to_frag / max(dist, 0.0001);
+    float falloff = 1.0 - dist / light.position.w;
+    float attenuation = falloff * falloff * max(dot(normal, to_light), 0.0);
+    if (light.color.w == 1.0) {
+      attenuation *= smoothstep(light.direction.w, light.params.x, dot(-to_light, light.direction.xyz));
+    }
+    if (attenuation <= 0.0) {
+      continue;
+    }
+    diffuse += light.color.rgb * attenuation * local_light_visibility(light, to_frag);
+  }
+  return diffuse;
+}
+#endif
+
+#ifdef REFLECTIONS
+layout(std140, set = 2, binding = 6) uniform ReflectionProbesWrap { ReflectionProbesData data; } reflection_probes;
+layout(set = 2, binding = 7) uniform textureCubeArray probe_cubes;
+layout(set = 2, binding = 8) uniform sampler probe_sampler;
+
+// Same as ReflectionProbe::weight in reflection_probe.rs
+float probe_weight(ReflectionProbeData probe, vec3 point) {
+  vec3 inside = min(point - probe.box_min.xyz, probe.box_max.xyz - point);
+  float depth = min(min(inside.x, inside.y), inside.z);
+  if (depth < 0.0) {
+    return 0.0;
+  }
+  if (probe.position.w <= 0.0) {
+    return 1.0;
+  }
+  return min(depth / probe.position.w, 1.0);
+}
+
+// Same as ReflectionProbe::box_projected_dir, from the probe to where the ray leaves its box
+vec3 box_projected_dir(ReflectionProbeData probe, vec3 point, vec3 dir) {
+  vec3 exits = max((probe.box_max.xyz - point) / dir, (probe.box_min.xyz - point) / dir);
+  return point + dir * min(min(exits.x, exits.y), exits.z) - probe.position.xyz;
+}
+
+// Probes around the fragment blended like blend_weights in reflection_probe.rs, black where none
+// reach
+vec3 probe_reflection(vec3 dir, float roughness) {
+  vec3 reflection = vec3(0.0);
+  float total = 0.0;
+  int probe_count = int(reflection_probes.data.params.x);
+  float level = roughness * reflection_probes.data.params.y;
+  for (int i = 0; i < probe_count && total < 1.0; i++) {
+    ReflectionProbeData probe = reflection_probes.data.probes[i];
+    float weight = probe_weight(probe, inGlobalPos.xyz) * (1.0 - total);
+    if (weight <= 0.0) {
+      continue;
+    }
+    vec4 cube_dir = vec4(box_projected_dir(probe, inGlobalPos.xyz, dir), float(i));
+    reflection += textureLod(samplerCubeArray(probe_cubes, probe_sampler), cube_dir, level).rgb * weight;
+    total += weight;
+  }
+  return reflection;
+}
+#endif
+
+void main() {
+  vec2 albedo_uv = inUV.xy;
+#ifdef VERTEX_STREAMS
+  if (material.data.params.w != 0.0) {
+    albedo_uv = inUV2;
+  }
+#endif
+  vec4 color = texture(sampler2D(albedo_texture, albedo_sampler), albedo_uv) * material.data.base_color;
+#ifdef VERTEX_STREAMS
+  color *= mix(vec4(1.0), inColor, material.data.params.z);
+#endif
+#ifdef ALPHA_CUTOUT
+  if (color.a < material.data.params.x) {
+    discard;
+  }
+#endif
+#ifdef LIT
+  float ambient = material.data.params.y;
+  vec3 normal = normalize(inNormal.xyz);
+  vec3 sun = shadow.data.sun_color.rgb * max(dot(normal, shadow.data.sun_dir.xyz), 0.0) * sun_visibility();
+  color.rgb *= ambient * shadow.data.ambient.rgb + (1.0 - ambient) * (sun + local_light_diffuse(normal));
+#endif
+#ifdef LIGHTMAPPED
+  color.rgb *= texture(sampler2D(lightmap_texture, lightmap_sampler), inUV2).rgb * LIGHTMAP_RANGE;
+#endif
+#ifdef REFLECTIONS
+  // Schlick's fresnel, rough surfaces brighten less towards grazing angles
+  vec3 surface_normal = normalize(inNormal.xyz);
+  vec3 view_dir = normalize(inGlobalPos.xyz - camera_buffer.data.pos.xyz);
+  float roughness = material.data.surface.x;
+  float reflectivity = material.data.surface.y;
+  float grazing = pow(1.0 - max(dot(-view_dir, surface_normal), 0.0), 5.0);
+  float fresnel = reflectivity + (max(1.0 - roughness, reflectivity) - reflectivity) * grazing;
+  color.rgb += probe_reflection(reflect(view_dir, surface_normal), roughness) * fresnel;
+#endif
+  outFragColor = color;
+}
@@ -0,0 +1,4 @@
+#version 460
+
+#define VERTEX_STREAMS
+#include "triangle_vertex.glsl"
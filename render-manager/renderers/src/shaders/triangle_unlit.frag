@@ -0,0 +1,3 @@
+#version 460
+
+#include "triangle_material.glsl"
@@ -0,0 +1,52 @@
+#include "common_structs.glsl"
+
+layout (location = 0) out vec4 outGlobalPos;
+layout (location = 1) out vec4 outUV;
+layout (location = 2) out vec4 outNormal;
+#ifdef VERTEX_STREAMS
+layout (location = 3) out vec4 outColor;
+layout (location = 4) out vec2 outUV2;
+#endif
+
+layout(std430, set = 0, binding = 0) readonly buffer VertexArray { VertexData verts[]; } vertex_buffer;
+layout(std430, set = 0, binding = 1) readonly buffer IndexArray { uint inds[]; } index_buffer;
+layout(std140, set = 0, binding = 2) uniform ObjectWrap { ObjectData data; } object_transfer;
+layout(std430, set = 0, binding = 3) readonly buffer MorphDeltaArray { MorphDelta deltas[]; } morph_buffer;
+layout(std140, set = 0, binding = 4) uniform MorphWrap { MorphData data; } morph_weights;
+#ifdef VERTEX_STREAMS
+layout(std430, set = 3, binding = 0) readonly buffer ColorArray { vec4 colors[]; } color_buffer;
+layout(std430, set = 3, binding = 1) readonly buffer UV2Array { vec2 uvs[]; } uv2_buffer;
+layout(std140, set = 3, binding = 2) uniform StreamsWrap { StreamsData data; } streams;
+#endif
+
+layout(push_constant) uniform CamWrap { CamData data; } camera_buffer;
+
+vec4 invert_y_axis(vec4 v) {
+  return vec4(v.x, -v.y, v.z, v.w);
+}
+
+void main() {
+  uint vert_id = index_buffer.inds[gl_VertexIndex];
+  vec4 position = vertex_buffer.verts[vert_id].position;
+  vec3 normal = vertex_buffer.verts[vert_id].normal.xyz;
+  uint target_count = morph_weights.data.counts.x;
+  uint vert_count = morph_weights.data.counts.y;
+  for (uint i = 0; i < target_count; i++) {
+    float weight = morph_weights.data.weights[i / 4][i % 4];
+    if (weight != 0.0) {
+      MorphDelta delta = morph_buffer.deltas[i * vert_count + vert_id];
+      position.xyz += weight * delta.position.xyz;
+      normal += weight * delta.normal.xyz;
+    }
+  }
+  vec4 global_pos = object_transfer.data.transform * position;
+  gl_Position = invert_y_axis(camera_buffer.data.view_proj_mat * global_pos);
+  outGlobalPos = global_pos;
+  outUV = vertex_buffer.verts[vert_id].uv;
+  outNormal = vec4(normalize(mat3(object_transfer.data.transform) * normal), 0.0);
+#ifdef VERTEX_STREAMS
+  outColor = streams.data.present.x != 0 ? color_buffer.colors[vert_id] : vec4(1.0);
+  outUV2 = streams.data.present.y != 0 ? uv2_buffer.uvs[vert_id] : outUV.xy;
+#endif
+  //debugPrintfEXT("%1.2v4f\n", gl_Position);
+}
@@ -0,0 +1,17 @@
+#version 460
+
+// Screen uv the surface moved by since the last frame, w 1 wherever a mesh was drawn
+
+layout (location = 0) in vec4 inClipPos;
+layout (location = 1) in vec4 inPrevClipPos;
+
+layout (location = 0) out vec4 outVelocity;
+
+vec2 clip_to_uv(vec4 clip_pos) {
+  vec2 ndc = clip_pos.xy / clip_pos.w;
+  return vec2(ndc.x, -ndc.y) * 0.5 + 0.5;
+}
+
+void main() {
+  outVelocity = vec4(clip_to_uv(inClipPos) - clip_to_uv(inPrevClipPos), 0.0, 1.0);
+}
@@ -0,0 +1,29 @@
+// Screen uv a pixel moved by since the last frame. The velocity map has w 1 where a mesh was drawn,
+// other pixels only moved with the camera, which is found from their depth
+
+vec2 camera_velocity(mat4 inv_view_proj, mat4 prev_view_proj, vec2 uv, float depth) {
+  vec2 ndc = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+  vec4 world_pos = inv_view_proj * vec4(ndc, depth, 1.0);
+  vec4 prev_clip_pos = prev_view_proj * vec4(world_pos.xyz / world_pos.w, 1.0);
+  vec2 prev_ndc = prev_clip_pos.xy / prev_clip_pos.w;
+  return uv - (vec2(prev_ndc.x, -prev_ndc.y) * 0.5 + 0.5);
+}
+
+vec2 pixel_velocity(
+  sampler2D velocity_map,
+  sampler2D scene_depth,
+  VelocityFrameData frame,
+  ivec2 texel,
+  vec2 uv
+) {
+  vec4 velocity = texelFetch(velocity_map, texel, 0);
+  if (velocity.w == 0.0) {
+    return camera_velocity(
+      frame.inv_view_proj,
+      frame.prev_view_proj,
+      uv,
+      texelFetch(scene_depth, texel, 0).r
+    );
+  }
+  return velocity.xy;
+}
@@ -0,0 +1,42 @@
+#version 460
+
+#include "common_structs.glsl"
+
+layout (location = 0) out vec4 outClipPos;
+layout (location = 1) out vec4 outPrevClipPos;
+
+layout(std430, set = 0, binding = 0) readonly buffer VertexArray { VertexData verts[]; } vertex_buffer;
+layout(std430, set = 0, binding = 1) readonly buffer IndexArray { uint inds[]; } index_buffer;
+layout(std140, set = 0, binding = 2) uniform ObjectWrap { ObjectData data; } object_transfer;
+layout(std430, set = 0, binding = 3) readonly buffer MorphDeltaArray { MorphDelta deltas[]; } morph_buffer;
+layout(std140, set = 0, binding = 4) uniform MorphWrap { MorphData data; } morph_weights;
+layout(std430, set = 0, binding = 5) readonly buffer PrevVertexArray { VertexData verts[]; } prev_vertex_buffer;
+
+layout(std140, set = 1, binding = 0) uniform VelocityFrameWrap { VelocityFrameData data; } velocity_frame;
+
+vec4 invert_y_axis(vec4 v) {
+  return vec4(v.x, -v.y, v.z, v.w);
+}
+
+void main() {
+  uint vert_id = index_buffer.inds[gl_VertexIndex];
+  vec4 position = vertex_buffer.verts[vert_id].position;
+  // Where the vertex was in model space, only skinned meshes move theirs
+  vec4 prev_position = prev_vertex_buffer.verts[vert_id].position;
+  uint target_count = morph_weights.data.counts.x;
+  uint vert_count = morph_weights.data.counts.y;
+  for (uint i = 0; i < target_count; i++) {
+    float weight = morph_weights.data.weights[i / 4][i % 4];
+    if (weight != 0.0) {
+      vec3 delta = weight * morph_buffer.deltas[i * vert_count + vert_id].position.xyz;
+      position.xyz += delta;
+      prev_position.xyz += delta;
+    }
+  }
+  // Same math as triangle.vert, so the depth matches the scene pass exactly
+  vec4 global_pos = object_transfer.data.transform * position;
+  gl_Position = invert_y_axis(velocity_frame.data.scene_view_proj * global_pos);
+  outClipPos = velocity_frame.data.view_proj * global_pos;
+  outPrevClipPos =
+    velocity_frame.data.prev_view_proj * (object_transfer.data.prev_transform * prev_position);
+}
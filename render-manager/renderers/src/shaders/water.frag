@@ -0,0 +1,57 @@
+#version 460
+
+#include "common_structs.glsl"
+
+layout (location = 0) in vec4 inGlobalPos;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout(std140, set = 0, binding = 0) uniform WaterWrap { WaterData data; } water;
+layout(set = 0, binding = 1) uniform texture2D normal_map;
+layout(set = 0, binding = 2) uniform sampler normal_sampler;
+// Scene seen from under the plane, only read when the plane has reflection
+layout(set = 1, binding = 0) uniform texture2D reflection_map;
+layout(set = 1, binding = 1) uniform sampler reflection_sampler;
+
+layout(push_constant) uniform FrameWrap { WaterFrameData data; } frame;
+
+const float SUN_SHININESS = 128.0;
+// Second layer is scaled so the two layers don't repeat together
+const float SECOND_LAYER_SCALE = 1.37;
+
+vec3 sample_normal(vec2 uv) {
+  return texture(sampler2D(normal_map, normal_sampler), uv).xyz * 2.0 - 1.0;
+}
+
+void main() {
+  WaterData data = water.data;
+  float time = frame.data.frame.x;
+
+  // Normal map is tangent space, x along the plane's x, y along its z and z out of the plane
+  vec2 uv = inGlobalPos.xz / data.shape.z;
+  vec3 n0 = sample_normal(uv + data.scroll.xy * time);
+  vec3 n1 = sample_normal(uv * SECOND_LAYER_SCALE + data.scroll.zw * time);
+  vec3 tangent_normal = normalize(vec3((n0.xy + n1.xy) * data.params.x, n0.z * n1.z));
+  mat3 basis = mat3(data.transform);
+  vec3 normal = normalize(
+    normalize(basis[0]) * tangent_normal.x
+      + normalize(basis[2]) * tangent_normal.y
+      + normalize(basis[1]) * tangent_normal.z
+  );
+
+  vec3 view_dir = normalize(frame.data.camera.pos.xyz - inGlobalPos.xyz);
+  float fresnel = pow(1.0 - clamp(abs(dot(normal, view_dir)), 0.0, 1.0), data.shape.w);
+
+  vec3 reflection = data.sky_color.rgb;
+  if (data.params.z > 0.5) {
+    // The reflection camera is mirrored below the plane, so its image is upside down
+    vec2 screen_uv = gl_FragCoord.xy * frame.data.frame.zw;
+    vec2 reflect_uv = vec2(screen_uv.x, 1.0 - screen_uv.y) + tangent_normal.xy * data.params.y;
+    reflection = texture(sampler2D(reflection_map, reflection_sampler), clamp(reflect_uv, 0.001, 0.999)).rgb;
+  }
+
+  vec3 sun_dir = frame.data.sun.xyz;
+  float sun = pow(max(dot(reflect(-sun_dir, normal), view_dir), 0.0), SUN_SHININESS) * frame.data.sun.w;
+  vec3 color = mix(data.color.rgb, reflection, fresnel) + sun;
+  outFragColor = vec4(color, clamp(mix(data.color.a, 1.0, fresnel) + sun, 0.0, 1.0));
+}
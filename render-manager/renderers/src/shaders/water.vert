@@ -0,0 +1,26 @@
+#version 460
+
+#include "common_structs.glsl"
+
+layout (location = 0) out vec4 outGlobalPos;
+
+layout(std140, set = 0, binding = 0) uniform WaterWrap { WaterData data; } water;
+
+layout(push_constant) uniform FrameWrap { WaterFrameData data; } frame;
+
+// Two triangles over the plane, scaled by its size
+const vec2 QUAD_CORNERS[6] = vec2[](
+  vec2(-0.5, -0.5), vec2(0.5, -0.5), vec2(0.5, 0.5),
+  vec2(-0.5, -0.5), vec2(0.5, 0.5), vec2(-0.5, 0.5)
+);
+
+vec4 invert_y_axis(vec4 v) {
+  return vec4(v.x, -v.y, v.z, v.w);
+}
+
+void main() {
+  vec2 corner = QUAD_CORNERS[gl_VertexIndex] * water.data.shape.xy;
+  vec4 global_pos = water.data.transform * vec4(corner.x, 0.0, corner.y, 1.0);
+  gl_Position = invert_y_axis(frame.data.camera.view_proj_mat * global_pos);
+  outGlobalPos = global_pos;
+}
@@ -0,0 +1,131 @@
+// Square region of the atlas in texels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasSlot {
+  pub x: u32,
+  pub y: u32,
+  pub size: u32,
+}
+
+impl AtlasSlot {
+  fn quarters(&self) -> [AtlasSlot; 4] {
+    let size = self.size / 2;
+    [(0, 0), (size, 0), (0, size), (size, size)].map(|(x, y)| AtlasSlot {
+      x: self.x + x,
+      y: self.y + y,
+      size,
+    })
+  }
+
+  // Offset and scale of the slot in atlas uvs
+  pub fn uv_rect(&self, atlas_size: u32) -> [f32; 4] {
+    let atlas_size = atlas_size as f32;
+    let size = self.size as f32 / atlas_size;
+    [self.x as f32 / atlas_size, self.y as f32 / atlas_size, size, size]
+  }
+}
+
+// Packs shadow maps of power of 2 sides into one square depth image, repacked from scratch every
+// frame. Free squares are split into quarters until they fit, so slots never overlap and the
+// smallest free square that fits is always used first
+pub struct ShadowAtlas {
+  size: u32,
+  min_slot_size: u32,
+  free: Vec<AtlasSlot>,
+}
+
+impl ShadowAtlas {
+  // size is rounded up to a power of 2
+  pub fn new(size: u32, min_slot_size: u32) -> Self {
+    let size = size.next_power_of_two();
+    Self {
+      size,
+      min_slot_size: min_slot_size.next_power_of_two().min(size),
+      free: vec![AtlasSlot { x: 0, y: 0, size }],
+    }
+  }
+
+  pub fn size(&self) -> u32 {
+    self.size
+  }
+
+  // Everything is free again, for the next frame's lights
+  pub fn clear(&mut self) {
+    self.free = vec![AtlasSlot { x: 0, y: 0, size: self.size }];
+  }
+
+  // Slot side for a requested side, a power of 2 within min_slot_size and the atlas size
+  pub fn slot_size(&self, requested: u32) -> u32 {
+    requested.next_power_of_two().clamp(self.min_slot_size, self.size)
+  }
+
+  // All count slots of the side or none of them, like the six faces of a point light. Call in
+  // priority order, lights allocated later are the ones left without a shadow when it fills up
+  pub fn allocate(&mut self, requested: u32, count: usize) -> Option<Vec<AtlasSlot>> {
+    let size = self.slot_size(requested);
+    let free_before = self.free.clone();
+    let slots = (0..count).map(|_| self.allocate_one(size)).collect::<Option<Vec<_>>>();
+    if slots.is_none() {
+      self.free = free_before;
+    }
+    slots
+  }
+
+  fn allocate_one(&mut self, size: u32) -> Option<AtlasSlot> {
+    let (idx, _) = self
+      .free
+      .iter()
+      .enumerate()
+      .filter(|(_, slot)| slot.size >= size)
+      .min_by_key(|(_, slot)| slot.size)?;
+    let mut slot = self.free.remove(idx);
+    while slot.size > size {
+      let [first, rest @ ..] = slot.quarters();
+      self.free.extend(rest);
+      slot = first;
+    }
+    Some(slot)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sizes_round_up_to_powers_of_2_within_the_atlas() {
+    let atlas = ShadowAtlas::new(1000, 100);
+    assert_eq!(atlas.size(), 1024);
+    assert_eq!(atlas.slot_size(1), 128);
+    assert_eq!(atlas.slot_size(129), 256);
+    assert_eq!(atlas.slot_size(4096), 1024);
+    // A min slot bigger than the atlas is the whole atlas
+    assert_eq!(ShadowAtlas::new(256, 1000).slot_size(1), 256);
+  }
+
+  #[test]
+  fn split_squares_are_used_up_before_whole_ones() {
+    let mut atlas = ShadowAtlas::new(1024, 64);
+    let first = atlas.allocate(256, 1).expect("slot should fit");
+    assert_eq!(first, vec![AtlasSlot { x: 0, y: 0, size: 256 }]);
+    // The rest of the 512 square it came out of, leaving the other three 512 squares whole
+    let rest = atlas.allocate(256, 3).expect("slots should fit");
+    assert!(rest.iter().all(|slot| slot.x < 512 && slot.y < 512));
+    assert_eq!(atlas.allocate(512, 3).map(|slots| slots.len()), Some(3));
+    assert_eq!(atlas.allocate(64, 1), None);
+  }
+
+  #[test]
+  fn clearing_frees_the_whole_atlas() {
+    let mut atlas = ShadowAtlas::new(512, 64);
+    assert!(atlas.allocate(512, 1).is_some());
+    assert_eq!(atlas.allocate(64, 1), None);
+    atlas.clear();
+    assert_eq!(atlas.allocate(512, 1), Some(vec![AtlasSlot { x: 0, y: 0, size: 512 }]));
+  }
+
+  #[test]
+  fn uv_rects_are_fractions_of_the_atlas() {
+    let slot = AtlasSlot { x: 256, y: 512, size: 128 };
+    assert_eq!(slot.uv_rect(1024), [0.25, 0.5, 0.125, 0.125]);
+  }
+}
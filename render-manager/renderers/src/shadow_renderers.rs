@@ -0,0 +1,1138 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdImage, AdImageView, AdSampler,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdFrameBuffer, AdPipeline, AdRenderPass},
+  ash_sync_wrappers::AdFence,
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{
+  glam,
+  light::{LightShape, LocalLight, SunLight},
+  triangle_mesh::TriMeshGenerator,
+  Camera3D,
+};
+
+use crate::{
+  reflection_probe_renderers::ReflectionProbeRenderer,
+  shadow_atlas::{AtlasSlot, ShadowAtlas},
+  triangle_mesh_renderers::TriMeshDraw,
+};
+
+static TRI_MESH_VERT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/triangle.vert.spv");
+
+pub const MIN_SHADOW_MAP_SIZE: u32 = 256;
+pub const MAX_SHADOW_MAP_SIZE: u32 = 4096;
+// MAX_SHADOW_CASCADES in common_structs.glsl has to match
+pub const MAX_SHADOW_CASCADES: u32 = 4;
+// Casters up to this far towards the sun from a cascade still cast into it
+const SHADOW_CASTER_DEPTH: f32 = 100.0;
+// 0 spaces cascade splits evenly, 1 logarithmically which gives near cascades more detail
+const CASCADE_SPLIT_LAMBDA: f32 = 0.75;
+// Share of a cascade from its border faded into the next one
+const CASCADE_BLEND_BAND: f32 = 0.1;
+// On top of the slope scaled bias the casters are drawn with, in shadow map depth
+const SHADOW_MAP_BIAS: f32 = 0.0005;
+// MAX_LOCAL_LIGHTS and MAX_LIGHT_SHADOW_SLOTS in common_structs.glsl have to match
+pub const MAX_LOCAL_LIGHTS: usize = 64;
+pub const MAX_LIGHT_SHADOW_SLOTS: usize = 64;
+// Atlas slot sides for lights within their range of the camera, farther ones get smaller slots
+const SPOT_SHADOW_SIZE: u32 = 1024;
+const POINT_SHADOW_FACE_SIZE: u32 = 512;
+const MIN_LIGHT_SHADOW_SIZE: u32 = 64;
+// Near plane of local light shadows as a share of the light's range
+const LIGHT_SHADOW_NEAR: f32 = 0.01;
+const LIGHT_SHADOW_BIAS: f32 = 0.0001;
+// Per frame sets, each resize makes a new batch
+const MAX_SHADOW_SETS: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowTechnique {
+  Off,
+  ShadowMap,
+  // Shadows traced into a mask by RtShadowRenderer from the depth prepass
+  RayTraced,
+}
+
+impl ShadowTechnique {
+  // Mode in ShadowData params
+  fn shader_mode(&self) -> f32 {
+    match self {
+      ShadowTechnique::Off => 0.0,
+      ShadowTechnique::ShadowMap => 1.0,
+      ShadowTechnique::RayTraced => 2.0,
+    }
+  }
+}
+
+// How the view is covered by shadow maps, only drawn with ShadowTechnique::ShadowMap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowCascades {
+  // Kept within 1..=MAX_SHADOW_CASCADES
+  pub count: u32,
+  // Side of each cascade's square map, kept within MIN_SHADOW_MAP_SIZE..=MAX_SHADOW_MAP_SIZE
+  pub map_size: u32,
+  // From the camera, past it the sun isn't shadowed
+  pub distance: f32,
+}
+
+// A light's shadow map in the atlas, drawn with camera
+#[derive(Debug, Clone, Copy)]
+pub struct LightShadowView {
+  pub slot: AtlasSlot,
+  pub camera: Camera3D,
+}
+
+// Depth prepass framebuffers and masks
+type SceneTargets = (Vec<Arc<AdFrameBuffer>>, Vec<Arc<AdImageView>>);
+// Per frame, framebuffers drawing into each cascade's layer and a view of all the layers
+type ShadowMaps = (Vec<Vec<Arc<AdFrameBuffer>>>, Vec<Arc<AdImageView>>);
+
+// Cameras a light's shadow is drawn with. One for spots, six for points facing +X, -X, +Y, -Y, +Z
+// and -Z, the order triangle_material.glsl picks them in
+pub fn light_shadow_cameras(light: &LocalLight) -> Vec<Camera3D> {
+  let near = (light.range * LIGHT_SHADOW_NEAR).max(0.01);
+  let camera = |fov: f32, dir: glam::Vec3| {
+    let up = if dir.y.abs() > 0.99 { glam::Vec3::Z } else { glam::Vec3::Y };
+    Camera3D {
+      pos: light.position.extend(1.0),
+      look_dir: dir.extend(0.0),
+      view_proj_mat: glam::Mat4::perspective_rh(fov, 1.0, near, light.range.max(2.0 * near))
+        * glam::Mat4::look_at_rh(light.position, light.position + dir, up),
+    }
+  };
+  match light.shape {
+    LightShape::Point => [
+      glam::Vec3::X,
+      glam::Vec3::NEG_X,
+      glam::Vec3::Y,
+      glam::Vec3::NEG_Y,
+      glam::Vec3::Z,
+      glam::Vec3::NEG_Z,
+    ]
+    .map(|dir| camera(std::f32::consts::FRAC_PI_2, dir))
+    .to_vec(),
+    LightShape::Spot { direction, outer_angle, .. } => vec![camera(
+      (2.0 * outer_angle).clamp(0.01, 3.0),
+      direction.try_normalize().unwrap_or(glam::Vec3::NEG_Y),
+    )],
+  }
+}
+
+// View distances each cascade ends at, nearest first, from near out to distance
+pub fn cascade_splits(near: f32, distance: f32, count: u32) -> Vec<f32> {
+  (1..=count)
+    .map(|i| {
+      let t = i as f32 / count as f32;
+      let log_split = near * (distance / near).powf(t);
+      let even_split = near + (distance - near) * t;
+      CASCADE_SPLIT_LAMBDA * log_split + (1.0 - CASCADE_SPLIT_LAMBDA) * even_split
+    })
+    .collect()
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ShadowData {
+  light_view_proj: [glam::Mat4; MAX_SHADOW_CASCADES as usize],
+  params: glam::Vec4,
+  cascade_params: glam::Vec4,
+  sun_dir: glam::Vec4,
+  sun_color: glam::Vec4,
+  ambient: glam::Vec4,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct LocalLightData {
+  position: glam::Vec4,
+  color: glam::Vec4,
+  direction: glam::Vec4,
+  params: glam::Vec4,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct LightShadowSlotData {
+  view_proj: glam::Mat4,
+  rect: glam::Vec4,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct LocalLightsData {
+  params: glam::Vec4,
+  lights: [LocalLightData; MAX_LOCAL_LIGHTS],
+  slots: [LightShadowSlotData; MAX_LIGHT_SHADOW_SLOTS],
+}
+
+impl LocalLightsData {
+  const EMPTY: Self = Self {
+    params: glam::Vec4::ZERO,
+    lights: [LocalLightData {
+      position: glam::Vec4::ZERO,
+      color: glam::Vec4::ZERO,
+      direction: glam::Vec4::ZERO,
+      params: glam::Vec4::ZERO,
+    }; MAX_LOCAL_LIGHTS],
+    slots: [LightShadowSlotData { view_proj: glam::Mat4::IDENTITY, rect: glam::Vec4::ZERO };
+      MAX_LIGHT_SHADOW_SLOTS],
+  };
+}
+
+impl ShadowData {
+  fn unshadowed(sun: &SunLight) -> Self {
+    Self {
+      light_view_proj: [glam::Mat4::IDENTITY; MAX_SHADOW_CASCADES as usize],
+      params: glam::Vec4::ZERO,
+      cascade_params: glam::Vec4::ZERO,
+      sun_dir: sun.direction.normalize_or_zero().extend(0.0),
+      sun_color: sun.radiance().extend(0.0),
+      ambient: glam::Vec4::from(sun.ambient),
+    }
+  }
+}
+
+// Shadows from the sun and the local lights shading lit materials, bound as set 2 of the mesh,
+// crowd and foliage pipelines along with the reflection probes. Only meshes cast shadows, drawn solid so alpha cutouts cast their
+// whole quad. Targets the technique doesn't use are kept at 1x1 so every set has something to bind
+#[derive(getset::Getters, getset::CopyGetters)]
+pub struct ShadowRenderer {
+  allocator: Arc<Mutex<Allocator>>,
+  #[getset(get_copy = "pub")]
+  technique: ShadowTechnique,
+  #[getset(get = "pub")]
+  dset_layout: Arc<AdDescriptorSetLayout>,
+  // Depth only, ends in DEPTH_STENCIL_READ_ONLY_OPTIMAL for shaders to read
+  render_pass: Arc<AdRenderPass>,
+  caster_pipeline: AdPipeline,
+  prepass_pipeline: AdPipeline,
+  depth_format: vk::Format,
+  // Even when the technique doesn't draw shadow maps
+  #[getset(get_copy = "pub")]
+  cascades: ShadowCascades,
+  // Per frame, one per cascade
+  cascade_frame_buffers: Vec<Vec<Arc<AdFrameBuffer>>>,
+  // Per frame, every cascade's layer for the lit materials
+  shadow_map_views: Vec<Arc<AdImageView>>,
+  // Scene depth of meshes the rays start from, only scene sized when traced
+  #[getset(get = "pub")]
+  prepass_frame_buffers: Vec<Arc<AdFrameBuffer>>,
+  // R32_SFLOAT sun visibility per scene pixel, always in the GENERAL layout
+  #[getset(get = "pub")]
+  mask_views: Vec<Arc<AdImageView>>,
+  // Local light shadows packed anew every frame, only drawn when the sun is shadowed too
+  atlas_frame_buffers: Vec<Arc<AdFrameBuffer>>,
+  shadow_buffers: Vec<Arc<AdBuffer>>,
+  // Per frame, only the sun is set
+  sun_unshadowed_buffers: Vec<Arc<AdBuffer>>,
+  local_light_buffers: Vec<Arc<AdBuffer>>,
+  shadow_dsets: Vec<AdDescriptorSet>,
+  // Per frame, the local lights of shadow_dsets with the sun unshadowed
+  sun_unshadowed_dsets: Vec<AdDescriptorSet>,
+  unshadowed_dset: AdDescriptorSet,
+}
+
+impl ShadowRenderer {
+  #[allow(clippy::too_many_arguments)]
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    cmd_buffer: &AdCommandBuffer,
+    tri_mesh_gen: &TriMeshGenerator,
+    technique: ShadowTechnique,
+    cascades: ShadowCascades,
+    atlas_size: u32,
+    depth_format: vk::Format,
+    scene_resolution: vk::Extent2D,
+    reflection_probes: &ReflectionProbeRenderer,
+    frames_in_flight: usize,
+  ) -> Result<Self, String> {
+    let dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::UNIFORM_BUFFER),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLER),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::UNIFORM_BUFFER),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::UNIFORM_BUFFER),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLER),
+      ],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_SHADOW_SETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::UNIFORM_BUFFER,
+          descriptor_count: 3 * MAX_SHADOW_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLED_IMAGE,
+          descriptor_count: 4 * MAX_SHADOW_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLER,
+          descriptor_count: 2 * MAX_SHADOW_SETS,
+        },
+      ],
+    )?);
+    let sampler = Arc::new(AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::NEAREST)
+        .min_filter(vk::Filter::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    )?);
+
+    let render_pass = Arc::new(AdRenderPass::new(
+      ash_device.clone(),
+      vk::RenderPassCreateFlags::default(),
+      &[vk::AttachmentDescription::default()
+        .format(depth_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)],
+      &[vk::SubpassDescription::default()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(
+          &vk::AttachmentReference::default()
+            .attachment(0)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+        )],
+      &[
+        vk::SubpassDependency::default()
+          .src_subpass(vk::SUBPASS_EXTERNAL)
+          .dst_subpass(0)
+          .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+          .dst_stage_mask(
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+              | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+          )
+          .src_access_mask(vk::AccessFlags::SHADER_READ)
+          .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE),
+        vk::SubpassDependency::default()
+          .src_subpass(0)
+          .dst_subpass(vk::SUBPASS_EXTERNAL)
+          .src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+          .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+          .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+          .dst_access_mask(vk::AccessFlags::SHADER_READ),
+      ],
+    )?);
+    let depth_pipeline = |depth_bias: bool| {
+      AdPipeline::new(
+        render_pass.clone(),
+        0,
+        HashMap::from([(vk::ShaderStageFlags::VERTEX, TRI_MESH_VERT_SHADER_CODE)]),
+        &[tri_mesh_gen.mesh_dset_layout()],
+        (vk::ShaderStageFlags::VERTEX, std::mem::size_of::<Camera3D>() as u32),
+        // Both faces, double sided materials don't have their own pipelines here
+        vk::PipelineRasterizationStateCreateInfo::default()
+          .cull_mode(vk::CullModeFlags::NONE)
+          .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+          .polygon_mode(vk::PolygonMode::FILL)
+          .line_width(1.0)
+          .depth_bias_enable(depth_bias)
+          .depth_bias_constant_factor(1.25)
+          .depth_bias_slope_factor(1.75),
+        &vk::PipelineColorBlendStateCreateInfo::default(),
+        &vk::PipelineDepthStencilStateCreateInfo::default()
+          .depth_test_enable(true)
+          .depth_write_enable(true)
+          .depth_compare_op(vk::CompareOp::LESS),
+        vk::SampleCountFlags::TYPE_1,
+      )
+    };
+    let caster_pipeline =
+      depth_pipeline(true).map_err(|e| format!("at creating shadow caster pipeline: {e}"))?;
+    let prepass_pipeline =
+      depth_pipeline(false).map_err(|e| format!("at creating depth prepass pipeline: {e}"))?;
+
+    let shadow_data_buffers = |name: &str| {
+      (0..frames_in_flight)
+        .map(|i| {
+          AdBuffer::new(
+            ash_device.clone(),
+            allocator.clone(),
+            MemoryLocation::CpuToGpu,
+            &format!("{name}_{i}"),
+            vk::BufferCreateFlags::empty(),
+            std::mem::size_of::<ShadowData>() as u64,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+          )
+          .map(Arc::new)
+        })
+        .collect::<Result<Vec<_>, String>>()
+    };
+    let shadow_buffers = shadow_data_buffers("shadow_data")?;
+    let sun_unshadowed_buffers = shadow_data_buffers("shadow_data_sun_unshadowed")?;
+    for buffer in shadow_buffers.iter().chain(sun_unshadowed_buffers.iter()) {
+      buffer.write_data(0, &[ShadowData::unshadowed(&SunLight::default())])?;
+    }
+    let unshadowed_buffer = Arc::new(AdBuffer::new(
+      ash_device.clone(),
+      allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      "shadow_data_unshadowed",
+      vk::BufferCreateFlags::empty(),
+      std::mem::size_of::<ShadowData>() as u64,
+      vk::BufferUsageFlags::UNIFORM_BUFFER,
+    )?);
+    unshadowed_buffer.write_data(0, &[ShadowData::unshadowed(&SunLight::default())])?;
+    let local_light_buffers = (0..frames_in_flight)
+      .map(|i| {
+        AdBuffer::new(
+          ash_device.clone(),
+          allocator.clone(),
+          MemoryLocation::CpuToGpu,
+          &format!("local_light_data_{i}"),
+          vk::BufferCreateFlags::empty(),
+          std::mem::size_of::<LocalLightsData>() as u64,
+          vk::BufferUsageFlags::UNIFORM_BUFFER,
+        )
+        .map(Arc::new)
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    let no_light_buffer = Arc::new(AdBuffer::new(
+      ash_device.clone(),
+      allocator.clone(),
+      MemoryLocation::CpuToGpu,
+      "local_light_data_none",
+      vk::BufferCreateFlags::empty(),
+      std::mem::size_of::<LocalLightsData>() as u64,
+      vk::BufferUsageFlags::UNIFORM_BUFFER,
+    )?);
+    no_light_buffer.write_data(0, &[LocalLightsData::EMPTY])?;
+
+    let cascades = ShadowCascades {
+      count: cascades.count.clamp(1, MAX_SHADOW_CASCADES),
+      map_size: cascades.map_size.clamp(MIN_SHADOW_MAP_SIZE, MAX_SHADOW_MAP_SIZE),
+      distance: cascades.distance,
+    };
+    let (cascade_frame_buffers, shadow_map_views) = Self::create_shadow_maps(
+      &render_pass,
+      &allocator,
+      depth_format,
+      technique,
+      cascades,
+      frames_in_flight,
+    )?;
+    let (prepass_frame_buffers, mask_views) = Self::create_scene_targets(
+      &render_pass,
+      &allocator,
+      depth_format,
+      match technique {
+        ShadowTechnique::RayTraced => scene_resolution,
+        ShadowTechnique::Off | ShadowTechnique::ShadowMap => vk::Extent2D { width: 1, height: 1 },
+      },
+      frames_in_flight,
+    )?;
+    let atlas_frame_buffers = Self::create_depth_targets(
+      &render_pass,
+      &allocator,
+      depth_format,
+      "shadow_atlas",
+      match technique {
+        ShadowTechnique::ShadowMap | ShadowTechnique::RayTraced => {
+          vk::Extent2D { width: atlas_size, height: atlas_size }
+        }
+        ShadowTechnique::Off => vk::Extent2D { width: 1, height: 1 },
+      },
+      frames_in_flight,
+    )?;
+    Self::init_targets(
+      cmd_buffer,
+      &shadow_map_views
+        .iter()
+        .cloned()
+        .chain(
+          prepass_frame_buffers
+            .iter()
+            .chain(atlas_frame_buffers.iter())
+            .map(|fb| fb.attachments()[0].clone()),
+        )
+        .collect::<Vec<_>>(),
+      &mask_views,
+    )?;
+    let unshadowed_dset = AdDescriptorSet::new(
+      dset_pool.clone(),
+      &[(
+        dset_layout.clone(),
+        Self::dset_bindings(
+          unshadowed_buffer.clone(),
+          &shadow_map_views[0],
+          &mask_views[0],
+          &sampler,
+          no_light_buffer,
+          &atlas_frame_buffers[0],
+          reflection_probes.dset_bindings(None),
+        ),
+      )],
+    )?
+    .pop()
+    .ok_or("no unshadowed set made")?;
+    let frame_dsets = |data_buffers: &[Arc<AdBuffer>]| {
+      AdDescriptorSet::new(
+        dset_pool.clone(),
+        &(0..frames_in_flight)
+          .map(|i| {
+            (
+              dset_layout.clone(),
+              Self::dset_bindings(
+                data_buffers[i % data_buffers.len()].clone(),
+                &shadow_map_views[i],
+                &mask_views[i],
+                &sampler,
+                local_light_buffers[i].clone(),
+                &atlas_frame_buffers[i],
+                reflection_probes.dset_bindings(Some(i)),
+              ),
+            )
+          })
+          .collect::<Vec<_>>(),
+      )
+    };
+    let shadow_dsets = frame_dsets(&shadow_buffers)?;
+    let sun_unshadowed_dsets = frame_dsets(&sun_unshadowed_buffers)?;
+
+    Ok(Self {
+      technique,
+      dset_layout,
+      render_pass,
+      caster_pipeline,
+      prepass_pipeline,
+      allocator,
+      depth_format,
+      cascades,
+      cascade_frame_buffers,
+      shadow_map_views,
+      prepass_frame_buffers,
+      mask_views,
+      atlas_frame_buffers,
+      shadow_buffers,
+      sun_unshadowed_buffers,
+      local_light_buffers,
+      shadow_dsets,
+      sun_unshadowed_dsets,
+      unshadowed_dset,
+    })
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn dset_bindings(
+    shadow_buffer: Arc<AdBuffer>,
+    shadow_map_view: &Arc<AdImageView>,
+    mask_view: &Arc<AdImageView>,
+    sampler: &Arc<AdSampler>,
+    local_light_buffer: Arc<AdBuffer>,
+    atlas_frame_buffer: &AdFrameBuffer,
+    probe_bindings: Vec<AdDescriptorBinding>,
+  ) -> Vec<AdDescriptorBinding> {
+    [
+      AdDescriptorBinding::UniformBuffer(shadow_buffer),
+      AdDescriptorBinding::Image2D((
+        shadow_map_view.clone(),
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+      )),
+      AdDescriptorBinding::Image2D((mask_view.clone(), vk::ImageLayout::GENERAL)),
+      AdDescriptorBinding::Sampler(sampler.clone()),
+      AdDescriptorBinding::UniformBuffer(local_light_buffer),
+      AdDescriptorBinding::Image2D((
+        atlas_frame_buffer.attachments()[0].clone(),
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+      )),
+    ]
+    .into_iter()
+    .chain(probe_bindings)
+    .collect()
+  }
+
+  // A depth array per frame with a layer per cascade
+  fn create_shadow_maps(
+    render_pass: &Arc<AdRenderPass>,
+    allocator: &Arc<Mutex<Allocator>>,
+    depth_format: vk::Format,
+    technique: ShadowTechnique,
+    cascades: ShadowCascades,
+    count: usize,
+  ) -> Result<ShadowMaps, String> {
+    let (resolution, layers) = match technique {
+      ShadowTechnique::ShadowMap => {
+        (vk::Extent2D { width: cascades.map_size, height: cascades.map_size }, cascades.count)
+      }
+      ShadowTechnique::Off | ShadowTechnique::RayTraced => {
+        (vk::Extent2D { width: 1, height: 1 }, 1)
+      }
+    };
+    let layer_range = |base_layer: u32, layer_count: u32| {
+      vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::DEPTH)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(base_layer)
+        .layer_count(layer_count)
+    };
+    let shadow_maps = (0..count)
+      .map(|i| {
+        let depth_img = AdImage::new_2d_array(
+          render_pass.ash_device().clone(),
+          allocator.clone(),
+          MemoryLocation::GpuOnly,
+          &format!("shadow_map_{i}"),
+          depth_format,
+          resolution,
+          vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+          layers,
+        )?;
+        let frame_buffers = (0..layers)
+          .map(|layer| {
+            let layer_view = AdImageView::create_view(
+              depth_img.clone(),
+              vk::ImageViewType::TYPE_2D,
+              layer_range(layer, 1),
+            )?;
+            AdFrameBuffer::new(render_pass.clone(), vec![layer_view], resolution, 1)
+          })
+          .collect::<Result<Vec<_>, String>>()?;
+        let array_view = AdImageView::create_view(
+          depth_img,
+          vk::ImageViewType::TYPE_2D_ARRAY,
+          layer_range(0, layers),
+        )?;
+        Ok((frame_buffers, array_view))
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    Ok(shadow_maps.into_iter().unzip())
+  }
+
+  fn create_depth_targets(
+    render_pass: &Arc<AdRenderPass>,
+    allocator: &Arc<Mutex<Allocator>>,
+    depth_format: vk::Format,
+    name: &str,
+    resolution: vk::Extent2D,
+    count: usize,
+  ) -> Result<Vec<Arc<AdFrameBuffer>>, String> {
+    (0..count)
+      .map(|i| {
+        let depth_img = AdImage::new_2d(
+          render_pass.ash_device().clone(),
+          allocator.clone(),
+          MemoryLocation::GpuOnly,
+          &format!("{name}_{i}"),
+          depth_format,
+          resolution,
+          vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+          vk::SampleCountFlags::TYPE_1,
+          1,
+        )?;
+        let depth_view = AdImageView::create_view(
+          depth_img,
+          vk::ImageViewType::TYPE_2D,
+          vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::DEPTH)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1),
+        )?;
+        AdFrameBuffer::new(render_pass.clone(), vec![depth_view], resolution, 1)
+      })
+      .collect()
+  }
+
+  // Depth prepass framebuffers and the masks traced from them
+  fn create_scene_targets(
+    render_pass: &Arc<AdRenderPass>,
+    allocator: &Arc<Mutex<Allocator>>,
+    depth_format: vk::Format,
+    resolution: vk::Extent2D,
+    count: usize,
+  ) -> Result<SceneTargets, String> {
+    let prepass_frame_buffers = Self::create_depth_targets(
+      render_pass,
+      allocator,
+      depth_format,
+      "shadow_prepass_depth",
+      resolution,
+      count,
+    )?;
+    let mask_views = (0..count)
+      .map(|i| {
+        let mask_img = AdImage::new_2d(
+          render_pass.ash_device().clone(),
+          allocator.clone(),
+          MemoryLocation::GpuOnly,
+          &format!("shadow_mask_{i}"),
+          vk::Format::R32_SFLOAT,
+          resolution,
+          vk::ImageUsageFlags::STORAGE
+            | vk::ImageUsageFlags::SAMPLED
+            | vk::ImageUsageFlags::TRANSFER_DST,
+          vk::SampleCountFlags::TYPE_1,
+          1,
+        )?;
+        AdImageView::create_view(
+          mask_img,
+          vk::ImageViewType::TYPE_2D,
+          vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1),
+        )
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    Ok((prepass_frame_buffers, mask_views))
+  }
+
+  // Depth targets start out readable and masks fully lit, so sets can be bound before anything
+  // is drawn into them. Submits on cmd_buffer and waits
+  fn init_targets(
+    cmd_buffer: &AdCommandBuffer,
+    depth_views: &[Arc<AdImageView>],
+    mask_views: &[Arc<AdImageView>],
+  ) -> Result<(), String> {
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::default())?;
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TOP_OF_PIPE,
+      vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &depth_views
+        .iter()
+        .map(|view| {
+          view
+            .layout_barrier(
+              cmd_buffer,
+              vk::ImageLayout::UNDEFINED,
+              vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            )
+            .src_access_mask(vk::AccessFlags::NONE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+        })
+        .chain(mask_views.iter().map(|view| {
+          view
+            .layout_barrier(cmd_buffer, vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL)
+            .src_access_mask(vk::AccessFlags::NONE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        }))
+        .collect::<Vec<_>>(),
+    );
+    for view in mask_views.iter() {
+      cmd_buffer.clear_color_image(
+        view.image().inner(),
+        vk::ImageLayout::GENERAL,
+        &vk::ClearColorValue { float32: [1.0; 4] },
+        &[view.subresource_range()],
+      );
+    }
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)],
+      &[],
+      &[],
+    );
+    cmd_buffer.end()?;
+    let fence = AdFence::new(
+      cmd_buffer.cmd_pool().queue().ash_device().clone(),
+      vk::FenceCreateFlags::empty(),
+    )?;
+    cmd_buffer.submit(&[], &[], Some(&fence))?;
+    fence.wait(999999999)?;
+    Ok(())
+  }
+
+  // Only the traced targets follow the scene resolution. Frames still using the old targets have
+  // to be done
+  pub fn resize(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    scene_resolution: vk::Extent2D,
+  ) -> Result<(), String> {
+    if self.technique != ShadowTechnique::RayTraced
+      || self.prepass_frame_buffers.first().is_some_and(|fb| fb.resolution() == scene_resolution)
+    {
+      return Ok(());
+    }
+    let (prepass_frame_buffers, mask_views) = Self::create_scene_targets(
+      &self.render_pass,
+      &self.allocator,
+      self.depth_format,
+      scene_resolution,
+      self.mask_views.len(),
+    )?;
+    Self::init_targets(
+      cmd_buffer,
+      &prepass_frame_buffers.iter().map(|fb| fb.attachments()[0].clone()).collect::<Vec<_>>(),
+      &mask_views,
+    )?;
+    for (dset, mask_view) in self
+      .shadow_dsets
+      .iter_mut()
+      .zip(mask_views.iter())
+      .chain(self.sun_unshadowed_dsets.iter_mut().zip(mask_views.iter()))
+    {
+      dset.set_binding(
+        2,
+        AdDescriptorBinding::Image2D((mask_view.clone(), vk::ImageLayout::GENERAL)),
+      )?;
+    }
+    self.prepass_frame_buffers = prepass_frame_buffers;
+    self.mask_views = mask_views;
+    Ok(())
+  }
+
+  pub fn map_size(&self) -> u32 {
+    self.cascades.map_size
+  }
+
+  // Clamped to MIN_SHADOW_MAP_SIZE..=MAX_SHADOW_MAP_SIZE. Only shadow maps are remade, frames
+  // still using the old ones have to be done
+  pub fn set_map_size(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    map_size: u32,
+  ) -> Result<(), String> {
+    let map_size = map_size.clamp(MIN_SHADOW_MAP_SIZE, MAX_SHADOW_MAP_SIZE);
+    if map_size == self.cascades.map_size {
+      return Ok(());
+    }
+    self.cascades.map_size = map_size;
+    if self.technique != ShadowTechnique::ShadowMap {
+      return Ok(());
+    }
+    let (cascade_frame_buffers, shadow_map_views) = Self::create_shadow_maps(
+      &self.render_pass,
+      &self.allocator,
+      self.depth_format,
+      self.technique,
+      self.cascades,
+      self.shadow_map_views.len(),
+    )?;
+    Self::init_targets(cmd_buffer, &shadow_map_views, &[])?;
+    for (dset, shadow_map_view) in self
+      .shadow_dsets
+      .iter_mut()
+      .chain(self.sun_unshadowed_dsets.iter_mut())
+      .chain(std::iter::once(&mut self.unshadowed_dset))
+      .zip(shadow_map_views.iter().cycle())
+    {
+      dset.set_binding(
+        1,
+        AdDescriptorBinding::Image2D((
+          shadow_map_view.clone(),
+          vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        )),
+      )?;
+    }
+    self.cascade_frame_buffers = cascade_frame_buffers;
+    self.shadow_map_views = shadow_map_views;
+    Ok(())
+  }
+
+  // One per cascade, nearest first, each around its slice of the view frustum. The sphere around
+  // the slice is covered rather than the slice itself so a cascade keeps its size as the camera
+  // turns, and it's snapped to whole texels so edges don't shimmer as the camera moves. sun_dir
+  // points towards the sun
+  pub fn cascade_cameras(&self, camera: Camera3D, sun_dir: glam::Vec3) -> Vec<Camera3D> {
+    let pos = camera.pos.truncate();
+    let look = camera.look_dir.truncate().normalize_or_zero();
+    let inv_view_proj = camera.view_proj_mat.inverse();
+    // From the camera through the corners of the view, 1 deep along look
+    let corner_rays = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].map(|(x, y)| {
+      let far_corner = inv_view_proj.project_point3(glam::vec3(x, y, 1.0)) - pos;
+      far_corner / far_corner.dot(look).max(f32::EPSILON)
+    });
+    let near = (inv_view_proj.project_point3(glam::Vec3::ZERO) - pos).dot(look).max(f32::EPSILON);
+    let sun_dir = sun_dir.normalize_or(glam::Vec3::Y);
+    // Any up works as long as it isn't along the sun
+    let up = if sun_dir.z.abs() < 0.99 { glam::Vec3::Z } else { glam::Vec3::X };
+    let light_rot = glam::Mat4::look_at_rh(glam::Vec3::ZERO, -sun_dir, up);
+    let mut slice_near = near;
+    cascade_splits(near, self.cascades.distance.max(near), self.cascades.count)
+      .into_iter()
+      .map(|slice_far| {
+        let corners = corner_rays
+          .iter()
+          .flat_map(|ray| [pos + *ray * slice_near, pos + *ray * slice_far])
+          .collect::<Vec<_>>();
+        slice_near = slice_far;
+        let center = corners.iter().sum::<glam::Vec3>() / corners.len() as f32;
+        // Rounded up so float noise doesn't change the texel size every frame
+        let radius = corners.iter().map(|c| c.distance(center)).fold(0.0, f32::max);
+        let radius = ((radius * 16.0).ceil() / 16.0).max(1.0 / 16.0);
+        let texel_size = 2.0 * radius / self.cascades.map_size as f32;
+        let light_center = light_rot.transform_point3(center);
+        let snapped = (light_center.truncate() / texel_size).round() * texel_size;
+        let eye = glam::vec3(snapped.x, snapped.y, light_center.z + radius + SHADOW_CASTER_DEPTH);
+        let proj = glam::Mat4::orthographic_rh(
+          -radius,
+          radius,
+          -radius,
+          radius,
+          0.0,
+          2.0 * radius + SHADOW_CASTER_DEPTH,
+        );
+        Camera3D {
+          pos: light_rot.inverse().transform_point3(eye).extend(0.0),
+          look_dir: (-sun_dir).extend(0.0),
+          view_proj_mat: proj * glam::Mat4::from_translation(-eye) * light_rot,
+        }
+      })
+      .collect()
+  }
+
+  // Writes this frame's shadow data and lights, call before recording anything reading the
+  // frame's sets. Gives the light shadows render has to draw
+  pub fn update(
+    &self,
+    frame_idx: usize,
+    camera: Camera3D,
+    sun: &SunLight,
+    lights: &[LocalLight],
+  ) -> Result<Vec<LightShadowView>, String> {
+    let mut light_view_proj = [glam::Mat4::IDENTITY; MAX_SHADOW_CASCADES as usize];
+    for (slot, light_camera) in
+      light_view_proj.iter_mut().zip(self.cascade_cameras(camera, sun.direction))
+    {
+      *slot = light_camera.view_proj_mat;
+    }
+    let unshadowed = ShadowData::unshadowed(sun);
+    let shadow_data = ShadowData {
+      light_view_proj,
+      params: glam::vec4(
+        self.technique.shader_mode(),
+        SHADOW_MAP_BIAS,
+        1.0 / self.cascades.map_size as f32,
+        1.0 / self.cascades.map_size as f32,
+      ),
+      cascade_params: glam::vec4(self.cascades.count as f32, CASCADE_BLEND_BAND, 0.0, 0.0),
+      ..unshadowed
+    };
+    self.shadow_buffers[frame_idx].write_data(0, &[shadow_data])?;
+    self.sun_unshadowed_buffers[frame_idx].write_data(0, &[unshadowed])?;
+    let (local_lights, light_views) = self.local_lights_data(camera, lights);
+    self.local_light_buffers[frame_idx].write_data(0, &[local_lights])?;
+    Ok(light_views)
+  }
+
+  // Lights in the order given up to MAX_LOCAL_LIGHTS, so the ones that matter most go first.
+  // Their shadows get atlas slots in the same order until the atlas or MAX_LIGHT_SHADOW_SLOTS runs
+  // out, lights after that are lit without one
+  fn local_lights_data(
+    &self,
+    camera: Camera3D,
+    lights: &[LocalLight],
+  ) -> (LocalLightsData, Vec<LightShadowView>) {
+    let camera_pos = camera.pos.truncate();
+    let atlas_size = self.atlas_frame_buffers[0].resolution().width;
+    let mut atlas = ShadowAtlas::new(atlas_size, MIN_LIGHT_SHADOW_SIZE);
+    let mut data = LocalLightsData::EMPTY;
+    let mut light_views: Vec<LightShadowView> = vec![];
+    for (light_data, light) in data.lights.iter_mut().zip(lights.iter()) {
+      let (shape, direction, cos_inner) = match light.shape {
+        LightShape::Point => (0.0, glam::Vec4::ZERO, 1.0),
+        LightShape::Spot { direction, inner_angle, outer_angle } => {
+          let cos_outer = outer_angle.cos();
+          (
+            1.0,
+            direction.try_normalize().unwrap_or(glam::Vec3::NEG_Y).extend(cos_outer),
+            // Kept over the outer cone so the fade between them is never empty
+            inner_angle.min(outer_angle).cos().max(cos_outer + 0.0001),
+          )
+        }
+      };
+      let mut first_slot = -1.0;
+      if light.casts_shadows && self.technique != ShadowTechnique::Off {
+        let cameras = light_shadow_cameras(light);
+        let full_size = match light.shape {
+          LightShape::Point => POINT_SHADOW_FACE_SIZE,
+          LightShape::Spot { .. } => SPOT_SHADOW_SIZE,
+        };
+        let distance = light.position.distance(camera_pos).max(f32::EPSILON);
+        let requested = (full_size as f32 * (light.range / distance).min(1.0)) as u32;
+        let slots = (light_views.len() + cameras.len() <= MAX_LIGHT_SHADOW_SLOTS)
+          .then(|| atlas.allocate(requested, cameras.len()))
+          .flatten();
+        if let Some(slots) = slots {
+          first_slot = light_views.len() as f32;
+          for (slot, camera) in slots.into_iter().zip(cameras) {
+            data.slots[light_views.len()] = LightShadowSlotData {
+              view_proj: camera.view_proj_mat,
+              rect: glam::Vec4::from_array(slot.uv_rect(atlas_size)),
+            };
+            light_views.push(LightShadowView { slot, camera });
+          }
+        }
+      }
+      *light_data = LocalLightData {
+        position: light.position.extend(light.range),
+        color: (glam::Vec4::from(light.color).truncate() * light.intensity).extend(shape),
+        direction,
+        params: glam::vec4(cos_inner, first_slot, 0.0, 0.0),
+      };
+    }
+    data.params = glam::vec4(
+      lights.len().min(MAX_LOCAL_LIGHTS) as f32,
+      1.0 / atlas_size as f32,
+      LIGHT_SHADOW_BIAS,
+      0.0,
+    );
+    (data, light_views)
+  }
+
+  // For draws with the camera passed to update. The traced mask is in screen space, so with
+  // other cameras use world_dset
+  pub fn camera_dset(&self, frame_idx: usize) -> &AdDescriptorSet {
+    match self.technique {
+      ShadowTechnique::Off => &self.sun_unshadowed_dsets[frame_idx],
+      ShadowTechnique::ShadowMap | ShadowTechnique::RayTraced => &self.shadow_dsets[frame_idx],
+    }
+  }
+
+  // For reflections, and crowds and foliage which aren't in the depth prepass
+  pub fn world_dset(&self, frame_idx: usize) -> &AdDescriptorSet {
+    match self.technique {
+      ShadowTechnique::ShadowMap => &self.shadow_dsets[frame_idx],
+      ShadowTechnique::Off | ShadowTechnique::RayTraced => &self.sun_unshadowed_dsets[frame_idx],
+    }
+  }
+
+  // Without the sun's shadows or local lights, lit by the default sun
+  pub fn unshadowed_dset(&self) -> &AdDescriptorSet {
+    &self.unshadowed_dset
+  }
+
+  // Clears the whole framebuffer, then draws the batches with each camera into its own rect
+  fn render_depth(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    pipeline: &AdPipeline,
+    views: &[(vk::Rect2D, Camera3D)],
+    batches: &[TriMeshDraw],
+  ) {
+    cmd_buffer.begin_render_pass(
+      self.render_pass.inner(),
+      frame_buffer.inner(),
+      vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: frame_buffer.resolution() },
+      &[vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } }],
+      vk::SubpassContents::INLINE,
+    );
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, pipeline.inner());
+    for (rect, camera) in views.iter() {
+      cmd_buffer.set_view_port(&[vk::Viewport {
+        x: rect.offset.x as f32,
+        y: rect.offset.y as f32,
+        width: rect.extent.width as f32,
+        height: rect.extent.height as f32,
+        min_depth: 0.0,
+        max_depth: 1.0,
+      }]);
+      cmd_buffer.set_scissor(&[*rect]);
+      cmd_buffer.set_push_constant_data(
+        pipeline.layout(),
+        vk::ShaderStageFlags::VERTEX,
+        AdBuffer::get_byte_slice(&[*camera]),
+      );
+      for (mesh, _) in batches.iter() {
+        cmd_buffer.bind_descriptor_sets(
+          vk::PipelineBindPoint::GRAPHICS,
+          pipeline.layout(),
+          &[mesh.dset().inner()],
+        );
+        cmd_buffer.draw(mesh.indx_count() as _);
+      }
+    }
+    cmd_buffer.end_render_pass();
+  }
+
+  // Outside a render pass, before the scene pass. Draws each cascade's shadow map, or the depth
+  // prepass the rays start from which RtShadowRenderer::trace follows, then the light shadows
+  // update gave into the atlas. Off does nothing
+  pub fn render(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    camera: Camera3D,
+    sun_dir: glam::Vec3,
+    light_views: &[LightShadowView],
+    batches: &[TriMeshDraw],
+  ) {
+    let whole = |frame_buffer: &AdFrameBuffer| vk::Rect2D {
+      offset: vk::Offset2D { x: 0, y: 0 },
+      extent: frame_buffer.resolution(),
+    };
+    match self.technique {
+      ShadowTechnique::Off => {}
+      ShadowTechnique::ShadowMap => {
+        for (frame_buffer, light_camera) in
+          self.cascade_frame_buffers[frame_idx].iter().zip(self.cascade_cameras(camera, sun_dir))
+        {
+          self.render_depth(
+            cmd_buffer,
+            frame_buffer,
+            &self.caster_pipeline,
+            &[(whole(frame_buffer), light_camera)],
+            batches,
+          );
+        }
+      }
+      ShadowTechnique::RayTraced => self.render_depth(
+        cmd_buffer,
+        &self.prepass_frame_buffers[frame_idx],
+        &self.prepass_pipeline,
+        &[(whole(&self.prepass_frame_buffers[frame_idx]), camera)],
+        batches,
+      ),
+    }
+    if !light_views.is_empty() {
+      let views = light_views
+        .iter()
+        .map(|view| {
+          let rect = vk::Rect2D {
+            offset: vk::Offset2D { x: view.slot.x as i32, y: view.slot.y as i32 },
+            extent: vk::Extent2D { width: view.slot.size, height: view.slot.size },
+          };
+          (rect, view.camera)
+        })
+        .collect::<Vec<_>>();
+      self.render_depth(
+        cmd_buffer,
+        &self.atlas_frame_buffers[frame_idx],
+        &self.caster_pipeline,
+        &views,
+        batches,
+      );
+    }
+  }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_data_wrappers::AdBuffer,
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::AdComputePipeline,
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::skinning::{SkinnedMeshGPU, SkinnedMeshGenerator};
+
+static SKINNING_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/skinning.comp.spv");
+
+const SKIN_GROUP_SIZE: u32 = 64;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct SkinningPushConstants {
+  // vertex count, joint count, 1 to pose the previous vertices too, unused
+  counts: [u32; 4],
+}
+
+// Poses skinned meshes in a compute pass before anything draws them, so they go through the same
+// vertex shader and pipelines as every other mesh
+pub struct SkinningRenderer {
+  pipeline: AdComputePipeline,
+}
+
+impl SkinningRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    skinned_mesh_gen: &SkinnedMeshGenerator,
+  ) -> Result<Self, String> {
+    let pipeline = AdComputePipeline::new(
+      ash_device,
+      SKINNING_SHADER_CODE,
+      &[skinned_mesh_gen.skin_dset_layout()],
+      std::mem::size_of::<SkinningPushConstants>() as u32,
+    )?;
+    Ok(Self { pipeline })
+  }
+
+  // Must be recorded outside a render pass, before the frame's first pass drawing meshes. With
+  // prev_pose the meshes' previous vertices are posed too, for a velocity pass after
+  pub fn skin(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    meshes: &[Arc<SkinnedMeshGPU>],
+    prev_pose: bool,
+  ) {
+    if meshes.is_empty() {
+      return;
+    }
+    // Previous frame's draws may still read the vertices being rewritten
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::VERTEX_SHADER,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[],
+    );
+
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.pipeline.inner());
+    for mesh in meshes.iter() {
+      cmd_buffer.bind_descriptor_sets(
+        vk::PipelineBindPoint::COMPUTE,
+        self.pipeline.layout(),
+        &[mesh.skin_dset().inner()],
+      );
+      cmd_buffer.set_push_constant_data(
+        self.pipeline.layout(),
+        vk::ShaderStageFlags::COMPUTE,
+        AdBuffer::get_byte_slice(&[SkinningPushConstants {
+          counts: [mesh.vertex_count(), mesh.joint_count(), prev_pose as u32, 0],
+        }]),
+      );
+      cmd_buffer.dispatch(mesh.vertex_count().div_ceil(SKIN_GROUP_SIZE), 1, 1);
+    }
+
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::PipelineStageFlags::VERTEX_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+      &[],
+      &[],
+    );
+  }
+}
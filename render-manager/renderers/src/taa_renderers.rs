@@ -0,0 +1,282 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdImage, AdImageView, AdSampler,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdComputePipeline, AdFrameBuffer},
+  ash_sync_wrappers::AdFence,
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::glam;
+
+use crate::{
+  post_renderers::{begin_scene_color_read, copy_into_scene_color, POST_FORMAT, POST_GROUP_SIZE},
+  render_targets::{color_range, reallocate_dsets},
+  velocity_renderers::VelocityRenderer,
+};
+
+static TAA_RESOLVE_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/taa_resolve.comp.spv");
+
+const MAX_TAA_SETS: u32 = 32;
+
+// Temporal anti-aliasing for scenes drawn with a jittered camera by TriMeshMaterialRenderer
+// without msaa. The resolve blends the frame into the history at where each pixel was last frame,
+// from the velocity of a VelocityRenderer, and copies the result back to the scene color. The two
+// history images are read one and written the other, always in the GENERAL layout. The color
+// images need SAMPLED and TRANSFER_DST usage
+pub struct TaaRenderer {
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  resolve_pipeline: AdComputePipeline,
+  resolve_dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  sampler: Arc<AdSampler>,
+  history_views: Vec<Arc<AdImageView>>,
+  // Two per framebuffer, reading history 0 and reading history 1
+  resolve_dsets: Vec<AdDescriptorSet>,
+}
+
+impl TaaRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+  ) -> Result<Self, String> {
+    let resolve_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_IMAGE),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::UNIFORM_BUFFER),
+      ],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_TAA_SETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+          descriptor_count: MAX_TAA_SETS * 4,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_IMAGE,
+          descriptor_count: MAX_TAA_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::UNIFORM_BUFFER,
+          descriptor_count: MAX_TAA_SETS,
+        },
+      ],
+    )?);
+    let resolve_pipeline = AdComputePipeline::new(
+      ash_device.clone(),
+      TAA_RESOLVE_SHADER_CODE,
+      &[&resolve_dset_layout],
+      std::mem::size_of::<glam::Vec4>() as u32,
+    )?;
+    let sampler = Arc::new(AdSampler::new_with_info(
+      ash_device.clone(),
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    )?);
+    Ok(Self {
+      ash_device,
+      allocator,
+      resolve_pipeline,
+      resolve_dset_layout,
+      dset_pool,
+      sampler,
+      history_views: vec![],
+      resolve_dsets: vec![],
+    })
+  }
+
+  // After velocity_renderer's targets are resized for the same framebuffers. Submits the history
+  // clears on cmd_buffer and waits for them
+  pub fn resize_targets(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+    velocity_renderer: &VelocityRenderer,
+  ) -> Result<(), String> {
+    let Some(resolution) = scene_frame_buffers.first().map(|fb| fb.resolution()) else {
+      return Err("no framebuffers to make taa targets for".to_string());
+    };
+    let color_range = color_range();
+
+    let history_images = (0..2)
+      .map(|i| {
+        AdImage::new_2d(
+          self.ash_device.clone(),
+          self.allocator.clone(),
+          MemoryLocation::GpuOnly,
+          &format!("taa_history_image_{i}"),
+          POST_FORMAT,
+          resolution,
+          vk::ImageUsageFlags::STORAGE
+            | vk::ImageUsageFlags::SAMPLED
+            | vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST,
+          vk::SampleCountFlags::TYPE_1,
+          1,
+        )
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    let history_views = history_images
+      .iter()
+      .map(|image| AdImageView::create_view(image.clone(), vk::ImageViewType::TYPE_2D, color_range))
+      .collect::<Result<Vec<_>, String>>()?;
+
+    let sampler = &self.sampler;
+    reallocate_dsets(
+      &mut self.resolve_dsets,
+      &self.dset_pool,
+      &self.resolve_dset_layout,
+      scene_frame_buffers.iter().enumerate().flat_map(|(frame_idx, fb)| {
+        let history_views = &history_views;
+        (0..2).map(move |read_idx| {
+          vec![
+            AdDescriptorBinding::Sampler2D((
+              fb.attachments()[0].clone(),
+              vk::ImageLayout::GENERAL,
+              sampler.clone(),
+            )),
+            AdDescriptorBinding::Sampler2D((
+              fb.attachments()[1].clone(),
+              vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+              sampler.clone(),
+            )),
+            AdDescriptorBinding::Sampler2D((
+              velocity_renderer.velocity_view(frame_idx).clone(),
+              vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+              sampler.clone(),
+            )),
+            AdDescriptorBinding::Sampler2D((
+              history_views[read_idx].clone(),
+              vk::ImageLayout::GENERAL,
+              sampler.clone(),
+            )),
+            AdDescriptorBinding::StorageImage((
+              history_views[1 - read_idx].clone(),
+              vk::ImageLayout::GENERAL,
+            )),
+            AdDescriptorBinding::UniformBuffer(
+              velocity_renderer.frame_data_buffer(frame_idx).clone(),
+            ),
+          ]
+        })
+      }),
+    )?;
+
+    // Cleared so the first resolve, which ignores the history, doesn't blend in garbage
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::default())?;
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TOP_OF_PIPE,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &history_images
+        .iter()
+        .map(|image| {
+          vk::ImageMemoryBarrier::default()
+            .image(image.inner())
+            .subresource_range(color_range)
+            .src_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+            .dst_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+            .src_access_mask(vk::AccessFlags::NONE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::GENERAL)
+        })
+        .collect::<Vec<_>>(),
+    );
+    for image in history_images.iter() {
+      cmd_buffer.clear_color_image(
+        image.inner(),
+        vk::ImageLayout::GENERAL,
+        &vk::ClearColorValue { float32: [0.0; 4] },
+        &[color_range],
+      );
+    }
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)],
+      &[],
+      &[],
+    );
+    cmd_buffer.end()?;
+    let fence = AdFence::new(self.ash_device.clone(), vk::FenceCreateFlags::empty())?;
+    cmd_buffer.submit(&[], &[], Some(&fence))?;
+    fence.wait(999999999)?;
+
+    self.history_views = history_views;
+    Ok(())
+  }
+
+  // Between the velocity render and finish of frame_idx, with the scene color in
+  // TRANSFER_SRC_OPTIMAL, where it's left. Reads history[read_idx] and writes the other, blending
+  // in history_weight of it, 0 while there is no history
+  pub fn resolve(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    read_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+    history_weight: f32,
+  ) -> Result<(), String> {
+    let Some(resolve_dset) = self.resolve_dsets.get(frame_idx * 2 + read_idx) else {
+      return Err(format!("no taa targets for frame {frame_idx}"));
+    };
+    begin_scene_color_read(cmd_buffer, scene_frame_buffer);
+    // The history written last frame is read, the one it was copied from is written
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::COMPUTE_SHADER,
+      vk::DependencyFlags::empty(),
+      &[vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)],
+      &[],
+      &[],
+    );
+    let resolution = scene_frame_buffer.resolution();
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.resolve_pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.resolve_pipeline.layout(),
+      &[resolve_dset.inner()],
+    );
+    cmd_buffer.set_push_constant_data(
+      self.resolve_pipeline.layout(),
+      vk::ShaderStageFlags::COMPUTE,
+      AdBuffer::get_byte_slice(&[glam::vec4(history_weight, 0.0, 0.0, 0.0)]),
+    );
+    cmd_buffer.dispatch(
+      resolution.width.div_ceil(POST_GROUP_SIZE),
+      resolution.height.div_ceil(POST_GROUP_SIZE),
+      1,
+    );
+    copy_into_scene_color(cmd_buffer, self.history_views[1 - read_idx].image(), scene_frame_buffer);
+    Ok(())
+  }
+}
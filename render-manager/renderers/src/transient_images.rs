@@ -0,0 +1,159 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    getset,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{AdAllocation, AdImage},
+};
+
+// Image only used from the start of first_pass to the end of last_pass of a frame, and never read
+// before being written again the next frame. Passes are numbered in the order they are recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransientImageDesc {
+  pub name: &'static str,
+  pub format: vk::Format,
+  pub usage: vk::ImageUsageFlags,
+  pub first_pass: u32,
+  pub last_pass: u32,
+}
+
+impl TransientImageDesc {
+  pub fn in_pass(
+    name: &'static str,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    pass: u32,
+  ) -> Self {
+    Self { name, format, usage, first_pass: pass, last_pass: pass }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TransientLifetime {
+  pub first_pass: u32,
+  pub last_pass: u32,
+  pub requirements: vk::MemoryRequirements,
+}
+
+impl TransientLifetime {
+  fn overlaps(&self, other: &Self) -> bool {
+    self.first_pass <= other.last_pass && other.first_pass <= self.last_pass
+  }
+}
+
+// Images sharing one block of memory, with what the block needs to hold any of them
+#[derive(Debug, Clone)]
+pub struct AliasGroup {
+  pub images: Vec<usize>,
+  pub requirements: vk::MemoryRequirements,
+}
+
+// Groups lifetimes, by index, so no two in a group overlap and all of a group can live in one
+// memory type. Biggest first, each into the first group it fits in, so the big images set the
+// block sizes and the small ones fill in around them
+pub fn plan_aliasing(lifetimes: &[TransientLifetime]) -> Vec<AliasGroup> {
+  let mut order = (0..lifetimes.len()).collect::<Vec<_>>();
+  order.sort_by_key(|&idx| std::cmp::Reverse(lifetimes[idx].requirements.size));
+  let mut groups: Vec<AliasGroup> = vec![];
+  for idx in order {
+    let lifetime = lifetimes[idx];
+    let fits = |group: &AliasGroup| {
+      group.requirements.memory_type_bits & lifetime.requirements.memory_type_bits != 0
+        && group.images.iter().all(|&other| !lifetimes[other].overlaps(&lifetime))
+    };
+    match groups.iter_mut().find(|group| fits(group)) {
+      Some(group) => {
+        group.images.push(idx);
+        group.requirements = vk::MemoryRequirements {
+          size: group.requirements.size.max(lifetime.requirements.size),
+          alignment: group.requirements.alignment.max(lifetime.requirements.alignment),
+          memory_type_bits: group.requirements.memory_type_bits
+            & lifetime.requirements.memory_type_bits,
+        };
+      }
+      None => groups.push(AliasGroup { images: vec![idx], requirements: lifetime.requirements }),
+    }
+  }
+  groups
+}
+
+// A frame's transient images at one resolution, made again on resize. Frames in flight all use
+// the same ones, like the targets they replace, so passes sharing memory have to be recorded on
+// one queue with a barrier from each pass's last use to the next one's first
+#[derive(getset::CopyGetters)]
+pub struct TransientImages {
+  images: HashMap<&'static str, Arc<AdImage>>,
+  // What the images would take with memory of their own
+  #[getset(get_copy = "pub")]
+  dedicated_size: vk::DeviceSize,
+  // What they take sharing it
+  #[getset(get_copy = "pub")]
+  aliased_size: vk::DeviceSize,
+}
+
+impl TransientImages {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    resolution: vk::Extent2D,
+    descs: &[TransientImageDesc],
+  ) -> Result<Self, String> {
+    let lifetimes = descs
+      .iter()
+      .map(|desc| {
+        Ok(TransientLifetime {
+          first_pass: desc.first_pass,
+          last_pass: desc.last_pass,
+          requirements: AdImage::aliased_2d_requirements(
+            &ash_device,
+            desc.format,
+            resolution,
+            desc.usage,
+            1,
+          )?,
+        })
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    let groups = plan_aliasing(&lifetimes);
+    let mut images = HashMap::new();
+    for (group_idx, group) in groups.iter().enumerate() {
+      let memory = Arc::new(Mutex::new(AdAllocation::new(
+        allocator.clone(),
+        &format!("transient_memory_{group_idx}"),
+        MemoryLocation::GpuOnly,
+        group.requirements,
+      )?));
+      for &image_idx in &group.images {
+        let desc = descs[image_idx];
+        let image = AdImage::new_2d_aliased(
+          ash_device.clone(),
+          memory.clone(),
+          desc.name,
+          desc.format,
+          resolution,
+          desc.usage,
+          1,
+        )?;
+        if images.insert(desc.name, image).is_some() {
+          return Err(format!("transient image {} asked for twice", desc.name));
+        }
+      }
+    }
+    Ok(Self {
+      images,
+      dedicated_size: lifetimes.iter().map(|lifetime| lifetime.requirements.size).sum(),
+      aliased_size: groups.iter().map(|group| group.requirements.size).sum(),
+    })
+  }
+
+  pub fn get(&self, name: &str) -> Result<Arc<AdImage>, String> {
+    self.images.get(name).cloned().ok_or(format!("no transient image {name}"))
+  }
+}
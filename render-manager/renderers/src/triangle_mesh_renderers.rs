@@ -9,61 +9,134 @@ use ash_ad_wrappers::{
     gpu_allocator::{vulkan::Allocator, MemoryLocation},
     AdAshDevice,
   },
-  ash_data_wrappers::{AdBuffer, AdImage, AdImageView},
+  ash_data_wrappers::{AdBuffer, AdDescriptorSet, AdDescriptorSetLayout, AdImage, AdImageView},
   ash_queue_wrappers::AdCommandBuffer,
   ash_render_wrappers::{AdFrameBuffer, AdPipeline, AdRenderPass},
   ash_sync_wrappers::AdFence,
 };
 use include_bytes_aligned::include_bytes_aligned;
+use jobs::JobPool;
 use renderables::{
-  flat_texture::{FlatTextureGPU, FlatTextureGenerator}, triangle_mesh::{TriMeshGPU, TriMeshGenerator}, Camera3D
+  color::Color,
+  flat_texture::FlatTextureGPU,
+  material::{MaterialGPU, MaterialGenerator, MaterialPipelineKey, ShaderVariant},
+  triangle_mesh::{TriMeshGPU, TriMeshGenerator},
+  Camera3D,
 };
 
-static FTEX_VERT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/triangle.vert.spv");
-static FTEX_FRAG_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/triangle_flat_tex.frag.spv");
+static TRI_MESH_VERT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/triangle.vert.spv");
+static STREAMS_VERT_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/triangle_streams.vert.spv");
+static UNLIT_FRAG_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/triangle_unlit.frag.spv");
+static LIT_FRAG_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/triangle_lit.frag.spv");
+static CUTOUT_FRAG_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/triangle_cutout.frag.spv");
+static UNLIT_STREAMS_FRAG_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/triangle_unlit_streams.frag.spv");
+static LIT_STREAMS_FRAG_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/triangle_lit_streams.frag.spv");
+static CUTOUT_STREAMS_FRAG_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/triangle_cutout_streams.frag.spv");
+static LIGHTMAPPED_FRAG_SHADER_CODE: &[u8] =
+  include_bytes_aligned!(4, "shaders/triangle_lightmapped.frag.spv");
+
+// Shows where nothing was drawn, zero alpha so it never covers anything blended over it
+pub const DEFAULT_CLEAR_COLOR: Color = Color::new(0.1, 0.1, 0.1, 0.0);
+
+pub type TriMeshDraw = (Arc<TriMeshGPU>, Arc<MaterialGPU>);
 
 pub struct TriMeshFlatTex {
   pub mesh: Arc<TriMeshGPU>,
   pub ftex: Arc<FlatTextureGPU>,
 }
 
-pub struct TriMeshTexRenderer {
-  pipelines: Vec<AdPipeline>,
+// With msaa the framebuffer attachments are the resolved color, the multisampled depth and the
+// multisampled color, so attachment 0 is always the single sampled output
+pub struct TriMeshMaterialRenderer {
+  // Built the first time a material needs one. Every pipeline has the same layout, the ones reading
+  // vertex streams have the mesh's streams set after it
+  pipelines: HashMap<MaterialPipelineKey, AdPipeline>,
+  // Hot reloaded fragment shaders used in place of the built in ones, by variant and whether they
+  // read vertex streams
+  frag_overrides: HashMap<(ShaderVariant, bool), Vec<u8>>,
+  // Why pipelines were built with the built in shader instead of the reloaded one
+  shader_errors: Vec<String>,
+  mesh_dset_layout: Arc<AdDescriptorSetLayout>,
+  material_dset_layout: Arc<AdDescriptorSetLayout>,
+  shadow_dset_layout: Arc<AdDescriptorSetLayout>,
+  streams_dset_layout: Arc<AdDescriptorSetLayout>,
   render_pass: Arc<AdRenderPass>,
+  color_format: vk::Format,
   depth_format: vk::Format,
+  samples: vk::SampleCountFlags,
+  clear_color: Color,
 }
 
-impl TriMeshTexRenderer {
+impl TriMeshMaterialRenderer {
   pub fn new(
     ash_device: Arc<AdAshDevice>,
     tri_mesh_gen: &TriMeshGenerator,
-    flat_tex_gen: &FlatTextureGenerator,
+    material_gen: &MaterialGenerator,
+    shadow_dset_layout: Arc<AdDescriptorSetLayout>,
+    color_format: vk::Format,
     depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
   ) -> Result<Self, String> {
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+    let mut attachments = vec![
+      vk::AttachmentDescription::default()
+        .format(color_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .initial_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .final_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .load_op(match multisampled {
+          true => vk::AttachmentLoadOp::DONT_CARE,
+          false => vk::AttachmentLoadOp::CLEAR,
+        }),
+      vk::AttachmentDescription::default()
+        .format(depth_format)
+        .samples(samples)
+        .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        // Kept for the editor overlay which depth tests against the scene
+        .store_op(vk::AttachmentStoreOp::STORE),
+    ];
+    if multisampled {
+      attachments.push(
+        vk::AttachmentDescription::default()
+          .format(color_format)
+          .samples(samples)
+          .initial_layout(vk::ImageLayout::UNDEFINED)
+          .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+          .load_op(vk::AttachmentLoadOp::CLEAR)
+          // Kept for the editor overlay which draws over the unresolved image and resolves again
+          .store_op(vk::AttachmentStoreOp::STORE),
+      );
+    }
+    let output_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(0)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let msaa_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(2)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let depth_attachment_ref = vk::AttachmentReference::default()
+      .attachment(1)
+      .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let subpass = match multisampled {
+      true => vk::SubpassDescription::default()
+        .color_attachments(&msaa_attachment_ref)
+        .resolve_attachments(&output_attachment_ref),
+      false => vk::SubpassDescription::default().color_attachments(&output_attachment_ref),
+    };
     let render_pass = AdRenderPass::new(
       ash_device.clone(),
       vk::RenderPassCreateFlags::default(),
-      &[vk::AttachmentDescription::default()
-          .format(vk::Format::R8G8B8A8_UNORM)
-          .samples(vk::SampleCountFlags::TYPE_1)
-          .initial_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
-          .final_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
-          .load_op(vk::AttachmentLoadOp::CLEAR),
-        vk::AttachmentDescription::default()
-          .format(depth_format)
-          .samples(vk::SampleCountFlags::TYPE_1)
-          .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-          .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-          .load_op(vk::AttachmentLoadOp::CLEAR)
-          .store_op(vk::AttachmentStoreOp::DONT_CARE)],
-      &[vk::SubpassDescription::default()
+      &attachments,
+      &[subpass
         .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-        .color_attachments(&[vk::AttachmentReference::default()
-          .attachment(0)
-          .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)])
-        .depth_stencil_attachment(&vk::AttachmentReference::default()
-          .attachment(1)
-          .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL))],
+        .depth_stencil_attachment(&depth_attachment_ref)],
       &[
         vk::SubpassDependency::default()
           .src_subpass(vk::SUBPASS_EXTERNAL)
@@ -83,20 +156,88 @@ impl TriMeshTexRenderer {
     )?;
     let render_pass = Arc::new(render_pass);
 
+    Ok(Self {
+      pipelines: HashMap::new(),
+      frag_overrides: HashMap::new(),
+      shader_errors: vec![],
+      mesh_dset_layout: tri_mesh_gen.mesh_dset_layout().clone(),
+      material_dset_layout: material_gen.material_dset_layout().clone(),
+      shadow_dset_layout,
+      streams_dset_layout: tri_mesh_gen.streams_dset_layout().clone(),
+      render_pass,
+      color_format,
+      depth_format,
+      samples,
+      clear_color: DEFAULT_CLEAR_COLOR,
+    })
+  }
+
+  // Sky behind everything drawn, alpha is kept at zero
+  pub fn set_clear_color(&mut self, color: Color) {
+    self.clear_color = color.with_alpha(0.0);
+  }
+
+  fn builtin_frag_shader_code(variant: ShaderVariant, vertex_streams: bool) -> &'static [u8] {
+    match (variant, vertex_streams) {
+      (ShaderVariant::Unlit, false) => UNLIT_FRAG_SHADER_CODE,
+      (ShaderVariant::Lit, false) => LIT_FRAG_SHADER_CODE,
+      (ShaderVariant::AlphaCutout, false) => CUTOUT_FRAG_SHADER_CODE,
+      (ShaderVariant::Unlit, true) => UNLIT_STREAMS_FRAG_SHADER_CODE,
+      (ShaderVariant::Lit, true) => LIT_STREAMS_FRAG_SHADER_CODE,
+      (ShaderVariant::AlphaCutout, true) => CUTOUT_STREAMS_FRAG_SHADER_CODE,
+      // Always with streams, see MaterialCPU::pipeline_key
+      (ShaderVariant::Lightmapped, _) => LIGHTMAPPED_FRAG_SHADER_CODE,
+    }
+  }
+
+  // With the reloaded fragment shader when there is one. A driver refusing it doesn't stop the
+  // draws, the pipeline is built with the built in shader and the error kept for
+  // take_shader_errors
+  fn create_pipeline(&mut self, key: MaterialPipelineKey) -> Result<AdPipeline, String> {
+    let builtin_code = Self::builtin_frag_shader_code(key.variant, key.vertex_streams);
+    let Some(code) = self.frag_overrides.get(&(key.variant, key.vertex_streams)) else {
+      return self.create_pipeline_with(key, builtin_code);
+    };
+    match self.create_pipeline_with(key, code) {
+      Ok(pipeline) => Ok(pipeline),
+      Err(e) => {
+        self.shader_errors.push(format!("at building reloaded shader, built in one used: {e}"));
+        self.create_pipeline_with(key, builtin_code)
+      }
+    }
+  }
+
+  fn create_pipeline_with(
+    &self,
+    key: MaterialPipelineKey,
+    frag_shader_code: &[u8],
+  ) -> Result<AdPipeline, String> {
+    let mut dset_layouts: Vec<&AdDescriptorSetLayout> =
+      vec![&self.mesh_dset_layout, &self.material_dset_layout, &self.shadow_dset_layout];
+    let vert_shader_code = match key.vertex_streams {
+      true => {
+        dset_layouts.push(&self.streams_dset_layout);
+        STREAMS_VERT_SHADER_CODE
+      }
+      false => TRI_MESH_VERT_SHADER_CODE,
+    };
     let triangle_rasterizer_info = vk::PipelineRasterizationStateCreateInfo::default()
-      .cull_mode(vk::CullModeFlags::BACK)
+      .cull_mode(match key.double_sided {
+        true => vk::CullModeFlags::NONE,
+        false => vk::CullModeFlags::BACK,
+      })
       .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
       .polygon_mode(vk::PolygonMode::FILL)
       .line_width(1.0);
 
-    let pipeline = AdPipeline::new(
-      render_pass.clone(),
+    AdPipeline::new(
+      self.render_pass.clone(),
       0,
       HashMap::from([
-        (vk::ShaderStageFlags::VERTEX, FTEX_VERT_SHADER_CODE),
-        (vk::ShaderStageFlags::FRAGMENT, FTEX_FRAG_SHADER_CODE),
+        (vk::ShaderStageFlags::VERTEX, vert_shader_code),
+        (vk::ShaderStageFlags::FRAGMENT, frag_shader_code),
       ]),
-      &[tri_mesh_gen.mesh_dset_layout(), flat_tex_gen.tex_dset_layout()],
+      &dset_layouts,
       (vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, std::mem::size_of::<Camera3D>() as u32),
       triangle_rasterizer_info,
       &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
@@ -107,10 +248,61 @@ impl TriMeshTexRenderer {
       &vk::PipelineDepthStencilStateCreateInfo::default()
         .depth_test_enable(true)
         .depth_write_enable(true)
-        .depth_compare_op(vk::CompareOp::LESS)
-    )?;
+        .depth_compare_op(vk::CompareOp::LESS),
+      self.samples,
+    )
+    .map_err(|e| format!("at creating {key:?} pipeline: {e}"))
+  }
+
+  // Hot reloads the fragment shader of a variant from SPIR-V, None goes back to the built in one.
+  // Pipelines already built for it are all made again first, when any of them fails they stay as
+  // they were and so does the shader. Frames in flight have to be done with them. Returns how
+  // many were rebuilt
+  pub fn reload_frag_shader(
+    &mut self,
+    variant: ShaderVariant,
+    vertex_streams: bool,
+    code: Option<Vec<u8>>,
+  ) -> Result<usize, String> {
+    let frag_code = match code.as_deref() {
+      Some(code) => code,
+      None => Self::builtin_frag_shader_code(variant, vertex_streams),
+    };
+    let rebuilt = self
+      .pipelines
+      .keys()
+      .filter(|key| key.variant == variant && key.vertex_streams == vertex_streams)
+      .map(|key| self.create_pipeline_with(*key, frag_code).map(|pipeline| (*key, pipeline)))
+      .collect::<Result<Vec<_>, String>>()
+      .map_err(|e| format!("at reloading {variant:?} shader, the last one that built stays: {e}"))?;
+    let rebuilt_count = rebuilt.len();
+    self.pipelines.extend(rebuilt);
+    match code {
+      Some(code) => self.frag_overrides.insert((variant, vertex_streams), code),
+      None => self.frag_overrides.remove(&(variant, vertex_streams)),
+    };
+    Ok(rebuilt_count)
+  }
 
-    Ok(Self { pipelines: vec![pipeline], render_pass, depth_format })
+  pub fn take_shader_errors(&mut self) -> Vec<String> {
+    std::mem::take(&mut self.shader_errors)
+  }
+
+  // Builds the pipelines the draws need and orders the draws so each pipeline and material is
+  // bound once. The result can be kept and rendered every frame until the draws change
+  pub fn build_batches(
+    &mut self,
+    mut objs: Vec<TriMeshDraw>,
+  ) -> Result<Vec<TriMeshDraw>, String> {
+    for (_, material) in objs.iter() {
+      let key = material.pipeline_key();
+      if !self.pipelines.contains_key(&key) {
+        let pipeline = self.create_pipeline(key)?;
+        self.pipelines.insert(key, pipeline);
+      }
+    }
+    objs.sort_by_key(|(_, material)| (material.pipeline_key(), Arc::as_ptr(material)));
+    Ok(objs)
   }
 
   pub fn create_framebuffers(
@@ -119,6 +311,8 @@ impl TriMeshTexRenderer {
     allocator: Arc<Mutex<Allocator>>,
     resolution: vk::Extent2D,
     count: usize,
+    // On top of what the render passes and the blit need, like STORAGE for compute passes
+    extra_color_usage: vk::ImageUsageFlags,
   ) -> Result<Vec<Arc<AdFrameBuffer>>, String> {
     let triangle_out_images = (0..count)
       .map(|i| {
@@ -127,9 +321,11 @@ impl TriMeshTexRenderer {
           allocator.clone(),
           MemoryLocation::GpuOnly,
           &format!("triangle_color_image_temp_{i}"),
-          vk::Format::R8G8B8A8_UNORM,
+          self.color_format,
           resolution,
-          vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+          vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | extra_color_usage,
           vk::SampleCountFlags::TYPE_1,
           1,
         ) else {
@@ -142,13 +338,31 @@ impl TriMeshTexRenderer {
           &format!("triangle_depth_image_temp_{i}"),
           self.depth_format,
           resolution,
-          vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-          vk::SampleCountFlags::TYPE_1,
+          // Sampled by the particle simulation for collisions
+          vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+          self.samples,
           1,
         ) else {
           return Err(format!("failed to create depth image {}", i))
         };
-        Ok((color_img, depth_img))
+        let msaa_color_img = match self.samples {
+          vk::SampleCountFlags::TYPE_1 => None,
+          samples => Some(
+            AdImage::new_2d(
+              self.render_pass.ash_device().clone(),
+              allocator.clone(),
+              MemoryLocation::GpuOnly,
+              &format!("triangle_msaa_color_image_temp_{i}"),
+              self.color_format,
+              resolution,
+              vk::ImageUsageFlags::COLOR_ATTACHMENT,
+              samples,
+              1,
+            )
+            .map_err(|e| format!("failed to create msaa color image {i}: {e}"))?,
+          ),
+        };
+        Ok((color_img, depth_img, msaa_color_img))
       })
       .collect::<Result<Vec<_>, _>>()?;
 
@@ -161,7 +375,7 @@ impl TriMeshTexRenderer {
       &[],
       &triangle_out_images
         .iter()
-        .map(|(color_img, _)| {
+        .map(|(color_img, _, _)| {
           vk::ImageMemoryBarrier::default()
             .image(color_img.inner())
             .subresource_range(
@@ -189,7 +403,7 @@ impl TriMeshTexRenderer {
       &[],
       &triangle_out_images
         .iter()
-        .map(|(_, depth_img)| {
+        .map(|(_, depth_img, _)| {
           vk::ImageMemoryBarrier::default()
             .image(depth_img.inner())
             .subresource_range(
@@ -215,69 +429,45 @@ impl TriMeshTexRenderer {
     fence.wait(999999999)?;
     fence.reset()?;
 
-    let triangle_color_image_views = (0..3)
-      .map(|i| {
-        AdImageView::create_view(
-          triangle_out_images[i].0.clone(),
-          vk::ImageViewType::TYPE_2D,
-          vk::ImageSubresourceRange {
-            aspect_mask: vk::ImageAspectFlags::COLOR,
-            base_mip_level: 0,
-            level_count: 1,
-            base_array_layer: 0,
-            layer_count: 1,
-          },
-        )
-      })
-      .collect::<Result<Vec<_>, _>>()?;
-    let triangle_depth_image_views = (0..3)
-      .map(|i| {
-        AdImageView::create_view(
-          triangle_out_images[i].1.clone(),
-          vk::ImageViewType::TYPE_2D,
-          vk::ImageSubresourceRange {
-            aspect_mask: vk::ImageAspectFlags::DEPTH,
-            base_mip_level: 0,
-            level_count: 1,
-            base_array_layer: 0,
-            layer_count: 1,
-          },
-        )
-      })
-      .collect::<Result<Vec<_>, _>>()?;
-
-    let triangle_frame_buffers = (0..3)
-      .map(|i| {
-        AdFrameBuffer::new(
-          self.render_pass.clone(),
-          vec![triangle_color_image_views[i].clone(), triangle_depth_image_views[i].clone()],
-          resolution,
-          1,
-        )
+    let color_subresource_range = vk::ImageSubresourceRange {
+      aspect_mask: vk::ImageAspectFlags::COLOR,
+      base_mip_level: 0,
+      level_count: 1,
+      base_array_layer: 0,
+      layer_count: 1,
+    };
+    let triangle_frame_buffers = triangle_out_images
+      .iter()
+      .map(|(color_img, depth_img, msaa_color_img)| {
+        let mut attachments = vec![
+          AdImageView::create_view(
+            color_img.clone(),
+            vk::ImageViewType::TYPE_2D,
+            color_subresource_range,
+          )?,
+          AdImageView::create_view(
+            depth_img.clone(),
+            vk::ImageViewType::TYPE_2D,
+            vk::ImageSubresourceRange {
+              aspect_mask: vk::ImageAspectFlags::DEPTH,
+              ..color_subresource_range
+            },
+          )?,
+        ];
+        if let Some(msaa_color_img) = msaa_color_img {
+          attachments.push(AdImageView::create_view(
+            msaa_color_img.clone(),
+            vk::ImageViewType::TYPE_2D,
+            color_subresource_range,
+          )?);
+        }
+        AdFrameBuffer::new(self.render_pass.clone(), attachments, resolution, 1)
       })
       .collect::<Result<Vec<_>, String>>()?;
     Ok(triangle_frame_buffers)
   }
 
-  pub fn render(
-    &self,
-    cmd_buffer: &AdCommandBuffer,
-    frame_buffer: &AdFrameBuffer,
-    camera: Camera3D,
-    objs: &[(Arc<TriMeshGPU>, Arc<FlatTextureGPU>)],
-  ) {
-    cmd_buffer.begin_render_pass(
-      self.render_pass.inner(),
-      frame_buffer.inner(),
-      vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: frame_buffer.resolution() },
-      &[
-        vk::ClearValue { color: vk::ClearColorValue { float32: [0.1, 0.1, 0.1, 0.0] } },
-        vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } },
-      ],
-      vk::SubpassContents::INLINE,
-    );
-    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, self.pipelines[0].inner());
-
+  fn set_full_viewport(cmd_buffer: &AdCommandBuffer, frame_buffer: &AdFrameBuffer) {
     cmd_buffer.set_view_port(&[vk::Viewport {
       x: 0.0,
       y: 0.0,
@@ -290,20 +480,176 @@ impl TriMeshTexRenderer {
       offset: vk::Offset2D { x: 0, y: 0 },
       extent: frame_buffer.resolution(),
     }]);
+  }
 
-    for obj in objs.iter() {
+  // With an indirect buffer each draw reads its command from the slot matching its batch index,
+  // first_slot being the batch index of objs[0]
+  fn record_draws(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    camera: Camera3D,
+    shadow_dset: &AdDescriptorSet,
+    objs: &[TriMeshDraw],
+    indirect: Option<(&AdBuffer, usize)>,
+  ) {
+    let mut bound_key = None;
+    let mut bound_material = None;
+    for (i, (mesh, material)) in objs.iter().enumerate() {
+      let key = material.pipeline_key();
+      let Some(pipeline) = self.pipelines.get(&key) else {
+        eprintln!("skipping draw: no pipeline for {key:?}");
+        continue;
+      };
+      if bound_key != Some(key) {
+        cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, pipeline.inner());
+        cmd_buffer.set_push_constant_data(
+          pipeline.layout(),
+          vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+          AdBuffer::get_byte_slice(&[camera]),
+        );
+        cmd_buffer.bind_descriptor_sets_from(
+          vk::PipelineBindPoint::GRAPHICS,
+          pipeline.layout(),
+          2,
+          &[shadow_dset.inner()],
+        );
+        bound_key = Some(key);
+        bound_material = None;
+      }
+      if bound_material != Some(Arc::as_ptr(material)) {
+        cmd_buffer.bind_descriptor_sets_from(
+          vk::PipelineBindPoint::GRAPHICS,
+          pipeline.layout(),
+          1,
+          &[material.dset().inner()],
+        );
+        bound_material = Some(Arc::as_ptr(material));
+      }
       cmd_buffer.bind_descriptor_sets(
         vk::PipelineBindPoint::GRAPHICS,
-        self.pipelines[0].layout(),
-        &[obj.0.dset().inner(), obj.1.dset().inner()],
-      );
-      cmd_buffer.set_push_constant_data(
-        self.pipelines[0].layout(),
-        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-        AdBuffer::get_byte_slice(&[camera]),
+        pipeline.layout(),
+        &[mesh.dset().inner()],
       );
-      cmd_buffer.draw(obj.0.indx_count() as _);
+      if key.vertex_streams {
+        cmd_buffer.bind_descriptor_sets_from(
+          vk::PipelineBindPoint::GRAPHICS,
+          pipeline.layout(),
+          3,
+          &[mesh.streams_dset().inner()],
+        );
+      }
+      match indirect {
+        Some((draw_buffer, first_slot)) => cmd_buffer.draw_indirect(
+          draw_buffer.inner(),
+          ((first_slot + i) * std::mem::size_of::<vk::DrawIndirectCommand>()) as _,
+          1,
+        ),
+        None => cmd_buffer.draw(mesh.indx_count() as _),
+      }
+    }
+  }
+
+  fn begin_render_pass(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    subpass_contents: vk::SubpassContents,
+  ) {
+    cmd_buffer.begin_render_pass(
+      self.render_pass.inner(),
+      frame_buffer.inner(),
+      vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: frame_buffer.resolution() },
+      // Clear values are per attachment, the msaa color one is ignored without msaa
+      &[
+        vk::ClearValue { color: vk::ClearColorValue { float32: self.clear_color.to_array() } },
+        vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } },
+        vk::ClearValue { color: vk::ClearColorValue { float32: self.clear_color.to_array() } },
+      ],
+      subpass_contents,
+    );
+  }
+
+  // Batches come from build_batches. Culled draws come from MeshCullRenderer::draw_buffer, with a
+  // command per batch. The shadow set comes from ShadowRenderer
+  pub fn render(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+    shadow_dset: &AdDescriptorSet,
+    batches: &[TriMeshDraw],
+    culled_draws: Option<&AdBuffer>,
+  ) -> Result<(), String> {
+    self.begin_render_pass(cmd_buffer, frame_buffer, vk::SubpassContents::INLINE);
+    Self::set_full_viewport(cmd_buffer, frame_buffer);
+    self.record_draws(
+      cmd_buffer,
+      camera,
+      shadow_dset,
+      batches,
+      culled_draws.map(|draws| (draws, 0)),
+    );
+    cmd_buffer.end_render_pass();
+    Ok(())
+  }
+
+  // Splits the draws over secondary command buffers recorded on the job pool. Each secondary buffer
+  // has to come from a different command pool, vulkan pools can't be recorded from two threads
+  #[allow(clippy::too_many_arguments)]
+  pub fn render_parallel(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    secondary_cmd_buffers: &[AdCommandBuffer],
+    frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+    shadow_dset: &AdDescriptorSet,
+    batches: &[TriMeshDraw],
+    culled_draws: Option<&AdBuffer>,
+    job_pool: &JobPool,
+  ) -> Result<(), String> {
+    if secondary_cmd_buffers.is_empty() {
+      return self.render(cmd_buffer, frame_buffer, camera, shadow_dset, batches, culled_draws);
     }
+    // Chunks keep the sorted order, so each one still binds every pipeline and material once
+    let chunk_size = batches.len().div_ceil(secondary_cmd_buffers.len()).max(1);
+    let chunks = batches
+      .chunks(chunk_size)
+      .zip(secondary_cmd_buffers.iter())
+      .enumerate()
+      .map(|(i, (chunk_objs, secondary_cmd_buffer))| {
+        (i * chunk_size, chunk_objs, secondary_cmd_buffer)
+      })
+      .collect::<Vec<_>>();
+    job_pool
+      .map(&chunks, |(first_slot, chunk_objs, secondary_cmd_buffer)| {
+        secondary_cmd_buffer.begin_secondary(
+          vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+            | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+          self.render_pass.inner(),
+          0,
+          frame_buffer.inner(),
+        )?;
+        Self::set_full_viewport(secondary_cmd_buffer, frame_buffer);
+        self.record_draws(
+          secondary_cmd_buffer,
+          camera,
+          shadow_dset,
+          chunk_objs,
+          culled_draws.map(|draws| (draws, *first_slot)),
+        );
+        secondary_cmd_buffer.end()
+      })?
+      .into_iter()
+      .collect::<Result<Vec<_>, String>>()
+      .map_err(|e| format!("at recording secondary draw cmds: {e}"))?;
+
+    self.begin_render_pass(
+      cmd_buffer,
+      frame_buffer,
+      vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+    );
+    cmd_buffer.execute_commands(&chunks.iter().map(|(_, _, x)| *x).collect::<Vec<_>>());
     cmd_buffer.end_render_pass();
+    Ok(())
   }
 }
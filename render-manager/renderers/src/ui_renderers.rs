@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_data_wrappers::{
+    AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout, AdSampler,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdComputePipeline, AdFrameBuffer},
+};
+use include_bytes_aligned::include_bytes_aligned;
+
+use crate::{
+  post_renderers::{begin_scene_color_read, POST_GROUP_SIZE},
+  render_targets::reallocate_dsets,
+};
+
+static UI_COMPOSITE_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/ui_composite.comp.spv");
+
+const MAX_UI_COMPOSITE_SETS: u32 = 16;
+
+// Blends a UI layer drawn by a DebugOverlayRenderer::new_layer over the finished scene, scaling
+// the scene to the layer's resolution on the way. The result is left in the layer, which is what
+// gets shown. Layer color images need STORAGE usage
+pub struct UiCompositeRenderer {
+  pipeline: AdComputePipeline,
+  dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  sampler: Arc<AdSampler>,
+  // One per frame in flight
+  dsets: Vec<AdDescriptorSet>,
+}
+
+impl UiCompositeRenderer {
+  pub fn new(ash_device: Arc<AdAshDevice>) -> Result<Self, String> {
+    let dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::SAMPLER),
+        (vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_IMAGE),
+      ],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_UI_COMPOSITE_SETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLED_IMAGE,
+          descriptor_count: MAX_UI_COMPOSITE_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLER,
+          descriptor_count: MAX_UI_COMPOSITE_SETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::STORAGE_IMAGE,
+          descriptor_count: MAX_UI_COMPOSITE_SETS,
+        },
+      ],
+    )?);
+    let pipeline =
+      AdComputePipeline::new(ash_device.clone(), UI_COMPOSITE_SHADER_CODE, &[&dset_layout], 0)?;
+    // The scene is usually smaller than the layer, off the edge it holds the edge color
+    let sampler = Arc::new(AdSampler::new_with_info(
+      ash_device,
+      &vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    )?);
+    Ok(Self { pipeline, dset_layout, dset_pool, sampler, dsets: vec![] })
+  }
+
+  // Framebuffers of the same frame in flight are paired up
+  pub fn resize_targets(
+    &mut self,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+    ui_frame_buffers: &[Arc<AdFrameBuffer>],
+  ) -> Result<(), String> {
+    reallocate_dsets(
+      &mut self.dsets,
+      &self.dset_pool,
+      &self.dset_layout,
+      scene_frame_buffers.iter().zip(ui_frame_buffers).map(|(scene_fb, ui_fb)| {
+        vec![
+          AdDescriptorBinding::Image2D((
+            scene_fb.attachments()[0].clone(),
+            vk::ImageLayout::GENERAL,
+          )),
+          AdDescriptorBinding::Sampler(self.sampler.clone()),
+          AdDescriptorBinding::StorageImage((
+            ui_fb.attachments()[0].clone(),
+            vk::ImageLayout::GENERAL,
+          )),
+        ]
+      }),
+    )
+  }
+
+  // After the layer's overlay pass and every pass drawing into the scene. Takes and leaves both
+  // color images in TRANSFER_SRC_OPTIMAL
+  pub fn composite(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+    ui_frame_buffer: &AdFrameBuffer,
+  ) -> Result<(), String> {
+    let Some(dset) = self.dsets.get(frame_idx) else {
+      return Err(format!("no ui composite targets for frame {frame_idx}"));
+    };
+    begin_scene_color_read(cmd_buffer, scene_frame_buffer);
+    let ui_view = &ui_frame_buffer.attachments()[0];
+    ui_view.transition_to_general(
+      cmd_buffer,
+      vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+      vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+      vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+    );
+
+    let resolution = ui_frame_buffer.resolution();
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.pipeline.inner());
+    cmd_buffer.bind_descriptor_sets(
+      vk::PipelineBindPoint::COMPUTE,
+      self.pipeline.layout(),
+      &[dset.inner()],
+    );
+    cmd_buffer.dispatch(
+      resolution.width.div_ceil(POST_GROUP_SIZE),
+      resolution.height.div_ceil(POST_GROUP_SIZE),
+      1,
+    );
+
+    scene_frame_buffer.attachments()[0].transition_from_general(
+      cmd_buffer,
+      vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::AccessFlags::TRANSFER_READ,
+    );
+    // The srgb encode after it waits on color attachment output like it does for the scene
+    ui_view.transition_from_general(
+      cmd_buffer,
+      vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+      vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+      vk::AccessFlags::TRANSFER_READ,
+    );
+    Ok(())
+  }
+}
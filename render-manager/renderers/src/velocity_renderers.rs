@@ -0,0 +1,350 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdImage, AdImageView,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdFrameBuffer, AdPipeline, AdRenderPass},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{glam, triangle_mesh::TriMeshGenerator};
+
+use crate::{
+  render_targets::{color_range, reallocate_dsets},
+  triangle_mesh_renderers::TriMeshDraw,
+};
+
+static VELOCITY_VERT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/velocity.vert.spv");
+static VELOCITY_FRAG_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/velocity.frag.spv");
+
+const VELOCITY_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const MAX_VELOCITY_SETS: u32 = 16;
+
+// VelocityFrameData in the shaders
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct VelocityFrameData {
+  // What the scene was drawn with, jittered with taa
+  pub scene_view_proj: glam::Mat4,
+  pub view_proj: glam::Mat4,
+  pub inv_view_proj: glam::Mat4,
+  pub prev_view_proj: glam::Mat4,
+  // 1 / width, 1 / height, unused, unused
+  pub params: glam::Vec4,
+}
+
+// Where each pixel of a scene drawn by TriMeshMaterialRenderer without msaa was last frame, for the
+// passes after it like taa and motion blur. Meshes write their own motion from their previous
+// transform and previous vertices, the other pixels only moved with the camera and velocity.glsl
+// finds theirs from the depth. Velocity images are left in SHADER_READ_ONLY_OPTIMAL and the scene depth read only until
+// finish is recorded
+pub struct VelocityRenderer {
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  render_pass: Arc<AdRenderPass>,
+  pipeline: AdPipeline,
+  frame_dset_layout: Arc<AdDescriptorSetLayout>,
+  dset_pool: Arc<AdDescriptorPool>,
+  // Sharing the depth of the scene framebuffers, one per frame in flight
+  frame_buffers: Vec<Arc<AdFrameBuffer>>,
+  // Written when the frame is recorded
+  frame_data_buffers: Vec<Arc<AdBuffer>>,
+  frame_dsets: Vec<AdDescriptorSet>,
+}
+
+impl VelocityRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    tri_mesh_gen: &TriMeshGenerator,
+    depth_format: vk::Format,
+  ) -> Result<Self, String> {
+    // Depth is only tested, the scene pass already wrote it. It's left read only for the passes
+    // sampling it
+    let render_pass = Arc::new(AdRenderPass::new(
+      ash_device.clone(),
+      vk::RenderPassCreateFlags::default(),
+      &[
+        vk::AttachmentDescription::default()
+          .format(VELOCITY_FORMAT)
+          .samples(vk::SampleCountFlags::TYPE_1)
+          .initial_layout(vk::ImageLayout::UNDEFINED)
+          .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+          .load_op(vk::AttachmentLoadOp::CLEAR)
+          .store_op(vk::AttachmentStoreOp::STORE),
+        vk::AttachmentDescription::default()
+          .format(depth_format)
+          .samples(vk::SampleCountFlags::TYPE_1)
+          .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+          .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+          .load_op(vk::AttachmentLoadOp::LOAD)
+          .store_op(vk::AttachmentStoreOp::STORE),
+      ],
+      &[vk::SubpassDescription::default()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&[vk::AttachmentReference::default()
+          .attachment(0)
+          .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)])
+        .depth_stencil_attachment(
+          &vk::AttachmentReference::default()
+            .attachment(1)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL),
+        )],
+      &[
+        // The last frame's passes read the velocity being cleared
+        vk::SubpassDependency::default()
+          .src_subpass(vk::SUBPASS_EXTERNAL)
+          .dst_subpass(0)
+          .src_stage_mask(
+            vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::COMPUTE_SHADER,
+          )
+          .dst_stage_mask(
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+              | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+          )
+          .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+          .dst_access_mask(
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+              | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+          ),
+        vk::SubpassDependency::default()
+          .src_subpass(0)
+          .dst_subpass(vk::SUBPASS_EXTERNAL)
+          .src_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+          )
+          .dst_stage_mask(vk::PipelineStageFlags::COMPUTE_SHADER)
+          .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+          .dst_access_mask(vk::AccessFlags::SHADER_READ),
+      ],
+    )?);
+
+    let frame_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[(vk::ShaderStageFlags::VERTEX, vk::DescriptorType::UNIFORM_BUFFER)],
+    )?);
+    let dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_VELOCITY_SETS,
+      &[vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::UNIFORM_BUFFER,
+        descriptor_count: MAX_VELOCITY_SETS,
+      }],
+    )?);
+
+    let pipeline = AdPipeline::new(
+      render_pass.clone(),
+      0,
+      HashMap::from([
+        (vk::ShaderStageFlags::VERTEX, VELOCITY_VERT_SHADER_CODE),
+        (vk::ShaderStageFlags::FRAGMENT, VELOCITY_FRAG_SHADER_CODE),
+      ]),
+      &[tri_mesh_gen.mesh_dset_layout(), &frame_dset_layout],
+      (vk::ShaderStageFlags::VERTEX, 0),
+      // Both sides, only what passes the equal depth test of the scene's own surfaces gets drawn
+      vk::PipelineRasterizationStateCreateInfo::default()
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0),
+      &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
+        vk::PipelineColorBlendAttachmentState::default()
+          .color_write_mask(vk::ColorComponentFlags::RGBA)
+          .blend_enable(false),
+      ]),
+      &vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL),
+      vk::SampleCountFlags::TYPE_1,
+    )
+    .map_err(|e| format!("at creating velocity pipeline: {e}"))?;
+    Ok(Self {
+      ash_device,
+      allocator,
+      render_pass,
+      pipeline,
+      frame_dset_layout,
+      dset_pool,
+      frame_buffers: vec![],
+      frame_data_buffers: vec![],
+      frame_dsets: vec![],
+    })
+  }
+
+  pub fn resolution(&self) -> vk::Extent2D {
+    self.frame_buffers.first().map(|fb| fb.resolution()).unwrap_or_default()
+  }
+
+  // Sized to the first framebuffer, all of them need the same resolution and a single sample
+  pub fn resize_targets(
+    &mut self,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+  ) -> Result<(), String> {
+    let Some(resolution) = scene_frame_buffers.first().map(|fb| fb.resolution()) else {
+      return Err("no framebuffers to make velocity targets for".to_string());
+    };
+    let color_range = color_range();
+    let frame_buffers = scene_frame_buffers
+      .iter()
+      .enumerate()
+      .map(|(i, fb)| {
+        let velocity_image = AdImage::new_2d(
+          self.ash_device.clone(),
+          self.allocator.clone(),
+          MemoryLocation::GpuOnly,
+          &format!("velocity_image_{i}"),
+          VELOCITY_FORMAT,
+          resolution,
+          vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+          vk::SampleCountFlags::TYPE_1,
+          1,
+        )?;
+        let velocity_view =
+          AdImageView::create_view(velocity_image, vk::ImageViewType::TYPE_2D, color_range)?;
+        AdFrameBuffer::new(
+          self.render_pass.clone(),
+          vec![velocity_view, fb.attachments()[1].clone()],
+          resolution,
+          1,
+        )
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    let frame_data_buffers = (0..scene_frame_buffers.len())
+      .map(|i| {
+        AdBuffer::new(
+          self.ash_device.clone(),
+          self.allocator.clone(),
+          MemoryLocation::CpuToGpu,
+          &format!("velocity_frame_data_{i}"),
+          vk::BufferCreateFlags::empty(),
+          std::mem::size_of::<VelocityFrameData>() as _,
+          vk::BufferUsageFlags::UNIFORM_BUFFER,
+        )
+        .map(Arc::new)
+      })
+      .collect::<Result<Vec<_>, String>>()?;
+    reallocate_dsets(
+      &mut self.frame_dsets,
+      &self.dset_pool,
+      &self.frame_dset_layout,
+      frame_data_buffers
+        .iter()
+        .map(|buffer| vec![AdDescriptorBinding::UniformBuffer(buffer.clone())]),
+    )?;
+    self.frame_buffers = frame_buffers;
+    self.frame_data_buffers = frame_data_buffers;
+    Ok(())
+  }
+
+  pub fn velocity_view(&self, frame_idx: usize) -> &Arc<AdImageView> {
+    &self.frame_buffers[frame_idx].attachments()[0]
+  }
+
+  // Bound by the passes reading the velocity, for the camera motion of pixels without a mesh
+  pub fn frame_data_buffer(&self, frame_idx: usize) -> &Arc<AdBuffer> {
+    &self.frame_data_buffers[frame_idx]
+  }
+
+  // Must be recorded outside a render pass after the scene is drawn into the framebuffer of
+  // frame_idx, with the batches it was drawn with
+  pub fn render(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    frame_data: VelocityFrameData,
+    batches: &[TriMeshDraw],
+  ) -> Result<(), String> {
+    let (Some(frame_buffer), Some(frame_dset)) =
+      (self.frame_buffers.get(frame_idx), self.frame_dsets.get(frame_idx))
+    else {
+      return Err(format!("no velocity targets for frame {frame_idx}"));
+    };
+    self.frame_data_buffers[frame_idx].write_data(0, &[frame_data])?;
+
+    let resolution = frame_buffer.resolution();
+    cmd_buffer.begin_render_pass(
+      self.render_pass.inner(),
+      frame_buffer.inner(),
+      vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: resolution },
+      &[
+        vk::ClearValue { color: vk::ClearColorValue { float32: [0.0; 4] } },
+        vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } },
+      ],
+      vk::SubpassContents::INLINE,
+    );
+    cmd_buffer.set_view_port(&[vk::Viewport {
+      x: 0.0,
+      y: 0.0,
+      width: resolution.width as f32,
+      height: resolution.height as f32,
+      min_depth: 0.0,
+      max_depth: 1.0,
+    }]);
+    cmd_buffer
+      .set_scissor(&[vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: resolution }]);
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, self.pipeline.inner());
+    cmd_buffer.bind_descriptor_sets_from(
+      vk::PipelineBindPoint::GRAPHICS,
+      self.pipeline.layout(),
+      1,
+      &[frame_dset.inner()],
+    );
+    // Cutouts aren't alpha tested here, their holes get the velocity of the surface around them
+    for (mesh, _) in batches {
+      cmd_buffer.bind_descriptor_sets(
+        vk::PipelineBindPoint::GRAPHICS,
+        self.pipeline.layout(),
+        &[mesh.dset().inner()],
+      );
+      cmd_buffer.draw(mesh.indx_count() as _);
+    }
+    cmd_buffer.end_render_pass();
+    Ok(())
+  }
+
+  // After the last pass reading the velocity, puts the scene depth back in
+  // DEPTH_STENCIL_ATTACHMENT_OPTIMAL for the overlays
+  pub fn finish(&self, cmd_buffer: &AdCommandBuffer, scene_frame_buffer: &AdFrameBuffer) {
+    let depth_image = scene_frame_buffer.attachments()[1].image();
+    let queue_family = cmd_buffer.cmd_pool().queue().family_index();
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+      vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[vk::ImageMemoryBarrier::default()
+        .image(depth_image.inner())
+        .subresource_range(
+          vk::ImageSubresourceRange::default()
+            .aspect_mask(depth_image.possible_image_aspect())
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1),
+        )
+        .src_queue_family_index(queue_family)
+        .dst_queue_family_index(queue_family)
+        .src_access_mask(vk::AccessFlags::NONE)
+        .dst_access_mask(
+          vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        )
+        .old_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+        .new_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)],
+    );
+  }
+}
@@ -0,0 +1,394 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_data_wrappers::{
+    AdBuffer, AdDescriptorBinding, AdDescriptorPool, AdDescriptorSet, AdDescriptorSetLayout,
+    AdImageView, AdSampler,
+  },
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{AdFrameBuffer, AdPipeline, AdRenderPass},
+};
+use include_bytes_aligned::include_bytes_aligned;
+use renderables::{
+  glam,
+  light::SunLight,
+  water::{WaterPlaneGPU, WaterPlaneGenerator},
+  Camera3D,
+};
+
+static WATER_VERT_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/water.vert.spv");
+static WATER_FRAG_SHADER_CODE: &[u8] = include_bytes_aligned!(4, "shaders/water.frag.spv");
+
+// Reflection dsets for every frame in flight, made again on resize, and the fallback
+const MAX_REFLECTION_DSETS: u32 = 16;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct WaterFrame {
+  camera: Camera3D,
+  // time in seconds, unused, 1 / target width, 1 / target height
+  frame: glam::Vec4,
+  // xyz towards the sun, w highlight brightness
+  sun: glam::Vec4,
+}
+
+// Camera mirrored under a level plane at height. Its image flipped upside down is what the plane
+// reflects, see water.frag
+pub fn reflection_camera(camera: Camera3D, height: f32) -> Camera3D {
+  let mut reflected = camera;
+  reflected.pos.y = 2.0 * height - camera.pos.y;
+  reflected.look_dir.y = -camera.look_dir.y;
+  reflected
+}
+
+// Blends water planes over the output of TriMeshMaterialRenderer, depth tested against the scene
+// without writing depth. Planes with reflection sample the scene rendered again with the
+// reflection camera into a separate framebuffer
+pub struct WaterRenderer {
+  pipeline: AdPipeline,
+  render_pass: Arc<AdRenderPass>,
+  reflection_dset_layout: Arc<AdDescriptorSetLayout>,
+  reflection_dset_pool: Arc<AdDescriptorPool>,
+  sampler: Arc<AdSampler>,
+  // Bound when there is no reflection, planes without reflection never read it
+  fallback_reflection_dset: AdDescriptorSet,
+}
+
+impl WaterRenderer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    water_gen: &WaterPlaneGenerator,
+    sampler: Arc<AdSampler>,
+    fallback_reflection: Arc<AdImageView>,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+  ) -> Result<Self, String> {
+    // Same attachments as the tri renderer framebuffers, keeping depth and msaa color for the
+    // particles and the editor overlay drawn after
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+    let mut attachments = vec![
+      vk::AttachmentDescription::default()
+        .format(color_format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .initial_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .final_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .load_op(match multisampled {
+          true => vk::AttachmentLoadOp::DONT_CARE,
+          false => vk::AttachmentLoadOp::LOAD,
+        })
+        .store_op(vk::AttachmentStoreOp::STORE),
+      vk::AttachmentDescription::default()
+        .format(depth_format)
+        .samples(samples)
+        .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::STORE),
+    ];
+    if multisampled {
+      attachments.push(
+        vk::AttachmentDescription::default()
+          .format(color_format)
+          .samples(samples)
+          .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+          .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+          .load_op(vk::AttachmentLoadOp::LOAD)
+          .store_op(vk::AttachmentStoreOp::STORE),
+      );
+    }
+    let output_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(0)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let msaa_attachment_ref = [vk::AttachmentReference::default()
+      .attachment(2)
+      .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let depth_attachment_ref = vk::AttachmentReference::default()
+      .attachment(1)
+      .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let subpass = match multisampled {
+      true => vk::SubpassDescription::default()
+        .color_attachments(&msaa_attachment_ref)
+        .resolve_attachments(&output_attachment_ref),
+      false => vk::SubpassDescription::default().color_attachments(&output_attachment_ref),
+    };
+    let render_pass = AdRenderPass::new(
+      ash_device.clone(),
+      vk::RenderPassCreateFlags::default(),
+      &attachments,
+      &[subpass
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(&depth_attachment_ref)],
+      &[
+        vk::SubpassDependency::default()
+          .src_subpass(vk::SUBPASS_EXTERNAL)
+          .dst_subpass(0)
+          .src_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+          )
+          .dst_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+          )
+          .src_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+          )
+          .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_READ
+              | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+          ),
+        vk::SubpassDependency::default()
+          .src_subpass(0)
+          .dst_subpass(vk::SUBPASS_EXTERNAL)
+          .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+          .dst_stage_mask(
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+              | vk::PipelineStageFlags::TRANSFER,
+          )
+          .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+          .dst_access_mask(
+            vk::AccessFlags::COLOR_ATTACHMENT_READ
+              | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+              | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+              | vk::AccessFlags::TRANSFER_READ,
+          ),
+      ],
+    )?;
+    let render_pass = Arc::new(render_pass);
+
+    let reflection_dset_layout = Arc::new(AdDescriptorSetLayout::new(
+      ash_device.clone(),
+      &[
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLED_IMAGE),
+        (vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::SAMPLER),
+      ],
+    )?);
+    let reflection_dset_pool = Arc::new(AdDescriptorPool::new(
+      ash_device.clone(),
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+      MAX_REFLECTION_DSETS,
+      &[
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLED_IMAGE,
+          descriptor_count: MAX_REFLECTION_DSETS,
+        },
+        vk::DescriptorPoolSize {
+          ty: vk::DescriptorType::SAMPLER,
+          descriptor_count: MAX_REFLECTION_DSETS,
+        },
+      ],
+    )?);
+    let fallback_reflection_dset = AdDescriptorSet::new(
+      reflection_dset_pool.clone(),
+      &[(
+        reflection_dset_layout.clone(),
+        vec![
+          AdDescriptorBinding::Image2D((
+            fallback_reflection,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          )),
+          AdDescriptorBinding::Sampler(sampler.clone()),
+        ],
+      )],
+    )?
+    .remove(0);
+
+    let pipeline = AdPipeline::new(
+      render_pass.clone(),
+      0,
+      HashMap::from([
+        (vk::ShaderStageFlags::VERTEX, WATER_VERT_SHADER_CODE),
+        (vk::ShaderStageFlags::FRAGMENT, WATER_FRAG_SHADER_CODE),
+      ]),
+      &[water_gen.water_dset_layout(), &reflection_dset_layout],
+      (
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        std::mem::size_of::<WaterFrame>() as u32,
+      ),
+      // Seen from under the surface too
+      vk::PipelineRasterizationStateCreateInfo::default()
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0),
+      &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
+        vk::PipelineColorBlendAttachmentState::default()
+          .color_write_mask(vk::ColorComponentFlags::RGBA)
+          .blend_enable(true)
+          .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+          .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+          .color_blend_op(vk::BlendOp::ADD)
+          .src_alpha_blend_factor(vk::BlendFactor::ONE)
+          .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+          .alpha_blend_op(vk::BlendOp::ADD),
+      ]),
+      &vk::PipelineDepthStencilStateCreateInfo::default()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS),
+      samples,
+    )?;
+
+    Ok(Self {
+      pipeline,
+      render_pass,
+      reflection_dset_layout,
+      reflection_dset_pool,
+      sampler,
+      fallback_reflection_dset,
+    })
+  }
+
+  // Reflection framebuffers need SAMPLED color images
+  pub fn create_reflection_dsets(
+    &self,
+    frame_buffers: &[Arc<AdFrameBuffer>],
+  ) -> Result<Vec<AdDescriptorSet>, String> {
+    AdDescriptorSet::new(
+      self.reflection_dset_pool.clone(),
+      &frame_buffers
+        .iter()
+        .map(|fb| {
+          (
+            self.reflection_dset_layout.clone(),
+            vec![
+              AdDescriptorBinding::Image2D((
+                fb.attachments()[0].clone(),
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+              )),
+              AdDescriptorBinding::Sampler(self.sampler.clone()),
+            ],
+          )
+        })
+        .collect::<Vec<_>>(),
+    )
+  }
+
+  fn reflection_barrier(
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+  ) -> vk::ImageMemoryBarrier<'static> {
+    let color_img = frame_buffer.attachments()[0].image();
+    vk::ImageMemoryBarrier::default()
+      .image(color_img.inner())
+      .subresource_range(
+        vk::ImageSubresourceRange::default()
+          .aspect_mask(vk::ImageAspectFlags::COLOR)
+          .layer_count(1)
+          .base_array_layer(0)
+          .level_count(1)
+          .base_mip_level(0),
+      )
+      .src_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+      .dst_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+      .old_layout(old_layout)
+      .new_layout(new_layout)
+  }
+
+  // reflection is the framebuffer the reflection camera rendered into this frame with its dset.
+  // Must be recorded outside a render pass
+  #[allow(clippy::too_many_arguments)]
+  pub fn render(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+    time_s: f32,
+    sun: &SunLight,
+    planes: &[Arc<WaterPlaneGPU>],
+    reflection: Option<(&AdFrameBuffer, &AdDescriptorSet)>,
+  ) {
+    if let Some((reflection_fb, _)) = reflection {
+      cmd_buffer.pipeline_barrier(
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        vk::PipelineStageFlags::FRAGMENT_SHADER,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &[Self::reflection_barrier(
+          cmd_buffer,
+          reflection_fb,
+          vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+          vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)],
+      );
+    }
+
+    cmd_buffer.begin_render_pass(
+      self.render_pass.inner(),
+      frame_buffer.inner(),
+      vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: frame_buffer.resolution() },
+      &[],
+      vk::SubpassContents::INLINE,
+    );
+    cmd_buffer.set_view_port(&[vk::Viewport {
+      x: 0.0,
+      y: 0.0,
+      width: frame_buffer.resolution().width as f32,
+      height: frame_buffer.resolution().height as f32,
+      min_depth: 0.0,
+      max_depth: 1.0,
+    }]);
+    cmd_buffer.set_scissor(&[vk::Rect2D {
+      offset: vk::Offset2D { x: 0, y: 0 },
+      extent: frame_buffer.resolution(),
+    }]);
+    cmd_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, self.pipeline.inner());
+    let frame = WaterFrame {
+      camera,
+      frame: glam::vec4(
+        time_s,
+        0.0,
+        1.0 / frame_buffer.resolution().width as f32,
+        1.0 / frame_buffer.resolution().height as f32,
+      ),
+      sun: sun.direction.normalize_or_zero().extend(sun.radiance().max_element()),
+    };
+    cmd_buffer.set_push_constant_data(
+      self.pipeline.layout(),
+      vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+      AdBuffer::get_byte_slice(&[frame]),
+    );
+    let reflection_dset = match reflection {
+      Some((_, reflection_dset)) => reflection_dset,
+      None => &self.fallback_reflection_dset,
+    };
+    for plane in planes.iter() {
+      cmd_buffer.bind_descriptor_sets(
+        vk::PipelineBindPoint::GRAPHICS,
+        self.pipeline.layout(),
+        &[plane.dset().inner(), reflection_dset.inner()],
+      );
+      cmd_buffer.draw(6);
+    }
+    cmd_buffer.end_render_pass();
+
+    // Back to where the tri renderer's render pass expects it next time
+    if let Some((reflection_fb, _)) = reflection {
+      cmd_buffer.pipeline_barrier(
+        vk::PipelineStageFlags::FRAGMENT_SHADER,
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &[Self::reflection_barrier(
+          cmd_buffer,
+          reflection_fb,
+          vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        )
+        .src_access_mask(vk::AccessFlags::SHADER_READ)
+        .dst_access_mask(vk::AccessFlags::NONE)],
+      );
+    }
+  }
+}
@@ -0,0 +1,95 @@
+use std::time::{Duration, Instant};
+
+use crate::resource_budget::ResourceUsage;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameTimeSummary {
+  pub average: Duration,
+  // 99 in 100 frames took this long or less
+  pub p99: Duration,
+  pub max: Duration,
+}
+
+// None for no frames
+pub fn summarize_frame_times(frame_times: &[Duration]) -> Option<FrameTimeSummary> {
+  if frame_times.is_empty() {
+    return None;
+  }
+  let mut sorted = frame_times.to_vec();
+  sorted.sort_unstable();
+  // Nearest rank, so a handful of frames still has its worst one as p99
+  let p99_rank = (sorted.len() * 99).div_ceil(100).max(1);
+  Some(FrameTimeSummary {
+    average: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+    p99: sorted[p99_rank - 1],
+    max: sorted[sorted.len() - 1],
+  })
+}
+
+// What the frames drawn between StartBenchmark and StopBenchmark took
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BenchmarkReport {
+  pub frames: u64,
+  pub duration: Duration,
+  // From one present to the next on the render thread
+  pub frame_time: Option<FrameTimeSummary>,
+  // None when the gpu can't write timestamps
+  pub gpu_frame_time: Option<FrameTimeSummary>,
+  pub average_draw_calls: f32,
+  pub max_draw_calls: u32,
+  // Most live at the end of any frame of the run
+  pub peak_resources: ResourceUsage,
+}
+
+pub struct BenchmarkCapture {
+  started_at: Instant,
+  last_presented_at: Instant,
+  frame_times: Vec<Duration>,
+  gpu_frame_times: Vec<Duration>,
+  draw_calls: u64,
+  max_draw_calls: u32,
+  peak_resources: ResourceUsage,
+}
+
+impl BenchmarkCapture {
+  pub fn new() -> Self {
+    let now = Instant::now();
+    Self {
+      started_at: now,
+      last_presented_at: now,
+      frame_times: vec![],
+      gpu_frame_times: vec![],
+      draw_calls: 0,
+      max_draw_calls: 0,
+      peak_resources: ResourceUsage::default(),
+    }
+  }
+
+  // Right after a frame was presented, with what the render manager sampled for it
+  pub fn frame_presented(&mut self, draw_calls: u32, resources: ResourceUsage) {
+    let now = Instant::now();
+    self.frame_times.push(now - self.last_presented_at);
+    self.last_presented_at = now;
+    self.draw_calls += draw_calls as u64;
+    self.max_draw_calls = self.max_draw_calls.max(draw_calls);
+    self.peak_resources = self.peak_resources.max(resources);
+  }
+
+  // Once the gpu finished a frame, frames in flight later than it was presented
+  pub fn frame_timed(&mut self, gpu_time: Duration) {
+    self.gpu_frame_times.push(gpu_time);
+  }
+
+  pub fn finish(self) -> BenchmarkReport {
+    let frames = self.frame_times.len() as u64;
+    BenchmarkReport {
+      frames,
+      duration: self.started_at.elapsed(),
+      frame_time: summarize_frame_times(&self.frame_times),
+      gpu_frame_time: summarize_frame_times(&self.gpu_frame_times),
+      average_draw_calls: self.draw_calls as f32 / frames.max(1) as f32,
+      max_draw_calls: self.max_draw_calls,
+      peak_resources: self.peak_resources,
+    }
+  }
+}
@@ -0,0 +1,61 @@
+use ash_ad_wrappers::ash_context::ash::vk;
+use engine_config::ColorOutput;
+
+// Shaders always output linear colors. The swapchain formats picked here decide where they get
+// encoded to sRGB for display, either by the blit to the swapchain or by the encode pass before it
+const SRGB_SURFACE_FORMATS: [vk::Format; 2] =
+  [vk::Format::B8G8R8A8_SRGB, vk::Format::R8G8B8A8_SRGB];
+const UNORM_SURFACE_FORMATS: [vk::Format; 2] =
+  [vk::Format::B8G8R8A8_UNORM, vk::Format::R8G8B8A8_UNORM];
+
+// HDR, lit colors go past 1 until the tonemap pass brings them into 0..1. Every gpu can render to,
+// blend, sample, store and blit it
+pub const SCENE_COLOR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+// What the swapchain has for the mode, for offscreen targets standing in for one and exported
+// frames
+pub fn display_format(color_output: ColorOutput) -> vk::Format {
+  match color_output {
+    ColorOutput::SrgbTarget => vk::Format::R8G8B8A8_SRGB,
+    ColorOutput::ShaderEncode => vk::Format::R8G8B8A8_UNORM,
+  }
+}
+
+// Usage the scene color images need on top of being render targets and blit sources. The minimap
+// and the post passes blit into them, the post passes sample them and the tonemap and sRGB encode
+// passes rewrite them in place
+pub fn scene_color_usage() -> vk::ImageUsageFlags {
+  vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE
+}
+
+fn surface_formats_for(color_output: ColorOutput) -> &'static [vk::Format] {
+  match color_output {
+    ColorOutput::SrgbTarget => &SRGB_SURFACE_FORMATS,
+    ColorOutput::ShaderEncode => &UNORM_SURFACE_FORMATS,
+  }
+}
+
+// Picks a surface format for the requested mode, switching modes when the surface has no format
+// for it. The blit to the swapchain only converts correctly when both sides agree on encoding
+pub fn select_surface_format(
+  surface_formats: &[vk::SurfaceFormatKHR],
+  requested: ColorOutput,
+) -> Result<(vk::SurfaceFormatKHR, ColorOutput), String> {
+  let fallback = match requested {
+    ColorOutput::SrgbTarget => ColorOutput::ShaderEncode,
+    ColorOutput::ShaderEncode => ColorOutput::SrgbTarget,
+  };
+  for color_output in [requested, fallback] {
+    let surface_format = surface_formats.iter().find(|surface_format| {
+      surface_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+        && surface_formats_for(color_output).contains(&surface_format.format)
+    });
+    if let Some(surface_format) = surface_format {
+      if color_output != requested {
+        eprintln!("no surface format for {requested:?} color output, using {color_output:?}");
+      }
+      return Ok((*surface_format, color_output));
+    }
+  }
+  Err(format!("no 8 bit rgba surface format with sRGB color space in {surface_formats:?}"))
+}
@@ -0,0 +1,42 @@
+use renderables::color_lut::ColorLutGPU;
+use renderers::exposure_renderers::ColorGradingLuts;
+
+use crate::handles::{ColorLutHandle, HandleRegistry};
+
+// Tables the tonemapped frame is graded through, loaded with LoadColorLut. A blend of 0 is all
+// from and 1 all to, a table of None grades nothing so grading can fade in and out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGrading {
+  pub from: Option<ColorLutHandle>,
+  pub to: Option<ColorLutHandle>,
+  pub blend: f32,
+}
+
+impl ColorGrading {
+  // Just the one table
+  pub fn lut(lut: ColorLutHandle) -> Self {
+    Self { from: Some(lut), to: Some(lut), blend: 0.0 }
+  }
+
+  // t of 0 is prev and 1 is self. The blend only moves smoothly while the same two tables are
+  // mixed, a change of tables is taken as it is
+  pub fn interpolate_from(&self, prev: &Self, t: f32) -> Self {
+    if (prev.from, prev.to) != (self.from, self.to) {
+      return *self;
+    }
+    Self { blend: prev.blend + (self.blend - prev.blend) * t, ..*self }
+  }
+
+  // Tables that were destroyed or failed to load grade nothing
+  pub fn luts<'a>(
+    &self,
+    registry: &'a HandleRegistry<ColorLutGPU>,
+    identity: &'a ColorLutGPU,
+  ) -> ColorGradingLuts<'a> {
+    let lookup = |lut: Option<ColorLutHandle>| match lut {
+      Some(lut) => registry.get(lut).map(|lut| lut.as_ref()).unwrap_or(identity),
+      None => identity,
+    };
+    ColorGradingLuts { from: lookup(self.from), to: lookup(self.to), blend: self.blend }
+  }
+}
@@ -0,0 +1,145 @@
+use std::{sync::Arc, time::Instant};
+
+use ash_ad_wrappers::{ash_queue_wrappers::AdCommandBuffer, ash_render_wrappers::AdFrameBuffer};
+use engine_config::PostProcessConfig;
+use renderables::{glam, Camera3D};
+use renderers::{
+  depth_of_field_renderers::{lens_coefficient, DepthOfFieldRenderer},
+  transient_images::TransientImages,
+};
+
+use crate::{
+  depth_readback::{DepthQuery, DepthReadback},
+  exposure::adaptation,
+};
+
+// Autofocus reads the nearest surface around the center of the screen
+const AUTOFOCUS_QUERY: DepthQuery = DepthQuery { screen_pos: glam::Vec2::new(0.5, 0.5), radius: 2 };
+// How fast autofocus pulls to a new distance, per second like exposure adaptation
+const AUTOFOCUS_RATE: f32 = 6.0;
+// Where autofocus goes with only sky under the center
+const AUTOFOCUS_FAR_DISTANCE: f32 = 1000.0;
+
+// Lens the depth of field is drawn with, from the post process config unless the snapshot sets
+// one. See PostProcessConfig for what each does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthOfFieldParams {
+  pub focus_distance: f32,
+  pub aperture: f32,
+  pub focal_length_mm: f32,
+  // focus_distance is only used until autofocus finds what is under the center
+  pub autofocus: bool,
+}
+
+impl DepthOfFieldParams {
+  pub fn from_config(config: &PostProcessConfig) -> Self {
+    Self {
+      focus_distance: config.dof_focus_distance,
+      aperture: config.dof_aperture,
+      focal_length_mm: config.dof_focal_length_mm,
+      autofocus: config.dof_autofocus,
+    }
+  }
+
+  // t of 0 is self and 1 is to, autofocus switches at the end
+  pub fn lerp(&self, to: &Self, t: f32) -> Self {
+    let lerp = |from: f32, to: f32| from + (to - from) * t;
+    Self {
+      focus_distance: lerp(self.focus_distance, to.focus_distance),
+      aperture: lerp(self.aperture, to.aperture),
+      focal_length_mm: lerp(self.focal_length_mm, to.focal_length_mm),
+      autofocus: if t < 1.0 { self.autofocus } else { to.autofocus },
+    }
+  }
+}
+
+// Picks the lens each frame is blurred with and runs autofocus through the depth readback
+pub struct DepthOfField {
+  renderer: DepthOfFieldRenderer,
+  config_params: DepthOfFieldParams,
+  override_params: Option<DepthOfFieldParams>,
+  // Distance autofocus has pulled to so far, None until its first readback
+  autofocus_distance: Option<f32>,
+  last_focused: Option<Instant>,
+}
+
+impl DepthOfField {
+  pub fn new(renderer: DepthOfFieldRenderer, config: &PostProcessConfig) -> Self {
+    Self {
+      renderer,
+      config_params: DepthOfFieldParams::from_config(config),
+      override_params: None,
+      autofocus_distance: None,
+      last_focused: None,
+    }
+  }
+
+  pub fn resize(
+    &mut self,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+    transient_images: &TransientImages,
+  ) -> Result<(), String> {
+    self.renderer.resize_targets(scene_frame_buffers, transient_images)
+  }
+
+  pub fn set_override(&mut self, override_params: Option<DepthOfFieldParams>) {
+    self.override_params = override_params;
+  }
+
+  pub fn params(&self) -> DepthOfFieldParams {
+    self.override_params.unwrap_or(self.config_params)
+  }
+
+  // Distance things are sharp at this frame
+  pub fn focus_distance(&self) -> f32 {
+    let params = self.params();
+    match (params.autofocus, self.autofocus_distance) {
+      (true, Some(distance)) => distance,
+      _ => params.focus_distance,
+    }
+  }
+
+  // Once per frame, after readback collected the frame being reused. Pulls the focus towards the
+  // last sample of the screen center and asks for the next one, unless the game's own query is
+  // still waiting to be recorded
+  pub fn update_focus(&mut self, readback: &mut DepthReadback) {
+    if !self.params().autofocus {
+      self.autofocus_distance = None;
+      self.last_focused = None;
+      return;
+    }
+    let now = Instant::now();
+    if let Some(sample) = readback.latest().filter(|sample| sample.query == AUTOFOCUS_QUERY) {
+      let target = sample.view_depth.unwrap_or(AUTOFOCUS_FAR_DISTANCE);
+      let pull = self
+        .last_focused
+        .map(|last| adaptation(now.duration_since(last).as_secs_f32(), AUTOFOCUS_RATE))
+        .unwrap_or(1.0);
+      let distance = self.autofocus_distance.unwrap_or(target);
+      self.autofocus_distance = Some(distance + (target - distance) * pull);
+      self.last_focused = Some(now);
+    }
+    if !readback.is_pending() {
+      readback.query(AUTOFOCUS_QUERY);
+    }
+  }
+
+  // After the post processing and before the tonemap, camera being what the scene was drawn with
+  pub fn apply(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+  ) -> Result<(), String> {
+    let params = self.params();
+    let focus_distance = self.focus_distance();
+    let lens = lens_coefficient(
+      focus_distance,
+      params.aperture,
+      params.focal_length_mm,
+      scene_frame_buffer.resolution().height,
+    );
+    self.renderer.apply(cmd_buffer, frame_idx, scene_frame_buffer, camera, focus_distance, lens)
+  }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{
+  ash_context::ash::vk, ash_queue_wrappers::AdCommandBuffer, ash_render_wrappers::AdFrameBuffer,
+};
+use renderables::{glam, Camera3D};
+use renderers::depth_readback_renderers::{DepthReadbackRenderer, MAX_DEPTH_READBACK_RADIUS};
+
+// Depth under a point on screen, for placing things where the cursor is or focusing on it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthQuery {
+  // In 0..1 with y going down, like Camera3D::picking_ray
+  pub screen_pos: glam::Vec2,
+  // Texels around the point searched for the nearest surface, up to 7. 0 reads the one texel
+  pub radius: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthSample {
+  pub query: DepthQuery,
+  // Nearest depth buffer value found, 1.0 when only the far plane or sky was
+  pub depth: f32,
+  // Distance along the camera's look direction and where the surface is, None for the far plane
+  pub view_depth: Option<f32>,
+  pub world_pos: Option<glam::Vec3>,
+  pub frame_number: u64,
+}
+
+// Texel of the scene depth under screen_pos, in 0..1 with y going down
+pub fn query_texel(screen_pos: glam::Vec2, resolution: vk::Extent2D) -> glam::IVec2 {
+  let res = glam::vec2(resolution.width as f32, resolution.height as f32);
+  (screen_pos.clamp(glam::Vec2::ZERO, glam::Vec2::ONE) * res)
+    .floor()
+    .as_ivec2()
+    .min(glam::ivec2(resolution.width as i32 - 1, resolution.height as i32 - 1))
+}
+
+// Nearest of the depths read back and where it is, with the camera the frame was drawn with
+pub fn depth_sample(
+  query: DepthQuery,
+  camera: &Camera3D,
+  depths: &[f32],
+  frame_number: u64,
+) -> DepthSample {
+  let depth = depths.iter().copied().fold(1.0f32, f32::min);
+  let world_pos = (depth < 1.0).then(|| camera.unproject(query.screen_pos, depth));
+  let view_depth = world_pos
+    .map(|pos| (pos - camera.pos.truncate()).dot(camera.look_dir.truncate().normalize_or_zero()));
+  DepthSample { query, depth, view_depth, world_pos, frame_number }
+}
+
+// Copies the depth under a query out of the frame it is recorded in, the sample is made once that
+// frame's slot comes around again and its fence has been waited on
+pub struct DepthReadback {
+  renderer: DepthReadbackRenderer,
+  pending: Option<DepthQuery>,
+  // Per frame in flight, what was recorded into it
+  in_flight: Vec<Option<(DepthQuery, Camera3D, u64)>>,
+  latest: Option<DepthSample>,
+}
+
+impl DepthReadback {
+  pub fn new(renderer: DepthReadbackRenderer, frames_in_flight: usize) -> Self {
+    Self { renderer, pending: None, in_flight: vec![None; frames_in_flight], latest: None }
+  }
+
+  pub fn resize(&mut self, scene_frame_buffers: &[Arc<AdFrameBuffer>]) -> Result<(), String> {
+    self.renderer.resize_targets(scene_frame_buffers)
+  }
+
+  // Replaces a query not recorded yet
+  pub fn query(&mut self, query: DepthQuery) {
+    self.pending = Some(query);
+  }
+
+  pub fn is_pending(&self) -> bool {
+    self.pending.is_some()
+  }
+
+  pub fn latest(&self) -> Option<DepthSample> {
+    self.latest
+  }
+
+  // Call once frame_idx's fence has been waited on, before recording into it again
+  pub fn collect(&mut self, frame_idx: usize) -> Result<(), String> {
+    let Some((query, camera, frame_number)) =
+      self.in_flight.get_mut(frame_idx).and_then(Option::take)
+    else {
+      return Ok(());
+    };
+    let depths = self.renderer.read(frame_idx, query.radius)?;
+    self.latest = Some(depth_sample(query, &camera, &depths, frame_number));
+    Ok(())
+  }
+
+  // After the scene depth is drawn into frame_buffer, camera being what it was drawn with
+  pub fn record(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+    frame_number: u64,
+  ) -> Result<(), String> {
+    let Some(mut query) = self.pending.take() else { return Ok(()) };
+    query.radius = query.radius.min(MAX_DEPTH_READBACK_RADIUS);
+    let texel = query_texel(query.screen_pos, frame_buffer.resolution());
+    self.renderer.record(cmd_buffer, frame_idx, frame_buffer, texel, query.radius)?;
+    if let Some(slot) = self.in_flight.get_mut(frame_idx) {
+      *slot = Some((query, camera, frame_number));
+    }
+    Ok(())
+  }
+}
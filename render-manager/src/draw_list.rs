@@ -0,0 +1,169 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::handles::{MaterialHandle, MeshHandle};
+
+// Layers a renderable is on or a camera sees, a renderable is drawn by cameras sharing a layer
+// with it. For things like editor only helpers or meshes only a minimap camera shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerMask(pub u32);
+
+impl LayerMask {
+  pub const NONE: LayerMask = LayerMask(0);
+  pub const ALL: LayerMask = LayerMask(u32::MAX);
+  // What renderables are added on
+  pub const DEFAULT: LayerMask = LayerMask::layer(0);
+
+  // Just the one layer, from 0 to 31
+  pub const fn layer(index: u32) -> Self {
+    Self(1 << index)
+  }
+
+  pub const fn union(self, other: LayerMask) -> Self {
+    Self(self.0 | other.0)
+  }
+
+  pub const fn without(self, other: LayerMask) -> Self {
+    Self(self.0 & !other.0)
+  }
+
+  pub const fn intersects(self, other: LayerMask) -> bool {
+    self.0 & other.0 != 0
+  }
+}
+
+impl Default for LayerMask {
+  fn default() -> Self {
+    Self::DEFAULT
+  }
+}
+
+struct DrawEntry {
+  material: Option<MaterialHandle>,
+  visible: bool,
+  layers: LayerMask,
+}
+
+// Renderables the game registered, kept across frames. Anything that changes which meshes get
+// drawn or with what marks it dirty, and only then are the draw batches built again
+pub struct DrawList {
+  entries: HashMap<MeshHandle, DrawEntry>,
+  // Hidden by room visibility for the current camera, separate from what the game hides
+  culled: HashSet<MeshHandle>,
+  camera_layers: LayerMask,
+  dirty: bool,
+}
+
+impl DrawList {
+  pub fn new() -> Self {
+    Self {
+      entries: HashMap::new(),
+      culled: HashSet::new(),
+      camera_layers: LayerMask::ALL,
+      dirty: false,
+    }
+  }
+
+  // Registering a mesh again replaces its material, makes it visible and puts it back on the
+  // default layer
+  pub fn add(&mut self, mesh: MeshHandle, material: Option<MaterialHandle>) {
+    let entry = DrawEntry { material, visible: true, layers: LayerMask::DEFAULT };
+    self.entries.insert(mesh, entry);
+    self.dirty = true;
+  }
+
+  pub fn remove(&mut self, mesh: MeshHandle) -> bool {
+    let removed = self.entries.remove(&mesh).is_some();
+    self.dirty |= removed;
+    removed
+  }
+
+  pub fn set_visible(&mut self, mesh: MeshHandle, visible: bool) -> Result<(), String> {
+    let entry = self.entries.get_mut(&mesh).ok_or(format!("{mesh:?} is not in the draw list"))?;
+    self.dirty |= entry.visible != visible;
+    entry.visible = visible;
+    Ok(())
+  }
+
+  pub fn set_layers(&mut self, mesh: MeshHandle, layers: LayerMask) -> Result<(), String> {
+    let entry = self.entries.get_mut(&mesh).ok_or(format!("{mesh:?} is not in the draw list"))?;
+    self.dirty |= entry.layers != layers;
+    entry.layers = layers;
+    Ok(())
+  }
+
+  // Only marks dirty when it changes, so it can be set every frame
+  pub fn set_camera_layers(&mut self, camera_layers: LayerMask) {
+    self.dirty |= camera_layers != self.camera_layers;
+    self.camera_layers = camera_layers;
+  }
+
+  pub fn camera_layers(&self) -> LayerMask {
+    self.camera_layers
+  }
+
+  fn is_shown(&self, entry: &DrawEntry) -> bool {
+    entry.visible && entry.layers.intersects(self.camera_layers)
+  }
+
+  pub fn meshes(&self) -> impl Iterator<Item = MeshHandle> + '_ {
+    self.entries.keys().copied()
+  }
+
+  // Visible entries on a layer the camera sees, culled ones too since they can still cast shadows
+  // into view. Ones on other layers don't, they aren't part of what the camera looks at
+  pub fn shown(&self) -> impl Iterator<Item = MeshHandle> + '_ {
+    self.entries.iter().filter(|(_, entry)| self.is_shown(entry)).map(|(mesh, _)| *mesh)
+  }
+
+  // Shown entries that have a material, culled ones included
+  pub fn shown_materials(&self) -> impl Iterator<Item = (MeshHandle, MaterialHandle)> + '_ {
+    self
+      .entries
+      .iter()
+      .filter(|(_, entry)| self.is_shown(entry))
+      .filter_map(|(mesh, entry)| Some((*mesh, entry.material?)))
+  }
+
+  // Only marks dirty when the set changes, so it can be set every frame
+  pub fn set_culled(&mut self, culled: HashSet<MeshHandle>) {
+    self.dirty |= culled != self.culled;
+    self.culled = culled;
+  }
+
+  // For resources the entries point at being created or destroyed
+  pub fn mark_dirty(&mut self) {
+    self.dirty = true;
+  }
+
+  pub fn is_dirty(&self) -> bool {
+    self.dirty
+  }
+
+  // Visible entries on any of the layers, for cameras other than the main one. Room culling is
+  // left out, it is only right for the main camera
+  pub fn visible_on(&self, layers: LayerMask) -> Vec<(MeshHandle, Option<MaterialHandle>)> {
+    self
+      .entries
+      .iter()
+      .filter(|(_, entry)| entry.visible && entry.layers.intersects(layers))
+      .map(|(mesh, entry)| (*mesh, entry.material))
+      .collect()
+  }
+
+  // Shown entries that aren't culled to build batches from, clears the dirty flag
+  pub fn take_visible(&mut self) -> Vec<(MeshHandle, Option<MaterialHandle>)> {
+    self.dirty = false;
+    self
+      .entries
+      .iter()
+      .filter(|(mesh, entry)| self.is_shown(entry) && !self.culled.contains(mesh))
+      .map(|(mesh, entry)| (*mesh, entry.material))
+      .collect()
+  }
+}
+
+impl Default for DrawList {
+  fn default() -> Self {
+    Self::new()
+  }
+}
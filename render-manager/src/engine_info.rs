@@ -0,0 +1,83 @@
+use ash_ad_wrappers::ash_context::{ash::vk, AdDeviceInfo};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrateVersion {
+  pub name: String,
+  pub version: String,
+}
+
+// Versions and the gpu the engine runs on, for the startup log and about or diagnostics screens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineInfo {
+  // Outermost first, the game and app put theirs in front of the renderer's
+  pub crates: Vec<CrateVersion>,
+  pub gpu: AdDeviceInfo,
+}
+
+fn mebibytes(bytes: u64) -> u64 {
+  bytes / (1024 * 1024)
+}
+
+impl EngineInfo {
+  pub fn new(gpu: AdDeviceInfo) -> Self {
+    let crates = [
+      ("render-manager", env!("CARGO_PKG_VERSION")),
+      ("renderers", renderers::VERSION),
+      ("renderables", renderables::VERSION),
+      ("ash-ad-wrappers", ash_ad_wrappers::VERSION),
+      ("graphics-backend", ash_ad_wrappers::graphics_backend::VERSION),
+    ]
+    .into_iter()
+    .map(|(name, version)| CrateVersion { name: name.to_string(), version: version.to_string() })
+    .collect();
+    Self { crates, gpu }
+  }
+
+  pub fn with_crate(mut self, name: &str, version: &str) -> Self {
+    self.crates.insert(0, CrateVersion { name: name.to_string(), version: version.to_string() });
+    self
+  }
+
+  // One line each, ready to log or show
+  pub fn report_lines(&self) -> Vec<String> {
+    let gpu = &self.gpu;
+    let mut lines = vec![
+      self
+        .crates
+        .iter()
+        .map(|krate| format!("{} {}", krate.name, krate.version))
+        .collect::<Vec<_>>()
+        .join(", "),
+      format!(
+        "gpu: {} ({:?}, vendor {:#06x} device {:#06x})",
+        gpu.name, gpu.device_type, gpu.vendor_id, gpu.device_id
+      ),
+      format!("vulkan {}, driver {}", gpu.api_version_string(), gpu.driver_version),
+    ];
+    if !gpu.driver_name.is_empty() {
+      lines.push(format!("driver: {} {}", gpu.driver_name, gpu.driver_info));
+    }
+    lines.push(format!("features: {}", gpu.features.join(", ")));
+    lines.push(format!("extensions: {}", gpu.extensions.join(", ")));
+    for family in gpu.queue_families.iter() {
+      lines.push(format!(
+        "queue family {}: {} queues, {:?}, {} timestamp bits",
+        family.index, family.queue_count, family.flags, family.timestamp_valid_bits
+      ));
+    }
+    for (heap_idx, heap) in gpu.memory_heaps.iter().enumerate() {
+      let kind = if heap.device_local { "device local" } else { "host" };
+      let host_visible = heap
+        .memory_types
+        .iter()
+        .filter(|flags| flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE))
+        .count();
+      lines.push(format!(
+        "memory heap {heap_idx}: {} MiB {kind}, {} memory types, {host_visible} host visible",
+        mebibytes(heap.size),
+        heap.memory_types.len()
+      ));
+    }
+    lines
+  }
+}
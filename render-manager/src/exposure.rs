@@ -0,0 +1,75 @@
+use std::{sync::Arc, time::Instant};
+
+use ash_ad_wrappers::{ash_queue_wrappers::AdCommandBuffer, ash_render_wrappers::AdFrameBuffer};
+use engine_config::{ExposureMode, PostProcessConfig};
+use renderers::exposure_renderers::{ColorGradingLuts, ExposureRenderer, TonemapExposure};
+
+// Share of the way to the metered exposure covered after elapsed_s at rate per second. The same
+// over a second whatever the frame rate
+pub fn adaptation(elapsed_s: f32, rate: f32) -> f32 {
+  1.0 - (-elapsed_s * rate).exp()
+}
+
+// Picks the exposure each frame is tonemapped with, from the config or an override
+pub struct Exposure {
+  renderer: ExposureRenderer,
+  mode: ExposureMode,
+  ev: f32,
+  ev_range: (f32, f32),
+  adaptation_rate: f32,
+  override_ev: Option<f32>,
+  // None until the first metered frame, which takes its exposure right away
+  last_metered: Option<Instant>,
+}
+
+impl Exposure {
+  pub fn new(renderer: ExposureRenderer, config: &PostProcessConfig) -> Self {
+    Self {
+      renderer,
+      mode: config.exposure_mode,
+      ev: config.exposure_ev,
+      ev_range: (config.min_exposure_ev, config.max_exposure_ev),
+      adaptation_rate: config.exposure_adaptation_rate,
+      override_ev: None,
+      last_metered: None,
+    }
+  }
+
+  pub fn resize(&mut self, scene_frame_buffers: &[Arc<AdFrameBuffer>]) -> Result<(), String> {
+    self.renderer.resize_targets(scene_frame_buffers)
+  }
+
+  pub fn set_override(&mut self, override_ev: Option<f32>) {
+    self.override_ev = override_ev;
+  }
+
+  fn frame_exposure(&mut self) -> TonemapExposure {
+    if let Some(ev) = self.override_ev {
+      return TonemapExposure::Fixed(ev);
+    }
+    match self.mode {
+      ExposureMode::Manual => TonemapExposure::Fixed(self.ev),
+      ExposureMode::Auto => {
+        let now = Instant::now();
+        let adaptation = self
+          .last_metered
+          .map(|last| adaptation(now.duration_since(last).as_secs_f32(), self.adaptation_rate))
+          .unwrap_or(1.0);
+        self.last_metered = Some(now);
+        TonemapExposure::Metered { adaptation, min_ev: self.ev_range.0, max_ev: self.ev_range.1 }
+      }
+    }
+  }
+
+  // After the post processing, before the overlays
+  pub fn tonemap(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+    grading: Option<ColorGradingLuts>,
+  ) -> Result<(), String> {
+    let exposure = self.frame_exposure();
+    self.renderer.tonemap(cmd_buffer, frame_idx, scene_frame_buffer, exposure, grading)
+  }
+}
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{ash_queue_wrappers::AdCommandBuffer, ash_render_wrappers::AdFrameBuffer};
+use engine_config::PostProcessConfig;
+use renderers::{
+  film_effects_renderers::{FilmEffectsParams, FilmEffectsRenderer, Vignette},
+  transient_images::TransientImages,
+};
+
+// The effects turned on in the post process config
+pub fn config_film_effects(config: &PostProcessConfig) -> FilmEffectsParams {
+  FilmEffectsParams {
+    vignette: config.vignette.then_some(Vignette {
+      intensity: config.vignette_intensity,
+      radius: config.vignette_radius,
+      softness: config.vignette_softness,
+    }),
+    chromatic_aberration: config
+      .chromatic_aberration
+      .then_some(config.chromatic_aberration_strength),
+    film_grain: config.film_grain.then_some(config.film_grain_intensity),
+  }
+}
+
+// Picks the effects each frame is drawn with, from the config or an override
+pub struct FilmEffects {
+  renderer: FilmEffectsRenderer,
+  config_params: FilmEffectsParams,
+  override_params: Option<FilmEffectsParams>,
+}
+
+impl FilmEffects {
+  pub fn new(renderer: FilmEffectsRenderer, config: &PostProcessConfig) -> Self {
+    Self { renderer, config_params: config_film_effects(config), override_params: None }
+  }
+
+  pub fn resize(
+    &mut self,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+    transient_images: &TransientImages,
+  ) -> Result<(), String> {
+    self.renderer.resize_targets(scene_frame_buffers, transient_images)
+  }
+
+  // For effects switched on or off while running, an override still wins
+  pub fn set_config(&mut self, config: &PostProcessConfig) {
+    self.config_params = config_film_effects(config);
+  }
+
+  pub fn set_override(&mut self, override_params: Option<FilmEffectsParams>) {
+    self.override_params = override_params;
+  }
+
+  pub fn params(&self) -> FilmEffectsParams {
+    self.override_params.unwrap_or(self.config_params)
+  }
+
+  // After the tonemap, before the overlays
+  pub fn apply(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+    frame_number: u64,
+  ) -> Result<(), String> {
+    self.renderer.apply(cmd_buffer, frame_idx, scene_frame_buffer, &self.params(), frame_number)
+  }
+}
@@ -0,0 +1,46 @@
+use renderdoc::{RenderDoc, V141};
+
+// RenderDoc's in application api. Only found when the app was launched from RenderDoc or it was
+// injected, the library is never loaded by the engine itself
+pub struct FrameCapture {
+  renderdoc: Option<RenderDoc<V141>>,
+  // Captures written so far that were already printed
+  reported_captures: u32,
+}
+
+impl FrameCapture {
+  pub fn new() -> Self {
+    let renderdoc = RenderDoc::<V141>::new().ok();
+    if let Some(renderdoc) = &renderdoc {
+      let (major, minor, patch) = renderdoc.get_api_version();
+      println!("renderdoc {major}.{minor}.{patch} attached, frames can be captured");
+    }
+    Self { renderdoc, reported_captures: 0 }
+  }
+
+  // Captures the next frames presented, each to its own file
+  pub fn trigger(&mut self, frames: u32) -> Result<(), String> {
+    let Some(renderdoc) = &mut self.renderdoc else {
+      return Err(
+        "renderdoc is not attached, launch the app from renderdoc to capture".to_string(),
+      );
+    };
+    if frames == 0 {
+      return Err("at least one frame should be captured".to_string());
+    }
+    renderdoc.trigger_multi_frame_capture(frames);
+    Ok(())
+  }
+
+  // After present, prints the files of captures finished since the last call
+  pub fn report_new_captures(&mut self) {
+    let Some(renderdoc) = &self.renderdoc else { return };
+    let num_captures = renderdoc.get_num_captures();
+    for capture_idx in self.reported_captures..num_captures {
+      if let Some((path, _)) = renderdoc.get_capture(capture_idx) {
+        println!("frame capture written to {}", path.display());
+      }
+    }
+    self.reported_captures = num_captures;
+  }
+}
@@ -0,0 +1,352 @@
+use ash_ad_wrappers::{
+  ash_context::ash::vk,
+  ash_data_wrappers::image::RgbaImage,
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::AdFrameBuffer,
+  backend::{VulkanDevice, VulkanImage},
+  graphics_backend::{
+    BackendBuffer, BufferDesc, BufferUsage, CommandList, Extent2D, Format, GraphicsDevice,
+    ImageDesc, MemoryLocation,
+  },
+};
+
+use crate::frame_export::ExportedFrame;
+
+// Differences at or under this are left out of the heatmap, sRGB rounding stays gray
+pub const DIFF_NOISE_FLOOR: f32 = 1.5 / 255.0;
+// Heatmap from the smallest difference shown to the largest one in the frame
+const HEAT_RAMP: [[f32; 3]; 4] =
+  [[0.1, 0.2, 1.0], [0.1, 0.9, 0.3], [1.0, 0.9, 0.1], [1.0, 0.1, 0.1]];
+
+// Largest weighted YIQ difference any two colors have
+const MAX_YIQ_DELTA: f32 = 35215.0 / (255.0 * 255.0);
+
+pub fn yiq(texel: [u8; 4]) -> [f32; 3] {
+  let [r, g, b] = [texel[0], texel[1], texel[2]].map(|x| x as f32 / 255.0);
+  [
+    0.298_895 * r + 0.586_622 * g + 0.114_482 * b,
+    0.595_978 * r - 0.274_176 * g - 0.321_802 * b,
+    0.211_470 * r - 0.522_617 * g + 0.311_147 * b,
+  ]
+}
+
+// Difference of two sRGB texels as seen, in 0..1. Brightness counts most, like in the YIQ
+// weighting pixelmatch uses. Alpha is left out, frames are opaque
+pub fn perceptual_delta(a: [u8; 4], b: [u8; 4]) -> f32 {
+  let ([ay, ai, aq], [by, bi, bq]) = (yiq(a), yiq(b));
+  let (y, i, q) = (ay - by, ai - bi, aq - bq);
+  ((0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / MAX_YIQ_DELTA).sqrt().min(1.0)
+}
+
+// t in 0..1 along HEAT_RAMP, as sRGB
+pub fn heat_color(t: f32) -> [u8; 4] {
+  let t = t.clamp(0.0, 1.0) * (HEAT_RAMP.len() - 1) as f32;
+  let idx = (t as usize).min(HEAT_RAMP.len() - 2);
+  let (a, b, f) = (HEAT_RAMP[idx], HEAT_RAMP[idx + 1], t - idx as f32);
+  let [r, g, b] = [0, 1, 2].map(|i| ((a[i] + (b[i] - a[i]) * f) * 255.0).round() as u8);
+  [r, g, b, u8::MAX]
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameDiffResult {
+  pub changed: usize,
+  pub max_delta: f32,
+  // The after frame darkened to gray, with changed pixels colored by how much they changed
+  // relative to max_delta so subtle differences show up too
+  pub heatmap: RgbaImage,
+}
+
+pub fn diff_heatmap(before: &RgbaImage, after: &RgbaImage) -> Result<FrameDiffResult, String> {
+  if before.dimensions() != after.dimensions() {
+    return Err(format!(
+      "before frame is {:?} but the after one is {:?}",
+      before.dimensions(),
+      after.dimensions()
+    ));
+  }
+  let deltas = before
+    .pixels()
+    .zip(after.pixels())
+    .map(|(b, a)| perceptual_delta(b.0, a.0))
+    .collect::<Vec<_>>();
+  let max_delta = deltas.iter().copied().fold(0.0f32, f32::max);
+  let range = (max_delta - DIFF_NOISE_FLOOR).max(f32::EPSILON);
+  let mut changed = 0;
+  let mut heatmap = RgbaImage::new(after.width(), after.height());
+  for ((delta, a), h) in deltas.iter().zip(after.pixels()).zip(heatmap.pixels_mut()) {
+    h.0 = match *delta > DIFF_NOISE_FLOOR {
+      true => {
+        changed += 1;
+        heat_color((delta - DIFF_NOISE_FLOOR) / range)
+      }
+      false => {
+        let gray = (yiq(a.0)[0] * 255.0 * 0.3) as u8;
+        [gray, gray, gray, u8::MAX]
+      }
+    };
+  }
+  Ok(FrameDiffResult { changed, max_delta, heatmap })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDiffCommand {
+  // The next frame finished is kept as the one compared against
+  CaptureBefore,
+  // The next frame finished is compared with the before one, and the heatmap shown
+  CaptureAfter,
+  // Whether the heatmap covers the scene, once there is one
+  Show(bool),
+  // Drops both frames and the heatmap
+  Clear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffSlot {
+  Before,
+  After,
+}
+
+// Uploaded through the backend traits, the image is ready once this returns
+pub fn upload_heatmap<D: GraphicsDevice>(
+  device: &D,
+  heatmap: &RgbaImage,
+) -> Result<D::Image, String> {
+  let texels = heatmap.as_raw();
+  let upload = device.create_buffer(&BufferDesc {
+    name: "frame_diff_heatmap_upload".to_string(),
+    size: texels.len() as u64,
+    usage: BufferUsage::default(),
+    location: MemoryLocation::CpuToGpu,
+  })?;
+  upload.write(0, texels)?;
+  let image = device.create_image(&ImageDesc {
+    name: "frame_diff_heatmap".to_string(),
+    format: Format::Rgba8Srgb,
+    extent: Extent2D { width: heatmap.width(), height: heatmap.height() },
+    sampled: false,
+    storage: false,
+  })?;
+  let mut commands = device.begin_commands()?;
+  commands.copy_buffer_to_image(&upload, &image)?;
+  device.submit_and_wait(commands)?;
+  Ok(image)
+}
+
+// Frames come back through a CpuCopy frame export, which is forced while one is being captured.
+// The heatmap is worked out on the cpu once both are in, then blitted over the scene
+pub struct FrameDiff {
+  device: VulkanDevice,
+  // What's being captured and the first frame number that can be it
+  pending: Option<(DiffSlot, u64)>,
+  before: Option<RgbaImage>,
+  heatmap: Option<VulkanImage>,
+  shown: bool,
+}
+
+impl FrameDiff {
+  pub fn new(device: VulkanDevice) -> Self {
+    Self { device, pending: None, before: None, heatmap: None, shown: false }
+  }
+
+  pub fn is_capturing(&self) -> bool {
+    self.pending.is_some()
+  }
+
+  // The heatmap isn't drawn into frames being captured
+  pub fn heatmap(&self) -> Option<&VulkanImage> {
+    self.heatmap.as_ref().filter(|_| self.shown && self.pending.is_none())
+  }
+
+  // Returns the heatmap replaced, for retiring once the frames in flight are done with it.
+  // frame_number is the next frame to be drawn
+  pub fn command(&mut self, command: FrameDiffCommand, frame_number: u64) -> Option<VulkanImage> {
+    match command {
+      FrameDiffCommand::CaptureBefore => self.pending = Some((DiffSlot::Before, frame_number)),
+      FrameDiffCommand::CaptureAfter => self.pending = Some((DiffSlot::After, frame_number)),
+      FrameDiffCommand::Show(shown) => self.shown = shown,
+      FrameDiffCommand::Clear => {
+        self.pending = None;
+        self.before = None;
+        return self.heatmap.take();
+      }
+    }
+    None
+  }
+
+  // With the latest exported frame, once per frame. Returns the heatmap replaced like command
+  pub fn collect(&mut self, latest: Option<ExportedFrame>) -> Result<Option<VulkanImage>, String> {
+    let Some((slot, first_frame)) = self.pending else { return Ok(None) };
+    let Some(ExportedFrame::Pixels { resolution, data, frame_number }) = latest else {
+      return Ok(None);
+    };
+    if frame_number < first_frame {
+      return Ok(None);
+    }
+    self.pending = None;
+    let mut texels = data.to_vec();
+    texels.chunks_exact_mut(4).for_each(|texel| texel[3] = u8::MAX);
+    let frame = RgbaImage::from_raw(resolution.width, resolution.height, texels)
+      .ok_or("exported frame is smaller than its resolution".to_string())?;
+    if slot == DiffSlot::Before {
+      self.before = Some(frame);
+      return Ok(None);
+    }
+    let Some(before) = &self.before else {
+      return Err("no before frame to compare with, capture one first".to_string());
+    };
+    let diff = diff_heatmap(before, &frame)?;
+    println!(
+      "frame diff: {} of {} pixels changed, up to {:.3}",
+      diff.changed,
+      resolution.width * resolution.height,
+      diff.max_delta
+    );
+    let heatmap = upload_heatmap(&self.device, &diff.heatmap)?;
+    self.shown = true;
+    Ok(self.heatmap.replace(heatmap))
+  }
+
+  // Like Minimap::composite, the scene color is in TRANSFER_SRC_OPTIMAL after its pass and is put
+  // back in it. Stretched over the whole scene, which may be drawn at another resolution. The
+  // heatmap stays in GENERAL, where the backend leaves its images
+  pub fn composite(&self, cmd_buffer: &AdCommandBuffer, scene_frame_buffer: &AdFrameBuffer) {
+    let Some(heatmap) = self.heatmap() else { return };
+    let heatmap = heatmap.view().image();
+    let scene_image = scene_frame_buffer.attachments()[0].image();
+    let color_range = vk::ImageSubresourceRange::default()
+      .aspect_mask(vk::ImageAspectFlags::COLOR)
+      .base_mip_level(0)
+      .level_count(1)
+      .base_array_layer(0)
+      .layer_count(1);
+    let color_layers = vk::ImageSubresourceLayers::default()
+      .aspect_mask(vk::ImageAspectFlags::COLOR)
+      .mip_level(0)
+      .base_array_layer(0)
+      .layer_count(1);
+    let queue_family = cmd_buffer.cmd_pool().queue().family_index();
+
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[vk::ImageMemoryBarrier::default()
+        .image(scene_image.inner())
+        .subresource_range(color_range)
+        .src_queue_family_index(queue_family)
+        .dst_queue_family_index(queue_family)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::TRANSFER_READ)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)],
+    );
+    cmd_buffer.blit_image(
+      heatmap.inner(),
+      vk::ImageLayout::GENERAL,
+      scene_image.inner(),
+      vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+      &[vk::ImageBlit::default()
+        .src_subresource(color_layers)
+        .src_offsets(heatmap.full_range_offset_3d())
+        .dst_subresource(color_layers)
+        .dst_offsets(scene_image.full_range_offset_3d())],
+      vk::Filter::NEAREST,
+    );
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+        | vk::PipelineStageFlags::COMPUTE_SHADER
+        | vk::PipelineStageFlags::TRANSFER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[vk::ImageMemoryBarrier::default()
+        .image(scene_image.inner())
+        .subresource_range(color_range)
+        .src_queue_family_index(queue_family)
+        .dst_queue_family_index(queue_family)
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(
+          vk::AccessFlags::COLOR_ATTACHMENT_READ
+            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::SHADER_READ
+            | vk::AccessFlags::SHADER_WRITE
+            | vk::AccessFlags::TRANSFER_READ,
+        )
+        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)],
+    );
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use ash_ad_wrappers::ash_data_wrappers::image::Rgba;
+
+  use super::*;
+
+  const GRAY: [u8; 4] = [100, 100, 100, 255];
+
+  #[test]
+  fn deltas_leave_out_alpha_and_peak_at_black_to_white() {
+    assert_eq!(perceptual_delta(GRAY, GRAY), 0.0);
+    assert_eq!(perceptual_delta(GRAY, [100, 100, 100, 0]), 0.0);
+    let lighter = [140, 120, 100, 255];
+    assert_eq!(perceptual_delta(GRAY, lighter), perceptual_delta(lighter, GRAY));
+    let black_to_white = perceptual_delta([0, 0, 0, 255], [255, 255, 255, 255]);
+    assert!(black_to_white > 0.95 && black_to_white <= 1.0);
+  }
+
+  #[test]
+  fn one_level_of_rounding_is_under_the_noise_floor() {
+    for channel in 0..3 {
+      let mut rounded = GRAY;
+      rounded[channel] += 1;
+      assert!(perceptual_delta(GRAY, rounded) <= DIFF_NOISE_FLOOR, "channel {channel}");
+    }
+    assert!(perceptual_delta(GRAY, [101, 101, 101, 255]) <= DIFF_NOISE_FLOOR);
+    assert!(perceptual_delta(GRAY, [102, 102, 102, 255]) > DIFF_NOISE_FLOOR);
+  }
+
+  #[test]
+  fn heat_runs_from_blue_to_red() {
+    let ramp = (0..=20).map(|i| heat_color(i as f32 / 20.0)).collect::<Vec<_>>();
+    for pair in ramp.windows(2) {
+      assert!(pair[1][0] >= pair[0][0] && pair[1][2] <= pair[0][2], "{pair:?}");
+    }
+    assert!(ramp.iter().all(|color| color[3] == u8::MAX));
+    assert_eq!(heat_color(-1.0), heat_color(0.0));
+  }
+
+  #[test]
+  fn heatmaps_scale_changes_over_the_floor_to_the_biggest_one() {
+    let before = RgbaImage::from_pixel(3, 1, Rgba(GRAY));
+    let mut after = before.clone();
+    after.put_pixel(0, 0, Rgba([101, 101, 101, 255]));
+    after.put_pixel(1, 0, Rgba([102, 102, 102, 255]));
+    // Just over the floor, and the only change, so it's the hot end
+    let diff = diff_heatmap(&before, &after).unwrap();
+    assert_eq!(diff.changed, 1);
+    assert_eq!(diff.heatmap.get_pixel(1, 0).0, heat_color(1.0));
+
+    after.put_pixel(2, 0, Rgba([200, 200, 200, 255]));
+    let diff = diff_heatmap(&before, &after).unwrap();
+    assert_eq!(diff.changed, 2);
+    assert_eq!(diff.max_delta, perceptual_delta(GRAY, [200, 200, 200, 255]));
+    assert_eq!(diff.heatmap.get_pixel(2, 0).0, heat_color(1.0));
+    let small = perceptual_delta(GRAY, [102, 102, 102, 255]) - DIFF_NOISE_FLOOR;
+    let t = small / (diff.max_delta - DIFF_NOISE_FLOOR);
+    assert!(t < 0.05);
+    assert_eq!(diff.heatmap.get_pixel(1, 0).0, heat_color(t));
+  }
+
+  #[test]
+  fn identical_frames_are_all_gray() {
+    let frame = RgbaImage::from_fn(4, 4, |x, y| Rgba([(x * 60) as u8, (y * 60) as u8, 90, 255]));
+    let diff = diff_heatmap(&frame, &frame).unwrap();
+    assert_eq!((diff.changed, diff.max_delta), (0, 0.0));
+    assert!(diff.heatmap.pixels().all(|texel| texel[0] == texel[1] && texel[1] == texel[2]));
+  }
+}
@@ -0,0 +1,336 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+    AdAshDevice,
+  },
+  ash_data_wrappers::{AdBuffer, AdExportImage, AdExternalHandle, AdImage},
+  ash_queue_wrappers::AdCommandBuffer,
+};
+use crash_report::log;
+
+// What shared images are made with, importers need the same
+pub fn shared_frame_usage() -> vk::ImageUsageFlags {
+  vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameExportMode {
+  // Into images other processes import, copied to the cpu instead when the gpu can't export memory
+  Shared,
+  // Read back to the cpu, for consumers that take pixels
+  CpuCopy,
+}
+
+// Shared mode falls back to copies without external memory on the device
+pub fn export_mode(requested: FrameExportMode, external_memory: bool) -> FrameExportMode {
+  match requested {
+    FrameExportMode::Shared if !external_memory => FrameExportMode::CpuCopy,
+    requested => requested,
+  }
+}
+
+// One of the images frames are exported into, its handle taken once when the images are made.
+// Import it as a dedicated allocation of size for an optimal tiling image of the format,
+// resolution and shared_frame_usage
+#[derive(Debug)]
+pub struct SharedFrameImage {
+  pub handle: AdExternalHandle,
+  pub size: u64,
+  pub format: vk::Format,
+  pub resolution: vk::Extent2D,
+}
+
+// Latest finished frame. Colors are sRGB encoded rgba8 either way
+#[derive(Debug, Clone)]
+pub enum ExportedFrame {
+  // The image at latest holds the frame, in GENERAL layout. It's drawn into again frames in flight
+  // frames later, consumers copy it out before then. Resizes make new images with new handles
+  Shared { images: Arc<Vec<SharedFrameImage>>, latest: usize, frame_number: u64 },
+  // Rows tightly packed, top row first
+  Pixels { resolution: vk::Extent2D, data: Arc<Vec<u8>>, frame_number: u64 },
+}
+
+enum ExportTarget {
+  Shared(AdExportImage),
+  // Blitted into for the format conversion, then copied into the host visible buffer
+  Copied(Arc<AdImage>, AdBuffer),
+}
+
+impl ExportTarget {
+  fn image(&self) -> vk::Image {
+    match self {
+      ExportTarget::Shared(image) => image.inner(),
+      ExportTarget::Copied(image, _) => image.inner(),
+    }
+  }
+}
+
+// Copies the finished scene color out every frame while a mode is set, made available once the
+// frame's slot comes around again and its fence has been waited on
+pub struct FrameExport {
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  // Display format, so the blit encodes colors the way the swapchain blit does
+  format: vk::Format,
+  mode: Option<FrameExportMode>,
+  // Per frame in flight, made on the first frame recorded after a mode change or resize
+  targets: Vec<ExportTarget>,
+  shared_images: Arc<Vec<SharedFrameImage>>,
+  // Per frame in flight, the frame number recorded into it
+  in_flight: Vec<Option<u64>>,
+  latest: Option<ExportedFrame>,
+}
+
+impl FrameExport {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    format: vk::Format,
+    frames_in_flight: usize,
+  ) -> Self {
+    Self {
+      ash_device,
+      allocator,
+      format,
+      mode: None,
+      targets: vec![],
+      shared_images: Arc::new(vec![]),
+      in_flight: vec![None; frames_in_flight],
+      latest: None,
+    }
+  }
+
+  pub fn set_mode(&mut self, mode: Option<FrameExportMode>) {
+    let mode = mode.map(|mode| export_mode(mode, self.ash_device.external_memory()));
+    if mode == Some(FrameExportMode::CpuCopy) && self.mode != mode {
+      log!("frame export copying frames to the cpu");
+    }
+    self.mode = mode;
+    self.resize();
+  }
+
+  pub fn resize(&mut self) {
+    self.targets.clear();
+    self.shared_images = Arc::new(vec![]);
+    self.in_flight.iter_mut().for_each(|slot| *slot = None);
+    self.latest = None;
+  }
+
+  pub fn latest(&self) -> Option<ExportedFrame> {
+    self.latest.clone()
+  }
+
+  fn create_targets(&mut self, resolution: vk::Extent2D) -> Result<(), String> {
+    let Some(mode) = self.mode else { return Ok(()) };
+    let mut shared_images = vec![];
+    for i in 0..self.in_flight.len() {
+      let name = format!("frame_export_{i}");
+      let target = match mode {
+        FrameExportMode::Shared => {
+          let image = AdExportImage::new_2d(
+            self.ash_device.clone(),
+            &name,
+            self.format,
+            resolution,
+            shared_frame_usage(),
+          )?;
+          shared_images.push(SharedFrameImage {
+            handle: image.export_handle()?,
+            size: image.size(),
+            format: self.format,
+            resolution,
+          });
+          ExportTarget::Shared(image)
+        }
+        FrameExportMode::CpuCopy => ExportTarget::Copied(
+          AdImage::new_2d(
+            self.ash_device.clone(),
+            self.allocator.clone(),
+            MemoryLocation::GpuOnly,
+            &name,
+            self.format,
+            resolution,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::SampleCountFlags::TYPE_1,
+            1,
+          )?,
+          AdBuffer::new(
+            self.ash_device.clone(),
+            self.allocator.clone(),
+            MemoryLocation::GpuToCpu,
+            &format!("frame_export_readback_{i}"),
+            vk::BufferCreateFlags::empty(),
+            resolution.width as u64 * resolution.height as u64 * 4,
+            vk::BufferUsageFlags::TRANSFER_DST,
+          )?,
+        ),
+      };
+      self.targets.push(target);
+    }
+    self.shared_images = Arc::new(shared_images);
+    Ok(())
+  }
+
+  // Call once frame_idx's fence has been waited on, before recording into it again
+  pub fn collect(&mut self, frame_idx: usize) -> Result<(), String> {
+    let Some(frame_number) = self.in_flight.get_mut(frame_idx).and_then(Option::take) else {
+      return Ok(());
+    };
+    self.latest = match &self.targets[frame_idx] {
+      ExportTarget::Shared(_) => Some(ExportedFrame::Shared {
+        images: self.shared_images.clone(),
+        latest: frame_idx,
+        frame_number,
+      }),
+      ExportTarget::Copied(image, buffer) => {
+        let data = buffer
+          .allocation()
+          .lock()
+          .map_err(|e| format!("at getting frame export buffer lock: {e}"))?
+          .read_data(0, buffer.size() as usize)?;
+        Some(ExportedFrame::Pixels {
+          resolution: vk::Extent2D {
+            width: image.resolution().width,
+            height: image.resolution().height,
+          },
+          data: Arc::new(data),
+          frame_number,
+        })
+      }
+    };
+    Ok(())
+  }
+
+  fn barrier(
+    cmd_buffer: &AdCommandBuffer,
+    image: vk::Image,
+    (src_stage, src_access, old_layout): (vk::PipelineStageFlags, vk::AccessFlags, vk::ImageLayout),
+    (dst_stage, dst_access, new_layout): (vk::PipelineStageFlags, vk::AccessFlags, vk::ImageLayout),
+  ) {
+    cmd_buffer.pipeline_barrier(
+      src_stage,
+      dst_stage,
+      vk::DependencyFlags::BY_REGION,
+      &[],
+      &[],
+      &[vk::ImageMemoryBarrier::default()
+        .image(image)
+        .subresource_range(
+          vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .layer_count(1)
+            .base_array_layer(0)
+            .level_count(1)
+            .base_mip_level(0),
+        )
+        .src_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+        .dst_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+        .src_access_mask(src_access)
+        .dst_access_mask(dst_access)
+        .old_layout(old_layout)
+        .new_layout(new_layout)],
+    );
+  }
+
+  // Must be recorded outside a render pass, once scene_color holds the finished frame in
+  // TRANSFER_SRC_OPTIMAL layout, as for the blit to the swapchain
+  pub fn record(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_color: &AdImage,
+    frame_number: u64,
+  ) -> Result<(), String> {
+    if self.mode.is_none() {
+      return Ok(());
+    }
+    let resolution = vk::Extent2D {
+      width: scene_color.resolution().width,
+      height: scene_color.resolution().height,
+    };
+    if self.targets.is_empty() {
+      self.create_targets(resolution)?;
+    }
+    let target = &self.targets[frame_idx];
+    // Whatever the consumer left it in is discarded
+    Self::barrier(
+      cmd_buffer,
+      target.image(),
+      (vk::PipelineStageFlags::TOP_OF_PIPE, vk::AccessFlags::NONE, vk::ImageLayout::UNDEFINED),
+      (
+        vk::PipelineStageFlags::TRANSFER,
+        vk::AccessFlags::TRANSFER_WRITE,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+      ),
+    );
+    let layers = vk::ImageSubresourceLayers::default()
+      .aspect_mask(vk::ImageAspectFlags::COLOR)
+      .mip_level(0)
+      .base_array_layer(0)
+      .layer_count(1);
+    cmd_buffer.blit_image(
+      scene_color.inner(),
+      vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+      target.image(),
+      vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+      &[vk::ImageBlit::default()
+        .src_subresource(layers)
+        .src_offsets(scene_color.full_range_offset_3d())
+        .dst_subresource(layers)
+        .dst_offsets(scene_color.full_range_offset_3d())],
+      vk::Filter::NEAREST,
+    );
+    let written = (
+      vk::PipelineStageFlags::TRANSFER,
+      vk::AccessFlags::TRANSFER_WRITE,
+      vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+    );
+    match target {
+      ExportTarget::Shared(image) => Self::barrier(
+        cmd_buffer,
+        image.inner(),
+        written,
+        (
+          vk::PipelineStageFlags::ALL_COMMANDS,
+          vk::AccessFlags::MEMORY_READ,
+          vk::ImageLayout::GENERAL,
+        ),
+      ),
+      ExportTarget::Copied(image, buffer) => {
+        Self::barrier(
+          cmd_buffer,
+          image.inner(),
+          written,
+          (
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+          ),
+        );
+        cmd_buffer.copy_image_to_buffer(
+          image.inner(),
+          vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+          buffer.inner(),
+          &[vk::BufferImageCopy::default()
+            .image_subresource(layers)
+            .image_extent(image.resolution())],
+        );
+        cmd_buffer.pipeline_barrier(
+          vk::PipelineStageFlags::TRANSFER,
+          vk::PipelineStageFlags::HOST,
+          vk::DependencyFlags::empty(),
+          &[vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)],
+          &[],
+          &[],
+        );
+      }
+    }
+    self.in_flight[frame_idx] = Some(frame_number);
+    Ok(())
+  }
+}
@@ -0,0 +1,25 @@
+use bumpalo::Bump;
+
+pub use bumpalo::collections::Vec as ScratchVec;
+
+// Arena the lists a frame builds while recording are bump allocated from. Reset at the start of
+// every frame, so after the first few frames the memory the last one used is reused instead of
+// being allocated again
+pub struct FrameScratch {
+  arena: Bump,
+}
+
+impl FrameScratch {
+  pub fn new() -> Self {
+    Self { arena: Bump::new() }
+  }
+
+  // Lists from the last frame can't still be borrowing the arena here
+  pub fn reset(&mut self) {
+    self.arena.reset();
+  }
+
+  pub fn arena(&self) -> &Bump {
+    &self.arena
+  }
+}
@@ -0,0 +1,154 @@
+use std::{
+  collections::VecDeque,
+  time::{Duration, Instant},
+};
+
+use renderables::mesh_arena::MeshArenaStats;
+
+use crate::{frame_sync::FrameSync, present::PresentTarget, resource_budget::ResourceUsage};
+
+// Weight of the newest latency or gpu time in the moving averages
+const LATENCY_SMOOTHING: f32 = 0.1;
+
+// For tuning how the simulation and render threads hand frames over
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameStats {
+  pub frames_drawn: u64,
+  // From the oldest input a frame was the first to show, to that frame being shown
+  pub input_latency: Option<Duration>,
+  pub average_input_latency: Option<Duration>,
+  // Latencies end when the frame got to the display with present wait, when the gpu finished
+  // drawing it otherwise
+  pub measured_at_display: bool,
+  // From the first to the last command of a frame on the gpu, None when it can't write timestamps
+  pub gpu_frame_time: Option<Duration>,
+  pub average_gpu_frame_time: Option<Duration>,
+  // Filled in by the render manager, the tracker leaves these empty
+  pub mesh_arena: MeshArenaStats,
+  // Live at the end of the last frame, and the most live at the end of any frame
+  pub resources: ResourceUsage,
+  pub peak_resources: ResourceUsage,
+  // Recorded for the last frame drawn
+  pub draw_calls: u32,
+  // Heap allocations the render thread made drawing it, only with the counting allocator
+  pub heap_allocations: Option<u64>,
+}
+
+struct PendingFrame {
+  frame_idx: usize,
+  // Only when the swapchain can tell when its presents are shown
+  present_id: Option<u64>,
+  input_received_at: Instant,
+}
+
+// Frames showing new inputs, until they are seen to be presented
+pub struct InputLatencyTracker {
+  pending: VecDeque<PendingFrame>,
+  stats: FrameStats,
+}
+
+impl InputLatencyTracker {
+  pub fn new() -> Self {
+    Self { pending: VecDeque::new(), stats: FrameStats::default() }
+  }
+
+  pub fn stats(&self) -> FrameStats {
+    self.stats
+  }
+
+  // Once the gpu finished a frame timed by GpuFrameTimer
+  pub fn frame_timed(&mut self, gpu_time: Duration) {
+    self.stats.gpu_frame_time = Some(gpu_time);
+    self.stats.average_gpu_frame_time = Some(match self.stats.average_gpu_frame_time {
+      Some(average) => {
+        average.mul_f32(1.0 - LATENCY_SMOOTHING) + gpu_time.mul_f32(LATENCY_SMOOTHING)
+      }
+      None => gpu_time,
+    });
+  }
+
+  // Right after the frame was presented
+  pub fn frame_presented(
+    &mut self,
+    frame_idx: usize,
+    present_id: Option<u64>,
+    input_received_at: Option<Instant>,
+  ) {
+    self.stats.frames_drawn += 1;
+    if let Some(input_received_at) = input_received_at {
+      self.pending.push_back(PendingFrame { frame_idx, present_id, input_received_at });
+    }
+  }
+
+  // Present ids start over with a new swapchain
+  pub fn swapchain_refreshed(&mut self) {
+    self.pending.clear();
+  }
+
+  // Doesn't wait, frames not shown yet are checked again on the next call. Must be called before
+  // a frame fence is reset for its next submit
+  pub fn poll(
+    &mut self,
+    frame_sync: &FrameSync,
+    swapchain: &dyn PresentTarget,
+  ) -> Result<(), String> {
+    while let Some(frame) = self.pending.front() {
+      let present_shown = match frame.present_id {
+        Some(present_id) => swapchain.is_present_shown(present_id)?,
+        None => None,
+      };
+      let shown = match present_shown {
+        Some(shown) => shown,
+        None => frame_sync.is_frame_done(frame.frame_idx)?,
+      };
+      if !shown {
+        break;
+      }
+      let latency = frame.input_received_at.elapsed();
+      self.stats.measured_at_display = present_shown.is_some();
+      self.stats.input_latency = Some(latency);
+      self.stats.average_input_latency = Some(match self.stats.average_input_latency {
+        Some(average) => {
+          average.mul_f32(1.0 - LATENCY_SMOOTHING) + latency.mul_f32(LATENCY_SMOOTHING)
+        }
+        None => latency,
+      });
+      self.pending.pop_front();
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn gpu_frame_times_average_from_the_first_one() {
+    let mut tracker = InputLatencyTracker::new();
+    assert_eq!(tracker.stats().average_gpu_frame_time, None);
+    tracker.frame_timed(Duration::from_millis(10));
+    assert_eq!(tracker.stats().average_gpu_frame_time, Some(Duration::from_millis(10)));
+    tracker.frame_timed(Duration::from_millis(20));
+    let stats = tracker.stats();
+    assert_eq!(stats.gpu_frame_time, Some(Duration::from_millis(20)));
+    let average = stats.average_gpu_frame_time.unwrap().as_secs_f64();
+    assert!((average - 0.011).abs() < 1e-6, "averaged to {average}");
+  }
+
+  #[test]
+  fn only_frames_showing_new_inputs_wait_to_be_seen() {
+    let mut tracker = InputLatencyTracker::new();
+    tracker.frame_presented(0, None, None);
+    tracker.frame_presented(1, Some(7), Some(Instant::now()));
+    tracker.frame_presented(0, None, Some(Instant::now()));
+    assert_eq!(tracker.stats().frames_drawn, 3);
+    assert_eq!(tracker.pending.len(), 2);
+    assert_eq!(tracker.pending[0].present_id, Some(7));
+    // Their present ids mean nothing to a new swapchain
+    tracker.swapchain_refreshed();
+    assert!(tracker.pending.is_empty());
+    assert_eq!(tracker.stats().frames_drawn, 3);
+    assert_eq!(tracker.stats().input_latency, None);
+  }
+}
@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_queue_wrappers::AdQueue,
+  ash_sync_wrappers::{AdFence, AdSemaphore},
+};
+
+// Tracks which semaphores have a signal pending that nobody has waited on yet.
+// Re-signalling such a semaphore is undefined behaviour that validation layers only sometimes catch
+pub(crate) struct SemaphoreHazardTracker {
+  acquire_pending: Vec<bool>,
+  render_pending: Vec<bool>,
+}
+
+impl SemaphoreHazardTracker {
+  pub(crate) fn new(frames_in_flight: usize, swapchain_image_count: usize) -> Self {
+    Self {
+      acquire_pending: vec![false; frames_in_flight],
+      render_pending: vec![false; swapchain_image_count],
+    }
+  }
+
+  pub(crate) fn acquired(&mut self, frame: usize) -> Result<(), String> {
+    if self.acquire_pending[frame] {
+      return Err(format!("acquire semaphore {frame} signalled again before being waited on"));
+    }
+    self.acquire_pending[frame] = true;
+    Ok(())
+  }
+
+  pub(crate) fn submitted(&mut self, frame: usize, image_idx: u32) -> Result<(), String> {
+    if !self.acquire_pending[frame] {
+      return Err(format!("render submit waits on unsignalled acquire semaphore {frame}"));
+    }
+    if self.render_pending[image_idx as usize] {
+      return Err(format!("render semaphore of image {image_idx} reused before present wait"));
+    }
+    self.acquire_pending[frame] = false;
+    self.render_pending[image_idx as usize] = true;
+    Ok(())
+  }
+
+  pub(crate) fn presented(&mut self, image_idx: u32) {
+    self.render_pending[image_idx as usize] = false;
+  }
+
+  pub(crate) fn resized(&mut self, swapchain_image_count: usize) {
+    self.render_pending = vec![false; swapchain_image_count];
+  }
+}
+
+// Which frame's fence to wait on so the cpu is at most `frames_ahead` frames ahead of the gpu, None
+// when waiting on the current frame's fence already covers it
+pub(crate) fn frame_to_wait(
+  current_frame: usize,
+  frames_in_flight: usize,
+  frames_ahead: usize,
+) -> Option<usize> {
+  if frames_ahead == 0 || frames_ahead >= frames_in_flight {
+    return None;
+  }
+  Some((current_frame + frames_in_flight - frames_ahead) % frames_in_flight)
+}
+
+// Per frame chain: acquire semaphore -> render submit wait, render submit signal -> present wait.
+// Acquire semaphores and fences are indexed by frame in flight, render semaphores by swapchain
+// image since a present keeps waiting on them until that image is acquired again
+pub struct FrameSync {
+  ash_device: Arc<AdAshDevice>,
+  acquire_semaphores: Vec<AdSemaphore>,
+  render_semaphores: Vec<AdSemaphore>,
+  frame_fences: Vec<Arc<AdFence>>,
+  current_frame: usize,
+  hazard_tracker: Option<SemaphoreHazardTracker>,
+}
+
+impl FrameSync {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    frames_in_flight: usize,
+    swapchain_image_count: usize,
+    validate: bool,
+  ) -> Result<Self, String> {
+    let acquire_semaphores = (0..frames_in_flight)
+      .map(|_| AdSemaphore::new(ash_device.clone(), vk::SemaphoreCreateFlags::default()))
+      .collect::<Result<Vec<_>, _>>()?;
+    let render_semaphores = (0..swapchain_image_count)
+      .map(|_| AdSemaphore::new(ash_device.clone(), vk::SemaphoreCreateFlags::default()))
+      .collect::<Result<Vec<_>, _>>()?;
+    let frame_fences = (0..frames_in_flight)
+      .map(|_| AdFence::new(ash_device.clone(), vk::FenceCreateFlags::SIGNALED).map(Arc::new))
+      .collect::<Result<Vec<_>, _>>()?;
+    let hazard_tracker =
+      validate.then(|| SemaphoreHazardTracker::new(frames_in_flight, swapchain_image_count));
+    Ok(Self {
+      ash_device,
+      acquire_semaphores,
+      render_semaphores,
+      frame_fences,
+      current_frame: 0,
+      hazard_tracker,
+    })
+  }
+
+  pub fn current_frame(&self) -> usize {
+    self.current_frame
+  }
+
+  pub fn set_validation(&mut self, validate: bool) {
+    self.hazard_tracker = validate.then(|| {
+      SemaphoreHazardTracker::new(self.acquire_semaphores.len(), self.render_semaphores.len())
+    });
+  }
+
+  pub fn acquire_semaphore(&self) -> &AdSemaphore {
+    &self.acquire_semaphores[self.current_frame]
+  }
+
+  pub fn render_semaphore(&self, image_idx: u32) -> &AdSemaphore {
+    &self.render_semaphores[image_idx as usize]
+  }
+
+  pub fn frame_fence(&self) -> &Arc<AdFence> {
+    &self.frame_fences[self.current_frame]
+  }
+
+  // Fence is only reset right before submit, and a failed submit is followed by release_acquired
+  // which signals it, so it's never left unsignalled
+  pub fn wait_for_frame(&self) -> Result<(), String> {
+    self.frame_fences[self.current_frame].wait(999999999)
+  }
+
+  // Waits for the frame `frames_ahead` frames before this one as well, so the cpu gets no further
+  // ahead of the gpu than that. Nothing past what wait_for_frame already waits for is a no-op
+  pub fn wait_for_frames_ahead(&self, frames_ahead: usize) -> Result<(), String> {
+    match frame_to_wait(self.current_frame, self.frame_fences.len(), frames_ahead) {
+      Some(frame) => self.frame_fences[frame].wait(999999999),
+      None => Ok(()),
+    }
+  }
+
+  // Whether the last submit of the frame finished, without waiting
+  pub fn is_frame_done(&self, frame_idx: usize) -> Result<bool, String> {
+    self.frame_fences[frame_idx].is_signalled()
+  }
+
+  // Every resize and resize_targets replaces targets and descriptor sets frames in flight may
+  // still use, callers go through here first
+  pub fn wait_all(&self) -> Result<(), String> {
+    for fence in self.frame_fences.iter() {
+      fence.wait(999999999)?;
+    }
+    Ok(())
+  }
+
+  pub fn mark_acquired(&mut self) -> Result<(), String> {
+    let frame = self.current_frame;
+    self.hazard_tracker.as_mut().map_or(Ok(()), |tracker| tracker.acquired(frame))
+  }
+
+  pub fn mark_submitted(&mut self, image_idx: u32) -> Result<(), String> {
+    let frame = self.current_frame;
+    self.hazard_tracker.as_mut().map_or(Ok(()), |tracker| tracker.submitted(frame, image_idx))
+  }
+
+  // For a frame given up on between acquire and render submit. An empty submit waits on the acquire
+  // semaphore and signals the image's render semaphore, so the image can still be presented
+  pub fn release_acquired(&mut self, queue: &AdQueue, image_idx: u32) -> Result<(), String> {
+    let frame = self.current_frame;
+    self.frame_fences[frame].reset()?;
+    queue.submit(
+      &[vk::SubmitInfo::default()
+        .wait_semaphores(&[self.acquire_semaphores[frame].inner()])
+        .wait_dst_stage_mask(&[vk::PipelineStageFlags::ALL_COMMANDS])
+        .signal_semaphores(&[self.render_semaphores[image_idx as usize].inner()])],
+      Some(&self.frame_fences[frame]),
+    )?;
+    self.mark_submitted(image_idx)
+  }
+
+  pub fn mark_presented(&mut self, image_idx: u32) {
+    if let Some(tracker) = self.hazard_tracker.as_mut() {
+      tracker.presented(image_idx);
+    }
+  }
+
+  pub fn advance(&mut self) {
+    self.current_frame = (self.current_frame + 1) % self.frame_fences.len();
+  }
+
+  pub fn resize_swapchain_images(&mut self, swapchain_image_count: usize) -> Result<(), String> {
+    if swapchain_image_count == self.render_semaphores.len() {
+      return Ok(());
+    }
+    self.wait_all()?;
+    self.render_semaphores = (0..swapchain_image_count)
+      .map(|_| AdSemaphore::new(self.ash_device.clone(), vk::SemaphoreCreateFlags::default()))
+      .collect::<Result<Vec<_>, _>>()?;
+    if let Some(tracker) = self.hazard_tracker.as_mut() {
+      tracker.resized(swapchain_image_count);
+    }
+    Ok(())
+  }
+}
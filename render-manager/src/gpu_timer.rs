@@ -0,0 +1,79 @@
+use std::{sync::Arc, time::Duration};
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, AdAshDevice},
+  ash_queue_wrappers::{AdCommandBuffer, AdQueryPool},
+};
+
+// Timestamps around each frame's commands, one pair per frame in flight. Does nothing on gpus
+// whose graphics queues can't write timestamps
+pub struct GpuFrameTimer {
+  // With nanoseconds per tick
+  query_pool: Option<(AdQueryPool, f32)>,
+  // Per frame in flight, whether its last commands had timestamps written
+  written: Vec<bool>,
+  #[cfg(feature = "tracy")]
+  tracy_timeline: Option<profiler::GpuTimeline>,
+}
+
+impl GpuFrameTimer {
+  pub fn new(ash_device: Arc<AdAshDevice>, frames_in_flight: usize) -> Result<Self, String> {
+    let query_pool = match ash_device.ash_instance().timestamp_period(ash_device.gpu()) {
+      Some(period) => {
+        Some((AdQueryPool::new_timestamps(ash_device, 2 * frames_in_flight as u32)?, period))
+      }
+      None => {
+        eprintln!("gpu timestamps not supported, gpu frame times won't be measured");
+        None
+      }
+    };
+    Ok(Self {
+      #[cfg(feature = "tracy")]
+      tracy_timeline: query_pool
+        .as_ref()
+        .map(|(_, period)| profiler::GpuTimeline::new("gpu", *period)),
+      query_pool,
+      written: vec![false; frames_in_flight],
+    })
+  }
+
+  // How long the gpu took on the last commands recorded for frame_idx. Call after waiting on the
+  // frame's fence and before recording it again
+  pub fn frame_time(&mut self, frame_idx: usize) -> Result<Option<Duration>, String> {
+    let Some((query_pool, period)) = &self.query_pool else { return Ok(None) };
+    if !std::mem::take(&mut self.written[frame_idx]) {
+      return Ok(None);
+    }
+    let Some(timestamps) = query_pool.get_timestamps(2 * frame_idx as u32, 2)? else {
+      return Ok(None);
+    };
+    #[cfg(feature = "tracy")]
+    if let Some(tracy_timeline) = &mut self.tracy_timeline {
+      tracy_timeline.zone("frame", timestamps[0], timestamps[1]);
+    }
+    let ticks = timestamps[1].saturating_sub(timestamps[0]);
+    Ok(Some(Duration::from_nanos((ticks as f64 * *period as f64) as u64)))
+  }
+
+  // First thing in the frame's commands
+  pub fn begin(&self, cmd_buffer: &AdCommandBuffer, frame_idx: usize) {
+    let Some((query_pool, _)) = &self.query_pool else { return };
+    cmd_buffer.reset_query_pool(query_pool.inner(), 2 * frame_idx as u32, 2);
+    cmd_buffer.write_timestamp(
+      vk::PipelineStageFlags::TOP_OF_PIPE,
+      query_pool.inner(),
+      2 * frame_idx as u32,
+    );
+  }
+
+  // Last thing in the frame's commands
+  pub fn end(&mut self, cmd_buffer: &AdCommandBuffer, frame_idx: usize) {
+    let Some((query_pool, _)) = &self.query_pool else { return };
+    cmd_buffer.write_timestamp(
+      vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+      query_pool.inner(),
+      2 * frame_idx as u32 + 1,
+    );
+    self.written[frame_idx] = true;
+  }
+}
@@ -0,0 +1,180 @@
+use std::{
+  fmt,
+  hash::{Hash, Hasher},
+  marker::PhantomData,
+  sync::Arc,
+};
+
+use validation::ValidationCategory;
+
+use renderables::{
+  color_lut::ColorLutGPU, crowd::CrowdGPU, flat_texture::FlatTextureGPU, foliage::FoliageGPU,
+  light::LocalLight, material::MaterialGPU, particles::ParticleSystemGPU,
+  triangle_mesh::TriMeshGPU, water::WaterPlaneGPU,
+};
+
+// Generational index to a GPU resource that lives on the render thread. Handles are plain data,
+// holding one does not keep any GPU memory alive
+pub struct RenderHandle<T> {
+  index: u32,
+  generation: u32,
+  _marker: PhantomData<fn() -> T>,
+}
+
+pub type MeshHandle = RenderHandle<TriMeshGPU>;
+pub type TextureHandle = RenderHandle<FlatTextureGPU>;
+pub type MaterialHandle = RenderHandle<MaterialGPU>;
+pub type ParticleHandle = RenderHandle<ParticleSystemGPU>;
+pub type CrowdHandle = RenderHandle<CrowdGPU>;
+pub type WaterHandle = RenderHandle<WaterPlaneGPU>;
+pub type FoliageHandle = RenderHandle<FoliageGPU>;
+pub type ColorLutHandle = RenderHandle<ColorLutGPU>;
+// Lights only live on the cpu, nothing on the gpu is made per light
+pub type LightHandle = RenderHandle<LocalLight>;
+
+impl<T> RenderHandle<T> {
+  pub fn index(&self) -> u32 {
+    self.index
+  }
+
+  pub fn generation(&self) -> u32 {
+    self.generation
+  }
+}
+
+impl<T> Clone for RenderHandle<T> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<T> Copy for RenderHandle<T> {}
+
+impl<T> PartialEq for RenderHandle<T> {
+  fn eq(&self, other: &Self) -> bool {
+    self.index == other.index && self.generation == other.generation
+  }
+}
+
+impl<T> Eq for RenderHandle<T> {}
+
+impl<T> Hash for RenderHandle<T> {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.index.hash(state);
+    self.generation.hash(state);
+  }
+}
+
+impl<T> fmt::Debug for RenderHandle<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "RenderHandle({}v{})", self.index, self.generation)
+  }
+}
+
+// Hands out handles on the sending side so the game doesn't have to wait for the render thread
+pub struct HandleAllocator<T> {
+  generations: Vec<u32>,
+  alive: Vec<bool>,
+  free_list: Vec<u32>,
+  _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> HandleAllocator<T> {
+  pub fn new() -> Self {
+    Self { generations: vec![], alive: vec![], free_list: vec![], _marker: PhantomData }
+  }
+
+  pub fn allocate(&mut self) -> RenderHandle<T> {
+    let index = match self.free_list.pop() {
+      Some(index) => index,
+      None => {
+        self.generations.push(0);
+        self.alive.push(false);
+        (self.generations.len() - 1) as u32
+      }
+    };
+    self.alive[index as usize] = true;
+    RenderHandle { index, generation: self.generations[index as usize], _marker: PhantomData }
+  }
+
+  pub fn is_alive(&self, handle: RenderHandle<T>) -> bool {
+    self.alive.get(handle.index as usize).copied().unwrap_or(false)
+      && self.generations[handle.index as usize] == handle.generation
+  }
+
+  // Handles allocated and not freed yet
+  pub fn live_count(&self) -> usize {
+    self.alive.iter().filter(|alive| **alive).count()
+  }
+
+  pub fn free(&mut self, handle: RenderHandle<T>) -> Result<(), String> {
+    if !self.is_alive(handle) {
+      validation::report(ValidationCategory::DestroyedHandle, || {
+        format!("freeing {handle:?} again")
+      });
+      return Err(format!("freeing stale or unknown handle {handle:?}"));
+    }
+    self.alive[handle.index as usize] = false;
+    self.generations[handle.index as usize] = self.generations[handle.index as usize].wrapping_add(1);
+    self.free_list.push(handle.index);
+    Ok(())
+  }
+}
+
+impl<T> Default for HandleAllocator<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+// Render thread side storage, resources are only reachable through handles of matching generation
+pub struct HandleRegistry<T> {
+  slots: Vec<Option<(u32, Arc<T>)>>,
+}
+
+impl<T> HandleRegistry<T> {
+  pub fn new() -> Self {
+    Self { slots: vec![] }
+  }
+
+  pub fn insert(&mut self, handle: RenderHandle<T>, resource: Arc<T>) {
+    let index = handle.index as usize;
+    if self.slots.len() <= index {
+      self.slots.resize_with(index + 1, || None);
+    }
+    self.slots[index] = Some((handle.generation, resource));
+  }
+
+  pub fn get(&self, handle: RenderHandle<T>) -> Result<&Arc<T>, String> {
+    match self.slots.get(handle.index as usize) {
+      Some(Some((generation, resource))) if *generation == handle.generation => Ok(resource),
+      Some(Some(_)) | Some(None) => {
+        validation::report(ValidationCategory::DestroyedHandle, || format!("using {handle:?}"));
+        Err(format!("stale handle {handle:?}"))
+      }
+      None => Err(format!("unknown handle {handle:?}")),
+    }
+  }
+
+  pub fn values(&self) -> impl Iterator<Item = &Arc<T>> {
+    self.slots.iter().filter_map(|slot| slot.as_ref().map(|(_, resource)| resource))
+  }
+
+  pub fn live_count(&self) -> usize {
+    self.slots.iter().filter(|slot| slot.is_some()).count()
+  }
+
+  pub fn remove(&mut self, handle: RenderHandle<T>) -> Result<Arc<T>, String> {
+    self.get(handle)?;
+    self.slots[handle.index as usize]
+      .take()
+      .map(|(_, resource)| resource)
+      .ok_or(format!("unknown handle {handle:?}"))
+  }
+}
+
+impl<T> Default for HandleRegistry<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
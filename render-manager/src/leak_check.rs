@@ -0,0 +1,63 @@
+use crate::resource_budget::ResourceUsage;
+
+// What the render thread had live once the frames in flight were done with everything destroyed
+// before it. Taken after unloading a level, anything over the baseline is what it left behind
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceSnapshot {
+  pub usage: ResourceUsage,
+  // Bytes per allocation name over all allocators, largest first
+  pub allocations: Vec<(String, u64)>,
+  // Resources in each handle registry of the render thread
+  pub registries: Vec<(&'static str, u64)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LeakedResource {
+  // Like descriptor sets, counted by the device wrappers
+  Usage(&'static str),
+  Registry(&'static str),
+  Allocation(String),
+}
+
+// Something there's more of than at the baseline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceLeak {
+  pub resource: LeakedResource,
+  pub baseline: u64,
+  pub live: u64,
+}
+
+impl ResourceSnapshot {
+  fn usage_counts(&self) -> [(&'static str, u64); 4] {
+    [
+      ("gpu memory bytes", self.usage.gpu_memory_bytes),
+      ("descriptor sets", self.usage.descriptor_sets),
+      ("command buffers", self.usage.command_buffers),
+      ("framebuffers", self.usage.framebuffers),
+    ]
+  }
+
+  // Named allocations are compared by their bytes, several with the same name count together.
+  // Going below the baseline isn't a leak, caches the baseline had may have been trimmed since
+  pub fn leaks_since(&self, baseline: &Self) -> Vec<ResourceLeak> {
+    let usage = self
+      .usage_counts()
+      .into_iter()
+      .zip(baseline.usage_counts())
+      .map(|((name, live), (_, baseline))| (LeakedResource::Usage(name), baseline, live));
+    let registries = self.registries.iter().map(|(name, live)| {
+      let baseline = baseline.registries.iter().find(|(other, _)| other == name);
+      (LeakedResource::Registry(name), baseline.map_or(0, |(_, count)| *count), *live)
+    });
+    let allocations = self.allocations.iter().map(|(name, live)| {
+      let baseline = baseline.allocations.iter().find(|(other, _)| other == name);
+      (LeakedResource::Allocation(name.clone()), baseline.map_or(0, |(_, bytes)| *bytes), *live)
+    });
+    usage
+      .chain(registries)
+      .chain(allocations)
+      .filter(|(_, baseline, live)| live > baseline)
+      .map(|(resource, baseline, live)| ResourceLeak { resource, baseline, live })
+      .collect()
+  }
+}
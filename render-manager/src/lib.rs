@@ -1,95 +1,664 @@
 use std::{
-  collections::HashMap,
-  sync::{Arc, Mutex, OnceLock},
+  collections::{HashMap, HashSet},
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex, Weak},
+  time::{Duration, Instant},
 };
 
 use ash_ad_wrappers::{
   ash_context::{
     ash::{khr, vk},
+    external_memory_extension,
     gpu_allocator::vulkan::Allocator,
-    AdAshDevice, GPUQueueType,
+    AdAshDevice, AdLiveObjectKind, GPUQueueType,
   },
-  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool, AdQueue},
-  ash_render_wrappers::AdFrameBuffer,
+  ash_data_wrappers::{AdDescriptorPoolStats, AdDescriptorSet},
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool, AdCommandPoolRegistry, AdQueue},
+  ash_render_wrappers::{compile_shader_file, shader_stage_from_path, AdFrameBuffer},
   ash_surface_wrappers::{AdSwapchain, AdSwapchainDevice},
-  ash_sync_wrappers::{AdFence, AdSemaphore},
+  ash_sync_wrappers::AdFence,
+  backend::VulkanImage,
 };
+use crash_report::{log, CrashCleanup};
+use engine_config::{
+  AntiAliasing, ColorOutput, QualityPreset, QualitySettings, RendererConfig, ShadowMode,
+  TextureCompression,
+};
+use event_bus::{ViewResized, WindowResized, WindowScaleChanged};
+use benchmark::BenchmarkCapture;
+use draw_list::DrawList;
+use frame_scratch::{FrameScratch, ScratchVec};
+use frame_stats::InputLatencyTracker;
+use frame_sync::FrameSync;
+use profiler::profile_scope;
+use handles::{HandleAllocator, HandleRegistry};
+use jobs::{JobHandle, TripleBufferWriter};
+use loading_screen::MessageBacklog;
+use exposure::Exposure;
+use depth_of_field::DepthOfField;
+use film_effects::FilmEffects;
+use depth_readback::DepthReadback;
+use frame_diff::FrameDiff;
+use frame_export::FrameExport;
+use recorder::Recorder;
+use frame_capture::FrameCapture;
+use gpu_timer::GpuFrameTimer;
+use quality_governor::QualityGovernor;
+use light_culling::LightCulling;
+use memory_heatmap::MemoryHeatmap;
+use minimap::Minimap;
+use perf_hud::PerfHud;
+use reflection_probes::ReflectionProbes;
+use present::PresentTarget;
+use resource_budget::ResourceBudget;
+use shadows::SceneShadows;
+use post_process::PostProcess;
+use post_targets::post_transient_images;
+use taa::TemporalAa;
+use texture_streaming::TextureStreamer;
+use texture_transcoding::TextureTranscoder;
+use transform_history::TransformHistory;
+use ui_layer::UiLayer;
+use world_labels::WorldLabels;
 use renderables::{
-  flat_texture::FlatTextureGenerator, triangle_mesh::TriMeshGenerator
+  cloth::{cloth_wind, ClothGenerator},
+  color_lut::ColorLutGenerator,
+  crowd::CrowdGenerator,
+  flat_texture::{DecodedFlatTexture, FlatTextureGenerator},
+  foliage::FoliageGenerator,
+  gizmo::make_gizmo_handle_mesh,
+  material::MaterialGenerator, particles::ParticleSystemGenerator, sdf_font::SdfFontCPU,
+  skinning::SkinnedMeshGenerator, triangle_mesh::TriMeshGenerator, water::WaterPlaneGenerator,
+};
+use renderers::{
+  cloth_renderers::{ClothRenderer, ClothStep},
+  color_renderers::SrgbEncodeRenderer, crowd_renderers::CrowdRenderer,
+  cull_renderers::MeshCullRenderer,
+  debug_renderers::{DebugOverlayRenderer, OverlayRect},
+  depth_of_field_renderers::DepthOfFieldRenderer,
+  depth_readback_renderers::{DepthPointsRenderer, DepthReadbackRenderer},
+  editor_renderers::EditorOverlayRenderer,
+  environment_renderers::{EnvironmentGenerator, EnvironmentMap},
+  exposure_renderers::ExposureRenderer, film_effects_renderers::FilmEffectsRenderer,
+  foliage_renderers::FoliageRenderer,
+  particle_renderers::ParticleRenderer, post_renderers::MotionBlurRenderer,
+  reflection_probe_renderers::ReflectionProbeRenderer, shadow_renderers::ShadowCascades,
+  skinning_renderers::SkinningRenderer, taa_renderers::TaaRenderer,
+  triangle_mesh_renderers::TriMeshMaterialRenderer, velocity_renderers::VelocityRenderer,
+  water_renderers::{self, WaterRenderer},
 };
-use renderers::triangle_mesh_renderers::TriMeshTexRenderer;
 
-pub use ash_ad_wrappers::ash_context::AdAshInstance;
+pub use ash_ad_wrappers::ash_context::{
+  AdAshInstance, AdDeviceInfo, AdMemoryHeapInfo, AdQueueFamilyInfo,
+};
 pub use ash_ad_wrappers::ash_surface_wrappers::{AdSurface, AdSurfaceInstance};
-pub use renderables::{glam, Camera3D};
-pub use renderables::triangle_mesh::{TriMeshCPU, TriMeshGPU, TriMeshTransform};
-pub use renderables::flat_texture::FlatTextureGPU;
+pub use offscreen::OffscreenWindow;
+pub use ash_ad_wrappers::{backend::VulkanDevice, graphics_backend};
+pub use renderables::{color::Color, glam, Camera3D};
+pub use renderables::triangle_mesh::{
+  MorphTargetCPU, MorphTargetDelta, MorphWeights, TriMeshCPU, TriMeshGPU, TriMeshTransform,
+  TriMeshVertex, VertexStreams, MAX_MORPH_TARGETS,
+};
+pub use renderables::crowd::{
+  BoneAnimationCPU, CrowdGPU, CrowdInstance, CrowdMeshCPU, CrowdVertex,
+};
+pub use renderables::flat_texture::{
+  FlatTextureDedupStats, FlatTextureGPU, TextureAddressMode, TextureColorSpace, TextureFilter,
+  TextureFormat, TextureImportSettings,
+};
+pub use renderables::sampler_cache::SamplerDesc;
+pub use renderables::foliage::{
+  make_foliage_card, FoliageCPU, FoliageGPU, FoliageInstance, FoliageParams, FoliageScatter,
+  ScatterParams, WindParams,
+};
+pub use renderables::gizmo::{Gizmo, GizmoAxis, GizmoMode};
+pub use renderables::mesh_arena::MeshArenaStats;
+pub use renderables::multiview::MultiviewCameras;
+pub use renderables::material::{
+  MaterialCPU, MaterialGPU, MaterialParams, MaterialPipelineKey, ShaderVariant, VertexStreamUse,
+};
+pub use renderables::light::{LightShape, LocalLight, SunLight};
+pub use renderables::reflection_probe::ReflectionProbe;
+pub use renderables::particles::{ParticleCollision, ParticleEmitter, ParticleSystemGPU};
+pub use renderables::skinning::{SkinnedMeshCPU, SkinnedMeshGPU};
+pub use renderables::cloth::{ClothCPU, ClothGPU, ClothSim};
+pub use renderables::color_lut::{ColorLutCPU, ColorLutGPU};
+pub use renderables::static_batch::{BatchedInstance, StaticBatch, StaticBatcher};
+pub use renderables::water::{WaterParams, WaterPlaneCPU, WaterPlaneGPU};
+pub use renderers::debug_renderers::DebugLine;
+pub use renderers::editor_renderers::GridSettings;
+pub use renderers::film_effects_renderers::{FilmEffectsParams, Vignette};
+pub use renderers::reflection_probe_renderers::MAX_REFLECTION_PROBES;
+
+pub use handles::{
+  ColorLutHandle, CrowdHandle, FoliageHandle, LightHandle, MaterialHandle, MeshHandle,
+  ParticleHandle, RenderHandle, TextureHandle, WaterHandle,
+};
+pub use benchmark::{summarize_frame_times, BenchmarkReport, FrameTimeSummary};
+pub use color_grading::ColorGrading;
+pub use depth_of_field::DepthOfFieldParams;
+pub use depth_readback::{DepthQuery, DepthSample};
+pub use engine_info::{CrateVersion, EngineInfo};
+pub use frame_diff::FrameDiffCommand;
+pub use frame_export::{shared_frame_usage, ExportedFrame, FrameExportMode, SharedFrameImage};
+pub use recorder::{RecordingOutput, RecordingSettings, RECORDING_RING_SIZE};
+pub use draw_list::LayerMask;
+pub use frame_stats::FrameStats;
+pub use lightmap::{
+  bake_lightmap, unwrap_lightmap_uvs, Lightmap, LightmapInstance, LightmapLights, LightmapSettings,
+  LIGHTMAP_RANGE,
+};
+pub use leak_check::{LeakedResource, ResourceLeak, ResourceSnapshot};
+pub use loading_screen::LoadingProgress;
+pub use minimap::MinimapSettings;
+pub use perf_hud::PhysicsHudStats;
+pub use quality_governor::{QualityCallback, QualityKnobs, QualityPressure};
+pub use resource_budget::ResourceUsage;
+pub use rooms::{ConvexRoom, Portal, RoomGraph};
+pub use snapshot::{CrowdState, FrameSnapshot, MeshState, SkinnedMeshState};
+pub use texture_streaming::TextureStreamingStats;
+pub use texture_transcoding::TextureCompressionStats;
+pub use ui_layer::UiShape;
+pub use view_policy::view_camera;
+pub use world_labels::{LabelAnchor, LabelIcon, WorldLabel};
+
+mod benchmark;
+mod color;
+mod color_grading;
+mod depth_of_field;
+mod depth_readback;
+mod draw_list;
+mod engine_info;
+mod exposure;
+mod film_effects;
+mod frame_capture;
+mod frame_diff;
+mod frame_export;
+mod frame_scratch;
+mod frame_stats;
+mod frame_sync;
+mod gpu_timer;
+mod handles;
+mod leak_check;
+mod light_culling;
+mod lightmap;
+mod loading_screen;
+mod memory_heatmap;
+mod minimap;
+mod offscreen;
+mod perf_hud;
+mod post_process;
+mod post_targets;
+mod quality_governor;
+mod recorder;
+mod reflection_probes;
+mod present;
+mod resource_budget;
+mod rooms;
+mod shadows;
+mod snapshot;
+mod taa;
+mod texture_streaming;
+mod texture_transcoding;
+mod transform_history;
+mod ui_layer;
+mod view_policy;
+mod world_labels;
+#[cfg(test)]
+mod visual_regression;
+#[cfg(test)]
+mod tests;
+
+// Drops what was retired long enough ago that no frame in flight can still be using it
+fn release_retired<T>(retired: &mut Vec<(u64, T)>, frame_number: u64, frames_in_flight: u64) {
+  retired.retain(|(retired_at, _)| retired_at + frames_in_flight > frame_number);
+}
+
+// What a frame's present means for the swapchain
+#[derive(Debug, PartialEq)]
+enum PresentOutcome {
+  Presented,
+  // Shown, but the swapchain no longer matches the window and should be recreated
+  Suboptimal,
+  // Not shown, the swapchain has to be recreated before the next frame
+  OutOfDate,
+}
+
+impl PresentOutcome {
+  fn new(present_res: Result<(), String>, suboptimal: bool) -> Result<Self, String> {
+    match present_res {
+      Err(e) if e.ends_with("ERROR_OUT_OF_DATE_KHR") => Ok(Self::OutOfDate),
+      Err(e) => Err(e),
+      Ok(()) if suboptimal => Ok(Self::Suboptimal),
+      Ok(()) => Ok(Self::Presented),
+    }
+  }
+}
 
 pub enum RendererMessage {
-  UploadTriMesh(String, TriMeshCPU, Arc<OnceLock<Arc<TriMeshGPU>>>),
-  UploadFlatTex(String, String, Arc<OnceLock<Arc<FlatTextureGPU>>>),
-  SetCamera(Camera3D),
-  DrawTriangleMeshesWithFlatTexture(Vec<(Arc<TriMeshGPU>, Option<Arc<FlatTextureGPU>>)>),
+  UploadTriMesh(String, TriMeshCPU, MeshHandle),
+  UploadMorphTriMesh(String, TriMeshCPU, Vec<MorphTargetCPU>, MeshHandle),
+  // Drawn as a mesh posed by the joint matrices from the snapshot, in the bind pose until then
+  UploadSkinnedMesh(String, SkinnedMeshCPU, MeshHandle),
+  // Drawn as a mesh whose vertices the cloth sim moves every frame, in model space under the
+  // mesh's transform
+  UploadCloth(String, ClothCPU, MeshHandle),
+  // Name, vfs path of the image file, Srgb for colors like albedo and Linear for data like normals,
+  // and the format to store it in when not the one the file fits best
+  UploadFlatTex(String, String, TextureColorSpace, Option<TextureFormat>, TextureHandle),
+  DestroyTriMesh(MeshHandle),
+  // Meshes are drawn from the time they are added until removed or destroyed, per frame state
+  // like transforms comes from the snapshot
+  AddRenderable(MeshHandle, Option<MaterialHandle>),
+  RemoveRenderable(MeshHandle),
+  SetRenderableVisible(MeshHandle, bool),
+  // Drawn only by cameras seeing one of the layers, see FrameSnapshot::camera_layers
+  SetRenderableLayers(MeshHandle, LayerMask),
+  DestroyFlatTex(TextureHandle),
+  // The texture is looked up once on creation, no texture uses the default one
+  CreateMaterial(String, MaterialCPU, Option<TextureHandle>, MaterialHandle),
+  // Same with the lightmap a Lightmapped material is lit by, uploaded as Srgb. See
+  // bake_lightmap
+  CreateLightmappedMaterial(
+    String,
+    MaterialCPU,
+    Option<TextureHandle>,
+    TextureHandle,
+    MaterialHandle,
+  ),
+  UpdateMaterialParams(MaterialHandle, MaterialParams),
+  DestroyMaterial(MaterialHandle),
+  SetEditorGrid(Option<GridSettings>),
+  SetGizmo(Option<Gizmo>),
+  // Top down map of the area around the camera in the top right corner
+  SetMinimap(Option<MinimapSettings>),
+  // Allocator blocks and usage drawn over the frame, details go to stdout
+  SetMemoryHeatmap(bool),
+  // Frame time plot and meters for draw calls, gpu memory and physics pairs in the bottom right,
+  // the numbers are written on them with a ui font and go to stdout
+  SetPerfHud(bool),
+  // What the game's physics did last tick, for the perf hud
+  SetPhysicsStats(PhysicsHudStats),
+  // World space lines drawn over everything until the next set, for collision shapes and
+  // contacts. Empty to stop drawing them
+  SetDebugLines(Vec<DebugLine>),
+  // Drawn over the scene and any HUD until the next set, in pixels of the scene as shown. Empty to
+  // stop drawing them
+  SetUiShapes(Vec<UiShape>),
+  // Drawn on the UI layer under the UI shapes until the next set, hidden behind the scene unless
+  // they have a through_walls color. Empty to stop drawing them
+  SetWorldLabels(Vec<WorldLabel>),
+  // Tonemaps with this exposure in stops, like exposure_ev in manual mode, for cutscenes or photo
+  // modes. None goes back to the configured exposure
+  SetExposure(Option<f32>),
+  // Vignette, chromatic aberration and film grain drawn instead of the configured ones, for
+  // cutscenes or photo modes. None goes back to the config
+  SetFilmEffects(Option<FilmEffectsParams>),
+  // Vfs path of a .cube file or a strip image with the blue slices side by side, graded with
+  // through the snapshot's color_grading
+  LoadColorLut(String, ColorLutHandle),
+  DestroyColorLut(ColorLutHandle),
+  // Captures this many frames with RenderDoc, starting at the next present. Only when the app was
+  // launched from RenderDoc or it was injected
+  TriggerCapture(u32),
+  // Reads the depth under a point on screen back from the next frame drawn, see
+  // Renderer::depth_sample
+  QueryDepth(DepthQuery),
+  // Copies every finished frame out for other processes, see Renderer::exported_frame. None stops
+  // it. Waits for the frames in flight
+  SetFrameExport(Option<FrameExportMode>),
+  // Captures frames to compare, like before and after a physics change, and shows a heatmap of
+  // what changed between them over the scene. Frames are exported with CpuCopy while capturing
+  FrameDiff(FrameDiffCommand),
+  // Writes frames out until StopRecording, replacing a recording already running. Frames are
+  // exported with CpuCopy meanwhile, whatever SetFrameExport asked for
+  StartRecording(RecordingSettings),
+  // Waits for the frames taken to be written
+  StopRecording,
+  // Times every frame drawn until StopBenchmark, replacing a benchmark already running
+  StartBenchmark,
+  // The report comes back through Renderer::take_benchmark_report
+  StopBenchmark,
+  // Waits for the frames in flight and frees what they held, then counts what's still live. The
+  // snapshot comes back through Renderer::take_resource_snapshot
+  SnapshotResources,
+  // Packs mesh vertices and indices together and frees the arena blocks left empty. Waits for the
+  // gpu to go idle, so for frames where a hitch won't show. Also done when a loading screen ends
+  CompactMeshBuffers,
+  // Left unused unless gpu_frame_budget_ms is set in the config
+  AddQualityCallback(QualityCallback),
+  // Switches to the preset's settings between frames, see QualitySettings
+  SetQuality(QualityPreset),
+  // Compiles a fragment shader file for the variant, with or without vertex streams, and rebuilds
+  // its pipelines with it. None goes back to the built in shader. Anything that fails is logged
+  // and the shader that last worked stays
+  ReloadMaterialShader(ShaderVariant, bool, Option<PathBuf>),
+  CreateParticleSystem(String, ParticleEmitter, ParticleHandle),
+  SetParticleEmitter(ParticleHandle, ParticleEmitter),
+  // Particle count and a seed for their random spread
+  EmitParticles(ParticleHandle, u32, u32),
+  DestroyParticleSystem(ParticleHandle),
+  // Max instances, the material is looked up once on creation like a material's texture.
+  // Instances come from the snapshot
+  CreateCrowd(String, CrowdMeshCPU, BoneAnimationCPU, u32, Option<MaterialHandle>, CrowdHandle),
+  DestroyCrowd(CrowdHandle),
+  // The normal map is looked up once on creation, no normal map uses generated ripples. Waves
+  // scroll with the render thread's clock
+  UploadWaterPlane(String, WaterPlaneCPU, Option<TextureHandle>, WaterHandle),
+  DestroyWaterPlane(WaterHandle),
+  // The material is looked up once on creation, no material uses alpha cutout grass blades
+  CreateFoliage(String, FoliageCPU, Option<MaterialHandle>, FoliageHandle),
+  DestroyFoliage(FoliageHandle),
+  // Sways every foliage
+  SetWind(WindParams),
+  // Adds the light or replaces what it was set to before, lights move by being set again
+  SetLight(LightHandle, LocalLight),
+  RemoveLight(LightHandle),
+  // Vfs path of an equirectangular .hdr or .exr image, prefiltered into a cubemap for skies and
+  // image based lighting. None removes it
+  SetEnvironment(Option<String>),
+  // Replaces the level's rooms, meshes in rooms that can't be seen from the camera are skipped
+  SetRooms(RoomGraph),
+  // Replaces the level's probes and captures them in the next frame drawn, at most
+  // MAX_REFLECTION_PROBES. Lit and lightmapped materials in their boxes reflect them
+  SetReflectionProbes(Vec<ReflectionProbe>),
+  // Uploads between the two are what the level needs before it can be shown. Until EndLoading is
+  // applied a loading screen with their progress is drawn instead of the scene
+  BeginLoading,
+  EndLoading,
   Stop,
 }
 
+// What frames are presented to. Offscreen frames are drawn the same but nobody sees them, for
+// headless runs
+pub enum RenderTarget {
+  Window(Arc<AdSurface>),
+  Offscreen(Arc<OffscreenWindow>),
+}
+
 pub struct Renderer {
-  thread: Option<std::thread::JoinHandle<Result<(), String>>>,
+  render_job: Option<JobHandle<Result<(), String>>>,
   ordered_cmds: Arc<Mutex<Vec<RendererMessage>>>,
+  snapshot_writer: TripleBufferWriter<FrameSnapshot>,
+  // Written by the render thread every frame
+  loading_progress: Arc<Mutex<Option<LoadingProgress>>>,
+  frame_stats: Arc<Mutex<FrameStats>>,
+  depth_sample: Arc<Mutex<Option<DepthSample>>>,
+  exported_frame: Arc<Mutex<Option<ExportedFrame>>>,
+  // Left until taken
+  benchmark_report: Arc<Mutex<Option<BenchmarkReport>>>,
+  resource_snapshot: Arc<Mutex<Option<ResourceSnapshot>>>,
+  // Set once the render thread has made its device
+  engine_info: Arc<Mutex<Option<EngineInfo>>>,
+  mesh_handles: HandleAllocator<TriMeshGPU>,
+  texture_handles: HandleAllocator<FlatTextureGPU>,
+  material_handles: HandleAllocator<MaterialGPU>,
+  particle_handles: HandleAllocator<ParticleSystemGPU>,
+  crowd_handles: HandleAllocator<CrowdGPU>,
+  water_handles: HandleAllocator<WaterPlaneGPU>,
+  foliage_handles: HandleAllocator<FoliageGPU>,
+  light_handles: HandleAllocator<LocalLight>,
+  color_lut_handles: HandleAllocator<ColorLutGPU>,
 }
 
 impl Renderer {
-  pub fn new(surface: Arc<AdSurface>) -> Result<Self, String> {
-    let ordered_cmds = Arc::new(Mutex::new(vec![]));
-    let renderer_ordered_cmds = ordered_cmds.clone();
+  // The handle's state with nothing drawing what's sent to it yet
+  fn without_render_thread(snapshot_writer: TripleBufferWriter<FrameSnapshot>) -> Self {
+    Self {
+      render_job: None,
+      ordered_cmds: Arc::new(Mutex::new(vec![])),
+      snapshot_writer,
+      loading_progress: Arc::new(Mutex::new(None)),
+      frame_stats: Arc::new(Mutex::new(FrameStats::default())),
+      depth_sample: Arc::new(Mutex::new(None)),
+      exported_frame: Arc::new(Mutex::new(None)),
+      benchmark_report: Arc::new(Mutex::new(None)),
+      resource_snapshot: Arc::new(Mutex::new(None)),
+      engine_info: Arc::new(Mutex::new(None)),
+      mesh_handles: HandleAllocator::new(),
+      texture_handles: HandleAllocator::new(),
+      material_handles: HandleAllocator::new(),
+      particle_handles: HandleAllocator::new(),
+      crowd_handles: HandleAllocator::new(),
+      water_handles: HandleAllocator::new(),
+      foliage_handles: HandleAllocator::new(),
+      light_handles: HandleAllocator::new(),
+      color_lut_handles: HandleAllocator::new(),
+    }
+  }
 
-    let thread = std::thread::spawn(move || {
-      let mut render_mgr = RenderManager::new(surface)?;
+  // No render thread behind it, handles are handed out and messages queue up unread. For testing
+  // the simulation on machines without a gpu
+  #[cfg(feature = "test-support")]
+  pub fn detached() -> Self {
+    Self::without_render_thread(jobs::triple_buffer(FrameSnapshot::default()).0)
+  }
+
+  pub fn new(target: RenderTarget, config: RendererConfig) -> Result<Self, String> {
+    let (snapshot_writer, mut snapshot_reader) = jobs::triple_buffer(FrameSnapshot::default());
+    let mut renderer = Self::without_render_thread(snapshot_writer);
+    let renderer_ordered_cmds = renderer.ordered_cmds.clone();
+    let renderer_loading_progress = renderer.loading_progress.clone();
+    let renderer_frame_stats = renderer.frame_stats.clone();
+    let renderer_depth_sample = renderer.depth_sample.clone();
+    let renderer_exported_frame = renderer.exported_frame.clone();
+    let renderer_benchmark_report = renderer.benchmark_report.clone();
+    let renderer_resource_snapshot = renderer.resource_snapshot.clone();
+    let renderer_engine_info = renderer.engine_info.clone();
+
+    // Before the job starts, the window's scale is published as soon as the renderer is made
+    let scale_events = event_bus::global().subscribe::<WindowScaleChanged>();
+
+    let render_job = jobs::global().spawn_dedicated("render", move || {
+      let mut render_mgr = RenderManager::new(target, config)?;
+      *renderer_engine_info.lock().map_err(|e| format!("at getting lock for engine info: {e}"))? =
+        Some(render_mgr.engine_info.clone());
+      let resize_events = event_bus::global().subscribe::<WindowResized>();
+      render_mgr.publish_view();
+      // Draws run one tick behind the simulation, blending between the last two snapshots
+      let mut prev_snapshot = FrameSnapshot::default();
+      let mut latest_snapshot = FrameSnapshot::default();
+      let mut latest_received = Instant::now();
+      let mut interpolated = FrameSnapshot::default();
+      let mut backlog = MessageBacklog::new();
+      let mut transform_history = TransformHistory::new();
       loop {
-        let mut quit_renderer = false;
-        let mut current_cmds = renderer_ordered_cmds
+        // Catches resizes before present reports the swapchain out of date, nothing to draw to
+        // while minimized
+        if resize_events.latest().is_some_and(|size| size.width > 0 && size.height > 0) {
+          let _ = render_mgr
+            .refresh_swapchain()
+            .inspect_err(|e| log!("at refreshing swapchain on resize: {e}"));
+        }
+        if let Some(scale) = scale_events.latest() {
+          render_mgr.window_scale_factor = scale.scale_factor;
+          render_mgr.publish_view();
+        }
+        // Taken together under the queue lock, so a snapshot never refers to meshes whose upload
+        // hasn't been taken yet
+        let (current_cmds, new_snapshot) = {
+          let mut queued_cmds = renderer_ordered_cmds
+            .lock()
+            .map_err(|e| format!("at getting lock for renderer work queue: {e}"))?;
+          (std::mem::take(&mut *queued_cmds), snapshot_reader.update())
+        };
+        if new_snapshot {
+          std::mem::swap(&mut prev_snapshot, &mut latest_snapshot);
+          latest_snapshot.clone_from(snapshot_reader.front());
+          latest_received = Instant::now();
+          transform_history.record(&latest_snapshot);
+          // Kept until a frame is presented, snapshots can come in faster than frames
+          render_mgr.input_received_at =
+            render_mgr.input_received_at.or(latest_snapshot.input_received_at);
+        }
+        backlog.push(current_cmds);
+        let quit_renderer = render_mgr.process_messages(backlog.next_batch());
+        if quit_renderer {
+          break;
+        }
+        let progress = backlog.progress();
+        // Everything the level needed is uploaded, packing it can hide behind the loading screen
+        if render_mgr.loading_progress.is_some() && progress.is_none() {
+          let _ = render_mgr
+            .compact_mesh_buffers()
+            .inspect_err(|e| log!("at compacting mesh buffers: {e}"));
+        }
+        *renderer_loading_progress
           .lock()
-          .map_err(|e| format!("at getting lock for renderer work queue: {e}"))?;
-        for message in current_cmds.drain(..) {
-          match message {
-            RendererMessage::UploadTriMesh(name, tri_mesh_cpu, tri_mesh_gpu) => {
-              let _ = render_mgr
-                .add_tri_mesh(name, &tri_mesh_cpu, tri_mesh_gpu)
-                .inspect_err(|e| eprintln!("error adding mesh: {e}"));
-            }
-            RendererMessage::UploadFlatTex(name, flat_tex_path, flat_tex_gpu) => {
-              let _ = render_mgr
-                .add_flat_texture(name, flat_tex_path, flat_tex_gpu)
-                .inspect_err(|e| eprintln!("error adding texture: {e}"));
-            }
-            RendererMessage::DrawTriangleMeshesWithFlatTexture(mesh_ftex_list) => {
-              for _ in 0..3 {
-                if let Ok(d_res) = render_mgr.draw(&mesh_ftex_list).inspect_err(|e| eprintln!("{}", e)) {
-                  if !d_res {
-                    break;
-                  }
-                }
+          .map_err(|e| format!("at getting lock for loading progress: {e}"))? = progress;
+        render_mgr.loading_progress = progress;
+        *renderer_frame_stats
+          .lock()
+          .map_err(|e| format!("at getting lock for frame stats: {e}"))? =
+          render_mgr.frame_stats();
+        *renderer_depth_sample
+          .lock()
+          .map_err(|e| format!("at getting lock for depth sample: {e}"))? =
+          render_mgr.depth_readback.latest();
+        *renderer_exported_frame
+          .lock()
+          .map_err(|e| format!("at getting lock for exported frame: {e}"))? =
+          render_mgr.frame_export.latest();
+        if let Some(report) = render_mgr.benchmark_report.take() {
+          *renderer_benchmark_report
+            .lock()
+            .map_err(|e| format!("at getting lock for benchmark report: {e}"))? = Some(report);
+        }
+        if let Some(snapshot) = render_mgr.resource_snapshot.take() {
+          *renderer_resource_snapshot
+            .lock()
+            .map_err(|e| format!("at getting lock for resource snapshot: {e}"))? = Some(snapshot);
+        }
+        // Snapshots may refer to meshes still in the backlog, they are left alone until it's done
+        if progress.is_some() {
+          for _ in 0..DRAW_ATTEMPTS {
+            if let Ok(d_res) = render_mgr.draw().inspect_err(|e| log!("{}", e)) {
+              if !d_res {
+                break;
               }
             }
-            RendererMessage::Stop => {
-              quit_renderer = true;
-            }
-            RendererMessage::SetCamera(camera3_d) =>{
-              render_mgr.camera = camera3_d
-            },
           }
+          profiler::end_frame();
+          continue;
         }
-        current_cmds.clear();
-        if quit_renderer {
-          break;
+        // Nothing to draw before the first tick
+        if latest_snapshot.sim_time == 0 {
+          std::thread::sleep(Duration::from_millis(1));
+          continue;
+        }
+        let tick_time = latest_snapshot.sim_time.saturating_sub(prev_snapshot.sim_time);
+        let blend = match prev_snapshot.sim_time {
+          0 => 1.0,
+          _ => latest_received.elapsed().as_micros() as f32 / tick_time.max(1) as f32,
+        };
+        latest_snapshot.interpolate_from(&prev_snapshot, blend, &mut interpolated);
+        transform_history.apply(&mut interpolated);
+        for mesh_state in interpolated.meshes.iter() {
+          let _ = render_mgr
+            .update_tri_mesh_transform(mesh_state.mesh, mesh_state.transform)
+            .inspect_err(|e| log!("error updating mesh transform: {e}"));
+          if let Some(weights) = mesh_state.morph_weights {
+            let _ = render_mgr
+              .update_morph_weights(mesh_state.mesh, weights)
+              .inspect_err(|e| log!("error updating morph weights: {e}"));
+          }
+        }
+        for skinned_state in interpolated.skinned_meshes.iter() {
+          let _ = render_mgr
+            .update_joint_matrices(skinned_state.mesh, &skinned_state.joint_matrices)
+            .inspect_err(|e| log!("error updating joint matrices: {e}"));
         }
+        for crowd_state in interpolated.crowds.iter() {
+          let _ = render_mgr
+            .update_crowd_instances(crowd_state.crowd, &crowd_state.instances)
+            .inspect_err(|e| log!("error updating crowd instances: {e}"));
+        }
+        render_mgr.camera = interpolated.camera;
+        render_mgr.camera_fov = interpolated.camera_fov;
+        render_mgr.set_camera_layers(interpolated.camera_layers);
+        if let Some(depth_of_field) = &mut render_mgr.depth_of_field {
+          depth_of_field.set_override(interpolated.depth_of_field);
+        }
+        render_mgr.color_grading = interpolated.color_grading;
+        render_mgr.sun = interpolated.sun;
+        render_mgr.tri_mesh_renderer.set_clear_color(interpolated.sky_color);
+        for _ in 0..DRAW_ATTEMPTS {
+          if let Ok(d_res) = render_mgr.draw().inspect_err(|e| log!("{}", e)) {
+            if !d_res {
+              break;
+            }
+          }
+        }
+        profiler::end_frame();
       }
-      return Ok::<(), String>(());
-    });
-    Ok(Self { thread: Some(thread), ordered_cmds })
+      render_mgr.shutdown()
+    })?;
+    renderer.render_job = Some(render_job);
+    Ok(renderer)
+  }
+
+  pub fn create_mesh_handle(&mut self) -> MeshHandle {
+    self.mesh_handles.allocate()
+  }
+
+  pub fn create_texture_handle(&mut self) -> TextureHandle {
+    self.texture_handles.allocate()
+  }
+
+  pub fn create_material_handle(&mut self) -> MaterialHandle {
+    self.material_handles.allocate()
+  }
+
+  pub fn create_particle_handle(&mut self) -> ParticleHandle {
+    self.particle_handles.allocate()
+  }
+
+  pub fn create_crowd_handle(&mut self) -> CrowdHandle {
+    self.crowd_handles.allocate()
   }
 
+  pub fn create_water_handle(&mut self) -> WaterHandle {
+    self.water_handles.allocate()
+  }
+
+  pub fn create_foliage_handle(&mut self) -> FoliageHandle {
+    self.foliage_handles.allocate()
+  }
+
+  pub fn create_light_handle(&mut self) -> LightHandle {
+    self.light_handles.allocate()
+  }
+
+  pub fn create_color_lut_handle(&mut self) -> ColorLutHandle {
+    self.color_lut_handles.allocate()
+  }
+
+  // Destroyed handles are recycled right away, the render thread sees the destroy before any
+  // upload reusing the slot since messages are processed in order
+  fn free_destroyed_handles(&mut self, batch: &[RendererMessage]) -> Result<(), String> {
+    for message in batch.iter() {
+      match message {
+        RendererMessage::DestroyTriMesh(handle) => self.mesh_handles.free(*handle)?,
+        RendererMessage::DestroyFlatTex(handle) => self.texture_handles.free(*handle)?,
+        RendererMessage::DestroyMaterial(handle) => self.material_handles.free(*handle)?,
+        RendererMessage::DestroyParticleSystem(handle) => self.particle_handles.free(*handle)?,
+        RendererMessage::DestroyCrowd(handle) => self.crowd_handles.free(*handle)?,
+        RendererMessage::DestroyWaterPlane(handle) => self.water_handles.free(*handle)?,
+        RendererMessage::DestroyFoliage(handle) => self.foliage_handles.free(*handle)?,
+        RendererMessage::RemoveLight(handle) => self.light_handles.free(*handle)?,
+        RendererMessage::DestroyColorLut(handle) => self.color_lut_handles.free(*handle)?,
+        _ => {}
+      }
+    }
+    let live_counts = self.live_handle_counts().map(|(kind, count)| format!("{count} {kind}"));
+    crash_report::set_field("live render handles", live_counts.join(", "));
+    Ok(())
+  }
+
+  // Waits for the render thread to take everything queued before, for setup work that should
+  // land before the first frame
   pub fn send_batch_sync(&mut self, mut batch: Vec<RendererMessage>) -> Result<bool, String> {
+    self.free_destroyed_handles(&batch)?;
     loop {
       let mut current_cmds = self
         .ordered_cmds
@@ -102,51 +671,402 @@ impl Renderer {
     }
     Ok(true)
   }
-}
 
-impl Drop for Renderer {
-  fn drop(&mut self) {
-    let Some(thread) = self.thread.take() else { return; };
+  // Progress of the uploads the loading screen is waiting on, None when the scene is drawn
+  pub fn loading_progress(&self) -> Result<Option<LoadingProgress>, String> {
+    Ok(
+      *self
+        .loading_progress
+        .lock()
+        .map_err(|e| format!("at getting lock for loading progress: {e}"))?,
+    )
+  }
+
+  // Captures the next frames with RenderDoc when it's attached, to catch the exact frames a bug
+  // shows in. Frames drawn for messages queued before are not captured
+  pub fn trigger_capture(&mut self, frames: u32) -> Result<(), String> {
+    self.queue_message(RendererMessage::TriggerCapture(frames))
+  }
+
+  // Lets the game trade quality for gpu time, see QualityCallback. The render thread only times
+  // frames against gpu_frame_budget_ms from the config
+  pub fn add_quality_callback(
+    &mut self,
+    callback: impl FnMut(QualityPressure, Duration, &mut QualityKnobs) + Send + 'static,
+  ) -> Result<(), String> {
+    self.queue_message(RendererMessage::AddQualityCallback(Box::new(callback)))
+  }
+
+  // Everything the preset bundles changes before the next frame is drawn. Msaa sample counts
+  // other than the one the renderer started with need a restart, msaa stays as it is until then
+  pub fn set_quality(&mut self, preset: QualityPreset) -> Result<(), String> {
+    self.queue_message(RendererMessage::SetQuality(preset))
+  }
+
+  // Hot reload of a material's fragment shader, see RendererMessage::ReloadMaterialShader
+  pub fn reload_material_shader(
+    &mut self,
+    variant: ShaderVariant,
+    vertex_streams: bool,
+    path: Option<PathBuf>,
+  ) -> Result<(), String> {
+    self.queue_message(RendererMessage::ReloadMaterialShader(variant, vertex_streams, path))
+  }
+
+  // The depth under screen_pos, in 0..1 with y going down, is read back from the next frame drawn.
+  // The nearest surface within radius texels is taken, so thin edges are easier to hit
+  pub fn query_depth(&mut self, screen_pos: glam::Vec2, radius: u32) -> Result<(), String> {
+    self.queue_message(RendererMessage::QueryDepth(DepthQuery { screen_pos, radius }))
+  }
+
+  // Result of the latest query_depth to come back, a few frames after it was made. Autofocus
+  // queries the center of the screen as well, check the sample's query when it's on
+  pub fn depth_sample(&self) -> Result<Option<DepthSample>, String> {
+    Ok(*self.depth_sample.lock().map_err(|e| format!("at getting lock for depth sample: {e}"))?)
+  }
+
+  // Shared falls back to CpuCopy when the gpu can't export memory
+  pub fn set_frame_export(&mut self, mode: Option<FrameExportMode>) -> Result<(), String> {
+    self.queue_message(RendererMessage::SetFrameExport(mode))
+  }
+
+  pub fn start_recording(&mut self, settings: RecordingSettings) -> Result<(), String> {
+    self.queue_message(RendererMessage::StartRecording(settings))
+  }
+
+  pub fn stop_recording(&mut self) -> Result<(), String> {
+    self.queue_message(RendererMessage::StopRecording)
+  }
+
+  pub fn start_benchmark(&mut self) -> Result<(), String> {
+    self.queue_message(RendererMessage::StartBenchmark)
+  }
+
+  pub fn stop_benchmark(&mut self) -> Result<(), String> {
+    self.queue_message(RendererMessage::StopBenchmark)
+  }
+
+  // Some once the render thread got to StopBenchmark
+  pub fn take_benchmark_report(&mut self) -> Result<Option<BenchmarkReport>, String> {
+    Ok(
+      self
+        .benchmark_report
+        .lock()
+        .map_err(|e| format!("at getting lock for benchmark report: {e}"))?
+        .take(),
+    )
+  }
+
+  pub fn snapshot_resources(&mut self) -> Result<(), String> {
+    self.queue_message(RendererMessage::SnapshotResources)
+  }
+
+  // Some once the render thread got to SnapshotResources
+  pub fn take_resource_snapshot(&mut self) -> Result<Option<ResourceSnapshot>, String> {
+    Ok(
+      self
+        .resource_snapshot
+        .lock()
+        .map_err(|e| format!("at getting lock for resource snapshot: {e}"))?
+        .take(),
+    )
+  }
+
+  // Latest frame finished while exporting, a couple of frames behind the one on screen
+  pub fn exported_frame(&self) -> Result<Option<ExportedFrame>, String> {
+    Ok(
+      self
+        .exported_frame
+        .lock()
+        .map_err(|e| format!("at getting lock for exported frame: {e}"))?
+        .clone(),
+    )
+  }
+
+  // After the messages already queued, without publishing a snapshot
+  fn queue_message(&mut self, message: RendererMessage) -> Result<(), String> {
+    self
+      .ordered_cmds
+      .lock()
+      .map_err(|e| format!("at getting lock for renderer work queue: {e}"))?
+      .push(message);
+    Ok(())
+  }
+
+  // As of the last frame the render thread drew
+  // None until the render thread is up
+  pub fn engine_info(&self) -> Result<Option<EngineInfo>, String> {
+    Ok(self.engine_info.lock().map_err(|e| format!("at getting lock for engine info: {e}"))?.clone())
+  }
+
+  pub fn frame_stats(&self) -> Result<FrameStats, String> {
+    Ok(*self.frame_stats.lock().map_err(|e| format!("at getting lock for frame stats: {e}"))?)
+  }
+
+  // Snapshot the next submit_frame publishes, still holds an older frame so fill all of it
+  pub fn snapshot_mut(&mut self) -> &mut FrameSnapshot {
+    self.snapshot_writer.back_mut()
+  }
+
+  // Queues the tick's messages and publishes the snapshot without waiting on the render thread
+  pub fn submit_frame(&mut self, mut batch: Vec<RendererMessage>) -> Result<(), String> {
+    self.free_destroyed_handles(&batch)?;
+    let mut current_cmds = self
+      .ordered_cmds
+      .lock()
+      .map_err(|e| format!("at getting lock for renderer work queue: {e}"))?;
+    current_cmds.append(&mut batch);
+    self.snapshot_writer.publish();
+    Ok(())
+  }
+
+  // Messages queued before are still processed, then the render thread waits for the gpu and
+  // tears down. Debug builds list handles the game never destroyed
+  pub fn shutdown(mut self) -> Result<(), String> {
+    if cfg!(debug_assertions) {
+      self.report_undestroyed_handles();
+    }
+    self.stop_render_thread()
+  }
+
+  fn live_handle_counts(&self) -> [(&'static str, usize); 9] {
+    [
+      ("mesh", self.mesh_handles.live_count()),
+      ("texture", self.texture_handles.live_count()),
+      ("material", self.material_handles.live_count()),
+      ("particle system", self.particle_handles.live_count()),
+      ("crowd", self.crowd_handles.live_count()),
+      ("water plane", self.water_handles.live_count()),
+      ("foliage", self.foliage_handles.live_count()),
+      ("light", self.light_handles.live_count()),
+      ("color lut", self.color_lut_handles.live_count()),
+    ]
+  }
+
+  fn report_undestroyed_handles(&self) {
+    for (kind, count) in self.live_handle_counts().iter().filter(|(_, count)| *count > 0) {
+      log!("leak: {count} {kind} handles not destroyed before shutdown");
+    }
+  }
 
-    if !thread.is_finished() {
-      let _ = self
+  fn stop_render_thread(&mut self) -> Result<(), String> {
+    let Some(render_job) = self.render_job.take() else { return Ok(()) };
+    if !render_job.is_finished() {
+      self
         .send_batch_sync(vec![RendererMessage::Stop])
-        .inspect_err(|e| eprintln!("at stopping renderer: {e}"));
+        .map_err(|e| format!("at stopping renderer: {e}"))?;
     }
-    let _ = thread.join()
-      .inspect_err(|_| eprintln!("at joining renderer thread"));
+    render_job
+      .wait()
+      .map_err(|e| format!("at waiting for renderer: {e}"))?
+      .map_err(|e| format!("renderer stopped with error: {e}"))
+  }
+}
+
+impl Drop for Renderer {
+  fn drop(&mut self) {
+    let _ = self.stop_render_thread().inspect_err(|e| log!("{e}"));
   }
 }
 
+// Vertical field of view in radians snapshots start with. Game side picking needs the same
+// projection the render thread draws with
+pub const CAMERA_FOV: f32 = 1.5;
+
+// Below this many draws recording inline beats the cost of splitting work over jobs
+const PARALLEL_RECORD_MIN_DRAWS: usize = 64;
+const MAX_RECORD_JOBS: usize = 4;
+// Tries at drawing a frame per loop, the ones after the first only when the swapchain was out of
+// date. Not tied to the frames in flight
+const DRAW_ATTEMPTS: usize = 3;
+
+type GPUQueues = HashMap<GPUQueueType, Arc<AdQueue>>;
+// Vfs path of a texture file, and how it's read. The color space is the one its import settings
+// pick, see RenderManager::texture_load
+type TextureLoad = (String, TextureColorSpace, Option<TextureFormat>, TextureImportSettings);
+
 const DEPTH_FORMAT_PREFERENCE: [vk::Format; 3] = [vk::Format::D24_UNORM_S8_UINT, vk::Format::D16_UNORM_S8_UINT, vk::Format::D32_SFLOAT];
 
 pub struct RenderManager {
   triangle_frame_buffers: Vec<Arc<AdFrameBuffer>>,
-  tri_mesh_tex_renderer: TriMeshTexRenderer,
+  tri_mesh_renderer: TriMeshMaterialRenderer,
+  editor_overlay_renderer: EditorOverlayRenderer,
+  debug_overlay_renderer: DebugOverlayRenderer,
+  // Where the memory heatmap and perf hud are drawn
+  ui_layer: UiLayer,
+  // From the window, what a ui_scale of 0 in the config follows
+  window_scale_factor: f64,
+  memory_heatmap: Option<MemoryHeatmap>,
+  perf_hud: Option<PerfHud>,
+  debug_lines: Vec<DebugLine>,
+  ui_shapes: Vec<UiShape>,
+  frame_capture: FrameCapture,
+  particle_renderer: ParticleRenderer,
+  // Per frame in flight, the depth attachment of the matching triangle framebuffer
+  particle_depth_dsets: Vec<AdDescriptorSet>,
+  // The gpu behind the graphics_backend traits, passes written against them record frames with it
+  backend_device: VulkanDevice,
+  // Only for ColorOutput::ShaderEncode, encodes the UI layer when it's drawn and the scene when not
+  srgb_encode_renderer: Option<SrgbEncodeRenderer<VulkanDevice>>,
+  // Only with RendererConfig::gpu_culling
+  mesh_culler: Option<MeshCullRenderer>,
+  // Only with taa or motion blur
+  post_process: Option<PostProcess>,
+  exposure: Exposure,
+  color_lut_gen: ColorLutGenerator,
+  color_lut_registry: HandleRegistry<ColorLutGPU>,
+  // From the snapshot, applied by the tonemap pass
+  color_grading: Option<ColorGrading>,
+  // From the snapshot too
+  sun: SunLight,
+  // Only with depth_of_field in the post process config
+  depth_of_field: Option<DepthOfField>,
+  film_effects: FilmEffects,
+  depth_readback: DepthReadback,
+  world_labels: WorldLabels,
+  frame_export: FrameExport,
+  frame_diff: FrameDiff,
+  // What SetFrameExport asked for, recordings export with CpuCopy until they stop
+  requested_frame_export: Option<FrameExportMode>,
+  recorder: Option<Recorder>,
+  benchmark: Option<BenchmarkCapture>,
+  // Finished benchmark, until the render thread hands it to the Renderer
+  benchmark_report: Option<BenchmarkReport>,
+  // Same for the last SnapshotResources
+  resource_snapshot: Option<ResourceSnapshot>,
+  // Recorded into the last frame's command buffer
+  draw_calls: u32,
+  // Made on the render thread drawing the last frame, only with the counting allocator
+  frame_allocations: Option<u64>,
+  frame_scratch: FrameScratch,
+  gpu_timer: GpuFrameTimer,
+  // Only with a gpu frame budget
+  quality_governor: Option<QualityGovernor>,
+  // Bumped whenever the batches or their transforms change, so the culler rewrites the bounds
+  cull_bounds_version: u64,
+  shadows: SceneShadows,
+  particle_registry: HandleRegistry<ParticleSystemGPU>,
+  particle_gen: ParticleSystemGenerator,
+  last_particle_sim: Option<std::time::Instant>,
+  crowd_renderer: CrowdRenderer,
+  crowd_registry: HandleRegistry<CrowdGPU>,
+  crowd_gen: CrowdGenerator,
+  water_renderer: WaterRenderer,
+  water_registry: HandleRegistry<WaterPlaneGPU>,
+  water_gen: WaterPlaneGenerator,
+  foliage_renderer: FoliageRenderer,
+  foliage_registry: HandleRegistry<FoliageGPU>,
+  foliage_gen: FoliageGenerator,
+  wind: WindParams,
+  lights: HashMap<LightHandle, LocalLight>,
+  light_culling: LightCulling,
+  // Time water waves scroll and foliage sways by
+  effect_clock: Instant,
+  // Per frame in flight, the scene seen from under the first reflective plane. Only made once a
+  // reflective plane is added
+  water_reflection_frame_buffers: Vec<Arc<AdFrameBuffer>>,
+  water_reflection_dsets: Vec<AdDescriptorSet>,
+  editor_grid: Option<GridSettings>,
+  gizmo: Option<Gizmo>,
+  gizmo_meshes: HashMap<(GizmoMode, GizmoAxis), Arc<TriMeshGPU>>,
+  minimap: Option<Minimap>,
 
-  flat_texes: HashMap<String, Arc<FlatTextureGPU>>,
+  flat_texes: HashMap<String, Weak<FlatTextureGPU>>,
+  flat_tex_registry: HandleRegistry<FlatTextureGPU>,
   flat_tex_gen: FlatTextureGenerator,
-  tri_meshes: HashMap<String, Arc<TriMeshGPU>>,
+  // Large textures keep only the mips their on screen size needs, None without texture_streaming
+  texture_streamer: Option<TextureStreamer>,
+  // Compresses the other textures on the job pool and swaps them in, None with compression off
+  texture_transcoder: Option<TextureTranscoder>,
+  material_registry: HandleRegistry<MaterialGPU>,
+  material_gen: MaterialGenerator,
+  // Used for draws without a material, unlit with the default texture
+  default_material: Arc<MaterialGPU>,
+  tri_meshes: HashMap<String, Weak<TriMeshGPU>>,
+  tri_mesh_registry: HandleRegistry<TriMeshGPU>,
   tri_mesh_gen: TriMeshGenerator,
+  // Also in tri_mesh_registry, these get posed before the frame's draws
+  skinned_meshes: HashMap<MeshHandle, Arc<SkinnedMeshGPU>>,
+  skinned_mesh_gen: SkinnedMeshGenerator,
+  skinning_renderer: SkinningRenderer,
+  // Also in tri_mesh_registry, stepped before the frame's draws
+  cloths: HashMap<MeshHandle, Arc<ClothGPU>>,
+  cloth_gen: ClothGenerator,
+  cloth_renderer: ClothRenderer,
+  last_cloth_sim: Option<Instant>,
+  environment_gen: EnvironmentGenerator,
+  environment: Option<Arc<EnvironmentMap>>,
+  reflection_probes: ReflectionProbes,
+  draw_list: DrawList,
+  room_graph: RoomGraph,
+  // Last transform from the snapshot, for placing meshes in rooms
+  mesh_transforms: HashMap<MeshHandle, glam::Mat4>,
+  // Last joint matrices set for skinned meshes
+  joint_poses: HashMap<MeshHandle, Vec<glam::Mat4>>,
+  // With post processing, meshes whose previous transform or pose on the gpu isn't the one they
+  // were last drawn with
+  moved_meshes: HashSet<MeshHandle>,
+  drawn_transforms: HashMap<MeshHandle, glam::Mat4>,
+  drawn_poses: HashMap<MeshHandle, Vec<glam::Mat4>>,
+  // Resolved from the draw list, sorted for binding
+  draw_batches: Vec<(Arc<TriMeshGPU>, Arc<MaterialGPU>)>,
+  // Destroyed resources are kept until frames that may still use them are done
+  retired_resources: Vec<(u64, Arc<dyn Send + Sync>)>,
+  frame_number: u64,
   camera: Camera3D,
+  // Vertical, in radians
+  camera_fov: f32,
+  // Loading screen drawn instead of the scene while set
+  loading_progress: Option<LoadingProgress>,
+  // Oldest input not shown by a presented frame yet
+  input_received_at: Option<Instant>,
+  input_latency: InputLatencyTracker,
+  resource_budget: ResourceBudget,
 
   gen_allocator: Arc<Mutex<Allocator>>,
-  render_semaphores: Vec<AdSemaphore>,
-  render_fences: Vec<AdFence>,
+  frame_sync: FrameSync,
   render_cmd_buffers: Vec<AdCommandBuffer>,
-  image_acquire_fence: AdFence,
-  swapchain: AdSwapchain,
+  // Per frame in flight, one buffer per recording job and every job slot has its own pool
+  secondary_cmd_buffers: Vec<Vec<AdCommandBuffer>>,
+  setup_fence: AdFence,
+  swapchain: Box<dyn PresentTarget>,
   depth_format: vk::Format,
+  // Msaa samples the scene targets and every pipeline drawing to them were made with
+  samples: vk::SampleCountFlags,
+  // The settings the config had, for switching back to the custom preset
+  custom_quality: QualitySettings,
+  // Applied before the next frame is recorded
+  pending_quality: Option<QualityPreset>,
+  // Set when something drawing to them changed, the scene targets are made again next frame
+  rebuild_targets: bool,
+  engine_info: EngineInfo,
   queues: HashMap<GPUQueueType, Arc<AdQueue>>,
   ash_device: Arc<AdAshDevice>,
+  // Only held so the crash report can reach it
+  _crash_cleanup: Arc<GpuIdleOnCrash>,
+  config: RendererConfig,
+}
+
+// Lets the gpu finish what was submitted before a crash takes the process down
+struct GpuIdleOnCrash(Arc<AdAshDevice>);
+
+impl CrashCleanup for GpuIdleOnCrash {
+  fn on_crash(&self) -> Result<(), String> {
+    self.0.wait_idle()
+  }
 }
 
 impl RenderManager {
-  pub fn new(surface: Arc<AdSurface>) -> Result<Self, String> {
+  pub fn new(target: RenderTarget, config: RendererConfig) -> Result<Self, String> {
+    match target {
+      RenderTarget::Window(surface) => Self::new_windowed(surface, config),
+      RenderTarget::Offscreen(window) => Self::new_offscreen(config, window),
+    }
+  }
+
+  fn new_windowed(surface: Arc<AdSurface>, config: RendererConfig) -> Result<Self, String> {
     let ash_instance = surface.surface_instance().ash_instance().clone();
-    let gpu = ash_instance.list_dedicated_gpus()?.iter().next().cloned().unwrap_or(
-      ash_instance.list_gpus()?.iter().next().cloned().ok_or("no supported gpus".to_string())?,
-    );
+    let gpu = Self::select_gpu(&ash_instance, config.gpu)?;
     let mut q_f_idxs = ash_instance.select_gpu_queue_families(gpu)?;
     q_f_idxs.insert(
       GPUQueueType::Present,
@@ -156,251 +1076,2561 @@ impl RenderManager {
         .next()
         .ok_or("no supported present queues".to_string())?,
     );
-    let qf_info = ash_instance.get_queue_family_props(gpu);
-    let mut queue_counts = HashMap::new();
-    for (_, qf_idx) in q_f_idxs.iter() {
-      let val_ptr = queue_counts.entry(*qf_idx).or_insert(0);
-      if qf_info[*qf_idx as usize].queue_count > *val_ptr {
-        *val_ptr += 1
-      };
+    let shadow_mode = shadows::select_mode(&ash_instance, gpu, config.shadow_mode);
+    let present_wait = match config.max_frame_latency {
+      0 => false,
+      _ if !ash_instance.supports_present_wait(gpu) => {
+        log!("present wait not supported, limiting frame latency with frame fences");
+        false
+      }
+      _ => true,
+    };
+    let (ash_device, queues) = Self::create_device(
+      ash_instance,
+      gpu,
+      q_f_idxs,
+      config.buffer_device_address,
+      shadow_mode == ShadowMode::RayTraced,
+      present_wait,
+    )?;
+
+    let surface_formats = surface.get_gpu_formats(ash_device.gpu())?;
+    let surface_caps = surface.get_gpu_capabilities(ash_device.gpu())?;
+    let surface_present_modes = surface.get_gpu_present_modes(ash_device.gpu())?;
+
+    let (surface_format, color_output) =
+      color::select_surface_format(&surface_formats, config.color_output)?;
+    // FIFO is the only mode every driver has to support
+    let present_mode = match config.vsync {
+      true => vk::PresentModeKHR::FIFO,
+      false => [vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE]
+        .into_iter()
+        .find(|mode| surface_present_modes.contains(mode))
+        .unwrap_or(vk::PresentModeKHR::FIFO),
+    };
+    let swapchain_resolution = match surface_caps.current_extent.width {
+      u32::MAX => vk::Extent2D::default().width(640).height(480),
+      _ => surface_caps.current_extent,
+    };
+
+    let (swapchain_image_count, frames_in_flight) =
+      present::select_frame_counts(&surface_caps, config.frames_in_flight);
+    if frames_in_flight != config.frames_in_flight {
+      log!(
+        "{} frames in flight need more swapchain images than the surface has, using {}",
+        config.frames_in_flight,
+        frames_in_flight
+      );
     }
+    let config = RendererConfig { frames_in_flight, ..config };
 
-    let device_extensions = vec![
-      khr::swapchain::NAME.as_ptr(),
-      #[cfg(target_os = "macos")]
-      khr::portability_subset::NAME.as_ptr(),
-    ];
+    let swapchain = AdSwapchain::new(
+      Arc::new(AdSwapchainDevice::new(ash_device.clone())),
+      surface,
+      queues.get(&GPUQueueType::Present).ok_or("no supported present queue")?.clone(),
+      swapchain_image_count,
+      surface_format.color_space,
+      surface_format.format,
+      swapchain_resolution,
+      vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+      surface_caps.current_transform,
+      present_mode,
+      None,
+    )?;
 
-    let ash_device = Arc::new(AdAshDevice::new(
+    Self::with_present_target(ash_device, queues, Box::new(swapchain), color_output, config)
+  }
+
+  // Presents to offscreen images instead of a window, frames go through the same path otherwise
+  fn new_offscreen(config: RendererConfig, window: Arc<OffscreenWindow>) -> Result<Self, String> {
+    let ash_instance = Arc::new(AdAshInstance::new(config.validation)?);
+    let gpu = Self::select_gpu(&ash_instance, config.gpu)?;
+    let mut q_f_idxs = ash_instance.select_gpu_queue_families(gpu)?;
+    q_f_idxs.insert(GPUQueueType::Present, q_f_idxs[&GPUQueueType::Graphics]);
+    let shadow_mode = shadows::select_mode(&ash_instance, gpu, config.shadow_mode);
+    let (ash_device, queues) = Self::create_device(
       ash_instance,
       gpu,
-      device_extensions,
-      vk::PhysicalDeviceFeatures::default(),
-      queue_counts.clone(),
-    )?);
+      q_f_idxs,
+      config.buffer_device_address,
+      shadow_mode == ShadowMode::RayTraced,
+      false,
+    )?;
+    let swapchain = offscreen::OffscreenSwapchain::new(
+      window,
+      queues[&GPUQueueType::Graphics].clone(),
+      ash_device.create_allocator("offscreen_swapchain")?,
+      color::display_format(config.color_output),
+      config.frames_in_flight as usize,
+    )?;
+    let color_output = config.color_output;
+    Self::with_present_target(ash_device, queues, Box::new(swapchain), color_output, config)
+  }
 
-    let mut queues = HashMap::new();
-    for (q_type, q_f_idx) in q_f_idxs {
-      let queue_idx = queue_counts.entry(q_f_idx).or_default();
-      if *queue_idx > 0 {
-        *queue_idx -= 1
+  // Everything past picking the device and the swapchain, shared with the offscreen present
+  // path headless runs and tests use
+  fn with_present_target(
+    ash_device: Arc<AdAshDevice>,
+    queues: GPUQueues,
+    swapchain: Box<dyn PresentTarget>,
+    color_output: ColorOutput,
+    mut config: RendererConfig,
+  ) -> Result<Self, String> {
+    let custom_quality = config.quality_settings();
+    if let Some(settings) = config.quality.settings() {
+      config.set_quality_settings(&settings);
+    }
+    let gpu = ash_device.gpu();
+    let mut depth_format = vk::Format::UNDEFINED;
+    for format in DEPTH_FORMAT_PREFERENCE {
+      let format_props = unsafe {
+        ash_device.ash_instance().inner().get_physical_device_format_properties(gpu, format)
       };
-      queues.insert(q_type, Arc::new(AdQueue::new(ash_device.clone(), q_f_idx, *queue_idx)));
+      if format_props.optimal_tiling_features.contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT) {
+        depth_format = format;
+        break;
+      }
+    }
+    if depth_format == vk::Format::UNDEFINED {
+      return Err("preferred depth format not supported".to_string());
+    }
+    let engine_info = EngineInfo::new(ash_device.device_info());
+    for line in engine_info.report_lines() {
+      log!("{line}");
+    }
+    crash_report::set_field("gpu", engine_info.gpu.name.clone());
+    let crash_cleanup = Arc::new(GpuIdleOnCrash(ash_device.clone()));
+    crash_report::register_cleanup("gpu idle", Arc::downgrade(&crash_cleanup) as _);
+
+    let color_format = color::SCENE_COLOR_FORMAT;
+    let frames_in_flight = config.frames_in_flight as usize;
+    let samples = match config.anti_aliasing {
+      AntiAliasing::Msaa => Self::select_sample_count(&ash_device, config.msaa_samples),
+      AntiAliasing::Taa => vk::SampleCountFlags::TYPE_1,
+    };
+    if config.anti_aliasing == AntiAliasing::Msaa && samples.as_raw() != config.msaa_samples {
+      log!("msaa x{} not supported, using x{}", config.msaa_samples, samples.as_raw());
+    }
+
+    let setup_fence = AdFence::new(ash_device.clone(), vk::FenceCreateFlags::default())?;
+
+    // Frames and uploads are recorded on the thread making the manager, pools from here error if
+    // used on any other. Graphics comes first so a family shared with it submits to that queue
+    let cmd_pool_registry = AdCommandPoolRegistry::new(
+      &[GPUQueueType::Graphics, GPUQueueType::Compute, GPUQueueType::Transfer]
+        .iter()
+        .filter_map(|queue_type| queues.get(queue_type).cloned())
+        .collect::<Vec<_>>(),
+      vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+    );
+    let render_cmd_pool =
+      cmd_pool_registry.pool(queues[&GPUQueueType::Graphics].family_index())?;
+    let upload_cmd_pool =
+      cmd_pool_registry.pool(queues[&GPUQueueType::Transfer].family_index())?;
+
+    let render_cmd_buffers =
+      AdCommandBuffer::new(
+        render_cmd_pool.clone(),
+        vk::CommandBufferLevel::PRIMARY,
+        frames_in_flight as u32,
+      )?;
+
+    let record_job_count = jobs::global().thread_count().min(MAX_RECORD_JOBS);
+    let mut secondary_cmd_buffers =
+      (0..render_cmd_buffers.len()).map(|_| vec![]).collect::<Vec<_>>();
+    // Shared pools, a slot's buffers are recorded on whichever worker picks up its job
+    for _ in 0..record_job_count {
+      let secondary_cmd_pool = Arc::new(AdCommandPool::new(
+        queues[&GPUQueueType::Graphics].clone(),
+        vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+      )?);
+      let pool_cmd_buffers = AdCommandBuffer::new(
+        secondary_cmd_pool,
+        vk::CommandBufferLevel::SECONDARY,
+        render_cmd_buffers.len() as u32,
+      )?;
+      for (frame_buffers, cmd_buffer) in secondary_cmd_buffers.iter_mut().zip(pool_cmd_buffers) {
+        frame_buffers.push(cmd_buffer);
+      }
+    }
+
+    let frame_sync =
+      FrameSync::new(
+        ash_device.clone(),
+        frames_in_flight,
+        swapchain.get_image_count(),
+        config.validation,
+      )?;
+
+    let gen_allocator = ash_device.create_allocator("general")?;
+    let tri_mesh_allocator = ash_device.create_allocator("tri_mesh")?;
+    let flat_tex_allocator = ash_device.create_allocator("flat_texture")?;
+
+    let tri_mesh_gen = TriMeshGenerator::new(
+      tri_mesh_allocator,
+      upload_cmd_pool.clone(),
+      config.mesh_block_size_kb as u64 * 1024,
+    )?;
+
+    let flat_tex_gen =
+      FlatTextureGenerator::new(flat_tex_allocator, upload_cmd_pool.clone())?;
+    let bc_supported =
+      ash_device.ash_instance().supports_texture_compression_bc(ash_device.gpu());
+    let compress_textures = config.texture_compression != TextureCompression::Off;
+    if compress_textures && !bc_supported {
+      log!("gpu can't sample BC formats, leaving texture compression off");
     }
+    let texture_transcoder = (compress_textures && bc_supported)
+      .then(|| TextureTranscoder::new(config.texture_compression));
+
+    let particle_gen =
+      ParticleSystemGenerator::new(gen_allocator.clone(), upload_cmd_pool.clone())?;
+
+    let crowd_gen =
+      CrowdGenerator::new(gen_allocator.clone(), upload_cmd_pool.clone())?;
+
+    let skinned_mesh_gen =
+      SkinnedMeshGenerator::new(gen_allocator.clone(), upload_cmd_pool.clone())?;
+    let skinning_renderer = SkinningRenderer::new(ash_device.clone(), &skinned_mesh_gen)?;
+    let cloth_gen = ClothGenerator::new(gen_allocator.clone(), upload_cmd_pool.clone())?;
+    let cloth_renderer = ClothRenderer::new(ash_device.clone(), &cloth_gen)?;
+    // Graphics pool, it builds the cubemaps in compute passes
+    let environment_gen =
+      EnvironmentGenerator::new(gen_allocator.clone(), render_cmd_pool.clone())?;
+
+    let color_lut_gen = ColorLutGenerator::new(gen_allocator.clone(), upload_cmd_pool.clone())?;
+
+    let water_gen =
+      WaterPlaneGenerator::new(ash_device.clone(), gen_allocator.clone(), &flat_tex_gen)?;
+
+    let material_gen = MaterialGenerator::new(ash_device.clone(), gen_allocator.clone())?;
+    let foliage_gen = FoliageGenerator::new(
+      gen_allocator.clone(),
+      upload_cmd_pool.clone(),
+      &flat_tex_gen,
+      &material_gen,
+    )?;
+    let default_material = Arc::new(material_gen.create_material(
+      "default_material",
+      &MaterialCPU::default(),
+      flat_tex_gen.get_default_texture(),
+      None,
+    )?);
+
+    let reflection_probe_renderer = ReflectionProbeRenderer::new(
+      ash_device.clone(),
+      gen_allocator.clone(),
+      &render_cmd_buffers[0],
+      &environment_gen,
+      frames_in_flight,
+    )?;
+    let shadows = SceneShadows::new(
+      ash_device.clone(),
+      gen_allocator.clone(),
+      queues[&GPUQueueType::Graphics].clone(),
+      &render_cmd_buffers[0],
+      &tri_mesh_gen,
+      config.shadow_mode,
+      ShadowCascades {
+        count: config.shadow_cascades,
+        map_size: config.shadow_map_size,
+        distance: config.shadow_distance,
+      },
+      config.shadow_atlas_size,
+      depth_format,
+      Self::scene_resolution(swapchain.resolution(), &config),
+      &reflection_probe_renderer,
+      frames_in_flight,
+    )?;
+
+    let tri_mesh_renderer = TriMeshMaterialRenderer::new(
+      ash_device.clone(),
+      &tri_mesh_gen,
+      &material_gen,
+      shadows.dset_layout().clone(),
+      color_format,
+      depth_format,
+      samples,
+    )?;
+    let reflection_probes = ReflectionProbes::new(
+      reflection_probe_renderer,
+      &render_cmd_buffers[0],
+      gen_allocator.clone(),
+      &tri_mesh_renderer,
+    )?;
+
+    let editor_overlay_renderer = EditorOverlayRenderer::new(
+      ash_device.clone(),
+      &tri_mesh_gen,
+      color_format,
+      depth_format,
+      samples,
+    )?;
+
+    let debug_overlay_renderer = DebugOverlayRenderer::new(
+      ash_device.clone(),
+      gen_allocator.clone(),
+      color_format,
+      depth_format,
+      samples,
+      frames_in_flight,
+    )?;
+    // HUDs still draw their shapes without a font, only the text is left out
+    let ui_font = match config.view.ui_font.as_str() {
+      "" => SdfFontCPU::empty(),
+      path => SdfFontCPU::load(path).unwrap_or_else(|e| {
+        log!("at loading ui font, text won't be drawn: {e}");
+        SdfFontCPU::empty()
+      }),
+    };
+    let ui_layer = UiLayer::new(
+      ash_device.clone(),
+      gen_allocator.clone(),
+      upload_cmd_pool.clone(),
+      color_format,
+      depth_format,
+      frames_in_flight,
+      ui_font,
+    )?;
+    let frame_diff = FrameDiff::new(VulkanDevice::new(
+      ash_device.clone(),
+      gen_allocator.clone(),
+      upload_cmd_pool.queue().clone(),
+    )?);
+
+    let particle_renderer =
+      ParticleRenderer::new(ash_device.clone(), &particle_gen, color_format, depth_format, samples)?;
+
+    let crowd_renderer = CrowdRenderer::new(
+      ash_device.clone(),
+      &crowd_gen,
+      &material_gen,
+      shadows.dset_layout().clone(),
+      color_format,
+      depth_format,
+      samples,
+    )?;
+
+    let foliage_renderer = FoliageRenderer::new(
+      ash_device.clone(),
+      &foliage_gen,
+      &material_gen,
+      shadows.dset_layout().clone(),
+      color_format,
+      depth_format,
+      samples,
+    )?;
+
+    let water_renderer = WaterRenderer::new(
+      ash_device.clone(),
+      &water_gen,
+      flat_tex_gen.sampler().clone(),
+      flat_tex_gen.get_default_texture().image_view().clone(),
+      color_format,
+      depth_format,
+      samples,
+    )?;
+    let mut gizmo_meshes = HashMap::new();
+    for mode in [GizmoMode::Translate, GizmoMode::Rotate, GizmoMode::Scale] {
+      for axis in GizmoAxis::ALL {
+        let handle_mesh = tri_mesh_gen.upload_tri_mesh(
+          &format!("gizmo_{mode:?}_{axis:?}"),
+          &make_gizmo_handle_mesh(mode, axis),
+        )?;
+        gizmo_meshes.insert((mode, axis), Arc::new(handle_mesh));
+      }
+    }
+
+    let mut triangle_frame_buffers = tri_mesh_renderer.create_framebuffers(
+      &render_cmd_buffers[0],
+      gen_allocator.clone(),
+      Self::scene_resolution(swapchain.resolution(), &config),
+      frames_in_flight,
+      color::scene_color_usage(),
+    )?;
+    for (i, fb) in triangle_frame_buffers.iter_mut().enumerate() {
+      fb.attachments()[0]
+        .image()
+        .allocation()
+        .lock()
+        .map_err(|e| format!("at getting image mem lock: {e}"))?
+        .rename(&format!("triangle_color_image_{i}"))?;
+      fb.attachments()[1]
+        .image()
+        .allocation()
+        .lock()
+        .map_err(|e| format!("at getting image mem lock: {e}"))?
+        .rename(&format!("triangle_depth_image_{i}"))?;
+    }
+    let particle_depth_dsets = particle_renderer.create_depth_dsets(&triangle_frame_buffers)?;
+    let backend_device = VulkanDevice::new(
+      ash_device.clone(),
+      gen_allocator.clone(),
+      queues[&GPUQueueType::Graphics].clone(),
+    )?;
+    let srgb_encode_renderer = match color_output {
+      ColorOutput::SrgbTarget => None,
+      ColorOutput::ShaderEncode => Some(SrgbEncodeRenderer::new(&backend_device)?),
+    };
+    let mesh_culler = match config.gpu_culling {
+      true => {
+        let mut mesh_culler = MeshCullRenderer::new(
+          ash_device.clone(),
+          gen_allocator.clone(),
+          frames_in_flight,
+          samples,
+        )?;
+        mesh_culler.resize_pyramid(&render_cmd_buffers[0], &triangle_frame_buffers)?;
+        Some(mesh_culler)
+      }
+      false => None,
+    };
+
+    let mut post_process = Self::post_process_renderers(
+      &ash_device,
+      &gen_allocator,
+      &tri_mesh_gen,
+      depth_format,
+      samples,
+      &config,
+    )?;
+    let mut depth_of_field = Self::depth_of_field_renderer(&ash_device, samples, &config)?;
+    let transient_images = post_transient_images(
+      ash_device.clone(),
+      gen_allocator.clone(),
+      &triangle_frame_buffers,
+      post_process.as_ref().is_some_and(|post_process| post_process.has_motion_blur()),
+      depth_of_field.is_some(),
+    )?;
+    log!(
+      "post targets share {} MiB, {} MiB with memory of their own",
+      transient_images.aliased_size() >> 20,
+      transient_images.dedicated_size() >> 20
+    );
+    if let Some(post_process) = &mut post_process {
+      post_process.resize(&render_cmd_buffers[0], &triangle_frame_buffers, &transient_images)?;
+    }
+
+    let mut exposure = Exposure::new(
+      ExposureRenderer::new(ash_device.clone(), gen_allocator.clone(), &color_lut_gen)?,
+      &config.post_process,
+    );
+    exposure.resize(&triangle_frame_buffers)?;
+    let mut depth_readback = DepthReadback::new(
+      DepthReadbackRenderer::new(
+        ash_device.clone(),
+        gen_allocator.clone(),
+        samples,
+        frames_in_flight,
+      )?,
+      frames_in_flight,
+    );
+    depth_readback.resize(&triangle_frame_buffers)?;
+    let mut world_labels = WorldLabels::new(
+      DepthPointsRenderer::new(ash_device.clone(), gen_allocator.clone(), samples, frames_in_flight)?,
+      frames_in_flight,
+    );
+    world_labels.resize(&triangle_frame_buffers)?;
+    let frame_export = FrameExport::new(
+      ash_device.clone(),
+      gen_allocator.clone(),
+      color::display_format(color_output),
+      frames_in_flight,
+    );
+    if let Some(depth_of_field) = &mut depth_of_field {
+      depth_of_field.resize(&triangle_frame_buffers, &transient_images)?;
+    }
+    let mut film_effects = FilmEffects::new(
+      FilmEffectsRenderer::new(ash_device.clone())?,
+      &config.post_process,
+    );
+    film_effects.resize(&triangle_frame_buffers, &transient_images)?;
+
+    let gpu_timer = GpuFrameTimer::new(ash_device.clone(), render_cmd_buffers.len())?;
+    let quality_governor = (config.gpu_frame_budget_ms > 0.0).then(|| {
+      QualityGovernor::new(Duration::from_secs_f32(config.gpu_frame_budget_ms / 1000.0))
+    });
+
+    let camera = Camera3D {
+      pos: glam::vec4(2.0, 2.0, 2.0, 0.0),
+      look_dir: glam::vec4(-1.0, -1.0, -1.0, 0.0),
+      view_proj_mat: glam::Mat4::IDENTITY,
+    };
+
+    Ok(Self {
+      ash_device,
+      _crash_cleanup: crash_cleanup,
+      queues,
+      depth_format,
+      samples,
+      custom_quality,
+      pending_quality: None,
+      rebuild_targets: false,
+      engine_info,
+      swapchain,
+      setup_fence,
+      render_cmd_buffers,
+      secondary_cmd_buffers,
+      frame_sync,
+      gen_allocator,
+      triangle_frame_buffers,
+      camera,
+      camera_fov: CAMERA_FOV,
+      tri_meshes: HashMap::new(),
+      tri_mesh_registry: HandleRegistry::new(),
+      tri_mesh_gen,
+      skinned_meshes: HashMap::new(),
+      skinned_mesh_gen,
+      skinning_renderer,
+      cloths: HashMap::new(),
+      cloth_gen,
+      cloth_renderer,
+      last_cloth_sim: None,
+      environment_gen,
+      reflection_probes,
+      environment: None,
+      draw_list: DrawList::new(),
+      room_graph: RoomGraph::default(),
+      mesh_transforms: HashMap::new(),
+      joint_poses: HashMap::new(),
+      moved_meshes: HashSet::new(),
+      drawn_transforms: HashMap::new(),
+      drawn_poses: HashMap::new(),
+      draw_batches: vec![],
+      tri_mesh_renderer,
+      editor_overlay_renderer,
+      debug_overlay_renderer,
+      ui_layer,
+      window_scale_factor: 1.0,
+      memory_heatmap: None,
+      perf_hud: None,
+      debug_lines: vec![],
+      ui_shapes: vec![],
+      frame_capture: FrameCapture::new(),
+      particle_renderer,
+      particle_depth_dsets,
+      backend_device,
+      srgb_encode_renderer,
+      mesh_culler,
+      post_process,
+      exposure,
+      color_lut_gen,
+      color_lut_registry: HandleRegistry::new(),
+      color_grading: None,
+      sun: SunLight::default(),
+      depth_of_field,
+      film_effects,
+      depth_readback,
+      world_labels,
+      frame_export,
+      frame_diff,
+      requested_frame_export: None,
+      recorder: None,
+      benchmark: None,
+      benchmark_report: None,
+      resource_snapshot: None,
+      draw_calls: 0,
+      frame_allocations: None,
+      frame_scratch: FrameScratch::new(),
+      gpu_timer,
+      quality_governor,
+      cull_bounds_version: 0,
+      shadows,
+      particle_registry: HandleRegistry::new(),
+      particle_gen,
+      last_particle_sim: None,
+      crowd_renderer,
+      crowd_registry: HandleRegistry::new(),
+      crowd_gen,
+      water_renderer,
+      water_registry: HandleRegistry::new(),
+      water_gen,
+      foliage_renderer,
+      foliage_registry: HandleRegistry::new(),
+      foliage_gen,
+      wind: WindParams::default(),
+      lights: HashMap::new(),
+      light_culling: LightCulling::new(
+        config.light_cull_contribution,
+        config.max_shadowed_lights as usize,
+      ),
+      effect_clock: Instant::now(),
+      water_reflection_frame_buffers: vec![],
+      water_reflection_dsets: vec![],
+      editor_grid: None,
+      gizmo: None,
+      minimap: None,
+      gizmo_meshes,
+      flat_texes: HashMap::new(),
+      flat_tex_registry: HandleRegistry::new(),
+      flat_tex_gen,
+      texture_streamer: config
+        .texture_streaming
+        .then(|| TextureStreamer::new(config.texture_budget_mb as u64 * 1024 * 1024)),
+      texture_transcoder,
+      material_registry: HandleRegistry::new(),
+      material_gen,
+      default_material,
+      retired_resources: vec![],
+      frame_number: 0,
+      loading_progress: None,
+      input_received_at: None,
+      input_latency: InputLatencyTracker::new(),
+      resource_budget: ResourceBudget::new(config.resource_budget.clone()),
+      config,
+    })
+  }
+
+  // See RendererConfig::gpu for how gpus are picked
+  fn select_gpu(ash_instance: &AdAshInstance, gpu: u32) -> Result<vk::PhysicalDevice, String> {
+    if gpu > 0 {
+      let gpus = ash_instance.list_gpus()?;
+      return gpus
+        .get(gpu as usize - 1)
+        .cloned()
+        .ok_or(format!("no gpu {gpu}, only {} found", gpus.len()));
+    }
+    ash_instance.list_dedicated_gpus()?.first().cloned().map_or_else(
+      || ash_instance.list_gpus()?.first().cloned().ok_or("no supported gpus".to_string()),
+      Ok,
+    )
+  }
+
+  fn create_device(
+    ash_instance: Arc<AdAshInstance>,
+    gpu: vk::PhysicalDevice,
+    q_f_idxs: HashMap<GPUQueueType, u32>,
+    buffer_device_address: bool,
+    // Only after shadows::select_mode checked the gpu supports it
+    ray_tracing: bool,
+    // Only when the gpu supports it
+    present_wait: bool,
+  ) -> Result<(Arc<AdAshDevice>, GPUQueues), String> {
+    // Acceleration structures are built from buffer addresses
+    let buffer_device_address = match buffer_device_address || ray_tracing {
+      true if !ash_instance.supports_buffer_device_address(gpu) => {
+        log!("buffer device addresses not supported, leaving them off");
+        false
+      }
+      requested => requested,
+    };
+    let qf_info = ash_instance.get_queue_family_props(gpu);
+    let mut queue_counts = HashMap::new();
+    for (_, qf_idx) in q_f_idxs.iter() {
+      let val_ptr = queue_counts.entry(*qf_idx).or_insert(0);
+      if qf_info[*qf_idx as usize].queue_count > *val_ptr {
+        *val_ptr += 1
+      };
+    }
+
+    let mut device_extensions = vec![
+      khr::swapchain::NAME.as_ptr(),
+      #[cfg(target_os = "macos")]
+      khr::portability_subset::NAME.as_ptr(),
+    ];
+    #[cfg(feature = "ray-tracing")]
+    if ray_tracing {
+      device_extensions.extend(ash_ad_wrappers::ash_rt_wrappers::AdRayTracingDevice::required_extensions());
+    }
+    if present_wait {
+      device_extensions.extend([khr::present_id::NAME.as_ptr(), khr::present_wait::NAME.as_ptr()]);
+    }
+    // Frame export shares images when the device has it, and copies them to the cpu otherwise
+    if let Some((extension, _)) =
+      external_memory_extension().filter(|_| ash_instance.supports_external_memory(gpu))
+    {
+      device_extensions.push(extension.as_ptr());
+    }
+    // Renderers push their short lived bindings instead of allocating sets for them when it's there
+    if ash_instance.supports_push_descriptor(gpu) {
+      device_extensions.push(khr::push_descriptor::NAME.as_ptr());
+    }
+
+    // Reflection probes are sampled as one cube array
+    if !ash_instance.supports_image_cube_array(gpu) {
+      return Err("gpu can't sample cube arrays".to_string());
+    }
+    // On wherever the gpu has them, so sparse buffers and images can be made on the device. BC
+    // formats too, for texture compression, and anisotropic filtering for textures asking for it
+    let features = ash_instance.sparse_support(gpu).enable_features(
+      vk::PhysicalDeviceFeatures::default()
+        .image_cube_array(true)
+        .texture_compression_bc(ash_instance.supports_texture_compression_bc(gpu))
+        .sampler_anisotropy(ash_instance.supports_sampler_anisotropy(gpu)),
+    );
+    // Same for multiview, render passes can then draw several views at once
+    let multiview_view_count = ash_instance.multiview_view_count(gpu);
+    let ash_device = Arc::new(AdAshDevice::new(
+      ash_instance,
+      gpu,
+      device_extensions,
+      features,
+      buffer_device_address,
+      ray_tracing,
+      present_wait,
+      multiview_view_count,
+      queue_counts.clone(),
+    )?);
+
+    let mut queues = HashMap::new();
+    for (q_type, q_f_idx) in q_f_idxs {
+      let queue_idx = queue_counts.entry(q_f_idx).or_default();
+      if *queue_idx > 0 {
+        *queue_idx -= 1
+      };
+      queues.insert(q_type, Arc::new(AdQueue::new(ash_device.clone(), q_f_idx, *queue_idx)));
+    }
+
+    Ok((ash_device, queues))
+  }
+
+  // Anti-aliasing and motion blur as the config has them, None with neither. Sized by the
+  // caller along with the targets
+  fn post_process_renderers(
+    ash_device: &Arc<AdAshDevice>,
+    gen_allocator: &Arc<Mutex<Allocator>>,
+    tri_mesh_gen: &TriMeshGenerator,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+    config: &RendererConfig,
+  ) -> Result<Option<PostProcess>, String> {
+    let taa = match config.anti_aliasing {
+      AntiAliasing::Msaa => None,
+      AntiAliasing::Taa => {
+        Some(TemporalAa::new(TaaRenderer::new(ash_device.clone(), gen_allocator.clone())?))
+      }
+    };
+    // The velocity it blurs along is drawn over a single sampled depth
+    let single_sampled = samples == vk::SampleCountFlags::TYPE_1;
+    if config.post_process.motion_blur && !single_sampled {
+      log!("motion blur needs msaa off or taa, leaving it off");
+    }
+    let motion_blur = match config.post_process.motion_blur && single_sampled {
+      true => Some((
+        MotionBlurRenderer::new(ash_device.clone())?,
+        config.post_process.motion_blur_intensity,
+      )),
+      false => None,
+    };
+    if taa.is_none() && motion_blur.is_none() {
+      return Ok(None);
+    }
+    let velocity_renderer =
+      VelocityRenderer::new(ash_device.clone(), gen_allocator.clone(), tri_mesh_gen, depth_format)?;
+    Ok(Some(PostProcess::new(velocity_renderer, taa, motion_blur)))
+  }
+
+  fn depth_of_field_renderer(
+    ash_device: &Arc<AdAshDevice>,
+    samples: vk::SampleCountFlags,
+    config: &RendererConfig,
+  ) -> Result<Option<DepthOfField>, String> {
+    match config.post_process.depth_of_field {
+      true => Ok(Some(DepthOfField::new(
+        DepthOfFieldRenderer::new(ash_device.clone(), samples)?,
+        &config.post_process,
+      ))),
+      false => Ok(None),
+    }
+  }
+
+  fn select_sample_count(ash_device: &AdAshDevice, requested: u32) -> vk::SampleCountFlags {
+    let limits = unsafe {
+      ash_device.ash_instance().inner().get_physical_device_properties(ash_device.gpu()).limits
+    };
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+    [64, 32, 16, 8, 4, 2]
+      .into_iter()
+      .map(vk::SampleCountFlags::from_raw)
+      .find(|samples| samples.as_raw() <= requested && supported.contains(*samples))
+      .unwrap_or(vk::SampleCountFlags::TYPE_1)
+  }
+
+  fn scaled_resolution(resolution: vk::Extent2D, render_scale: f32) -> vk::Extent2D {
+    vk::Extent2D {
+      width: ((resolution.width as f32 * render_scale) as u32).max(1),
+      height: ((resolution.height as f32 * render_scale) as u32).max(1),
+    }
+  }
+
+  // Of the part of the swapchain the scene is drawn in, see scene_viewport
+  fn scene_resolution(swapchain_res: vk::Extent2D, config: &RendererConfig) -> vk::Extent2D {
+    let viewport = view_policy::scene_viewport(&config.view, swapchain_res);
+    Self::scaled_resolution(viewport.extent, config.render_scale)
+  }
+
+  // What the scene is projected with for the camera's fov, see FovAxis
+  fn projection_fov(&self) -> f32 {
+    let scene_res = self.triangle_frame_buffers[0].resolution();
+    let aspect_ratio = scene_res.width as f32 / scene_res.height.max(1) as f32;
+    view_policy::projection_fov(&self.config.view, self.camera_fov, aspect_ratio)
+  }
+
+  pub fn add_tri_mesh(
+    &mut self,
+    name: String,
+    mesh: &TriMeshCPU,
+    handle: MeshHandle,
+  ) -> Result<(), String> {
+    profile_scope!("add_tri_mesh");
+    let s_time = std::time::Instant::now();
+    let tri_mesh_gpu = match self.tri_meshes.get(&name).and_then(|x| x.upgrade()) {
+      Some(existing) => existing,
+      None => {
+        let uploaded = Arc::new(self.tri_mesh_gen.upload_tri_mesh(&name, mesh)?);
+        self.tri_meshes.insert(name.clone(), Arc::downgrade(&uploaded));
+        uploaded
+      }
+    };
+    self.shadows.add_mesh(&name, mesh, handle, true)?;
+    println!("mesh {} upload time: {}ms", &name, s_time.elapsed().as_millis());
+    self.tri_mesh_registry.insert(handle, tri_mesh_gpu);
+    self.draw_list.mark_dirty();
+    Ok(())
+  }
+
+  // Never shared by name like plain meshes, every mesh has its own weights
+  pub fn add_morph_tri_mesh(
+    &mut self,
+    name: String,
+    mesh: &TriMeshCPU,
+    morph_targets: &[MorphTargetCPU],
+    handle: MeshHandle,
+  ) -> Result<(), String> {
+    profile_scope!("add_morph_tri_mesh");
+    let tri_mesh_gpu = self.tri_mesh_gen.upload_morph_tri_mesh(&name, mesh, morph_targets)?;
+    self.shadows.add_mesh(&name, mesh, handle, false)?;
+    self.tri_mesh_registry.insert(handle, Arc::new(tri_mesh_gpu));
+    self.draw_list.mark_dirty();
+    Ok(())
+  }
+
+  // Not shared by name either, every mesh has its own pose
+  pub fn add_skinned_mesh(
+    &mut self,
+    name: String,
+    mesh: &SkinnedMeshCPU,
+    handle: MeshHandle,
+  ) -> Result<(), String> {
+    profile_scope!("add_skinned_mesh");
+    let skinned_mesh =
+      self.skinned_mesh_gen.create_skinned_mesh(&name, mesh, &self.tri_mesh_gen)?;
+    self.shadows.add_mesh(&name, &mesh.bind_pose(), handle, false)?;
+    self.tri_mesh_registry.insert(handle, skinned_mesh.mesh().clone());
+    self.skinned_meshes.insert(handle, Arc::new(skinned_mesh));
+    self.draw_list.mark_dirty();
+    Ok(())
+  }
+
+  // Each cloth is its own mesh, stepped on the gpu when the config asks for it
+  pub fn add_cloth(
+    &mut self,
+    name: String,
+    cloth: &ClothCPU,
+    handle: MeshHandle,
+  ) -> Result<(), String> {
+    profile_scope!("add_cloth");
+    let cloth_gpu =
+      self.cloth_gen.create_cloth(&name, cloth, &self.tri_mesh_gen, self.config.gpu_cloth)?;
+    self.shadows.add_mesh(&name, &cloth.rest_mesh(), handle, false)?;
+    self.tri_mesh_registry.insert(handle, cloth_gpu.mesh().clone());
+    self.cloths.insert(handle, Arc::new(cloth_gpu));
+    self.draw_list.mark_dirty();
+    Ok(())
+  }
+
+  fn apply_frame_export(&mut self) -> Result<(), String> {
+    self.frame_sync.wait_all()?;
+    self.frame_export.set_mode(match self.recorder.is_some() || self.frame_diff.is_capturing() {
+      true => Some(FrameExportMode::CpuCopy),
+      false => self.requested_frame_export,
+    });
+    Ok(())
+  }
+
+  fn stop_recording(&mut self) {
+    let Some(recorder) = self.recorder.take() else { return };
+    let _ = recorder
+      .finish()
+      .inspect(|written| log!("recording wrote {written} frames"))
+      .inspect_err(|e| log!("at finishing recording: {e}"));
+    let _ = self.apply_frame_export().inspect_err(|e| log!("at stopping recording: {e}"));
+  }
+
+  // Applies messages in order, errors are reported and skipped. True once Stop is seen, later
+  // messages still apply
+  pub fn process_messages(&mut self, messages: Vec<RendererMessage>) -> bool {
+    let mut stop = false;
+    // Texture files are decoded on the job pool up front, uploads still run in message order
+    let tex_loads = messages
+      .iter()
+      .filter_map(|message| match message {
+        RendererMessage::UploadFlatTex(name, flat_tex_path, color_space, format, _)
+          if !self.has_flat_texture(name) =>
+        {
+          let requested = (flat_tex_path.clone(), *color_space, *format);
+          Some((requested, Self::texture_load(flat_tex_path, *color_space, *format)))
+        }
+        _ => None,
+      })
+      .collect::<HashMap<_, _>>();
+    let decoded_texes =
+      Self::decode_flat_textures(tex_loads.values().cloned().collect::<HashSet<_>>());
+    for message in messages {
+      match message {
+        RendererMessage::UploadTriMesh(name, tri_mesh_cpu, handle) => {
+          let _ = self
+            .add_tri_mesh(name, &tri_mesh_cpu, handle)
+            .inspect_err(|e| log!("error adding mesh: {e}"));
+        }
+        RendererMessage::UploadMorphTriMesh(name, tri_mesh_cpu, morph_targets, handle) => {
+          let _ = self
+            .add_morph_tri_mesh(name, &tri_mesh_cpu, &morph_targets, handle)
+            .inspect_err(|e| log!("error adding morph mesh: {e}"));
+        }
+        RendererMessage::UploadSkinnedMesh(name, skinned_mesh_cpu, handle) => {
+          let _ = self
+            .add_skinned_mesh(name, &skinned_mesh_cpu, handle)
+            .inspect_err(|e| log!("error adding skinned mesh: {e}"));
+        }
+        RendererMessage::UploadCloth(name, cloth_cpu, handle) => {
+          let _ = self
+            .add_cloth(name, &cloth_cpu, handle)
+            .inspect_err(|e| log!("error adding cloth: {e}"));
+        }
+        RendererMessage::UploadFlatTex(name, flat_tex_path, color_space, format, handle) => {
+          let tex_load = match tex_loads.get(&(flat_tex_path.clone(), color_space, format)) {
+            Some(tex_load) => tex_load.clone(),
+            None => Self::texture_load(&flat_tex_path, color_space, format),
+          };
+          let decoded_tex = decoded_texes.get(&tex_load);
+          let _ = self
+            .add_flat_texture(name, tex_load, decoded_tex, handle)
+            .inspect_err(|e| log!("error adding texture: {e}"));
+        }
+        RendererMessage::DestroyTriMesh(handle) => {
+          let _ = self
+            .destroy_tri_mesh(handle)
+            .inspect_err(|e| log!("error destroying mesh: {e}"));
+        }
+        RendererMessage::AddRenderable(mesh, material) => {
+          self.add_renderable(mesh, material);
+        }
+        RendererMessage::RemoveRenderable(mesh) => {
+          let _ = self
+            .remove_renderable(mesh)
+            .inspect_err(|e| log!("error removing renderable: {e}"));
+        }
+        RendererMessage::SetRenderableVisible(mesh, visible) => {
+          let _ = self
+            .set_renderable_visible(mesh, visible)
+            .inspect_err(|e| log!("error setting renderable visibility: {e}"));
+        }
+        RendererMessage::SetRenderableLayers(mesh, layers) => {
+          let _ = self
+            .set_renderable_layers(mesh, layers)
+            .inspect_err(|e| log!("error setting renderable layers: {e}"));
+        }
+        RendererMessage::DestroyFlatTex(handle) => {
+          let _ = self
+            .destroy_flat_texture(handle)
+            .inspect_err(|e| log!("error destroying texture: {e}"));
+        }
+        RendererMessage::CreateMaterial(name, material, texture, handle) => {
+          let _ = self
+            .add_material(&name, &material, texture, None, handle)
+            .inspect_err(|e| log!("error adding material: {e}"));
+        }
+        RendererMessage::CreateLightmappedMaterial(name, material, texture, lightmap, handle) => {
+          let _ = self
+            .add_material(&name, &material, texture, Some(lightmap), handle)
+            .inspect_err(|e| log!("error adding material: {e}"));
+        }
+        RendererMessage::UpdateMaterialParams(handle, params) => {
+          let _ = self
+            .update_material_params(handle, &params)
+            .inspect_err(|e| log!("error updating material: {e}"));
+        }
+        RendererMessage::DestroyMaterial(handle) => {
+          let _ = self
+            .destroy_material(handle)
+            .inspect_err(|e| log!("error destroying material: {e}"));
+        }
+        RendererMessage::Stop => {
+          stop = true;
+        }
+        RendererMessage::SetEditorGrid(grid) => {
+          self.editor_grid = grid;
+        }
+        RendererMessage::SetGizmo(gizmo) => {
+          self.gizmo = gizmo;
+        }
+        RendererMessage::SetMinimap(settings) => {
+          self.set_minimap(settings);
+        }
+        RendererMessage::SetMemoryHeatmap(enabled) => {
+          self.memory_heatmap = enabled.then(MemoryHeatmap::new);
+        }
+        RendererMessage::SetPerfHud(enabled) => {
+          self.perf_hud = enabled.then(PerfHud::new);
+        }
+        RendererMessage::SetPhysicsStats(stats) => {
+          if let Some(perf_hud) = &mut self.perf_hud {
+            perf_hud.set_physics_stats(stats);
+          }
+        }
+        RendererMessage::SetDebugLines(lines) => self.debug_lines = lines,
+        RendererMessage::SetUiShapes(shapes) => self.ui_shapes = shapes,
+        RendererMessage::SetWorldLabels(labels) => self.world_labels.set(labels),
+        RendererMessage::SetExposure(ev) => self.exposure.set_override(ev),
+        RendererMessage::SetFilmEffects(effects) => self.film_effects.set_override(effects),
+        RendererMessage::LoadColorLut(path, handle) => {
+          let _ = self
+            .load_color_lut(&path, handle)
+            .inspect_err(|e| log!("error loading color lut: {e}"));
+        }
+        RendererMessage::DestroyColorLut(handle) => {
+          let _ = self
+            .destroy_color_lut(handle)
+            .inspect_err(|e| log!("error destroying color lut: {e}"));
+        }
+        RendererMessage::QueryDepth(query) => self.depth_readback.query(query),
+        RendererMessage::SetFrameExport(mode) => {
+          self.requested_frame_export = mode;
+          let _ = self.apply_frame_export().inspect_err(|e| log!("at setting frame export: {e}"));
+        }
+        RendererMessage::StartRecording(settings) => {
+          self.stop_recording();
+          self.recorder = Some(Recorder::new(settings));
+          let _ = self.apply_frame_export().inspect_err(|e| log!("at starting recording: {e}"));
+        }
+        RendererMessage::StopRecording => self.stop_recording(),
+        RendererMessage::FrameDiff(command) => {
+          let was_capturing = self.frame_diff.is_capturing();
+          if let Some(heatmap) = self.frame_diff.command(command, self.frame_number) {
+            self.retired_resources.push((self.frame_number, Arc::new(heatmap)));
+          }
+          if self.frame_diff.is_capturing() != was_capturing {
+            let _ = self.apply_frame_export().inspect_err(|e| log!("at setting frame export: {e}"));
+          }
+        }
+        RendererMessage::StartBenchmark => self.benchmark = Some(BenchmarkCapture::new()),
+        RendererMessage::StopBenchmark => match self.benchmark.take() {
+          Some(benchmark) => self.benchmark_report = Some(benchmark.finish()),
+          None => log!("no benchmark running to stop"),
+        },
+        RendererMessage::SnapshotResources => {
+          self.resource_snapshot = self
+            .snapshot_resources()
+            .inspect_err(|e| log!("at taking resource snapshot: {e}"))
+            .ok();
+        }
+        RendererMessage::AddQualityCallback(callback) => match &mut self.quality_governor {
+          Some(quality_governor) => quality_governor.add_callback(callback),
+          None => log!("no gpu frame budget configured, quality callback left unused"),
+        },
+        RendererMessage::SetQuality(preset) => self.pending_quality = Some(preset),
+        RendererMessage::TriggerCapture(frames) => {
+          let _ = self
+            .frame_capture
+            .trigger(frames)
+            .inspect_err(|e| log!("at triggering frame capture: {e}"));
+        }
+        RendererMessage::ReloadMaterialShader(variant, vertex_streams, path) => {
+          let _ = self
+            .apply_material_shader(variant, vertex_streams, path.as_deref())
+            .inspect_err(|e| log!("{e}"));
+        }
+        RendererMessage::CompactMeshBuffers => {
+          let _ = self
+            .compact_mesh_buffers()
+            .inspect_err(|e| log!("at compacting mesh buffers: {e}"));
+        }
+        RendererMessage::CreateParticleSystem(name, emitter, handle) => {
+          let _ = self
+            .add_particle_system(&name, &emitter, handle)
+            .inspect_err(|e| log!("error adding particle system: {e}"));
+        }
+        RendererMessage::SetParticleEmitter(handle, emitter) => {
+          let _ = self
+            .set_particle_emitter(handle, &emitter)
+            .inspect_err(|e| log!("error updating particle emitter: {e}"));
+        }
+        RendererMessage::EmitParticles(handle, count, seed) => {
+          let _ = self
+            .emit_particles(handle, count, seed)
+            .inspect_err(|e| log!("error emitting particles: {e}"));
+        }
+        RendererMessage::DestroyParticleSystem(handle) => {
+          let _ = self
+            .destroy_particle_system(handle)
+            .inspect_err(|e| log!("error destroying particle system: {e}"));
+        }
+        RendererMessage::CreateCrowd(name, mesh, animation, max_instances, material, handle) => {
+          let _ = self
+            .add_crowd(&name, &mesh, &animation, max_instances, material, handle)
+            .inspect_err(|e| log!("error adding crowd: {e}"));
+        }
+        RendererMessage::DestroyCrowd(handle) => {
+          let _ = self
+            .destroy_crowd(handle)
+            .inspect_err(|e| log!("error destroying crowd: {e}"));
+        }
+        RendererMessage::UploadWaterPlane(name, plane, normal_map, handle) => {
+          let _ = self
+            .add_water_plane(&name, &plane, normal_map, handle)
+            .inspect_err(|e| log!("error adding water plane: {e}"));
+        }
+        RendererMessage::DestroyWaterPlane(handle) => {
+          let _ = self
+            .destroy_water_plane(handle)
+            .inspect_err(|e| log!("error destroying water plane: {e}"));
+        }
+        RendererMessage::CreateFoliage(name, foliage, material, handle) => {
+          let _ = self
+            .add_foliage(&name, &foliage, material, handle)
+            .inspect_err(|e| log!("error adding foliage: {e}"));
+        }
+        RendererMessage::DestroyFoliage(handle) => {
+          let _ = self
+            .destroy_foliage(handle)
+            .inspect_err(|e| log!("error destroying foliage: {e}"));
+        }
+        RendererMessage::SetWind(wind) => {
+          self.wind = wind;
+        }
+        RendererMessage::SetLight(handle, light) => {
+          self.lights.insert(handle, light);
+        }
+        RendererMessage::RemoveLight(handle) => {
+          self.lights.remove(&handle);
+        }
+        RendererMessage::SetEnvironment(path) => {
+          let _ = self
+            .set_environment(path.as_deref())
+            .inspect_err(|e| log!("error setting environment: {e}"));
+        }
+        RendererMessage::SetRooms(room_graph) => {
+          self.room_graph = room_graph;
+        }
+        RendererMessage::SetReflectionProbes(probes) => {
+          let _ = self
+            .reflection_probes
+            .set_probes(&probes)
+            .inspect_err(|e| log!("error setting reflection probes: {e}"));
+        }
+        // Taken out by the message backlog on the render thread
+        RendererMessage::BeginLoading | RendererMessage::EndLoading => {}
+      }
+    }
+    stop
+  }
+
+  pub fn has_flat_texture(&self, name: &str) -> bool {
+    self.flat_texes.get(name).is_some_and(|x| x.strong_count() > 0)
+  }
+
+  // How a texture asked for with color_space and format loads once the import settings in its
+  // .meta file are applied. A meta file that doesn't parse is reported and the defaults used
+  fn texture_load(
+    path: &str,
+    color_space: TextureColorSpace,
+    format: Option<TextureFormat>,
+  ) -> TextureLoad {
+    let settings = asset_meta::load::<TextureImportSettings>(vfs::global(), path)
+      .inspect_err(|e| log!("{e}, loading {path} with default settings"))
+      .unwrap_or_default();
+    (path.to_string(), settings.color_space(color_space), format, settings)
+  }
+
+  // Failed decodes are left out, add_flat_texture retries them and reports the error
+  pub fn decode_flat_textures(
+    tex_loads: HashSet<TextureLoad>,
+  ) -> HashMap<TextureLoad, DecodedFlatTexture> {
+    if tex_loads.is_empty() {
+      return HashMap::new();
+    }
+    profile_scope!("decode_textures");
+    let tex_loads = tex_loads.into_iter().collect::<Vec<_>>();
+    let decode = |(path, color_space, format, _): &TextureLoad| {
+      DecodedFlatTexture::load(path, *color_space, *format)
+    };
+    match jobs::global().map(&tex_loads, decode) {
+      Ok(decoded) => tex_loads
+        .into_iter()
+        .zip(decoded)
+        .filter_map(|(tex_load, decoded)| Some((tex_load, decoded.ok()?)))
+        .collect(),
+      Err(e) => {
+        log!("at decoding textures: {e}");
+        HashMap::new()
+      }
+    }
+  }
+
+  pub fn add_flat_texture(
+    &mut self,
+    name: String,
+    (tex_path, color_space, format, settings): TextureLoad,
+    decoded_tex: Option<&DecodedFlatTexture>,
+    handle: TextureHandle,
+  ) -> Result<(), String> {
+    let s_time = std::time::Instant::now();
+    let flat_tex_gpu = match self.flat_texes.get(&name).and_then(|x| x.upgrade()) {
+      Some(existing) => {
+        if let Some(texture_transcoder) = &mut self.texture_transcoder {
+          texture_transcoder.share(&name, handle);
+        }
+        existing
+      }
+      None => {
+        let loaded;
+        let decoded_tex = match decoded_tex {
+          Some(decoded_tex) => decoded_tex,
+          None => {
+            loaded = DecodedFlatTexture::load(&tex_path, color_space, format)?;
+            &loaded
+          }
+        };
+        // Textures whose import settings pick their mips aren't streamed
+        let streamer = self.texture_streamer.as_mut().filter(|_| {
+          settings.mips.is_none() && texture_streaming::is_streamed(decoded_tex.resolution)
+        });
+        match streamer {
+          // Not shared by name, each handle streams its own mips
+          Some(streamer) => {
+            let first_mip =
+              streamer.add(handle, &name, (tex_path, color_space, format, settings), decoded_tex);
+            Arc::new(self.flat_tex_gen.upload_flat_texture_mips(
+              &name,
+              decoded_tex.format,
+              color_space,
+              settings.sampler(),
+              &decoded_tex.mips(first_mip),
+            )?)
+          }
+          None => {
+            let uploaded =
+              self.flat_tex_gen.upload_deduplicated(&name, decoded_tex, color_space, settings)?;
+            self.flat_texes.insert(name.clone(), Arc::downgrade(&uploaded));
+            // Drawn as uploaded until the compressed one is swapped in
+            if let Some(texture_transcoder) = &mut self.texture_transcoder {
+              texture_transcoder.add(handle, &name, color_space, settings, decoded_tex);
+            }
+            uploaded
+          }
+        }
+      }
+    };
+    println!("tex {} upload time: {}ms", &name, s_time.elapsed().as_millis());
+    self.flat_tex_registry.insert(handle, flat_tex_gpu);
+    Ok(())
+  }
+
+  pub fn update_tri_mesh_transform(
+    &mut self,
+    handle: MeshHandle,
+    transform: TriMeshTransform,
+  ) -> Result<(), String> {
+    self.tri_mesh_registry.get(handle)?.update_transform(transform)?;
+    self.mesh_transforms.insert(handle, transform.transform);
+    self.moved_meshes.insert(handle);
+    self.cull_bounds_version += 1;
+    Ok(())
+  }
+
+  pub fn update_morph_weights(
+    &mut self,
+    handle: MeshHandle,
+    weights: MorphWeights,
+  ) -> Result<(), String> {
+    self.tri_mesh_registry.get(handle)?.update_morph_weights(weights)
+  }
+
+  pub fn update_joint_matrices(
+    &mut self,
+    handle: MeshHandle,
+    matrices: &[glam::Mat4],
+  ) -> Result<(), String> {
+    self
+      .skinned_meshes
+      .get(&handle)
+      .ok_or(format!("mesh {handle:?} is not skinned"))?
+      .update_joints(matrices)?;
+    self.joint_poses.insert(handle, matrices.to_vec());
+    self.moved_meshes.insert(handle);
+    Ok(())
+  }
+
+  pub fn destroy_tri_mesh(&mut self, handle: MeshHandle) -> Result<(), String> {
+    let tri_mesh_gpu = self.tri_mesh_registry.remove(handle)?;
+    self.retired_resources.push((self.frame_number, tri_mesh_gpu));
+    self.draw_list.remove(handle);
+    self.mesh_transforms.remove(&handle);
+    self.joint_poses.remove(&handle);
+    self.moved_meshes.remove(&handle);
+    self.drawn_transforms.remove(&handle);
+    self.drawn_poses.remove(&handle);
+    if let Some(skinned_mesh) = self.skinned_meshes.remove(&handle) {
+      self.retired_resources.push((self.frame_number, skinned_mesh));
+    }
+    if let Some(cloth) = self.cloths.remove(&handle) {
+      self.retired_resources.push((self.frame_number, cloth));
+    }
+    if let Some(blas) = self.shadows.remove_mesh(handle) {
+      self.retired_resources.push((self.frame_number, blas));
+    }
+    Ok(())
+  }
+
+  fn apply_material_shader(
+    &mut self,
+    variant: ShaderVariant,
+    vertex_streams: bool,
+    path: Option<&Path>,
+  ) -> Result<(), String> {
+    let code = match path {
+      Some(path) => {
+        if shader_stage_from_path(path) != Some(vk::ShaderStageFlags::FRAGMENT) {
+          return Err(format!("at reloading {variant:?} shader: {path:?} isn't a fragment shader"));
+        }
+        // The compiler's output is in the error
+        let words = compile_shader_file(path).map_err(|e| {
+          format!("at reloading {variant:?} shader, the last one that built stays: {e}")
+        })?;
+        Some(words.iter().flat_map(|word| word.to_le_bytes()).collect())
+      }
+      None => None,
+    };
+    // The pipelines replaced may be in use by frames in flight
+    self.frame_sync.wait_all()?;
+    let rebuilt = self.tri_mesh_renderer.reload_frag_shader(variant, vertex_streams, code)?;
+    log!("{variant:?} shader reloaded, {rebuilt} pipelines rebuilt");
+    Ok(())
+  }
+
+  pub fn snapshot_resources(&mut self) -> Result<ResourceSnapshot, String> {
+    self.frame_sync.wait_all()?;
+    // Nothing in flight refers to them now, they'd only count as leaked
+    self.retired_resources.clear();
+    let diagnostics = self.ash_device.memory_diagnostics()?;
+    let live_objects = self.ash_device.live_objects();
+    let usage = ResourceUsage {
+      gpu_memory_bytes: diagnostics.used_bytes(),
+      descriptor_sets: live_objects.count(AdLiveObjectKind::DescriptorSet),
+      command_buffers: live_objects.count(AdLiveObjectKind::CommandBuffer),
+      framebuffers: live_objects.count(AdLiveObjectKind::Framebuffer),
+    };
+    let registries = [
+      ("mesh", self.tri_mesh_registry.live_count()),
+      ("texture", self.flat_tex_registry.live_count()),
+      ("material", self.material_registry.live_count()),
+      ("particle system", self.particle_registry.live_count()),
+      ("crowd", self.crowd_registry.live_count()),
+      ("water plane", self.water_registry.live_count()),
+      ("foliage", self.foliage_registry.live_count()),
+      ("color lut", self.color_lut_registry.live_count()),
+      ("light", self.lights.len()),
+    ];
+    Ok(ResourceSnapshot {
+      usage,
+      allocations: diagnostics.usage_by_name(),
+      registries: registries.into_iter().map(|(name, count)| (name, count as u64)).collect(),
+    })
+  }
+
+  // Destroyed meshes only leave holes in the mesh arena, this moves the live ones together
+  pub fn compact_mesh_buffers(&mut self) -> Result<MeshArenaStats, String> {
+    profile_scope!("compact_mesh_buffers");
+    self.frame_sync.wait_all()?;
+    // Nothing in flight refers to them now, their ranges are freed before packing
+    self.retired_resources.clear();
+    let before = self.tri_mesh_gen.mesh_arena_stats()?;
+    let after = self.tri_mesh_gen.compact_mesh_arena()?;
+    if after.compactions > before.compactions {
+      println!(
+        "mesh buffers compacted, {} bytes moved and {} bytes given back",
+        after.moved_bytes - before.moved_bytes,
+        after.reclaimed_bytes - before.reclaimed_bytes
+      );
+    }
+    Ok(after)
+  }
+
+  // Frame timings with the mesh arena and resource usage
+  pub fn frame_stats(&self) -> FrameStats {
+    FrameStats {
+      mesh_arena: self.tri_mesh_gen.mesh_arena_stats().unwrap_or_default(),
+      resources: self.resource_budget.usage(),
+      peak_resources: self.resource_budget.peak(),
+      draw_calls: self.draw_calls,
+      heap_allocations: self.frame_allocations,
+      ..self.input_latency.stats()
+    }
+  }
+
+  pub fn destroy_flat_texture(&mut self, handle: TextureHandle) -> Result<(), String> {
+    let flat_tex_gpu = self.flat_tex_registry.remove(handle)?;
+    self.retired_resources.push((self.frame_number, flat_tex_gpu));
+    if let Some(texture_streamer) = &mut self.texture_streamer {
+      texture_streamer.remove(handle);
+    }
+    if let Some(texture_transcoder) = &mut self.texture_transcoder {
+      texture_transcoder.remove(handle);
+    }
+    Ok(())
+  }
+
+  // Screen sizes of what each material is drawn on pick the mips streamed textures want. Loads
+  // that finished are uploaded, the materials using them are pointed at the new upload and the
+  // old one retired
+  fn stream_textures(&mut self) -> Result<(), String> {
+    let projection_fov = self.projection_fov();
+    let Some(texture_streamer) = &mut self.texture_streamer else {
+      return Ok(());
+    };
+    profile_scope!("stream_textures");
+    for (handle, first_mip, mips) in texture_streamer.take_loaded() {
+      let (Some(name), Some((color_space, settings))) =
+        (texture_streamer.name(handle), texture_streamer.read_as(handle))
+      else {
+        continue;
+      };
+      let (format, mips) = mips.map_err(|e| format!("at streaming texture {name}: {e}"))?;
+      let streamed = Arc::new(self.flat_tex_gen.upload_flat_texture_mips(
+        name,
+        format,
+        color_space,
+        settings.sampler(),
+        &mips,
+      )?);
+      for material in texture_streamer.materials(handle) {
+        let Ok(material_gpu) = self.material_registry.get(material) else { continue };
+        let old_binding = self.material_gen.swap_albedo(material_gpu, streamed.clone())?;
+        self.retired_resources.push((self.frame_number, Arc::new(old_binding)));
+      }
+      let old_tex = self.flat_tex_registry.remove(handle)?;
+      self.retired_resources.push((self.frame_number, old_tex));
+      self.flat_tex_registry.insert(handle, streamed);
+      texture_streamer.set_resident(handle, first_mip);
+    }
+
+    let screen_height = self.triangle_frame_buffers[0].resolution().height;
+    let camera_pos = self.camera.pos.truncate();
+    let mut material_sizes = HashMap::new();
+    for (mesh, material) in self.draw_list.shown_materials() {
+      let Ok(mesh_gpu) = self.tri_mesh_registry.get(mesh) else { continue };
+      let bounds = mesh_gpu.world_bounds()?;
+      let size = texture_streaming::projected_size(
+        bounds.w,
+        bounds.truncate().distance(camera_pos),
+        projection_fov,
+        screen_height,
+      );
+      let max_size = material_sizes.entry(material).or_insert(0.0f32);
+      *max_size = max_size.max(size);
+    }
+    texture_streamer.update_desired(&material_sizes);
+    texture_streamer.start_loads();
+    Ok(())
+  }
+
+  pub fn texture_streaming_stats(&self) -> Option<TextureStreamingStats> {
+    self.texture_streamer.as_ref().map(|x| x.stats())
+  }
+
+  // Textures done compressing are uploaded, the materials and handles using them are pointed at
+  // the compressed upload and the uncompressed one retired
+  fn transcode_textures(&mut self) -> Result<(), String> {
+    let Some(texture_transcoder) = &mut self.texture_transcoder else {
+      return Ok(());
+    };
+    profile_scope!("transcode_textures");
+    for transcoded in texture_transcoder.pump() {
+      let compressed = Arc::new(self.flat_tex_gen.upload_compressed(
+        &transcoded.name,
+        &transcoded.compressed,
+        transcoded.color_space,
+        transcoded.sampler,
+      )?);
+      for material in transcoded.materials {
+        let Ok(material_gpu) = self.material_registry.get(material) else { continue };
+        let old_binding = self.material_gen.swap_albedo(material_gpu, compressed.clone())?;
+        self.retired_resources.push((self.frame_number, Arc::new(old_binding)));
+      }
+      for handle in transcoded.handles {
+        let Ok(old_tex) = self.flat_tex_registry.remove(handle) else { continue };
+        self.retired_resources.push((self.frame_number, old_tex));
+        self.flat_tex_registry.insert(handle, compressed.clone());
+      }
+      self.flat_texes.insert(transcoded.name, Arc::downgrade(&compressed));
+    }
+    Ok(())
+  }
+
+  pub fn texture_compression_stats(&self) -> Option<TextureCompressionStats> {
+    self.texture_transcoder.as_ref().map(|x| x.stats())
+  }
+
+  pub fn add_material(
+    &mut self,
+    name: &str,
+    material: &MaterialCPU,
+    texture: Option<TextureHandle>,
+    lightmap: Option<TextureHandle>,
+    handle: MaterialHandle,
+  ) -> Result<(), String> {
+    let albedo = match texture {
+      Some(texture) => self.flat_tex_registry.get(texture)?.clone(),
+      None => self.flat_tex_gen.get_default_texture(),
+    };
+    let lightmap =
+      lightmap.map(|lightmap| self.flat_tex_registry.get(lightmap).cloned()).transpose()?;
+    let material_gpu = self.material_gen.create_material(name, material, albedo, lightmap)?;
+    self.material_registry.insert(handle, Arc::new(material_gpu));
+    if let (Some(texture_streamer), Some(texture)) = (&mut self.texture_streamer, texture) {
+      texture_streamer.add_material(texture, handle);
+    }
+    if let (Some(texture_transcoder), Some(texture)) = (&mut self.texture_transcoder, texture) {
+      texture_transcoder.add_material(texture, handle);
+    }
+    // Draws registered before the material existed fell back to the default one
+    self.draw_list.mark_dirty();
+    Ok(())
+  }
+
+  pub fn update_material_params(
+    &mut self,
+    handle: MaterialHandle,
+    params: &MaterialParams,
+  ) -> Result<(), String> {
+    self.material_registry.get(handle)?.update_params(params)
+  }
+
+  pub fn destroy_material(&mut self, handle: MaterialHandle) -> Result<(), String> {
+    let material_gpu = self.material_registry.remove(handle)?;
+    self.retired_resources.push((self.frame_number, material_gpu));
+    if let Some(texture_streamer) = &mut self.texture_streamer {
+      texture_streamer.remove_material(handle);
+    }
+    if let Some(texture_transcoder) = &mut self.texture_transcoder {
+      texture_transcoder.remove_material(handle);
+    }
+    self.draw_list.mark_dirty();
+    Ok(())
+  }
+
+  pub fn add_particle_system(
+    &mut self,
+    name: &str,
+    emitter: &ParticleEmitter,
+    handle: ParticleHandle,
+  ) -> Result<(), String> {
+    let particle_system = self.particle_gen.create_particle_system(name, emitter)?;
+    self.particle_registry.insert(handle, Arc::new(particle_system));
+    Ok(())
+  }
+
+  pub fn set_particle_emitter(
+    &mut self,
+    handle: ParticleHandle,
+    emitter: &ParticleEmitter,
+  ) -> Result<(), String> {
+    self.particle_registry.get(handle)?.update_emitter(emitter)
+  }
+
+  pub fn emit_particles(
+    &mut self,
+    handle: ParticleHandle,
+    count: u32,
+    seed: u32,
+  ) -> Result<(), String> {
+    self.particle_registry.get(handle)?.emit(count, seed)
+  }
+
+  pub fn destroy_particle_system(&mut self, handle: ParticleHandle) -> Result<(), String> {
+    let particle_system = self.particle_registry.remove(handle)?;
+    self.retired_resources.push((self.frame_number, particle_system));
+    Ok(())
+  }
+
+  pub fn add_crowd(
+    &mut self,
+    name: &str,
+    mesh: &CrowdMeshCPU,
+    animation: &BoneAnimationCPU,
+    max_instances: u32,
+    material: Option<MaterialHandle>,
+    handle: CrowdHandle,
+  ) -> Result<(), String> {
+    let material = match material {
+      Some(material) => self.material_registry.get(material)?.clone(),
+      None => self.default_material.clone(),
+    };
+    let crowd = self.crowd_gen.create_crowd(name, mesh, animation, max_instances, material)?;
+    self.crowd_registry.insert(handle, Arc::new(crowd));
+    Ok(())
+  }
+
+  pub fn update_crowd_instances(
+    &mut self,
+    handle: CrowdHandle,
+    instances: &[CrowdInstance],
+  ) -> Result<(), String> {
+    self.crowd_registry.get(handle)?.update_instances(instances)
+  }
+
+  pub fn destroy_crowd(&mut self, handle: CrowdHandle) -> Result<(), String> {
+    let crowd = self.crowd_registry.remove(handle)?;
+    self.retired_resources.push((self.frame_number, crowd));
+    Ok(())
+  }
+
+  pub fn add_water_plane(
+    &mut self,
+    name: &str,
+    plane: &WaterPlaneCPU,
+    normal_map: Option<TextureHandle>,
+    handle: WaterHandle,
+  ) -> Result<(), String> {
+    let normal_map = match normal_map {
+      Some(normal_map) => Some(self.flat_tex_registry.get(normal_map)?.clone()),
+      None => None,
+    };
+    let water_plane = self.water_gen.create_water_plane(name, plane, normal_map)?;
+    self.water_registry.insert(handle, Arc::new(water_plane));
+    Ok(())
+  }
+
+  pub fn destroy_water_plane(&mut self, handle: WaterHandle) -> Result<(), String> {
+    let water_plane = self.water_registry.remove(handle)?;
+    self.retired_resources.push((self.frame_number, water_plane));
+    Ok(())
+  }
+
+  pub fn add_foliage(
+    &mut self,
+    name: &str,
+    foliage: &FoliageCPU,
+    material: Option<MaterialHandle>,
+    handle: FoliageHandle,
+  ) -> Result<(), String> {
+    let material = match material {
+      Some(material) => Some(self.material_registry.get(material)?.clone()),
+      None => None,
+    };
+    let foliage_gpu = self.foliage_gen.create_foliage(name, foliage, material)?;
+    self.foliage_registry.insert(handle, Arc::new(foliage_gpu));
+    Ok(())
+  }
+
+  pub fn destroy_foliage(&mut self, handle: FoliageHandle) -> Result<(), String> {
+    let foliage_gpu = self.foliage_registry.remove(handle)?;
+    self.retired_resources.push((self.frame_number, foliage_gpu));
+    Ok(())
+  }
+
+  pub fn load_color_lut(&mut self, path: &str, handle: ColorLutHandle) -> Result<(), String> {
+    let lut = self.color_lut_gen.upload(path, &ColorLutCPU::load(path)?)?;
+    self.color_lut_registry.insert(handle, Arc::new(lut));
+    Ok(())
+  }
+
+  pub fn destroy_color_lut(&mut self, handle: ColorLutHandle) -> Result<(), String> {
+    let lut = self.color_lut_registry.remove(handle)?;
+    self.retired_resources.push((self.frame_number, lut));
+    Ok(())
+  }
+
+  // The old environment is retired, frames in flight may still sample it
+  pub fn set_environment(&mut self, path: Option<&str>) -> Result<(), String> {
+    let environment = match path {
+      Some(path) => {
+        let decoded =
+          DecodedFlatTexture::load(path, TextureColorSpace::Linear, Some(TextureFormat::Rgba16F))?;
+        let equirect = self.flat_tex_gen.upload_flat_texture_texels(
+          &format!("{path}_equirect"),
+          decoded.format,
+          TextureColorSpace::Linear,
+          decoded.resolution,
+          &decoded.texels,
+        )?;
+        Some(Arc::new(self.environment_gen.create_environment(path, &equirect)?))
+      }
+      None => None,
+    };
+    if let Some(old_environment) = std::mem::replace(&mut self.environment, environment) {
+      self.retired_resources.push((self.frame_number, old_environment));
+    }
+    Ok(())
+  }
+
+  // For skies and image based lighting to sample, None until an environment is set
+  pub fn environment(&self) -> Option<&Arc<EnvironmentMap>> {
+    self.environment.as_ref()
+  }
+
+  // Smallest box first, the order materials blend them in
+  pub fn reflection_probes(&self) -> &[ReflectionProbe] {
+    self.reflection_probes.probes()
+  }
+
+  // Half the scene resolution, the reflection is blurred by the waves anyway
+  fn refresh_water_reflection(&mut self, frame_idx: usize) -> Result<(), String> {
+    let scene_res = self.triangle_frame_buffers[0].resolution();
+    let reflection_res = vk::Extent2D {
+      width: (scene_res.width / 2).max(1),
+      height: (scene_res.height / 2).max(1),
+    };
+    if self
+      .water_reflection_frame_buffers
+      .first()
+      .is_some_and(|fb| fb.resolution() == reflection_res)
+    {
+      return Ok(());
+    }
+    // Older frames may still be reading the reflections being replaced
+    if !self.water_reflection_frame_buffers.is_empty() {
+      self.frame_sync.wait_all()?;
+    }
+    self.water_reflection_frame_buffers = self.tri_mesh_renderer.create_framebuffers(
+      &self.render_cmd_buffers[frame_idx],
+      self.gen_allocator.clone(),
+      reflection_res,
+      self.render_cmd_buffers.len(),
+      vk::ImageUsageFlags::SAMPLED,
+    )?;
+    self.water_reflection_dsets =
+      self.water_renderer.create_reflection_dsets(&self.water_reflection_frame_buffers)?;
+    Ok(())
+  }
+
+  pub fn set_minimap(&mut self, settings: Option<MinimapSettings>) {
+    match (&mut self.minimap, settings) {
+      (Some(minimap), Some(settings)) => minimap.set_settings(settings),
+      (_, settings) => {
+        // Frames in flight may still draw into the old map
+        if let Some(old_minimap) = self.minimap.take() {
+          self.retired_resources.push((self.frame_number, Arc::new(old_minimap)));
+        }
+        self.minimap = settings.map(Minimap::new);
+      }
+    }
+    // The map's batches are built from its layers
+    self.draw_list.mark_dirty();
+  }
+
+  // Made the first time a HUD is up, and again when what it's shown at changes
+  fn refresh_ui_layer(&mut self, frame_idx: usize, resolution: vk::Extent2D) -> Result<(), String> {
+    if !self.ui_layer.needs_resize(resolution) {
+      return Ok(());
+    }
+    if self.ui_layer.has_frame_buffers() {
+      self.frame_sync.wait_all()?;
+    }
+    self.ui_layer.resize(
+      &self.render_cmd_buffers[frame_idx],
+      self.gen_allocator.clone(),
+      resolution,
+      &self.triangle_frame_buffers,
+    )
+  }
+
+  fn refresh_minimap(&mut self, frame_idx: usize) -> Result<(), String> {
+    let scene_res = self.triangle_frame_buffers[0].resolution();
+    let Some(minimap) = &mut self.minimap else { return Ok(()) };
+    if !minimap.needs_resize(scene_res) {
+      return Ok(());
+    }
+    if minimap.has_frame_buffers() {
+      self.frame_sync.wait_all()?;
+    }
+    minimap.resize(
+      &self.render_cmd_buffers[frame_idx],
+      self.gen_allocator.clone(),
+      &self.tri_mesh_renderer,
+      scene_res,
+      self.render_cmd_buffers.len(),
+    )
+  }
+
+  pub fn add_renderable(&mut self, mesh: MeshHandle, material: Option<MaterialHandle>) {
+    self.draw_list.add(mesh, material);
+  }
+
+  pub fn remove_renderable(&mut self, mesh: MeshHandle) -> Result<(), String> {
+    match self.draw_list.remove(mesh) {
+      true => Ok(()),
+      false => Err(format!("{mesh:?} is not in the draw list")),
+    }
+  }
+
+  pub fn set_renderable_visible(&mut self, mesh: MeshHandle, visible: bool) -> Result<(), String> {
+    self.draw_list.set_visible(mesh, visible)
+  }
+
+  pub fn set_renderable_layers(
+    &mut self,
+    mesh: MeshHandle,
+    layers: LayerMask,
+  ) -> Result<(), String> {
+    self.draw_list.set_layers(mesh, layers)
+  }
+
+  pub fn set_camera_layers(&mut self, camera_layers: LayerMask) {
+    self.draw_list.set_camera_layers(camera_layers);
+  }
+
+  // Culls meshes whose bounds don't touch any room seen from the camera. Meshes outside every
+  // room are always drawn, so are crowds and particles which spread over rooms
+  fn cull_rooms(&mut self) {
+    let Some(visible_rooms) = self.room_graph.visible_rooms(&self.camera) else {
+      self.draw_list.set_culled(HashSet::new());
+      return;
+    };
+    let culled = self
+      .draw_list
+      .meshes()
+      .filter(|mesh| {
+        let Ok(mesh_gpu) = self.tri_mesh_registry.get(*mesh) else { return false };
+        let transform = self.mesh_transforms.get(mesh).copied().unwrap_or_default();
+        let (scale, _, center) = transform.to_scale_rotation_translation();
+        let radius = mesh_gpu.bounding_radius() * scale.abs().max_element();
+        let mut in_room = false;
+        for (room, visible) in self.room_graph.rooms.iter().zip(visible_rooms.iter()) {
+          if room.overlaps_sphere(center, radius) {
+            if *visible {
+              return false;
+            }
+            in_room = true;
+          }
+        }
+        in_room
+      })
+      .collect();
+    self.draw_list.set_culled(culled);
+  }
+
+  // Uses the default material for meshes without one, skips draws with stale handles
+  fn resolve_draws(
+    &self,
+    entries: Vec<(MeshHandle, Option<MaterialHandle>)>,
+  ) -> Vec<(Arc<TriMeshGPU>, Arc<MaterialGPU>)> {
+    entries
+      .into_iter()
+      .filter_map(|(mesh, opt_material)| {
+        let mesh = self
+          .tri_mesh_registry
+          .get(mesh)
+          .inspect_err(|e| log!("skipping draw: {e}"))
+          .ok()?
+          .clone();
+        let material = match opt_material {
+          Some(material) => self
+            .material_registry
+            .get(material)
+            .inspect_err(|e| log!("using default material: {e}"))
+            .ok()
+            .cloned()
+            .unwrap_or(self.default_material.clone()),
+          None => self.default_material.clone(),
+        };
+        Some((mesh, material))
+      })
+      .collect()
+  }
 
-    let mut depth_format = vk::Format::UNDEFINED;
-    for format in DEPTH_FORMAT_PREFERENCE {
-      let format_props = unsafe {
-        ash_device.ash_instance().inner().get_physical_device_format_properties(gpu, format)
-      };
-      if format_props.optimal_tiling_features.contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT) {
-        depth_format = format;
-        break;
+  fn rebuild_draw_batches(&mut self) -> Result<(), String> {
+    let visible = self.draw_list.take_visible();
+    let mesh_materials = self.resolve_draws(visible);
+    self.draw_batches = self.tri_mesh_renderer.build_batches(mesh_materials)?;
+    self.cull_bounds_version += 1;
+    if let Some(layers) = self.minimap.as_ref().map(|minimap| minimap.settings().layers) {
+      let mesh_materials = self.resolve_draws(self.draw_list.visible_on(layers));
+      let minimap_batches = self.tri_mesh_renderer.build_batches(mesh_materials)?;
+      if let Some(minimap) = &mut self.minimap {
+        minimap.set_draw_batches(minimap_batches);
       }
     }
-    if depth_format == vk::Format::UNDEFINED {
-      return Err("preferred depth format not supported".to_string());
+    Ok(())
+  }
+
+  // Buffers the cpu rewrites every frame get tied to the frame that just read them, so validation
+  // can catch a write landing before that frame is done
+  fn mark_buffers_in_flight(&self) {
+    if !validation::is_enabled() {
+      return;
+    }
+    let fence = self.frame_sync.frame_fence();
+    for handle in self.draw_list.shown() {
+      if let Some(skinned_mesh) = self.skinned_meshes.get(&handle) {
+        skinned_mesh.mark_in_flight(fence);
+      }
+      if let Some(cloth) = self.cloths.get(&handle) {
+        cloth.mark_in_flight(fence);
+      }
     }
+  }
 
-    let surface_formats = surface.get_gpu_formats(ash_device.gpu())?;
-    let surface_caps = surface.get_gpu_capabilities(ash_device.gpu())?;
-    let surface_present_modes = surface.get_gpu_present_modes(ash_device.gpu())?;
+  // Steps shown cloth by the time since the last step, capped so a long frame doesn't blow it
+  // apart. Gravity and wind go into each cloth's model space
+  fn simulate_cloth(&mut self, frame_idx: usize) -> Result<(), String> {
+    let now = Instant::now();
+    let time_s =
+      self.last_cloth_sim.map(|last| now.duration_since(last).as_secs_f32().min(1.0 / 30.0));
+    self.last_cloth_sim = Some(now);
+    let time_s = time_s.unwrap_or(0.0);
+    let wind = cloth_wind(self.wind, self.effect_clock.elapsed().as_secs_f32());
+    let mut gpu_steps = ScratchVec::new_in(self.frame_scratch.arena());
+    for handle in self.draw_list.shown() {
+      let Some(cloth) = self.cloths.get(&handle) else { continue };
+      let to_model =
+        self.mesh_transforms.get(&handle).copied().unwrap_or(glam::Mat4::IDENTITY).inverse();
+      let gravity = to_model.transform_vector3(glam::Vec3::NEG_Y * 9.81);
+      let wind = to_model.transform_vector3(wind);
+      match cloth.sim_dset() {
+        Some(_) => gpu_steps.push(ClothStep { cloth: cloth.clone(), gravity, wind }),
+        None => cloth.step_cpu(time_s, gravity, wind)?,
+      }
+    }
+    self.cloth_renderer.simulate(&self.render_cmd_buffers[frame_idx], &gpu_steps, time_s);
+    Ok(())
+  }
 
-    let surface_format = surface_formats
-      .iter()
-      .find(|f| f.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR)
-      .cloned()
-      .unwrap_or(surface_formats[0]);
-    let present_mode = surface_present_modes
-      .iter()
-      .find(|m| **m == vk::PresentModeKHR::MAILBOX)
-      .cloned()
-      .unwrap_or(vk::PresentModeKHR::FIFO);
+  // Gives moved meshes the transform and pose they were last drawn with as their previous ones.
+  // Meshes that stopped get theirs caught up a frame later, so they stop showing motion
+  fn refresh_prev_transforms(&mut self) -> Result<(), String> {
+    for handle in std::mem::take(&mut self.moved_meshes) {
+      let mut moving = false;
+      if let Some(transform) = self.mesh_transforms.get(&handle).copied() {
+        let prev_transform = self.drawn_transforms.insert(handle, transform).unwrap_or(transform);
+        self.tri_mesh_registry.get(handle)?.update_prev_transform(prev_transform)?;
+        moving |= prev_transform != transform;
+      }
+      if let (Some(skinned_mesh), Some(pose)) =
+        (self.skinned_meshes.get(&handle), self.joint_poses.get(&handle))
+      {
+        let prev_pose =
+          self.drawn_poses.insert(handle, pose.clone()).unwrap_or_else(|| pose.clone());
+        skinned_mesh.update_prev_joints(&prev_pose)?;
+        moving |= prev_pose != *pose;
+      }
+      if moving {
+        self.moved_meshes.insert(handle);
+      }
+    }
+    Ok(())
+  }
 
-    let swapchain_resolution = match surface_caps.current_extent.width {
-      u32::MAX => vk::Extent2D::default().width(640).height(480),
-      _ => surface_caps.current_extent,
+  // Everything drawn into the scene framebuffer when not loading, the command buffer is already
+  // begun and gets encoded and blitted to the swapchain after. Returns the HUD rects, for the UI
+  // layer
+  fn record_scene(
+    &mut self,
+    frame_idx: usize,
+    current_aspect_ratio: f32,
+    water_planes: &[Arc<WaterPlaneGPU>],
+    reflection_height: Option<f32>,
+  ) -> Result<Vec<OverlayRect>, String> {
+    {
+      profile_scope!("cull_rooms");
+      self.cull_rooms();
+    }
+    if self.draw_list.is_dirty() {
+      profile_scope!("rebuild_draw_batches");
+      self.rebuild_draw_batches()?;
+    }
+    if self.post_process.is_some() {
+      self.refresh_prev_transforms()?;
+    }
+    // Effects drawn after the post processing, like the editor helpers, use the camera as is
+    let scene_camera = match &self.post_process {
+      Some(post_process) => post_process.scene_camera(self.camera),
+      None => self.camera,
     };
+    if !self.skinned_meshes.is_empty() {
+      profile_scope!("skin_meshes");
+      let skinned_meshes = ScratchVec::from_iter_in(
+        self.draw_list.shown().filter_map(|mesh| self.skinned_meshes.get(&mesh).cloned()),
+        self.frame_scratch.arena(),
+      );
+      self.skinning_renderer.skin(
+        &self.render_cmd_buffers[frame_idx],
+        &skinned_meshes,
+        self.post_process.is_some(),
+      );
+    }
+    if !self.cloths.is_empty() {
+      profile_scope!("simulate_cloth");
+      self.simulate_cloth(frame_idx)?;
+    }
+    if let Some(mesh_culler) = &mut self.mesh_culler {
+      profile_scope!("cull_meshes");
+      mesh_culler.cull(
+        &self.render_cmd_buffers[frame_idx],
+        frame_idx,
+        self.camera,
+        &self.draw_batches,
+        self.cull_bounds_version,
+      )?;
+    }
+    let culled_draws = self.mesh_culler.as_ref().and_then(|culler| culler.draw_buffer(frame_idx));
+    {
+      profile_scope!("record_shadows");
+      self.shadows.record(
+        &self.render_cmd_buffers[frame_idx],
+        frame_idx,
+        self.camera,
+        &self.sun,
+        &self.light_culling.prioritize(self.lights.values(), self.camera, self.projection_fov()),
+        &self.draw_batches,
+        self.draw_list.shown().map(|mesh| {
+          (mesh, self.mesh_transforms.get(&mesh).copied().unwrap_or_default())
+        }),
+      )?;
+    }
+    // Meshes only like the water reflection, without room culling since probes see into other
+    // rooms. Later frames reflect what was captured, the batches are only built to capture
+    {
+      profile_scope!("record_reflection_probes");
+      let probe_batches = match self.reflection_probes.capture_pending() {
+        true => {
+          let camera_layers = self.draw_list.camera_layers();
+          let mesh_materials = self.resolve_draws(self.draw_list.visible_on(camera_layers));
+          self.tri_mesh_renderer.build_batches(mesh_materials)?
+        }
+        false => vec![],
+      };
+      self.reflection_probes.record(
+        &self.render_cmd_buffers[frame_idx],
+        frame_idx,
+        &self.tri_mesh_renderer,
+        self.shadows.world_dset(frame_idx),
+        &probe_batches,
+        &self.environment_gen,
+      )?;
+    }
 
-    let swapchain_image_count = std::cmp::min(
-      surface_caps.min_image_count + 1,
-      std::cmp::max(surface_caps.max_image_count, std::cmp::min(surface_caps.min_image_count, 3)),
-    );
-
-    let swapchain = AdSwapchain::new(
-      Arc::new(AdSwapchainDevice::new(ash_device.clone())),
-      surface,
-      queues.get(&GPUQueueType::Present).ok_or("no supported present queue")?.clone(),
-      swapchain_image_count,
-      surface_format.color_space,
-      surface_format.format,
-      swapchain_resolution,
-      vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::COLOR_ATTACHMENT,
-      surface_caps.current_transform,
-      present_mode,
-      None,
-    )?;
-
-    let image_acquire_fence = AdFence::new(ash_device.clone(), vk::FenceCreateFlags::default())?;
-
-    let render_cmd_pool = Arc::new(AdCommandPool::new(
-      queues[&GPUQueueType::Graphics].clone(),
-      vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
-    )?);
+    // Meshes only, crowds and particles are left out of the reflection. Geometry under the water
+    // isn't clipped away and shows up mirrored too
+    if let Some(height) = reflection_height {
+      let mut reflection_camera = water_renderers::reflection_camera(self.camera, height);
+      reflection_camera.refresh_vp_matrix(self.projection_fov(), current_aspect_ratio);
+      self.tri_mesh_renderer.render(
+        &self.render_cmd_buffers[frame_idx],
+        &self.water_reflection_frame_buffers[frame_idx],
+        reflection_camera,
+        self.shadows.world_dset(frame_idx),
+        &self.draw_batches,
+        None,
+      )?;
+    }
+    // Meshes only like the reflection, without room culling since it sees over the walls
+    if let Some(minimap) = &self.minimap {
+      self.tri_mesh_renderer.render(
+        &self.render_cmd_buffers[frame_idx],
+        minimap.frame_buffer(frame_idx),
+        minimap::minimap_camera(self.camera, minimap.settings()),
+        self.shadows.world_dset(frame_idx),
+        minimap.draw_batches(),
+        None,
+      )?;
+    }
 
-    let render_cmd_buffers =
-      AdCommandBuffer::new(render_cmd_pool.clone(), vk::CommandBufferLevel::PRIMARY, 3)?;
+    if self.draw_batches.len() >= PARALLEL_RECORD_MIN_DRAWS {
+      self.tri_mesh_renderer.render_parallel(
+        &self.render_cmd_buffers[frame_idx],
+        &self.secondary_cmd_buffers[frame_idx],
+        &self.triangle_frame_buffers[frame_idx],
+        scene_camera,
+        self.shadows.camera_dset(frame_idx),
+        &self.draw_batches,
+        culled_draws,
+        jobs::global(),
+      )?;
+    } else {
+      self.tri_mesh_renderer.render(
+        &self.render_cmd_buffers[frame_idx],
+        &self.triangle_frame_buffers[frame_idx],
+        scene_camera,
+        self.shadows.camera_dset(frame_idx),
+        &self.draw_batches,
+        culled_draws,
+      )?;
+    }
+    // Only meshes occlude, the crowds and foliage drawn after don't
+    if let Some(mesh_culler) = &mut self.mesh_culler {
+      mesh_culler.build_pyramid(
+        &self.render_cmd_buffers[frame_idx],
+        frame_idx,
+        &self.triangle_frame_buffers[frame_idx],
+        scene_camera,
+      )?;
+    }
 
-    let render_semaphores = (0..3)
-      .map(|_| AdSemaphore::new(ash_device.clone(), vk::SemaphoreCreateFlags::default()))
-      .collect::<Result<Vec<_>, _>>()?;
+    // After the scene so particles collide with crowds too
+    let scratch = self.frame_scratch.arena();
+    let crowds = ScratchVec::from_iter_in(self.crowd_registry.values().cloned(), scratch);
+    if !crowds.is_empty() {
+      self.crowd_renderer.render(
+        &self.render_cmd_buffers[frame_idx],
+        &self.triangle_frame_buffers[frame_idx],
+        scene_camera,
+        self.shadows.world_dset(frame_idx),
+        &crowds,
+      )?;
+    }
 
-    let render_fences = (0..3)
-      .map(|_| AdFence::new(ash_device.clone(), vk::FenceCreateFlags::SIGNALED))
-      .collect::<Result<Vec<_>, _>>()?;
+    let effect_time = self.effect_clock.elapsed().as_secs_f32();
+    let foliages = ScratchVec::from_iter_in(self.foliage_registry.values().cloned(), scratch);
+    if !foliages.is_empty() {
+      self.foliage_renderer.render(
+        &self.render_cmd_buffers[frame_idx],
+        &self.triangle_frame_buffers[frame_idx],
+        scene_camera,
+        self.shadows.world_dset(frame_idx),
+        effect_time,
+        self.wind,
+        &foliages,
+      )?;
+    }
 
-    let gen_allocator = Arc::new(Mutex::new(ash_device.create_allocator()?));
-    let tri_mesh_allocator = Arc::new(Mutex::new(ash_device.create_allocator()?));
-    let flat_tex_allocator = Arc::new(Mutex::new(ash_device.create_allocator()?));
+    // Before particles, which blend over the water
+    if !water_planes.is_empty() {
+      let reflection = reflection_height.map(|_| {
+        (
+          self.water_reflection_frame_buffers[frame_idx].as_ref(),
+          &self.water_reflection_dsets[frame_idx],
+        )
+      });
+      self.water_renderer.render(
+        &self.render_cmd_buffers[frame_idx],
+        &self.triangle_frame_buffers[frame_idx],
+        scene_camera,
+        effect_time,
+        &self.sun,
+        water_planes,
+        reflection,
+      );
+    }
 
-    let tri_mesh_gen =
-      TriMeshGenerator::new(tri_mesh_allocator, queues[&GPUQueueType::Transfer].clone())?;
+    // Clamped so particles don't tunnel through surfaces after a stall
+    let now = std::time::Instant::now();
+    let particle_frame_time = self
+      .last_particle_sim
+      .map(|last| now.duration_since(last).as_secs_f32().min(0.05))
+      .unwrap_or(0.0);
+    self.last_particle_sim = Some(now);
+    let particle_systems =
+      ScratchVec::from_iter_in(self.particle_registry.values().cloned(), scratch);
+    if !particle_systems.is_empty() {
+      let mut particle_sims = ScratchVec::with_capacity_in(particle_systems.len(), scratch);
+      for system in particle_systems.iter() {
+        particle_sims.push((system.clone(), system.take_sim_params(particle_frame_time)?));
+      }
+      self.particle_renderer.simulate(
+        &self.render_cmd_buffers[frame_idx],
+        &self.triangle_frame_buffers[frame_idx],
+        &self.particle_depth_dsets[frame_idx],
+        scene_camera,
+        &particle_sims,
+      );
+      self.particle_renderer.render(
+        &self.render_cmd_buffers[frame_idx],
+        &self.triangle_frame_buffers[frame_idx],
+        scene_camera,
+        &particle_systems,
+      );
+    }
 
-    let flat_tex_gen =
-      FlatTextureGenerator::new(flat_tex_allocator, queues[&GPUQueueType::Transfer].clone())?;
+    self.depth_readback.record(
+      &self.render_cmd_buffers[frame_idx],
+      frame_idx,
+      &self.triangle_frame_buffers[frame_idx],
+      scene_camera,
+      self.frame_number,
+    )?;
+    self.world_labels.record(
+      &self.render_cmd_buffers[frame_idx],
+      frame_idx,
+      &self.triangle_frame_buffers[frame_idx],
+      self.camera,
+      &self.mesh_transforms,
+    )?;
 
-    let tri_mesh_tex_renderer =
-      TriMeshTexRenderer::new(ash_device.clone(), &tri_mesh_gen, &flat_tex_gen, depth_format)?;
+    if let Some(post_process) = &mut self.post_process {
+      profile_scope!("post_process");
+      post_process.record(
+        &self.render_cmd_buffers[frame_idx],
+        frame_idx,
+        &self.triangle_frame_buffers[frame_idx],
+        self.camera,
+        &self.draw_batches,
+      )?;
+    }
+    if let Some(depth_of_field) = &self.depth_of_field {
+      profile_scope!("depth_of_field");
+      depth_of_field.apply(
+        &self.render_cmd_buffers[frame_idx],
+        frame_idx,
+        &self.triangle_frame_buffers[frame_idx],
+        scene_camera,
+      )?;
+    }
+    {
+      profile_scope!("tonemap");
+      let identity_lut = self.color_lut_gen.identity();
+      let grading =
+        self.color_grading.map(|grading| grading.luts(&self.color_lut_registry, &identity_lut));
+      self.exposure.tonemap(
+        &self.render_cmd_buffers[frame_idx],
+        frame_idx,
+        &self.triangle_frame_buffers[frame_idx],
+        grading,
+      )?;
+    }
+    {
+      profile_scope!("film_effects");
+      self.film_effects.apply(
+        &self.render_cmd_buffers[frame_idx],
+        frame_idx,
+        &self.triangle_frame_buffers[frame_idx],
+        self.frame_number,
+      )?;
+    }
 
-    let mut triangle_frame_buffers = tri_mesh_tex_renderer.create_framebuffers(
-      &render_cmd_buffers[0],
-      gen_allocator.clone(),
-      swapchain_resolution,
-      3,
-    )?;
-    for (i, fb) in triangle_frame_buffers.iter_mut().enumerate() {
-      fb.attachments()[0]
-        .image()
-        .allocation()
-        .lock()
-        .map_err(|e| format!("at getting image mem lock: {e}"))?
-        .rename(&format!("triangle_color_image_{i}"))?;
-      fb.attachments()[1]
-        .image()
-        .allocation()
-        .lock()
-        .map_err(|e| format!("at getting image mem lock: {e}"))?
-        .rename(&format!("triangle_depth_image_{i}"))?;
+    if self.editor_grid.is_some() || self.gizmo.is_some() {
+      let gizmo_handles = match self.gizmo {
+        Some(gizmo) => {
+          let handle_transform =
+            TriMeshTransform { transform: gizmo.handle_transform(self.camera.pos.truncate()) };
+          GizmoAxis::ALL
+            .iter()
+            .map(|axis| {
+              let handle_mesh = self.gizmo_meshes[&(gizmo.mode, *axis)].clone();
+              handle_mesh.update_transform(handle_transform)?;
+              Ok((handle_mesh, axis.color(gizmo.highlighted == Some(*axis))))
+            })
+            .collect::<Result<Vec<_>, String>>()?
+        }
+        None => vec![],
+      };
+      self.editor_overlay_renderer.render(
+        &self.render_cmd_buffers[frame_idx],
+        &self.triangle_frame_buffers[frame_idx],
+        self.camera,
+        self.editor_grid,
+        &gizmo_handles,
+      );
+    }
+    // Over the editor helpers like the rest of the HUD, under the debug views
+    if let Some(minimap) = &self.minimap {
+      minimap.composite(
+        &self.render_cmd_buffers[frame_idx],
+        frame_idx,
+        &self.triangle_frame_buffers[frame_idx],
+      );
     }
+    self.frame_diff.composite(
+      &self.render_cmd_buffers[frame_idx],
+      &self.triangle_frame_buffers[frame_idx],
+    );
 
-    let camera = Camera3D {
-      pos: glam::vec4(2.0, 2.0, 2.0, 0.0),
-      look_dir: glam::vec4(-1.0, -1.0, -1.0, 0.0),
-      view_proj_mat: glam::Mat4::IDENTITY,
+    let dset_stats = match self.memory_heatmap {
+      Some(_) => self.descriptor_pool_stats()?,
+      None => vec![],
     };
-
-    Ok(Self {
-      ash_device,
-      queues,
-      depth_format,
-      swapchain,
-      image_acquire_fence,
-      render_cmd_buffers,
-      render_semaphores,
-      render_fences,
-      gen_allocator,
-      triangle_frame_buffers,
-      camera,
-      tri_meshes: HashMap::new(),
-      tri_mesh_gen,
-      tri_mesh_tex_renderer,
-      flat_texes: HashMap::new(),
-      flat_tex_gen,
-    })
+    let mut overlay_rects = vec![];
+    if let Some(memory_heatmap) = &mut self.memory_heatmap {
+      memory_heatmap.refresh(&self.ash_device, &dset_stats, self.flat_tex_gen.dedup_stats())?;
+      overlay_rects.extend_from_slice(memory_heatmap.rects());
+    }
+    let frame_stats = if self.perf_hud.is_some() { self.frame_stats() } else { Default::default() };
+    if let Some(perf_hud) = &mut self.perf_hud {
+      let gpu_memory_budget = self.config.resource_budget.gpu_memory_mb as u64 * 1024 * 1024;
+      perf_hud.update(&frame_stats, gpu_memory_budget);
+      overlay_rects.extend_from_slice(perf_hud.rects());
+    }
+    if !self.debug_lines.is_empty() {
+      self.debug_overlay_renderer.render(
+        &self.render_cmd_buffers[frame_idx],
+        &self.triangle_frame_buffers[frame_idx],
+        frame_idx,
+        self.camera,
+        &self.debug_lines,
+        &[],
+      )?;
+    }
+    Ok(overlay_rects)
   }
 
-  pub fn add_tri_mesh(
+  // Cleared by drawing the scene pass with nothing in it, then the logo and bar over it
+  fn record_loading_screen(
     &mut self,
-    name: String,
-    mesh: &TriMeshCPU,
-    output: Arc<OnceLock<Arc<TriMeshGPU>>>,
+    frame_idx: usize,
+    progress: LoadingProgress,
+    aspect_ratio: f32,
   ) -> Result<(), String> {
-    let s_time = std::time::Instant::now();
-    let tri_mesh_gpu = self
-      .tri_meshes
-      .entry(name.clone())
-      .or_insert(Arc::new(self.tri_mesh_gen.upload_tri_mesh(&name, mesh)?));
-    println!("mesh {} upload time: {}ms", &name, s_time.elapsed().as_millis());
-    output
-      .set(tri_mesh_gpu.clone())
-      .map_err(|_| "at setting mesh output".to_string())?;
+    self.tri_mesh_renderer.render(
+      &self.render_cmd_buffers[frame_idx],
+      &self.triangle_frame_buffers[frame_idx],
+      self.camera,
+      self.shadows.unshadowed_dset(),
+      &[],
+      None,
+    )?;
+    let rects =
+      loading_screen::layout(progress, aspect_ratio, self.effect_clock.elapsed().as_secs_f32());
+    self.debug_overlay_renderer.render(
+      &self.render_cmd_buffers[frame_idx],
+      &self.triangle_frame_buffers[frame_idx],
+      frame_idx,
+      self.camera,
+      &[],
+      &rects,
+    )
+  }
+
+  // Lets the quality callbacks act on sustained gpu frame times. A new render scale is picked up
+  // by the resize before this frame is recorded
+  fn govern_quality(&mut self, frame_idx: usize, gpu_time: Duration) -> Result<(), String> {
+    let Some(quality_governor) = &mut self.quality_governor else { return Ok(()) };
+    let Some(pressure) = quality_governor.frame_timed(gpu_time) else { return Ok(()) };
+    let knobs = QualityKnobs {
+      render_scale: self.config.render_scale,
+      shadow_map_size: self.shadows.map_size(),
+    };
+    let adjusted = quality_governor.adjust(pressure, knobs);
+    self.config.render_scale = adjusted.render_scale.clamp(0.25, 2.0);
+    if adjusted.shadow_map_size != knobs.shadow_map_size {
+      // Frames in flight may still sample the shadow maps being replaced
+      self.frame_sync.wait_all()?;
+      self.shadows.set_map_size(&self.render_cmd_buffers[frame_idx], adjusted.shadow_map_size)?;
+    }
     Ok(())
   }
 
-  pub fn add_flat_texture(
-    &mut self,
-    name: String,
-    tex_path: String,
-    output: Arc<OnceLock<Arc<FlatTextureGPU>>>
-  ) -> Result<(), String> {
-    let s_time = std::time::Instant::now();
-    let flat_tex_gpu = self
-      .flat_texes
-      .entry(name.clone())
-      .or_insert(Arc::new(self.flat_tex_gen.upload_flat_texture(&name, &tex_path)?));
-    println!("tex {} upload time: {}ms", &name, s_time.elapsed().as_millis());
-    output
-      .set(flat_tex_gpu.clone())
-      .map_err(|_| "at setting tex output".to_string())?;
+  // Anything drawing to the scene targets that changed is made again here, and the targets by the
+  // resize after
+  fn apply_quality(&mut self, frame_idx: usize) -> Result<(), String> {
+    let Some(preset) = self.pending_quality.take() else { return Ok(()) };
+    let mut settings = preset.settings().unwrap_or(self.custom_quality);
+    let samples = match settings.anti_aliasing {
+      AntiAliasing::Msaa => Self::select_sample_count(&self.ash_device, settings.msaa_samples),
+      AntiAliasing::Taa => vk::SampleCountFlags::TYPE_1,
+    };
+    if samples != self.samples {
+      log!(
+        "quality {} needs {} samples, msaa stays at x{} until the renderer restarts",
+        preset.name(),
+        samples.as_raw(),
+        self.samples.as_raw()
+      );
+      settings.anti_aliasing = AntiAliasing::Msaa;
+      settings.msaa_samples = self.samples.as_raw();
+    }
+    settings.render_scale = settings.render_scale.clamp(0.25, 2.0);
+    let previous = self.config.quality_settings();
+    self.config.set_quality_settings(&settings);
+    self.config.quality = preset;
+    // Frames in flight may still use what's replaced
+    self.frame_sync.wait_all()?;
+    if settings.shadow_map_size != self.shadows.map_size() {
+      self.shadows.set_map_size(&self.render_cmd_buffers[frame_idx], settings.shadow_map_size)?;
+    }
+    self.film_effects.set_config(&self.config.post_process);
+    if settings.anti_aliasing != previous.anti_aliasing
+      || settings.motion_blur != previous.motion_blur
+    {
+      self.post_process = Self::post_process_renderers(
+        &self.ash_device,
+        &self.gen_allocator,
+        &self.tri_mesh_gen,
+        self.depth_format,
+        self.samples,
+        &self.config,
+      )?;
+      self.rebuild_targets = true;
+    }
+    if settings.depth_of_field != previous.depth_of_field {
+      self.depth_of_field =
+        Self::depth_of_field_renderer(&self.ash_device, self.samples, &self.config)?;
+      self.rebuild_targets = true;
+    }
     Ok(())
   }
 
-  pub fn draw(
+  pub fn draw(&mut self) -> Result<bool, String> {
+    self.draw_recorded_by(Self::record_frame)
+  }
+
+  // With the frame's commands recorded by record, tests pass one that fails to see draw recover
+  fn draw_recorded_by(
     &mut self,
-    mesh_ftex_list: &[(Arc<TriMeshGPU>, Option<Arc<FlatTextureGPU>>)],
+    record: impl FnOnce(&mut Self, usize, u32) -> Result<(), String>,
   ) -> Result<bool, String> {
+    profile_scope!("draw");
+    {
+      profile_scope!("wait_for_frame");
+      self.frame_sync.wait_for_frame()?;
+    }
+    if self.config.max_frame_latency > 0 {
+      profile_scope!("wait_for_latency");
+      let max_latency = self.config.max_frame_latency;
+      // Fences only keep the cpu from getting ahead of the gpu, presents queued for display still
+      // add latency then
+      if !self.swapchain.wait_for_presents(max_latency as u64 - 1)? {
+        self.frame_sync.wait_for_frames_ahead(max_latency as usize)?;
+      }
+    }
+    let allocations_at_start = profiler::thread_allocations();
+    self.frame_scratch.reset();
+    self.input_latency.poll(&self.frame_sync, self.swapchain.as_ref())?;
+    let frame_idx = self.frame_sync.current_frame();
+    let _ = self.depth_readback.collect(frame_idx).inspect_err(|e| log!("at reading depth: {e}"));
+    let _ = self
+      .world_labels
+      .collect(frame_idx)
+      .inspect_err(|e| log!("at reading world label depths: {e}"));
+    let _ = self
+      .frame_export
+      .collect(frame_idx)
+      .inspect_err(|e| log!("at collecting exported frame: {e}"));
+    if let Some(recorder) = &mut self.recorder {
+      if let Err(e) = recorder.push(self.frame_export.latest()) {
+        log!("at recording frame: {e}");
+        self.stop_recording();
+      }
+    }
+    if self.frame_diff.is_capturing() {
+      match self.frame_diff.collect(self.frame_export.latest()) {
+        Ok(Some(heatmap)) => self.retired_resources.push((self.frame_number, Arc::new(heatmap))),
+        Ok(None) => {}
+        Err(e) => log!("at diffing frames: {e}"),
+      }
+      if !self.frame_diff.is_capturing() {
+        let _ = self.apply_frame_export().inspect_err(|e| log!("at setting frame export: {e}"));
+      }
+    }
+    if let Some(depth_of_field) = &mut self.depth_of_field {
+      depth_of_field.update_focus(&mut self.depth_readback);
+    }
+    if let Some(gpu_time) = self.gpu_timer.frame_time(frame_idx)? {
+      self.input_latency.frame_timed(gpu_time);
+      if let Some(benchmark) = &mut self.benchmark {
+        benchmark.frame_timed(gpu_time);
+      }
+      profiler::report_time(profiler::GPU_FRAME_BUDGET, gpu_time);
+      self.govern_quality(frame_idx, gpu_time)?;
+    }
+    self.apply_quality(frame_idx)?;
+    for e in self.tri_mesh_renderer.take_shader_errors() {
+      log!("{e}");
+    }
+    let frames_in_flight = self.render_cmd_buffers.len() as u64;
+    let frame_number = self.frame_number;
+    release_retired(&mut self.retired_resources, frame_number, frames_in_flight);
+    if self.loading_progress.is_none() {
+      let _ = self.stream_textures().inspect_err(|e| log!("at streaming textures: {e}"));
+      let _ = self.transcode_textures().inspect_err(|e| log!("at compressing textures: {e}"));
+    }
+
     // Acquiring next image to draw
-    let (image_idx, refresh_needed) = self
+    let Some((image_idx, suboptimal)) = self
       .swapchain
-      .acquire_next_image(None, Some(&self.image_acquire_fence))
-      .map_err(|e| format!("at acquiring next image: {e}"))?;
-    self.image_acquire_fence.wait_and_reset(999999999)?;
-
-    if refresh_needed {
-      let _ = self
-        .swapchain
-        .refresh_resolution()
-        .inspect_err(|e| eprintln!("at refreshing swapchain res: {e}"));
+      .acquire_next_image(Some(self.frame_sync.acquire_semaphore()), None)
+      .map_err(|e| format!("at acquiring next image: {e}"))?
+    else {
+      self.refresh_swapchain()?;
       return Ok(true);
+    };
+    self.frame_sync.mark_acquired()?;
+    let recorded = record(self, frame_idx, image_idx);
+
+    profile_scope!("submit_present");
+    let submitted = recorded.and_then(|_| {
+      self.frame_sync.frame_fence().reset()?;
+      self.render_cmd_buffers[frame_idx]
+        .submit(
+          &[self.frame_sync.render_semaphore(image_idx)],
+          &[(self.frame_sync.acquire_semaphore(), vk::PipelineStageFlags::TRANSFER)],
+          Some(self.frame_sync.frame_fence()),
+        )
+        .map_err(|e| format!("error submitting cmds: {e}"))
+    });
+    // Past the acquire an error can't just return, the signalled acquire semaphore would trip the
+    // next acquire using it and a fence reset for a failed submit would never signal
+    if let Err(e) = submitted {
+      let _ =
+        self.abandon_frame(frame_idx, image_idx).inspect_err(|e| log!("at abandoning frame: {e}"));
+      return Err(e);
     }
+    // Reported after the present, returning now would leave the render semaphore signalled
+    let submit_hazard = self.frame_sync.mark_submitted(image_idx);
+    self.mark_buffers_in_flight();
 
-    self.render_fences[image_idx as usize].wait_and_reset(999999999)?;
+    let present_res =
+      self.swapchain.present_image(image_idx, &[self.frame_sync.render_semaphore(image_idx)]);
+    // Present waits on the semaphore even when the swapchain turns out to be out of date
+    self.frame_sync.mark_presented(image_idx);
+    if present_res.is_ok() {
+      self.input_latency.frame_presented(
+        frame_idx,
+        self.swapchain.last_present_id(),
+        self.input_received_at.take(),
+      );
+      self.frame_capture.report_new_captures();
+    }
+    self.frame_sync.advance();
+    let _ = self
+      .resource_budget
+      .sample(&self.ash_device, self.frame_number)
+      .inspect_err(|e| log!("at sampling resource usage: {e}"));
+    if let Some(benchmark) = self.benchmark.as_mut().filter(|_| present_res.is_ok()) {
+      benchmark.frame_presented(self.draw_calls, self.resource_budget.usage());
+    }
+    self.frame_allocations =
+      allocations_at_start.zip(profiler::thread_allocations()).map(|(start, end)| end - start);
+    self.frame_number += 1;
+    crash_report::set_field("frame", self.frame_number);
+    submit_hazard?;
+    match PresentOutcome::new(present_res, suboptimal)? {
+      PresentOutcome::Presented => Ok(false),
+      PresentOutcome::Suboptimal => self.refresh_swapchain().map(|_| false),
+      PresentOutcome::OutOfDate => self.refresh_swapchain().map(|_| true),
+    }
+  }
 
+  fn record_frame(&mut self, frame_idx: usize, image_idx: u32) -> Result<(), String> {
     if !self.swapchain.initialized() {
       self
         .swapchain
-        .initialize(&self.render_cmd_buffers[image_idx as usize])
+        .initialize(&self.render_cmd_buffers[frame_idx])
         .map_err(|e| format!("at adding init cmds:  {e}"))?;
 
-      self.render_cmd_buffers[image_idx as usize]
-        .submit(&[], &[], Some(&self.image_acquire_fence))
+      self.render_cmd_buffers[frame_idx]
+        .submit(&[], &[], Some(&self.setup_fence))
         .map_err(|e| format!("error submitting cmds: {e}"))?;
 
-      self.image_acquire_fence.wait_and_reset(999999999)?;
+      self.setup_fence.wait_and_reset(999999999)?;
       self.swapchain.set_initialized();
     }
 
-    let current_sc_res = self.swapchain.resolution();
+    let scene_res = Self::scene_resolution(self.swapchain.resolution(), &self.config);
     let triangle_out_image_res =
       self.triangle_frame_buffers[0].attachments()[0].image().resolution();
-    if current_sc_res.height != triangle_out_image_res.height
-      || current_sc_res.width != triangle_out_image_res.width
+    if scene_res.height != triangle_out_image_res.height
+      || scene_res.width != triangle_out_image_res.width
+      || std::mem::take(&mut self.rebuild_targets)
     {
-      self.triangle_frame_buffers = self.tri_mesh_tex_renderer.create_framebuffers(
-        &self.render_cmd_buffers[0],
+      // Older frames may still be rendering into the framebuffers being replaced
+      self.frame_sync.wait_all()?;
+      self.triangle_frame_buffers = self.tri_mesh_renderer.create_framebuffers(
+        &self.render_cmd_buffers[frame_idx],
         self.gen_allocator.clone(),
-        current_sc_res,
-        3,
+        scene_res,
+        self.render_cmd_buffers.len(),
+        color::scene_color_usage(),
       )?;
       for (i, fb) in self.triangle_frame_buffers.iter_mut().enumerate() {
         fb.attachments()[0]
@@ -416,35 +3646,127 @@ impl RenderManager {
           .map_err(|e| format!("at getting image mem lock: {e}"))?
           .rename(&format!("triangle_depth_image_{i}"))?;
       }
+      self.particle_depth_dsets =
+        self.particle_renderer.create_depth_dsets(&self.triangle_frame_buffers)?;
+      if let Some(mesh_culler) = &mut self.mesh_culler {
+        mesh_culler
+          .resize_pyramid(&self.render_cmd_buffers[frame_idx], &self.triangle_frame_buffers)?;
+      }
+      let transient_images = post_transient_images(
+        self.ash_device.clone(),
+        self.gen_allocator.clone(),
+        &self.triangle_frame_buffers,
+        self.post_process.as_ref().is_some_and(|post_process| post_process.has_motion_blur()),
+        self.depth_of_field.is_some(),
+      )?;
+      if let Some(post_process) = &mut self.post_process {
+        post_process.resize(
+          &self.render_cmd_buffers[frame_idx],
+          &self.triangle_frame_buffers,
+          &transient_images,
+        )?;
+      }
+      self.exposure.resize(&self.triangle_frame_buffers)?;
+      self.film_effects.resize(&self.triangle_frame_buffers, &transient_images)?;
+      self.depth_readback.resize(&self.triangle_frame_buffers)?;
+      self.world_labels.resize(&self.triangle_frame_buffers)?;
+      self.frame_export.resize();
+      if let Some(depth_of_field) = &mut self.depth_of_field {
+        depth_of_field.resize(&self.triangle_frame_buffers, &transient_images)?;
+      }
+      self.shadows.resize(&self.render_cmd_buffers[frame_idx], scene_res)?;
+      self.ui_layer.clear_targets();
+    }
+
+    let water_planes = self.water_registry.values().cloned().collect::<Vec<_>>();
+    let reflection_height =
+      water_planes.iter().find(|plane| plane.reflection()).map(|plane| plane.height());
+    if reflection_height.is_some() {
+      self.refresh_water_reflection(frame_idx)?;
+    }
+    self.refresh_minimap(frame_idx)?;
+    let swapchain_res = self.swapchain.resolution();
+    let viewport = view_policy::scene_viewport(&self.config.view, swapchain_res);
+    let hud_shown = self.loading_progress.is_none()
+      && (self.memory_heatmap.is_some()
+        || self.perf_hud.is_some()
+        || !self.ui_shapes.is_empty()
+        || !self.world_labels.is_empty());
+    if hud_shown {
+      self.refresh_ui_layer(frame_idx, viewport.extent)?;
     }
 
     // Camera update
-    let current_aspect_ratio = self.triangle_frame_buffers[image_idx as usize].resolution().width
+    let current_aspect_ratio = self.triangle_frame_buffers[frame_idx].resolution().width
       as f32
-      / self.triangle_frame_buffers[image_idx as usize].resolution().height as f32;
-    self.camera.refresh_vp_matrix(1.5, current_aspect_ratio);
+      / self.triangle_frame_buffers[frame_idx].resolution().height as f32;
+    self.camera.refresh_vp_matrix(self.projection_fov(), current_aspect_ratio);
 
-    self.render_cmd_buffers[image_idx as usize]
+    profile_scope!("record_cmds");
+    self.render_cmd_buffers[frame_idx]
       .begin(vk::CommandBufferUsageFlags::default())
       .map_err(|e| format!("at beginning render cmd buffer:  {e}"))?;
+    self.gpu_timer.begin(&self.render_cmd_buffers[frame_idx], frame_idx);
 
+    let hud_rects = match self.loading_progress {
+      Some(progress) => {
+        self.record_loading_screen(frame_idx, progress, current_aspect_ratio)?;
+        vec![]
+      }
+      None => {
+        self.record_scene(frame_idx, current_aspect_ratio, &water_planes, reflection_height)?
+      }
+    };
+    // With a HUD up the frame shown is the UI layer with the scene under it
+    let ui_drawn = hud_shown
+      && (!hud_rects.is_empty() || !self.ui_shapes.is_empty() || !self.world_labels.is_empty());
+    if ui_drawn {
+      profile_scope!("ui_layer");
+      let layer_resolution = glam::vec2(viewport.extent.width as f32, viewport.extent.height as f32);
+      let mut ui_shapes = self
+        .perf_hud
+        .as_ref()
+        .map(|perf_hud| perf_hud.labels(layer_resolution))
+        .unwrap_or_default();
+      ui_shapes.extend(self.world_labels.shapes(layer_resolution, self.ui_layer.font()));
+      ui_shapes.extend_from_slice(&self.ui_shapes);
+      self.ui_layer.record(
+        &self.render_cmd_buffers[frame_idx],
+        frame_idx,
+        &self.triangle_frame_buffers[frame_idx],
+        self.camera,
+        &hud_rects,
+        &ui_shapes,
+      )?;
+    }
+    let shown_frame_buffer = match ui_drawn {
+      true => self.ui_layer.frame_buffer(frame_idx),
+      false => &self.triangle_frame_buffers[frame_idx],
+    };
+    if let Some(srgb_encode_renderer) = &self.srgb_encode_renderer {
+      let cmd_buffer = &self.render_cmd_buffers[frame_idx];
+      let color_view = &shown_frame_buffer.attachments()[0];
+      let color =
+        VulkanImage::from_view(color_view.clone(), vk::ImageLayout::TRANSFER_SRC_OPTIMAL)?;
+      let mut commands = self.backend_device.record_into(cmd_buffer);
+      srgb_encode_renderer.encode(&mut commands, &color)?;
+      self.retired_resources.push((self.frame_number, Arc::new(commands.finish())));
+      // Back to where the render passes leave it, for the blit
+      color_view.transition_from_general(
+        cmd_buffer,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::AccessFlags::TRANSFER_READ,
+      );
+    }
+    self.frame_export.record(
+      &self.render_cmd_buffers[frame_idx],
+      frame_idx,
+      shown_frame_buffer.attachments()[0].image(),
+      self.frame_number,
+    )?;
 
-    // Use default flat tex for meshes without tex
-    let filled_flat_tex = mesh_ftex_list
-      .iter()
-      .map(|(mesh, opt_flat_tex)| {
-        (mesh.clone(), opt_flat_tex.clone().unwrap_or(self.flat_tex_gen.get_default_texture()))
-      })
-      .collect::<Vec<_>>();
-
-    self.tri_mesh_tex_renderer.render(
-      &self.render_cmd_buffers[image_idx as usize],
-      &self.triangle_frame_buffers[image_idx as usize],
-      self.camera,
-      &filled_flat_tex,
-    );
-
-    self.render_cmd_buffers[image_idx as usize].pipeline_barrier(
+    self.render_cmd_buffers[frame_idx].pipeline_barrier(
       vk::PipelineStageFlags::TRANSFER,
       vk::PipelineStageFlags::TRANSFER,
       vk::DependencyFlags::BY_REGION,
@@ -468,8 +3790,20 @@ impl RenderManager {
         .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)],
     );
 
-    self.render_cmd_buffers[image_idx as usize].blit_image(
-      self.triangle_frame_buffers[image_idx as usize].attachments()[0].image().inner(),
+    // Black bars around a letter or pillarboxed scene
+    if viewport.extent != swapchain_res {
+      self.render_cmd_buffers[frame_idx].clear_color_image(
+        self.swapchain.get_image(image_idx as usize),
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        &vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] },
+        &[vk::ImageSubresourceRange::default()
+          .aspect_mask(vk::ImageAspectFlags::COLOR)
+          .layer_count(1)
+          .level_count(1)],
+      );
+    }
+    self.render_cmd_buffers[frame_idx].blit_image(
+      shown_frame_buffer.attachments()[0].image().inner(),
       vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
       self.swapchain.get_image(image_idx as usize),
       vk::ImageLayout::TRANSFER_DST_OPTIMAL,
@@ -481,11 +3815,7 @@ impl RenderManager {
             .base_array_layer(0)
             .layer_count(1),
         )
-        .src_offsets(
-          self.triangle_frame_buffers[image_idx as usize].attachments()[0]
-            .image()
-            .full_range_offset_3d(),
-        )
+        .src_offsets(shown_frame_buffer.attachments()[0].image().full_range_offset_3d())
         .dst_subresource(
           vk::ImageSubresourceLayers::default()
             .aspect_mask(vk::ImageAspectFlags::COLOR)
@@ -493,11 +3823,21 @@ impl RenderManager {
             .base_array_layer(0)
             .layer_count(1),
         )
-        .dst_offsets(self.swapchain.full_range_offset_3d())],
-      vk::Filter::NEAREST,
+        .dst_offsets([
+          vk::Offset3D { x: viewport.offset.x, y: viewport.offset.y, z: 0 },
+          vk::Offset3D {
+            x: viewport.offset.x + viewport.extent.width as i32,
+            y: viewport.offset.y + viewport.extent.height as i32,
+            z: 1,
+          },
+        ])],
+      match ui_drawn || self.config.render_scale == 1.0 {
+        true => vk::Filter::NEAREST,
+        false => vk::Filter::LINEAR,
+      },
     );
 
-    self.render_cmd_buffers[image_idx as usize].pipeline_barrier(
+    self.render_cmd_buffers[frame_idx].pipeline_barrier(
       vk::PipelineStageFlags::TRANSFER,
       vk::PipelineStageFlags::TRANSFER,
       vk::DependencyFlags::BY_REGION,
@@ -520,38 +3860,122 @@ impl RenderManager {
         .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
         .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)],
     );
+    self.gpu_timer.end(&self.render_cmd_buffers[frame_idx], frame_idx);
 
-    self.render_cmd_buffers[image_idx as usize]
+    self.render_cmd_buffers[frame_idx]
       .end()
       .map_err(|e| format!("at ending render cmd buffer: {e}"))?;
+    self.draw_calls = self.render_cmd_buffers[frame_idx].draw_calls();
+    Ok(())
+  }
 
-    self.render_cmd_buffers[image_idx as usize]
-      .submit(
-        &[&self.render_semaphores[image_idx as usize]],
-        &[],
-        Some(&self.render_fences[image_idx as usize]),
-      )
-      .map_err(|e| format!("error submitting cmds: {e}"))?;
+  // Waits on the acquire semaphore with an empty submit and presents the image as it is, so the
+  // semaphore chain is where a drawn frame would've left it
+  fn abandon_frame(&mut self, frame_idx: usize, image_idx: u32) -> Result<(), String> {
+    self.render_cmd_buffers[frame_idx].reset()?;
+    self.frame_sync.release_acquired(&self.queues[&GPUQueueType::Graphics], image_idx)?;
+    let present_res =
+      self.swapchain.present_image(image_idx, &[self.frame_sync.render_semaphore(image_idx)]);
+    self.frame_sync.mark_presented(image_idx);
+    self.frame_sync.advance();
+    match PresentOutcome::new(present_res, false)? {
+      PresentOutcome::Presented | PresentOutcome::Suboptimal => Ok(()),
+      PresentOutcome::OutOfDate => self.refresh_swapchain(),
+    }
+  }
 
-    if let Err(e) =
-      self.swapchain.present_image(image_idx, vec![&self.render_semaphores[image_idx as usize]])
-    {
-      if e.ends_with("ERROR_OUT_OF_DATE_KHR") {
-        let _ = self
-          .swapchain
-          .refresh_resolution()
-          .inspect_err(|e| eprintln!("at refreshing swapchain res: {e}"));
-        return Ok(true);
-      }
+  pub fn set_sync_validation(&mut self, validate: bool) {
+    self.frame_sync.set_validation(validate);
+  }
+
+  // The gpu behind the graphics_backend traits, for code written to run on any backend
+  pub fn graphics_device(&self) -> Result<VulkanDevice, String> {
+    VulkanDevice::new(
+      self.ash_device.clone(),
+      self.gen_allocator.clone(),
+      self.queues[&GPUQueueType::Graphics].clone(),
+    )
+  }
+
+  // For tuning how many sets the asset pools start with
+  pub fn descriptor_pool_stats(&self) -> Result<Vec<AdDescriptorPoolStats>, String> {
+    [
+      self.tri_mesh_gen.mesh_dset_pools(),
+      self.skinned_mesh_gen.skin_dset_pools(),
+      self.cloth_gen.sim_dset_pools(),
+      self.flat_tex_gen.tex_dset_pools(),
+      self.material_gen.material_dset_pools(),
+      self.particle_gen.particle_dset_pools(),
+      self.crowd_gen.crowd_dset_pools(),
+      self.water_gen.water_dset_pools(),
+      self.foliage_gen.foliage_dset_pools(),
+    ]
+    .iter()
+    .map(|dset_pools| dset_pools.stats())
+    .collect()
+  }
+
+  pub fn texture_dedup_stats(&self) -> FlatTextureDedupStats {
+    self.flat_tex_gen.dedup_stats()
+  }
+
+  fn refresh_swapchain(&mut self) -> Result<(), String> {
+    self.frame_sync.wait_all()?;
+    self.input_latency.swapchain_refreshed();
+    let _ = self
+      .swapchain
+      .refresh_resolution()
+      .inspect_err(|e| log!("at refreshing swapchain res: {e}"));
+    self.publish_view();
+    self.frame_sync.resize_swapchain_images(self.swapchain.get_image_count())
+  }
+
+  // For HUDs to lay themselves out in, see ViewResized
+  fn publish_view(&self) {
+    let viewport = view_policy::scene_viewport(&self.config.view, self.swapchain.resolution());
+    let safe_area = view_policy::hud_safe_area(&self.config.view, viewport);
+    event_bus::global().publish(ViewResized {
+      viewport: view_policy::rect_array(viewport),
+      safe_area: view_policy::rect_array(safe_area),
+      ui_scale: ui_layer::resolve_ui_scale(self.config.view.ui_scale, self.window_scale_factor),
+    });
+  }
+
+  // Nothing is destroyed until the gpu is idle, then resources go before the generators and
+  // renderers they were made with, and the device last. Debug builds report what outlived it
+  pub fn shutdown(mut self) -> Result<(), String> {
+    self.stop_recording();
+    self.frame_sync.wait_all()?;
+    self.ash_device.wait_idle()?;
+    self.retired_resources.clear();
+    self.draw_batches.clear();
+    self.skinned_meshes.clear();
+    self.cloths.clear();
+    self.gizmo_meshes.clear();
+    self.minimap = None;
+    let ash_device = self.ash_device.clone();
+    drop(self);
+    if cfg!(debug_assertions) {
+      Self::report_leaks(&ash_device)?;
+    }
+    Ok(())
+  }
+
+  // Anything still holding the device or an allocator after the render manager is gone leaked
+  fn report_leaks(ash_device: &Arc<AdAshDevice>) -> Result<(), String> {
+    let device_refs = Arc::strong_count(ash_device) - 1;
+    if device_refs > 0 {
+      log!("leak: {device_refs} references to the device outlived the renderer");
+    }
+    for (name, bytes) in ash_device.memory_diagnostics()?.usage_by_name() {
+      log!("leak: allocation {name} of {bytes} bytes still live");
     }
-    Ok(false)
+    Ok(())
   }
 }
 
 impl Drop for RenderManager {
   fn drop(&mut self) {
-    for fence in self.render_fences.iter() {
-      let _ = fence.wait_and_reset(999999999);
-    }
+    let _ = self.frame_sync.wait_all().inspect_err(|e| log!("at waiting for frames: {e}"));
   }
 }
@@ -0,0 +1,134 @@
+use renderables::{glam, light::LocalLight, Camera3D};
+use renderers::cull_renderers::frustum_planes;
+
+// Lights fade in from the threshold up to this many times it instead of popping in
+const FADE_RANGE: f32 = 2.0;
+
+// Rough share of the screen the light's range covers times how bright it is. 0 when its range is
+// out of view
+pub fn light_contribution(light: &LocalLight, camera: Camera3D, fov: f32) -> f32 {
+  let center = light.position.extend(1.0);
+  if frustum_planes(camera.view_proj_mat).iter().any(|plane| plane.dot(center) < -light.range) {
+    return 0.0;
+  }
+  let distance = light.position.distance(camera.pos.truncate());
+  let coverage = match distance > light.range {
+    true => ((light.range / distance).asin().tan() / (fov * 0.5).tan()).powi(2).min(1.0),
+    false => 1.0,
+  };
+  let color = glam::Vec4::from(light.color);
+  coverage * light.intensity * color.x.max(color.y).max(color.z)
+}
+
+// Keeps dense scenes from shading and shadowing every light they have
+pub struct LightCulling {
+  min_contribution: f32,
+  max_shadowed: usize,
+}
+
+impl LightCulling {
+  pub fn new(min_contribution: f32, max_shadowed: usize) -> Self {
+    Self { min_contribution, max_shadowed }
+  }
+
+  // Lights contributing over the threshold, most first. Ones close to it are dimmed towards it and
+  // only the first max_shadowed casting shadows keep them
+  pub fn prioritize<'a>(
+    &self,
+    lights: impl Iterator<Item = &'a LocalLight>,
+    camera: Camera3D,
+    fov: f32,
+  ) -> Vec<LocalLight> {
+    let mut visible = lights
+      .filter_map(|light| {
+        let contribution = light_contribution(light, camera, fov);
+        (contribution > self.min_contribution).then_some((contribution, *light))
+      })
+      .collect::<Vec<_>>();
+    visible.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut shadowed = 0;
+    visible
+      .into_iter()
+      .map(|(contribution, mut light)| {
+        if self.min_contribution > 0.0 {
+          let fade_width = self.min_contribution * (FADE_RANGE - 1.0);
+          light.intensity *= ((contribution - self.min_contribution) / fade_width).min(1.0);
+        }
+        if light.casts_shadows {
+          light.casts_shadows = shadowed < self.max_shadowed;
+          shadowed += 1;
+        }
+        light
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use renderables::color::Color;
+
+  use super::*;
+  use crate::CAMERA_FOV;
+
+  fn camera() -> Camera3D {
+    let mut camera =
+      Camera3D::new(glam::vec4(0.0, 0.0, 0.0, 1.0), glam::vec4(0.0, 0.0, -1.0, 0.0), 1.0);
+    camera.refresh_vp_matrix(CAMERA_FOV, 1.0);
+    camera
+  }
+
+  fn light_at(position: glam::Vec3, range: f32) -> LocalLight {
+    LocalLight { position, range, ..Default::default() }
+  }
+
+  #[test]
+  fn lights_around_the_camera_give_their_brightest_channel() {
+    let light = LocalLight {
+      color: Color::rgb(0.5, 0.25, 0.1),
+      intensity: 2.0,
+      ..light_at(glam::vec3(1.0, 0.0, -2.0), 5.0)
+    };
+    assert_eq!(light_contribution(&light, camera(), CAMERA_FOV), 1.0);
+  }
+
+  #[test]
+  fn far_lights_fall_off_with_the_square_of_their_distance() {
+    let contribution =
+      |z| light_contribution(&light_at(glam::vec3(0.0, 0.0, z), 1.0), camera(), CAMERA_FOV);
+    assert!((contribution(-50.0) / contribution(-100.0) - 4.0).abs() < 0.01);
+  }
+
+  #[test]
+  fn lights_count_until_their_range_is_out_of_view() {
+    // About 2 units past the right side of the view
+    let beside = |range| light_at(glam::vec3(12.0, 0.0, -10.0), range);
+    assert!(light_contribution(&beside(5.0), camera(), CAMERA_FOV) > 0.0);
+    assert_eq!(light_contribution(&beside(1.0), camera(), CAMERA_FOV), 0.0);
+  }
+
+  #[test]
+  fn lights_fade_in_up_to_twice_the_threshold() {
+    let light = light_at(glam::vec3(0.0, 0.0, -30.0), 1.0);
+    let contribution = light_contribution(&light, camera(), CAMERA_FOV);
+    let kept =
+      |threshold| LightCulling::new(threshold, 0).prioritize([light].iter(), camera(), CAMERA_FOV);
+    assert!((kept(contribution / 1.5)[0].intensity - 0.5).abs() < 1e-4);
+    assert_eq!(kept(contribution / 2.5)[0].intensity, 1.0);
+    assert!(kept(contribution).is_empty());
+  }
+
+  #[test]
+  fn shadows_go_to_the_brightest_lights_casting_them() {
+    let at =
+      |z, casts_shadows| LocalLight { casts_shadows, ..light_at(glam::vec3(0.0, 0.0, z), 1.0) };
+    let lights = [at(-40.0, true), at(-10.0, false), at(-20.0, true), at(-80.0, true)];
+    let kept = LightCulling::new(0.0, 1).prioritize(lights.iter(), camera(), CAMERA_FOV);
+    let depths = kept.iter().map(|light| light.position.z).collect::<Vec<_>>();
+    assert_eq!(depths, vec![-10.0, -20.0, -40.0, -80.0]);
+    // Without a threshold nothing is dimmed
+    assert!(kept.iter().all(|light| light.intensity == 1.0));
+    let shadowed = kept.iter().map(|light| light.casts_shadows).collect::<Vec<_>>();
+    assert_eq!(shadowed, vec![false, true, false, false]);
+  }
+}
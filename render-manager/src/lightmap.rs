@@ -0,0 +1,624 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use ash_ad_wrappers::ash_data_wrappers::image;
+use renderables::{
+  color::Color,
+  glam,
+  light::{LightShape, LocalLight, SunLight, DEFAULT_SUN_DIR},
+  triangle_mesh::TriMeshCPU,
+};
+
+// Light is stored divided by this so overbright spots fit in 8 bits, LIGHTMAP_RANGE in
+// triangle_material.glsl has to match
+pub const LIGHTMAP_RANGE: f32 = 4.0;
+// Triangles turned further than this from the first one of a chart start a chart of their own
+const CHART_NORMAL_COS: f32 = 0.9;
+// Each try that doesn't fit the charts shrinks the texels per unit by a fifth
+const PACK_ATTEMPTS: i32 = 16;
+// Rays start this far off surfaces so they don't hit the one they left
+const RAY_BIAS: f32 = 1e-3;
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightmapSettings {
+  // Texels across the square lightmap
+  pub resolution: u32,
+  // Texels across a unit of the meshes, lowered until every chart fits
+  pub texels_per_unit: f32,
+  // Empty texels around each chart, so filtering at its border doesn't read its neighbours
+  pub padding: u32,
+  // Passes carrying light one bounce further each, the sky is gathered in the first. 0 adds the
+  // sky everywhere without any occlusion
+  pub bounces: u32,
+  // Rays per texel in each bounce pass
+  pub samples: u32,
+}
+
+impl Default for LightmapSettings {
+  fn default() -> Self {
+    Self { resolution: 512, texels_per_unit: 8.0, padding: 2, bounces: 1, samples: 64 }
+  }
+}
+
+// Triangles facing about the same way that share vertices, flattened onto their plane together
+struct Chart {
+  mesh: usize,
+  triangles: Vec<usize>,
+  // Corners of each triangle on the chart's plane, in the mesh's units
+  corners: Vec<[glam::Vec2; 3]>,
+  min: glam::Vec2,
+  size: glam::Vec2,
+}
+
+fn mesh_charts(mesh_idx: usize, mesh: &TriMeshCPU) -> Vec<Chart> {
+  let positions =
+    |triangle: &[u32; 3]| triangle.map(|idx| mesh.vertices[idx as usize].pos.truncate());
+  let normals = mesh
+    .triangles
+    .iter()
+    .map(|triangle| {
+      let [a, b, c] = positions(triangle);
+      (b - a).cross(c - a).normalize_or_zero()
+    })
+    .collect::<Vec<_>>();
+  let mut vertex_triangles = vec![vec![]; mesh.vertices.len()];
+  for (i, triangle) in mesh.triangles.iter().enumerate() {
+    for idx in triangle {
+      vertex_triangles[*idx as usize].push(i);
+    }
+  }
+
+  let mut charted = vec![false; mesh.triangles.len()];
+  let mut charts = vec![];
+  for seed in 0..mesh.triangles.len() {
+    if charted[seed] {
+      continue;
+    }
+    charted[seed] = true;
+    let normal = normals[seed];
+    let mut triangles = vec![seed];
+    let mut next = 0;
+    while let Some(triangle) = triangles.get(next).copied() {
+      for idx in mesh.triangles[triangle] {
+        for &neighbour in vertex_triangles[idx as usize].iter() {
+          if !charted[neighbour] && normals[neighbour].dot(normal) >= CHART_NORMAL_COS {
+            charted[neighbour] = true;
+            triangles.push(neighbour);
+          }
+        }
+      }
+      next += 1;
+    }
+    let (u, v) = normal.normalize_or(glam::Vec3::Y).any_orthonormal_pair();
+    let corners = triangles
+      .iter()
+      .map(|triangle| {
+        positions(&mesh.triangles[*triangle]).map(|pos| glam::vec2(pos.dot(u), pos.dot(v)))
+      })
+      .collect::<Vec<_>>();
+    let (min, max) =
+      corners.iter().flatten().fold((glam::Vec2::MAX, glam::Vec2::MIN), |(min, max), corner| {
+        (min.min(*corner), max.max(*corner))
+      });
+    charts.push(Chart { mesh: mesh_idx, triangles, corners, min, size: max - min });
+  }
+  charts
+}
+
+// Charts in rows, tallest first. Where each chart's min corner goes in texels, None if they don't
+// all fit
+fn pack_charts(
+  charts: &[Chart],
+  scale: f32,
+  settings: &LightmapSettings,
+) -> Option<Vec<glam::Vec2>> {
+  let padding = settings.padding as f32;
+  let padded = |chart: &Chart| (chart.size * scale).ceil() + 2.0 * padding;
+  let mut order = (0..charts.len()).collect::<Vec<_>>();
+  order.sort_by(|a, b| padded(&charts[*b]).y.total_cmp(&padded(&charts[*a]).y));
+  let resolution = settings.resolution as f32;
+  let mut offsets = vec![glam::Vec2::ZERO; charts.len()];
+  let (mut x, mut y, mut row_height) = (0.0, 0.0, 0.0f32);
+  for idx in order {
+    let size = padded(&charts[idx]);
+    if x + size.x > resolution {
+      (x, y, row_height) = (0.0, y + row_height, 0.0);
+    }
+    if x + size.x > resolution || y + size.y > resolution {
+      return None;
+    }
+    offsets[idx] = glam::vec2(x, y) + padding;
+    x += size.x;
+    row_height = row_height.max(size.y);
+  }
+  Some(offsets)
+}
+
+// Lays the meshes out together in one lightmap as their second uvs. Vertices on the border between
+// charts are split between them, so vertex counts grow. Sizes are in the meshes' own units, they
+// should be placed without scaling. Meshes that already have second uvs, like ones imported with
+// their lightmap layout, are left as they are
+pub fn unwrap_lightmap_uvs(
+  meshes: &mut [TriMeshCPU],
+  settings: &LightmapSettings,
+) -> Result<(), String> {
+  let mut charts = vec![];
+  for (i, mesh) in meshes.iter().enumerate() {
+    if mesh.streams.uv2.is_some() {
+      continue;
+    }
+    if let Some(idx) =
+      mesh.triangles.iter().flatten().find(|idx| **idx as usize >= mesh.vertices.len())
+    {
+      return Err(format!("mesh {i} uses vertex {idx} of {}", mesh.vertices.len()));
+    }
+    charts.extend(mesh_charts(i, mesh));
+  }
+  let resolution = settings.resolution;
+  let (scale, offsets) = (0..PACK_ATTEMPTS)
+    .map(|attempt| settings.texels_per_unit * 0.8f32.powi(attempt))
+    .find_map(|scale| pack_charts(&charts, scale, settings).map(|offsets| (scale, offsets)))
+    .ok_or(format!("{} charts don't fit in a {resolution}x{resolution} lightmap", charts.len()))?;
+
+  let mut unwrapped: HashMap<usize, TriMeshCPU> = HashMap::new();
+  for (chart, offset) in charts.iter().zip(offsets) {
+    let source = &meshes[chart.mesh];
+    let mesh = unwrapped.entry(chart.mesh).or_insert_with(|| {
+      let mut mesh = TriMeshCPU::new(vec![], vec![]);
+      mesh.streams.colors = source.streams.colors.as_ref().map(|_| vec![]);
+      mesh.streams.uv2 = Some(vec![]);
+      mesh
+    });
+    // Vertices shared inside a chart stay shared, they land on the same spot
+    let mut remap = HashMap::new();
+    for (triangle, corners) in chart.triangles.iter().zip(chart.corners.iter()) {
+      let mut new_triangle = [0; 3];
+      for (corner, idx) in source.triangles[*triangle].iter().enumerate() {
+        new_triangle[corner] = *remap.entry(*idx).or_insert_with(|| {
+          let uv2 = (offset + (corners[corner] - chart.min) * scale) / resolution as f32;
+          mesh.vertices.push(source.vertices[*idx as usize]);
+          if let (Some(colors), Some(source_colors)) =
+            (mesh.streams.colors.as_mut(), source.streams.colors.as_ref())
+          {
+            colors.push(source_colors[*idx as usize]);
+          }
+          mesh.streams.uv2.get_or_insert_with(Vec::new).push(uv2);
+          mesh.vertices.len() as u32 - 1
+        });
+      }
+      mesh.triangles.push(new_triangle);
+    }
+  }
+  for (i, mesh) in unwrapped {
+    meshes[i] = mesh;
+  }
+  Ok(())
+}
+
+// A mesh placed in the level to bake light onto, where it goes in the lightmap is in its second uvs
+pub struct LightmapInstance<'a> {
+  pub mesh: &'a TriMeshCPU,
+  pub transform: glam::Mat4,
+  // Share of the light reaching it that bounces off
+  pub albedo: Color,
+}
+
+// Lights baked in. All of them are shadowed, casts_shadows only picks lights for the shadow atlas
+// at runtime
+#[derive(Debug, Clone)]
+pub struct LightmapLights {
+  pub sun: Option<SunLight>,
+  pub local: Vec<LocalLight>,
+  // Light from wherever the sky is seen, as much as it gives a surface open to all of it
+  pub sky: Color,
+}
+
+struct BakeTriangle {
+  corners: [glam::Vec3; 3],
+  normals: [glam::Vec3; 3],
+  uvs: [glam::Vec2; 3],
+  albedo: glam::Vec3,
+}
+
+impl BakeTriangle {
+  fn interpolate<T>(values: [T; 3], bary: glam::Vec3) -> T
+  where
+    T: std::ops::Mul<f32, Output = T> + std::ops::Add<Output = T>,
+  {
+    let [a, b, c] = values;
+    a * bary.x + b * bary.y + c * bary.z
+  }
+
+  fn centroid(&self) -> glam::Vec3 {
+    (self.corners[0] + self.corners[1] + self.corners[2]) / 3.0
+  }
+}
+
+// Same layout as the static collision mesh's. Leaves have count > 0 and own triangles
+// first..first + count, inner nodes have count 0 and first is the index of the right child
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+  min: glam::Vec3,
+  max: glam::Vec3,
+  first: u32,
+  count: u32,
+}
+
+struct RayHit {
+  triangle: usize,
+  // Weights of the three corners
+  bary: glam::Vec3,
+}
+
+struct TriangleBvh {
+  triangles: Vec<BakeTriangle>,
+  nodes: Vec<BvhNode>,
+}
+
+impl TriangleBvh {
+  fn new(triangles: Vec<BakeTriangle>) -> Self {
+    let mut bvh = Self { triangles, nodes: vec![] };
+    if !bvh.triangles.is_empty() {
+      bvh.build_node(0, bvh.triangles.len());
+    }
+    bvh
+  }
+
+  // Splits at the median centroid along the longest side, triangles are reordered in place
+  fn build_node(&mut self, first: usize, count: usize) {
+    let (min, max) = self.triangles[first..first + count]
+      .iter()
+      .flat_map(|triangle| triangle.corners)
+      .fold((glam::Vec3::MAX, glam::Vec3::MIN), |(min, max), corner| {
+        (min.min(corner), max.max(corner))
+      });
+    let node_idx = self.nodes.len();
+    self.nodes.push(BvhNode { min, max, first: first as u32, count: count as u32 });
+    if count <= MAX_LEAF_TRIANGLES {
+      return;
+    }
+    let size = max - min;
+    let axis = match size.max_element() {
+      x if x == size.x => 0,
+      y if y == size.y => 1,
+      _ => 2,
+    };
+    let half = count / 2;
+    self.triangles[first..first + count]
+      .select_nth_unstable_by(half, |a, b| a.centroid()[axis].total_cmp(&b.centroid()[axis]));
+    self.build_node(first, half);
+    self.nodes[node_idx].first = self.nodes.len() as u32;
+    self.nodes[node_idx].count = 0;
+    self.build_node(first + half, count - half);
+  }
+
+  fn ray_triangle(
+    origin: glam::Vec3,
+    dir: glam::Vec3,
+    triangle: &BakeTriangle,
+  ) -> Option<(f32, glam::Vec2)> {
+    let [a, b, c] = triangle.corners;
+    let (edge_1, edge_2) = (b - a, c - a);
+    let p = dir.cross(edge_2);
+    let det = edge_1.dot(p);
+    if det.abs() < f32::EPSILON {
+      return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+      return None;
+    }
+    let q = s.cross(edge_1);
+    let v = dir.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+      return None;
+    }
+    let t = edge_2.dot(q) * inv_det;
+    (t >= 0.0).then_some((t, glam::vec2(u, v)))
+  }
+
+  // Closest hit along the ray, or any hit at all when any_hit is set. dir has to be normalized
+  fn raycast(
+    &self,
+    origin: glam::Vec3,
+    dir: glam::Vec3,
+    max_dist: f32,
+    any_hit: bool,
+  ) -> Option<RayHit> {
+    let inv_dir = dir.recip();
+    let mut closest: Option<(f32, usize, glam::Vec2)> = None;
+    let mut stack = Vec::from_iter((!self.nodes.is_empty()).then_some(0));
+    while let Some(node_idx) = stack.pop() {
+      let node = self.nodes[node_idx];
+      let max_dist = closest.map(|x| x.0).unwrap_or(max_dist);
+      let t_1 = (node.min - origin) * inv_dir;
+      let t_2 = (node.max - origin) * inv_dir;
+      if t_1.min(t_2).max_element().max(0.0) > t_1.max(t_2).min_element().min(max_dist) {
+        continue;
+      }
+      if node.count == 0 {
+        stack.push(node.first as usize);
+        stack.push(node_idx + 1);
+        continue;
+      }
+      for triangle_idx in node.first as usize..(node.first + node.count) as usize {
+        let Some((t, uv)) = Self::ray_triangle(origin, dir, &self.triangles[triangle_idx]) else {
+          continue;
+        };
+        if t <= max_dist && closest.is_none_or(|x| t < x.0) {
+          closest = Some((t, triangle_idx, uv));
+          if any_hit {
+            stack.clear();
+            break;
+          }
+        }
+      }
+    }
+    closest
+      .map(|(_, triangle, uv)| RayHit { triangle, bary: glam::vec3(1.0 - uv.x - uv.y, uv.x, uv.y) })
+  }
+
+  fn occluded(&self, origin: glam::Vec3, dir: glam::Vec3, max_dist: f32) -> bool {
+    self.raycast(origin, dir, max_dist, true).is_some()
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Texel {
+  pos: glam::Vec3,
+  normal: glam::Vec3,
+}
+
+// World position and normal at the center of every texel a triangle covers
+fn rasterize(triangles: &[BakeTriangle], resolution: u32) -> Vec<Option<Texel>> {
+  let size = resolution as f32;
+  let mut texels = vec![None; (resolution * resolution) as usize];
+  for triangle in triangles {
+    let uvs = triangle.uvs.map(|uv| uv * size);
+    let (edge_1, edge_2) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+    let area = edge_1.perp_dot(edge_2);
+    if area.abs() < f32::EPSILON {
+      continue;
+    }
+    let min = uvs[0].min(uvs[1]).min(uvs[2]).floor().max(glam::Vec2::ZERO);
+    let max = uvs[0].max(uvs[1]).max(uvs[2]).ceil().min(glam::Vec2::splat(size));
+    for y in min.y as u32..max.y as u32 {
+      for x in min.x as u32..max.x as u32 {
+        let offset = glam::vec2(x as f32 + 0.5, y as f32 + 0.5) - uvs[0];
+        let (b_1, b_2) = (offset.perp_dot(edge_2) / area, edge_1.perp_dot(offset) / area);
+        if b_1 < -1e-4 || b_2 < -1e-4 || b_1 + b_2 > 1.0 + 1e-4 {
+          continue;
+        }
+        let bary = glam::vec3(1.0 - b_1 - b_2, b_1, b_2);
+        texels[(y * resolution + x) as usize] = Some(Texel {
+          pos: BakeTriangle::interpolate(triangle.corners, bary),
+          normal: BakeTriangle::interpolate(triangle.normals, bary).normalize_or(glam::Vec3::Y),
+        });
+      }
+    }
+  }
+  texels
+}
+
+fn smoothstep(edge_0: f32, edge_1: f32, x: f32) -> f32 {
+  let t = ((x - edge_0) / (edge_1 - edge_0)).clamp(0.0, 1.0);
+  t * t * (3.0 - 2.0 * t)
+}
+
+// Falls off like local_light_diffuse in triangle_material.glsl
+fn direct_light(bvh: &TriangleBvh, lights: &LightmapLights, texel: &Texel) -> glam::Vec3 {
+  let origin = texel.pos + texel.normal * RAY_BIAS;
+  let mut light = glam::Vec3::ZERO;
+  if let Some(sun) = &lights.sun {
+    let dir = sun.direction.normalize_or(DEFAULT_SUN_DIR);
+    let facing = texel.normal.dot(dir);
+    if facing > 0.0 && !bvh.occluded(origin, dir, f32::MAX) {
+      light += sun.radiance() * facing;
+    }
+  }
+  for local in lights.local.iter() {
+    let to_light = local.position - texel.pos;
+    let dist = to_light.length();
+    if dist >= local.range || dist <= RAY_BIAS {
+      continue;
+    }
+    let dir = to_light / dist;
+    let falloff = 1.0 - dist / local.range;
+    let mut attenuation = falloff * falloff * texel.normal.dot(dir).max(0.0);
+    if let LightShape::Spot { direction, inner_angle, outer_angle } = local.shape {
+      let cos_outer = outer_angle.cos();
+      let cos_inner = inner_angle.min(outer_angle).cos().max(cos_outer + 0.0001);
+      let direction = direction.normalize_or(glam::Vec3::NEG_Y);
+      attenuation *= smoothstep(cos_outer, cos_inner, (-dir).dot(direction));
+    }
+    if attenuation > 0.0 && !bvh.occluded(origin, dir, dist - RAY_BIAS) {
+      light += glam::Vec4::from(local.color).truncate() * local.intensity * attenuation;
+    }
+  }
+  light
+}
+
+fn radical_inverse(mut bits: u32) -> f32 {
+  bits = bits.reverse_bits();
+  bits as f32 / 4_294_967_296.0
+}
+
+// Light bounced onto the texel from the last pass, and sky where rays get out. Cosine weighted
+// hammersley directions, turned by a hash of the texel so neighbours don't band together
+fn gathered_light(
+  bvh: &TriangleBvh,
+  lights: &LightmapLights,
+  last_pass: &[glam::Vec3],
+  settings: &LightmapSettings,
+  texel_idx: usize,
+  texel: &Texel,
+) -> glam::Vec3 {
+  let origin = texel.pos + texel.normal * RAY_BIAS;
+  let (tangent, bitangent) = texel.normal.any_orthonormal_pair();
+  let hash = (texel_idx as u32).wrapping_mul(0x9e37_79b9) ^ 0x85eb_ca6b;
+  let rotation = glam::vec2(radical_inverse(hash), (hash >> 8) as f32 / 16_777_216.0);
+  let sky = glam::Vec4::from(lights.sky).truncate();
+  let size = settings.resolution as f32;
+  let samples = settings.samples.max(1);
+  let mut light = glam::Vec3::ZERO;
+  for i in 0..samples {
+    let r_1 = ((i as f32 + 0.5) / samples as f32 + rotation.x).fract();
+    let r_2 = (radical_inverse(i) + rotation.y).fract();
+    let (sin, cos) = (std::f32::consts::TAU * r_2).sin_cos();
+    let dir = (tangent * cos + bitangent * sin) * r_1.sqrt() + texel.normal * (1.0 - r_1).sqrt();
+    let Some(hit) = bvh.raycast(origin, dir, f32::MAX, false) else {
+      light += sky;
+      continue;
+    };
+    let triangle = &bvh.triangles[hit.triangle];
+    // Backs of surfaces are inside something
+    if BakeTriangle::interpolate(triangle.normals, hit.bary).dot(dir) >= 0.0 {
+      continue;
+    }
+    let uv = BakeTriangle::interpolate(triangle.uvs, hit.bary) * size;
+    let texel = uv.floor().clamp(glam::Vec2::ZERO, glam::Vec2::splat(size - 1.0));
+    light += triangle.albedo * last_pass[(texel.y * size + texel.x) as usize];
+  }
+  light / samples as f32
+}
+
+// Spreads light out into the empty texels around charts, one texel per step, so filtering at their
+// borders doesn't pull in black
+fn dilate(light: &mut [glam::Vec3], texels: &[Option<Texel>], resolution: u32, steps: u32) {
+  let size = resolution as i32;
+  let mut filled = texels.iter().map(Option::is_some).collect::<Vec<_>>();
+  for _ in 0..steps {
+    let mut next_filled = filled.clone();
+    for y in 0..size {
+      for x in 0..size {
+        let idx = (y * size + x) as usize;
+        if filled[idx] {
+          continue;
+        }
+        let (mut sum, mut count) = (glam::Vec3::ZERO, 0);
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)] {
+          let (nx, ny) = (x + dx, y + dy);
+          if (0..size).contains(&nx) && (0..size).contains(&ny) && filled[(ny * size + nx) as usize]
+          {
+            sum += light[(ny * size + nx) as usize];
+            count += 1;
+          }
+        }
+        if count > 0 {
+          light[idx] = sum / count as f32;
+          next_filled[idx] = true;
+        }
+      }
+    }
+    filled = next_filled;
+  }
+}
+
+// Light reaching each texel, what a lit material would be shaded with
+pub struct Lightmap {
+  resolution: u32,
+  texels: Vec<glam::Vec3>,
+}
+
+impl Lightmap {
+  pub fn resolution(&self) -> u32 {
+    self.resolution
+  }
+
+  pub fn texel(&self, x: u32, y: u32) -> glam::Vec3 {
+    self.texels[(y * self.resolution + x) as usize]
+  }
+
+  // Srgb encoded light over LIGHTMAP_RANGE
+  pub fn to_image(&self) -> image::RgbaImage {
+    image::RgbaImage::from_fn(self.resolution, self.resolution, |x, y| {
+      let light = (self.texel(x, y) / LIGHTMAP_RANGE).min(glam::Vec3::ONE);
+      image::Rgba(Color::rgb(light.x, light.y, light.z).to_srgb8())
+    })
+  }
+
+  // Png with a .meta next to it, so it loads clamped and without mips
+  pub fn save(&self, path: &Path) -> Result<(), String> {
+    self
+      .to_image()
+      .save(path)
+      .map_err(|e| format!("at writing lightmap {}: {e}", path.display()))?;
+    let meta_path = format!("{}.meta", path.display());
+    std::fs::write(&meta_path, "(srgb: true, mips: false, address_mode: ClampToEdge)")
+      .map_err(|e| format!("at writing lightmap meta {meta_path}: {e}"))
+  }
+}
+
+// Direct light with shadows, then bounce passes gathering it back off the surfaces it reached.
+// Every instance needs second uvs, see unwrap_lightmap_uvs. Runs on the job pool, seconds to
+// minutes depending on the texels and samples, so it's meant for building levels
+pub fn bake_lightmap(
+  instances: &[LightmapInstance],
+  lights: &LightmapLights,
+  settings: &LightmapSettings,
+) -> Result<Lightmap, String> {
+  if settings.resolution == 0 {
+    return Err("lightmap resolution can't be 0".to_string());
+  }
+  let mut triangles = vec![];
+  for (i, instance) in instances.iter().enumerate() {
+    let mesh = instance.mesh;
+    let uv2 =
+      mesh.streams.uv2.as_ref().ok_or(format!("mesh {i} has no second uvs to bake into"))?;
+    if uv2.len() != mesh.vertices.len() {
+      return Err(format!(
+        "mesh {i} has {} second uvs for {} vertices",
+        uv2.len(),
+        mesh.vertices.len()
+      ));
+    }
+    let normal_transform = glam::Mat3::from_mat4(instance.transform).inverse().transpose();
+    let albedo = glam::Vec4::from(instance.albedo).truncate();
+    for triangle in mesh.triangles.iter() {
+      let vertices = triangle
+        .map(|idx| mesh.vertices.get(idx as usize).map(|vertex| (vertex, uv2[idx as usize])));
+      let [Some(a), Some(b), Some(c)] = vertices else {
+        return Err(format!("mesh {i} uses a vertex past its {}", mesh.vertices.len()));
+      };
+      triangles.push(BakeTriangle {
+        corners: [a, b, c]
+          .map(|(vertex, _)| instance.transform.transform_point3(vertex.pos.truncate())),
+        normals: [a, b, c]
+          .map(|(vertex, _)| (normal_transform * vertex.normal.truncate()).normalize_or_zero()),
+        uvs: [a.1, b.1, c.1],
+        albedo,
+      });
+    }
+  }
+  let bvh = TriangleBvh::new(triangles);
+  let texels = rasterize(&bvh.triangles, settings.resolution);
+
+  let pool = jobs::global();
+  let direct = pool.map(&texels, |texel| {
+    texel.as_ref().map_or(glam::Vec3::ZERO, |texel| direct_light(&bvh, lights, texel))
+  })?;
+  let mut light = direct.clone();
+  if settings.bounces == 0 {
+    let sky = glam::Vec4::from(lights.sky).truncate();
+    for (light, texel) in light.iter_mut().zip(texels.iter()) {
+      if texel.is_some() {
+        *light += sky;
+      }
+    }
+  }
+  dilate(&mut light, &texels, settings.resolution, settings.padding);
+  for _ in 0..settings.bounces {
+    let mut next = direct.clone();
+    pool.for_each_mut(&mut next, |i, next| {
+      if let Some(texel) = &texels[i] {
+        *next += gathered_light(&bvh, lights, &light, settings, i, texel);
+      }
+    })?;
+    dilate(&mut next, &texels, settings.resolution, settings.padding);
+    light = next;
+  }
+  Ok(Lightmap { resolution: settings.resolution, texels: light })
+}
@@ -0,0 +1,163 @@
+use std::collections::VecDeque;
+
+use renderables::{color::Color, glam};
+use renderers::debug_renderers::OverlayRect;
+
+use crate::RendererMessage;
+
+// Uploads applied per frame while loading, few enough that the screen keeps redrawing between them
+const UPLOADS_PER_FRAME: u32 = 8;
+// In normalized device coordinates, y pointing down
+const LOGO_TILE: f32 = 0.12;
+const LOGO_GAP: f32 = 0.02;
+const LOGO_CENTER_Y: f32 = -0.15;
+// Column and row of each logo tile, clockwise from the top left
+const LOGO_TILES: [(f32, f32); 4] = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+const BAR_WIDTH: f32 = 1.0;
+const BAR_HEIGHT: f32 = 0.03;
+const BAR_TOP: f32 = 0.2;
+const BAR_BORDER: f32 = 0.006;
+const BACKGROUND_COLOR: Color = Color::from_srgb(0.02, 0.02, 0.025, 1.0);
+const LOGO_COLOR: Color = Color::from_srgb(0.85, 0.45, 0.1, 1.0);
+const TRACK_COLOR: Color = Color::from_srgb(0.1, 0.1, 0.12, 1.0);
+const FILL_COLOR: Color = Color::from_srgb(0.9, 0.9, 0.9, 1.0);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadingProgress {
+  // Uploads applied so far and the ones queued before EndLoading. More uploads queued later raise
+  // the total, so the fraction can step back
+  pub done: u32,
+  pub total: u32,
+}
+
+impl LoadingProgress {
+  pub fn fraction(&self) -> f32 {
+    match self.total {
+      0 => 1.0,
+      total => self.done as f32 / total as f32,
+    }
+  }
+}
+
+// Messages that make gpu resources, what loading progress is counted in
+fn is_upload(message: &RendererMessage) -> bool {
+  matches!(
+    message,
+    RendererMessage::UploadTriMesh(..)
+      | RendererMessage::UploadMorphTriMesh(..)
+      | RendererMessage::UploadSkinnedMesh(..)
+      | RendererMessage::UploadCloth(..)
+      | RendererMessage::UploadFlatTex(..)
+      | RendererMessage::CreateMaterial(..)
+      | RendererMessage::CreateParticleSystem(..)
+      | RendererMessage::CreateCrowd(..)
+      | RendererMessage::UploadWaterPlane(..)
+      | RendererMessage::CreateFoliage(..)
+      | RendererMessage::SetEnvironment(Some(_))
+  )
+}
+
+// Messages taken from the game and not applied yet. Outside loading everything is applied at once
+// like before, between BeginLoading and EndLoading a few uploads go per frame so the loading
+// screen draws in between
+pub(crate) struct MessageBacklog {
+  messages: VecDeque<RendererMessage>,
+  // Uploads applied since BeginLoading
+  loading: Option<u32>,
+}
+
+impl MessageBacklog {
+  pub fn new() -> Self {
+    Self { messages: VecDeque::new(), loading: None }
+  }
+
+  pub fn push(&mut self, messages: Vec<RendererMessage>) {
+    self.messages.extend(messages);
+  }
+
+  // The loading markers are taken out here and never reach process_messages
+  pub fn next_batch(&mut self) -> Vec<RendererMessage> {
+    let mut batch = vec![];
+    let mut uploads = 0;
+    while let Some(message) = self.messages.front() {
+      match message {
+        RendererMessage::BeginLoading => {
+          self.loading.get_or_insert(0);
+          self.messages.pop_front();
+          continue;
+        }
+        RendererMessage::EndLoading => {
+          self.loading = None;
+          self.messages.pop_front();
+          continue;
+        }
+        _ => {}
+      }
+      if let Some(done) = &mut self.loading {
+        if is_upload(message) {
+          if uploads == UPLOADS_PER_FRAME {
+            break;
+          }
+          uploads += 1;
+          *done += 1;
+        }
+      }
+      batch.extend(self.messages.pop_front());
+    }
+    batch
+  }
+
+  // None once everything up to EndLoading is applied, the scene draws again from then
+  pub fn progress(&self) -> Option<LoadingProgress> {
+    let done = self.loading?;
+    let queued = self
+      .messages
+      .iter()
+      .take_while(|message| !matches!(message, RendererMessage::EndLoading))
+      .filter(|message| is_upload(message))
+      .count() as u32;
+    Some(LoadingProgress { done, total: done + queued })
+  }
+}
+
+fn rect(left: f32, top: f32, width: f32, height: f32, color: Color) -> OverlayRect {
+  OverlayRect { rect: glam::vec4(left, top, width, height), color }
+}
+
+// A 2x2 tile logo pulsing one tile after another so the screen looks alive through long uploads,
+// then the progress bar under it. Widths are divided by the aspect ratio to keep tiles square
+pub(crate) fn layout(
+  progress: LoadingProgress,
+  aspect_ratio: f32,
+  time_s: f32,
+) -> Vec<OverlayRect> {
+  let mut rects = vec![rect(-1.0, -1.0, 2.0, 2.0, BACKGROUND_COLOR)];
+
+  let tile_width = LOGO_TILE / aspect_ratio;
+  let gap_width = LOGO_GAP / aspect_ratio;
+  let logo_left = -(tile_width + gap_width * 0.5);
+  let logo_top = LOGO_CENTER_Y - LOGO_TILE - LOGO_GAP * 0.5;
+  for (i, (column, row)) in LOGO_TILES.into_iter().enumerate() {
+    let pulse = 0.5 + 0.5 * (time_s * 4.0 - i as f32 * std::f32::consts::FRAC_PI_2).sin();
+    rects.push(rect(
+      logo_left + column * (tile_width + gap_width),
+      logo_top + row * (LOGO_TILE + LOGO_GAP),
+      tile_width,
+      LOGO_TILE,
+      LOGO_COLOR.with_alpha(0.4 + 0.6 * pulse),
+    ));
+  }
+
+  let bar_left = -BAR_WIDTH * 0.5;
+  let border_width = BAR_BORDER / aspect_ratio;
+  rects.push(rect(
+    bar_left - border_width,
+    BAR_TOP - BAR_BORDER,
+    BAR_WIDTH + border_width * 2.0,
+    BAR_HEIGHT + BAR_BORDER * 2.0,
+    TRACK_COLOR,
+  ));
+  let fill_width = BAR_WIDTH * progress.fraction().clamp(0.0, 1.0);
+  rects.push(rect(bar_left, BAR_TOP, fill_width, BAR_HEIGHT, FILL_COLOR));
+  rects
+}
@@ -0,0 +1,191 @@
+use std::{
+  hash::{DefaultHasher, Hash, Hasher},
+  time::{Duration, Instant},
+};
+
+use ash_ad_wrappers::{
+  ash_context::{AdAshDevice, MemoryDiagnostics},
+  ash_data_wrappers::AdDescriptorPoolStats,
+};
+use renderables::{color::Color, flat_texture::FlatTextureDedupStats, glam};
+use renderers::debug_renderers::OverlayRect;
+
+// Reports lock every allocator, uploads would stall on them if taken every frame
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+// In normalized device coordinates, from the top left corner
+const PANEL_LEFT: f32 = -0.98;
+const PANEL_TOP: f32 = -0.98;
+const PANEL_PADDING: f32 = 0.01;
+const BAR_WIDTH: f32 = 0.8;
+const MARKER_WIDTH: f32 = 0.02;
+const ROW_HEIGHT: f32 = 0.025;
+const ROW_GAP: f32 = 0.008;
+// Keeps tiny allocations visible in large blocks
+const MIN_ALLOCATION_WIDTH: f32 = 0.002;
+const MAX_BLOCK_ROWS: usize = 32;
+const MAX_NAME_ROWS: usize = 8;
+const BACKGROUND_COLOR: Color = Color::BLACK.with_alpha(0.7);
+const FREE_COLOR: Color = Color::from_srgb(0.08, 0.08, 0.08, 1.0);
+const MIB: f32 = 1024.0 * 1024.0;
+
+// One row per allocator memory block with its allocations colored by name and a marker going
+// from green to red with fragmentation, then bars of the largest users by allocation name.
+// There is no text rendering, so what the rows and colors are is printed to stdout, along with
+// how full the descriptor pools are
+pub struct MemoryHeatmap {
+  refreshed_at: Option<Instant>,
+  rects: Vec<OverlayRect>,
+  // Printed again only when it changes
+  legend: Vec<String>,
+}
+
+impl MemoryHeatmap {
+  pub fn new() -> Self {
+    Self { refreshed_at: None, rects: vec![], legend: vec![] }
+  }
+
+  pub fn rects(&self) -> &[OverlayRect] {
+    &self.rects
+  }
+
+  pub fn refresh(
+    &mut self,
+    ash_device: &AdAshDevice,
+    dset_stats: &[AdDescriptorPoolStats],
+    tex_dedup_stats: FlatTextureDedupStats,
+  ) -> Result<(), String> {
+    if self.refreshed_at.is_some_and(|refreshed_at| refreshed_at.elapsed() < REFRESH_INTERVAL) {
+      return Ok(());
+    }
+    self.refreshed_at = Some(Instant::now());
+    let diagnostics = ash_device.memory_diagnostics()?;
+    let usage_by_name = diagnostics.usage_by_name();
+    let name_rows = &usage_by_name[..usage_by_name.len().min(MAX_NAME_ROWS)];
+    self.rects = Self::layout(&diagnostics, name_rows);
+
+    let legend = diagnostics
+      .allocators
+      .iter()
+      .map(|allocator| format!("{} {} blocks", allocator.name, allocator.blocks.len()))
+      .chain(name_rows.iter().map(|(name, _)| name.clone()))
+      .chain(
+        dset_stats.iter().map(|stats| format!("{} {} dset pools", stats.name, stats.pool_count)),
+      )
+      .chain([format!("{} textures deduplicated", tex_dedup_stats.deduplicated_textures)])
+      .collect::<Vec<_>>();
+    if legend != self.legend {
+      println!(
+        "memory heatmap: {:.1} of {:.1} MiB used",
+        diagnostics.used_bytes() as f32 / MIB,
+        diagnostics.reserved_bytes() as f32 / MIB
+      );
+      for allocator in diagnostics.allocators.iter() {
+        println!(
+          "  {}: {} block rows, {:.1} of {:.1} MiB used",
+          allocator.name,
+          allocator.blocks.len(),
+          allocator.used_bytes() as f32 / MIB,
+          allocator.reserved_bytes() as f32 / MIB
+        );
+      }
+      for (i, (name, bytes)) in name_rows.iter().enumerate() {
+        println!("  bar {}: {name} {:.2} MiB", i + 1, *bytes as f32 / MIB);
+      }
+      for stats in dset_stats.iter().filter(|stats| stats.pool_count > 0) {
+        println!(
+          "  {} dsets: {} of {} in {} pools, {} failed allocations",
+          stats.name,
+          stats.live_sets,
+          stats.set_capacity,
+          stats.pool_count,
+          stats.failed_allocations
+        );
+      }
+      if tex_dedup_stats.deduplicated_textures > 0 {
+        println!(
+          "  textures: {} uploaded, {} deduplicated saving {:.2} MiB",
+          tex_dedup_stats.uploaded_textures,
+          tex_dedup_stats.deduplicated_textures,
+          tex_dedup_stats.deduplicated_bytes as f32 / MIB
+        );
+      }
+      self.legend = legend;
+    }
+    Ok(())
+  }
+
+  fn layout(diagnostics: &MemoryDiagnostics, name_rows: &[(String, u64)]) -> Vec<OverlayRect> {
+    let mut rects = vec![];
+    let bar_left = PANEL_LEFT + PANEL_PADDING + MARKER_WIDTH + ROW_GAP;
+    let mut row_top = PANEL_TOP + PANEL_PADDING;
+
+    let blocks = diagnostics
+      .allocators
+      .iter()
+      .flat_map(|allocator| allocator.blocks.iter())
+      .take(MAX_BLOCK_ROWS)
+      .collect::<Vec<_>>();
+    // Bar lengths are relative to the largest block so block sizes compare too
+    let max_block_size = blocks.iter().map(|block| block.size).max().unwrap_or(1).max(1);
+    for block in blocks {
+      let fragmentation = block.fragmentation();
+      rects.push(OverlayRect {
+        rect: glam::vec4(PANEL_LEFT + PANEL_PADDING, row_top, MARKER_WIDTH, ROW_HEIGHT),
+        color: Color::GREEN.lerp(Color::RED, fragmentation),
+      });
+      let bar_width = BAR_WIDTH * block.size as f32 / max_block_size as f32;
+      rects.push(OverlayRect {
+        rect: glam::vec4(bar_left, row_top, bar_width, ROW_HEIGHT),
+        color: FREE_COLOR,
+      });
+      for allocation in block.allocations.iter() {
+        let start = allocation.offset as f32 / block.size as f32;
+        let width = allocation.size as f32 / block.size as f32;
+        rects.push(OverlayRect {
+          rect: glam::vec4(
+            bar_left + bar_width * start,
+            row_top,
+            (bar_width * width).max(MIN_ALLOCATION_WIDTH),
+            ROW_HEIGHT,
+          ),
+          color: Self::name_color(&allocation.name),
+        });
+      }
+      row_top += ROW_HEIGHT + ROW_GAP;
+    }
+
+    row_top += ROW_GAP;
+    let max_name_bytes = name_rows.first().map(|(_, bytes)| *bytes).unwrap_or(1).max(1);
+    for (name, bytes) in name_rows.iter() {
+      rects.push(OverlayRect {
+        rect: glam::vec4(
+          bar_left,
+          row_top,
+          BAR_WIDTH * *bytes as f32 / max_name_bytes as f32,
+          ROW_HEIGHT,
+        ),
+        color: Self::name_color(name),
+      });
+      row_top += ROW_HEIGHT + ROW_GAP;
+    }
+
+    let background = OverlayRect {
+      rect: glam::vec4(
+        PANEL_LEFT,
+        PANEL_TOP,
+        bar_left + BAR_WIDTH + PANEL_PADDING - PANEL_LEFT,
+        row_top - ROW_GAP + PANEL_PADDING - PANEL_TOP,
+      ),
+      color: BACKGROUND_COLOR,
+    };
+    rects.insert(0, background);
+    rects
+  }
+
+  // Same name, same color in both the block rows and the usage bars
+  fn name_color(name: &str) -> Color {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    Color::from_hsv((hasher.finish() % 360) as f32, 1.0, 1.0)
+  }
+}
@@ -0,0 +1,202 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, gpu_allocator::vulkan::Allocator},
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::AdFrameBuffer,
+};
+use renderables::{glam, Camera3D};
+use renderers::triangle_mesh_renderers::{TriMeshDraw, TriMeshMaterialRenderer};
+
+use crate::draw_list::LayerMask;
+
+// Gap between the map and the screen edges, as a fraction of the screen height
+const MINIMAP_MARGIN: f32 = 0.02;
+const MIN_MINIMAP_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MinimapSettings {
+  // World units from the main camera to the edges of the map
+  pub view_radius: f32,
+  // How far over the main camera the map looks down from, anything higher isn't drawn
+  pub height: f32,
+  // Side of the square map as a fraction of the screen height
+  pub screen_fraction: f32,
+  // Renderables the map shows, like markers on a layer the main camera doesn't see
+  pub layers: LayerMask,
+}
+
+impl Default for MinimapSettings {
+  fn default() -> Self {
+    Self { view_radius: 20.0, height: 100.0, screen_fraction: 0.25, layers: LayerMask::ALL }
+  }
+}
+
+// Orthographic, looking straight down at the main camera with -z at the top of the map
+pub fn minimap_camera(main_camera: Camera3D, settings: &MinimapSettings) -> Camera3D {
+  let center = main_camera.pos.truncate();
+  let eye = center + glam::Vec3::Y * settings.height;
+  let radius = settings.view_radius;
+  let view_proj_mat = glam::Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, 1000.0)
+    * glam::Mat4::look_at_rh(eye, center, glam::Vec3::NEG_Z);
+  Camera3D { pos: eye.extend(1.0), look_dir: glam::Vec4::NEG_Y, view_proj_mat }
+}
+
+// A second view of the scene drawn into its own framebuffers, then blitted into the top right
+// corner of the scene color like a HUD element
+pub struct Minimap {
+  settings: MinimapSettings,
+  frame_buffers: Vec<Arc<AdFrameBuffer>>,
+  // Built with the main draw batches, from the renderables on the map's layers
+  draw_batches: Vec<TriMeshDraw>,
+}
+
+impl Minimap {
+  pub fn new(settings: MinimapSettings) -> Self {
+    Self { settings, frame_buffers: vec![], draw_batches: vec![] }
+  }
+
+  pub fn settings(&self) -> &MinimapSettings {
+    &self.settings
+  }
+
+  pub fn set_settings(&mut self, settings: MinimapSettings) {
+    self.settings = settings;
+  }
+
+  pub fn draw_batches(&self) -> &[TriMeshDraw] {
+    &self.draw_batches
+  }
+
+  pub fn set_draw_batches(&mut self, draw_batches: Vec<TriMeshDraw>) {
+    self.draw_batches = draw_batches;
+  }
+
+  pub fn resolution_for(&self, scene_res: vk::Extent2D) -> vk::Extent2D {
+    let size = (scene_res.height as f32 * self.settings.screen_fraction) as u32;
+    // Tiny windows get a map as big as they are
+    let size = size.max(MIN_MINIMAP_SIZE).min(scene_res.width.min(scene_res.height).max(1));
+    vk::Extent2D { width: size, height: size }
+  }
+
+  // True before the first framebuffers are made too
+  pub fn needs_resize(&self, scene_res: vk::Extent2D) -> bool {
+    let resolution = self.resolution_for(scene_res);
+    !self.frame_buffers.first().is_some_and(|fb| fb.resolution() == resolution)
+  }
+
+  pub fn has_frame_buffers(&self) -> bool {
+    !self.frame_buffers.is_empty()
+  }
+
+  pub fn resize(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    allocator: Arc<Mutex<Allocator>>,
+    tri_mesh_renderer: &TriMeshMaterialRenderer,
+    scene_res: vk::Extent2D,
+    frames_in_flight: usize,
+  ) -> Result<(), String> {
+    self.frame_buffers = tri_mesh_renderer.create_framebuffers(
+      cmd_buffer,
+      allocator,
+      self.resolution_for(scene_res),
+      frames_in_flight,
+      vk::ImageUsageFlags::empty(),
+    )?;
+    Ok(())
+  }
+
+  pub fn frame_buffer(&self, frame_idx: usize) -> &Arc<AdFrameBuffer> {
+    &self.frame_buffers[frame_idx]
+  }
+
+  // Top right corner of the scene, in blit offsets
+  fn corner_offsets(&self, scene_res: vk::Extent2D, size: vk::Extent2D) -> [vk::Offset3D; 2] {
+    let margin = (scene_res.height as f32 * MINIMAP_MARGIN) as i32;
+    let right = scene_res.width as i32 - margin;
+    [
+      vk::Offset3D { x: right - size.width as i32, y: margin, z: 0 },
+      vk::Offset3D { x: right, y: margin + size.height as i32, z: 1 },
+    ]
+  }
+
+  // After the map and the scene are drawn. Both are in TRANSFER_SRC_OPTIMAL after their passes
+  // and the scene color is put back in it
+  pub fn composite(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+  ) {
+    let map_image = self.frame_buffers[frame_idx].attachments()[0].image();
+    let scene_image = scene_frame_buffer.attachments()[0].image();
+    let color_range = vk::ImageSubresourceRange::default()
+      .aspect_mask(vk::ImageAspectFlags::COLOR)
+      .base_mip_level(0)
+      .level_count(1)
+      .base_array_layer(0)
+      .layer_count(1);
+    let color_layers = vk::ImageSubresourceLayers::default()
+      .aspect_mask(vk::ImageAspectFlags::COLOR)
+      .mip_level(0)
+      .base_array_layer(0)
+      .layer_count(1);
+    let queue_family = cmd_buffer.cmd_pool().queue().family_index();
+
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[vk::ImageMemoryBarrier::default()
+        .image(scene_image.inner())
+        .subresource_range(color_range)
+        .src_queue_family_index(queue_family)
+        .dst_queue_family_index(queue_family)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::TRANSFER_READ)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)],
+    );
+    let map_res = self.frame_buffers[frame_idx].resolution();
+    cmd_buffer.blit_image(
+      map_image.inner(),
+      vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+      scene_image.inner(),
+      vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+      &[vk::ImageBlit::default()
+        .src_subresource(color_layers)
+        .src_offsets(map_image.full_range_offset_3d())
+        .dst_subresource(color_layers)
+        .dst_offsets(self.corner_offsets(scene_frame_buffer.resolution(), map_res))],
+      vk::Filter::NEAREST,
+    );
+    // Overlays draw over the map, then the scene is encoded or blitted to the swapchain
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+        | vk::PipelineStageFlags::COMPUTE_SHADER
+        | vk::PipelineStageFlags::TRANSFER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[vk::ImageMemoryBarrier::default()
+        .image(scene_image.inner())
+        .subresource_range(color_range)
+        .src_queue_family_index(queue_family)
+        .dst_queue_family_index(queue_family)
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(
+          vk::AccessFlags::COLOR_ATTACHMENT_READ
+            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::SHADER_READ
+            | vk::AccessFlags::SHADER_WRITE
+            | vk::AccessFlags::TRANSFER_READ,
+        )
+        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)],
+    );
+  }
+}
@@ -0,0 +1,211 @@
+use std::sync::{
+  atomic::{AtomicU64, Ordering},
+  Arc, Mutex,
+};
+
+use ash_ad_wrappers::{
+  ash_context::{
+    ash::vk,
+    gpu_allocator::{vulkan::Allocator, MemoryLocation},
+  },
+  ash_data_wrappers::AdImage,
+  ash_queue_wrappers::{AdCommandBuffer, AdQueue, MAX_SUBMIT_SEMAPHORES},
+  ash_sync_wrappers::{AdFence, AdSemaphore},
+};
+
+use crate::present::PresentTarget;
+
+// Stands in for the window an OffscreenSwapchain presents to, resized and counting presents like
+// one
+pub struct OffscreenWindow {
+  extent: Mutex<vk::Extent2D>,
+  presented_frames: AtomicU64,
+}
+
+impl OffscreenWindow {
+  pub fn new(width: u32, height: u32) -> Arc<Self> {
+    Arc::new(Self {
+      extent: Mutex::new(vk::Extent2D { width, height }),
+      presented_frames: AtomicU64::new(0),
+    })
+  }
+
+  pub fn resize(&self, width: u32, height: u32) {
+    *self.extent.lock().unwrap_or_else(|e| e.into_inner()) = vk::Extent2D { width, height };
+  }
+
+  pub fn extent(&self) -> vk::Extent2D {
+    *self.extent.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  pub fn presented_frames(&self) -> u64 {
+    self.presented_frames.load(Ordering::Acquire)
+  }
+}
+
+// Offscreen images in place of a swapchain. Acquire and present are empty submits signalling and
+// waiting on the frame semaphores, so the frame sync chain runs like it does with a real one.
+// Acquires report out of date after the window is resized until the images are refreshed
+pub struct OffscreenSwapchain {
+  window: Arc<OffscreenWindow>,
+  queue: Arc<AdQueue>,
+  allocator: Arc<Mutex<Allocator>>,
+  format: vk::Format,
+  image_count: usize,
+  images: Vec<Arc<AdImage>>,
+  resolution: vk::Extent2D,
+  next_image: usize,
+  initialized: bool,
+}
+
+impl OffscreenSwapchain {
+  pub fn new(
+    window: Arc<OffscreenWindow>,
+    queue: Arc<AdQueue>,
+    allocator: Arc<Mutex<Allocator>>,
+    format: vk::Format,
+    image_count: usize,
+  ) -> Result<Self, String> {
+    let resolution = window.extent();
+    let mut offscreen_swapchain = Self {
+      window,
+      queue,
+      allocator,
+      format,
+      image_count,
+      images: vec![],
+      resolution,
+      next_image: 0,
+      initialized: false,
+    };
+    offscreen_swapchain.images = offscreen_swapchain.create_images(resolution)?;
+    Ok(offscreen_swapchain)
+  }
+
+  fn create_images(&self, resolution: vk::Extent2D) -> Result<Vec<Arc<AdImage>>, String> {
+    (0..self.image_count)
+      .map(|i| {
+        AdImage::new_2d(
+          self.queue.ash_device().clone(),
+          self.allocator.clone(),
+          MemoryLocation::GpuOnly,
+          &format!("offscreen_swapchain_image_{i}"),
+          self.format,
+          resolution,
+          vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+          vk::SampleCountFlags::TYPE_1,
+          1,
+        )
+      })
+      .collect()
+  }
+}
+
+impl PresentTarget for OffscreenSwapchain {
+  fn resolution(&self) -> vk::Extent2D {
+    self.resolution
+  }
+
+  fn get_image_count(&self) -> usize {
+    self.images.len()
+  }
+
+  fn get_image(&self, idx: usize) -> vk::Image {
+    self.images[idx % self.images.len()].inner()
+  }
+
+  fn initialized(&self) -> bool {
+    self.initialized
+  }
+
+  fn initialize(&mut self, cmd_buffer: &AdCommandBuffer) -> Result<(), String> {
+    if self.initialized {
+      return Ok(());
+    }
+    cmd_buffer.begin(vk::CommandBufferUsageFlags::default())?;
+    cmd_buffer.pipeline_barrier(
+      vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::DependencyFlags::BY_REGION,
+      &[],
+      &[],
+      &self
+        .images
+        .iter()
+        .map(|image| {
+          vk::ImageMemoryBarrier::default()
+            .image(image.inner())
+            .subresource_range(
+              vk::ImageSubresourceRange::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .layer_count(1)
+                .base_array_layer(0)
+                .level_count(1)
+                .base_mip_level(0),
+            )
+            .src_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+            .dst_queue_family_index(cmd_buffer.cmd_pool().queue().family_index())
+            .src_access_mask(vk::AccessFlags::NONE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+        })
+        .collect::<Vec<_>>(),
+    );
+    cmd_buffer.end()
+  }
+
+  fn set_initialized(&mut self) {
+    self.initialized = true;
+  }
+
+  fn acquire_next_image(
+    &mut self,
+    semaphore: Option<&AdSemaphore>,
+    fence: Option<&AdFence>,
+  ) -> Result<Option<(u32, bool)>, String> {
+    if self.window.extent() != self.resolution {
+      return Ok(None);
+    }
+    let signal_semaphore = semaphore.map(|semaphore| semaphore.inner());
+    self
+      .queue
+      .submit(&[vk::SubmitInfo::default().signal_semaphores(signal_semaphore.as_slice())], fence)?;
+    let image_idx = self.next_image;
+    self.next_image = (self.next_image + 1) % self.images.len();
+    Ok(Some((image_idx as u32, false)))
+  }
+
+  fn present_image(
+    &mut self,
+    _image_idx: u32,
+    wait_semaphores: &[&AdSemaphore],
+  ) -> Result<(), String> {
+    if wait_semaphores.len() > MAX_SUBMIT_SEMAPHORES {
+      return Err(format!("at presenting offscreen: over {MAX_SUBMIT_SEMAPHORES} semaphores"));
+    }
+    let mut wait_handles = [vk::Semaphore::null(); MAX_SUBMIT_SEMAPHORES];
+    for (handle, semaphore) in wait_handles.iter_mut().zip(wait_semaphores) {
+      *handle = semaphore.inner();
+    }
+    let wait_semaphores = &wait_handles[..wait_semaphores.len()];
+    let wait_stages = [vk::PipelineStageFlags::ALL_COMMANDS; MAX_SUBMIT_SEMAPHORES];
+    self.queue.submit(
+      &[vk::SubmitInfo::default()
+        .wait_semaphores(wait_semaphores)
+        .wait_dst_stage_mask(&wait_stages[..wait_semaphores.len()])],
+      None,
+    )?;
+    self.window.presented_frames.fetch_add(1, Ordering::AcqRel);
+    Ok(())
+  }
+
+  fn refresh_resolution(&mut self) -> Result<(), String> {
+    let resolution = self.window.extent();
+    self.images = self.create_images(resolution)?;
+    self.resolution = resolution;
+    self.next_image = 0;
+    self.initialized = false;
+    Ok(())
+  }
+}
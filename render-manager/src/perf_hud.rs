@@ -0,0 +1,249 @@
+use std::{
+  collections::VecDeque,
+  time::{Duration, Instant},
+};
+
+use renderables::{color::Color, glam};
+use renderers::debug_renderers::OverlayRect;
+
+use crate::{frame_stats::FrameStats, ui_layer::UiShape};
+
+// Frames in the frame time plot, one bar each
+const PLOT_FRAMES: usize = 120;
+// Bars reach the top of the plot at this frame time, longer frames are cut off
+const PLOT_MAX_FRAME_TIME: Duration = Duration::from_millis(50);
+// Lines across the plot at 60 and 30 fps
+const PLOT_BUDGETS: [Duration; 2] = [Duration::from_micros(16_667), Duration::from_micros(33_333)];
+// Numbers are redrawn and go to stdout this often, the rects are made every frame
+const PRINT_INTERVAL: Duration = Duration::from_secs(1);
+// In normalized device coordinates, from the bottom right corner as the minimap has the top right
+const PANEL_RIGHT: f32 = 0.98;
+const PANEL_BOTTOM: f32 = 0.98;
+const PANEL_PADDING: f32 = 0.01;
+const PLOT_WIDTH: f32 = 0.6;
+const PLOT_HEIGHT: f32 = 0.2;
+const BUDGET_LINE_HEIGHT: f32 = 0.004;
+const METER_HEIGHT: f32 = 0.025;
+const METER_GAP: f32 = 0.008;
+// Text is this much of a meter's height, and inset from the left of the plot by the padding
+const TEXT_HEIGHT: f32 = 0.8;
+const BACKGROUND_COLOR: Color = Color::BLACK.with_alpha(0.7);
+const METER_BACK_COLOR: Color = Color::from_srgb(0.08, 0.08, 0.08, 1.0);
+const BUDGET_LINE_COLOR: Color = Color::WHITE.with_alpha(0.5);
+const DRAW_CALLS_COLOR: Color = Color::from_srgb(0.3, 0.6, 1.0, 1.0);
+const GPU_MEMORY_COLOR: Color = Color::from_srgb(0.8, 0.4, 1.0, 1.0);
+const PHYSICS_PAIRS_COLOR: Color = Color::from_srgb(1.0, 0.7, 0.2, 1.0);
+const TEXT_COLOR: Color = Color::WHITE;
+const MIB: f32 = 1024.0 * 1024.0;
+
+// Sent by the game each tick the hud is on, the renderer can't see the physics engine
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhysicsHudStats {
+  pub bodies: usize,
+  // Pairs the broad phase handed to the narrow phase in the last step
+  pub contact_pairs: usize,
+}
+
+// Frame times as bars colored by which budget they fit in, then meters for draw calls, gpu memory
+// and physics pairs, each against the most seen or the configured budget. The numbers are written
+// over the plot and meters with the ui font, and printed to stdout for logs
+pub struct PerfHud {
+  last_frame_at: Option<Instant>,
+  frame_times: VecDeque<Duration>,
+  physics: PhysicsHudStats,
+  peak_draw_calls: u32,
+  peak_contact_pairs: usize,
+  printed_at: Option<Instant>,
+  rects: Vec<OverlayRect>,
+  // Top left in normalized device coordinates like the rects, one over the plot then one per meter
+  labels: Vec<(glam::Vec2, String)>,
+}
+
+impl PerfHud {
+  pub fn new() -> Self {
+    Self {
+      last_frame_at: None,
+      frame_times: VecDeque::with_capacity(PLOT_FRAMES),
+      physics: PhysicsHudStats::default(),
+      peak_draw_calls: 0,
+      peak_contact_pairs: 0,
+      printed_at: None,
+      rects: vec![],
+      labels: vec![],
+    }
+  }
+
+  pub fn rects(&self) -> &[OverlayRect] {
+    &self.rects
+  }
+
+  // Text for the UI layer, resolution is the layer's. Sized to the meters, which scale with it
+  pub fn labels(&self, resolution: glam::Vec2) -> Vec<UiShape> {
+    let size = METER_HEIGHT * TEXT_HEIGHT * resolution.y * 0.5;
+    self
+      .labels
+      .iter()
+      .map(|(position, text)| UiShape::Text {
+        position: (*position + 1.0) * 0.5 * resolution,
+        text: text.clone(),
+        size,
+        color: TEXT_COLOR,
+      })
+      .collect()
+  }
+
+  pub fn set_physics_stats(&mut self, physics: PhysicsHudStats) {
+    self.physics = physics;
+    self.peak_contact_pairs = self.peak_contact_pairs.max(physics.contact_pairs);
+  }
+
+  // Once a frame, the time between calls is the frame time plotted. gpu_memory_budget is 0 when
+  // there's no ceiling, the meter is against the peak then
+  pub fn update(&mut self, stats: &FrameStats, gpu_memory_budget: u64) {
+    let now = Instant::now();
+    if let Some(last_frame_at) = self.last_frame_at {
+      if self.frame_times.len() == PLOT_FRAMES {
+        self.frame_times.pop_front();
+      }
+      self.frame_times.push_back(now - last_frame_at);
+    }
+    self.last_frame_at = Some(now);
+    self.peak_draw_calls = self.peak_draw_calls.max(stats.draw_calls);
+
+    let gpu_memory_max = match gpu_memory_budget {
+      0 => stats.peak_resources.gpu_memory_bytes,
+      budget => budget,
+    };
+    let meters = [
+      (fraction(stats.draw_calls as f32, self.peak_draw_calls as f32), DRAW_CALLS_COLOR),
+      (fraction(stats.resources.gpu_memory_bytes as f32, gpu_memory_max as f32), GPU_MEMORY_COLOR),
+      (
+        fraction(self.physics.contact_pairs as f32, self.peak_contact_pairs as f32),
+        PHYSICS_PAIRS_COLOR,
+      ),
+    ];
+    self.rects = Self::layout(&self.frame_times, &meters);
+
+    if self.printed_at.is_some_and(|printed_at| printed_at.elapsed() < PRINT_INTERVAL) {
+      return;
+    }
+    self.printed_at = Some(now);
+    let average = match self.frame_times.len() {
+      0 => Duration::ZERO,
+      len => self.frame_times.iter().sum::<Duration>() / len as u32,
+    };
+    let worst = self.frame_times.iter().max().copied().unwrap_or_default();
+    let frame_text = format!(
+      "{:.2} ms frames ({:.2} ms worst, gpu {:.2} ms)",
+      average.as_secs_f32() * 1000.0,
+      worst.as_secs_f32() * 1000.0,
+      stats.average_gpu_frame_time.unwrap_or_default().as_secs_f32() * 1000.0
+    );
+    let meter_texts = [
+      format!("{} draw calls", stats.draw_calls),
+      format!("{:.1} MiB gpu memory", stats.resources.gpu_memory_bytes as f32 / MIB),
+      format!("{} physics bodies in {} pairs", self.physics.bodies, self.physics.contact_pairs),
+    ];
+    println!("perf hud: {frame_text}, {}", meter_texts.join(", "));
+    self.labels = Self::label_positions(meter_texts.len())
+      .into_iter()
+      .zip([frame_text].into_iter().chain(meter_texts))
+      .collect();
+    if let Some(heap_allocations) = stats.heap_allocations {
+      println!("perf hud: {heap_allocations} heap allocations drawing the last frame");
+    }
+  }
+
+  // Same panel as layout, text sits at the top left of the plot and of each meter
+  fn label_positions(meters: usize) -> Vec<glam::Vec2> {
+    let panel_height =
+      2.0 * PANEL_PADDING + PLOT_HEIGHT + meters as f32 * (METER_HEIGHT + METER_GAP);
+    let plot_left = PANEL_RIGHT - PLOT_WIDTH - PANEL_PADDING;
+    let plot_top = PANEL_BOTTOM - panel_height + PANEL_PADDING;
+    let text_left = plot_left + PANEL_PADDING;
+    let meter_tops = (0..meters)
+      .map(|i| plot_top + PLOT_HEIGHT + METER_GAP + i as f32 * (METER_HEIGHT + METER_GAP));
+    std::iter::once(plot_top + PANEL_PADDING)
+      .chain(meter_tops.map(|top| top + METER_HEIGHT * (1.0 - TEXT_HEIGHT) * 0.5))
+      .map(|top| glam::vec2(text_left, top))
+      .collect()
+  }
+
+  pub(crate) fn layout(
+    frame_times: &VecDeque<Duration>,
+    meters: &[(f32, Color)],
+  ) -> Vec<OverlayRect> {
+    let panel_left = PANEL_RIGHT - PLOT_WIDTH - 2.0 * PANEL_PADDING;
+    let plot_left = panel_left + PANEL_PADDING;
+    let panel_height =
+      2.0 * PANEL_PADDING + PLOT_HEIGHT + meters.len() as f32 * (METER_HEIGHT + METER_GAP);
+    let panel_top = PANEL_BOTTOM - panel_height;
+    let plot_top = panel_top + PANEL_PADDING;
+    let plot_bottom = plot_top + PLOT_HEIGHT;
+    let bar_width = PLOT_WIDTH / PLOT_FRAMES as f32;
+    let height_of = |frame_time: Duration| {
+      PLOT_HEIGHT * (frame_time.as_secs_f32() / PLOT_MAX_FRAME_TIME.as_secs_f32()).min(1.0)
+    };
+
+    let mut rects = vec![];
+    // Newest frame on the right
+    let first_bar = PLOT_FRAMES - frame_times.len();
+    for (i, frame_time) in frame_times.iter().enumerate() {
+      let height = height_of(*frame_time);
+      let color = if *frame_time <= PLOT_BUDGETS[0] {
+        Color::GREEN
+      } else if *frame_time <= PLOT_BUDGETS[1] {
+        Color::YELLOW
+      } else {
+        Color::RED
+      };
+      rects.push(OverlayRect {
+        rect: glam::vec4(
+          plot_left + bar_width * (first_bar + i) as f32,
+          plot_bottom - height,
+          bar_width,
+          height,
+        ),
+        color,
+      });
+    }
+    for budget in PLOT_BUDGETS {
+      rects.push(OverlayRect {
+        rect: glam::vec4(
+          plot_left,
+          plot_bottom - height_of(budget),
+          PLOT_WIDTH,
+          BUDGET_LINE_HEIGHT,
+        ),
+        color: BUDGET_LINE_COLOR,
+      });
+    }
+
+    let mut row_top = plot_bottom + METER_GAP;
+    for (fill, color) in meters.iter() {
+      rects.push(OverlayRect {
+        rect: glam::vec4(plot_left, row_top, PLOT_WIDTH, METER_HEIGHT),
+        color: METER_BACK_COLOR,
+      });
+      rects.push(OverlayRect {
+        rect: glam::vec4(plot_left, row_top, PLOT_WIDTH * fill, METER_HEIGHT),
+        color: *color,
+      });
+      row_top += METER_HEIGHT + METER_GAP;
+    }
+
+    let background = OverlayRect {
+      rect: glam::vec4(panel_left, panel_top, PLOT_WIDTH + 2.0 * PANEL_PADDING, panel_height),
+      color: BACKGROUND_COLOR,
+    };
+    rects.insert(0, background);
+    rects
+  }
+}
+
+fn fraction(value: f32, max: f32) -> f32 {
+  if max <= 0.0 {
+    return 0.0;
+  }
+  (value / max).clamp(0.0, 1.0)
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{ash_queue_wrappers::AdCommandBuffer, ash_render_wrappers::AdFrameBuffer};
+use renderables::{glam, Camera3D};
+use renderers::{
+  post_renderers::MotionBlurRenderer,
+  transient_images::TransientImages,
+  triangle_mesh_renderers::TriMeshDraw,
+  velocity_renderers::{VelocityFrameData, VelocityRenderer},
+};
+
+use crate::taa::TemporalAa;
+
+// Passes over the finished scene that need to know where its pixels were last frame. They share
+// one velocity render, then run in order: taa first so the blur works on the anti-aliased image
+pub struct PostProcess {
+  velocity_renderer: VelocityRenderer,
+  // Unjittered, of the last frame the velocity was rendered for. None right after a resize
+  prev_view_proj: Option<glam::Mat4>,
+  taa: Option<TemporalAa>,
+  // With its intensity
+  motion_blur: Option<(MotionBlurRenderer, f32)>,
+}
+
+impl PostProcess {
+  pub fn new(
+    velocity_renderer: VelocityRenderer,
+    taa: Option<TemporalAa>,
+    motion_blur: Option<(MotionBlurRenderer, f32)>,
+  ) -> Self {
+    Self { velocity_renderer, prev_view_proj: None, taa, motion_blur }
+  }
+
+  pub fn has_motion_blur(&self) -> bool {
+    self.motion_blur.is_some()
+  }
+
+  // Motion starts over, the first frame after shows none
+  pub fn resize(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+    transient_images: &TransientImages,
+  ) -> Result<(), String> {
+    self.velocity_renderer.resize_targets(scene_frame_buffers)?;
+    if let Some(taa) = &mut self.taa {
+      taa.resize(cmd_buffer, scene_frame_buffers, &self.velocity_renderer)?;
+    }
+    if let Some((motion_blur_renderer, _)) = &mut self.motion_blur {
+      motion_blur_renderer.resize_targets(
+        scene_frame_buffers,
+        &self.velocity_renderer,
+        transient_images,
+      )?;
+    }
+    self.prev_view_proj = None;
+    Ok(())
+  }
+
+  // What the scene is drawn with this frame, jittered with taa
+  pub fn scene_camera(&self, camera: Camera3D) -> Camera3D {
+    match &self.taa {
+      Some(taa) => taa.jittered_camera(camera, self.velocity_renderer.resolution()),
+      None => camera,
+    }
+  }
+
+  // After everything the passes apply to is drawn into scene_frame_buffer, with the unjittered
+  // camera and the batches the scene was drawn with. Leaves the color in TRANSFER_SRC_OPTIMAL and
+  // the depth in DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+  pub fn record(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+    batches: &[TriMeshDraw],
+  ) -> Result<(), String> {
+    let resolution = scene_frame_buffer.resolution();
+    let frame_data = VelocityFrameData {
+      scene_view_proj: self.scene_camera(camera).view_proj_mat,
+      view_proj: camera.view_proj_mat,
+      inv_view_proj: camera.view_proj_mat.inverse(),
+      prev_view_proj: self.prev_view_proj.unwrap_or(camera.view_proj_mat),
+      params: glam::vec4(1.0 / resolution.width as f32, 1.0 / resolution.height as f32, 0.0, 0.0),
+    };
+    self.velocity_renderer.render(cmd_buffer, frame_idx, frame_data, batches)?;
+    if let Some(taa) = &mut self.taa {
+      taa.resolve(cmd_buffer, frame_idx, scene_frame_buffer)?;
+    }
+    if let Some((motion_blur_renderer, intensity)) = &self.motion_blur {
+      motion_blur_renderer.apply(cmd_buffer, frame_idx, scene_frame_buffer, *intensity)?;
+    }
+    self.velocity_renderer.finish(cmd_buffer, scene_frame_buffer);
+    self.prev_view_proj = Some(camera.view_proj_mat);
+    Ok(())
+  }
+}
@@ -0,0 +1,43 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{gpu_allocator::vulkan::Allocator, AdAshDevice},
+  ash_render_wrappers::AdFrameBuffer,
+};
+use renderers::{
+  depth_of_field_renderers::DepthOfFieldRenderer,
+  film_effects_renderers::FilmEffectsRenderer,
+  post_renderers::MotionBlurRenderer,
+  transient_images::{TransientImageDesc, TransientImages},
+};
+
+// The post passes with intermediates of their own, in the order they're recorded. Each ends by
+// copying its result over the scene color, and the barriers of that copy hold the next pass back
+// until the last one is done with its intermediates, so they can all share memory. The taa history
+// and the depth pyramids are read the frame after they're written and keep memory of their own
+const MOTION_BLUR_PASS: u32 = 0;
+const DEPTH_OF_FIELD_PASS: u32 = 1;
+const FILM_EFFECTS_PASS: u32 = 2;
+
+// Intermediates of the post passes turned on, for the frames in flight to share. Made again on
+// resize, after the frames in flight are waited on
+pub fn post_transient_images(
+  ash_device: Arc<AdAshDevice>,
+  allocator: Arc<Mutex<Allocator>>,
+  scene_frame_buffers: &[Arc<AdFrameBuffer>],
+  motion_blur: bool,
+  depth_of_field: bool,
+) -> Result<TransientImages, String> {
+  let Some(resolution) = scene_frame_buffers.first().map(|fb| fb.resolution()) else {
+    return Err("no framebuffers to make post targets for".to_string());
+  };
+  let mut descs: Vec<TransientImageDesc> = vec![];
+  if motion_blur {
+    descs.extend(MotionBlurRenderer::transient_images(MOTION_BLUR_PASS));
+  }
+  if depth_of_field {
+    descs.extend(DepthOfFieldRenderer::transient_images(DEPTH_OF_FIELD_PASS));
+  }
+  descs.extend(FilmEffectsRenderer::transient_images(FILM_EFFECTS_PASS));
+  TransientImages::new(ash_device, allocator, resolution, &descs)
+}
@@ -0,0 +1,119 @@
+use ash_ad_wrappers::{
+  ash_context::ash::vk,
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_surface_wrappers::AdSwapchain,
+  ash_sync_wrappers::{AdFence, AdSemaphore},
+};
+
+// Swapchain images to ask for and the frames in flight that fit them. One image more than the
+// surface's minimum so acquires don't wait on the compositor, and at least one per frame in flight.
+// Frames in flight drop to the image count when the surface caps it lower
+pub fn select_frame_counts(
+  surface_caps: &vk::SurfaceCapabilitiesKHR,
+  frames_in_flight: u32,
+) -> (u32, u32) {
+  let wanted = (surface_caps.min_image_count + 1).max(frames_in_flight);
+  let image_count = match surface_caps.max_image_count {
+    0 => wanted,
+    max_image_count => wanted.min(max_image_count),
+  };
+  (image_count, frames_in_flight.min(image_count))
+}
+
+// What drawing needs from a swapchain. Frames are blitted into its images, which sit in
+// PRESENT_SRC_KHR between frames
+pub trait PresentTarget {
+  fn resolution(&self) -> vk::Extent2D;
+  fn get_image_count(&self) -> usize;
+  fn get_image(&self, idx: usize) -> vk::Image;
+  fn initialized(&self) -> bool;
+  // Records moving every image into PRESENT_SRC_KHR, set_initialized once it has run
+  fn initialize(&mut self, cmd_buffer: &AdCommandBuffer) -> Result<(), String>;
+  fn set_initialized(&mut self);
+  // None when out of date, see AdSwapchain::acquire_next_image
+  fn acquire_next_image(
+    &mut self,
+    semaphore: Option<&AdSemaphore>,
+    fence: Option<&AdFence>,
+  ) -> Result<Option<(u32, bool)>, String>;
+  fn present_image(
+    &mut self,
+    image_idx: u32,
+    wait_semaphores: &[&AdSemaphore],
+  ) -> Result<(), String>;
+  // Blocks until at most `queued` presents are waiting to be shown, false when presents can't be
+  // waited on
+  fn wait_for_presents(&self, queued: u64) -> Result<bool, String> {
+    let _ = queued;
+    Ok(false)
+  }
+  // Id the last present_image got, None when presents aren't numbered
+  fn last_present_id(&self) -> Option<u64> {
+    None
+  }
+  // Without waiting, None when it can't be told
+  fn is_present_shown(&self, present_id: u64) -> Result<Option<bool>, String> {
+    let _ = present_id;
+    Ok(None)
+  }
+  // Recreates the images at the current window size
+  fn refresh_resolution(&mut self) -> Result<(), String>;
+}
+
+impl PresentTarget for AdSwapchain {
+  fn resolution(&self) -> vk::Extent2D {
+    AdSwapchain::resolution(self)
+  }
+
+  fn get_image_count(&self) -> usize {
+    AdSwapchain::get_image_count(self)
+  }
+
+  fn get_image(&self, idx: usize) -> vk::Image {
+    AdSwapchain::get_image(self, idx)
+  }
+
+  fn initialized(&self) -> bool {
+    AdSwapchain::initialized(self)
+  }
+
+  fn initialize(&mut self, cmd_buffer: &AdCommandBuffer) -> Result<(), String> {
+    AdSwapchain::initialize(self, cmd_buffer)
+  }
+
+  fn set_initialized(&mut self) {
+    AdSwapchain::set_initialized(self)
+  }
+
+  fn acquire_next_image(
+    &mut self,
+    semaphore: Option<&AdSemaphore>,
+    fence: Option<&AdFence>,
+  ) -> Result<Option<(u32, bool)>, String> {
+    AdSwapchain::acquire_next_image(self, semaphore, fence)
+  }
+
+  fn present_image(
+    &mut self,
+    image_idx: u32,
+    wait_semaphores: &[&AdSemaphore],
+  ) -> Result<(), String> {
+    AdSwapchain::present_image(self, image_idx, wait_semaphores)
+  }
+
+  fn wait_for_presents(&self, queued: u64) -> Result<bool, String> {
+    AdSwapchain::wait_for_presents(self, queued)
+  }
+
+  fn last_present_id(&self) -> Option<u64> {
+    Some(AdSwapchain::last_present_id(self))
+  }
+
+  fn is_present_shown(&self, present_id: u64) -> Result<Option<bool>, String> {
+    AdSwapchain::is_present_shown(self, present_id)
+  }
+
+  fn refresh_resolution(&mut self) -> Result<(), String> {
+    AdSwapchain::refresh_resolution(self)
+  }
+}
@@ -0,0 +1,86 @@
+use std::{collections::VecDeque, time::Duration};
+
+// Gpu frame times averaged into the sustained time the governor acts on
+pub const SUSTAIN_FRAMES: usize = 60;
+// Sustained times under this share of the budget are headroom. The gap up to the budget keeps a
+// raise from landing straight back over it
+const HEADROOM_RATIO: f32 = 0.7;
+// After lowering, headroom is ignored for this many frames so a drop that overshot isn't undone
+// right away
+pub const RAISE_COOLDOWN_FRAMES: u32 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityPressure {
+  OverBudget,
+  Headroom,
+}
+
+// Quality the renderer can change while running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityKnobs {
+  // Like render_scale in the config, kept within 0.25..=2
+  pub render_scale: f32,
+  // Side of each cascade's square shadow map, kept within 256..=4096. Only used with shadow maps
+  pub shadow_map_size: u32,
+}
+
+// Called on the render thread with the sustained gpu frame time, lowers knobs when over budget and
+// raises them with headroom. Callbacks run in the order they were added, each seeing the knobs as
+// the ones before left them
+pub type QualityCallback = Box<dyn FnMut(QualityPressure, Duration, &mut QualityKnobs) + Send>;
+
+pub struct QualityGovernor {
+  budget: Duration,
+  // Newest last
+  frame_times: VecDeque<Duration>,
+  raise_cooldown: u32,
+  callbacks: Vec<QualityCallback>,
+}
+
+impl QualityGovernor {
+  pub fn new(budget: Duration) -> Self {
+    Self { budget, frame_times: VecDeque::new(), raise_cooldown: 0, callbacks: vec![] }
+  }
+
+  pub fn add_callback(&mut self, callback: QualityCallback) {
+    self.callbacks.push(callback);
+  }
+
+  // Average over the last SUSTAIN_FRAMES, None until that many were timed
+  pub fn sustained_frame_time(&self) -> Option<Duration> {
+    (self.frame_times.len() == SUSTAIN_FRAMES)
+      .then(|| self.frame_times.iter().sum::<Duration>() / SUSTAIN_FRAMES as u32)
+  }
+
+  // Pressure once it's sustained, for adjust to act on
+  pub fn frame_timed(&mut self, gpu_time: Duration) -> Option<QualityPressure> {
+    if self.frame_times.len() == SUSTAIN_FRAMES {
+      self.frame_times.pop_front();
+    }
+    self.frame_times.push_back(gpu_time);
+    self.raise_cooldown = self.raise_cooldown.saturating_sub(1);
+    let sustained = self.sustained_frame_time()?;
+    if sustained > self.budget {
+      Some(QualityPressure::OverBudget)
+    } else if sustained < self.budget.mul_f32(HEADROOM_RATIO) && self.raise_cooldown == 0 {
+      Some(QualityPressure::Headroom)
+    } else {
+      None
+    }
+  }
+
+  // Runs the callbacks on knobs. Frames timed so far were at the old quality, so the next
+  // pressure waits on a full window at the new one
+  pub fn adjust(&mut self, pressure: QualityPressure, knobs: QualityKnobs) -> QualityKnobs {
+    let Some(sustained) = self.sustained_frame_time() else { return knobs };
+    let mut adjusted = knobs;
+    for callback in self.callbacks.iter_mut() {
+      callback(pressure, sustained, &mut adjusted);
+    }
+    self.frame_times.clear();
+    if pressure == QualityPressure::OverBudget {
+      self.raise_cooldown = RAISE_COOLDOWN_FRAMES;
+    }
+    adjusted
+  }
+}
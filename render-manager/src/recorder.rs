@@ -0,0 +1,183 @@
+use std::{
+  io::Write,
+  path::PathBuf,
+  process::{Command, Stdio},
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use ash_ad_wrappers::{ash_context::ash::vk, ash_data_wrappers::image};
+use crash_report::log;
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use jobs::JobHandle;
+
+use crate::frame_export::ExportedFrame;
+
+// Frames read back but not written yet. Past this many, frames are dropped instead of holding the
+// render thread up
+pub const RECORDING_RING_SIZE: usize = 8;
+
+type RecordedFrame = (vk::Extent2D, Arc<Vec<u8>>);
+// Frames go in through the sender, the job returns how many it wrote
+type FrameWriter = (Sender<RecordedFrame>, JobHandle<Result<u64, String>>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordingOutput {
+  // frame_000000.png and on in the directory, made if missing
+  PngSequence(PathBuf),
+  // Program and its args, fed raw sRGB rgba8 frames through stdin. {width}, {height} and {fps} in
+  // the args are replaced once the first frame comes in, like
+  // ffmpeg -f rawvideo -pix_fmt rgba -s {width}x{height} -r {fps} -i - out.mp4
+  Pipe(String, Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingSettings {
+  pub output: RecordingOutput,
+  // Frames written per second of recording. Slower frames are written again to keep the timing
+  pub fps: u32,
+}
+
+// Frames to take for a cadence of fps after elapsed, given taken of them already were. The first
+// is taken right at the start
+pub fn frames_due(elapsed: Duration, fps: u32, taken: u64) -> u64 {
+  let due = (elapsed.as_secs_f64() * fps.max(1) as f64).floor() as u64 + 1;
+  due.saturating_sub(taken)
+}
+
+pub fn pipe_args(args: &[String], resolution: vk::Extent2D, fps: u32) -> Vec<String> {
+  args
+    .iter()
+    .map(|arg| {
+      arg
+        .replace("{width}", &resolution.width.to_string())
+        .replace("{height}", &resolution.height.to_string())
+        .replace("{fps}", &fps.to_string())
+    })
+    .collect()
+}
+
+fn write_frames(
+  output: RecordingOutput,
+  resolution: vk::Extent2D,
+  fps: u32,
+  frames: Receiver<RecordedFrame>,
+) -> Result<u64, String> {
+  let mut written = 0;
+  match output {
+    RecordingOutput::PngSequence(dir) => {
+      std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("at making recording dir {}: {e}", dir.display()))?;
+      for (resolution, data) in frames {
+        let path = dir.join(format!("frame_{written:06}.png"));
+        // Alpha is whatever the passes left in it, the frames are saved opaque
+        let mut texels = data.to_vec();
+        texels.chunks_exact_mut(4).for_each(|texel| texel[3] = u8::MAX);
+        image::save_buffer(
+          &path,
+          &texels,
+          resolution.width,
+          resolution.height,
+          image::ExtendedColorType::Rgba8,
+        )
+        .map_err(|e| format!("at writing {}: {e}", path.display()))?;
+        written += 1;
+      }
+    }
+    RecordingOutput::Pipe(program, args) => {
+      let mut encoder = Command::new(&program)
+        .args(pipe_args(&args, resolution, fps))
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("at starting encoder {program}: {e}"))?;
+      let mut stdin = encoder.stdin.take().ok_or(format!("no stdin to encoder {program}"))?;
+      for (frame_resolution, data) in frames {
+        if frame_resolution != resolution {
+          log!("recording stopped, frames changed size for encoder {program}");
+          break;
+        }
+        stdin.write_all(&data).map_err(|e| format!("at piping frame to {program}: {e}"))?;
+        written += 1;
+      }
+      // The encoder finishes once its input closes
+      drop(stdin);
+      let status = encoder.wait().map_err(|e| format!("at waiting for encoder {program}: {e}"))?;
+      if !status.success() {
+        return Err(format!("encoder {program} exited with {status}"));
+      }
+    }
+  }
+  Ok(written)
+}
+
+// Takes exported frames at the settings' cadence and hands them to a thread writing them out. The
+// writer starts with the first frame, so pipes know the size
+pub struct Recorder {
+  settings: RecordingSettings,
+  started_at: Instant,
+  // Cadence frames passed, repeats and dropped frames included
+  taken: u64,
+  dropped: u64,
+  last_frame_number: Option<u64>,
+  writer: Option<FrameWriter>,
+}
+
+impl Recorder {
+  pub fn new(settings: RecordingSettings) -> Self {
+    Self {
+      settings,
+      started_at: Instant::now(),
+      taken: 0,
+      dropped: 0,
+      last_frame_number: None,
+      writer: None,
+    }
+  }
+
+  // With the latest exported frame, each frame drawn. Errors when the writer has stopped
+  pub fn push(&mut self, frame: Option<ExportedFrame>) -> Result<(), String> {
+    let Some(ExportedFrame::Pixels { resolution, data, frame_number }) = frame else {
+      return Ok(());
+    };
+    if self.last_frame_number == Some(frame_number) {
+      return Ok(());
+    }
+    self.last_frame_number = Some(frame_number);
+    if self.writer.is_none() {
+      let (sender, receiver) = crossbeam_channel::bounded(RECORDING_RING_SIZE);
+      let (output, fps) = (self.settings.output.clone(), self.settings.fps);
+      let job = jobs::global()
+        .spawn_dedicated("recorder", move || write_frames(output, resolution, fps, receiver))?;
+      // The cadence counts from the first frame
+      self.started_at = Instant::now();
+      self.writer = Some((sender, job));
+    }
+    let Some((sender, _)) = &self.writer else { return Ok(()) };
+    let due = frames_due(self.started_at.elapsed(), self.settings.fps, self.taken);
+    self.taken += due;
+    for i in 0..due {
+      match sender.try_send((resolution, data.clone())) {
+        Ok(_) => {}
+        Err(TrySendError::Full(_)) => {
+          self.dropped += due - i;
+          break;
+        }
+        Err(TrySendError::Disconnected(_)) => {
+          return Err("recording writer stopped".to_string());
+        }
+      }
+    }
+    Ok(())
+  }
+
+  // Waits for the frames sent so far to be written, returns how many were
+  pub fn finish(mut self) -> Result<u64, String> {
+    let Some((sender, job)) = self.writer.take() else { return Ok(0) };
+    drop(sender);
+    let written = job.wait()??;
+    if self.dropped > 0 {
+      log!("recording dropped {} frames the writer couldn't keep up with", self.dropped);
+    }
+    Ok(written)
+  }
+}
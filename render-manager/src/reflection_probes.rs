@@ -0,0 +1,83 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, gpu_allocator::vulkan::Allocator},
+  ash_data_wrappers::AdDescriptorSet,
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::AdFrameBuffer,
+};
+use renderables::reflection_probe::ReflectionProbe;
+use renderers::{
+  environment_renderers::EnvironmentGenerator,
+  reflection_probe_renderers::{ReflectionProbeRenderer, PROBE_FACE_SIZE},
+  triangle_mesh_renderers::{TriMeshDraw, TriMeshMaterialRenderer},
+};
+
+// The level's reflection probes, captured in the first frame drawn after they're set. Levels set
+// them after their meshes so the capture sees the whole level
+pub struct ReflectionProbes {
+  renderer: ReflectionProbeRenderer,
+  // Each face is drawn into it, then blitted into its probe's cube
+  frame_buffer: Arc<AdFrameBuffer>,
+  capture_pending: bool,
+}
+
+impl ReflectionProbes {
+  pub fn new(
+    renderer: ReflectionProbeRenderer,
+    cmd_buffer: &AdCommandBuffer,
+    allocator: Arc<Mutex<Allocator>>,
+    tri_mesh_renderer: &TriMeshMaterialRenderer,
+  ) -> Result<Self, String> {
+    let frame_buffer = tri_mesh_renderer
+      .create_framebuffers(
+        cmd_buffer,
+        allocator,
+        vk::Extent2D { width: PROBE_FACE_SIZE, height: PROBE_FACE_SIZE },
+        1,
+        vk::ImageUsageFlags::empty(),
+      )?
+      .remove(0);
+    Ok(Self { renderer, frame_buffer, capture_pending: false })
+  }
+
+  pub fn probes(&self) -> &[ReflectionProbe] {
+    self.renderer.probes()
+  }
+
+  pub fn set_probes(&mut self, probes: &[ReflectionProbe]) -> Result<(), String> {
+    self.renderer.set_probes(probes)?;
+    self.capture_pending = !probes.is_empty();
+    Ok(())
+  }
+
+  pub fn capture_pending(&self) -> bool {
+    self.capture_pending
+  }
+
+  // Before the frame's lit draws, after the shadows they're drawn with are recorded
+  pub fn record(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    tri_mesh_renderer: &TriMeshMaterialRenderer,
+    shadow_dset: &AdDescriptorSet,
+    batches: &[TriMeshDraw],
+    environment_gen: &EnvironmentGenerator,
+  ) -> Result<(), String> {
+    self.renderer.update(frame_idx)?;
+    if !self.capture_pending {
+      return Ok(());
+    }
+    self.renderer.capture(
+      cmd_buffer,
+      &self.frame_buffer,
+      tri_mesh_renderer,
+      shadow_dset,
+      batches,
+      environment_gen,
+    )?;
+    self.capture_pending = false;
+    Ok(())
+  }
+}
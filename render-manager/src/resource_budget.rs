@@ -0,0 +1,94 @@
+use ash_ad_wrappers::ash_context::{AdAshDevice, AdLiveObjectKind};
+use engine_config::ResourceBudgetConfig;
+use validation::ValidationCategory;
+
+// Memory reports lock and walk every allocator, so memory is only read every this many frames.
+// Peaks of it can be missed in between, the object counts are read every frame
+const MEMORY_SAMPLE_INTERVAL: u64 = 30;
+
+// What the renderer has live on the device
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+  pub gpu_memory_bytes: u64,
+  pub descriptor_sets: u64,
+  pub command_buffers: u64,
+  pub framebuffers: u64,
+}
+
+impl ResourceUsage {
+  pub(crate) fn max(self, other: Self) -> Self {
+    Self {
+      gpu_memory_bytes: self.gpu_memory_bytes.max(other.gpu_memory_bytes),
+      descriptor_sets: self.descriptor_sets.max(other.descriptor_sets),
+      command_buffers: self.command_buffers.max(other.command_buffers),
+      framebuffers: self.framebuffers.max(other.framebuffers),
+    }
+  }
+}
+
+// Keeps the highest usage seen at the end of a frame and reports each resource to validation when
+// it goes over its ceiling, once until it is back under
+pub struct ResourceBudget {
+  ceilings: ResourceBudgetConfig,
+  usage: ResourceUsage,
+  peak: ResourceUsage,
+  over: [bool; 4],
+}
+
+impl ResourceBudget {
+  pub fn new(ceilings: ResourceBudgetConfig) -> Self {
+    Self {
+      ceilings,
+      usage: ResourceUsage::default(),
+      peak: ResourceUsage::default(),
+      over: [false; 4],
+    }
+  }
+
+  pub fn usage(&self) -> ResourceUsage {
+    self.usage
+  }
+
+  pub fn peak(&self) -> ResourceUsage {
+    self.peak
+  }
+
+  pub fn sample(&mut self, ash_device: &AdAshDevice, frame_number: u64) -> Result<(), String> {
+    let live_objects = ash_device.live_objects();
+    let gpu_memory_bytes = match frame_number % MEMORY_SAMPLE_INTERVAL {
+      0 => ash_device.memory_diagnostics()?.used_bytes(),
+      _ => self.usage.gpu_memory_bytes,
+    };
+    self.record(ResourceUsage {
+      gpu_memory_bytes,
+      descriptor_sets: live_objects.count(AdLiveObjectKind::DescriptorSet),
+      command_buffers: live_objects.count(AdLiveObjectKind::CommandBuffer),
+      framebuffers: live_objects.count(AdLiveObjectKind::Framebuffer),
+    });
+    Ok(())
+  }
+
+  pub fn record(&mut self, usage: ResourceUsage) {
+    self.usage = usage;
+    self.peak = self.peak.max(usage);
+    let checks = [
+      (
+        "gpu memory bytes",
+        usage.gpu_memory_bytes,
+        self.ceilings.gpu_memory_mb as u64 * 1024 * 1024,
+      ),
+      ("descriptor sets", usage.descriptor_sets, self.ceilings.descriptor_sets as u64),
+      ("command buffers", usage.command_buffers, self.ceilings.command_buffers as u64),
+      ("framebuffers", usage.framebuffers, self.ceilings.framebuffers as u64),
+    ];
+    for ((name, live, ceiling), over) in checks.into_iter().zip(self.over.iter_mut()) {
+      let now_over = ceiling > 0 && live > ceiling;
+      if now_over && !*over {
+        validation::report(ValidationCategory::ResourceBudget, || {
+          format!("{live} {name} live, over the budget of {ceiling}")
+        });
+      }
+      *over = now_over;
+    }
+  }
+}
@@ -0,0 +1,142 @@
+use renderables::{glam, Camera3D};
+
+// Stops traversal through long portal chains, rooms only reachable past it stay culled
+const MAX_PORTAL_DEPTH: usize = 32;
+// Portal corners closer to the camera plane than this can't be projected
+const MIN_PORTAL_W: f32 = 1e-4;
+
+// Convex volume bounded by planes as normal and distance, normals pointing out of the room
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvexRoom {
+  pub name: String,
+  pub planes: Vec<glam::Vec4>,
+}
+
+impl ConvexRoom {
+  pub fn from_box(name: &str, min: glam::Vec3, max: glam::Vec3) -> Self {
+    Self {
+      name: name.to_string(),
+      planes: vec![
+        glam::Vec4::X.with_w(-max.x),
+        glam::Vec4::NEG_X.with_w(min.x),
+        glam::Vec4::Y.with_w(-max.y),
+        glam::Vec4::NEG_Y.with_w(min.y),
+        glam::Vec4::Z.with_w(-max.z),
+        glam::Vec4::NEG_Z.with_w(min.z),
+      ],
+    }
+  }
+
+  pub fn contains_point(&self, point: glam::Vec3) -> bool {
+    self.overlaps_sphere(point, 0.0)
+  }
+
+  // Conservative, spheres near the edges of the room can pass without touching it
+  pub fn overlaps_sphere(&self, center: glam::Vec3, radius: f32) -> bool {
+    self.planes.iter().all(|plane| plane.truncate().dot(center) + plane.w <= radius)
+  }
+}
+
+// Convex polygon joining two rooms, looking through it is the only way to see from one into
+// the other
+#[derive(Debug, Clone, PartialEq)]
+pub struct Portal {
+  // Indices into RoomGraph::rooms
+  pub rooms: [usize; 2],
+  pub vertices: Vec<glam::Vec3>,
+}
+
+// Screen space bounds in normalized device coordinates as min x, min y, max x, max y
+#[derive(Debug, Clone, Copy)]
+struct ClipRect(glam::Vec4);
+
+impl ClipRect {
+  const FULL: Self = Self(glam::Vec4::new(-1.0, -1.0, 1.0, 1.0));
+
+  fn intersect(&self, other: &Self) -> Option<Self> {
+    let rect = glam::vec4(
+      self.0.x.max(other.0.x),
+      self.0.y.max(other.0.y),
+      self.0.z.min(other.0.z),
+      self.0.w.min(other.0.w),
+    );
+    (rect.x < rect.z && rect.y < rect.w).then_some(Self(rect))
+  }
+}
+
+// Rooms and portals of a level. Empty graphs cull nothing
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoomGraph {
+  pub rooms: Vec<ConvexRoom>,
+  pub portals: Vec<Portal>,
+}
+
+impl RoomGraph {
+  // Which rooms can be seen from the camera, through chains of portals from the rooms it is in.
+  // Each portal narrows the screen rect the rooms past it are seen through. None when the camera
+  // isn't in any room, everything is drawn then. Needs the view projection matrix refreshed
+  pub fn visible_rooms(&self, camera: &Camera3D) -> Option<Vec<bool>> {
+    let camera_pos = camera.pos.truncate();
+    let mut visible = vec![false; self.rooms.len()];
+    let mut path = vec![];
+    for (room_idx, room) in self.rooms.iter().enumerate() {
+      if room.contains_point(camera_pos) {
+        self.traverse(camera, room_idx, ClipRect::FULL, &mut path, &mut visible);
+      }
+    }
+    visible.contains(&true).then_some(visible)
+  }
+
+  fn traverse(
+    &self,
+    camera: &Camera3D,
+    room_idx: usize,
+    clip_rect: ClipRect,
+    path: &mut Vec<usize>,
+    visible: &mut [bool],
+  ) {
+    visible[room_idx] = true;
+    if path.len() >= MAX_PORTAL_DEPTH {
+      return;
+    }
+    path.push(room_idx);
+    for portal in self.portals.iter() {
+      let next_room = match portal.rooms {
+        [a, b] if a == room_idx => b,
+        [a, b] if b == room_idx => a,
+        _ => continue,
+      };
+      // Rooms already on the path are seen through a wider rect, going back adds nothing
+      if next_room >= self.rooms.len() || path.contains(&next_room) {
+        continue;
+      }
+      let Some(portal_rect) = Self::portal_rect(camera, portal) else { continue };
+      if let Some(next_rect) = clip_rect.intersect(&portal_rect) {
+        self.traverse(camera, next_room, next_rect, path, visible);
+      }
+    }
+    path.pop();
+  }
+
+  // Screen bounds of the portal, None when it is entirely behind the camera. Portals crossing
+  // the camera plane can't be bounded, the whole screen is kept for them
+  fn portal_rect(camera: &Camera3D, portal: &Portal) -> Option<ClipRect> {
+    let clip_points = portal
+      .vertices
+      .iter()
+      .map(|vertex| camera.view_proj_mat * vertex.extend(1.0))
+      .collect::<Vec<_>>();
+    if clip_points.iter().all(|point| point.w < MIN_PORTAL_W) {
+      return None;
+    }
+    if clip_points.iter().any(|point| point.w < MIN_PORTAL_W) {
+      return Some(ClipRect::FULL);
+    }
+    let rect =
+      clip_points.iter().fold(glam::vec4(f32::MAX, f32::MAX, f32::MIN, f32::MIN), |rect, point| {
+        let ndc = point.truncate().truncate() / point.w;
+        glam::vec4(rect.x.min(ndc.x), rect.y.min(ndc.y), rect.z.max(ndc.x), rect.w.max(ndc.y))
+      });
+    Some(ClipRect(rect))
+  }
+}
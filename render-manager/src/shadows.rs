@@ -0,0 +1,234 @@
+#[cfg(feature = "ray-tracing")]
+use std::{collections::HashMap, sync::Weak};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "ray-tracing")]
+use ash_ad_wrappers::ash_rt_wrappers::AdAccelerationStructure;
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, gpu_allocator::vulkan::Allocator, AdAshDevice, AdAshInstance},
+  ash_data_wrappers::{AdDescriptorSet, AdDescriptorSetLayout},
+  ash_queue_wrappers::{AdCommandBuffer, AdQueue},
+};
+use engine_config::ShadowMode;
+#[cfg(feature = "ray-tracing")]
+use renderers::rt_shadow_renderers::RtShadowRenderer;
+use renderables::{
+  glam,
+  light::{LocalLight, SunLight},
+  triangle_mesh::{TriMeshCPU, TriMeshGenerator},
+  Camera3D,
+};
+use renderers::{
+  reflection_probe_renderers::ReflectionProbeRenderer,
+  shadow_renderers::{ShadowCascades, ShadowRenderer, ShadowTechnique},
+  triangle_mesh_renderers::TriMeshDraw,
+};
+
+use crate::handles::MeshHandle;
+
+// What the build and the gpu can do of the configured mode, ray traced shadows fall back to
+// shadow maps. The device needs ray tracing enabled when this gives RayTraced
+pub fn select_mode(
+  ash_instance: &AdAshInstance,
+  gpu: vk::PhysicalDevice,
+  requested: ShadowMode,
+) -> ShadowMode {
+  match requested {
+    ShadowMode::RayTraced if !cfg!(feature = "ray-tracing") => {
+      eprintln!("built without the ray-tracing feature, using shadow maps");
+      ShadowMode::ShadowMap
+    }
+    ShadowMode::RayTraced if !ash_instance.supports_ray_tracing(gpu) => {
+      eprintln!("ray tracing not supported, using shadow maps");
+      ShadowMode::ShadowMap
+    }
+    mode => mode,
+  }
+}
+
+// The shadow renderer and, when traced, the acceleration structures of every mesh
+pub struct SceneShadows {
+  renderer: ShadowRenderer,
+  #[cfg(feature = "ray-tracing")]
+  tracer: Option<RtShadowRenderer>,
+  // Shared by name like the gpu meshes
+  #[cfg(feature = "ray-tracing")]
+  blases: HashMap<String, Weak<AdAccelerationStructure>>,
+  #[cfg(feature = "ray-tracing")]
+  mesh_blases: HashMap<MeshHandle, Arc<AdAccelerationStructure>>,
+}
+
+impl SceneShadows {
+  // Traces only when the device was made with ray tracing, see select_mode
+  #[allow(clippy::too_many_arguments)]
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    graphics_queue: Arc<AdQueue>,
+    cmd_buffer: &AdCommandBuffer,
+    tri_mesh_gen: &TriMeshGenerator,
+    mode: ShadowMode,
+    cascades: ShadowCascades,
+    atlas_size: u32,
+    depth_format: vk::Format,
+    scene_resolution: vk::Extent2D,
+    reflection_probes: &ReflectionProbeRenderer,
+    frames_in_flight: usize,
+  ) -> Result<Self, String> {
+    let technique = match mode {
+      ShadowMode::Off => ShadowTechnique::Off,
+      ShadowMode::RayTraced if cfg!(feature = "ray-tracing") && ash_device.ray_tracing() => {
+        ShadowTechnique::RayTraced
+      }
+      ShadowMode::ShadowMap | ShadowMode::RayTraced => ShadowTechnique::ShadowMap,
+    };
+    let renderer = ShadowRenderer::new(
+      ash_device.clone(),
+      allocator.clone(),
+      cmd_buffer,
+      tri_mesh_gen,
+      technique,
+      cascades,
+      atlas_size,
+      depth_format,
+      scene_resolution,
+      reflection_probes,
+      frames_in_flight,
+    )?;
+    #[cfg(feature = "ray-tracing")]
+    let tracer = match technique {
+      ShadowTechnique::RayTraced => {
+        Some(RtShadowRenderer::new(ash_device, allocator, graphics_queue, &renderer)?)
+      }
+      ShadowTechnique::Off | ShadowTechnique::ShadowMap => None,
+    };
+    #[cfg(not(feature = "ray-tracing"))]
+    let _ = graphics_queue;
+    Ok(Self {
+      renderer,
+      #[cfg(feature = "ray-tracing")]
+      tracer,
+      #[cfg(feature = "ray-tracing")]
+      blases: HashMap::new(),
+      #[cfg(feature = "ray-tracing")]
+      mesh_blases: HashMap::new(),
+    })
+  }
+
+  pub fn dset_layout(&self) -> &Arc<AdDescriptorSetLayout> {
+    self.renderer.dset_layout()
+  }
+
+  pub fn camera_dset(&self, frame_idx: usize) -> &AdDescriptorSet {
+    self.renderer.camera_dset(frame_idx)
+  }
+
+  pub fn world_dset(&self, frame_idx: usize) -> &AdDescriptorSet {
+    self.renderer.world_dset(frame_idx)
+  }
+
+  pub fn unshadowed_dset(&self) -> &AdDescriptorSet {
+    self.renderer.unshadowed_dset()
+  }
+
+  // Frames still using the old targets have to be done
+  pub fn resize(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    scene_resolution: vk::Extent2D,
+  ) -> Result<(), String> {
+    self.renderer.resize(cmd_buffer, scene_resolution)?;
+    #[cfg(feature = "ray-tracing")]
+    if let Some(tracer) = &mut self.tracer {
+      tracer.set_targets(&self.renderer)?;
+    }
+    Ok(())
+  }
+
+  pub fn map_size(&self) -> u32 {
+    self.renderer.map_size()
+  }
+
+  // Frames still using the old shadow maps have to be done
+  pub fn set_map_size(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    map_size: u32,
+  ) -> Result<(), String> {
+    self.renderer.set_map_size(cmd_buffer, map_size)
+  }
+
+  // Builds what the mesh is traced against, nothing unless traced. Meshes not shared by name
+  // get their own
+  pub fn add_mesh(
+    &mut self,
+    name: &str,
+    mesh: &TriMeshCPU,
+    handle: MeshHandle,
+    shared: bool,
+  ) -> Result<(), String> {
+    #[cfg(feature = "ray-tracing")]
+    if let Some(tracer) = &self.tracer {
+      let existing = match shared {
+        true => self.blases.get(name).and_then(|blas| blas.upgrade()),
+        false => None,
+      };
+      let blas = match existing {
+        Some(existing) => existing,
+        None => {
+          let blas = Arc::new(tracer.create_blas(&format!("{name}_blas"), mesh)?);
+          if shared {
+            self.blases.insert(name.to_string(), Arc::downgrade(&blas));
+          }
+          blas
+        }
+      };
+      self.mesh_blases.insert(handle, blas);
+    }
+    #[cfg(not(feature = "ray-tracing"))]
+    let _ = (name, mesh, handle, shared);
+    Ok(())
+  }
+
+  // To be retired, frames in flight may still trace against it
+  pub fn remove_mesh(&mut self, handle: MeshHandle) -> Option<Arc<dyn Send + Sync>> {
+    #[cfg(feature = "ray-tracing")]
+    let removed = self.mesh_blases.remove(&handle).map(|blas| blas as Arc<dyn Send + Sync>);
+    #[cfg(not(feature = "ray-tracing"))]
+    let removed = {
+      let _ = handle;
+      None
+    };
+    removed
+  }
+
+  // Before anything drawn with the frame's sets, outside a render pass. Batches are drawn into
+  // the shadow maps or the depth prepass and the light shadows, casters are the meshes rays can
+  // hit with their transforms
+  #[allow(clippy::too_many_arguments)]
+  pub fn record(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    camera: Camera3D,
+    sun: &SunLight,
+    lights: &[LocalLight],
+    batches: &[TriMeshDraw],
+    casters: impl Iterator<Item = (MeshHandle, glam::Mat4)>,
+  ) -> Result<(), String> {
+    let light_views = self.renderer.update(frame_idx, camera, sun, lights)?;
+    self.renderer.render(cmd_buffer, frame_idx, camera, sun.direction, &light_views, batches);
+    #[cfg(feature = "ray-tracing")]
+    if let Some(tracer) = &mut self.tracer {
+      let casters = casters
+        .filter_map(|(mesh, transform)| {
+          self.mesh_blases.get(&mesh).map(|blas| (blas.as_ref(), transform))
+        })
+        .collect::<Vec<_>>();
+      tracer.trace(cmd_buffer, frame_idx, camera, sun.direction, &casters)?;
+    }
+    #[cfg(not(feature = "ray-tracing"))]
+    let _ = casters;
+    Ok(())
+  }
+}
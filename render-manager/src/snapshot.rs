@@ -0,0 +1,186 @@
+use std::{collections::HashMap, time::Instant};
+
+use renderables::{
+  color::Color,
+  crowd::CrowdInstance,
+  glam,
+  light::SunLight,
+  triangle_mesh::{MorphWeights, TriMeshTransform},
+  Camera3D,
+};
+use renderers::triangle_mesh_renderers::DEFAULT_CLEAR_COLOR;
+
+use crate::{
+  color_grading::ColorGrading,
+  depth_of_field::DepthOfFieldParams,
+  draw_list::LayerMask,
+  handles::{CrowdHandle, MeshHandle},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct MeshState {
+  pub mesh: MeshHandle,
+  pub transform: TriMeshTransform,
+  pub morph_weights: Option<MorphWeights>,
+  // Sim time in microseconds the transform was worked out at, for objects updated less often than
+  // every tick. The render thread then blends the last two updates at its own time or carries
+  // them on past the newest. None blends between snapshots like everything else
+  pub updated_at: Option<u128>,
+}
+
+// Pose of a mesh uploaded with UploadSkinnedMesh, its transform still goes in MeshState
+#[derive(Debug, Clone)]
+pub struct SkinnedMeshState {
+  pub mesh: MeshHandle,
+  pub joint_matrices: Vec<glam::Mat4>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CrowdState {
+  pub crowd: CrowdHandle,
+  pub instances: Vec<CrowdInstance>,
+}
+
+// Per tick state the render thread needs on top of the draw list it keeps
+#[derive(Debug, Clone)]
+pub struct FrameSnapshot {
+  // Simulation time the snapshot was taken at in microseconds, 0 until the first tick
+  pub sim_time: u128,
+  pub camera: Camera3D,
+  // Vertical field of view of the camera in radians
+  pub camera_fov: f32,
+  // Renderables on none of these layers aren't drawn
+  pub camera_layers: LayerMask,
+  pub meshes: Vec<MeshState>,
+  pub skinned_meshes: Vec<SkinnedMeshState>,
+  pub crowds: Vec<CrowdState>,
+  // Replaces the depth of field settings from the config while set, blended between ticks so it
+  // can be animated. Only drawn with depth_of_field on in the config
+  pub depth_of_field: Option<DepthOfFieldParams>,
+  // Color lookup tables the tonemapped frame goes through, None leaves it as it is
+  pub color_grading: Option<ColorGrading>,
+  // Lights the lit materials and casts the cascaded shadows
+  pub sun: SunLight,
+  // Cleared to behind everything drawn, alpha is ignored
+  pub sky_color: Color,
+  // When the oldest input the tick handled arrived, None if it had none
+  pub input_received_at: Option<Instant>,
+}
+
+impl Default for FrameSnapshot {
+  fn default() -> Self {
+    Self {
+      sim_time: 0,
+      camera: Camera3D::new(glam::Vec4::W, glam::Vec4::NEG_Z, 1.0),
+      camera_fov: crate::CAMERA_FOV,
+      camera_layers: LayerMask::ALL,
+      meshes: vec![],
+      skinned_meshes: vec![],
+      crowds: vec![],
+      depth_of_field: None,
+      color_grading: None,
+      sun: SunLight::default(),
+      sky_color: DEFAULT_CLEAR_COLOR,
+      input_received_at: None,
+    }
+  }
+}
+
+fn lerp_matrix(from: &glam::Mat4, to: &glam::Mat4, t: f32) -> glam::Mat4 {
+  let (from_scale, from_rot, from_pos) = from.to_scale_rotation_translation();
+  let (to_scale, to_rot, to_pos) = to.to_scale_rotation_translation();
+  glam::Mat4::from_scale_rotation_translation(
+    from_scale.lerp(to_scale, t),
+    from_rot.slerp(to_rot, t),
+    from_pos.lerp(to_pos, t),
+  )
+}
+
+impl FrameSnapshot {
+  // Blends from prev towards self, t of 0 is prev and 1 is self. Meshes missing from prev, like
+  // newly spawned objects, are taken as they are. So are poses and crowds whose joint or instance
+  // count changed
+  pub fn interpolate_from(&self, prev: &FrameSnapshot, t: f32, out: &mut FrameSnapshot) {
+    let t = t.clamp(0.0, 1.0);
+    let prev_meshes =
+      prev.meshes.iter().map(|mesh_state| (mesh_state.mesh, mesh_state)).collect::<HashMap<_, _>>();
+    out.sim_time = prev.sim_time + (self.sim_time.saturating_sub(prev.sim_time) as f32 * t) as u128;
+    out.camera = self.camera;
+    out.camera_layers = self.camera_layers;
+    out.input_received_at = self.input_received_at;
+    out.depth_of_field = match (prev.depth_of_field, self.depth_of_field) {
+      (Some(prev_params), Some(params)) => Some(prev_params.lerp(&params, t)),
+      (_, params) => params,
+    };
+    out.color_grading = match (prev.color_grading, self.color_grading) {
+      (Some(prev_grading), Some(grading)) => Some(grading.interpolate_from(&prev_grading, t)),
+      (_, grading) => grading,
+    };
+    out.sun = prev.sun.lerp(&self.sun, t);
+    out.sky_color = prev.sky_color.lerp(self.sky_color, t);
+    out.camera.pos = prev.camera.pos.lerp(self.camera.pos, t);
+    out.camera.look_dir = prev.camera.look_dir.lerp(self.camera.look_dir, t);
+    out.camera_fov = prev.camera_fov + (self.camera_fov - prev.camera_fov) * t;
+    out.meshes.clear();
+    out.meshes.extend(self.meshes.iter().map(|mesh_state| {
+      let Some(prev_state) = prev_meshes.get(&mesh_state.mesh) else { return *mesh_state };
+      MeshState {
+        mesh: mesh_state.mesh,
+        transform: TriMeshTransform {
+          transform: lerp_matrix(
+            &prev_state.transform.transform,
+            &mesh_state.transform.transform,
+            t,
+          ),
+        },
+        morph_weights: match (prev_state.morph_weights, mesh_state.morph_weights) {
+          (Some(prev_weights), Some(weights)) => Some(prev_weights * (1.0 - t) + weights * t),
+          (_, weights) => weights,
+        },
+        updated_at: mesh_state.updated_at,
+      }
+    }));
+    out.skinned_meshes.clear();
+    out.skinned_meshes.extend(self.skinned_meshes.iter().map(|skinned_state| {
+      let prev_state =
+        prev.skinned_meshes.iter().find(|prev_state| prev_state.mesh == skinned_state.mesh);
+      match prev_state {
+        Some(prev_state)
+          if prev_state.joint_matrices.len() == skinned_state.joint_matrices.len() =>
+        {
+          SkinnedMeshState {
+            mesh: skinned_state.mesh,
+            joint_matrices: prev_state
+              .joint_matrices
+              .iter()
+              .zip(skinned_state.joint_matrices.iter())
+              .map(|(prev_matrix, matrix)| lerp_matrix(prev_matrix, matrix, t))
+              .collect(),
+          }
+        }
+        _ => skinned_state.clone(),
+      }
+    }));
+    out.crowds.clear();
+    out.crowds.extend(self.crowds.iter().map(|crowd_state| {
+      let prev_state = prev.crowds.iter().find(|prev_state| prev_state.crowd == crowd_state.crowd);
+      match prev_state {
+        Some(prev_state) if prev_state.instances.len() == crowd_state.instances.len() => {
+          CrowdState {
+            crowd: crowd_state.crowd,
+            instances: prev_state
+              .instances
+              .iter()
+              .zip(crowd_state.instances.iter())
+              .map(|(prev_instance, instance)| CrowdInstance {
+                transform: lerp_matrix(&prev_instance.transform, &instance.transform, t),
+                anim: prev_instance.anim.lerp(instance.anim, t),
+              })
+              .collect(),
+          }
+        }
+        _ => crowd_state.clone(),
+      }
+    }));
+  }
+}
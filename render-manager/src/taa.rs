@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use ash_ad_wrappers::{
+  ash_context::ash::vk, ash_queue_wrappers::AdCommandBuffer, ash_render_wrappers::AdFrameBuffer,
+};
+use renderables::{glam, Camera3D};
+use renderers::{taa_renderers::TaaRenderer, velocity_renderers::VelocityRenderer};
+
+// Share of the history in each resolved frame, higher is smoother but slower to show changes
+const HISTORY_WEIGHT: f32 = 0.9;
+// Frames before the jitter pattern repeats
+const JITTER_PHASES: u64 = 8;
+
+// Radical inverse of index in base, consecutive indices spread evenly over 0..1
+pub fn halton(mut index: u32, base: u32) -> f32 {
+  let mut result = 0.0;
+  let mut fraction = 1.0;
+  while index > 0 {
+    fraction /= base as f32;
+    result += fraction * (index % base) as f32;
+    index /= base;
+  }
+  result
+}
+
+// Sub-pixel camera offset of a frame in normalized device coordinates, up to half a pixel each
+// way. Halton (2, 3) from index 1, which covers the pixel evenly over the phases
+pub fn jitter_offset(frame: u64, resolution: vk::Extent2D) -> glam::Vec2 {
+  let index = (frame % JITTER_PHASES) as u32 + 1;
+  let pixel_offset = glam::vec2(halton(index, 2), halton(index, 3)) - 0.5;
+  pixel_offset * 2.0 / glam::vec2(resolution.width as f32, resolution.height as f32)
+}
+
+// Jitters the scene camera every frame and resolves the frames into a history, see TaaRenderer
+pub struct TemporalAa {
+  renderer: TaaRenderer,
+  frame: u64,
+  // False until a frame is resolved into the history
+  has_history: bool,
+  read_idx: usize,
+}
+
+impl TemporalAa {
+  pub fn new(renderer: TaaRenderer) -> Self {
+    Self { renderer, frame: 0, has_history: false, read_idx: 0 }
+  }
+
+  // After the velocity targets are resized. The history starts over
+  pub fn resize(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+    velocity_renderer: &VelocityRenderer,
+  ) -> Result<(), String> {
+    self.renderer.resize_targets(cmd_buffer, scene_frame_buffers, velocity_renderer)?;
+    self.has_history = false;
+    Ok(())
+  }
+
+  // What the scene is drawn with this frame
+  pub fn jittered_camera(&self, camera: Camera3D, resolution: vk::Extent2D) -> Camera3D {
+    let jitter = jitter_offset(self.frame, resolution);
+    Camera3D {
+      view_proj_mat: glam::Mat4::from_translation(jitter.extend(0.0)) * camera.view_proj_mat,
+      ..camera
+    }
+  }
+
+  // After the velocity of the frame is rendered. Moves on to the next jitter
+  pub fn resolve(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+  ) -> Result<(), String> {
+    let history_weight = if self.has_history { HISTORY_WEIGHT } else { 0.0 };
+    self.renderer.resolve(
+      cmd_buffer,
+      frame_idx,
+      self.read_idx,
+      scene_frame_buffer,
+      history_weight,
+    )?;
+    self.read_idx = 1 - self.read_idx;
+    self.has_history = true;
+    self.frame += 1;
+    Ok(())
+  }
+}
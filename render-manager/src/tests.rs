@@ -0,0 +1,3684 @@
+use std::{
+  collections::{HashMap, VecDeque},
+  path::Path,
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, driver_version_string, gpu_allocator},
+  ash_data_wrappers::{image, AdBuffer, AdDescriptorBinding, AdDescriptorSetLayout, AdImage},
+  ash_queue_wrappers::AdCommandBuffer,
+  ash_render_wrappers::{
+    compile_shader, AdComputePipeline, AdPipeline, AdRenderPass, AdShaderBinding, AdShaderLanguage,
+    AdShaderReflection,
+  },
+};
+use engine_config::{
+  AntiAliasing, ColorOutput, EngineConfig, ExposureMode, FovAxis, PostProcessConfig, QualityPreset,
+  RendererConfig, ResourceBudgetConfig, ShadowMode, TextureCompression, ViewConfig,
+};
+use renderables::{
+  cloth::{ClothCPU, ClothSim},
+  color_lut::ColorLutCPU,
+  flat_texture::{
+    mip_count, DecodedFlatTexture, TextureAddressMode,
+    TextureColorSpace::{Linear, Srgb},
+    TextureFilter, TextureFormat, TextureImportSettings,
+  },
+  glam,
+  mesh_arena::{plan_compaction, ArenaRange, RangeAllocator, ARENA_ALIGNMENT},
+  reflection_probe::{blend_weights, ReflectionProbe},
+  sampler_cache::SamplerDesc,
+  sdf_font::{signed_distance_field, SdfFontCPU, SdfGlyph, SDF_GLYPH_SIZE},
+  static_batch::StaticBatcher,
+  texture_compression::{self, BlockFormat, BlockQuality, CompressedFlatTexture},
+  triangle_mesh::TriMeshCPU,
+};
+use renderers::{
+  color_renderers::SrgbEncodeRenderer,
+  depth_of_field_renderers::lens_coefficient,
+  film_effects_renderers::{aberration_pixels, FilmEffectsParams, Vignette},
+  sdf_renderers::{SdfShape, SdfShapeKind},
+  shadow_atlas::ShadowAtlas,
+  shadow_renderers,
+  transient_images::{plan_aliasing, TransientLifetime},
+};
+use validation::ValidationCategory;
+
+use crate::{
+  bake_lightmap,
+  benchmark::{summarize_frame_times, BenchmarkCapture},
+  color_grading::ColorGrading,
+  depth_of_field::DepthOfFieldParams,
+  depth_readback::{self, DepthQuery},
+  exposure,
+  film_effects::config_film_effects,
+  frame_capture::FrameCapture,
+  frame_diff::{diff_heatmap, heat_color, upload_heatmap, DIFF_NOISE_FLOOR},
+  frame_export::{self, ExportedFrame, FrameExportMode},
+  frame_scratch::{FrameScratch, ScratchVec},
+  frame_sync::{frame_to_wait, SemaphoreHazardTracker},
+  graphics_backend::{
+    BackendBuffer, BackendImage, Binding, BufferDesc, BufferUsage, CommandList, Extent2D, Format,
+    GraphicsDevice, ImageDesc, MemoryLocation, NullCommand, NullDevice,
+  },
+  handles::{HandleAllocator, HandleRegistry},
+  leak_check::{LeakedResource, ResourceLeak, ResourceSnapshot},
+  light_culling::{light_contribution, LightCulling},
+  loading_screen::MessageBacklog,
+  minimap::minimap_camera,
+  offscreen::OffscreenWindow,
+  perf_hud::PerfHud,
+  post_targets::post_transient_images,
+  present::select_frame_counts,
+  quality_governor,
+  recorder::{self, Recorder},
+  release_retired,
+  resource_budget::{ResourceBudget, ResourceUsage},
+  snapshot::{FrameSnapshot, MeshState},
+  taa,
+  texture_streaming::{self, TextureStreamer},
+  texture_transcoding,
+  transform_history::TransformHistory,
+  ui_layer::{resolve_ui_scale, sdf_shapes},
+  unwrap_lightmap_uvs, view_camera,
+  view_policy::{hud_safe_area, projection_fov, scene_viewport},
+  visual_regression::{self, Tolerance},
+  world_labels::{is_label_occluded, label_shapes, project_label},
+  Camera3D, Color, CrowdVertex, LabelAnchor, LabelIcon, LayerMask, LightShape, Lightmap,
+  LightmapInstance, LightmapLights, LightmapSettings, LoadingProgress, LocalLight, MaterialCPU,
+  MeshHandle, MinimapSettings, MultiviewCameras, PresentOutcome, QualityKnobs, QualityPressure,
+  RecordingOutput, RecordingSettings, RenderManager, RendererMessage, ShaderVariant,
+  SkinnedMeshCPU, SunLight, TriMeshTransform, UiShape, VertexStreamUse, WorldLabel, CAMERA_FOV,
+  MAX_REFLECTION_PROBES,
+};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+
+// Tests drawing with it are ignored, run them with cargo test -- --ignored on a machine with a
+// vulkan capable gpu
+fn headless_render_manager(config: RendererConfig) -> (RenderManager, Arc<OffscreenWindow>) {
+  let window = OffscreenWindow::new(WIDTH, HEIGHT);
+  let config = RendererConfig { validation: false, ..config };
+  let render_mgr = RenderManager::new_offscreen(config, window.clone())
+    .expect("headless render manager should initialize, is there a vulkan capable gpu?");
+  (render_mgr, window)
+}
+
+fn cuboid_messages(name: &str, mesh: MeshHandle) -> Vec<RendererMessage> {
+  let cuboid = TriMeshCPU::make_cuboid(glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y, 1.0);
+  vec![
+    RendererMessage::UploadTriMesh(name.to_string(), cuboid, mesh),
+    RendererMessage::AddRenderable(mesh, None),
+  ]
+}
+
+fn draw_frames(render_mgr: &mut RenderManager, count: u64) {
+  for _ in 0..count {
+    assert!(!render_mgr.draw().expect("frame should draw"));
+  }
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn frame_loop_cycles_frames_in_flight() {
+  let (mut render_mgr, window) = headless_render_manager(RendererConfig::default());
+  let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+  draw_frames(&mut render_mgr, 300);
+  assert_eq!(render_mgr.frame_number, 300);
+  assert_eq!(window.presented_frames(), 300);
+  assert_eq!(render_mgr.frame_sync.current_frame() as u64, 300 % frames_in_flight);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn resize_recreates_swapchain_and_framebuffers() {
+  let config = RendererConfig { render_scale: 0.5, ..Default::default() };
+  let (mut render_mgr, window) = headless_render_manager(config);
+  draw_frames(&mut render_mgr, 3);
+
+  window.resize(WIDTH * 2, HEIGHT);
+  // Out of date acquire, nothing is presented
+  assert!(render_mgr.draw().expect("resize should refresh the swapchain"));
+  assert_eq!(window.presented_frames(), 3);
+  assert_eq!(render_mgr.swapchain.resolution(), window.extent());
+
+  draw_frames(&mut render_mgr, 3);
+  assert_eq!(window.presented_frames(), 6);
+  for frame_buffer in render_mgr.triangle_frame_buffers.iter() {
+    let resolution = frame_buffer.attachments()[0].image().resolution();
+    assert_eq!((resolution.width, resolution.height), (WIDTH, HEIGHT / 2));
+  }
+}
+
+#[test]
+fn semaphore_tracking_follows_the_frame_loop() {
+  let (frames_in_flight, image_count) = (2, 3);
+  let mut tracker = SemaphoreHazardTracker::new(frames_in_flight, image_count);
+  // Images come back out of order, as a present engine may hand them out
+  for frame_number in 0..300usize {
+    let frame = frame_number % frames_in_flight;
+    let image_idx = (frame_number * 7 % image_count) as u32;
+    tracker.acquired(frame).unwrap();
+    tracker.submitted(frame, image_idx).unwrap();
+    tracker.presented(image_idx);
+  }
+}
+
+#[test]
+fn semaphore_tracking_catches_broken_chains() {
+  let mut tracker = SemaphoreHazardTracker::new(2, 3);
+  assert!(tracker.submitted(0, 0).unwrap_err().contains("unsignalled acquire semaphore 0"));
+  tracker.acquired(0).unwrap();
+  assert!(tracker.acquired(0).unwrap_err().contains("signalled again"));
+  tracker.submitted(0, 1).unwrap();
+  // Image 1 wasn't presented yet, so its render semaphore is still signalled
+  tracker.acquired(1).unwrap();
+  assert!(tracker.submitted(1, 1).unwrap_err().contains("image 1 reused"));
+
+  // A new swapchain starts with fresh render semaphores, acquires still have to be waited on
+  tracker.resized(4);
+  tracker.submitted(1, 1).unwrap();
+  tracker.presented(1);
+  tracker.acquired(1).unwrap();
+  tracker.submitted(1, 3).unwrap();
+}
+
+#[test]
+fn frames_failing_after_acquire_release_the_acquire_semaphore() {
+  // Returning from the failed frame as is leaves the semaphore signalled for the next acquire
+  let mut returned = SemaphoreHazardTracker::new(1, 2);
+  returned.acquired(0).unwrap();
+  assert!(returned.acquired(0).is_err());
+
+  // The empty submit release_acquired does waits on it like a render submit
+  let mut released = SemaphoreHazardTracker::new(1, 2);
+  released.acquired(0).unwrap();
+  released.submitted(0, 1).unwrap();
+  released.presented(1);
+  released.acquired(0).unwrap();
+  released.submitted(0, 0).unwrap();
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn frame_failing_mid_recording_keeps_the_frame_loop_going() {
+  let (mut render_mgr, window) = headless_render_manager(RendererConfig::default());
+  render_mgr.set_sync_validation(true);
+  let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+  draw_frames(&mut render_mgr, 3);
+  let failing_record = |render_mgr: &mut RenderManager, frame_idx: usize, _| {
+    render_mgr.render_cmd_buffers[frame_idx].begin(vk::CommandBufferUsageFlags::default())?;
+    Err("at recording frame: failed for testing".to_string())
+  };
+  assert!(render_mgr.draw_recorded_by(failing_record).unwrap_err().contains("failed for testing"));
+  // The acquired image is handed back, every acquire semaphore gets used again after it
+  assert_eq!(window.presented_frames(), 4);
+  draw_frames(&mut render_mgr, 2 * frames_in_flight);
+  assert_eq!(window.presented_frames(), 4 + 2 * frames_in_flight);
+  assert_eq!(render_mgr.frame_number, 3 + 2 * frames_in_flight);
+}
+
+#[test]
+fn retired_resources_outlive_the_frames_in_flight() {
+  let frames_in_flight = 3;
+  let mut retired = Vec::new();
+  let mut released = Vec::new();
+  for frame_number in 0..300u64 {
+    release_retired(&mut retired, frame_number, frames_in_flight);
+    let resource = Arc::new(frame_number);
+    released.push(Arc::downgrade(&resource));
+    retired.push((frame_number, resource));
+    // Dropped once the frame slot it was retired in comes round again, its fence waited on by then
+    let kept = released.iter().filter(|x| x.strong_count() > 0).count() as u64;
+    assert_eq!(kept, (frame_number + 1).min(frames_in_flight));
+    assert!(released[..released.len() - kept as usize].iter().all(|x| x.strong_count() == 0));
+  }
+}
+
+#[test]
+fn frame_pacing_waits_on_the_frame_that_many_frames_back() {
+  assert_eq!(frame_to_wait(0, 3, 1), Some(2));
+  assert_eq!(frame_to_wait(2, 3, 1), Some(1));
+  assert_eq!(frame_to_wait(1, 3, 2), Some(2));
+  // The current frame's fence is waited on anyway
+  assert_eq!(frame_to_wait(1, 3, 0), None);
+  assert_eq!(frame_to_wait(1, 3, 3), None);
+  assert_eq!(frame_to_wait(0, 1, 1), None);
+}
+
+#[test]
+fn presents_decide_when_the_swapchain_is_recreated() {
+  assert_eq!(PresentOutcome::new(Ok(()), false), Ok(PresentOutcome::Presented));
+  assert_eq!(PresentOutcome::new(Ok(()), true), Ok(PresentOutcome::Suboptimal));
+  let out_of_date = Err("at presenting: ERROR_OUT_OF_DATE_KHR".to_string());
+  assert_eq!(PresentOutcome::new(out_of_date, false), Ok(PresentOutcome::OutOfDate));
+  let lost = Err("at presenting: ERROR_DEVICE_LOST".to_string());
+  assert_eq!(PresentOutcome::new(lost.clone(), true), lost.map(|_| PresentOutcome::Presented));
+}
+
+#[test]
+fn scene_resolution_follows_the_window_and_render_scale() {
+  let extent = |width, height| vk::Extent2D { width, height };
+  let config = RendererConfig { render_scale: 0.5, ..Default::default() };
+  assert_eq!(RenderManager::scene_resolution(extent(1920, 1080), &config), extent(960, 540));
+  // Resizing the window resizes the scene targets with it
+  assert_eq!(RenderManager::scene_resolution(extent(1280, 720), &config), extent(640, 360));
+  // A minimized window still gets targets to render into
+  assert_eq!(RenderManager::scene_resolution(extent(0, 0), &config), extent(1, 1));
+
+  let boxed = RendererConfig {
+    view: ViewConfig { max_aspect: 16.0 / 9.0, ..Default::default() },
+    render_scale: 1.0,
+    ..Default::default()
+  };
+  assert_eq!(RenderManager::scene_resolution(extent(2560, 1080), &boxed), extent(1920, 1080));
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn messages_update_draw_batches() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  let other_cube = mesh_handles.allocate();
+  let mut messages = cuboid_messages("cube", cube);
+  messages.extend(cuboid_messages("other_cube", other_cube));
+  assert!(!render_mgr.process_messages(messages));
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(render_mgr.draw_batches.len(), 2);
+
+  assert!(!render_mgr.process_messages(vec![RendererMessage::SetRenderableVisible(cube, false)]));
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(render_mgr.draw_batches.len(), 1);
+
+  assert!(!render_mgr.process_messages(vec![RendererMessage::RemoveRenderable(other_cube)]));
+  draw_frames(&mut render_mgr, 1);
+  assert!(render_mgr.draw_batches.is_empty());
+
+  // Messages after Stop still apply
+  assert!(render_mgr.process_messages(vec![
+    RendererMessage::Stop,
+    RendererMessage::SetRenderableVisible(cube, true),
+  ]));
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(render_mgr.draw_batches.len(), 1);
+}
+
+#[test]
+fn layer_masks_union_and_leave_out_layers() {
+  let editor_layer = LayerMask::layer(31);
+  let play_layers = LayerMask::ALL.without(editor_layer);
+  assert!(LayerMask::DEFAULT.intersects(play_layers));
+  assert!(!editor_layer.intersects(play_layers));
+  assert_eq!(editor_layer.union(LayerMask::DEFAULT), LayerMask(0x8000_0001));
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn camera_draws_renderables_sharing_a_layer() {
+  let editor_layer = LayerMask::layer(31);
+  let play_layers = LayerMask::ALL.without(editor_layer);
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  let helper = mesh_handles.allocate();
+  let mut messages = cuboid_messages("cube", cube);
+  messages.extend(cuboid_messages("helper", helper));
+  messages.push(RendererMessage::SetRenderableLayers(helper, editor_layer));
+  render_mgr.process_messages(messages);
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(render_mgr.draw_batches.len(), 2);
+
+  render_mgr.set_camera_layers(play_layers);
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(render_mgr.draw_batches.len(), 1);
+  assert_eq!(render_mgr.draw_list.shown().collect::<Vec<_>>(), vec![cube]);
+
+  // A camera only seeing the editor layer, like a minimap camera with its own set
+  render_mgr.set_camera_layers(editor_layer);
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(render_mgr.draw_list.shown().collect::<Vec<_>>(), vec![helper]);
+  render_mgr.process_messages(vec![RendererMessage::SetRenderableLayers(helper, LayerMask::NONE)]);
+  draw_frames(&mut render_mgr, 1);
+  assert!(render_mgr.draw_batches.is_empty());
+}
+
+#[test]
+fn minimap_camera_centers_under_the_main_camera_north_up() {
+  let marker_layer = LayerMask::layer(1);
+  let settings = MinimapSettings { view_radius: 10.0, layers: marker_layer, ..Default::default() };
+  let main_camera = Camera3D::new(glam::vec4(5.0, 2.0, 5.0, 1.0), glam::Vec4::NEG_Z, 1.0);
+  let map_camera = minimap_camera(main_camera, &settings);
+  let on_map = |point: glam::Vec3| map_camera.view_proj_mat.project_point3(point).truncate();
+  // Centered under the main camera, north up and east to the right
+  assert!(on_map(glam::vec3(5.0, 0.0, 5.0)).abs_diff_eq(glam::Vec2::ZERO, 1e-5));
+  assert!(on_map(glam::vec3(5.0, 0.0, -5.0)).abs_diff_eq(glam::Vec2::Y, 1e-5));
+  assert!(on_map(glam::vec3(15.0, 0.0, 5.0)).abs_diff_eq(glam::Vec2::X, 1e-5));
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn minimap_draws_its_own_layers_into_a_corner() {
+  let marker_layer = LayerMask::layer(1);
+  let settings = MinimapSettings { view_radius: 10.0, layers: marker_layer, ..Default::default() };
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  let marker = mesh_handles.allocate();
+  let mut messages = cuboid_messages("cube", cube);
+  messages.extend(cuboid_messages("marker", marker));
+  messages.push(RendererMessage::SetRenderableLayers(marker, marker_layer));
+  messages.push(RendererMessage::SetMinimap(Some(settings)));
+  render_mgr.process_messages(messages);
+  render_mgr.set_camera_layers(LayerMask::ALL.without(marker_layer));
+  draw_frames(&mut render_mgr, 2);
+  assert_eq!(render_mgr.draw_batches.len(), 1);
+  let minimap = render_mgr.minimap.as_ref().expect("minimap should be set");
+  assert_eq!(minimap.draw_batches().len(), 1);
+  let map_size = HEIGHT / 4;
+  assert_eq!(
+    minimap.frame_buffer(0).resolution(),
+    vk::Extent2D { width: map_size, height: map_size }
+  );
+
+  render_mgr.process_messages(vec![RendererMessage::SetMinimap(None)]);
+  draw_frames(&mut render_mgr, 1);
+  assert!(render_mgr.minimap.is_none());
+}
+
+#[test]
+fn taa_jitters_within_a_pixel() {
+  assert_eq!(taa::halton(1, 2), 0.5);
+  assert_eq!(taa::halton(3, 2), 0.75);
+  assert!((taa::halton(2, 3) - 2.0 / 3.0).abs() < 1e-6);
+  let resolution = vk::Extent2D { width: WIDTH, height: HEIGHT };
+  let pixel = glam::vec2(2.0 / WIDTH as f32, 2.0 / HEIGHT as f32);
+  let jitters = (0..8).map(|frame| taa::jitter_offset(frame, resolution)).collect::<Vec<_>>();
+  for (i, jitter) in jitters.iter().enumerate() {
+    assert!(jitter.abs().cmple(pixel * 0.5).all());
+    assert!(!jitters[..i].contains(jitter));
+  }
+  assert_eq!(taa::jitter_offset(8, resolution), jitters[0]);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn taa_tracks_moved_meshes() {
+  let config =
+    RendererConfig { anti_aliasing: AntiAliasing::Taa, msaa_samples: 4, ..Default::default() };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  draw_frames(&mut render_mgr, 2);
+  assert_eq!(render_mgr.triangle_frame_buffers[0].attachments().len(), 2);
+
+  let moved = glam::Mat4::from_translation(glam::Vec3::X);
+  render_mgr
+    .update_tri_mesh_transform(cube, TriMeshTransform { transform: moved })
+    .expect("cube should move");
+  draw_frames(&mut render_mgr, 1);
+  // Drawn once from where it was, the next frame catches its previous transform up
+  assert_eq!(render_mgr.drawn_transforms[&cube], moved);
+  assert!(render_mgr.moved_meshes.contains(&cube));
+  draw_frames(&mut render_mgr, 1);
+  assert!(render_mgr.moved_meshes.is_empty());
+}
+
+#[test]
+fn motion_blur_intensity_is_validated() {
+  assert!(EngineConfig::builder().motion_blur(true).motion_blur_intensity(1.5).build().is_ok());
+  assert!(EngineConfig::builder().motion_blur_intensity(-0.5).build().is_err());
+  assert!(EngineConfig::builder().motion_blur_intensity(5.0).build().is_err());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn motion_blur_shares_velocity_with_taa_and_needs_one_sample() {
+  let post_process =
+    PostProcessConfig { motion_blur: true, motion_blur_intensity: 1.0, ..Default::default() };
+  let msaa_config =
+    RendererConfig { msaa_samples: 4, post_process: post_process.clone(), ..Default::default() };
+  let (msaa_mgr, _window) = headless_render_manager(msaa_config);
+  // Left off when the gpu does msaa, drawn without it when the gpu can't. Msaa framebuffers have
+  // a resolve attachment after the color and depth
+  let single_sampled = msaa_mgr.triangle_frame_buffers[0].attachments().len() == 2;
+  assert_eq!(msaa_mgr.post_process.is_some(), single_sampled);
+  drop(msaa_mgr);
+
+  for anti_aliasing in [AntiAliasing::Msaa, AntiAliasing::Taa] {
+    let config =
+      RendererConfig { anti_aliasing, post_process: post_process.clone(), ..Default::default() };
+    let (mut render_mgr, _window) = headless_render_manager(config);
+    assert!(render_mgr.post_process.is_some());
+    let mut mesh_handles = HandleAllocator::new();
+    let cube = mesh_handles.allocate();
+    render_mgr.process_messages(cuboid_messages("cube", cube));
+    draw_frames(&mut render_mgr, 2);
+    render_mgr
+      .update_tri_mesh_transform(
+        cube,
+        TriMeshTransform { transform: glam::Mat4::from_translation(glam::Vec3::X) },
+      )
+      .expect("cube should move");
+    draw_frames(&mut render_mgr, 2);
+    assert!(render_mgr.moved_meshes.is_empty());
+  }
+}
+
+#[test]
+fn auto_exposure_adapts_within_clamps() {
+  assert_eq!(exposure::adaptation(0.0, 1.5), 0.0);
+  assert!((exposure::adaptation(1.0, 1.0) - 0.632).abs() < 0.001);
+  // Two half second frames cover as much as one second frame
+  let half = exposure::adaptation(0.5, 1.5);
+  assert!((1.0 - (1.0 - half) * (1.0 - half) - exposure::adaptation(1.0, 1.5)).abs() < 0.0001);
+  assert!(EngineConfig::builder().exposure_ev_range(2.0, -2.0).build().is_err());
+  assert!(EngineConfig::builder().exposure_adaptation_rate(0.0).build().is_err());
+  assert!(EngineConfig::builder().exposure_mode(ExposureMode::Auto).build().is_ok());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn auto_exposure_draws_and_takes_manual_overrides() {
+  let post_process = PostProcessConfig { exposure_mode: ExposureMode::Auto, ..Default::default() };
+  let config = RendererConfig { post_process, ..Default::default() };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  draw_frames(&mut render_mgr, 3);
+  render_mgr.process_messages(vec![RendererMessage::SetExposure(Some(1.0))]);
+  draw_frames(&mut render_mgr, 2);
+  render_mgr.process_messages(vec![RendererMessage::SetExposure(None)]);
+  draw_frames(&mut render_mgr, 2);
+}
+
+#[test]
+fn frame_capture_is_refused_without_renderdoc() {
+  // Refused whether or not renderdoc is attached to the test run
+  assert!(FrameCapture::new().trigger(0).is_err());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn frame_capture_is_requested_through_messages() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  draw_frames(&mut render_mgr, 1);
+  // Without renderdoc it's only reported, frames keep being drawn
+  render_mgr.process_messages(vec![RendererMessage::TriggerCapture(2)]);
+  draw_frames(&mut render_mgr, 3);
+}
+
+#[test]
+fn quality_governor_waits_for_sustained_pressure() {
+  let mut governor = quality_governor::QualityGovernor::new(Duration::from_millis(10));
+  governor.add_callback(Box::new(|pressure, _, knobs| match pressure {
+    QualityPressure::OverBudget => knobs.render_scale -= 0.25,
+    QualityPressure::Headroom => knobs.render_scale += 0.25,
+  }));
+  let knobs = QualityKnobs { render_scale: 1.0, shadow_map_size: 2048 };
+  let over_budget = Duration::from_millis(12);
+  for _ in 1..quality_governor::SUSTAIN_FRAMES {
+    assert_eq!(governor.frame_timed(over_budget), None);
+  }
+  assert_eq!(governor.frame_timed(over_budget), Some(QualityPressure::OverBudget));
+  let knobs = governor.adjust(QualityPressure::OverBudget, knobs);
+  assert_eq!(knobs.render_scale, 0.75);
+
+  // Under the budget without headroom nothing changes
+  let settled_frames = 2 * quality_governor::SUSTAIN_FRAMES as u32;
+  for _ in 0..settled_frames {
+    assert_eq!(governor.frame_timed(Duration::from_millis(8)), None);
+  }
+  // Headroom is only given once lowering has cooled down
+  let frames_to_headroom = (1..)
+    .find(|_| governor.frame_timed(Duration::from_millis(5)).is_some())
+    .expect("headroom should come");
+  assert_eq!(settled_frames + frames_to_headroom, quality_governor::RAISE_COOLDOWN_FRAMES);
+  assert_eq!(governor.adjust(QualityPressure::Headroom, knobs).render_scale, 1.0);
+  assert_eq!(governor.frame_timed(Duration::from_millis(5)), None);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn quality_callbacks_lower_render_scale_and_shadows() {
+  let config = RendererConfig {
+    gpu_frame_budget_ms: 1.0,
+    shadow_mode: ShadowMode::ShadowMap,
+    ..Default::default()
+  };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  render_mgr.process_messages(vec![RendererMessage::AddQualityCallback(Box::new(
+    |pressure, _, knobs| {
+      if pressure == QualityPressure::OverBudget {
+        *knobs = QualityKnobs { render_scale: 0.5, shadow_map_size: 512 };
+      }
+    },
+  ))]);
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  draw_frames(&mut render_mgr, 2 * quality_governor::SUSTAIN_FRAMES as u64);
+  // Fast gpus may never go over even this budget
+  if render_mgr.config.render_scale == 0.5 {
+    draw_frames(&mut render_mgr, 1);
+    assert_eq!(render_mgr.shadows.map_size(), 512);
+    let resolution = render_mgr.triangle_frame_buffers[0].resolution();
+    assert_eq!(
+      resolution,
+      RenderManager::scaled_resolution(render_mgr.swapchain.resolution(), 0.5)
+    );
+  }
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn destroyed_mesh_outlives_frames_in_flight() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  draw_frames(&mut render_mgr, 5);
+  let cube_gpu = render_mgr.tri_meshes["cube"].clone();
+
+  render_mgr.process_messages(vec![RendererMessage::DestroyTriMesh(cube)]);
+  // Frames drawn before the destroy may still read the mesh until their fences are waited on
+  for _ in 0..frames_in_flight {
+    assert!(cube_gpu.strong_count() > 0);
+    draw_frames(&mut render_mgr, 1);
+  }
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(cube_gpu.strong_count(), 0);
+  assert!(render_mgr.retired_resources.is_empty());
+}
+
+#[test]
+fn loading_backlog_applies_uploads_over_frames() {
+  let mut mesh_handles = HandleAllocator::new();
+  let mut backlog = MessageBacklog::new();
+  let mut messages = vec![RendererMessage::SetWind(Default::default())];
+  messages.push(RendererMessage::BeginLoading);
+  for i in 0..20 {
+    messages.extend(cuboid_messages(&format!("cube_{i}"), mesh_handles.allocate()));
+  }
+  messages.push(RendererMessage::EndLoading);
+  messages.push(RendererMessage::SetWind(Default::default()));
+  backlog.push(messages);
+  assert_eq!(backlog.progress(), None);
+
+  // Stops before the ninth upload, the renderable after the eighth still goes with it
+  assert_eq!(backlog.next_batch().len(), 17);
+  assert_eq!(backlog.progress(), Some(LoadingProgress { done: 8, total: 20 }));
+  // Uploads queued later count towards the same loading screen
+  backlog.push(cuboid_messages("late_cube", mesh_handles.allocate()));
+  assert_eq!(backlog.progress(), Some(LoadingProgress { done: 8, total: 20 }));
+  assert_eq!(backlog.next_batch().len(), 16);
+  assert_eq!(backlog.progress(), Some(LoadingProgress { done: 16, total: 20 }));
+
+  // The rest of the loading uploads, then everything after EndLoading at once
+  assert_eq!(backlog.next_batch().len(), 11);
+  assert_eq!(backlog.progress(), None);
+  assert!(backlog.next_batch().is_empty());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn loading_screen_replaces_scene_until_loaded() {
+  let (mut render_mgr, window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  render_mgr.loading_progress = Some(LoadingProgress { done: 1, total: 4 });
+  draw_frames(&mut render_mgr, 3);
+  // Nothing from the scene is recorded while loading
+  assert!(render_mgr.draw_batches.is_empty());
+  assert_eq!(window.presented_frames(), 3);
+
+  render_mgr.loading_progress = None;
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(render_mgr.draw_batches.len(), 1);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn shadowed_scene_draws_through_resize() {
+  // Ray traced shadows fall back to shadow maps where the gpu or build can't trace
+  for shadow_mode in [ShadowMode::ShadowMap, ShadowMode::RayTraced] {
+    let config = RendererConfig { shadow_mode, ..Default::default() };
+    let (mut render_mgr, window) = headless_render_manager(config);
+    let mut mesh_handles = HandleAllocator::new();
+    let cube = mesh_handles.allocate();
+    render_mgr.process_messages(cuboid_messages("cube", cube));
+    draw_frames(&mut render_mgr, 3);
+
+    window.resize(WIDTH * 2, HEIGHT);
+    assert!(render_mgr.draw().expect("resize should refresh the swapchain"));
+    draw_frames(&mut render_mgr, 3);
+
+    let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+    render_mgr.process_messages(vec![RendererMessage::DestroyTriMesh(cube)]);
+    draw_frames(&mut render_mgr, frames_in_flight + 1);
+    assert!(render_mgr.retired_resources.is_empty());
+  }
+}
+
+#[test]
+fn shadow_cascade_splits_favor_the_near_cascade() {
+  let splits = shadow_renderers::cascade_splits(1.0, 60.0, 3);
+  assert_eq!(splits.len(), 3);
+  assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+  assert!((splits[2] - 60.0).abs() < 1e-3);
+  // Nearer than an even split, so the nearest cascade gets the finest texels
+  assert!(splits[0] < 1.0 + 59.0 / 3.0);
+  assert_eq!(shadow_renderers::cascade_splits(1.0, 60.0, 1), splits[2..].to_vec());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn shadow_cascades_draw() {
+  let config = RendererConfig {
+    shadow_mode: ShadowMode::ShadowMap,
+    shadow_cascades: 3,
+    shadow_map_size: 1024,
+    ..Default::default()
+  };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  assert_eq!(render_mgr.shadows.map_size(), 1024);
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  draw_frames(&mut render_mgr, 3);
+  render_mgr.frame_sync.wait_all().expect("frames should finish");
+  render_mgr
+    .shadows
+    .set_map_size(&render_mgr.render_cmd_buffers[0], 512)
+    .expect("cascades should be remade");
+  draw_frames(&mut render_mgr, 3);
+}
+
+#[test]
+fn sun_and_sky_blend_between_snapshots() {
+  let dawn = FrameSnapshot {
+    sun: SunLight {
+      direction: glam::Vec3::X,
+      color: Color::rgb(1.0, 0.5, 0.2),
+      intensity: 0.5,
+      ambient: Color::rgb(0.1, 0.1, 0.2),
+    },
+    sky_color: Color::rgb(0.8, 0.4, 0.2),
+    ..Default::default()
+  };
+  let noon = FrameSnapshot {
+    sun: SunLight { direction: glam::Vec3::Y, ..Default::default() },
+    sky_color: Color::rgb(0.4, 0.6, 1.0),
+    ..Default::default()
+  };
+  let mut out = FrameSnapshot::default();
+  noon.interpolate_from(&dawn, 0.5, &mut out);
+  // Turned rather than blended, so it stays a unit vector halfway round
+  assert!((out.sun.direction.length() - 1.0).abs() < 1e-5);
+  assert!(
+    (out.sun.direction.angle_between(glam::Vec3::X) - std::f32::consts::FRAC_PI_4).abs() < 1e-4
+  );
+  assert!((out.sun.intensity - 0.75).abs() < 1e-5);
+  assert_eq!(out.sky_color, dawn.sky_color.lerp(noon.sky_color, 0.5));
+  assert_eq!(noon.sun.radiance(), glam::Vec3::ONE);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn sun_and_sky_light_the_frame() {
+  let dawn = FrameSnapshot {
+    sun: SunLight {
+      direction: glam::Vec3::X,
+      color: Color::rgb(1.0, 0.5, 0.2),
+      intensity: 0.5,
+      ambient: Color::rgb(0.1, 0.1, 0.2),
+    },
+    sky_color: Color::rgb(0.8, 0.4, 0.2),
+    ..Default::default()
+  };
+  let config = RendererConfig { shadow_mode: ShadowMode::ShadowMap, ..Default::default() };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  // Straight down the old up vector of the cascades too
+  for direction in [glam::Vec3::Y, glam::Vec3::Z, glam::vec3(-1.0, 0.2, 0.0)] {
+    render_mgr.sun = SunLight { direction, ..dawn.sun };
+    render_mgr.tri_mesh_renderer.set_clear_color(dawn.sky_color);
+    draw_frames(&mut render_mgr, 2);
+  }
+}
+
+#[test]
+fn shadow_atlas_packs_lights_all_or_nothing() {
+  let mut atlas = ShadowAtlas::new(2048, 64);
+  assert_eq!(atlas.slot_size(300), 512);
+  assert_eq!(atlas.slot_size(10), 64);
+  let spot = atlas.allocate(1024, 1).expect("spot shadow should fit");
+  let point = atlas.allocate(512, 6).expect("point shadow should fit");
+  // Six 512 slots are left, a light wanting more gets none and takes nothing from the next
+  assert_eq!(atlas.allocate(512, 8), None);
+  let second_point = atlas.allocate(512, 6).expect("second point shadow should fit");
+  assert_eq!(atlas.allocate(64, 1), None);
+
+  let slots = spot.iter().chain(point.iter()).chain(second_point.iter()).collect::<Vec<_>>();
+  for (i, a) in slots.iter().enumerate() {
+    assert!(a.x + a.size <= 2048 && a.y + a.size <= 2048);
+    for b in slots[i + 1..].iter() {
+      let apart =
+        a.x + a.size <= b.x || b.x + b.size <= a.x || a.y + a.size <= b.y || b.y + b.size <= a.y;
+      assert!(apart, "{a:?} overlaps {b:?}");
+    }
+  }
+}
+
+#[test]
+fn transient_images_alias_when_their_passes_never_overlap() {
+  let lifetime = |first_pass, last_pass, size, memory_type_bits| TransientLifetime {
+    first_pass,
+    last_pass,
+    requirements: vk::MemoryRequirements { size, alignment: size / 4, memory_type_bits },
+  };
+  let lifetimes = [
+    lifetime(0, 0, 64, 0b11),
+    lifetime(1, 2, 256, 0b11),
+    lifetime(1, 1, 128, 0b11),
+    lifetime(3, 3, 512, 0b01),
+    // Overlaps nothing, but only fits memory none of the others can use
+    lifetime(4, 4, 32, 0b100),
+  ];
+  let groups = plan_aliasing(&lifetimes);
+  let mut grouped = groups.iter().flat_map(|group| group.images.clone()).collect::<Vec<_>>();
+  grouped.sort();
+  assert_eq!(grouped, (0..lifetimes.len()).collect::<Vec<_>>());
+  for group in &groups {
+    for (i, &a) in group.images.iter().enumerate() {
+      let a = lifetimes[a];
+      assert!(a.requirements.size <= group.requirements.size);
+      assert_eq!(group.requirements.alignment % a.requirements.alignment, 0);
+      assert_ne!(group.requirements.memory_type_bits & a.requirements.memory_type_bits, 0);
+      for &b in &group.images[i + 1..] {
+        let b = lifetimes[b];
+        assert!(a.last_pass < b.first_pass || b.last_pass < a.first_pass);
+      }
+    }
+  }
+  // The biggest takes the first block, and the ones of pass 0 and of passes 1 to 2 overlap neither
+  // it nor each other. The one of pass 1 overlaps passes 1 to 2 and the odd memory type fits no
+  // block, so each gets its own
+  assert_eq!(groups.len(), 3);
+  assert_eq!(groups[0].images, vec![3, 1, 0]);
+  assert_eq!(groups[0].requirements.memory_type_bits, 0b01);
+  assert_eq!(groups.iter().map(|group| group.requirements.size).sum::<u64>(), 512 + 128 + 32);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn post_targets_alias_transient_images_and_draw() {
+  let post_process = PostProcessConfig {
+    motion_blur: true,
+    depth_of_field: true,
+    vignette: true,
+    ..Default::default()
+  };
+  let config =
+    RendererConfig { anti_aliasing: AntiAliasing::Taa, post_process, ..Default::default() };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  let transient_images = post_transient_images(
+    render_mgr.ash_device.clone(),
+    render_mgr.gen_allocator.clone(),
+    &render_mgr.triangle_frame_buffers,
+    true,
+    true,
+  )
+  .expect("post targets should be made");
+  // Motion blur and film effects run outside depth of field, and fit in what it uses
+  let memory =
+    |name| transient_images.get(name).expect("target should be made").allocation().clone();
+  let dof_memory =
+    ["dof_coc_image", "dof_near_image", "dof_far_image", "dof_output_image"].map(memory);
+  for name in ["motion_blur_image", "film_effects_output_image"] {
+    assert!(dof_memory.iter().any(|dof_memory| Arc::ptr_eq(dof_memory, &memory(name))));
+  }
+  assert!(transient_images.aliased_size() < transient_images.dedicated_size());
+  drop(transient_images);
+  let mut mesh_handles = HandleAllocator::new();
+  render_mgr.process_messages(cuboid_messages("cube", mesh_handles.allocate()));
+  let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+  draw_frames(&mut render_mgr, frames_in_flight + 2);
+}
+
+#[test]
+fn local_light_shadow_cameras_follow_the_shader_face_order() {
+  let point = LocalLight { range: 8.0, casts_shadows: true, ..Default::default() };
+  let faces = shadow_renderers::light_shadow_cameras(&point);
+  assert_eq!(faces.len(), 6);
+  // Faces are in the order the shader picks them by the major axis
+  for (face, dir) in [glam::Vec3::X, glam::Vec3::NEG_X, glam::Vec3::Y].into_iter().enumerate() {
+    let ndc = faces[face].view_proj_mat.project_point3(dir * 4.0);
+    assert!(ndc.x.abs() < 1e-4 && ndc.y.abs() < 1e-4 && (0.0..1.0).contains(&ndc.z));
+  }
+  let spot = LocalLight {
+    position: glam::vec3(0.0, 4.0, 0.0),
+    shape: LightShape::Spot { direction: glam::Vec3::NEG_Y, inner_angle: 0.3, outer_angle: 0.5 },
+    casts_shadows: true,
+    ..Default::default()
+  };
+  assert_eq!(shadow_renderers::light_shadow_cameras(&spot).len(), 1);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn local_lights_shade_and_cast_into_the_atlas() {
+  let point = LocalLight { range: 8.0, casts_shadows: true, ..Default::default() };
+  let spot = LocalLight {
+    position: glam::vec3(0.0, 4.0, 0.0),
+    shape: LightShape::Spot { direction: glam::Vec3::NEG_Y, inner_angle: 0.3, outer_angle: 0.5 },
+    casts_shadows: true,
+    ..Default::default()
+  };
+  let config = RendererConfig { shadow_mode: ShadowMode::ShadowMap, ..Default::default() };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  let mut light_handles = HandleAllocator::new();
+  let (point_light, spot_light) = (light_handles.allocate(), light_handles.allocate());
+  render_mgr.process_messages(vec![
+    RendererMessage::SetLight(
+      point_light,
+      LocalLight { position: glam::vec3(2.0, 1.0, 0.0), ..point },
+    ),
+    RendererMessage::SetLight(spot_light, spot),
+  ]);
+  draw_frames(&mut render_mgr, 3);
+  assert_eq!(render_mgr.lights.len(), 2);
+  render_mgr.process_messages(vec![RendererMessage::RemoveLight(point_light)]);
+  draw_frames(&mut render_mgr, 2);
+  assert_eq!(render_mgr.lights.len(), 1);
+}
+
+#[test]
+fn lights_are_culled_faded_and_shadow_capped_by_contribution() {
+  let mut camera =
+    Camera3D::new(glam::vec4(0.0, 0.0, 0.0, 1.0), glam::vec4(0.0, 0.0, -1.0, 0.0), 1.0);
+  camera.refresh_vp_matrix(CAMERA_FOV, 1.0);
+  let light_at = |z: f32| LocalLight {
+    position: glam::vec3(0.0, 0.0, z),
+    range: 5.0,
+    casts_shadows: true,
+    ..Default::default()
+  };
+  let near = light_at(-3.0);
+  let far = light_at(-400.0);
+  let behind = light_at(20.0);
+  assert_eq!(light_contribution(&near, camera, CAMERA_FOV), 1.0);
+  assert_eq!(light_contribution(&behind, camera, CAMERA_FOV), 0.0);
+  let far_contribution = light_contribution(&far, camera, CAMERA_FOV);
+  assert!(far_contribution > 0.0 && far_contribution < 0.01);
+
+  // The far light sits between the threshold and twice it, so it's dimmed rather than dropped
+  let culling = LightCulling::new(far_contribution * 0.75, 1);
+  let lights = [far, behind, light_at(-10.0), near];
+  let kept = culling.prioritize(lights.iter(), camera, CAMERA_FOV);
+  assert_eq!(kept.len(), 3);
+  assert_eq!(kept[0].position, near.position);
+  assert_eq!(kept[2].position, far.position);
+  assert!(kept[2].intensity > 0.0 && kept[2].intensity < 1.0);
+  assert_eq!(kept.iter().filter(|light| light.casts_shadows).count(), 1);
+  assert!(kept[0].casts_shadows);
+  assert_eq!(
+    LightCulling::new(far_contribution * 1.5, 8)
+      .prioritize(lights.iter(), camera, CAMERA_FOV)
+      .len(),
+    2
+  );
+}
+
+// Top half follows the second joint
+fn skinned_cuboid() -> SkinnedMeshCPU {
+  let cuboid = TriMeshCPU::make_cuboid(glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y, 1.0);
+  SkinnedMeshCPU {
+    verts: cuboid
+      .vertices
+      .iter()
+      .map(|vert| CrowdVertex {
+        pos: vert.pos,
+        normal: vert.normal,
+        uv: vert.uv,
+        joints: [(vert.pos.y > 0.0) as u32, 0, 0, 0],
+        weights: glam::Vec4::X,
+      })
+      .collect(),
+    triangles: cuboid.triangles,
+    joint_count: 2,
+    bounding_radius: 2.0,
+  }
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn skinned_mesh_draws_as_mesh() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(vec![
+    RendererMessage::UploadSkinnedMesh("skinned_cube".to_string(), skinned_cuboid(), cube),
+    RendererMessage::AddRenderable(cube, None),
+  ]);
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(render_mgr.draw_batches.len(), 1);
+
+  let pose = [glam::Mat4::IDENTITY, glam::Mat4::from_translation(glam::Vec3::Y)];
+  assert!(render_mgr.update_joint_matrices(cube, &pose[..1]).is_err());
+  render_mgr.update_joint_matrices(cube, &pose).expect("pose should match the joints");
+  draw_frames(&mut render_mgr, 3);
+
+  render_mgr.process_messages(vec![RendererMessage::DestroyTriMesh(cube)]);
+  assert!(render_mgr.update_joint_matrices(cube, &pose).is_err());
+  draw_frames(&mut render_mgr, frames_in_flight + 1);
+  assert!(render_mgr.retired_resources.is_empty());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn skinned_mesh_tracks_previous_pose_for_velocity() {
+  let config = RendererConfig { anti_aliasing: AntiAliasing::Taa, ..Default::default() };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(vec![
+    RendererMessage::UploadSkinnedMesh("skinned_cube".to_string(), skinned_cuboid(), cube),
+    RendererMessage::AddRenderable(cube, None),
+  ]);
+  let rest = [glam::Mat4::IDENTITY; 2];
+  render_mgr.update_joint_matrices(cube, &rest).expect("pose should match the joints");
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(render_mgr.drawn_poses[&cube], rest);
+  assert!(render_mgr.moved_meshes.is_empty());
+
+  let raised = [glam::Mat4::IDENTITY, glam::Mat4::from_translation(glam::Vec3::Y)];
+  render_mgr.update_joint_matrices(cube, &raised).expect("pose should match the joints");
+  draw_frames(&mut render_mgr, 1);
+  // Drawn once from the rest pose, the next frame catches its previous pose up
+  assert_eq!(render_mgr.drawn_poses[&cube], raised);
+  assert!(render_mgr.moved_meshes.contains(&cube));
+  draw_frames(&mut render_mgr, 1);
+  assert!(render_mgr.moved_meshes.is_empty());
+
+  render_mgr.process_messages(vec![RendererMessage::DestroyTriMesh(cube)]);
+  assert!(!render_mgr.drawn_poses.contains_key(&cube));
+}
+
+#[test]
+fn cloth_hangs_from_its_pins_and_moves_in_wind() {
+  let cloth = ClothCPU::new(8, 6, 0.25);
+  assert!(ClothCPU { pinned: vec![48], ..cloth.clone() }.validate().is_err());
+  assert!(ClothCPU::new(1, 6, 0.25).validate().is_err());
+  let gravity = glam::Vec3::NEG_Y * 9.81;
+  let mut still = ClothSim::new(&cloth).expect("cloth should be valid");
+  let mut windy = ClothSim::new(&cloth).expect("cloth should be valid");
+  for _ in 0..120 {
+    still.step(1.0 / 60.0, gravity, glam::Vec3::ZERO);
+    windy.step(1.0 / 60.0, gravity, glam::Vec3::Z * 5.0);
+  }
+  let rest = cloth.rest_positions();
+  for pin in cloth.pinned.iter() {
+    assert_eq!(still.positions()[*pin as usize], rest[*pin as usize]);
+  }
+  // Sags between the pins without stretching much along the rows
+  let middle = (cloth.columns / 2) as usize;
+  assert!(still.positions()[middle].y < rest[middle].y);
+  for row in still.positions().chunks(cloth.columns as usize) {
+    for pair in row.windows(2) {
+      assert!((pair[0].distance(pair[1]) - cloth.spacing).abs() < cloth.spacing * 0.1);
+    }
+  }
+  let bottom = rest.len() - 1 - middle;
+  assert!(windy.positions()[bottom].z > still.positions()[bottom].z + 0.1);
+  assert_eq!(still.vertices().len(), cloth.particle_count());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn cloth_draws_and_retires_with_cpu_and_gpu_sims() {
+  let cloth = ClothCPU::new(8, 6, 0.25);
+  for gpu_cloth in [false, true] {
+    let config = RendererConfig { gpu_cloth, ..Default::default() };
+    let (mut render_mgr, _window) = headless_render_manager(config);
+    let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+    let mut mesh_handles = HandleAllocator::new();
+    let flag = mesh_handles.allocate();
+    render_mgr.process_messages(vec![
+      RendererMessage::UploadCloth("flag".to_string(), cloth.clone(), flag),
+      RendererMessage::AddRenderable(flag, None),
+    ]);
+    draw_frames(&mut render_mgr, 3);
+    assert_eq!(render_mgr.draw_batches.len(), 1);
+    assert_eq!(render_mgr.cloths[&flag].sim_dset().is_some(), gpu_cloth);
+
+    render_mgr.process_messages(vec![RendererMessage::DestroyTriMesh(flag)]);
+    assert!(render_mgr.cloths.is_empty());
+    draw_frames(&mut render_mgr, frames_in_flight + 1);
+    assert!(render_mgr.retired_resources.is_empty());
+  }
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn frame_cmd_buffers_record_on_owning_thread_only() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let cmd_buffer = &render_mgr.render_cmd_buffers[0];
+  let off_thread = std::thread::scope(|scope| {
+    scope.spawn(|| cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)).join()
+  })
+  .expect("recording thread should not panic");
+  assert!(off_thread.is_err());
+  // Job slots move between workers, their pools stay shared
+  assert!(render_mgr
+    .secondary_cmd_buffers
+    .iter()
+    .flatten()
+    .all(|cmd_buffer| cmd_buffer.cmd_pool().owner().is_none()));
+  draw_frames(&mut render_mgr, 3);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn transfer_helpers_fill_update_clear_and_read_back() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  draw_frames(&mut render_mgr, 1);
+  render_mgr.frame_sync.wait_all().expect("frames should finish");
+  let ash_device = render_mgr.ash_device.clone();
+  let extent = vk::Extent2D { width: 4, height: 4 };
+  let texel_count = (extent.width * extent.height) as usize;
+  // Filled and updated words, then the color image's texels, then the depth image's
+  let readback = AdBuffer::new(
+    ash_device.clone(),
+    render_mgr.gen_allocator.clone(),
+    gpu_allocator::MemoryLocation::GpuToCpu,
+    "transfer_readback",
+    vk::BufferCreateFlags::empty(),
+    (16 + texel_count * 8) as vk::DeviceSize,
+    vk::BufferUsageFlags::TRANSFER_DST,
+  )
+  .expect("buffer should be created");
+  let image = |name, format, aspect| {
+    let image = AdImage::new_2d(
+      ash_device.clone(),
+      render_mgr.gen_allocator.clone(),
+      gpu_allocator::MemoryLocation::GpuOnly,
+      name,
+      format,
+      extent,
+      vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+      vk::SampleCountFlags::TYPE_1,
+      1,
+    )
+    .expect("image should be created");
+    let range =
+      vk::ImageSubresourceRange::default().aspect_mask(aspect).level_count(1).layer_count(1);
+    (image, range)
+  };
+  let (color, color_range) =
+    image("transfer_color", vk::Format::R8G8B8A8_UNORM, vk::ImageAspectFlags::COLOR);
+  let (depth, depth_range) =
+    image("transfer_depth", vk::Format::D32_SFLOAT, vk::ImageAspectFlags::DEPTH);
+  let barrier = |image: &AdImage, range, old_layout, new_layout, src_access, dst_access| {
+    vk::ImageMemoryBarrier::default()
+      .image(image.inner())
+      .subresource_range(range)
+      .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+      .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+      .old_layout(old_layout)
+      .new_layout(new_layout)
+      .src_access_mask(src_access)
+      .dst_access_mask(dst_access)
+  };
+  let images = [(&color, color_range), (&depth, depth_range)];
+  let transitions = |old_layout, new_layout, src_access, dst_access| {
+    images
+      .iter()
+      .map(|(image, range)| barrier(image, *range, old_layout, new_layout, src_access, dst_access))
+      .collect::<Vec<_>>()
+  };
+  let copy_region = |offset, aspect| {
+    vk::BufferImageCopy::default()
+      .buffer_offset(offset)
+      .image_subresource(vk::ImageSubresourceLayers::default().aspect_mask(aspect).layer_count(1))
+      .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+  };
+
+  let cmd_buffer = AdCommandBuffer::new(
+    render_mgr.render_cmd_buffers[0].cmd_pool().clone(),
+    vk::CommandBufferLevel::PRIMARY,
+    1,
+  )
+  .expect("cmd buffer should be allocated")
+  .remove(0);
+  cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT).expect("recording should begin");
+  cmd_buffer.fill_buffer(readback.inner(), 0, 16, 0xdeadbeef);
+  cmd_buffer.pipeline_barrier(
+    vk::PipelineStageFlags::TRANSFER,
+    vk::PipelineStageFlags::TRANSFER,
+    vk::DependencyFlags::empty(),
+    &[vk::MemoryBarrier::default()
+      .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+      .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)],
+    &[],
+    &transitions(
+      vk::ImageLayout::UNDEFINED,
+      vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+      vk::AccessFlags::NONE,
+      vk::AccessFlags::TRANSFER_WRITE,
+    ),
+  );
+  cmd_buffer
+    .update_buffer(readback.inner(), 8, AdBuffer::get_byte_slice(&[7u32, 9u32]))
+    .expect("small aligned update should record");
+  assert!(cmd_buffer.update_buffer(readback.inner(), 0, &[1, 2, 3]).is_err());
+  assert!(cmd_buffer.update_buffer(readback.inner(), 2, &[1, 2, 3, 4]).is_err());
+  cmd_buffer.clear_color_image(
+    color.inner(),
+    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+    &vk::ClearColorValue { float32: [0.0, 1.0, 0.0, 1.0] },
+    &[color_range],
+  );
+  cmd_buffer.clear_depth_stencil_image(
+    depth.inner(),
+    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+    &vk::ClearDepthStencilValue { depth: 0.25, stencil: 0 },
+    &[depth_range],
+  );
+  cmd_buffer.pipeline_barrier(
+    vk::PipelineStageFlags::TRANSFER,
+    vk::PipelineStageFlags::TRANSFER,
+    vk::DependencyFlags::empty(),
+    &[],
+    &[],
+    &transitions(
+      vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+      vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+      vk::AccessFlags::TRANSFER_WRITE,
+      vk::AccessFlags::TRANSFER_READ,
+    ),
+  );
+  cmd_buffer.copy_image_to_buffer(
+    color.inner(),
+    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+    readback.inner(),
+    &[copy_region(16, vk::ImageAspectFlags::COLOR)],
+  );
+  cmd_buffer.copy_image_to_buffer(
+    depth.inner(),
+    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+    readback.inner(),
+    &[copy_region(16 + texel_count as u64 * 4, vk::ImageAspectFlags::DEPTH)],
+  );
+  cmd_buffer.pipeline_barrier(
+    vk::PipelineStageFlags::TRANSFER,
+    vk::PipelineStageFlags::HOST,
+    vk::DependencyFlags::empty(),
+    &[vk::MemoryBarrier::default()
+      .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+      .dst_access_mask(vk::AccessFlags::HOST_READ)],
+    &[],
+    &[],
+  );
+  cmd_buffer.end().expect("recording should end");
+  cmd_buffer.submit(&[], &[], Some(&render_mgr.setup_fence)).expect("cmds should submit");
+  render_mgr.setup_fence.wait_and_reset(999999999).expect("transfers should finish");
+
+  let words = readback.read_data::<u32>(0, 4).expect("words should read back");
+  assert_eq!(words, vec![0xdeadbeef, 0xdeadbeef, 7, 9]);
+  let texels = readback.read_data::<[u8; 4]>(16, texel_count).expect("texels should read back");
+  assert!(texels.iter().all(|texel| *texel == [0, 255, 0, 255]));
+  let depths =
+    readback.read_data::<f32>(16 + texel_count * 4, texel_count).expect("depths should read back");
+  assert!(depths.iter().all(|depth| *depth == 0.25));
+}
+
+#[test]
+fn stereo_cameras_sit_an_eye_distance_apart() {
+  let camera = Camera3D::new(glam::vec4(0.0, 1.0, 5.0, 1.0), glam::Vec4::NEG_Z, CAMERA_FOV);
+  let stereo = MultiviewCameras::stereo(&camera, 0.064, CAMERA_FOV, 1.0);
+  assert_eq!(stereo.view_count(), 2);
+  assert_eq!(stereo.view_mask(), 0b11);
+  let (left, right) = (stereo.views[0], stereo.views[1]);
+  assert!((right.pos - left.pos).abs_diff_eq(glam::vec4(0.064, 0.0, 0.0, 0.0), 1e-6));
+  assert_eq!(left.look_dir, camera.look_dir);
+  assert_ne!(left.view_proj_mat, right.view_proj_mat);
+  assert_eq!(MultiviewCameras::new(&[camera; 8]).view_count(), 6);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn multiview_passes_draw_each_view_in_their_mask() {
+  let camera = Camera3D::new(glam::vec4(0.0, 1.0, 5.0, 1.0), glam::Vec4::NEG_Z, CAMERA_FOV);
+  let stereo = MultiviewCameras::stereo(&camera, 0.064, CAMERA_FOV, 1.0);
+  let (render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let ash_device = render_mgr.ash_device.clone();
+  let attachments = [vk::AttachmentDescription::default()
+    .format(vk::Format::R8G8B8A8_UNORM)
+    .samples(vk::SampleCountFlags::TYPE_1)
+    .load_op(vk::AttachmentLoadOp::CLEAR)
+    .store_op(vk::AttachmentStoreOp::STORE)
+    .initial_layout(vk::ImageLayout::UNDEFINED)
+    .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+  let color_refs = [vk::AttachmentReference::default()
+    .attachment(0)
+    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+  let subpasses = [vk::SubpassDescription::default()
+    .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+    .color_attachments(&color_refs)];
+  let multiview = |view_masks: &[u32]| {
+    AdRenderPass::new_multiview(
+      ash_device.clone(),
+      vk::RenderPassCreateFlags::default(),
+      &attachments,
+      &subpasses,
+      &[],
+      view_masks,
+      &[],
+    )
+  };
+  let plain = multiview(&[]).expect("pass without view masks should be made");
+  assert_eq!(plain.view_count(0), 1);
+  let max_views = ash_device.multiview_view_count();
+  if max_views < 2 {
+    assert!(multiview(&[0b11]).is_err());
+    return;
+  }
+  let stereo_pass = multiview(&[stereo.view_mask()]).expect("stereo pass should be made");
+  assert_eq!(stereo_pass.view_count(0), 2);
+  assert!(multiview(&[0]).is_err());
+  assert!(multiview(&[0b11, 0b11]).is_err());
+  if max_views < 32 {
+    assert!(multiview(&[1 << max_views]).is_err());
+  }
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn mesh_dset_pools_grow_past_their_first_pool() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+  // Renderers may have made meshes of their own already
+  let initial_stats = render_mgr.tri_mesh_gen.mesh_dset_pools().stats().expect("stats should lock");
+  let mut mesh_handles = HandleAllocator::new();
+  let meshes = (0..300).map(|_| mesh_handles.allocate()).collect::<Vec<MeshHandle>>();
+  for (i, mesh) in meshes.iter().enumerate() {
+    render_mgr.process_messages(cuboid_messages(&format!("cube_{i}"), *mesh));
+  }
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(render_mgr.draw_batches.len(), 300);
+
+  let mesh_stats = render_mgr.tri_mesh_gen.mesh_dset_pools().stats().expect("stats should lock");
+  assert!(mesh_stats.pool_count >= 2);
+  assert_eq!(mesh_stats.live_sets, initial_stats.live_sets + 300);
+  assert!(mesh_stats.failed_allocations > 0);
+  assert!(mesh_stats.set_capacity >= 300);
+
+  render_mgr
+    .process_messages(meshes.iter().map(|mesh| RendererMessage::DestroyTriMesh(*mesh)).collect());
+  draw_frames(&mut render_mgr, frames_in_flight + 1);
+  let mesh_stats = render_mgr.tri_mesh_gen.mesh_dset_pools().stats().expect("stats should lock");
+  assert_eq!(mesh_stats.live_sets, initial_stats.live_sets);
+  assert_eq!(mesh_stats.allocated_sets, initial_stats.allocated_sets + 300);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn frame_latency_falls_back_to_frame_fences() {
+  let config = RendererConfig { max_frame_latency: 1, ..Default::default() };
+  let (mut render_mgr, window) = headless_render_manager(config);
+  // Offscreen images can't be waited on like presents
+  assert!(!render_mgr.ash_device.present_wait());
+  let frames_in_flight = render_mgr.render_cmd_buffers.len();
+  draw_frames(&mut render_mgr, 10);
+  assert_eq!(window.presented_frames(), 10);
+  for frames_ahead in 0..=frames_in_flight {
+    render_mgr.frame_sync.wait_for_frames_ahead(frames_ahead).expect("fence wait should finish");
+  }
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn input_latency_measured_once_frame_is_done() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+  draw_frames(&mut render_mgr, 2);
+  assert_eq!(render_mgr.input_latency.stats().input_latency, None);
+
+  let input_received_at = Instant::now();
+  render_mgr.input_received_at = Some(input_received_at);
+  draw_frames(&mut render_mgr, 1);
+  assert_eq!(render_mgr.input_received_at, None);
+  // Seen done at the latest when its frame fence is waited on for reuse
+  draw_frames(&mut render_mgr, frames_in_flight);
+  let stats = render_mgr.input_latency.stats();
+  assert_eq!(stats.frames_drawn, 3 + frames_in_flight);
+  assert!(!stats.measured_at_display);
+  let input_latency = stats.input_latency.expect("frame with the input should be done");
+  assert!(input_latency <= input_received_at.elapsed());
+  assert_eq!(stats.average_input_latency, Some(input_latency));
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn shutdown_releases_device_with_frames_in_flight() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  draw_frames(&mut render_mgr, 3);
+  let cube_gpu = render_mgr.tri_meshes["cube"].clone();
+  render_mgr.process_messages(vec![RendererMessage::DestroyTriMesh(cube)]);
+  mesh_handles.free(cube).expect("cube handle should be live");
+  assert_eq!(mesh_handles.live_count(), 0);
+  // Still retired, waiting on the frames in flight
+  assert!(cube_gpu.strong_count() > 0);
+
+  let ash_device = Arc::downgrade(&render_mgr.ash_device);
+  render_mgr.shutdown().expect("render manager should shut down");
+  assert_eq!(cube_gpu.strong_count(), 0);
+  assert_eq!(ash_device.strong_count(), 0);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn same_texture_file_uploads_once_per_color_space() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let path = "renderables/src/flat_texture/albedo_default.png";
+  let mut texture_handles = HandleAllocator::new();
+  let (albedo, albedo_copy, mask) =
+    (texture_handles.allocate(), texture_handles.allocate(), texture_handles.allocate());
+  render_mgr.process_messages(vec![
+    RendererMessage::UploadFlatTex("albedo".to_string(), path.to_string(), Srgb, None, albedo),
+    RendererMessage::UploadFlatTex(
+      "albedo_copy".to_string(),
+      path.to_string(),
+      Srgb,
+      None,
+      albedo_copy,
+    ),
+    RendererMessage::UploadFlatTex("mask".to_string(), path.to_string(), Linear, None, mask),
+  ]);
+  let albedo_gpu = render_mgr.flat_tex_registry.get(albedo).expect("albedo should upload");
+  let copy_gpu = render_mgr.flat_tex_registry.get(albedo_copy).expect("copy should upload");
+  let mask_gpu = render_mgr.flat_tex_registry.get(mask).expect("mask should upload");
+  assert!(Arc::ptr_eq(albedo_gpu, copy_gpu));
+  assert!(!Arc::ptr_eq(albedo_gpu, mask_gpu));
+  let stats = render_mgr.texture_dedup_stats();
+  assert_eq!(stats.uploaded_textures, 2);
+  assert_eq!(stats.deduplicated_textures, 1);
+  assert!(stats.deduplicated_bytes > 0);
+}
+
+// A 64x64 mask with a meta file making it linear, mipped and clamped, as mount/mask.png
+fn mount_texture_meta_test(mount: &str) -> std::path::PathBuf {
+  let dir = std::env::temp_dir().join(format!("residue_{mount}_{}", std::process::id()));
+  std::fs::create_dir_all(&dir).expect("temp dir should be made");
+  let mask = image::RgbaImage::from_fn(64, 64, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+  let mut png = std::io::Cursor::new(vec![]);
+  mask.write_to(&mut png, image::ImageFormat::Png).expect("mask should encode");
+  std::fs::write(dir.join("mask.png"), png.get_ref()).expect("mask should be written");
+  std::fs::write(dir.join("mask.png.meta"), "(srgb: false, mips: true, address_mode: ClampToEdge)")
+    .expect("meta should be written");
+  vfs::global().mount_dir(mount, &dir).expect("temp dir should mount");
+  dir
+}
+
+#[test]
+fn texture_meta_file_overrides_import_settings() {
+  let dir = mount_texture_meta_test("texture_meta_test");
+
+  let settings =
+    asset_meta::load::<TextureImportSettings>(vfs::global(), "texture_meta_test/mask.png")
+      .expect("meta should parse");
+  assert_eq!(settings.color_space(Srgb), Linear);
+  assert_eq!(settings.address_mode, TextureAddressMode::ClampToEdge);
+  let no_meta =
+    asset_meta::load::<TextureImportSettings>(vfs::global(), "texture_meta_test/other.png")
+      .expect("missing meta should give defaults");
+  assert_eq!(no_meta, TextureImportSettings::default());
+  assert_eq!(no_meta.color_space(Srgb), Srgb);
+  vfs::global().unmount("texture_meta_test").expect("temp dir should unmount");
+  let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn texture_meta_settings_reach_the_uploaded_texture() {
+  let dir = mount_texture_meta_test("texture_meta_gpu_test");
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut texture_handles = HandleAllocator::new();
+  let handle = texture_handles.allocate();
+  let path = "texture_meta_gpu_test/mask.png".to_string();
+  render_mgr.process_messages(vec![RendererMessage::UploadFlatTex(
+    "mask".to_string(),
+    path,
+    Srgb,
+    None,
+    handle,
+  )]);
+  let mask_gpu = render_mgr.flat_tex_registry.get(handle).expect("mask should upload");
+  assert_eq!(mask_gpu.image_view().image().format(), vk::Format::R8G8B8A8_UNORM);
+  assert_eq!(mask_gpu.image_view().subresource_range().level_count, 7);
+  assert!(!Arc::ptr_eq(mask_gpu.sampler(), render_mgr.flat_tex_gen.sampler()));
+  vfs::global().unmount("texture_meta_gpu_test").expect("temp dir should unmount");
+  let _ = std::fs::remove_dir_all(&dir);
+}
+
+// Floor and road sampled the same way through their meta files, sign without one
+fn mount_sampler_cache_test(mount: &str) -> std::path::PathBuf {
+  let dir = std::env::temp_dir().join(format!("residue_{mount}_{}", std::process::id()));
+  std::fs::create_dir_all(&dir).expect("temp dir should be made");
+  for (file, color) in [("floor.png", 40), ("road.png", 90), ("sign.png", 140)] {
+    let texture = image::RgbaImage::from_pixel(16, 16, image::Rgba([color, color, color, 255]));
+    texture.save(dir.join(file)).expect("texture should save");
+  }
+  for file in ["floor.png.meta", "road.png.meta"] {
+    std::fs::write(dir.join(file), "(filter: Linear, anisotropy: 8)")
+      .expect("meta should be written");
+  }
+  vfs::global().mount_dir(mount, &dir).expect("temp dir should mount");
+  dir
+}
+
+#[test]
+fn texture_meta_files_pick_the_sampler() {
+  let dir = mount_sampler_cache_test("sampler_cache_test");
+  let settings =
+    asset_meta::load::<TextureImportSettings>(vfs::global(), "sampler_cache_test/floor.png")
+      .expect("meta should parse");
+  assert_eq!((settings.filter, settings.anisotropy), (TextureFilter::Linear, 8));
+  let trilinear =
+    SamplerDesc { filter: TextureFilter::Linear, anisotropy: 8, ..Default::default() };
+  assert_eq!(settings.sampler(), trilinear);
+  assert_ne!(settings.sampler(), TextureImportSettings::default().sampler());
+  vfs::global().unmount("sampler_cache_test").expect("temp dir should unmount");
+  let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn textures_with_the_same_sampling_share_a_sampler() {
+  let dir = mount_sampler_cache_test("sampler_cache_gpu_test");
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut texture_handles = HandleAllocator::new();
+  let handles = ["floor", "road", "sign"].map(|_| texture_handles.allocate());
+  render_mgr.process_messages(
+    ["floor", "road", "sign"]
+      .iter()
+      .zip(handles)
+      .map(|(name, handle)| {
+        let path = format!("sampler_cache_gpu_test/{name}.png");
+        RendererMessage::UploadFlatTex(name.to_string(), path, Srgb, None, handle)
+      })
+      .collect(),
+  );
+  let [floor, road, sign] =
+    handles.map(|handle| render_mgr.flat_tex_registry.get(handle).expect("texture should load"));
+  assert!(Arc::ptr_eq(floor.sampler(), road.sampler()));
+  assert!(Arc::ptr_eq(sign.sampler(), render_mgr.flat_tex_gen.sampler()));
+  assert!(!Arc::ptr_eq(floor.sampler(), sign.sampler()));
+  assert_eq!(render_mgr.flat_tex_gen.sampler_cache().len(), 2);
+  vfs::global().unmount("sampler_cache_gpu_test").expect("temp dir should unmount");
+  let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn sixteen_bit_grayscale_keeps_its_precision() {
+  let heights = image::ImageBuffer::<image::Luma<u16>, _>::from_fn(4, 4, |x, y| {
+    image::Luma([(x * 4 + y) as u16 * 4097])
+  });
+  let mut png = std::io::Cursor::new(vec![]);
+  heights.write_to(&mut png, image::ImageFormat::Png).expect("heights should encode");
+  let decoded =
+    DecodedFlatTexture::decode(png.get_ref(), Linear, None).expect("heights should decode");
+  assert_eq!(decoded.format, TextureFormat::R16);
+  assert_eq!(decoded.texels.len(), 4 * 4 * 2);
+  assert_eq!(u16::from_ne_bytes([decoded.texels[30], decoded.texels[31]]), 15 * 4097);
+  let as_color =
+    DecodedFlatTexture::decode(png.get_ref(), Srgb, None).expect("heights should decode");
+  assert_eq!(as_color.format, TextureFormat::Rgba8);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn sixteen_bit_grayscale_uploads_as_r16() {
+  let heights = image::ImageBuffer::<image::Luma<u16>, _>::from_fn(4, 4, |x, y| {
+    image::Luma([(x * 4 + y) as u16 * 4097])
+  });
+  let mut png = std::io::Cursor::new(vec![]);
+  heights.write_to(&mut png, image::ImageFormat::Png).expect("heights should encode");
+  let decoded =
+    DecodedFlatTexture::decode(png.get_ref(), Linear, None).expect("heights should decode");
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let heights_gpu = render_mgr
+    .flat_tex_gen
+    .upload_deduplicated("heights", &decoded, Linear, TextureImportSettings::default())
+    .expect("heights should upload");
+  assert_eq!(heights_gpu.image_view().image().format(), vk::Format::R16_UNORM);
+}
+
+#[test]
+fn colors_decode_srgb_and_keep_vec4_layout() {
+  // Decoded in const, has to match the transfer function done with powf
+  const MID_GRAY: Color = Color::from_srgb(0.5, 0.5, 0.5, 0.5);
+  assert!((MID_GRAY.r - 0.5f32.powf(2.2)).abs() < 0.01);
+  assert!((MID_GRAY.r - ((0.5f32 + 0.055) / 1.055).powf(2.4)).abs() < 1e-6);
+  assert_eq!(MID_GRAY.a, 0.5);
+  for value in [0.0, 0.02, 0.25, 0.5, 0.85, 1.0] {
+    let [r, ..] = Color::from_srgb(value, 0.0, 0.0, 1.0).to_srgb();
+    assert!((r - value).abs() < 1e-5, "{value} came back as {r}");
+  }
+  assert_eq!(Color::from_srgb8(255, 128, 0, 255).to_srgb8(), [255, 128, 0, 255]);
+
+  assert_eq!(Color::from_hsv(0.0, 1.0, 1.0), Color::RED);
+  assert_eq!(Color::from_hsv(480.0, 1.0, 1.0), Color::GREEN);
+  assert_eq!(Color::from_hsv(-120.0, 1.0, 1.0), Color::BLUE);
+  assert_eq!(Color::from_hsv(200.0, 0.0, 0.5), Color::GRAY);
+
+  // Goes into push constants and uniform structs in place of a vec4
+  assert_eq!(std::mem::size_of::<Color>(), std::mem::size_of::<glam::Vec4>());
+  assert_eq!(std::mem::align_of::<Color>(), 16);
+  assert_eq!(glam::Vec4::from(Color::YELLOW.with_alpha(0.5)), glam::vec4(1.0, 1.0, 0.0, 0.5));
+}
+
+#[test]
+fn hdr_images_decode_to_half_floats() {
+  let sky = image::Rgb32FImage::from_pixel(64, 32, image::Rgb([4.0, 2.0, 0.5]));
+  let mut hdr = std::io::Cursor::new(vec![]);
+  image::DynamicImage::from(sky).write_to(&mut hdr, image::ImageFormat::Hdr).expect("sky encodes");
+  let decoded = DecodedFlatTexture::decode(hdr.get_ref(), Linear, None).expect("sky should decode");
+  assert_eq!(decoded.format, TextureFormat::Rgba16F);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn hdr_equirect_becomes_prefiltered_cubemap() {
+  let sky = image::Rgb32FImage::from_pixel(64, 32, image::Rgb([4.0, 2.0, 0.5]));
+  let mut hdr = std::io::Cursor::new(vec![]);
+  image::DynamicImage::from(sky).write_to(&mut hdr, image::ImageFormat::Hdr).expect("sky encodes");
+  let decoded = DecodedFlatTexture::decode(hdr.get_ref(), Linear, None).expect("sky should decode");
+  let (render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let equirect = render_mgr
+    .flat_tex_gen
+    .upload_flat_texture_texels("sky", decoded.format, Linear, decoded.resolution, &decoded.texels)
+    .expect("sky should upload");
+  let environment =
+    render_mgr.environment_gen.create_environment("sky_env", &equirect).expect("cubemap builds");
+  assert_eq!(environment.face_size(), 16);
+  assert_eq!(environment.image().array_layers(), 6);
+  assert_eq!(environment.roughness_levels(), 5);
+  assert_eq!(environment.level_roughness(4), 1.0);
+  assert_eq!(environment.view().view_type(), vk::ImageViewType::CUBE);
+}
+
+const COUNT_WGSL: &str = r#"
+@group(0) @binding(0) var<storage, read_write> words: array<u32>;
+
+@compute @workgroup_size(1)
+fn count(@builtin(global_invocation_id) id: vec3<u32>) {
+  words[id.x] = id.x + 1u;
+}
+"#;
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn push_descriptors_bind_without_allocating_sets() {
+  let (render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let ash_device = render_mgr.ash_device.clone();
+  let layout_bindings = [(vk::ShaderStageFlags::COMPUTE, vk::DescriptorType::STORAGE_BUFFER)];
+  let allocated = AdDescriptorSetLayout::new(ash_device.clone(), &layout_bindings).unwrap();
+  assert!(!allocated.push_descriptor());
+  let listed = ash_device.device_info().features.contains(&"push_descriptor");
+  assert_eq!(listed, ash_device.push_descriptor());
+  if !ash_device.push_descriptor() {
+    assert!(AdDescriptorSetLayout::new_push(ash_device.clone(), &layout_bindings).is_err());
+    return;
+  }
+  let pushed = AdDescriptorSetLayout::new_push(ash_device.clone(), &layout_bindings).unwrap();
+  assert!(pushed.push_descriptor());
+
+  let compute = vk::ShaderStageFlags::COMPUTE;
+  let count_words = compile_shader(AdShaderLanguage::Wgsl, COUNT_WGSL.as_bytes(), compute).unwrap();
+  let count_spv = count_words.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>();
+  let pipeline = AdComputePipeline::new(ash_device.clone(), &count_spv, &[&pushed], 0).unwrap();
+  let words = Arc::new(
+    AdBuffer::new(
+      ash_device.clone(),
+      render_mgr.gen_allocator.clone(),
+      gpu_allocator::MemoryLocation::GpuToCpu,
+      "pushed_words",
+      vk::BufferCreateFlags::empty(),
+      16,
+      vk::BufferUsageFlags::STORAGE_BUFFER,
+    )
+    .expect("buffer should be created"),
+  );
+  let cmd_buffer = AdCommandBuffer::new(
+    render_mgr.render_cmd_buffers[0].cmd_pool().clone(),
+    vk::CommandBufferLevel::PRIMARY,
+    1,
+  )
+  .expect("cmd buffer should be allocated")
+  .remove(0);
+  cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT).expect("recording should begin");
+  cmd_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, pipeline.inner());
+  let bindings = [AdDescriptorBinding::StorageBuffer(words.clone())];
+  let push = |layout: &AdDescriptorSetLayout| {
+    layout.push(&cmd_buffer, vk::PipelineBindPoint::COMPUTE, pipeline.layout(), 0, &bindings)
+  };
+  // Sets of layouts made for allocating can't be pushed
+  assert!(push(&allocated).is_err());
+  push(&pushed).expect("bindings should push");
+  cmd_buffer.dispatch(4, 1, 1);
+  cmd_buffer.pipeline_barrier(
+    vk::PipelineStageFlags::COMPUTE_SHADER,
+    vk::PipelineStageFlags::HOST,
+    vk::DependencyFlags::empty(),
+    &[vk::MemoryBarrier::default()
+      .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+      .dst_access_mask(vk::AccessFlags::HOST_READ)],
+    &[],
+    &[],
+  );
+  cmd_buffer.end().expect("recording should end");
+  cmd_buffer.submit(&[], &[], Some(&render_mgr.setup_fence)).expect("cmds should submit");
+  render_mgr.setup_fence.wait_and_reset(999999999).expect("dispatch should finish");
+  assert_eq!(words.read_data::<u32>(0, 4).expect("words should read back"), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn static_batcher_merges_by_key_in_world_space() {
+  let cube = || TriMeshCPU::make_cuboid(glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y, 1.0);
+  let cube_vertices = cube().vertices.len();
+  let mut batcher = StaticBatcher::new(cube_vertices * 2);
+  let turned = glam::Mat4::from_rotation_translation(
+    glam::Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+    glam::vec3(10.0, 0.0, 0.0),
+  );
+  let first = batcher.add("stone", &cube(), glam::Mat4::IDENTITY);
+  let second = batcher.add("stone", &cube(), turned);
+  let other_key = batcher.add("wood", &cube(), glam::Mat4::IDENTITY);
+  // Over the vertex limit of the first stone batch
+  let third = batcher.add("stone", &cube(), glam::Mat4::from_translation(glam::Vec3::Y * 5.0));
+
+  let second_instance = batcher.instance(second).unwrap().clone();
+  assert_eq!(second_instance.batch, batcher.instance(first).unwrap().batch);
+  assert_eq!(second_instance.vertices.start as usize, cube_vertices);
+  assert_eq!(second_instance.triangles.start as usize, cube().triangles.len());
+  assert_eq!(second_instance.bounds.truncate(), glam::vec3(10.0, 0.0, 0.0));
+  assert_ne!(batcher.instance(other_key).unwrap().batch, second_instance.batch);
+  assert_ne!(batcher.instance(third).unwrap().batch, second_instance.batch);
+
+  let (batches, instances) = batcher.finish();
+  assert_eq!(batches.len(), 3);
+  assert_eq!(instances.len(), 4);
+  let stone = &batches[second_instance.batch];
+  assert_eq!(stone.key, "stone");
+  assert_eq!(stone.instances, vec![first, second]);
+  assert_eq!(stone.mesh.vertices.len(), cube_vertices * 2);
+  assert!(stone.mesh.triangles.iter().flatten().all(|idx| (*idx as usize) < cube_vertices * 2));
+  assert_eq!(stone.bounds.truncate(), glam::vec3(5.0, 0.0, 0.0));
+  // Vertices come back to world space through the batch transform, normals are turned with them
+  let source = cube();
+  for (i, vertex) in
+    stone.mesh.vertices[second_instance.vertices.start as usize..].iter().enumerate()
+  {
+    let world = stone.transform.transform_point3(vertex.pos.truncate());
+    assert!(world.abs_diff_eq(turned.transform_point3(source.vertices[i].pos.truncate()), 1e-4));
+    let normal = turned.transform_vector3(source.vertices[i].normal.truncate());
+    assert!(vertex.normal.truncate().abs_diff_eq(normal, 1e-4));
+  }
+}
+
+#[test]
+fn tube_mesh_wraps_the_line_facing_out() {
+  let points =
+    [glam::Vec3::ZERO, glam::Vec3::Y, glam::vec3(1.0, 2.0, 0.0), glam::vec3(1.0, 2.0, 3.0)];
+  let tube = TriMeshCPU::make_tube(&points, 0.25, 6);
+  assert_eq!(tube.vertices.len(), points.len() * 7);
+  assert_eq!(tube.triangles.len(), (points.len() - 1) * 6 * 2);
+  for (i, vertex) in tube.vertices.iter().enumerate() {
+    let center = points[i / 7];
+    let normal = vertex.normal.truncate();
+    assert!((vertex.pos.truncate() - center - normal * 0.25).length() < 1e-5);
+    assert!((normal.length() - 1.0).abs() < 1e-5);
+  }
+  // Front faces point the same way as the vertex normals
+  for triangle in tube.triangles.iter() {
+    let [a, b, c] = triangle.map(|idx| tube.vertices[idx as usize].pos.truncate());
+    let face_normal = (b - a).cross(c - a);
+    assert!(face_normal.dot(tube.vertices[triangle[0] as usize].normal.truncate()) > 0.0);
+  }
+  assert_eq!(tube.vertices.last().unwrap().uv.y, 1.0 + 2.0f32.sqrt() + 3.0);
+  assert!(TriMeshCPU::make_tube(&points[..1], 0.25, 6).vertices.is_empty());
+}
+
+#[test]
+fn mesh_arena_ranges_merge_and_compact() {
+  let mut ranges = RangeAllocator::new(4096);
+  let first = ranges.allocate(100, ARENA_ALIGNMENT).unwrap();
+  let second = ranges.allocate(300, ARENA_ALIGNMENT).unwrap();
+  let third = ranges.allocate(100, ARENA_ALIGNMENT).unwrap();
+  assert_eq!((first, second, third), (0, 256, 768));
+  ranges.free(second, 300);
+  // Fits in the hole the second range left
+  assert_eq!(ranges.allocate(200, ARENA_ALIGNMENT), Some(256));
+  ranges.free(256, 200);
+  ranges.free(first, 100);
+  ranges.free(third, 100);
+  assert_eq!(ranges.free_bytes(), 4096);
+  assert_eq!(ranges.largest_free(), 4096);
+
+  let live = [
+    (7, ArenaRange { block: 1, offset: 512, size: 300 }),
+    (3, ArenaRange { block: 0, offset: 2048, size: 100 }),
+    (5, ArenaRange { block: 2, offset: 0, size: 5000 }),
+  ];
+  let (blocks, placed) = plan_compaction(&live, 1024);
+  assert_eq!(blocks.iter().map(|block| block.size()).collect::<Vec<_>>(), vec![1024, 5000]);
+  assert_eq!(
+    placed,
+    vec![
+      (3, ArenaRange { block: 0, offset: 0, size: 100 }),
+      (7, ArenaRange { block: 0, offset: 256, size: 300 }),
+      (5, ArenaRange { block: 1, offset: 0, size: 5000 }),
+    ]
+  );
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn destroyed_meshes_are_compacted_out_of_the_arena() {
+  let config = RendererConfig { mesh_block_size_kb: 64, ..Default::default() };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  let mut mesh_handles = HandleAllocator::new();
+  let meshes = (0..100).map(|_| mesh_handles.allocate()).collect::<Vec<_>>();
+  for (i, mesh) in meshes.iter().enumerate() {
+    render_mgr.process_messages(cuboid_messages(&format!("cube_{i}"), *mesh));
+  }
+  draw_frames(&mut render_mgr, 2);
+  let before = render_mgr.frame_stats().mesh_arena;
+  assert!(before.block_bytes > 64 * 1024);
+
+  let destroyed = meshes
+    .iter()
+    .enumerate()
+    .filter(|(i, _)| i % 10 != 0)
+    .map(|(_, mesh)| RendererMessage::DestroyTriMesh(*mesh))
+    .collect::<Vec<_>>();
+  render_mgr.process_messages(destroyed);
+  render_mgr.process_messages(vec![RendererMessage::CompactMeshBuffers]);
+  let after = render_mgr.frame_stats().mesh_arena;
+  assert_eq!(after.compactions, 1);
+  assert_eq!(after.block_bytes, 64 * 1024);
+  assert_eq!(after.reclaimed_bytes, before.block_bytes - after.block_bytes);
+  assert_eq!(after.live_bytes * 10, before.live_bytes);
+  // The meshes left draw from their new ranges
+  draw_frames(&mut render_mgr, 3);
+
+  // Nothing left to give back
+  render_mgr.process_messages(vec![RendererMessage::CompactMeshBuffers]);
+  assert_eq!(render_mgr.frame_stats().mesh_arena.compactions, 1);
+}
+
+#[test]
+fn timestamped_transforms_blend_and_extrapolate_per_mesh() {
+  let mut mesh_handles = HandleAllocator::new();
+  let (slow, every_tick) = (mesh_handles.allocate(), mesh_handles.allocate());
+  let snapshot_at = |sim_time: u128, x: f32, updated_at: u128| {
+    let transform = glam::Mat4::from_rotation_translation(
+      glam::Quat::from_rotation_y(x),
+      glam::vec3(x, 0.0, 0.0),
+    );
+    FrameSnapshot {
+      sim_time,
+      meshes: vec![
+        MeshState {
+          mesh: slow,
+          transform: TriMeshTransform { transform },
+          morph_weights: None,
+          updated_at: Some(updated_at),
+        },
+        MeshState {
+          mesh: every_tick,
+          transform: TriMeshTransform { transform },
+          morph_weights: None,
+          updated_at: None,
+        },
+      ],
+      ..Default::default()
+    }
+  };
+  let mut history = TransformHistory::new();
+  history.record(&snapshot_at(10_000, 0.0, 0));
+  // Only one update so far, held where it is
+  assert_eq!(history.transform_at(slow, 50_000).unwrap().w_axis.x, 0.0);
+  assert!(history.transform_at(every_tick, 50_000).is_none());
+  // Repeated snapshots of the same update don't count as updates
+  history.record(&snapshot_at(20_000, 0.0, 0));
+  history.record(&snapshot_at(100_000, 0.1, 100_000));
+
+  let x_at = |time: u128| history.transform_at(slow, time).unwrap().w_axis.x;
+  assert!((x_at(50_000) - 0.05).abs() < 1e-5);
+  assert!((x_at(150_000) - 0.15).abs() < 1e-5);
+  // Carried on at most as far as the last two updates were apart
+  assert!((x_at(500_000) - 0.2).abs() < 1e-5);
+  let (_, rotation, _) =
+    history.transform_at(slow, 150_000).unwrap().to_scale_rotation_translation();
+  assert!(rotation.abs_diff_eq(glam::Quat::from_rotation_y(0.15), 1e-4));
+
+  let mut snapshot = snapshot_at(150_000, 0.1, 100_000);
+  history.apply(&mut snapshot);
+  assert!((snapshot.meshes[0].transform.transform.w_axis.x - 0.15).abs() < 1e-5);
+  assert_eq!(snapshot.meshes[1].transform.transform.w_axis.x, 0.1);
+
+  // Gone from the snapshot, like a destroyed object
+  history.record(&FrameSnapshot::default());
+  assert!(history.transform_at(slow, 150_000).is_none());
+}
+
+#[test]
+fn destroyed_handles_are_counted_by_validation() {
+  // Counts are global and other tests run alongside, so only growth is checked
+  let destroyed_handles = || validation::failure_count(ValidationCategory::DestroyedHandle);
+  let before = destroyed_handles();
+  let mut handles = HandleAllocator::<u32>::new();
+  let mut registry = HandleRegistry::new();
+  let handle = handles.allocate();
+  registry.insert(handle, Arc::new(7));
+  assert!(registry.get(handle).is_ok());
+  handles.free(handle).unwrap();
+  registry.remove(handle).unwrap();
+
+  assert!(registry.get(handle).is_err());
+  assert!(handles.free(handle).is_err());
+  assert!(destroyed_handles() >= before + 2);
+  assert!(validation::summary().contains("destroyed handle"));
+}
+
+#[test]
+fn resource_budget_keeps_peaks_and_reports_going_over_once() {
+  let over_budget = || validation::failure_count(ValidationCategory::ResourceBudget);
+  let before = over_budget();
+  let ceilings = ResourceBudgetConfig { framebuffers: 8, ..Default::default() };
+  let mut budget = ResourceBudget::new(ceilings);
+  let usage = |framebuffers, descriptor_sets| ResourceUsage {
+    descriptor_sets,
+    framebuffers,
+    ..Default::default()
+  };
+  budget.record(usage(4, 900));
+  budget.record(usage(6, 100));
+  assert_eq!(budget.usage(), usage(6, 100));
+  assert_eq!(budget.peak(), usage(6, 900));
+  assert_eq!(over_budget(), before);
+  // Framebuffers made every frame and never dropped
+  for framebuffers in 9..20 {
+    budget.record(usage(framebuffers, 100));
+  }
+  assert_eq!(over_budget(), before + 1);
+  budget.record(usage(6, 100));
+  budget.record(usage(9, 100));
+  assert_eq!(over_budget(), before + 2);
+  assert_eq!(budget.peak().framebuffers, 19);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn frame_stats_count_live_resources_within_the_budget() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  draw_frames(&mut render_mgr, 3);
+  let stats = render_mgr.frame_stats();
+  assert!(stats.resources.command_buffers >= render_mgr.render_cmd_buffers.len() as u64);
+  assert!(stats.resources.framebuffers > 0 && stats.resources.descriptor_sets > 0);
+  assert!(stats.resources.gpu_memory_bytes > 0);
+  let config = RendererConfig::default().resource_budget;
+  assert!(stats.peak_resources.framebuffers <= config.framebuffers as u64);
+  assert!(stats.peak_resources.command_buffers <= config.command_buffers as u64);
+}
+
+#[test]
+fn streamed_textures_pick_mips_by_screen_size_within_budget() {
+  let resolution = vk::Extent2D { width: 2048, height: 1024 };
+  assert_eq!(mip_count(resolution), 12);
+  assert_eq!(texture_streaming::initial_mip(resolution), 4);
+  assert_eq!(texture_streaming::mip_for_footprint(resolution, 4096.0), 0);
+  assert_eq!(texture_streaming::mip_for_footprint(resolution, 500.0), 2);
+  assert_eq!(texture_streaming::mip_for_footprint(resolution, 0.5), 11);
+  assert!(texture_streaming::projected_size(1.0, 0.5, CAMERA_FOV, HEIGHT).is_infinite());
+
+  // Odd sizes repeat the last column, so the 3x2 level averages the right edge twice
+  let small = DecodedFlatTexture {
+    content_hash: 0,
+    format: TextureFormat::R8,
+    resolution: vk::Extent2D { width: 3, height: 2 },
+    texels: vec![0, 40, 80, 0, 40, 80],
+  };
+  let mips = small.mips(0);
+  assert_eq!(mips.len(), 2);
+  assert_eq!(mips[1].0, vk::Extent2D { width: 1, height: 1 });
+  assert_eq!(mips[1].1, vec![20]);
+  assert_eq!(small.mips(1).len(), 1);
+
+  let decoded = DecodedFlatTexture {
+    content_hash: 0,
+    format: TextureFormat::Rgba8,
+    resolution,
+    texels: vec![],
+  };
+  let bytes = |first_mip| texture_streaming::mip_chain_bytes(resolution, 4, first_mip);
+  // Room for the two drawn textures at mip 1 and the undrawn one where it starts
+  let mut streamer = TextureStreamer::new(bytes(1) * 2 + bytes(4));
+  let mut textures = HandleAllocator::new();
+  let mut materials = HandleAllocator::new();
+  let load = ("tex.png".to_string(), Srgb, None, TextureImportSettings::default());
+  let [near, far, unseen] = [(); 3].map(|_| textures.allocate());
+  for texture in [near, far, unseen] {
+    assert_eq!(streamer.add(texture, "tex", load.clone(), &decoded), 4);
+  }
+  let [near_material, far_material] = [(); 2].map(|_| materials.allocate());
+  streamer.add_material(near, near_material);
+  streamer.add_material(far, far_material);
+  streamer.update_desired(&[(near_material, 4096.0), (far_material, 4096.0)].into());
+
+  // Undrawn textures give up their mips first, and the one freeing memory is planned first
+  let plan = streamer.plan();
+  assert_eq!(plan[0], (unseen, 11));
+  assert_eq!(plan[1].1, 1);
+  for (texture, first_mip) in plan {
+    streamer.set_resident(texture, first_mip);
+  }
+  let remaining = streamer.plan();
+  assert_eq!(remaining.len(), 1);
+  assert_eq!(remaining[0].1, 1);
+  streamer.set_resident(remaining[0].0, 1);
+  assert!(streamer.plan().is_empty());
+  let stats = streamer.stats();
+  assert_eq!(stats.resident_bytes, bytes(1) * 2 + bytes(11));
+  assert!(stats.resident_bytes <= stats.budget_bytes);
+  assert_eq!(stats.uploads, 3);
+
+  // Drawn a single mip smaller isn't worth a re-upload
+  streamer.update_desired(&[(near_material, 500.0), (far_material, 4096.0)].into());
+  assert!(streamer.plan().is_empty());
+  streamer.update_desired(&[(near_material, 200.0), (far_material, 4096.0)].into());
+  assert_eq!(streamer.plan(), vec![(near, 3)]);
+}
+
+// Texels of a BC1 block the way the gpu reads it
+fn decode_bc1_block(block: &[u8]) -> [[i32; 4]; 16] {
+  let expand = |c: u16| {
+    let (r, g, b) = ((c >> 11) as i32, ((c >> 5) & 63) as i32, (c & 31) as i32);
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2), 255]
+  };
+  let (c0, c1) =
+    (u16::from_le_bytes([block[0], block[1]]), u16::from_le_bytes([block[2], block[3]]));
+  assert!(c0 > c1, "blocks should use the four color mode");
+  let (e0, e1) = (expand(c0), expand(c1));
+  let palette = [
+    e0,
+    e1,
+    [0, 1, 2, 3].map(|c| (2 * e0[c] + e1[c]) / 3),
+    [0, 1, 2, 3].map(|c| (e0[c] + 2 * e1[c]) / 3),
+  ];
+  let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+  std::array::from_fn(|i| palette[(indices >> (i * 2)) as usize & 3])
+}
+
+// Texels of a BC7 mode 6 block the way the gpu reads it
+fn decode_bc7_mode6_block(block: &[u8]) -> [[i32; 4]; 16] {
+  let bits = u128::from_le_bytes(block.try_into().unwrap());
+  assert_eq!(bits & 0x7f, 0x40, "blocks should be mode 6");
+  let mut offset = 7;
+  let mut read = |count: u32| {
+    let value = (bits >> offset) as u32 & ((1 << count) - 1);
+    offset += count;
+    value as i32
+  };
+  // Channel by channel, each with the first endpoint's bits then the second's
+  let ends: [[i32; 2]; 4] = std::array::from_fn(|_| [read(7), read(7)]);
+  let (p0, p1) = (read(1), read(1));
+  let (e0, e1) = (ends.map(|x| x[0] << 1 | p0), ends.map(|x| x[1] << 1 | p1));
+  let weights = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+  std::array::from_fn(|i| {
+    let w = weights[read(if i == 0 { 3 } else { 4 }) as usize];
+    [0, 1, 2, 3].map(|c| ((64 - w) * e0[c] + w * e1[c] + 32) >> 6)
+  })
+}
+
+// Largest difference of any channel, and the root mean square one, after a round trip through
+// the blocks
+fn block_errors(format: BlockFormat, quality: BlockQuality, texels: &[u8]) -> (i32, f64) {
+  let extent = vk::Extent2D { width: 8, height: 8 };
+  let blocks = texture_compression::encode_block_rows(format, quality, extent, texels, 0..2);
+  assert_eq!(blocks.len(), format.level_bytes(extent));
+  let (mut max, mut sum) = (0, 0.0);
+  for (block_idx, block) in blocks.chunks_exact(format.block_bytes()).enumerate() {
+    let decoded = match format {
+      BlockFormat::Bc1 => decode_bc1_block(block),
+      BlockFormat::Bc7 => decode_bc7_mode6_block(block),
+    };
+    for (i, texel) in decoded.iter().enumerate() {
+      let x = (block_idx % 2) * 4 + i % 4;
+      let y = (block_idx / 2) * 4 + i / 4;
+      for channel in 0..4 {
+        let diff = (texel[channel] - texels[(y * 8 + x) * 4 + channel] as i32).abs();
+        max = max.max(diff);
+        sum += (diff * diff) as f64;
+      }
+    }
+  }
+  (max, (sum / 256.0).sqrt())
+}
+
+#[test]
+fn bc_blocks_encode_close_split_into_chunks_and_cache() {
+  // Colors along a line through each block, what a block's two endpoints can hold
+  let gradient = (0..64)
+    .flat_map(|i| {
+      let t = (i % 8 + i / 8) as u8 * 15;
+      [t, t / 2 + 40, 255 - t, 255]
+    })
+    .collect::<Vec<_>>();
+  let mut seed = 7u32;
+  let noise = (0..256)
+    .map(|_| {
+      seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+      (seed >> 24) as u8
+    })
+    .collect::<Vec<_>>();
+  assert!(texture_compression::is_opaque(&gradient) && !texture_compression::is_opaque(&noise));
+
+  // Smooth colors come back close whichever way they're compressed
+  let (bc1_max, _) = block_errors(BlockFormat::Bc1, BlockQuality::Fast, &gradient);
+  assert!(bc1_max <= 16, "bc1 gradient off by {bc1_max}");
+  let (bc7_max, _) = block_errors(BlockFormat::Bc7, BlockQuality::Fast, &gradient);
+  assert!(bc7_max <= 8, "bc7 gradient off by {bc7_max}");
+  // Refining never makes a block worse
+  let (_, fast_rms) = block_errors(BlockFormat::Bc7, BlockQuality::Fast, &noise);
+  let (_, refined_rms) = block_errors(BlockFormat::Bc7, BlockQuality::Refined, &noise);
+  assert!(refined_rms <= fast_rms, "refined {refined_rms} against fast {fast_rms}");
+  let (_, bc1_refined_rms) = block_errors(BlockFormat::Bc1, BlockQuality::Refined, &gradient);
+  assert!(bc1_refined_rms <= block_errors(BlockFormat::Bc1, BlockQuality::Fast, &gradient).1);
+
+  // Edge blocks are whole, with their missing texels repeated
+  let odd = vk::Extent2D { width: 6, height: 5 };
+  assert_eq!(BlockFormat::Bc1.level_bytes(odd), 4 * 8);
+  let odd_blocks = texture_compression::encode_block_rows(
+    BlockFormat::Bc7,
+    BlockQuality::Fast,
+    odd,
+    &noise[..120],
+    1..2,
+  );
+  assert_eq!(odd_blocks.len(), 2 * 16);
+
+  // Every block row of every level lands in exactly one chunk
+  let resolution = vk::Extent2D { width: 1024, height: 512 };
+  let mips = (0..mip_count(resolution))
+    .map(|mip| (renderables::flat_texture::mip_extent(resolution, mip), vec![]))
+    .collect::<Vec<_>>();
+  let chunks = texture_transcoding::split_chunks(&mips);
+  assert_eq!(chunks.iter().filter(|(level, _)| *level == 0).count(), 8);
+  for (level, (extent, _)) in mips.iter().enumerate() {
+    let rows =
+      chunks.iter().filter(|(x, _)| *x == level).map(|(_, rows)| rows.len()).sum::<usize>();
+    assert_eq!(rows as u32, texture_compression::block_rows(*extent));
+  }
+
+  let compressed = CompressedFlatTexture {
+    format: BlockFormat::Bc7,
+    resolution: odd,
+    mips: vec![(odd, odd_blocks.repeat(2)), (vk::Extent2D { width: 3, height: 2 }, vec![7; 16])],
+  };
+  let bytes = compressed.to_cache_bytes();
+  let cached = CompressedFlatTexture::from_cache_bytes(bytes.clone()).expect("should read back");
+  assert_eq!(
+    (cached.format, cached.resolution, cached.mips),
+    (compressed.format, odd, compressed.mips)
+  );
+  assert!(CompressedFlatTexture::from_cache_bytes(bytes[..bytes.len() - 1].to_vec()).is_none());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn textures_compress_to_bc_blocks_over_several_frames() {
+  let config =
+    RendererConfig { texture_compression: TextureCompression::Quality, ..Default::default() };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  if render_mgr.texture_compression_stats().is_none() {
+    eprintln!("gpu can't sample BC formats, skipping");
+    return;
+  }
+  let path = "renderables/src/flat_texture/albedo_default.png";
+  let mut texture_handles = HandleAllocator::new();
+  let (albedo, albedo_copy) = (texture_handles.allocate(), texture_handles.allocate());
+  render_mgr.process_messages(vec![
+    RendererMessage::UploadFlatTex("albedo".to_string(), path.to_string(), Srgb, None, albedo),
+    RendererMessage::UploadFlatTex("albedo".to_string(), path.to_string(), Srgb, None, albedo_copy),
+  ]);
+  let uncompressed =
+    render_mgr.flat_tex_registry.get(albedo).expect("albedo should upload").clone();
+  assert_eq!(uncompressed.image_view().image().format(), vk::Format::R8G8B8A8_SRGB);
+  // Drawn uncompressed until the jobs are done
+  let start = Instant::now();
+  while render_mgr.texture_compression_stats().unwrap().compressed == 0 {
+    assert!(start.elapsed() < Duration::from_secs(60), "compression should finish");
+    draw_frames(&mut render_mgr, 1);
+  }
+  let albedo_gpu = render_mgr.flat_tex_registry.get(albedo).expect("albedo should stay");
+  assert!(Arc::ptr_eq(albedo_gpu, render_mgr.flat_tex_registry.get(albedo_copy).unwrap()));
+  assert_eq!(albedo_gpu.image_view().image().format(), vk::Format::BC7_SRGB_BLOCK);
+  let stats = render_mgr.texture_compression_stats().unwrap();
+  assert_eq!(stats.pending, 0);
+  assert!(stats.compressed_bytes * 4 <= stats.uncompressed_bytes);
+}
+
+#[test]
+fn depth_samples_skip_the_far_plane_around_the_surface() {
+  let res = vk::Extent2D { width: WIDTH, height: HEIGHT };
+  assert_eq!(depth_readback::query_texel(glam::vec2(0.5, 0.5), res), glam::ivec2(160, 120));
+  assert_eq!(depth_readback::query_texel(glam::vec2(1.0, 1.2), res), glam::ivec2(319, 239));
+
+  let mut camera = Camera3D {
+    pos: glam::Vec4::ZERO,
+    look_dir: glam::Vec4::NEG_Z,
+    view_proj_mat: glam::Mat4::IDENTITY,
+  };
+  camera.refresh_vp_matrix(CAMERA_FOV, WIDTH as f32 / HEIGHT as f32);
+  let clip = camera.view_proj_mat * glam::vec4(0.0, 0.0, -10.0, 1.0);
+  let query = DepthQuery { screen_pos: glam::vec2(0.5, 0.5), radius: 1 };
+  // The far plane around the surface is skipped
+  let sample = depth_readback::depth_sample(query, &camera, &[1.0, clip.z / clip.w, 1.0], 7);
+  assert!((sample.view_depth.unwrap() - 10.0).abs() < 0.01);
+  assert!(sample.world_pos.unwrap().distance(glam::vec3(0.0, 0.0, -10.0)) < 0.01);
+  let sky = depth_readback::depth_sample(query, &camera, &[1.0; 9], 7);
+  assert_eq!((sky.depth, sky.view_depth, sky.world_pos), (1.0, None, None));
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn depth_readback_finds_the_nearest_surface_under_the_cursor() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  render_mgr.process_messages(vec![RendererMessage::QueryDepth(DepthQuery {
+    screen_pos: glam::vec2(0.5, 0.5),
+    radius: 2,
+  })]);
+  // Read back once the frame's slot comes around again
+  let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+  draw_frames(&mut render_mgr, frames_in_flight + 1);
+  let sample = render_mgr.depth_readback.latest().expect("depth query should come back");
+  // The camera looks at the cube's center from about 3.5 away
+  let view_depth = sample.view_depth.expect("cube should be under the cursor");
+  assert!(view_depth > 1.0 && view_depth < 3.47);
+  assert!(sample.world_pos.unwrap().abs().max_element() < 1.01);
+}
+
+#[test]
+fn depth_of_field_blurs_with_the_lens_and_blends_between_snapshots() {
+  // Wider apertures and closer focus blur more
+  let lens = lens_coefficient(10.0, 2.8, 50.0, HEIGHT);
+  assert!(lens > 0.0);
+  assert!(lens_coefficient(10.0, 1.4, 50.0, HEIGHT) > lens);
+  assert!(lens_coefficient(2.0, 2.8, 50.0, HEIGHT) > lens);
+  assert!(lens_coefficient(10.0, 2.8, 50.0, HEIGHT * 2) > lens);
+
+  let from = DepthOfFieldParams {
+    focus_distance: 2.0,
+    aperture: 2.0,
+    focal_length_mm: 50.0,
+    autofocus: false,
+  };
+  let to = DepthOfFieldParams {
+    focus_distance: 6.0,
+    aperture: 4.0,
+    focal_length_mm: 50.0,
+    autofocus: true,
+  };
+  let half = from.lerp(&to, 0.5);
+  assert_eq!((half.focus_distance, half.aperture, half.autofocus), (4.0, 3.0, false));
+  assert!(from.lerp(&to, 1.0).autofocus);
+  let prev = FrameSnapshot { depth_of_field: Some(from), ..Default::default() };
+  let next = FrameSnapshot { depth_of_field: Some(to), ..Default::default() };
+  let mut out = FrameSnapshot::default();
+  next.interpolate_from(&prev, 0.5, &mut out);
+  assert_eq!(out.depth_of_field, Some(half));
+
+  assert!(EngineConfig::builder().depth_of_field(5.0, 2.8, 35.0, true).build().is_ok());
+  assert!(EngineConfig::builder().depth_of_field(0.0, 2.8, 35.0, false).build().is_err());
+  assert!(EngineConfig::builder().depth_of_field(5.0, 0.1, 35.0, false).build().is_err());
+  assert!(EngineConfig::builder().depth_of_field(5.0, 2.8, 2.0, false).build().is_err());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn depth_of_field_autofocuses_on_the_center_until_overridden() {
+  for msaa_samples in [1, 4] {
+    let post_process =
+      PostProcessConfig { depth_of_field: true, dof_autofocus: true, ..Default::default() };
+    let config = RendererConfig { msaa_samples, post_process, ..Default::default() };
+    let (mut render_mgr, _window) = headless_render_manager(config);
+    let mut mesh_handles = HandleAllocator::new();
+    render_mgr.process_messages(cuboid_messages("cube", mesh_handles.allocate()));
+    let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+    draw_frames(&mut render_mgr, frames_in_flight + 2);
+    // The camera looks at the cube's center from about 3.5 away
+    let depth_of_field = render_mgr.depth_of_field.as_ref().expect("depth of field should be on");
+    let focus_distance = depth_of_field.focus_distance();
+    assert!(focus_distance > 1.0 && focus_distance < 3.47, "focused at {focus_distance}");
+
+    // The game's lens takes over from the config's
+    let manual = DepthOfFieldParams {
+      focus_distance: 20.0,
+      aperture: 8.0,
+      focal_length_mm: 85.0,
+      autofocus: false,
+    };
+    if let Some(depth_of_field) = &mut render_mgr.depth_of_field {
+      depth_of_field.set_override(Some(manual));
+    }
+    draw_frames(&mut render_mgr, 2);
+    assert_eq!(render_mgr.depth_of_field.as_ref().unwrap().focus_distance(), 20.0);
+  }
+}
+
+#[test]
+fn color_luts_load_from_cube_files_and_strips_and_blend() {
+  let cube = "# made by hand\nTITLE \"identity\"\nLUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\n\
+    DOMAIN_MAX 1 1 1\n0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+  let identity = ColorLutCPU::parse_cube(cube).expect("cube file should parse");
+  assert_eq!(identity, ColorLutCPU::identity(2));
+  assert!(ColorLutCPU::parse_cube("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+  assert!(ColorLutCPU::parse_cube("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+  assert!(ColorLutCPU::parse_cube(&cube.replace("DOMAIN_MAX 1 1 1", "DOMAIN_MAX 2 2 2")).is_err());
+
+  // Blue slices side by side, red across each and green down
+  let strip = image::RgbaImage::from_fn(8, 2, |x, y| {
+    image::Rgba([(x % 2 * 255) as u8, (y * 255) as u8, (x / 2 * 85) as u8, 255])
+  });
+  assert!(ColorLutCPU::from_strip(&strip).is_err());
+  let strip = image::RgbaImage::from_fn(4, 2, |x, y| {
+    image::Rgba([(x % 2 * 255) as u8, (y * 255) as u8, (x / 2 * 255) as u8, 255])
+  });
+  assert_eq!(ColorLutCPU::from_strip(&strip).expect("strip should load"), identity);
+  let mut png = std::io::Cursor::new(vec![]);
+  strip.write_to(&mut png, image::ImageFormat::Png).expect("strip encodes");
+  assert_eq!(ColorLutCPU::decode("grade.png", png.get_ref()).unwrap(), identity);
+  assert_eq!(ColorLutCPU::decode("grade.CUBE", cube.as_bytes()).unwrap(), identity);
+
+  let mut lut_handles = HandleAllocator::new();
+  let (day, night) = (lut_handles.allocate(), lut_handles.allocate());
+  let prev = ColorGrading { from: Some(day), to: Some(night), blend: 0.2 };
+  let next = ColorGrading { blend: 0.6, ..prev };
+  assert!((next.interpolate_from(&prev, 0.5).blend - 0.4).abs() < 1e-6);
+  // Switching tables doesn't blend the amount across them
+  let other = ColorGrading { from: Some(night), to: None, blend: 0.1 };
+  assert_eq!(other.interpolate_from(&prev, 0.5), other);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn color_luts_upload_and_grade_the_frame() {
+  let mut lut_handles = HandleAllocator::new();
+  let (day, night) = (lut_handles.allocate(), lut_handles.allocate());
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut inverted = ColorLutCPU::identity(16);
+  for texel in inverted.texels.iter_mut() {
+    *texel = glam::Vec3::ONE - *texel;
+  }
+  let day_lut = render_mgr.color_lut_gen.upload("day", &inverted).expect("lut should upload");
+  assert_eq!(day_lut.size(), 16);
+  assert_eq!(day_lut.image_view().image().resolution().depth, 16);
+  render_mgr.color_lut_registry.insert(day, Arc::new(day_lut));
+  let mut mesh_handles = HandleAllocator::new();
+  render_mgr.process_messages(cuboid_messages("cube", mesh_handles.allocate()));
+  // The night table was never loaded, it grades nothing
+  render_mgr.color_grading = Some(ColorGrading { from: Some(day), to: Some(night), blend: 0.5 });
+  draw_frames(&mut render_mgr, 3);
+  render_mgr.process_messages(vec![RendererMessage::DestroyColorLut(day)]);
+  draw_frames(&mut render_mgr, 3);
+}
+
+#[test]
+fn film_effects_follow_the_config_and_are_validated() {
+  assert!(config_film_effects(&PostProcessConfig::default()).is_off());
+  let post_process = PostProcessConfig {
+    vignette: true,
+    chromatic_aberration: true,
+    chromatic_aberration_strength: 4.0,
+    ..Default::default()
+  };
+  let effects = config_film_effects(&post_process);
+  assert!(!effects.is_off());
+  assert_eq!(effects.chromatic_aberration, Some(4.0));
+  assert_eq!(effects.film_grain, None);
+  // Strengths of 0 turn an effect off as well
+  let faded = FilmEffectsParams { film_grain: Some(0.0), ..Default::default() };
+  assert!(faded.is_off());
+  assert_eq!(aberration_pixels(3.0, 1080), 3.0);
+  assert_eq!(aberration_pixels(3.0, 2160), 6.0);
+
+  assert!(EngineConfig::builder().vignette(0.5, 0.5, 0.4).film_grain(0.1).build().is_ok());
+  assert!(EngineConfig::builder().vignette(1.5, 0.5, 0.4).build().is_err());
+  assert!(EngineConfig::builder().vignette(0.5, 0.5, 0.0).build().is_err());
+  assert!(EngineConfig::builder().chromatic_aberration(64.0).build().is_err());
+  assert!(EngineConfig::builder().film_grain(-0.1).build().is_err());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn film_effects_toggle_from_config_and_messages() {
+  let post_process = PostProcessConfig {
+    vignette: true,
+    chromatic_aberration: true,
+    chromatic_aberration_strength: 4.0,
+    ..Default::default()
+  };
+  let effects = config_film_effects(&post_process);
+  let config = RendererConfig { post_process, ..Default::default() };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  let mut mesh_handles = HandleAllocator::new();
+  render_mgr.process_messages(cuboid_messages("cube", mesh_handles.allocate()));
+  draw_frames(&mut render_mgr, 3);
+  let dark_and_grainy = FilmEffectsParams {
+    vignette: Some(Vignette { intensity: 1.0, radius: 0.2, softness: 0.3 }),
+    chromatic_aberration: None,
+    film_grain: Some(0.2),
+  };
+  render_mgr.process_messages(vec![RendererMessage::SetFilmEffects(Some(dark_and_grainy))]);
+  assert_eq!(render_mgr.film_effects.params(), dark_and_grainy);
+  draw_frames(&mut render_mgr, 2);
+  render_mgr.process_messages(vec![RendererMessage::SetFilmEffects(Some(Default::default()))]);
+  draw_frames(&mut render_mgr, 2);
+  render_mgr.process_messages(vec![RendererMessage::SetFilmEffects(None)]);
+  assert_eq!(render_mgr.film_effects.params(), effects);
+  draw_frames(&mut render_mgr, 2);
+}
+
+#[test]
+fn driver_versions_unpack_per_vendor() {
+  // NVIDIA packs 10.8.8.6 bits, most others use vulkan's packing
+  assert_eq!(driver_version_string(0x10de, (550 << 22) | (54 << 14) | (14 << 6)), "550.54.14.0");
+  assert_eq!(driver_version_string(0x1002, vk::make_api_version(0, 2, 0, 302)), "2.0.302");
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn engine_info_reports_versions_and_the_device() {
+  let (render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let info = render_mgr.engine_info.clone().with_crate("game", "1.2.3");
+  assert_eq!(info.crates[0].name, "game");
+  assert_eq!(info.crates[1].name, "render-manager");
+  assert!(!info.gpu.name.is_empty());
+  assert!(info.gpu.api_version >= (1, 2, 0));
+  assert!(info.gpu.extensions.iter().any(|name| name == "VK_KHR_swapchain"));
+  assert!(info
+    .gpu
+    .queue_families
+    .iter()
+    .any(|family| family.flags.contains(vk::QueueFlags::GRAPHICS)));
+  assert!(info.gpu.device_local_bytes() > 0);
+  let report = info.report_lines();
+  assert!(report[0].starts_with("game 1.2.3, render-manager "));
+  assert_eq!(
+    report.iter().filter(|line| line.starts_with("memory heap")).count(),
+    info.gpu.memory_heaps.len()
+  );
+}
+
+#[test]
+fn frame_budgets_leave_out_waits_and_name_the_slowest_scopes() {
+  let scope = |name, parent: Option<usize>, start_us, end_us| profiler::ScopeRecord {
+    name,
+    depth: if parent.is_some() { 1 } else { 0 },
+    parent,
+    start_us,
+    end_us,
+  };
+  let frame = profiler::FrameRecord {
+    thread_name: "render".to_string(),
+    thread_id: 1,
+    frame_index: 0,
+    start_us: 0,
+    end_us: 12_000,
+    scopes: vec![
+      scope("draw", None, 0, 12_000),
+      scope("wait_for_frame", Some(0), 0, 5_000),
+      scope("shadows", Some(0), 5_000, 6_000),
+      scope("opaque", Some(0), 6_000, 9_000),
+      scope("shadows", Some(0), 9_000, 10_500),
+    ],
+  };
+  assert_eq!(frame.budget_time("draw"), Some(Duration::from_millis(7)));
+  assert_eq!(frame.budget_time("physics"), None);
+  assert_eq!(
+    frame.slowest_children("draw", 3),
+    vec![("opaque", Duration::from_millis(3)), ("shadows", Duration::from_micros(2_500))]
+  );
+
+  let config = EngineConfig::builder()
+    .frame_budgets(4.0, 8.0, 0.0)
+    .scope_budget("opaque", 2.0)
+    .build()
+    .unwrap();
+  assert_eq!(
+    config.budgets.scope_budgets(),
+    vec![("physics".to_string(), 4.0), ("draw".to_string(), 8.0), ("opaque".to_string(), 2.0)]
+  );
+  assert!(EngineConfig::builder().frame_budgets(-1.0, 8.0, 10.0).build().is_err());
+  assert!(EngineConfig::builder().scope_budget("", 2.0).build().is_err());
+}
+
+const BLUR_WGSL: &str = r#"
+struct Params { radius: f32, strength: f32 }
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var source: texture_2d<f32>;
+@group(0) @binding(2) var source_sampler: sampler;
+@group(1) @binding(0) var output: texture_storage_2d<rgba16float, write>;
+var<push_constant> offset: vec2<f32>;
+
+@compute @workgroup_size(8, 8)
+fn blur(@builtin(global_invocation_id) id: vec3<u32>) {
+  let size = vec2<f32>(textureDimensions(source));
+  let uv = (vec2<f32>(id.xy) + offset) / size;
+  let color = textureSampleLevel(source, source_sampler, uv, params.radius);
+  textureStore(output, id.xy, color * params.strength);
+}
+"#;
+
+const FULLSCREEN_GLSL: &str = r#"#version 450
+layout(set = 0, binding = 0) uniform Params { float radius; float strength; } params;
+layout(location = 0) out vec2 outUV;
+
+void main() {
+  outUV = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+  gl_Position = vec4(outUV * 2.0 - 1.0, params.radius, 1.0);
+}
+"#;
+
+#[test]
+fn shaders_compile_from_wgsl_and_glsl_and_reflect_their_layouts() {
+  let language = |file_name| AdShaderLanguage::from_path(Path::new(file_name));
+  assert_eq!(language("blur.comp.wgsl"), Some(AdShaderLanguage::Wgsl));
+  assert_eq!(language("water.frag"), Some(AdShaderLanguage::Glsl));
+  assert_eq!(language("water.frag.spv"), Some(AdShaderLanguage::Spirv));
+  assert_eq!(language("notes.txt"), None);
+
+  let compute = vk::ShaderStageFlags::COMPUTE;
+  let reflection =
+    AdShaderReflection::from_source(AdShaderLanguage::Wgsl, BLUR_WGSL.as_bytes(), compute).unwrap();
+  let binding = |set, binding, stages, descriptor_type| AdShaderBinding {
+    set,
+    binding,
+    stages,
+    descriptor_type,
+  };
+  let blur_bindings = vec![
+    binding(0, 0, compute, vk::DescriptorType::UNIFORM_BUFFER),
+    binding(0, 1, compute, vk::DescriptorType::SAMPLED_IMAGE),
+    binding(0, 2, compute, vk::DescriptorType::SAMPLER),
+    binding(1, 0, compute, vk::DescriptorType::STORAGE_IMAGE),
+  ];
+  assert_eq!(reflection.bindings, blur_bindings);
+  assert_eq!(reflection.push_constant_size, 8);
+
+  // The compiled SPIR-V reads back the same, with the entry point renamed to main
+  let blur_words = compile_shader(AdShaderLanguage::Wgsl, BLUR_WGSL.as_bytes(), compute).unwrap();
+  let blur_spv = blur_words.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>();
+  let spv_reflection = AdPipeline::reflect_shaders(&HashMap::from([(compute, &blur_spv[..])]));
+  assert_eq!(spv_reflection.unwrap().bindings, blur_bindings);
+
+  // Stages of a pipeline merge into one layout
+  let vertex = vk::ShaderStageFlags::VERTEX;
+  let mut fullscreen =
+    AdShaderReflection::from_source(AdShaderLanguage::Glsl, FULLSCREEN_GLSL.as_bytes(), vertex)
+      .unwrap();
+  assert!(compile_shader(AdShaderLanguage::Glsl, FULLSCREEN_GLSL.as_bytes(), vertex).is_ok());
+  fullscreen.merge(&reflection).unwrap();
+  assert_eq!(fullscreen.bindings[0].stages, vertex | compute);
+  assert_eq!(fullscreen.bindings.len(), 4);
+  let mut clashing = reflection.clone();
+  clashing.bindings[0].descriptor_type = vk::DescriptorType::STORAGE_BUFFER;
+  assert!(clashing.merge(&reflection).is_err());
+
+  assert!(compile_shader(AdShaderLanguage::Wgsl, BLUR_WGSL.as_bytes(), vertex).is_err());
+  assert!(compile_shader(AdShaderLanguage::Wgsl, b"fn broken( {", compute).is_err());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn reflected_compute_pipelines_make_their_set_layouts() {
+  let compute = vk::ShaderStageFlags::COMPUTE;
+  let blur_words = compile_shader(AdShaderLanguage::Wgsl, BLUR_WGSL.as_bytes(), compute).unwrap();
+  let blur_spv = blur_words.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>();
+  let (render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let (_pipeline, set_layouts) =
+    AdComputePipeline::new_reflected(render_mgr.ash_device.clone(), &blur_spv).unwrap();
+  assert_eq!(set_layouts.len(), 2);
+}
+
+const MAGENTA_FRAG_GLSL: &str = r#"#version 450
+layout(location = 0) out vec4 outFragColor;
+
+void main() {
+  outFragColor = vec4(1.0, 0.0, 1.0, 1.0);
+}
+"#;
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn material_shader_reloads_keep_the_last_shader_that_built() {
+  let fragment = vk::ShaderStageFlags::FRAGMENT;
+  let magenta_words =
+    compile_shader(AdShaderLanguage::Glsl, MAGENTA_FRAG_GLSL.as_bytes(), fragment).unwrap();
+  let magenta_spv = magenta_words.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>();
+
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  render_mgr.process_messages(cuboid_messages("cube", mesh_handles.allocate()));
+  draw_frames(&mut render_mgr, 1);
+  render_mgr.frame_sync.wait_all().expect("frames should finish");
+
+  // Not even a shader module, the pipelines built before are kept
+  let broken = Some(vec![0u8; 6]);
+  let renderer = &mut render_mgr.tri_mesh_renderer;
+  assert!(renderer.reload_frag_shader(ShaderVariant::Unlit, false, broken.clone()).is_err());
+  let rebuilt = renderer.reload_frag_shader(ShaderVariant::Unlit, false, Some(magenta_spv));
+  assert!(rebuilt.expect("magenta should build") > 0);
+  // Nothing is built for lit materials yet, the broken shader only fails when one is
+  assert_eq!(renderer.reload_frag_shader(ShaderVariant::Lit, false, broken), Ok(0));
+  assert!(renderer.take_shader_errors().is_empty());
+  draw_frames(&mut render_mgr, 1);
+
+  // Compile errors from files are logged, drawing goes on
+  let dir = std::env::temp_dir().join(format!("residue_shader_reload_{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let broken_path = dir.join("broken.frag");
+  std::fs::write(&broken_path, "#version 450\nvoid main() { oops }\n").unwrap();
+  render_mgr.process_messages(vec![RendererMessage::ReloadMaterialShader(
+    ShaderVariant::Unlit,
+    false,
+    Some(broken_path),
+  )]);
+  draw_frames(&mut render_mgr, 1);
+  render_mgr.frame_sync.wait_all().expect("frames should finish");
+  let rebuilt = render_mgr.tri_mesh_renderer.reload_frag_shader(ShaderVariant::Unlit, false, None);
+  assert!(rebuilt.expect("built in shader should build again") > 0);
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn snapshot_camera_fov_blends_between_ticks() {
+  assert_eq!(FrameSnapshot::default().camera_fov, CAMERA_FOV);
+  let wide = FrameSnapshot { camera_fov: 1.6, ..Default::default() };
+  let narrow = FrameSnapshot { camera_fov: 0.8, ..Default::default() };
+  let mut out = FrameSnapshot::default();
+  narrow.interpolate_from(&wide, 0.25, &mut out);
+  assert!((out.camera_fov - 1.4).abs() < 1e-5);
+  narrow.interpolate_from(&wide, 2.0, &mut out);
+  assert_eq!(out.camera_fov, narrow.camera_fov);
+}
+
+#[test]
+fn vertex_streams_merge_and_pick_the_material_shader() {
+  let cube = || TriMeshCPU::make_cuboid(glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y, 1.0);
+  let cube_vertices = cube().vertices.len();
+  assert!(cube().with_colors(vec![Color::BLACK; cube_vertices - 1]).is_err());
+  let colored = || cube().with_colors(vec![Color::BLACK; cube_vertices]).unwrap();
+  // The plain cube's half of the stream is filled in white, uv2 stays left out
+  let merged = cube().merge(colored());
+  let colors = merged.streams.colors.as_ref().unwrap();
+  assert_eq!(colors.len(), cube_vertices * 2);
+  assert_eq!((colors[0], colors[cube_vertices]), (Color::WHITE, Color::BLACK));
+  assert!(merged.streams.uv2.is_none());
+  let mut batcher = StaticBatcher::new(cube_vertices * 2);
+  batcher.add("stone", &colored(), glam::Mat4::IDENTITY);
+  batcher.add("stone", &cube(), glam::Mat4::IDENTITY);
+  let (batches, _) = batcher.finish();
+  assert_eq!(batches[0].mesh.streams.colors.as_ref().unwrap()[cube_vertices], Color::WHITE);
+
+  let tinted = MaterialCPU {
+    vertex_streams: VertexStreamUse { colors: true, albedo_uv2: false },
+    ..Default::default()
+  };
+  assert!(tinted.pipeline_key().vertex_streams);
+  assert!(tinted.pipeline_key().shader_defines().contains_key("VERTEX_STREAMS"));
+  assert!(!MaterialCPU::default().pipeline_key().shader_defines().contains_key("VERTEX_STREAMS"));
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn vertex_streams_bind_for_materials_reading_them() {
+  let cube = || TriMeshCPU::make_cuboid(glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y, 1.0);
+  let cube_vertices = cube().vertices.len();
+  let colored = || cube().with_colors(vec![Color::BLACK; cube_vertices]).unwrap();
+  let tinted = MaterialCPU {
+    vertex_streams: VertexStreamUse { colors: true, albedo_uv2: false },
+    ..Default::default()
+  };
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  let mut material_handles = HandleAllocator::new();
+  let colored_mesh = mesh_handles.allocate();
+  let plain_mesh = mesh_handles.allocate();
+  let material = material_handles.allocate();
+  render_mgr.process_messages(vec![
+    RendererMessage::UploadTriMesh("colored".to_string(), colored(), colored_mesh),
+    RendererMessage::UploadTriMesh("plain".to_string(), cube(), plain_mesh),
+    RendererMessage::CreateMaterial("tinted".to_string(), tinted, None, material),
+    RendererMessage::AddRenderable(colored_mesh, Some(material)),
+    // Drawn with the empty streams
+    RendererMessage::AddRenderable(plain_mesh, Some(material)),
+  ]);
+  draw_frames(&mut render_mgr, 3);
+  assert_eq!(render_mgr.draw_batches.len(), 2);
+  assert!(render_mgr
+    .draw_batches
+    .iter()
+    .all(|(_, material)| material.pipeline_key().vertex_streams));
+}
+
+#[test]
+fn lightmaps_unwrap_charts_and_bake_shadowed_sun_and_sky() {
+  let floor =
+    TriMeshCPU::make_rect(glam::Vec3::ZERO, glam::Vec3::X * 10.0, glam::Vec3::NEG_Z * 10.0);
+  let cube =
+    TriMeshCPU::make_cuboid(glam::vec3(0.0, 1.5, 0.0), glam::Vec3::X * 2.0, glam::Vec3::Y, 2.0);
+  let settings = LightmapSettings { resolution: 64, texels_per_unit: 4.0, ..Default::default() };
+  let mut meshes = vec![floor, cube];
+  unwrap_lightmap_uvs(&mut meshes, &settings).unwrap();
+  for mesh in meshes.iter() {
+    let uv2 = mesh.streams.uv2.as_ref().unwrap();
+    assert_eq!(uv2.len(), mesh.vertices.len());
+    assert!(uv2
+      .iter()
+      .all(|uv| uv.cmpge(glam::Vec2::ZERO).all() && uv.cmple(glam::Vec2::ONE).all()));
+  }
+  // Every face of the cube is its own chart, none of their vertices were shared to begin with
+  assert_eq!(meshes[1].vertices.len(), 24);
+  let too_small = LightmapSettings { resolution: 4, ..settings };
+  let mut plain =
+    vec![TriMeshCPU::make_cuboid(glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y, 1.0)];
+  assert!(unwrap_lightmap_uvs(&mut plain, &too_small).is_err());
+
+  let floor_uv2 = meshes[0].streams.uv2.clone().unwrap();
+  let center = floor_uv2.iter().sum::<glam::Vec2>() / 4.0;
+  let texel_at = |lightmap: &Lightmap, uv: glam::Vec2| {
+    let texel = uv * settings.resolution as f32;
+    lightmap.texel(texel.x as u32, texel.y as u32)
+  };
+  let instances = meshes
+    .iter()
+    .map(|mesh| LightmapInstance { mesh, transform: glam::Mat4::IDENTITY, albedo: Color::WHITE })
+    .collect::<Vec<_>>();
+  let overhead_sun = SunLight { direction: glam::Vec3::Y, ..Default::default() };
+  let lights = LightmapLights { sun: Some(overhead_sun), local: vec![], sky: Color::BLACK };
+  let direct_only = LightmapSettings { bounces: 0, ..settings };
+  let lightmap = bake_lightmap(&instances, &lights, &direct_only).unwrap();
+  let open_floor = texel_at(&lightmap, floor_uv2[0].lerp(center, 0.1));
+  assert!(open_floor.abs_diff_eq(glam::Vec3::ONE, 1e-3));
+  assert_eq!(texel_at(&lightmap, center), glam::Vec3::ZERO);
+
+  // The cube hides some of the sky from the floor under it
+  let sky_only = LightmapLights { sun: None, local: vec![], sky: Color::WHITE };
+  let bounced = LightmapSettings { samples: 32, ..settings };
+  let lightmap = bake_lightmap(&instances, &sky_only, &bounced).unwrap();
+  let open_floor = texel_at(&lightmap, floor_uv2[0].lerp(center, 0.1));
+  assert!(open_floor.x > 0.9 && open_floor.x <= 1.0);
+  assert!(texel_at(&lightmap, center).x < open_floor.x * 0.9);
+
+  let lightmapped = MaterialCPU { variant: ShaderVariant::Lightmapped, ..Default::default() };
+  assert!(lightmapped.pipeline_key().vertex_streams);
+  assert!(lightmapped.pipeline_key().shader_defines().contains_key("LIGHTMAPPED"));
+  let dir = std::env::temp_dir().join(format!("residue_lightmap_{}", std::process::id()));
+  std::fs::create_dir_all(&dir).expect("temp dir should be made");
+  lightmap.save(&dir.join("lightmap.png")).expect("lightmap should save");
+  vfs::global().mount_dir("lightmap_test", &dir).expect("temp dir should mount");
+  let settings =
+    asset_meta::load::<TextureImportSettings>(vfs::global(), "lightmap_test/lightmap.png")
+      .expect("meta should parse");
+  assert_eq!(
+    (settings.address_mode, settings.mips),
+    (TextureAddressMode::ClampToEdge, Some(false))
+  );
+  vfs::global().unmount("lightmap_test").expect("temp dir should unmount");
+  let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn lightmapped_materials_draw_with_their_lightmap() {
+  let mut floor =
+    vec![TriMeshCPU::make_rect(glam::Vec3::ZERO, glam::Vec3::X * 10.0, glam::Vec3::NEG_Z * 10.0)];
+  let settings = LightmapSettings { resolution: 64, texels_per_unit: 4.0, ..Default::default() };
+  unwrap_lightmap_uvs(&mut floor, &settings).unwrap();
+  let floor = floor.remove(0);
+  let lightmapped = MaterialCPU { variant: ShaderVariant::Lightmapped, ..Default::default() };
+  let dir = std::env::temp_dir().join(format!("residue_lightmap_gpu_{}", std::process::id()));
+  std::fs::create_dir_all(&dir).expect("temp dir should be made");
+  image::RgbaImage::from_pixel(64, 64, image::Rgba([200, 200, 200, 255]))
+    .save(dir.join("lightmap.png"))
+    .expect("lightmap should save");
+  vfs::global().mount_dir("lightmap_gpu_test", &dir).expect("temp dir should mount");
+
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  let mut texture_handles = HandleAllocator::new();
+  let mut material_handles = HandleAllocator::new();
+  let (floor_mesh, texture, material) =
+    (mesh_handles.allocate(), texture_handles.allocate(), material_handles.allocate());
+  render_mgr.process_messages(vec![
+    RendererMessage::UploadTriMesh("floor".to_string(), floor, floor_mesh),
+    RendererMessage::UploadFlatTex(
+      "lightmap".to_string(),
+      "lightmap_gpu_test/lightmap.png".to_string(),
+      Srgb,
+      None,
+      texture,
+    ),
+    RendererMessage::CreateLightmappedMaterial(
+      "lightmapped".to_string(),
+      lightmapped,
+      None,
+      texture,
+      material,
+    ),
+    RendererMessage::AddRenderable(floor_mesh, Some(material)),
+  ]);
+  draw_frames(&mut render_mgr, 3);
+  assert_eq!(render_mgr.draw_batches.len(), 1);
+  assert_eq!(render_mgr.draw_batches[0].1.pipeline_key(), lightmapped.pipeline_key());
+  vfs::global().unmount("lightmap_gpu_test").expect("temp dir should unmount");
+  let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn reflection_probes_blend_box_projected_and_face_their_cameras() {
+  let room = ReflectionProbe {
+    position: glam::vec3(0.0, 1.0, 0.0),
+    box_min: glam::vec3(-5.0, 0.0, -5.0),
+    box_max: glam::vec3(5.0, 3.0, 5.0),
+    blend_distance: 1.0,
+  };
+  let alcove = ReflectionProbe {
+    position: glam::vec3(4.0, 1.0, 0.0),
+    box_min: glam::vec3(3.0, 0.0, -1.0),
+    box_max: glam::vec3(5.0, 3.0, 1.0),
+    blend_distance: 0.5,
+  };
+  assert_eq!(room.weight(glam::vec3(0.0, 1.5, 0.0)), 1.0);
+  assert_eq!(room.weight(glam::vec3(4.5, 1.5, 0.0)), 0.5);
+  assert_eq!(room.weight(glam::vec3(6.0, 1.5, 0.0)), 0.0);
+  // The alcove takes what it covers, the room fills in the rest near the alcove's edge
+  assert_eq!(blend_weights(&[alcove, room], glam::vec3(4.5, 1.5, 0.0)), vec![1.0, 0.0]);
+  assert_eq!(blend_weights(&[alcove, room], glam::vec3(4.25, 1.5, 0.75)), vec![0.5, 0.375]);
+
+  // Off center, the reflection is looked up towards where the ray hits the wall
+  let point = glam::vec3(2.0, 1.0, 0.0);
+  let dir = glam::vec3(1.0, 0.0, 1.0).normalize();
+  let projected = room.box_projected_dir(point, dir);
+  assert!(projected.abs_diff_eq(glam::vec3(5.0, 0.0, 3.0), 1e-4));
+
+  // Each camera looks down its face, drawing it mirrored: +s of the face on the left, -t on top
+  let faces = [
+    (glam::Vec3::X, glam::Vec3::NEG_Z, glam::Vec3::Y),
+    (glam::Vec3::NEG_X, glam::Vec3::Z, glam::Vec3::Y),
+    (glam::Vec3::Y, glam::Vec3::X, glam::Vec3::NEG_Z),
+    (glam::Vec3::NEG_Y, glam::Vec3::X, glam::Vec3::Z),
+    (glam::Vec3::Z, glam::Vec3::X, glam::Vec3::Y),
+    (glam::Vec3::NEG_Z, glam::Vec3::NEG_X, glam::Vec3::Y),
+  ];
+  for (camera, (dir, right, top)) in room.face_cameras(0.1, 100.0).into_iter().zip(faces) {
+    let ndc = |offset: glam::Vec3| camera.view_proj_mat.project_point3(room.position + offset);
+    assert!(ndc(dir).truncate().abs_diff_eq(glam::Vec2::ZERO, 1e-5));
+    let corner = ndc(dir + (right + top) * 0.5);
+    assert!(corner.x < 0.0 && corner.y > 0.0);
+  }
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn reflection_probes_capture_when_set() {
+  let room = ReflectionProbe {
+    position: glam::vec3(0.0, 1.0, 0.0),
+    box_min: glam::vec3(-5.0, 0.0, -5.0),
+    box_max: glam::vec3(5.0, 3.0, 5.0),
+    blend_distance: 1.0,
+  };
+  let alcove = ReflectionProbe {
+    position: glam::vec3(4.0, 1.0, 0.0),
+    box_min: glam::vec3(3.0, 0.0, -1.0),
+    box_max: glam::vec3(5.0, 3.0, 1.0),
+    blend_distance: 0.5,
+  };
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  let mut material_handles = HandleAllocator::new();
+  let (mesh, material) = (mesh_handles.allocate(), material_handles.allocate());
+  let lit = MaterialCPU { variant: ShaderVariant::Lit, ..Default::default() };
+  render_mgr.process_messages(vec![
+    RendererMessage::UploadTriMesh(
+      "cube".to_string(),
+      TriMeshCPU::make_cuboid(glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y, 1.0),
+      mesh,
+    ),
+    RendererMessage::CreateMaterial("lit".to_string(), lit, None, material),
+    RendererMessage::AddRenderable(mesh, Some(material)),
+    RendererMessage::SetReflectionProbes(vec![room, alcove]),
+  ]);
+  assert_eq!(render_mgr.reflection_probes(), &[alcove, room]);
+  assert!(render_mgr.reflection_probes.capture_pending());
+  draw_frames(&mut render_mgr, 2);
+  assert!(!render_mgr.reflection_probes.capture_pending());
+  // Too many are turned down whole
+  render_mgr.process_messages(vec![RendererMessage::SetReflectionProbes(vec![
+    room;
+    MAX_REFLECTION_PROBES
+      + 1
+  ])]);
+  assert_eq!(render_mgr.reflection_probes().len(), 2);
+  draw_frames(&mut render_mgr, 2);
+}
+
+#[test]
+fn frame_export_copies_without_external_memory() {
+  use FrameExportMode::{CpuCopy, Shared};
+  assert_eq!(frame_export::export_mode(Shared, true), Shared);
+  assert_eq!(frame_export::export_mode(Shared, false), CpuCopy);
+  assert_eq!(frame_export::export_mode(CpuCopy, true), CpuCopy);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn frames_export_shared_or_copied_once_their_slot_comes_around() {
+  use FrameExportMode::{CpuCopy, Shared};
+  let (mut render_mgr, window) = headless_render_manager(RendererConfig::default());
+  let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+  draw_frames(&mut render_mgr, 2);
+  assert!(render_mgr.frame_export.latest().is_none());
+
+  render_mgr.process_messages(vec![RendererMessage::SetFrameExport(Some(CpuCopy))]);
+  draw_frames(&mut render_mgr, frames_in_flight + 1);
+  let Some(ExportedFrame::Pixels { resolution, data, frame_number }) =
+    render_mgr.frame_export.latest()
+  else {
+    panic!("copied frame should come back");
+  };
+  assert_eq!((resolution.width, resolution.height), (WIDTH, HEIGHT));
+  assert_eq!(data.len(), (WIDTH * HEIGHT * 4) as usize);
+  assert!(frame_number >= 2 && frame_number < render_mgr.frame_number);
+
+  render_mgr.process_messages(vec![RendererMessage::SetFrameExport(Some(Shared))]);
+  assert!(render_mgr.frame_export.latest().is_none());
+  draw_frames(&mut render_mgr, frames_in_flight + 1);
+  match render_mgr.frame_export.latest() {
+    Some(ExportedFrame::Shared { images, latest, .. }) => {
+      assert!(render_mgr.ash_device.external_memory());
+      assert_eq!(images.len() as u64, frames_in_flight);
+      assert!(latest < images.len());
+      assert!(images.iter().all(|image| image.size >= (WIDTH * HEIGHT * 4) as u64));
+    }
+    Some(ExportedFrame::Pixels { .. }) => assert!(!render_mgr.ash_device.external_memory()),
+    None => panic!("shared frame should come back"),
+  }
+
+  // New targets at the new size, nothing exported from before
+  window.resize(WIDTH * 2, HEIGHT);
+  assert!(render_mgr.draw().expect("resize should refresh the swapchain"));
+  draw_frames(&mut render_mgr, frames_in_flight + 1);
+  let resolution = match render_mgr.frame_export.latest() {
+    Some(ExportedFrame::Shared { images, latest, .. }) => images[latest].resolution,
+    Some(ExportedFrame::Pixels { resolution, .. }) => resolution,
+    None => panic!("frame should be exported after resizing"),
+  };
+  assert_eq!((resolution.width, resolution.height), (WIDTH * 2, HEIGHT));
+
+  render_mgr.process_messages(vec![RendererMessage::SetFrameExport(None)]);
+  draw_frames(&mut render_mgr, frames_in_flight + 1);
+  assert!(render_mgr.frame_export.latest().is_none());
+}
+
+#[test]
+fn recorder_keeps_its_cadence_and_writes_png_sequences() {
+  // The first frame right away, then one per 1 / fps, repeated when frames come in slower
+  assert_eq!(recorder::frames_due(Duration::ZERO, 30, 0), 1);
+  assert_eq!(recorder::frames_due(Duration::from_millis(20), 30, 1), 0);
+  assert_eq!(recorder::frames_due(Duration::from_millis(40), 30, 1), 1);
+  assert_eq!(recorder::frames_due(Duration::from_millis(110), 30, 2), 2);
+  let args = ["-s".to_string(), "{width}x{height}".to_string(), "-r".to_string(), "{fps}".into()];
+  assert_eq!(
+    recorder::pipe_args(&args, vk::Extent2D { width: 320, height: 240 }, 60),
+    vec!["-s", "320x240", "-r", "60"]
+  );
+
+  let dir = std::env::temp_dir().join(format!("residue_recording_{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  let settings = RecordingSettings { output: RecordingOutput::PngSequence(dir.clone()), fps: 1 };
+  let mut recorder = Recorder::new(settings.clone());
+  let resolution = vk::Extent2D { width: 4, height: 2 };
+  let texels = Arc::new((0..32).map(|i| i as u8).collect::<Vec<_>>());
+  for frame_number in [3, 3, 4] {
+    let frame = ExportedFrame::Pixels { resolution, data: texels.clone(), frame_number };
+    recorder.push(Some(frame)).expect("frame should be taken");
+  }
+  // Frame 3 starts the cadence, 4 comes in before the next second
+  assert_eq!(recorder.finish(), Ok(1));
+  let written =
+    image::open(dir.join("frame_000000.png")).expect("frame should be saved").to_rgba8();
+  assert_eq!((written.width(), written.height()), (4, 2));
+  assert_eq!(written.get_pixel(1, 0).0, [4, 5, 6, 255]);
+  assert!(!dir.join("frame_000001.png").exists());
+  std::fs::remove_dir_all(&dir).expect("recording dir should be removed");
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn recorder_writes_frames_exported_while_recording() {
+  let dir = std::env::temp_dir().join(format!("residue_gpu_recording_{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  let settings = RecordingSettings { output: RecordingOutput::PngSequence(dir.clone()), fps: 1000 };
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let frames_in_flight = render_mgr.render_cmd_buffers.len() as u64;
+  render_mgr.process_messages(vec![
+    RendererMessage::SetFrameExport(Some(FrameExportMode::Shared)),
+    RendererMessage::StartRecording(RecordingSettings { fps: 1000, ..settings }),
+  ]);
+  draw_frames(&mut render_mgr, frames_in_flight + 3);
+  render_mgr.process_messages(vec![RendererMessage::StopRecording]);
+  assert!(render_mgr.recorder.is_none());
+  let frame = image::open(dir.join("frame_000000.png")).expect("frame should be recorded");
+  assert_eq!((frame.width(), frame.height()), (WIDTH, HEIGHT));
+  // Back to sharing once the recording is done
+  draw_frames(&mut render_mgr, frames_in_flight + 1);
+  assert!(!matches!(
+    (render_mgr.frame_export.latest(), render_mgr.ash_device.external_memory()),
+    (Some(ExportedFrame::Pixels { .. }), true)
+  ));
+  std::fs::remove_dir_all(&dir).expect("recording dir should be removed");
+}
+
+#[test]
+fn visual_regression_weighs_brightness_and_counts_changed_pixels() {
+  let gray = |value: u8| image::Rgba([value, value, value, 255]);
+  assert_eq!(visual_regression::perceptual_delta([90, 40, 200, 255], [90, 40, 200, 0]), 0.0);
+  assert!(visual_regression::perceptual_delta(gray(0).0, gray(255).0) > 0.9);
+  // Brightness changes show more than hue changes of the same size
+  let base = [120, 120, 120, 255];
+  assert!(
+    visual_regression::perceptual_delta(base, [140, 140, 140, 255])
+      > visual_regression::perceptual_delta(base, [140, 120, 100, 255])
+  );
+  let golden = image::RgbaImage::from_pixel(100, 10, gray(128));
+  let mut actual = golden.clone();
+  actual.put_pixel(3, 3, gray(130));
+  let tolerance = Tolerance::default();
+  let rounding = visual_regression::compare(&actual, &golden, tolerance).unwrap();
+  assert_eq!(rounding.changed, 0);
+  assert!(rounding.within(tolerance));
+  actual.put_pixel(4, 4, gray(255));
+  actual.put_pixel(5, 5, gray(0));
+  let broken = visual_regression::compare(&actual, &golden, tolerance).unwrap();
+  assert_eq!(broken.changed, 2);
+  assert!(!broken.within(tolerance));
+  assert_eq!(broken.diff_image.get_pixel(4, 4).0, [255, 0, 0, 255]);
+  assert!(visual_regression::compare(&image::RgbaImage::new(10, 100), &golden, tolerance).is_err());
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn canonical_scenes_match_their_golden_images() {
+  let tolerance = Tolerance::default();
+  let mut mesh_handles = HandleAllocator::new();
+  let mut material_handles = HandleAllocator::new();
+  let lit = MaterialCPU { variant: ShaderVariant::Lit, ..Default::default() };
+  // Both ways of getting to sRGB have to draw the same frame
+  let scenes = [
+    ("unlit_cube", ColorOutput::SrgbTarget, None),
+    ("unlit_cube", ColorOutput::ShaderEncode, None),
+    ("lit_cube", ColorOutput::SrgbTarget, Some(lit)),
+  ];
+  for (name, color_output, material) in scenes {
+    let config = RendererConfig { color_output, ..Default::default() };
+    let (mut render_mgr, _window) = headless_render_manager(config);
+    let cube = mesh_handles.allocate();
+    let messages = match material {
+      None => cuboid_messages(name, cube),
+      Some(material) => {
+        let handle = material_handles.allocate();
+        let cuboid = TriMeshCPU::make_cuboid(glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y, 1.0);
+        vec![
+          RendererMessage::UploadTriMesh(name.to_string(), cuboid, cube),
+          RendererMessage::CreateMaterial(name.to_string(), material, None, handle),
+          RendererMessage::AddRenderable(cube, Some(handle)),
+        ]
+      }
+    };
+    render_mgr.process_messages(messages);
+    let frame = visual_regression::render_frame(&mut render_mgr).expect("frame should come back");
+    assert_eq!(frame.dimensions(), (WIDTH, HEIGHT));
+    if let Err(e) = visual_regression::check_golden(name, &frame, tolerance) {
+      panic!("{e}");
+    }
+  }
+}
+
+#[test]
+fn benchmark_summarizes_frame_times_and_draw_calls() {
+  let frame_times = (1..=200).map(Duration::from_millis).collect::<Vec<_>>();
+  let summary = summarize_frame_times(&frame_times).unwrap();
+  assert_eq!(summary.average, Duration::from_micros(100_500));
+  assert_eq!(summary.p99, Duration::from_millis(198));
+  assert_eq!(summary.max, Duration::from_millis(200));
+  // Too few frames for 1 in 100 to stand apart, the worst is the p99
+  let few = summarize_frame_times(&[Duration::from_millis(30), Duration::from_millis(10)]).unwrap();
+  assert_eq!(few.p99, Duration::from_millis(30));
+  assert!(summarize_frame_times(&[]).is_none());
+
+  let mut capture = BenchmarkCapture::new();
+  let usage = |gpu_memory_bytes| ResourceUsage { gpu_memory_bytes, ..Default::default() };
+  capture.frame_presented(10, usage(300));
+  capture.frame_presented(30, usage(100));
+  capture.frame_timed(Duration::from_millis(4));
+  let report = capture.finish();
+  assert_eq!(report.frames, 2);
+  assert_eq!(report.average_draw_calls, 20.0);
+  assert_eq!(report.max_draw_calls, 30);
+  assert_eq!(report.peak_resources.gpu_memory_bytes, 300);
+  assert_eq!(report.gpu_frame_time.unwrap().max, Duration::from_millis(4));
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn benchmark_reports_the_frames_between_start_and_stop() {
+  let (mut render_mgr, window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  render_mgr.process_messages(cuboid_messages("cube", mesh_handles.allocate()));
+  draw_frames(&mut render_mgr, 2);
+  render_mgr.process_messages(vec![RendererMessage::StartBenchmark]);
+  draw_frames(&mut render_mgr, 20);
+  render_mgr.process_messages(vec![RendererMessage::StopBenchmark]);
+  let report = render_mgr.benchmark_report.take().expect("stopping should report");
+  assert_eq!(report.frames, 20);
+  assert_eq!(window.presented_frames(), 22);
+  assert!(report.max_draw_calls > 0);
+  assert!(render_mgr.frame_stats().draw_calls > 0);
+  assert!(report.frame_time.unwrap().p99 >= report.frame_time.unwrap().average);
+  assert!(report.peak_resources.gpu_memory_bytes > 0);
+}
+
+#[test]
+fn perf_hud_colors_frames_by_budget_and_fills_meters() {
+  let frame_times = [8, 25, 70].map(Duration::from_millis).into_iter().collect::<VecDeque<_>>();
+  let meters = [(0.5, Color::BLUE), (1.0, Color::MAGENTA)];
+  let rects = PerfHud::layout(&frame_times, &meters);
+  // Background, a bar per frame, the two budget lines, then a back and a fill per meter
+  assert_eq!(rects.len(), 1 + 3 + 2 + 2 * 2);
+  let background = rects[0].rect;
+  for rect in rects.iter().map(|x| x.rect) {
+    assert!(rect.x >= background.x && rect.x + rect.z <= background.x + background.z + 1e-5);
+    assert!(rect.y >= background.y && rect.y + rect.w <= background.y + background.w + 1e-5);
+  }
+  assert!(background.y + background.w <= 1.0);
+  let bars = &rects[1..4];
+  assert_eq!(
+    bars.iter().map(|x| x.color).collect::<Vec<_>>(),
+    [Color::GREEN, Color::YELLOW, Color::RED]
+  );
+  // Slower frames are taller, the newest is on the right
+  assert!(bars[0].rect.w < bars[1].rect.w && bars[1].rect.w < bars[2].rect.w);
+  assert!(bars[2].rect.x > bars[1].rect.x);
+  let [half_back, half_fill] = [&rects[6], &rects[7]];
+  assert!((half_fill.rect.z - half_back.rect.z * 0.5).abs() < 1e-5);
+  assert_eq!(half_fill.color, Color::BLUE);
+  assert_eq!(rects[9].rect.z, rects[8].rect.z);
+}
+
+#[test]
+fn frame_scratch_stops_growing_once_warmed_up() {
+  let mut scratch = FrameScratch::new();
+  let draw_frame = |scratch: &mut FrameScratch| {
+    scratch.reset();
+    let mut draws = ScratchVec::new_in(scratch.arena());
+    draws.extend(0..4096u32);
+    let barriers = ScratchVec::from_iter_in(draws.iter().map(|draw| draw * 2), scratch.arena());
+    assert_eq!(barriers.len(), 4096);
+    scratch.arena().allocated_bytes()
+  };
+  for _ in 0..4 {
+    draw_frame(&mut scratch);
+  }
+  let warmed_up = draw_frame(&mut scratch);
+  for _ in 0..16 {
+    assert_eq!(draw_frame(&mut scratch), warmed_up);
+  }
+  // Only with the counting allocator
+  assert_eq!(profiler::thread_allocations(), None);
+}
+
+#[test]
+fn frames_in_flight_fit_the_surface() {
+  let caps = |min_image_count, max_image_count| vk::SurfaceCapabilitiesKHR {
+    min_image_count,
+    max_image_count,
+    ..Default::default()
+  };
+  assert_eq!(select_frame_counts(&caps(2, 8), 3), (3, 3));
+  assert_eq!(select_frame_counts(&caps(2, 8), 2), (3, 2));
+  assert_eq!(select_frame_counts(&caps(2, 0), 4), (4, 4));
+  assert_eq!(select_frame_counts(&caps(3, 8), 2), (4, 2));
+  // Capped surfaces keep to their max, frames in flight with them
+  assert_eq!(select_frame_counts(&caps(2, 3), 4), (3, 3));
+}
+
+#[test]
+fn ui_scale_follows_the_window_unless_set() {
+  assert_eq!(resolve_ui_scale(0.0, 1.0), 1.0);
+  assert_eq!(resolve_ui_scale(0.0, 2.0), 2.0);
+  assert_eq!(resolve_ui_scale(1.5, 2.0), 1.5);
+  // Odd scale factors from the window stay readable
+  assert_eq!(resolve_ui_scale(0.0, 8.0), 4.0);
+  assert_eq!(resolve_ui_scale(0.0, 0.1), 0.5);
+  assert_eq!(resolve_ui_scale(0.0, f64::NAN), 1.0);
+}
+
+#[test]
+fn view_policy_boxes_the_scene_and_keeps_the_fov_axis() {
+  let config = ViewConfig { min_aspect: 4.0 / 3.0, max_aspect: 21.0 / 9.0, ..Default::default() };
+  let extent = |width, height| vk::Extent2D { width, height };
+  // Within the limits the scene takes the whole window
+  let full = scene_viewport(&config, extent(1920, 1080));
+  assert_eq!((full.offset, full.extent), (vk::Offset2D { x: 0, y: 0 }, extent(1920, 1080)));
+  // Super ultra-wide gets pillarboxed, a portrait window letterboxed
+  let pillarboxed = scene_viewport(&config, extent(5120, 1440));
+  assert_eq!((pillarboxed.offset.x, pillarboxed.extent), (880, extent(3360, 1440)));
+  let letterboxed = scene_viewport(&config, extent(900, 1600));
+  assert_eq!((letterboxed.offset.y, letterboxed.extent), (462, extent(900, 675)));
+
+  let safe = ViewConfig { safe_area_x: 0.05, safe_area_y: 0.1, ..config.clone() };
+  let safe_area = hud_safe_area(&safe, pillarboxed);
+  assert_eq!(safe_area.offset, vk::Offset2D { x: 880 + 168, y: 144 });
+  assert_eq!(safe_area.extent, extent(3360 - 2 * 168, 1440 - 2 * 144));
+
+  // Vertical keeps the fov as is, horizontal keeps the reference aspect's width
+  let fov = 1.0;
+  assert_eq!(projection_fov(&config, fov, 2.0), fov);
+  let horizontal = ViewConfig { fov_axis: FovAxis::Horizontal, ..config.clone() };
+  assert!((projection_fov(&horizontal, fov, horizontal.reference_aspect) - fov).abs() < 1e-5);
+  let half_width = |vertical_fov: f32, aspect: f32| (vertical_fov * 0.5).tan() * aspect;
+  let narrow = projection_fov(&horizontal, fov, 4.0 / 3.0);
+  assert!(narrow > fov);
+  assert!((half_width(narrow, 4.0 / 3.0) - half_width(fov, 16.0 / 9.0)).abs() < 1e-5);
+
+  // Cursors on the bars miss the scene, the ones on it land where the scene is
+  let camera = Camera3D::new(glam::Vec4::ZERO, glam::Vec4::NEG_Z, CAMERA_FOV);
+  let (_, on_bar) = view_camera(&config, camera, fov, (5120, 1440), Some((0.05, 0.5)));
+  assert_eq!(on_bar, None);
+  let (view_camera, center) = view_camera(&config, camera, fov, (5120, 1440), Some((0.5, 0.5)));
+  assert!(center.unwrap().abs_diff_eq(glam::vec2(0.5, 0.5), 1e-5));
+  let (_, ray_dir) = view_camera.picking_ray(center.unwrap());
+  assert!(ray_dir.abs_diff_eq(glam::Vec3::NEG_Z, 1e-4));
+}
+
+#[test]
+fn quality_presets_parse_and_custom_keeps_the_config() {
+  assert_eq!(QualityPreset::parse("medium"), Some(QualityPreset::Medium));
+  assert_eq!(QualityPreset::Custom.settings(), None);
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn quality_presets_switch_between_frames() {
+  let config = RendererConfig { quality: QualityPreset::Low, ..Default::default() };
+  let (mut render_mgr, _window) = headless_render_manager(config);
+  let mut mesh_handles = HandleAllocator::new();
+  let cube = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("cube", cube));
+  draw_frames(&mut render_mgr, 2);
+  let resolution = render_mgr.triangle_frame_buffers[0].resolution();
+  assert_eq!((resolution.width, resolution.height), (WIDTH * 3 / 4, HEIGHT * 3 / 4));
+  assert!(render_mgr.post_process.is_none());
+
+  render_mgr.process_messages(vec![RendererMessage::SetQuality(QualityPreset::High)]);
+  draw_frames(&mut render_mgr, 2);
+  let resolution = render_mgr.triangle_frame_buffers[0].resolution();
+  assert_eq!((resolution.width, resolution.height), (WIDTH, HEIGHT));
+  assert!(render_mgr.post_process.as_ref().is_some_and(|x| x.has_motion_blur()));
+  assert!(render_mgr.depth_of_field.is_some());
+  assert!(render_mgr.film_effects.params().vignette.is_some());
+
+  // Back to what the config had written
+  render_mgr.process_messages(vec![RendererMessage::SetQuality(QualityPreset::Custom)]);
+  draw_frames(&mut render_mgr, 2);
+  assert_eq!(render_mgr.config.quality_settings(), RendererConfig::default().quality_settings());
+  assert!(render_mgr.post_process.is_none() && render_mgr.depth_of_field.is_none());
+}
+
+#[test]
+fn resource_snapshot_leaks_name_what_grew() {
+  let baseline = ResourceSnapshot {
+    allocations: vec![("font_atlas".to_string(), 4096)],
+    registries: vec![("mesh", 2)],
+    ..Default::default()
+  };
+  let mut after = baseline.clone();
+  after.allocations.push(("streamed_mip_3".to_string(), 1024));
+  after.allocations[0].1 = 2048;
+  after.usage.descriptor_sets = 5;
+  assert_eq!(
+    after.leaks_since(&baseline),
+    vec![
+      ResourceLeak { resource: LeakedResource::Usage("descriptor sets"), baseline: 0, live: 5 },
+      ResourceLeak {
+        resource: LeakedResource::Allocation("streamed_mip_3".to_string()),
+        baseline: 0,
+        live: 1024
+      },
+    ]
+  );
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn resource_snapshots_name_what_outlived_the_level() {
+  let (mut render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let mut mesh_handles = HandleAllocator::new();
+  // Loaded and unloaded once first, so the baseline has what stays around after the first level
+  let level_cycle = |render_mgr: &mut RenderManager, mesh_handles: &mut HandleAllocator<_>| {
+    let cube = mesh_handles.allocate();
+    render_mgr.process_messages(cuboid_messages("cube", cube));
+    draw_frames(render_mgr, 2);
+    render_mgr.process_messages(vec![RendererMessage::DestroyTriMesh(cube)]);
+    draw_frames(render_mgr, 2);
+  };
+  level_cycle(&mut render_mgr, &mut mesh_handles);
+  let baseline = render_mgr.snapshot_resources().expect("snapshot should be taken");
+  level_cycle(&mut render_mgr, &mut mesh_handles);
+  let after = render_mgr.snapshot_resources().expect("snapshot should be taken");
+  assert_eq!(after.leaks_since(&baseline), vec![]);
+
+  let kept = mesh_handles.allocate();
+  render_mgr.process_messages(cuboid_messages("kept", kept));
+  draw_frames(&mut render_mgr, 2);
+  let after = render_mgr.snapshot_resources().expect("snapshot should be taken");
+  let leaks = after.leaks_since(&baseline);
+  assert!(leaks.contains(&ResourceLeak {
+    resource: LeakedResource::Registry("mesh"),
+    baseline: 0,
+    live: 1
+  }));
+}
+
+#[test]
+fn distance_fields_cross_the_middle_at_the_edge() {
+  // 6x6 square in the middle of a 10x10 bitmap, its left column half covered
+  let mut coverage = vec![0.0; 100];
+  for y in 2..8 {
+    for x in 2..8 {
+      coverage[y * 10 + x] = if x == 2 { 0.5 } else { 1.0 };
+    }
+  }
+  let spread = 2;
+  let field = signed_distance_field(&coverage, 10, 10, spread);
+  let width = 10 + 2 * spread as usize;
+  assert_eq!(field.len(), width * width);
+  let at = |x: usize, y: usize| field[(y + spread as usize) * width + x + spread as usize];
+  // Edge pixels sit on the middle, the inside goes up and the outside down
+  assert_eq!(at(2, 4), 128);
+  assert!(at(5, 4) > at(3, 4) && at(3, 4) > 128);
+  assert!(at(1, 4) < 128 && at(0, 4) < at(1, 4));
+  assert_eq!(at(4, 4), at(5, 5));
+  // Past the spread it's clamped, the padding included
+  assert_eq!(field[0], 0);
+}
+
+fn test_font() -> SdfFontCPU {
+  let glyph = |u: f32, advance: f32| SdfGlyph {
+    uv: glam::vec4(u, 0.0, u + 0.25, 1.0),
+    offset: glam::vec2(-2.0, -20.0),
+    size: glam::vec2(16.0, 24.0),
+    advance,
+  };
+  let space = SdfGlyph { size: glam::Vec2::ZERO, ..glyph(0.0, 8.0) };
+  SdfFontCPU {
+    width: 64,
+    height: 32,
+    texels: vec![0; 64 * 32],
+    glyphs: HashMap::from([('a', glyph(0.0, 12.0)), ('?', glyph(0.5, 10.0)), (' ', space)]),
+    ascent: 24.0,
+    line_height: 30.0,
+    kerning: HashMap::from([(('a', 'a'), -2.0)]),
+  }
+}
+
+#[test]
+fn text_is_laid_out_from_glyph_metrics_at_any_size() {
+  let font = test_font();
+  let placed = font.layout("aa b\na", SDF_GLYPH_SIZE);
+  // The space takes room but draws nothing, missing glyphs fall back to ?
+  assert_eq!(placed.len(), 4);
+  assert_eq!(placed[0].rect, glam::vec4(-2.0, 4.0, 16.0, 24.0));
+  // Kerned closer by the pair's kerning
+  assert_eq!(placed[1].rect.x, -2.0 + 12.0 - 2.0);
+  assert_eq!(placed[2].rect.x, -2.0 + 12.0 - 2.0 + 12.0 + 8.0);
+  assert_eq!(placed[2].uv.x, 0.5);
+  assert_eq!(placed[3].rect.y, 4.0 + 30.0);
+
+  let half = font.layout("aa b\na", SDF_GLYPH_SIZE * 0.5);
+  for (half, full) in half.iter().zip(placed.iter()) {
+    assert_eq!(half.rect, full.rect * 0.5);
+    assert_eq!(half.uv, full.uv);
+  }
+  assert_eq!(
+    font.measure("aa b\na", SDF_GLYPH_SIZE),
+    glam::vec2(12.0 - 2.0 + 12.0 + 8.0 + 10.0, 60.0)
+  );
+  assert_eq!(font.distance_range(SDF_GLYPH_SIZE * 2.0), 2.0 * font.distance_range(SDF_GLYPH_SIZE));
+  assert!(SdfFontCPU::empty().layout("aa", 16.0).is_empty());
+}
+
+#[test]
+fn ui_shapes_become_padded_sdf_quads() {
+  let font = test_font();
+  let shapes = [
+    UiShape::RoundedRect {
+      rect: glam::vec4(10.0, 20.0, 100.0, 50.0),
+      radius: 8.0,
+      outline: 0.0,
+      color: Color::WHITE,
+    },
+    UiShape::Circle {
+      center: glam::vec2(50.0, 50.0),
+      radius: 10.0,
+      outline: 2.0,
+      color: Color::RED,
+    },
+    UiShape::Line {
+      start: glam::vec2(30.0, 10.0),
+      end: glam::vec2(10.0, 40.0),
+      thickness: 4.0,
+      color: Color::GREEN,
+    },
+    UiShape::Text {
+      position: glam::vec2(100.0, 200.0),
+      text: "a a".to_string(),
+      size: SDF_GLYPH_SIZE,
+      color: Color::BLUE,
+    },
+  ];
+  let sdf_shapes = sdf_shapes(&shapes, &font);
+  assert_eq!(sdf_shapes.len(), 3 + 2);
+  // Grown by a pixel on every side for the edge to fade out in
+  assert_eq!(sdf_shapes[0].rect, glam::vec4(9.0, 19.0, 102.0, 52.0));
+  assert_eq!(sdf_shapes[0].params.x, 8.0);
+  assert_eq!(sdf_shapes[0].thickness, 0.0);
+  // Circles are rounded squares with a radius of half their side
+  assert_eq!(
+    sdf_shapes[1],
+    SdfShape::rounded_rect(glam::vec4(40.0, 40.0, 20.0, 20.0), 10.0, 2.0, Color::RED)
+  );
+  assert_eq!(sdf_shapes[2].kind, SdfShapeKind::Line);
+  assert_eq!(sdf_shapes[2].rect, glam::vec4(7.0, 7.0, 26.0, 36.0));
+  assert_eq!(sdf_shapes[2].params, glam::vec4(30.0, 10.0, 10.0, 40.0));
+  for glyph in &sdf_shapes[3..] {
+    assert_eq!(glyph.kind, SdfShapeKind::Glyph);
+    assert_eq!(glyph.distance_range, font.distance_range(SDF_GLYPH_SIZE));
+    assert_eq!(glyph.color, Color::BLUE);
+  }
+  assert_eq!(sdf_shapes[3].rect.x, 98.0);
+  assert_eq!(sdf_shapes[4].rect.x, 98.0 + 12.0 + 8.0);
+  // Matches the shader's layout of four vec4s
+  assert_eq!(std::mem::size_of::<SdfShape>(), 64);
+}
+
+#[test]
+fn perf_hud_labels_sit_on_the_panel() {
+  let mut perf_hud = PerfHud::new();
+  perf_hud.update(&Default::default(), 0);
+  let resolution = glam::vec2(1920.0, 1080.0);
+  let labels = perf_hud.labels(resolution);
+  // Frame times over the plot, then one per meter
+  assert_eq!(labels.len(), 4);
+  let frame_times = [Duration::from_millis(16)].into_iter().collect::<VecDeque<_>>();
+  let background = PerfHud::layout(&frame_times, &[(0.0, Color::WHITE); 3])[0].rect;
+  let background = (background.truncate().truncate() + 1.0) * 0.5 * resolution;
+  let mut last_top = 0.0;
+  for label in labels {
+    let UiShape::Text { position, size, .. } = label else { panic!("perf hud label isn't text") };
+    assert!(position.x > background.x && position.y > background.y);
+    assert!(position.y > last_top && size > 0.0);
+    last_top = position.y;
+  }
+}
+
+#[test]
+fn world_labels_scale_with_distance_and_skip_what_isnt_on_screen() {
+  let camera = Camera3D::new(glam::Vec4::ZERO, glam::Vec4::NEG_Z, CAMERA_FOV);
+  let label = WorldLabel::new(LabelAnchor::Position(glam::Vec3::ZERO), "crate");
+  let projected = project_label(&camera, glam::vec3(0.0, 0.0, -10.0), &label).unwrap();
+  assert!(projected.screen_pos.abs_diff_eq(glam::vec2(0.5, 0.5), 1e-5));
+  assert!((projected.view_depth - 10.0).abs() < 1e-4);
+  assert!((projected.size - label.size).abs() < 1e-4);
+  // Above the camera is up the screen
+  let above = project_label(&camera, glam::vec3(0.0, 1.0, -10.0), &label).unwrap();
+  assert!(above.screen_pos.y < 0.5);
+  // Closer gets bigger and further smaller, up to the limits
+  let near = project_label(&camera, glam::vec3(0.0, 0.0, -8.0), &label).unwrap();
+  assert!(near.size > projected.size && near.size < label.max_size);
+  let close = project_label(&camera, glam::vec3(0.0, 0.0, -2.0), &label).unwrap();
+  assert_eq!(close.size, label.max_size);
+  let far = project_label(&camera, glam::vec3(0.0, 0.0, -200.0), &label).unwrap();
+  assert_eq!(far.size, label.min_size);
+  assert!(project_label(&camera, glam::vec3(0.0, 0.0, 10.0), &label).is_none());
+  assert!(project_label(&camera, glam::vec3(100.0, 0.0, -1.0), &label).is_none());
+}
+
+#[test]
+fn occluded_world_labels_hide_or_draw_through_walls() {
+  let camera = Camera3D::new(glam::Vec4::ZERO, glam::Vec4::NEG_Z, CAMERA_FOV);
+  let mut label = WorldLabel::new(LabelAnchor::Position(glam::Vec3::ZERO), "a");
+  label.icon = Some(LabelIcon::Dot);
+  let projected = project_label(&camera, glam::vec3(0.0, 0.0, -10.0), &label).unwrap();
+  let depth_at =
+    |distance: f32| camera.view_proj_mat.project_point3(glam::vec3(0.0, 0.0, -distance)).z;
+  assert!(is_label_occluded(&camera, &projected, depth_at(5.0)));
+  // The surface the label sits on and anything behind it don't hide it, nor does the sky
+  assert!(!is_label_occluded(&camera, &projected, depth_at(9.9)));
+  assert!(!is_label_occluded(&camera, &projected, depth_at(20.0)));
+  assert!(!is_label_occluded(&camera, &projected, 1.0));
+
+  let font = test_font();
+  let resolution = glam::vec2(200.0, 100.0);
+  let shapes = label_shapes(&label, &projected, false, resolution, &font);
+  assert_eq!(shapes.len(), 2);
+  let UiShape::Circle { center, color, .. } = shapes[0] else { panic!("icon isn't a circle") };
+  assert_eq!(center, glam::vec2(100.0, 50.0));
+  assert_eq!(color, label.color);
+  let UiShape::Text { position, size, .. } = shapes[1] else { panic!("label text isn't text") };
+  let extent = font.measure("a", size);
+  assert!((position.x + extent.x / 2.0 - center.x).abs() < 1e-4);
+  assert!(position.y + extent.y < center.y);
+  assert!(label_shapes(&label, &projected, true, resolution, &font).is_empty());
+
+  label.through_walls = Some(Color::RED);
+  let shapes = label_shapes(&label, &projected, true, resolution, &font);
+  assert!(shapes.iter().all(|shape| matches!(
+    shape,
+    UiShape::Circle { color: Color::RED, .. } | UiShape::Text { color: Color::RED, .. }
+  )));
+}
+
+#[test]
+fn frame_diff_heatmap_colors_changes_by_how_big_they_are() {
+  assert_eq!(heat_color(0.0), [26, 51, 255, 255]);
+  assert_eq!(heat_color(1.0), [255, 26, 26, 255]);
+  assert_eq!(heat_color(2.0), heat_color(1.0));
+
+  let before = image::RgbaImage::from_pixel(4, 2, image::Rgba([100, 100, 100, 255]));
+  let mut after = before.clone();
+  // Rounding, a small change and a big one
+  after.put_pixel(0, 0, image::Rgba([101, 100, 100, 255]));
+  after.put_pixel(1, 0, image::Rgba([110, 110, 110, 255]));
+  after.put_pixel(2, 0, image::Rgba([250, 250, 250, 255]));
+  let diff = diff_heatmap(&before, &after).unwrap();
+  assert_eq!(diff.changed, 2);
+  assert!(diff.max_delta > DIFF_NOISE_FLOOR);
+  // Unchanged pixels are darkened gray, the biggest change is the hot end
+  let unchanged = diff.heatmap.get_pixel(3, 1).0;
+  assert!(unchanged[0] == unchanged[1] && unchanged[1] == unchanged[2] && unchanged[0] < 100);
+  let rounded = diff.heatmap.get_pixel(0, 0).0;
+  assert!(rounded[0] == rounded[1] && rounded[0].abs_diff(unchanged[0]) <= 1);
+  assert_eq!(diff.heatmap.get_pixel(2, 0).0, heat_color(1.0));
+  let small = diff.heatmap.get_pixel(1, 0).0;
+  assert!(small[2] > small[0]);
+
+  assert!(diff_heatmap(&before, &image::RgbaImage::new(2, 4)).is_err());
+}
+
+#[test]
+fn frame_diff_heatmaps_upload_through_the_graphics_backend() {
+  let mut heatmap = image::RgbaImage::new(3, 2);
+  heatmap.put_pixel(2, 1, image::Rgba([255, 20, 10, 255]));
+  let device = NullDevice::new();
+  let uploaded = upload_heatmap(&device, &heatmap).unwrap();
+  assert_eq!(uploaded.format(), Format::Rgba8Srgb);
+  assert_eq!(uploaded.extent(), Extent2D { width: 3, height: 2 });
+  assert_eq!(uploaded.pixels().unwrap(), heatmap.as_raw().clone());
+  assert_eq!(
+    device.submitted_commands().unwrap(),
+    vec![NullCommand::CopyBufferToImage {
+      src: "frame_diff_heatmap_upload".to_string(),
+      dst: "frame_diff_heatmap".to_string(),
+    }]
+  );
+}
+
+const SCALE_WGSL: &str = r#"
+@group(0) @binding(0) var<storage, read> numbers: array<u32>;
+@group(0) @binding(1) var<storage, read_write> scaled: array<u32>;
+var<push_constant> scale: u32;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+  if id.x < arrayLength(&numbers) {
+    scaled[id.x] = numbers[id.x] * scale;
+  }
+}
+"#;
+
+// Written once against the traits, runs on any backend
+fn scale_on_gpu<D: GraphicsDevice>(
+  device: &D,
+  numbers: &[u32],
+  scale: u32,
+) -> Result<Vec<u32>, String> {
+  let size = (numbers.len() * 4) as u64;
+  let buffer = |name: &str, storage, location| {
+    let usage = BufferUsage { storage, ..Default::default() };
+    device.create_buffer(&BufferDesc { name: name.to_string(), size, usage, location })
+  };
+  let upload = buffer("upload", false, MemoryLocation::CpuToGpu)?;
+  let input = buffer("numbers", true, MemoryLocation::GpuOnly)?;
+  let output = buffer("scaled", true, MemoryLocation::GpuOnly)?;
+  let readback = buffer("readback", false, MemoryLocation::GpuToCpu)?;
+  upload.write(0, &numbers.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>())?;
+  let spirv =
+    compile_shader(AdShaderLanguage::Wgsl, SCALE_WGSL.as_bytes(), vk::ShaderStageFlags::COMPUTE)?;
+  let pipeline = device.create_compute_pipeline("scale", &spirv)?;
+
+  let mut commands = device.begin_commands()?;
+  commands.copy_buffer(&upload, 0, &input, 0, size)?;
+  commands.barrier();
+  commands.dispatch(
+    &pipeline,
+    &[Binding::StorageBuffer(&input), Binding::StorageBuffer(&output)],
+    &scale.to_le_bytes(),
+    [(numbers.len() as u32).div_ceil(64), 1, 1],
+  )?;
+  commands.barrier();
+  commands.copy_buffer(&output, 0, &readback, 0, size)?;
+  device.submit_and_wait(commands)?;
+  let bytes = readback.read(0, size)?;
+  Ok(bytes.chunks_exact(4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]])).collect())
+}
+
+#[test]
+fn backend_code_records_the_same_on_the_null_backend() {
+  let numbers = (0..100).collect::<Vec<u32>>();
+  // The null backend copies but never runs shaders, so nothing gets scaled
+  let device = NullDevice::new();
+  assert_eq!(scale_on_gpu(&device, &numbers, 3).unwrap(), vec![0; 100]);
+  let submitted = device.submitted_commands().unwrap();
+  assert_eq!(submitted.len(), 5);
+  assert_eq!(
+    submitted[2],
+    NullCommand::Dispatch {
+      pipeline: "scale".to_string(),
+      bindings: 2,
+      push_constant_size: 4,
+      group_counts: [2, 1, 1],
+    }
+  );
+}
+
+#[test]
+fn srgb_encode_covers_the_color_image_on_the_null_backend() {
+  let device = NullDevice::new();
+  let srgb_encode = SrgbEncodeRenderer::new(&device).unwrap();
+  let color = device
+    .create_image(&ImageDesc {
+      name: "scene_color".to_string(),
+      format: Format::Rgba16Float,
+      extent: Extent2D { width: 1921, height: 1080 },
+      sampled: false,
+      storage: true,
+    })
+    .unwrap();
+  let mut commands = device.begin_commands().unwrap();
+  srgb_encode.encode(&mut commands, &color).unwrap();
+  device.submit_and_wait(commands).unwrap();
+  assert_eq!(
+    device.submitted_commands().unwrap(),
+    vec![NullCommand::Dispatch {
+      pipeline: "srgb_encode".to_string(),
+      bindings: 1,
+      push_constant_size: 0,
+      group_counts: [241, 135, 1],
+    }]
+  );
+}
+
+#[test]
+#[ignore = "needs a vulkan capable gpu"]
+fn backend_code_runs_on_vulkan() {
+  let (render_mgr, _window) = headless_render_manager(RendererConfig::default());
+  let device = render_mgr.graphics_device().unwrap();
+  assert!(device.name().starts_with("vulkan"));
+  let numbers = (0..100).collect::<Vec<u32>>();
+  let scaled = scale_on_gpu(&device, &numbers, 3).unwrap();
+  assert_eq!(scaled, numbers.iter().map(|x| x * 3).collect::<Vec<_>>());
+
+  let heatmap = image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255]));
+  let uploaded = upload_heatmap(&device, &heatmap).unwrap();
+  assert_eq!(uploaded.extent(), Extent2D { width: 4, height: 4 });
+  assert!(SrgbEncodeRenderer::new(&device).is_ok());
+}
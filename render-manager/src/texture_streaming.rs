@@ -0,0 +1,281 @@
+use std::{
+  cmp::Reverse,
+  collections::{HashMap, HashSet},
+};
+
+use ash_ad_wrappers::ash_context::ash::vk;
+use jobs::JobHandle;
+use renderables::flat_texture::{
+  mip_count, mip_extent, DecodedFlatTexture, TextureColorSpace, TextureFormat,
+  TextureImportSettings,
+};
+
+use crate::{
+  handles::{MaterialHandle, TextureHandle},
+  TextureLoad,
+};
+
+// Textures this many texels across or more are streamed, smaller ones are uploaded whole
+pub const STREAMED_MIN_SIZE: u32 = 1024;
+// Largest a streamed texture's first upload gets across
+pub const INITIAL_MAX_SIZE: u32 = 128;
+// Every change decodes the file again and re-uploads the texture's mips, so only this many are
+// loading at once
+const MAX_PENDING_LOADS: usize = 2;
+
+// Format and levels from the first mip down, decoded on the job pool
+pub type StreamedMips = Result<(TextureFormat, Vec<(vk::Extent2D, Vec<u8>)>), String>;
+
+pub fn is_streamed(resolution: vk::Extent2D) -> bool {
+  resolution.width.max(resolution.height) >= STREAMED_MIN_SIZE
+}
+
+// First mip at most INITIAL_MAX_SIZE across
+pub fn initial_mip(resolution: vk::Extent2D) -> u32 {
+  (0..mip_count(resolution))
+    .find(|mip| {
+      let extent = mip_extent(resolution, *mip);
+      extent.width.max(extent.height) <= INITIAL_MAX_SIZE
+    })
+    .unwrap_or(mip_count(resolution) - 1)
+}
+
+// Bytes the levels from first_mip down to 1x1 take up
+pub fn mip_chain_bytes(resolution: vk::Extent2D, texel_bytes: usize, first_mip: u32) -> u64 {
+  (first_mip..mip_count(resolution))
+    .map(|mip| {
+      let extent = mip_extent(resolution, mip);
+      extent.width as u64 * extent.height as u64 * texel_bytes as u64
+    })
+    .sum()
+}
+
+// Pixels across the screen a sphere of radius covers at distance away, or infinity with the
+// camera inside it
+pub fn projected_size(radius: f32, distance: f32, fov: f32, screen_height: u32) -> f32 {
+  if distance <= radius {
+    return f32::INFINITY;
+  }
+  radius / (distance * (fov / 2.0).tan()) * screen_height as f32
+}
+
+// Mip with about a texel per pixel for a texture covering projected_size pixels across
+pub fn mip_for_footprint(resolution: vk::Extent2D, projected_size: f32) -> u32 {
+  let last_mip = mip_count(resolution) - 1;
+  if projected_size <= 1.0 {
+    return last_mip;
+  }
+  let size = resolution.width.max(resolution.height) as f32;
+  ((size / projected_size).log2().floor().max(0.0) as u32).min(last_mip)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextureStreamingStats {
+  pub textures: usize,
+  // What streamed textures take up now, and would with every mip
+  pub resident_bytes: u64,
+  pub full_bytes: u64,
+  pub budget_bytes: u64,
+  pub uploads: u64,
+}
+
+struct StreamedTexture {
+  name: String,
+  load: TextureLoad,
+  resolution: vk::Extent2D,
+  texel_bytes: usize,
+  // Largest level on the gpu, 0 is full resolution
+  resident_mip: u32,
+  // None when none of its materials were drawn last update
+  desired_mip: Option<u32>,
+  materials: HashSet<MaterialHandle>,
+}
+
+impl StreamedTexture {
+  fn bytes(&self, first_mip: u32) -> u64 {
+    mip_chain_bytes(self.resolution, self.texel_bytes, first_mip)
+  }
+
+  fn last_mip(&self) -> u32 {
+    mip_count(self.resolution) - 1
+  }
+}
+
+// Which mips of each large texture are kept on the gpu. Textures are re-uploaded with a different
+// first mip and their materials pointed at the new upload, see RenderManager::stream_textures
+pub struct TextureStreamer {
+  textures: HashMap<TextureHandle, StreamedTexture>,
+  // First mip being loaded for each texture
+  pending: HashMap<TextureHandle, (u32, JobHandle<StreamedMips>)>,
+  budget_bytes: u64,
+  uploads: u64,
+}
+
+impl TextureStreamer {
+  pub fn new(budget_bytes: u64) -> Self {
+    Self { textures: HashMap::new(), pending: HashMap::new(), budget_bytes, uploads: 0 }
+  }
+
+  // Returns the first mip to upload
+  pub fn add(
+    &mut self,
+    handle: TextureHandle,
+    name: &str,
+    load: TextureLoad,
+    decoded: &DecodedFlatTexture,
+  ) -> u32 {
+    let resident_mip = initial_mip(decoded.resolution);
+    let texture = StreamedTexture {
+      name: name.to_string(),
+      load,
+      resolution: decoded.resolution,
+      texel_bytes: decoded.format.texel_bytes(),
+      resident_mip,
+      desired_mip: None,
+      materials: HashSet::new(),
+    };
+    self.textures.insert(handle, texture);
+    resident_mip
+  }
+
+  pub fn remove(&mut self, handle: TextureHandle) {
+    self.textures.remove(&handle);
+    self.pending.remove(&handle);
+  }
+
+  pub fn add_material(&mut self, texture: TextureHandle, material: MaterialHandle) {
+    if let Some(texture) = self.textures.get_mut(&texture) {
+      texture.materials.insert(material);
+    }
+  }
+
+  pub fn remove_material(&mut self, material: MaterialHandle) {
+    for texture in self.textures.values_mut() {
+      texture.materials.remove(&material);
+    }
+  }
+
+  pub fn materials(&self, handle: TextureHandle) -> Vec<MaterialHandle> {
+    self.textures.get(&handle).map(|x| x.materials.iter().copied().collect()).unwrap_or_default()
+  }
+
+  pub fn name(&self, handle: TextureHandle) -> Option<&str> {
+    self.textures.get(&handle).map(|x| x.name.as_str())
+  }
+
+  // Color space and import settings the texture was loaded with
+  pub fn read_as(
+    &self,
+    handle: TextureHandle,
+  ) -> Option<(TextureColorSpace, TextureImportSettings)> {
+    self.textures.get(&handle).map(|x| (x.load.1, x.load.3))
+  }
+
+  // Screen sizes in pixels each material was drawn at, a texture wants the mip for the largest of
+  // its materials
+  pub fn update_desired(&mut self, material_sizes: &HashMap<MaterialHandle, f32>) {
+    for texture in self.textures.values_mut() {
+      let size = texture
+        .materials
+        .iter()
+        .filter_map(|material| material_sizes.get(material))
+        .fold(None, |max: Option<f32>, size| Some(max.map_or(*size, |max| max.max(*size))));
+      texture.desired_mip = size.map(|size| mip_for_footprint(texture.resolution, size));
+    }
+  }
+
+  // First mip each texture should have to stay in budget, only for the ones that need changing.
+  // Textures that weren't drawn keep what they have until the budget runs out, and drawn ones
+  // only drop a single mip once under budget pressure, so moving the camera doesn't thrash
+  pub fn plan(&self) -> Vec<(TextureHandle, u32)> {
+    let mut targets = self
+      .textures
+      .iter()
+      .map(|(handle, texture)| {
+        let target = match texture.desired_mip {
+          Some(desired) if desired != texture.resident_mip + 1 => desired,
+          _ => texture.resident_mip,
+        };
+        (*handle, target)
+      })
+      .collect::<HashMap<_, _>>();
+    let mut total =
+      targets.iter().map(|(handle, mip)| self.textures[handle].bytes(*mip)).sum::<u64>();
+    while total > self.budget_bytes {
+      // Undrawn textures go first, then whichever frees the most
+      let coarsened = targets
+        .iter()
+        .filter(|(handle, mip)| **mip < self.textures[*handle].last_mip())
+        .max_by_key(|(handle, mip)| {
+          let texture = &self.textures[*handle];
+          (texture.desired_mip.is_none(), texture.bytes(**mip) - texture.bytes(**mip + 1))
+        })
+        .map(|(handle, _)| *handle);
+      let Some(handle) = coarsened else { break };
+      let (Some(mip), Some(texture)) = (targets.get_mut(&handle), self.textures.get(&handle))
+      else {
+        break;
+      };
+      total -= texture.bytes(*mip) - texture.bytes(*mip + 1);
+      *mip += 1;
+    }
+    let mut changes = targets
+      .into_iter()
+      .filter(|(handle, mip)| {
+        *mip != self.textures[handle].resident_mip && !self.pending.contains_key(handle)
+      })
+      .collect::<Vec<_>>();
+    // Freeing memory before taking more, then the textures furthest from what they want
+    changes.sort_by_key(|(handle, mip)| {
+      let resident_mip = self.textures[handle].resident_mip;
+      (*mip < resident_mip, Reverse(resident_mip.abs_diff(*mip)))
+    });
+    changes.truncate(MAX_PENDING_LOADS.saturating_sub(self.pending.len()));
+    changes
+  }
+
+  // Starts decoding the planned changes on the job pool, take_loaded hands them back
+  pub fn start_loads(&mut self) {
+    for (handle, first_mip) in self.plan() {
+      let (tex_path, color_space, format, _) = self.textures[&handle].load.clone();
+      let job = jobs::global().spawn(move || {
+        let decoded = DecodedFlatTexture::load(&tex_path, color_space, format)?;
+        Ok((decoded.format, decoded.mips(first_mip)))
+      });
+      self.pending.insert(handle, (first_mip, job));
+    }
+  }
+
+  // Loads that finished, with the first mip they start at
+  pub fn take_loaded(&mut self) -> Vec<(TextureHandle, u32, StreamedMips)> {
+    let finished = self
+      .pending
+      .iter()
+      .filter(|(_, (_, job))| job.is_finished())
+      .map(|(x, _)| *x)
+      .collect::<Vec<_>>();
+    finished
+      .into_iter()
+      .filter_map(|handle| self.pending.remove(&handle).map(|x| (handle, x)))
+      .map(|(handle, (first_mip, job))| (handle, first_mip, job.wait().and_then(|x| x)))
+      .collect()
+  }
+
+  // Also counts as an upload
+  pub fn set_resident(&mut self, handle: TextureHandle, first_mip: u32) {
+    if let Some(texture) = self.textures.get_mut(&handle) {
+      texture.resident_mip = first_mip;
+      self.uploads += 1;
+    }
+  }
+
+  pub fn stats(&self) -> TextureStreamingStats {
+    TextureStreamingStats {
+      textures: self.textures.len(),
+      resident_bytes: self.textures.values().map(|x| x.bytes(x.resident_mip)).sum(),
+      full_bytes: self.textures.values().map(|x| x.bytes(0)).sum(),
+      budget_bytes: self.budget_bytes,
+      uploads: self.uploads,
+    }
+  }
+}
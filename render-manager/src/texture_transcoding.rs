@@ -0,0 +1,347 @@
+use std::{
+  collections::{HashMap, HashSet},
+  ops::Range,
+  sync::Arc,
+};
+
+use ash_ad_wrappers::ash_context::ash::vk;
+use crash_report::log;
+use engine_config::TextureCompression;
+use jobs::JobHandle;
+use renderables::{
+  flat_texture::TextureImportSettings,
+  flat_texture::{mip_count, DecodedFlatTexture, TextureColorSpace, TextureFormat},
+  sampler_cache::SamplerDesc,
+  texture_compression::{self, BlockFormat, BlockQuality, CompressedFlatTexture},
+};
+
+use crate::handles::{MaterialHandle, TextureHandle};
+
+// Blocks encoded per job, about a millisecond of work with refined endpoints in release builds
+pub const CHUNK_BLOCKS: u32 = 4096;
+// Compressed textures uploaded per frame, each one is a blocking upload like any other texture's
+const MAX_UPLOADS_PER_FRAME: usize = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextureCompressionStats {
+  // Still being compressed or waiting for upload
+  pub pending: usize,
+  pub compressed: u64,
+  // Of the compressed ones, how many were found in the asset cache
+  pub cache_hits: u64,
+  // What the compressed textures would take as rgba8, and what they take now
+  pub uncompressed_bytes: u64,
+  pub compressed_bytes: u64,
+}
+
+// Level and block rows of it one job encodes
+type Chunk = (usize, Range<u32>);
+
+// Rgba8 levels to encode and how they're split up
+struct EncodeInput {
+  cache_hash: u64,
+  format: BlockFormat,
+  quality: BlockQuality,
+  resolution: vk::Extent2D,
+  mips: Vec<(vk::Extent2D, Vec<u8>)>,
+  chunks: Vec<Chunk>,
+}
+
+enum Prepared {
+  Cached(CompressedFlatTexture),
+  Encode(EncodeInput),
+}
+
+enum Stage {
+  // Looking the texture up in the asset cache, and making its levels when it isn't there
+  Preparing(JobHandle<Result<Prepared, String>>),
+  Encoding {
+    input: Arc<EncodeInput>,
+    next_chunk: usize,
+    running: Vec<(usize, JobHandle<Vec<u8>>)>,
+    encoded: Vec<Option<Vec<u8>>>,
+  },
+  Done(Arc<CompressedFlatTexture>),
+}
+
+struct TranscodedTexture {
+  // Textures added earlier get their chunks scheduled first
+  order: u64,
+  // Textures loaded under the same name share one upload
+  handles: HashSet<TextureHandle>,
+  materials: HashSet<MaterialHandle>,
+  color_space: TextureColorSpace,
+  sampler: SamplerDesc,
+  rgba8_bytes: u64,
+  stage: Stage,
+}
+
+// A compressed texture ready to replace the uncompressed upload of its handles
+pub struct Transcoded {
+  pub name: String,
+  pub handles: Vec<TextureHandle>,
+  pub materials: Vec<MaterialHandle>,
+  pub color_space: TextureColorSpace,
+  pub sampler: SamplerDesc,
+  pub compressed: Arc<CompressedFlatTexture>,
+}
+
+fn block_format(compression: TextureCompression, texels: &[u8]) -> (BlockFormat, BlockQuality) {
+  match (compression, texture_compression::is_opaque(texels)) {
+    (TextureCompression::Quality, _) => (BlockFormat::Bc7, BlockQuality::Refined),
+    (_, true) => (BlockFormat::Bc1, BlockQuality::Fast),
+    (_, false) => (BlockFormat::Bc7, BlockQuality::Fast),
+  }
+}
+
+// Bands of block rows of about CHUNK_BLOCKS each, level after level
+pub fn split_chunks(mips: &[(vk::Extent2D, Vec<u8>)]) -> Vec<Chunk> {
+  let mut chunks = vec![];
+  for (level, (extent, _)) in mips.iter().enumerate() {
+    let rows = texture_compression::block_rows(*extent);
+    let rows_per_chunk = (CHUNK_BLOCKS / extent.width.div_ceil(4)).max(1);
+    let mut first_row = 0;
+    while first_row < rows {
+      let last_row = (first_row + rows_per_chunk).min(rows);
+      chunks.push((level, first_row..last_row));
+      first_row = last_row;
+    }
+  }
+  chunks
+}
+
+fn prepare(
+  decoded: DecodedFlatTexture,
+  compression: TextureCompression,
+  all_mips: bool,
+) -> Result<Prepared, String> {
+  let (format, quality) = block_format(compression, &decoded.texels);
+  let levels = if all_mips { mip_count(decoded.resolution) } else { 1 };
+  let cache_hash =
+    CompressedFlatTexture::cache_hash(&decoded.texels, decoded.resolution, levels, format, quality);
+  if let Some(cached) = CompressedFlatTexture::load_cached(cache_hash) {
+    return Ok(Prepared::Cached(cached));
+  }
+  let mips = match all_mips {
+    true => decoded.mips(0),
+    false => vec![(decoded.resolution, decoded.texels)],
+  };
+  Ok(Prepared::Encode(EncodeInput {
+    cache_hash,
+    format,
+    quality,
+    resolution: decoded.resolution,
+    chunks: split_chunks(&mips),
+    mips,
+  }))
+}
+
+// Compresses user textures to BC formats on the job pool, a few chunks of blocks at a time so
+// frames keep most of the workers. Textures are drawn with their rgba8 upload meanwhile, and
+// RenderManager::transcode_textures swaps the compressed one in once it's done
+pub struct TextureTranscoder {
+  compression: TextureCompression,
+  textures: HashMap<String, TranscodedTexture>,
+  added: u64,
+  stats: TextureCompressionStats,
+}
+
+impl TextureTranscoder {
+  pub fn new(compression: TextureCompression) -> Self {
+    Self {
+      compression,
+      textures: HashMap::new(),
+      added: 0,
+      stats: TextureCompressionStats::default(),
+    }
+  }
+
+  // Only rgba8 textures are compressed, others are left as they are
+  pub fn add(
+    &mut self,
+    handle: TextureHandle,
+    name: &str,
+    color_space: TextureColorSpace,
+    settings: TextureImportSettings,
+    decoded: &DecodedFlatTexture,
+  ) {
+    if decoded.format != TextureFormat::Rgba8 {
+      return;
+    }
+    if let Some(texture) = self.textures.get_mut(name) {
+      texture.handles.insert(handle);
+      return;
+    }
+    let decoded = DecodedFlatTexture {
+      content_hash: decoded.content_hash,
+      format: decoded.format,
+      resolution: decoded.resolution,
+      texels: decoded.texels.clone(),
+    };
+    let compression = self.compression;
+    let all_mips = settings.mips == Some(true);
+    let job = jobs::global().spawn(move || prepare(decoded, compression, all_mips));
+    self.added += 1;
+    let texture = TranscodedTexture {
+      order: self.added,
+      handles: HashSet::from([handle]),
+      materials: HashSet::new(),
+      color_space,
+      sampler: settings.sampler(),
+      rgba8_bytes: 0,
+      stage: Stage::Preparing(job),
+    };
+    self.textures.insert(name.to_string(), texture);
+  }
+
+  // For another handle to a texture loaded under the same name
+  pub fn share(&mut self, name: &str, handle: TextureHandle) {
+    if let Some(texture) = self.textures.get_mut(name) {
+      texture.handles.insert(handle);
+    }
+  }
+
+  // Work left on the texture is dropped with its last handle
+  pub fn remove(&mut self, handle: TextureHandle) {
+    for texture in self.textures.values_mut() {
+      texture.handles.remove(&handle);
+    }
+    self.textures.retain(|_, texture| !texture.handles.is_empty());
+  }
+
+  pub fn add_material(&mut self, texture: TextureHandle, material: MaterialHandle) {
+    let found = self.textures.values_mut().find(|x| x.handles.contains(&texture));
+    if let Some(texture) = found {
+      texture.materials.insert(material);
+    }
+  }
+
+  pub fn remove_material(&mut self, material: MaterialHandle) {
+    for texture in self.textures.values_mut() {
+      texture.materials.remove(&material);
+    }
+  }
+
+  // Moves textures along, keeping at most half the job threads encoding, and hands back the ones
+  // finished this frame. Textures that fail are reported and stay uncompressed
+  pub fn pump(&mut self) -> Vec<Transcoded> {
+    let prepared = self
+      .textures
+      .iter()
+      .filter(|(_, texture)| matches!(&texture.stage, Stage::Preparing(job) if job.is_finished()))
+      .map(|(name, _)| name.clone())
+      .collect::<Vec<_>>();
+    for name in prepared {
+      let Some(mut texture) = self.textures.remove(&name) else { continue };
+      let Stage::Preparing(job) = texture.stage else { continue };
+      texture.stage = match job.wait().and_then(|x| x) {
+        Ok(Prepared::Cached(cached)) => {
+          texture.rgba8_bytes = cached.mips.iter().map(|(x, _)| rgba8_bytes(*x)).sum();
+          self.stats.cache_hits += 1;
+          Stage::Done(Arc::new(cached))
+        }
+        Ok(Prepared::Encode(input)) => {
+          texture.rgba8_bytes = input.mips.iter().map(|(x, _)| rgba8_bytes(*x)).sum();
+          Stage::Encoding {
+            encoded: vec![None; input.chunks.len()],
+            input: Arc::new(input),
+            next_chunk: 0,
+            running: vec![],
+          }
+        }
+        Err(e) => {
+          log!("at compressing texture {name}: {e}");
+          continue;
+        }
+      };
+      self.textures.insert(name, texture);
+    }
+
+    let max_running = (jobs::global().thread_count() / 2).max(1);
+    let mut running_total = 0;
+    let mut order = self.textures.keys().cloned().collect::<Vec<_>>();
+    order.sort_by_key(|name| self.textures[name].order);
+    for name in order {
+      let Some(texture) = self.textures.get_mut(&name) else { continue };
+      let Stage::Encoding { input, next_chunk, running, encoded } = &mut texture.stage else {
+        continue;
+      };
+      let mut still_running = vec![];
+      for (chunk_idx, job) in running.drain(..) {
+        match job.is_finished() {
+          true => encoded[chunk_idx] = job.wait().ok(),
+          false => still_running.push((chunk_idx, job)),
+        }
+      }
+      *running = still_running;
+      while running_total + running.len() < max_running && *next_chunk < input.chunks.len() {
+        let chunk_idx = *next_chunk;
+        let input = input.clone();
+        let job = jobs::global().spawn(move || {
+          let (level, rows) = input.chunks[chunk_idx].clone();
+          let (extent, texels) = &input.mips[level];
+          texture_compression::encode_block_rows(input.format, input.quality, *extent, texels, rows)
+        });
+        running.push((chunk_idx, job));
+        *next_chunk += 1;
+      }
+      running_total += running.len();
+      if *next_chunk < input.chunks.len() || !running.is_empty() {
+        continue;
+      }
+      // A chunk job that failed leaves a gap, the texture stays uncompressed
+      let Some(encoded) = encoded.drain(..).collect::<Option<Vec<_>>>() else {
+        log!("at compressing texture {name}: an encoding job failed");
+        self.textures.remove(&name);
+        continue;
+      };
+      let mut mips =
+        input.mips.iter().map(|(extent, _)| (*extent, vec![])).collect::<Vec<(_, Vec<u8>)>>();
+      for ((level, _), blocks) in input.chunks.iter().zip(encoded) {
+        mips[*level].1.extend(blocks);
+      }
+      let compressed = Arc::new(CompressedFlatTexture {
+        format: input.format,
+        resolution: input.resolution,
+        mips,
+      });
+      let (to_cache, cache_hash) = (compressed.clone(), input.cache_hash);
+      jobs::global().spawn(move || to_cache.store_cached(cache_hash));
+      texture.stage = Stage::Done(compressed);
+    }
+
+    let mut done = self
+      .textures
+      .iter()
+      .filter(|(_, texture)| matches!(texture.stage, Stage::Done(_)))
+      .map(|(name, texture)| (texture.order, name.clone()))
+      .collect::<Vec<_>>();
+    done.sort();
+    done.truncate(MAX_UPLOADS_PER_FRAME);
+    let mut finished = vec![];
+    for (_, name) in done {
+      let Some(texture) = self.textures.remove(&name) else { continue };
+      let Stage::Done(compressed) = texture.stage else { continue };
+      self.stats.compressed += 1;
+      self.stats.uncompressed_bytes += texture.rgba8_bytes;
+      self.stats.compressed_bytes += compressed.bytes();
+      finished.push(Transcoded {
+        name,
+        handles: texture.handles.into_iter().collect(),
+        materials: texture.materials.into_iter().collect(),
+        color_space: texture.color_space,
+        sampler: texture.sampler,
+        compressed,
+      });
+    }
+    finished
+  }
+
+  pub fn stats(&self) -> TextureCompressionStats {
+    TextureCompressionStats { pending: self.textures.len(), ..self.stats }
+  }
+}
+
+fn rgba8_bytes(extent: vk::Extent2D) -> u64 {
+  extent.width as u64 * extent.height as u64 * 4
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use renderables::glam;
+
+use crate::{handles::MeshHandle, snapshot::FrameSnapshot};
+
+// Transforms are carried on past their newest update by at most this many microseconds, or the
+// time between their last two updates if that's shorter. They hold still after
+const MAX_EXTRAPOLATION: u128 = 100_000;
+
+#[derive(Debug, Clone, Copy)]
+struct TimedTransform {
+  time: u128,
+  transform: glam::Mat4,
+}
+
+fn blend(from: &TimedTransform, to: &TimedTransform, time: u128) -> glam::Mat4 {
+  let t = (time - from.time) as f32 / (to.time - from.time).max(1) as f32;
+  let (from_scale, from_rot, from_pos) = from.transform.to_scale_rotation_translation();
+  let (to_scale, to_rot, to_pos) = to.transform.to_scale_rotation_translation();
+  // Past to the rotation keeps turning by the step from made to it, slerp would stop at to
+  let rotation = if t > 1.0 {
+    let mut step = to_rot * from_rot.inverse();
+    // The short way round
+    if step.w < 0.0 {
+      step = -step;
+    }
+    let (axis, angle) = step.to_axis_angle();
+    glam::Quat::from_axis_angle(axis, angle * (t - 1.0)) * to_rot
+  } else {
+    from_rot.slerp(to_rot, t)
+  };
+  glam::Mat4::from_scale_rotation_translation(
+    from_scale.lerp(to_scale, t),
+    rotation,
+    from_pos.lerp(to_pos, t),
+  )
+}
+
+// Last two timestamped updates of meshes the game moves less often than it ticks, so the render
+// thread can place them at its own time instead of stepping with every update
+pub struct TransformHistory {
+  meshes: HashMap<MeshHandle, (Option<TimedTransform>, TimedTransform)>,
+}
+
+impl TransformHistory {
+  pub fn new() -> Self {
+    Self { meshes: HashMap::new() }
+  }
+
+  // For every new snapshot. Meshes it has without a timestamp are forgotten, so are ones it lacks
+  pub fn record(&mut self, snapshot: &FrameSnapshot) {
+    let mut meshes = HashMap::with_capacity(self.meshes.len());
+    for mesh_state in snapshot.meshes.iter() {
+      let Some(time) = mesh_state.updated_at else { continue };
+      let update = TimedTransform { time, transform: mesh_state.transform.transform };
+      let history = match self.meshes.get(&mesh_state.mesh) {
+        Some((prev, latest)) if latest.time == time => (*prev, *latest),
+        Some((_, latest)) if latest.time < time => (Some(*latest), update),
+        // New, or a recycled handle starting over
+        _ => (None, update),
+      };
+      meshes.insert(mesh_state.mesh, history);
+    }
+    self.meshes = meshes;
+  }
+
+  // Placed at the sim time in microseconds, between the last two updates or carried on past the
+  // newest. None for meshes without timestamped updates
+  pub fn transform_at(&self, mesh: MeshHandle, time: u128) -> Option<glam::Mat4> {
+    let (prev, latest) = self.meshes.get(&mesh)?;
+    let Some(prev) = prev else { return Some(latest.transform) };
+    let reach = (latest.time - prev.time).min(MAX_EXTRAPOLATION);
+    let time = time.clamp(prev.time, latest.time + reach);
+    Some(blend(prev, latest, time))
+  }
+
+  // Overrides the snapshot's transforms of meshes with timestamped updates
+  pub fn apply(&self, snapshot: &mut FrameSnapshot) {
+    let time = snapshot.sim_time;
+    for mesh_state in snapshot.meshes.iter_mut() {
+      if let Some(transform) = self.transform_at(mesh_state.mesh, time) {
+        mesh_state.transform.transform = transform;
+      }
+    }
+  }
+}
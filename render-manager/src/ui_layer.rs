@@ -0,0 +1,186 @@
+use std::sync::{Arc, Mutex};
+
+use ash_ad_wrappers::{
+  ash_context::{ash::vk, gpu_allocator::vulkan::Allocator, AdAshDevice},
+  ash_queue_wrappers::{AdCommandBuffer, AdCommandPool},
+  ash_render_wrappers::AdFrameBuffer,
+};
+use renderables::{
+  color::Color,
+  glam,
+  sdf_font::{SdfFontCPU, SdfFontGPU},
+  Camera3D,
+};
+use renderers::{
+  debug_renderers::{DebugOverlayRenderer, OverlayRect},
+  sdf_renderers::{SdfShape, SdfShapeRenderer},
+  ui_renderers::UiCompositeRenderer,
+};
+
+// Smallest scale the UI is drawn at, and the largest. Past these it's unreadable or doesn't fit
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 4.0;
+
+// How much bigger than authored the UI is drawn. A ui_scale of 0 in the config follows the
+// window's scale factor, so UI keeps its physical size on high dpi screens
+pub fn resolve_ui_scale(ui_scale: f32, window_scale_factor: f64) -> f32 {
+  let scale = match ui_scale > 0.0 {
+    true => ui_scale,
+    false => window_scale_factor as f32,
+  };
+  match scale.is_finite() {
+    true => scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE),
+    false => 1.0,
+  }
+}
+
+// Drawn on the UI layer from signed distances, so edges stay sharp at any size. Positions and
+// sizes are in pixels from the top left of the layer, which is the scene as shown
+#[derive(Debug, Clone, PartialEq)]
+pub enum UiShape {
+  // rect is left, top, width, height. Filled, or just an outline that wide inside the edge
+  RoundedRect { rect: glam::Vec4, radius: f32, outline: f32, color: Color },
+  Circle { center: glam::Vec2, radius: f32, outline: f32, color: Color },
+  Line { start: glam::Vec2, end: glam::Vec2, thickness: f32, color: Color },
+  // position is the top left of the first line, size its height. Only drawn with a ui_font
+  // configured
+  Text { position: glam::Vec2, text: String, size: f32, color: Color },
+}
+
+// In draw order, text becomes a shape per glyph
+pub fn sdf_shapes(shapes: &[UiShape], font: &SdfFontCPU) -> Vec<SdfShape> {
+  let mut sdf_shapes = Vec::with_capacity(shapes.len());
+  for shape in shapes {
+    match shape {
+      UiShape::RoundedRect { rect, radius, outline, color } => {
+        sdf_shapes.push(SdfShape::rounded_rect(*rect, *radius, *outline, *color))
+      }
+      UiShape::Circle { center, radius, outline, color } => {
+        sdf_shapes.push(SdfShape::circle(*center, *radius, *outline, *color))
+      }
+      UiShape::Line { start, end, thickness, color } => {
+        sdf_shapes.push(SdfShape::line(*start, *end, *thickness, *color))
+      }
+      UiShape::Text { position, text, size, color } => {
+        let distance_range = font.distance_range(*size);
+        sdf_shapes.extend(font.layout(text, *size).into_iter().map(|glyph| {
+          let rect = glyph.rect + glam::vec4(position.x, position.y, 0.0, 0.0);
+          SdfShape::glyph(rect, glyph.uv, distance_range, *color)
+        }));
+      }
+    }
+  }
+  sdf_shapes
+}
+
+// HUD overlays drawn into framebuffers of their own at the resolution the scene is shown at, then
+// blended over the scene scaled up to it, so they stay sharp at any render scale. Framebuffers are
+// only made once there's something to draw on the layer
+pub struct UiLayer {
+  overlay_renderer: DebugOverlayRenderer,
+  shape_renderer: SdfShapeRenderer,
+  // Laid out on the cpu, the atlas is only bound by the shape renderer
+  font: SdfFontCPU,
+  composite_renderer: UiCompositeRenderer,
+  frame_buffers: Vec<Arc<AdFrameBuffer>>,
+}
+
+impl UiLayer {
+  pub fn new(
+    ash_device: Arc<AdAshDevice>,
+    allocator: Arc<Mutex<Allocator>>,
+    upload_cmd_pool: Arc<AdCommandPool>,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    frames_in_flight: usize,
+    font: SdfFontCPU,
+  ) -> Result<Self, String> {
+    let overlay_renderer = DebugOverlayRenderer::new_layer(
+      ash_device.clone(),
+      allocator.clone(),
+      color_format,
+      depth_format,
+      frames_in_flight,
+    )?;
+    let font_atlas =
+      SdfFontGPU::upload(allocator.clone(), upload_cmd_pool, "ui_font_atlas", &font)?;
+    let shape_renderer = SdfShapeRenderer::new(
+      ash_device.clone(),
+      allocator,
+      color_format,
+      depth_format,
+      frames_in_flight,
+      &font_atlas,
+    )?;
+    let composite_renderer = UiCompositeRenderer::new(ash_device)?;
+    Ok(Self { overlay_renderer, shape_renderer, font, composite_renderer, frame_buffers: vec![] })
+  }
+
+  // True before the first framebuffers are made too
+  pub fn font(&self) -> &SdfFontCPU {
+    &self.font
+  }
+
+  pub fn needs_resize(&self, resolution: vk::Extent2D) -> bool {
+    !self.frame_buffers.first().is_some_and(|fb| fb.resolution() == resolution)
+  }
+
+  pub fn has_frame_buffers(&self) -> bool {
+    !self.frame_buffers.is_empty()
+  }
+
+  // For when the scene framebuffers are replaced, the next resize pairs the layer with the new
+  // ones. Frames in flight may still use what's dropped, wait for them first
+  pub fn clear_targets(&mut self) {
+    self.frame_buffers.clear();
+  }
+
+  pub fn resize(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    allocator: Arc<Mutex<Allocator>>,
+    resolution: vk::Extent2D,
+    scene_frame_buffers: &[Arc<AdFrameBuffer>],
+  ) -> Result<(), String> {
+    self.clear_targets();
+    self.frame_buffers = self.overlay_renderer.create_framebuffers(
+      cmd_buffer,
+      allocator,
+      resolution,
+      scene_frame_buffers.len(),
+      crate::color::scene_color_usage(),
+    )?;
+    for (i, fb) in self.frame_buffers.iter().enumerate() {
+      fb.attachments()[0]
+        .image()
+        .allocation()
+        .lock()
+        .map_err(|e| format!("at getting image mem lock: {e}"))?
+        .rename(&format!("ui_color_image_{i}"))?;
+    }
+    self.composite_renderer.resize_targets(scene_frame_buffers, &self.frame_buffers)
+  }
+
+  pub fn frame_buffer(&self, frame_idx: usize) -> &Arc<AdFrameBuffer> {
+    &self.frame_buffers[frame_idx]
+  }
+
+  // After everything drawn into the scene. The rects are in normalized device coordinates like
+  // they are drawn over the scene, shapes go over them, and the composite is left in the layer's
+  // framebuffer
+  pub fn record(
+    &self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    scene_frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+    rects: &[OverlayRect],
+    shapes: &[UiShape],
+  ) -> Result<(), String> {
+    let frame_buffer = &self.frame_buffers[frame_idx];
+    self.overlay_renderer.render(cmd_buffer, frame_buffer, frame_idx, camera, &[], rects)?;
+    let sdf_shapes = sdf_shapes(shapes, &self.font);
+    self.shape_renderer.render(cmd_buffer, frame_buffer, frame_idx, &sdf_shapes)?;
+    self.composite_renderer.composite(cmd_buffer, frame_idx, scene_frame_buffer, frame_buffer)
+  }
+}
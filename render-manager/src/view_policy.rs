@@ -0,0 +1,80 @@
+use ash_ad_wrappers::ash_context::ash::vk;
+use engine_config::{FovAxis, ViewConfig};
+use renderables::{glam, Camera3D};
+
+// Part of the target the scene is drawn in, centered with black bars on the sides it's cut from.
+// The whole target while its aspect ratio is within the config's
+pub(crate) fn scene_viewport(config: &ViewConfig, target: vk::Extent2D) -> vk::Rect2D {
+  let aspect = target.width as f32 / target.height.max(1) as f32;
+  let extent = if config.min_aspect > 0.0 && aspect < config.min_aspect {
+    let height = (target.width as f32 / config.min_aspect).round() as u32;
+    vk::Extent2D { width: target.width, height: height.clamp(1, target.height.max(1)) }
+  } else if config.max_aspect > 0.0 && aspect > config.max_aspect {
+    let width = (target.height as f32 * config.max_aspect).round() as u32;
+    vk::Extent2D { width: width.clamp(1, target.width.max(1)), height: target.height }
+  } else {
+    target
+  };
+  vk::Rect2D {
+    offset: vk::Offset2D {
+      x: (target.width.saturating_sub(extent.width) / 2) as i32,
+      y: (target.height.saturating_sub(extent.height) / 2) as i32,
+    },
+    extent,
+  }
+}
+
+// What the projection gets for the camera's fov, see FovAxis
+pub(crate) fn projection_fov(config: &ViewConfig, fov: f32, aspect_ratio: f32) -> f32 {
+  match config.fov_axis {
+    FovAxis::Vertical => fov,
+    FovAxis::Horizontal => {
+      let half_width = (fov * 0.5).tan() * config.reference_aspect;
+      2.0 * (half_width / aspect_ratio.max(f32::EPSILON)).atan()
+    }
+  }
+}
+
+// Part of the viewport HUDs should stay in
+pub(crate) fn hud_safe_area(config: &ViewConfig, viewport: vk::Rect2D) -> vk::Rect2D {
+  let margin_x = (viewport.extent.width as f32 * config.safe_area_x.clamp(0.0, 0.5)) as u32;
+  let margin_y = (viewport.extent.height as f32 * config.safe_area_y.clamp(0.0, 0.5)) as u32;
+  vk::Rect2D {
+    offset: vk::Offset2D {
+      x: viewport.offset.x + margin_x as i32,
+      y: viewport.offset.y + margin_y as i32,
+    },
+    extent: vk::Extent2D {
+      width: viewport.extent.width.saturating_sub(2 * margin_x).max(1),
+      height: viewport.extent.height.saturating_sub(2 * margin_y).max(1),
+    },
+  }
+}
+
+// The camera projected like the scene is in a window_size window, and where a cursor at
+// cursor_pos, in 0..1 of the window with y going down, is on the scene the same way for
+// Camera3D::picking_ray. None when it's on the bars
+pub fn view_camera(
+  config: &ViewConfig,
+  mut camera: Camera3D,
+  fov: f32,
+  window_size: (u32, u32),
+  cursor_pos: Option<(f32, f32)>,
+) -> (Camera3D, Option<glam::Vec2>) {
+  let window = vk::Extent2D { width: window_size.0.max(1), height: window_size.1.max(1) };
+  let viewport = scene_viewport(config, window);
+  let aspect_ratio = viewport.extent.width as f32 / viewport.extent.height as f32;
+  camera.refresh_vp_matrix(projection_fov(config, fov, aspect_ratio), aspect_ratio);
+  let view_pos = cursor_pos.and_then(|(x, y)| {
+    let pixel = glam::vec2(x * window.width as f32, y * window.height as f32);
+    let min = glam::vec2(viewport.offset.x as f32, viewport.offset.y as f32);
+    let size = glam::vec2(viewport.extent.width as f32, viewport.extent.height as f32);
+    let pos = (pixel - min) / size;
+    (pos.cmpge(glam::Vec2::ZERO).all() && pos.cmple(glam::Vec2::ONE).all()).then_some(pos)
+  });
+  (camera, view_pos)
+}
+
+pub(crate) fn rect_array(rect: vk::Rect2D) -> [u32; 4] {
+  [rect.offset.x as u32, rect.offset.y as u32, rect.extent.width, rect.extent.height]
+}
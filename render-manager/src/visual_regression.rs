@@ -0,0 +1,148 @@
+use std::path::{Path, PathBuf};
+
+use ash_ad_wrappers::ash_data_wrappers::image::{self, RgbaImage};
+
+pub use crate::frame_diff::perceptual_delta;
+use crate::frame_diff::yiq;
+use crate::{ExportedFrame, FrameExportMode, RenderManager, RendererMessage};
+
+// Set to write the frames drawn as the new goldens instead of comparing against them
+pub const UPDATE_GOLDENS_VAR: &str = "RESIDUE_UPDATE_GOLDENS";
+// Where mismatches leave their frame and diff image, target/visual_diffs when unset
+pub const DIFF_DIR_VAR: &str = "RESIDUE_VISUAL_DIFF_DIR";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+  // Perceptual difference a pixel can have before it counts as changed, in 0..1
+  pub pixel_threshold: f32,
+  // Share of the pixels that can change before the images count as different
+  pub max_changed_share: f32,
+}
+
+impl Default for Tolerance {
+  // Loose enough for drivers rounding differently, not for a pass going missing
+  fn default() -> Self {
+    Self { pixel_threshold: 0.1, max_changed_share: 0.001 }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+  pub changed: usize,
+  pub max_delta: f32,
+  // The golden faded to gray with changed pixels in red
+  pub diff_image: RgbaImage,
+}
+
+impl ImageDiff {
+  pub fn within(&self, tolerance: Tolerance) -> bool {
+    let pixels = (self.diff_image.width() * self.diff_image.height()).max(1) as f32;
+    self.changed as f32 / pixels <= tolerance.max_changed_share
+  }
+}
+
+pub fn compare(
+  actual: &RgbaImage,
+  golden: &RgbaImage,
+  tolerance: Tolerance,
+) -> Result<ImageDiff, String> {
+  if actual.dimensions() != golden.dimensions() {
+    return Err(format!(
+      "frame is {:?} but the golden is {:?}",
+      actual.dimensions(),
+      golden.dimensions()
+    ));
+  }
+  let mut diff = ImageDiff {
+    changed: 0,
+    max_delta: 0.0,
+    diff_image: RgbaImage::new(golden.width(), golden.height()),
+  };
+  for ((a, g), d) in actual.pixels().zip(golden.pixels()).zip(diff.diff_image.pixels_mut()) {
+    let delta = perceptual_delta(a.0, g.0);
+    diff.max_delta = diff.max_delta.max(delta);
+    *d = match delta > tolerance.pixel_threshold {
+      true => {
+        diff.changed += 1;
+        image::Rgba([255, 0, 0, 255])
+      }
+      false => {
+        let gray = (yiq(g.0)[0] * 255.0 * 0.25 + 191.0) as u8;
+        image::Rgba([gray, gray, gray, 255])
+      }
+    };
+  }
+  Ok(diff)
+}
+
+pub fn golden_path(name: &str) -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{name}.png"))
+}
+
+fn diff_dir() -> PathBuf {
+  std::env::var_os(DIFF_DIR_VAR)
+    .map(PathBuf::from)
+    .unwrap_or(Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("visual_diffs"))
+}
+
+fn save(image: &RgbaImage, path: &Path) -> Result<(), String> {
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir).map_err(|e| format!("at making dir {}: {e}", dir.display()))?;
+  }
+  image.save(path).map_err(|e| format!("at saving {}: {e}", path.display()))
+}
+
+// Compares the frame against the named golden. Missing goldens are recorded from the frame, as
+// are all of them with UPDATE_GOLDENS_VAR set. Mismatches leave the frame and the diff in the
+// diff dir for looking at
+pub fn check_golden(name: &str, actual: &RgbaImage, tolerance: Tolerance) -> Result<(), String> {
+  let golden_path = golden_path(name);
+  if std::env::var_os(UPDATE_GOLDENS_VAR).is_some() || !golden_path.exists() {
+    save(actual, &golden_path)?;
+    eprintln!("recorded golden {}", golden_path.display());
+    return Ok(());
+  }
+  let golden = image::open(&golden_path)
+    .map_err(|e| format!("at reading golden {}: {e}", golden_path.display()))?
+    .to_rgba8();
+  let dir = diff_dir();
+  let actual_path = dir.join(format!("{name}_actual.png"));
+  let diff = match compare(actual, &golden, tolerance) {
+    Ok(diff) => diff,
+    Err(e) => {
+      save(actual, &actual_path)?;
+      return Err(format!("{name}: {e}, frame saved to {}", actual_path.display()));
+    }
+  };
+  if diff.within(tolerance) {
+    return Ok(());
+  }
+  let diff_path = dir.join(format!("{name}_diff.png"));
+  save(actual, &actual_path)?;
+  save(&diff.diff_image, &diff_path)?;
+  Err(format!(
+    "{name}: {} pixels changed, up to {:.3}, see {} and {}",
+    diff.changed,
+    diff.max_delta,
+    actual_path.display(),
+    diff_path.display()
+  ))
+}
+
+// Draws until a frame comes back through a CpuCopy frame export and returns it opaque. The scene
+// should leave out what animates with the render thread's clock, like water and foliage
+pub fn render_frame(render_mgr: &mut RenderManager) -> Result<RgbaImage, String> {
+  render_mgr
+    .process_messages(vec![RendererMessage::SetFrameExport(Some(FrameExportMode::CpuCopy))]);
+  for _ in 0..=render_mgr.render_cmd_buffers.len() {
+    render_mgr.draw()?;
+  }
+  let Some(ExportedFrame::Pixels { resolution, data, .. }) = render_mgr.frame_export.latest()
+  else {
+    return Err("no frame came back from the export".to_string());
+  };
+  let mut texels = data.to_vec();
+  texels.chunks_exact_mut(4).for_each(|texel| texel[3] = u8::MAX);
+  RgbaImage::from_raw(resolution.width, resolution.height, texels)
+    .ok_or("exported frame is smaller than its resolution".to_string())
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ash_ad_wrappers::{ash_queue_wrappers::AdCommandBuffer, ash_render_wrappers::AdFrameBuffer};
+use renderables::{color::Color, glam, sdf_font::SdfFontCPU, Camera3D};
+use renderers::depth_readback_renderers::{DepthPointsRenderer, MAX_DEPTH_POINTS};
+
+use crate::depth_readback::{depth_sample, query_texel, DepthQuery};
+use crate::handles::MeshHandle;
+use crate::ui_layer::UiShape;
+
+// How far in front of a label the scene has to be to hide it, so the surface it sits on doesn't
+pub const LABEL_OCCLUSION_BIAS: f32 = 0.25;
+// Gap between the icon and the text over it, in label heights
+const LABEL_ICON_GAP: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LabelAnchor {
+  Position(glam::Vec3),
+  // Follows the mesh's translation, the offset is in world space so it stays above it when turning
+  Mesh(MeshHandle, glam::Vec3),
+}
+
+// Drawn at the anchor, with the text over it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelIcon {
+  Dot,
+  Ring,
+  Square,
+}
+
+// Text and an icon drawn on the UI layer where a point in the world is, like object names while
+// debugging or markers for the player
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldLabel {
+  pub anchor: LabelAnchor,
+  pub text: String,
+  pub icon: Option<LabelIcon>,
+  pub color: Color,
+  // Pixel height of the text at reference_distance from the camera, closer is bigger and further
+  // smaller but never past min_size and max_size
+  pub size: f32,
+  pub reference_distance: f32,
+  pub min_size: f32,
+  pub max_size: f32,
+  // With the scene in front of the anchor the label is hidden, or drawn in this color
+  pub through_walls: Option<Color>,
+}
+
+impl WorldLabel {
+  pub fn new(anchor: LabelAnchor, text: impl Into<String>) -> Self {
+    Self {
+      anchor,
+      text: text.into(),
+      icon: None,
+      color: Color::WHITE,
+      size: 18.0,
+      reference_distance: 10.0,
+      min_size: 10.0,
+      max_size: 24.0,
+      through_walls: None,
+    }
+  }
+}
+
+// Where a label is drawn this frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProjectedLabel {
+  // In 0..1 with y going down, like DepthQuery
+  pub screen_pos: glam::Vec2,
+  // Distance along the camera's look direction, compared with the scene's
+  pub view_depth: f32,
+  pub size: f32,
+}
+
+pub fn label_size(label: &WorldLabel, distance: f32) -> f32 {
+  let size = label.size * label.reference_distance / distance.max(f32::EPSILON);
+  size.clamp(label.min_size, label.max_size.max(label.min_size))
+}
+
+// None for anchors behind the camera or off screen
+pub fn project_label(
+  camera: &Camera3D,
+  position: glam::Vec3,
+  label: &WorldLabel,
+) -> Option<ProjectedLabel> {
+  let clip = camera.view_proj_mat * position.extend(1.0);
+  if clip.w <= 0.0 {
+    return None;
+  }
+  let ndc = clip.truncate() / clip.w;
+  if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || ndc.z > 1.0 {
+    return None;
+  }
+  // Shaders flip y after the view projection, see Camera3D::picking_ray
+  let screen_pos = glam::vec2((ndc.x + 1.0) / 2.0, (1.0 - ndc.y) / 2.0);
+  let to_label = position - camera.pos.truncate();
+  let view_depth = to_label.dot(camera.look_dir.truncate().normalize_or_zero());
+  Some(ProjectedLabel { screen_pos, view_depth, size: label_size(label, to_label.length()) })
+}
+
+// depth is what the scene depth had under the label, with the camera it was drawn with
+pub fn is_label_occluded(camera: &Camera3D, projected: &ProjectedLabel, depth: f32) -> bool {
+  let query = DepthQuery { screen_pos: projected.screen_pos, radius: 0 };
+  let sample = depth_sample(query, camera, &[depth], 0);
+  sample.view_depth.is_some_and(|scene| scene + LABEL_OCCLUSION_BIAS < projected.view_depth)
+}
+
+// The icon centered on the anchor and the text centered over it, resolution being the UI layer's
+pub fn label_shapes(
+  label: &WorldLabel,
+  projected: &ProjectedLabel,
+  occluded: bool,
+  resolution: glam::Vec2,
+  font: &SdfFontCPU,
+) -> Vec<UiShape> {
+  let color = match (occluded, label.through_walls) {
+    (false, _) => label.color,
+    (true, Some(color)) => color,
+    (true, None) => return vec![],
+  };
+  let center = projected.screen_pos * resolution;
+  let size = projected.size;
+  let icon_radius = size * 0.3;
+  let mut shapes = vec![];
+  match label.icon {
+    Some(LabelIcon::Dot) => {
+      shapes.push(UiShape::Circle { center, radius: icon_radius, outline: 0.0, color })
+    }
+    Some(LabelIcon::Ring) => shapes.push(UiShape::Circle {
+      center,
+      radius: icon_radius,
+      outline: (size * 0.1).max(1.0),
+      color,
+    }),
+    Some(LabelIcon::Square) => shapes.push(UiShape::RoundedRect {
+      rect: glam::vec4(
+        center.x - icon_radius,
+        center.y - icon_radius,
+        2.0 * icon_radius,
+        2.0 * icon_radius,
+      ),
+      radius: 0.0,
+      outline: 0.0,
+      color,
+    }),
+    None => {}
+  }
+  if !label.text.is_empty() {
+    let extent = font.measure(&label.text, size);
+    let above = match label.icon {
+      Some(_) => icon_radius + LABEL_ICON_GAP * size,
+      None => 0.0,
+    };
+    let position = glam::vec2(center.x - extent.x / 2.0, center.y - above - extent.y);
+    shapes.push(UiShape::Text { position, text: label.text.clone(), size, color });
+  }
+  shapes
+}
+
+// What was recorded into a frame, generation being that of the labels it was for
+struct LabelReadback {
+  generation: u64,
+  camera: Camera3D,
+  // Label index and where it was, per texel read
+  points: Vec<(usize, ProjectedLabel)>,
+}
+
+// Labels set by the game, occlusion tested against the scene depth read back under each. Results
+// land a frame in flight later, labels draw as visible until their first one does
+pub struct WorldLabels {
+  renderer: DepthPointsRenderer,
+  labels: Vec<WorldLabel>,
+  // Bumped whenever labels are set at other anchors, so results for the old ones are dropped
+  generation: u64,
+  occluded: Vec<bool>,
+  // Per label, as of the last frame recorded
+  projected: Vec<Option<ProjectedLabel>>,
+  // Per frame in flight
+  in_flight: Vec<Option<LabelReadback>>,
+}
+
+impl WorldLabels {
+  pub fn new(renderer: DepthPointsRenderer, frames_in_flight: usize) -> Self {
+    Self {
+      renderer,
+      labels: vec![],
+      generation: 0,
+      occluded: vec![],
+      projected: vec![],
+      in_flight: (0..frames_in_flight).map(|_| None).collect(),
+    }
+  }
+
+  pub fn resize(&mut self, scene_frame_buffers: &[Arc<AdFrameBuffer>]) -> Result<(), String> {
+    self.renderer.resize_targets(scene_frame_buffers)
+  }
+
+  // Games may set the same labels every tick, with the anchors unchanged what's occluded is kept
+  pub fn set(&mut self, labels: Vec<WorldLabel>) {
+    let same_anchors = labels.len() == self.labels.len()
+      && labels.iter().zip(&self.labels).all(|(label, old)| label.anchor == old.anchor);
+    if !same_anchors {
+      self.generation += 1;
+      self.occluded = vec![false; labels.len()];
+      self.projected = vec![None; labels.len()];
+    }
+    self.labels = labels;
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.labels.is_empty()
+  }
+
+  // Call once frame_idx's fence has been waited on, before recording into it again
+  pub fn collect(&mut self, frame_idx: usize) -> Result<(), String> {
+    let Some(readback) = self.in_flight.get_mut(frame_idx).and_then(Option::take) else {
+      return Ok(());
+    };
+    if readback.generation != self.generation {
+      return Ok(());
+    }
+    let depths = self.renderer.read(frame_idx, readback.points.len())?;
+    for ((label_idx, projected), depth) in readback.points.iter().zip(depths) {
+      if let Some(occluded) = self.occluded.get_mut(*label_idx) {
+        *occluded = is_label_occluded(&readback.camera, projected, depth);
+      }
+    }
+    Ok(())
+  }
+
+  // After the scene depth is drawn into frame_buffer, camera being what it was drawn with.
+  // Projects the labels for shapes too
+  pub fn record(
+    &mut self,
+    cmd_buffer: &AdCommandBuffer,
+    frame_idx: usize,
+    frame_buffer: &AdFrameBuffer,
+    camera: Camera3D,
+    mesh_transforms: &HashMap<MeshHandle, glam::Mat4>,
+  ) -> Result<(), String> {
+    if self.labels.is_empty() {
+      return Ok(());
+    }
+    for (label, projected) in self.labels.iter().zip(self.projected.iter_mut()) {
+      let position = match label.anchor {
+        LabelAnchor::Position(position) => Some(position),
+        LabelAnchor::Mesh(mesh, offset) => {
+          mesh_transforms.get(&mesh).map(|transform| transform.w_axis.truncate() + offset)
+        }
+      };
+      *projected = position.and_then(|position| project_label(&camera, position, label));
+    }
+    let points = self
+      .projected
+      .iter()
+      .enumerate()
+      .filter_map(|(i, projected)| projected.map(|projected| (i, projected)))
+      .take(MAX_DEPTH_POINTS)
+      .collect::<Vec<_>>();
+    let texels = points
+      .iter()
+      .map(|(_, projected)| query_texel(projected.screen_pos, frame_buffer.resolution()))
+      .collect::<Vec<_>>();
+    self.renderer.record(cmd_buffer, frame_idx, frame_buffer, &texels)?;
+    if let Some(slot) = self.in_flight.get_mut(frame_idx) {
+      *slot = Some(LabelReadback { generation: self.generation, camera, points });
+    }
+    Ok(())
+  }
+
+  pub fn shapes(&self, resolution: glam::Vec2, font: &SdfFontCPU) -> Vec<UiShape> {
+    self
+      .labels
+      .iter()
+      .zip(self.projected.iter().zip(self.occluded.iter()))
+      .filter_map(|(label, (projected, occluded))| {
+        projected.map(|projected| label_shapes(label, &projected, *occluded, resolution, font))
+      })
+      .flatten()
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use renderables::sdf_font::SdfGlyph;
+
+  use super::*;
+  use crate::CAMERA_FOV;
+
+  // Only an a, 10 wide on a 20 high line at SDF_GLYPH_SIZE
+  fn font() -> SdfFontCPU {
+    let glyph = SdfGlyph {
+      uv: glam::Vec4::ZERO,
+      offset: glam::Vec2::ZERO,
+      size: glam::vec2(10.0, 20.0),
+      advance: 10.0,
+    };
+    SdfFontCPU {
+      width: 1,
+      height: 1,
+      texels: vec![0],
+      glyphs: HashMap::from([('a', glyph)]),
+      ascent: 16.0,
+      line_height: 20.0,
+      kerning: HashMap::new(),
+    }
+  }
+
+  fn label(text: &str, icon: Option<LabelIcon>) -> WorldLabel {
+    WorldLabel { icon, ..WorldLabel::new(LabelAnchor::Position(glam::Vec3::ZERO), text) }
+  }
+
+  fn projected(screen_pos: glam::Vec2, size: f32) -> ProjectedLabel {
+    ProjectedLabel { screen_pos, view_depth: 10.0, size }
+  }
+
+  #[test]
+  fn sizes_stay_within_the_limits() {
+    let mut label = label("a", None);
+    assert_eq!(label_size(&label, label.reference_distance), label.size);
+    assert_eq!(label_size(&label, 0.0), label.max_size);
+    assert_eq!(label_size(&label, f32::MAX), label.min_size);
+    // A max under the min leaves the min
+    label.max_size = 5.0;
+    assert_eq!(label_size(&label, 1.0), label.min_size);
+  }
+
+  #[test]
+  fn labels_are_sized_by_distance_with_their_depth_along_the_view() {
+    let camera = Camera3D::new(glam::Vec4::ZERO, glam::Vec4::NEG_Z, CAMERA_FOV);
+    let label = label("a", None);
+    let position = glam::vec3(3.0, -2.0, -10.0);
+    let projected = project_label(&camera, position, &label).unwrap();
+    // Right of and under the middle, y goes down the screen
+    assert!(projected.screen_pos.x > 0.5 && projected.screen_pos.y > 0.5);
+    assert!((projected.view_depth - 10.0).abs() < 1e-4);
+    assert!((projected.size - label_size(&label, position.length())).abs() < 1e-4);
+  }
+
+  #[test]
+  fn icons_center_on_the_anchor_with_the_text_over_them() {
+    let font = font();
+    let resolution = glam::vec2(400.0, 200.0);
+    let at = projected(glam::vec2(0.25, 0.5), 20.0);
+    let mut label = label("aa", Some(LabelIcon::Square));
+    let shapes = label_shapes(&label, &at, false, resolution, &font);
+    assert_eq!(shapes.len(), 2);
+    // 0.3 of the size out from the anchor at (100, 100) each way
+    let UiShape::RoundedRect { rect, .. } = shapes[0] else { panic!("square icon isn't a rect") };
+    assert!(rect.abs_diff_eq(glam::vec4(94.0, 94.0, 12.0, 12.0), 1e-4));
+    let UiShape::Text { position, .. } = shapes[1] else { panic!("label text isn't text") };
+    let extent = font.measure("aa", 20.0);
+    assert!((position.x + extent.x / 2.0 - 100.0).abs() < 1e-4);
+    // A quarter of the size between the top of the icon and the bottom of the text
+    assert!((position.y + extent.y - 89.0).abs() < 1e-4);
+
+    label.icon = Some(LabelIcon::Ring);
+    let shapes = label_shapes(&label, &at, false, resolution, &font);
+    let UiShape::Circle { center, radius, outline, .. } = shapes[0] else {
+      panic!("ring icon isn't a circle")
+    };
+    assert_eq!(center, glam::vec2(100.0, 100.0));
+    assert!((radius - 6.0).abs() < 1e-4 && (outline - 2.0).abs() < 1e-4);
+    // Small rings keep a pixel of outline
+    let small = label_shapes(&label, &projected(at.screen_pos, 5.0), false, resolution, &font);
+    assert!(matches!(small[0], UiShape::Circle { outline: 1.0, .. }));
+  }
+
+  #[test]
+  fn text_without_an_icon_sits_on_the_anchor() {
+    let font = font();
+    let resolution = glam::vec2(200.0, 100.0);
+    let at = projected(glam::vec2(0.5, 0.5), 20.0);
+    let shapes = label_shapes(&label("a", None), &at, false, resolution, &font);
+    assert_eq!(shapes.len(), 1);
+    let UiShape::Text { position, .. } = shapes[0] else { panic!("label text isn't text") };
+    let bottom_middle = position + font.measure("a", 20.0) * glam::vec2(0.5, 1.0);
+    assert!(bottom_middle.abs_diff_eq(glam::vec2(100.0, 50.0), 1e-4));
+    // Nothing to draw without text or an icon
+    assert!(label_shapes(&label("", None), &at, false, resolution, &font).is_empty());
+  }
+}
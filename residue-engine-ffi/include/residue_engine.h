@@ -0,0 +1,68 @@
+// C api of residue-engine-ffi. Strings are utf8 bytes and their length, no nul needed. Every call
+// returns a ResidueResult, residue_last_error has the message of the last one that failed
+#ifndef RESIDUE_ENGINE_H
+#define RESIDUE_ENGINE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum ResidueResult {
+  RESIDUE_OK = 0,
+  RESIDUE_NULL_ARGUMENT = 1,
+  RESIDUE_INVALID_UTF8 = 2,
+  RESIDUE_FAILED = 3,
+  // No frame finished yet, try again after the next update
+  RESIDUE_NO_FRAME = 4,
+  // Width and height are still written, for sizing the buffer
+  RESIDUE_BUFFER_TOO_SMALL = 5,
+  // Destroying the engine is all that's safe after this
+  RESIDUE_PANICKED = 6,
+} ResidueResult;
+
+typedef struct ResidueEngine ResidueEngine;
+
+// Message of the last call that failed on this thread, NULL when none has. Valid until the next
+// call that fails on it
+const char *residue_last_error(void);
+
+// Empty paths for ./engine.toml and ./scene.toml. One engine per process
+ResidueResult residue_engine_create(const uint8_t *config_path, size_t config_path_len,
+                                    const uint8_t *scene_path, size_t scene_path_len,
+                                    ResidueEngine **out_engine);
+ResidueResult residue_engine_destroy(ResidueEngine *engine);
+
+// Replaces the scene on the next update
+ResidueResult residue_engine_load_scene(ResidueEngine *engine, const uint8_t *path,
+                                        size_t path_len);
+
+// Runs the fixed ticks that fit in the seconds since the last update, out_ticks may be NULL
+ResidueResult residue_engine_update(ResidueEngine *engine, double elapsed_s, uint32_t *out_ticks);
+
+// Character keys by the text they type like "w", named ones by winit's name like "Space"
+ResidueResult residue_engine_key(ResidueEngine *engine, const uint8_t *key, size_t key_len,
+                                 bool pressed);
+// In pixels from the top left of the frame
+ResidueResult residue_engine_mouse_move(ResidueEngine *engine, double x, double y);
+// 0 for the left button, 1 for the right and 2 for the middle one
+ResidueResult residue_engine_mouse_button(ResidueEngine *engine, uint32_t button, bool pressed);
+ResidueResult residue_engine_resize(ResidueEngine *engine, uint32_t width, uint32_t height);
+
+// Copies the latest finished frame as sRGB rgba8, rows tightly packed and top row first. Out
+// pointers may be NULL
+ResidueResult residue_engine_read_frame(ResidueEngine *engine, uint8_t *pixels, size_t pixels_len,
+                                        uint32_t *out_width, uint32_t *out_height,
+                                        uint64_t *out_frame_number);
+
+// After a benchmark or a replay runs out, destroy the engine then
+ResidueResult residue_engine_is_finished(ResidueEngine *engine, bool *out_finished);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,275 @@
+// What pointers these take is in include/residue_engine.h, written for the hosts calling them
+#![allow(clippy::missing_safety_doc)]
+
+use std::{
+  cell::RefCell,
+  ffi::{c_char, CString},
+  panic::{catch_unwind, AssertUnwindSafe},
+  path::{Path, PathBuf},
+  ptr,
+  time::Duration,
+};
+
+use residue_engine::{
+  input::{key_from_name, MouseButton},
+  renderer::ExportedFrame,
+  EmbeddedEngine, EngineBuilder, EngineConfig, LaunchOptions, ENGINE_CONFIG_PATH,
+};
+
+// What every call returns, the message of the last one that failed on a thread is kept for
+// residue_last_error
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResidueResult {
+  Ok = 0,
+  NullArgument = 1,
+  InvalidUtf8 = 2,
+  Failed = 3,
+  // No frame finished yet, the host tries again after the next update
+  NoFrame = 4,
+  // Width and height are still written, for sizing the buffer
+  BufferTooSmall = 5,
+  // The engine is left as it was when it panicked, destroying it is all that's safe
+  Panicked = 6,
+}
+
+// Opaque to the host, owned by it from residue_engine_create until residue_engine_destroy
+pub struct ResidueEngine {
+  engine: EmbeddedEngine,
+}
+
+type CallError = (ResidueResult, String);
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+// Neither errors nor panics cross into the host, they become a result and the last error
+fn guarded(call: impl FnOnce() -> Result<(), CallError>) -> ResidueResult {
+  let (result, error) = match catch_unwind(AssertUnwindSafe(call)) {
+    Ok(Ok(())) => return ResidueResult::Ok,
+    Ok(Err(error)) => error,
+    Err(_) => (ResidueResult::Panicked, "engine panicked".to_string()),
+  };
+  // Nul bytes would cut the message short in C
+  let error = CString::new(error.replace('\0', " ")).unwrap_or_default();
+  LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(error));
+  result
+}
+
+fn failed(error: String) -> CallError {
+  (ResidueResult::Failed, error)
+}
+
+unsafe fn engine_arg<'a>(engine: *mut ResidueEngine) -> Result<&'a mut EmbeddedEngine, CallError> {
+  match engine.as_mut() {
+    Some(engine) => Ok(&mut engine.engine),
+    None => Err((ResidueResult::NullArgument, "engine is null".to_string())),
+  }
+}
+
+// Utf8 bytes and their length like the gameplay api, no nul needed
+unsafe fn str_arg<'a>(text: *const u8, len: usize, name: &str) -> Result<&'a str, CallError> {
+  if text.is_null() {
+    return Err((ResidueResult::NullArgument, format!("{name} is null")));
+  }
+  std::str::from_utf8(std::slice::from_raw_parts(text, len))
+    .map_err(|e| (ResidueResult::InvalidUtf8, format!("{name} isn't utf8: {e}")))
+}
+
+unsafe fn write_out<T>(out: *mut T, value: T) {
+  if let Some(out) = out.as_mut() {
+    *out = value;
+  }
+}
+
+// Message of the last call that failed on this thread, null when none has. Valid until the next
+// call that fails on it
+#[no_mangle]
+pub extern "C" fn residue_last_error() -> *const c_char {
+  LAST_ERROR.with(|last_error| last_error.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+// Reads the engine config at config_path, ENGINE_CONFIG_PATH with no bytes, and loads the scene at
+// scene_path through the mounted assets, ./scene.toml with no bytes. Frames are drawn offscreen at
+// the config's headless resolution. One engine per process
+#[no_mangle]
+pub unsafe extern "C" fn residue_engine_create(
+  config_path: *const u8,
+  config_path_len: usize,
+  scene_path: *const u8,
+  scene_path_len: usize,
+  out_engine: *mut *mut ResidueEngine,
+) -> ResidueResult {
+  guarded(|| {
+    let out_engine =
+      out_engine.as_mut().ok_or((ResidueResult::NullArgument, "out_engine is null".to_string()))?;
+    *out_engine = ptr::null_mut();
+    let config_path = match config_path_len {
+      0 => ENGINE_CONFIG_PATH,
+      _ => str_arg(config_path, config_path_len, "config_path")?,
+    };
+    let scene_path = match scene_path_len {
+      0 => None,
+      _ => Some(PathBuf::from(str_arg(scene_path, scene_path_len, "scene_path")?)),
+    };
+    let config = EngineConfig::load(Path::new(config_path)).map_err(failed)?;
+    let launch = LaunchOptions { scene_path, ..Default::default() };
+    let engine = EngineBuilder::new(config).with_launch_options(launch).embed().map_err(failed)?;
+    *out_engine = Box::into_raw(Box::new(ResidueEngine { engine }));
+    Ok(())
+  })
+}
+
+// Waits for the render thread and the gpu, the engine is freed even when that fails
+#[no_mangle]
+pub unsafe extern "C" fn residue_engine_destroy(engine: *mut ResidueEngine) -> ResidueResult {
+  guarded(|| {
+    if engine.is_null() {
+      return Err((ResidueResult::NullArgument, "engine is null".to_string()));
+    }
+    Box::from_raw(engine).engine.shutdown().map_err(failed)
+  })
+}
+
+// Replaces the scene on the next update, a scene that doesn't load fails here and the current one
+// stays
+#[no_mangle]
+pub unsafe extern "C" fn residue_engine_load_scene(
+  engine: *mut ResidueEngine,
+  path: *const u8,
+  path_len: usize,
+) -> ResidueResult {
+  guarded(|| engine_arg(engine)?.load_scene(str_arg(path, path_len, "path")?).map_err(failed))
+}
+
+// Runs the fixed ticks that fit in the seconds since the last update, out_ticks gets how many ran.
+// It may be null
+#[no_mangle]
+pub unsafe extern "C" fn residue_engine_update(
+  engine: *mut ResidueEngine,
+  elapsed_s: f64,
+  out_ticks: *mut u32,
+) -> ResidueResult {
+  guarded(|| {
+    let engine = engine_arg(engine)?;
+    let elapsed = Duration::try_from_secs_f64(elapsed_s)
+      .map_err(|e| failed(format!("at reading elapsed seconds {elapsed_s}: {e}")))?;
+    let ticks = engine.update(elapsed).map_err(failed)?;
+    write_out(out_ticks, ticks);
+    Ok(())
+  })
+}
+
+// Character keys by the text they type without modifiers like "w", named ones by winit's name
+// like "Space"
+#[no_mangle]
+pub unsafe extern "C" fn residue_engine_key(
+  engine: *mut ResidueEngine,
+  key: *const u8,
+  key_len: usize,
+  pressed: bool,
+) -> ResidueResult {
+  guarded(|| {
+    let key = key_from_name(str_arg(key, key_len, "key")?);
+    let inputs = engine_arg(engine)?.inputs_mut();
+    match pressed {
+      true => inputs.update_key_pressed(key),
+      false => inputs.update_key_released(key),
+    }
+    Ok(())
+  })
+}
+
+// In pixels from the top left of the frame
+#[no_mangle]
+pub unsafe extern "C" fn residue_engine_mouse_move(
+  engine: *mut ResidueEngine,
+  x: f64,
+  y: f64,
+) -> ResidueResult {
+  guarded(|| {
+    engine_arg(engine)?.inputs_mut().update_cursor_pos(x, y);
+    Ok(())
+  })
+}
+
+// 0 for the left button, 1 for the right and 2 for the middle one
+#[no_mangle]
+pub unsafe extern "C" fn residue_engine_mouse_button(
+  engine: *mut ResidueEngine,
+  button: u32,
+  pressed: bool,
+) -> ResidueResult {
+  guarded(|| {
+    let button = match button {
+      0 => MouseButton::Left,
+      1 => MouseButton::Right,
+      2 => MouseButton::Middle,
+      _ => return Err(failed(format!("no mouse button {button}"))),
+    };
+    let inputs = engine_arg(engine)?.inputs_mut();
+    match pressed {
+      true => inputs.update_mouse_pressed(button),
+      false => inputs.update_mouse_released(button),
+    }
+    Ok(())
+  })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn residue_engine_resize(
+  engine: *mut ResidueEngine,
+  width: u32,
+  height: u32,
+) -> ResidueResult {
+  guarded(|| {
+    if width == 0 || height == 0 {
+      return Err(failed(format!("can't draw at {width}x{height}")));
+    }
+    engine_arg(engine)?.resize(width, height);
+    Ok(())
+  })
+}
+
+// Copies the latest finished frame into pixels as sRGB rgba8, rows tightly packed and top row
+// first. The frame's width and height are written whenever there is one, any out pointer may be
+// null
+#[no_mangle]
+pub unsafe extern "C" fn residue_engine_read_frame(
+  engine: *mut ResidueEngine,
+  pixels: *mut u8,
+  pixels_len: usize,
+  out_width: *mut u32,
+  out_height: *mut u32,
+  out_frame_number: *mut u64,
+) -> ResidueResult {
+  guarded(|| {
+    let frame = engine_arg(engine)?.frame().map_err(failed)?;
+    let Some(ExportedFrame::Pixels { resolution, data, frame_number }) = frame else {
+      return Err((ResidueResult::NoFrame, "no frame finished yet".to_string()));
+    };
+    write_out(out_width, resolution.width);
+    write_out(out_height, resolution.height);
+    if pixels.is_null() || pixels_len < data.len() {
+      let error = format!("frame needs {} bytes, got {pixels_len}", data.len());
+      return Err((ResidueResult::BufferTooSmall, error));
+    }
+    std::slice::from_raw_parts_mut(pixels, data.len()).copy_from_slice(&data);
+    write_out(out_frame_number, frame_number);
+    Ok(())
+  })
+}
+
+// Like after a benchmark or a replay running out, the host should destroy it then
+#[no_mangle]
+pub unsafe extern "C" fn residue_engine_is_finished(
+  engine: *mut ResidueEngine,
+  out_finished: *mut bool,
+) -> ResidueResult {
+  guarded(|| {
+    let finished = engine_arg(engine)?.is_finished();
+    write_out(out_finished, finished);
+    Ok(())
+  })
+}
@@ -0,0 +1,319 @@
+use crash_report::log;
+use engine_config::EngineConfig;
+use event_bus::{
+  FileDropped, FocusGained, FocusLost, KeyAction, KeyActionState, WindowResized, WindowScaleChanged,
+};
+#[cfg(feature = "gamepad")]
+use event_bus::{GamepadConnected, GamepadDisconnected};
+use game_logic::{LaunchOptions, Scheduler};
+use gameplay_api::LinkedGameplay;
+use input_aggregator::InputAggregator;
+use render_manager::AdAshInstance;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+use winit::application::ApplicationHandler;
+use winit::dpi::PhysicalSize;
+use winit::event::WindowEvent;
+use winit::event_loop::{ActiveEventLoop, ControlFlow};
+use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
+#[cfg(target_os = "windows")]
+use winit::platform::windows::WindowAttributesExtWindows;
+use winit::window;
+use winit::window::{Fullscreen, WindowAttributes, WindowId};
+
+use crate::builder::WindowDesc;
+use crate::engine::Engine;
+
+// Runs the engine in a winit event loop, made by EngineBuilder. Owns the window and engine from
+// the loop resuming until it exits, and feeds window events to the input and the event bus
+pub struct EngineApp {
+  engine: Option<Engine>,
+  // Filled in here from window events, read and cleared by the simulation each tick
+  input_aggregator: Arc<Mutex<InputAggregator>>,
+  ash_instance: Arc<AdAshInstance>,
+  config: EngineConfig,
+  launch: LaunchOptions,
+  // Handed to the game once the window is up
+  gameplay: Option<LinkedGameplay>,
+  scheduler: Option<Scheduler>,
+  window_desc: WindowDesc,
+  ime_allowed: bool,
+  // Started with the engine, None before that or when the platform's gamepad support failed
+  #[cfg(feature = "gamepad")]
+  gamepads: Option<gilrs::Gilrs>,
+  // Why the loop exited early, if it did
+  error: Option<String>,
+}
+
+impl EngineApp {
+  // Sets up the globals everything else uses, so only once per process
+  pub(crate) fn new(
+    config: EngineConfig,
+    launch: LaunchOptions,
+    gameplay: Option<LinkedGameplay>,
+    scheduler: Scheduler,
+    window_desc: WindowDesc,
+  ) -> Result<Self, String> {
+    Engine::init_globals(&config)?;
+    let ash_instance = Arc::new(AdAshInstance::new(config.renderer.validation)?);
+    Ok(Self {
+      ash_instance,
+      config,
+      launch,
+      gameplay,
+      scheduler: Some(scheduler),
+      window_desc,
+      input_aggregator: Arc::new(Mutex::new(InputAggregator::new())),
+      engine: None,
+      ime_allowed: false,
+      #[cfg(feature = "gamepad")]
+      gamepads: None,
+      error: None,
+    })
+  }
+
+  // Once the loop has exited, Err when it was because the engine failed
+  pub fn finish(self) -> Result<(), String> {
+    self.error.map_or(Ok(()), Err)
+  }
+
+  fn fail(&mut self, event_loop: &ActiveEventLoop, error: String) {
+    log!("{error}");
+    self.error = Some(error);
+    event_loop.exit();
+  }
+
+  fn create_window(&self, event_loop: &ActiveEventLoop) -> Result<window::Window, String> {
+    let icon_image = self.window_desc.icon.and_then(|icon| image::load_from_memory(icon).ok());
+    let icon = icon_image.and_then(|icon_image| {
+      let icon_res = (icon_image.width(), icon_image.height());
+      window::Icon::from_rgba(icon_image.into_bytes(), icon_res.0, icon_res.1).ok()
+    });
+    let mut window_attributes = WindowAttributes::default()
+      .with_window_icon(icon.clone())
+      .with_title(self.window_desc.title.clone());
+    // Only windows has a taskbar icon separate from the window's
+    #[cfg(target_os = "windows")]
+    {
+      window_attributes = window_attributes.with_taskbar_icon(icon);
+    }
+    let window_config = &self.config.window;
+    if window_config.fullscreen {
+      window_attributes = window_attributes.with_fullscreen(Some(Fullscreen::Borderless(None)));
+    } else if window_config.width > 0 {
+      window_attributes = window_attributes
+        .with_inner_size(PhysicalSize::new(window_config.width, window_config.height));
+    }
+    event_loop.create_window(window_attributes).map_err(|e| format!("error creating window: {e}"))
+  }
+
+  fn inputs(&self) -> MutexGuard<'_, InputAggregator> {
+    match self.input_aggregator.lock() {
+      Ok(inputs) => inputs,
+      Err(e) => e.into_inner(),
+    }
+  }
+
+  // For events the game reacts to, so their latency to the screen can be measured
+  fn inputs_at(&self, received_at: Instant) -> MutexGuard<'_, InputAggregator> {
+    let mut inputs = self.inputs();
+    inputs.record_input(received_at);
+    inputs
+  }
+
+  // The IME only gets keys while something has text focus, so it doesn't eat game controls
+  // After the engine starts so its subscribers hear about the gamepads already plugged in
+  #[cfg(feature = "gamepad")]
+  fn start_gamepads(&mut self) {
+    let gamepads = match gilrs::Gilrs::new() {
+      Ok(gamepads) => gamepads,
+      Err(e) => return log!("at starting gamepad support: {e}"),
+    };
+    for (id, gamepad) in gamepads.gamepads() {
+      let name = gamepad.name().to_string();
+      event_bus::global().publish(GamepadConnected { id: id.into(), name });
+    }
+    self.gamepads = Some(gamepads);
+  }
+
+  // Only connections for now, gamepad buttons and sticks don't reach the input aggregator
+  #[cfg(feature = "gamepad")]
+  fn poll_gamepads(&mut self) {
+    let Some(gamepads) = self.gamepads.as_mut() else { return };
+    while let Some(gilrs::Event { id, event, .. }) = gamepads.next_event() {
+      match event {
+        gilrs::EventType::Connected => {
+          let name = gamepads.gamepad(id).name().to_string();
+          event_bus::global().publish(GamepadConnected { id: id.into(), name });
+        }
+        gilrs::EventType::Disconnected => {
+          event_bus::global().publish(GamepadDisconnected { id: id.into() });
+        }
+        _ => {}
+      }
+    }
+  }
+
+  fn sync_ime_allowed(&mut self) {
+    let text_input_active = self.inputs().is_text_input_active();
+    let engine = self.engine.as_ref().filter(|_| self.ime_allowed != text_input_active);
+    if let Some(window) = engine.and_then(|engine| engine.window()) {
+      window.set_ime_allowed(text_input_active);
+      self.ime_allowed = text_input_active;
+    }
+  }
+}
+
+impl ApplicationHandler for EngineApp {
+  fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+    if self.engine.is_none() {
+      let window = match self.window_desc.headless {
+        true => None,
+        false => match self.create_window(event_loop) {
+          Ok(window) => Some(window),
+          Err(e) => return self.fail(event_loop, e),
+        },
+      };
+      let (width, height) = match &window {
+        Some(window) => (window.inner_size().width, window.inner_size().height),
+        None => Engine::headless_resolution(&self.config),
+      };
+      self.inputs().update_window_size(width, height);
+      let engine = match Engine::start(
+        window,
+        self.ash_instance.clone(),
+        self.input_aggregator.clone(),
+        &self.config,
+        &self.launch,
+        self.gameplay.take(),
+        self.scheduler.take(),
+      ) {
+        Ok(x) => x,
+        Err(e) => return self.fail(event_loop, format!("error starting engine: {e}")),
+      };
+      // After the start so the renderer is subscribed to it
+      if let Some(window) = engine.window() {
+        event_bus::global().publish(WindowScaleChanged { scale_factor: window.scale_factor() });
+      }
+      self.engine = Some(engine);
+      #[cfg(feature = "gamepad")]
+      self.start_gamepads();
+    }
+  }
+
+  fn window_event(
+    &mut self,
+    event_loop: &ActiveEventLoop,
+    _window_id: WindowId,
+    event: WindowEvent,
+  ) {
+    // println!("event: {event:?}");
+    // Taken before the input lock, a tick may be holding it
+    let received_at = Instant::now();
+    match event {
+      WindowEvent::ActivationTokenDone { .. } => {}
+      WindowEvent::Resized(size) => {
+        self.inputs().update_window_size(size.width, size.height);
+        event_bus::global().publish(WindowResized { width: size.width, height: size.height });
+      }
+      WindowEvent::Moved(_) => {}
+      WindowEvent::CloseRequested => {
+        // #[cfg(target_os = "macos")]
+        // let _ = self.window.take();
+        event_loop.exit();
+      }
+      WindowEvent::Destroyed => {}
+      WindowEvent::DroppedFile(path) => {
+        event_bus::global().publish(FileDropped { path });
+      }
+      WindowEvent::HoveredFile(_) => {}
+      WindowEvent::HoveredFileCancelled => {}
+      WindowEvent::Focused(true) => {
+        event_bus::global().publish(FocusGained);
+      }
+      WindowEvent::Focused(false) => {
+        event_bus::global().publish(FocusLost);
+      }
+      WindowEvent::KeyboardInput { event, .. } => {
+        let state = match event.state {
+          winit::event::ElementState::Pressed => {
+            let text = event.text.as_ref().map(|text| text.as_str());
+            let mut inputs = self.inputs_at(received_at);
+            if !inputs.update_text_key(&event.logical_key, text) {
+              inputs.update_key_pressed(event.key_without_modifiers());
+            }
+            KeyActionState::Pressed
+          }
+          winit::event::ElementState::Released => {
+            self.inputs_at(received_at).update_key_released(event.key_without_modifiers());
+            KeyActionState::Released
+          }
+        };
+        event_bus::global().publish(KeyAction {
+          key: event.key_without_modifiers(),
+          state,
+          repeat: event.repeat,
+        });
+      }
+      WindowEvent::ModifiersChanged(modifiers) => {
+        self.inputs().update_modifiers(modifiers.state());
+      }
+      WindowEvent::Ime(ime) => {
+        self.inputs().update_ime(ime);
+      }
+      WindowEvent::CursorMoved { position, .. } => {
+        self.inputs_at(received_at).update_cursor_pos(position.x, position.y);
+      }
+      WindowEvent::CursorEntered { .. } => {}
+      WindowEvent::CursorLeft { .. } => {
+        self.inputs().update_cursor_left();
+      }
+      WindowEvent::MouseWheel { .. } => {}
+      WindowEvent::MouseInput { state, button, .. } => match state {
+        winit::event::ElementState::Pressed => {
+          self.inputs_at(received_at).update_mouse_pressed(button);
+        }
+        winit::event::ElementState::Released => {
+          self.inputs_at(received_at).update_mouse_released(button);
+        }
+      },
+      WindowEvent::PinchGesture { .. } => {}
+      WindowEvent::PanGesture { .. } => {}
+      WindowEvent::DoubleTapGesture { .. } => {}
+      WindowEvent::RotationGesture { .. } => {}
+      WindowEvent::TouchpadPressure { .. } => {}
+      WindowEvent::AxisMotion { .. } => {}
+      WindowEvent::Touch(_) => {}
+      WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+        event_bus::global().publish(WindowScaleChanged { scale_factor });
+      }
+      WindowEvent::ThemeChanged(_) => {}
+      WindowEvent::Occluded(_) => {}
+      WindowEvent::RedrawRequested => {}
+    }
+  }
+
+  fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+    if self.engine.as_ref().is_some_and(|engine| engine.is_finished()) {
+      event_loop.exit();
+      return;
+    }
+    if self.engine.as_ref().is_some_and(|engine| !engine.is_running()) {
+      return self.fail(event_loop, "simulation stopped unexpectedly".to_string());
+    }
+    self.sync_ime_allowed();
+    #[cfg(feature = "gamepad")]
+    self.poll_gamepads();
+    // Wakes up once a tick to pick up text input changes from the simulation
+    event_loop.set_control_flow(ControlFlow::wait_duration(self.config.simulation.tick_duration()));
+  }
+
+  fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+    if let Some(engine) = self.engine.take() {
+      if let Err(e) = engine.shutdown() {
+        log!("at shutting down engine: {e}");
+        self.error.get_or_insert(format!("at shutting down engine: {e}"));
+      }
+    }
+  }
+}
@@ -0,0 +1,129 @@
+use engine_config::{EngineConfig, PhysicsConfig, RendererConfig};
+#[cfg(feature = "physics")]
+use game_logic::ReproBundle;
+use game_logic::{LaunchOptions, Scheduler};
+use gameplay_api::{Gameplay, LinkedGameplay};
+use winit::event_loop::EventLoop;
+
+use crate::app::EngineApp;
+use crate::embedded::EmbeddedEngine;
+
+// How the window looks. Its size and fullscreen come from the config's window section
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowDesc {
+  pub title: String,
+  // Encoded image, like the bytes of a .ico or .png, for the title bar and taskbar
+  pub icon: Option<&'static [u8]>,
+  // No window at all, frames are drawn offscreen the size of the config's window section. For
+  // machines nobody is watching, the run ends when the game finishes
+  pub headless: bool,
+}
+
+impl Default for WindowDesc {
+  fn default() -> Self {
+    Self { title: "Residue Engine".to_string(), icon: None, headless: false }
+  }
+}
+
+// Everything the engine needs before it can open a window, like
+// EngineBuilder::new(config).with_window(desc).with_game(MyGame::default()).run()
+#[derive(Default)]
+pub struct EngineBuilder {
+  config: EngineConfig,
+  window: WindowDesc,
+  launch: LaunchOptions,
+  gameplay: Option<LinkedGameplay>,
+  scheduler: Scheduler,
+}
+
+impl EngineBuilder {
+  pub fn new(config: EngineConfig) -> Self {
+    Self { config, ..Default::default() }
+  }
+
+  pub fn with_window(mut self, window: WindowDesc) -> Self {
+    self.window = window;
+    self
+  }
+
+  pub fn with_renderer(mut self, renderer: RendererConfig) -> Self {
+    self.config.renderer = renderer;
+    self
+  }
+
+  pub fn with_physics(mut self, physics: PhysicsConfig) -> Self {
+    self.config.physics = physics;
+    self
+  }
+
+  // The game's own code, ticked by the simulation while playing like a gameplay library is
+  pub fn with_game<G: Gameplay + Send + 'static>(mut self, game: G) -> Self {
+    self.gameplay = Some(LinkedGameplay::new(game));
+    self
+  }
+
+  // A stage of the tick that runs after the stages in after and before the ones in before. The
+  // engine's are input, gameplay, physics, animation and render, in that order
+  pub fn with_stage(mut self, name: &str, after: &[&str], before: &[&str]) -> Result<Self, String> {
+    self.scheduler.add_stage(name, after, before)?;
+    Ok(self)
+  }
+
+  // The game's own code ticked in a stage, after what's already in it
+  pub fn with_system<G: Gameplay + Send + 'static>(
+    mut self,
+    stage: &str,
+    name: &str,
+    system: G,
+  ) -> Result<Self, String> {
+    self.scheduler.add_system(stage, name, system)?;
+    Ok(self)
+  }
+
+  // Which scene, replay or gameplay library to run
+  pub fn with_launch_options(mut self, launch: LaunchOptions) -> Self {
+    self.launch = launch;
+    self
+  }
+
+  // For running in an event loop made elsewhere. Sets up the job pool, assets and strings for the
+  // process, so only one engine can be built
+  pub fn build(self) -> Result<EngineApp, String> {
+    let config = repro_config(self.config, &self.launch)?;
+    config.validate()?;
+    EngineApp::new(config, self.launch, self.gameplay, self.scheduler, self.window)
+  }
+
+  // For hosts ticking the engine themselves instead of a window's event loop, drawn offscreen the
+  // size of the config's window section. Sets up the same globals as build
+  pub fn embed(self) -> Result<EmbeddedEngine, String> {
+    let config = repro_config(self.config, &self.launch)?;
+    config.validate()?;
+    EmbeddedEngine::start(&config, &self.launch, self.gameplay, self.scheduler)
+  }
+
+  // Opens the window and runs until it's closed or the game finishes
+  pub fn run(self) -> Result<(), String> {
+    let mut app = self.build().map_err(|e| format!("at building engine: {e}"))?;
+    let event_loop = EventLoop::new().map_err(|e| format!("at creating event loop: {e}"))?;
+    event_loop.run_app(&mut app).map_err(|e| format!("at running event loop: {e}"))?;
+    app.finish()
+  }
+}
+
+// Ticks and physics steps as long as where a repro bundle was saved, or its input plays out
+// differently
+#[cfg(feature = "physics")]
+fn repro_config(mut config: EngineConfig, launch: &LaunchOptions) -> Result<EngineConfig, String> {
+  if let Some(repro_path) = &launch.repro_path {
+    let bundle = ReproBundle::load(repro_path)?;
+    config.physics = bundle.config.physics;
+    config.simulation = bundle.config.simulation;
+  }
+  Ok(config)
+}
+
+#[cfg(not(feature = "physics"))]
+fn repro_config(config: EngineConfig, _: &LaunchOptions) -> Result<EngineConfig, String> {
+  Ok(config)
+}
@@ -0,0 +1,103 @@
+use engine_config::EngineConfig;
+use event_bus::WindowResized;
+use game_logic::{Game, LaunchOptions, Scheduler};
+use gameplay_api::LinkedGameplay;
+use input_aggregator::InputAggregator;
+use render_manager::{ExportedFrame, FrameExportMode, OffscreenWindow, RenderTarget};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::engine::Engine;
+
+// The engine ticked by a host instead of a winit event loop, for embedding it in tools and
+// launchers through the C api. Draws offscreen on the render thread as usual, the host takes the
+// finished frames as pixels. Made by EngineBuilder::embed
+pub struct EmbeddedEngine {
+  game: Game,
+  window: Arc<OffscreenWindow>,
+  // Filled in by the host between updates, read and cleared each tick
+  inputs: InputAggregator,
+  tick_duration: Duration,
+  max_ticks_per_update: u32,
+  // Time the host advanced by that didn't make up a whole tick yet
+  pending_time: Duration,
+}
+
+impl EmbeddedEngine {
+  pub(crate) fn start(
+    config: &EngineConfig,
+    launch: &LaunchOptions,
+    gameplay: Option<LinkedGameplay>,
+    scheduler: Scheduler,
+  ) -> Result<Self, String> {
+    Engine::init_globals(config)?;
+    let (width, height) = Engine::headless_resolution(config);
+    let window = OffscreenWindow::new(width, height);
+    let mut game = Game::new(RenderTarget::Offscreen(window.clone()), config, launch)
+      .map_err(|e| format!("at creating game: {e}"))?;
+    if let Some(gameplay) = gameplay {
+      game.set_gameplay(gameplay);
+    }
+    game.set_scheduler(scheduler);
+    game.set_frame_export(Some(FrameExportMode::CpuCopy))?;
+    let mut inputs = InputAggregator::new();
+    inputs.update_window_size(width, height);
+    Ok(Self {
+      game,
+      window,
+      inputs,
+      tick_duration: config.simulation.tick_duration(),
+      max_ticks_per_update: config.simulation.max_ticks_per_update,
+      pending_time: Duration::ZERO,
+    })
+  }
+
+  pub fn inputs_mut(&mut self) -> &mut InputAggregator {
+    self.inputs.record_input(Instant::now());
+    &mut self.inputs
+  }
+
+  // Replaces the scene on the next tick, see Game::load_scene
+  pub fn load_scene(&mut self, path: &str) -> Result<(), String> {
+    self.game.load_scene(path)
+  }
+
+  // Runs the fixed ticks that fit in the time the host advanced by, returns how many ran. Past
+  // the config's max ticks per update the rest of the time is dropped like the simulation does
+  pub fn update(&mut self, elapsed: Duration) -> Result<u32, String> {
+    self.pending_time += elapsed;
+    let mut ticks = 0;
+    while self.pending_time >= self.tick_duration && ticks < self.max_ticks_per_update {
+      let result = self.game.update(&mut self.inputs, self.tick_duration);
+      self.inputs.clear_key_states();
+      self.pending_time -= self.tick_duration;
+      ticks += 1;
+      result?;
+    }
+    if self.pending_time >= self.tick_duration {
+      self.pending_time = Duration::ZERO;
+    }
+    Ok(ticks)
+  }
+
+  // Frames drawn after this are the new size, the ones in flight are still the old one
+  pub fn resize(&mut self, width: u32, height: u32) {
+    self.window.resize(width, height);
+    self.inputs.update_window_size(width, height);
+    event_bus::global().publish(WindowResized { width, height });
+  }
+
+  // Latest frame the render thread finished, a couple of frames behind the last tick
+  pub fn frame(&self) -> Result<Option<ExportedFrame>, String> {
+    self.game.exported_frame()
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.game.is_finished()
+  }
+
+  // Waits for the render thread to finish its queue and the gpu to go idle
+  pub fn shutdown(self) -> Result<(), String> {
+    self.game.shutdown()
+  }
+}
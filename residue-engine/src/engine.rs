@@ -0,0 +1,138 @@
+use asset_cache::AssetCache;
+use engine_config::EngineConfig;
+use game_logic::{Game, LaunchOptions, Scheduler, Simulation};
+use gameplay_api::LinkedGameplay;
+use input_aggregator::InputAggregator;
+use localization::Localization;
+use render_manager::{AdAshInstance, AdSurface, AdSurfaceInstance, OffscreenWindow, RenderTarget};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use winit::window::Window;
+
+// Offscreen size when the config leaves the window size to the os
+const HEADLESS_RESOLUTION: (u32, u32) = (1280, 720);
+
+// Where frames go. The surface goes away before the window it was made from
+enum Output {
+  Window { surface: Arc<AdSurface>, window: Box<Window> },
+  Offscreen,
+}
+
+// Everything running for a window, or for offscreen images when headless. Fields are in the order
+// they go away if dropped, shutdown does the same explicitly and reports errors instead of leaving
+// it to drop order
+pub struct Engine {
+  simulation: Simulation,
+  output: Output,
+}
+
+impl Engine {
+  // Draws to the window, or offscreen the size of the config's window section without one
+  pub fn start(
+    window: Option<Window>,
+    ash_instance: Arc<AdAshInstance>,
+    input_aggregator: Arc<Mutex<InputAggregator>>,
+    config: &EngineConfig,
+    launch: &LaunchOptions,
+    gameplay: Option<LinkedGameplay>,
+    scheduler: Option<Scheduler>,
+  ) -> Result<Self, String> {
+    let (target, output) = match window {
+      Some(window) => {
+        let surface_instance = Arc::new(AdSurfaceInstance::new(ash_instance));
+        let surface = Arc::new(
+          AdSurface::new(surface_instance, &window)
+            .map_err(|e| format!("at creating surface: {e}"))?,
+        );
+        (
+          RenderTarget::Window(surface.clone()),
+          Output::Window { surface, window: Box::new(window) },
+        )
+      }
+      None => {
+        let (width, height) = Self::headless_resolution(config);
+        (RenderTarget::Offscreen(OffscreenWindow::new(width, height)), Output::Offscreen)
+      }
+    };
+    let mut game =
+      Game::new(target, config, launch).map_err(|e| format!("at creating game: {e}"))?;
+    if let Some(gameplay) = gameplay {
+      game.set_gameplay(gameplay);
+    }
+    if let Some(scheduler) = scheduler {
+      game.set_scheduler(scheduler);
+    }
+    // The renderer is up and has the scene uploads queued, drawing starts with the first tick
+    let simulation = Simulation::start(game, input_aggregator, &config.simulation)
+      .map_err(|e| format!("at starting simulation: {e}"))?;
+    Ok(Self { simulation, output })
+  }
+
+  // Budgets, the job pool, assets and strings everything else uses, so only once per process
+  pub(crate) fn init_globals(config: &EngineConfig) -> Result<(), String> {
+    for (scope, ms) in config.budgets.scope_budgets() {
+      profiler::set_budget(&scope, Some(Duration::from_secs_f32(ms / 1000.0)));
+    }
+    let warning_interval = Duration::from_secs_f32(config.budgets.warning_interval_s);
+    profiler::set_budget_warning_interval(warning_interval);
+    // The global pool has to be sized before anything touches it
+    if config.jobs.worker_threads > 0 {
+      jobs::init_global(config.jobs.worker_threads)?;
+    }
+    let vfs = vfs::Vfs::new();
+    for mount in config.assets.mounts.iter() {
+      vfs
+        .mount(&mount.mount_point, Path::new(&mount.path))
+        .map_err(|e| format!("at mounting assets at \"{}\": {e}", mount.mount_point))?;
+    }
+    let vfs = vfs::init_global(vfs)?;
+    if !config.assets.cache_dir.is_empty() {
+      asset_cache::init_global(AssetCache::new(Path::new(&config.assets.cache_dir))?)?;
+    }
+    let localization = &config.localization;
+    localization::init_global(Localization::load(
+      vfs,
+      &localization.strings_dir,
+      &localization.language,
+      &localization.fallback_language,
+    )?)?;
+    Ok(())
+  }
+
+  pub fn headless_resolution(config: &EngineConfig) -> (u32, u32) {
+    match config.window.width > 0 && config.window.height > 0 {
+      true => (config.window.width, config.window.height),
+      false => HEADLESS_RESOLUTION,
+    }
+  }
+
+  // None when headless
+  pub fn window(&self) -> Option<&Window> {
+    match &self.output {
+      Output::Window { window, .. } => Some(window),
+      Output::Offscreen => None,
+    }
+  }
+
+  pub fn is_running(&self) -> bool {
+    self.simulation.is_running()
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.simulation.is_finished()
+  }
+
+  // The simulation stops first so nothing queues more renderer work, the game destroys what it
+  // made and the renderer finishes its queue and waits for the gpu. Only then do the surface and
+  // window it draws to go away
+  pub fn shutdown(self) -> Result<(), String> {
+    let Self { simulation, output } = self;
+    let game_shutdown = simulation.stop().and_then(|game| game.shutdown());
+    if let Output::Window { surface, window } = output {
+      drop(surface);
+      drop(window);
+    }
+    game_shutdown
+  }
+}
@@ -0,0 +1,114 @@
+// The engine as one dependency. Games should only need what's re-exported here, the crates behind
+// it are free to move things around and rename them between releases as long as this stays put
+
+#[cfg(feature = "render")]
+pub use app::EngineApp;
+#[cfg(feature = "render")]
+pub use builder::{EngineBuilder, WindowDesc};
+#[cfg(feature = "render")]
+pub use embedded::EmbeddedEngine;
+#[cfg(feature = "render")]
+pub use engine::Engine;
+pub use engine_config::{EngineConfig, EngineConfigBuilder, ENGINE_CONFIG_PATH};
+pub use engine_config::{
+  FovAxis, PhysicsConfig, QualityPreset, QualitySettings, RendererConfig, SimulationConfig,
+  ViewConfig, WindowConfig,
+};
+#[cfg(feature = "render")]
+pub use game_logic::{
+  BenchmarkSettings, Game, GameMode, LaunchOptions, LeakCheckSettings, Simulation,
+};
+#[cfg(feature = "render")]
+pub use game_logic::{Scheduler, SystemTiming};
+pub use localization::tr;
+
+#[cfg(feature = "render")]
+mod app;
+#[cfg(feature = "render")]
+mod builder;
+#[cfg(feature = "render")]
+mod embedded;
+#[cfg(feature = "render")]
+mod engine;
+
+// Vectors and matrices, and the shapes physics and picking work with
+pub mod math {
+  pub use geometry::glam;
+  // f64 world positions and moving the origin f32 positions are measured from
+  pub use geometry::world;
+  pub use geometry::{
+    Aabb, Capsule, Direction, LineSegment, Orientation, Plane, Point, Ray, Triangle,
+  };
+}
+
+// Everything drawn goes to the render thread as RendererMessages through the Renderer
+#[cfg(feature = "render")]
+pub mod renderer {
+  pub use render_manager::{
+    Camera3D, Color, ExportedFrame, FrameDiffCommand, FrameStats, LabelAnchor, LabelIcon,
+    LightShape, LocalLight, MaterialCPU, MaterialParams, Renderer, RendererMessage, SunLight,
+    TriMeshCPU, TriMeshTransform, UiShape, WorldLabel,
+  };
+  pub use render_manager::{
+    LightHandle, MaterialHandle, MeshHandle, ParticleHandle, RenderHandle, TextureHandle,
+  };
+  pub use render_manager::{ParticleEmitter, ReflectionProbe, TextureImportSettings};
+}
+
+#[cfg(feature = "physics")]
+pub mod physics {
+  pub use physics::checked_math::CheckedMath;
+  pub use physics::force::SingleBodyForce;
+  pub use physics::material::{CombineRule, MaterialPairRule, PhysicsMaterial};
+  pub use physics::static_mesh::StaticTriangleMesh;
+  pub use physics::structs::{Mass, PolygonFace, RigidBodyType, Sphere};
+  pub use physics::PhysicsEngine as Physics;
+}
+
+// Filled in from window events, the simulation reads and clears it each tick
+#[cfg(feature = "render")]
+pub mod input {
+  pub use input_aggregator::{key_from_name, Ime, Key, ModifiersState, MouseButton, NamedKey};
+  pub use input_aggregator::{InputAggregator as Input, KeyState, TextInput};
+}
+
+// Files come from the mounted dirs and packs, derived data like decoded textures from the cache
+pub mod assets {
+  pub use asset_cache::{global as cache, AssetCache, AssetCacheStats};
+  pub use vfs::{global, normalize_path, AssetPack, Vfs as Assets, PACK_EXTENSION};
+}
+
+pub mod events {
+  pub use event_bus::{global as bus, Event, EventBus, Subscription};
+  pub use event_bus::{
+    AssetReloaded, FileDropped, FocusGained, FocusLost, GamepadConnected, GamepadDisconnected,
+    ViewResized, WindowResized, WindowScaleChanged,
+  };
+  #[cfg(feature = "render")]
+  pub use event_bus::{KeyAction, KeyActionState};
+}
+
+// What games implement and get handed each tick, the same for built in and library gameplay
+pub mod gameplay {
+  pub use gameplay_api::{export_gameplay, Gameplay, Services, Vec3};
+}
+
+pub mod jobs {
+  pub use jobs::{global, JobGraph, JobHandle, JobId, JobPool};
+}
+
+// use residue_engine::prelude::*; for what most game code touches
+pub mod prelude {
+  pub use crate::assets::Assets;
+  pub use crate::gameplay::{Gameplay, Services};
+  #[cfg(feature = "render")]
+  pub use crate::input::{Input, Key, KeyState, NamedKey};
+  pub use crate::math::glam;
+  #[cfg(feature = "physics")]
+  pub use crate::physics::Physics;
+  #[cfg(feature = "render")]
+  pub use crate::renderer::{Camera3D, Color, Renderer, RendererMessage};
+  pub use crate::{tr, EngineConfig};
+  #[cfg(feature = "render")]
+  pub use crate::{Engine, EngineBuilder, LaunchOptions, WindowDesc};
+}
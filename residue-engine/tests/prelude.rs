@@ -0,0 +1,103 @@
+// Built as a game would use the engine, a re-export of something that's gone fails to compile here
+#![allow(unused_imports)]
+
+use residue_engine::prelude::*;
+use residue_engine::{assets, events, jobs, math};
+
+#[test]
+fn prelude_names_resolve() {
+  let names = [
+    std::any::type_name::<Assets>(),
+    std::any::type_name::<EngineConfig>(),
+    std::any::type_name::<Services>(),
+    std::any::type_name::<math::glam::Vec3>(),
+  ];
+  assert!(names.iter().all(|x| !x.is_empty()));
+}
+
+#[cfg(feature = "physics")]
+#[test]
+fn physics_names_resolve() {
+  use residue_engine::physics;
+  let names = [std::any::type_name::<Physics>(), std::any::type_name::<physics::Mass>()];
+  assert!(names.iter().all(|x| !x.is_empty()));
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn render_names_resolve() {
+  use residue_engine::{input, renderer};
+  let names = [
+    std::any::type_name::<Engine>(),
+    std::any::type_name::<EngineBuilder>(),
+    std::any::type_name::<Renderer>(),
+    std::any::type_name::<Input>(),
+    std::any::type_name::<input::KeyState>(),
+    std::any::type_name::<renderer::FrameStats>(),
+  ];
+  assert!(names.iter().all(|x| !x.is_empty()));
+}
+
+// A game as its own type, handed to the builder instead of built as a library
+#[derive(Default)]
+struct Counter {
+  ticks: u64,
+}
+
+impl Gameplay for Counter {
+  fn load(state: &[u8]) -> Self {
+    Self { ticks: state.try_into().map(u64::from_le_bytes).unwrap_or(0) }
+  }
+
+  fn tick(&mut self, _services: &mut Services, _tick_us: u64) {
+    self.ticks += 1;
+  }
+
+  fn save(&self) -> Vec<u8> {
+    self.ticks.to_le_bytes().to_vec()
+  }
+}
+
+#[test]
+fn game_state_survives_save_and_load() {
+  let saved = Counter { ticks: 7 }.save();
+  assert_eq!(Counter::load(&saved).ticks, 7);
+  assert_eq!(Counter::load(&[]).ticks, 0);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn builder_takes_a_game_value() {
+  let _builder = EngineBuilder::new(EngineConfig::default())
+    .with_game(Counter::default())
+    .with_launch_options(LaunchOptions::default());
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn stages_run_between_the_ones_they_name() {
+  let mut scheduler = residue_engine::Scheduler::default();
+  scheduler.add_stage("ai", &["input"], &["physics"]).unwrap();
+  scheduler.add_stage("late", &["render"], &[]).unwrap();
+  scheduler.add_system("ai", "counter", Counter::default()).unwrap();
+  assert_eq!(
+    scheduler.stage_names(),
+    ["input", "gameplay", "ai", "physics", "animation", "render", "late"]
+  );
+  assert!(scheduler.add_stage("loop", &["render"], &["input"]).is_err());
+  assert!(scheduler.add_system("nowhere", "counter", Counter::default()).is_err());
+  assert!(scheduler.add_system("ai", "counter", Counter::default()).is_err());
+  // Failed adds leave the order as it was
+  assert_eq!(scheduler.stage_names().len(), 7);
+  let timings = scheduler.timings();
+  assert_eq!((timings[2].stage.as_str(), timings[2].system.as_str()), ("ai", "counter"));
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn builder_takes_systems_in_new_stages() {
+  let builder = EngineBuilder::new(EngineConfig::default())
+    .with_stage("ai", &["gameplay"], &["physics"])
+    .and_then(|x| x.with_system("ai", "counter", Counter::default()));
+  assert!(builder.is_ok());
+}
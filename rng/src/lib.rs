@@ -0,0 +1,185 @@
+#[cfg(feature = "test-support")]
+pub mod test_support;
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+pub const PHYSICS_JITTER_STREAM: &str = "physics_jitter";
+pub const PARTICLES_STREAM: &str = "particles";
+pub const GAMEPLAY_STREAM: &str = "gameplay";
+// Prefix of the per object streams, followed by the object's name
+pub const OBJECT_STREAM_PREFIX: &str = "object/";
+
+// Only used to expand seeds, output is the same on every platform
+fn split_mix_64(state: &mut u64) -> u64 {
+  *state = state.wrapping_add(0x9E3779B97F4A7C15);
+  let mut z = *state;
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+  z ^ (z >> 31)
+}
+
+// FNV-1a, std hashers are not guaranteed to be stable across releases
+fn hash_name(name: &str) -> u64 {
+  name
+    .bytes()
+    .fold(0xCBF29CE484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001B3))
+}
+
+// xoshiro256**
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RngStream {
+  state: [u64; 4],
+}
+
+impl RngStream {
+  pub fn from_seed(seed: u64) -> Self {
+    let mut seed_state = seed;
+    let state = [
+      split_mix_64(&mut seed_state),
+      split_mix_64(&mut seed_state),
+      split_mix_64(&mut seed_state),
+      split_mix_64(&mut seed_state),
+    ];
+    Self { state }
+  }
+
+  pub fn next_u64(&mut self) -> u64 {
+    let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+    let t = self.state[1] << 17;
+    self.state[2] ^= self.state[0];
+    self.state[3] ^= self.state[1];
+    self.state[1] ^= self.state[2];
+    self.state[0] ^= self.state[3];
+    self.state[2] ^= t;
+    self.state[3] = self.state[3].rotate_left(45);
+    result
+  }
+
+  pub fn next_u32(&mut self) -> u32 {
+    (self.next_u64() >> 32) as u32
+  }
+
+  // Uniform in [0, 1)
+  pub fn next_f32(&mut self) -> f32 {
+    (self.next_u64() >> 40) as f32 * (1.0 / (1u64 << 24) as f32)
+  }
+
+  pub fn next_f64(&mut self) -> f64 {
+    (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+  }
+
+  pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+    min + (max - min) * self.next_f32()
+  }
+
+  // Uniform in [min, max), returns min for empty ranges
+  pub fn range_u32(&mut self, min: u32, max: u32) -> u32 {
+    if max <= min {
+      return min;
+    }
+    // Multiply-shift keeps the bias below 2^-32 without a rejection loop
+    min + ((self.next_u32() as u64 * (max - min) as u64) >> 32) as u32
+  }
+
+  pub fn range_i32(&mut self, min: i32, max: i32) -> i32 {
+    if max <= min {
+      return min;
+    }
+    let span = (max as i64 - min as i64) as u64;
+    min + ((self.next_u32() as u64 * span) >> 32) as i32
+  }
+
+  pub fn chance(&mut self, probability: f32) -> bool {
+    self.next_f32() < probability
+  }
+
+  pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+    if items.is_empty() {
+      return None;
+    }
+    items.get(self.range_u32(0, items.len() as u32) as usize)
+  }
+
+  // Point in the unit sphere, as x, y, z
+  pub fn in_unit_sphere(&mut self) -> [f32; 3] {
+    loop {
+      let p = [self.range_f32(-1.0, 1.0), self.range_f32(-1.0, 1.0), self.range_f32(-1.0, 1.0)];
+      if p[0] * p[0] + p[1] * p[1] + p[2] * p[2] <= 1.0 {
+        return p;
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RngSnapshot {
+  root_seed: u64,
+  streams: BTreeMap<String, RngStream>,
+}
+
+// Root generator handing out named streams. A stream only depends on the root seed and its name,
+// so adding a new system doesn't shift the random sequence any other system sees
+#[derive(Debug, Clone)]
+pub struct RngService {
+  root_seed: u64,
+  streams: BTreeMap<String, RngStream>,
+}
+
+impl RngService {
+  pub fn new(root_seed: u64) -> Self {
+    Self { root_seed, streams: BTreeMap::new() }
+  }
+
+  pub fn root_seed(&self) -> u64 {
+    self.root_seed
+  }
+
+  pub fn reseed(&mut self, root_seed: u64) {
+    self.root_seed = root_seed;
+    self.streams.clear();
+  }
+
+  pub fn stream(&mut self, name: &str) -> &mut RngStream {
+    let root_seed = self.root_seed;
+    self
+      .streams
+      .entry(name.to_string())
+      .or_insert_with(|| RngStream::from_seed(root_seed ^ hash_name(name)))
+  }
+
+  pub fn physics_jitter(&mut self) -> &mut RngStream {
+    self.stream(PHYSICS_JITTER_STREAM)
+  }
+
+  pub fn particles(&mut self) -> &mut RngStream {
+    self.stream(PARTICLES_STREAM)
+  }
+
+  pub fn gameplay(&mut self) -> &mut RngStream {
+    self.stream(GAMEPLAY_STREAM)
+  }
+
+  // Objects get their own stream, so spawning or reordering others doesn't change what one draws
+  pub fn object(&mut self, object_name: &str) -> &mut RngStream {
+    self.stream(&format!("{OBJECT_STREAM_PREFIX}{object_name}"))
+  }
+
+  pub fn snapshot(&self) -> RngSnapshot {
+    RngSnapshot { root_seed: self.root_seed, streams: self.streams.clone() }
+  }
+
+  pub fn restore(&mut self, snapshot: &RngSnapshot) {
+    self.root_seed = snapshot.root_seed;
+    self.streams = snapshot.streams.clone();
+  }
+}
+
+impl Default for RngService {
+  fn default() -> Self {
+    Self::new(0)
+  }
+}
@@ -0,0 +1,18 @@
+use crate::RngStream;
+
+// Cases each property is checked with
+pub const FUZZ_CASES: u64 = 2000;
+// Set to check the properties with other cases than the fixed ones
+pub const FUZZ_SEED_VAR: &str = "RESIDUE_FUZZ_SEED";
+
+// Panics with the case that broke the property. Every case gets its own stream, so one can be
+// rerun alone by its seed
+pub fn fuzz(property: &str, mut check: impl FnMut(&mut RngStream) -> Result<(), String>) {
+  let seed = std::env::var(FUZZ_SEED_VAR).ok().and_then(|x| x.parse::<u64>().ok()).unwrap_or(0);
+  for case in 0..FUZZ_CASES {
+    let case_seed = seed.wrapping_mul(FUZZ_CASES).wrapping_add(case);
+    if let Err(e) = check(&mut RngStream::from_seed(case_seed)) {
+      panic!("{property} broke for case seed {case_seed}: {e}");
+    }
+  }
+}
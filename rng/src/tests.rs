@@ -0,0 +1,93 @@
+use crate::{RngService, RngStream, GAMEPLAY_STREAM, PARTICLES_STREAM};
+
+fn take_u64(stream: &mut RngStream, count: usize) -> Vec<u64> {
+  (0..count).map(|_| stream.next_u64()).collect()
+}
+
+#[test]
+fn same_seed_gives_the_same_sequence() {
+  let mut a = RngService::new(42);
+  let mut b = RngService::new(42);
+  assert_eq!(take_u64(a.gameplay(), 64), take_u64(b.gameplay(), 64));
+  assert_eq!(
+    take_u64(&mut RngStream::from_seed(7), 64),
+    take_u64(&mut RngStream::from_seed(7), 64)
+  );
+
+  let mut other = RngService::new(43);
+  assert_ne!(take_u64(RngService::new(42).gameplay(), 64), take_u64(other.gameplay(), 64));
+
+  // Reseeding starts the streams over
+  a.reseed(42);
+  assert_eq!(take_u64(a.gameplay(), 64), take_u64(RngService::new(42).gameplay(), 64));
+}
+
+#[test]
+fn named_streams_are_independent() {
+  let mut service = RngService::new(42);
+  let gameplay = take_u64(service.gameplay(), 64);
+  let particles = take_u64(service.particles(), 64);
+  assert_ne!(gameplay, particles);
+
+  // Drawing from another stream, or opening new ones first, doesn't shift a stream's sequence
+  let mut busy = RngService::new(42);
+  take_u64(busy.stream("new_system"), 100);
+  let particles_first = take_u64(busy.stream(PARTICLES_STREAM), 64);
+  assert_eq!(take_u64(busy.stream(GAMEPLAY_STREAM), 64), gameplay);
+  assert_eq!(particles_first, particles);
+
+  // Every object has its own stream
+  let crate_a = take_u64(service.object("crate_a"), 64);
+  assert_ne!(crate_a, take_u64(service.object("crate_b"), 64));
+  assert_eq!(take_u64(RngService::new(42).object("crate_a"), 64), crate_a);
+}
+
+#[test]
+fn restoring_a_snapshot_replays_the_rest_of_the_sequence() {
+  let mut service = RngService::new(9);
+  take_u64(service.gameplay(), 10);
+  take_u64(service.particles(), 3);
+  let snapshot = service.snapshot();
+  let gameplay = take_u64(service.gameplay(), 32);
+  let particles = take_u64(service.particles(), 32);
+
+  // Into the same service after moving on, and into one with another seed
+  service.restore(&snapshot);
+  assert_eq!(take_u64(service.gameplay(), 32), gameplay);
+  assert_eq!(take_u64(service.particles(), 32), particles);
+  let mut other = RngService::new(1);
+  other.restore(&snapshot);
+  assert_eq!(other.root_seed(), 9);
+  assert_eq!(take_u64(other.gameplay(), 32), gameplay);
+  assert_eq!(take_u64(other.particles(), 32), particles);
+  // Streams first opened after the snapshot come from the restored seed
+  assert_eq!(
+    take_u64(other.stream("new_system"), 8),
+    take_u64(RngService::new(9).stream("new_system"), 8)
+  );
+}
+
+#[test]
+fn ranges_stay_in_bounds() {
+  let mut stream = RngStream::from_seed(3);
+  for (min, max) in [(-1.0, 1.0), (0.0, 1e-3), (5.0, 1e6), (-2.5, -2.0)] {
+    for _ in 0..2000 {
+      let x = stream.range_f32(min, max);
+      assert!(x >= min && x < max, "{x} out of {min}..{max}");
+    }
+  }
+  for (min, max) in [(0, 1), (0, 2), (10, 17), (0, u32::MAX), (u32::MAX - 3, u32::MAX)] {
+    for _ in 0..2000 {
+      let x = stream.range_u32(min, max);
+      assert!(x >= min && x < max, "{x} out of {min}..{max}");
+    }
+  }
+  // Every value of a small range comes up
+  let mut seen = [false; 6];
+  (0..600).for_each(|_| seen[stream.range_u32(0, 6) as usize] = true);
+  assert!(seen.iter().all(|x| *x));
+  // Empty ranges give min
+  assert_eq!(stream.range_u32(5, 5), 5);
+  assert_eq!(stream.range_u32(6, 5), 6);
+  assert_eq!(stream.range_f32(2.0, 2.0), 2.0);
+}
@@ -0,0 +1,210 @@
+use clap::Parser;
+use residue_engine::{
+  BenchmarkSettings, EngineConfig, LaunchOptions, LeakCheckSettings, ENGINE_CONFIG_PATH,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Command line options, these win over the engine config file and its env overrides
+#[derive(Debug, Clone, Parser)]
+#[command(about = "Residue Engine")]
+pub struct Cli {
+  #[arg(long, value_name = "PATH", default_value = ENGINE_CONFIG_PATH, help = "Engine config file")]
+  pub config: PathBuf,
+  #[arg(long, value_name = "PATH", help = "Scene file to load, from the mounted assets")]
+  pub level: Option<PathBuf>,
+  #[arg(long, conflicts_with = "windowed", help = "Borderless over the whole monitor")]
+  pub fullscreen: bool,
+  #[arg(long, help = "In a window, even when the config asks for fullscreen")]
+  pub windowed: bool,
+  #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_resolution, help = "Window size")]
+  pub resolution: Option<(u32, u32)>,
+  // Same numbering as RendererConfig::gpu, 0 is left out since that is what not passing it does
+  #[arg(
+    long,
+    value_name = "INDEX",
+    value_parser = clap::value_parser!(u32).range(1..),
+    help = "Gpu to render on, counting from 1 in the order the vulkan driver lists them"
+  )]
+  pub gpu: Option<u32>,
+  #[arg(long, value_name = "CODE", help = "Language for ui and console text, like en")]
+  pub language: Option<String>,
+  #[arg(long, help = "Record profiler scopes from the start")]
+  pub profile: bool,
+  #[arg(long, value_name = "FRAMES", help = "Capture frames with renderdoc once the scene loads")]
+  pub capture: Option<u32>,
+  #[arg(long, help = "Draw offscreen without a window, for machines nobody is watching")]
+  pub headless: bool,
+  #[arg(long, value_name = "PATH", help = "Recorded input to play back, quits once it runs out")]
+  pub replay: Option<PathBuf>,
+  #[arg(
+    long,
+    value_name = "PATH",
+    conflicts_with = "replay",
+    help = "Write this run's input to a file for --replay"
+  )]
+  pub record: Option<PathBuf>,
+  #[arg(
+    long,
+    value_name = "PATH",
+    conflicts_with_all = ["level", "replay", "benchmark"],
+    help = "Repro bundle saved with the repro save console command, played back from its snapshots"
+  )]
+  pub repro: Option<PathBuf>,
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "Gameplay library built from the gameplay crate, reloaded when it's rebuilt"
+  )]
+  pub gameplay_lib: Option<PathBuf>,
+  #[arg(
+    long,
+    conflicts_with = "level",
+    help = "Time a camera path through a generated scene, write a report and quit"
+  )]
+  pub benchmark: bool,
+  #[arg(long, value_name = "COUNT", requires = "benchmark", help = "Props in the benchmark scene")]
+  pub benchmark_objects: Option<u32>,
+  #[arg(long, value_name = "COUNT", requires = "benchmark", help = "Point lights over the props")]
+  pub benchmark_lights: Option<u32>,
+  #[arg(
+    long,
+    value_name = "COUNT",
+    requires = "benchmark",
+    help = "Textures the props cycle through, 0 for none"
+  )]
+  pub benchmark_textures: Option<u32>,
+  #[arg(
+    long,
+    value_name = "SECONDS",
+    value_parser = parse_seconds,
+    requires = "benchmark",
+    help = "Length of the timed run"
+  )]
+  pub benchmark_seconds: Option<Duration>,
+  #[arg(long, value_name = "PATH", requires = "benchmark", help = "Where the toml report goes")]
+  pub benchmark_report: Option<PathBuf>,
+  #[arg(
+    long,
+    conflicts_with_all = ["benchmark", "repro"],
+    help = "Load, play and unload the level over and over, report what it leaves behind and quit"
+  )]
+  pub leak_check: bool,
+  #[arg(long, value_name = "COUNT", requires = "leak_check", help = "Loads after the first")]
+  pub leak_check_cycles: Option<u32>,
+  #[arg(
+    long,
+    value_name = "SECONDS",
+    value_parser = parse_seconds,
+    requires = "leak_check",
+    help = "Time each load is played"
+  )]
+  pub leak_check_seconds: Option<Duration>,
+  #[arg(long, value_name = "PATH", requires = "leak_check", help = "Where the toml report goes")]
+  pub leak_check_report: Option<PathBuf>,
+}
+
+fn parse_resolution(value: &str) -> Result<(u32, u32), String> {
+  let (width, height) =
+    value.split_once(['x', 'X']).ok_or(format!("expected WIDTHxHEIGHT, got {value}"))?;
+  let width = width.parse::<u32>().map_err(|e| format!("at parsing width {width}: {e}"))?;
+  let height = height.parse::<u32>().map_err(|e| format!("at parsing height {height}: {e}"))?;
+  if width == 0 || height == 0 {
+    return Err(format!("resolution can't be 0 wide or high, got {value}"));
+  }
+  Ok((width, height))
+}
+
+// Whole or fractional seconds, too long or not finite is turned down instead of panicking later
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+  let seconds = value.parse::<f32>().map_err(|e| format!("at parsing seconds {value}: {e}"))?;
+  if !seconds.is_finite() || seconds < 0.0 {
+    return Err(format!("seconds have to be a finite number, 0 or more, got {value}"));
+  }
+  Duration::try_from_secs_f32(seconds).map_err(|e| format!("{value} seconds is too long: {e}"))
+}
+
+impl Cli {
+  // Loads the config file and puts the command line over it
+  pub fn engine_config(&self) -> Result<EngineConfig, String> {
+    let mut config = EngineConfig::load(&self.config)?;
+    if self.fullscreen || self.windowed {
+      config.window.fullscreen = self.fullscreen;
+    }
+    if let Some((width, height)) = self.resolution {
+      config.window.width = width;
+      config.window.height = height;
+    }
+    if let Some(gpu) = self.gpu {
+      config.renderer.gpu = gpu;
+    }
+    if let Some(language) = &self.language {
+      config.localization.language = language.clone();
+    }
+    config.validate().map_err(|e| format!("with command line options: {e}"))?;
+    Ok(config)
+  }
+
+  pub fn launch_options(&self) -> LaunchOptions {
+    LaunchOptions {
+      scene_path: self.level.clone(),
+      capture_frames: self.capture.unwrap_or(0),
+      replay_path: self.replay.clone(),
+      record_path: self.record.clone(),
+      repro_path: self.repro.clone(),
+      gameplay_library: self.gameplay_lib.clone(),
+      benchmark: self.benchmark.then(|| self.benchmark_settings()),
+      leak_check: self.leak_check.then(|| self.leak_check_settings()),
+    }
+  }
+
+  // Defaults for whatever isn't passed
+  fn benchmark_settings(&self) -> BenchmarkSettings {
+    let defaults = BenchmarkSettings::default();
+    BenchmarkSettings {
+      objects: self.benchmark_objects.unwrap_or(defaults.objects),
+      lights: self.benchmark_lights.unwrap_or(defaults.lights),
+      textures: self.benchmark_textures.unwrap_or(defaults.textures),
+      duration: self.benchmark_seconds.unwrap_or(defaults.duration),
+      report_path: self.benchmark_report.clone().unwrap_or(defaults.report_path),
+      ..defaults
+    }
+  }
+
+  fn leak_check_settings(&self) -> LeakCheckSettings {
+    let defaults = LeakCheckSettings::default();
+    LeakCheckSettings {
+      cycles: self.leak_check_cycles.unwrap_or(defaults.cycles).max(1),
+      play_time: self.leak_check_seconds.unwrap_or(defaults.play_time),
+      report_path: self.leak_check_report.clone().unwrap_or(defaults.report_path),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn seconds_parse_fractions() {
+    assert_eq!(parse_seconds("1.5"), Ok(Duration::from_millis(1500)));
+    assert_eq!(parse_seconds("0"), Ok(Duration::ZERO));
+  }
+
+  #[test]
+  fn seconds_turn_down_what_would_panic() {
+    for value in ["inf", "-inf", "NaN", "1e30", "-1", "ten"] {
+      assert!(parse_seconds(value).is_err(), "{value} was let through");
+    }
+  }
+
+  #[test]
+  fn benchmark_seconds_reach_the_settings() {
+    let cli = Cli::try_parse_from(["residue", "--benchmark", "--benchmark-seconds", "2"]).unwrap();
+    assert_eq!(cli.launch_options().benchmark.unwrap().duration, Duration::from_secs(2));
+    assert!(Cli::try_parse_from(["residue", "--benchmark", "--benchmark-seconds", "inf"]).is_err());
+    assert!(
+      Cli::try_parse_from(["residue", "--leak-check", "--leak-check-seconds", "1e30"]).is_err()
+    );
+  }
+}
@@ -1,14 +1,32 @@
-use crate::app_activity::AppActivity;
-use winit::event_loop::{ControlFlow, EventLoop};
+use crate::cli::Cli;
+use clap::Parser;
+use residue_engine::{EngineBuilder, WindowDesc};
+use std::path::Path;
 
-mod app_activity;
+mod cli;
+
+static WINDOW_ICON_BYTES: &[u8] = include_bytes!("../assets/icon.ico");
+
+#[cfg(feature = "alloc-audit")]
+#[global_allocator]
+static ALLOCATOR: profiler::CountingAllocator = profiler::CountingAllocator;
 
 fn main() {
-  let mut app =
-    AppActivity::new().inspect_err(|e| eprintln!("{e}")).expect("error initializing app activity");
-  let window_event_loop = EventLoop::new()
+  let cli = Cli::parse();
+  crash_report::install(Path::new(crash_report::CRASH_REPORT_DIR));
+  if cli.profile {
+    profiler::set_enabled(true);
+  }
+  let window = WindowDesc {
+    title: "Residue Engine".to_string(),
+    icon: Some(WINDOW_ICON_BYTES),
+    headless: cli.headless,
+  };
+  cli
+    .engine_config()
+    .and_then(|config| {
+      EngineBuilder::new(config).with_window(window).with_launch_options(cli.launch_options()).run()
+    })
     .inspect_err(|e| eprintln!("{e}"))
-    .expect("error initializing window event loop");
-  window_event_loop.set_control_flow(ControlFlow::Poll);
-  let _ = window_event_loop.run_app(&mut app).inspect_err(|e| eprintln!("{e}"));
+    .expect("error running engine");
 }
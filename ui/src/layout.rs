@@ -0,0 +1,99 @@
+// Screen space rect in pixels, y going down like the cursor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+  pub min: glam::Vec2,
+  pub max: glam::Vec2,
+}
+
+impl Rect {
+  pub fn new(min: glam::Vec2, max: glam::Vec2) -> Self {
+    Self { min, max }
+  }
+
+  pub fn from_size(size: glam::Vec2) -> Self {
+    Self { min: glam::Vec2::ZERO, max: size }
+  }
+
+  pub fn size(&self) -> glam::Vec2 {
+    self.max - self.min
+  }
+
+  pub fn center(&self) -> glam::Vec2 {
+    (self.min + self.max) * 0.5
+  }
+
+  // Point at a fraction of the rect, (0, 0) is the top left corner and (1, 1) the bottom right
+  pub fn point_at(&self, fraction: glam::Vec2) -> glam::Vec2 {
+    self.min + self.size() * fraction
+  }
+
+  pub fn contains(&self, point: glam::Vec2) -> bool {
+    point.cmpge(self.min).all() && point.cmplt(self.max).all()
+  }
+
+  pub fn intersect(&self, other: &Rect) -> Rect {
+    let min = self.min.max(other.min);
+    Rect { min, max: self.max.min(other.max).max(min) }
+  }
+}
+
+// Where a node attaches in its parent, as fractions of the parent rect. min and max apart stretch
+// the node with the parent on that axis, the same pins it to a point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anchor {
+  pub min: glam::Vec2,
+  pub max: glam::Vec2,
+}
+
+impl Anchor {
+  pub const TOP_LEFT: Self = Self::point(0.0, 0.0);
+  pub const TOP: Self = Self::point(0.5, 0.0);
+  pub const TOP_RIGHT: Self = Self::point(1.0, 0.0);
+  pub const LEFT: Self = Self::point(0.0, 0.5);
+  pub const CENTER: Self = Self::point(0.5, 0.5);
+  pub const RIGHT: Self = Self::point(1.0, 0.5);
+  pub const BOTTOM_LEFT: Self = Self::point(0.0, 1.0);
+  pub const BOTTOM: Self = Self::point(0.5, 1.0);
+  pub const BOTTOM_RIGHT: Self = Self::point(1.0, 1.0);
+  pub const STRETCH: Self = Self { min: glam::Vec2::new(0.0, 0.0), max: glam::Vec2::new(1.0, 1.0) };
+  pub const STRETCH_TOP: Self =
+    Self { min: glam::Vec2::new(0.0, 0.0), max: glam::Vec2::new(1.0, 0.0) };
+  pub const STRETCH_BOTTOM: Self =
+    Self { min: glam::Vec2::new(0.0, 1.0), max: glam::Vec2::new(1.0, 1.0) };
+
+  pub const fn point(x: f32, y: f32) -> Self {
+    Self { min: glam::Vec2::new(x, y), max: glam::Vec2::new(x, y) }
+  }
+}
+
+// How reference units, the units node offsets and sizes are authored in, turn into pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalePolicy {
+  // One unit is one pixel whatever the resolution
+  ConstantPixels,
+  // Scales with the viewport height, what most HUDs want so text keeps its size across aspects
+  MatchHeight { reference_height: f32 },
+  MatchWidth { reference_width: f32 },
+  // Whole reference resolution stays on screen, the extra space goes to the longer side
+  Fit { reference: glam::Vec2 },
+  // Reference resolution covers the screen, parts of it may be off the shorter side
+  Fill { reference: glam::Vec2 },
+}
+
+impl ScalePolicy {
+  pub fn scale(&self, viewport: glam::Vec2) -> f32 {
+    let scale = match self {
+      ScalePolicy::ConstantPixels => 1.0,
+      ScalePolicy::MatchHeight { reference_height } => viewport.y / reference_height,
+      ScalePolicy::MatchWidth { reference_width } => viewport.x / reference_width,
+      ScalePolicy::Fit { reference } => (viewport / *reference).min_element(),
+      ScalePolicy::Fill { reference } => (viewport / *reference).max_element(),
+    };
+    // Zero sized references would make everything infinite
+    if scale.is_finite() && scale > 0.0 {
+      scale
+    } else {
+      1.0
+    }
+  }
+}
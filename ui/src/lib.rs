@@ -0,0 +1,322 @@
+use input_aggregator::{InputAggregator, KeyState, MouseButton};
+
+mod layout;
+#[cfg(test)]
+mod tests;
+
+pub use layout::{Anchor, Rect, ScalePolicy};
+
+// Offsets and sizes are in reference units, see ScalePolicy. On axes the anchor stretches, size is
+// added to the stretched span, so a negative size insets the node from both edges
+#[derive(Debug, Clone, PartialEq)]
+pub struct UiNode {
+  pub name: String,
+  pub anchor: Anchor,
+  // Point of the node put on the anchor, fractions of its own size with (0, 0) the top left
+  pub pivot: glam::Vec2,
+  pub offset: glam::Vec2,
+  pub size: glam::Vec2,
+  // Hidden nodes hide their children too
+  pub visible: bool,
+  // Only interactive nodes take hover and clicks, the rest let them through to what is below
+  pub interactive: bool,
+  // Children are only hit inside this node's rect
+  pub clip_children: bool,
+  // String table key of the text drawn on the node, see UiTree::text
+  pub text: Option<String>,
+  // Root nodes are laid out against the safe area, these against the whole viewport, for
+  // backgrounds and fades
+  pub ignore_safe_area: bool,
+}
+
+impl UiNode {
+  // Pivot matches the anchor, so a node anchored to a corner or edge sits inside the parent
+  pub fn new(name: &str, anchor: Anchor, size: glam::Vec2) -> Self {
+    Self {
+      name: name.to_string(),
+      anchor,
+      pivot: (anchor.min + anchor.max) * 0.5,
+      offset: glam::Vec2::ZERO,
+      size,
+      visible: true,
+      interactive: false,
+      clip_children: false,
+      text: None,
+      ignore_safe_area: false,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UiNodeId {
+  idx: u32,
+  generation: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiEvent {
+  HoverStart(UiNodeId),
+  HoverEnd(UiNodeId),
+  Pressed(UiNodeId),
+  // Left button went down and came back up over the same node
+  Clicked(UiNodeId),
+}
+
+struct NodeEntry {
+  node: UiNode,
+  parent: Option<UiNodeId>,
+  children: Vec<UiNodeId>,
+  rect: Rect,
+  // Area where the node can be hit, its rect cut by clipping ancestors
+  hit_rect: Rect,
+}
+
+struct NodeSlot {
+  generation: u32,
+  entry: Option<NodeEntry>,
+}
+
+// Nodes in a tree laid out against the viewport, drawn and hit parent first then children in the
+// order they were added. Ids of removed nodes stop resolving even when their slot is reused
+pub struct UiTree {
+  slots: Vec<NodeSlot>,
+  free_slots: Vec<u32>,
+  roots: Vec<UiNodeId>,
+  scale_policy: ScalePolicy,
+  // On top of the policy's scale, for readability on high dpi screens
+  ui_scale: f32,
+  viewport: glam::Vec2,
+  // Part of the viewport HUDs keep to, the whole of it when None
+  safe_area: Option<Rect>,
+  hovered: Option<UiNodeId>,
+  pressed: Option<UiNodeId>,
+}
+
+impl UiTree {
+  pub fn new(scale_policy: ScalePolicy) -> Self {
+    Self {
+      slots: vec![],
+      free_slots: vec![],
+      roots: vec![],
+      scale_policy,
+      ui_scale: 1.0,
+      viewport: glam::Vec2::ONE,
+      safe_area: None,
+      hovered: None,
+      pressed: None,
+    }
+  }
+
+  pub fn set_scale_policy(&mut self, scale_policy: ScalePolicy) {
+    self.scale_policy = scale_policy;
+    self.layout(self.viewport);
+  }
+
+  // From the renderer's ViewResized, in pixels like the viewport
+  pub fn set_safe_area(&mut self, safe_area: Option<Rect>) {
+    self.safe_area = safe_area;
+    self.layout(self.viewport);
+  }
+
+  // From the renderer's ViewResized too
+  pub fn set_ui_scale(&mut self, ui_scale: f32) {
+    self.ui_scale = ui_scale;
+    self.layout(self.viewport);
+  }
+
+  pub fn scale(&self) -> f32 {
+    self.scale_policy.scale(self.viewport) * self.ui_scale
+  }
+
+  fn entry(&self, id: UiNodeId) -> Option<&NodeEntry> {
+    let slot = self.slots.get(id.idx as usize)?;
+    (slot.generation == id.generation).then_some(slot.entry.as_ref()?)
+  }
+
+  fn entry_mut(&mut self, id: UiNodeId) -> Option<&mut NodeEntry> {
+    let slot = self.slots.get_mut(id.idx as usize)?;
+    (slot.generation == id.generation).then_some(slot.entry.as_mut()?)
+  }
+
+  // Root nodes are laid out against the safe area, see UiNode::ignore_safe_area
+  pub fn add(&mut self, parent: Option<UiNodeId>, node: UiNode) -> Result<UiNodeId, String> {
+    if let Some(parent) = parent {
+      if self.entry(parent).is_none() {
+        return Err(format!("at adding ui node {}: parent node is gone", node.name));
+      }
+    }
+    let entry = NodeEntry {
+      node,
+      parent,
+      children: vec![],
+      rect: Rect::from_size(glam::Vec2::ZERO),
+      hit_rect: Rect::from_size(glam::Vec2::ZERO),
+    };
+    let id = match self.free_slots.pop() {
+      Some(idx) => {
+        let slot = &mut self.slots[idx as usize];
+        slot.entry = Some(entry);
+        UiNodeId { idx, generation: slot.generation }
+      }
+      None => {
+        self.slots.push(NodeSlot { generation: 0, entry: Some(entry) });
+        UiNodeId { idx: self.slots.len() as u32 - 1, generation: 0 }
+      }
+    };
+    match parent.and_then(|parent| self.entry_mut(parent)) {
+      Some(parent_entry) => parent_entry.children.push(id),
+      None => self.roots.push(id),
+    }
+    self.layout(self.viewport);
+    Ok(id)
+  }
+
+  // Takes the whole subtree with it
+  pub fn remove(&mut self, id: UiNodeId) {
+    let Some(parent) = self.entry(id).map(|entry| entry.parent) else { return };
+    match parent.and_then(|parent| self.entry_mut(parent)) {
+      Some(parent_entry) => parent_entry.children.retain(|child| *child != id),
+      None => self.roots.retain(|root| *root != id),
+    }
+    let mut stack = vec![id];
+    while let Some(node_id) = stack.pop() {
+      let slot = &mut self.slots[node_id.idx as usize];
+      if let Some(entry) = slot.entry.take() {
+        stack.extend(entry.children);
+      }
+      slot.generation += 1;
+      self.free_slots.push(node_id.idx);
+      if self.hovered == Some(node_id) {
+        self.hovered = None;
+      }
+      if self.pressed == Some(node_id) {
+        self.pressed = None;
+      }
+    }
+  }
+
+  pub fn node(&self, id: UiNodeId) -> Option<&UiNode> {
+    self.entry(id).map(|entry| &entry.node)
+  }
+
+  // Changes show up in rects after the next layout
+  pub fn node_mut(&mut self, id: UiNodeId) -> Option<&mut UiNode> {
+    self.entry_mut(id).map(|entry| &mut entry.node)
+  }
+
+  pub fn children(&self, id: UiNodeId) -> &[UiNodeId] {
+    self.entry(id).map(|entry| entry.children.as_slice()).unwrap_or(&[])
+  }
+
+  // First node with the name in draw order
+  pub fn find(&self, name: &str) -> Option<UiNodeId> {
+    self.walk(false).into_iter().find(|id| self.node(*id).is_some_and(|node| node.name == name))
+  }
+
+  // The node's text in the current language, looked up each call so a language switch shows on
+  // the next frame drawn
+  pub fn text(&self, id: UiNodeId) -> Option<String> {
+    self.node(id)?.text.as_deref().map(|key| localization::tr!(key))
+  }
+
+  pub fn rect(&self, id: UiNodeId) -> Option<Rect> {
+    self.entry(id).map(|entry| entry.rect)
+  }
+
+  fn place(node: &UiNode, parent: Rect, scale: f32) -> Rect {
+    let anchor_min = parent.point_at(node.anchor.min);
+    let anchor_max = parent.point_at(node.anchor.max);
+    let span = anchor_max - anchor_min;
+    let size = (span + node.size * scale).max(glam::Vec2::ZERO);
+    let pivot_pos = anchor_min + span * node.pivot + node.offset * scale;
+    let min = pivot_pos - size * node.pivot;
+    Rect::new(min, min + size)
+  }
+
+  // viewport is the window size in pixels
+  pub fn layout(&mut self, viewport: glam::Vec2) {
+    self.viewport = viewport;
+    let scale = self.scale();
+    let screen = Rect::from_size(viewport);
+    let safe_area = self.safe_area.map_or(screen, |safe_area| safe_area.intersect(&screen));
+    let mut stack = self.roots.iter().rev().map(|root| (*root, screen, screen)).collect::<Vec<_>>();
+    while let Some((id, parent_rect, parent_hit_rect)) = stack.pop() {
+      let Some(entry) = self.entry_mut(id) else { continue };
+      let parent_rect = match entry.parent.is_none() && !entry.node.ignore_safe_area {
+        true => safe_area,
+        false => parent_rect,
+      };
+      entry.rect = Self::place(&entry.node, parent_rect, scale);
+      entry.hit_rect = parent_hit_rect;
+      let child_hit_rect = match entry.node.clip_children {
+        true => parent_hit_rect.intersect(&entry.rect),
+        false => parent_hit_rect,
+      };
+      let rect = entry.rect;
+      stack.extend(entry.children.iter().rev().map(|child| (*child, rect, child_hit_rect)));
+    }
+  }
+
+  // Depth first, parents before children, optionally skipping hidden subtrees
+  fn walk(&self, visible_only: bool) -> Vec<UiNodeId> {
+    let mut order = vec![];
+    let mut stack = self.roots.iter().rev().copied().collect::<Vec<_>>();
+    while let Some(id) = stack.pop() {
+      let Some(entry) = self.entry(id) else { continue };
+      if visible_only && !entry.node.visible {
+        continue;
+      }
+      order.push(id);
+      stack.extend(entry.children.iter().rev());
+    }
+    order
+  }
+
+  // Visible nodes back to front, what a sprite or text pass draws in order
+  pub fn draw_order(&self) -> Vec<UiNodeId> {
+    self.walk(true)
+  }
+
+  // Frontmost visible interactive node under a point in pixels
+  pub fn hit_test(&self, point: glam::Vec2) -> Option<UiNodeId> {
+    self.walk(true).into_iter().rev().find(|id| {
+      self.entry(*id).is_some_and(|entry| {
+        entry.node.interactive && entry.rect.contains(point) && entry.hit_rect.contains(point)
+      })
+    })
+  }
+
+  // Anything interactive under the cursor, game input like picking should skip the frame if so
+  pub fn is_pointer_captured(&self) -> bool {
+    self.hovered.is_some() || self.pressed.is_some()
+  }
+
+  // Call once a frame after layout, before the input states are cleared
+  pub fn update_input(&mut self, inputs: &InputAggregator) -> Vec<UiEvent> {
+    let mut events = vec![];
+    let (width, height) = inputs.window_size();
+    let hovered = inputs
+      .cursor_pos()
+      .map(|(x, y)| glam::vec2(x * width as f32, y * height as f32))
+      .and_then(|point| self.hit_test(point));
+    if hovered != self.hovered {
+      events.extend(self.hovered.map(UiEvent::HoverEnd));
+      events.extend(hovered.map(UiEvent::HoverStart));
+      self.hovered = hovered;
+    }
+
+    let left_mouse = inputs.is_mouse_pressed(MouseButton::Left);
+    if left_mouse.is_just_pressed() {
+      self.pressed = hovered;
+      events.extend(hovered.map(UiEvent::Pressed));
+    }
+    if matches!(left_mouse, KeyState::Released) {
+      if let Some(pressed) = self.pressed.take() {
+        if hovered == Some(pressed) {
+          events.push(UiEvent::Clicked(pressed));
+        }
+      }
+    }
+    events
+  }
+}
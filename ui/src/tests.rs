@@ -0,0 +1,189 @@
+use input_aggregator::{InputAggregator, MouseButton};
+
+use crate::{Anchor, Rect, ScalePolicy, UiEvent, UiNode, UiTree};
+
+const VIEWPORT: glam::Vec2 = glam::Vec2::new(800.0, 600.0);
+
+fn rect(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Rect {
+  Rect::new(glam::vec2(min_x, min_y), glam::vec2(max_x, max_y))
+}
+
+fn button(name: &str, anchor: Anchor, size: glam::Vec2) -> UiNode {
+  UiNode { interactive: true, ..UiNode::new(name, anchor, size) }
+}
+
+fn laid_out(scale_policy: ScalePolicy) -> UiTree {
+  let mut tree = UiTree::new(scale_policy);
+  tree.layout(VIEWPORT);
+  tree
+}
+
+#[test]
+fn anchored_nodes_sit_inside_their_parent() {
+  let mut tree = laid_out(ScalePolicy::ConstantPixels);
+  let size = glam::vec2(100.0, 50.0);
+  let top_left = tree.add(None, UiNode::new("tl", Anchor::TOP_LEFT, size)).unwrap();
+  let center = tree.add(None, UiNode::new("c", Anchor::CENTER, size)).unwrap();
+  let bottom_right = tree.add(None, UiNode::new("br", Anchor::BOTTOM_RIGHT, size)).unwrap();
+  assert_eq!(tree.rect(top_left), Some(rect(0.0, 0.0, 100.0, 50.0)));
+  assert_eq!(tree.rect(center), Some(rect(350.0, 275.0, 450.0, 325.0)));
+  assert_eq!(tree.rect(bottom_right), Some(rect(700.0, 550.0, 800.0, 600.0)));
+}
+
+#[test]
+fn stretched_nodes_follow_the_parent_and_negative_sizes_inset() {
+  let mut tree = laid_out(ScalePolicy::ConstantPixels);
+  let bar = tree.add(None, UiNode::new("bar", Anchor::STRETCH_TOP, glam::vec2(0.0, 40.0))).unwrap();
+  let inset =
+    tree.add(None, UiNode::new("inset", Anchor::STRETCH, glam::Vec2::splat(-20.0))).unwrap();
+  assert_eq!(tree.rect(bar), Some(rect(0.0, 0.0, 800.0, 40.0)));
+  assert_eq!(tree.rect(inset), Some(rect(10.0, 10.0, 790.0, 590.0)));
+
+  tree.layout(glam::vec2(1000.0, 500.0));
+  assert_eq!(tree.rect(bar), Some(rect(0.0, 0.0, 1000.0, 40.0)));
+  assert_eq!(tree.rect(inset), Some(rect(10.0, 10.0, 990.0, 490.0)));
+}
+
+#[test]
+fn children_are_placed_in_their_parent_with_offsets() {
+  let mut tree = laid_out(ScalePolicy::ConstantPixels);
+  let panel =
+    tree.add(None, UiNode::new("panel", Anchor::CENTER, glam::vec2(200.0, 100.0))).unwrap();
+  let child = UiNode {
+    offset: glam::vec2(-5.0, 5.0),
+    ..UiNode::new("close", Anchor::TOP_RIGHT, glam::vec2(20.0, 20.0))
+  };
+  let child = tree.add(Some(panel), child).unwrap();
+  assert_eq!(tree.rect(child), Some(rect(475.0, 255.0, 495.0, 275.0)));
+  assert_eq!(tree.children(panel), &[child]);
+}
+
+#[test]
+fn scale_policies_scale_offsets_and_sizes() {
+  let size = glam::vec2(100.0, 50.0);
+  let mut tree = UiTree::new(ScalePolicy::MatchHeight { reference_height: 300.0 });
+  let node = tree.add(None, UiNode::new("n", Anchor::TOP_LEFT, size)).unwrap();
+  tree.layout(VIEWPORT);
+  assert_eq!(tree.scale(), 2.0);
+  assert_eq!(tree.rect(node), Some(rect(0.0, 0.0, 200.0, 100.0)));
+
+  tree.set_ui_scale(1.5);
+  assert_eq!(tree.rect(node), Some(rect(0.0, 0.0, 300.0, 150.0)));
+
+  let reference = glam::vec2(400.0, 400.0);
+  assert_eq!(ScalePolicy::Fit { reference }.scale(VIEWPORT), 1.5);
+  assert_eq!(ScalePolicy::Fill { reference }.scale(VIEWPORT), 2.0);
+  assert_eq!(ScalePolicy::MatchWidth { reference_width: 0.0 }.scale(VIEWPORT), 1.0);
+}
+
+#[test]
+fn roots_keep_to_the_safe_area_unless_told_not_to() {
+  let mut tree = laid_out(ScalePolicy::ConstantPixels);
+  let hud = tree.add(None, UiNode::new("hud", Anchor::STRETCH, glam::Vec2::ZERO)).unwrap();
+  let fade =
+    UiNode { ignore_safe_area: true, ..UiNode::new("fade", Anchor::STRETCH, glam::Vec2::ZERO) };
+  let fade = tree.add(None, fade).unwrap();
+  tree.set_safe_area(Some(rect(40.0, 20.0, 760.0, 580.0)));
+  assert_eq!(tree.rect(hud), Some(rect(40.0, 20.0, 760.0, 580.0)));
+  assert_eq!(tree.rect(fade), Some(rect(0.0, 0.0, 800.0, 600.0)));
+
+  // Safe areas past the viewport are cut to it
+  tree.set_safe_area(Some(rect(-10.0, 0.0, 900.0, 600.0)));
+  assert_eq!(tree.rect(hud), Some(rect(0.0, 0.0, 800.0, 600.0)));
+}
+
+#[test]
+fn hit_tests_find_the_frontmost_interactive_node() {
+  let mut tree = laid_out(ScalePolicy::ConstantPixels);
+  let panel =
+    tree.add(None, UiNode::new("panel", Anchor::TOP_LEFT, glam::vec2(200.0, 200.0))).unwrap();
+  let back =
+    tree.add(Some(panel), button("back", Anchor::TOP_LEFT, glam::vec2(100.0, 100.0))).unwrap();
+  let front =
+    tree.add(Some(panel), button("front", Anchor::TOP_LEFT, glam::vec2(50.0, 50.0))).unwrap();
+  assert_eq!(tree.hit_test(glam::vec2(25.0, 25.0)), Some(front));
+  assert_eq!(tree.hit_test(glam::vec2(75.0, 75.0)), Some(back));
+  // The panel isn't interactive, so it lets the point through
+  assert_eq!(tree.hit_test(glam::vec2(150.0, 150.0)), None);
+  // Rects include their min edge and not their max
+  assert_eq!(tree.hit_test(glam::vec2(100.0, 10.0)), None);
+
+  tree.node_mut(front).unwrap().visible = false;
+  assert_eq!(tree.hit_test(glam::vec2(25.0, 25.0)), Some(back));
+  tree.node_mut(panel).unwrap().visible = false;
+  assert_eq!(tree.hit_test(glam::vec2(75.0, 75.0)), None);
+  assert!(tree.draw_order().is_empty());
+}
+
+#[test]
+fn clipping_parents_cut_where_children_are_hit() {
+  let mut tree = laid_out(ScalePolicy::ConstantPixels);
+  let list = UiNode {
+    clip_children: true,
+    ..UiNode::new("list", Anchor::TOP_LEFT, glam::vec2(100.0, 100.0))
+  };
+  let list = tree.add(None, list).unwrap();
+  let item = UiNode {
+    offset: glam::vec2(0.0, 80.0),
+    ..button("item", Anchor::TOP_LEFT, glam::vec2(100.0, 40.0))
+  };
+  let item = tree.add(Some(list), item).unwrap();
+  assert_eq!(tree.hit_test(glam::vec2(50.0, 90.0)), Some(item));
+  assert_eq!(tree.hit_test(glam::vec2(50.0, 110.0)), None);
+
+  tree.node_mut(list).unwrap().clip_children = false;
+  tree.layout(VIEWPORT);
+  assert_eq!(tree.hit_test(glam::vec2(50.0, 110.0)), Some(item));
+}
+
+#[test]
+fn removed_nodes_take_their_subtree_and_ids_go_stale() {
+  let mut tree = laid_out(ScalePolicy::ConstantPixels);
+  let panel =
+    tree.add(None, UiNode::new("panel", Anchor::TOP_LEFT, glam::vec2(200.0, 200.0))).unwrap();
+  let child =
+    tree.add(Some(panel), button("child", Anchor::TOP_LEFT, glam::vec2(50.0, 50.0))).unwrap();
+  tree.remove(panel);
+  assert_eq!(tree.node(panel), None);
+  assert_eq!(tree.node(child), None);
+  assert_eq!(tree.find("child"), None);
+  assert!(tree.add(Some(panel), UiNode::new("orphan", Anchor::CENTER, glam::Vec2::ONE)).is_err());
+
+  // Reuses a freed slot, the old ids still don't resolve to it
+  let reused = tree.add(None, UiNode::new("reused", Anchor::CENTER, glam::Vec2::ONE)).unwrap();
+  assert_eq!(tree.find("reused"), Some(reused));
+  assert_eq!(tree.node(panel), None);
+  assert_eq!(tree.node(child), None);
+}
+
+#[test]
+fn pointer_input_hovers_presses_and_clicks() {
+  let mut tree = laid_out(ScalePolicy::ConstantPixels);
+  let ok = tree.add(None, button("ok", Anchor::TOP_LEFT, glam::vec2(100.0, 50.0))).unwrap();
+  let mut inputs = InputAggregator::new();
+  inputs.update_window_size(VIEWPORT.x as u32, VIEWPORT.y as u32);
+
+  inputs.update_cursor_pos(400.0, 300.0);
+  assert_eq!(tree.update_input(&inputs), vec![]);
+  assert!(!tree.is_pointer_captured());
+
+  inputs.update_cursor_pos(20.0, 20.0);
+  assert_eq!(tree.update_input(&inputs), vec![UiEvent::HoverStart(ok)]);
+  assert!(tree.is_pointer_captured());
+
+  inputs.update_mouse_pressed(MouseButton::Left);
+  assert_eq!(tree.update_input(&inputs), vec![UiEvent::Pressed(ok)]);
+  inputs.clear_key_states();
+  inputs.update_mouse_released(MouseButton::Left);
+  assert_eq!(tree.update_input(&inputs), vec![UiEvent::Clicked(ok)]);
+  inputs.clear_key_states();
+
+  // Released somewhere else it's no click
+  inputs.update_mouse_pressed(MouseButton::Left);
+  tree.update_input(&inputs);
+  inputs.clear_key_states();
+  inputs.update_cursor_pos(400.0, 300.0);
+  inputs.update_mouse_released(MouseButton::Left);
+  assert_eq!(tree.update_input(&inputs), vec![UiEvent::HoverEnd(ok)]);
+  assert!(!tree.is_pointer_captured());
+}
@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+// Checks are only built into debug builds unless the release-checks feature asks for them
+pub const COMPILED: bool = cfg!(any(debug_assertions, feature = "release-checks"));
+// Failures printed per category, the rest are only counted
+const LOGGED_PER_CATEGORY: u64 = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ValidationCategory {
+  // Cpu writes to memory a submitted frame may still be reading
+  BufferInFlight,
+  // Handles used after the resource behind them was destroyed
+  DestroyedHandle,
+  // Bindings that don't match the descriptor set layout they are written to
+  DescriptorType,
+  // More of a resource live than its configured ceiling
+  ResourceBudget,
+}
+
+impl ValidationCategory {
+  pub const ALL: [ValidationCategory; 4] = [
+    ValidationCategory::BufferInFlight,
+    ValidationCategory::DestroyedHandle,
+    ValidationCategory::DescriptorType,
+    ValidationCategory::ResourceBudget,
+  ];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      ValidationCategory::BufferInFlight => "buffer in flight",
+      ValidationCategory::DestroyedHandle => "destroyed handle",
+      ValidationCategory::DescriptorType => "descriptor type",
+      ValidationCategory::ResourceBudget => "resource budget",
+    }
+  }
+
+  fn index(&self) -> usize {
+    *self as usize
+  }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static FAILURES: [AtomicU64; ValidationCategory::ALL.len()] =
+  [const { AtomicU64::new(0) }; ValidationCategory::ALL.len()];
+
+// On by default where compiled in, turning it off skips the checks as well as the reports
+pub fn set_enabled(enabled: bool) {
+  ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+  COMPILED && ENABLED.load(Ordering::Relaxed)
+}
+
+// Counts a failure, the message is only made for the first few of each category
+pub fn report(category: ValidationCategory, message: impl FnOnce() -> String) {
+  if !is_enabled() {
+    return;
+  }
+  let count = FAILURES[category.index()].fetch_add(1, Ordering::Relaxed) + 1;
+  if count <= LOGGED_PER_CATEGORY {
+    eprintln!("validation ({}): {}", category.name(), message());
+  }
+  if count == LOGGED_PER_CATEGORY {
+    eprintln!("validation ({}): further failures are only counted", category.name());
+  }
+}
+
+// Reports when passed is false, hands passed back so callers can bail out too
+pub fn check(category: ValidationCategory, passed: bool, message: impl FnOnce() -> String) -> bool {
+  if !passed {
+    report(category, message);
+  }
+  passed
+}
+
+pub fn failure_count(category: ValidationCategory) -> u64 {
+  FAILURES[category.index()].load(Ordering::Relaxed)
+}
+
+// Every category with how often it failed since the last reset
+pub fn failure_counts() -> Vec<(ValidationCategory, u64)> {
+  ValidationCategory::ALL.iter().map(|category| (*category, failure_count(*category))).collect()
+}
+
+pub fn reset_counts() {
+  for failures in FAILURES.iter() {
+    failures.store(0, Ordering::Relaxed);
+  }
+}
+
+pub fn summary() -> String {
+  if !COMPILED {
+    return "validation not compiled in".to_string();
+  }
+  let counts = failure_counts()
+    .iter()
+    .map(|(category, count)| format!("{}: {count}", category.name()))
+    .collect::<Vec<_>>()
+    .join(", ");
+  format!("validation {}, {counts}", if is_enabled() { "on" } else { "off" })
+}
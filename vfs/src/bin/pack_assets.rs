@@ -0,0 +1,12 @@
+use std::path::Path;
+
+// Builds an asset pack for shipping builds: pack_assets <asset dir> <out.rpak>
+fn main() -> Result<(), String> {
+  let args = std::env::args().collect::<Vec<_>>();
+  let [_, src_dir, out_path] = args.as_slice() else {
+    return Err(format!("usage: pack_assets <asset dir> <out.{}>", vfs::PACK_EXTENSION));
+  };
+  let file_count = vfs::write_pack(Path::new(src_dir), Path::new(out_path))?;
+  println!("packed {file_count} files from {src_dir} into {out_path}");
+  Ok(())
+}
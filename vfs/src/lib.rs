@@ -0,0 +1,246 @@
+use std::{
+  collections::BTreeSet,
+  path::{Path, PathBuf},
+  sync::{Arc, OnceLock, RwLock},
+};
+
+use jobs::JobHandle;
+
+mod mapped;
+mod pack;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+#[cfg(test)]
+mod tests;
+
+pub use mapped::MappedBytes;
+pub use pack::{write_pack, AssetPack};
+
+pub const PACK_EXTENSION: &str = "rpak";
+
+static GLOBAL_VFS: OnceLock<Vfs> = OnceLock::new();
+
+// Vfs paths are relative, use '/' separators and can't climb above the root. Resolves "." and
+// "..", so old style paths like "./background.png" keep working
+pub fn normalize_path(path: &str) -> String {
+  let mut components: Vec<&str> = vec![];
+  for component in path.split(['/', '\\']) {
+    match component {
+      "" | "." => {}
+      ".." => {
+        components.pop();
+      }
+      _ => components.push(component),
+    }
+  }
+  components.join("/")
+}
+
+enum MountSource {
+  Dir(PathBuf),
+  Pack(AssetPack),
+}
+
+struct Mount {
+  // Normalized, empty for the root
+  mount_point: String,
+  source: MountSource,
+}
+
+impl Mount {
+  // Path relative to the mount, if the vfs path is under it
+  fn relative<'a>(&self, path: &'a str) -> Option<&'a str> {
+    if self.mount_point.is_empty() {
+      return Some(path);
+    }
+    match path.strip_prefix(self.mount_point.as_str())? {
+      "" => Some(""),
+      rest => rest.strip_prefix('/'),
+    }
+  }
+
+  fn contains(&self, rel_path: &str) -> bool {
+    match &self.source {
+      MountSource::Dir(dir) => dir.join(rel_path).is_file(),
+      MountSource::Pack(pack) => pack.contains(rel_path),
+    }
+  }
+
+  fn read(&self, rel_path: &str) -> Result<Vec<u8>, String> {
+    match &self.source {
+      MountSource::Dir(dir) => {
+        let os_path = dir.join(rel_path);
+        std::fs::read(&os_path).map_err(|e| format!("at reading {}: {e}", os_path.display()))
+      }
+      MountSource::Pack(pack) => pack.read(rel_path),
+    }
+  }
+
+  fn read_mapped(&self, rel_path: &str) -> Result<MappedBytes, String> {
+    match &self.source {
+      // Loose files can change under a map, so they're copied like read does
+      MountSource::Dir(_) => self.read(rel_path).map(MappedBytes::from),
+      MountSource::Pack(pack) => pack.read_mapped(rel_path),
+    }
+  }
+
+  fn list(&self, rel_dir: &str, files: &mut BTreeSet<String>) {
+    let to_vfs_path = |rel_path: &str| normalize_path(&format!("{}/{rel_path}", self.mount_point));
+    match &self.source {
+      MountSource::Dir(dir) => {
+        let mut dirs = vec![dir.join(rel_dir)];
+        while let Some(current_dir) = dirs.pop() {
+          let Ok(entries) = std::fs::read_dir(&current_dir) else { continue };
+          for entry_path in entries.flatten().map(|entry| entry.path()) {
+            if entry_path.is_dir() {
+              dirs.push(entry_path);
+            } else if let Ok(rel_path) = entry_path.strip_prefix(dir) {
+              files.insert(to_vfs_path(&rel_path.to_string_lossy()));
+            }
+          }
+        }
+      }
+      MountSource::Pack(pack) => {
+        let prefix = if rel_dir.is_empty() { String::new() } else { format!("{rel_dir}/") };
+        for entry_path in pack.entry_paths().filter(|path| path.starts_with(&prefix)) {
+          files.insert(to_vfs_path(entry_path));
+        }
+      }
+    }
+  }
+}
+
+// Mounts loose directories and asset packs under vfs paths. When mounts overlap the one mounted
+// last wins, so loose files mounted after a pack override what's in it. Cloning is cheap and
+// clones share the mounts
+#[derive(Clone, Default)]
+pub struct Vfs {
+  mounts: Arc<RwLock<Vec<Arc<Mount>>>>,
+}
+
+impl Vfs {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn add_mount(&self, mount_point: &str, source: MountSource) -> Result<(), String> {
+    let mount = Mount { mount_point: normalize_path(mount_point), source };
+    self
+      .mounts
+      .write()
+      .map_err(|e| format!("at getting vfs mounts lock: {e}"))?
+      .push(Arc::new(mount));
+    Ok(())
+  }
+
+  pub fn mount_dir(&self, mount_point: &str, dir: &Path) -> Result<(), String> {
+    if !dir.is_dir() {
+      return Err(format!("can't mount {}, not a directory", dir.display()));
+    }
+    self.add_mount(mount_point, MountSource::Dir(dir.to_path_buf()))
+  }
+
+  pub fn mount_pack(&self, mount_point: &str, pack_path: &Path) -> Result<(), String> {
+    let pack = AssetPack::open(pack_path)?;
+    self.add_mount(mount_point, MountSource::Pack(pack))
+  }
+
+  // Packs are picked by their extension, anything else has to be a directory
+  pub fn mount(&self, mount_point: &str, path: &Path) -> Result<(), String> {
+    match path.extension().is_some_and(|ext| ext == PACK_EXTENSION) {
+      true => self.mount_pack(mount_point, path),
+      false => self.mount_dir(mount_point, path),
+    }
+  }
+
+  // Removes every mount at the mount point, returns how many there were
+  pub fn unmount(&self, mount_point: &str) -> Result<usize, String> {
+    let mount_point = normalize_path(mount_point);
+    let mut mounts = self.mounts.write().map_err(|e| format!("at getting vfs mounts lock: {e}"))?;
+    let prev_len = mounts.len();
+    mounts.retain(|mount| mount.mount_point != mount_point);
+    Ok(prev_len - mounts.len())
+  }
+
+  // Snapshot of the mounts, so reads don't hold the lock while touching the disk
+  fn mounts(&self) -> Vec<Arc<Mount>> {
+    match self.mounts.read() {
+      Ok(mounts) => mounts.clone(),
+      Err(e) => e.into_inner().clone(),
+    }
+  }
+
+  pub fn exists(&self, path: &str) -> bool {
+    let path = normalize_path(path);
+    self
+      .mounts()
+      .iter()
+      .any(|mount| mount.relative(&path).is_some_and(|rel_path| mount.contains(rel_path)))
+  }
+
+  // The mount that wins for path, with path relative to it
+  fn find(&self, path: &str) -> Result<(Arc<Mount>, String), String> {
+    let path = normalize_path(path);
+    for mount in self.mounts().iter().rev() {
+      let Some(rel_path) = mount.relative(&path) else { continue };
+      if mount.contains(rel_path) {
+        return Ok((mount.clone(), rel_path.to_string()));
+      }
+    }
+    Err(format!("{path} not found in any vfs mount"))
+  }
+
+  pub fn read(&self, path: &str) -> Result<Vec<u8>, String> {
+    let (mount, rel_path) = self.find(path)?;
+    mount.read(&rel_path).map_err(|e| format!("at reading vfs path {path}: {e}"))
+  }
+
+  // Same file read gets, for big assets parsed straight from where they are. Files in packs are
+  // mapped and not copied, loose files are read in since they may be edited while mapped
+  pub fn read_mapped(&self, path: &str) -> Result<MappedBytes, String> {
+    let (mount, rel_path) = self.find(path)?;
+    mount.read_mapped(&rel_path).map_err(|e| format!("at mapping vfs path {path}: {e}"))
+  }
+
+  pub fn read_to_string(&self, path: &str) -> Result<String, String> {
+    String::from_utf8(self.read(path)?).map_err(|e| format!("at reading {path} as text: {e}"))
+  }
+
+  // Runs the read on the global job pool
+  pub fn read_async(&self, path: &str) -> JobHandle<Result<Vec<u8>, String>> {
+    let (vfs, path) = (self.clone(), path.to_string());
+    jobs::global().spawn(move || vfs.read(&path))
+  }
+
+  // Every file under a directory across all mounts, sorted and without duplicates
+  pub fn list(&self, dir: &str) -> Vec<String> {
+    let dir = normalize_path(dir);
+    let mut files = BTreeSet::new();
+    for mount in self.mounts().iter() {
+      match mount.relative(&dir) {
+        Some(rel_dir) => mount.list(rel_dir, &mut files),
+        // Mounts inside the directory are listed whole
+        None if dir.is_empty() || mount.mount_point.starts_with(&format!("{dir}/")) => {
+          mount.list("", &mut files)
+        }
+        None => {}
+      }
+    }
+    files.into_iter().collect()
+  }
+}
+
+// Vfs shared by engine subsystems, has the working directory at the root unless init_global ran
+// first
+pub fn global() -> &'static Vfs {
+  GLOBAL_VFS.get_or_init(|| {
+    let vfs = Vfs::new();
+    let _ = vfs.mount_dir("", Path::new(".")).inspect_err(|e| eprintln!("at mounting cwd: {e}"));
+    vfs
+  })
+}
+
+pub fn init_global(vfs: Vfs) -> Result<&'static Vfs, String> {
+  GLOBAL_VFS.set(vfs).map_err(|_| "global vfs is already initialized".to_string())?;
+  Ok(global())
+}
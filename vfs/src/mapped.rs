@@ -0,0 +1,70 @@
+use std::{fs, ops::Deref, ops::Range, path::Path, sync::Arc};
+
+use memmap2::Mmap;
+
+enum Backing {
+  Map(Mmap),
+  Copied(Vec<u8>),
+}
+
+// A vfs file's bytes, or the part of one a pack entry takes up. Pack entries are mapped, reading
+// them reads the page cache and nothing is copied up front. Loose files are read in instead, they
+// get edited and saved over while the game runs. Cloning is cheap and clones share the bytes
+#[derive(Clone)]
+pub struct MappedBytes {
+  backing: Arc<Backing>,
+  range: Range<usize>,
+}
+
+impl MappedBytes {
+  // Only for files nothing writes to while they're mapped, like packs. Truncating a mapped file
+  // makes reading the pages past its new end fault with SIGBUS on unix, which can't be caught
+  // as an error, and writing to one changes the bytes under whoever is reading them
+  pub(crate) fn map_file(path: &Path) -> Result<Self, String> {
+    let file = fs::File::open(path).map_err(|e| format!("at opening {}: {e}", path.display()))?;
+    // Safety: the map is only read. Packs aren't written in place while mounted, write_pack
+    // renames a new file over the old one and the map keeps the old one's contents
+    let map =
+      unsafe { Mmap::map(&file) }.map_err(|e| format!("at mapping {}: {e}", path.display()))?;
+    let range = 0..map.len();
+    Ok(Self { backing: Arc::new(Backing::Map(map)), range })
+  }
+
+  // The sub range, None when it isn't inside these bytes
+  pub fn slice(&self, range: Range<usize>) -> Option<Self> {
+    if range.start > range.end || range.end > self.range.len() {
+      return None;
+    }
+    let start = self.range.start + range.start;
+    Some(Self { backing: self.backing.clone(), range: start..start + range.len() })
+  }
+
+  // Whether the bytes are read from a map instead of a copy
+  pub fn is_mapped(&self) -> bool {
+    matches!(*self.backing, Backing::Map(_))
+  }
+}
+
+impl From<Vec<u8>> for MappedBytes {
+  fn from(bytes: Vec<u8>) -> Self {
+    let range = 0..bytes.len();
+    Self { backing: Arc::new(Backing::Copied(bytes)), range }
+  }
+}
+
+impl Deref for MappedBytes {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    match &*self.backing {
+      Backing::Map(map) => &map[self.range.clone()],
+      Backing::Copied(bytes) => &bytes[self.range.clone()],
+    }
+  }
+}
+
+impl AsRef<[u8]> for MappedBytes {
+  fn as_ref(&self) -> &[u8] {
+    self
+  }
+}
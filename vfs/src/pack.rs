@@ -0,0 +1,177 @@
+use std::{
+  collections::BTreeMap,
+  fs,
+  io::{Read, Write},
+  path::{Path, PathBuf},
+};
+
+use crate::{normalize_path, MappedBytes};
+
+// Pack layout, all integers little endian:
+// magic "RPAK", version u32, entry count u32,
+// per entry: path length u32, path utf8, data offset u64 from the file start, data length u64,
+// then the entry data in the same order
+const PACK_MAGIC: &[u8; 4] = b"RPAK";
+const PACK_VERSION: u32 = 1;
+// Entries start on multiples of this, so arrays of numbers in them can be viewed in place. Older
+// packs without the padding still read, offsets are in the index either way
+const ENTRY_ALIGN: u64 = 16;
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, String> {
+  let mut bytes = [0; 4];
+  reader.read_exact(&mut bytes).map_err(|e| format!("at reading pack index: {e}"))?;
+  Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, String> {
+  let mut bytes = [0; 8];
+  reader.read_exact(&mut bytes).map_err(|e| format!("at reading pack index: {e}"))?;
+  Ok(u64::from_le_bytes(bytes))
+}
+
+// The file is mapped, entry data is paged in from it as it's read
+pub struct AssetPack {
+  map: MappedBytes,
+  // path -> (offset, length)
+  entries: BTreeMap<String, (u64, u64)>,
+}
+
+impl AssetPack {
+  pub fn open(path: &Path) -> Result<Self, String> {
+    let file =
+      fs::File::open(path).map_err(|e| format!("at opening pack {}: {e}", path.display()))?;
+    let file_len = file.metadata().map_err(|e| format!("at reading pack metadata: {e}"))?.len();
+    let mut reader = std::io::BufReader::new(file);
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic).map_err(|e| format!("at reading pack header: {e}"))?;
+    if &magic != PACK_MAGIC {
+      return Err(format!("{} is not an asset pack", path.display()));
+    }
+    let version = read_u32(&mut reader)?;
+    if version != PACK_VERSION {
+      return Err(format!("{} has unsupported pack version {version}", path.display()));
+    }
+
+    let entry_count = read_u32(&mut reader)?;
+    let mut entries = BTreeMap::new();
+    for _ in 0..entry_count {
+      let path_len = read_u32(&mut reader)? as u64;
+      // Before allocating for it, a broken index could ask for gigabytes
+      if path_len > file_len {
+        return Err(format!("{} has a pack entry path longer than the file", path.display()));
+      }
+      let mut path_bytes = vec![0; path_len as usize];
+      reader.read_exact(&mut path_bytes).map_err(|e| format!("at reading pack index: {e}"))?;
+      let entry_path =
+        String::from_utf8(path_bytes).map_err(|e| format!("at reading pack entry path: {e}"))?;
+      let (offset, len) = (read_u64(&mut reader)?, read_u64(&mut reader)?);
+      if offset.checked_add(len).is_none_or(|end| end > file_len) {
+        return Err(format!("pack entry {entry_path} is out of the file bounds"));
+      }
+      entries.insert(normalize_path(&entry_path), (offset, len));
+    }
+    Ok(Self { map: MappedBytes::map_file(path)?, entries })
+  }
+
+  pub fn contains(&self, path: &str) -> bool {
+    self.entries.contains_key(path)
+  }
+
+  pub fn entry_paths(&self) -> impl Iterator<Item = &String> {
+    self.entries.keys()
+  }
+
+  pub fn read(&self, path: &str) -> Result<Vec<u8>, String> {
+    Ok(self.read_mapped(path)?.to_vec())
+  }
+
+  // The entry's part of the mapped pack, without copying it
+  pub fn read_mapped(&self, path: &str) -> Result<MappedBytes, String> {
+    let &(offset, len) = self.entries.get(path).ok_or(format!("{path} not in pack"))?;
+    // Checked against the file's length when the index was read
+    self
+      .map
+      .slice(offset as usize..(offset + len) as usize)
+      .ok_or(format!("{path} is past the end of the pack, it changed since it was opened"))
+  }
+}
+
+// Every file under the directory, as paths relative to it
+fn collect_files(dir: &Path) -> Result<BTreeMap<String, PathBuf>, String> {
+  let mut files = BTreeMap::new();
+  let mut dirs = vec![dir.to_path_buf()];
+  while let Some(current_dir) = dirs.pop() {
+    let entries = fs::read_dir(&current_dir)
+      .map_err(|e| format!("at reading dir {}: {e}", current_dir.display()))?;
+    for entry in entries {
+      let entry_path = entry.map_err(|e| format!("at reading dir entry: {e}"))?.path();
+      if entry_path.is_dir() {
+        dirs.push(entry_path);
+        continue;
+      }
+      let Ok(rel_path) = entry_path.strip_prefix(dir) else { continue };
+      files.insert(normalize_path(&rel_path.to_string_lossy()), entry_path);
+    }
+  }
+  Ok(files)
+}
+
+// Packs every file under a directory, returns the number of files packed. Written next to
+// out_path and renamed over it, a game with the old pack mounted keeps reading the old one
+pub fn write_pack(src_dir: &Path, out_path: &Path) -> Result<usize, String> {
+  let mut tmp_name = out_path.file_name().unwrap_or_default().to_os_string();
+  tmp_name.push(".tmp");
+  let tmp_path = out_path.with_file_name(tmp_name);
+  let packed = write_pack_file(src_dir, &tmp_path).inspect_err(|_| {
+    let _ = fs::remove_file(&tmp_path);
+  })?;
+  fs::rename(&tmp_path, out_path)
+    .map_err(|e| format!("at moving pack into place at {}: {e}", out_path.display()))?;
+  Ok(packed)
+}
+
+fn write_pack_file(src_dir: &Path, out_path: &Path) -> Result<usize, String> {
+  let files = collect_files(src_dir)?;
+  let index_len = 12 + files.keys().map(|path| 4 + path.len() as u64 + 16).sum::<u64>();
+
+  let mut index = Vec::with_capacity(index_len as usize);
+  index.extend_from_slice(PACK_MAGIC);
+  index.extend_from_slice(&PACK_VERSION.to_le_bytes());
+  index.extend_from_slice(&(files.len() as u32).to_le_bytes());
+  let mut offset = index_len.next_multiple_of(ENTRY_ALIGN);
+  let mut lens = BTreeMap::new();
+  for (path, os_path) in files.iter() {
+    let len = fs::metadata(os_path)
+      .map_err(|e| format!("at reading metadata of {}: {e}", os_path.display()))?
+      .len();
+    index.extend_from_slice(&(path.len() as u32).to_le_bytes());
+    index.extend_from_slice(path.as_bytes());
+    index.extend_from_slice(&offset.to_le_bytes());
+    index.extend_from_slice(&len.to_le_bytes());
+    offset = (offset + len).next_multiple_of(ENTRY_ALIGN);
+    lens.insert(path.clone(), len);
+  }
+
+  let out_file = fs::File::create(out_path)
+    .map_err(|e| format!("at creating pack {}: {e}", out_path.display()))?;
+  let mut writer = std::io::BufWriter::new(out_file);
+  writer.write_all(&index).map_err(|e| format!("at writing pack index: {e}"))?;
+  let mut written = index_len;
+  for (path, os_path) in files.iter() {
+    let padding = written.next_multiple_of(ENTRY_ALIGN) - written;
+    writer
+      .write_all(&[0; ENTRY_ALIGN as usize][..padding as usize])
+      .map_err(|e| format!("at writing pack {}: {e}", out_path.display()))?;
+    let file =
+      fs::File::open(os_path).map_err(|e| format!("at opening {}: {e}", os_path.display()))?;
+    let copied = std::io::copy(&mut file.take(lens[path]), &mut writer)
+      .map_err(|e| format!("at packing {}: {e}", os_path.display()))?;
+    if copied != lens[path] {
+      return Err(format!("{} changed while packing", os_path.display()));
+    }
+    written += padding + copied;
+  }
+  writer.flush().map_err(|e| format!("at writing pack {}: {e}", out_path.display()))?;
+  Ok(files.len())
+}
@@ -0,0 +1,27 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+// Removed when dropped, so failed tests don't leave it around for the next run. Named after the
+// test and process, so tests running at once don't share one
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+  pub fn new(name: &str) -> Self {
+    let dir = std::env::temp_dir().join(format!("residue_{name}_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    Self(dir)
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.0
+  }
+}
+
+impl Drop for TempDir {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.0);
+  }
+}
@@ -0,0 +1,168 @@
+use std::fs;
+
+use crate::test_support::TempDir;
+use crate::{write_pack, AssetPack, MappedBytes, Vfs};
+
+// Index entries as written, path -> (offset, len)
+fn read_index(bytes: &[u8]) -> Vec<(String, u64, u64)> {
+  let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+  let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+  let mut at = 12;
+  let mut entries = vec![];
+  for _ in 0..u32_at(8) {
+    let path_len = u32_at(at) as usize;
+    let path = String::from_utf8(bytes[at + 4..at + 4 + path_len].to_vec()).unwrap();
+    at += 4 + path_len;
+    entries.push((path, u64_at(at), u64_at(at + 8)));
+    at += 16;
+  }
+  entries
+}
+
+// A pack with one entry, its data at 37 and 5 long. Header fields are written as given
+fn raw_pack(magic: &[u8; 4], version: u32, path_len: u32, offset: u64, len: u64) -> Vec<u8> {
+  let mut bytes = magic.to_vec();
+  bytes.extend_from_slice(&version.to_le_bytes());
+  bytes.extend_from_slice(&1u32.to_le_bytes());
+  bytes.extend_from_slice(&path_len.to_le_bytes());
+  bytes.extend_from_slice(b"a.txt");
+  bytes.extend_from_slice(&offset.to_le_bytes());
+  bytes.extend_from_slice(&len.to_le_bytes());
+  bytes.extend_from_slice(b"hello");
+  bytes
+}
+
+#[test]
+fn packs_read_back_every_entry_byte_for_byte_at_aligned_offsets() {
+  let temp = TempDir::new("vfs_pack_round_trip");
+  let src = temp.path().join("src");
+  let files: [(&str, Vec<u8>); 5] = [
+    ("a.txt", b"hello".to_vec()),
+    ("empty.bin", vec![]),
+    ("textures/odd.bin", (0..37u8).collect()),
+    ("textures/big.bin", (0..70_000u32).map(|x| (x * 7) as u8).collect()),
+    ("levels/one/level.toml", b"name = \"one\"\n".to_vec()),
+  ];
+  for (path, data) in files.iter() {
+    let os_path = src.join(path);
+    fs::create_dir_all(os_path.parent().unwrap()).unwrap();
+    fs::write(os_path, data).unwrap();
+  }
+  let pack_path = temp.path().join("assets.rpak");
+  assert_eq!(write_pack(&src, &pack_path).unwrap(), files.len());
+
+  let pack = AssetPack::open(&pack_path).unwrap();
+  assert_eq!(pack.entry_paths().count(), files.len());
+  for (path, data) in files.iter() {
+    assert!(pack.contains(path), "{path}");
+    assert_eq!(&pack.read(path).unwrap(), data, "{path}");
+    assert_eq!(&*pack.read_mapped(path).unwrap(), data.as_slice(), "{path}");
+  }
+  assert!(pack.read("missing.txt").is_err());
+
+  let bytes = fs::read(&pack_path).unwrap();
+  let index = read_index(&bytes);
+  assert_eq!(index.len(), files.len());
+  for (path, offset, len) in index {
+    assert_eq!(offset % 16, 0, "{path} starts at {offset}");
+    let data = &files.iter().find(|(file, _)| *file == path).unwrap().1;
+    assert_eq!(&bytes[offset as usize..(offset + len) as usize], data.as_slice(), "{path}");
+  }
+
+  // Mounted, the entries show up under the mount point like a directory's files would
+  let vfs = Vfs::new();
+  vfs.mount("assets", &pack_path).unwrap();
+  assert_eq!(vfs.read("assets/textures/odd.bin").unwrap(), files[2].1);
+  assert_eq!(vfs.list("assets/textures"), ["assets/textures/big.bin", "assets/textures/odd.bin"]);
+}
+
+#[test]
+fn packs_with_a_bad_magic_or_version_are_rejected() {
+  let temp = TempDir::new("vfs_pack_header");
+  let pack_path = temp.path().join("bad.rpak");
+  fs::write(&pack_path, raw_pack(b"RPAK", 1, 5, 37, 5)).unwrap();
+  assert_eq!(AssetPack::open(&pack_path).unwrap().read("a.txt").unwrap(), b"hello");
+
+  fs::write(&pack_path, raw_pack(b"ZPAK", 1, 5, 37, 5)).unwrap();
+  assert!(AssetPack::open(&pack_path).err().unwrap().contains("not an asset pack"));
+  fs::write(&pack_path, raw_pack(b"RPAK", 2, 5, 37, 5)).unwrap();
+  assert!(AssetPack::open(&pack_path).err().unwrap().contains("unsupported pack version 2"));
+  // Too short to have a header
+  fs::write(&pack_path, b"RP").unwrap();
+  assert!(AssetPack::open(&pack_path).is_err());
+}
+
+#[test]
+fn pack_entries_past_the_end_of_the_file_are_rejected() {
+  let temp = TempDir::new("vfs_pack_bounds");
+  let pack_path = temp.path().join("bad.rpak");
+  for (offset, len) in [(37, 6), (42, 1), (1000, 0), (u64::MAX, 2), (0, u64::MAX)] {
+    fs::write(&pack_path, raw_pack(b"RPAK", 1, 5, offset, len)).unwrap();
+    let err = AssetPack::open(&pack_path).err().unwrap();
+    assert!(err.contains("out of the file bounds"), "{offset} {len}: {err}");
+  }
+  // Paths longer than the whole file fail before anything is allocated for them
+  fs::write(&pack_path, raw_pack(b"RPAK", 1, u32::MAX, 37, 5)).unwrap();
+  assert!(AssetPack::open(&pack_path).err().unwrap().contains("longer than the file"));
+  // A truncated index
+  let mut truncated = raw_pack(b"RPAK", 1, 5, 37, 5);
+  truncated.truncate(30);
+  fs::write(&pack_path, truncated).unwrap();
+  assert!(AssetPack::open(&pack_path).is_err());
+}
+
+#[test]
+fn mapped_bytes_slices_stay_inside_their_range() {
+  let temp = TempDir::new("vfs_mapped");
+  let path = temp.path().join("data.bin");
+  fs::write(&path, (0..32u8).collect::<Vec<_>>()).unwrap();
+  let bytes = MappedBytes::map_file(&path).unwrap();
+  let slice = bytes.slice(8..16).unwrap();
+  assert_eq!(&*slice, &(8..16u8).collect::<Vec<_>>()[..]);
+  assert_eq!(&*slice.slice(2..4).unwrap(), &[10, 11]);
+  assert!(slice.slice(4..9).is_none());
+  assert!(bytes.slice(0..33).is_none());
+}
+
+#[test]
+fn only_pack_entries_are_mapped_and_loose_files_are_copied() {
+  let temp = TempDir::new("vfs_mapped_mounts");
+  let (src, loose) = (temp.path().join("src"), temp.path().join("loose"));
+  fs::create_dir_all(&src).unwrap();
+  fs::create_dir_all(&loose).unwrap();
+  fs::write(src.join("mesh.bin"), [1, 2, 3]).unwrap();
+  fs::write(loose.join("level.bin"), [4, 5, 6, 7]).unwrap();
+  let pack_path = temp.path().join("assets.rpak");
+  write_pack(&src, &pack_path).unwrap();
+
+  let vfs = Vfs::new();
+  vfs.mount("packed", &pack_path).unwrap();
+  vfs.mount("loose", &loose).unwrap();
+  let packed = vfs.read_mapped("packed/mesh.bin").unwrap();
+  assert!(packed.is_mapped());
+  assert_eq!(&*packed, &[1, 2, 3]);
+  let copied = vfs.read_mapped("loose/level.bin").unwrap();
+  assert!(!copied.is_mapped());
+  // Truncating a loose file can't pull the bytes out from under whoever holds them
+  fs::write(loose.join("level.bin"), []).unwrap();
+  assert_eq!(&*copied, &[4, 5, 6, 7]);
+}
+
+#[test]
+fn rewriting_a_mounted_pack_leaves_the_mapped_one_as_it_was() {
+  let temp = TempDir::new("vfs_pack_rewrite");
+  let src = temp.path().join("src");
+  fs::create_dir_all(&src).unwrap();
+  fs::write(src.join("a.bin"), vec![9; 4096]).unwrap();
+  let pack_path = temp.path().join("assets.rpak");
+  write_pack(&src, &pack_path).unwrap();
+  let vfs = Vfs::new();
+  vfs.mount("", &pack_path).unwrap();
+  let before = vfs.read_mapped("a.bin").unwrap();
+
+  fs::write(src.join("a.bin"), [1]).unwrap();
+  write_pack(&src, &pack_path).unwrap();
+  assert_eq!(&*before, &[9; 4096][..]);
+  assert_eq!(AssetPack::open(&pack_path).unwrap().read("a.bin").unwrap(), [1]);
+  assert!(!temp.path().join("assets.rpak.tmp").exists());
+}